@@ -0,0 +1,12 @@
+#!/bin/bash
+# Checks that every supported feature combination builds, including the
+# headless analytics-only server (no gpu, speech or websocket).
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+export CARGO_TARGET_DIR="${CARGO_TARGET_DIR:-target/feature-checks}"
+
+for features in "" "websocket" "gpu,speech,websocket"; do
+    echo "==> cargo check --no-default-features --features \"${features}\""
+    cargo check --all-targets --no-default-features --features "${features}"
+done
@@ -1,68 +1,507 @@
 //! Client Manager Actor to replace static APP_CLIENT_MANAGER singleton
 
 use actix::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use crate::actors::messages::*;
+use crate::config::{CaptionSettings, ClientLodSettings, CoViewSettings, NodeWatchSettings};
+#[cfg(feature = "websocket")]
 use crate::handlers::socket_flow_handler::SocketFlowServer;
+use crate::models::spatial_anchor::DEFAULT_ROOM;
+use crate::services::admin_feed::{AdminFeed, AdminMessage, ClientNotice};
+use crate::services::webhook_service::WebhookService;
+use crate::utils::auth::Identity;
+use crate::utils::binary_protocol;
+use crate::utils::captions;
+use crate::utils::client_lod::ClientLod;
+use crate::utils::co_selection::SelectionTracker;
+use crate::utils::edge_visibility;
+use crate::utils::frame_accounting::FrameTotals;
+use crate::utils::frame_hash::{SentFrame, SentFrameLog, SharedHash};
+use crate::utils::node_watch::{self, NodeWatches, WatchError, WatchEvent, WatchInfo};
+use crate::utils::socket_flow_messages::PoseUpdate;
+use crate::utils::time_sync::FrameTiming;
 // WsMessage is no longer needed here as we use custom messages
-use log::{debug, warn};
+use log::{debug, info, trace, warn};
+
+// Pose relays are capped at 15 Hz per client
+const POSE_RELAY_INTERVAL: Duration = Duration::from_millis(1000 / 15);
+
+/// Outbound channels for a connected client. Held as recipients so the manager
+/// does not depend on the concrete socket actor.
+#[derive(Clone)]
+pub struct ClientHandle {
+    pub text: Recipient<SendToClientText>,
+    pub binary: Recipient<SendToClientBinary>,
+    pub close: Recipient<CloseConnection>,
+}
+
+#[cfg(feature = "websocket")]
+impl From<Addr<SocketFlowServer>> for ClientHandle {
+    fn from(addr: Addr<SocketFlowServer>) -> Self {
+        Self {
+            text: addr.clone().recipient(),
+            binary: addr.clone().recipient(),
+            close: addr.recipient(),
+        }
+    }
+}
+
+// Agents only ever receive JSON text
+#[derive(Clone)]
+pub struct AgentHandle {
+    pub name: String,
+    pub text: Recipient<SendToClientText>,
+    pub close: Recipient<CloseConnection>,
+}
 
 pub struct ClientManagerActor {
-    clients: HashMap<usize, Addr<SocketFlowServer>>,
+    clients: HashMap<usize, ClientHandle>,
+    client_rooms: HashMap<usize, String>,
+    // Who each client authenticated as and the role it was granted at connect
+    client_identities: HashMap<usize, Identity>,
+    last_pose_relay: HashMap<usize, Instant>,
+    // Edge types each client has switched off; left out of the graph events it's sent
+    hidden_edge_types: HashMap<usize, HashSet<String>>,
+    // What each client's position frames carried and skipped, as its socket reports it
+    frame_accounting: HashMap<usize, FrameTotals>,
+    // Hashes of the frames sent to clients that asked for them
+    sent_frames: HashMap<usize, SentFrameLog>,
+    // Agent sessions, keyed by id from the same counter as clients
+    agents: HashMap<usize, AgentHandle>,
+    // Graph diffs and annotation events are also posted to configured webhooks
+    webhooks: Option<Arc<WebhookService>>,
+    captions: CaptionSettings,
+    // Each client's most recent selection, for speech that targets "this node"
+    selected_nodes: HashMap<usize, u32>,
+    // Selections for co-viewed edges; only present when they are enabled
+    selections: Option<SelectionTracker>,
+    // Connects and disconnects are reported to admin dashboards
+    admin_feed: Option<Arc<AdminFeed>>,
+    // Nodes clients and agents watch, told apart by id as both come from `next_id`
+    node_watches: NodeWatches,
+    // Each client's own top nodes, from its selections, gaze and viewpoint
+    lod: ClientLod,
     next_id: AtomicUsize,
 }
 
+/// Stable per-client display colour, spread around the hue wheel by the golden ratio
+pub fn client_color(client_id: usize) -> String {
+    let hue = ((client_id as f32) * 0.618_034).fract() * 6.0;
+    let x = 1.0 - ((hue % 2.0) - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Slightly desaturated so cursors stay readable on the dark background
+    let channel = |c: f32| ((0.3 + 0.7 * c) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
 impl ClientManagerActor {
     pub fn new() -> Self {
         Self {
             clients: HashMap::new(),
+            client_rooms: HashMap::new(),
+            client_identities: HashMap::new(),
+            last_pose_relay: HashMap::new(),
+            hidden_edge_types: HashMap::new(),
+            frame_accounting: HashMap::new(),
+            sent_frames: HashMap::new(),
+            agents: HashMap::new(),
+            webhooks: None,
+            captions: CaptionSettings::default(),
+            selected_nodes: HashMap::new(),
+            selections: None,
+            admin_feed: None,
+            node_watches: NodeWatches::new(NodeWatchSettings::default()),
+            lod: ClientLod::new(ClientLodSettings::default()),
             next_id: AtomicUsize::new(1),
         }
     }
 
-    pub fn register_client(&mut self, addr: Addr<SocketFlowServer>) -> usize {
+    pub fn with_webhooks(mut self, webhooks: Arc<WebhookService>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    pub fn with_captions(mut self, captions: CaptionSettings) -> Self {
+        self.captions = captions;
+        self
+    }
+
+    pub fn with_co_view(mut self, settings: &CoViewSettings) -> Self {
+        self.selections = settings.enabled
+            .then(|| SelectionTracker::new(
+                settings.max_selections_per_session,
+                settings.max_pending_sessions,
+                Duration::from_secs(settings.session_idle_secs),
+            ));
+        self
+    }
+
+    pub fn with_admin_feed(mut self, admin_feed: Arc<AdminFeed>) -> Self {
+        self.admin_feed = Some(admin_feed);
+        self
+    }
+
+    pub fn with_node_watches(mut self, settings: NodeWatchSettings) -> Self {
+        self.node_watches = NodeWatches::new(settings);
+        self
+    }
+
+    pub fn with_client_lod(mut self, settings: ClientLodSettings) -> Self {
+        self.lod = ClientLod::new(settings);
+        self
+    }
+
+    fn notify_admins(&self, event: &str, client_id: usize, role: Option<String>) {
+        if let Some(admin_feed) = &self.admin_feed {
+            admin_feed.publish(AdminMessage::Client(ClientNotice {
+                event: event.to_string(),
+                client_id,
+                role,
+                clients: self.clients.len(),
+            }));
+        }
+    }
+
+    pub fn register_client(&mut self, handle: ClientHandle, identity: Identity) -> usize {
         let client_id = self.next_id.fetch_add(1, Ordering::SeqCst);
-        self.clients.insert(client_id, addr);
-        debug!("Client {} registered. Total clients: {}", client_id, self.clients.len());
+        self.clients.insert(client_id, handle);
+        self.client_rooms.insert(client_id, DEFAULT_ROOM.to_string());
+        debug!("Client {} registered as {}. Total clients: {}", client_id, identity.role.as_str(), self.clients.len());
+        self.notify_admins("connected", client_id, Some(identity.role.as_str().to_string()));
+        self.client_identities.insert(client_id, identity);
         client_id
     }
 
+    pub fn client_identity(&self, client_id: usize) -> Option<&Identity> {
+        self.client_identities.get(&client_id)
+    }
+
     pub fn unregister_client(&mut self, client_id: usize) {
+        let room = self.client_rooms.remove(&client_id);
+        self.client_identities.remove(&client_id);
+        self.hidden_edge_types.remove(&client_id);
+        self.frame_accounting.remove(&client_id);
+        self.sent_frames.remove(&client_id);
+        self.selected_nodes.remove(&client_id);
+        self.lod.remove(client_id);
+        self.node_watches.release(client_id, Instant::now());
+        if let Some(selections) = self.selections.as_mut() {
+            selections.finish(client_id);
+        }
+        let had_pose = self.last_pose_relay.remove(&client_id).is_some();
         if self.clients.remove(&client_id).is_some() {
             debug!("Client {} unregistered. Total clients: {}", client_id, self.clients.len());
+            self.notify_admins("disconnected", client_id, None);
+            // Tell peers to drop this client's cursor right away
+            if let (Some(room), true) = (room, had_pose) {
+                self.announce_pose_left(&room, client_id);
+            }
         } else {
             warn!("Attempted to unregister non-existent client {}", client_id);
         }
     }
 
-    pub fn broadcast_to_all(&self, data: Vec<u8>) {
+    pub fn broadcast_to_all(&mut self, data: Vec<u8>, keyframe: bool, priority: Arc<HashSet<u32>>, generation: u64, timing: FrameTiming) {
         if self.clients.is_empty() {
             return;
         }
 
         debug!("Broadcasting {} bytes to {} clients", data.len(), self.clients.len());
-        
-        for (_client_id, addr) in &self.clients {
-            addr.do_send(SendToClientBinary(data.clone()));
+
+        if self.lod.wants_positions() {
+            match binary_protocol::decode_node_data(&data) {
+                Ok(positions) => self.lod.observe(&positions),
+                Err(e) => trace!("Frame not decoded for level of detail: {}", e),
+            }
+        }
+        self.lod.refresh_if_due(Instant::now());
+
+        // Every client gets the same bytes, so they share the one hash
+        let hash = SharedHash::default();
+        let no_lod = Arc::new(HashSet::new());
+        for (client_id, handle) in &self.clients {
+            handle.binary.do_send(SendToClientBinary {
+                data: data.clone(),
+                keyframe,
+                priority: priority.clone(),
+                lod: self.lod.priority(*client_id).unwrap_or_else(|| no_lod.clone()),
+                generation,
+                timing,
+                hash: hash.clone(),
+            });
         }
     }
 
     pub fn broadcast_message(&self, message: String) {
-        if self.clients.is_empty() {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify_broadcast(&message);
+        }
+        if self.clients.is_empty() && self.agents.is_empty() {
             return;
         }
 
-        debug!("Broadcasting message to {} clients", self.clients.len());
-        
-        for (_client_id, addr) in &self.clients {
-            addr.do_send(SendToClientText(message.clone()));
+        debug!("Broadcasting message to {} clients and {} agents", self.clients.len(), self.agents.len());
+
+        for (client_id, handle) in &self.clients {
+            let text = match self.hidden_edge_types.get(client_id) {
+                Some(hidden) => edge_visibility::filter_event(&message, hidden),
+                None => message.clone(),
+            };
+            handle.text.do_send(SendToClientText(text));
+        }
+        // Text broadcasts are graph events, which agents subscribe to
+        for agent in self.agents.values() {
+            agent.text.do_send(SendToClientText(message.clone()));
+        }
+        if !self.node_watches.is_empty() {
+            if let Some((source, changes)) = node_watch::changes_in(&message) {
+                self.notify_node_changes(&source, &changes);
+            }
+        }
+    }
+
+    /// Sends each watcher of the changed nodes a `node_changes` event. Returns how many
+    /// watchers it reached.
+    pub fn notify_node_changes(&self, source: &str, changes: &[(u32, WatchEvent)]) -> usize {
+        if changes.is_empty() {
+            return 0;
+        }
+        let mut sent = 0;
+        for (subscriber, event) in self.node_watches.notifications(source, changes) {
+            let text = self.clients.get(&subscriber).map(|c| &c.text)
+                .or_else(|| self.agents.get(&subscriber).map(|a| &a.text));
+            if let Some(text) = text {
+                text.do_send(SendToClientText(event));
+                sent += 1;
+            }
+        }
+        sent
+    }
+
+    // Who may resume a watch: the client's user, or the agent by name
+    fn watch_owner(&self, subscriber: usize) -> Result<Option<String>, WatchError> {
+        if let Some(identity) = self.client_identities.get(&subscriber) {
+            return Ok(identity.pubkey.clone());
         }
+        self.agents.get(&subscriber).map(|agent| Some(format!("agent:{}", agent.name))).ok_or(WatchError::Unknown)
+    }
+
+    pub fn watch_nodes(&mut self, subscriber: usize, ids: Vec<u32>, events: Vec<WatchEvent>, now: Instant) -> Result<WatchInfo, WatchError> {
+        let owner = self.watch_owner(subscriber)?;
+        let info = self.node_watches.watch(subscriber, owner, ids, events, now)?;
+        debug!("Subscriber {} watches {} nodes", subscriber, info.ids.len());
+        Ok(info)
+    }
+
+    pub fn resume_node_watch(&mut self, subscriber: usize, session: &str, now: Instant) -> Result<WatchInfo, WatchError> {
+        let owner = self.watch_owner(subscriber)?;
+        self.node_watches.resume(session, subscriber, owner.as_deref(), now)
+    }
+
+    pub fn register_agent(&mut self, handle: AgentHandle) -> usize {
+        let agent_id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        debug!("Agent {} ({}) registered", agent_id, handle.name);
+        self.agents.insert(agent_id, handle);
+        agent_id
+    }
+
+    pub fn unregister_agent(&mut self, agent_id: usize) {
+        self.node_watches.release(agent_id, Instant::now());
+        if let Some(agent) = self.agents.remove(&agent_id) {
+            debug!("Agent {} ({}) unregistered", agent_id, agent.name);
+        }
+    }
+
+    /// Sends a `server_shutdown` notice to every session, then asks each socket to
+    /// close. Sessions unregister themselves as their sockets stop.
+    pub fn notify_shutdown(&self, reason: &str) -> usize {
+        let notice = serde_json::json!({
+            "type": "server_shutdown",
+            "reason": reason,
+        }).to_string();
+        self.broadcast_message(notice);
+
+        // Mailboxes are FIFO, so the notice is delivered before the close frame
+        for handle in self.clients.values() {
+            handle.close.do_send(CloseConnection { reason: reason.to_string() });
+        }
+        for agent in self.agents.values() {
+            agent.close.do_send(CloseConnection { reason: reason.to_string() });
+        }
+        info!("Notified {} clients and {} agents of shutdown", self.clients.len(), self.agents.len());
+        self.clients.len() + self.agents.len()
+    }
+
+    pub fn set_client_room(&mut self, client_id: usize, room: String) -> Result<(), String> {
+        if !self.clients.contains_key(&client_id) {
+            return Err(format!("Client {} is not registered", client_id));
+        }
+        debug!("Client {} joined room {}", client_id, room);
+        let previous = self.client_rooms.insert(client_id, room.clone());
+        // The old room's peers would otherwise keep showing the cursor until it times out
+        if let Some(previous) = previous.filter(|previous| *previous != room) {
+            if self.last_pose_relay.remove(&client_id).is_some() {
+                self.announce_pose_left(&previous, client_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn announce_pose_left(&self, room: &str, client_id: usize) {
+        let message = serde_json::json!({
+            "type": "pose_left",
+            "clientId": client_id,
+        });
+        self.broadcast_to_room(room, message.to_string());
+    }
+
+    pub fn broadcast_to_room(&self, room: &str, message: String) -> usize {
+        let mut sent = 0;
+        for (client_id, handle) in &self.clients {
+            if self.client_rooms.get(client_id).map(|r| r.as_str()) == Some(room) {
+                handle.text.do_send(SendToClientText(message.clone()));
+                sent += 1;
+            }
+        }
+        debug!("Broadcast message to {} clients in room {}", sent, room);
+        sent
+    }
+
+    /// Sends a speech caption to every client in the speaker's room. The speaker is matched
+    /// to a graph client by pubkey for their room and presence colour; anyone else speaks
+    /// into the default room. Returns how many clients it reached.
+    pub fn share_caption(&self, caption: &ShareCaption) -> usize {
+        let speaker = caption.pubkey.as_ref().and_then(|pubkey| {
+            self.client_identities.iter()
+                .filter(|(_, identity)| identity.pubkey.as_ref() == Some(pubkey))
+                .map(|(client_id, _)| *client_id)
+                .min()
+        });
+        let room = speaker.and_then(|id| self.client_rooms.get(&id)).map(String::as_str).unwrap_or(DEFAULT_ROOM);
+        if !self.captions.allows_room(room) {
+            trace!("Captions are off in room {}", room);
+            return 0;
+        }
+        let message = captions::caption_event(caption, speaker.map(client_color), self.captions.max_chars);
+        self.broadcast_to_room(room, message)
+    }
+
+    /// The selection of the user's graph client. With several clients signed in as the
+    /// same user, the oldest one that has selected anything wins, as for captions.
+    pub fn selected_node(&self, pubkey: &str) -> Option<u32> {
+        self.client_identities.iter()
+            .filter(|(_, identity)| identity.pubkey.as_deref() == Some(pubkey))
+            .filter_map(|(client_id, _)| self.selected_nodes.get(client_id).map(|node| (*client_id, *node)))
+            .min_by_key(|(client_id, _)| *client_id)
+            .map(|(_, node)| node)
+    }
+
+    /// Relays a pose to the sender's room peers. Returns how many peers received it;
+    /// updates arriving faster than the relay interval are dropped (Ok(0)).
+    pub fn relay_pose(&mut self, client_id: usize, update: &PoseUpdate, now: Instant) -> Result<usize, String> {
+        let room = self.client_rooms.get(&client_id)
+            .ok_or_else(|| format!("Client {} is not registered", client_id))?
+            .clone();
+
+        if let Some(last) = self.last_pose_relay.get(&client_id) {
+            if now.duration_since(*last) < POSE_RELAY_INTERVAL {
+                trace!("Dropping pose update from client {} (rate limited)", client_id);
+                return Ok(0);
+            }
+        }
+        self.last_pose_relay.insert(client_id, now);
+        self.lod.set_viewpoint(client_id, update.head.position.into());
+        if let Some(node_id) = update.gaze_node {
+            self.lod.touch(client_id, node_id, now);
+        }
+
+        let message = serde_json::json!({
+            "type": "pose",
+            "clientId": client_id,
+            "color": client_color(client_id),
+            "head": update.head,
+            "cursor": update.cursor,
+            "gazeNode": update.gaze_node,
+        }).to_string();
+
+        let mut sent = 0;
+        for (peer_id, handle) in &self.clients {
+            if *peer_id != client_id && self.client_rooms.get(peer_id) == Some(&room) {
+                handle.text.do_send(SendToClientText(message.clone()));
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Returns the types that went from hidden to shown
+    pub fn set_edge_type_visibility(&mut self, client_id: usize, types: HashMap<String, bool>) -> Result<Vec<String>, String> {
+        if !self.clients.contains_key(&client_id) {
+            return Err(format!("Client {} is not registered", client_id));
+        }
+        let hidden = self.hidden_edge_types.entry(client_id).or_default();
+        let shown = edge_visibility::apply_changes(hidden, types);
+        if hidden.is_empty() {
+            self.hidden_edge_types.remove(&client_id);
+        }
+        Ok(shown)
+    }
+
+    pub fn record_frame_accounting(&mut self, client_id: usize, totals: &FrameTotals) {
+        if !self.clients.contains_key(&client_id) {
+            return;
+        }
+        self.frame_accounting.entry(client_id).or_default().add(totals);
+    }
+
+    pub fn frame_accounting(&self) -> &HashMap<usize, FrameTotals> {
+        &self.frame_accounting
+    }
+
+    pub fn record_sent_frame(&mut self, client_id: usize, frame: SentFrame) {
+        if !self.clients.contains_key(&client_id) {
+            return;
+        }
+        self.sent_frames.entry(client_id).or_default().record(frame);
+    }
+
+    /// The frames logged for a connected client; empty if it never asked for hashes
+    pub fn sent_frames(&self, client_id: usize) -> Option<Vec<SentFrame>> {
+        self.clients.contains_key(&client_id)
+            .then(|| self.sent_frames.get(&client_id).map(SentFrameLog::frames).unwrap_or_default())
+    }
+
+    pub fn set_lod_importance(&mut self, importance: &HashMap<u32, f32>) {
+        self.lod.set_importance(importance);
+    }
+
+    /// Each client's current level-of-detail top nodes
+    pub fn client_lod(&self) -> HashMap<usize, Vec<u32>> {
+        self.lod.top_nodes()
     }
 
     pub fn get_client_count(&self) -> usize {
         self.clients.len()
     }
+
+    pub fn client_counts_by_room(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for client_id in self.clients.keys() {
+            let room = self.client_rooms.get(client_id).map(String::as_str).unwrap_or(DEFAULT_ROOM);
+            *counts.entry(room.to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
 }
 
 impl Actor for ClientManagerActor {
@@ -77,11 +516,45 @@ impl Actor for ClientManagerActor {
     }
 }
 
+#[cfg(feature = "websocket")]
 impl Handler<RegisterClient> for ClientManagerActor {
     type Result = Result<usize, String>;
 
     fn handle(&mut self, msg: RegisterClient, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.register_client(msg.addr))
+        Ok(self.register_client(msg.addr.into(), msg.identity))
+    }
+}
+
+#[cfg(any(test, feature = "loadtest"))]
+impl Handler<RegisterSimulatedClient> for ClientManagerActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: RegisterSimulatedClient, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.register_client(msg.handle, msg.identity))
+    }
+}
+
+impl Handler<GetClientIdentity> for ClientManagerActor {
+    type Result = Option<Identity>;
+
+    fn handle(&mut self, msg: GetClientIdentity, _ctx: &mut Self::Context) -> Self::Result {
+        self.client_identity(msg.client_id).cloned()
+    }
+}
+
+impl Handler<SetEdgeTypeVisibility> for ClientManagerActor {
+    type Result = Result<Vec<String>, String>;
+
+    fn handle(&mut self, msg: SetEdgeTypeVisibility, _ctx: &mut Self::Context) -> Self::Result {
+        self.set_edge_type_visibility(msg.client_id, msg.types)
+    }
+}
+
+impl Handler<GetHiddenEdgeTypes> for ClientManagerActor {
+    type Result = MessageResult<GetHiddenEdgeTypes>;
+
+    fn handle(&mut self, msg: GetHiddenEdgeTypes, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.hidden_edge_types.get(&msg.client_id).cloned().unwrap_or_default())
     }
 }
 
@@ -98,7 +571,24 @@ impl Handler<BroadcastNodePositions> for ClientManagerActor {
     type Result = Result<(), String>;
 
     fn handle(&mut self, msg: BroadcastNodePositions, _ctx: &mut Self::Context) -> Self::Result {
-        self.broadcast_to_all(msg.positions);
+        self.broadcast_to_all(msg.positions, msg.keyframe, msg.priority, msg.generation, msg.timing);
+        Ok(())
+    }
+}
+
+impl Handler<RegisterAgent> for ClientManagerActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: RegisterAgent, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.register_agent(AgentHandle { name: msg.name, text: msg.text, close: msg.close }))
+    }
+}
+
+impl Handler<UnregisterAgent> for ClientManagerActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: UnregisterAgent, _ctx: &mut Self::Context) -> Self::Result {
+        self.unregister_agent(msg.agent_id);
         Ok(())
     }
 }
@@ -112,10 +602,503 @@ impl Handler<BroadcastMessage> for ClientManagerActor {
     }
 }
 
+impl Handler<WatchNodes> for ClientManagerActor {
+    type Result = Result<WatchInfo, WatchError>;
+
+    fn handle(&mut self, msg: WatchNodes, _ctx: &mut Self::Context) -> Self::Result {
+        match msg.session {
+            Some(session) => self.resume_node_watch(msg.subscriber, &session, Instant::now()),
+            None => self.watch_nodes(msg.subscriber, msg.ids, msg.events, Instant::now()),
+        }
+    }
+}
+
+impl Handler<NotifyNodeChanges> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyNodeChanges, _ctx: &mut Self::Context) -> Self::Result {
+        self.notify_node_changes(&msg.source, &msg.changes);
+    }
+}
+
+impl Handler<NotifyShutdown> for ClientManagerActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: NotifyShutdown, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.notify_shutdown(&msg.reason))
+    }
+}
+
 impl Handler<GetClientCount> for ClientManagerActor {
     type Result = Result<usize, String>;
 
     fn handle(&mut self, _msg: GetClientCount, _ctx: &mut Self::Context) -> Self::Result {
         Ok(self.get_client_count())
     }
-}
\ No newline at end of file
+}
+
+impl Handler<GetRoomClientCounts> for ClientManagerActor {
+    type Result = MessageResult<GetRoomClientCounts>;
+
+    fn handle(&mut self, _msg: GetRoomClientCounts, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.client_counts_by_room())
+    }
+}
+
+impl Handler<RecordFrameAccounting> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordFrameAccounting, _ctx: &mut Self::Context) -> Self::Result {
+        self.record_frame_accounting(msg.client_id, &msg.totals);
+    }
+}
+
+impl Handler<GetFrameAccounting> for ClientManagerActor {
+    type Result = MessageResult<GetFrameAccounting>;
+
+    fn handle(&mut self, _msg: GetFrameAccounting, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.frame_accounting().clone())
+    }
+}
+
+impl Handler<RecordSentFrame> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordSentFrame, _ctx: &mut Self::Context) -> Self::Result {
+        self.record_sent_frame(msg.client_id, msg.frame);
+    }
+}
+
+impl Handler<GetSentFrames> for ClientManagerActor {
+    type Result = MessageResult<GetSentFrames>;
+
+    fn handle(&mut self, msg: GetSentFrames, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.sent_frames(msg.client_id))
+    }
+}
+
+impl Handler<SetClientRoom> for ClientManagerActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetClientRoom, _ctx: &mut Self::Context) -> Self::Result {
+        self.set_client_room(msg.client_id, msg.room)
+    }
+}
+
+impl Handler<BroadcastToRoom> for ClientManagerActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: BroadcastToRoom, _ctx: &mut Self::Context) -> Self::Result {
+        self.broadcast_to_room(&msg.room, msg.message);
+        Ok(())
+    }
+}
+
+impl Handler<SendToClient> for ClientManagerActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SendToClient, _ctx: &mut Self::Context) -> Self::Result {
+        let handle = self.clients.get(&msg.client_id).ok_or_else(|| format!("Client {} is not connected", msg.client_id))?;
+        handle.text.do_send(SendToClientText(msg.message));
+        Ok(())
+    }
+}
+
+impl Handler<ShareCaption> for ClientManagerActor {
+    type Result = usize;
+
+    fn handle(&mut self, msg: ShareCaption, _ctx: &mut Self::Context) -> Self::Result {
+        self.share_caption(&msg)
+    }
+}
+
+impl Handler<RelayPose> for ClientManagerActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: RelayPose, _ctx: &mut Self::Context) -> Self::Result {
+        self.relay_pose(msg.client_id, &msg.update, Instant::now())
+    }
+}
+
+impl Handler<RecordSelection> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordSelection, _ctx: &mut Self::Context) -> Self::Result {
+        if self.clients.contains_key(&msg.client_id) {
+            self.selected_nodes.insert(msg.client_id, msg.node_id);
+            self.lod.touch(msg.client_id, msg.node_id, Instant::now());
+        }
+        if let Some(selections) = self.selections.as_mut().filter(|_| self.clients.contains_key(&msg.client_id)) {
+            if let Some(metadata_id) = msg.metadata_id {
+                selections.record(msg.client_id, metadata_id, Instant::now());
+            }
+        }
+    }
+}
+
+impl Handler<SetLodImportance> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetLodImportance, _ctx: &mut Self::Context) -> Self::Result {
+        self.set_lod_importance(&msg.importance);
+    }
+}
+
+impl Handler<GetClientLod> for ClientManagerActor {
+    type Result = MessageResult<GetClientLod>;
+
+    fn handle(&mut self, _msg: GetClientLod, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.client_lod())
+    }
+}
+
+impl Handler<GetSelectedNode> for ClientManagerActor {
+    type Result = Option<u32>;
+
+    fn handle(&mut self, msg: GetSelectedNode, _ctx: &mut Self::Context) -> Self::Result {
+        self.selected_node(&msg.pubkey)
+    }
+}
+
+impl Handler<TakeFinishedSelections> for ClientManagerActor {
+    type Result = MessageResult<TakeFinishedSelections>;
+
+    fn handle(&mut self, _msg: TakeFinishedSelections, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.selections.as_mut().map(|s| s.take_finished(Instant::now())).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::feature_access::Role;
+    use crate::utils::socket_flow_messages::Pose;
+    use std::sync::{Arc, Mutex};
+
+    // Stand-in for a websocket client that records every text frame it is sent
+    struct RecordingClient {
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Actor for RecordingClient {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(msg.0);
+        }
+    }
+
+    impl Handler<SendToClientBinary> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, _msg: SendToClientBinary, _ctx: &mut Self::Context) {}
+    }
+
+    impl Handler<CloseConnection> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, msg: CloseConnection, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(format!("close:{}", msg.reason));
+        }
+    }
+
+    fn spawn_client() -> (ClientHandle, Arc<Mutex<Vec<String>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let addr = RecordingClient { received: received.clone() }.start();
+        let handle = ClientHandle {
+            text: addr.clone().recipient(),
+            binary: addr.clone().recipient(),
+            close: addr.recipient(),
+        };
+        (handle, received)
+    }
+
+    fn pose_update() -> PoseUpdate {
+        PoseUpdate {
+            head: Pose { position: [0.0, 1.6, 0.0], orientation: [0.0, 0.0, 0.0, 1.0] },
+            cursor: None,
+            gaze_node: Some(7),
+        }
+    }
+
+    fn pose_messages(received: &Arc<Mutex<Vec<String>>>) -> Vec<serde_json::Value> {
+        received.lock().unwrap().iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+            .filter(|v| v["type"] == "pose")
+            .collect()
+    }
+
+    #[actix::test]
+    async fn test_pose_relay_fan_out_and_rate_limit() {
+        let mut manager = ClientManagerActor::new();
+        let (a, a_rx) = spawn_client();
+        let (b, b_rx) = spawn_client();
+        let (c, c_rx) = spawn_client();
+        let viewer = Identity { pubkey: None, role: Role::Viewer };
+        let a_id = manager.register_client(a, viewer.clone());
+        let _b_id = manager.register_client(b, viewer.clone());
+        let c_id = manager.register_client(c, viewer);
+        manager.set_client_room(c_id, "other-room".to_string()).unwrap();
+
+        let start = Instant::now();
+        // Only the peer in the same room receives it, never the sender
+        assert_eq!(manager.relay_pose(a_id, &pose_update(), start).unwrap(), 1);
+        // A second update inside the 15 Hz window is dropped
+        assert_eq!(manager.relay_pose(a_id, &pose_update(), start + Duration::from_millis(10)).unwrap(), 0);
+        // After the interval it is relayed again
+        assert_eq!(manager.relay_pose(a_id, &pose_update(), start + POSE_RELAY_INTERVAL).unwrap(), 1);
+
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let b_poses = pose_messages(&b_rx);
+        assert_eq!(b_poses.len(), 2);
+        assert_eq!(b_poses[0]["clientId"], a_id);
+        assert_eq!(b_poses[0]["color"], client_color(a_id));
+        assert_eq!(b_poses[0]["gazeNode"], 7);
+        assert!(pose_messages(&a_rx).is_empty());
+        assert!(pose_messages(&c_rx).is_empty());
+
+        // Disconnect stops relaying immediately and notifies peers
+        // Viewers still take part in presence
+        assert_eq!(manager.client_identity(a_id).unwrap().role, Role::Viewer);
+        manager.unregister_client(a_id);
+        assert!(manager.client_identity(a_id).is_none());
+        assert!(manager.relay_pose(a_id, &pose_update(), start + Duration::from_secs(1)).is_err());
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        let left: Vec<String> = b_rx.lock().unwrap().iter()
+            .filter(|m| m.contains("pose_left"))
+            .cloned()
+            .collect();
+        assert_eq!(left.len(), 1);
+    }
+
+    #[test]
+    fn test_pose_update_reads_the_spec_payload() {
+        let head = serde_json::json!({ "position": [0.0, 1.6, 0.0], "orientation": [0.0, 0.0, 0.0, 1.0] });
+        let spec: PoseUpdate = serde_json::from_value(serde_json::json!({ "type": "pose", "head": head, "gaze_node": 7 })).unwrap();
+        assert_eq!(spec.gaze_node, Some(7));
+        assert_eq!(spec.head, pose_update().head);
+        assert!(spec.cursor.is_none());
+        let camel: PoseUpdate = serde_json::from_value(serde_json::json!({ "head": head, "gazeNode": 7 })).unwrap();
+        assert_eq!(camel.gaze_node, Some(7));
+    }
+
+    #[actix::test]
+    async fn test_switching_rooms_drops_the_cursor_from_the_old_room() {
+        let mut manager = ClientManagerActor::new();
+        let (a, _a_rx) = spawn_client();
+        let (b, b_rx) = spawn_client();
+        let (c, c_rx) = spawn_client();
+        let viewer = Identity { pubkey: None, role: Role::Viewer };
+        let a_id = manager.register_client(a, viewer.clone());
+        let _b_id = manager.register_client(b, viewer.clone());
+        let c_id = manager.register_client(c, viewer);
+        manager.set_client_room(c_id, "lab".to_string()).unwrap();
+
+        assert_eq!(manager.relay_pose(a_id, &pose_update(), Instant::now()).unwrap(), 1);
+        manager.set_client_room(a_id, "lab".to_string()).unwrap();
+        // Rejoining the same room is not a switch
+        manager.set_client_room(a_id, "lab".to_string()).unwrap();
+        // The new room sees the next pose straight away
+        assert_eq!(manager.relay_pose(a_id, &pose_update(), Instant::now()).unwrap(), 1);
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let left = |received: &Arc<Mutex<Vec<String>>>| received.lock().unwrap().iter()
+            .filter(|m| m.contains("pose_left"))
+            .count();
+        assert_eq!(left(&b_rx), 1);
+        assert_eq!(left(&c_rx), 0);
+        assert_eq!(pose_messages(&c_rx).len(), 1);
+    }
+
+    #[actix::test]
+    async fn test_hidden_edge_types_are_per_client() {
+        let mut manager = ClientManagerActor::new();
+        let (a, a_rx) = spawn_client();
+        let (b, b_rx) = spawn_client();
+        let viewer = Identity { pubkey: None, role: Role::Viewer };
+        let a_id = manager.register_client(a, viewer.clone());
+        let _b_id = manager.register_client(b, viewer);
+
+        let hide = HashMap::from([("similarity".to_string(), false)]);
+        assert!(manager.set_edge_type_visibility(a_id, hide).unwrap().is_empty());
+        let event = serde_json::json!({
+            "type": "graph_diff",
+            "addedEdges": [
+                { "id": "1-2", "source": 1, "target": 2, "weight": 1.0 },
+                { "id": "2-3", "source": 2, "target": 3, "weight": 0.9, "edgeType": "similarity" },
+            ],
+        }).to_string();
+        manager.broadcast_message(event);
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let edges = |rx: &Arc<Mutex<Vec<String>>>| -> usize {
+            let received = rx.lock().unwrap();
+            let event: serde_json::Value = serde_json::from_str(received.last().unwrap()).unwrap();
+            event["addedEdges"].as_array().unwrap().len()
+        };
+        assert_eq!(edges(&a_rx), 1);
+        assert_eq!(edges(&b_rx), 2);
+
+        let show = HashMap::from([("similarity".to_string(), true)]);
+        assert_eq!(manager.set_edge_type_visibility(a_id, show).unwrap(), vec!["similarity"]);
+        assert!(manager.set_edge_type_visibility(999, HashMap::new()).is_err());
+    }
+
+    #[actix::test]
+    async fn test_node_watchers_only_hear_of_their_own_nodes() {
+        let mut manager = ClientManagerActor::new();
+        let (client, client_rx) = spawn_client();
+        let (agent, agent_rx) = spawn_client();
+        let client_id = manager.register_client(client, Identity { pubkey: Some("alice".to_string()), role: Role::Viewer });
+        let agent_id = manager.register_agent(AgentHandle { name: "docs-bot".to_string(), text: agent.text, close: agent.close });
+        let now = Instant::now();
+        manager.watch_nodes(client_id, vec![1, 2], vec![], now).unwrap();
+        let agent_watch = manager.watch_nodes(agent_id, vec![7, 8], vec![WatchEvent::Metadata, WatchEvent::Annotations], now).unwrap();
+
+        // A mixed batch touching both sets, neither, and edges the agent doesn't watch for
+        let batch = [
+            serde_json::json!({ "type": "graph_diff", "addedEdges": [{ "id": "2-7", "source": 2, "target": 7, "weight": 1.0 }], "removedEdges": ["9-1"] }),
+            serde_json::json!({ "type": "node_metadata_update", "reason": "aging", "nodes": [{ "nodeId": 8 }, { "nodeId": 5 }] }),
+            serde_json::json!({ "type": "annotation_created", "nodeId": 1 }),
+            serde_json::json!({ "type": "annotation_deleted", "nodeId": 7 }),
+            serde_json::json!({ "type": "node_attribute_diff", "nodeIds": [3, 4] }),
+            serde_json::json!({ "type": "pose", "clientId": 9 }),
+        ];
+        for event in batch {
+            manager.broadcast_message(event.to_string());
+        }
+        assert_eq!(manager.notify_node_changes("metadata", &[(2, WatchEvent::Metadata)]), 1);
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let changes = |rx: &Arc<Mutex<Vec<String>>>| -> Vec<(String, serde_json::Value)> {
+            rx.lock().unwrap().iter()
+                .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+                .filter(|v| v["type"] == "node_changes")
+                .map(|v| (v["source"].as_str().unwrap().to_string(), v["nodes"].clone()))
+                .collect()
+        };
+        assert_eq!(changes(&client_rx), vec![
+            ("graph_diff".to_string(), serde_json::json!([{ "nodeId": 1, "events": ["edges"] }, { "nodeId": 2, "events": ["edges"] }])),
+            ("annotation_created".to_string(), serde_json::json!([{ "nodeId": 1, "events": ["annotations"] }])),
+            ("metadata".to_string(), serde_json::json!([{ "nodeId": 2, "events": ["metadata"] }])),
+        ]);
+        assert_eq!(changes(&agent_rx), vec![
+            ("node_metadata_update".to_string(), serde_json::json!([{ "nodeId": 8, "events": ["metadata"] }])),
+            ("annotation_deleted".to_string(), serde_json::json!([{ "nodeId": 7, "events": ["annotations"] }])),
+        ]);
+
+        // The agent reconnects under a new id and picks its watch back up
+        manager.unregister_agent(agent_id);
+        let (agent, agent_rx) = spawn_client();
+        let agent_id = manager.register_agent(AgentHandle { name: "docs-bot".to_string(), text: agent.text, close: agent.close });
+        assert_eq!(manager.resume_node_watch(agent_id, &agent_watch.session, Instant::now()), Ok(agent_watch));
+        manager.broadcast_message(serde_json::json!({ "type": "annotation_created", "nodeId": 8 }).to_string());
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        assert_eq!(changes(&agent_rx).len(), 1);
+        assert_eq!(manager.watch_nodes(999, vec![1], vec![], Instant::now()), Err(WatchError::Unknown));
+    }
+
+    #[actix::test]
+    async fn test_sent_frames_are_kept_per_client() {
+        use crate::utils::frame_hash::{self, SENT_FRAME_HISTORY};
+
+        let mut manager = ClientManagerActor::new();
+        let viewer = Identity { pubkey: None, role: Role::Viewer };
+        let a_id = manager.register_client(spawn_client().0, viewer.clone());
+        let b_id = manager.register_client(spawn_client().0, viewer);
+        let frame = |seq: u64, data: &[u8]| SentFrame {
+            seq, hash: frame_hash::hash(data), bytes: data.len() + 4, nodes: data.len() / 28, keyframe: seq == 0, server_time_us: seq * 16_000,
+        };
+        for seq in 0..(SENT_FRAME_HISTORY as u64 + 5) {
+            manager.record_sent_frame(a_id, frame(seq, &[seq as u8; 56]));
+        }
+        manager.record_sent_frame(b_id, frame(0, &[9; 28]));
+        manager.record_sent_frame(999, frame(0, &[9; 28]));
+
+        let a_frames = manager.sent_frames(a_id).unwrap();
+        assert_eq!(a_frames.len(), SENT_FRAME_HISTORY);
+        assert_eq!(a_frames[0].seq, 5);
+        assert_eq!(a_frames.last().unwrap().hash, frame_hash::hash(&[(SENT_FRAME_HISTORY as u64 + 4) as u8; 56]));
+        assert_eq!(manager.sent_frames(b_id).unwrap(), vec![frame(0, &[9; 28])]);
+        assert_eq!(manager.sent_frames(999), None);
+
+        manager.unregister_client(a_id);
+        assert_eq!(manager.sent_frames(a_id), None);
+    }
+
+    #[actix::test]
+    async fn test_captions_fan_out_to_the_speakers_room() {
+        use crate::config::SpeechSessionSettings;
+        use crate::services::speech_session_service::{SessionOptions, SpeechSessionService};
+        use crate::types::speech::TranscriptionSegment;
+
+        let settings = CaptionSettings { max_chars: 12, disabled_rooms: vec!["quiet".to_string()], ..Default::default() };
+        let mut manager = ClientManagerActor::new().with_captions(settings);
+        let (a, a_rx) = spawn_client();
+        let (b, b_rx) = spawn_client();
+        let speaker = Identity { pubkey: Some("a1b2c3d4e5f6".to_string()), role: Role::Editor };
+        let a_id = manager.register_client(a, speaker);
+        let b_id = manager.register_client(b, Identity { pubkey: None, role: Role::Viewer });
+        manager.set_client_room(a_id, "lab".to_string()).unwrap();
+        manager.set_client_room(b_id, "lab".to_string()).unwrap();
+
+        // The speaker's speech socket opens a session that shares captions
+        let sessions = SpeechSessionService::new(SpeechSessionSettings::default());
+        let options = SessionOptions { share_captions: true, display_name: Some("Ada".to_string()), ..Default::default() };
+        let session = sessions.create("speech-1", Some("a1b2c3d4e5f6".to_string()), options, Instant::now()).unwrap();
+        let options = sessions.options(&session, "speech-1", Instant::now()).unwrap();
+        let segment = TranscriptionSegment { text: "Look at the quantum cluster".to_string(), language: Some("en".to_string()), detected: false, is_final: true };
+        let caption = captions::caption_for(&options, Some("a1b2c3d4e5f6".to_string()), &segment).unwrap();
+        assert_eq!(manager.share_caption(&caption), 2);
+
+        // Opted out: nothing to share
+        assert!(captions::caption_for(&SessionOptions::default(), Some("a1b2c3d4e5f6".to_string()), &segment).is_none());
+        // A room with captions switched off gets nothing, and neither does any room once
+        // captions are off altogether
+        manager.set_client_room(a_id, "quiet".to_string()).unwrap();
+        assert_eq!(manager.share_caption(&caption), 0);
+        manager.set_client_room(a_id, "lab".to_string()).unwrap();
+        manager.captions.enabled = false;
+        assert_eq!(manager.share_caption(&caption), 0);
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let captions = |rx: &Arc<Mutex<Vec<String>>>| -> Vec<serde_json::Value> {
+            rx.lock().unwrap().iter()
+                .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+                .filter(|v| v["type"] == "caption")
+                .collect()
+        };
+        for rx in [&a_rx, &b_rx] {
+            let received = captions(rx);
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0]["speaker"], "Ada");
+            assert_eq!(received[0]["color"], client_color(a_id));
+            assert_eq!(received[0]["text"], "Look at the…");
+            assert_eq!(received[0]["isFinal"], true);
+        }
+    }
+
+    #[actix::test]
+    async fn test_idle_session_is_counted_once_by_metadata_id() {
+        let settings = CoViewSettings { enabled: true, session_idle_secs: 1, ..Default::default() };
+        let mut manager = ClientManagerActor::new().with_co_view(&settings);
+        let (client, _rx) = spawn_client();
+        let client_id = manager.register_client(client, Identity { pubkey: None, role: Role::Viewer });
+        let manager = manager.start();
+
+        // "a" comes back under a new node id after a rebuild; it is still the one node
+        for (node_id, metadata_id) in [(1, "a"), (2, "b"), (5, "a")] {
+            manager.send(RecordSelection { client_id, node_id, metadata_id: Some(metadata_id.to_string()) }).await.unwrap();
+        }
+        manager.send(RecordSelection { client_id, node_id: 9, metadata_id: None }).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(1100)).await;
+
+        // Still connected, but idle: the session ends and is counted
+        let mut finished = manager.send(TakeFinishedSelections).await.unwrap();
+        finished[0].sort();
+        assert_eq!(finished, vec![vec!["a".to_string(), "b".to_string()]]);
+        assert!(manager.send(TakeFinishedSelections).await.unwrap().is_empty());
+    }
+}
@@ -9,10 +9,15 @@ use cudarc::nvrtc::Ptx;
 use cudarc::driver::sys::CUdevice_attribute_enum;
 
 use crate::models::graph::GraphData;
-use crate::models::simulation_params::SimulationParams;
+use crate::models::simulation_params::{PhaseProfile, PhaseProfiles, SimulationParams, SimulationPhase};
 use crate::utils::socket_flow_messages::BinaryNodeData;
+use crate::utils::degree_repulsion::{repulsion_scales, scaled_mass};
 use crate::types::vec3::Vec3Data;
 use crate::actors::messages::*;
+use crate::services::event_log::EventLog;
+use crate::utils::gpu_compute::time_on_device;
+use crate::utils::gpu_reconnect::{self, FaultInjector, GpuError, GpuReconnect};
+use crate::utils::gpu_timing::{self, GpuTimings};
 use std::path::Path;
 use std::env;
 use std::sync::Arc;
@@ -41,11 +46,29 @@ pub struct GPUComputeActor {
     node_data: Option<CudaSlice<BinaryNodeData>>,
     num_nodes: u32,
     node_indices: HashMap<u32, usize>,
+    // Graph generation node_indices was built for; positions are copied on every upload
+    indexed_generation: Option<u64>,
+    // Per-node degree repulsion scales, rebuilt with node_indices
+    repulsion_scales: Vec<f32>,
     simulation_params: SimulationParams,
+    // The kernel's per-phase parameter table, and what was last uploaded to it
+    phase_profiles: Option<CudaSlice<PhaseProfile>>,
+    uploaded_profiles: Option<PhaseProfiles>,
     iteration_count: u32,
     gpu_failure_count: u32,
     last_failure_reset: Instant,
     cpu_fallback_active: bool,
+    // Per-stage step timings, collected only while switched on
+    timing: GpuTimings,
+    // GPU failures are recorded here so operators see them as they happen
+    event_log: Option<Arc<EventLog>>,
+    // The graph last sent for upload; a rebuild falls back to it if the source can't answer
+    last_graph: Option<GraphData>,
+    // The graph actor, asked for current positions when a lost context is rebuilt
+    graph_source: Option<Recipient<GetPhysicsGraph>>,
+    reconnect: GpuReconnect,
+    // Errors queued here are returned by the next kernel launch in place of running it
+    faults: FaultInjector,
 }
 
 // Struct to hold the results of GPU initialization
@@ -65,14 +88,48 @@ impl GPUComputeActor {
             node_data: None,
             num_nodes: 0,
             node_indices: HashMap::new(),
+            indexed_generation: None,
+            repulsion_scales: Vec::new(),
             simulation_params: SimulationParams::default(),
+            phase_profiles: None,
+            uploaded_profiles: None,
             iteration_count: 0,
             gpu_failure_count: 0,
             last_failure_reset: Instant::now(),
             cpu_fallback_active: false,
+            timing: GpuTimings::default(),
+            event_log: None,
+            last_graph: None,
+            graph_source: None,
+            reconnect: GpuReconnect::default(),
+            faults: FaultInjector::default(),
         }
     }
 
+    pub fn with_event_log(mut self, event_log: Arc<EventLog>) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    fn record_event(&self, kind: &str, detail: serde_json::Value) {
+        if let Some(event_log) = &self.event_log {
+            event_log.record("gpu", kind, detail);
+        }
+    }
+
+    fn record_failure(&self, error_msg: &str) {
+        self.record_event("gpu_failure", serde_json::json!({
+            "error": error_msg,
+            "failureCount": self.gpu_failure_count,
+            "cpuFallback": self.cpu_fallback_active,
+        }));
+    }
+
     // --- Static GPU Initialization Logic ---
 
     async fn static_test_gpu_capabilities() -> Result<(), Error> {
@@ -182,48 +239,197 @@ impl GPUComputeActor {
         })
     }
 
+    /// The check a rebuilt instance must pass before physics moves back onto it
+    fn test_compute(init: &GpuInitializationResult) -> Result<(), GpuError> {
+        init.device.synchronize().map_err(GpuError::from)?;
+        let readback = init.device.dtoh_sync_copy(&init.node_data).map_err(GpuError::from)?;
+        if readback.len() != init.num_nodes as usize {
+            return Err(GpuError::Failed(format!("Read back {} of {} nodes", readback.len(), init.num_nodes)));
+        }
+        Ok(())
+    }
+
     // --- Instance Methods ---
 
-    fn update_graph_data_internal(&mut self, graph: &GraphData) -> Result<(), Error> {
-        let device = self.device.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Device not initialized"))?;
-        let node_data_slice = self.node_data.as_mut().ok_or_else(|| Error::new(ErrorKind::Other, "Node data not initialized"))?;
+    fn install(&mut self, init: GpuInitializationResult) {
+        self.device = Some(init.device);
+        self.force_kernel = Some(init.force_kernel);
+        self.node_data = Some(init.node_data);
+        self.num_nodes = init.num_nodes;
+        self.node_indices = init.node_indices;
+        self.indexed_generation = None;
+        self.phase_profiles = None;
+        self.uploaded_profiles = None;
+
+        // Reset other relevant state
+        self.iteration_count = 0;
+        self.gpu_failure_count = 0;
+        self.last_failure_reset = Instant::now();
+        self.cpu_fallback_active = false;
+    }
+
+    fn teardown(&mut self) {
+        self.device = None;
+        self.force_kernel = None;
+        self.node_data = None;
+        self.num_nodes = 0;
+        self.node_indices.clear();
+        self.indexed_generation = None;
+        self.phase_profiles = None;
+        self.uploaded_profiles = None;
+        self.cpu_fallback_active = true;
+    }
+
+    /// Drops a dead context and starts rebuilding in the background; physics stays on
+    /// the CPU until a new instance passes its test
+    fn context_lost(&mut self, error: GpuError, ctx: &mut Context<Self>) {
+        if !self.reconnect.context_lost(&error, Instant::now()) {
+            return;
+        }
+        error!("GPU context lost, running physics on the CPU while reconnecting: {}", error);
+        self.teardown();
+        self.record_event("gpu_context_lost", serde_json::json!({ "error": error.to_string() }));
+        self.attempt_reconnect(ctx);
+    }
+
+    fn attempt_reconnect(&mut self, ctx: &mut Context<Self>) {
+        let attempt = self.reconnect.begin_attempt();
+        // Physics kept moving on the CPU since the last upload; start from where it is now
+        let source = self.graph_source.clone();
+        let fetch = async move {
+            match source {
+                Some(source) => source.send(GetPhysicsGraph).await.map_err(|e| e.to_string())?.map(Some),
+                None => Ok(None),
+            }
+        };
+        ctx.spawn(fetch.into_actor(self).then(move |fetched, actor, _ctx| {
+            match fetched {
+                Ok(Some(graph)) => actor.last_graph = Some(graph),
+                Ok(None) => {}
+                Err(e) => warn!("GPU reconnect attempt {} couldn't fetch the current graph, using the last upload: {}", attempt, e),
+            }
+            let graph = actor.last_graph.clone().unwrap_or_default();
+            info!("GPU reconnect attempt {} with {} nodes", attempt, graph.nodes.len());
+            let injected = actor.faults.check();
+            let create = async move {
+                injected?;
+                Self::perform_gpu_initialization(graph).await.map_err(GpuError::from)
+            };
+            gpu_reconnect::recreate(create, Self::test_compute).into_actor(actor)
+        }).map(move |result, actor, ctx| {
+            let result = result.and_then(|init| {
+                actor.install(init);
+                // Positions sent while the attempt ran are newer than the ones it uploaded
+                let reuploaded = actor.reupload_last_graph();
+                if reuploaded.is_err() {
+                    actor.teardown();
+                }
+                reuploaded
+            });
+            match result {
+                Ok(()) => {
+                    let downtime = actor.reconnect.recovered(Instant::now());
+                    info!("GPU reconnected on attempt {} after {}ms on the CPU", attempt, downtime.as_millis());
+                    actor.record_event("gpu_reconnected", serde_json::json!({
+                        "attempt": attempt,
+                        "downtimeMs": downtime.as_millis() as u64,
+                    }));
+                }
+                Err(e) => {
+                    let retry_in = actor.reconnect.attempt_failed(&e);
+                    warn!("GPU reconnect attempt {} failed, retrying in {}s: {}", attempt, retry_in.as_secs(), e);
+                    actor.record_event("gpu_reconnect_failed", serde_json::json!({
+                        "attempt": attempt,
+                        "error": e.to_string(),
+                        "retryInMs": retry_in.as_millis() as u64,
+                    }));
+                    ctx.run_later(retry_in, |actor, ctx| actor.attempt_reconnect(ctx));
+                }
+            }
+        }));
+    }
+
+    fn reupload_last_graph(&mut self) -> Result<(), GpuError> {
+        let Some(graph) = self.last_graph.take() else {
+            return Ok(());
+        };
+        let uploaded = self.update_graph_data_internal(&graph);
+        self.last_graph = Some(graph);
+        uploaded
+    }
+
+    fn update_graph_data_internal(&mut self, graph: &GraphData) -> Result<(), GpuError> {
+        self.faults.check()?;
+        let device = self.device.as_ref().ok_or_else(|| GpuError::Failed("Device not initialized".into()))?;
+        let node_data_slice = self.node_data.as_mut().ok_or_else(|| GpuError::Failed("Node data not initialized".into()))?;
  
         trace!("Updating graph data for {} nodes", graph.nodes.len());
         
-        self.node_indices.clear();
-        for (idx, node) in graph.nodes.iter().enumerate() {
-            self.node_indices.insert(node.id, idx);
+        if self.indexed_generation != Some(graph.generation) || self.node_indices.len() != graph.nodes.len() {
+            self.node_indices.clear();
+            for (idx, node) in graph.nodes.iter().enumerate() {
+                self.node_indices.insert(node.id, idx);
+            }
+            self.repulsion_scales = repulsion_scales(graph, self.simulation_params.degree_repulsion_factor);
+            self.indexed_generation = Some(graph.generation);
         }
 
         if graph.nodes.len() as u32 != self.num_nodes {
             info!("Reallocating GPU buffer for {} nodes", graph.nodes.len());
             *node_data_slice = device.alloc_zeros::<BinaryNodeData>(graph.nodes.len())
-                .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+                .map_err(GpuError::from)?;
             self.num_nodes = graph.nodes.len() as u32;
             self.iteration_count = 0; // Reset iteration count on realloc
         }
 
         let mut host_node_data = Vec::with_capacity(graph.nodes.len());
-        for node in &graph.nodes {
+        // The kernel has no per-node repulsion input; degree scaling rides on the mass byte
+        for (node, scale) in graph.nodes.iter().zip(&self.repulsion_scales) {
             host_node_data.push(BinaryNodeData {
                 position: node.data.position.clone(),
                 velocity: node.data.velocity.clone(),
-                mass: node.data.mass,
+                mass: scaled_mass(node.data.mass, *scale),
                 flags: node.data.flags,
                 padding: node.data.padding,
             });
         }
 
+        let started = Instant::now();
         device.htod_sync_copy_into(&host_node_data, node_data_slice)
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy node data to GPU: {}", e)))?;
+            .map_err(|e| GpuError::from(e).context("Failed to copy node data to GPU"))?;
+        self.timing.record_upload(gpu_timing::millis_since(started));
         
         Ok(())
     }
 
-    fn compute_forces_internal(&mut self) -> Result<(), Error> {
-        let device = self.device.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Device not initialized"))?;
-        let force_kernel = self.force_kernel.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Kernel not initialized"))?;
-        let node_data = self.node_data.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Node data not initialized"))?;
+    /// Uploads the phase profile table if a profile changed since the last upload;
+    /// switching phase alone never re-uploads
+    fn sync_phase_profiles(&mut self) -> Result<(), GpuError> {
+        let device = self.device.as_ref().ok_or_else(|| GpuError::Failed("Device not initialized".into()))?;
+        let profiles = self.simulation_params.profiles();
+        if self.phase_profiles.is_some() && self.uploaded_profiles == Some(profiles) {
+            return Ok(());
+        }
+        if self.phase_profiles.is_none() {
+            self.phase_profiles = Some(device.alloc_zeros::<PhaseProfile>(SimulationPhase::ALL.len())
+                .map_err(GpuError::from)?);
+        }
+        let table = self.phase_profiles.as_mut().ok_or_else(|| GpuError::Failed("Phase profiles not allocated".into()))?;
+        trace!("Uploading phase profiles: {:?}", profiles);
+        device.htod_sync_copy_into(&profiles, table)
+            .map_err(|e| GpuError::from(e).context("Failed to copy phase profiles to GPU"))?;
+        self.uploaded_profiles = Some(profiles);
+        Ok(())
+    }
+
+    fn compute_forces_internal(&mut self) -> Result<(), GpuError> {
+        if !self.cpu_fallback_active {
+            self.sync_phase_profiles()?;
+        }
+        let device = self.device.as_ref().ok_or_else(|| GpuError::Failed("Device not initialized".into()))?;
+        let force_kernel = self.force_kernel.as_ref().ok_or_else(|| GpuError::Failed("Kernel not initialized".into()))?;
+        let node_data = self.node_data.as_ref().ok_or_else(|| GpuError::Failed("Node data not initialized".into()))?;
+        let phase_profiles = self.phase_profiles.as_ref();
 
         if self.cpu_fallback_active {
             warn!("GPU compute in CPU fallback mode, skipping GPU kernel");
@@ -250,44 +456,50 @@ impl GPUComputeActor {
             shared_mem_bytes: SHARED_MEM_SIZE,
         };
 
-        let launch_result = unsafe {
-            force_kernel.clone().launch(cfg, (
-                node_data,
-                self.num_nodes as i32,
-                self.simulation_params.spring_strength,
-                self.simulation_params.damping,
-                self.simulation_params.repulsion,
-                self.simulation_params.time_step,
-                self.simulation_params.max_repulsion_distance,
-                if self.simulation_params.enable_bounds {
-                    self.simulation_params.viewport_bounds
-                } else {
-                    f32::MAX
-                },
-                self.iteration_count as i32,
-            ))
+        let phase_profiles = phase_profiles.ok_or_else(|| GpuError::Failed("Phase profiles not initialized".into()))?;
+        let started = Instant::now();
+        let launch = || {
+            self.faults.check()?;
+            unsafe {
+                force_kernel.clone().launch(cfg, (
+                    node_data,
+                    self.num_nodes as i32,
+                    phase_profiles,
+                    self.simulation_params.phase.index() as i32,
+                    self.iteration_count as i32,
+                ))
+            }.map_err(GpuError::from)
+        };
+        // Timed launches wait for the kernel between their events
+        let launch_result = if self.timing.is_enabled() {
+            time_on_device(device, launch).map(|((), kernel_ms)| Some(kernel_ms))
+        } else {
+            launch().map(|()| None)
         };
 
         match launch_result {
-            Ok(_) => {
+            Ok(kernel_ms) => {
                 match device.synchronize() {
                     Ok(_) => {
                         if self.iteration_count % DEBUG_THROTTLE == 0 {
                             trace!("Force computation completed successfully");
                         }
+                        if let Some(kernel_ms) = kernel_ms {
+                            self.timing.record_kernel(kernel_ms, gpu_timing::millis_since(started));
+                        }
                         self.iteration_count += 1;
                         Ok(())
                     },
-                    Err(e) => self.handle_gpu_error(format!("GPU synchronization failed: {}", e)),
+                    Err(e) => self.handle_gpu_error(GpuError::from(e).context("GPU synchronization failed")),
                 }
             },
-            Err(e) => self.handle_gpu_error(format!("Kernel launch failed: {}", e)),
+            Err(e) => self.handle_gpu_error(e.context("Kernel launch failed")),
         }
     }
 
-    fn handle_gpu_error(&mut self, error_msg: String) -> Result<(), Error> {
+    pub(crate) fn handle_gpu_error(&mut self, error: GpuError) -> Result<(), GpuError> {
         self.gpu_failure_count += 1;
-        error!("GPU error (failure {}/{}): {}", self.gpu_failure_count, MAX_GPU_FAILURES, error_msg);
+        error!("GPU error (failure {}/{}): {}", self.gpu_failure_count, MAX_GPU_FAILURES, error);
 
         if self.gpu_failure_count >= MAX_GPU_FAILURES {
             warn!("GPU failure count exceeded limit, activating CPU fallback mode");
@@ -296,12 +508,13 @@ impl GPUComputeActor {
             // self.gpu_failure_count = 0; // Don't reset immediately, let the interval handle it
             // self.last_failure_reset = Instant::now();
         }
-        Err(Error::new(ErrorKind::Other, error_msg))
+        self.record_failure(&error.to_string());
+        Err(error)
     }
 
-    fn get_node_data_internal(&self) -> Result<Vec<BinaryNodeData>, Error> {
-        let device = self.device.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Device not initialized"))?;
-        let node_data = self.node_data.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Node data not initialized"))?;
+    fn get_node_data_internal(&mut self) -> Result<Vec<BinaryNodeData>, GpuError> {
+        let device = self.device.as_ref().ok_or_else(|| GpuError::Failed("Device not initialized".into()))?;
+        let node_data = self.node_data.as_ref().ok_or_else(|| GpuError::Failed("Node data not initialized".into()))?;
 
         let mut gpu_raw_data = vec![BinaryNodeData {
             position: Vec3Data::zero(),
@@ -311,8 +524,10 @@ impl GPUComputeActor {
             padding: [0, 0],
         }; self.num_nodes as usize];
 
+        let started = Instant::now();
         device.dtoh_sync_copy_into(node_data, &mut gpu_raw_data)
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy data from GPU: {}", e)))?;
+            .map_err(|e| GpuError::from(e).context("Failed to copy data from GPU"))?;
+        self.timing.record_readback(gpu_timing::millis_since(started));
 
         Ok(gpu_raw_data)
     }
@@ -334,6 +549,7 @@ impl Handler<InitializeGPU> for GPUComputeActor {
     type Result = ResponseActFuture<Self, Result<(), String>>;
 
     fn handle(&mut self, msg: InitializeGPU, _ctx: &mut Self::Context) -> Self::Result {
+        self.last_graph = Some(msg.graph.clone());
         let graph_data_owned = msg.graph;
 
         let fut = GPUComputeActor::perform_gpu_initialization(graph_data_owned);
@@ -345,29 +561,14 @@ impl Handler<InitializeGPU> for GPUComputeActor {
             actor_fut.map(|result_of_logic, actor, _ctx_map| {
                 match result_of_logic {
                     Ok(init_result) => {
-                        actor.device = Some(init_result.device);
-                        actor.force_kernel = Some(init_result.force_kernel);
-                        actor.node_data = Some(init_result.node_data);
-                        actor.num_nodes = init_result.num_nodes;
-                        actor.node_indices = init_result.node_indices;
-                        
-                        // Reset other relevant state
-                        actor.iteration_count = 0;
-                        actor.gpu_failure_count = 0;
-                        actor.last_failure_reset = Instant::now();
-                        actor.cpu_fallback_active = false;
-
+                        actor.install(init_result);
                         info!("GPU initialization successful (applied static logic result)");
                         Ok(())
                     }
                     Err(e) => {
                         error!("GPU initialization failed (static logic): {}", e);
-                        actor.device = None;
-                        actor.force_kernel = None;
-                        actor.node_data = None;
-                        actor.num_nodes = 0;
-                        actor.node_indices.clear();
-                        actor.cpu_fallback_active = true; // Fallback on init failure
+                        actor.teardown(); // Fallback on init failure
+                        actor.record_failure(&format!("GPU initialization failed: {}", e));
                         Err(e.to_string())
                     }
                 }
@@ -379,17 +580,28 @@ impl Handler<InitializeGPU> for GPUComputeActor {
 impl Handler<UpdateGPUGraphData> for GPUComputeActor {
     type Result = Result<(), String>;
 
-    fn handle(&mut self, msg: UpdateGPUGraphData, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: UpdateGPUGraphData, ctx: &mut Self::Context) -> Self::Result {
+        if self.reconnect.is_reconnecting() {
+            // Uploaded once a new instance is up
+            self.last_graph = Some(msg.graph);
+            return Ok(());
+        }
         if self.device.is_none() {
             warn!("Attempted to update GPU graph data, but GPU is not initialized. CPU fallback may be active.");
             // Depending on desired behavior, could return Ok(()) or an error.
             // For now, let it proceed to update_graph_data_internal which will fail if device is None.
         }
-        match self.update_graph_data_internal(&msg.graph) {
+        let uploaded = self.update_graph_data_internal(&msg.graph);
+        self.last_graph = Some(msg.graph);
+        match uploaded {
             Ok(_) => {
                 trace!("Graph data updated successfully");
                 Ok(())
             },
+            Err(e) if e.is_context_lost() => {
+                self.context_lost(e, ctx);
+                Ok(())
+            },
             Err(e) => {
                 error!("Failed to update graph data: {}", e);
                 Err(e.to_string())
@@ -398,26 +610,55 @@ impl Handler<UpdateGPUGraphData> for GPUComputeActor {
     }
 }
 
+impl Handler<UseGraphSource> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UseGraphSource, _ctx: &mut Self::Context) -> Self::Result {
+        self.graph_source = Some(msg.source);
+    }
+}
+
 impl Handler<UpdateSimulationParams> for GPUComputeActor {
     type Result = Result<(), String>;
 
     fn handle(&mut self, msg: UpdateSimulationParams, _ctx: &mut Self::Context) -> Self::Result {
         trace!("Updating simulation parameters: {:?}", msg.params);
+        if msg.params.degree_repulsion_factor != self.simulation_params.degree_repulsion_factor {
+            // Rebuild the repulsion scales on the next upload
+            self.indexed_generation = None;
+        }
         self.simulation_params = msg.params;
         Ok(())
     }
 }
 
+impl Handler<SetSimulationPhase> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetSimulationPhase, _ctx: &mut Self::Context) -> Self::Result {
+        trace!("Switching simulation phase to {:?}", msg.phase);
+        self.simulation_params.phase = msg.phase;
+    }
+}
+
 impl Handler<ComputeForces> for GPUComputeActor {
     type Result = Result<(), String>;
 
-    fn handle(&mut self, _msg: ComputeForces, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, _msg: ComputeForces, ctx: &mut Self::Context) -> Self::Result {
+        if self.reconnect.is_reconnecting() {
+            trace!("GPU reconnecting, physics runs on the CPU");
+            return Ok(());
+        }
         if self.device.is_none() {
             warn!("Attempted to compute forces, but GPU is not initialized. CPU fallback may be active.");
             return Ok(()); // Or Err, if strict GPU mode is required
         }
         match self.compute_forces_internal() {
             Ok(_) => Ok(()),
+            Err(e) if e.is_context_lost() => {
+                self.context_lost(e, ctx);
+                Ok(())
+            },
             Err(e) => {
                 if self.cpu_fallback_active {
                     warn!("GPU compute failed, CPU fallback active: {}", e);
@@ -434,7 +675,10 @@ impl Handler<ComputeForces> for GPUComputeActor {
 impl Handler<GetNodeData> for GPUComputeActor {
     type Result = Result<Vec<BinaryNodeData>, String>;
 
-    fn handle(&mut self, _msg: GetNodeData, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, _msg: GetNodeData, ctx: &mut Self::Context) -> Self::Result {
+        if self.reconnect.is_reconnecting() {
+            return Err("GPU reconnecting".to_string());
+        }
          if self.device.is_none() {
             warn!("Attempted to get node data, but GPU is not initialized.");
             return Err("GPU not initialized".to_string());
@@ -446,7 +690,11 @@ impl Handler<GetNodeData> for GPUComputeActor {
             },
             Err(e) => {
                 error!("Failed to get node data from GPU: {}", e);
-                Err(e.to_string())
+                let message = e.to_string();
+                if e.is_context_lost() {
+                    self.context_lost(e, ctx);
+                }
+                Err(message)
             }
         }
     }
@@ -462,6 +710,98 @@ impl Handler<GetGPUStatus> for GPUComputeActor {
             failure_count: self.gpu_failure_count,
             iteration_count: self.iteration_count,
             num_nodes: self.num_nodes,
+            timing: self.timing.summary(),
+            reconnect: self.reconnect.stats(Instant::now()),
         })
     }
 }
+
+impl Handler<SetGpuTiming> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetGpuTiming, _ctx: &mut Self::Context) -> Self::Result {
+        info!("GPU step timing {}", if msg.enabled { "on" } else { "off" });
+        self.timing.set_enabled(msg.enabled);
+    }
+}
+
+impl Handler<GetGpuStepTimings> for GPUComputeActor {
+    type Result = MessageResult<GetGpuStepTimings>;
+
+    fn handle(&mut self, msg: GetGpuStepTimings, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.timing.recent(msg.steps))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::node::Node;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Stands in for the graph actor: answers with where CPU physics has moved the nodes
+    struct LiveGraph {
+        graph: GraphData,
+        asked: Arc<AtomicUsize>,
+    }
+
+    impl Actor for LiveGraph {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<GetPhysicsGraph> for LiveGraph {
+        type Result = Result<GraphData, String>;
+
+        fn handle(&mut self, _msg: GetPhysicsGraph, _ctx: &mut Self::Context) -> Self::Result {
+            self.asked.fetch_add(1, Ordering::SeqCst);
+            Ok(self.graph.clone())
+        }
+    }
+
+    fn graph_at(x: f32) -> GraphData {
+        let mut graph = GraphData::new();
+        let mut node = Node::new_with_id("a".into(), Some(1));
+        node.data.position = Vec3Data::new(x, 0.0, 0.0);
+        graph.nodes.push(node);
+        graph
+    }
+
+    // There's no device here, so the rebuild is failed by the injector before it reaches CUDA
+    #[actix::test]
+    async fn test_lost_context_rebuilds_from_the_live_graph() {
+        let event_log = Arc::new(EventLog::new());
+        let faults = FaultInjector::default();
+        let gpu = GPUComputeActor::new()
+            .with_event_log(event_log.clone())
+            .with_fault_injector(faults.clone())
+            .start();
+        let asked = Arc::new(AtomicUsize::new(0));
+        let live = LiveGraph { graph: graph_at(5.0), asked: asked.clone() }.start();
+        gpu.send(UseGraphSource { source: live.recipient() }).await.unwrap();
+
+        // The upload hits a dead context; the caller carries on as if it landed
+        faults.inject(GpuError::ContextLost("CUDA_ERROR_ILLEGAL_ADDRESS".into()));
+        faults.inject(GpuError::Failed("no CUDA devices".into()));
+        assert!(gpu.send(UpdateGPUGraphData { graph: graph_at(0.0) }).await.unwrap().is_ok());
+
+        let kinds = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let kinds: Vec<String> = event_log.recent(16).into_iter().map(|event| event.kind).collect();
+                if kinds.iter().any(|kind| kind == "gpu_reconnect_failed") {
+                    return kinds;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        assert!(kinds.iter().any(|kind| kind == "gpu_context_lost"));
+        // The rebuild started from the graph actor's positions, not the stale upload
+        assert_eq!(asked.load(Ordering::SeqCst), 1);
+
+        // Physics stays on the CPU and the next attempt is scheduled
+        let status = gpu.send(GetGPUStatus).await.unwrap();
+        assert!(status.reconnect.reconnecting);
+        assert_eq!((status.reconnect.contexts_lost, status.reconnect.failed_attempts), (1, 1));
+        assert!(status.reconnect.last_error.unwrap().contains("no CUDA devices"));
+        assert_eq!(gpu.send(GetNodeData).await.unwrap().unwrap_err(), "GPU reconnecting");
+    }
+}
@@ -0,0 +1,125 @@
+//! Stand-in for `gpu_compute_actor` in builds without the `gpu` feature.
+//!
+//! Answers the same messages so the graph actor and admin feed need no changes:
+//! initialization fails, the actor stays in CPU fallback, and compute requests are no-ops.
+
+use actix::prelude::*;
+use log::{info, warn};
+use std::sync::Arc;
+
+use crate::actors::messages::*;
+use crate::services::event_log::EventLog;
+use crate::utils::gpu_reconnect::ReconnectStats;
+use crate::utils::gpu_timing::GpuTimingSummary;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+const DISABLED: &str = "built without the gpu feature";
+
+#[derive(Debug, Default)]
+pub struct GPUComputeActor {
+    // Kept so callers can attach a log regardless of the build
+    event_log: Option<Arc<EventLog>>,
+}
+
+impl GPUComputeActor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_event_log(mut self, event_log: Arc<EventLog>) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+}
+
+impl Actor for GPUComputeActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        info!("GPUComputeActor started ({}); physics runs on the CPU", DISABLED);
+    }
+}
+
+impl Handler<InitializeGPU> for GPUComputeActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: InitializeGPU, _ctx: &mut Self::Context) -> Self::Result {
+        warn!("GPU initialization requested, but this binary was {}", DISABLED);
+        Err(DISABLED.to_string())
+    }
+}
+
+impl Handler<UpdateGPUGraphData> for GPUComputeActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: UpdateGPUGraphData, _ctx: &mut Self::Context) -> Self::Result {
+        Err(DISABLED.to_string())
+    }
+}
+
+impl Handler<UseGraphSource> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: UseGraphSource, _ctx: &mut Self::Context) -> Self::Result {}
+}
+
+impl Handler<UpdateSimulationParams> for GPUComputeActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: UpdateSimulationParams, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(())
+    }
+}
+
+impl Handler<SetSimulationPhase> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: SetSimulationPhase, _ctx: &mut Self::Context) -> Self::Result {}
+}
+
+impl Handler<ComputeForces> for GPUComputeActor {
+    type Result = Result<(), String>;
+
+    // Same as the real actor with no device: the caller falls back to the CPU
+    fn handle(&mut self, _msg: ComputeForces, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(())
+    }
+}
+
+impl Handler<GetNodeData> for GPUComputeActor {
+    type Result = Result<Vec<BinaryNodeData>, String>;
+
+    fn handle(&mut self, _msg: GetNodeData, _ctx: &mut Self::Context) -> Self::Result {
+        Err("GPU not initialized".to_string())
+    }
+}
+
+impl Handler<GetGPUStatus> for GPUComputeActor {
+    type Result = MessageResult<GetGPUStatus>;
+
+    fn handle(&mut self, _msg: GetGPUStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(GPUStatus {
+            is_initialized: false,
+            cpu_fallback_active: true,
+            failure_count: 0,
+            iteration_count: 0,
+            num_nodes: 0,
+            timing: GpuTimingSummary::default(),
+            reconnect: ReconnectStats::default(),
+        })
+    }
+}
+
+impl Handler<SetGpuTiming> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: SetGpuTiming, _ctx: &mut Self::Context) -> Self::Result {}
+}
+
+impl Handler<GetGpuStepTimings> for GPUComputeActor {
+    type Result = MessageResult<GetGpuStepTimings>;
+
+    fn handle(&mut self, _msg: GetGpuStepTimings, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(Vec::new())
+    }
+}
@@ -1,49 +1,322 @@
 //! Graph Service Actor to replace Arc<RwLock<GraphService>>
 
 use actix::prelude::*;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+use chrono::Utc;
 use tokio::time::Duration;
 use log::{debug, info, warn, error};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 // use actix::fut::WrapFuture; // Unused import
  
 use crate::actors::messages::*;
 use crate::actors::client_manager_actor::ClientManagerActor;
 use crate::models::node::Node;
-use crate::models::edge::Edge;
+use crate::models::edge::{Edge, WeightSource};
 use crate::models::metadata::MetadataStore;
 use crate::models::graph::GraphData;
 use crate::utils::socket_flow_messages::{BinaryNodeData, glam_to_vec3data}; // Added glam_to_vec3data
 use crate::utils::binary_protocol;
 use crate::actors::gpu_compute_actor::GPUComputeActor;
+use crate::config::{AgingSettings, AnalyticsRefreshSettings, AttentionSettings, ColorMappingSettings, DisturbanceSettings, EdgeDecaySettings, EdgeWeightSettings, FrameBudgetSettings, IdleSettings, RewireSettings, SimulationSettings, WarmupSettings};
+use crate::models::graph::{GraphDiff, GraphGenerations, GraphSnapshot, GraphStats};
+use crate::utils::attention::{AttentionTracker, rank_lod_importance};
+use crate::utils::coloring::{self, pagerank_until, NodeColor};
+use crate::utils::co_selection::{CoViewedPair, CO_VIEWED_EDGE_TYPE};
+use crate::utils::physics_flags;
+use crate::services::embedding_service::{SimilarityPair, SIMILARITY_EDGE_TYPE};
+use crate::services::integrity_check::IntegrityView;
+use crate::utils::time_sync::FrameTiming;
+use crate::models::position_history::{HistoryStep, PositionEdit, PositionHistory};
+use crate::models::group_transform::{self, NodeLocks, RigidTransform, MAX_GROUP_SIZE};
+use crate::types::vec3::Vec3Data;
+
+use crate::models::layout::NodeLayout;
+use crate::models::simulation_params::{SimulationMode, SimulationPhase};
+use crate::utils::position_recording::{FrameKind, PositionRecorder, PositionReplay, RecordingState, RecordingStatus, ReplayStatus};
+use crate::utils::update_priority::{self, PRIORITY_HUB_COUNT};
+use crate::utils::aging::{self, NodeAge, AGE_OPACITY_KEY, ARCHIVED_KEY};
+use crate::utils::edge_decay;
+use crate::utils::edge_visibility;
+use crate::utils::edge_weights;
+use crate::utils::layout_quality::{self, LayoutQuality};
+use crate::utils::placement::{self, PLACEMENT_JITTER, SETTLE_DAMPING, SETTLE_FRAMES, SPHERE_RADIUS};
+use crate::utils::warmup::{self, Warmup, WarmupStatus};
+use crate::utils::idle::{IdleChange, IdleStatus, IdleTracker};
+use crate::utils::frame_budget::{BudgetTick, FrameBudget};
+use crate::utils::disturbance::DisturbanceRamp;
+use crate::utils::reheat::{self, ReheatOutcome, ReheatScope};
+use crate::utils::rewire;
+use crate::utils::analytics_refresh::{AnalyticsKind, AnalyticsRefresh};
+use crate::utils::node_watch::WatchEvent;
+use crate::services::event_log::EventLog;
+use crate::services::external_links::ExternalLinkService;
+use crate::models::pins::{PinConflict, PinInfo, PinReport, PinSource, PinStore, PinnedNode};
+use crate::models::node_aliases::AliasStore;
+use crate::models::node_ids::{IdConflict, NodeIdMap, SharedNodeIds};
+use crate::models::graph_journal::{self, GraphJournal, JournalRecord};
+use crate::services::topic_extraction;
+use crate::models::node_attributes::{self, AttributeUpdateOutcome, AttributeUpdateStatus, NodeAttributeResult};
+use crate::utils::node_merge::{self, MergeOutcome};
+use crate::utils::graph_transaction::{self, TransactionError, TransactionOp, TransactionOutcome, TransactionUndo, MAX_UNDO_TRANSACTIONS};
+use crate::utils::skeleton::{self, SkeletonStrategy};
+use crate::utils::community_view::GraphView;
+use crate::utils::spatial_index::{Frustum, SpatialGrid, SpatialHit, SpatialNode, SpatialResult};
+use crate::utils::simulation_clock::{self, LoopClock, SimulationClock, SimulationModeStatus, StepAccumulator};
+
+// Squared movement below which a position update doesn't count as a layout change,
+// so a settled graph stops advancing the position generation
+const SETTLED_EPSILON_SQ: f32 = 1e-6;
+
+// How long an undone/redone node is held still so physics doesn't immediately drift it away
+const UNDO_PIN_DURATION: Duration = Duration::from_secs(2);
+
+// A dragged node stays in every frame for this long after its last move
+const GRAB_PRIORITY_DURATION: Duration = Duration::from_secs(1);
+
+// How often the connected-client count is checked for idling; also the longest a
+// client waits for an idle loop to wake
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct GraphServiceActor {
     graph_data: Arc<GraphData>, // Changed to Arc<GraphData>
     node_map: HashMap<u32, Node>,
-    // gpu_compute_addr: Option<Addr<GPUComputeActor>>, // Unused
+    // Only told which phase to run; layout steps don't go through it yet
+    gpu_compute_addr: Option<Addr<GPUComputeActor>>,
+    phase: SimulationPhase,
     client_manager: Addr<ClientManagerActor>,
     simulation_running: AtomicBool,
     shutdown_complete: Arc<AtomicBool>,
-    next_node_id: AtomicU32,
+    // Every node id this actor hands out comes from here
+    node_ids: SharedNodeIds,
+    // Gaze attention, deliberately kept out of graph_data so it is never persisted
+    attention: AttentionTracker,
+    attention_settings: AttentionSettings,
+    // Embedding similarity edges, kept by metadata id and re-applied after every rebuild
+    similarity_pairs: Vec<SimilarityPair>,
+    similarity_enabled: bool,
+    similarity_spring_multiplier: f32,
+    // Behavioural edges from co-selection, kept the same way
+    co_viewed_pairs: Vec<CoViewedPair>,
+    // Manual position edits per room, for undo/redo
+    position_history: PositionHistory,
+    // Applied transactions that can still be undone, most recent last
+    transactions: VecDeque<TransactionUndo>,
+    next_transaction_id: u64,
+    pinned_until: HashMap<u32, Instant>,
+    // Nodes being dragged, sent to throttled clients in every frame
+    grabbed_until: HashMap<u32, Instant>,
+    // Multi-select moves lock their group to the mover until it settles
+    node_locks: NodeLocks,
+    // Top PageRank nodes and the graph generation they were ranked at
+    priority_hubs: Option<(u64, Vec<u32>)>,
+    // Skeleton edge ids per strategy, and the graph generation they were computed at
+    skeletons: HashMap<SkeletonStrategy, (u64, Arc<Vec<String>>)>,
+    // Communities collapsed into super-nodes, derived at the generation it carries
+    community_view: Option<Arc<GraphView>>,
+    // PageRank by node id and the graph generation it was computed at
+    pagerank: Option<(u64, Arc<HashMap<u32, f32>>)>,
+    // Layout quality score and the generations it was scored at
+    layout_quality: Option<(GraphGenerations, LayoutQuality)>,
+    // While set, physics springs only this skeleton's edges
+    skeleton_springs: Option<SkeletonStrategy>,
+    // Bumped whenever the layout changes; snapshots are skipped while it stands still
+    position_generation: u64,
+    color_mapping: ColorMappingSettings,
+    // Colours set by hand through the attribute API, by metadata id; these win over the mapping
+    color_overrides: HashMap<String, String>,
+    aging: AgingSettings,
+    aging_timer: Option<SpawnHandle>,
+    // Aged-out nodes; kept in the graph but left out of position broadcasts
+    archived: HashSet<u32>,
+    // Size each aging node had before it was scaled, so passes don't compound
+    unaged_size: HashMap<u32, Option<f32>>,
+    edge_weights: EdgeWeightSettings,
+    // How removals with `rewire` link the removed node's neighbours
+    rewire: RewireSettings,
+    // Edge types whose weight fades unless the edge is re-created
+    edge_decay: EdgeDecaySettings,
+    // Edge types physics ignores; clients are still sent them
+    physics_disabled_types: HashSet<String>,
+    // Hand-placed nodes physics leaves alone; persisted by metadata id
+    pins: PinStore,
+    // Metadata ids pinned through the API since startup, as opposed to restored ones
+    runtime_pins: HashSet<String>,
+    pinned_nodes: HashSet<u32>,
+    pin_conflicts: Vec<PinConflict>,
+    // Metadata ids merged into other nodes; rebuilds fold them in
+    aliases: AliasStore,
+    // Runtime changes, replayed after every build so they outlive rebuilds and restarts
+    journal: Option<GraphJournal>,
+    // Nodes placed by the last rebuild and their neighbours, with frames left to settle
+    settling: HashMap<u32, u32>,
+    // Every broadcast position frame is appended here while a recording runs
+    recorder: Option<PositionRecorder>,
+    // Takes the place of physics while set
+    replay: Option<PositionReplay>,
+    replay_clock: Instant,
+    warmup_settings: WarmupSettings,
+    // Set after a rebuild while physics runs unbroadcast
+    warmup: Option<Warmup>,
+    // False until the first build has warmed up
+    graph_ready: bool,
+    // Physics and broadcasts pause while nobody is connected
+    idle: IdleTracker,
+    idle_check: Option<SpawnHandle>,
+    physics_iterations: u64,
+    // Turns real time into fixed-size physics substeps
+    steps: StepAccumulator,
+    // Timing of the step being broadcast; frames sent outside a step have none
+    step_timing: Option<FrameTiming>,
+    // Which loop ticks run physics and what they send, per simulation mode
+    simulation: SimulationClock,
+    // Where the loop's time comes from; virtual in deterministic mode
+    loop_clock: LoopClock,
+    // Placement and jitter randomness, seeded in deterministic mode
+    rng: StdRng,
+    // Grid over node positions for proximity queries, built lazily for the generations it's keyed by
+    spatial_index: Option<(GraphGenerations, Arc<SpatialGrid>)>,
+    // Holds physics off for a few frames when steps overrun the tick
+    frame_budget: FrameBudget,
+    // Extra damping after drags and insertions, easing off over a couple of seconds
+    disturbance: DisturbanceRamp,
+    // Recomputes stale analytics in the background once the layout settles
+    analytics: AnalyticsRefresh,
+    // Position generation at the last refresh check, to tell when the layout holds still
+    analytics_positions_seen: u64,
+    // Computes each physics step
+    layout: LayoutFn,
+    event_log: Option<Arc<EventLog>>,
+    // References to other rooms, re-checked against every build
+    external_links: Option<Arc<ExternalLinkService>>,
+}
+
+// An analytics result computed in the background
+enum RefreshedAnalytics {
+    PageRank(Arc<HashMap<u32, f32>>),
+    Skeleton(Arc<Vec<String>>),
+    LayoutQuality(LayoutQuality),
+}
+
+/// One physics step over the actor's graph, returning the new positions
+pub type LayoutFn = fn(&mut GraphServiceActor) -> Result<Vec<(u32, BinaryNodeData)>, String>;
+
+// Folds a substep's positions into the step's, each node keeping its place from the first
+// substep that moved it
+fn merge_substep(moved: &mut Vec<(u32, BinaryNodeData)>, substep: Vec<(u32, BinaryNodeData)>) {
+    if moved.is_empty() {
+        *moved = substep;
+        return;
+    }
+    let mut index: HashMap<u32, usize> = moved.iter().enumerate().map(|(i, (id, _))| (*id, i)).collect();
+    for (id, data) in substep {
+        match index.get(&id) {
+            Some(&i) => moved[i].1 = data,
+            None => {
+                index.insert(id, moved.len());
+                moved.push((id, data));
+            }
+        }
+    }
 }
 
+// Sent with the event raised when physics stays overloaded
+const FRAME_BUDGET_SUGGESTION: &str = "Physics can't keep up with this graph; switch system.simulation.mode to hybrid or local so clients share the layout work, or raise system.frame_budget.budget_ms";
+
 impl GraphServiceActor {
     pub fn new(
         client_manager: Addr<ClientManagerActor>,
-        _gpu_compute_addr: Option<Addr<GPUComputeActor>>, // Marked as unused
+        gpu_compute_addr: Option<Addr<GPUComputeActor>>,
     ) -> Self {
         Self {
             graph_data: Arc::new(GraphData::new()), // Changed to Arc::new
             node_map: HashMap::new(),
-            // gpu_compute_addr, // Unused
+            gpu_compute_addr,
+            phase: SimulationPhase::Dynamic,
             client_manager,
             simulation_running: AtomicBool::new(false),
             shutdown_complete: Arc::new(AtomicBool::new(false)),
-            next_node_id: AtomicU32::new(1),
+            node_ids: NodeIdMap::in_memory().shared(),
+            attention: AttentionTracker::new(Duration::from_secs_f32(AttentionSettings::default().half_life_secs)),
+            attention_settings: AttentionSettings::default(),
+            similarity_pairs: Vec::new(),
+            similarity_enabled: false,
+            similarity_spring_multiplier: 1.0,
+            co_viewed_pairs: Vec::new(),
+            position_history: PositionHistory::new(),
+            transactions: VecDeque::new(),
+            next_transaction_id: 1,
+            pinned_until: HashMap::new(),
+            grabbed_until: HashMap::new(),
+            node_locks: NodeLocks::new(),
+            priority_hubs: None,
+            skeletons: HashMap::new(),
+            community_view: None,
+            pagerank: None,
+            layout_quality: None,
+            skeleton_springs: None,
+            position_generation: 0,
+            color_mapping: ColorMappingSettings::default(),
+            color_overrides: HashMap::new(),
+            aging: AgingSettings::default(),
+            aging_timer: None,
+            archived: HashSet::new(),
+            unaged_size: HashMap::new(),
+            edge_weights: EdgeWeightSettings::default(),
+            rewire: RewireSettings::default(),
+            edge_decay: EdgeDecaySettings::default(),
+            physics_disabled_types: HashSet::new(),
+            pins: PinStore::in_memory(),
+            runtime_pins: HashSet::new(),
+            pinned_nodes: HashSet::new(),
+            pin_conflicts: Vec::new(),
+            aliases: AliasStore::in_memory(),
+            journal: None,
+            settling: HashMap::new(),
+            recorder: None,
+            replay: None,
+            replay_clock: Instant::now(),
+            warmup_settings: WarmupSettings::default(),
+            warmup: None,
+            graph_ready: false,
+            idle: IdleTracker::new(IdleSettings::default()),
+            idle_check: None,
+            physics_iterations: 0,
+            steps: StepAccumulator::new(simulation_clock::TICK, SimulationSettings::default().max_substeps),
+            step_timing: None,
+            simulation: SimulationClock::new(SimulationSettings::default(), Instant::now()),
+            loop_clock: LoopClock::Wall,
+            rng: StdRng::from_entropy(),
+            spatial_index: None,
+            frame_budget: FrameBudget::new(FrameBudgetSettings::default()),
+            disturbance: DisturbanceRamp::new(DisturbanceSettings::default()),
+            analytics: AnalyticsRefresh::new(AnalyticsRefreshSettings::default()),
+            analytics_positions_seen: 0,
+            layout: Self::calculate_layout_cpu,
+            event_log: None,
+            external_links: None,
         }
     }
 
+    pub fn with_event_log(mut self, event_log: Arc<EventLog>) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+
+    pub fn with_external_links(mut self, external_links: Arc<ExternalLinkService>) -> Self {
+        self.external_links = Some(external_links);
+        self
+    }
+
+    /// Replaces the physics step, e.g. with a deliberately slow one in tests
+    pub fn with_layout(mut self, layout: LayoutFn) -> Self {
+        self.layout = layout;
+        self
+    }
+
     pub fn get_graph_data(&self) -> &GraphData { // Returns a reference to the inner GraphData
         &self.graph_data // Dereferences Arc<GraphData> to &GraphData
     }
@@ -57,6 +330,8 @@ impl GraphServiceActor {
         
         // Update node_map
         self.node_map.insert(node.id, node.clone());
+        self.position_generation += 1;
+        self.topology_changed();
         
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         // Add to graph data if not already present
@@ -75,6 +350,14 @@ impl GraphServiceActor {
     pub fn remove_node(&mut self, node_id: u32) {
         // Remove from node_map
         self.node_map.remove(&node_id);
+        self.position_generation += 1;
+        self.topology_changed();
+        self.grabbed_until.remove(&node_id);
+        self.node_locks.release(node_id);
+        self.archived.remove(&node_id);
+        self.unaged_size.remove(&node_id);
+        self.pinned_nodes.remove(&node_id);
+        self.settling.remove(&node_id);
         
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         // Remove from graph data
@@ -88,7 +371,9 @@ impl GraphServiceActor {
 
     pub fn add_edge(&mut self, edge: Edge) {
         let edge_id = edge.id.clone(); // Store the ID before moving edge
+        self.topology_changed();
         
+        let decays = self.edge_decay.half_life_days.contains_key(edge.type_name());
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         // Add to graph data if not already present
         if !graph_data_mut.edges.iter().any(|e| e.id == edge.id) {
@@ -96,7 +381,11 @@ impl GraphServiceActor {
         } else {
             // Update existing edge
             if let Some(existing) = graph_data_mut.edges.iter_mut().find(|e| e.id == edge.id) {
-                *existing = edge; // Move edge here instead of cloning
+                if decays && existing.type_name() == edge.type_name() {
+                    edge_decay::reinforce(existing, &edge);
+                } else {
+                    *existing = edge; // Move edge here instead of cloning
+                }
             }
         }
         
@@ -105,23 +394,83 @@ impl GraphServiceActor {
 
     pub fn remove_edge(&mut self, edge_id: &str) {
         Arc::make_mut(&mut self.graph_data).edges.retain(|e| e.id != edge_id);
+        self.topology_changed();
         debug!("Removed edge: {}", edge_id);
     }
 
+    // Every change to nodes, edges, node attributes or edge weights goes through here.
+    // Position updates bump position_generation instead.
+    fn topology_changed(&mut self) {
+        Arc::make_mut(&mut self.graph_data).generation += 1;
+        // A background refresh of the old graph is wasted work now
+        self.analytics.invalidate(self.generations());
+    }
+
+    pub fn generations(&self) -> GraphGenerations {
+        GraphGenerations {
+            generation: self.graph_data.generation,
+            position_generation: self.position_generation,
+        }
+    }
+
+    /// The spatial index over the current positions, rebuilt on first use after they change
+    pub fn spatial_index(&mut self) -> Arc<SpatialGrid> {
+        let generations = self.generations();
+        match &self.spatial_index {
+            Some((built, grid)) if *built == generations => grid.clone(),
+            _ => {
+                let grid = Arc::new(SpatialGrid::build(&self.graph_data.nodes));
+                self.spatial_index = Some((generations, grid.clone()));
+                grid
+            }
+        }
+    }
+
+    pub fn nodes_within(&mut self, center: glam::Vec3, radius: f32, limit: usize) -> (Vec<SpatialHit>, usize) {
+        self.spatial_index().within(center, radius, limit)
+    }
+
+    pub fn nodes_in_frustum(&mut self, frustum: &Frustum, limit: usize) -> (Vec<SpatialHit>, usize) {
+        self.spatial_index().in_frustum(frustum, limit)
+    }
+
     pub fn build_from_metadata(&mut self, metadata: MetadataStore) -> Result<(), String> {
+        // Merged files fold into the node they were merged into
+        let metadata = self.aliases.fold(&metadata);
         let mut new_graph_data = GraphData::new(); // Create a new GraphData instance
+        // Nodes that survive the rebuild keep their place in the layout
+        let previous: HashMap<String, BinaryNodeData> = self.graph_data.nodes.iter()
+            .map(|n| (n.metadata_id.clone(), n.data))
+            .collect();
+        let mut new_ids = HashSet::new();
         self.node_map.clear(); // Clear node_map separately
+        self.position_generation += 1;
+
+        // Files in name order, so new ids and edge order come out the same every build
+        let mut files: Vec<_> = metadata.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let node_ids = self.node_ids.lock().unwrap()
+            .assign_all(files.iter().map(|(filename, _)| filename.trim_end_matches(".md")));
+        self.report_id_conflicts();
 
         // Build nodes from metadata
-        // Assuming metadata is MetadataStore which is HashMap<String, crate::models::metadata::Metadata>
-        for (filename_with_ext, file_meta_data) in &metadata {
-            let node_id_val = self.next_node_id.fetch_add(1, Ordering::SeqCst);
+        for (&(filename_with_ext, file_meta_data), node_id_val) in files.iter().zip(node_ids) {
             let metadata_id_val = filename_with_ext.trim_end_matches(".md").to_string();
             
             let mut node = Node::new_with_id(metadata_id_val.clone(), Some(node_id_val));
             node.label = file_meta_data.file_name.trim_end_matches(".md").to_string();
             node.set_file_size(file_meta_data.file_size as u64);
             node.data.flags = 1;
+            match previous.get(&metadata_id_val) {
+                Some(data) => {
+                    node.data.position = data.position;
+                    node.data.velocity = data.velocity;
+                    node.data.flags = data.flags;
+                }
+                None => {
+                    new_ids.insert(node_id_val);
+                }
+            }
 
             node.metadata.insert("fileName".to_string(), file_meta_data.file_name.clone());
             node.metadata.insert("fileSize".to_string(), file_meta_data.file_size.to_string());
@@ -135,6 +484,18 @@ impl GraphServiceActor {
             if let Some(last_process) = file_meta_data.last_perplexity_process {
                 node.metadata.insert("lastPerplexityProcess".to_string(), last_process.to_rfc3339());
             }
+            if !file_meta_data.tags.is_empty() {
+                node.metadata.insert("tags".to_string(), file_meta_data.tags.join(","));
+                // First tag doubles as the node's group for clustering and colouring
+                node.group = file_meta_data.tags.first().cloned();
+            }
+            if !file_meta_data.auto_tags.is_empty() {
+                node.metadata.insert("autoTags".to_string(), file_meta_data.auto_tags.join(","));
+            }
+            let aliases = self.aliases.aliases_of(&metadata_id_val);
+            if !aliases.is_empty() {
+                node.metadata.insert("aliases".to_string(), aliases.join(","));
+            }
             node.metadata.insert("metadataId".to_string(), metadata_id_val);
 
             // Add to new_graph_data and self.node_map
@@ -143,8 +504,8 @@ impl GraphServiceActor {
         }
 
         // Build edges from topic counts
-        let mut edge_map: HashMap<(u32, u32), f32> = HashMap::new();
-        for (source_filename_ext, source_meta) in &metadata {
+        let mut edge_map: BTreeMap<(u32, u32), f32> = BTreeMap::new();
+        for &(source_filename_ext, source_meta) in &files {
             let source_metadata_id = source_filename_ext.trim_end_matches(".md");
             if let Some(source_node) = self.node_map.values().find(|n| n.metadata_id == source_metadata_id) {
                 for (target_filename_ext, count) in &source_meta.topic_counts {
@@ -159,14 +520,47 @@ impl GraphServiceActor {
             }
         }
 
-        for ((source_id, target_id), weight) in edge_map {
-            new_graph_data.edges.push(Edge::new(source_id, target_id, weight));
+        // Physics gets the transformed weight, the raw count is kept alongside it
+        let (pairs, counts): (Vec<(u32, u32)>, Vec<f32>) = edge_map.into_iter().unzip();
+        let built_at = Utc::now();
+        let weights = edge_weights::transform_weights(&counts, &self.edge_weights);
+        for (((source_id, target_id), count), weight) in pairs.into_iter().zip(counts).zip(weights) {
+            let mut edge = Edge::new(source_id, target_id, weight).credited_to(WeightSource::TopicCounts, built_at);
+            edge.raw_weight = Some(count);
+            new_graph_data.edges.push(edge);
         }
         
+        // New files start next to the neighbours they link to, and the neighbourhood is
+        // damped for a while so the new springs don't jolt the settled layout
+        let settling = placement::place_new_nodes(&mut new_graph_data, &new_ids, PLACEMENT_JITTER, SPHERE_RADIUS, &mut self.rng);
+        for node in &new_graph_data.nodes {
+            if let Some(map_node) = self.node_map.get_mut(&node.id) {
+                map_node.data = node.data;
+            }
+        }
+        self.settling.clear();
+        if !previous.is_empty() {
+            self.settling = settling.into_iter().map(|id| (id, SETTLE_FRAMES)).collect();
+        }
+
         // Populate metadata in new_graph_data (assuming metadata is MetadataStore)
-        new_graph_data.metadata = metadata.clone(); // Clone the entire store
+        new_graph_data.metadata = metadata.clone().into(); // Clone the entire store
+        // The counter carries on across rebuilds so caches never see an old value again
+        new_graph_data.generation = self.graph_data.generation + 1;
 
         self.graph_data = Arc::new(new_graph_data); // Replace the old Arc with the new one
+        self.replay_journal();
+        self.revalidate_external_links();
+        self.apply_similarity_edges();
+        self.apply_co_viewed_edges();
+        // Clients reload the whole graph after a rebuild, so no colour or aging broadcast here.
+        // Node ids are fresh, so aging state starts over.
+        self.apply_colors();
+        self.archived.clear();
+        self.unaged_size.clear();
+        self.apply_aging();
+        self.restore_pins();
+        self.begin_warmup();
         
         info!("Built graph from metadata: {} nodes, {} edges",
               self.graph_data.nodes.len(), self.graph_data.edges.len());
@@ -174,13 +568,199 @@ impl GraphServiceActor {
         Ok(())
     }
 
+    /// Fills node.color from the colour mapping, or from a hand-set colour where there is
+    /// one. Returns the nodes whose colour changed.
+    fn apply_colors(&mut self) -> Vec<NodeColor> {
+        let colors = coloring::compute_colors(&self.color_mapping, &self.graph_data.nodes, &self.graph_data.edges);
+        if colors.is_empty() && self.color_overrides.is_empty() && self.graph_data.nodes.iter().all(|n| n.color.is_none()) {
+            return Vec::new();
+        }
+
+        let mut changed = Vec::new();
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        for node in graph_data_mut.nodes.iter_mut() {
+            let color = self.color_overrides.get(&node.metadata_id).cloned()
+                .or_else(|| colors.get(&node.id).cloned());
+            if node.color != color {
+                node.color = color.clone();
+                if let Some(mapped) = self.node_map.get_mut(&node.id) {
+                    mapped.color = color.clone();
+                }
+                changed.push(NodeColor { node_id: node.id, color });
+            }
+        }
+        if !changed.is_empty() {
+            self.topology_changed();
+        }
+        changed
+    }
+
+    /// Recolours after a change that can shift other nodes' colours (a new group, a
+    /// new hub) and pushes whatever changed to clients
+    fn recolor_and_broadcast(&mut self) -> usize {
+        let changed = self.apply_colors();
+        if !changed.is_empty() {
+            let event = serde_json::json!({
+                "type": "node_metadata_update",
+                "reason": "color_mapping",
+                "strategy": self.color_mapping.strategy,
+                "nodes": changed,
+            });
+            self.client_manager.do_send(BroadcastMessage { message: event.to_string() });
+        }
+        changed.len()
+    }
+
+    /// Scales and fades nodes by how long ago their file changed, and archives the
+    /// stalest. Returns the nodes whose aging state changed.
+    fn apply_aging(&mut self) -> Vec<NodeAge> {
+        let now = chrono::Utc::now();
+        let mut changed = Vec::new();
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        for node in graph_data_mut.nodes.iter_mut() {
+            let was_archived = self.archived.contains(&node.id);
+            let age = match aging::evaluate(node, now, &self.aging) {
+                Some(age) => age,
+                None => {
+                    // Aging switched off, or the node lost its file date: put it back as it was
+                    let base = match self.unaged_size.remove(&node.id) {
+                        Some(base) => base,
+                        None => continue,
+                    };
+                    node.size = base;
+                    node.metadata.remove(AGE_OPACITY_KEY);
+                    node.metadata.remove(ARCHIVED_KEY);
+                    self.archived.remove(&node.id);
+                    if let Some(mapped) = self.node_map.get_mut(&node.id) {
+                        mapped.size = base;
+                        mapped.metadata.remove(AGE_OPACITY_KEY);
+                        mapped.metadata.remove(ARCHIVED_KEY);
+                    }
+                    changed.push(NodeAge { node_id: node.id, age_opacity: 1.0, size_scale: 1.0, archived: false });
+                    continue;
+                }
+            };
+
+            let base = *self.unaged_size.entry(node.id).or_insert(node.size);
+            let size = Some(base.unwrap_or(1.0) * age.size_scale);
+            let opacity = format!("{:.3}", age.age_opacity);
+            if node.size == size && node.metadata.get(AGE_OPACITY_KEY) == Some(&opacity) && was_archived == age.archived {
+                continue;
+            }
+            node.size = size;
+            node.metadata.insert(AGE_OPACITY_KEY.to_string(), opacity.clone());
+            if age.archived {
+                node.metadata.insert(ARCHIVED_KEY.to_string(), "true".to_string());
+                self.archived.insert(node.id);
+            } else {
+                node.metadata.remove(ARCHIVED_KEY);
+                self.archived.remove(&node.id);
+            }
+            if let Some(mapped) = self.node_map.get_mut(&node.id) {
+                mapped.size = size;
+                mapped.metadata.insert(AGE_OPACITY_KEY.to_string(), opacity);
+                match node.metadata.get(ARCHIVED_KEY) {
+                    Some(flag) => mapped.metadata.insert(ARCHIVED_KEY.to_string(), flag.clone()),
+                    None => mapped.metadata.remove(ARCHIVED_KEY),
+                };
+            }
+            changed.push(age);
+        }
+        if !changed.is_empty() {
+            self.topology_changed();
+        }
+        changed
+    }
+
+    fn age_and_broadcast(&mut self) -> usize {
+        let changed = self.apply_aging();
+        if !changed.is_empty() {
+            let event = serde_json::json!({
+                "type": "node_metadata_update",
+                "reason": "aging",
+                "nodes": changed,
+            });
+            self.client_manager.do_send(BroadcastMessage { message: event.to_string() });
+        }
+        changed.len()
+    }
+
+    /// Swaps the similarity-typed edges for the current pair set. Edge weight is the
+    /// cosine similarity scaled by the similarity spring multiplier, so these
+    /// springs can be tuned independently of topic edges.
+    pub fn apply_similarity_edges(&mut self) -> usize {
+        let ids: HashMap<&str, u32> = self.graph_data.nodes.iter()
+            .map(|n| (n.metadata_id.as_str(), n.id))
+            .collect();
+        let mut added = Vec::new();
+        let now = Utc::now();
+        for pair in self.similarity_pairs.iter().filter(|_| self.similarity_enabled) {
+            if let (Some(&source), Some(&target)) = (ids.get(pair.source.as_str()), ids.get(pair.target.as_str())) {
+                let mut edge = Edge::new(source, target, pair.similarity * self.similarity_spring_multiplier)
+                    .credited_to(WeightSource::Similarity, now);
+                edge.id = format!("sim-{}-{}", source, target);
+                edge.edge_type = Some(SIMILARITY_EDGE_TYPE.to_string());
+                added.push(edge);
+            }
+        }
+        let count = added.len();
+
+        // The periodic refresh mostly resends the same pairs; leave the generation alone then
+        let mut current = self.graph_data.edges.iter()
+            .filter(|e| e.edge_type.as_deref() == Some(SIMILARITY_EDGE_TYPE));
+        let unchanged = added.iter().all(|edge| current.next().is_some_and(|e| e.id == edge.id && e.weight == edge.weight))
+            && current.next().is_none();
+        if unchanged {
+            return count;
+        }
+
+        self.topology_changed();
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        graph_data_mut.edges.retain(|e| e.edge_type.as_deref() != Some(SIMILARITY_EDGE_TYPE));
+        graph_data_mut.edges.extend(added);
+        count
+    }
+
+    /// Swaps the co-viewed edges for the current pair set; pairs whose nodes are gone are skipped
+    pub fn apply_co_viewed_edges(&mut self) -> usize {
+        // Nothing to add or take away, as always while co-viewed edges are off
+        if self.co_viewed_pairs.is_empty()
+            && !self.graph_data.edges.iter().any(|e| e.edge_type.as_deref() == Some(CO_VIEWED_EDGE_TYPE)) {
+            return 0;
+        }
+        self.topology_changed();
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        graph_data_mut.edges.retain(|e| e.edge_type.as_deref() != Some(CO_VIEWED_EDGE_TYPE));
+
+        let ids: HashMap<&str, u32> = graph_data_mut.nodes.iter()
+            .map(|n| (n.metadata_id.as_str(), n.id))
+            .collect();
+        let now = Utc::now();
+        let added: Vec<Edge> = self.co_viewed_pairs.iter()
+            .filter_map(|pair| {
+                let (&source, &target) = (ids.get(pair.source.as_str())?, ids.get(pair.target.as_str())?);
+                let mut edge = Edge::new(source, target, pair.weight).credited_to(WeightSource::CoSelection, now);
+                edge.id = format!("coview-{}-{}", source, target);
+                edge.edge_type = Some(CO_VIEWED_EDGE_TYPE.to_string());
+                Some(edge)
+            })
+            .collect();
+        let count = added.len();
+        graph_data_mut.edges.extend(added);
+        count
+    }
+
     pub fn update_node_positions(&mut self, positions: Vec<(u32, BinaryNodeData)>) {
         let mut updated_count = 0;
+        let mut moved = false;
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         
         for (node_id, position_data) in positions {
             // Update in node_map
             if let Some(node) = self.node_map.get_mut(&node_id) {
+                let (old, new) = (node.data.position, position_data.position);
+                let (dx, dy, dz) = (new.x - old.x, new.y - old.y, new.z - old.z);
+                moved |= dx * dx + dy * dy + dz * dz > SETTLED_EPSILON_SQ;
                 node.data.position = position_data.position;
                 node.data.velocity = position_data.velocity;
                 updated_count += 1;
@@ -193,6 +773,9 @@ impl GraphServiceActor {
             }
         }
         
+        if moved {
+            self.position_generation += 1;
+        }
         debug!("Updated positions for {} nodes", updated_count);
     }
 
@@ -206,299 +789,3432 @@ impl GraphServiceActor {
         info!("Starting physics simulation loop");
 
         // Start the simulation interval
-        ctx.run_interval(Duration::from_millis(16), |actor, _ctx| {
-            if !actor.simulation_running.load(Ordering::SeqCst) {
+        ctx.run_interval(simulation_clock::TICK, |actor, ctx| {
+            actor.expire_warmup();
+            // A replay keeps playing with physics stopped; it doesn't need it
+            if !actor.simulation_running.load(Ordering::SeqCst) && actor.replay.is_none() {
+                return;
+            }
+            // Deterministic runs only move when stepped
+            if actor.loop_clock.is_virtual() {
                 return;
             }
 
             actor.run_simulation_step();
+            actor.refresh_analytics(ctx);
         });
     }
 
     fn run_simulation_step(&mut self) {
+        // Nobody is watching
+        if self.idle.is_idle() {
+            return;
+        }
+        if self.replay.is_some() {
+            self.step_replay();
+            return;
+        }
+        // Nothing to lay out or send until nodes arrive
+        if self.graph_data.nodes.is_empty() {
+            return;
+        }
+        // A warm-up steps every tick whatever the mode; after that the mode decides
+        let frame = match self.warmup {
+            Some(_) => Some(FrameKind::Delta),
+            None => self.simulation.tick(self.loop_clock.now()),
+        };
+        let Some(frame) = frame else {
+            return;
+        };
+        // An overloaded loop holds physics off for a few frames so the runtime gets a turn;
+        // deterministic runs are paced by their steps instead
+        if !self.loop_clock.is_virtual() {
+            if let BudgetTick::Skip { resend } = self.frame_budget.tick(Instant::now()) {
+                // Held-off frames aren't caught up afterwards, which would undo the back-off
+                self.steps.reset();
+                // Clients keep getting the last positions meanwhile
+                if resend && self.warmup.is_none() {
+                    let snapshot = self.full_keyframe();
+                    self.broadcast_positions(&snapshot, FrameKind::Delta);
+                }
+                return;
+            }
+        }
+        // As many fixed substeps as the real time since the last step covers. A warm-up
+        // isn't watched as it runs, and a loop backing off can't catch up, so both run
+        // one substep a tick.
+        if self.warmup.is_some() || self.frame_budget.backing_off() {
+            self.steps.reset();
+        }
+        let substeps = self.steps.advance(self.loop_clock.now());
+        if substeps == 0 {
+            return;
+        }
+        let step_started = Instant::now();
+
         // Run physics calculation (GPU or CPU fallback)
-        match self.calculate_layout() {
-            Ok(updated_positions) => {
-                if !updated_positions.is_empty() {
-                    // Update positions
-                    self.update_node_positions(updated_positions.clone());
-                    
-                    // Broadcast to clients
-                    if let Ok(binary_data) = self.encode_node_positions(&updated_positions) {
-                        self.client_manager.do_send(BroadcastNodePositions { 
-                            positions: binary_data 
-                        });
+        let mut moved = Vec::new();
+        let mut energy = None;
+        let mut stepped = false;
+        for _ in 0..substeps {
+            match self.calculate_layout() {
+                Ok(mut updated_positions) => {
+                    self.physics_iterations += 1;
+                    self.apply_attention_attraction(&mut updated_positions);
+                    self.hold_pinned_nodes(&mut updated_positions);
+                    self.damp_settling_nodes(&mut updated_positions);
+                    self.damp_disturbance(&mut updated_positions);
+                    energy = self.warmup.is_some()
+                        .then(|| warmup::step_energy(&self.node_map, &updated_positions));
+                    if !updated_positions.is_empty() {
+                        // Update positions
+                        self.update_node_positions(updated_positions.clone());
                     }
+                    merge_substep(&mut moved, updated_positions);
+                    stepped = true;
+                }
+                Err(e) => {
+                    error!("Physics simulation step failed: {}", e);
+                    break;
                 }
             }
-            Err(e) => {
-                error!("Physics simulation step failed: {}", e);
+        }
+        if stepped {
+            self.step_timing = Some(self.steps.timing());
+            match energy {
+                // Nothing goes to clients until the layout is presentable
+                Some(energy) => self.advance_warmup(energy),
+                // A hybrid correction carries every node, not just the ones that moved
+                None if frame == FrameKind::Keyframe => {
+                    let keyframe = self.full_keyframe();
+                    self.broadcast_positions(&keyframe, FrameKind::Keyframe);
+                }
+                None if !moved.is_empty() => {
+                    self.broadcast_positions(&moved, FrameKind::Delta);
+                }
+                None => {}
             }
         }
+        self.step_timing = None;
+        if !self.loop_clock.is_virtual() {
+            self.record_step_time(step_started);
+        }
     }
 
-    fn calculate_layout(&self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
-        // For now, always use CPU fallback since GPU actor communication is async
-        // TODO: Refactor simulation loop to handle async GPU computation properly
-        self.calculate_layout_cpu()
+    fn record_step_time(&mut self, started: Instant) {
+        let now = Instant::now();
+        if !self.frame_budget.record_step(now.duration_since(started), now) {
+            return;
+        }
+        let status = self.frame_budget.status();
+        warn!(
+            "Physics steps of {:.0}ms overrun the {}ms frame budget; {} frames skipped in a row",
+            status.last_step_ms.unwrap_or_default(), status.budget_ms, status.consecutive_skips
+        );
+        if let Some(event_log) = &self.event_log {
+            event_log.record("physics", "frame_budget_exceeded", serde_json::json!({
+                "lastStepMs": status.last_step_ms,
+                "skipFactor": status.skip_factor,
+                "consecutiveSkips": status.consecutive_skips,
+                "nodes": self.graph_data.nodes.len(),
+                "suggestion": FRAME_BUDGET_SUGGESTION,
+            }));
+        }
     }
 
-    /*
-    fn initiate_gpu_computation(&self, ctx: &mut Context<Self>) {
-        // Send GPU computation request if GPU compute actor is available
-        if let Some(ref gpu_compute_addr) = self.gpu_compute_addr {
-            // Update graph data in GPU
-            let graph_data_for_gpu = crate::models::graph::GraphData { // Renamed to avoid conflict
-                nodes: self.graph_data.nodes.clone(),
-                edges: self.graph_data.edges.clone(),
-                metadata: self.graph_data.metadata.clone(), // Include metadata
-                id_to_metadata: self.graph_data.id_to_metadata.clone(), // Include id_to_metadata
-            };
-            
-            gpu_compute_addr.do_send(UpdateGPUGraphData { graph: graph_data_for_gpu });
-            
-            // Request computation
-            let addr = gpu_compute_addr.clone();
-            // Collect node IDs beforehand to avoid borrowing `self` inside the async block
-            let node_ids_in_order: Vec<u32> = self.graph_data.nodes.iter().map(|n| n.id).collect();
-
-            let future = async move {
-                match addr.send(ComputeForces).await {
-                    Ok(Ok(())) => {
-                        // Now get the results
-                        match addr.send(GetNodeData).await {
-                            Ok(Ok(node_data)) => {
-                                // Convert to position updates
-                                let mut positions = Vec::new();
-                                for (index, data) in node_data.iter().enumerate() {
-                                    // Use the pre-collected node_ids_in_order
-                                    if let Some(node_id) = node_ids_in_order.get(index) {
-                                        positions.push((*node_id, data.clone()));
-                                    }
-                                }
-                                positions
-                            },
-                            _ => Vec::new()
-                        }
-                    },
-                    _ => Vec::new()
-                }
-            };
-            
-            // Convert future to ActorFuture and spawn it
-            ctx.wait(future.into_actor(self).map(|positions, actor, _ctx| {
-                if !positions.is_empty() {
-                    actor.update_node_positions(positions.clone());
-                    
-                    // Broadcast to clients
-                    if let Ok(binary_data) = actor.encode_node_positions(&positions) {
-                        actor.client_manager.do_send(BroadcastNodePositions {
-                            positions: binary_data
-                        });
-                    }
-                }
-            }));
+    // Holds broadcasts back after a rebuild until the layout has settled
+    fn begin_warmup(&mut self) {
+        // Without server physics or nodes there is nothing to settle; clients lay the graph out
+        if self.warmup_settings.skip_warmup || !self.simulation.status().server_physics || self.graph_data.nodes.is_empty() {
+            self.warmup = None;
+            self.graph_ready = true;
+            return;
         }
+        info!("Warming up the layout for up to {} steps before broadcasting", self.warmup_settings.max_iterations);
+        self.warmup = Some(Warmup::new(self.warmup_settings.clone(), Instant::now()));
+        self.graph_ready = false;
     }
-    */
 
-    fn calculate_layout_cpu(&self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
-        // Simple CPU physics simulation
-        let mut updated_positions = Vec::new();
-        
-        for node in &self.graph_data.nodes {
-            // Simple physics: apply some random movement for demo
-            let mut new_data = node.data.clone();
-            new_data.position.x += (rand::random::<f32>() - 0.5) * 0.1;
-            new_data.position.y += (rand::random::<f32>() - 0.5) * 0.1;
-            new_data.position.z += (rand::random::<f32>() - 0.5) * 0.1;
-            
-            updated_positions.push((node.id, new_data));
+    fn advance_warmup(&mut self, energy: f32) {
+        let Some(running) = self.warmup.as_mut() else {
+            return;
+        };
+        let done = running.record(energy);
+        let status = running.status();
+        if !done {
+            if running.should_report() {
+                self.client_manager.do_send(BroadcastMessage { message: warmup::progress_message(&status) });
+            }
+            return;
         }
-        
-        Ok(updated_positions)
+        info!("Layout warm-up finished after {} steps (energy {:.5})", status.iterations, energy);
+        self.finish_warmup();
     }
 
-    fn encode_node_positions(&self, positions: &[(u32, BinaryNodeData)]) -> Result<Vec<u8>, String> {
-        // Now binary_protocol expects (u32, BinaryNodeData) directly
-        Ok(binary_protocol::encode_node_data(positions))
+    // Ends a warm-up where it stands: the graph is ready and clients get the layout as is
+    fn finish_warmup(&mut self) {
+        let Some(running) = self.warmup.take() else {
+            return;
+        };
+        self.graph_ready = true;
+        let status = WarmupStatus { ready: true, progress: 1.0, ..running.status() };
+        self.client_manager.do_send(BroadcastMessage { message: warmup::progress_message(&status) });
+        // Clients start from the settled layout
+        let keyframe = self.full_keyframe();
+        self.broadcast_positions(&keyframe, FrameKind::Keyframe);
     }
-}
 
-impl Actor for GraphServiceActor {
-    type Context = Context<Self>;
+    // A warm-up past its wall-clock cap is over whether or not physics got to step
+    fn expire_warmup(&mut self) {
+        if self.warmup.as_ref().is_some_and(|w| w.timed_out(Instant::now())) {
+            warn!("Layout warm-up hit its {}s cap; broadcasting the layout as it stands", self.warmup_settings.max_secs);
+            self.finish_warmup();
+        }
+    }
 
-    fn started(&mut self, ctx: &mut Self::Context) {
-        info!("GraphServiceActor started");
-        self.start_simulation_loop(ctx);
+    pub fn warmup_status(&self) -> WarmupStatus {
+        let status = match &self.warmup {
+            // A settle after waking from idle doesn't make the graph unready
+            Some(warmup) if !self.graph_ready => warmup.status(),
+            _ => WarmupStatus {
+                ready: self.graph_ready,
+                progress: if self.graph_ready { 1.0 } else { 0.0 },
+                iterations: 0,
+                energy: None,
+                empty_graph: false,
+            },
+        };
+        WarmupStatus { empty_graph: self.graph_data.nodes.is_empty(), ..status }
     }
 
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
-        self.simulation_running.store(false, Ordering::SeqCst);
-        self.shutdown_complete.store(true, Ordering::SeqCst);
-        info!("GraphServiceActor stopped");
+    fn schedule_idle_check(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.idle_check.take() {
+            ctx.cancel_future(handle);
+        }
+        // Short idle periods (tests, mostly) need checks to match
+        let idle_after = Duration::from_secs_f32(self.idle.settings().idle_after_secs.max(0.0));
+        let interval = IDLE_CHECK_INTERVAL.min(idle_after / 2).max(Duration::from_millis(10));
+        self.idle_check = Some(ctx.run_interval(interval, |actor, ctx| actor.check_idle(ctx)));
     }
-}
 
-// Message handlers
-impl Handler<GetGraphData> for GraphServiceActor {
-    type Result = Result<GraphData, String>; // Result type changed from Arc<GraphData>
- 
-    fn handle(&mut self, _msg: GetGraphData, _ctx: &mut Self::Context) -> Self::Result {
-        info!("DEBUG_VERIFICATION: GraphServiceActor handling GetGraphData with OWNED data clone strategy.");
-        Ok((*self.graph_data).clone()) // Clones the GraphData itself
+    fn check_idle(&mut self, ctx: &mut Context<Self>) {
+        let fut = self.client_manager.send(GetRoomClientCounts);
+        ctx.spawn(fut.into_actor(self).map(|result, actor, _ctx| {
+            // Every room watches the one shared graph
+            let clients = match result {
+                Ok(counts) => counts.values().sum(),
+                Err(e) => {
+                    warn!("Failed to get client counts for idling: {}", e);
+                    return;
+                }
+            };
+            match actor.idle.observe(clients, Instant::now()) {
+                IdleChange::Sleep => info!(
+                    "No clients for {}s; pausing physics and broadcasts",
+                    actor.idle.settings().idle_after_secs
+                ),
+                IdleChange::Wake => actor.wake_from_idle(),
+                IdleChange::None => {}
+            }
+        }));
     }
-}
 
-impl Handler<UpdateNodePositions> for GraphServiceActor {
-    type Result = Result<(), String>;
+    // The layout was frozen while idle; give it a short settle before clients get a keyframe
+    fn wake_from_idle(&mut self) {
+        info!("Client connected; resuming physics");
+        // The idle gap isn't time physics should catch up, or clients interpolate over
+        self.steps.reset();
+        // A build's warm-up that was interrupted just carries on
+        if !self.graph_ready || self.warmup.is_some() {
+            return;
+        }
+        let iterations = self.idle.settings().wake_settle_iterations;
+        if iterations == 0 || !self.simulation.status().server_physics {
+            let keyframe = self.full_keyframe();
+            self.broadcast_positions(&keyframe, FrameKind::Keyframe);
+            return;
+        }
+        self.warmup = Some(Warmup::new(WarmupSettings {
+            max_iterations: iterations,
+            // Settling progress isn't worth reporting
+            progress_interval: u32::MAX,
+            ..self.warmup_settings.clone()
+        }, Instant::now()));
+    }
 
-    fn handle(&mut self, msg: UpdateNodePositions, _ctx: &mut Self::Context) -> Self::Result {
-        self.update_node_positions(msg.positions);
-        Ok(())
+    pub fn idle_status(&self) -> IdleStatus {
+        self.idle.status(Instant::now(), self.physics_iterations)
     }
-}
 
-impl Handler<AddNode> for GraphServiceActor {
-    type Result = Result<(), String>;
+    /// Feeds the replay's due frames to clients instead of running physics. Replayed
+    /// positions are written into the graph too, so a client that connects mid-replay
+    /// gets the replayed layout as its initial keyframe.
+    fn step_replay(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.replay_clock);
+        self.replay_clock = now;
+        let frames = match self.replay.as_mut() {
+            Some(replay) => replay.advance(elapsed),
+            None => return,
+        };
+        for frame in frames {
+            let positions = binary_protocol::decode_node_data(&frame.payload).unwrap_or_default();
+            self.update_node_positions(positions);
+            // Sent exactly as recorded
+            let priority = self.update_priority();
+            self.client_manager.do_send(BroadcastNodePositions {
+                positions: frame.payload,
+                keyframe: frame.kind == FrameKind::Keyframe,
+                priority,
+                generation: self.graph_data.generation,
+                timing: FrameTiming::unstepped(),
+            });
+        }
+    }
 
-    fn handle(&mut self, msg: AddNode, _ctx: &mut Self::Context) -> Self::Result {
-        self.add_node(msg.node);
-        Ok(())
+    /// Writes a replay keyframe into the graph and pushes it to every client
+    fn apply_replay_keyframe(&mut self, keyframe: Vec<(u32, BinaryNodeData)>) {
+        self.update_node_positions(keyframe.clone());
+        self.broadcast_positions(&keyframe, FrameKind::Keyframe);
     }
-}
 
-impl Handler<RemoveNode> for GraphServiceActor {
-    type Result = Result<(), String>;
+    fn full_keyframe(&self) -> Vec<(u32, BinaryNodeData)> {
+        self.graph_data.nodes.iter()
+            .map(|n| (n.id, n.data))
+            .collect()
+    }
 
-    fn handle(&mut self, msg: RemoveNode, _ctx: &mut Self::Context) -> Self::Result {
-        self.remove_node(msg.node_id);
-        Ok(())
+    /// Sends positions to every client, and to the recording if one is running
+    fn broadcast_positions(&mut self, positions: &[(u32, BinaryNodeData)], kind: FrameKind) {
+        let visible: Vec<(u32, BinaryNodeData)>;
+        let positions = if self.archived.is_empty() {
+            positions
+        } else {
+            visible = positions.iter().filter(|(id, _)| !self.archived.contains(id)).copied().collect();
+            &visible
+        };
+        let binary_data = match self.encode_node_positions(positions) {
+            Ok(binary_data) => binary_data,
+            Err(e) => {
+                error!("Failed to encode positions: {}", e);
+                return;
+            }
+        };
+        self.record_frame(kind, &binary_data);
+        let priority = self.update_priority();
+        self.client_manager.do_send(BroadcastNodePositions {
+            positions: binary_data,
+            keyframe: kind == FrameKind::Keyframe,
+            priority,
+            generation: self.graph_data.generation,
+            timing: self.step_timing.unwrap_or_else(FrameTiming::unstepped),
+        });
     }
-}
 
-impl Handler<AddEdge> for GraphServiceActor {
-    type Result = Result<(), String>;
+    /// Ids of the edges in the graph's skeleton, cached until the topology changes
+    pub fn spanning_skeleton(&mut self, strategy: SkeletonStrategy) -> Arc<Vec<String>> {
+        let generation = self.graph_data.generation;
+        self.skeletons.retain(|_, (computed_at, _)| *computed_at == generation);
+        let edges = &self.graph_data.edges;
+        self.skeletons.entry(strategy)
+            .or_insert_with(|| (generation, Arc::new(skeleton::skeleton_edge_ids(edges, strategy))))
+            .1
+            .clone()
+    }
 
-    fn handle(&mut self, msg: AddEdge, _ctx: &mut Self::Context) -> Self::Result {
-        self.add_edge(msg.edge);
-        Ok(())
+    /// The aggregated community view, cached until the topology changes
+    pub fn community_view(&mut self) -> Arc<GraphView> {
+        let generation = self.graph_data.generation;
+        match &self.community_view {
+            Some(view) if view.generation == generation => view.clone(),
+            _ => {
+                let view = Arc::new(GraphView::aggregate(&self.graph_data));
+                self.community_view = Some(view.clone());
+                view
+            }
+        }
     }
-}
 
-impl Handler<RemoveEdge> for GraphServiceActor {
-    type Result = Result<(), String>;
+    /// PageRank by node id, cached until the topology changes
+    pub fn page_rank(&mut self) -> Arc<HashMap<u32, f32>> {
+        let generation = self.graph_data.generation;
+        match &self.pagerank {
+            Some((ranked_at, ranks)) if *ranked_at == generation => ranks.clone(),
+            _ => {
+                let ranks = Arc::new(coloring::pagerank(&self.graph_data.nodes, &self.graph_data.edges));
+                self.pagerank = Some((generation, ranks.clone()));
+                ranks
+            }
+        }
+    }
 
-    fn handle(&mut self, msg: RemoveEdge, _ctx: &mut Self::Context) -> Self::Result {
-        self.remove_edge(&msg.edge_id);
-        Ok(())
+    // The background refresh's score if it's current; scoring a layout still moving would
+    // only be stale by the next step, so that isn't cached
+    fn layout_quality(&self) -> LayoutQuality {
+        match &self.layout_quality {
+            Some((scored_at, quality)) if *scored_at == self.generations() => *quality,
+            _ => layout_quality::evaluate(&self.graph_data.nodes, &self.graph_data.edges),
+        }
     }
-}
 
-impl Handler<GetNodeMap> for GraphServiceActor {
-    type Result = Result<HashMap<u32, Node>, String>;
+    // Analytics whose cache doesn't match the current graph
+    fn stale_analytics(&self) -> Vec<AnalyticsKind> {
+        let generations = self.generations();
+        AnalyticsKind::ALL.into_iter()
+            .filter(|kind| match kind {
+                AnalyticsKind::PageRank => !matches!(&self.pagerank, Some((at, _)) if *at == generations.generation),
+                AnalyticsKind::Skeleton => !matches!(self.skeletons.get(&SkeletonStrategy::Mst), Some((at, _)) if *at == generations.generation),
+                AnalyticsKind::LayoutQuality => !matches!(&self.layout_quality, Some((at, _)) if *at == generations),
+            })
+            .collect()
+    }
 
-    fn handle(&mut self, _msg: GetNodeMap, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.node_map.clone())
+    // Nothing is still settling after the last change and physics has time to spare; an
+    // idle loop has nothing but time
+    fn layout_settled(&self) -> bool {
+        self.idle.is_idle() || (self.warmup.is_none()
+            && self.settling.is_empty()
+            && self.disturbance.damping(self.loop_clock.now()).is_none()
+            && !self.frame_budget.status().overloaded)
     }
-}
 
-impl Handler<BuildGraphFromMetadata> for GraphServiceActor {
-    type Result = Result<(), String>;
+    // Starts recomputing the most important stale analytics on the blocking pool, if the
+    // layout has settled and nothing else is being refreshed. The layout score waits for
+    // positions to hold still, as it would be stale again by the time it finished.
+    fn refresh_analytics(&mut self, ctx: &mut Context<Self>) {
+        let generations = self.generations();
+        self.analytics.invalidate(generations);
+        let positions_still = std::mem::replace(&mut self.analytics_positions_seen, self.position_generation) == self.position_generation;
+        if !self.analytics.enabled() || !self.graph_ready || !self.layout_settled() {
+            return;
+        }
+        let mut stale = self.stale_analytics();
+        if !positions_still && !self.idle.is_idle() {
+            stale.retain(|kind| *kind != AnalyticsKind::LayoutQuality);
+        }
+        let Some((kind, cancel)) = self.analytics.start(&stale, generations, Instant::now()) else {
+            return;
+        };
+        debug!("Refreshing {:?} in the background at generation {}", kind, generations.generation);
+        let graph = self.graph_data.clone();
+        let work = tokio::task::spawn_blocking(move || match kind {
+            AnalyticsKind::PageRank => pagerank_until(&graph.nodes, &graph.edges, &|| cancel.is_cancelled())
+                .map(|ranks| RefreshedAnalytics::PageRank(Arc::new(ranks))),
+            AnalyticsKind::Skeleton => Some(RefreshedAnalytics::Skeleton(Arc::new(
+                skeleton::skeleton_edge_ids(&graph.edges, SkeletonStrategy::Mst)))),
+            AnalyticsKind::LayoutQuality => Some(RefreshedAnalytics::LayoutQuality(
+                layout_quality::evaluate(&graph.nodes, &graph.edges))),
+        });
+        ctx.spawn(work.into_actor(self).map(move |result, actor, ctx| {
+            let generations = actor.generations();
+            let keep = actor.analytics.finish(kind, generations, Instant::now());
+            match result {
+                Ok(Some(refreshed)) if keep => actor.store_analytics(refreshed, generations),
+                Ok(_) => debug!("Dropped a {:?} refresh overtaken by a change", kind),
+                Err(e) => warn!("Background {:?} refresh failed: {}", kind, e),
+            }
+            // On to the next stale one while the layout stays settled
+            actor.refresh_analytics(ctx);
+        }));
+    }
 
-    fn handle(&mut self, msg: BuildGraphFromMetadata, _ctx: &mut Self::Context) -> Self::Result {
-        self.build_from_metadata(msg.metadata)
+    fn store_analytics(&mut self, refreshed: RefreshedAnalytics, generations: GraphGenerations) {
+        match refreshed {
+            RefreshedAnalytics::PageRank(ranks) => self.pagerank = Some((generations.generation, ranks)),
+            RefreshedAnalytics::Skeleton(edge_ids) => {
+                self.skeletons.retain(|_, (computed_at, _)| *computed_at == generations.generation);
+                self.skeletons.insert(SkeletonStrategy::Mst, (generations.generation, edge_ids));
+            }
+            RefreshedAnalytics::LayoutQuality(quality) => self.layout_quality = Some((generations, quality)),
+        }
     }
-}
 
-impl Handler<StartSimulation> for GraphServiceActor {
-    type Result = Result<(), String>;
+    /// Nodes a throttled client gets in every frame: grabbed and pinned nodes, and hubs
+    fn update_priority(&mut self) -> Arc<HashSet<u32>> {
+        let now = Instant::now();
+        self.grabbed_until.retain(|_, until| *until > now);
+        let generation = self.graph_data.generation;
+        if !matches!(&self.priority_hubs, Some((ranked_at, _)) if *ranked_at == generation) {
+            let ranks = self.page_rank();
+            self.priority_hubs = Some((generation, update_priority::top_ranked(&ranks, PRIORITY_HUB_COUNT)));
+            // Each client's level of detail blends this with its own interest
+            self.client_manager.do_send(SetLodImportance { importance: ranks });
+        }
 
-    fn handle(&mut self, _msg: StartSimulation, ctx: &mut Self::Context) -> Self::Result {
-        self.start_simulation_loop(ctx);
-        Ok(())
+        let mut priority: HashSet<u32> = self.grabbed_until.keys().copied().collect();
+        priority.extend(self.pinned_until.keys().copied());
+        priority.extend(self.priority_hubs.iter().flat_map(|(_, hubs)| hubs.iter().copied()));
+        Arc::new(priority)
     }
-}
 
-impl Handler<StopSimulation> for GraphServiceActor {
-    type Result = Result<(), String>;
+    fn record_frame(&mut self, kind: FrameKind, payload: &[u8]) {
+        let recorder = match self.recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        match recorder.record(kind, payload) {
+            Ok(true) => {}
+            Ok(false) => {
+                if let Some(recorder) = self.recorder.take() {
+                    match recorder.finish() {
+                        Ok(status) => info!("Position recording {:?} reached its cap after {} frames ({} bytes)",
+                            status.path, status.frames, status.bytes),
+                        Err(e) => error!("Failed to finish capped position recording: {}", e),
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Position recording failed, stopping it: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
 
-    fn handle(&mut self, _msg: StopSimulation, _ctx: &mut Self::Context) -> Self::Result {
-        self.simulation_running.store(false, Ordering::SeqCst);
-        Ok(())
+    // Also holds nodes whose physics flags say they are fixed or inactive
+    fn hold_pinned_nodes(&mut self, positions: &mut Vec<(u32, BinaryNodeData)>) {
+        let now = Instant::now();
+        self.pinned_until.retain(|_, until| *until > now);
+        positions.retain(|(node_id, _)| !self.is_held(*node_id));
     }
-}
 
-impl Handler<UpdateNodePosition> for GraphServiceActor {
-    type Result = Result<(), String>;
+    // Pinned, briefly held after an undo, or kept still by its physics flags
+    fn is_held(&self, node_id: u32) -> bool {
+        self.pinned_until.contains_key(&node_id)
+            || self.pinned_nodes.contains(&node_id)
+            || self.node_map.get(&node_id).is_some_and(|n| !physics_flags::moves(n.data.flags))
+    }
 
-    fn handle(&mut self, msg: UpdateNodePosition, _ctx: &mut Self::Context) -> Self::Result {
-        // Update node in the node map
-        if let Some(node) = self.node_map.get_mut(&msg.node_id) {
-            // Preserve existing mass and flags
-            let original_mass = node.data.mass;
-            let original_flags = node.data.flags;
-            
-            node.data.position = glam_to_vec3data(msg.position);
-            node.data.velocity = glam_to_vec3data(msg.velocity);
-            
-            // Restore mass and flags
-            node.data.mass = original_mass;
-            node.data.flags = original_flags;
-        } else {
-            debug!("Received update for unknown node ID: {}", msg.node_id);
-            return Err(format!("Unknown node ID: {}", msg.node_id));
+    fn damp_settling_nodes(&mut self, positions: &mut [(u32, BinaryNodeData)]) {
+        if self.settling.is_empty() {
+            return;
         }
-        
-        // Update corresponding node in graph
+        for (node_id, data) in positions.iter_mut() {
+            if let (true, Some(node)) = (self.settling.contains_key(node_id), self.node_map.get(node_id)) {
+                placement::damp_step(node.data.position, data, SETTLE_DAMPING);
+            }
+        }
+        self.settling.retain(|_, frames| {
+            *frames -= 1;
+            *frames > 0
+        });
+    }
+
+    fn damp_disturbance(&mut self, positions: &mut [(u32, BinaryNodeData)]) {
+        let node_map = &self.node_map;
+        self.disturbance.damp(positions, |node_id| node_map.get(&node_id).map(|n| n.data.position), self.loop_clock.now());
+    }
+
+    // A disturbance landing while the layout is being finalized puts it back into the
+    // dynamic phase, where the ramp settles it
+    fn disturbed(&mut self, started: bool) {
+        if started && self.phase == SimulationPhase::Finalize {
+            info!("Disturbed while finalizing the layout; back to the dynamic phase");
+            self.set_phase(SimulationPhase::Dynamic);
+        }
+    }
+
+    // Edges of the types that changed start or stop pulling on their ends, which is a
+    // topology change to physics and disturbs the layout like an insertion
+    fn set_physics_disabled_types(&mut self, disabled: HashSet<String>) {
+        let changed: HashSet<&str> = disabled.symmetric_difference(&self.physics_disabled_types).map(String::as_str).collect();
+        if changed.is_empty() {
+            return;
+        }
+        let ends: Vec<u32> = self.graph_data.edges.iter()
+            .filter(|e| e.affects_physics && changed.contains(e.type_name()))
+            .flat_map(|e| [e.source, e.target])
+            .collect();
+        self.physics_disabled_types = disabled;
+        info!("Edge types left out of physics: {:?}", self.physics_disabled_type_names());
+        self.topology_changed();
+        let started = self.disturbance.record_rewiring(ends, &self.graph_data.edges, self.loop_clock.now());
+        self.disturbed(started);
+    }
+
+    fn physics_disabled_type_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.physics_disabled_types.iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn set_phase(&mut self, phase: SimulationPhase) {
+        self.phase = phase;
+        if let Some(gpu_compute_addr) = &self.gpu_compute_addr {
+            gpu_compute_addr.do_send(SetSimulationPhase { phase });
+        }
+    }
+
+    /// Puts every persisted pin back on its node after the node set changed. Pins whose
+    /// file has gone are kept, and reported as conflicts until it comes back.
+    fn restore_pins(&mut self) -> usize {
+        self.pinned_nodes.clear();
+        self.pin_conflicts.clear();
+        if self.pins.pins().is_empty() {
+            return 0;
+        }
+        let ids: HashMap<String, u32> = self.graph_data.nodes.iter()
+            .map(|n| (n.metadata_id.clone(), n.id))
+            .collect();
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
-        for node_in_graph_data in &mut graph_data_mut.nodes { // Iterate over mutable graph_data
-            if node_in_graph_data.id == msg.node_id {
-                // Preserve mass and flags
-                let original_mass = node_in_graph_data.data.mass;
-                let original_flags = node_in_graph_data.data.flags;
-                
-                node_in_graph_data.data.position = glam_to_vec3data(msg.position);
-                node_in_graph_data.data.velocity = glam_to_vec3data(msg.velocity);
-                
-                // Restore mass and flags
-                node_in_graph_data.data.mass = original_mass;
-                node_in_graph_data.data.flags = original_flags;
-                break;
+        for (metadata_id, pin) in self.pins.pins() {
+            let Some(&node_id) = ids.get(metadata_id) else {
+                warn!("Pinned node {} has no file in the metadata store", metadata_id);
+                self.pin_conflicts.push(PinConflict {
+                    metadata_id: metadata_id.clone(),
+                    reason: "file no longer in the metadata store".to_string(),
+                });
+                continue;
+            };
+            for node in graph_data_mut.nodes.iter_mut().filter(|n| n.id == node_id)
+                .chain(self.node_map.get_mut(&node_id))
+            {
+                node.data.position = pin.position.into();
+                node.data.velocity = Vec3Data::zero();
             }
+            self.pinned_nodes.insert(node_id);
         }
-        
-        Ok(())
+        self.pin_conflicts.sort_by(|a, b| a.metadata_id.cmp(&b.metadata_id));
+        info!("Restored {} pinned nodes ({} conflicts)", self.pinned_nodes.len(), self.pin_conflicts.len());
+        self.pinned_nodes.len()
     }
-}
 
-impl Handler<SimulationStep> for GraphServiceActor {
-    type Result = Result<(), String>;
+    pub fn pin_report(&self) -> PinReport {
+        let mut pins: Vec<PinInfo> = self.graph_data.nodes.iter()
+            .filter(|n| self.pinned_nodes.contains(&n.id))
+            .filter_map(|n| {
+                let pin = self.pins.get(&n.metadata_id)?;
+                Some(PinInfo {
+                    node_id: n.id,
+                    metadata_id: n.metadata_id.clone(),
+                    position: pin.position,
+                    pinned_at: pin.pinned_at,
+                    source: if self.runtime_pins.contains(&n.metadata_id) { PinSource::Runtime } else { PinSource::Metadata },
+                })
+            })
+            .collect();
+        pins.sort_by_key(|p| p.node_id);
+        PinReport { pins, conflicts: self.pin_conflicts.clone() }
+    }
 
-    fn handle(&mut self, _msg: SimulationStep, _ctx: &mut Self::Context) -> Self::Result {
-        // Just run one simulation step
-        self.run_simulation_step();
-        Ok(())
+    /// Appends to the runtime change journal, if there is one. The records are only built
+    /// when there is, and are built before the change so removals can still be named.
+    fn journal(&mut self, records: impl FnOnce(&Self) -> Vec<JournalRecord>) {
+        if self.journal.is_some() {
+            let records = records(self);
+            if let Some(journal) = &mut self.journal {
+                journal.append(records);
+            }
+        }
     }
-}
 
-impl Handler<UpdateGraphData> for GraphServiceActor {
-    type Result = Result<(), String>;
+    /// Reserves `count` consecutive ids for new runtime nodes and returns the first.
+    /// Nodes added with explicit ids never went through the id map, and nodes replayed
+    /// from the journal keep the `runtime-N` metadata id an earlier run gave them, so the
+    /// ids stay clear of both.
+    fn allocate_runtime_ids(&mut self, count: u32) -> u32 {
+        let highest = self.node_map.values()
+            .flat_map(|n| [Some(n.id), n.metadata_id.strip_prefix("runtime-").and_then(|id| id.parse().ok())])
+            .flatten()
+            .max()
+            .unwrap_or(0);
+        let mut node_ids = self.node_ids.lock().unwrap();
+        let first = node_ids.allocate(highest + 1, count, |id| format!("runtime-{}", id));
+        if let Err(e) = node_ids.save() {
+            error!("Failed to save node ids: {}", e);
+        }
+        drop(node_ids);
+        self.report_id_conflicts();
+        first
+    }
 
-    fn handle(&mut self, msg: UpdateGraphData, _ctx: &mut Self::Context) -> Self::Result {
-        info!("Updating graph data with {} nodes, {} edges",
-              msg.graph_data.nodes.len(), msg.graph_data.edges.len());
-        
-        // Update graph data by creating a new Arc
-        self.graph_data = Arc::new(msg.graph_data);
-        
-        // Rebuild node map
-        self.node_map.clear();
-        for node in &self.graph_data.nodes { // Dereferences Arc for iteration
+    // Collisions with ids another instance handed out, resolved when the map last synced
+    fn report_id_conflicts(&mut self) {
+        let conflicts: Vec<IdConflict> = self.node_ids.lock().unwrap().take_conflicts();
+        if conflicts.is_empty() {
+            return;
+        }
+        if let Some(event_log) = &self.event_log {
+            event_log.record("graph", "node_id_conflicts", serde_json::json!({ "conflicts": conflicts }));
+        }
+        topic_extraction::record_id_conflicts(conflicts);
+    }
+
+    fn metadata_id_of(&self, node_id: u32) -> Option<String> {
+        self.node_map.get(&node_id).map(|n| n.metadata_id.clone())
+    }
+
+    /// Checks links to and from other rooms against the graph just built and puts the
+    /// dangling ones in the build report. Every room shows this one graph, so each room's
+    /// ends are checked against it.
+    fn revalidate_external_links(&self) {
+        let Some(external_links) = &self.external_links else {
+            return;
+        };
+        let metadata_ids: HashSet<String> = self.graph_data.nodes.iter().map(|n| n.metadata_id.clone()).collect();
+        for room in external_links.rooms() {
+            external_links.revalidate(&room, &metadata_ids);
+        }
+        let dangling = external_links.dangling();
+        if !dangling.is_empty() {
+            warn!("{} external links point at documents that are missing", dangling.len());
+        }
+        topic_extraction::record_dangling_external_links(dangling);
+    }
+
+    /// Puts the journaled runtime changes back on a graph just built from metadata, and
+    /// adds what happened to the build report
+    fn replay_journal(&mut self) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        let truncated = journal.take_truncation();
+        if journal.records().is_empty() && truncated.is_none() {
+            return;
+        }
+        let mut node_ids = self.node_ids.lock().unwrap();
+        let mut replay = graph_journal::replay(Arc::make_mut(&mut self.graph_data), journal.records(), &mut |metadata_id| node_ids.id_for(metadata_id));
+        if let Err(e) = node_ids.save() {
+            error!("Failed to save node ids: {}", e);
+        }
+        drop(node_ids);
+        replay.truncated = truncated;
+        for (metadata_id, color) in std::mem::take(&mut replay.color_overrides) {
+            self.color_overrides.insert(metadata_id, color);
+        }
+        self.node_map = self.graph_data.nodes.iter().map(|n| (n.id, n.clone())).collect();
+        self.topology_changed();
+        info!("Replayed {} graph journal records", replay.applied);
+        if !replay.skipped.is_empty() {
+            warn!("Skipped {} graph journal records that no longer apply: {:?}", replay.skipped.len(), replay.skipped);
+        }
+        topic_extraction::record_journal_replay(replay);
+    }
+
+    /// Swaps in a graph a transaction or its undo produced, brings the node map and
+    /// per-node state along and recolours
+    fn commit_graph_edit(&mut self, graph: GraphData, diff: &GraphDiff) {
+        self.journal(|actor| graph_journal::records_for_diff(&actor.graph_data, &graph, diff));
+        self.graph_data = Arc::new(graph);
+        for node_id in &diff.removed_nodes {
+            // Already gone from the graph, this clears the node's per-node state
+            self.remove_node(*node_id);
+        }
+        let touched: HashSet<u32> = diff.added_nodes.iter().map(|n| n.id)
+            .chain(diff.updated_nodes.iter().map(|u| u.node_id))
+            .collect();
+        for node in self.graph_data.nodes.iter().filter(|n| touched.contains(&n.id)) {
             self.node_map.insert(node.id, node.clone());
         }
+        if !diff.updated_nodes.is_empty() {
+            let changes = diff.updated_nodes.iter().map(|u| (u.node_id, WatchEvent::Metadata)).collect();
+            self.client_manager.do_send(NotifyNodeChanges { source: "graph_diff".to_string(), changes });
+        }
+        self.topology_changed();
+        self.position_generation += 1;
+        self.recolor_and_broadcast();
+    }
+
+    /// Moves the node of an undone/redone edit, pins it and pushes the position to clients
+    fn apply_history_step(&mut self, step: &HistoryStep, undo: bool) {
+        for skipped in &step.skipped {
+            info!("Skipping {} of node {} ({}): node no longer exists",
+                if undo { "undo" } else { "redo" }, skipped.node_id, skipped.metadata_id);
+        }
+        let edit = match &step.applied {
+            Some(edit) => edit,
+            None => return,
+        };
+        let mut data = match self.node_map.get(&edit.node_id) {
+            Some(node) => node.data,
+            None => return,
+        };
+        data.position = if undo { edit.before } else { edit.after };
+        data.velocity = Vec3Data { x: 0.0, y: 0.0, z: 0.0 };
+
+        let positions = vec![(edit.node_id, data)];
+        self.update_node_positions(positions.clone());
+        self.pinned_until.insert(edit.node_id, Instant::now() + UNDO_PIN_DURATION);
+        self.broadcast_positions(&positions, FrameKind::Delta);
+    }
+
+    fn apply_attention_attraction(&mut self, positions: &mut [(u32, BinaryNodeData)]) {
+        let now = Instant::now();
+        let node_map = &self.node_map;
+        self.attention.prune(now, |id| node_map.contains_key(&id));
+        if !self.attention_settings.attraction_enabled || self.attention.is_empty() {
+            return;
+        }
+
+        let offsets = self.attention.attraction_offsets(
+            &self.graph_data.nodes,
+            &self.graph_data.edges,
+            self.attention_settings.attraction_strength,
+            now,
+        );
+        for (node_id, data) in positions.iter_mut() {
+            if let Some(offset) = offsets.get(node_id) {
+                data.position.x += offset.x;
+                data.position.y += offset.y;
+                data.position.z += offset.z;
+            }
+        }
+    }
+
+    pub fn graph_stats(&self, lod_limit: usize) -> GraphStats {
+        let now = Instant::now();
+        let attention = self.attention_settings.feed_lod
+            .then_some((&self.attention, self.attention_settings.lod_weight));
+        let mut lod_ranking = rank_lod_importance(&self.graph_data.nodes, &self.graph_data.edges, attention, now);
+        lod_ranking.truncate(lod_limit);
+
+        GraphStats {
+            node_count: self.graph_data.nodes.len(),
+            edge_count: self.graph_data.edges.len(),
+            attention: self.attention.scores(now),
+            lod_ranking,
+            generation: self.graph_data.generation,
+            position_generation: self.position_generation,
+            layout_quality: self.layout_quality(),
+            physics_partitions: crate::services::graph_service::partition_stats(),
+            physics_steps: self.steps.status(),
+            provenance_entries: self.graph_data.edges.iter().map(|e| e.provenance.len()).sum(),
+            provenance_bytes: self.graph_data.edges.iter().map(Edge::provenance_bytes).sum(),
+            analytics_refresh: self.analytics.status(&self.stale_analytics()),
+        }
+    }
+
+    fn calculate_layout(&mut self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+        // For now, always use CPU fallback since GPU actor communication is async
+        // TODO: Refactor simulation loop to handle async GPU computation properly
+        (self.layout)(self)
+    }
+
+    /*
+    fn initiate_gpu_computation(&self, ctx: &mut Context<Self>) {
+        // Send GPU computation request if GPU compute actor is available
+        if let Some(ref gpu_compute_addr) = self.gpu_compute_addr {
+            // Update graph data in GPU
+            let graph_data_for_gpu = crate::models::graph::GraphData { // Renamed to avoid conflict
+                nodes: self.graph_data.nodes.clone(),
+                edges: self.graph_data.edges.clone(),
+                metadata: self.graph_data.metadata.clone(), // Include metadata
+                id_to_metadata: self.graph_data.id_to_metadata.clone(), // Include id_to_metadata
+            };
+            
+            gpu_compute_addr.do_send(UpdateGPUGraphData { graph: graph_data_for_gpu });
+            
+            // Request computation
+            let addr = gpu_compute_addr.clone();
+            // Collect node IDs beforehand to avoid borrowing `self` inside the async block
+            let node_ids_in_order: Vec<u32> = self.graph_data.nodes.iter().map(|n| n.id).collect();
+
+            let future = async move {
+                match addr.send(ComputeForces).await {
+                    Ok(Ok(())) => {
+                        // Now get the results
+                        match addr.send(GetNodeData).await {
+                            Ok(Ok(node_data)) => {
+                                // Convert to position updates
+                                let mut positions = Vec::new();
+                                for (index, data) in node_data.iter().enumerate() {
+                                    // Use the pre-collected node_ids_in_order
+                                    if let Some(node_id) = node_ids_in_order.get(index) {
+                                        positions.push((*node_id, data.clone()));
+                                    }
+                                }
+                                positions
+                            },
+                            _ => Vec::new()
+                        }
+                    },
+                    _ => Vec::new()
+                }
+            };
+            
+            // Convert future to ActorFuture and spawn it
+            ctx.wait(future.into_actor(self).map(|positions, actor, _ctx| {
+                if !positions.is_empty() {
+                    actor.update_node_positions(positions.clone());
+                    
+                    // Broadcast to clients
+                    if let Ok(binary_data) = actor.encode_node_positions(&positions) {
+                        actor.client_manager.do_send(BroadcastNodePositions {
+                            positions: binary_data
+                        });
+                    }
+                }
+            }));
+        }
+    }
+    */
+
+    fn calculate_layout_cpu(&mut self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+        // Simple CPU physics simulation
+        let mut updated_positions = Vec::new();
         
-        info!("Graph data updated successfully");
-        Ok(())
+        for node in &self.graph_data.nodes {
+            // Simple physics: apply some random movement for demo
+            let mut new_data = node.data;
+            new_data.position.x += (self.rng.gen::<f32>() - 0.5) * 0.1;
+            new_data.position.y += (self.rng.gen::<f32>() - 0.5) * 0.1;
+            new_data.position.z += (self.rng.gen::<f32>() - 0.5) * 0.1;
+            
+            updated_positions.push((node.id, new_data));
+        }
+        
+        Ok(updated_positions)
+    }
+
+    fn encode_node_positions(&self, positions: &[(u32, BinaryNodeData)]) -> Result<Vec<u8>, String> {
+        // Now binary_protocol expects (u32, BinaryNodeData) directly
+        Ok(binary_protocol::encode_node_data(positions))
+    }
+}
+
+impl Actor for GraphServiceActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("GraphServiceActor started");
+        self.start_simulation_loop(ctx);
+        self.schedule_idle_check(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.simulation_running.store(false, Ordering::SeqCst);
+        self.shutdown_complete.store(true, Ordering::SeqCst);
+        info!("GraphServiceActor stopped");
+    }
+}
+
+// Message handlers
+impl Handler<GetGraphData> for GraphServiceActor {
+    type Result = Result<GraphData, String>; // Result type changed from Arc<GraphData>
+ 
+    fn handle(&mut self, _msg: GetGraphData, _ctx: &mut Self::Context) -> Self::Result {
+        info!("DEBUG_VERIFICATION: GraphServiceActor handling GetGraphData with OWNED data clone strategy.");
+        Ok((*self.graph_data).clone()) // Clones the GraphData itself
+    }
+}
+
+impl Handler<GetGraphSnapshot> for GraphServiceActor {
+    type Result = Result<GraphSnapshot, String>;
+
+    fn handle(&mut self, _msg: GetGraphSnapshot, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.graph_data.clone())
+    }
+}
+
+impl Handler<UpdateNodePositions> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: UpdateNodePositions, _ctx: &mut Self::Context) -> Self::Result {
+        let moves: Vec<(u32, f32)> = msg.positions.iter()
+            .filter_map(|(id, data)| self.node_map.get(id).map(|n| (*id, glam::Vec3::from(n.data.position).distance(data.position.into()))))
+            .collect();
+        let started = self.disturbance.record_moves(moves, &self.graph_data.edges, self.loop_clock.now());
+        self.update_node_positions(msg.positions);
+        self.disturbed(started);
+        Ok(())
+    }
+}
+
+impl Handler<AddNode> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: AddNode, _ctx: &mut Self::Context) -> Self::Result {
+        self.journal(|_| vec![JournalRecord::AddNode { node: msg.node.clone() }]);
+        let node_id = msg.node.id;
+        self.add_node(msg.node);
+        let started = self.disturbance.record_insertions([node_id], &self.graph_data.edges, self.loop_clock.now());
+        self.disturbed(started);
+        self.recolor_and_broadcast();
+        Ok(())
+    }
+}
+
+impl Handler<CreateNode> for GraphServiceActor {
+    type Result = Result<(Node, Option<Edge>), String>;
+
+    fn handle(&mut self, msg: CreateNode, _ctx: &mut Self::Context) -> Self::Result {
+        let node_id = self.allocate_runtime_ids(1);
+        let metadata_id = format!("runtime-{}", node_id);
+        let mut node = Node::new_with_id(metadata_id.clone(), Some(node_id)).with_label(msg.label);
+        node.metadata = msg.metadata;
+        node.metadata.insert("metadataId".to_string(), metadata_id);
+
+        // A stale link target (node removed by a rebuild) just leaves the new node unlinked
+        let link = msg.link_to.and_then(|id| self.node_map.get(&id).map(|n| (id, n.data.position)));
+        if let Some((_, position)) = link {
+            // Start next to the node it's linked to rather than at the origin
+            node.data.position = position;
+            node.data.position.x += 1.0;
+            node.data.position.y += 1.0;
+        }
+        self.add_node(node.clone());
+
+        let edge = link.map(|(target, _)| {
+            let mut edge = Edge::new(node_id, target, 1.0).credited_to(WeightSource::Manual, Utc::now());
+            edge.edge_type = Some(msg.edge_type);
+            edge
+        });
+        if let Some(edge) = &edge {
+            self.add_edge(edge.clone());
+        }
+        let started = self.disturbance.record_insertions([node_id], &self.graph_data.edges, self.loop_clock.now());
+        self.disturbed(started);
+        self.recolor_and_broadcast();
+        let node = self.node_map.get(&node_id).cloned().unwrap_or(node);
+        self.journal(|actor| {
+            let mut records = vec![JournalRecord::AddNode { node: node.clone() }];
+            if let Some((edge, target)) = edge.as_ref().zip(edge.as_ref().and_then(|e| actor.metadata_id_of(e.target))) {
+                records.push(JournalRecord::AddEdge { source: node.metadata_id.clone(), target, edge: edge.clone() });
+            }
+            records
+        });
+        info!("Created runtime node {} ({})", node_id, node.label);
+        Ok((node, edge))
+    }
+}
+
+impl Handler<RemoveNode> for GraphServiceActor {
+    type Result = Result<GraphDiff, String>;
+
+    fn handle(&mut self, msg: RemoveNode, _ctx: &mut Self::Context) -> Self::Result {
+        let node_id = msg.node_id;
+        let rewired = if msg.rewire {
+            rewire::rewire_through(&self.graph_data.edges, node_id, &self.rewire, Utc::now()).unwrap_or_else(|e| {
+                warn!("Removing without rewiring: {}", e);
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let present = self.graph_data.nodes.iter().any(|n| n.id == node_id);
+        let removed_edges: Vec<String> = self.graph_data.edges.iter()
+            .filter(|e| e.source == node_id || e.target == node_id)
+            .map(|e| e.id.clone())
+            .collect();
+        self.journal(|actor| {
+            let mut records: Vec<JournalRecord> = rewired.iter().filter_map(|edge| Some(JournalRecord::AddEdge {
+                source: actor.metadata_id_of(edge.source)?,
+                target: actor.metadata_id_of(edge.target)?,
+                edge: edge.clone(),
+            })).collect();
+            records.extend(actor.metadata_id_of(node_id).map(|metadata_id| JournalRecord::RemoveNode { metadata_id }));
+            records
+        });
+        self.remove_node(node_id);
+        for edge in &rewired {
+            self.add_edge(edge.clone());
+        }
+        self.recolor_and_broadcast();
+
+        let diff = GraphDiff {
+            removed_nodes: if present { vec![node_id] } else { Vec::new() },
+            removed_edges,
+            added_edges: rewired,
+            generation: Some(self.graph_data.generation),
+            ..Default::default()
+        };
+        if !diff.is_empty() {
+            self.client_manager.do_send(BroadcastMessage { message: diff.to_event("remove").to_string() });
+        }
+        Ok(diff)
+    }
+}
+
+impl Handler<AddEdge> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, mut msg: AddEdge, _ctx: &mut Self::Context) -> Self::Result {
+        // Edges posted without a trail were made by hand
+        if msg.edge.provenance.is_empty() {
+            msg.edge = msg.edge.credited_to(WeightSource::Manual, Utc::now());
+        }
+        self.journal(|actor| match (actor.metadata_id_of(msg.edge.source), actor.metadata_id_of(msg.edge.target)) {
+            (Some(source), Some(target)) => vec![JournalRecord::AddEdge { source, target, edge: msg.edge.clone() }],
+            _ => Vec::new(),
+        });
+        self.add_edge(msg.edge);
+        // Only PageRank colouring depends on edges
+        self.recolor_and_broadcast();
+        Ok(())
+    }
+}
+
+impl Handler<RemoveEdge> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: RemoveEdge, _ctx: &mut Self::Context) -> Self::Result {
+        self.journal(|actor| {
+            let ends = actor.graph_data.edges.iter().find(|e| e.id == msg.edge_id)
+                .and_then(|e| Some((actor.metadata_id_of(e.source)?, actor.metadata_id_of(e.target)?)));
+            ends.map(|(source, target)| JournalRecord::RemoveEdge { source, target }).into_iter().collect()
+        });
+        self.remove_edge(&msg.edge_id);
+        self.recolor_and_broadcast();
+        Ok(())
+    }
+}
+
+impl Handler<GetNodeMap> for GraphServiceActor {
+    type Result = Result<HashMap<u32, Node>, String>;
+
+    fn handle(&mut self, _msg: GetNodeMap, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.node_map.clone())
+    }
+}
+
+impl Handler<GetEgoNetwork> for GraphServiceActor {
+    type Result = Result<HashSet<u32>, String>;
+
+    fn handle(&mut self, msg: GetEgoNetwork, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.node_map.contains_key(&msg.node_id) {
+            return Err(format!("Unknown node ID: {}", msg.node_id));
+        }
+        Ok(update_priority::ego_network(msg.node_id, &self.graph_data.edges))
+    }
+}
+
+impl Handler<BuildGraphFromMetadata> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: BuildGraphFromMetadata, _ctx: &mut Self::Context) -> Self::Result {
+        self.build_from_metadata(msg.metadata)
+    }
+}
+
+impl Handler<StartSimulation> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: StartSimulation, ctx: &mut Self::Context) -> Self::Result {
+        if self.shutdown_complete.load(Ordering::SeqCst) {
+            return Err("Graph service is shut down".to_string());
+        }
+        self.start_simulation_loop(ctx);
+        Ok(())
+    }
+}
+
+impl Handler<StopSimulation> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: StopSimulation, _ctx: &mut Self::Context) -> Self::Result {
+        self.simulation_running.store(false, Ordering::SeqCst);
+        // With physics stopped a warm-up would never finish
+        self.finish_warmup();
+        Ok(())
+    }
+}
+
+impl Handler<ShutdownGraph> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: ShutdownGraph, _ctx: &mut Self::Context) -> Self::Result {
+        self.simulation_running.store(false, Ordering::SeqCst);
+        self.shutdown_complete.store(true, Ordering::SeqCst);
+        self.replay = None;
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish()?;
+        }
+        info!("GraphServiceActor simulation stopped for shutdown ({} nodes)", self.graph_data.nodes.len());
+        Ok(())
+    }
+}
+
+impl Handler<UpdateNodePosition> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: UpdateNodePosition, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(editor) = &msg.edited_by {
+            if self.node_locks.is_held_by_other(msg.node_id, editor.client_id, Instant::now()) {
+                return Err(format!("Node {} is being moved by another client", msg.node_id));
+            }
+            if self.pinned_nodes.contains(&msg.node_id) {
+                return Err(format!("Node {} is pinned; unpin it before moving it", msg.node_id));
+            }
+        }
+
+        let displacement = self.node_map.get(&msg.node_id)
+            .map(|n| glam::Vec3::from(n.data.position).distance(msg.position));
+
+        // Update node in the node map
+        if let Some(node) = self.node_map.get_mut(&msg.node_id) {
+            let before = node.data.position;
+            let after = glam_to_vec3data(msg.position);
+            let moved = before.x != after.x || before.y != after.y || before.z != after.z;
+            if moved {
+                self.position_generation += 1;
+            }
+            if let Some(editor) = &msg.edited_by {
+                self.grabbed_until.insert(msg.node_id, Instant::now() + GRAB_PRIORITY_DURATION);
+                if moved {
+                    self.position_history.record(&editor.room, PositionEdit {
+                        node_id: msg.node_id,
+                        metadata_id: node.metadata_id.clone(),
+                        before,
+                        after,
+                        client: editor.client_id,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                    });
+                }
+            }
+
+            // Preserve existing mass and flags
+            let original_mass = node.data.mass;
+            let original_flags = node.data.flags;
+            
+            node.data.position = glam_to_vec3data(msg.position);
+            node.data.velocity = glam_to_vec3data(msg.velocity);
+            
+            // Restore mass and flags
+            node.data.mass = original_mass;
+            node.data.flags = original_flags;
+        } else {
+            debug!("Received update for unknown node ID: {}", msg.node_id);
+            return Err(format!("Unknown node ID: {}", msg.node_id));
+        }
+        
+        // Update corresponding node in graph
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        for node_in_graph_data in &mut graph_data_mut.nodes { // Iterate over mutable graph_data
+            if node_in_graph_data.id == msg.node_id {
+                // Preserve mass and flags
+                let original_mass = node_in_graph_data.data.mass;
+                let original_flags = node_in_graph_data.data.flags;
+                
+                node_in_graph_data.data.position = glam_to_vec3data(msg.position);
+                node_in_graph_data.data.velocity = glam_to_vec3data(msg.velocity);
+                
+                // Restore mass and flags
+                node_in_graph_data.data.mass = original_mass;
+                node_in_graph_data.data.flags = original_flags;
+                break;
+            }
+        }
+        let moves = displacement.map(|d| (msg.node_id, d));
+        let started = self.disturbance.record_moves(moves, &self.graph_data.edges, self.loop_clock.now());
+        self.disturbed(started);
+
+        Ok(())
+    }
+}
+
+impl Handler<TransformNodes> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: TransformNodes, _ctx: &mut Self::Context) -> Self::Result {
+        let node_ids = group_transform::dedup_group(&msg.node_ids);
+        if node_ids.is_empty() {
+            return Err("No nodes to transform".to_string());
+        }
+        if node_ids.len() > MAX_GROUP_SIZE {
+            return Err(format!("At most {} nodes can be moved at once", MAX_GROUP_SIZE));
+        }
+        let transform = RigidTransform::new(msg.translation, msg.rotation, msg.pivot)?;
+        if let Some(unknown) = node_ids.iter().find(|id| !self.node_map.contains_key(id)) {
+            return Err(format!("Unknown node ID: {}", unknown));
+        }
+
+        let now = Instant::now();
+        let until = now + msg.settle;
+        self.node_locks.prune(now);
+        if let Some(editor) = &msg.edited_by {
+            self.node_locks.try_lock_all(editor.client_id, &node_ids, until, now)
+                .map_err(|held| format!("Nodes are being moved by another client: {:?}", held))?;
+        }
+
+        // Every position comes from the same transform, so intra-group offsets hold
+        // exactly; the group isn't put on the undo stack, which is per node
+        let positions: Vec<(u32, BinaryNodeData)> = node_ids.iter()
+            .map(|id| {
+                let mut data = self.node_map[id].data;
+                data.position = glam_to_vec3data(transform.apply(data.position.into()));
+                data.velocity = Vec3Data { x: 0.0, y: 0.0, z: 0.0 };
+                (*id, data)
+            })
+            .collect();
+        let moves: Vec<(u32, f32)> = positions.iter()
+            .map(|(id, data)| (*id, glam::Vec3::from(self.node_map[id].data.position).distance(data.position.into())))
+            .collect();
+        let started = self.disturbance.record_moves(moves, &self.graph_data.edges, self.loop_clock.now());
+        self.update_node_positions(positions.clone());
+        self.disturbed(started);
+        for id in &node_ids {
+            self.pinned_until.insert(*id, until);
+            self.grabbed_until.insert(*id, now + GRAB_PRIORITY_DURATION);
+        }
+        // Delta frames aren't thinned for nodes in the priority set, so the whole group
+        // lands in the same frame for every client
+        self.broadcast_positions(&positions, FrameKind::Delta);
+        Ok(positions.len())
+    }
+}
+
+impl Handler<GetLayout> for GraphServiceActor {
+    type Result = Result<(u64, Vec<NodeLayout>), String>;
+
+    fn handle(&mut self, _msg: GetLayout, _ctx: &mut Self::Context) -> Self::Result {
+        let nodes = self.graph_data.nodes.iter()
+            .map(|n| NodeLayout { metadata_id: n.metadata_id.clone(), position: n.data.position })
+            .collect();
+        Ok((self.position_generation, nodes))
+    }
+}
+
+impl Handler<GetGenerations> for GraphServiceActor {
+    type Result = Result<GraphGenerations, String>;
+
+    fn handle(&mut self, _msg: GetGenerations, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.generations())
+    }
+}
+
+impl Handler<QuerySpatial> for GraphServiceActor {
+    type Result = Result<SpatialResult, String>;
+
+    fn handle(&mut self, msg: QuerySpatial, _ctx: &mut Self::Context) -> Self::Result {
+        let (hits, total) = self.spatial_index().query(&msg.query, msg.limit);
+        let nodes = hits.into_iter()
+            .map(|hit| SpatialNode {
+                id: hit.node_id,
+                label: self.node_map.get(&hit.node_id).map(|n| n.label.clone()).unwrap_or_default(),
+                distance: hit.distance,
+            })
+            .collect::<Vec<_>>();
+        Ok(SpatialResult { generations: self.generations(), total, truncated: nodes.len() < total, nodes })
+    }
+}
+
+impl Handler<WarmStartLayout> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: WarmStartLayout, _ctx: &mut Self::Context) -> Self::Result {
+        let saved: HashMap<&str, Vec3Data> = msg.nodes.iter()
+            .map(|n| (n.metadata_id.as_str(), n.position))
+            .collect();
+        // Nodes without a saved position keep their current one
+        let positions: Vec<(u32, BinaryNodeData)> = self.graph_data.nodes.iter()
+            .filter_map(|n| saved.get(n.metadata_id.as_str()).map(|pos| {
+                let mut data = n.data;
+                data.position = *pos;
+                data.velocity = Vec3Data { x: 0.0, y: 0.0, z: 0.0 };
+                (n.id, data)
+            }))
+            .collect();
+        let placed = positions.len();
+        self.update_node_positions(positions);
+        self.position_generation += 1;
+
+        // Keyframe: every node, not just the ones that moved
+        let keyframe = self.full_keyframe();
+        self.broadcast_positions(&keyframe, FrameKind::Keyframe);
+        info!("Warm-started layout: placed {} of {} nodes", placed, self.graph_data.nodes.len());
+        Ok(placed)
+    }
+}
+
+impl Handler<UndoPositionEdit> for GraphServiceActor {
+    type Result = Result<HistoryStep, String>;
+
+    fn handle(&mut self, msg: UndoPositionEdit, _ctx: &mut Self::Context) -> Self::Result {
+        let node_map = &self.node_map;
+        let step = self.position_history.undo(&msg.room, |id, metadata_id| {
+            node_map.get(&id).is_some_and(|n| n.metadata_id == metadata_id)
+        });
+        self.apply_history_step(&step, true);
+        Ok(step)
+    }
+}
+
+impl Handler<RedoPositionEdit> for GraphServiceActor {
+    type Result = Result<HistoryStep, String>;
+
+    fn handle(&mut self, msg: RedoPositionEdit, _ctx: &mut Self::Context) -> Self::Result {
+        let node_map = &self.node_map;
+        let step = self.position_history.redo(&msg.room, |id, metadata_id| {
+            node_map.get(&id).is_some_and(|n| n.metadata_id == metadata_id)
+        });
+        self.apply_history_step(&step, false);
+        Ok(step)
+    }
+}
+
+impl Handler<SimulationStep> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, _msg: SimulationStep, ctx: &mut Self::Context) -> Self::Result {
+        // Just run one simulation step, a tick after the last on a virtual clock
+        self.loop_clock.advance();
+        self.run_simulation_step();
+        self.refresh_analytics(ctx);
+        Ok(())
+    }
+}
+
+impl Handler<UpdateGraphData> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: UpdateGraphData, _ctx: &mut Self::Context) -> Self::Result {
+        info!("Updating graph data with {} nodes, {} edges",
+              msg.graph_data.nodes.len(), msg.graph_data.edges.len());
+        
+        // Update graph data by creating a new Arc
+        let mut graph_data = msg.graph_data;
+        graph_data.generation = graph_data.generation.max(self.graph_data.generation) + 1;
+        self.graph_data = Arc::new(graph_data);
+        
+        // Rebuild node map
+        self.node_map.clear();
+        for node in &self.graph_data.nodes { // Dereferences Arc for iteration
+            self.node_map.insert(node.id, node.clone());
+        }
+        self.replay_journal();
+        self.revalidate_external_links();
+        self.position_generation += 1;
+        self.apply_similarity_edges();
+        self.apply_co_viewed_edges();
+        self.apply_colors();
+        let graph_data = &self.graph_data;
+        self.unaged_size.retain(|id, _| graph_data.nodes.iter().any(|n| n.id == *id));
+        self.archived.clear();
+        self.apply_aging();
+        self.restore_pins();
+        self.begin_warmup();
+        
+        info!("Graph data updated successfully");
+        Ok(())
+    }
+}
+
+impl Handler<RecordGazeFocus> for GraphServiceActor {
+    type Result = Result<f32, String>;
+
+    fn handle(&mut self, msg: RecordGazeFocus, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.node_map.contains_key(&msg.node_id) {
+            return Err(format!("Node {} not found", msg.node_id));
+        }
+        if let Some(viewpoint) = msg.viewpoint {
+            self.attention.set_viewpoint(viewpoint);
+        }
+        Ok(self.attention.record(msg.node_id, msg.dwell_ms, Instant::now()))
+    }
+}
+
+impl Handler<GetGraphStats> for GraphServiceActor {
+    type Result = Result<GraphStats, String>;
+
+    fn handle(&mut self, msg: GetGraphStats, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.graph_stats(msg.lod_limit))
+    }
+}
+
+impl Handler<UpdateAttentionSettings> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateAttentionSettings, _ctx: &mut Self::Context) -> Self::Result {
+        let half_life = Duration::from_secs_f32(msg.settings.half_life_secs.max(0.1));
+        self.attention.set_half_life(half_life, Instant::now());
+        self.attention_settings = msg.settings;
+    }
+}
+
+impl Handler<UpdateNodeMetadata> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: UpdateNodeMetadata, _ctx: &mut Self::Context) -> Self::Result {
+        let node_id = self.node_map.values()
+            .find(|n| n.metadata_id == msg.metadata_id)
+            .map(|n| n.id)
+            .ok_or_else(|| format!("Node {} not found", msg.metadata_id))?;
+
+        // Tag updates also move the node between groups
+        let group = msg.entries.get("tags")
+            .map(|tags| tags.split(',').next().filter(|t| !t.is_empty()).map(str::to_string));
+        if let Some(node) = self.node_map.get_mut(&node_id) {
+            node.metadata.extend(msg.entries.clone());
+            if let Some(group) = &group {
+                node.group = group.clone();
+            }
+        }
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        if let Some(node) = graph_data_mut.nodes.iter_mut().find(|n| n.id == node_id) {
+            node.metadata.extend(msg.entries);
+            if let Some(group) = group {
+                node.group = group;
+            }
+        }
+        self.topology_changed();
+        // Tags and enrichment aren't broadcast, so only the node's watchers hear of them
+        self.client_manager.do_send(NotifyNodeChanges {
+            source: "metadata".to_string(),
+            changes: vec![(node_id, WatchEvent::Metadata)],
+        });
+        self.recolor_and_broadcast();
+        // A newer lastModified brings an aged or archived node straight back
+        self.age_and_broadcast();
+        Ok(())
+    }
+}
+
+impl Handler<SetSimilarityEdges> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: SetSimilarityEdges, _ctx: &mut Self::Context) -> Self::Result {
+        self.similarity_pairs = msg.pairs;
+        self.similarity_enabled = msg.enabled;
+        self.similarity_spring_multiplier = msg.spring_multiplier;
+        let count = self.apply_similarity_edges();
+        info!("Applied {} similarity edges (enabled: {})", count, msg.enabled);
+        self.recolor_and_broadcast();
+        Ok(count)
+    }
+}
+
+impl Handler<SetCoViewedEdges> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: SetCoViewedEdges, _ctx: &mut Self::Context) -> Self::Result {
+        self.co_viewed_pairs = msg.pairs;
+        let count = self.apply_co_viewed_edges();
+        info!("Applied {} co-viewed edges", count);
+        self.recolor_and_broadcast();
+        Ok(count)
+    }
+}
+
+impl Handler<SetColorMapping> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: SetColorMapping, _ctx: &mut Self::Context) -> Self::Result {
+        self.color_mapping = msg.mapping;
+        let changed = self.recolor_and_broadcast();
+        info!("Colour mapping set to {:?}; {} nodes recoloured", self.color_mapping.strategy, changed);
+        Ok(changed)
+    }
+}
+
+impl Handler<SetAgingSettings> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: SetAgingSettings, ctx: &mut Self::Context) -> Self::Result {
+        self.aging = msg.settings;
+        if let Some(timer) = self.aging_timer.take() {
+            ctx.cancel_future(timer);
+        }
+        if self.aging.enabled {
+            let interval = Duration::from_secs(self.aging.interval_secs.max(60));
+            self.aging_timer = Some(ctx.run_interval(interval, |actor, _ctx| {
+                actor.age_and_broadcast();
+            }));
+        }
+        let changed = self.age_and_broadcast();
+        info!("Aging {}; {} nodes changed, {} archived",
+            if self.aging.enabled { "enabled" } else { "disabled" }, changed, self.archived.len());
+        Ok(self.archived.len())
+    }
+}
+
+impl Handler<SetEdgeWeightSettings> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: SetEdgeWeightSettings, _ctx: &mut Self::Context) -> Self::Result {
+        if !(msg.settings.min_weight.is_finite() && msg.settings.max_weight.is_finite()) {
+            return Err("Edge weight bounds must be finite".to_string());
+        }
+        if msg.settings.min_weight > msg.settings.max_weight {
+            return Err(format!("min_weight {} is above max_weight {}", msg.settings.min_weight, msg.settings.max_weight));
+        }
+        self.edge_weights = msg.settings;
+        let changed = edge_weights::reweight_edges(&mut Arc::make_mut(&mut self.graph_data).edges, &self.edge_weights);
+        if changed > 0 {
+            self.topology_changed();
+        }
+        info!("Edge weight transform set to {:?}; {} edges reweighted", self.edge_weights.transform, changed);
+        Ok(changed)
+    }
+}
+
+impl Handler<SetEdgeTypePhysics> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetEdgeTypePhysics, _ctx: &mut Self::Context) -> Self::Result {
+        self.set_physics_disabled_types(msg.disabled.into_iter().collect());
+        Ok(())
+    }
+}
+
+impl Handler<ToggleEdgeTypePhysics> for GraphServiceActor {
+    type Result = Result<Vec<String>, String>;
+
+    fn handle(&mut self, msg: ToggleEdgeTypePhysics, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(edge_type) = msg.changes.keys().find(|t| t.trim().is_empty()) {
+            return Err(format!("invalid edge type '{}'", edge_type));
+        }
+        let mut disabled = self.physics_disabled_types.clone();
+        for (edge_type, affects_physics) in msg.changes {
+            if affects_physics {
+                disabled.remove(&edge_type);
+            } else {
+                disabled.insert(edge_type);
+            }
+        }
+        self.set_physics_disabled_types(disabled);
+        Ok(self.physics_disabled_type_names())
+    }
+}
+
+impl Handler<GetEdgeTypePhysics> for GraphServiceActor {
+    type Result = Vec<String>;
+
+    fn handle(&mut self, _msg: GetEdgeTypePhysics, _ctx: &mut Self::Context) -> Self::Result {
+        self.physics_disabled_type_names()
+    }
+}
+
+impl Handler<SetEdgeDecaySettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetEdgeDecaySettings, _ctx: &mut Self::Context) -> Self::Result {
+        self.edge_decay = msg.settings;
+        Ok(())
+    }
+}
+
+impl Handler<DecayEdges> for GraphServiceActor {
+    type Result = Result<GraphDiff, String>;
+
+    fn handle(&mut self, msg: DecayEdges, _ctx: &mut Self::Context) -> Self::Result {
+        if self.edge_decay.half_life_days.is_empty() {
+            return Ok(GraphDiff::default());
+        }
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        let result = edge_decay::decay_edges(
+            &mut graph_data_mut.edges,
+            &self.edge_decay.half_life_days,
+            self.edge_decay.floor,
+            msg.elapsed_secs,
+        );
+        if result.updated.is_empty() && result.removed.is_empty() {
+            return Ok(GraphDiff::default());
+        }
+        self.topology_changed();
+        // Decay never touches nodes, so the removed edges still resolve against the live graph
+        self.journal(|actor| graph_journal::records_for_edge_changes(&actor.graph_data, &result.updated, &result.removed));
+        let diff = GraphDiff {
+            updated_edges: result.updated,
+            removed_edges: result.removed.into_iter().map(|e| e.id).collect(),
+            generation: Some(self.graph_data.generation),
+            ..Default::default()
+        };
+        debug!("Edge decay weakened {} edges and removed {}", diff.updated_edges.len(), diff.removed_edges.len());
+        self.client_manager.do_send(BroadcastMessage { message: diff.to_event("decay").to_string() });
+        Ok(diff)
+    }
+}
+
+impl Handler<SetRewireSettings> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetRewireSettings, _ctx: &mut Self::Context) -> Self::Result {
+        self.rewire = msg.settings;
+    }
+}
+
+impl Handler<SetWarmupSettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetWarmupSettings, _ctx: &mut Self::Context) -> Self::Result {
+        // A skip takes effect straight away; otherwise the new settings apply from the next rebuild
+        if msg.settings.skip_warmup {
+            self.finish_warmup();
+        }
+        self.warmup_settings = msg.settings;
+        Ok(())
+    }
+}
+
+impl Handler<GetWarmupStatus> for GraphServiceActor {
+    type Result = Result<WarmupStatus, String>;
+
+    fn handle(&mut self, _msg: GetWarmupStatus, _ctx: &mut Self::Context) -> Self::Result {
+        self.expire_warmup();
+        Ok(self.warmup_status())
+    }
+}
+
+impl Handler<SetIdleSettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetIdleSettings, ctx: &mut Self::Context) -> Self::Result {
+        if self.idle.set_settings(msg.settings) == IdleChange::Wake {
+            self.wake_from_idle();
+        }
+        self.schedule_idle_check(ctx);
+        Ok(())
+    }
+}
+
+impl Handler<SetAnalyticsRefreshSettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetAnalyticsRefreshSettings, _ctx: &mut Self::Context) -> Self::Result {
+        self.analytics.set_settings(msg.settings);
+        Ok(())
+    }
+}
+
+impl Handler<SetSimulationSettings> for GraphServiceActor {
+    type Result = Result<SimulationModeStatus, String>;
+
+    fn handle(&mut self, msg: SetSimulationSettings, _ctx: &mut Self::Context) -> Self::Result {
+        simulation_clock::validate(&msg.settings)?;
+        let previous = self.simulation.mode();
+        // Entering deterministic mode, or changing its seed, starts the sequence over; a
+        // virtual clock carries on so frame timing never runs backwards
+        let reseed = msg.settings.deterministic
+            && (!self.loop_clock.is_virtual() || msg.settings.seed != self.simulation.settings().seed);
+        if reseed {
+            self.rng = StdRng::seed_from_u64(msg.settings.seed);
+        } else if !msg.settings.deterministic && self.loop_clock.is_virtual() {
+            self.rng = StdRng::from_entropy();
+        }
+        if msg.settings.deterministic != self.loop_clock.is_virtual() {
+            self.loop_clock = LoopClock::for_settings(&msg.settings, Instant::now());
+            self.steps.reset();
+        }
+        self.steps.set_max_substeps(msg.settings.max_substeps);
+        self.simulation.set_settings(msg.settings, self.loop_clock.now());
+        let status = self.simulation.status();
+        if status.mode != previous {
+            info!("Simulation mode switched from {:?} to {:?}", previous, status.mode);
+        }
+        self.client_manager.do_send(BroadcastMessage { message: status.to_event() });
+
+        // Clients take over a layout that was still warming up as it stands
+        if !status.server_physics && self.warmup.take().is_some() {
+            self.graph_ready = true;
+        }
+        // Entering or leaving local mode, clients start over from the server's layout:
+        // the seed for their own simulation, or the end of it
+        let local_changed = (previous == SimulationMode::Local) != (status.mode == SimulationMode::Local);
+        if local_changed && self.graph_ready && self.warmup.is_none() {
+            let keyframe = self.full_keyframe();
+            self.broadcast_positions(&keyframe, FrameKind::Keyframe);
+        }
+        Ok(status)
+    }
+}
+
+impl Handler<GetSimulationSettings> for GraphServiceActor {
+    type Result = Result<SimulationSettings, String>;
+
+    fn handle(&mut self, _msg: GetSimulationSettings, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.simulation.settings().clone())
+    }
+}
+
+impl Handler<SetFrameBudgetSettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetFrameBudgetSettings, _ctx: &mut Self::Context) -> Self::Result {
+        self.frame_budget.set_settings(msg.settings);
+        Ok(())
+    }
+}
+
+impl Handler<SetDisturbanceSettings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetDisturbanceSettings, _ctx: &mut Self::Context) -> Self::Result {
+        self.disturbance.set_settings(msg.settings);
+        Ok(())
+    }
+}
+
+impl Handler<GetDisturbanceStatus> for GraphServiceActor {
+    type Result = MessageResult<GetDisturbanceStatus>;
+
+    fn handle(&mut self, _msg: GetDisturbanceStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.disturbance.status(self.loop_clock.now(), self.phase))
+    }
+}
+
+impl Handler<ReheatLayout> for GraphServiceActor {
+    type Result = Result<ReheatOutcome, String>;
+
+    fn handle(&mut self, msg: ReheatLayout, _ctx: &mut Self::Context) -> Self::Result {
+        let request = msg.request;
+        if let Err((field, reason)) = request.validate() {
+            return Err(format!("{} {}", field, reason));
+        }
+        let matched = match &request.scope {
+            ReheatScope::Global => self.graph_data.nodes.iter().map(|n| n.id).collect(),
+            ReheatScope::Filter(filter) => filter.select(&self.graph_data.nodes),
+        };
+        let now = Instant::now();
+        self.pinned_until.retain(|_, until| *until > now);
+        let (kicked, held): (Vec<u32>, Vec<u32>) = matched.into_iter().partition(|id| !self.is_held(*id));
+        let energy_before = reheat::kinetic_energy(self.node_map.values());
+
+        let kicks: HashMap<u32, Vec3Data> = kicked.iter()
+            .filter_map(|id| {
+                let node = self.node_map.get_mut(id)?;
+                node.data.velocity = (glam::Vec3::from(node.data.velocity) + reheat::kick(request.intensity, &mut self.rng)).into();
+                Some((*id, node.data.velocity))
+            })
+            .collect();
+        for node in Arc::make_mut(&mut self.graph_data).nodes.iter_mut() {
+            if let Some(velocity) = kicks.get(&node.id) {
+                node.data.velocity = *velocity;
+            }
+        }
+
+        // Nothing left calming the layout, so the kicks carry it somewhere new
+        self.disturbance.end();
+        self.settling.retain(|id, _| !kicks.contains_key(id));
+        self.set_phase(SimulationPhase::Dynamic);
+        let outcome = ReheatOutcome {
+            kicked: kicks.len(),
+            held: held.len(),
+            energy_before,
+            energy_after: reheat::kinetic_energy(self.node_map.values()),
+            phase: self.phase,
+        };
+        info!("Reheated the layout: {} nodes kicked at intensity {}, {} held", outcome.kicked, request.intensity, outcome.held);
+        self.client_manager.do_send(BroadcastMessage { message: outcome.to_event(request.intensity) });
+        Ok(outcome)
+    }
+}
+
+impl Handler<SetSimulationPhase> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetSimulationPhase, _ctx: &mut Self::Context) -> Self::Result {
+        self.set_phase(msg.phase);
+    }
+}
+
+impl Handler<GetFrameBudgetStatus> for GraphServiceActor {
+    type Result = MessageResult<GetFrameBudgetStatus>;
+
+    fn handle(&mut self, _msg: GetFrameBudgetStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.frame_budget.status())
+    }
+}
+
+impl Handler<GetIdleStatus> for GraphServiceActor {
+    type Result = Result<IdleStatus, String>;
+
+    fn handle(&mut self, _msg: GetIdleStatus, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.idle_status())
+    }
+}
+
+impl Handler<GetPhysicsGraph> for GraphServiceActor {
+    type Result = Result<GraphData, String>;
+
+    fn handle(&mut self, _msg: GetPhysicsGraph, _ctx: &mut Self::Context) -> Self::Result {
+        let mut graph = (*self.graph_data).clone();
+        if let Some(strategy) = self.skeleton_springs {
+            graph.edges = skeleton::skeleton_edges(&graph.edges, &self.spanning_skeleton(strategy)).into();
+        }
+        graph.edges = edge_visibility::physics_edges(&graph.edges, &self.physics_disabled_types).into();
+        Ok(graph)
+    }
+}
+
+impl Handler<GetSkeleton> for GraphServiceActor {
+    type Result = Result<(u64, Arc<Vec<String>>), String>;
+
+    fn handle(&mut self, msg: GetSkeleton, _ctx: &mut Self::Context) -> Self::Result {
+        Ok((self.graph_data.generation, self.spanning_skeleton(msg.strategy)))
+    }
+}
+
+impl Handler<GetCommunityView> for GraphServiceActor {
+    type Result = MessageResult<GetCommunityView>;
+
+    fn handle(&mut self, _msg: GetCommunityView, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.community_view())
+    }
+}
+
+impl Handler<GetCachedPageRank> for GraphServiceActor {
+    type Result = Option<(u64, Arc<HashMap<u32, f32>>)>;
+
+    fn handle(&mut self, _msg: GetCachedPageRank, _ctx: &mut Self::Context) -> Self::Result {
+        self.pagerank.clone().filter(|(ranked_at, _)| *ranked_at == self.graph_data.generation)
+    }
+}
+
+impl Handler<CachePageRank> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CachePageRank, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.generation == self.graph_data.generation {
+            self.pagerank = Some((msg.generation, msg.ranks));
+        }
+    }
+}
+
+impl Handler<SetSkeletonSprings> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: SetSkeletonSprings, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.strategy != self.skeleton_springs {
+            info!("Physics springs: {}", match msg.strategy {
+                Some(strategy) => format!("{:?} skeleton only", strategy),
+                None => "every edge".to_string(),
+            });
+        }
+        self.skeleton_springs = msg.strategy;
+        Ok(())
+    }
+}
+
+impl Handler<UsePinStore> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: UsePinStore, _ctx: &mut Self::Context) -> Self::Result {
+        self.pins = PinStore::load(msg.path);
+        self.runtime_pins.clear();
+        Ok(self.restore_pins())
+    }
+}
+
+impl Handler<UseAliasStore> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: UseAliasStore, _ctx: &mut Self::Context) -> Self::Result {
+        self.aliases = AliasStore::load(msg.path);
+        Ok(self.aliases.aliases().len())
+    }
+}
+
+impl Handler<UseNodeIdMap> for GraphServiceActor {
+    type Result = usize;
+
+    fn handle(&mut self, msg: UseNodeIdMap, _ctx: &mut Self::Context) -> Self::Result {
+        self.node_ids = msg.ids;
+        self.node_ids.lock().unwrap().len()
+    }
+}
+
+impl Handler<UseGraphJournal> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: UseGraphJournal, _ctx: &mut Self::Context) -> Self::Result {
+        let journal = GraphJournal::open(msg.dir).map_err(|e| {
+            error!("Runtime graph changes won't be journaled: {}", e);
+            e
+        })?;
+        let loaded = journal.records().len();
+        self.journal = Some(journal);
+        // Normally sent before the first build; a graph that's already there gets it now
+        if !self.graph_data.nodes.is_empty() {
+            self.replay_journal();
+            self.recolor_and_broadcast();
+        }
+        Ok(loaded)
+    }
+}
+
+impl Handler<MergeNodes> for GraphServiceActor {
+    type Result = Result<MergeOutcome, String>;
+
+    fn handle(&mut self, msg: MergeNodes, _ctx: &mut Self::Context) -> Self::Result {
+        // A shallow copy: merging rewrites the nodes and edges, so only those segments are
+        // copied, and the live graph is left as it was for the journal and for dry runs
+        let mut merged = (*self.graph_data).clone();
+        let mut outcome = node_merge::merge_nodes(&mut merged, msg.keep, &msg.merge)?;
+        if msg.dry_run {
+            return Ok(outcome);
+        }
+        let keep_id = self.node_map.get(&msg.keep)
+            .map(|n| n.metadata_id.clone())
+            .ok_or_else(|| format!("Node {} not found", msg.keep))?;
+        // Persisted before the graph changes, so a rebuild can never undo a merge
+        self.aliases.add(&outcome.aliases, &keep_id)?;
+
+        merged.metadata = self.aliases.fold(&merged.metadata).into();
+        self.journal(|actor| graph_journal::records_for_diff(&actor.graph_data, &merged, &outcome.diff));
+        let kept_metadata = merged.nodes.iter().find(|n| n.id == msg.keep).map(|n| n.metadata.clone());
+        if let (Some(node), Some(metadata)) = (self.node_map.get_mut(&msg.keep), kept_metadata) {
+            node.metadata = metadata;
+        }
+        self.graph_data = Arc::new(merged);
+        for node_id in &outcome.diff.removed_nodes {
+            // Edges are already gone, this clears the node's per-node state
+            self.remove_node(*node_id);
+        }
+        self.recolor_and_broadcast();
+        outcome.diff.generation = Some(self.graph_data.generation);
+        info!("Merged nodes {:?} into {} ({})", outcome.diff.removed_nodes, msg.keep, keep_id);
+        self.client_manager.do_send(BroadcastMessage { message: outcome.diff.to_event("merge").to_string() });
+        Ok(outcome)
+    }
+}
+
+impl Handler<ApplyGraphTransaction> for GraphServiceActor {
+    type Result = Result<TransactionOutcome, TransactionError>;
+
+    fn handle(&mut self, msg: ApplyGraphTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        // Everything runs on a copy inside this one handler, so no other message sees the
+        // graph part way through and a failure leaves nothing to roll back
+        let mut graph = (*self.graph_data).clone();
+        let reserved = msg.ops.iter().filter(|op| matches!(op, TransactionOp::CreateNode { .. })).count();
+        let first_id = self.allocate_runtime_ids(reserved as u32);
+        let mut applied = graph_transaction::apply(&mut graph, &msg.ops, &msg.actor, first_id, &self.rewire)?;
+
+        let settling = placement::place_new_nodes(&mut graph, &applied.unplaced, PLACEMENT_JITTER, SPHERE_RADIUS, &mut self.rng);
+        for node in applied.diff.added_nodes.iter_mut() {
+            if let Some(placed) = graph.nodes.iter().find(|n| n.id == node.id) {
+                node.data = placed.data;
+            }
+        }
+        self.commit_graph_edit(graph, &applied.diff);
+        self.settling.extend(settling.into_iter().map(|id| (id, SETTLE_FRAMES)));
+
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id += 1;
+        applied.undo.transaction_id = transaction_id;
+        if self.transactions.len() == MAX_UNDO_TRANSACTIONS {
+            self.transactions.pop_front();
+        }
+        self.transactions.push_back(applied.undo);
+
+        applied.diff.generation = Some(self.graph_data.generation);
+        info!("Transaction {} by {}: {} operations applied", transaction_id, msg.actor, msg.ops.len());
+        for warning in &applied.warnings {
+            warn!("Transaction {}: {}", transaction_id, warning);
+        }
+        self.client_manager.do_send(BroadcastMessage { message: applied.diff.to_event(&msg.actor).to_string() });
+        Ok(TransactionOutcome { transaction_id, diff: applied.diff, handles: applied.handles, warnings: applied.warnings })
+    }
+}
+
+impl Handler<UndoGraphTransaction> for GraphServiceActor {
+    type Result = Result<TransactionOutcome, String>;
+
+    fn handle(&mut self, msg: UndoGraphTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        let undo = self.transactions.pop_back().ok_or_else(|| "No transaction to undo".to_string())?;
+        let mut graph = (*self.graph_data).clone();
+        // An entry that can't be undone any more stays dropped, so the one before it can be
+        let mut diff = graph_transaction::revert(&mut graph, &undo)
+            .map_err(|e| format!("Transaction {} can no longer be undone: {}", undo.transaction_id, e))?;
+        self.commit_graph_edit(graph, &diff);
+        diff.generation = Some(self.graph_data.generation);
+        info!("Transaction {} by {} undone by {}", undo.transaction_id, undo.actor, msg.actor);
+        self.client_manager.do_send(BroadcastMessage { message: diff.to_event(&msg.actor).to_string() });
+        Ok(TransactionOutcome { transaction_id: undo.transaction_id, diff, handles: Default::default(), warnings: Vec::new() })
+    }
+}
+
+impl Handler<SetNodePin> for GraphServiceActor {
+    type Result = Result<Option<PinInfo>, String>;
+
+    fn handle(&mut self, msg: SetNodePin, _ctx: &mut Self::Context) -> Self::Result {
+        let node = self.node_map.get(&msg.node_id)
+            .ok_or_else(|| format!("Node {} not found", msg.node_id))?;
+        let metadata_id = node.metadata_id.clone();
+
+        if !msg.pinned {
+            self.pins.unpin(&metadata_id)?;
+            self.pinned_nodes.remove(&msg.node_id);
+            self.runtime_pins.remove(&metadata_id);
+            info!("Unpinned node {} ({})", msg.node_id, metadata_id);
+            return Ok(None);
+        }
+
+        if let Some(position) = msg.position {
+            if !position.is_finite() {
+                return Err("Pin position contains non-finite values".to_string());
+            }
+        }
+        let position = msg.position.map(Vec3Data::from).unwrap_or(node.data.position);
+        let pin = PinnedNode { position: position.into(), pinned_at: chrono::Utc::now() };
+        self.pins.pin(&metadata_id, pin.clone())?;
+        self.pinned_nodes.insert(msg.node_id);
+        self.runtime_pins.insert(metadata_id.clone());
+
+        let mut data = node.data;
+        data.position = position;
+        data.velocity = Vec3Data::zero();
+        let positions = vec![(msg.node_id, data)];
+        self.update_node_positions(positions.clone());
+        self.broadcast_positions(&positions, FrameKind::Delta);
+        info!("Pinned node {} ({}) at {:?}", msg.node_id, metadata_id, pin.position);
+
+        Ok(Some(PinInfo {
+            node_id: msg.node_id,
+            metadata_id,
+            position: pin.position,
+            pinned_at: pin.pinned_at,
+            source: PinSource::Runtime,
+        }))
+    }
+}
+
+impl Handler<GetPins> for GraphServiceActor {
+    type Result = Result<PinReport, String>;
+
+    fn handle(&mut self, _msg: GetPins, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.pin_report())
+    }
+}
+
+impl Handler<GetIntegrityView> for GraphServiceActor {
+    type Result = Result<IntegrityView, String>;
+
+    fn handle(&mut self, _msg: GetIntegrityView, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(IntegrityView {
+            graph: self.graph_data.clone(),
+            node_map_ids: self.node_map.keys().copied().collect(),
+            pinned: self.pins.pins().keys().cloned().collect(),
+        })
+    }
+}
+
+impl Handler<DropPins> for GraphServiceActor {
+    type Result = Result<usize, String>;
+
+    fn handle(&mut self, msg: DropPins, _ctx: &mut Self::Context) -> Self::Result {
+        let mut dropped = 0;
+        for metadata_id in &msg.metadata_ids {
+            if self.pins.unpin(metadata_id)? {
+                dropped += 1;
+            }
+            self.runtime_pins.remove(metadata_id);
+        }
+        let ids: HashMap<&str, u32> = self.graph_data.nodes.iter().map(|n| (n.metadata_id.as_str(), n.id)).collect();
+        for metadata_id in &msg.metadata_ids {
+            if let Some(node_id) = ids.get(metadata_id.as_str()) {
+                self.pinned_nodes.remove(node_id);
+            }
+        }
+        self.pin_conflicts.retain(|c| !msg.metadata_ids.contains(&c.metadata_id));
+        Ok(dropped)
+    }
+}
+
+impl Handler<UpdateNodeAttributes> for GraphServiceActor {
+    type Result = Result<AttributeUpdateOutcome, String>;
+
+    fn handle(&mut self, msg: UpdateNodeAttributes, _ctx: &mut Self::Context) -> Self::Result {
+        // Resolved here rather than by the caller so the selection and the update see the same graph
+        let requested = match (msg.node_ids, &msg.filter) {
+            (Some(ids), _) => {
+                let mut seen = HashSet::new();
+                ids.into_iter().filter(|id| seen.insert(*id)).collect::<Vec<_>>()
+            }
+            (None, Some(filter)) => filter.select(&self.graph_data.nodes),
+            (None, None) => return Err("Either node ids or a filter is required".to_string()),
+        };
+
+        let index: HashMap<u32, usize> = self.graph_data.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let graph_data_mut = Arc::make_mut(&mut self.graph_data);
+        let mut results = Vec::with_capacity(requested.len());
+        for node_id in requested {
+            let Some(&i) = index.get(&node_id) else {
+                results.push(NodeAttributeResult {
+                    node_id,
+                    status: AttributeUpdateStatus::NotFound,
+                    changed: Vec::new(),
+                    before: None,
+                    after: None,
+                });
+                continue;
+            };
+            let node = &mut graph_data_mut.nodes[i];
+            let before = msg.set.snapshot(node);
+            let changed = msg.set.apply(node);
+            if let Some(mapped) = self.node_map.get_mut(&node_id) {
+                msg.set.apply(mapped);
+            }
+            if let Some(color) = &msg.set.color {
+                self.color_overrides.insert(node.metadata_id.clone(), color.clone());
+            }
+            let (status, before, after) = if changed.is_empty() {
+                (AttributeUpdateStatus::Unchanged, None, None)
+            } else {
+                (AttributeUpdateStatus::Updated, Some(before), Some(msg.set.snapshot(node)))
+            };
+            results.push(NodeAttributeResult { node_id, status, changed, before, after });
+        }
+
+        let updated = results.iter().filter(|r| r.status == AttributeUpdateStatus::Updated).count();
+        self.journal(|actor| results.iter()
+            .filter(|r| r.status == AttributeUpdateStatus::Updated)
+            .filter_map(|r| actor.metadata_id_of(r.node_id))
+            .map(|metadata_id| JournalRecord::SetAttributes { metadata_id, set: msg.set.clone() })
+            .collect());
+        let mut event = None;
+        if updated > 0 {
+            self.topology_changed();
+            let diff = node_attributes::attribute_diff_event(&msg.set, &results, &msg.actor, self.graph_data.generation);
+            self.client_manager.do_send(BroadcastMessage { message: diff.to_string() });
+            event = Some(diff);
+            // A new group or type can shift the colours of other nodes too
+            if msg.set.group.is_some() || msg.set.node_type.is_some() {
+                self.recolor_and_broadcast();
+            }
+        }
+        info!("Attribute update by {}: {} of {} nodes changed", msg.actor, updated, results.len());
+        Ok(AttributeUpdateOutcome { results, updated, generation: self.graph_data.generation, event })
+    }
+}
+
+impl Handler<GetColorMapping> for GraphServiceActor {
+    type Result = Result<ColorMappingSettings, String>;
+
+    fn handle(&mut self, _msg: GetColorMapping, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.color_mapping.clone())
+    }
+}
+
+impl Handler<StartRecording> for GraphServiceActor {
+    type Result = Result<RecordingStatus, String>;
+
+    fn handle(&mut self, msg: StartRecording, _ctx: &mut Self::Context) -> Self::Result {
+        if self.recorder.is_some() {
+            return Err("A recording is already running".to_string());
+        }
+        if self.replay.is_some() {
+            return Err("Cannot record while a replay is running".to_string());
+        }
+        let mut recorder = PositionRecorder::create(&msg.path, msg.max_bytes, msg.max_duration)?;
+        // Open with the whole layout so the recording replays from any starting graph
+        let keyframe = self.encode_node_positions(&self.full_keyframe())?;
+        if !recorder.record(FrameKind::Keyframe, &keyframe)? {
+            return Err("Recording caps are too small for a single keyframe".to_string());
+        }
+        info!("Recording position stream to {:?}", msg.path);
+        let status = recorder.status();
+        self.recorder = Some(recorder);
+        Ok(status)
+    }
+}
+
+impl Handler<StopRecording> for GraphServiceActor {
+    type Result = Result<RecordingStatus, String>;
+
+    fn handle(&mut self, _msg: StopRecording, _ctx: &mut Self::Context) -> Self::Result {
+        let recorder = self.recorder.take().ok_or_else(|| "No recording is running".to_string())?;
+        let status = recorder.finish()?;
+        info!("Stopped position recording {:?}: {} frames, {} bytes", status.path, status.frames, status.bytes);
+        Ok(status)
+    }
+}
+
+impl Handler<StartReplay> for GraphServiceActor {
+    type Result = Result<ReplayStatus, String>;
+
+    fn handle(&mut self, msg: StartReplay, _ctx: &mut Self::Context) -> Self::Result {
+        if self.shutdown_complete.load(Ordering::SeqCst) {
+            return Err("Graph service is shut down".to_string());
+        }
+        if self.recorder.is_some() {
+            return Err("Stop the recording before starting a replay".to_string());
+        }
+        let mut replay = PositionReplay::new(msg.frames, msg.speed)?;
+        replay.play();
+        let keyframe = replay.keyframe();
+        let status = replay.status();
+        // Replacing a running replay is fine; it simply starts over with the new one
+        self.replay = Some(replay);
+        self.replay_clock = Instant::now();
+        self.apply_replay_keyframe(keyframe);
+        info!("Replaying {} recorded frames ({} ms) at {}x", status.frames, status.duration_ms, status.speed);
+        Ok(status)
+    }
+}
+
+impl Handler<ControlReplay> for GraphServiceActor {
+    type Result = Result<Option<ReplayStatus>, String>;
+
+    fn handle(&mut self, msg: ControlReplay, _ctx: &mut Self::Context) -> Self::Result {
+        let replay = self.replay.as_mut().ok_or_else(|| "No replay is running".to_string())?;
+        match msg.command {
+            ReplayCommand::Play => {
+                replay.play();
+                self.replay_clock = Instant::now();
+            }
+            ReplayCommand::Pause => replay.pause(),
+            ReplayCommand::Speed(speed) => replay.set_speed(speed)?,
+            ReplayCommand::Seek(position_ms) => {
+                let keyframe = replay.seek(position_ms);
+                self.apply_replay_keyframe(keyframe);
+            }
+            ReplayCommand::Stop => {
+                self.replay = None;
+                info!("Replay stopped; physics resumes");
+                return Ok(None);
+            }
+        }
+        Ok(self.replay.as_ref().map(|replay| replay.status()))
+    }
+}
+
+impl Handler<GetRecordingState> for GraphServiceActor {
+    type Result = Result<RecordingState, String>;
+
+    fn handle(&mut self, _msg: GetRecordingState, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(RecordingState {
+            mode: if self.replay.is_some() { SimulationMode::Replay } else { self.simulation.mode() },
+            recording: self.recorder.as_ref().map(|recorder| recorder.status()),
+            replay: self.replay.as_ref().map(|replay| replay.status()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::graph::Segment;
+    use crate::models::metadata::Metadata;
+    use crate::utils::spatial_index::SpatialQuery;
+    use chrono::Utc;
+
+    #[actix_web::test]
+    async fn test_touching_an_archived_file_restores_it() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager, None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        store.insert("old.md".to_string(), Metadata {
+            file_name: "old.md".to_string(),
+            last_modified: Utc::now() - chrono::Duration::days(400),
+            ..Default::default()
+        });
+        store.insert("new.md".to_string(), Metadata {
+            file_name: "new.md".to_string(),
+            last_modified: Utc::now(),
+            ..Default::default()
+        });
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+
+        let settings = AgingSettings { enabled: true, half_life_days: 30.0, hide_after_days: 365.0, ..Default::default() };
+        assert_eq!(graph.send(SetAgingSettings { settings }).await.unwrap().unwrap(), 1);
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let old = nodes.values().find(|n| n.metadata_id == "old").unwrap();
+        assert!(aging::is_archived(old));
+        assert!(old.size.unwrap() < 0.5);
+        assert!(!aging::is_archived(nodes.values().find(|n| n.metadata_id == "new").unwrap()));
+
+        // A fresh lastModified counts as a touch and brings the node straight back
+        let entries = HashMap::from([("lastModified".to_string(), Utc::now().to_rfc3339())]);
+        graph.send(UpdateNodeMetadata { metadata_id: "old".to_string(), entries }).await.unwrap().unwrap();
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let old = nodes.values().find(|n| n.metadata_id == "old").unwrap();
+        assert!(!aging::is_archived(old));
+        assert!((old.size.unwrap() - 1.0).abs() < 1e-3);
+
+        // Switching aging off puts sizes back as they were
+        graph.send(SetAgingSettings { settings: AgingSettings::default() }).await.unwrap().unwrap();
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        assert!(nodes.values().all(|n| n.size.is_none() && !n.metadata.contains_key(AGE_OPACITY_KEY)));
+    }
+
+    #[actix_web::test]
+    async fn test_physics_sees_transformed_edge_weights() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager, None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), Metadata {
+            file_name: "a.md".to_string(),
+            topic_counts: HashMap::from([("b.md".to_string(), 16)]),
+            ..Default::default()
+        });
+        store.insert("b.md".to_string(), Metadata { file_name: "b.md".to_string(), ..Default::default() });
+
+        let settings = EdgeWeightSettings { transform: crate::config::EdgeWeightTransform::Sqrt, ..Default::default() };
+        graph.send(SetEdgeWeightSettings { settings }).await.unwrap().unwrap();
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        // This is the graph the GPU upload and the force pass are fed
+        let edges = graph.send(GetGraphData).await.unwrap().unwrap().edges;
+        assert_eq!(edges.len(), 1);
+        assert_eq!((edges[0].weight, edges[0].raw_weight), (4.0, Some(16.0)));
+
+        // A runtime change re-derives weights without a rebuild
+        let settings = EdgeWeightSettings { max_weight: 10.0, ..Default::default() };
+        assert_eq!(graph.send(SetEdgeWeightSettings { settings }).await.unwrap().unwrap(), 1);
+        let edges = graph.send(GetGraphData).await.unwrap().unwrap().edges;
+        assert_eq!((edges[0].weight, edges[0].raw_weight), (10.0, Some(16.0)));
+    }
+
+    #[actix_web::test]
+    async fn test_edge_provenance_explains_the_weight() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), Metadata {
+            file_name: "a.md".to_string(),
+            topic_counts: HashMap::from([("b.md".to_string(), 16)]),
+            ..Default::default()
+        });
+        store.insert("b.md".to_string(), Metadata { file_name: "b.md".to_string(), ..Default::default() });
+        let settings = EdgeWeightSettings { transform: crate::config::EdgeWeightTransform::Sqrt, ..Default::default() };
+        graph.send(SetEdgeWeightSettings { settings }).await.unwrap().unwrap();
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let decay = EdgeDecaySettings { half_life_days: HashMap::from([("topic".to_string(), 1.0)]), ..Default::default() };
+        graph.send(SetEdgeDecaySettings { settings: decay }).await.unwrap().unwrap();
+
+        // Built from counts, re-transformed, reinforced by hand, then decayed for a day
+        graph.send(SetEdgeWeightSettings { settings: EdgeWeightSettings::default() }).await.unwrap().unwrap();
+        let (source, target) = {
+            let edges = graph.send(GetGraphData).await.unwrap().unwrap().edges;
+            (edges[0].source, edges[0].target)
+        };
+        graph.send(AddEdge { edge: Edge::new(source, target, 4.0) }).await.unwrap().unwrap();
+        graph.send(DecayEdges { elapsed_secs: 86_400.0 }).await.unwrap().unwrap();
+
+        let edge = graph.send(GetGraphData).await.unwrap().unwrap().edges[0].clone();
+        let trail: Vec<(WeightSource, f32)> = edge.provenance.iter().map(|c| (c.source, c.contribution)).collect();
+        assert_eq!(trail, [
+            (WeightSource::TopicCounts, 4.0),
+            (WeightSource::Transform, 12.0),
+            (WeightSource::Manual, 4.0),
+            (WeightSource::Decay, -10.0),
+        ]);
+        assert_eq!(edge.weight, 10.0);
+        assert!(edge.provenance.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        let stats = graph.send(GetGraphStats { lod_limit: 0 }).await.unwrap().unwrap();
+        assert_eq!(stats.provenance_entries, 4);
+        assert!(stats.provenance_bytes > 0);
+    }
+
+    #[actix_web::test]
+    async fn test_pins_survive_a_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("pins-actor-test-{}", uuid::Uuid::new_v4()))
+            .join("layout_state.json");
+        let store = || {
+            let mut store = MetadataStore::new();
+            for name in ["hub.md", "leaf.md"] {
+                store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+            }
+            store
+        };
+        let start = |path: std::path::PathBuf| async move {
+            let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+            graph.send(StopSimulation).await.unwrap().unwrap();
+            graph.send(UsePinStore { path }).await.unwrap().unwrap();
+            graph
+        };
+
+        let graph = start(path.clone()).await;
+        graph.send(BuildGraphFromMetadata { metadata: store() }).await.unwrap().unwrap();
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let hub = nodes.values().find(|n| n.metadata_id == "hub").unwrap().id;
+        let pin = graph.send(SetNodePin { node_id: hub, pinned: true, position: Some(glam::Vec3::new(5.0, 6.0, 7.0)) })
+            .await.unwrap().unwrap().unwrap();
+        assert_eq!(pin.source, PinSource::Runtime);
+        // Pinned nodes can't be dragged
+        let drag = UpdateNodePosition {
+            node_id: hub,
+            position: glam::Vec3::ZERO,
+            velocity: glam::Vec3::ZERO,
+            edited_by: Some(EditAttribution { client_id: 1, room: "default".to_string() }),
+        };
+        assert!(graph.send(drag).await.unwrap().is_err());
+
+        // A new actor over the same file restores the pin on rebuild, with fresh node ids
+        let graph = start(path.clone()).await;
+        graph.send(BuildGraphFromMetadata { metadata: store() }).await.unwrap().unwrap();
+        let report = graph.send(GetPins).await.unwrap().unwrap();
+        assert_eq!(report.pins.len(), 1);
+        assert_eq!((report.pins[0].metadata_id.as_str(), report.pins[0].source), ("hub", PinSource::Metadata));
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let hub = nodes.values().find(|n| n.metadata_id == "hub").unwrap();
+        assert_eq!(hub.data.position.as_array(), [5.0, 6.0, 7.0]);
+
+        // The file going away is a conflict, not a lost pin
+        let mut without_hub = store();
+        without_hub.remove("hub.md");
+        graph.send(BuildGraphFromMetadata { metadata: without_hub }).await.unwrap().unwrap();
+        let report = graph.send(GetPins).await.unwrap().unwrap();
+        assert!(report.pins.is_empty());
+        assert_eq!(report.conflicts.len(), 1);
+        graph.send(BuildGraphFromMetadata { metadata: store() }).await.unwrap().unwrap();
+        assert_eq!(graph.send(GetPins).await.unwrap().unwrap().pins.len(), 1);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_generations_track_the_right_changes() {
+        async fn generations(graph: &Addr<GraphServiceActor>) -> GraphGenerations {
+            graph.send(GetGenerations).await.unwrap().unwrap()
+        }
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+
+        let mut last = generations(&graph).await;
+        let mut assert_bumped = |now: GraphGenerations, topology: bool, positions: bool, step: &str| {
+            assert_eq!(now.generation > last.generation, topology, "topology after {}", step);
+            assert_eq!(now.position_generation > last.position_generation, positions, "positions after {}", step);
+            last = now;
+        };
+
+        graph.send(BuildGraphFromMetadata { metadata: store.clone() }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, true, "rebuild");
+        let ids: Vec<u32> = graph.send(GetNodeMap).await.unwrap().unwrap().keys().copied().collect();
+
+        let moved = UpdateNodePosition { node_id: ids[0], position: glam::Vec3::ONE, velocity: glam::Vec3::ZERO, edited_by: None };
+        graph.send(moved).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, false, true, "position update");
+
+        let edge = Edge::new(ids[0], ids[1], 1.0);
+        let edge_id = edge.id.clone();
+        graph.send(AddEdge { edge }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, false, "edge add");
+        graph.send(RemoveEdge { edge_id }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, false, "edge removal");
+
+        let entries = HashMap::from([("tags".to_string(), "x".to_string())]);
+        graph.send(UpdateNodeMetadata { metadata_id: "a".to_string(), entries }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, false, "metadata update");
+
+        let similarity = |enabled: bool| SetSimilarityEdges {
+            pairs: vec![SimilarityPair { source: "a".to_string(), target: "b".to_string(), similarity: 0.9 }],
+            enabled,
+            spring_multiplier: 1.0,
+        };
+        graph.send(similarity(false)).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, false, false, "disabled similarity refresh");
+        graph.send(similarity(true)).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, false, "similarity edges");
+        graph.send(similarity(true)).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, false, false, "unchanged similarity refresh");
+
+        graph.send(AddNode { node: Node::new_with_id("c".to_string(), Some(999)) }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, true, "node add");
+        graph.send(RemoveNode { node_id: 999, rewire: false }).await.unwrap().unwrap();
+        assert_bumped(generations(&graph).await, true, true, "node removal");
+
+        // A rebuild carries on from the old count rather than starting over at zero
+        let before = last.generation;
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let after = generations(&graph).await;
+        assert!(after.generation > before);
+        assert_eq!(graph.send(GetGraphStats { lod_limit: 0 }).await.unwrap().unwrap().generation, after.generation);
+    }
+
+    #[actix_web::test]
+    async fn test_spatial_queries_follow_the_layout() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md", "c.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let id = |metadata_id: &str| nodes.values().find(|n| n.metadata_id == metadata_id).unwrap().id;
+        let place = |metadata_id: &str, x: f32| UpdateNodePosition { node_id: id(metadata_id), position: glam::Vec3::new(x, 0.0, 0.0), velocity: glam::Vec3::ZERO, edited_by: None };
+        for (metadata_id, x) in [("a", 2.0), ("b", -1.0), ("c", 30.0)] {
+            graph.send(place(metadata_id, x)).await.unwrap().unwrap();
+        }
+
+        let near_origin = || QuerySpatial { query: SpatialQuery::Radius { center: glam::Vec3::ZERO, radius: 5.0 }, limit: 10 };
+        let result = graph.send(near_origin()).await.unwrap().unwrap();
+        let found: Vec<(&str, f32)> = result.nodes.iter().map(|n| (n.label.as_str(), n.distance)).collect();
+        assert_eq!(found, [("b", 1.0), ("a", 2.0)]);
+        assert_eq!((result.total, result.truncated), (2, false));
+
+        // Moving a node rebuilds the index, and the answer carries the newer generation
+        graph.send(place("c", 0.5)).await.unwrap().unwrap();
+        let moved = graph.send(near_origin()).await.unwrap().unwrap();
+        assert!(moved.generations.position_generation > result.generations.position_generation);
+        let ids: Vec<u32> = moved.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, [id("c"), id("b"), id("a")]);
+        let capped = graph.send(QuerySpatial { limit: 1, ..near_origin() }).await.unwrap().unwrap();
+        assert_eq!((capped.nodes.len(), capped.total, capped.truncated), (1, 3, true));
+    }
+
+    #[actix_web::test]
+    async fn test_drag_while_finalizing_ramps_back_to_dynamic() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        graph.send(SetSimulationPhase { phase: SimulationPhase::Finalize }).await.unwrap();
+        let node = graph.send(GetNodeMap).await.unwrap().unwrap().into_values().next().unwrap();
+        let nudge = |offset: f32| UpdateNodePosition {
+            node_id: node.id,
+            position: glam::Vec3::from(node.data.position) + glam::Vec3::new(offset, 0.0, 0.0),
+            velocity: glam::Vec3::ZERO,
+            edited_by: None,
+        };
+
+        // A nudge under the threshold leaves the layout finalizing
+        graph.send(nudge(0.5)).await.unwrap().unwrap();
+        let status = graph.send(GetDisturbanceStatus).await.unwrap();
+        assert_eq!((status.active, status.phase), (false, SimulationPhase::Finalize));
+
+        graph.send(nudge(40.0)).await.unwrap().unwrap();
+        let status = graph.send(GetDisturbanceStatus).await.unwrap();
+        assert!(status.active && status.damping > 0.0);
+        assert_eq!(status.phase, SimulationPhase::Dynamic);
+        assert_eq!((status.disturbances, status.last_displacement), (1, Some(39.5)));
+    }
+
+    #[actix_web::test]
+    async fn test_reheat_kicks_free_nodes_and_leaves_pinned_ones() {
+        use crate::testing::TestHarness;
+        use crate::utils::reheat::ReheatRequest;
+
+        let mut harness = TestHarness::new(SimulationSettings::default()).await;
+        harness.graph.send(SetWarmupSettings { settings: WarmupSettings { skip_warmup: true, ..Default::default() } }).await.unwrap().unwrap();
+        harness.build(&["a.md", "b.md", "c.md"]).await;
+        let nodes = harness.graph.send(GetNodeMap).await.unwrap().unwrap();
+        let pinned = *nodes.keys().min().unwrap();
+        harness.graph.send(SetNodePin { node_id: pinned, pinned: true, position: None }).await.unwrap().unwrap();
+        let pinned_at = harness.graph.send(GetNodeMap).await.unwrap().unwrap()[&pinned].data.position;
+        harness.graph.send(SetSimulationPhase { phase: SimulationPhase::Finalize }).await.unwrap();
+        harness.received().await;
+
+        let request = ReheatRequest { intensity: 4.0, scope: ReheatScope::Global };
+        let outcome = harness.graph.send(ReheatLayout { request }).await.unwrap().unwrap();
+        assert_eq!((outcome.kicked, outcome.held, outcome.phase), (2, 1, SimulationPhase::Dynamic));
+        // Nothing was moving, so the layout gains exactly what the two kicks carry
+        let gained = outcome.energy_after - outcome.energy_before;
+        assert!((gained - 8.0).abs() < 0.08, "gained {}", gained);
+
+        let received = harness.step(5).await;
+        let told = received.iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m.text()?).ok())
+            .any(|v| v["type"] == "physics_reheat" && v["kicked"] == 2);
+        assert!(told);
+        let after = harness.graph.send(GetNodeMap).await.unwrap().unwrap();
+        assert_eq!(glam::Vec3::from(after[&pinned].data.position), glam::Vec3::from(pinned_at));
+        assert_eq!(glam::Vec3::from(after[&pinned].data.velocity), glam::Vec3::ZERO);
+        for id in nodes.keys().filter(|id| **id != pinned) {
+            assert_ne!(glam::Vec3::from(after[id].data.position), glam::Vec3::from(nodes[id].data.position));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_hidden_types_stay_in_physics_unless_disabled() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in 1..=3 {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        let mut similarity = Edge::new(2, 3, 0.9);
+        similarity.edge_type = Some(SIMILARITY_EDGE_TYPE.to_string());
+        graph.send(AddEdge { edge: Edge::new(1, 2, 1.0) }).await.unwrap().unwrap();
+        graph.send(AddEdge { edge: similarity }).await.unwrap().unwrap();
+
+        // Nothing disabled: physics sees every edge
+        assert_eq!(graph.send(GetPhysicsGraph).await.unwrap().unwrap().edges.len(), 2);
+
+        graph.send(SetEdgeTypePhysics { disabled: vec![SIMILARITY_EDGE_TYPE.to_string()] }).await.unwrap().unwrap();
+        let physics = graph.send(GetPhysicsGraph).await.unwrap().unwrap();
+        assert_eq!(physics.edges.len(), 1);
+        assert_eq!(physics.edges[0].type_name(), "topic");
+        // Clients are still sent both
+        assert_eq!(graph.send(GetGraphData).await.unwrap().unwrap().edges.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_visual_edges_and_toggled_types_leave_physics() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in 1..=3 {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        let mut similarity = Edge::new(2, 3, 0.9);
+        similarity.edge_type = Some(SIMILARITY_EDGE_TYPE.to_string());
+        graph.send(AddEdge { edge: Edge { affects_physics: false, ..Edge::new(1, 2, 1.0) } }).await.unwrap().unwrap();
+        graph.send(AddEdge { edge: similarity }).await.unwrap().unwrap();
+
+        // The visual-only edge is sent to clients, flagged, but never sprung
+        let physics = graph.send(GetPhysicsGraph).await.unwrap().unwrap();
+        assert_eq!(physics.edges.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["2-3"]);
+        let sent = serde_json::to_value(&graph.send(GetGraphData).await.unwrap().unwrap().edges).unwrap();
+        assert_eq!(sent[0]["affectsPhysics"], false);
+        assert!(sent[1].get("affectsPhysics").is_none());
+
+        // Switching a type out at runtime is a topology change and disturbs the layout
+        let before = graph.send(GetGenerations).await.unwrap().unwrap().generation;
+        let disturbances = graph.send(GetDisturbanceStatus).await.unwrap().disturbances;
+        let changes = HashMap::from([(SIMILARITY_EDGE_TYPE.to_string(), false)]);
+        let disabled = graph.send(ToggleEdgeTypePhysics { changes }).await.unwrap().unwrap();
+        assert_eq!(disabled, vec![SIMILARITY_EDGE_TYPE]);
+        assert!(graph.send(GetPhysicsGraph).await.unwrap().unwrap().edges.is_empty());
+        assert!(graph.send(GetGenerations).await.unwrap().unwrap().generation > before);
+        let status = graph.send(GetDisturbanceStatus).await.unwrap();
+        assert!(status.active && status.disturbances == disturbances + 1);
+        assert_eq!(graph.send(GetEdgeTypePhysics).await.unwrap(), vec![SIMILARITY_EDGE_TYPE]);
+    }
+
+    #[actix_web::test]
+    async fn test_skeleton_springs_switch_the_physics_edges() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in 1..=3 {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        for (source, target, weight) in [(1, 2, 3.0), (2, 3, 2.0), (1, 3, 1.0)] {
+            graph.send(AddEdge { edge: Edge::new(source, target, weight) }).await.unwrap().unwrap();
+        }
+        let (generation, tree) = graph.send(GetSkeleton { strategy: SkeletonStrategy::Mst }).await.unwrap().unwrap();
+        assert_eq!(*tree, vec!["1-2", "2-3"]);
+        assert_eq!(graph.send(GetPhysicsGraph).await.unwrap().unwrap().edges.len(), 3);
+
+        graph.send(SetSkeletonSprings { strategy: Some(SkeletonStrategy::Mst) }).await.unwrap().unwrap();
+        let physics = graph.send(GetPhysicsGraph).await.unwrap().unwrap();
+        let ids: Vec<&str> = physics.edges.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["1-2", "2-3"]);
+        // Clients are still sent every edge
+        assert_eq!(graph.send(GetGraphData).await.unwrap().unwrap().edges.len(), 3);
+
+        // A heavier edge changes the topology, so the skeleton is recomputed
+        graph.send(AddEdge { edge: Edge::new(1, 3, 5.0) }).await.unwrap().unwrap();
+        let (regenerated, tree) = graph.send(GetSkeleton { strategy: SkeletonStrategy::Mst }).await.unwrap().unwrap();
+        assert!(regenerated > generation);
+        assert_eq!(*tree, vec!["1-2", "1-3"]);
+        assert_eq!(graph.send(GetPhysicsGraph).await.unwrap().unwrap().edges.len(), 2);
+
+        graph.send(SetSkeletonSprings { strategy: None }).await.unwrap().unwrap();
+        assert_eq!(graph.send(GetPhysicsGraph).await.unwrap().unwrap().edges.len(), 3);
+    }
+
+    // Stands in for a websocket; binary frames are logged as "keyframe" or "delta"
+    struct RecordingSocket {
+        received: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Actor for RecordingSocket {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for RecordingSocket {
+        type Result = ();
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(msg.0);
+        }
+    }
+
+    impl Handler<SendToClientBinary> for RecordingSocket {
+        type Result = ();
+        fn handle(&mut self, msg: SendToClientBinary, _ctx: &mut Self::Context) {
+            let kind = if msg.keyframe { "keyframe" } else { "delta" };
+            self.received.lock().unwrap().push(kind.to_string());
+        }
+    }
+
+    impl Handler<CloseConnection> for RecordingSocket {
+        type Result = ();
+        fn handle(&mut self, _msg: CloseConnection, _ctx: &mut Self::Context) {}
+    }
+
+    #[actix_web::test]
+    async fn test_nothing_is_broadcast_during_warmup() {
+        use crate::testing::{frame_kinds, TestHarness};
+
+        let mut harness = TestHarness::new(SimulationSettings::default()).await;
+        let settings = WarmupSettings { max_iterations: 5, energy_threshold: 0.0, progress_interval: 2, ..Default::default() };
+        harness.graph.send(SetWarmupSettings { settings }).await.unwrap().unwrap();
+        assert!(!harness.graph.send(GetWarmupStatus).await.unwrap().unwrap().ready);
+
+        harness.build(&["a.md", "b.md"]).await;
+        let mut received = harness.step(4).await;
+        let status = harness.graph.send(GetWarmupStatus).await.unwrap().unwrap();
+        assert!(!status.ready);
+        assert!((status.progress - 0.8).abs() < 1e-6);
+
+        received.extend(harness.step(1).await);
+        assert!(harness.graph.send(GetWarmupStatus).await.unwrap().unwrap().ready);
+        received.extend(harness.step(1).await);
+
+        assert_eq!(frame_kinds(&received), ["keyframe", "delta"]);
+        // Progress at steps 2 and 4, then the ready message, all before the keyframe
+        let keyframe_at = received.iter().position(|m| m.frame_kind() == Some("keyframe")).unwrap();
+        let progress: Vec<serde_json::Value> = received[..keyframe_at].iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m.text()?).ok())
+            .filter(|v| v["type"] == "warmup_progress")
+            .collect();
+        assert_eq!(progress.len(), 3);
+        assert_eq!(progress[2]["ready"], true);
+    }
+
+    #[actix_web::test]
+    async fn test_stopping_physics_ends_warmup() {
+        use crate::testing::{frame_kinds, TestHarness};
+
+        let mut harness = TestHarness::new(SimulationSettings::default()).await;
+        let settings = WarmupSettings { max_iterations: 1000, energy_threshold: 0.0, ..Default::default() };
+        harness.graph.send(SetWarmupSettings { settings }).await.unwrap().unwrap();
+        harness.build(&["a.md", "b.md"]).await;
+        harness.step(2).await;
+        assert!(!harness.graph.send(GetWarmupStatus).await.unwrap().unwrap().ready);
+
+        // No more steps are coming, so the graph is ready with the layout it has
+        harness.graph.send(StopSimulation).await.unwrap().unwrap();
+        assert!(harness.graph.send(GetWarmupStatus).await.unwrap().unwrap().ready);
+        assert_eq!(frame_kinds(&harness.received().await), ["keyframe"]);
+    }
+
+    #[actix_web::test]
+    async fn test_frames_follow_the_simulation_mode() {
+        use crate::testing::{frame_kinds, TestHarness};
+
+        let mut harness = TestHarness::new(SimulationSettings::default()).await;
+        harness.graph.send(SetWarmupSettings { settings: WarmupSettings { skip_warmup: true, ..Default::default() } }).await.unwrap().unwrap();
+        harness.build(&["a.md", "b.md"]).await;
+        let mode = |mode| SimulationSettings { mode, hybrid_step_hz: 20.0, correction_interval_secs: 0.1, ..Default::default() };
+
+        // Local: clients get the layout to start from, then nothing
+        harness.set_simulation(mode(SimulationMode::Local)).await.unwrap();
+        let mut received = harness.step(4).await;
+        assert_eq!(harness.graph.send(GetIdleStatus).await.unwrap().unwrap().iterations, 0);
+
+        // Hybrid: a keyframe to leave local mode, then a step every 50ms, the fourth 16ms
+        // tick, and a correction once 0.1s has passed
+        harness.set_simulation(mode(SimulationMode::Hybrid)).await.unwrap();
+        received.extend(harness.step(9).await);
+
+        // Remote: a delta every step
+        harness.set_simulation(mode(SimulationMode::Remote)).await.unwrap();
+        received.extend(harness.step(2).await);
+        assert!(harness.set_simulation(mode(SimulationMode::Replay)).await.is_err());
+
+        assert_eq!(frame_kinds(&received), ["keyframe", "keyframe", "delta", "delta", "keyframe", "delta", "delta"]);
+        let modes: Vec<String> = received.iter()
+            .filter_map(|m| SimulationModeStatus::from_event(m.text()?))
+            .map(|status| format!("{:?}", status.mode))
+            .collect();
+        assert_eq!(modes, ["Local", "Hybrid", "Remote"]);
+        let settings = harness.graph.send(GetSimulationSettings).await.unwrap().unwrap();
+        assert_eq!(settings.mode, SimulationMode::Remote);
+        assert!(settings.deterministic);
+    }
+
+    #[actix_web::test]
+    async fn test_empty_graph_pauses_physics_until_nodes_arrive() {
+        use crate::testing::{frame_kinds, TestHarness};
+
+        let mut harness = TestHarness::new(SimulationSettings::default()).await;
+        // Nothing to warm up, so an empty graph is ready straight away
+        harness.build(&[]).await;
+        let status = harness.graph.send(GetWarmupStatus).await.unwrap().unwrap();
+        assert!(status.ready && status.empty_graph);
+        assert!(frame_kinds(&harness.step(5).await).is_empty());
+        assert_eq!(harness.graph.send(GetIdleStatus).await.unwrap().unwrap().iterations, 0);
+
+        // A lone node starts at the origin and physics picks up again
+        harness.build(&["only.md"]).await;
+        assert!(!harness.graph.send(GetWarmupStatus).await.unwrap().unwrap().empty_graph);
+        let position = |graph: GraphData| glam::Vec3::from(graph.nodes[0].data.position);
+        assert_eq!(position(harness.graph.send(GetGraphData).await.unwrap().unwrap()), glam::Vec3::ZERO);
+        harness.step(5).await;
+        assert!(harness.graph.send(GetIdleStatus).await.unwrap().unwrap().iterations > 0);
+        assert!(position(harness.graph.send(GetGraphData).await.unwrap().unwrap()).is_finite());
+    }
+
+    // Two and a half frames of the test's 50ms budget, and several loop ticks
+    fn slow_layout(actor: &mut GraphServiceActor) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+        std::thread::sleep(Duration::from_millis(125));
+        actor.calculate_layout_cpu()
+    }
+
+    #[actix_web::test]
+    async fn test_overloaded_physics_backs_off_and_stays_responsive() {
+        use crate::services::event_log::EventLog;
+        use actix_web::{test, web, App, HttpResponse};
+
+        let event_log = Arc::new(EventLog::new());
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager, None)
+            .with_layout(slow_layout)
+            .with_event_log(event_log.clone())
+            .start();
+        graph.send(SetWarmupSettings { settings: WarmupSettings { skip_warmup: true, ..Default::default() } }).await.unwrap().unwrap();
+        graph.send(SetIdleSettings { settings: IdleSettings { always_on: true, ..Default::default() } }).await.unwrap().unwrap();
+        let settings = FrameBudgetSettings { budget_ms: 50.0, max_consecutive_skips: 5, ..Default::default() };
+        graph.send(SetFrameBudgetSettings { settings }).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md", "c.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(graph.clone())).route(
+            "/budget",
+            web::get().to(|graph: web::Data<Addr<GraphServiceActor>>| async move {
+                HttpResponse::Ok().json(graph.send(GetFrameBudgetStatus).await.unwrap())
+            }),
+        )).await;
+
+        // Without the back-off the loop's timer never catches up and nothing else runs
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        let mut status = serde_json::Value::Null;
+        for _ in 0..5 {
+            let started = Instant::now();
+            status = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/budget").to_request()).await;
+            assert!(started.elapsed() < Duration::from_millis(500), "request took {:?}", started.elapsed());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        // Each step overruns by two frames, so two are skipped after each
+        assert_eq!(status["skipFactor"], 2);
+        assert_eq!(status["overloaded"], true);
+        assert!(status["lastStepMs"].as_f64().unwrap() >= 125.0);
+        let events = event_log.recent(10);
+        assert_eq!(events.iter().filter(|e| e.kind == "frame_budget_exceeded").count(), 1);
+        assert!(events[0].detail["suggestion"].as_str().unwrap().contains("simulation.mode"));
+    }
+
+    // Physics that leaves every node where it is, so the layout settles straight away
+    fn still_layout(actor: &mut GraphServiceActor) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+        Ok(actor.node_map.values().map(|node| (node.id, node.data)).collect())
+    }
+
+    #[actix_web::test]
+    async fn test_analytics_are_warm_once_the_layout_settles() {
+        use crate::utils::analytics_refresh::{AnalyticsKind, AnalyticsRefreshStatus};
+
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).with_layout(still_layout).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let settings = SimulationSettings { deterministic: true, ..Default::default() };
+        graph.send(SetSimulationSettings { settings }).await.unwrap().unwrap();
+        graph.send(SetWarmupSettings { settings: WarmupSettings { skip_warmup: true, ..Default::default() } }).await.unwrap().unwrap();
+        graph.send(SetIdleSettings { settings: IdleSettings { always_on: true, ..Default::default() } }).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md", "c.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+
+        // Steps the virtual clock until nothing is queued or running; refreshes finish off the actor
+        async fn settle(graph: &Addr<GraphServiceActor>) -> AnalyticsRefreshStatus {
+            for _ in 0..500 {
+                graph.send(SimulationStep).await.unwrap().unwrap();
+                let status = graph.send(GetGraphStats { lod_limit: 0 }).await.unwrap().unwrap().analytics_refresh;
+                if status.queue.is_empty() && status.running.is_none() {
+                    return status;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            panic!("analytics never refreshed");
+        }
+        settle(&graph).await;
+
+        // An insertion disturbs the layout, so nothing is refreshed until the ramp is over
+        graph.send(AddNode { node: Node::new_with_id("d".to_string(), Some(999)) }).await.unwrap().unwrap();
+        graph.send(SimulationStep).await.unwrap().unwrap();
+        // The step's broadcast already ranked the nodes for frame priority
+        let stats = graph.send(GetGraphStats { lod_limit: 0 }).await.unwrap().unwrap();
+        assert_eq!(stats.analytics_refresh.queue, [AnalyticsKind::Skeleton, AnalyticsKind::LayoutQuality]);
+        assert_eq!(stats.analytics_refresh.running, None);
+
+        let status = settle(&graph).await;
+        let generations = graph.send(GetGenerations).await.unwrap().unwrap();
+        for kind in [AnalyticsKind::Skeleton, AnalyticsKind::LayoutQuality] {
+            assert_eq!(status.last_runs[&kind].generation, generations.generation);
+        }
+        let (ranked_at, ranks) = graph.send(GetCachedPageRank).await.unwrap().unwrap();
+        assert_eq!((ranked_at, ranks.len()), (generations.generation, 4));
+    }
+
+    #[actix_web::test]
+    async fn test_simulation_idles_without_clients() {
+        use crate::actors::client_manager_actor::ClientHandle;
+        use crate::config::feature_access::Role;
+        use crate::utils::auth::Identity;
+
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(SetWarmupSettings { settings: WarmupSettings { skip_warmup: true, ..Default::default() } }).await.unwrap().unwrap();
+        let settings = IdleSettings { idle_after_secs: 0.05, wake_settle_iterations: 3, ..Default::default() };
+        graph.send(SetIdleSettings { settings }).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["a.md", "b.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+
+        // Nobody has connected, so the loop goes idle and stops computing
+        actix::clock::sleep(Duration::from_millis(200)).await;
+        let status = graph.send(GetIdleStatus).await.unwrap().unwrap();
+        assert!(status.idle);
+        actix::clock::sleep(Duration::from_millis(100)).await;
+        assert_eq!(graph.send(GetIdleStatus).await.unwrap().unwrap().iterations, status.iterations);
+
+        // A client connects: physics resumes and it gets a keyframe once settled
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let socket = RecordingSocket { received: received.clone() }.start();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        let client_id = client_manager.send(RegisterSimulatedClient {
+            handle,
+            identity: Identity { pubkey: None, role: Role::Viewer },
+        }).await.unwrap().unwrap();
+        actix::clock::sleep(Duration::from_millis(150)).await;
+        let awake = graph.send(GetIdleStatus).await.unwrap().unwrap();
+        assert!(!awake.idle);
+        assert_eq!(awake.clients, 1);
+        assert!(awake.iterations > status.iterations);
+        assert_eq!(received.lock().unwrap().iter().find(|m| *m == "keyframe" || *m == "delta").map(String::as_str), Some("keyframe"));
+
+        // And idles again once it leaves
+        client_manager.send(UnregisterClient { client_id }).await.unwrap().unwrap();
+        actix::clock::sleep(Duration::from_millis(200)).await;
+        let status = graph.send(GetIdleStatus).await.unwrap().unwrap();
+        assert!(status.idle);
+        actix::clock::sleep(Duration::from_millis(100)).await;
+        assert_eq!(graph.send(GetIdleStatus).await.unwrap().unwrap().iterations, status.iterations);
+    }
+
+    #[actix_web::test]
+    async fn test_snapshot_is_unaffected_by_writes_during_an_export() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in [1, 2] {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        graph.send(AddEdge { edge: Edge::new(1, 2, 1.0) }).await.unwrap().unwrap();
+
+        // With no write in between, two snapshots are the same graph rather than copies
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        assert!(Arc::ptr_eq(&snapshot, &graph.send(GetGraphSnapshot).await.unwrap().unwrap()));
+        let expected = crate::utils::gltf_export::build_gltf(&snapshot.nodes, &snapshot.edges);
+
+        // The export reads nodes, then waits for the graph to change before reading edges
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (changed_tx, changed_rx) = std::sync::mpsc::channel::<()>();
+        let exporting = snapshot.clone();
+        let export = std::thread::spawn(move || {
+            let nodes = exporting.nodes.clone();
+            started_tx.send(()).unwrap();
+            changed_rx.recv().unwrap();
+            crate::utils::gltf_export::build_gltf(&nodes, &exporting.edges)
+        });
+        started_rx.recv().unwrap();
+
+        let mut data = snapshot.nodes[0].data;
+        data.position.x += 100.0;
+        graph.send(UpdateNodePositions { positions: vec![(1, data)] }).await.unwrap().unwrap();
+        graph.send(AddNode { node: Node::new_with_id("n3".to_string(), Some(3)) }).await.unwrap().unwrap();
+        graph.send(RemoveEdge { edge_id: "1-2".to_string() }).await.unwrap().unwrap();
+        changed_tx.send(()).unwrap();
+
+        assert_eq!(export.join().unwrap(), expected);
+        assert_eq!((snapshot.nodes.len(), snapshot.edges.len()), (2, 1));
+        let live = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        assert!(!Arc::ptr_eq(&snapshot, &live));
+        assert_eq!((live.nodes.len(), live.edges.len()), (3, 0));
+        assert!(live.generation > snapshot.generation);
+        assert_eq!(live.nodes[0].data.position.x, snapshot.nodes[0].data.position.x + 100.0);
+    }
+
+    #[actix_web::test]
+    async fn test_position_updates_copy_only_the_nodes() {
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in [1, 2] {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        graph.send(AddEdge { edge: Edge::new(1, 2, 1.0) }).await.unwrap().unwrap();
+
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        let mut data = snapshot.nodes[0].data;
+        data.position.x += 100.0;
+        graph.send(UpdateNodePositions { positions: vec![(1, data)] }).await.unwrap().unwrap();
+
+        // The held snapshot forced a copy, but only of the segment that changed
+        let live = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        assert!(!Arc::ptr_eq(&snapshot, &live));
+        assert!(!Segment::ptr_eq(&snapshot.nodes, &live.nodes));
+        assert!(Segment::ptr_eq(&snapshot.edges, &live.edges));
+        assert!(Segment::ptr_eq(&snapshot.metadata, &live.metadata));
+        assert!(Segment::ptr_eq(&snapshot.id_to_metadata, &live.id_to_metadata));
+        assert_eq!(live.nodes[0].data.position.x, snapshot.nodes[0].data.position.x + 100.0);
+    }
+
+    #[actix_web::test]
+    async fn test_rebuild_after_an_id_fix_uses_the_fixed_ids() {
+        use crate::models::node_ids::NodeIdMap;
+        use crate::services::integrity_check;
+
+        let mut store = MetadataStore::new();
+        for (name, node_id) in [("a.md", "5"), ("b.md", "5"), ("c.md", "x"), ("d.md", "2")] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), node_id: node_id.to_string(), ..Default::default() });
+        }
+        // As at startup, the ids metadata carries seed the map, which already holds a node
+        // added at runtime with the next id up
+        let mut ids = NodeIdMap::in_memory();
+        ids.allocate(6, 1, |id| format!("runtime-{}", id));
+        ids.backfill(store.iter().map(|(name, m)| (name.trim_end_matches(".md"), m.node_id.parse().unwrap_or(0)))).unwrap();
+        let ids = ids.shared();
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        graph.send(UseNodeIdMap { ids: ids.clone() }).await.unwrap();
+        graph.send(BuildGraphFromMetadata { metadata: store.clone() }).await.unwrap().unwrap();
+
+        let reassigned = integrity_check::reassign_node_ids(&mut store, &mut ids.lock().unwrap());
+        assert_eq!(reassigned.iter().map(|r| r.file_name.as_str()).collect::<Vec<_>>(), vec!["b.md", "c.md"]);
+        graph.send(BuildGraphFromMetadata { metadata: store.clone() }).await.unwrap().unwrap();
+
+        // Every node has the id its metadata now names, and no two share one
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        let mut built: Vec<(String, u32)> = data.nodes.iter().map(|n| (n.metadata_id.clone(), n.id)).collect();
+        let mut fixed: Vec<(String, u32)> = store.iter()
+            .map(|(name, m)| (name.trim_end_matches(".md").to_string(), m.node_id.parse().unwrap()))
+            .collect();
+        built.sort();
+        fixed.sort();
+        assert_eq!(built, fixed);
+        assert_eq!(fixed.iter().map(|(_, id)| id).collect::<HashSet<_>>().len(), 4);
+    }
+
+    #[actix_web::test]
+    async fn test_merged_nodes_stay_merged_across_rebuilds() {
+        let file = |name: &str, topics: &[(&str, usize)]| Metadata {
+            file_name: name.to_string(),
+            topic_counts: topics.iter().map(|(t, c)| (t.to_string(), *c)).collect(),
+            ..Default::default()
+        };
+        let mut store = MetadataStore::new();
+        store.insert("GraphQL.md".to_string(), file("GraphQL.md", &[("REST.md", 2)]));
+        store.insert("Graph QL.md".to_string(), file("Graph QL.md", &[("REST.md", 1), ("GraphQL.md", 4)]));
+        store.insert("REST.md".to_string(), file("REST.md", &[]));
+        let path = std::env::temp_dir().join(format!("node-aliases-{}.json", uuid::Uuid::new_v4()));
+
+        let start = || {
+            let (path, store) = (path.clone(), store.clone());
+            async move {
+                let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+                graph.send(StopSimulation).await.unwrap().unwrap();
+                graph.send(UseAliasStore { path }).await.unwrap().unwrap();
+                graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+                graph
+            }
+        };
+        // Metadata ids of the nodes and the raw weight between each pair
+        let shape = |graph: Addr<GraphServiceActor>| async move {
+            let data = graph.send(GetGraphData).await.unwrap().unwrap();
+            let name = |id: u32| data.nodes.iter().find(|n| n.id == id).unwrap().metadata_id.clone();
+            let mut nodes: Vec<String> = data.nodes.iter().map(|n| n.metadata_id.clone()).collect();
+            nodes.sort();
+            let mut edges: Vec<(String, String, Option<f32>)> = data.edges.iter()
+                .map(|e| {
+                    let (a, b) = (name(e.source), name(e.target));
+                    (a.clone().min(b.clone()), a.max(b), e.raw_weight)
+                })
+                .collect();
+            edges.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+            (nodes, edges)
+        };
+
+        let graph = start().await;
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let id_of = |metadata_id: &str| nodes.values().find(|n| n.metadata_id == metadata_id).unwrap().id;
+        let (keep, merge) = (id_of("GraphQL"), id_of("Graph QL"));
+
+        // A dry run changes nothing
+        let preview = graph.send(MergeNodes { keep, merge: vec![merge], dry_run: true }).await.unwrap().unwrap();
+        assert_eq!(preview.diff.removed_nodes, vec![merge]);
+        assert_eq!(shape(graph.clone()).await.0.len(), 3);
+
+        graph.send(MergeNodes { keep, merge: vec![merge], dry_run: false }).await.unwrap().unwrap();
+        let merged = shape(graph.clone()).await;
+        // The link between the two became a self-loop and is gone; the REST links add up
+        assert_eq!(merged, (
+            vec!["GraphQL".to_string(), "REST".to_string()],
+            vec![("GraphQL".to_string(), "REST".to_string(), Some(3.0))],
+        ));
+
+        // Rebuilding, in this actor or a fresh one reading the same alias file, comes out the same
+        graph.send(BuildGraphFromMetadata { metadata: store.clone() }).await.unwrap().unwrap();
+        assert_eq!(shape(graph).await, merged);
+        let restarted = start().await;
+        assert_eq!(shape(restarted.clone()).await, merged);
+        let nodes = restarted.send(GetNodeMap).await.unwrap().unwrap();
+        assert_eq!(nodes.values().find(|n| n.metadata_id == "GraphQL").unwrap().metadata["aliases"], "Graph QL");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[actix_web::test]
+    async fn test_attribute_update_by_filter_broadcasts_diff() {
+        use crate::actors::client_manager_actor::ClientHandle;
+        use crate::config::feature_access::Role;
+        use crate::models::graph_filter::GraphFilter;
+        use crate::models::node_attributes::AttributeSet;
+        use crate::utils::auth::Identity;
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let socket = RecordingSocket { received: received.clone() }.start();
+        let mut client_manager = ClientManagerActor::new();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        client_manager.register_client(handle, Identity { pubkey: None, role: Role::Viewer });
+        let graph = GraphServiceActor::new(client_manager.start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for (id, name, tags) in [(1, "a.md", "physics"), (2, "b.md", "physics"), (3, "c.md", "hobby")] {
+            let node = Node::new_with_id(name.to_string(), Some(id)).with_metadata("tags".to_string(), tags.to_string());
+            graph.send(AddNode { node }).await.unwrap().unwrap();
+        }
+        let before = graph.send(GetGraphData).await.unwrap().unwrap().generation;
+
+        let set = AttributeSet {
+            color: Some("#ff8800".into()),
+            metadata: HashMap::from([("reviewed".to_string(), "yes".to_string())]),
+            ..Default::default()
+        };
+        let filter = GraphFilter { metadata: HashMap::from([("tags".to_string(), "physics".to_string())]), ..Default::default() };
+        let outcome = graph.send(UpdateNodeAttributes { node_ids: None, filter: Some(filter), set: set.clone(), actor: "editor".into() })
+            .await.unwrap().unwrap();
+        let ids: Vec<u32> = outcome.results.iter().map(|r| r.node_id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(outcome.updated, 2);
+        assert!(outcome.generation > before);
+
+        // Both copies of the node see the change
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        for id in [1, 2] {
+            assert_eq!(data.nodes.iter().find(|n| n.id == id).unwrap().color.as_deref(), Some("#ff8800"));
+            assert_eq!(nodes[&id].metadata["reviewed"], "yes");
+        }
+        assert!(!nodes[&3].metadata.contains_key("reviewed"));
+
+        // Missing ids are reported rather than failing the batch; nothing changed, so nothing is sent
+        let outcome = graph.send(UpdateNodeAttributes { node_ids: Some(vec![1, 99]), filter: None, set, actor: "editor".into() })
+            .await.unwrap().unwrap();
+        let statuses: Vec<AttributeUpdateStatus> = outcome.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![AttributeUpdateStatus::Unchanged, AttributeUpdateStatus::NotFound]);
+        assert!(outcome.event.is_none());
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let diffs: Vec<serde_json::Value> = received.lock().unwrap().iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+            .filter(|v| v["type"] == "node_attribute_diff")
+            .collect();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0]["nodeIds"], serde_json::json!([1, 2]));
+        assert_eq!(diffs[0]["changedKeys"], serde_json::json!(["color", "metadata.reviewed"]));
+        assert_eq!(diffs[0]["set"]["color"], "#ff8800");
+        assert_eq!(diffs[0]["actor"], "editor");
+    }
+
+    #[actix_web::test]
+    async fn test_transaction_is_all_or_nothing_with_one_diff() {
+        use crate::actors::client_manager_actor::ClientHandle;
+        use crate::config::feature_access::Role;
+        use crate::utils::auth::Identity;
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let socket = RecordingSocket { received: received.clone() }.start();
+        let mut client_manager = ClientManagerActor::new();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        client_manager.register_client(handle, Identity { pubkey: None, role: Role::Viewer });
+        let graph = GraphServiceActor::new(client_manager.start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        for id in 1..=2 {
+            graph.send(AddNode { node: Node::new_with_id(format!("n{}", id), Some(id)) }).await.unwrap().unwrap();
+        }
+        graph.send(AddEdge { edge: Edge::new(1, 2, 1.0) }).await.unwrap().unwrap();
+        let before = graph.send(GetGraphData).await.unwrap().unwrap();
+        let ops = |json: serde_json::Value| serde_json::from_value::<Vec<TransactionOp>>(json).unwrap();
+
+        // Fails at the third operation: the node and edge before it are discarded too
+        let failing = ops(serde_json::json!([
+            { "op": "create_node", "handle": "hub", "label": "Hub" },
+            { "op": "create_edge", "source": "hub", "target": 1 },
+            { "op": "remove_edge", "edgeId": "2-9" }
+        ]));
+        let err = graph.send(ApplyGraphTransaction { ops: failing, actor: "script".into() }).await.unwrap().unwrap_err();
+        assert_eq!(err.op_index, Some(2));
+        assert_eq!(err.reason, "edge 2-9 not found");
+        let after = graph.send(GetGraphData).await.unwrap().unwrap();
+        assert_eq!(after.generation, before.generation);
+        assert_eq!(after.nodes.len(), 2);
+        assert_eq!(after.edges.len(), 1);
+        assert_eq!(graph.send(GetNodeMap).await.unwrap().unwrap().len(), 2);
+
+        let batch = ops(serde_json::json!([
+            { "op": "create_node", "handle": "hub", "label": "Hub" },
+            { "op": "create_node", "handle": "leaf", "label": "Leaf", "metadata": { "tags": "draft" } },
+            { "op": "create_edge", "source": "hub", "target": "leaf" },
+            { "op": "create_edge", "source": "hub", "target": 1 },
+            { "op": "retag", "node": 2, "tags": ["reviewed"] }
+        ]));
+        let outcome = graph.send(ApplyGraphTransaction { ops: batch, actor: "script".into() }).await.unwrap().unwrap();
+        let (hub, leaf) = (outcome.handles["hub"], outcome.handles["leaf"]);
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        assert_eq!(nodes[&leaf].group.as_deref(), Some("draft"));
+        assert_eq!(nodes[&2].metadata["tags"], "reviewed");
+        // Started next to node 1 rather than at the origin
+        assert_ne!(nodes[&hub].data.position.x, 0.0);
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        assert!(data.edges.iter().any(|e| e.source == hub && e.target == leaf));
+        assert_eq!(outcome.diff.generation, Some(data.generation));
+
+        let undone = graph.send(UndoGraphTransaction { actor: "script".into() }).await.unwrap().unwrap();
+        assert_eq!(undone.transaction_id, outcome.transaction_id);
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        assert_eq!(data.nodes.len(), 2);
+        assert_eq!(data.edges.len(), 1);
+        assert!(!graph.send(GetNodeMap).await.unwrap().unwrap()[&2].metadata.contains_key("tags"));
+        assert!(graph.send(UndoGraphTransaction { actor: "script".into() }).await.unwrap().is_err());
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        // Nothing for the failed batch, one diff for the applied one and one for its undo
+        let diffs: Vec<serde_json::Value> = received.lock().unwrap().iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+            .filter(|v| v["type"] == "graph_diff")
+            .collect();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0]["actor"], "script");
+        assert_eq!(diffs[0]["addedNodes"].as_array().unwrap().len(), 2);
+        assert_eq!(diffs[0]["addedEdges"].as_array().unwrap().len(), 2);
+        assert_eq!(diffs[0]["updatedNodes"][0]["nodeId"], 2);
+        assert_eq!(diffs[1]["removedNodes"], serde_json::json!([hub, leaf]));
+    }
+
+    #[actix_web::test]
+    async fn test_runtime_changes_survive_a_restart() {
+        use crate::models::node_attributes::AttributeSet;
+
+        let file = |name: &str, topics: &[(&str, usize)]| Metadata {
+            file_name: name.to_string(),
+            topic_counts: topics.iter().map(|(t, c)| (t.to_string(), *c)).collect(),
+            ..Default::default()
+        };
+        let mut store = MetadataStore::new();
+        store.insert("Rust.md".to_string(), file("Rust.md", &[("Ownership.md", 2)]));
+        store.insert("Ownership.md".to_string(), file("Ownership.md", &[]));
+        store.insert("Lifetimes.md".to_string(), file("Lifetimes.md", &[]));
+        let dir = std::env::temp_dir().join(format!("graph-journal-{}", uuid::Uuid::new_v4()));
+
+        let start = || {
+            let (dir, store) = (dir.clone(), store.clone());
+            async move {
+                let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+                graph.send(StopSimulation).await.unwrap().unwrap();
+                graph.send(UseGraphJournal { dir }).await.unwrap().unwrap();
+                graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+                graph
+            }
+        };
+        let graph = start().await;
+        let nodes = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let id_of = |nodes: &HashMap<u32, Node>, metadata_id: &str| nodes.values().find(|n| n.metadata_id == metadata_id).unwrap().id;
+
+        let (note, _) = graph.send(CreateNode {
+            label: "Borrowing".to_string(),
+            metadata: HashMap::new(),
+            link_to: Some(id_of(&nodes, "Rust")),
+            edge_type: "spoken".to_string(),
+        }).await.unwrap().unwrap();
+        let set = AttributeSet { color: Some("#00ff00".to_string()), ..Default::default() };
+        graph.send(UpdateNodeAttributes { node_ids: Some(vec![id_of(&nodes, "Lifetimes")]), filter: None, set, actor: "editor".into() })
+            .await.unwrap().unwrap();
+        graph.send(RemoveNode { node_id: id_of(&nodes, "Ownership"), rewire: false }).await.unwrap().unwrap();
+        let ops = serde_json::from_value::<Vec<TransactionOp>>(serde_json::json!([
+            { "op": "create_node", "handle": "t", "label": "Traits", "metadata": { "tags": "draft" } },
+            { "op": "create_edge", "source": "t", "target": note.id }
+        ])).unwrap();
+        graph.send(ApplyGraphTransaction { ops, actor: "script".into() }).await.unwrap().unwrap();
+
+        // The writer is in the background; wait for it to catch up
+        let mut written = 0;
+        for _ in 0..100 {
+            written = graph_journal::read_journal(&dir).unwrap().records.len();
+            if written == 6 {
+                break;
+            }
+            actix::clock::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(written, 6);
+
+        let restarted = start().await;
+        let data = restarted.send(GetGraphData).await.unwrap().unwrap();
+        let mut labels: Vec<&str> = data.nodes.iter().map(|n| n.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["Borrowing", "Lifetimes", "Rust", "Traits"]);
+        let nodes = restarted.send(GetNodeMap).await.unwrap().unwrap();
+        assert_eq!(nodes.len(), 4);
+        let (borrowing, traits) = (id_of(&nodes, &note.metadata_id), nodes.values().find(|n| n.label == "Traits").unwrap());
+        assert_eq!(traits.group.as_deref(), Some("draft"));
+        assert_eq!(nodes[&id_of(&nodes, "Lifetimes")].color.as_deref(), Some("#00ff00"));
+        let mut edges: Vec<(u32, u32, Option<&str>)> = data.edges.iter().map(|e| (e.source, e.target, e.edge_type.as_deref())).collect();
+        edges.sort();
+        let mut expected = vec![(borrowing, id_of(&nodes, "Rust"), Some("spoken")), (traits.id, borrowing, Some("scripted"))];
+        expected.sort();
+        assert_eq!(edges, expected);
+
+        // A new runtime node doesn't take a metadata id a replayed one already has
+        let (fresh, _) = restarted.send(CreateNode { label: "Macros".to_string(), metadata: HashMap::new(), link_to: None, edge_type: "spoken".to_string() })
+            .await.unwrap().unwrap();
+        assert!(nodes.values().all(|n| n.metadata_id != fresh.metadata_id && n.id != fresh.id));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[actix_web::test]
+    async fn test_node_ids_are_stable_across_restarts_and_instances() {
+        use crate::models::node_ids::NodeIdMap;
+
+        let path = std::env::temp_dir().join(format!("id-map-{}.json", uuid::Uuid::new_v4()));
+        let store = |names: &[&str]| -> MetadataStore {
+            names.iter().map(|name| (format!("{}.md", name), Metadata { file_name: format!("{}.md", name), ..Default::default() })).collect()
+        };
+        // Each instance loads the shared file, as a fresh process would
+        let start = || {
+            let path = path.clone();
+            async move {
+                let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+                graph.send(StopSimulation).await.unwrap().unwrap();
+                graph.send(UseNodeIdMap { ids: NodeIdMap::load(path).shared() }).await.unwrap();
+                graph
+            }
+        };
+        let ids = |nodes: HashMap<u32, Node>| -> HashMap<String, u32> { nodes.into_values().map(|n| (n.metadata_id, n.id)).collect() };
+
+        let graph = start().await;
+        graph.send(BuildGraphFromMetadata { metadata: store(&["Alpha", "Beta", "Gamma"]) }).await.unwrap().unwrap();
+        let (runtime, _) = graph.send(CreateNode { label: "Note".into(), metadata: HashMap::new(), link_to: None, edge_type: "spoken".into() })
+            .await.unwrap().unwrap();
+        let first = ids(graph.send(GetNodeMap).await.unwrap().unwrap());
+        // Rebuilds used to hand out fresh ids every time
+        graph.send(BuildGraphFromMetadata { metadata: store(&["Alpha", "Beta", "Gamma"]) }).await.unwrap().unwrap();
+        assert_eq!(ids(graph.send(GetNodeMap).await.unwrap().unwrap())["Beta"], first["Beta"]);
+
+        // After a restart, survivors keep their ids and a new file takes one nobody had
+        let restarted = start().await;
+        restarted.send(BuildGraphFromMetadata { metadata: store(&["Beta", "Delta", "Gamma"]) }).await.unwrap().unwrap();
+        let second = ids(restarted.send(GetNodeMap).await.unwrap().unwrap());
+        assert_eq!((second["Beta"], second["Gamma"]), (first["Beta"], first["Gamma"]));
+        assert!(![first["Alpha"], runtime.id].contains(&second["Delta"]));
+
+        // Two instances building new files at once collide, and the next builds split them
+        let (one, two) = (start().await, start().await);
+        let (a, b) = futures::join!(
+            one.send(BuildGraphFromMetadata { metadata: store(&["Beta", "Epsilon"]) }),
+            two.send(BuildGraphFromMetadata { metadata: store(&["Beta", "Zeta"]) }),
+        );
+        a.unwrap().unwrap();
+        b.unwrap().unwrap();
+        let all = store(&["Beta", "Epsilon", "Zeta"]);
+        for instance in [&one, &two] {
+            instance.send(BuildGraphFromMetadata { metadata: all.clone() }).await.unwrap().unwrap();
+        }
+        let (one, two) = (ids(one.send(GetNodeMap).await.unwrap().unwrap()), ids(two.send(GetNodeMap).await.unwrap().unwrap()));
+        assert_eq!(one, two);
+        assert_eq!(one["Beta"], first["Beta"]);
+        let mut unique: Vec<u32> = one.values().copied().collect();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 3);
+        let _ = std::fs::remove_file(path);
     }
 }
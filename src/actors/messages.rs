@@ -3,14 +3,25 @@
 use actix::prelude::*;
 use glam::Vec3;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use crate::models::node::Node;
 use crate::models::edge::Edge;
 use crate::models::metadata::MetadataStore;
-use crate::config::AppFullSettings;
-use crate::models::graph::GraphData as ServiceGraphData;
-use crate::utils::socket_flow_messages::BinaryNodeData;
-use crate::models::simulation_params::SimulationParams;
+use crate::config::{AgingSettings, AppFullSettings, AttentionSettings, ColorMappingSettings, EdgeWeightSettings};
+use crate::models::graph::{GraphData as ServiceGraphData, GraphGenerations, GraphSnapshot, GraphStats};
+use crate::models::position_history::HistoryStep;
+use crate::models::layout::NodeLayout;
+use crate::models::node_ids::SharedNodeIds;
+use crate::utils::spatial_index::{SpatialQuery, SpatialResult};
+use crate::utils::node_watch::{WatchError, WatchEvent, WatchInfo};
+use crate::utils::position_recording::{RecordedFrame, RecordingState, RecordingStatus, ReplayStatus};
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::utils::socket_flow_messages::{BinaryNodeData, PoseUpdate};
+use crate::utils::time_sync::FrameTiming;
+use crate::models::simulation_params::{SimulationParams, SimulationPhase};
+use crate::services::embedding_service::SimilarityPair;
 use crate::models::graph::GraphData as ModelsGraphData;
 
 // Graph Service Actor Messages
@@ -18,6 +29,11 @@ use crate::models::graph::GraphData as ModelsGraphData;
 #[rtype(result = "Result<ServiceGraphData, String>")]
 pub struct GetGraphData;
 
+// Read-only and O(1); prefer this over GetGraphData unless the caller needs to mutate
+#[derive(Message)]
+#[rtype(result = "Result<GraphSnapshot, String>")]
+pub struct GetGraphSnapshot;
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct UpdateNodePositions {
@@ -30,10 +46,24 @@ pub struct AddNode {
     pub node: Node,
 }
 
+// Creates a runtime (non-file) node with a server-assigned id, optionally linked
+// to an existing node. Returns the new node and edge so callers can broadcast them.
 #[derive(Message)]
-#[rtype(result = "Result<(), String>")]
+#[rtype(result = "Result<(Node, Option<Edge>), String>")]
+pub struct CreateNode {
+    pub label: String,
+    pub metadata: HashMap<String, String>,
+    pub link_to: Option<u32>,
+    pub edge_type: String,
+}
+
+// Removes a node and its edges; with `rewire` its neighbours are linked directly first.
+// The diff covers the removals and any rewired edges.
+#[derive(Message)]
+#[rtype(result = "Result<crate::models::graph::GraphDiff, String>")]
 pub struct RemoveNode {
     pub node_id: u32,
+    pub rewire: bool,
 }
 
 #[derive(Message)]
@@ -52,6 +82,13 @@ pub struct RemoveEdge {
 #[rtype(result = "Result<HashMap<u32, Node>, String>")]
 pub struct GetNodeMap;
 
+// A node and its direct neighbours
+#[derive(Message)]
+#[rtype(result = "Result<HashSet<u32>, String>")]
+pub struct GetEgoNetwork {
+    pub node_id: u32,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct BuildGraphFromMetadata {
@@ -68,6 +105,54 @@ pub struct UpdateNodePosition {
     pub node_id: u32,
     pub position: Vec3,
     pub velocity: Vec3,
+    // Set for moves made by hand in a client; only these are undoable
+    pub edited_by: Option<EditAttribution>,
+}
+
+// Moves a multi-selection as one rigid body. The group is locked to the mover and
+// pinned for `settle`; fails without moving anything if any node is locked by someone else.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct TransformNodes {
+    pub node_ids: Vec<u32>,
+    pub translation: Vec3,
+    pub rotation: Option<glam::Quat>,
+    pub pivot: Vec3,
+    pub settle: Duration,
+    pub edited_by: Option<EditAttribution>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EditAttribution {
+    pub client_id: usize,
+    pub room: String,
+}
+
+// Current node positions plus the position generation, which only moves when the
+// layout actually changes
+#[derive(Message)]
+#[rtype(result = "Result<(u64, Vec<NodeLayout>), String>")]
+pub struct GetLayout;
+
+// Load saved positions into the live graph and broadcast a full keyframe. Result is
+// the number of nodes placed.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct WarmStartLayout {
+    pub nodes: Vec<NodeLayout>,
+}
+
+// Revert/reapply the room's most recent manual position edit
+#[derive(Message)]
+#[rtype(result = "Result<HistoryStep, String>")]
+pub struct UndoPositionEdit {
+    pub room: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<HistoryStep, String>")]
+pub struct RedoPositionEdit {
+    pub room: String,
 }
 
 #[derive(Message)]
@@ -78,12 +163,379 @@ pub struct SimulationStep;
 #[rtype(result = "Result<(), String>")]
 pub struct StopSimulation;
 
+// Final stop during server shutdown; the simulation loop is not restarted afterwards
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct ShutdownGraph;
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct UpdateGraphData {
     pub graph_data: ServiceGraphData,
 }
 
+// Merges entries into a node's metadata map without a rebuild
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct UpdateNodeMetadata {
+    pub metadata_id: String,
+    pub entries: HashMap<String, String>,
+}
+
+// Replaces the aging settings and re-runs aging right away; the result is how many
+// nodes are archived
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct SetAgingSettings {
+    pub settings: AgingSettings,
+}
+
+// Re-derives metadata edge weights from their raw counts without a rebuild; the result
+// is how many edges changed. GPU buffers need a fresh upload afterwards.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct SetEdgeWeightSettings {
+    pub settings: EdgeWeightSettings,
+}
+
+// Edge types left out of physics for every client
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetEdgeTypePhysics {
+    pub disabled: Vec<String>,
+}
+
+// Switches edge types into or out of physics at runtime (type -> affects physics).
+// Replies with every type now left out.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<String>, String>")]
+pub struct ToggleEdgeTypePhysics {
+    pub changes: HashMap<String, bool>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<String>")]
+pub struct GetEdgeTypePhysics;
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetEdgeDecaySettings {
+    pub settings: crate::config::EdgeDecaySettings,
+}
+
+// One decay pass covering `elapsed_secs` since the last; returns what changed
+#[derive(Message)]
+#[rtype(result = "Result<crate::models::graph::GraphDiff, String>")]
+pub struct DecayEdges {
+    pub elapsed_secs: f64,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetRewireSettings {
+    pub settings: crate::config::RewireSettings,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetWarmupSettings {
+    pub settings: crate::config::WarmupSettings,
+}
+
+// Whether the layout has finished warming up after the last rebuild
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::warmup::WarmupStatus, String>")]
+pub struct GetWarmupStatus;
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetIdleSettings {
+    pub settings: crate::config::IdleSettings,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetFrameBudgetSettings {
+    pub settings: crate::config::FrameBudgetSettings,
+}
+
+// How far physics is backing off because steps overrun the frame budget
+#[derive(Message)]
+#[rtype(result = "crate::utils::frame_budget::FrameBudgetStatus")]
+pub struct GetFrameBudgetStatus;
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetAnalyticsRefreshSettings {
+    pub settings: crate::config::AnalyticsRefreshSettings,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetDisturbanceSettings {
+    pub settings: crate::config::DisturbanceSettings,
+}
+
+// Whether physics is being calmed after a drag or insertion, and how hard
+#[derive(Message)]
+#[rtype(result = "crate::utils::disturbance::DisturbanceStatus")]
+pub struct GetDisturbanceStatus;
+
+// Kicks the chosen nodes to shake a settled layout loose; held nodes are left alone
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::reheat::ReheatOutcome, String>")]
+pub struct ReheatLayout {
+    pub request: crate::utils::reheat::ReheatRequest,
+}
+
+// Switches the simulation mode at runtime; every client is told the new mode
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::simulation_clock::SimulationModeStatus, String>")]
+pub struct SetSimulationSettings {
+    pub settings: crate::config::SimulationSettings,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<crate::config::SimulationSettings, String>")]
+pub struct GetSimulationSettings;
+
+// Whether the simulation is paused for lack of clients
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::idle::IdleStatus, String>")]
+pub struct GetIdleStatus;
+
+// The graph as physics sees it: edges of physics-disabled types removed
+#[derive(Message)]
+#[rtype(result = "Result<ServiceGraphData, String>")]
+pub struct GetPhysicsGraph;
+
+// Ids of the skeleton's edges and the graph generation they belong to
+#[derive(Message)]
+#[rtype(result = "Result<(u64, Arc<Vec<String>>), String>")]
+pub struct GetSkeleton {
+    pub strategy: crate::utils::skeleton::SkeletonStrategy,
+}
+
+// Communities collapsed into super-nodes, for the current generation
+#[derive(Message)]
+#[rtype(result = "Arc<crate::utils::community_view::GraphView>")]
+pub struct GetCommunityView;
+
+// PageRank by node id if it's cached for the current generation, with that generation
+#[derive(Message)]
+#[rtype(result = "Option<(u64, Arc<HashMap<u32, f32>>)>")]
+pub struct GetCachedPageRank;
+
+// PageRank computed outside the actor; kept only if the graph is still at `generation`
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CachePageRank {
+    pub generation: u64,
+    pub ranks: Arc<HashMap<u32, f32>>,
+}
+
+// Springs only the given skeleton's edges, or every edge again with None. GPU buffers
+// need a fresh upload afterwards.
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetSkeletonSprings {
+    pub strategy: Option<crate::utils::skeleton::SkeletonStrategy>,
+}
+
+// Loads pins from the layout state file and re-applies them to the current graph
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct UsePinStore {
+    pub path: PathBuf,
+}
+
+// Loads the node alias table that rebuilds fold merged files through
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct UseAliasStore {
+    pub path: PathBuf,
+}
+
+// Shares the process's node id map with the graph actor, so builds and runtime nodes take
+// their ids from it; returns how many ids it holds
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct UseNodeIdMap {
+    pub ids: SharedNodeIds,
+}
+
+// Loads the runtime change journal in `dir`, replays it after every build and appends
+// every runtime change to it from then on; returns how many records were loaded
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct UseGraphJournal {
+    pub dir: PathBuf,
+}
+
+// Folds `merge` into `keep`: edges move over, metadata merges and the merged metadata
+// ids become aliases of the kept node. A dry run leaves the graph as it was.
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::node_merge::MergeOutcome, String>")]
+pub struct MergeNodes {
+    pub keep: u32,
+    pub merge: Vec<u32>,
+    pub dry_run: bool,
+}
+
+// Runs a scripted batch of edits all-or-nothing and broadcasts its net effect as one diff
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::graph_transaction::TransactionOutcome, crate::utils::graph_transaction::TransactionError>")]
+pub struct ApplyGraphTransaction {
+    pub ops: Vec<crate::utils::graph_transaction::TransactionOp>,
+    pub actor: String,
+}
+
+// Reverses the most recent transaction still on the undo stack
+#[derive(Message)]
+#[rtype(result = "Result<crate::utils::graph_transaction::TransactionOutcome, String>")]
+pub struct UndoGraphTransaction {
+    pub actor: String,
+}
+
+// Pins a node at `position` (or where it is now), or unpins it. Pins are persisted
+// right away and held against physics.
+#[derive(Message)]
+#[rtype(result = "Result<Option<crate::models::pins::PinInfo>, String>")]
+pub struct SetNodePin {
+    pub node_id: u32,
+    pub pinned: bool,
+    pub position: Option<Vec3>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<crate::models::pins::PinReport, String>")]
+pub struct GetPins;
+
+// The graph, node_map ids and persisted pins as one consistent view for the integrity check
+#[derive(Message)]
+#[rtype(result = "Result<crate::services::integrity_check::IntegrityView, String>")]
+pub struct GetIntegrityView;
+
+// Forgets persisted pins by metadata id, e.g. ones whose file is gone. Returns how many were dropped.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct DropPins {
+    pub metadata_ids: Vec<String>,
+}
+
+// Sets attributes on the nodes listed, or on every node the filter matches, in one step.
+// Missing ids are reported per id rather than failing the batch.
+#[derive(Message)]
+#[rtype(result = "Result<crate::models::node_attributes::AttributeUpdateOutcome, String>")]
+pub struct UpdateNodeAttributes {
+    pub node_ids: Option<Vec<u32>>,
+    pub filter: Option<crate::models::graph_filter::GraphFilter>,
+    pub set: crate::models::node_attributes::AttributeSet,
+    pub actor: String,
+}
+
+// Replaces the similarity edge set; edges are keyed by metadata id and re-applied after rebuilds
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct SetSimilarityEdges {
+    pub pairs: Vec<SimilarityPair>,
+    pub enabled: bool,
+    pub spring_multiplier: f32,
+}
+
+// Replaces the co-viewed edge layer; pairs are keyed by metadata id and re-applied after rebuilds
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct SetCoViewedEdges {
+    pub pairs: Vec<crate::utils::co_selection::CoViewedPair>,
+}
+
+// Gaze dwell on a node; returns the node's new attention score
+#[derive(Message)]
+#[rtype(result = "Result<f32, String>")]
+pub struct RecordGazeFocus {
+    pub node_id: u32,
+    pub dwell_ms: u64,
+    pub viewpoint: Option<Vec3>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<GraphStats, String>")]
+pub struct GetGraphStats {
+    pub lod_limit: usize,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<GraphGenerations, String>")]
+pub struct GetGenerations;
+
+/// Nodes near a point or in view, nearest first, capped at `limit`
+#[derive(Message)]
+#[rtype(result = "Result<SpatialResult, String>")]
+pub struct QuerySpatial {
+    pub query: SpatialQuery,
+    pub limit: usize,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateAttentionSettings {
+    pub settings: AttentionSettings,
+}
+
+// Recolours every node and broadcasts the colours that changed. Returns how many changed.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct SetColorMapping {
+    pub mapping: ColorMappingSettings,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<ColorMappingSettings, String>")]
+pub struct GetColorMapping;
+
+// Appends every broadcast position frame to `path`, starting with a keyframe, until
+// stopped or a cap is reached
+#[derive(Message)]
+#[rtype(result = "Result<RecordingStatus, String>")]
+pub struct StartRecording {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub max_duration: Duration,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<RecordingStatus, String>")]
+pub struct StopRecording;
+
+// Replaces physics with the recorded frames until the replay is stopped
+#[derive(Message)]
+#[rtype(result = "Result<ReplayStatus, String>")]
+pub struct StartReplay {
+    pub frames: Vec<RecordedFrame>,
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReplayCommand {
+    Play,
+    Pause,
+    Seek(u32),
+    Speed(f32),
+    Stop,
+}
+
+// None once the replay has been stopped
+#[derive(Message)]
+#[rtype(result = "Result<Option<ReplayStatus>, String>")]
+pub struct ControlReplay {
+    pub command: ReplayCommand,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<RecordingState, String>")]
+pub struct GetRecordingState;
+
 // Settings Actor Messages
 #[derive(Message)]
 #[rtype(result = "Result<AppFullSettings, String>")]
@@ -123,11 +575,55 @@ pub struct UpdateMetadata {
 #[rtype(result = "Result<(), String>")]
 pub struct RefreshMetadata;
 
+// Records a Perplexity refresh for one file and returns the updated store for persisting
+#[derive(Message)]
+#[rtype(result = "Result<MetadataStore, String>")]
+pub struct SetPerplexityLink {
+    pub file_name: String,
+    pub link: String,
+    pub processed_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Stores auto-tag proposals for one file; manual tags are left alone
+#[derive(Message)]
+#[rtype(result = "Result<MetadataStore, String>")]
+pub struct SetAutoTags {
+    pub file_name: String,
+    pub sha1: String,
+    pub proposals: Vec<String>,
+}
+
+// Accepts/rejects pending auto-tag proposals for one file
+#[derive(Message)]
+#[rtype(result = "Result<MetadataStore, String>")]
+pub struct ReviewAutoTags {
+    pub file_name: String,
+    pub accept: Vec<String>,
+    pub reject: Vec<String>,
+}
+
 // Client Manager Actor Messages
+#[cfg(feature = "websocket")]
 #[derive(Message)]
 #[rtype(result = "Result<usize, String>")]
 pub struct RegisterClient {
     pub addr: actix::Addr<crate::handlers::socket_flow_handler::SocketFlowServer>,
+    pub identity: crate::utils::auth::Identity,
+}
+
+// In-process stand-in for a socket, used by the dev load test and in tests
+#[cfg(any(test, feature = "loadtest"))]
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct RegisterSimulatedClient {
+    pub handle: crate::actors::client_manager_actor::ClientHandle,
+    pub identity: crate::utils::auth::Identity,
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<crate::utils::auth::Identity>")]
+pub struct GetClientIdentity {
+    pub client_id: usize,
 }
 
 #[derive(Message)]
@@ -140,6 +636,29 @@ pub struct UnregisterClient {
 #[rtype(result = "Result<(), String>")]
 pub struct BroadcastNodePositions {
     pub positions: Vec<u8>,
+    // Keyframes go to every client whole; deltas may be thinned for throttled clients
+    pub keyframe: bool,
+    // Nodes a throttled client must still get in every delta
+    pub priority: Arc<HashSet<u32>>,
+    // Graph generation the frame's node ids belong to
+    pub generation: u64,
+    // Sent in the frame header to clients that asked for interpolation hints
+    pub timing: FrameTiming,
+}
+
+// Agents only receive JSON text events, never binary position frames
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct RegisterAgent {
+    pub name: String,
+    pub text: Recipient<SendToClientText>,
+    pub close: Recipient<CloseConnection>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct UnregisterAgent {
+    pub agent_id: usize,
 }
 
 #[derive(Message)]
@@ -152,15 +671,188 @@ pub struct BroadcastMessage {
 #[rtype(result = "Result<usize, String>")]
 pub struct GetClientCount;
 
+// Connected clients per room; rooms nobody is in are left out
+#[derive(Message)]
+#[rtype(result = "HashMap<String, usize>")]
+pub struct GetRoomClientCounts;
+
+// A socket's frame accounting since its last report
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RecordFrameAccounting {
+    pub client_id: usize,
+    pub totals: crate::utils::frame_accounting::FrameTotals,
+}
+
+// Frame accounting per connected client since it connected
+#[derive(Message)]
+#[rtype(result = "HashMap<usize, crate::utils::frame_accounting::FrameTotals>")]
+pub struct GetFrameAccounting;
+
+// A position frame a socket sent to a client that asked for frame hashes
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RecordSentFrame {
+    pub client_id: usize,
+    pub frame: crate::utils::frame_hash::SentFrame,
+}
+
+// The last frames sent to a client, oldest first; None if it isn't connected
+#[derive(Message)]
+#[rtype(result = "Option<Vec<crate::utils::frame_hash::SentFrame>>")]
+pub struct GetSentFrames {
+    pub client_id: usize,
+}
+
+// A node the client selected. It becomes the client's current selection and, when
+// co-viewed edges are enabled, feeds them too.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RecordSelection {
+    pub client_id: usize,
+    pub node_id: u32,
+    // Resolved when the selection is made; co-viewed counts need it to outlive rebuilds
+    pub metadata_id: Option<String>,
+}
+
+// Graph-wide node importance for ranking each client's level of detail; sent when it changes
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetLodImportance {
+    pub importance: Arc<HashMap<u32, f32>>,
+}
+
+// Each client's current level-of-detail top nodes, most important first
+#[derive(Message)]
+#[rtype(result = "HashMap<usize, Vec<u32>>")]
+pub struct GetClientLod;
+
+// The node a user last selected in their graph client, matched by pubkey as captions are
+#[derive(Message)]
+#[rtype(result = "Option<u32>")]
+pub struct GetSelectedNode {
+    pub pubkey: String,
+}
+
+// Metadata id sets of the sessions that ended since the last call, with nothing to say
+// whose they were
+#[derive(Message)]
+#[rtype(result = "Vec<Vec<String>>")]
+pub struct TakeFinishedSelections;
+
+// Sets the nodes a client or agent watches, replacing what it watched before, or with a
+// `session` picks up the watch a dropped connection left. `subscriber` is a client or
+// agent id.
+#[derive(Message)]
+#[rtype(result = "Result<WatchInfo, WatchError>")]
+pub struct WatchNodes {
+    pub subscriber: usize,
+    pub session: Option<String>,
+    pub ids: Vec<u32>,
+    pub events: Vec<WatchEvent>,
+}
+
+// Node changes that aren't broadcast, for watchers of those nodes only
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct NotifyNodeChanges {
+    pub source: String,
+    pub changes: Vec<(u32, WatchEvent)>,
+}
+
+// Assigns a client to a room; clients start in the default room
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetClientRoom {
+    pub client_id: usize,
+    pub room: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct BroadcastToRoom {
+    pub room: String,
+    pub message: String,
+}
+
+// Text for one client; fails if it isn't connected
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SendToClient {
+    pub client_id: usize,
+    pub message: String,
+}
+
+// A transcription from a speech session sharing captions; sent on to the graph clients in
+// the speaker's room. Result is the number of clients reached.
+#[derive(Message, Debug, Clone, PartialEq)]
+#[rtype(result = "usize")]
+pub struct ShareCaption {
+    pub pubkey: Option<String>,
+    pub display_name: Option<String>,
+    pub text: String,
+    pub language: Option<String>,
+    pub is_final: bool,
+}
+
+// Relay a client's head/cursor pose to room peers; result is the number of peers reached
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct RelayPose {
+    pub client_id: usize,
+    pub update: PoseUpdate,
+}
+
+// Show or hide edge types for one client; result is the types that went from hidden
+// to shown, whose edges the client needs resending
+#[derive(Message)]
+#[rtype(result = "Result<Vec<String>, String>")]
+pub struct SetEdgeTypeVisibility {
+    pub client_id: usize,
+    pub types: HashMap<String, bool>,
+}
+
+// Edge types a client has hidden, for resending its view
+#[derive(Message)]
+#[rtype(result = "HashSet<String>")]
+pub struct GetHiddenEdgeTypes {
+    pub client_id: usize,
+}
+
 // Messages for ClientManagerActor to send to individual SocketFlowServer clients
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct SendToClientBinary(pub Vec<u8>);
+pub struct SendToClientBinary {
+    pub data: Vec<u8>,
+    pub keyframe: bool,
+    pub priority: Arc<HashSet<u32>>,
+    // This client's own top nodes, also sent in every throttled frame
+    pub lod: Arc<HashSet<u32>>,
+    pub generation: u64,
+    pub timing: FrameTiming,
+    // Of `data`, for clients that asked for frame hashes
+    pub hash: crate::utils::frame_hash::SharedHash,
+}
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct SendToClientText(pub String);
 
+// Ask a socket to close itself with a going-away close frame
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseConnection {
+    pub reason: String,
+}
+
+// Tell every client and agent the server is going down, then close their sockets.
+// Result is the number of sessions notified.
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct NotifyShutdown {
+    pub reason: String,
+}
+
 // GPU Compute Actor Messages
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
@@ -174,12 +866,28 @@ pub struct UpdateGPUGraphData {
     pub graph: ModelsGraphData,
 }
 
+// Where the GPU actor fetches the live physics graph when it rebuilds a lost context,
+// so the new instance starts from the CPU's positions rather than its last upload
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UseGraphSource {
+    pub source: Recipient<GetPhysicsGraph>,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct UpdateSimulationParams {
     pub params: SimulationParams,
 }
 
+// Picks which uploaded phase profile the next step runs with. Sent to the graph actor,
+// which passes it on to the GPU actor and may go back to dynamic after a disturbance.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetSimulationPhase {
+    pub phase: SimulationPhase,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct ComputeForces;
@@ -199,4 +907,22 @@ pub struct GPUStatus {
     pub failure_count: u32,
     pub iteration_count: u32,
     pub num_nodes: u32,
+    // Per-stage step timings; empty unless timing is switched on
+    pub timing: crate::utils::gpu_timing::GpuTimingSummary,
+    // Lost-context outages and the reconnects that ended them
+    pub reconnect: crate::utils::gpu_reconnect::ReconnectStats,
+}
+
+// Switches per-stage GPU step timing on or off
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetGpuTiming {
+    pub enabled: bool,
+}
+
+// The last `steps` timed GPU steps, oldest first
+#[derive(Message)]
+#[rtype(result = "Vec<crate::utils::gpu_timing::StepBreakdown>")]
+pub struct GetGpuStepTimings {
+    pub steps: usize,
 }
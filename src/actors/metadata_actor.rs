@@ -111,6 +111,45 @@ impl Handler<UpdateMetadata> for MetadataActor {
     }
 }
 
+impl Handler<SetPerplexityLink> for MetadataActor {
+    type Result = Result<MetadataStore, String>;
+
+    fn handle(&mut self, msg: SetPerplexityLink, _ctx: &mut Self::Context) -> Self::Result {
+        // The file may have been dropped by a rebuild while the request was in flight
+        let entry = self.metadata.get_mut(&msg.file_name)
+            .ok_or_else(|| format!("No metadata for {}", msg.file_name))?;
+        entry.perplexity_link = msg.link;
+        entry.last_perplexity_process = Some(msg.processed_at);
+        Ok(self.metadata.clone())
+    }
+}
+
+impl Handler<SetAutoTags> for MetadataActor {
+    type Result = Result<MetadataStore, String>;
+
+    fn handle(&mut self, msg: SetAutoTags, _ctx: &mut Self::Context) -> Self::Result {
+        let entry = self.metadata.get_mut(&msg.file_name)
+            .ok_or_else(|| format!("No metadata for {}", msg.file_name))?;
+        // Content changed while the proposal was generated; the next pass will redo it
+        if entry.sha1 != msg.sha1 {
+            return Err(format!("{} changed while tagging", msg.file_name));
+        }
+        entry.set_auto_tags(msg.proposals, &msg.sha1);
+        Ok(self.metadata.clone())
+    }
+}
+
+impl Handler<ReviewAutoTags> for MetadataActor {
+    type Result = Result<MetadataStore, String>;
+
+    fn handle(&mut self, msg: ReviewAutoTags, _ctx: &mut Self::Context) -> Self::Result {
+        let entry = self.metadata.get_mut(&msg.file_name)
+            .ok_or_else(|| format!("No metadata for {}", msg.file_name))?;
+        entry.review_auto_tags(&msg.accept, &msg.reject);
+        Ok(self.metadata.clone())
+    }
+}
+
 impl Handler<RefreshMetadata> for MetadataActor {
     type Result = Result<(), String>;
 
@@ -4,6 +4,10 @@ pub mod graph_actor;
 pub mod settings_actor;
 pub mod metadata_actor;
 pub mod client_manager_actor;
+#[cfg(feature = "gpu")]
+pub mod gpu_compute_actor;
+#[cfg(not(feature = "gpu"))]
+#[path = "gpu_compute_actor_disabled.rs"]
 pub mod gpu_compute_actor;
 pub mod protected_settings_actor;
 pub mod messages;
@@ -1,23 +1,68 @@
+use std::collections::HashMap;
 use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
 use actix::prelude::*;
-use actix_web::web;
-use log::info;
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::{info, warn};
 
+use crate::actors::messages::{BroadcastMessage, CreateNode, DropPins, GetGenerations, GetGraphSnapshot, GetIntegrityView, GetMetadata, GetPhysicsGraph, GetSettings, SetAgingSettings, SetAnalyticsRefreshSettings, SetColorMapping, SetEdgeDecaySettings, SetEdgeTypePhysics, SetDisturbanceSettings, SetEdgeWeightSettings, SetFrameBudgetSettings, SetRewireSettings, SetIdleSettings, SetSimulationSettings, SetSkeletonSprings, SetWarmupSettings, ToggleEdgeTypePhysics, UpdateAttentionSettings, UpdateGPUGraphData, UpdateMetadata, UpdateSimulationParams, UseAliasStore, UseGraphJournal, UseGraphSource, UseNodeIdMap, UsePinStore};
 use crate::actors::{GraphServiceActor, SettingsActor, MetadataActor, ClientManagerActor, GPUComputeActor, ProtectedSettingsActor};
-use crate::config::AppFullSettings; // Renamed for clarity, ClientFacingSettings removed
+use crate::config::{AppFullSettings, EdgeWeightSettings, PhysicsOverrides, TopicExtractionSettings, WebhookEventKind}; // Renamed for clarity, ClientFacingSettings removed
 use tokio::time::Duration;
-use crate::config::feature_access::FeatureAccess;
+use crate::config::feature_access::{FeatureAccess, Role};
 use crate::models::metadata::MetadataStore;
+use crate::models::metadata_schema::MetadataSchema;
+use crate::models::spatial_anchor::DEFAULT_ROOM;
+use crate::models::graph::GraphDiff;
+use crate::models::node::Node;
+use crate::models::node_ids::{NodeIdMap, SharedNodeIds, NODE_ID_MAP_PATH};
 use crate::models::protected_settings::{ProtectedSettings, ApiKeys, NostrUser};
 use crate::services::github::{GitHubClient, ContentAPI};
 use crate::services::perplexity_service::PerplexityService;
 use crate::services::speech_service::SpeechService;
 use crate::services::ragflow_service::RAGFlowService;
 use crate::services::nostr_service::NostrService;
+use crate::services::agent_service::AgentService;
+use crate::services::anchor_service::AnchorService;
+use crate::services::audit_log::{AuditLog, AuditRecord};
+use crate::services::embedding_service::EmbeddingService;
+use crate::services::enrichment_service::EnrichmentService;
+use crate::services::admin_feed::AdminFeed;
+use crate::services::event_log::EventLog;
+use crate::services::saved_view_service::SavedViewService;
+use crate::services::file_service::FileService;
+use crate::services::graph_service::{self, GraphRebuilds};
+use crate::services::integrity_check::{self, AppliedFixes, IntegrityInput, IntegrityReport, IssueKind};
+use crate::services::label_placement_service::LabelPlacementService;
+use crate::services::layout_quality_service::LayoutQualityService;
+use crate::services::layout_snapshot_service::LayoutSnapshotService;
+use crate::services::recording_service::RecordingService;
+use crate::services::room_access::RoomAccessService;
+use crate::services::external_links::ExternalLinkService;
+use crate::services::room_physics::RoomPhysicsService;
+use crate::services::edge_bundle_service::EdgeBundleService;
+use crate::services::co_view_service::CoViewService;
+use crate::services::edge_decay_service::EdgeDecayService;
+use crate::services::speech_session_service::SpeechSessionService;
+use crate::services::pagination_session_service::PaginationSessionService;
+use crate::services::job_service::JobService;
+use crate::services::annotation_service::AnnotationService;
+use crate::services::preview_service::PreviewService;
+use crate::services::query_service::{QueryResult, QueryService};
+use crate::services::summary_service::SummaryService;
+use crate::services::tagging_service::TaggingService;
+use crate::services::telemetry_service::TelemetryService;
+use crate::services::topic_extraction::{self, BuildReport};
+use crate::services::webhook_service::WebhookService;
+use crate::utils::auth::{self, AccessControl, Identity};
+use crate::utils::co_selection::CO_VIEWED_EDGE_TYPE;
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::skeleton::SkeletonStrategy;
 
 #[derive(Clone)]
 pub struct AppState {
     pub graph_service_addr: Addr<GraphServiceActor>,
+    // Rebuilds asked for while one runs wait behind it, newest store winning
+    pub graph_rebuilds: Arc<GraphRebuilds>,
     pub gpu_compute_addr: Option<Addr<GPUComputeActor>>, // Changed to actor address
     pub settings_addr: Addr<SettingsActor>,
     pub protected_settings_addr: Addr<ProtectedSettingsActor>,
@@ -29,7 +74,41 @@ pub struct AppState {
     pub ragflow_service: Option<Arc<RAGFlowService>>,
     pub speech_service: Option<Arc<SpeechService>>,
     pub nostr_service: Option<web::Data<NostrService>>,
+    pub anchor_service: Arc<AnchorService>,
+    pub annotation_service: Arc<AnnotationService>,
+    pub event_log: Arc<EventLog>,
+    // Durable record of who changed what, queried at `/api/audit`
+    pub audit_log: Arc<AuditLog>,
+    // Diagnostics, stats, events and client notices for `/ws/admin`
+    pub admin_feed: Arc<AdminFeed>,
+    pub agent_service: Arc<AgentService>,
+    pub preview_service: Arc<PreviewService>,
+    pub telemetry_service: Arc<TelemetryService>,
+    pub enrichment_service: Arc<EnrichmentService>,
+    pub embedding_service: Arc<EmbeddingService>,
+    pub query_service: Arc<QueryService>,
+    pub summary_service: Arc<SummaryService>,
+    pub tagging_service: Arc<TaggingService>,
+    pub layout_snapshot_service: Arc<LayoutSnapshotService>,
+    pub recording_service: Arc<RecordingService>,
+    pub room_physics: Arc<RoomPhysicsService>,
+    pub room_access: Arc<RoomAccessService>,
+    // Links between documents in different rooms, shown as portals
+    pub external_links: Arc<ExternalLinkService>,
+    pub metadata_schema: Arc<MetadataSchema>,
+    // Numeric node ids by metadata id, shared by every path that assigns them
+    pub node_ids: SharedNodeIds,
+    pub edge_bundle_service: Arc<EdgeBundleService>,
+    pub label_placements: Arc<LabelPlacementService>,
+    pub saved_views: Arc<SavedViewService>,
+    pub speech_sessions: Arc<SpeechSessionService>,
+    pub pagination_sessions: Arc<PaginationSessionService>,
+    pub jobs: Arc<JobService>,
+    // Shared by every worker's rate limit middleware
+    pub rate_limiter: Arc<RateLimiter>,
+    pub webhooks: Arc<WebhookService>,
     pub feature_access: web::Data<FeatureAccess>,
+    pub access_control: AccessControl,
     pub ragflow_session_id: String,
     pub active_connections: Arc<AtomicUsize>,
 }
@@ -47,32 +126,170 @@ impl AppState {
         info!("[AppState::new] Initializing actor system");
         tokio::time::sleep(Duration::from_millis(50)).await;
         
+        let event_log = Arc::new(EventLog::new());
+        let audit_log = Arc::new(AuditLog::new(settings.system.audit.clone()));
+        let webhooks = Arc::new(WebhookService::new(settings.system.webhooks.clone(), event_log.clone()));
+        webhooks.clone().start();
+        let admin_feed = Arc::new(AdminFeed::new(event_log.clone()));
+        let graph_rebuilds = Arc::new(GraphRebuilds::new());
+
         // Start actors
         info!("[AppState::new] Starting ClientManagerActor");
-        let client_manager_addr = ClientManagerActor::new().start();
+        let client_manager_addr = ClientManagerActor::new()
+            .with_webhooks(webhooks.clone())
+            .with_captions(settings.system.captions.clone())
+            .with_node_watches(settings.system.node_watches.clone())
+            .with_co_view(&settings.system.co_view)
+            .with_client_lod(settings.system.client_lod.clone())
+            .with_admin_feed(admin_feed.clone())
+            .start();
         
+        let attention_settings = settings.system.attention.clone();
+        let color_mapping = settings.system.color_mapping.clone();
+        let aging_settings = settings.system.aging.clone();
+        let edge_weight_settings = settings.system.edge_weights.clone();
+        let rewire_settings = settings.system.rewire.clone();
+        let mut edge_type_settings = settings.system.edge_types.clone();
+        let co_view_settings = settings.system.co_view.clone();
+        if !co_view_settings.affects_physics {
+            edge_type_settings.physics_disabled.push(CO_VIEWED_EDGE_TYPE.to_string());
+        }
+        let edge_decay_settings = settings.system.edge_decay.clone();
+        let warmup_settings = settings.system.warmup.clone();
+        let idle_settings = settings.system.idle.clone();
+        let frame_budget_settings = settings.system.frame_budget.clone();
+        let analytics_refresh_settings = settings.system.analytics_refresh.clone();
+        let disturbance_settings = settings.system.disturbance.clone();
+        let enrichment_settings = settings.system.enrichment.clone();
+        let embedding_settings = settings.system.embeddings.clone();
+        let summary_settings = settings.system.summaries.clone();
+        let tagging_settings = settings.system.tagging.clone();
+        let agent_settings = settings.system.agents.clone();
+        let layout_snapshot_settings = settings.system.layout_snapshots.clone();
+        let recording_settings = settings.system.recording.clone();
+        let access_settings = settings.system.access.clone();
+        let speech_session_settings = settings.system.speech_sessions.clone();
+        let pagination_session_settings = settings.system.pagination_sessions.clone();
+        let job_settings = settings.system.jobs.clone();
+        let simulation_settings = settings.system.simulation.clone();
+        let rate_limiter = Arc::new(RateLimiter::new(settings.system.rate_limit.clone()));
+        rate_limiter.clone().start();
+        let layout_quality_settings = settings.system.layout_quality.clone();
+        let room_physics = Arc::new(RoomPhysicsService::new(settings.system.rooms.clone()));
+        let external_links = Arc::new(ExternalLinkService::new(&settings.system.external_links));
+        let metadata_schema = Arc::new(MetadataSchema::new(&settings.system.metadata_schema));
+        let global_physics = settings.visualisation.physics.clone();
+
         info!("[AppState::new] Starting SettingsActor");
         let settings_addr = SettingsActor::new(settings).start();
         
         info!("[AppState::new] Starting MetadataActor");
         let metadata_addr = MetadataActor::new(MetadataStore::new()).start();
         
-        info!("[AppState::new] Starting GPUComputeActor");
-        let gpu_compute_addr = Some(GPUComputeActor::new().start());
+        // Deterministic simulations stay on the CPU; GPU float reductions don't reproduce
+        let gpu_compute_addr = if simulation_settings.deterministic {
+            info!("[AppState::new] Deterministic simulation, not starting GPUComputeActor");
+            None
+        } else if !cfg!(feature = "gpu") {
+            info!("[AppState::new] Built without the gpu feature, not starting GPUComputeActor");
+            None
+        } else {
+            info!("[AppState::new] Starting GPUComputeActor");
+            Some(GPUComputeActor::new().with_event_log(event_log.clone()).start())
+        };
+        // The shared graph's loop belongs to the default room, so its overrides apply there
+        if room_physics.overrides(DEFAULT_ROOM) != PhysicsOverrides::default() {
+            if let Some(gpu_compute_addr) = &gpu_compute_addr {
+                gpu_compute_addr.do_send(UpdateSimulationParams { params: room_physics.params(DEFAULT_ROOM, &global_physics) });
+            }
+        }
         
         info!("[AppState::new] Starting GraphServiceActor");
         let graph_service_addr = GraphServiceActor::new(
             client_manager_addr.clone(),
             gpu_compute_addr.clone()
-        ).start();
+        ).with_event_log(event_log.clone()).with_external_links(external_links.clone()).start();
+        if let Some(gpu_compute_addr) = &gpu_compute_addr {
+            gpu_compute_addr.do_send(UseGraphSource { source: graph_service_addr.clone().recipient() });
+        }
+        graph_service_addr.do_send(UpdateAttentionSettings { settings: attention_settings });
+        graph_service_addr.do_send(SetColorMapping { mapping: color_mapping });
+        graph_service_addr.do_send(SetAgingSettings { settings: aging_settings });
+        graph_service_addr.do_send(SetEdgeWeightSettings { settings: edge_weight_settings });
+        graph_service_addr.do_send(SetRewireSettings { settings: rewire_settings });
+        graph_service_addr.do_send(SetEdgeTypePhysics { disabled: edge_type_settings.physics_disabled });
+        graph_service_addr.do_send(SetEdgeDecaySettings { settings: edge_decay_settings.clone() });
+        graph_service_addr.do_send(SetWarmupSettings { settings: warmup_settings });
+        graph_service_addr.do_send(SetIdleSettings { settings: idle_settings });
+        graph_service_addr.do_send(SetFrameBudgetSettings { settings: frame_budget_settings });
+        graph_service_addr.do_send(SetAnalyticsRefreshSettings { settings: analytics_refresh_settings });
+        graph_service_addr.do_send(SetDisturbanceSettings { settings: disturbance_settings });
+        graph_service_addr.do_send(SetSimulationSettings { settings: simulation_settings });
+        graph_service_addr.do_send(UsePinStore { path: std::path::PathBuf::from(crate::models::pins::LAYOUT_STATE_PATH) });
+        // Ids metadata already carries seed the map, so nodes keep them when it first appears
+        let mut node_ids = NodeIdMap::load(std::path::PathBuf::from(NODE_ID_MAP_PATH));
+        if let Ok(metadata) = FileService::load_or_create_metadata() {
+            let stored = metadata.iter()
+                .map(|(file_name, meta)| (file_name.trim_end_matches(".md"), meta.node_id.parse().unwrap_or(0)));
+            match node_ids.backfill(stored) {
+                Ok(0) => {}
+                Ok(count) => info!("Backfilled {} node ids from metadata", count),
+                Err(e) => warn!("Failed to save backfilled node ids: {}", e),
+            }
+        }
+        let node_ids = node_ids.shared();
+        graph_service_addr.do_send(UseNodeIdMap { ids: node_ids.clone() });
+        graph_service_addr.do_send(UseAliasStore { path: std::path::PathBuf::from(crate::models::node_aliases::NODE_ALIASES_PATH) });
+        graph_service_addr.do_send(UseGraphJournal { dir: std::path::PathBuf::from(crate::models::graph_journal::GRAPH_JOURNAL_DIR) });
         
+        // Background link refresh; a no-op unless enabled and perplexity is configured
+        let enrichment_service = Arc::new(
+            EnrichmentService::new(perplexity_service.clone(), enrichment_settings).with_webhooks(webhooks.clone()),
+        );
+        enrichment_service.clone().start(metadata_addr.clone(), graph_service_addr.clone());
+
+        let embedding_service = Arc::new(EmbeddingService::new(embedding_settings));
+        embedding_service.clone().start(metadata_addr.clone(), graph_service_addr.clone());
+
+        let summary_service = Arc::new(SummaryService::new(summary_settings));
+        summary_service.clone().start();
+
+        // Auto-tagging shares the summaries LLM backend
+        let tagging_service = Arc::new(TaggingService::new(summary_service.backend(), tagging_settings));
+        tagging_service.clone().start(metadata_addr.clone(), graph_service_addr.clone());
+
+        let layout_snapshot_service = Arc::new(LayoutSnapshotService::new(layout_snapshot_settings));
+        layout_snapshot_service.clone().start(graph_service_addr.clone());
+
+        let annotation_service = Arc::new(AnnotationService::new());
+        let agent_service = Arc::new(AgentService::new(
+            agent_settings,
+            graph_service_addr.clone(),
+            client_manager_addr.clone(),
+            annotation_service.clone(),
+            event_log.clone(),
+        ).with_audit_log(audit_log.clone()));
+        let edge_bundle_service = Arc::new(EdgeBundleService::new(event_log.clone()));
+        // Only runs when some edge type has a half-life
+        Arc::new(EdgeDecayService::new(edge_decay_settings, event_log.clone())).start(graph_service_addr.clone());
+        Arc::new(LayoutQualityService::new(layout_quality_settings, event_log.clone(), room_physics.clone()))
+            .start(graph_service_addr.clone(), settings_addr.clone(), gpu_compute_addr.clone());
+        Arc::new(CoViewService::new(co_view_settings)).start(graph_service_addr.clone(), client_manager_addr.clone());
+        admin_feed.clone().start(graph_service_addr.clone(), client_manager_addr.clone(), gpu_compute_addr.clone(), graph_rebuilds.clone());
+
         info!("[AppState::new] Starting ProtectedSettingsActor");
         let protected_settings_addr = ProtectedSettingsActor::new(ProtectedSettings::default()).start();
         
         info!("[AppState::new] Actor system initialization complete");
-        
+
+        let feature_access = web::Data::new(FeatureAccess::from_env());
+        if access_settings.read_only_mode {
+            info!("[AppState::new] Read-only mode: only admins can make changes");
+        }
+
         Ok(Self {
             graph_service_addr,
+            graph_rebuilds,
             gpu_compute_addr,
             settings_addr,
             protected_settings_addr,
@@ -84,7 +301,36 @@ impl AppState {
             ragflow_service,
             speech_service,
             nostr_service: None,
-            feature_access: web::Data::new(FeatureAccess::from_env()),
+            anchor_service: Arc::new(AnchorService::new()),
+            annotation_service,
+            event_log,
+            audit_log,
+            admin_feed,
+            agent_service,
+            preview_service: Arc::new(PreviewService::new()),
+            telemetry_service: Arc::new(TelemetryService::new()),
+            enrichment_service,
+            embedding_service,
+            query_service: Arc::new(QueryService::new()),
+            summary_service,
+            tagging_service,
+            layout_snapshot_service,
+            recording_service: Arc::new(RecordingService::new(recording_settings)),
+            room_physics,
+            room_access: Arc::new(RoomAccessService::new()),
+            external_links,
+            metadata_schema,
+            node_ids,
+            edge_bundle_service,
+            label_placements: Arc::new(LabelPlacementService::new()),
+            saved_views: Arc::new(SavedViewService::new()),
+            speech_sessions: Arc::new(SpeechSessionService::new(speech_session_settings)),
+            pagination_sessions: Arc::new(PaginationSessionService::new(pagination_session_settings)),
+            jobs: Arc::new(JobService::new(job_settings)),
+            rate_limiter,
+            webhooks,
+            access_control: AccessControl::new(feature_access.clone(), access_settings),
+            feature_access,
             ragflow_session_id,
             active_connections: Arc::new(AtomicUsize::new(0)),
         })
@@ -139,6 +385,11 @@ impl AppState {
         self.feature_access.is_power_user(pubkey)
     }
 
+    /// Resolves the caller's role and rejects it with a `forbidden` error below `required`
+    pub async fn require_role(&self, req: &HttpRequest, required: Role) -> Result<Identity, HttpResponse> {
+        auth::require_role(req, self.nostr_service.as_ref().map(|n| n.get_ref()), &self.access_control, required).await
+    }
+
     pub fn can_sync_settings(&self, pubkey: &str) -> bool {
         self.feature_access.can_sync_settings(pubkey)
     }
@@ -166,4 +417,193 @@ impl AppState {
     pub fn get_metadata_addr(&self) -> &Addr<MetadataActor> {
         &self.metadata_addr
     }
+
+    /// Switches the edge weight transform at runtime. Edges are reweighted in place and the
+    /// GPU gets a fresh copy of the graph, since its edge buffer still holds the old weights.
+    pub async fn set_edge_weight_settings(&self, settings: EdgeWeightSettings) -> Result<usize, String> {
+        let changed = self.graph_service_addr.send(SetEdgeWeightSettings { settings }).await
+            .map_err(|e| format!("Graph service unavailable: {}", e))??;
+        if changed > 0 {
+            if let Some(gpu_addr) = &self.gpu_compute_addr {
+                let graph = self.graph_service_addr.send(GetPhysicsGraph).await
+                    .map_err(|e| format!("Graph service unavailable: {}", e))??;
+                gpu_addr.do_send(UpdateGPUGraphData { graph });
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Switches physics between springing every edge and only a skeleton's, at runtime.
+    /// The GPU gets a fresh copy of the graph with the new edge set.
+    pub async fn set_skeleton_springs(&self, strategy: Option<SkeletonStrategy>) -> Result<(), String> {
+        self.graph_service_addr.send(SetSkeletonSprings { strategy }).await
+            .map_err(|e| format!("Graph service unavailable: {}", e))??;
+        if let Some(gpu_addr) = &self.gpu_compute_addr {
+            let graph = self.graph_service_addr.send(GetPhysicsGraph).await
+                .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            gpu_addr.do_send(UpdateGPUGraphData { graph });
+        }
+        Ok(())
+    }
+
+    /// Switches edge types into or out of physics at runtime (type -> affects physics).
+    /// The GPU gets a fresh copy of the graph with the new edge set.
+    pub async fn set_edge_type_physics(&self, changes: HashMap<String, bool>) -> Result<Vec<String>, String> {
+        let disabled = self.graph_service_addr.send(ToggleEdgeTypePhysics { changes }).await
+            .map_err(|e| format!("Graph service unavailable: {}", e))??;
+        if let Some(gpu_addr) = &self.gpu_compute_addr {
+            let graph = self.graph_service_addr.send(GetPhysicsGraph).await
+                .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            gpu_addr.do_send(UpdateGPUGraphData { graph });
+        }
+        Ok(disabled)
+    }
+
+    /// Rebuilds the graph from `metadata`, queued behind any rebuild already running;
+    /// resolves once this metadata or newer has been built
+    pub async fn rebuild_graph(&self, metadata: MetadataStore) -> Result<(), String> {
+        graph_service::rebuild_graph(&self.graph_rebuilds, &self.graph_service_addr, metadata).await
+    }
+
+    /// Fills in the topic_counts the upstream extractor left empty, when the fallback
+    /// pass is enabled, so the rebuild that follows gets edges for those files
+    pub async fn prepare_metadata_for_build(&self, metadata: MetadataStore) -> (MetadataStore, BuildReport) {
+        let settings = match self.settings_addr.send(GetSettings).await {
+            Ok(Ok(settings)) => settings.system.topic_extraction,
+            _ => TopicExtractionSettings::default(),
+        };
+        let (metadata, report) = topic_extraction::prepare_for_build(metadata, &settings).await;
+        self.webhooks.notify(WebhookEventKind::BuildReport, serde_json::to_value(&report).unwrap_or_default());
+        (metadata, report)
+    }
+
+    /// Cross-checks metadata, the graph and persisted layout state. With `fix`, orphaned pins
+    /// and snapshot entries are dropped and bad or colliding node ids reassigned, then the graph
+    /// rebuilt from the fixed metadata; the rest is only reported. The report lists what was
+    /// found before fixing, and goes to the event log.
+    pub async fn verify_integrity(&self, fix: bool) -> Result<IntegrityReport, String> {
+        let metadata = self.metadata_addr.send(GetMetadata).await
+            .map_err(|e| format!("Metadata service unavailable: {}", e))??;
+        let view = self.graph_service_addr.send(GetIntegrityView).await
+            .map_err(|e| format!("Graph service unavailable: {}", e))??;
+        let annotated = self.annotation_service.metadata_ids().await;
+        let mut layouts = Vec::new();
+        for snapshot in self.layout_snapshot_service.list().await? {
+            match self.layout_snapshot_service.load(&snapshot.name).await {
+                Ok(file) => layouts.push((snapshot.name, file.nodes)),
+                Err(e) => warn!("Integrity check skipping layout snapshot: {}", e),
+            }
+        }
+        let mut report = integrity_check::check(&IntegrityInput {
+            metadata: &metadata,
+            view: &view,
+            annotated: &annotated,
+            layouts: &layouts,
+        });
+
+        if fix {
+            let mut fixes = AppliedFixes::default();
+            let orphan_pins: Vec<String> = report.of_kind(IssueKind::OrphanPin).map(|i| i.subject.clone()).collect();
+            if !orphan_pins.is_empty() {
+                fixes.dropped_pins = self.graph_service_addr.send(DropPins { metadata_ids: orphan_pins }).await
+                    .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            }
+            let known = integrity_check::metadata_ids(&metadata);
+            for (name, nodes) in &layouts {
+                if nodes.iter().any(|n| !known.contains(&n.metadata_id)) {
+                    fixes.dropped_layout_entries += self.layout_snapshot_service.retain_nodes(name, |id| known.contains(id)).await?;
+                }
+            }
+            if report.of_kind(IssueKind::InvalidNodeId).chain(report.of_kind(IssueKind::DuplicateNodeId)).next().is_some() {
+                let mut metadata = metadata;
+                fixes.reassigned_ids = {
+                    let mut ids = self.node_ids.lock().unwrap();
+                    let reassigned = integrity_check::reassign_node_ids(&mut metadata, &mut ids);
+                    ids.save()?;
+                    reassigned
+                };
+                FileService::save_metadata(&metadata).map_err(|e| format!("Failed to save metadata: {}", e))?;
+                self.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await
+                    .map_err(|e| format!("Metadata service unavailable: {}", e))??;
+                // Until it's rebuilt the graph still holds the nodes built from the bad ids
+                let (metadata, _) = self.prepare_metadata_for_build(metadata).await;
+                self.rebuild_graph(metadata).await?;
+                fixes.rebuilt_graph = true;
+            }
+            report.fixes = Some(fixes);
+        }
+
+        if report.issues.is_empty() {
+            info!("Integrity check passed");
+        } else {
+            warn!("Integrity check found {} auto-fixable and {} other problems", report.auto_fixable, report.needs_attention);
+        }
+        self.event_log.record("integrity", "verify", serde_json::to_value(&report).unwrap_or_default());
+        Ok(report)
+    }
+
+    /// Runs a natural-language graph query against the current graph. Shared by
+    /// the REST endpoint and the voice path.
+    pub async fn run_graph_query(&self, query: &str, candidate: Option<usize>) -> Result<QueryResult, String> {
+        let graph = self.graph_service_addr.send(GetGraphSnapshot).await
+            .map_err(|e| format!("Graph service unavailable: {}", e))??;
+        self.query_service.run(query, candidate, &graph).await
+    }
+
+    /// Creates a node from a spoken "new note" command, links it to the speaker's
+    /// focused node and pushes the diff to every client. Returns the node and the
+    /// node it was linked to, if the focus was still valid.
+    pub async fn create_spoken_note(&self, title: String, transcription: String, focused: Option<u32>) -> Result<(Node, Option<u32>), String> {
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert("source".to_string(), "speech".to_string());
+        metadata.insert("transcription".to_string(), transcription);
+        metadata.insert("createdAt".to_string(), chrono::Utc::now().to_rfc3339());
+
+        let (node, edge) = self.graph_service_addr.send(CreateNode {
+            label: title,
+            metadata,
+            link_to: focused,
+            edge_type: "spoken".to_string(),
+        }).await.map_err(|e| format!("Graph service unavailable: {}", e))??;
+
+        let generation = self.graph_service_addr.send(GetGenerations).await.ok()
+            .and_then(|r| r.ok())
+            .map(|g| g.generation);
+        let diff = GraphDiff {
+            added_nodes: vec![node.clone()],
+            added_edges: edge.iter().cloned().collect(),
+            generation,
+            ..Default::default()
+        };
+        self.event_log.record("speech", "create_node", serde_json::json!({ "nodeId": node.id, "label": node.label }));
+        self.audit_log.record("speech", AuditRecord::from_diff("create_node", &diff));
+        self.client_manager_addr.do_send(BroadcastMessage { message: diff.to_event("speech").to_string() });
+        Ok((node, edge.map(|e| e.target)))
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// A full state over the repo's own settings, with physics kept off the GPU and no
+    /// GitHub token or optional services, for tests that go through the real handlers
+    pub(crate) async fn for_tests() -> Self {
+        let mut settings: AppFullSettings = config::Config::builder()
+            .add_source(config::File::from(std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/data/settings.yaml"))))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .unwrap();
+        settings.system.simulation.deterministic = true;
+        let github_config = crate::services::github::config::GitHubConfig {
+            token: String::new(),
+            owner: "test".to_string(),
+            repo: "test".to_string(),
+            base_path: String::new(),
+            rate_limit: false,
+            version: "v3".to_string(),
+        };
+        let shared = Arc::new(tokio::sync::RwLock::new(settings.clone()));
+        let github_client = Arc::new(GitHubClient::new(github_config, shared).await.unwrap());
+        let content_api = Arc::new(ContentAPI::new(github_client.clone()));
+        Self::new(settings, github_client, content_api, None, None, None, String::new()).await.unwrap()
+    }
 }
@@ -2,6 +2,33 @@ use std::env;
 use std::fs;
 use std::path::PathBuf;
 use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// What a client may do. Ordered so a higher role includes everything below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Connect, read and watch position broadcasts
+    Viewer,
+    // Also move nodes, annotate and change graph content
+    Editor,
+    // Also server-wide settings and admin endpoints
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn can_edit(&self) -> bool {
+        *self >= Role::Editor
+    }
+}
 
 /// Represents the access control configuration for various features and user roles
 pub struct FeatureAccess {
@@ -16,6 +43,8 @@ pub struct FeatureAccess {
     // Role-based access control
     pub power_users: Vec<String>,
     pub settings_sync_enabled: Vec<String>,
+    // Authenticated but read-only
+    pub viewers: Vec<String>,
 }
 
 impl FeatureAccess {
@@ -33,6 +62,7 @@ impl FeatureAccess {
             // Role-based access
             power_users: Self::load_pubkeys_from_env("POWER_USER_PUBKEYS"),
             settings_sync_enabled: Self::load_pubkeys_from_env("SETTINGS_SYNC_ENABLED_PUBKEYS"),
+            viewers: Self::load_pubkeys_from_env("VIEWER_PUBKEYS"),
         }
     }
 
@@ -125,6 +155,18 @@ impl FeatureAccess {
         self.power_users.contains(&pubkey.to_string())
     }
 
+    /// Role of an authenticated pubkey: power users are admins, listed viewers are
+    /// read-only and everyone else can edit
+    pub fn role_for(&self, pubkey: &str) -> Role {
+        if self.is_power_user(pubkey) {
+            Role::Admin
+        } else if self.viewers.contains(&pubkey.to_string()) {
+            Role::Viewer
+        } else {
+            Role::Editor
+        }
+    }
+
     /// Checks if a pubkey has settings sync access
     pub fn can_sync_settings(&self, pubkey: &str) -> bool {
         // Power users automatically get settings sync access
@@ -177,6 +219,7 @@ mod tests {
         env::set_var("PERPLEXITY_ENABLED_PUBKEYS", "pub1,pub2");
         env::set_var("OPENAI_ENABLED_PUBKEYS", "pub1");
         env::set_var("SETTINGS_SYNC_ENABLED_PUBKEYS", "pub2");
+        env::set_var("VIEWER_PUBKEYS", "pub3");
     }
 
     #[test]
@@ -230,4 +273,15 @@ mod tests {
         assert!(pub2_features.contains(&"perplexity".to_string()));
         assert!(pub2_features.contains(&"settings_sync".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_roles() {
+        setup_test_env();
+        let access = FeatureAccess::from_env();
+
+        assert_eq!(access.role_for("pub1"), Role::Admin);
+        assert_eq!(access.role_for("pub2"), Role::Editor);
+        assert_eq!(access.role_for("pub3"), Role::Viewer);
+        assert!(Role::Admin.can_edit() && Role::Editor.can_edit() && !Role::Viewer.can_edit());
+    }
+}
@@ -3,7 +3,11 @@ use log::{debug, error}; // Added error log
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_yaml;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+
+use crate::models::metadata_schema::MetadataKey;
+use crate::models::simulation_params::SimulationMode;
 // use std::collections::BTreeMap; // For ordered map during serialization - Removed as unused
 
 pub mod feature_access;
@@ -109,6 +113,94 @@ pub struct PhysicsSettings {
     pub repulsion_distance: f32,
     pub mass_scale: f32,
     pub boundary_damping: f32,
+    // Scales each node's repulsion by 1 + factor * ln(degree); 0 is off
+    #[serde(default)]
+    pub degree_repulsion_factor: f32,
+    // Per-phase kernel parameters, over the ones derived from the settings above
+    #[serde(default)]
+    pub phases: PhysicsPhases,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+// One simulation phase's departures from its derived profile; unset fields keep it
+pub struct PhaseOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spring_strength: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repulsion_strength: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repulsion_distance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damping: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_step: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds_size: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_bounds: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boundary_damping: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PhysicsPhases {
+    pub initial: PhaseOverrides,
+    pub dynamic: PhaseOverrides,
+    pub finalize: PhaseOverrides,
+}
+
+macro_rules! physics_overrides {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+        #[serde(default)]
+        // A room's departures from the global physics settings; unset fields inherit
+        pub struct PhysicsOverrides {
+            $(
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $field: Option<$ty>,
+            )*
+        }
+
+        impl PhysicsOverrides {
+            pub fn apply(&self, base: &PhysicsSettings) -> PhysicsSettings {
+                let mut physics = base.clone();
+                $(
+                    if let Some(value) = self.$field {
+                        physics.$field = value;
+                    }
+                )*
+                physics
+            }
+
+            /// Fields set in `patch` replace ours; the rest are kept
+            pub fn merge(&mut self, patch: &PhysicsOverrides) {
+                $(
+                    if patch.$field.is_some() {
+                        self.$field = patch.$field;
+                    }
+                )*
+            }
+        }
+    };
+}
+
+physics_overrides! {
+    attraction_strength: f32,
+    bounds_size: f32,
+    collision_radius: f32,
+    damping: f32,
+    enable_bounds: bool,
+    enabled: bool,
+    iterations: u32,
+    max_velocity: f32,
+    repulsion_strength: f32,
+    spring_strength: f32,
+    repulsion_distance: f32,
+    mass_scale: f32,
+    boundary_damping: f32,
+    degree_repulsion_factor: f32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -289,6 +381,1071 @@ pub struct ServerSystemConfigFromFile {
     pub debug: DebugSettings, // Assumes YAML debug section matches DebugSettings struct fields (snake_case)
     #[serde(default)]
     pub persist_settings: bool,
+    #[serde(default)]
+    pub attention: AttentionSettings,
+    #[serde(default)]
+    pub enrichment: EnrichmentSettings,
+    #[serde(default)]
+    pub embeddings: EmbeddingSettings,
+    #[serde(default)]
+    pub summaries: SummarySettings,
+    #[serde(default)]
+    pub tagging: TaggingSettings,
+    #[serde(default)]
+    pub agents: AgentSettings,
+    #[serde(default)]
+    pub shutdown: ShutdownSettings,
+    #[serde(default)]
+    pub layout_snapshots: LayoutSnapshotSettings,
+    #[serde(default)]
+    pub access: AccessSettings,
+    #[serde(default)]
+    pub color_mapping: ColorMappingSettings,
+    #[serde(default)]
+    pub recording: RecordingSettings,
+    #[serde(default)]
+    pub group_transform: GroupTransformSettings,
+    #[serde(default)]
+    pub aging: AgingSettings,
+    #[serde(default)]
+    pub edge_weights: EdgeWeightSettings,
+    #[serde(default)]
+    pub edge_types: EdgeTypeSettings,
+    #[serde(default)]
+    pub edge_decay: EdgeDecaySettings,
+    #[serde(default)]
+    pub warmup: WarmupSettings,
+    #[serde(default)]
+    pub idle: IdleSettings,
+    #[serde(default)]
+    pub frame_budget: FrameBudgetSettings,
+    #[serde(default)]
+    pub analytics_refresh: AnalyticsRefreshSettings,
+    #[serde(default)]
+    pub disturbance: DisturbanceSettings,
+    #[serde(default)]
+    pub speech_sessions: SpeechSessionSettings,
+    #[serde(default)]
+    pub pagination_sessions: PaginationSessionSettings,
+    #[serde(default)]
+    pub speech_utterances: SpeechUtteranceSettings,
+    #[serde(default)]
+    pub speech_recordings: SpeechRecordingSettings,
+    #[serde(default)]
+    pub audit: AuditSettings,
+    #[serde(default)]
+    pub rewire: RewireSettings,
+    #[serde(default)]
+    pub captions: CaptionSettings,
+    #[serde(default)]
+    pub node_watches: NodeWatchSettings,
+    #[serde(default)]
+    pub jobs: JobSettings,
+    #[serde(default)]
+    pub simulation: SimulationSettings,
+    #[serde(default)]
+    pub topic_extraction: TopicExtractionSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+    #[serde(default)]
+    pub integrity: IntegritySettings,
+    #[serde(default)]
+    pub node_attributes: NodeAttributeSettings,
+    #[serde(default)]
+    pub metadata_schema: MetadataSchemaSettings,
+    #[serde(default)]
+    pub layout_quality: LayoutQualitySettings,
+    #[serde(default)]
+    pub co_view: CoViewSettings,
+    #[serde(default)]
+    pub client_lod: ClientLodSettings,
+    #[serde(default)]
+    pub physics_partitions: PhysicsPartitionSettings,
+    // Per-room physics, merged over visualisation.physics
+    #[serde(default)]
+    pub rooms: HashMap<String, PhysicsOverrides>,
+    #[serde(default)]
+    pub external_links: ExternalLinkSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+// Gaze attention scoring; server-only, never sent to clients
+pub struct AttentionSettings {
+    pub half_life_secs: f32,
+    pub feed_lod: bool,
+    pub lod_weight: f32,
+    pub attraction_enabled: bool,
+    pub attraction_strength: f32,
+}
+
+impl Default for AttentionSettings {
+    fn default() -> Self {
+        Self {
+            half_life_secs: 30.0, feed_lod: true, lod_weight: 1.0,
+            attraction_enabled: false, attraction_strength: 0.002,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+// Background Perplexity link refresh; uses the perplexity api settings for credentials
+pub struct EnrichmentSettings {
+    pub enabled: bool,
+    pub max_age_hours: u64,
+    pub concurrency: usize,
+    pub interval_secs: u64,
+    pub max_retries: u32,
+}
+
+impl Default for EnrichmentSettings {
+    fn default() -> Self {
+        Self { enabled: false, max_age_hours: 168, concurrency: 2, interval_secs: 3600, max_retries: 2 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Document embeddings for similarity edges; api_url is any OpenAI-compatible /embeddings server
+pub struct EmbeddingSettings {
+    pub enabled: bool,
+    pub api_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub timeout_secs: u64,
+    pub max_chars: usize,
+    pub interval_secs: u64,
+    pub similarity_threshold: f32,
+    pub max_neighbours: usize,
+    pub spring_multiplier: f32,
+}
+
+impl Default for EmbeddingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false, api_url: String::new(), api_key: None,
+            model: "text-embedding-3-small".to_string(), timeout_secs: 30, max_chars: 8000,
+            interval_secs: 600, similarity_threshold: 0.8, max_neighbours: 5, spring_multiplier: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// LLM node summaries; api_url is any OpenAI-compatible /chat/completions server
+pub struct SummarySettings {
+    pub enabled: bool,
+    pub api_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub timeout_secs: u64,
+    pub max_input_chars: usize,
+    pub max_tokens: u32,
+    pub max_summary_chars: usize,
+    pub workers: usize,
+    pub queue_size: usize,
+    pub requests_per_minute: u32,
+    pub retry_after_secs: u64,
+    // Regexes replaced with [REDACTED] before content is sent to the backend
+    pub redact_patterns: Vec<String>,
+}
+
+impl Default for SummarySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false, api_url: String::new(), api_key: None,
+            model: "gpt-4o-mini".to_string(), timeout_secs: 30, max_input_chars: 6000,
+            max_tokens: 120, max_summary_chars: 400, workers: 2, queue_size: 64,
+            requests_per_minute: 30, retry_after_secs: 5, redact_patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Auto-tag proposals; uses the summaries LLM backend
+pub struct TaggingSettings {
+    pub enabled: bool,
+    pub max_tags: usize,
+    pub vocabulary: Vec<String>,
+    // Accept any tag the model proposes instead of only vocabulary terms
+    pub open_vocabulary: bool,
+    pub interval_secs: u64,
+    pub max_input_chars: usize,
+    pub max_tokens: u32,
+}
+
+impl Default for TaggingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false, max_tags: 5, vocabulary: Vec::new(), open_vocabulary: false,
+            interval_secs: 3600, max_input_chars: 6000, max_tokens: 60,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgentKey {
+    pub name: String,
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Programmatic agents on /ws/agent; each key maps to the identity its changes are attributed to
+pub struct AgentSettings {
+    pub enabled: bool,
+    pub api_keys: Vec<AgentKey>,
+    pub max_batch_size: usize,
+    pub commands_per_minute: u32,
+}
+
+impl Default for AgentSettings {
+    fn default() -> Self {
+        Self { enabled: false, api_keys: Vec::new(), max_batch_size: 50, commands_per_minute: 600 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Ordered teardown on SIGTERM/SIGINT; stages still pending at the deadline are skipped
+pub struct ShutdownSettings {
+    pub deadline_secs: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self { deadline_secs: 20 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Periodic layout snapshots under data/layout_snapshots, newest `retention` kept
+pub struct LayoutSnapshotSettings {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub retention: usize,
+}
+
+impl Default for LayoutSnapshotSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval_secs: 600, retention: 24 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Caps on position stream recordings under data/recordings; a recording stops
+// itself at whichever limit it reaches first
+pub struct RecordingSettings {
+    pub max_megabytes: u64,
+    pub max_duration_secs: u64,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self { max_megabytes: 64, max_duration_secs: 1800 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Multi-select moves: the group is pinned and locked to the mover for this long
+// after each transform so physics doesn't pull it apart mid-drag
+pub struct GroupTransformSettings {
+    pub settle_ms: u64,
+}
+
+impl Default for GroupTransformSettings {
+    fn default() -> Self {
+        Self { settle_ms: 1500 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Documents untouched for a long time shrink and fade by half every half-life, and
+// are archived out of the default view after `hide_after_days` (0 never archives)
+pub struct AgingSettings {
+    pub enabled: bool,
+    pub half_life_days: f32,
+    pub hide_after_days: f32,
+    pub min_size_scale: f32,
+    pub interval_secs: u64,
+}
+
+impl Default for AgingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            half_life_days: 90.0,
+            hide_after_days: 365.0,
+            min_size_scale: 0.3,
+            interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeWeightTransform {
+    Linear,
+    Log,
+    Sqrt,
+    Rank,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// How shared-topic counts become edge weights. Counts are heavy-tailed, so without
+// `log`/`sqrt`/`rank` a few hub links dominate the springs. Applied before clamping.
+pub struct EdgeWeightSettings {
+    pub transform: EdgeWeightTransform,
+    pub min_weight: f32,
+    pub max_weight: f32,
+}
+
+impl Default for EdgeWeightSettings {
+    fn default() -> Self {
+        Self {
+            transform: EdgeWeightTransform::Linear,
+            min_weight: 0.0,
+            max_weight: 1_000_000.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+// Edge types left out of physics for everyone. Hiding a type in a client is display
+// only; its edges keep pulling on the layout unless the type is listed here.
+pub struct EdgeTypeSettings {
+    pub physics_disabled: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Edges of the listed types lose half their weight every `half_life_days` unless they
+// are re-created, and are removed once they fall below `floor`. Types not listed never decay.
+pub struct EdgeDecaySettings {
+    pub half_life_days: HashMap<String, f32>,
+    pub floor: f32,
+    pub interval_secs: u64,
+}
+
+impl Default for EdgeDecaySettings {
+    fn default() -> Self {
+        Self {
+            half_life_days: HashMap::new(),
+            floor: 0.05,
+            interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Physics runs without broadcasting after a (re)build until the layout has settled,
+// so clients never see the initial explosion. Ends after `max_iterations` steps or
+// once the mean squared step falls below `energy_threshold`, whichever comes first.
+pub struct WarmupSettings {
+    pub skip_warmup: bool,
+    pub max_iterations: u32,
+    pub energy_threshold: f32,
+    // Steps between progress messages to connected clients
+    pub progress_interval: u32,
+    // Wall-clock cap, for when physics steps slowly or not at all; 0 for none
+    pub max_secs: f32,
+}
+
+impl Default for WarmupSettings {
+    fn default() -> Self {
+        Self {
+            skip_warmup: false,
+            max_iterations: 300,
+            energy_threshold: 0.001,
+            progress_interval: 30,
+            max_secs: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Physics and broadcasting pause once no client has been connected for `idle_after_secs`.
+// The first client to connect wakes the loop, which settles for `wake_settle_iterations`
+// steps before sending a keyframe. `always_on` keeps the loop running regardless.
+pub struct IdleSettings {
+    pub always_on: bool,
+    pub idle_after_secs: f32,
+    pub wake_settle_iterations: u32,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            always_on: false,
+            idle_after_secs: 300.0,
+            wake_settle_iterations: 30,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Stale PageRank, skeleton and layout quality are recomputed in the background once the
+// layout settles, so the first request after a change finds them cached. Off, they are
+// only computed when something asks.
+pub struct AnalyticsRefreshSettings {
+    pub enabled: bool,
+}
+
+impl Default for AnalyticsRefreshSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// References between rooms declared up front, as "room:metadata_id" of the referring
+// document to "room:metadata_id" of the one it refers to, each with `weight`. More can
+// be imported at runtime. Clients show them as portals; they never enter physics.
+pub struct ExternalLinkSettings {
+    pub aliases: BTreeMap<String, String>,
+    pub weight: f32,
+}
+
+impl Default for ExternalLinkSettings {
+    fn default() -> Self {
+        Self { aliases: BTreeMap::new(), weight: 1.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// A physics step that takes longer than `budget_ms` holds physics off for the following
+// frames, more while steps keep overrunning (up to `max_skip_factor`) and fewer once they
+// fit. Past `max_consecutive_skips` frames skipped without a step fitting, an event
+// suggests lightening the load.
+pub struct FrameBudgetSettings {
+    pub enabled: bool,
+    pub budget_ms: f32,
+    pub max_skip_factor: u32,
+    pub max_consecutive_skips: u32,
+}
+
+impl Default for FrameBudgetSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // One tick of the simulation loop
+            budget_ms: 16.0,
+            max_skip_factor: 60,
+            max_consecutive_skips: 600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// A client drag or insertion that moves a node at least `displacement_threshold` damps
+// physics by an extra `peak_damping`, easing back to normal over `ramp_secs`. `scope`
+// picks whether the whole graph is damped or only the moved nodes and their neighbours.
+pub struct DisturbanceSettings {
+    pub enabled: bool,
+    pub displacement_threshold: f32,
+    pub peak_damping: f32,
+    pub ramp_secs: f32,
+    pub scope: DisturbanceScope,
+}
+
+impl Default for DisturbanceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            displacement_threshold: 1.0,
+            peak_damping: 0.3,
+            ramp_secs: 2.0,
+            scope: DisturbanceScope::Global,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisturbanceScope {
+    Global,
+    Neighbourhood,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// A speech session outlives its socket by `ttl_secs`, so a client that reconnects in time
+// can resume it. `max_sessions` caps how many exist at once.
+pub struct SpeechSessionSettings {
+    pub ttl_secs: f32,
+    pub max_sessions: usize,
+}
+
+impl Default for SpeechSessionSettings {
+    fn default() -> Self {
+        Self { ttl_secs: 1800.0, max_sessions: 500 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// A pagination session is dropped after `ttl_secs` without a page read. Past
+// `max_sessions` the least recently used one is dropped to make room.
+pub struct PaginationSessionSettings {
+    pub ttl_secs: f32,
+    pub max_sessions: usize,
+}
+
+impl Default for PaginationSessionSettings {
+    fn default() -> Self {
+        Self { ttl_secs: 300.0, max_sessions: 100 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// A client's node watch outlives its socket by `ttl_secs`, so a client that reconnects in
+// time can resume it. One watch covers at most `max_ids` nodes, and at most
+// `max_sessions` watches exist at once.
+pub struct NodeWatchSettings {
+    pub ttl_secs: f32,
+    pub max_ids: usize,
+    pub max_sessions: usize,
+}
+
+impl Default for NodeWatchSettings {
+    fn default() -> Self {
+        Self { ttl_secs: 600.0, max_ids: 1000, max_sessions: 1000 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Captions from speech sessions that opt in with `share_captions` go to every graph client
+// in the speaker's room, cut to `max_chars`. Rooms in `disabled_rooms` never get them.
+pub struct CaptionSettings {
+    pub enabled: bool,
+    pub max_chars: usize,
+    pub disabled_rooms: Vec<String>,
+}
+
+impl Default for CaptionSettings {
+    fn default() -> Self {
+        Self { enabled: true, max_chars: 280, disabled_rooms: Vec::new() }
+    }
+}
+
+impl CaptionSettings {
+    pub fn allows_room(&self, room: &str) -> bool {
+        self.enabled && !self.disabled_rooms.iter().any(|r| r == room)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Finished TTS audio stays downloadable for `retention_secs`. Past `max_utterances` the
+// oldest finished ones are dropped early.
+pub struct SpeechUtteranceSettings {
+    pub retention_secs: f32,
+    pub max_utterances: usize,
+}
+
+impl Default for SpeechUtteranceSettings {
+    fn default() -> Self {
+        Self { retention_secs: 600.0, max_utterances: 200 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Inbound audio of sessions an admin records, for debugging transcripts. Segments are
+// deleted after `retention_secs`; a session keeps its newest `max_segments_per_session`;
+// recording stops while the files take up `max_total_bytes`.
+pub struct SpeechRecordingSettings {
+    pub directory: String,
+    pub retention_secs: f32,
+    pub max_segments_per_session: usize,
+    pub max_total_bytes: u64,
+}
+
+impl Default for SpeechRecordingSettings {
+    fn default() -> Self {
+        Self {
+            directory: "/app/data/speech_recordings".to_string(),
+            retention_secs: 86400.0,
+            max_segments_per_session: 500,
+            max_total_bytes: 500 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Durable trail of authenticated mutations, as NDJSON rotated at `max_file_bytes` with
+// `max_files` kept. Entries older than `retention_days` (0 keeps everything) are dropped.
+// Before/after values over `max_change_bytes` are left out, and `redact_values` masks
+// them all, keeping only their keys.
+pub struct AuditSettings {
+    pub directory: String,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    pub retention_days: u32,
+    pub redact_values: bool,
+    pub max_change_bytes: usize,
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self {
+            directory: "/app/data/audit".to_string(),
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 10,
+            retention_days: 90,
+            redact_values: false,
+            max_change_bytes: 2048,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RewireWeight {
+    Min,
+    Product,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Removing a node with `rewire` links each pair of its neighbours directly, weighted by
+// the two removed edges. Nodes whose neighbours would need more than `max_edges` new
+// edges are removed without rewiring.
+pub struct RewireSettings {
+    pub weight: RewireWeight,
+    pub max_edges: usize,
+}
+
+impl Default for RewireSettings {
+    fn default() -> Self {
+        Self {
+            weight: RewireWeight::Min,
+            max_edges: 45,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Long-running graph operations. Handlers wait `inline_wait_ms` for a result before
+// answering with a job id; finished jobs stay pollable for `retention_secs`. At most
+// `max_running` run at once; further submissions get 429 until one finishes.
+pub struct JobSettings {
+    pub inline_wait_ms: u64,
+    pub retention_secs: f32,
+    pub max_jobs: usize,
+    pub max_running: usize,
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        Self { inline_wait_ms: 2000, retention_secs: 600.0, max_jobs: 200, max_running: 8 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Where the layout is computed. `remote` runs physics here and streams every step;
+// `local` leaves layout to the clients and only relays topology and metadata; `hybrid`
+// steps here at `hybrid_step_hz` as a reference for clients to blend with, and sends a
+// full authoritative keyframe every `correction_interval_secs`.
+// `deterministic` is for reproducible tests: physics stays on the CPU, randomness comes
+// from `seed`, and the loop only advances when stepped, on a virtual clock.
+// Physics runs in fixed 16ms substeps to keep up with real time; `max_substeps` caps how
+// many one loop iteration catches up, and time beyond that is skipped rather than run.
+pub struct SimulationSettings {
+    pub mode: SimulationMode,
+    pub hybrid_step_hz: f32,
+    pub correction_interval_secs: f32,
+    pub deterministic: bool,
+    pub seed: u64,
+    pub max_substeps: u32,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self { mode: SimulationMode::Remote, hybrid_step_hz: 5.0, correction_interval_secs: 5.0, deterministic: false, seed: 0, max_substeps: 4 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Fallback for files the upstream extractor left without topic_counts: before a rebuild
+// their markdown under `markdown_dir` is scanned for links to other files. Files over
+// `max_file_bytes` are skipped, and the whole pass stops after `time_budget_ms`.
+pub struct TopicExtractionSettings {
+    pub enabled: bool,
+    pub markdown_dir: String,
+    pub max_file_bytes: u64,
+    pub time_budget_ms: u64,
+}
+
+impl Default for TopicExtractionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            markdown_dir: "/app/data/markdown".to_string(),
+            max_file_bytes: 1024 * 1024,
+            time_budget_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+// A token bucket: up to `burst` requests at once, refilled at `per_second`
+pub struct RateBudget {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Limits on REST mutations (POST/PUT/PATCH/DELETE), per caller: the session's pubkey, or
+// the client address without a valid session. Paths under a `routes` prefix get their own
+// bucket (the longest prefix wins); every other mutation shares the `default` one.
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub default: RateBudget,
+    pub routes: HashMap<String, RateBudget>,
+    // Peer addresses whose X-Forwarded-For is believed; anyone else is charged by peer
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        let routes = HashMap::from([
+            ("/api/metadata/import".to_string(), RateBudget { burst: 2.0, per_second: 0.1 }),
+            ("/api/graph/refresh".to_string(), RateBudget { burst: 2.0, per_second: 0.2 }),
+            ("/api/graph/update".to_string(), RateBudget { burst: 2.0, per_second: 0.2 }),
+            // Clients report telemetry continuously
+            ("/api/telemetry".to_string(), RateBudget { burst: 60.0, per_second: 20.0 }),
+        ]);
+        Self { enabled: true, default: RateBudget { burst: 30.0, per_second: 10.0 }, routes, trusted_proxies: vec!["127.0.0.1".to_string()] }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    GraphDiff,
+    BuildReport,
+    Annotations,
+    Enrichment,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::GraphDiff => "graph_diff",
+            WebhookEventKind::BuildReport => "build_report",
+            WebhookEventKind::Annotations => "annotations",
+            WebhookEventKind::Enrichment => "enrichment",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    // Signs each body with HMAC-SHA256 when set
+    #[serde(default)]
+    pub secret: Option<String>,
+    // Categories to deliver; empty means all of them
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Outbound webhooks. A delivery is tried `max_attempts` times, backing off from
+// `initial_backoff_ms` up to `max_backoff_ms`. After `failure_threshold` failed deliveries
+// in a row an endpoint's circuit opens: its events are dropped, and it's re-probed every
+// `probe_interval_secs` until it answers.
+pub struct WebhookSettings {
+    pub endpoints: Vec<WebhookEndpoint>,
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub timeout_ms: u64,
+    pub failure_threshold: u32,
+    pub probe_interval_secs: f32,
+    pub queue_capacity: usize,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            timeout_ms: 5000,
+            failure_threshold: 5,
+            probe_interval_secs: 60.0,
+            queue_capacity: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Cross-checks metadata, the graph and persisted layout state once the first graph is
+// built. With `auto_fix`, orphaned layout entries are dropped and colliding node ids
+// reassigned; everything else is only reported.
+pub struct IntegritySettings {
+    pub check_on_startup: bool,
+    pub auto_fix: bool,
+}
+
+impl Default for IntegritySettings {
+    fn default() -> Self {
+        Self {
+            check_on_startup: true,
+            auto_fix: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+// Node types the bulk attribute API accepts; an empty list accepts any type
+pub struct NodeAttributeSettings {
+    pub node_types: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+// Node metadata keys beyond the built-in ones, each with a type (int, float, string,
+// date or enum with its values). Metadata writes are checked against them; with
+// `strict`, keys that are neither built in nor declared here are rejected.
+pub struct MetadataSchemaSettings {
+    pub strict: bool,
+    pub keys: BTreeMap<String, MetadataKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// The layout is scored every `interval_secs` and the score logged. With `auto_nudge`, once
+// the score sits on a plateau below `target_score`, spring and repulsion are stepped by
+// `step_fraction` within their ranges and a step is kept only if the score improves.
+pub struct LayoutQualitySettings {
+    pub interval_secs: u64,
+    pub auto_nudge: bool,
+    pub target_score: f32,
+    // Scores within `plateau_tolerance` of each other over this many samples count as a plateau
+    pub plateau_samples: usize,
+    pub plateau_tolerance: f32,
+    pub step_fraction: f32,
+    pub spring_range: [f32; 2],
+    pub repulsion_range: [f32; 2],
+}
+
+impl Default for LayoutQualitySettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            auto_nudge: false,
+            target_score: 0.75,
+            plateau_samples: 5,
+            plateau_tolerance: 0.01,
+            step_fraction: 0.1,
+            spring_range: [0.05, 1.0],
+            repulsion_range: [0.02, 1.0],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Behavioural `co_viewed` edges between nodes often selected in the same session. Off
+// by default. Ended sessions are kept only as anonymous node sets; every `interval_secs`
+// they are counted per pair, counts fade with `half_life_days`, and pairs counted at
+// least `threshold` times become edges weighing `weight_per_session` per count.
+// A session ends on disconnect or after `session_idle_secs` without a selection.
+pub struct CoViewSettings {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub max_selections_per_session: usize,
+    pub session_idle_secs: u64,
+    // Ended sessions waiting for the next run; the oldest are dropped beyond this
+    pub max_pending_sessions: usize,
+    pub max_pairs: usize,
+    pub threshold: f32,
+    pub weight_per_session: f32,
+    pub half_life_days: f32,
+    // Off keeps the layer out of the simulation, so it is only drawn
+    pub affects_physics: bool,
+}
+
+impl Default for CoViewSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 600,
+            max_selections_per_session: 50,
+            session_idle_secs: 1800,
+            max_pending_sessions: 1000,
+            max_pairs: 10_000,
+            threshold: 3.0,
+            weight_per_session: 0.1,
+            half_life_days: 7.0,
+            affects_physics: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Which nodes a throttled client gets in every frame beyond the shared priority set.
+// Every `refresh_secs` each client's top `top_n` are re-ranked by blending the graph-wide
+// importance (PageRank), how recently the client selected or gazed at a node (halving
+// every `interaction_half_life_secs`), and closeness to its viewpoint (falling off by
+// e over `proximity_scale` graph units).
+pub struct ClientLodSettings {
+    pub enabled: bool,
+    pub top_n: usize,
+    pub refresh_secs: f32,
+    pub importance_weight: f32,
+    pub interaction_weight: f32,
+    pub proximity_weight: f32,
+    pub interaction_half_life_secs: f32,
+    pub proximity_scale: f32,
+}
+
+impl Default for ClientLodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            top_n: 64,
+            refresh_secs: 2.0,
+            importance_weight: 1.0,
+            interaction_weight: 2.0,
+            proximity_weight: 1.0,
+            interaction_half_life_secs: 20.0,
+            proximity_scale: 50.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Partitioned CPU physics for graphs too big for one core. `partitions` 0 runs one per
+// available core; fewer run when that would leave a partition under
+// `min_nodes_per_partition`. The graph is repartitioned when its node count changes or
+// the share of edges crossing partitions grows by more than `repartition_threshold`.
+pub struct PhysicsPartitionSettings {
+    pub enabled: bool,
+    pub partitions: usize,
+    pub min_nodes_per_partition: usize,
+    pub repartition_threshold: f32,
+}
+
+impl Default for PhysicsPartitionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            partitions: 0,
+            min_nodes_per_partition: 500,
+            repartition_threshold: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+// Role enforcement. Clients that connect without a session get `anonymous_role`;
+// `read_only_mode` demotes everyone but admins to viewer.
+pub struct AccessSettings {
+    pub read_only_mode: bool,
+    pub anonymous_role: feature_access::Role,
+}
+
+impl Default for AccessSettings {
+    fn default() -> Self {
+        // Unauthenticated clients have always been able to edit
+        Self { read_only_mode: false, anonymous_role: feature_access::Role::Editor }
+    }
+}
+
+impl AccessSettings {
+    /// Role actually granted: the pubkey's role (or the anonymous one), capped at
+    /// viewer in read-only mode unless it is an admin
+    pub fn effective_role(&self, role: Option<feature_access::Role>) -> feature_access::Role {
+        let role = role.unwrap_or(self.anonymous_role);
+        if self.read_only_mode && role != feature_access::Role::Admin {
+            feature_access::Role::Viewer
+        } else {
+            role
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorStrategy {
+    // Leave node.color unset and let clients colour nodes themselves
+    None,
+    ByGroup,
+    ByNodeType,
+    ByMetadataKey,
+    ByAge,
+    ByPagerank,
+}
+
+// Categorical palettes, all colourblind-safe
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorPalette {
+    OkabeIto,
+    TolBright,
+    TolMuted,
+}
+
+// Perceptually uniform gradients for the continuous strategies
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorGradient {
+    Viridis,
+    Cividis,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+// Server-side node colouring so everyone in a room sees the same colours
+pub struct ColorMappingSettings {
+    pub strategy: ColorStrategy,
+    // Metadata entry to colour by with `by_metadata_key`
+    pub metadata_key: String,
+    pub palette: ColorPalette,
+    pub gradient: ColorGradient,
+}
+
+impl Default for ColorMappingSettings {
+    fn default() -> Self {
+        Self {
+            strategy: ColorStrategy::None,
+            metadata_key: String::new(),
+            palette: ColorPalette::OkabeIto,
+            gradient: ColorGradient::Viridis,
+        }
+    }
 }
 
 // --- Client-Facing Config Structs (for JSON, camelCase) ---
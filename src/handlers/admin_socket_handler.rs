@@ -0,0 +1,229 @@
+use actix::prelude::*;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use log::{info, warn};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::services::admin_feed::{AdminFeed, AdminFilter, AdminSubscriber};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Sent by the socket's pump whenever its subscriber has something queued
+#[derive(Message)]
+#[rtype(result = "()")]
+struct FlushOutbox;
+
+/// Websocket session for an ops dashboard. It sends `{"type": "subscribe", "topics": [...],
+/// "eventKinds": [...]}` once connected and gets the matching admin feed messages as JSON.
+pub struct AdminSocket {
+    feed: Arc<AdminFeed>,
+    subscription: Option<(usize, Arc<AdminSubscriber>)>,
+    heartbeat: Instant,
+}
+
+impl AdminSocket {
+    pub fn new(feed: Arc<AdminFeed>) -> Self {
+        Self { feed, subscription: None, heartbeat: Instant::now() }
+    }
+
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.heartbeat) > CLIENT_TIMEOUT {
+                info!("[AdminSocket] Dashboard heartbeat failed, disconnecting");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn handle_subscribe(&self, msg: serde_json::Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let filter = match serde_json::from_value::<AdminFilter>(msg) {
+            Ok(filter) if !filter.topics.is_empty() => filter,
+            Ok(_) => {
+                ctx.text(json!({ "type": "error", "message": "Subscribe to at least one topic" }).to_string());
+                return;
+            }
+            Err(e) => {
+                ctx.text(json!({ "type": "error", "message": format!("Invalid subscription: {}", e) }).to_string());
+                return;
+            }
+        };
+        let reply = json!({ "type": "subscribed", "topics": filter.topics, "eventKinds": filter.event_kinds });
+        if let Some((_, subscriber)) = &self.subscription {
+            subscriber.set_filter(filter);
+        }
+        ctx.text(reply.to_string());
+    }
+}
+
+impl Actor for AdminSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("[AdminSocket] Dashboard connected");
+        self.start_heartbeat(ctx);
+
+        let (id, subscriber) = self.feed.subscribe();
+        self.subscription = Some((id, subscriber.clone()));
+        // Waits on the subscriber and hands over to the socket one flush at a time, so a
+        // slow socket leaves messages in the outbox, where backpressure is applied
+        let addr = ctx.address();
+        tokio::spawn(async move {
+            loop {
+                subscriber.notified().await;
+                if addr.send(FlushOutbox).await.is_err() {
+                    break;
+                }
+            }
+        });
+        ctx.text(json!({ "type": "connected", "topics": ["diagnostics", "stats", "events", "clients"] }).to_string());
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some((id, _)) = self.subscription.take() {
+            self.feed.unsubscribe(id);
+        }
+        info!("[AdminSocket] Dashboard disconnected");
+    }
+}
+
+impl Handler<FlushOutbox> for AdminSocket {
+    type Result = ();
+
+    fn handle(&mut self, _msg: FlushOutbox, ctx: &mut Self::Context) {
+        let Some((_, subscriber)) = &self.subscription else {
+            return;
+        };
+        let (messages, lagging) = subscriber.take();
+        for message in messages {
+            match serde_json::to_string(&message) {
+                Ok(text) => ctx.text(text),
+                Err(e) => warn!("[AdminSocket] Failed to serialize admin message: {}", e),
+            }
+        }
+        if lagging {
+            warn!("[AdminSocket] Dashboard fell behind the admin feed, disconnecting");
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Again,
+                description: Some("Fell behind the admin feed; reconnect and catch up from /api/graph/events".to_string()),
+            }));
+            ctx.stop();
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for AdminSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.heartbeat = Instant::now();
+            }
+            Ok(ws::Message::Text(text)) => {
+                self.heartbeat = Instant::now();
+                match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(msg) => match msg.get("type").and_then(|t| t.as_str()) {
+                        Some("subscribe") => self.handle_subscribe(msg, ctx),
+                        Some("ping") => ctx.text(json!({ "type": "pong" }).to_string()),
+                        _ => ctx.text(json!({ "type": "error", "message": "Unknown message type" }).to_string()),
+                    },
+                    Err(e) => ctx.text(json!({ "type": "error", "message": format!("Invalid JSON: {}", e) }).to_string()),
+                }
+            }
+            Ok(ws::Message::Binary(_)) => {
+                ctx.text(json!({ "type": "error", "message": "The admin channel takes JSON text messages" }).to_string());
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+pub async fn admin_socket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Err(response) = app_state.require_role(&req, Role::Admin).await {
+        warn!("[AdminSocket] Rejected connection without the admin role");
+        return Ok(response);
+    }
+    ws::start(AdminSocket::new(app_state.admin_feed.clone()), &req, stream)
+}
+
+// Drives the real GPU actor's failure path
+#[cfg(all(test, feature = "gpu"))]
+mod tests {
+    use super::*;
+    use crate::actors::GPUComputeActor;
+    use crate::services::event_log::EventLog;
+    use crate::utils::gpu_reconnect::GpuError;
+    use actix_web::{App, HttpServer};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    async fn next_json(socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> serde_json::Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap().unwrap().unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_gpu_failure_reaches_a_subscribed_dashboard() {
+        let event_log = Arc::new(EventLog::new());
+        let feed = Arc::new(AdminFeed::new(event_log.clone()));
+        feed.clone().follow_events();
+
+        let server_feed = feed.clone();
+        let server = HttpServer::new(move || {
+            let feed = server_feed.clone();
+            App::new().route("/ws/admin", web::get().to(move |req: HttpRequest, stream: web::Payload| {
+                let feed = feed.clone();
+                async move { ws::start(AdminSocket::new(feed), &req, stream) }
+            }))
+        })
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws/admin", addr)).await.unwrap();
+        assert_eq!(next_json(&mut socket).await["type"], "connected");
+
+        let subscribe = json!({ "type": "subscribe", "topics": ["events"], "eventKinds": ["gpu_failure"] });
+        socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+        assert_eq!(next_json(&mut socket).await["type"], "subscribed");
+
+        // Filtered out, then the GPU path fails
+        event_log.record("agent:indexer", "create_node", json!({ "nodeId": 1 }));
+        let mut gpu = GPUComputeActor::new().with_event_log(event_log.clone());
+        assert!(gpu.handle_gpu_error(GpuError::Failed("Kernel launch failed: test".into())).is_err());
+
+        let message = next_json(&mut socket).await;
+        assert_eq!(message["type"], "event");
+        assert_eq!(message["data"]["kind"], "gpu_failure");
+        assert_eq!(message["data"]["actor"], "gpu");
+        assert_eq!(message["data"]["detail"]["error"], "Kernel launch failed: test");
+        assert_eq!(message["data"]["detail"]["failureCount"], 1);
+        assert_eq!(feed.subscriber_count(), 1);
+    }
+}
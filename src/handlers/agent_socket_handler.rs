@@ -0,0 +1,223 @@
+use actix::prelude::*;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use log::{debug, error, info, warn};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::actors::messages::{CloseConnection, RegisterAgent, SendToClientText, UnregisterAgent, WatchNodes};
+use crate::app_state::AppState;
+use crate::services::agent_service::AgentBatch;
+use crate::utils::node_watch::WatchRequest;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Websocket session for one authenticated agent. Receives graph events as JSON
+/// text (never binary position frames) and accepts `mutate` batches and `watchNodes`.
+pub struct AgentSocket {
+    agent: String,
+    app_state: Arc<AppState>,
+    agent_id: Option<usize>,
+    heartbeat: Instant,
+}
+
+impl AgentSocket {
+    pub fn new(agent: String, app_state: Arc<AppState>) -> Self {
+        Self {
+            agent,
+            app_state,
+            agent_id: None,
+            heartbeat: Instant::now(),
+        }
+    }
+
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.heartbeat) > CLIENT_TIMEOUT {
+                info!("[AgentSocket] Agent {} heartbeat failed, disconnecting", act.agent);
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn handle_mutate(&self, msg: serde_json::Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let request_id = msg.get("requestId").cloned();
+        let batch = match serde_json::from_value::<AgentBatch>(msg) {
+            Ok(batch) => batch,
+            Err(e) => {
+                ctx.text(json!({
+                    "type": "mutation_rejected",
+                    "requestId": request_id,
+                    "errors": [format!("Invalid batch: {}", e)]
+                }).to_string());
+                return;
+            }
+        };
+
+        let agent_service = self.app_state.agent_service.clone();
+        let agent = self.agent.clone();
+        let addr = ctx.address();
+        let fut = async move {
+            let reply = match agent_service.handle_batch(&agent, batch).await {
+                Ok(result) => json!({ "type": "mutation_result", "data": result }),
+                Err(errors) => json!({ "type": "mutation_rejected", "requestId": request_id, "errors": errors }),
+            };
+            addr.do_send(SendToClientText(reply.to_string()));
+        };
+        ctx.spawn(fut.into_actor(self));
+    }
+
+    // As for graph clients: a fresh watch, or a `session` to pick one back up
+    fn handle_watch_nodes(&self, msg: serde_json::Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(subscriber) = self.agent_id else {
+            ctx.text(json!({ "type": "error", "message": "Not registered yet" }).to_string());
+            return;
+        };
+        let request = match serde_json::from_value::<WatchRequest>(msg) {
+            Ok(request) => request,
+            Err(e) => {
+                ctx.text(json!({ "type": "error", "message": format!("Invalid watchNodes: {}", e) }).to_string());
+                return;
+            }
+        };
+        let watch = WatchNodes { subscriber, session: request.session, ids: request.ids, events: request.events };
+        let fut = self.app_state.client_manager_addr.send(watch);
+        ctx.spawn(fut.into_actor(self).map(|result, _act, ctx| {
+            let reply = match result {
+                Ok(Ok(info)) => info.to_ws_message(),
+                Ok(Err(e)) => e.to_ws_message(),
+                Err(e) => json!({ "type": "error", "message": format!("Client manager unavailable: {}", e) }).to_string(),
+            };
+            ctx.text(reply);
+        }));
+    }
+}
+
+impl Actor for AgentSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("[AgentSocket] Agent {} connected", self.agent);
+        self.start_heartbeat(ctx);
+
+        let register = RegisterAgent {
+            name: self.agent.clone(),
+            text: ctx.address().recipient(),
+            close: ctx.address().recipient(),
+        };
+        self.app_state.client_manager_addr
+            .send(register)
+            .into_actor(self)
+            .then(|result, act, ctx| {
+                match result {
+                    Ok(Ok(agent_id)) => {
+                        act.agent_id = Some(agent_id);
+                        ctx.text(json!({ "type": "connected", "agent": act.agent, "agentId": agent_id }).to_string());
+                    }
+                    _ => {
+                        error!("[AgentSocket] Failed to register agent {}", act.agent);
+                        ctx.stop();
+                    }
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(agent_id) = self.agent_id.take() {
+            self.app_state.client_manager_addr.do_send(UnregisterAgent { agent_id });
+        }
+        info!("[AgentSocket] Agent {} disconnected", self.agent);
+    }
+}
+
+impl Handler<SendToClientText> for AgentSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendToClientText, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+impl Handler<CloseConnection> for AgentSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseConnection, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Away,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for AgentSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.heartbeat = Instant::now();
+            }
+            Ok(ws::Message::Text(text)) => {
+                self.heartbeat = Instant::now();
+                debug!("[AgentSocket] {} sent {} bytes", self.agent, text.len());
+                match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(msg) => match msg.get("type").and_then(|t| t.as_str()) {
+                        Some("mutate") => self.handle_mutate(msg, ctx),
+                        Some("watchNodes") => self.handle_watch_nodes(msg, ctx),
+                        Some("ping") => ctx.text(json!({ "type": "pong" }).to_string()),
+                        _ => ctx.text(json!({ "type": "error", "message": "Unknown message type" }).to_string()),
+                    },
+                    Err(e) => ctx.text(json!({ "type": "error", "message": format!("Invalid JSON: {}", e) }).to_string()),
+                }
+            }
+            Ok(ws::Message::Binary(_)) => {
+                ctx.text(json!({ "type": "error", "message": "Agents must send JSON text messages" }).to_string());
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+// API key from `X-Agent-Key`, `Authorization: Bearer ...` or `?key=`
+fn agent_key(req: &HttpRequest) -> Option<String> {
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    header("X-Agent-Key")
+        .or_else(|| header("Authorization").and_then(|v| v.strip_prefix("Bearer ").map(str::to_string)))
+        .or_else(|| {
+            web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+                .ok()
+                .and_then(|q| q.get("key").cloned())
+        })
+}
+
+pub async fn agent_socket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if !app_state.agent_service.is_enabled() {
+        return Ok(HttpResponse::NotFound().json(json!({ "error": "Agent channel is disabled" })));
+    }
+    let agent = match agent_key(&req).and_then(|key| app_state.agent_service.authenticate(&key)) {
+        Some(agent) => agent,
+        None => {
+            warn!("[AgentSocket] Rejected connection with missing or unknown API key");
+            return Ok(HttpResponse::Unauthorized().json(json!({ "error": "Invalid agent API key" })));
+        }
+    };
+
+    ws::start(AgentSocket::new(agent, app_state.into_inner()), &req, stream)
+}
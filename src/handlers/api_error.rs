@@ -0,0 +1,281 @@
+//! Errors returned by the graph and metadata HTTP handlers. Every one goes out as
+//! `{ code, message, details }` with a status that matches, so clients can tell a graph
+//! that isn't built yet from a bad parameter or a failed build.
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::models::annotation::AnnotationError;
+use crate::models::metadata_schema::MetadataViolation;
+use crate::services::anchor_service::AnchorError;
+use crate::services::external_links::ExternalLinkError;
+use crate::services::room_access::RoomAccessError;
+use crate::services::job_service::{JobsFull, JOBS_FULL_RETRY_SECS};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    GraphNotReady,
+    NotFound(String),
+    // The caller has to sign in first
+    Unauthorized(String),
+    // Signed in with the right role, but not allowed to touch this particular thing
+    Forbidden(String),
+    // Was there once, and won't be again
+    Gone(String),
+    InvalidParameter { name: String, reason: String },
+    // Another long-running operation of the same kind holds the lock
+    Conflict(String),
+    PayloadTooLarge(String),
+    // Metadata values the schema turned down
+    InvalidMetadata(Vec<MetadataViolation>),
+    // The caller's request budget for this route is spent
+    RateLimited { retry_after_secs: u64 },
+    // A job ended without a result; `status` is the one the job chose for its failure
+    JobFailed { job_id: String, status: u16, message: String },
+    // A backend is missing or saturated; worth trying again later, not straight away
+    ServiceUnavailable { message: String, retry_after_secs: Option<u64> },
+    Internal(String),
+}
+
+impl From<JobsFull> for ApiError {
+    fn from(_: JobsFull) -> Self {
+        ApiError::RateLimited { retry_after_secs: JOBS_FULL_RETRY_SECS }
+    }
+}
+
+impl From<AnnotationError> for ApiError {
+    fn from(e: AnnotationError) -> Self {
+        match e {
+            AnnotationError::EmptyText | AnnotationError::TooLong(_) => ApiError::invalid("text", e.to_string()),
+            AnnotationError::InvalidOffset => ApiError::invalid("anchorOffset", e.to_string()),
+            AnnotationError::NodeFull => ApiError::Conflict(e.to_string()),
+            AnnotationError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            AnnotationError::NotAuthor => ApiError::Forbidden(e.to_string()),
+            AnnotationError::Storage(cause) => ApiError::storage("annotations", cause),
+        }
+    }
+}
+
+impl From<AnchorError> for ApiError {
+    fn from(e: AnchorError) -> Self {
+        match e {
+            AnchorError::InvalidLabel(reason) => ApiError::invalid("label", reason),
+            AnchorError::InvalidRoom(reason) => ApiError::invalid("room", reason),
+            AnchorError::InvalidTransform(reason) => ApiError::invalid("transform", reason),
+            AnchorError::WrongRoom { .. } => ApiError::invalid("anchorId", e.to_string()),
+            AnchorError::RoomFull(_) => ApiError::Conflict(e.to_string()),
+            AnchorError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            AnchorError::Storage(cause) => ApiError::storage("anchors", cause),
+        }
+    }
+}
+
+impl From<RoomAccessError> for ApiError {
+    fn from(e: RoomAccessError) -> Self {
+        match e {
+            RoomAccessError::NotMember(_) | RoomAccessError::NotOwner(_) | RoomAccessError::RoomInUse(_) => {
+                ApiError::Forbidden(e.to_string())
+            }
+            RoomAccessError::AnonymousCaller => ApiError::Unauthorized(e.to_string()),
+            RoomAccessError::DefaultRoom => ApiError::invalid("room", e.to_string()),
+            RoomAccessError::InvalidInvite => ApiError::invalid("token", e.to_string()),
+            RoomAccessError::InviteExpired => ApiError::Gone(e.to_string()),
+            RoomAccessError::Storage(cause) => ApiError::storage("room access", cause),
+        }
+    }
+}
+
+impl From<ExternalLinkError> for ApiError {
+    fn from(e: ExternalLinkError) -> Self {
+        match e {
+            ExternalLinkError::Storage(cause) => ApiError::storage("external links", cause),
+            _ => ApiError::invalid("links", e.to_string()),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn invalid(name: &str, reason: impl Into<String>) -> Self {
+        ApiError::InvalidParameter { name: name.to_string(), reason: reason.into() }
+    }
+
+    /// Actor mailbox failures; the service behind it is gone or overloaded
+    pub fn unavailable(service: &str, e: impl fmt::Display) -> Self {
+        ApiError::Internal(format!("{} unavailable: {}", service, e))
+    }
+
+    // The caller only hears that saving failed, so the cause goes to the log
+    fn storage(what: &str, cause: impl fmt::Display) -> Self {
+        error!("Failed to save {}: {}", what, cause);
+        ApiError::Internal(format!("Failed to save {}", what))
+    }
+
+    /// Graph builds report their failures as plain strings
+    pub fn from_build_error(e: impl fmt::Display) -> Self {
+        ApiError::Internal(format!("Failed to build graph: {}", e))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::GraphNotReady => "graph_not_ready",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Gone(_) => "gone",
+            ApiError::InvalidParameter { .. } => "invalid_parameter",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::InvalidMetadata(_) => "invalid_metadata",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::JobFailed { .. } => "job_failed",
+            ApiError::ServiceUnavailable { .. } => "service_unavailable",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            ApiError::InvalidParameter { name, reason } => json!({ "name": name, "reason": reason }),
+            ApiError::RateLimited { retry_after_secs } => json!({ "retryAfter": retry_after_secs }),
+            ApiError::ServiceUnavailable { retry_after_secs: Some(retry_after_secs), .. } => json!({ "retryAfter": retry_after_secs }),
+            ApiError::InvalidMetadata(violations) => json!({ "violations": violations }),
+            ApiError::JobFailed { job_id, .. } => json!({ "jobId": job_id }),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::GraphNotReady => write!(f, "The graph has not been built yet"),
+            ApiError::NotFound(what) => write!(f, "{} not found", what),
+            ApiError::InvalidParameter { name, reason } => write!(f, "Invalid parameter '{}': {}", name, reason),
+            ApiError::RateLimited { retry_after_secs } => {
+                write!(f, "Too many requests, retry in {} seconds", retry_after_secs)
+            }
+            ApiError::InvalidMetadata(violations) => {
+                let keys: Vec<&str> = violations.iter().map(|v| v.key.as_str()).collect();
+                write!(f, "Invalid metadata for {}", keys.join(", "))
+            }
+            ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::Gone(message)
+            | ApiError::Conflict(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::JobFailed { message, .. }
+            | ApiError::ServiceUnavailable { message, .. }
+            | ApiError::Internal(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::GraphNotReady | ApiError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::InvalidParameter { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::InvalidMetadata(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::JobFailed { status, .. } => StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::RateLimited { retry_after_secs } | ApiError::ServiceUnavailable { retry_after_secs: Some(retry_after_secs), .. } = self {
+            response.insert_header(("Retry-After", retry_after_secs.to_string()));
+        }
+        response.json(json!({
+            "code": self.code(),
+            "message": self.to_string(),
+            "details": self.details(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(error: ApiError) -> (StatusCode, Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let bytes = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_status_codes_and_bodies() {
+        let (status, value) = body(ApiError::GraphNotReady).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::SERVICE_UNAVAILABLE, Some("graph_not_ready")));
+        assert!(value["details"].is_null());
+
+        let (status, value) = body(ApiError::NotFound("Node 42".to_string())).await;
+        assert_eq!((status, value["message"].as_str()), (StatusCode::NOT_FOUND, Some("Node 42 not found")));
+
+        let (status, value) = body(ApiError::invalid("page_size", "must be greater than 0")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(value["code"], "invalid_parameter");
+        assert_eq!(value["details"], json!({ "name": "page_size", "reason": "must be greater than 0" }));
+
+        let (status, value) = body(ApiError::from_build_error("disk full")).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::INTERNAL_SERVER_ERROR, Some("internal")));
+        assert_eq!(value["message"], "Failed to build graph: disk full");
+
+        let (status, value) = body(ApiError::from(AnnotationError::NotAuthor)).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::FORBIDDEN, Some("forbidden")));
+        let (status, value) = body(ApiError::from(AnnotationError::Storage("disk full".to_string()))).await;
+        // Where the write failed stays in the log
+        assert_eq!((status, value["message"].as_str()), (StatusCode::INTERNAL_SERVER_ERROR, Some("Failed to save annotations")));
+
+        let (status, value) = body(ApiError::from(RoomAccessError::AnonymousCaller)).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::UNAUTHORIZED, Some("unauthorized")));
+        let (status, _) = body(ApiError::from(RoomAccessError::InviteExpired)).await;
+        assert_eq!(status, StatusCode::GONE);
+
+        let busy = ApiError::ServiceUnavailable { message: "Summary queue is full".to_string(), retry_after_secs: Some(30) };
+        assert_eq!(busy.error_response().headers().get("Retry-After").unwrap(), "30");
+        let (status, value) = body(busy).await;
+        assert_eq!((status, value["details"]["retryAfter"].as_u64()), (StatusCode::SERVICE_UNAVAILABLE, Some(30)));
+
+        let failed = ApiError::JobFailed { job_id: "job-1".to_string(), status: 502, message: "Upstream timed out".to_string() };
+        let (status, value) = body(failed).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::BAD_GATEWAY, Some("job_failed")));
+        assert_eq!(value["details"]["jobId"], "job-1");
+
+        let (status, _) = body(ApiError::Conflict("An import is already running".to_string())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, value) = body(ApiError::PayloadTooLarge("Import is too large".to_string())).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("payload_too_large")));
+
+        let response = ApiError::RateLimited { retry_after_secs: 3 }.error_response();
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "3");
+        let (status, value) = body(ApiError::RateLimited { retry_after_secs: 3 }).await;
+        assert_eq!((status, value["details"]["retryAfter"].as_u64()), (StatusCode::TOO_MANY_REQUESTS, Some(3)));
+
+        let violation = MetadataViolation {
+            key: "fileSize".to_string(),
+            value: "big".to_string(),
+            expected: None,
+            allowed: Vec::new(),
+            reason: "expected a whole number".to_string(),
+        };
+        let (status, value) = body(ApiError::InvalidMetadata(vec![violation])).await;
+        assert_eq!((status, value["code"].as_str()), (StatusCode::UNPROCESSABLE_ENTITY, Some("invalid_metadata")));
+        assert_eq!(value["details"]["violations"][0]["key"], "fileSize");
+        assert_eq!(value["message"], "Invalid metadata for fileSize");
+    }
+}
@@ -0,0 +1,117 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use crate::AppState;
+use crate::config::feature_access::Role;
+use crate::actors::messages::BroadcastToRoom;
+use crate::handlers::api_error::ApiError;
+use crate::models::spatial_anchor::{AnchorFrame, CreateAnchorRequest};
+use serde::Deserialize;
+use log::info;
+
+#[derive(Debug, Deserialize)]
+pub struct AnchorQuery {
+    pub room: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetActiveAnchorRequest {
+    pub anchor_id: String,
+}
+
+// Push the new frame to every client in the room so they re-anchor together
+fn broadcast_frame_changed(state: &AppState, frame: &AnchorFrame) {
+    let message = serde_json::json!({
+        "type": "frame_changed",
+        "frame": frame,
+    });
+    state.client_manager_addr.do_send(BroadcastToRoom {
+        room: frame.room.clone(),
+        message: message.to_string(),
+    });
+}
+
+pub async fn list_anchors(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<AnchorQuery>,
+) -> impl Responder {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    // Anchors in rooms the caller can't join are left out
+    let anchors: Vec<_> = state.anchor_service.list(query.room.as_deref()).await
+        .into_iter()
+        .filter(|anchor| state.room_access.can_access(&anchor.room, &identity))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "anchors": anchors }))
+}
+
+pub async fn create_anchor(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    request: web::Json<CreateAnchorRequest>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let anchor = state.anchor_service.create(request.into_inner()).await?;
+    // The first anchor in a room becomes active, so let clients know
+    let frame = state.anchor_service.frame_for_room(&anchor.room).await;
+    if frame.anchor_id.as_deref() == Some(anchor.id.as_str()) {
+        broadcast_frame_changed(&state, &frame);
+    }
+    Ok(HttpResponse::Created().json(anchor))
+}
+
+pub async fn delete_anchor(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let (anchor, frame) = state.anchor_service.delete(&path.into_inner()).await?;
+    if let Some(frame) = frame {
+        broadcast_frame_changed(&state, &frame);
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "id": anchor.id
+    })))
+}
+
+pub async fn get_active_anchor(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let frame = state.anchor_service.frame_for_room(&path.into_inner()).await;
+    HttpResponse::Ok().json(frame)
+}
+
+pub async fn set_active_anchor(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    request: web::Json<SetActiveAnchorRequest>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let frame = state.anchor_service.set_active(&path.into_inner(), &request.anchor_id).await?;
+    info!("Broadcasting frame_changed for room {}", frame.room);
+    broadcast_frame_changed(&state, &frame);
+    Ok(HttpResponse::Ok().json(frame))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/anchors")
+            .route("", web::get().to(list_anchors))
+            .route("", web::post().to(create_anchor))
+            .route("/active/{room}", web::get().to(get_active_anchor))
+            .route("/active/{room}", web::put().to(set_active_anchor))
+            .route("/{id}", web::delete().to(delete_anchor))
+    );
+}
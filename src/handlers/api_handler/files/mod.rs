@@ -1,13 +1,17 @@
-use actix_web::{web, Error as ActixError, HttpResponse};
+use actix_web::{web, Error as ActixError, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::actors::messages::{GetSettings, UpdateMetadata, BuildGraphFromMetadata, GetNodeData as GetGpuNodeData};
+use crate::actors::messages::{GetSettings, UpdateMetadata, GetNodeData as GetGpuNodeData};
 use serde_json::json;
 use log::{info, debug, error};
 
 use crate::AppState;
+use crate::config::feature_access::Role;
 use crate::services::file_service::{FileService, MARKDOWN_DIR};
 
-pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse {
+pub async fn fetch_and_process_files(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return response;
+    }
     info!("Initiating optimized file fetch and processing");
 
     let mut metadata_store = match FileService::load_or_create_metadata() {
@@ -32,7 +36,7 @@ pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse
         }
     };
     
-    let file_service = FileService::new(settings.clone());
+    let file_service = FileService::new(settings.clone(), state.node_ids.clone());
     
     match file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata_store).await {
         Ok(processed_files) => {
@@ -61,9 +65,9 @@ pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse
                 }));
             }
 
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-                Ok(Ok(())) => {
+            // Queued behind any rebuild already running
+            match state.rebuild_graph(metadata_store.clone()).await {
+                Ok(()) => {
                     info!("Graph data structure updated successfully via GraphServiceActor");
 
                     // If GPU is present, potentially trigger an update or fetch data
@@ -90,19 +94,12 @@ pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse
                         "processed_files": file_names
                     }))
                 },
-                Ok(Err(e)) => {
+                Err(e) => {
                     error!("GraphServiceActor failed to build graph from metadata: {}", e);
                     HttpResponse::InternalServerError().json(json!({
                         "status": "error",
                         "message": format!("Failed to build graph: {}", e)
                     }))
-                },
-                Err(e) => {
-                    error!("Failed to build graph data: {}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "status": "error",
-                        "message": format!("Failed to build graph data: {}", e)
-                    }))
                 }
             }
         },
@@ -130,7 +127,10 @@ pub async fn get_file_content(_state: web::Data<AppState>, file_name: web::Path<
     }
 }
 
-pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
+pub async fn refresh_graph(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return response;
+    }
     info!("Manually triggering graph refresh");
 
     let metadata_store = match FileService::load_or_create_metadata() {
@@ -144,8 +144,8 @@ pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
         }
     };
 
-    match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-        Ok(Ok(())) => {
+    match state.rebuild_graph(metadata_store.clone()).await {
+        Ok(()) => {
             info!("Graph data structure refreshed successfully via GraphServiceActor");
 
             if let Some(gpu_addr) = &state.gpu_compute_addr {
@@ -167,19 +167,12 @@ pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
                 "message": "Graph refreshed successfully"
             }))
         },
-        Ok(Err(e)) => {
+        Err(e) => {
             error!("GraphServiceActor failed to build graph from metadata: {}", e);
             HttpResponse::InternalServerError().json(json!({
                 "status": "error",
                 "message": format!("Failed to build graph: {}", e)
             }))
-        },
-        Err(e) => {
-            error!("Failed to refresh graph data: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to refresh graph data: {}", e)
-            }))
         }
         // This was the Ok(Err(e)) arm that was already applied by the previous attempt.
         // The original diff had a comma here, which was incorrect.
@@ -255,7 +248,10 @@ pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
     }
 }
 
-pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, ActixError> {
+pub async fn update_graph(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ActixError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
     let metadata_store = match FileService::load_or_create_metadata() {
         Ok(store) => store,
         Err(e) => {
@@ -267,8 +263,8 @@ pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, Ac
         }
     };
 
-    match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-        Ok(Ok(())) => {
+    match state.rebuild_graph(metadata_store.clone()).await {
+        Ok(()) => {
             info!("Graph data structure updated successfully via GraphServiceActor in update_graph");
 
             if let Some(gpu_addr) = &state.gpu_compute_addr {
@@ -291,13 +287,6 @@ pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, Ac
             })))
         },
         Err(e) => {
-            error!("Failed to build graph: {}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to build graph: {}", e)
-            })))
-        },
-        Ok(Err(e)) => {
             error!("GraphServiceActor failed to build graph from metadata: {}", e);
             Ok(HttpResponse::InternalServerError().json(json!({
                 "status": "error",
@@ -1,22 +1,61 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use crate::AppState;
+use crate::handlers::api_error::ApiError;
 use serde::{Serialize, Deserialize};
 use log::{info, debug, error, warn};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use crate::models::metadata::Metadata;
+use crate::models::annotation::{Annotation, AnnotationError, CreateAnnotationRequest};
+use crate::services::nostr_service::NostrService;
+use crate::services::audit_log::AuditRecord;
+use crate::config::feature_access::Role;
+use crate::config::{ColorGradient, ColorPalette, ColorStrategy, PhysicsOverrides};
+use crate::models::spatial_anchor::{validate_room, DEFAULT_ROOM};
+use crate::utils::auth::{check_role, session_pubkey, verify_authenticated};
 use crate::models::node::Node; // Changed from socket_flow_messages::Node
+use crate::models::graph::GraphSnapshot;
+use crate::models::graph_filter::GraphFilter;
+use crate::models::saved_view::{SavedView, ViewScope};
+use crate::services::saved_view_service::SavedViewService;
+use crate::models::node_attributes::{AttributeSet, AttributeUpdateStatus};
 use crate::services::file_service::FileService;
+use crate::services::pagination_session_service::{PageSort, PageView};
+use crate::services::room_access::{RoomAccessError, RoomRole};
+use crate::services::external_links::ExternalLink;
+use crate::services::summary_service::{SummaryError, SummaryLookup};
+use crate::services::tagging_service::pending_proposals;
+use crate::services::topic_extraction;
+use crate::services::edge_bundle_service::BundleError;
+use crate::services::job_service::JobError;
+use crate::handlers::job_handler::job_response;
+use crate::utils::co_selection::CO_VIEWED_EDGE_TYPE;
+use crate::utils::coloring::pagerank_until;
+use crate::utils::edge_bundling::BundleParams;
+use crate::utils::aging;
+use crate::utils::layout_metrics;
+use crate::utils::label_placement::LabelPlacement;
+use crate::utils::param_sweep::{self, SweepGraph, SweepGrid};
+use crate::utils::physics_flags::{self, PhysicsFlagsPatch};
+use crate::utils::graph_transaction::TransactionOp;
+use crate::utils::community_view::GraphView;
+use crate::utils::projection::Projection;
+use crate::utils::skeleton::SkeletonStrategy;
+use crate::utils::spatial_index::{Frustum, SpatialQuery};
+use crate::utils::simulation_clock::SimulationModeStatus;
+use crate::models::simulation_params::SimulationMode;
 // GraphService direct import is no longer needed as we use actors
 // use crate::services::graph_service::GraphService;
-use crate::actors::messages::{GetGraphData, GetMetadata, GetSettings, BuildGraphFromMetadata};
+use crate::actors::messages::{GetGraphSnapshot, GetMetadata, GetSettings, GetNodeMap, BroadcastMessage, GetGraphStats, GetGenerations, UndoPositionEdit, RedoPositionEdit, GetColorMapping, SetColorMapping, GetPins, MergeNodes, UpdateNodeAttributes, SetNodePin, UpdateSimulationParams, GetLayout, GetWarmupStatus, GetSkeleton, GetCachedPageRank, CachePageRank, GetCommunityView, GetEdgeTypePhysics, GetSimulationSettings, SetSimulationSettings, GetClientIdentity, ApplyGraphTransaction, UndoGraphTransaction, QuerySpatial};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct GraphResponse {
-    pub nodes: Vec<Node>,
-    pub edges: Vec<crate::models::edge::Edge>,
-    pub metadata: HashMap<String, Metadata>,
+pub struct GraphResponse<'a> {
+    pub nodes: &'a [Node],
+    pub edges: &'a [crate::models::edge::Edge],
+    pub metadata: &'a HashMap<String, Metadata>,
+    pub generation: u64,
 }
 
 #[derive(Serialize)]
@@ -29,6 +68,16 @@ pub struct PaginatedGraphResponse {
     pub current_page: usize,
     pub total_items: usize,
     pub page_size: usize,
+    // Pages fetched at different generations don't belong together
+    pub generation: u64,
+    // Layout generation of the snapshot the page was cut from, so a client can tell how stale it is
+    pub position_generation: u64,
+    // Pass back as `session` to page through the same snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    // Keyed by numeric node id, only present when include_annotations=true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<u32, Vec<Annotation>>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,42 +87,105 @@ pub struct GraphQuery {
     pub page_size: Option<usize>,
     pub sort: Option<String>,
     pub filter: Option<String>,
+    pub include_annotations: Option<bool>,
+    // Aged-out nodes and their edges are left out unless asked for
+    pub include_archived: Option<bool>,
+    // Comma-separated edge types to leave out, e.g. "similarity,spoken"
+    pub hide_edge_types: Option<String>,
+    // "new" to pin a snapshot for the pages that follow, or the id a previous page returned
+    pub session: Option<String>,
+    // "community" pages through community super-nodes and the edges between them
+    pub aggregation: Option<String>,
 }
 
-pub async fn get_graph_data(state: web::Data<AppState>) -> impl Responder {
-    info!("Received request for graph data");
-    let graph_data_result = state.graph_service_addr.send(GetGraphData).await;
-
-    match graph_data_result {
-        Ok(Ok(graph_data_owned)) => { // graph_data_owned is now GraphData
-            debug!("Preparing graph response with {} nodes and {} edges",
-                graph_data_owned.nodes.len(),
-                graph_data_owned.edges.len()
-            );
- 
-            // Clone data from the owned GraphData for the response
-            let response = GraphResponse {
-                nodes: graph_data_owned.nodes.clone(),
-                edges: graph_data_owned.edges.clone(),
-                metadata: graph_data_owned.metadata.clone(),
-            };
-            HttpResponse::Ok().json(response)
-        }
+// The `session` value that opens a pagination session
+const NEW_PAGINATION_SESSION: &str = "new";
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedQuery {
+    pub include_archived: Option<bool>,
+}
+
+// An empty graph that hasn't finished its first build isn't an empty result
+async fn check_built(state: &AppState, graph: &crate::models::graph::GraphData) -> Result<(), ApiError> {
+    if !graph.nodes.is_empty() {
+        return Ok(());
+    }
+    match state.graph_service_addr.send(GetWarmupStatus).await {
+        Ok(Ok(status)) if !status.ready => Err(ApiError::GraphNotReady),
+        _ => Ok(()),
+    }
+}
+
+async fn fetch_graph_data(state: &AppState) -> Result<GraphSnapshot, ApiError> {
+    match state.graph_service_addr.send(GetGraphSnapshot).await {
+        Ok(Ok(graph_data)) => Ok(graph_data),
         Ok(Err(e)) => {
             error!("Failed to get graph data from actor: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}))
+            Err(ApiError::Internal(format!("Failed to retrieve graph data: {}", e)))
         }
         Err(e) => {
             error!("Mailbox error getting graph data: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}))
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+pub async fn get_graph_data(req: HttpRequest, state: web::Data<AppState>, query: web::Query<ArchivedQuery>) -> Result<HttpResponse, ApiError> {
+    info!("Received request for graph data");
+    // Read before the data, so a change in between only makes the tag stale, never ahead
+    let etag = match state.graph_service_addr.send(GetGenerations).await {
+        Ok(Ok(generations)) => Some(generations.etag()),
+        _ => None,
+    };
+    if let Some(etag) = &etag {
+        let matches = req.headers().get("If-None-Match")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v == etag);
+        if matches {
+            return Ok(HttpResponse::NotModified().insert_header(("ETag", etag.clone())).finish());
         }
     }
+    let mut graph = fetch_graph_data(&state).await?;
+    check_built(&state, &graph).await?;
+    if !query.include_archived.unwrap_or(false) {
+        graph = aging::snapshot_without_archived(graph);
+    }
+    debug!("Preparing graph response with {} nodes and {} edges",
+        graph.nodes.len(),
+        graph.edges.len()
+    );
+
+    let response = GraphResponse {
+        nodes: &graph.nodes,
+        edges: &graph.edges,
+        metadata: &graph.metadata,
+        generation: graph.generation,
+    };
+    let mut builder = HttpResponse::Ok();
+    if let Some(etag) = etag {
+        builder.insert_header(("ETag", etag));
+    }
+    Ok(builder.json(response))
+}
+
+/// Slice bounds and page count for a 0-based page of a non-empty list
+pub fn page_bounds(page: usize, page_size: usize, total_items: usize) -> Result<(usize, usize, usize), ApiError> {
+    if page_size == 0 {
+        return Err(ApiError::invalid("page_size", "must be greater than 0"));
+    }
+    let total_pages = total_items.div_ceil(page_size);
+    if page >= total_pages {
+        return Err(ApiError::invalid("page", format!("page {} exceeds total available pages {}", page + 1, total_pages)));
+    }
+    let start = page * page_size;
+    Ok((start, std::cmp::min(start + page_size, total_items), total_pages))
 }
 
 pub async fn get_paginated_graph_data(
     state: web::Data<AppState>,
     query: web::Query<GraphQuery>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     info!("Received request for paginated graph data with params: {:?}", query);
 
     let page = query.page.map(|p| p.saturating_sub(1)).unwrap_or(0);
@@ -81,226 +193,2278 @@ pub async fn get_paginated_graph_data(
 
     if page_size == 0 {
         error!("Invalid page size: {}", page_size);
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Page size must be greater than 0"
-        }));
+        return Err(ApiError::invalid("page_size", "must be greater than 0"));
     }
+    let sort = PageSort::parse(query.sort.as_deref()).map_err(|e| ApiError::invalid("sort", e))?;
+    let aggregate = match query.aggregation.as_deref() {
+        None | Some("none") => false,
+        Some("community") => true,
+        Some(other) => return Err(ApiError::invalid("aggregation", format!("'{}' isn't supported; use none or community", other))),
+    };
 
-    // This part is complex due to mutable access.
-    // For now, let's assume get_graph_data_mut was for reading and we use GetGraphData.
-    // If mutable access is truly needed, specific messages for modifications are required.
-    let graph_result = state.graph_service_addr.send(GetGraphData).await;
-    let graph_data_owned = match graph_result { // graph_data_owned is GraphData
-        Ok(Ok(g_owned)) => g_owned,
-        _ => {
-            error!("Failed to get graph data for pagination");
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}));
+    // A session serves every page from the snapshot and ordering its first page saw; its
+    // sort, include_archived and aggregation are the ones it was created with
+    let (session, view) = match query.session.as_deref() {
+        Some(id) if id != NEW_PAGINATION_SESSION => {
+            let view = state.pagination_sessions.get(id, Instant::now())
+                .ok_or_else(|| ApiError::NotFound(format!("Pagination session {}", id)))?;
+            (Some(id.to_string()), view)
+        }
+        requested => {
+            // Read before the snapshot, so the advertised generation is never ahead of it
+            let position_generation = match state.graph_service_addr.send(GetGenerations).await {
+                Ok(Ok(generations)) => generations.position_generation,
+                Ok(Err(e)) => return Err(ApiError::Internal(format!("Failed to read graph generations: {}", e))),
+                Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+            };
+            let mut graph = fetch_graph_data(&state).await?;
+            check_built(&state, &graph).await?;
+            if aggregate {
+                graph = Arc::new(community_graph(&state, &graph).await?);
+            }
+            let view = PageView::new(graph, position_generation, sort, query.include_archived.unwrap_or(false));
+            let session = requested.map(|_| state.pagination_sessions.create(view.clone(), Instant::now()));
+            (session, view)
         }
     };
-    let total_items = graph_data_owned.nodes.len();
-    
+    let graph = view.snapshot.clone();
+    let total_items = view.len();
+
     if total_items == 0 {
         debug!("Graph is empty");
-        return HttpResponse::Ok().json(PaginatedGraphResponse {
+        return Ok(HttpResponse::Ok().json(PaginatedGraphResponse {
             nodes: Vec::new(),
             edges: Vec::new(),
             metadata: HashMap::new(),
-            total_pages: 0,
+            // One page, with nothing on it
+            total_pages: 1,
             current_page: 1,
             total_items: 0,
             page_size,
-        });
-    }
-
-    let total_pages = (total_items + page_size - 1) / page_size;
-
-    if page >= total_pages {
-        warn!("Requested page {} exceeds total pages {}", page + 1, total_pages);
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": format!("Page {} exceeds total available pages {}", page + 1, total_pages)
+            generation: graph.generation,
+            position_generation: view.position_generation,
+            session,
+            annotations: None,
         }));
     }
 
-    let start = page * page_size;
-    let end = std::cmp::min(start + page_size, total_items);
+    let (start, end, total_pages) = match page_bounds(page, page_size, total_items) {
+        Ok(bounds) => bounds,
+        Err(e) => {
+            warn!("Requested page {} exceeds total pages", page + 1);
+            return Err(e);
+        }
+    };
 
     debug!("Calculating slice from {} to {} out of {} total items", start, end, total_items);
- 
-    let page_nodes = graph_data_owned.nodes[start..end].to_vec();
- 
-    let node_ids: std::collections::HashSet<_> = page_nodes.iter()
-        .map(|node| node.id)
-        .collect();
- 
-    let relevant_edges: Vec<_> = graph_data_owned.edges.iter()
-        .filter(|edge| {
-            node_ids.contains(&edge.source) || node_ids.contains(&edge.target)
-        })
-        .cloned()
-        .collect();
- 
+
+    let page_nodes = view.nodes(start, end);
+
+    let hidden_types: std::collections::HashSet<&str> = query.hide_edge_types.as_deref()
+        .map(|types| types.split(',').map(str::trim).filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default();
+    let relevant_edges = view.edges_for(&page_nodes, &hidden_types);
+
     debug!("Found {} relevant edges for {} nodes", relevant_edges.len(), page_nodes.len());
- 
+
+    let annotations = if query.include_annotations.unwrap_or(false) {
+        let mut by_metadata = state.annotation_service
+            .list_many(page_nodes.iter().map(|n| n.metadata_id.as_str()))
+            .await;
+        Some(page_nodes.iter()
+            .filter_map(|n| by_metadata.remove(&n.metadata_id).map(|notes| (n.id, notes)))
+            .collect())
+    } else {
+        None
+    };
+
     let response = PaginatedGraphResponse {
         nodes: page_nodes,
         edges: relevant_edges,
-        metadata: graph_data_owned.metadata.clone(),
+        metadata: (*graph.metadata).clone(),
         total_pages,
         current_page: page + 1,
         total_items,
         page_size,
+        generation: graph.generation,
+        position_generation: view.position_generation,
+        session,
+        annotations,
     };
 
-    HttpResponse::Ok().json(response)
+    Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn refresh_graph(state: web::Data<AppState>) -> impl Responder {
+/// The snapshot collapsed into community super-nodes at their members' centroids
+async fn community_graph(state: &AppState, graph: &crate::models::graph::GraphData) -> Result<crate::models::graph::GraphData, ApiError> {
+    let view = state.graph_service_addr.send(GetCommunityView).await
+        .map_err(|e| ApiError::unavailable("Graph service", e))?;
+    // The cached view may be a generation off the snapshot; then work it out for this one
+    if view.generation == graph.generation {
+        Ok(view.to_graph(graph))
+    } else {
+        Ok(GraphView::aggregate(graph).to_graph(graph))
+    }
+}
+
+/// DELETE /api/graph/data/paginated/{session} - drop a pagination session before its TTL runs out
+pub async fn delete_pagination_session(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    if state.pagination_sessions.delete(&id) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::NotFound(format!("Pagination session {}", id)))
+    }
+}
+
+pub async fn refresh_graph(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
     info!("Received request to refresh graph");
-    
-    let metadata_result = state.metadata_addr.send(GetMetadata).await;
-    
-    match metadata_result {
-        Ok(Ok(metadata_store)) => {
-            debug!("Building graph from {} metadata entries", metadata_store.len());
-            
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store }).await {
-                Ok(Ok(())) => {
-                    // Optionally, if we need to preserve old positions, that logic would need to be
-                    // part of the GraphServiceActor's BuildGraphFromMetadata handler or a subsequent message.
-                    // For simplicity here, we assume the actor handles the build correctly.
-                    info!("Graph refreshed successfully via GraphServiceActor");
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "success": true,
-                        "message": "Graph refreshed successfully"
-                    }))
-                }
-                Ok(Err(e)) => {
-                    error!("GraphServiceActor failed to build graph from metadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build graph: {}", e)
-                    }))
-                }
-                Err(e) => {
-                    error!("Mailbox error sending BuildGraphFromMetadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": "Graph service unavailable during refresh"
-                    }))
-                }
-            }
-        }
+
+    let metadata_store = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata_store)) => metadata_store,
         Ok(Err(e)) => {
             error!("Failed to get metadata from MetadataActor: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Failed to retrieve metadata for graph refresh"
-            }))
+            return Err(ApiError::Internal(format!("Failed to retrieve metadata for graph refresh: {}", e)));
         }
         Err(e) => {
             error!("Mailbox error getting metadata: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Metadata service unavailable"
-            }))
+            return Err(ApiError::unavailable("Metadata service", e));
+        }
+    };
+    debug!("Building graph from {} metadata entries", metadata_store.len());
+    let (metadata_store, build_report) = state.prepare_metadata_for_build(metadata_store).await;
+
+    // A refresh asked for during a rebuild runs straight after it
+    match state.rebuild_graph(metadata_store).await {
+        Ok(()) => {
+            info!("Graph refreshed successfully via GraphServiceActor");
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Graph refreshed successfully",
+                "buildReport": build_report
+            })))
+        }
+        Err(e) => {
+            error!("GraphServiceActor failed to build graph from metadata: {}", e);
+            Err(ApiError::from_build_error(e))
         }
-// Removed duplicate success block from here
     }
 }
 
-pub async fn update_graph(state: web::Data<AppState>) -> impl Responder {
+pub async fn update_graph(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
     info!("Received request to update graph");
     
-    let mut metadata = match FileService::load_or_create_metadata() {
-        Ok(m) => m,
-        Err(e) => {
-            error!("Failed to load metadata: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": format!("Failed to load metadata: {}", e)
-            }));
-        }
-    };
+    let mut metadata = FileService::load_or_create_metadata().map_err(|e| {
+        error!("Failed to load metadata: {}", e);
+        ApiError::Internal(format!("Failed to load metadata: {}", e))
+    })?;
     
-    let settings_result = state.settings_addr.send(GetSettings).await;
-    let settings = match settings_result {
+    let settings = match state.settings_addr.send(GetSettings).await {
         Ok(Ok(s)) => Arc::new(tokio::sync::RwLock::new(s)),
         _ => {
             error!("Failed to retrieve settings for FileService in update_graph");
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Failed to retrieve application settings"
-            }));
+            return Err(ApiError::Internal("Failed to retrieve application settings".to_string()));
         }
     };
     
-    let file_service = FileService::new(settings.clone());
-    match file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata).await {
-        Ok(processed_files) => {
-            if processed_files.is_empty() {
-                debug!("No new files to process");
-                return HttpResponse::Ok().json(serde_json::json!({
-                    "success": true,
-                    "message": "No updates needed"
-                }));
-            }
-            
-            debug!("Processing {} new files", processed_files.len());
-            
-            {
-                // Send UpdateMetadata message to MetadataActor
-                if let Err(e) = state.metadata_addr.send(crate::actors::messages::UpdateMetadata { metadata: metadata.clone() }).await {
-                     error!("Failed to send UpdateMetadata to MetadataActor: {}", e);
-                     // Potentially return error if this is critical
-                }
+    let file_service = FileService::new(settings.clone(), state.node_ids.clone());
+    let processed_files = file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata).await
+        .map_err(|e| {
+            error!("Failed to fetch and process files: {}", e);
+            ApiError::Internal(format!("Failed to fetch and process files: {}", e))
+        })?;
+    if processed_files.is_empty() {
+        debug!("No new files to process");
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "No updates needed"
+        })));
+    }
+    
+    debug!("Processing {} new files", processed_files.len());
+    
+    {
+        // Send UpdateMetadata message to MetadataActor
+        if let Err(e) = state.metadata_addr.send(crate::actors::messages::UpdateMetadata { metadata: metadata.clone() }).await {
+             error!("Failed to send UpdateMetadata to MetadataActor: {}", e);
+             // Potentially return error if this is critical
+        }
+    }
+    
+    // Extraction fills a copy; the store the MetadataActor holds keeps the upstream counts
+    let (metadata, build_report) = state.prepare_metadata_for_build(metadata).await;
+
+    // Queued behind any rebuild already running
+    match state.rebuild_graph(metadata).await {
+        Ok(()) => {
+            // Position preservation logic would need to be handled by the actor or subsequent messages.
+            debug!("Graph updated successfully via GraphServiceActor after file processing");
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("Graph updated with {} new files", processed_files.len()),
+                "buildReport": build_report
+            })))
+        },
+        Err(e) => {
+            error!("GraphServiceActor failed to build graph from metadata: {}", e);
+            Err(ApiError::from_build_error(e))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VerifyRequest {
+    // Apply the auto-fixes as well as reporting
+    #[serde(default)]
+    pub fix: bool,
+}
+
+/// POST /api/graph/verify - cross-check metadata, the graph and persisted layout state
+pub async fn verify_graph(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: Option<web::Json<VerifyRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let fix = body.is_some_and(|b| b.fix);
+    info!("Integrity check requested (fix: {})", fix);
+    let report = state.verify_integrity(fix).await.map_err(|e| {
+        error!("Integrity check failed: {}", e);
+        ApiError::Internal(e)
+    })?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// GET /api/graph/build-report - what the last rebuild did before building edges, such
+/// as which files had topic_counts extracted from their markdown
+pub async fn get_build_report() -> Result<HttpResponse, ApiError> {
+    topic_extraction::last_report()
+        .map(|report| HttpResponse::Ok().json(report))
+        .ok_or_else(|| ApiError::NotFound("Build report".to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    // How many entries of the LOD ranking to return
+    pub lod_limit: Option<usize>,
+}
+
+/// GET /api/graph/stats - counts, live attention scores and the LOD importance ranking
+pub async fn get_graph_stats(
+    state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let lod_limit = query.lod_limit.unwrap_or(100).min(10_000);
+    match state.graph_service_addr.send(GetGraphStats { lod_limit }).await {
+        Ok(Ok(stats)) => Ok(HttpResponse::Ok().json(stats)),
+        Ok(Err(e)) => {
+            error!("Failed to compute graph stats: {}", e);
+            Err(ApiError::Internal(e))
+        }
+        Err(e) => {
+            error!("Graph service mailbox error: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphQueryRequest {
+    pub query: String,
+    // Index of a candidate interpretation from a previous ambiguous response
+    pub candidate: Option<usize>,
+}
+
+/// POST /api/graph/query - natural-language query returning a highlighted subgraph
+pub async fn query_graph(
+    state: web::Data<AppState>,
+    request: web::Json<GraphQueryRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request = request.into_inner();
+    match state.run_graph_query(&request.query, request.candidate).await {
+        Ok(result) => {
+            debug!("Graph query '{}' matched {} nodes", request.query, result.matched_ids.len());
+            Ok(HttpResponse::Ok().json(result))
+        }
+        Err(e) if e.contains("unavailable") => {
+            error!("Graph query failed: {}", e);
+            Err(ApiError::Internal(e))
+        }
+        Err(e) => Err(ApiError::invalid("query", e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarityUpdate {
+    pub enabled: Option<bool>,
+    pub threshold: Option<f32>,
+    pub k: Option<usize>,
+}
+
+/// GET /api/graph/similarity - current similarity edge parameters
+pub async fn get_similarity_params(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(state.embedding_service.params())
+}
+
+/// PUT /api/graph/similarity - toggle similarity edges or change threshold / k at runtime
+pub async fn update_similarity_params(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<SimilarityUpdate>,
+) -> Result<HttpResponse, ApiError> {
+    // Server-wide, so admins only
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let mut params = state.embedding_service.params();
+    if let Some(enabled) = update.enabled {
+        params.enabled = enabled;
+    }
+    if let Some(threshold) = update.threshold {
+        if !(-1.0..=1.0).contains(&threshold) {
+            return Err(ApiError::invalid("threshold", "must be between -1 and 1"));
+        }
+        params.threshold = threshold;
+    }
+    if let Some(k) = update.k {
+        if k == 0 || k > 50 {
+            return Err(ApiError::invalid("k", "must be between 1 and 50"));
+        }
+        params.k = k;
+    }
+    state.embedding_service.set_params(params);
+
+    let store = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store,
+        _ => {
+            error!("Failed to get metadata for similarity update");
+            return Err(ApiError::Internal("Failed to retrieve metadata".to_string()));
+        }
+    };
+    match state.embedding_service.publish(&store, &state.graph_service_addr).await {
+        Ok(edge_count) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "params": params,
+            "edgeCount": edge_count
+        }))),
+        Err(e) => {
+            error!("Failed to apply similarity edges: {}", e);
+            Err(ApiError::Internal(e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorMappingUpdate {
+    pub strategy: Option<ColorStrategy>,
+    pub metadata_key: Option<String>,
+    pub palette: Option<ColorPalette>,
+    pub gradient: Option<ColorGradient>,
+}
+
+/// GET /api/graph/coloring - the active node colour mapping
+pub async fn get_color_mapping(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(GetColorMapping).await {
+        Ok(Ok(mapping)) => Ok(HttpResponse::Ok().json(mapping)),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => {
+            error!("Mailbox error getting colour mapping: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeBundleQuery {
+    pub iterations: Option<usize>,
+    pub compatibility_threshold: Option<f32>,
+}
+
+/// GET /api/graph/edges/bundles - force-directed edge bundles for the current layout, as
+/// one polyline per edge. Cached until the layout moves or the topology changes. Runs as
+/// a job: past the inline wait this answers 202 with the job to poll.
+pub async fn get_edge_bundles(req: HttpRequest, state: web::Data<AppState>, query: web::Query<EdgeBundleQuery>) -> Result<HttpResponse, ApiError> {
+    let defaults = BundleParams::default();
+    let params = BundleParams {
+        iterations: query.iterations.unwrap_or(defaults.iterations),
+        compatibility_threshold: query.compatibility_threshold.unwrap_or(defaults.compatibility_threshold),
+    };
+    params.validate().map_err(|e| ApiError::invalid("query", e))?;
+    let submitter = session_pubkey(&req, state.nostr_service.as_ref().map(|n| n.get_ref())).await;
+    let service = state.edge_bundle_service.clone();
+    let graph_addr = state.graph_service_addr.clone();
+    let submitted = state.jobs.submit_async("edge_bundles", submitter, move |cancel| async move {
+        match service.compute_edge_bundles(params, &graph_addr, &cancel).await {
+            Ok(result) => Ok(serde_json::json!({
+                "computedAt": result.computed_at,
+                "cached": result.cached,
+                "bundles": result.bundles.as_slice(),
+            })),
+            Err(e @ BundleError::InvalidParams(_)) => Err(JobError::new(400, e.to_string())),
+            Err(e @ BundleError::TooManyEdges { .. }) => Err(JobError::new(422, e.to_string())),
+            Err(e @ BundleError::Cancelled) => Err(JobError::new(409, e.to_string())),
+            Err(BundleError::Failed(e)) => {
+                error!("Edge bundling failed: {}", e);
+                Err(JobError::new(500, e))
             }
-            
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata }).await {
-                Ok(Ok(())) => {
-                    // Position preservation logic would need to be handled by the actor or subsequent messages.
-                    debug!("Graph updated successfully via GraphServiceActor after file processing");
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "success": true,
-                        "message": format!("Graph updated with {} new files", processed_files.len())
-                    }))
-                },
-                Ok(Err(e)) => {
-                    error!("GraphServiceActor failed to build graph from metadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build graph: {}", e)
-                    }))
-                },
-                Err(e) => {
-                    error!("Failed to build new graph: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build new graph: {}", e)
-                    }))
-                }
+        }
+    });
+    let id = submitted?;
+    job_response(&id, state.jobs.wait_inline(&id).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoViewedQuery {
+    // Only edges touching this node
+    pub node: Option<u32>,
+    pub min_weight: Option<f32>,
+}
+
+/// GET /api/graph/edges/co-viewed - the behavioural edge layer on its own, derived from
+/// what clients select together. Empty unless co-viewed edges are enabled.
+pub async fn get_co_viewed_edges(state: web::Data<AppState>, query: web::Query<CoViewedQuery>) -> Result<HttpResponse, ApiError> {
+    if query.min_weight.is_some_and(|w| !w.is_finite()) {
+        return Err(ApiError::invalid("min_weight", "must be a finite number"));
+    }
+    let graph = fetch_graph_data(&state).await?;
+    let min_weight = query.min_weight.unwrap_or(0.0);
+    let edges: Vec<&crate::models::edge::Edge> = graph.edges.iter()
+        .filter(|e| e.edge_type.as_deref() == Some(CO_VIEWED_EDGE_TYPE))
+        .filter(|e| query.node.is_none_or(|n| e.source == n || e.target == n))
+        .filter(|e| e.weight >= min_weight)
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "edgeType": CO_VIEWED_EDGE_TYPE,
+        "generation": graph.generation,
+        "edges": edges,
+    })))
+}
+
+/// GET /api/graph/edges/{edge_id} - one edge with the trail of changes that explains its weight
+pub async fn get_edge(state: web::Data<AppState>, edge_id: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let graph = fetch_graph_data(&state).await?;
+    let edge = graph.edges.iter()
+        .find(|e| e.id == *edge_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Edge {}", edge_id)))?;
+    let label = |id: u32| graph.nodes.iter().find(|n| n.id == id).map(|n| n.label.clone());
+    // Differs from the weight only when a change left no entry, as a journal replay does
+    let explained: f32 = edge.provenance.iter().map(|c| c.contribution).sum();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "edge": edge,
+        "sourceLabel": label(edge.source),
+        "targetLabel": label(edge.target),
+        "explainedWeight": explained,
+        "generation": graph.generation,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PagerankQuery {
+    // How many of the top-ranked nodes to return
+    pub limit: Option<usize>,
+}
+
+/// GET /api/graph/pagerank - nodes by PageRank, highest first. Answered from the graph
+/// service's cache when it's current, otherwise run as a job like edge bundling is and
+/// cached for the next request.
+pub async fn get_pagerank(req: HttpRequest, state: web::Data<AppState>, query: web::Query<PagerankQuery>) -> Result<HttpResponse, ApiError> {
+    let graph = fetch_graph_data(&state).await?;
+    check_built(&state, &graph).await?;
+    let limit = query.limit.unwrap_or(100).min(10_000);
+    let cached = state.graph_service_addr.send(GetCachedPageRank).await
+        .map_err(|e| ApiError::unavailable("Graph service", e))?
+        .filter(|(generation, _)| *generation == graph.generation);
+    let submitter = session_pubkey(&req, state.nostr_service.as_ref().map(|n| n.get_ref())).await;
+    let graph_service = state.graph_service_addr.clone();
+    let id = state.jobs.submit("pagerank", submitter, move |cancel| {
+        let ranks = match cached {
+            Some((_, ranks)) => ranks,
+            None => {
+                let ranks = Arc::new(pagerank_until(&graph.nodes, &graph.edges, &|| cancel.is_cancelled())
+                    .ok_or_else(|| JobError::new(409, "PageRank was cancelled"))?);
+                graph_service.do_send(CachePageRank { generation: graph.generation, ranks: ranks.clone() });
+                ranks
             }
-        },
+        };
+        let mut ranked: Vec<(&Node, f32)> = graph.nodes.iter().map(|n| (n, ranks[&n.id])).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+        let top: Vec<serde_json::Value> = ranked.into_iter()
+            .take(limit)
+            .map(|(node, score)| serde_json::json!({ "nodeId": node.id, "metadataId": node.metadata_id, "score": score }))
+            .collect();
+        Ok(serde_json::json!({ "generation": graph.generation, "nodeCount": graph.nodes.len(), "ranks": top }))
+    })?;
+    job_response(&id, state.jobs.wait_inline(&id).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SkeletonQuery {
+    // "mst" (the default) or "topk"
+    pub strategy: Option<String>,
+    // Edges kept per node by "topk"
+    pub k: Option<usize>,
+}
+
+/// GET /api/graph/skeleton - ids of the edges in a simplified backbone of the graph,
+/// either its maximum-weight spanning tree or each node's k heaviest edges. Nodes are
+/// unchanged. Cached until the topology changes.
+pub async fn get_skeleton(state: web::Data<AppState>, query: web::Query<SkeletonQuery>) -> Result<HttpResponse, ApiError> {
+    let strategy = SkeletonStrategy::parse(query.strategy.as_deref().unwrap_or("mst"), query.k)
+        .map_err(|e| ApiError::invalid("strategy", e))?;
+    match state.graph_service_addr.send(GetSkeleton { strategy }).await {
+        Ok(Ok((generation, edge_ids))) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "skeleton": strategy,
+            "generation": generation,
+            "edgeIds": edge_ids.as_slice(),
+        }))),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SkeletonPhysicsUpdate {
+    pub enabled: bool,
+    pub strategy: Option<String>,
+    pub k: Option<usize>,
+}
+
+/// PUT /api/graph/skeleton/physics - spring only the skeleton's edges, which calms
+/// hairball layouts, or every edge again. Clients are still sent every edge.
+pub async fn update_skeleton_physics(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<SkeletonPhysicsUpdate>,
+) -> Result<HttpResponse, ApiError> {
+    // Physics is shared by every viewer, so admins only
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let strategy = match update.enabled {
+        true => Some(SkeletonStrategy::parse(update.strategy.as_deref().unwrap_or("mst"), update.k)
+            .map_err(|e| ApiError::invalid("strategy", e))?),
+        false => None,
+    };
+    state.set_skeleton_springs(strategy).await.map_err(ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "enabled": update.enabled, "skeleton": strategy })))
+}
+
+/// GET /api/graph/edges/physics - edge types left out of physics. Their edges, and any
+/// edge flagged `affectsPhysics: false`, are drawn but don't pull on the layout.
+pub async fn get_edge_type_physics(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(GetEdgeTypePhysics).await {
+        Ok(disabled) => Ok(HttpResponse::Ok().json(serde_json::json!({ "physicsDisabled": disabled }))),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EdgeTypePhysicsUpdate {
+    // Edge type -> whether its edges affect physics
+    pub types: HashMap<String, bool>,
+}
+
+/// PUT /api/graph/edges/physics - switches edge types into or out of physics. The layout
+/// is disturbed where edges start or stop pulling. Clients are still sent every edge.
+pub async fn update_edge_type_physics(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<EdgeTypePhysicsUpdate>,
+) -> Result<HttpResponse, ApiError> {
+    // Physics is shared by every viewer, so admins only
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let types = update.into_inner().types;
+    if let Some(edge_type) = types.keys().find(|t| t.trim().is_empty()) {
+        return Err(ApiError::invalid("types", format!("invalid edge type '{}'", edge_type)));
+    }
+    let disabled = state.set_edge_type_physics(types).await.map_err(ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "physicsDisabled": disabled })))
+}
+
+/// GET /api/graph/simulation - where the layout is computed: remote, local or hybrid
+pub async fn get_simulation_mode(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(GetSimulationSettings).await {
+        Ok(Ok(settings)) => Ok(HttpResponse::Ok().json(SimulationModeStatus::from(&settings))),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationModeUpdate {
+    pub mode: Option<SimulationMode>,
+    pub hybrid_step_hz: Option<f32>,
+    pub correction_interval_secs: Option<f32>,
+}
+
+/// PUT /api/graph/simulation - switch the simulation mode at runtime. Every client is
+/// sent a `simulation_mode` event; entering or leaving local mode also sends a keyframe.
+pub async fn update_simulation_mode(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<SimulationModeUpdate>,
+) -> Result<HttpResponse, ApiError> {
+    // Changes what every viewer runs, so admins only
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let mut settings = match state.graph_service_addr.send(GetSimulationSettings).await {
+        Ok(Ok(settings)) => settings,
+        Ok(Err(e)) => return Err(ApiError::Internal(e)),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    let update = update.into_inner();
+    if let Some(mode) = update.mode {
+        settings.mode = mode;
+    }
+    if let Some(hybrid_step_hz) = update.hybrid_step_hz {
+        settings.hybrid_step_hz = hybrid_step_hz;
+    }
+    if let Some(correction_interval_secs) = update.correction_interval_secs {
+        settings.correction_interval_secs = correction_interval_secs;
+    }
+    match state.graph_service_addr.send(SetSimulationSettings { settings }).await {
+        Ok(Ok(status)) => Ok(HttpResponse::Ok().json(status)),
+        Ok(Err(e)) => Err(ApiError::invalid("mode", e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+/// PUT /api/graph/coloring - switch the colour strategy at runtime; every client is recoloured
+pub async fn update_color_mapping(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<ColorMappingUpdate>,
+) -> Result<HttpResponse, ApiError> {
+    // Colours are shared by every viewer, so admins only
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let mut mapping = match state.graph_service_addr.send(GetColorMapping).await {
+        Ok(Ok(mapping)) => mapping,
+        Ok(Err(e)) => return Err(ApiError::Internal(e)),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    let update = update.into_inner();
+    if let Some(strategy) = update.strategy {
+        mapping.strategy = strategy;
+    }
+    if let Some(metadata_key) = update.metadata_key {
+        mapping.metadata_key = metadata_key.trim().to_string();
+    }
+    if let Some(palette) = update.palette {
+        mapping.palette = palette;
+    }
+    if let Some(gradient) = update.gradient {
+        mapping.gradient = gradient;
+    }
+    if mapping.strategy == ColorStrategy::ByMetadataKey && mapping.metadata_key.is_empty() {
+        return Err(ApiError::invalid("metadataKey", "by_metadata_key needs a metadataKey"));
+    }
+
+    match state.graph_service_addr.send(SetColorMapping { mapping: mapping.clone() }).await {
+        Ok(Ok(recolored)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "mapping": mapping,
+            "recolored": recolored
+        }))),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
         Err(e) => {
-            error!("Failed to fetch and process files: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": format!("Failed to fetch and process files: {}", e)
-            }))
+            error!("Mailbox error setting colour mapping: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
         }
     }
 }
 
-// Configure routes using snake_case
-pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/graph")
-            // Match client's endpoint pattern exactly
-            .route("/data", web::get().to(get_graph_data))
-            .route("/data/paginated", web::get().to(get_paginated_graph_data))
-            .route("/update", web::post().to(update_graph))
-            // Keep refresh endpoint for admin/maintenance
-            .route("/refresh", web::post().to(refresh_graph))
-    );
+#[derive(Debug, Deserialize)]
+pub struct PinRequest {
+    pub pinned: bool,
+    // Where to hold the node; defaults to where it is now
+    pub position: Option<[f32; 3]>,
+}
+
+/// GET /api/graph/pins - pinned nodes, whether each was pinned this session or restored
+/// from the layout state file, and pins whose file has gone
+pub async fn get_pins(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(GetPins).await {
+        Ok(Ok(report)) => Ok(HttpResponse::Ok().json(report)),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => {
+            error!("Mailbox error getting pins: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+/// PUT /api/graph/nodes/{id}/pin - pin a node in place (persisted across restarts) or unpin it
+pub async fn set_node_pin(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+    body: web::Json<PinRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let node_id = path.into_inner();
+    let body = body.into_inner();
+    if body.position.is_some_and(|p| p.iter().any(|v| !v.is_finite())) {
+        return Err(ApiError::invalid("position", "must be finite numbers"));
+    }
+    let message = SetNodePin {
+        node_id,
+        pinned: body.pinned,
+        position: body.position.map(glam::Vec3::from),
+    };
+    match state.graph_service_addr.send(message).await {
+        Ok(Ok(pin)) => {
+            let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+            let operation = if body.pinned { "pin_node" } else { "unpin_node" };
+            state.audit_log.record(&actor, AuditRecord::new(operation).nodes([node_id]).change(None, Some(serde_json::json!(pin))));
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "nodeId": node_id, "pinned": body.pinned, "pin": pin })))
+        }
+        // The position was checked above, so only the node can be missing
+        Ok(Err(_)) => Err(ApiError::NotFound(format!("Node {}", node_id))),
+        Err(e) => {
+            error!("Mailbox error setting pin on node {}: {}", node_id, e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeNodesRequest {
+    pub keep: u32,
+    pub merge: Vec<u32>,
+    #[serde(default, alias = "dry_run")]
+    pub dry_run: bool,
+}
+
+/// POST /api/graph/nodes/merge - fold aliased nodes into one. Edges move to the kept node
+/// and the merged files stay folded into it across rebuilds. `dryRun` returns the diff
+/// without applying it.
+pub async fn merge_nodes(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<MergeNodesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let body = body.into_inner();
+    if body.merge.is_empty() {
+        return Err(ApiError::invalid("merge", "list at least one node to merge"));
+    }
+    let merged = body.merge.clone();
+    let message = MergeNodes { keep: body.keep, merge: body.merge, dry_run: body.dry_run };
+    match state.graph_service_addr.send(message).await {
+        Ok(Ok(outcome)) => {
+            if !body.dry_run {
+                let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+                let record = AuditRecord::from_diff("merge_nodes", &outcome.diff)
+                    .nodes(std::iter::once(body.keep).chain(merged.iter().copied()))
+                    .change(None, Some(serde_json::json!({ "keep": body.keep, "merged": merged, "aliases": outcome.aliases })));
+                state.audit_log.record(&actor, record);
+            }
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "dryRun": body.dry_run,
+                "keep": body.keep,
+                "aliases": outcome.aliases,
+                "conflicts": outcome.conflicts,
+                "diff": outcome.diff,
+            })))
+        }
+        Ok(Err(e)) if e.ends_with(" not found") => Err(ApiError::NotFound(e.trim_end_matches(" not found").to_string())),
+        Ok(Err(e)) if e.starts_with("Failed") => Err(ApiError::Internal(e)),
+        Ok(Err(e)) => Err(ApiError::invalid("merge", e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphTransactionRequest {
+    pub ops: Vec<TransactionOp>,
+}
+
+/// POST /api/graph/transactions - apply an ordered batch of edits all-or-nothing. Later
+/// operations can name nodes created earlier by handle. The first failing operation
+/// rejects the whole batch and is reported by index; on success clients get one diff.
+pub async fn apply_graph_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<GraphTransactionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let ops = body.into_inner().ops;
+    let op_count = ops.len();
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    let outcome = match state.graph_service_addr.send(ApplyGraphTransaction { ops, actor: actor.clone() }).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => {
+            let field = e.op_index.map_or("ops".to_string(), |i| format!("ops[{}]", i));
+            return Err(ApiError::invalid(&field, e.to_string()));
+        }
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    state.event_log.record(&actor, "graph_transaction", serde_json::json!({
+        "transactionId": outcome.transaction_id,
+        "operations": op_count,
+        "generation": outcome.diff.generation,
+    }));
+    state.audit_log.record(&actor, AuditRecord::from_diff("graph_transaction", &outcome.diff));
+    Ok(HttpResponse::Ok().json(outcome))
+}
+
+/// POST /api/graph/transactions/undo - reverse the most recent transaction
+pub async fn undo_graph_transaction(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    let outcome = match state.graph_service_addr.send(UndoGraphTransaction { actor: actor.clone() }).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => return Err(ApiError::Conflict(e)),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    state.event_log.record(&actor, "graph_transaction_undo", serde_json::json!({
+        "transactionId": outcome.transaction_id,
+        "generation": outcome.diff.generation,
+    }));
+    state.audit_log.record(&actor, AuditRecord::from_diff("graph_transaction_undo", &outcome.diff));
+    Ok(HttpResponse::Ok().json(outcome))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateNodeAttributesRequest {
+    // Exactly one of ids or filter
+    pub ids: Option<Vec<u32>>,
+    pub filter: Option<GraphFilter>,
+    pub set: AttributeSet,
+}
+
+/// PATCH /api/graph/nodes - set group, colour, type, metadata keys or physics flags on
+/// many nodes at once, picked by id or by filter. Ids that don't exist are reported per id;
+/// the rest are still updated.
+pub async fn update_node_attributes(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<UpdateNodeAttributesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let body = body.into_inner();
+    match (&body.ids, &body.filter) {
+        (Some(_), Some(_)) | (None, None) => return Err(ApiError::invalid("ids", "give either ids or a filter")),
+        (Some(ids), None) if ids.is_empty() => return Err(ApiError::invalid("ids", "list at least one node")),
+        (None, Some(filter)) if filter.is_empty() => return Err(ApiError::invalid("filter", "set at least one filter field")),
+        _ => {}
+    }
+    if body.set.is_empty() {
+        return Err(ApiError::invalid("set", "set at least one attribute"));
+    }
+    let known_types = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => settings.system.node_attributes.node_types,
+        _ => return Err(ApiError::Internal("Failed to retrieve application settings".to_string())),
+    };
+    if let Err((field, reason)) = body.set.validate(&known_types) {
+        return Err(ApiError::invalid(&field, reason));
+    }
+    let violations = state.metadata_schema.validate(&body.set.metadata);
+    if !violations.is_empty() {
+        return Err(ApiError::InvalidMetadata(violations));
+    }
+
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    let message = UpdateNodeAttributes { node_ids: body.ids, filter: body.filter, set: body.set, actor: actor.clone() };
+    match state.graph_service_addr.send(message).await {
+        Ok(Ok(outcome)) => {
+            if let Some(event) = &outcome.event {
+                state.event_log.record(&actor, "node_attributes", event.clone());
+                state.audit_log.record(&actor, AuditRecord::from_attribute_results("update_node_attributes", &outcome.results));
+            }
+            Ok(HttpResponse::Ok().json(outcome))
+        }
+        Ok(Err(e)) => Err(ApiError::invalid("ids", e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+/// PATCH /api/graph/nodes/{id}/physics-flags - set or clear one node's active, fixed,
+/// noRepulsion and extraDamping flags; flags left out keep their value
+pub async fn update_node_physics_flags(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+    body: web::Json<PhysicsFlagsPatch>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let node_id = path.into_inner();
+    let patch = body.into_inner();
+    if patch.is_empty() {
+        return Err(ApiError::invalid("body", "set at least one of active, fixed, noRepulsion, extraDamping"));
+    }
+
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    let set = AttributeSet { physics_flags: Some(patch), ..Default::default() };
+    let message = UpdateNodeAttributes { node_ids: Some(vec![node_id]), filter: None, set, actor: actor.clone() };
+    let outcome = match state.graph_service_addr.send(message).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => return Err(ApiError::invalid("id", e)),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    if outcome.results.first().is_some_and(|r| r.status == AttributeUpdateStatus::NotFound) {
+        return Err(ApiError::NotFound(format!("Node {}", node_id)));
+    }
+    if let Some(event) = &outcome.event {
+        state.event_log.record(&actor, "node_attributes", event.clone());
+        state.audit_log.record(&actor, AuditRecord::from_attribute_results("update_physics_flags", &outcome.results));
+    }
+    let graph = fetch_graph_data(&state).await?;
+    let flags = graph.nodes.iter().find(|n| n.id == node_id).map_or(patch.apply(physics_flags::ACTIVE), |n| n.data.flags);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "nodeId": node_id,
+        "updated": outcome.updated > 0,
+        "physicsFlags": physics_flags::describe(flags),
+    })))
+}
+
+/// PATCH /api/graph/nodes/{id}/metadata - set metadata keys on one node. Values are
+/// checked against the metadata schema first; keys left out keep their value.
+pub async fn update_node_metadata(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+    body: web::Json<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let node_id = path.into_inner();
+    let set = AttributeSet { metadata: body.into_inner(), ..Default::default() };
+    if set.is_empty() {
+        return Err(ApiError::invalid("body", "set at least one metadata key"));
+    }
+    if let Err((field, reason)) = set.validate(&[]) {
+        return Err(ApiError::invalid(&field, reason));
+    }
+    let violations = state.metadata_schema.validate(&set.metadata);
+    if !violations.is_empty() {
+        return Err(ApiError::InvalidMetadata(violations));
+    }
+
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    let message = UpdateNodeAttributes { node_ids: Some(vec![node_id]), filter: None, set, actor: actor.clone() };
+    let outcome = match state.graph_service_addr.send(message).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => return Err(ApiError::invalid("id", e)),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    if outcome.results.first().is_some_and(|r| r.status == AttributeUpdateStatus::NotFound) {
+        return Err(ApiError::NotFound(format!("Node {}", node_id)));
+    }
+    if let Some(event) = &outcome.event {
+        state.event_log.record(&actor, "node_attributes", event.clone());
+        state.audit_log.record(&actor, AuditRecord::from_attribute_results("update_node_metadata", &outcome.results));
+    }
+    let graph = fetch_graph_data(&state).await?;
+    let metadata = graph.nodes.iter().find(|n| n.id == node_id).map(|n| n.metadata.clone()).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "nodeId": node_id,
+        "updated": outcome.updated > 0,
+        "metadata": metadata,
+    })))
+}
+
+/// GET /api/graph/metadata-schema - the metadata keys the server knows, their types,
+/// and whether unknown keys are rejected
+pub async fn get_metadata_schema(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(state.metadata_schema.as_ref())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    // gltf when left out, or html
+    pub format: Option<String>,
+    // Case-insensitive substring match on label or metadata id
+    pub filter: Option<String>,
+    // Comma-separated numeric node ids to restrict the export to
+    pub node_ids: Option<String>,
+    // Projection the embedded label placements are laid out in; xy when left out
+    pub projection: Option<String>,
+    // Room whose links to other rooms go in a glTF export as portals; the default room when left out
+    pub room: Option<String>,
+}
+
+/// GET /api/graph/export - the graph as a glTF scene, or with `format=html` as a single
+/// self-contained page with an inline viewer for people without the client
+pub async fn export_graph(
+    state: web::Data<AppState>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let format = query.format.as_deref().unwrap_or("gltf").to_lowercase();
+    if format != "gltf" && format != "html" {
+        return Err(ApiError::invalid("format", format!("unsupported export format '{}'", format)));
+    }
+
+    let node_ids: Option<std::collections::HashSet<u32>> = match &query.node_ids {
+        Some(ids) => match ids.split(',').filter(|s| !s.trim().is_empty()).map(|s| s.trim().parse::<u32>()).collect() {
+            Ok(ids) => Some(ids),
+            Err(_) => return Err(ApiError::invalid("node_ids", "must be a comma-separated list of numeric ids")),
+        },
+        None => None,
+    };
+    let filter = query.filter.as_ref().map(|f| f.to_lowercase());
+    let projection = parse_projection(query.projection.as_deref())?;
+    let room = match &query.room {
+        Some(room) => validate_room(room).map_err(|e| ApiError::invalid("room", e))?,
+        None => DEFAULT_ROOM.to_string(),
+    };
+
+    // A snapshot, so the simulation moving nodes mid-build can't tear the document
+    let graph = fetch_graph_data(&state).await?;
+    check_built(&state, &graph).await?;
+    let selected = move |n: &&Node| {
+        node_ids.as_ref().is_none_or(|ids| ids.contains(&n.id))
+            && filter.as_ref().is_none_or(|f| {
+                n.label.to_lowercase().contains(f) || n.metadata_id.to_lowercase().contains(f)
+            })
+    };
+
+    if format == "html" {
+        let snapshot = web::block(move || {
+            let nodes: Vec<Node> = graph.nodes.iter().filter(selected).cloned().collect();
+            crate::utils::html_export::build(&nodes, &graph.edges, "Knowledge graph", Default::default())
+        }).await.map_err(|e| {
+            error!("HTML export task failed: {}", e);
+            ApiError::Internal("Export failed".to_string())
+        })?;
+        info!("Exporting HTML snapshot ({} nodes, {} edges)", snapshot.node_count(), snapshot.edge_count());
+        return Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Content-Disposition", "attachment; filename=\"graph.html\""))
+            .streaming(futures::stream::iter(snapshot.map(Ok::<_, actix_web::Error>))));
+    }
+
+    let labels: HashMap<u32, LabelPlacement> = match state.label_placements.placements(projection, &state.graph_service_addr).await {
+        Ok(result) => result.labels.iter().map(|l| (l.node_id, *l)).collect(),
+        Err(e) => {
+            // The export is still useful without them
+            warn!("Exporting without label placements: {}", e);
+            HashMap::new()
+        }
+    };
+
+    // Building the document is CPU-bound, keep it off the async workers
+    let external_links = state.external_links.clone();
+    let document = web::block(move || {
+        let nodes: Vec<Node> = graph.nodes.iter().filter(selected).cloned().collect();
+        let mut document = crate::utils::gltf_export::build_gltf_with_labels(&nodes, &graph.edges, &labels);
+        crate::utils::gltf_export::add_portals(&mut document, &nodes, &external_links.portals(&room, &nodes));
+        document.to_string()
+    }).await.map_err(|e| {
+        error!("glTF export task failed: {}", e);
+        ApiError::Internal("Export failed".to_string())
+    })?;
+
+    info!("Exported glTF scene ({} bytes)", document.len());
+    Ok(HttpResponse::Ok()
+        .content_type("model/gltf+json")
+        .insert_header(("Content-Disposition", "attachment; filename=\"graph.gltf\""))
+        .body(document))
+}
+
+fn parse_projection(projection: Option<&str>) -> Result<Projection, ApiError> {
+    match projection.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(projection) => Projection::parse_query(projection).map_err(|e| ApiError::invalid("projection", e)),
+        None => Ok(Projection::XY),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    // svg when left out, or png
+    pub format: Option<String>,
+    pub width: Option<u32>,
+    // Follows the layout's aspect ratio when left out
+    pub height: Option<u32>,
+    // xz, xy or six comma-separated numbers; xy when left out
+    pub projection: Option<String>,
+    // Labels for this many of the most important nodes; svg only
+    pub labels: Option<usize>,
+}
+
+// Stills above this size take too long to draw on request
+const MAX_RENDER_NODES: usize = 20_000;
+
+fn still_response(format: &str, body: Vec<u8>) -> HttpResponse {
+    let (content_type, extension) = if format == "png" { ("image/png", "png") } else { ("image/svg+xml", "svg") };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("inline; filename=\"graph.{}\"", extension)))
+        // The layout keeps moving, so each request draws it afresh
+        .insert_header(("Cache-Control", "no-store"))
+        .body(body)
+}
+
+/// GET /api/graph/render - a still image of the current layout for embedding elsewhere,
+/// as SVG or, with `format=png`, PNG without labels
+pub async fn render_graph(
+    state: web::Data<AppState>,
+    query: web::Query<RenderQuery>,
+) -> Result<HttpResponse, ApiError> {
+    use crate::utils::still_render::{self, StillOptions, MAX_SIZE, MIN_SIZE};
+
+    let format = query.format.as_deref().unwrap_or("svg").to_lowercase();
+    if format != "svg" && format != "png" {
+        return Err(ApiError::invalid("format", format!("unsupported image format '{}'", format)));
+    }
+    for (name, size) in [("width", query.width), ("height", query.height)] {
+        if size.is_some_and(|size| !(MIN_SIZE..=MAX_SIZE).contains(&size)) {
+            return Err(ApiError::invalid(name, format!("must be between {} and {}", MIN_SIZE, MAX_SIZE)));
+        }
+    }
+    let options = StillOptions {
+        width: query.width.unwrap_or(StillOptions::default().width),
+        height: query.height,
+        projection: parse_projection(query.projection.as_deref())?,
+        labels: if format == "svg" { query.labels.unwrap_or(0) } else { 0 },
+        max_nodes: MAX_RENDER_NODES,
+    };
+
+    let graph = fetch_graph_data(&state).await?;
+    check_built(&state, &graph).await?;
+    if graph.nodes.len() > MAX_RENDER_NODES {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "graph has {} nodes; stills are limited to {}", graph.nodes.len(), MAX_RENDER_NODES
+        )));
+    }
+
+    let labels: HashMap<u32, LabelPlacement> = if options.labels == 0 {
+        HashMap::new()
+    } else {
+        match state.label_placements.placements(options.projection, &state.graph_service_addr).await {
+            Ok(result) => result.labels.iter().map(|l| (l.node_id, *l)).collect(),
+            Err(e) => {
+                warn!("Rendering without label placements: {}", e);
+                HashMap::new()
+            }
+        }
+    };
+
+    // Rasterising is CPU-bound, keep it off the async workers
+    let png = format == "png";
+    let (body, nodes, edges) = web::block(move || {
+        let still = still_render::compose(&graph.nodes, &graph.edges, &labels, &options)?;
+        let body = if png { still.to_png() } else { still.to_svg().into_bytes() };
+        Ok::<_, String>((body, still.node_count(), still.edge_count()))
+    }).await.map_err(|e| {
+        error!("Render task failed: {}", e);
+        ApiError::Internal("Render failed".to_string())
+    })?.map_err(ApiError::PayloadTooLarge)?;
+
+    debug!("Rendered {} still ({} nodes, {} edges, {} bytes)", format, nodes, edges, body.len());
+    Ok(still_response(&format, body))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelPlacementQuery {
+    // xz, xy or six comma-separated numbers; xy when left out
+    pub projection: Option<String>,
+}
+
+/// GET /api/graph/labels/placement - non-overlapping label spots for every node, most
+/// important first, cached until the layout or the graph changes
+pub async fn get_label_placement(state: web::Data<AppState>, query: web::Query<LabelPlacementQuery>) -> Result<HttpResponse, ApiError> {
+    let projection = parse_projection(query.projection.as_deref())?;
+    let result = state.label_placements.placements(projection, &state.graph_service_addr).await.map_err(|e| {
+        error!("Label placement failed: {}", e);
+        ApiError::Internal("Label placement failed".to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "generation": result.generations.generation,
+        "positionGeneration": result.generations.position_generation,
+        "projection": projection,
+        "cached": result.cached,
+        "labels": result.labels.as_slice(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpatialRadiusQuery {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub r: f32,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpatialFrustumRequest {
+    pub eye: [f32; 3],
+    pub forward: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    pub fov_deg: f32,
+    #[serde(default = "default_aspect")]
+    pub aspect: f32,
+    #[serde(default)]
+    pub near: f32,
+    pub far: f32,
+    pub limit: Option<usize>,
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_aspect() -> f32 {
+    1.0
+}
+
+const DEFAULT_SPATIAL_LIMIT: usize = 100;
+const MAX_SPATIAL_LIMIT: usize = 1_000;
+
+fn spatial_limit(limit: Option<usize>) -> Result<usize, ApiError> {
+    match limit.unwrap_or(DEFAULT_SPATIAL_LIMIT) {
+        0 => Err(ApiError::invalid("limit", "must be at least 1")),
+        limit => Ok(limit.min(MAX_SPATIAL_LIMIT)),
+    }
+}
+
+async fn query_spatial(state: &AppState, query: SpatialQuery, limit: usize) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(QuerySpatial { query, limit }).await {
+        Ok(Ok(result)) => Ok(HttpResponse::Ok().json(result)),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => Err(ApiError::unavailable("Graph service", e)),
+    }
+}
+
+/// GET /api/graph/spatial?x=&y=&z=&r= - nodes within r of a point, nearest first. The
+/// generations returned are the positions the distances were measured against.
+pub async fn get_spatial_nodes(state: web::Data<AppState>, query: web::Query<SpatialRadiusQuery>) -> Result<HttpResponse, ApiError> {
+    let center = glam::Vec3::new(query.x, query.y, query.z);
+    if !center.is_finite() {
+        return Err(ApiError::invalid("x", "coordinates must be finite numbers"));
+    }
+    if !(query.r.is_finite() && query.r > 0.0) {
+        return Err(ApiError::invalid("r", "must be a positive number"));
+    }
+    let limit = spatial_limit(query.limit)?;
+    query_spatial(&state, SpatialQuery::Radius { center, radius: query.r }, limit).await
+}
+
+/// POST /api/graph/spatial/frustum - nodes a perspective camera sees, nearest the eye first
+pub async fn query_spatial_frustum(state: web::Data<AppState>, request: web::Json<SpatialFrustumRequest>) -> Result<HttpResponse, ApiError> {
+    if !request.eye.iter().all(|c| c.is_finite()) {
+        return Err(ApiError::invalid("eye", "coordinates must be finite numbers"));
+    }
+    let frustum = Frustum::perspective(
+        glam::Vec3::from(request.eye),
+        glam::Vec3::from(request.forward),
+        glam::Vec3::from(request.up),
+        request.fov_deg.to_radians(),
+        request.aspect,
+        request.near,
+        request.far,
+    ).map_err(|e| ApiError::invalid("frustum", e))?;
+    let limit = spatial_limit(request.limit)?;
+    query_spatial(&state, SpatialQuery::Frustum(frustum), limit).await
+}
+
+// Annotations are stored by metadata id, so map the numeric id from the URL first
+async fn resolve_metadata_id(state: &AppState, node_id: u32) -> Result<String, ApiError> {
+    match state.graph_service_addr.send(GetNodeMap).await {
+        Ok(Ok(node_map)) => node_map.get(&node_id)
+            .map(|node| node.metadata_id.clone())
+            .ok_or_else(|| ApiError::NotFound(format!("Node {}", node_id))),
+        _ => {
+            error!("Failed to get node map while resolving node {}", node_id);
+            Err(ApiError::Internal("Graph service unavailable".to_string()))
+        }
+    }
+}
+
+pub async fn get_node_annotations(
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+) -> Result<HttpResponse, ApiError> {
+    let node_id = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+    let annotations = state.annotation_service.list(&metadata_id).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "nodeId": node_id,
+        "annotations": annotations
+    })))
+}
+
+fn log_rejected_annotation(node_id: u32, e: &AnnotationError) {
+    if !matches!(e, AnnotationError::Storage(_)) {
+        warn!("Rejected annotation change on node {}: {}", node_id, e);
+    }
+}
+
+pub async fn create_node_annotation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    path: web::Path<u32>,
+    request: web::Json<CreateAnnotationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let author = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&author), Role::Editor) {
+        return Ok(response);
+    }
+    let node_id = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+
+    let annotation = state.annotation_service.create(&metadata_id, &author, request.into_inner()).await
+        .inspect_err(|e| log_rejected_annotation(node_id, e))?;
+    info!("User {} annotated node {} ({})", author, node_id, metadata_id);
+    let event = serde_json::json!({
+        "type": "annotation_created",
+        "nodeId": node_id,
+        "annotation": annotation
+    });
+    state.client_manager_addr.do_send(BroadcastMessage { message: event.to_string() });
+    state.audit_log.record(&author, AuditRecord::new("create_annotation")
+        .nodes([node_id])
+        .change(None, Some(serde_json::json!({ "annotationId": annotation.id, "text": annotation.text }))));
+    Ok(HttpResponse::Created().json(annotation))
+}
+
+pub async fn delete_node_annotation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    path: web::Path<(u32, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let pubkey = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&pubkey), Role::Editor) {
+        return Ok(response);
+    }
+    let (node_id, annotation_id) = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+
+    let is_power_user = state.is_power_user(&pubkey);
+    let annotation = state.annotation_service
+        .delete(&metadata_id, &annotation_id, |a| a.author == pubkey || is_power_user)
+        .await
+        .inspect_err(|e| log_rejected_annotation(node_id, e))?;
+    let event = serde_json::json!({
+        "type": "annotation_deleted",
+        "nodeId": node_id,
+        "annotationId": annotation.id
+    });
+    state.client_manager_addr.do_send(BroadcastMessage { message: event.to_string() });
+    state.audit_log.record(&pubkey, AuditRecord::new("delete_annotation")
+        .nodes([node_id])
+        .change(Some(serde_json::json!({ "annotationId": annotation.id, "text": annotation.text })), None));
+    Ok(HttpResponse::Ok().json(serde_json::json!({"success": true})))
+}
+
+pub async fn get_node_preview(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+) -> Result<HttpResponse, ApiError> {
+    let node_id = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+
+    // Metadata and markdown files are keyed by file name, node metadata ids drop the extension
+    let file_name = format!("{}.md", metadata_id);
+    let metadata = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store.get(&file_name).cloned(),
+        _ => None,
+    };
+    let sha1 = metadata.as_ref().map(|m| m.sha1.clone()).unwrap_or_default();
+
+    // Previews are immutable per content hash
+    if !sha1.is_empty() {
+        let etag = format!("\"{}\"", sha1);
+        let matches = req.headers().get("If-None-Match")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v == etag);
+        if matches {
+            return Ok(HttpResponse::NotModified().insert_header(("ETag", etag)).finish());
+        }
+    }
+
+    let source = std::path::Path::new(crate::services::file_service::MARKDOWN_DIR).join(&file_name);
+    if !source.exists() {
+        return Err(ApiError::NotFound(format!("Source file for node {}", node_id)));
+    }
+
+    let preview_service = state.preview_service.clone();
+    let lookup_id = metadata_id.clone();
+    let result = web::block(move || preview_service.get_or_render(&lookup_id, &sha1, &source)).await;
+
+    match result {
+        Ok(Ok((preview, cache_hit))) => {
+            debug!("Preview for node {} ({}) served, cache hit: {}", node_id, metadata_id, cache_hit);
+            let mut response = HttpResponse::Ok();
+            if !preview.sha1.is_empty() {
+                response.insert_header(("ETag", format!("\"{}\"", preview.sha1)));
+                response.insert_header(("Cache-Control", "public, max-age=86400"));
+            } else {
+                response.insert_header(("Cache-Control", "no-cache"));
+            }
+            Ok(response.json(preview))
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to render preview for {}: {}", metadata_id, e);
+            Err(ApiError::NotFound(format!("Readable source for node {}", node_id)))
+        }
+        Err(e) => {
+            error!("Preview task failed for {}: {}", metadata_id, e);
+            Err(ApiError::Internal("Preview generation failed".to_string()))
+        }
+    }
+}
+
+/// GET /api/graph/nodes/{id}/summary - cached LLM summary, or 202 while one is generated
+pub async fn get_node_summary(
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+) -> Result<HttpResponse, ApiError> {
+    let node_id = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+
+    let file_name = format!("{}.md", metadata_id);
+    let sha1 = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store.get(&file_name).map(|m| m.sha1.clone()).unwrap_or_default(),
+        _ => String::new(),
+    };
+    if sha1.is_empty() {
+        return Err(ApiError::NotFound(format!("Content hash for node {}", node_id)));
+    }
+
+    let source = std::path::Path::new(crate::services::file_service::MARKDOWN_DIR).join(&file_name);
+    match state.summary_service.request(&metadata_id, &sha1, source) {
+        Ok(SummaryLookup::Ready(summary)) => Ok(HttpResponse::Ok()
+            .insert_header(("ETag", format!("\"{}\"", summary.sha1)))
+            .json(summary)),
+        Ok(SummaryLookup::Pending { retry_after_secs }) => Ok(HttpResponse::Accepted()
+            .insert_header(("Retry-After", retry_after_secs.to_string()))
+            .json(serde_json::json!({
+                "status": "pending",
+                "metadataId": metadata_id,
+                "retryAfter": retry_after_secs
+            }))),
+        Err(SummaryError::QueueFull) => {
+            warn!("Summary queue full, rejecting request for {}", metadata_id);
+            Err(ApiError::ServiceUnavailable {
+                message: "Summary queue is full, try again later".to_string(),
+                retry_after_secs: Some(30),
+            })
+        }
+        Err(e) => Err(ApiError::ServiceUnavailable { message: e.to_string(), retry_after_secs: None }),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagReviewRequest {
+    #[serde(default)]
+    pub accept: Vec<String>,
+    #[serde(default)]
+    pub reject: Vec<String>,
+}
+
+/// GET /api/graph/tags/pending - every node with unreviewed auto-tag proposals
+pub async fn get_pending_tags(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => Ok(HttpResponse::Ok().json(pending_proposals(&store))),
+        _ => {
+            error!("Failed to read metadata for tag review");
+            Err(ApiError::Internal("Failed to read metadata".to_string()))
+        }
+    }
+}
+
+/// POST /api/graph/nodes/{id}/tags/review - accept proposals into `tags` or reject them
+pub async fn review_node_tags(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    path: web::Path<u32>,
+    body: web::Json<TagReviewRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let pubkey = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&pubkey), Role::Editor) {
+        return Ok(response);
+    }
+    let node_id = path.into_inner();
+    let metadata_id = resolve_metadata_id(&state, node_id).await?;
+
+    let body = body.into_inner();
+    info!("Tag review for {} by {}: accept {:?}, reject {:?}", metadata_id, pubkey, body.accept, body.reject);
+    match state.tagging_service
+        .review(&format!("{}.md", metadata_id), body.accept, body.reject, &state.metadata_addr, &state.graph_service_addr)
+        .await
+    {
+        Ok(metadata) => {
+            state.audit_log.record(&pubkey, AuditRecord::new("review_tags")
+                .nodes([node_id])
+                .change(None, Some(serde_json::json!({ "tags": metadata.tags, "rejectedTags": metadata.rejected_tags }))));
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "metadataId": metadata_id,
+                "tags": metadata.tags,
+                "autoTags": metadata.auto_tags,
+                "rejectedTags": metadata.rejected_tags
+            })))
+        }
+        Err(e) => {
+            warn!("Tag review failed for {}: {}", metadata_id, e);
+            Err(ApiError::Internal(e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub limit: Option<usize>,
+}
+
+/// GET /api/graph/events?limit=N - recent attributed graph mutations
+pub async fn get_graph_events(state: web::Data<AppState>, query: web::Query<EventsQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(100).min(1000);
+    HttpResponse::Ok().json(state.event_log.recent(limit))
+}
+
+#[derive(Deserialize)]
+pub struct PositionHistoryRequest {
+    pub room: Option<String>,
+}
+
+/// POST /api/graph/undo and /redo - revert/reapply the room's most recent manual move
+async fn position_history(req: HttpRequest, state: web::Data<AppState>, body: Option<web::Json<PositionHistoryRequest>>, undo: bool) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = match body.and_then(|b| b.into_inner().room) {
+        Some(room) => validate_room(&room).map_err(|e| ApiError::invalid("room", e))?,
+        None => DEFAULT_ROOM.to_string(),
+    };
+    // Someone outside the room can't rewind what its members did
+    state.room_access.check_join(&room, &identity)?;
+
+    let result = if undo {
+        state.graph_service_addr.send(UndoPositionEdit { room }).await
+    } else {
+        state.graph_service_addr.send(RedoPositionEdit { room }).await
+    };
+    match result {
+        Ok(Ok(step)) => {
+            if let Some(record) = AuditRecord::from_position_step(undo, &step) {
+                let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+                state.audit_log.record(&actor, record);
+            }
+            Ok(HttpResponse::Ok().json(step))
+        }
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => {
+            error!("Mailbox error during position {}: {}", if undo { "undo" } else { "redo" }, e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+pub async fn undo_position(req: HttpRequest, state: web::Data<AppState>, body: Option<web::Json<PositionHistoryRequest>>) -> Result<HttpResponse, ApiError> {
+    position_history(req, state, body, true).await
+}
+
+pub async fn redo_position(req: HttpRequest, state: web::Data<AppState>, body: Option<web::Json<PositionHistoryRequest>>) -> Result<HttpResponse, ApiError> {
+    position_history(req, state, body, false).await
+}
+
+/// GET /api/graph/snapshots - layout snapshots, newest first
+pub async fn list_layout_snapshots(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.layout_snapshot_service.list().await {
+        Ok(snapshots) => Ok(HttpResponse::Ok().json(snapshots)),
+        Err(e) => {
+            error!("Failed to list layout snapshots: {}", e);
+            Err(ApiError::Internal(e))
+        }
+    }
+}
+
+// Whether `name` is a saved snapshot, so a missing one is a 404 rather than a failed read
+async fn check_snapshot_exists(state: &AppState, name: &str) -> Result<(), ApiError> {
+    let snapshots = state.layout_snapshot_service.list().await.map_err(ApiError::Internal)?;
+    if snapshots.iter().any(|s| s.name == name) {
+        Ok(())
+    } else {
+        Err(ApiError::NotFound(format!("Snapshot {}", name)))
+    }
+}
+
+/// POST /api/graph/snapshots/{name}/restore - load a snapshot into the live graph
+pub async fn restore_layout_snapshot(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let pubkey = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&pubkey), Role::Editor) {
+        return Ok(response);
+    }
+    let name = path.into_inner();
+    check_snapshot_exists(&state, &name).await?;
+
+    info!("Layout snapshot {} restore requested by {}", name, pubkey);
+    match state.layout_snapshot_service.restore(&name, &state.graph_service_addr).await {
+        Ok(placed) => {
+            state.audit_log.record(&pubkey, AuditRecord::new("restore_layout_snapshot")
+                .change(None, Some(serde_json::json!({ "name": name, "nodesPlaced": placed }))));
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "name": name, "nodesPlaced": placed })))
+        }
+        Err(e) => {
+            error!("Failed to restore layout snapshot {}: {}", name, e);
+            Err(ApiError::Internal(e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareSnapshotsRequest {
+    // Snapshot names, or "live" for the current layout
+    pub a: String,
+    pub b: String,
+    pub top: Option<usize>,
+    pub bins: Option<usize>,
+}
+
+async fn layout_positions(state: &AppState, name: &str) -> Result<HashMap<String, glam::Vec3>, ApiError> {
+    let nodes = if name == "live" {
+        match state.graph_service_addr.send(GetLayout).await {
+            Ok(Ok((_, nodes))) => nodes,
+            Ok(Err(e)) => return Err(ApiError::Internal(e)),
+            Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+        }
+    } else {
+        check_snapshot_exists(state, name).await?;
+        state.layout_snapshot_service.load(name).await.map_err(ApiError::Internal)?.nodes
+    };
+    Ok(nodes.into_iter().map(|n| (n.metadata_id, glam::Vec3::from(n.position))).collect())
+}
+
+/// POST /api/graph/snapshots/compare - how far nodes moved between two layouts
+pub async fn compare_layout_snapshots(
+    state: web::Data<AppState>,
+    body: web::Json<CompareSnapshotsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let a = layout_positions(&state, &body.a).await?;
+    let b = layout_positions(&state, &body.b).await?;
+    // Snapshots only keep positions; labels come from the live graph where the node still exists
+    let labels: HashMap<String, String> = match state.graph_service_addr.send(GetGraphSnapshot).await {
+        Ok(Ok(graph)) => graph.nodes.iter().map(|n| (n.metadata_id.clone(), n.label.clone())).collect(),
+        _ => HashMap::new(),
+    };
+    let label = |id: &str| labels.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+    let pairing = layout_metrics::pair_layouts(&a, &b);
+    let distances: Vec<f32> = pairing.matched.iter().map(|(_, pa, pb)| pa.distance(*pb)).collect();
+    let stats = layout_metrics::displacement_stats(&distances, body.bins.unwrap_or(10).clamp(1, 100));
+    let (points_a, points_b): (Vec<glam::Vec3>, Vec<glam::Vec3>) =
+        pairing.matched.iter().map(|(_, pa, pb)| (*pa, *pb)).unzip();
+    let aligned_rms = layout_metrics::procrustes_rms(&points_a, &points_b);
+
+    let mut moved: Vec<(&String, f32)> = pairing.matched.iter().map(|(id, _, _)| id).zip(distances.iter().copied()).collect();
+    moved.sort_by(|x, y| y.1.total_cmp(&x.1));
+    let most_moved: Vec<serde_json::Value> = moved.iter()
+        .take(body.top.unwrap_or(10))
+        .map(|(id, distance)| serde_json::json!({ "metadataId": id, "label": label(id), "displacement": distance }))
+        .collect();
+    let listed = |ids: &[String]| -> Vec<serde_json::Value> {
+        ids.iter().map(|id| serde_json::json!({ "metadataId": id, "label": label(id) })).collect()
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "a": body.a,
+        "b": body.b,
+        "displacement": stats,
+        "alignedRms": aligned_rms,
+        "mostMoved": most_moved,
+        "onlyInA": listed(&pairing.only_a),
+        "onlyInB": listed(&pairing.only_b),
+    })))
+}
+
+async fn global_physics(state: &AppState) -> Result<crate::config::PhysicsSettings, ApiError> {
+    match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => Ok(settings.visualisation.physics),
+        _ => {
+            error!("Failed to get settings for room physics");
+            Err(ApiError::Internal("Settings service unavailable".to_string()))
+        }
+    }
+}
+
+/// PATCH /api/graphs/{room}/physics - merge physics overrides into the room's. Only that
+/// room's loop picks the change up.
+pub async fn patch_room_physics(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    patch: web::Json<PhysicsOverrides>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    let global = global_physics(&state).await?;
+    let overrides = state.room_physics.patch(&room, &patch);
+    let params = state.room_physics.params(&room, &global);
+
+    // The shared graph's loop runs in the default room
+    let mut applied = false;
+    if room == DEFAULT_ROOM {
+        if let Some(gpu_compute_addr) = &state.gpu_compute_addr {
+            match gpu_compute_addr.send(UpdateSimulationParams { params }).await {
+                Ok(Ok(())) => applied = true,
+                Ok(Err(e)) => warn!("GPU rejected physics for room {}: {}", room, e),
+                Err(e) => warn!("GPU compute actor unavailable: {}", e),
+            }
+        }
+    }
+    info!("Updated physics overrides for room {}", room);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room": room,
+        "overrides": overrides,
+        "applied": applied,
+    })))
+}
+
+/// GET /api/graphs/{room}/physics/effective - the global physics with the room's overrides applied
+pub async fn get_effective_room_physics(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    let global = global_physics(&state).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room": room,
+        "overrides": state.room_physics.overrides(&room),
+        "physics": state.room_physics.effective(&room, &global),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PhysicsSweepRequest {
+    pub grid: SweepGrid,
+    pub iterations: u32,
+    #[serde(default)]
+    pub graph: SweepGraph,
+}
+
+/// POST /api/graphs/{room}/physics/sweep - score a grid of repulsion, spring and damping
+/// values by laying out the graph offline with each, starting from the room's effective
+/// physics. Runs as a job reporting how many combinations are done; the result ranks
+/// them and gives the best as overrides to PATCH into the room. Admin only.
+pub async fn sweep_room_physics(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    request: web::Json<PhysicsSweepRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Admin).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    let PhysicsSweepRequest { grid, iterations, graph } = request.into_inner();
+    grid.validate().map_err(|e| ApiError::invalid("grid", e))?;
+    if iterations == 0 || iterations > param_sweep::MAX_ITERATIONS {
+        return Err(ApiError::invalid("iterations", format!("must be between 1 and {}", param_sweep::MAX_ITERATIONS)));
+    }
+    let graph = match graph {
+        SweepGraph::Snapshot => {
+            let graph = fetch_graph_data(&state).await?;
+            check_built(&state, &graph).await?;
+            graph
+        }
+        SweepGraph::Generated { nodes, edges_per_node, seed } => {
+            if nodes == 0 || nodes > param_sweep::MAX_GENERATED_NODES {
+                return Err(ApiError::invalid("graph", format!("generated graphs have 1 to {} nodes", param_sweep::MAX_GENERATED_NODES)));
+            }
+            Arc::new(param_sweep::generated_graph(nodes, edges_per_node, seed))
+        }
+    };
+    let global = global_physics(&state).await?;
+    let base = state.room_physics.params(&room, &global);
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    info!("Sweeping {} physics combinations over {} nodes for room {}", grid.points().len(), graph.nodes.len(), room);
+    let id = state.jobs.submit_with_progress("physics_sweep", identity.pubkey, move |cancel, progress| {
+        param_sweep::run(&graph, &base, &grid, iterations, workers, &|| cancel.is_cancelled(), &|done| progress.report(&done))
+            .ok_or_else(|| JobError::new(409, "The physics sweep was cancelled"))
+    })?;
+    job_response(&id, state.jobs.wait_inline(&id).await)
+}
+
+/// GET /api/graphs - the rooms the caller can join
+pub async fn list_rooms(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    let mut rooms: Vec<String> = state.anchor_service.list(None).await.into_iter().map(|anchor| anchor.room).collect();
+    rooms.extend(state.room_physics.rooms());
+    rooms.extend(state.room_access.rooms());
+    rooms.push(DEFAULT_ROOM.to_string());
+    rooms.sort();
+    rooms.dedup();
+
+    let rooms: Vec<serde_json::Value> = state.room_access.visible(rooms.iter().map(String::as_str), &identity)
+        .into_iter()
+        .map(|room| {
+            let acl = state.room_access.acl_for(&room, &identity);
+            serde_json::json!({
+                "room": room,
+                "restricted": acl.is_some(),
+                "owner": acl.map(|acl| acl.owner).filter(|owner| !owner.is_empty()),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "rooms": rooms }))
+}
+
+// Whether an open room already has anchors or physics settings, so isn't the caller's to claim
+async fn room_in_use(state: &AppState, room: &str) -> bool {
+    state.room_physics.rooms().iter().any(|r| r == room) || !state.anchor_service.list(Some(room)).await.is_empty()
+}
+
+/// GET /api/graphs/{room}/members - the room's owner and members, to anyone who can join it
+pub async fn get_room_members(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    state.room_access.check_join(&room, &identity)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room": room,
+        "acl": state.room_access.acl(&room),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoomMemberRequest {
+    pub pubkey: String,
+    // None removes the member
+    pub role: Option<RoomRole>,
+}
+
+/// POST /api/graphs/{room}/members - add, change or remove a member. Owner or admin only;
+/// the first call on a new room makes the caller its owner, while an open room already
+/// in use can only be claimed by an admin.
+pub async fn set_room_member(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    request: web::Json<SetRoomMemberRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    if request.pubkey.trim().is_empty() {
+        return Err(ApiError::invalid("pubkey", "is required"));
+    }
+    let in_use = room_in_use(&state, &room).await;
+    let acl = state.room_access.set_member(&room, &identity, request.pubkey.trim(), request.role, in_use)?;
+    info!("Room {} member {} set to {:?}", room, request.pubkey.trim(), request.role);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "room": room, "acl": acl })))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInviteRequest {
+    #[serde(default = "default_invite_role")]
+    pub role: RoomRole,
+    pub ttl_secs: Option<i64>,
+}
+
+fn default_invite_role() -> RoomRole {
+    RoomRole::Viewer
+}
+
+/// POST /api/graphs/{room}/invites - a single-use invite link. Owner or admin only.
+pub async fn create_room_invite(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    request: web::Json<CreateInviteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    let in_use = room_in_use(&state, &room).await;
+    let invite = state.room_access.create_invite(&room, &identity, request.role, request.ttl_secs, in_use, chrono::Utc::now())?;
+    Ok(HttpResponse::Created().json(invite))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedeemInviteRequest {
+    pub token: String,
+}
+
+/// POST /api/graphs/{room}/invites/redeem - join the room with an invite token
+pub async fn redeem_room_invite(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    request: web::Json<RedeemInviteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let pubkey = identity.pubkey.ok_or(RoomAccessError::AnonymousCaller)?;
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    let role = state.room_access.redeem(&room, &request.token, &pubkey, chrono::Utc::now())?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "room": room, "role": role })))
+}
+
+/// GET /api/graphs/{room}/external-links - links between the room's documents and other
+/// rooms', with any end found missing at its room's last build marked dangling
+pub async fn get_external_links(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let room = validate_room(&path).map_err(|e| ApiError::invalid("room", e))?;
+    state.room_access.check_join(&room, &identity)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room": room,
+        "links": state.external_links.for_room(&room),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportExternalLinksRequest {
+    pub links: Vec<ExternalLink>,
+}
+
+/// POST /api/graphs/external-links - add links between rooms, or reweight existing ones.
+/// The caller must be able to join every room named; the links are checked against the
+/// graph straight away.
+pub async fn import_external_links(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    request: web::Json<ImportExternalLinksRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    for link in &request.links {
+        for room in [&link.from_room, &link.to_room] {
+            state.room_access.check_join(room.trim(), &identity)?;
+        }
+    }
+    let imported = state.external_links.import(&request.links)?;
+    // Rooms share the one graph, so its documents are every room's
+    match fetch_graph_data(&state).await {
+        Ok(graph) => {
+            let metadata_ids: std::collections::HashSet<String> = graph.nodes.iter().map(|n| n.metadata_id.clone()).collect();
+            for room in state.external_links.rooms() {
+                state.external_links.revalidate(&room, &metadata_ids);
+            }
+        }
+        Err(e) => warn!("External links will be checked at the next build: {}", e),
+    }
+    info!("Imported {} external links", imported);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "imported": imported,
+        "dangling": state.external_links.dangling(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveViewQuery {
+    #[serde(default)]
+    pub replace: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyViewRequest {
+    #[serde(default)]
+    pub scope: ViewScope,
+    // The websocket client the view is for, as sent in its `registered` message
+    pub client_id: usize,
+}
+
+/// GET /api/graph/views - saved views, by name
+pub async fn list_saved_views(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.saved_views.list().await)
+}
+
+/// POST /api/graph/views - save a named filter, layout, colouring and edge visibility.
+/// A name that's taken is a conflict unless `?replace=true`.
+pub async fn create_saved_view(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<SaveViewQuery>,
+    body: web::Json<SavedView>,
+) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let view = body.into_inner();
+    let graph = fetch_graph_data(&state).await?;
+    if let Err((field, reason)) = view.validate(&graph.nodes) {
+        return Err(ApiError::invalid(&field, reason));
+    }
+    let author = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    match state.saved_views.save(view, &author, query.replace).await {
+        Ok(Some(view)) => Ok(HttpResponse::Created().json(view)),
+        Ok(None) => Err(ApiError::Conflict("A view with that name already exists; pass replace=true to overwrite it".to_string())),
+        Err(e) => Err(ApiError::Internal(e)),
+    }
+}
+
+/// DELETE /api/graph/views/{name}
+pub async fn delete_saved_view(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let name = path.into_inner();
+    match state.saved_views.delete(&name).await {
+        Ok(Some(view)) => Ok(HttpResponse::Ok().json(view)),
+        Ok(None) => Err(ApiError::NotFound(format!("View {}", name))),
+        Err(e) => Err(ApiError::Internal(e)),
+    }
+}
+
+/// POST /api/graph/views/{name}/apply - apply a saved view for one websocket client. The
+/// client gets a `view_applied` message; with `"scope": "room"` the colouring and layout
+/// mode change for everyone, which takes the same admin role as setting them directly.
+pub async fn apply_saved_view(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<ApplyViewRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let required = if body.scope == ViewScope::Room { Role::Admin } else { Role::Viewer };
+    let identity = match state.require_role(&req, required).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let name = path.into_inner();
+    let view = state.saved_views.get(&name).await.ok_or_else(|| ApiError::NotFound(format!("View {}", name)))?;
+    let connected = state.client_manager_addr.send(GetClientIdentity { client_id: body.client_id }).await
+        .map_err(|e| ApiError::unavailable("Client manager", e))?;
+    if connected.is_none() {
+        return Err(ApiError::invalid("clientId", "no connected client with that id"));
+    }
+
+    let applied = SavedViewService::apply(&view, body.scope, body.client_id, &state.graph_service_addr, &state.client_manager_addr)
+        .await
+        .map_err(ApiError::Internal)?;
+    if body.scope == ViewScope::Room {
+        let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+        state.event_log.record(&actor, "view_applied", serde_json::json!({ "name": applied.name, "recolored": applied.recolored }));
+    }
+    Ok(HttpResponse::Ok().json(applied))
+}
+
+// Configure routes using snake_case
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/graph")
+            // Match client's endpoint pattern exactly
+            .route("/data", web::get().to(get_graph_data))
+            .route("/data/paginated", web::get().to(get_paginated_graph_data))
+            .route("/data/paginated/{session}", web::delete().to(delete_pagination_session))
+            .route("/update", web::post().to(update_graph))
+            // Keep refresh endpoint for admin/maintenance
+            .route("/refresh", web::post().to(refresh_graph))
+            .route("/build-report", web::get().to(get_build_report))
+            .route("/verify", web::post().to(verify_graph))
+            .route("/export", web::get().to(export_graph))
+            .route("/render", web::get().to(render_graph))
+            .route("/labels/placement", web::get().to(get_label_placement))
+            .route("/spatial", web::get().to(get_spatial_nodes))
+            .route("/spatial/frustum", web::post().to(query_spatial_frustum))
+            .route("/stats", web::get().to(get_graph_stats))
+            .route("/events", web::get().to(get_graph_events))
+            .route("/undo", web::post().to(undo_position))
+            .route("/redo", web::post().to(redo_position))
+            .route("/snapshots", web::get().to(list_layout_snapshots))
+            .route("/snapshots/compare", web::post().to(compare_layout_snapshots))
+            .route("/snapshots/{name}/restore", web::post().to(restore_layout_snapshot))
+            .route("/query", web::post().to(query_graph))
+            .route("/similarity", web::get().to(get_similarity_params))
+            .route("/similarity", web::put().to(update_similarity_params))
+            .route("/coloring", web::get().to(get_color_mapping))
+            .route("/coloring", web::put().to(update_color_mapping))
+            .route("/edges/bundles", web::get().to(get_edge_bundles))
+            .route("/edges/co-viewed", web::get().to(get_co_viewed_edges))
+            .route("/edges/physics", web::get().to(get_edge_type_physics))
+            .route("/edges/physics", web::put().to(update_edge_type_physics))
+            .route("/edges/{edge_id}", web::get().to(get_edge))
+            .route("/pagerank", web::get().to(get_pagerank))
+            .route("/skeleton", web::get().to(get_skeleton))
+            .route("/skeleton/physics", web::put().to(update_skeleton_physics))
+            .route("/simulation", web::get().to(get_simulation_mode))
+            .route("/simulation", web::put().to(update_simulation_mode))
+            .route("/pins", web::get().to(get_pins))
+            .route("/views", web::get().to(list_saved_views))
+            .route("/views", web::post().to(create_saved_view))
+            .route("/views/{name}", web::delete().to(delete_saved_view))
+            .route("/views/{name}/apply", web::post().to(apply_saved_view))
+            .route("/transactions", web::post().to(apply_graph_transaction))
+            .route("/transactions/undo", web::post().to(undo_graph_transaction))
+            .route("/nodes", web::patch().to(update_node_attributes))
+            .route("/nodes/merge", web::post().to(merge_nodes))
+            .route("/nodes/{id}/pin", web::put().to(set_node_pin))
+            .route("/nodes/{id}/physics-flags", web::patch().to(update_node_physics_flags))
+            .route("/nodes/{id}/metadata", web::patch().to(update_node_metadata))
+            .route("/metadata-schema", web::get().to(get_metadata_schema))
+            .route("/nodes/{id}/preview", web::get().to(get_node_preview))
+            .route("/nodes/{id}/summary", web::get().to(get_node_summary))
+            .route("/nodes/{id}/tags/review", web::post().to(review_node_tags))
+            .route("/tags/pending", web::get().to(get_pending_tags))
+            .route("/nodes/{id}/annotations", web::get().to(get_node_annotations))
+            .route("/nodes/{id}/annotations", web::post().to(create_node_annotation))
+            .route("/nodes/{id}/annotations/{annotation_id}", web::delete().to(delete_node_annotation))
+    );
+    cfg.service(
+        web::scope("/graphs")
+            .route("", web::get().to(list_rooms))
+            .route("/external-links", web::post().to(import_external_links))
+            .route("/{room}/members", web::get().to(get_room_members))
+            .route("/{room}/members", web::post().to(set_room_member))
+            .route("/{room}/invites", web::post().to(create_room_invite))
+            .route("/{room}/invites/redeem", web::post().to(redeem_room_invite))
+            .route("/{room}/physics", web::patch().to(patch_room_physics))
+            .route("/{room}/physics/effective", web::get().to(get_effective_room_physics))
+            .route("/{room}/physics/sweep", web::post().to(sweep_room_physics))
+            .route("/{room}/external-links", web::get().to(get_external_links))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    #[test]
+    fn test_page_bounds_name_the_bad_parameter() {
+        assert_eq!(page_bounds(0, 10, 25), Ok((0, 10, 3)));
+        assert_eq!(page_bounds(2, 10, 25), Ok((20, 25, 3)));
+        assert!(matches!(page_bounds(0, 0, 25), Err(ApiError::InvalidParameter { name, .. }) if name == "page_size"));
+        let error = page_bounds(3, 10, 25).unwrap_err();
+        assert_eq!(error, ApiError::invalid("page", "page 4 exceeds total available pages 3"));
+        assert_eq!(error.status_code(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_still_response_headers() {
+        let header = |response: &HttpResponse, name: &str| response.headers().get(name).unwrap().to_str().unwrap().to_string();
+        let svg = still_response("svg", b"<svg/>".to_vec());
+        assert_eq!(header(&svg, "content-type"), "image/svg+xml");
+        assert_eq!(header(&svg, "content-disposition"), "inline; filename=\"graph.svg\"");
+        assert_eq!(header(&svg, "cache-control"), "no-store");
+        let png = still_response("png", Vec::new());
+        assert_eq!(header(&png, "content-type"), "image/png");
+        assert_eq!(header(&png, "content-disposition"), "inline; filename=\"graph.png\"");
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_refreshes_wait_and_build_the_latest_store() {
+        use crate::actors::messages::{GetGraphData, UpdateMetadata};
+        use crate::models::metadata::MetadataStore;
+        use actix_web::{test as actix_test, App};
+
+        let mut state = AppState::for_tests().await;
+        state.access_control.settings.anonymous_role = Role::Editor;
+        let metadata_addr = state.metadata_addr.clone();
+        let graph_addr = state.graph_service_addr.clone();
+        let rebuilds = state.graph_rebuilds.clone();
+        let app = actix_test::init_service(App::new().app_data(web::Data::new(state)).configure(config)).await;
+        let store = |names: &[&str]| -> MetadataStore {
+            names.iter().enumerate().map(|(i, name)| (name.to_string(), Metadata {
+                file_name: name.to_string(),
+                node_id: (i + 1).to_string(),
+                ..Default::default()
+            })).collect()
+        };
+        let (app, metadata_addr) = (&app, &metadata_addr);
+        // Each refresh reads the store set just before it
+        let refresh = |names: &'static [&'static str]| async move {
+            metadata_addr.do_send(UpdateMetadata { metadata: store(names) });
+            actix_test::call_service(app, actix_test::TestRequest::post().uri("/graph/refresh").to_request()).await
+        };
+
+        // The first refresh builds; the other two wait behind it and share one build of the last store
+        let (first, second, third) = futures::join!(refresh(&["a.md"]), refresh(&["a.md", "b.md"]), refresh(&["c.md"]));
+        for response in [first, second, third] {
+            assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+        }
+        let status = rebuilds.status();
+        assert_eq!((status.builds, status.coalesced), (2, 1));
+        let graph = graph_addr.send(GetGraphData).await.unwrap().unwrap();
+        assert_eq!(graph.metadata.keys().collect::<Vec<_>>(), ["c.md"]);
+    }
 }
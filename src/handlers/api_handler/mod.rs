@@ -1,3 +1,4 @@
+pub mod anchors;
 pub mod files;
 pub mod graph;
 pub mod visualisation;
@@ -22,13 +23,29 @@ use actix_web::web;
 
 // Configure all API routes
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("") // Removed redundant /api prefix
-            .configure(files::config)
-            .configure(graph::config)
-            .configure(visualisation::config)
-            .configure(crate::handlers::nostr_handler::config)
-            .configure(crate::handlers::settings_handler::config)
-            .configure(crate::handlers::ragflow_handler::config) // Add this line
-    );
+    let scope = web::scope("") // Removed redundant /api prefix
+        .configure(files::config)
+        .configure(graph::config)
+        .configure(anchors::config)
+        .configure(visualisation::config)
+        .configure(crate::handlers::nostr_handler::config)
+        .configure(crate::handlers::settings_handler::config)
+        .configure(crate::handlers::ragflow_handler::config) // Add this line
+        .configure(crate::handlers::telemetry_handler::config)
+        .configure(crate::handlers::enrichment_handler::config)
+        .configure(crate::handlers::recording_handler::config)
+        .configure(crate::handlers::job_handler::config)
+        .configure(crate::handlers::webhook_handler::config)
+        .configure(crate::handlers::audit_handler::config)
+        .configure(crate::handlers::capabilities_handler::config)
+        .configure(crate::handlers::metadata_handler::config)
+        .configure(crate::handlers::client_handler::config)
+        .configure(crate::handlers::gpu_handler::config)
+        .configure(crate::handlers::physics_handler::config);
+    #[cfg(feature = "speech")]
+    let scope = scope.configure(crate::handlers::speech_handler::config);
+    // Dev-only; the routes don't exist unless built with the loadtest feature
+    #[cfg(feature = "loadtest")]
+    let scope = scope.configure(crate::handlers::loadtest_handler::config);
+    cfg.service(scope);
 }
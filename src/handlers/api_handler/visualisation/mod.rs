@@ -1,7 +1,8 @@
 use crate::config::{Settings, SystemSettings, ClientWebSocketSettings};
 use crate::AppState;
+use crate::config::feature_access::Role;
 use crate::actors::messages::{GetSettings, UpdateSettings};
-use actix_web::{error::ErrorInternalServerError, web, Error, HttpResponse, Result};
+use actix_web::{error::ErrorInternalServerError, web, Error, HttpRequest, HttpResponse, Result};
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -314,10 +315,15 @@ pub async fn get_setting(
 }
 
 pub async fn update_setting(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
     path: web::Path<(String, String)>,
     value: web::Json<Value>,
 ) -> HttpResponse {
+    // These are the server-wide settings every client sees
+    if let Err(response) = app_state.require_role(&req, Role::Admin).await {
+        return response;
+    }
     let (category, setting) = path.into_inner();
     info!(
         "Updating setting for category: {}, setting: {}",
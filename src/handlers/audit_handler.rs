@@ -0,0 +1,34 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::services::audit_log::{AuditQuery, MAX_PAGE_SIZE};
+
+/// GET /api/audit?node=&principal=&operation=&since=&cursor=&limit= - attributed
+/// mutations, newest first. Pass the page's `nextCursor` back as `cursor` for older ones.
+pub async fn get_audit_trail(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let query = query.into_inner();
+    if query.limit.is_some_and(|limit| limit == 0 || limit > MAX_PAGE_SIZE) {
+        return Err(ApiError::invalid("limit", format!("must be 1-{}", MAX_PAGE_SIZE)));
+    }
+    // Reads whole files, so it stays off the worker thread
+    let audit_log = state.audit_log.clone();
+    let page = web::block(move || audit_log.query(&query)).await
+        .map_err(|e| ApiError::Internal(format!("Audit query failed: {}", e)))?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/audit")
+            .route("", web::get().to(get_audit_trail))
+    );
+}
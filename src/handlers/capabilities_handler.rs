@@ -0,0 +1,81 @@
+use actix_web::{web, HttpResponse};
+
+use crate::utils::capabilities;
+
+/// GET /api/capabilities - what this build's websocket protocol supports, for clients to
+/// check before connecting. Nothing in it is secret, so it needs no login.
+pub async fn get_capabilities() -> HttpResponse {
+    HttpResponse::Ok().json(capabilities::capabilities())
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/capabilities")
+            .route("", web::get().to(get_capabilities))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::capabilities::Handshake;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    async fn test_published_options_are_what_the_handshake_accepts() {
+        let app = test::init_service(App::new().configure(config)).await;
+        let document: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/capabilities").to_request()).await;
+        let options = document["handshakeOptions"].as_array().unwrap();
+        let required: Vec<&str> = options.iter()
+            .filter(|o| o["required"] == true)
+            .map(|o| o["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(required, ["localPhysics"]);
+
+        // Every advertised value, alone and all together, is accepted; sending a default
+        // is the same as leaving it out
+        let base = json!({ "type": "capabilities", "localPhysics": true });
+        let mut everything = base.clone();
+        for option in options {
+            let name = option["name"].as_str().unwrap();
+            let accepted: Vec<Value> = match option["type"].as_str().unwrap() {
+                "boolean" => vec![json!(true), json!(false)],
+                "integer" => option["values"].as_array().unwrap().clone(),
+                "choice" => option["values"].as_array().unwrap().clone(),
+                "projection" => option["names"].as_array().unwrap().iter().cloned()
+                    .chain([json!([[1, 0, 0], [0, 0, 1]])])
+                    .collect(),
+                other => panic!("unexpected option type {}", other),
+            };
+            for value in &accepted {
+                let mut msg = base.clone();
+                msg[name] = value.clone();
+                assert!(Handshake::parse(&msg).is_ok(), "{} = {} was advertised but rejected", name, value);
+            }
+            everything[name] = accepted[0].clone();
+            if !option["default"].is_null() {
+                let mut msg = base.clone();
+                msg[name] = option["default"].clone();
+                assert_eq!(Handshake::parse(&msg), Handshake::parse(&base));
+            }
+        }
+        assert!(Handshake::parse(&everything).is_ok());
+
+        // Headers and encodings are switched on by options the handshake knows
+        let names: Vec<&str> = options.iter().map(|o| o["name"].as_str().unwrap()).collect();
+        for toggle in document["frameHeaders"].as_array().unwrap().iter()
+            .chain(document["frameEncodings"].as_array().unwrap())
+            .filter_map(|entry| entry["enabledBy"].as_str())
+        {
+            assert!(names.contains(&toggle), "{} isn't a handshake option", toggle);
+        }
+
+        // Unadvertised versions and options are refused
+        let versions = document["supportedProtocolVersions"].as_array().unwrap();
+        let unsupported = versions.iter().filter_map(Value::as_u64).max().unwrap() + 1;
+        assert!(Handshake::parse(&json!({ "localPhysics": true, "protocolVersion": unsupported })).is_err());
+        assert!(Handshake::parse(&json!({ "localPhysics": true, "quantization": 16 })).is_err());
+        assert_eq!(document["features"]["websocket"], cfg!(feature = "websocket"));
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde_json::json;
+
+use crate::actors::messages::GetSentFrames;
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::utils::frame_hash::SENT_FRAME_HISTORY;
+
+/// GET /api/clients/{id}/frames - hashes and sizes of the last frames sent to a client, to
+/// match against a hash the client reports. Empty unless it asked for frame hashes.
+pub async fn get_sent_frames(req: HttpRequest, state: web::Data<AppState>, client_id: web::Path<usize>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let client_id = client_id.into_inner();
+    match state.client_manager_addr.send(GetSentFrames { client_id }).await {
+        Ok(Some(frames)) => Ok(HttpResponse::Ok().json(json!({
+            "clientId": client_id,
+            "retained": SENT_FRAME_HISTORY,
+            "frames": frames,
+        }))),
+        Ok(None) => Err(ApiError::NotFound(format!("Connected client {}", client_id))),
+        Err(e) => {
+            error!("Mailbox error getting sent frames: {}", e);
+            Err(ApiError::unavailable("Client manager", e))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/clients")
+            .route("/{id}/frames", web::get().to(get_sent_frames))
+    );
+}
@@ -0,0 +1,72 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::actors::messages::GetMetadata;
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::services::nostr_service::NostrService;
+use crate::utils::auth::{check_role, verify_authenticated};
+
+#[derive(Debug, Deserialize)]
+pub struct RunQuery {
+    // Metadata id or file name of the node to refresh
+    pub node: String,
+}
+
+/// GET /api/enrichment/status - queue depth, counters and recent failures of the link refresh job
+pub async fn get_status(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(state.enrichment_service.status())
+}
+
+/// POST /api/enrichment/run?node=... - force-refresh one node, ignoring its age and backoff
+pub async fn run_enrichment(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    query: web::Query<RunQuery>,
+) -> Result<HttpResponse, ApiError> {
+    // Every call costs an external API request, so keep it to power users
+    let pubkey = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&pubkey), Role::Admin) {
+        return Ok(response);
+    }
+    if !state.enrichment_service.is_configured() {
+        return Err(ApiError::ServiceUnavailable { message: "Perplexity service not configured".to_string(), retry_after_secs: None });
+    }
+
+    let file_name = if query.node.ends_with(".md") { query.node.clone() } else { format!("{}.md", query.node) };
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) if store.contains_key(&file_name) => {}
+        Ok(Ok(_)) => return Err(ApiError::NotFound(format!("Node {}", query.node))),
+        _ => {
+            error!("Failed to read metadata for enrichment");
+            return Err(ApiError::Internal("Failed to read metadata".to_string()));
+        }
+    }
+
+    info!("Forced enrichment of {} requested by {}", file_name, pubkey);
+    match state.enrichment_service
+        .refresh(&file_name, &state.metadata_addr, &state.graph_service_addr)
+        .await
+    {
+        Ok(link) => Ok(HttpResponse::Ok().json(json!({
+            "fileName": file_name,
+            "perplexityLink": link
+        }))),
+        Err(e) => Err(ApiError::ServiceUnavailable { message: e, retry_after_secs: None }),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/enrichment")
+            .route("/status", web::get().to(get_status))
+            .route("/run", web::post().to(run_enrichment))
+    );
+}
@@ -0,0 +1,86 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::actors::messages::{GetGPUStatus, GetGpuStepTimings, SetGpuTiming};
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::utils::gpu_timing::{self, TIMING_WINDOW};
+
+#[derive(Debug, Deserialize)]
+pub struct TimingQuery {
+    // Recent steps to include; the whole window by default
+    pub steps: Option<usize>,
+    // "table" for a plain-text per-step breakdown, JSON otherwise
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimingToggle {
+    pub enabled: bool,
+}
+
+fn gpu_not_running() -> ApiError {
+    ApiError::ServiceUnavailable { message: "GPU compute is not running".to_string(), retry_after_secs: None }
+}
+
+/// GET /api/gpu/timing - per-stage timings of recent GPU physics steps and their
+/// histograms, or with `?format=table` one row per step
+pub async fn get_timing(req: HttpRequest, state: web::Data<AppState>, query: web::Query<TimingQuery>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let gpu = state.gpu_compute_addr.as_ref().ok_or_else(gpu_not_running)?;
+    let steps = query.steps.unwrap_or(TIMING_WINDOW).min(TIMING_WINDOW);
+    let (status, recent) = match (gpu.send(GetGPUStatus).await, gpu.send(GetGpuStepTimings { steps }).await) {
+        (Ok(status), Ok(recent)) => (status, recent),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Mailbox error getting GPU timings: {}", e);
+            return Err(ApiError::unavailable("GPU compute", e));
+        }
+    };
+    if query.format.as_deref() == Some("table") {
+        return Ok(HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(gpu_timing::breakdown_table(&recent)));
+    }
+    Ok(HttpResponse::Ok().json(json!({ "summary": status.timing, "steps": recent })))
+}
+
+/// PUT /api/gpu/timing - switch timing collection on or off; it adds a little to every step
+pub async fn set_timing(req: HttpRequest, state: web::Data<AppState>, body: web::Json<TimingToggle>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let gpu = state.gpu_compute_addr.as_ref().ok_or_else(gpu_not_running)?;
+    match gpu.send(SetGpuTiming { enabled: body.enabled }).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({ "enabled": body.enabled }))),
+        Err(e) => {
+            error!("Mailbox error toggling GPU timing: {}", e);
+            Err(ApiError::unavailable("GPU compute", e))
+        }
+    }
+}
+
+/// GET /api/gpu/timing/metrics - the stage histograms and reconnect counters for Prometheus to scrape
+pub async fn get_timing_metrics(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let gpu = state.gpu_compute_addr.as_ref().ok_or_else(gpu_not_running)?;
+    match gpu.send(GetGPUStatus).await {
+        Ok(status) => Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(gpu_timing::prometheus_text(&status.timing) + &status.reconnect.prometheus_text())),
+        Err(e) => {
+            error!("Mailbox error getting GPU timings: {}", e);
+            Err(ApiError::unavailable("GPU compute", e))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/gpu")
+            .route("/timing", web::get().to(get_timing))
+            .route("/timing", web::put().to(set_timing))
+            .route("/timing/metrics", web::get().to(get_timing_metrics))
+    );
+}
@@ -1,17 +1,24 @@
 use actix_web::{web, HttpResponse, Result, get};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use crate::AppState;
 use log::{info, error};
 use chrono::Utc;
-use crate::actors::messages::{GetMetadata, GetGraphData}; // Assuming GetGraphData returns the necessary counts or the GraphData struct
+use crate::actors::messages::{GetMetadata, GetGraphData, GetClientCount, GetClientLod, GetDisturbanceStatus, GetFrameAccounting, GetFrameBudgetStatus, GetGPUStatus, GetIdleStatus, GetWarmupStatus}; // Assuming GetGraphData returns the necessary counts or the GraphData struct
+use crate::utils::frame_accounting::FrameTotals;
+use crate::utils::frame_budget::FrameBudgetStatus;
+use crate::utils::idle::IdleStatus;
+use crate::utils::warmup::WarmupStatus;
 // If GraphServiceActor needs a specific message for diagnostics:
 // use crate::actors::messages::GetSimulationDiagnostics;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 pub struct PhysicsSimulationStatus {
     status: String,
     details: String,
     timestamp: String,
+    warmup: Option<WarmupStatus>,
+    idle: Option<IdleStatus>,
+    frame_budget: Option<FrameBudgetStatus>,
 }
 
 pub async fn health_check(app_state: web::Data<AppState>) -> Result<HttpResponse> {
@@ -43,46 +50,123 @@ pub async fn health_check(app_state: web::Data<AppState>) -> Result<HttpResponse
 }
 
 #[get("/physics")]
-pub async fn check_physics_simulation(_app_state: web::Data<AppState>) -> Result<HttpResponse> {
+pub async fn check_physics_simulation(app_state: web::Data<AppState>) -> Result<HttpResponse> {
     let current_time = Utc::now();
-    
-    // Assuming GraphServiceActor has a message like GetSimulationDiagnostics
-    // If not, this part needs to be adapted based on how diagnostics are exposed by the actor.
-    // For now, let's assume a placeholder or that GraphServiceActor itself doesn't expose this directly anymore
-    // and this logic might need to be re-evaluated or moved.
-    // If `get_simulation_diagnostics` was a method on the old GraphService struct,
-    // it needs a corresponding message for the GraphServiceActor.
-    // Let's assume for now we get a generic status from the actor.
-    
-    // Placeholder for actual diagnostic fetching from an actor if available.
-    // This might involve sending a specific message to GraphServiceActor or GPUComputeActor.
-    // For example, if GPUComputeActor provides status:
-    // use crate::actors::messages::GetGPUStatus;
-    // let gpu_status_result = app_state.gpu_compute_addr.as_ref()
-    //     .map(|addr| addr.send(GetGPUStatus).await);
+    let warmup = match app_state.graph_service_addr.send(GetWarmupStatus).await {
+        Ok(Ok(status)) => Some(status),
+        _ => {
+            error!("Failed to get warm-up status from GraphServiceActor");
+            None
+        }
+    };
+    let idle = match app_state.graph_service_addr.send(GetIdleStatus).await {
+        Ok(Ok(status)) => Some(status),
+        _ => None,
+    };
+    let frame_budget = app_state.graph_service_addr.send(GetFrameBudgetStatus).await.ok();
+    let (status, details) = match (&warmup, &idle) {
+        (Some(w), _) if w.empty_graph && w.ready => ("empty".to_string(), "Graph has no nodes; physics and broadcasts paused until some are added".to_string()),
+        (_, Some(i)) if i.idle => ("idle".to_string(), "No clients connected; physics and broadcasts paused".to_string()),
+        (Some(w), _) if w.ready && frame_budget.as_ref().is_some_and(|b| b.overloaded) => (
+            "overloaded".to_string(),
+            format!(
+                "Physics steps overrun the frame budget; skipping {} frames after each",
+                frame_budget.as_ref().map_or(0, |b| b.skip_factor)
+            ),
+        ),
+        (Some(w), _) if w.ready => ("running".to_string(), "Layout is warmed up and broadcasting".to_string()),
+        (Some(w), _) => ("warming_up".to_string(), format!("Layout warm-up {:.0}% done, not broadcasting yet", w.progress * 100.0)),
+        (None, _) => ("unknown".to_string(), "Graph service unavailable".to_string()),
+    };
+    info!("Physics simulation diagnostic check at {}: {}", current_time, details);
 
-    // For now, let's construct a simplified diagnostic string.
-    // The original `get_simulation_diagnostics` was on `GraphService` struct, not actor.
-    // This functionality might need to be re-implemented via actor messages if still required.
-    // As a temporary measure, we'll return a generic status.
-    
-    let diagnostics = "Physics simulation status check via actor system (detailed diagnostics TBD)".to_string();
-    info!("Physics simulation diagnostic check at {}: {}", current_time, diagnostics);
-    
-    // Simplified status determination until actor-based diagnostics are clear
-    let status = "checking".to_string(); // Placeholder
-    
     Ok(HttpResponse::Ok().json(PhysicsSimulationStatus {
         status,
-        details: diagnostics,
+        details,
         timestamp: current_time.to_rfc3339(),
+        warmup,
+        idle,
+        frame_budget,
     }))
 }
 
+/// Readiness per component; 503 until every one is ready. The graph isn't ready until
+/// its layout has warmed up after the last rebuild. A built graph with no nodes is ready,
+/// flagged as empty.
+pub async fn readyz(app_state: web::Data<AppState>) -> Result<HttpResponse> {
+    let graph = match app_state.graph_service_addr.send(GetWarmupStatus).await {
+        Ok(Ok(status)) => serde_json::json!({ "ready": status.ready, "progress": status.progress, "emptyGraph": status.empty_graph }),
+        _ => serde_json::json!({ "ready": false, "error": "Graph service unavailable" }),
+    };
+    let ready = graph["ready"].as_bool().unwrap_or(false);
+    // Reported but not waited on: the graph is usable while speech providers warm up,
+    // and speech requests say so themselves
+    let speech = match &app_state.speech_service {
+        Some(speech) => {
+            let capabilities = speech.readiness();
+            serde_json::json!({ "ready": capabilities.values().all(|c| c.settled()), "capabilities": capabilities })
+        }
+        None => serde_json::json!({ "ready": false, "error": "Speech service is not available" }),
+    };
+    let body = serde_json::json!({
+        "ready": ready,
+        "components": { "graph": graph, "speech": speech },
+    });
+    if ready {
+        Ok(HttpResponse::Ok().json(body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(body))
+    }
+}
+
+/// Aggregated runtime counters, including client telemetry
+pub async fn metrics(app_state: web::Data<AppState>) -> Result<HttpResponse> {
+    let client_count = match app_state.client_manager_addr.send(GetClientCount).await {
+        Ok(Ok(count)) => count,
+        _ => 0,
+    };
+    let per_client = app_state.client_manager_addr.send(GetFrameAccounting).await.unwrap_or_default();
+    let client_lod = app_state.client_manager_addr.send(GetClientLod).await.unwrap_or_default();
+    let frame_budget = app_state.graph_service_addr.send(GetFrameBudgetStatus).await.ok();
+    let disturbance = app_state.graph_service_addr.send(GetDisturbanceStatus).await.ok();
+    let gpu_timing = match &app_state.gpu_compute_addr {
+        Some(gpu) => gpu.send(GetGPUStatus).await.ok().map(|status| status.timing),
+        None => None,
+    };
+    let mut frames = FrameTotals::default();
+    for totals in per_client.values() {
+        frames.add(totals);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "connectedClients": client_count,
+        "frameAccounting": {
+            "total": frames,
+            "clients": per_client,
+        },
+        "clientLod": client_lod,
+        "frameBudget": frame_budget,
+        "disturbance": disturbance,
+        "gpuTiming": gpu_timing,
+        "telemetry": app_state.telemetry_service.counters(),
+        "rateLimit": app_state.rate_limiter.counters(),
+        "webhooks": app_state.webhooks.counters(),
+    })))
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("")
             .route(web::get().to(health_check))
     );
+    cfg.service(
+        web::resource("/metrics")
+            .route(web::get().to(metrics))
+    );
+    cfg.service(
+        web::resource("/readyz")
+            .route(web::get().to(readyz))
+    );
     cfg.service(check_physics_simulation);
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::services::job_service::{JobState, JobStatus};
+use crate::utils::auth::Identity;
+
+/// GET /api/jobs/{id} - a job's status, and its result once finished
+pub async fn get_job(req: HttpRequest, state: web::Data<AppState>, id: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Viewer).await {
+        return Ok(response);
+    }
+    let status = state.jobs.status(&id).ok_or_else(|| job_not_found(&id))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+fn job_not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("Job {}", id))
+}
+
+// Whoever started a job may cancel it, as may any editor
+fn may_cancel(identity: &Identity, submitter: Option<&str>) -> bool {
+    identity.role >= Role::Editor || submitter.is_some_and(|submitter| identity.pubkey.as_deref() == Some(submitter))
+}
+
+/// DELETE /api/jobs/{id} - cancel a running job. Its submitter or an editor only.
+pub async fn cancel_job(req: HttpRequest, state: web::Data<AppState>, id: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Viewer).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let submitter = state.jobs.submitter(&id).ok_or_else(|| job_not_found(&id))?;
+    if !may_cancel(&identity, submitter.as_deref()) {
+        return Err(ApiError::Forbidden("Only the job's submitter or an editor can cancel it".to_string()));
+    }
+    let status = state.jobs.cancel(&id).ok_or_else(|| job_not_found(&id))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// What a handler that waited inline for job `id` answers: the result if it finished in
+/// time, otherwise 202 with where to poll
+pub fn job_response(id: &str, status: Option<JobStatus>) -> Result<HttpResponse, ApiError> {
+    let status = status.ok_or_else(|| job_not_found(id))?;
+    match status.state {
+        JobState::Completed { result } => Ok(HttpResponse::Ok().json(result)),
+        JobState::Running => {
+            let url = format!("/api/jobs/{}", id);
+            Ok(HttpResponse::Accepted()
+                .insert_header(("Location", url.as_str()))
+                .json(json!({ "jobId": id, "kind": status.kind, "status": "running", "statusUrl": url })))
+        }
+        JobState::Failed(e) => Err(ApiError::JobFailed { job_id: id.to_string(), status: e.status, message: e.error }),
+        JobState::Cancelled => Err(ApiError::JobFailed { job_id: id.to_string(), status: 409, message: "Job was cancelled".to_string() }),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/jobs")
+            .route("/{id}", web::get().to(get_job))
+            .route("/{id}", web::delete().to(cancel_job))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_submitter_or_an_editor_cancels() {
+        let viewer = |pubkey: &str| Identity { pubkey: Some(pubkey.to_string()), role: Role::Viewer };
+        assert!(may_cancel(&viewer("alice"), Some("alice")));
+        assert!(!may_cancel(&viewer("bob"), Some("alice")));
+        assert!(!may_cancel(&Identity { pubkey: None, role: Role::Viewer }, None));
+        assert!(may_cancel(&Identity { pubkey: Some("carol".to_string()), role: Role::Editor }, Some("alice")));
+        assert!(may_cancel(&Identity { pubkey: None, role: Role::Editor }, None));
+    }
+}
@@ -0,0 +1,33 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::services::load_test::{self, LoadTestParams};
+
+/// POST /api/dev/loadtest - run simulated clients against the live broadcast path and
+/// return the summary once the run ends. Only built with the `loadtest` feature.
+pub async fn run_load_test(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<LoadTestParams>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let params = body.into_inner();
+    params.validate().map_err(|e| ApiError::invalid("body", e))?;
+    if load_test::is_running() {
+        return Err(ApiError::Conflict("A load test is already running".to_string()));
+    }
+    let summary = load_test::run_load_test(params, state.client_manager_addr.clone(), state.graph_service_addr.clone()).await
+        .map_err(ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/dev")
+            .route("/loadtest", web::post().to(run_load_test))
+    );
+}
@@ -0,0 +1,272 @@
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+use crate::actors::messages::GetMetadata;
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::app_state::AppState;
+use crate::handlers::api_error::ApiError;
+use crate::config::feature_access::Role;
+use crate::models::metadata_schema::MetadataSchema;
+use crate::services::edge_recompute::{self, RecomputeReport, Weighting};
+use crate::services::metadata_import::{self, ImportReport, MAX_IMPORT_BYTES};
+use crate::services::graph_service::GraphRebuilds;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecomputeQuery {
+    pub weighting: Option<Weighting>,
+}
+
+enum SpoolError {
+    TooLarge,
+    Failed(String),
+}
+
+// Writes the body to a temp file as it arrives, so a large import is never held in memory
+async fn spool(mut payload: web::Payload, path: &Path) -> Result<(), SpoolError> {
+    let mut file = tokio::fs::File::create(path).await.map_err(|e| SpoolError::Failed(e.to_string()))?;
+    let mut written: u64 = 0;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| SpoolError::Failed(e.to_string()))?;
+        written += chunk.len() as u64;
+        if written > MAX_IMPORT_BYTES {
+            return Err(SpoolError::TooLarge);
+        }
+        file.write_all(&chunk).await.map_err(|e| SpoolError::Failed(e.to_string()))?;
+    }
+    file.flush().await.map_err(|e| SpoolError::Failed(e.to_string()))
+}
+
+async fn fetch_metadata(metadata_addr: &Addr<MetadataActor>) -> Result<crate::models::metadata::MetadataStore, ApiError> {
+    match metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => Ok(store),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => Err(ApiError::unavailable("Metadata service", e)),
+    }
+}
+
+/// POST /api/metadata/import - replace the metadata store with the posted one (JSON,
+/// optionally gzipped). Every entry is validated first; `?dry_run=true` returns the
+/// report and the diff without applying anything.
+pub async fn import_metadata(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let dry_run = query.dry_run.unwrap_or(false);
+    run_import(&state.metadata_addr, &state.graph_service_addr, &state.graph_rebuilds, state.metadata_schema.clone(), dry_run, payload).await
+}
+
+async fn run_import(
+    metadata_addr: &Addr<MetadataActor>,
+    graph_addr: &Addr<GraphServiceActor>,
+    rebuilds: &GraphRebuilds,
+    schema: Arc<MetadataSchema>,
+    dry_run: bool,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let Some(_guard) = metadata_import::try_begin() else {
+        return Err(ApiError::Conflict("A metadata import is already running".to_string()));
+    };
+
+    let path: PathBuf = std::env::temp_dir().join(format!("metadata-import-{}.json", uuid::Uuid::new_v4()));
+    let spooled = spool(payload, &path).await;
+    if let Err(e) = spooled {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(match e {
+            SpoolError::TooLarge => ApiError::PayloadTooLarge(format!("Import is larger than {} bytes", MAX_IMPORT_BYTES)),
+            SpoolError::Failed(e) => {
+                error!("Failed to receive metadata import: {}", e);
+                ApiError::invalid("body", format!("failed to read import: {}", e))
+            }
+        });
+    }
+
+    let parse_path = path.clone();
+    let parsed = web::block(move || {
+        let result = std::fs::File::open(&parse_path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| metadata_import::parse_store(file, &schema));
+        let _ = std::fs::remove_file(&parse_path);
+        result
+    }).await;
+    let (store, mut issues) = match parsed {
+        Ok(Ok(parsed)) => parsed,
+        Ok(Err(e)) => return Err(ApiError::invalid("body", e)),
+        Err(e) => {
+            error!("Metadata import task failed: {}", e);
+            return Err(ApiError::Internal("Metadata import failed".to_string()));
+        }
+    };
+
+    let existing = fetch_metadata(metadata_addr).await?;
+    issues.extend(metadata_import::validate(&store, &existing, chrono::Utc::now()));
+    let mut report = ImportReport {
+        dry_run,
+        applied: false,
+        files: store.len(),
+        issues,
+        diff: metadata_import::import_diff(&existing, &store),
+        generation: None,
+    };
+    if dry_run {
+        return Ok(HttpResponse::Ok().json(report));
+    }
+    // The report itself says what's wrong with each entry
+    if !report.issues.is_empty() {
+        warn!("Rejected metadata import with {} issues", report.issues.len());
+        return Ok(HttpResponse::UnprocessableEntity().json(report));
+    }
+
+    match metadata_import::apply_import(store, metadata_addr, graph_addr, rebuilds).await {
+        Ok(generation) => {
+            info!("Imported metadata for {} files: {} added, {} updated, {} removed",
+                report.files, report.diff.added_files.len(), report.diff.updated_files.len(), report.diff.removed_files.len());
+            report.applied = true;
+            report.generation = Some(generation);
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(e) => {
+            error!("Failed to apply metadata import: {}", e);
+            Err(ApiError::from_build_error(e))
+        }
+    }
+}
+
+/// POST /api/metadata/recompute-edges - re-derive topic links from each file's topic
+/// list (`?weighting=count|tfidf|jaccard`) and rebuild the graph with them
+pub async fn recompute_edges(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<RecomputeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    let Some(_guard) = edge_recompute::try_begin() else {
+        return Err(ApiError::Conflict("An edge recomputation is already running".to_string()));
+    };
+    let weighting = query.weighting.unwrap_or_default();
+
+    let snapshot = fetch_metadata(&state.metadata_addr).await?;
+    let files_with_topics = snapshot.values().filter(|m| !m.topics.is_empty()).count();
+    let computed = web::block(move || {
+        edge_recompute::recompute_topic_counts(&snapshot, weighting, edge_recompute::is_cancelled)
+    }).await;
+    let counts = match computed {
+        Ok(Ok(counts)) => counts,
+        Ok(Err(e)) => {
+            info!("{}", e);
+            return Err(ApiError::Conflict(e));
+        }
+        Err(e) => {
+            error!("Edge recomputation task failed: {}", e);
+            return Err(ApiError::Internal("Edge recomputation failed".to_string()));
+        }
+    };
+    // Last chance to back out; once the rebuild starts it runs to the end
+    if edge_recompute::is_cancelled() {
+        return Err(ApiError::Conflict("Edge recomputation was cancelled".to_string()));
+    }
+
+    match edge_recompute::apply_counts(counts, &state.metadata_addr, &state.graph_service_addr, &state.graph_rebuilds).await {
+        Ok((diff, generation)) => {
+            info!("Recomputed edges for {} files ({:?}): {} links added, {} removed",
+                files_with_topics, weighting, diff.added_links.len(), diff.removed_links.len());
+            Ok(HttpResponse::Ok().json(RecomputeReport { weighting, files_with_topics, diff, generation }))
+        }
+        Err(e) => {
+            error!("Failed to apply recomputed edges: {}", e);
+            Err(ApiError::from_build_error(e))
+        }
+    }
+}
+
+/// DELETE /api/metadata/recompute-edges - cancel the running recomputation
+pub async fn cancel_recompute_edges(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Editor).await {
+        return Ok(response);
+    }
+    if edge_recompute::cancel() {
+        Ok(HttpResponse::Accepted().json(json!({ "cancelling": true })))
+    } else {
+        Err(ApiError::NotFound("Edge recomputation".to_string()))
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/metadata")
+            .route("/import", web::post().to(import_metadata))
+            .route("/recompute-edges", web::post().to(recompute_edges))
+            .route("/recompute-edges", web::delete().to(cancel_recompute_edges))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::ClientManagerActor;
+    use crate::config::MetadataSchemaSettings;
+    use crate::models::metadata::MetadataStore;
+    use actix::Actor;
+    use actix_web::http::StatusCode;
+    use actix_web::{test as actix_test, App};
+
+    #[actix_web::test]
+    async fn test_import_reports_rejects_and_refuses_overlap() {
+        let metadata_addr = MetadataActor::new(MetadataStore::new()).start();
+        let graph_addr = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        let rebuilds = Arc::new(GraphRebuilds::new());
+        let schema = Arc::new(MetadataSchema::new(&MetadataSchemaSettings::default()));
+        let app = actix_test::init_service(App::new().route("/import", web::post().to(
+            move |query: web::Query<ImportQuery>, payload: web::Payload| {
+                let (metadata_addr, graph_addr, rebuilds, schema) =
+                    (metadata_addr.clone(), graph_addr.clone(), rebuilds.clone(), schema.clone());
+                async move { run_import(&metadata_addr, &graph_addr, &rebuilds, schema, query.dry_run.unwrap_or(false), payload).await }
+            },
+        ))).await;
+        let post = |uri: &str, body: &'static str| actix_test::TestRequest::post().uri(uri).set_payload(body).to_request();
+
+        let store = r#"{ "a.md": { "fileName": "a.md", "nodeId": "1", "topicCounts": { "b.md": 1 } },
+                         "b.md": { "fileName": "b.md", "nodeId": "2" } }"#;
+        let response = actix_test::call_service(&app, post("/import?dry_run=true", store)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let report: serde_json::Value = actix_test::read_body_json(response).await;
+        assert_eq!((report["dryRun"].as_bool(), report["applied"].as_bool()), (Some(true), Some(false)));
+        assert_eq!(report["diff"]["addedFiles"], serde_json::json!(["a.md", "b.md"]));
+        assert_eq!(report["diff"]["addedLinks"], serde_json::json!([["a", "b"]]));
+
+        // Entries with problems keep the whole import from being applied
+        let bad = r#"{ "a.md": { "fileName": "a.md", "nodeId": "x" } }"#;
+        let response = actix_test::call_service(&app, post("/import", bad)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let report: serde_json::Value = actix_test::read_body_json(response).await;
+        assert_eq!(report["issues"][0]["field"], "nodeId");
+        let response = actix_test::call_service(&app, post("/import", "[1, 2]")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // While another import holds the guard a second one is refused
+        let guard = metadata_import::try_begin().unwrap();
+        let response = actix_test::call_service(&app, post("/import?dry_run=true", store)).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        drop(guard);
+        let response = actix_test::call_service(&app, post("/import?dry_run=true", store)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
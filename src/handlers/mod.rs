@@ -1,9 +1,31 @@
+#[cfg(feature = "websocket")]
+pub mod admin_socket_handler;
+#[cfg(feature = "websocket")]
+pub mod agent_socket_handler;
+pub mod api_error;
 pub mod api_handler;
+pub mod audit_handler;
+pub mod capabilities_handler;
+pub mod client_handler;
+pub mod enrichment_handler;
+pub mod gpu_handler;
 pub mod health_handler;
+pub mod job_handler;
+#[cfg(feature = "loadtest")]
+pub mod loadtest_handler;
+pub mod metadata_handler;
 pub mod pages_handler;
 pub mod perplexity_handler;
+pub mod physics_handler;
 pub mod ragflow_handler;
+pub mod recording_handler;
 pub mod settings_handler;
+#[cfg(feature = "websocket")]
 pub mod socket_flow_handler;
+#[cfg(feature = "speech")]
+pub mod speech_handler;
+#[cfg(all(feature = "speech", feature = "websocket"))]
 pub mod speech_socket_handler;
+pub mod telemetry_handler;
+pub mod webhook_handler;
 pub mod nostr_handler;
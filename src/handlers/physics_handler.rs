@@ -0,0 +1,41 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::actors::messages::ReheatLayout;
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::utils::reheat::ReheatRequest;
+
+/// POST /api/physics/reheat - kick every node, or the ones a filter matches, to shake a
+/// settled layout out of a poor arrangement. Pinned and fixed nodes stay where they are.
+pub async fn reheat(req: HttpRequest, state: web::Data<AppState>, body: web::Json<ReheatRequest>) -> Result<HttpResponse, ApiError> {
+    let identity = match state.require_role(&req, Role::Editor).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let request = body.into_inner();
+    if let Err((field, reason)) = request.validate() {
+        return Err(ApiError::invalid(&field, reason));
+    }
+    let outcome = match state.graph_service_addr.send(ReheatLayout { request: request.clone() }).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => return Err(ApiError::Internal(format!("Failed to reheat the layout: {}", e))),
+        Err(e) => return Err(ApiError::unavailable("Graph service", e)),
+    };
+    let actor = identity.pubkey.unwrap_or_else(|| "anonymous".to_string());
+    state.event_log.record(&actor, "physics_reheat", json!({
+        "intensity": request.intensity,
+        "scope": request.scope,
+        "kicked": outcome.kicked,
+        "held": outcome.held,
+    }));
+    Ok(HttpResponse::Ok().json(outcome))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/physics")
+            .route("/reheat", web::post().to(reheat))
+    );
+}
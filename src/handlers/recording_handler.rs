@@ -0,0 +1,121 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::actors::messages::{ControlReplay, GetRecordingState, ReplayCommand};
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayQuery {
+    pub speed: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayControlRequest {
+    // play | pause | seek | speed | stop
+    pub action: String,
+    pub position_ms: Option<u32>,
+    pub speed: Option<f32>,
+}
+
+/// GET /api/recording/status - whether a recording or replay is running, and where it is
+pub async fn get_status(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    match state.graph_service_addr.send(GetRecordingState).await {
+        Ok(Ok(recording_state)) => Ok(HttpResponse::Ok().json(recording_state)),
+        Ok(Err(e)) => Err(ApiError::Internal(e)),
+        Err(e) => {
+            error!("Mailbox error getting recording state: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+/// GET /api/recording/list - recordings on disk, newest first
+pub async fn list_recordings(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let recordings = state.recording_service.list().await
+        .inspect_err(|e| error!("Failed to list recordings: {}", e))
+        .map_err(ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(recordings))
+}
+
+/// POST /api/recording/start - record every broadcast position frame until stopped or capped
+pub async fn start_recording(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let (name, status) = state.recording_service.start(&state.graph_service_addr).await.map_err(ApiError::Conflict)?;
+    Ok(HttpResponse::Ok().json(json!({ "name": name, "status": status })))
+}
+
+/// POST /api/recording/stop
+pub async fn stop_recording(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let status = state.recording_service.stop(&state.graph_service_addr).await.map_err(ApiError::Conflict)?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// POST /api/recording/replay/{name}?speed=1.0 - stop physics and play a recording back
+pub async fn start_replay(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<ReplayQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let name = path.into_inner();
+    let recordings = state.recording_service.list().await.map_err(ApiError::Internal)?;
+    if !recordings.iter().any(|r| r.name == name) {
+        return Err(ApiError::NotFound(format!("Recording {}", name)));
+    }
+    let status = state.recording_service.replay(&name, query.speed.unwrap_or(1.0), &state.graph_service_addr).await
+        .map_err(|e| ApiError::invalid("recording", e))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// POST /api/recording/replay/control - play/pause/seek/speed/stop the running replay
+pub async fn control_replay(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<ReplayControlRequest>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let command = match body.action.as_str() {
+        "play" => ReplayCommand::Play,
+        "pause" => ReplayCommand::Pause,
+        "stop" => ReplayCommand::Stop,
+        "seek" => ReplayCommand::Seek(body.position_ms.ok_or_else(|| ApiError::invalid("positionMs", "seek needs positionMs"))?),
+        "speed" => ReplayCommand::Speed(body.speed.ok_or_else(|| ApiError::invalid("speed", "speed needs speed"))?),
+        other => return Err(ApiError::invalid("action", format!("Unknown action '{}'", other))),
+    };
+    match state.graph_service_addr.send(ControlReplay { command }).await {
+        Ok(Ok(status)) => Ok(HttpResponse::Ok().json(json!({ "replay": status }))),
+        Ok(Err(e)) => Err(ApiError::Conflict(e)),
+        Err(e) => {
+            error!("Mailbox error controlling replay: {}", e);
+            Err(ApiError::unavailable("Graph service", e))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/recording")
+            .route("/status", web::get().to(get_status))
+            .route("/list", web::get().to(list_recordings))
+            .route("/start", web::post().to(start_recording))
+            .route("/stop", web::post().to(stop_recording))
+            // Before /replay/{name}, which would otherwise swallow it
+            .route("/replay/control", web::post().to(control_replay))
+            .route("/replay/{name}", web::post().to(start_replay))
+    );
+}
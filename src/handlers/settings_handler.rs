@@ -7,7 +7,9 @@ use crate::actors::messages::{GetSettings, UpdateSettings};
 use actix_web::{web, Error, HttpResponse, HttpRequest};
 use chrono::Utc;
 use serde_json::json;
-use crate::config::feature_access::FeatureAccess;
+use crate::config::feature_access::{FeatureAccess, Role};
+use crate::utils::auth::forbidden_body;
+use crate::services::audit_log::AuditRecord;
 use log::{info, error, warn, debug};
 use std::time::Instant;
 
@@ -17,6 +19,11 @@ fn convert_to_ui_settings(full_settings: &AppFullSettings) -> UISettings {
     UISettings::from(full_settings) // Rely on the From trait implementation
 }
 
+// Settings changes are audited by section only; the values can include API keys
+fn settings_audit_record(scope: &str, sections: &[&str]) -> AuditRecord {
+    AuditRecord::new("update_settings").change(None, Some(json!({ "scope": scope, "sections": sections })))
+}
+
 // --- Helper Macros for Merging Settings ---
 
 // Helper macro for merging Option fields
@@ -72,7 +79,7 @@ async fn clear_all_settings_cache(
     };
     if !feature_access.is_power_user(&pubkey) {
         warn!("Non-power user {} attempted to clear all settings caches", pubkey);
-        return Ok(HttpResponse::Forbidden().body("Only power users can clear all settings caches"));
+        return Ok(HttpResponse::Forbidden().json(forbidden_body(feature_access.role_for(&pubkey), Role::Admin)));
     }
     UserSettings::clear_all_cache();
     info!("Power user {} cleared all settings caches", pubkey);
@@ -187,6 +194,7 @@ async fn update_user_settings(
 ) -> Result<HttpResponse, Error> {
     let _start_time = Instant::now(); // Prefixed with underscore
     let client_payload = payload.into_inner();
+    let sections = client_payload.sections();
 
     debug!("Received client settings payload: {:?}", client_payload);
 
@@ -267,6 +275,7 @@ async fn update_user_settings(
                 merge_copy_option!(target_physics.repulsion_distance, physics_dto.repulsion_distance);
                 merge_copy_option!(target_physics.mass_scale, physics_dto.mass_scale);
                 merge_copy_option!(target_physics.boundary_damping, physics_dto.boundary_damping);
+                merge_copy_option!(target_physics.degree_repulsion_factor, physics_dto.degree_repulsion_factor);
             }
              if let Some(rendering_dto) = vis_dto.rendering { // rendering_dto is ClientRenderingSettings
                 let target_rendering = &mut target_vis.rendering; // Type: config::RenderingSettings
@@ -440,6 +449,7 @@ async fn update_user_settings(
         match state.settings_addr.send(UpdateSettings { settings: settings.clone() }).await {
             Ok(Ok(())) => {
                 info!("Power user {} updated global settings", pubkey);
+                state.audit_log.record(&pubkey, settings_audit_record("global", &sections));
                 let updated_ui_settings = convert_to_ui_settings(&settings);
                 Ok(HttpResponse::Ok().json(updated_ui_settings))
             }
@@ -503,6 +513,7 @@ async fn update_user_settings(
                 merge_copy_option!(target_physics.repulsion_distance, physics_dto.repulsion_distance);
                 merge_copy_option!(target_physics.mass_scale, physics_dto.mass_scale);
                 merge_copy_option!(target_physics.boundary_damping, physics_dto.boundary_damping);
+                merge_copy_option!(target_physics.degree_repulsion_factor, physics_dto.degree_repulsion_factor);
             }
              if let Some(rendering_dto) = vis_dto.rendering { // rendering_dto is ClientRenderingSettings
                 let target_rendering = &mut target_vis.rendering; // Type: config::RenderingSettings
@@ -653,6 +664,7 @@ async fn update_user_settings(
         }
 
         debug!("User {} updated their settings", pubkey);
+        state.audit_log.record(&pubkey, settings_audit_record("user", &sections));
         Ok(HttpResponse::Ok().json(&user_settings.settings))
     }
 }
@@ -672,6 +684,7 @@ async fn update_settings( // This is the deprecated endpoint
     // TODO: Refactor or remove this deprecated endpoint properly.
     // The following is a placeholder and likely incorrect without proper mapping.
     let client_payload = payload.into_inner();
+    let sections = client_payload.sections();
     debug!("Deserialized payload via deprecated /user-settings: {:?}", client_payload);
 
     let pubkey = match req.headers().get("X-Nostr-Pubkey") {
@@ -684,7 +697,7 @@ async fn update_settings( // This is the deprecated endpoint
 
     if !feature_access.is_power_user(&pubkey) {
         warn!("Non-power user {} attempted to modify global settings via /user-settings", pubkey);
-        return Ok(HttpResponse::Forbidden().body("Only power users can modify global settings"));
+        return Ok(HttpResponse::Forbidden().json(forbidden_body(feature_access.role_for(&pubkey), Role::Admin)));
     }
 
     // Perform the same careful merge as in update_user_settings
@@ -746,6 +759,7 @@ async fn update_settings( // This is the deprecated endpoint
             merge_copy_option!(target_physics.repulsion_distance, physics_dto.repulsion_distance);
             merge_copy_option!(target_physics.mass_scale, physics_dto.mass_scale);
             merge_copy_option!(target_physics.boundary_damping, physics_dto.boundary_damping);
+            merge_copy_option!(target_physics.degree_repulsion_factor, physics_dto.degree_repulsion_factor);
         }
          if let Some(rendering_dto) = vis_dto.rendering { // rendering_dto is ClientRenderingSettings
             let target_rendering = &mut target_vis.rendering; // Type: config::RenderingSettings
@@ -913,6 +927,7 @@ if let Some(auth_dto) = client_payload.auth {
     match state.settings_addr.send(UpdateSettings { settings: settings.clone() }).await {
         Ok(Ok(())) => {
             info!("Power user {} updated global settings via deprecated /user-settings endpoint", pubkey);
+            state.audit_log.record(&pubkey, settings_audit_record("global", &sections));
             let updated_ui_settings = convert_to_ui_settings(&settings);
             Ok(HttpResponse::Ok().json(updated_ui_settings))
         }
@@ -2,14 +2,28 @@ use actix::{prelude::*, Actor, Handler, Message};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use log::{trace, debug, error, info, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
 
 use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::utils::auth::{self, Identity};
 use crate::utils::binary_protocol;
+use crate::utils::capabilities::Handshake;
+use crate::utils::community_view::{GraphView, MemberPositions};
+use crate::utils::frame_accounting::{FrameAccount, FrameAccounting, MAX_ECHO_FRAMES};
+use crate::utils::frame_hash::{self, SentFrame, SharedHash};
+use crate::utils::projection::Projection;
+use crate::utils::resync::{self, ResyncFrame, ResyncState, ResyncThrottle};
 use crate::types::vec3::Vec3Data;
-use crate::utils::socket_flow_messages::{BinaryNodeData, PingMessage, PongMessage};
+use crate::utils::socket_flow_messages::{BinaryNodeData, GazeFocus, PingMessage, PongMessage, PoseUpdate};
+use crate::utils::time_sync::{self, FrameTiming, TimeSyncReply};
+use crate::utils::update_priority::{self, FrameScheduler, MAX_CLIENT_PRIORITY_NODES};
+use crate::utils::simulation_clock::SimulationModeStatus;
+use crate::config::SimulationSettings;
+use crate::models::spatial_anchor::{self, DEFAULT_ROOM};
+use crate::services::audit_log::AuditRecord;
 
 // Constants for throttling debug logs
 const DEBUG_LOG_SAMPLE_RATE: usize = 10; // Only log 1 in 10 updates
@@ -19,6 +33,10 @@ const DEFAULT_POSITION_DEADBAND: f32 = 0.01; // 1cm deadband
 const DEFAULT_VELOCITY_DEADBAND: f32 = 0.005; // 5mm/s deadband
 // Default values for dynamic update rate
 const BATCH_UPDATE_WINDOW_MS: u64 = 200;  // Check motion every 200ms
+// A drag is audited as one entry once its moves have stopped this long
+const MOVE_AUDIT_QUIET: std::time::Duration = std::time::Duration::from_secs(1);
+// An expanded community's members go out in messages of at most this many nodes
+const COMMUNITY_MEMBERS_PER_MESSAGE: usize = 500;
 
 // Note: Now using u32 node IDs throughout the system
 
@@ -31,6 +49,8 @@ pub struct PreReadSocketSettings {
     pub motion_damping: f32,
     pub heartbeat_interval_ms: u64, // Added for heartbeat
     pub heartbeat_timeout_ms: u64,  // Added for heartbeat
+    pub group_settle_ms: u64, // How long a multi-select move stays pinned and locked
+    pub frame_accounting_echo: bool, // Dev-only: clients may ask for their recent frame accounting
 }
 
 // Old ClientManager struct removed - now using ClientManagerActor
@@ -44,9 +64,17 @@ struct SetClientId(usize);
 impl Handler<SetClientId> for SocketFlowServer {
     type Result = ();
 
-    fn handle(&mut self, msg: SetClientId, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: SetClientId, ctx: &mut Self::Context) -> Self::Result {
         self.client_id = Some(msg.0);
         info!("[WebSocket] Client assigned ID: {}", msg.0);
+        // Lets the client name itself to REST calls that act on its socket, like applying a view
+        ctx.text(serde_json::json!({ "type": "registered", "clientId": msg.0 }).to_string());
+
+        // Room may have been chosen before registration completed
+        if self.room != DEFAULT_ROOM {
+            use crate::actors::messages::SetClientRoom;
+            self.client_manager_addr.do_send(SetClientRoom { client_id: msg.0, room: self.room.clone() });
+        }
     }
 }
 
@@ -60,7 +88,7 @@ impl Handler<BroadcastPositionUpdate> for SocketFlowServer {
             let binary_data = binary_protocol::encode_node_data(&msg.0);
             
             // Send to client directly (permessage-deflate handles compression)
-            ctx.binary(binary_data);
+            self.send_positions(ctx, binary_data, FrameTiming::unstepped(), false, None);
             
             // Debug logging - limit to avoid spamming logs
             if self.should_log_update() {
@@ -75,13 +103,50 @@ impl Handler<BroadcastPositionUpdate> for SocketFlowServer {
 pub struct BroadcastPositionUpdate(pub Vec<(u32, BinaryNodeData)>);
 
 // Import the new messages
-use crate::actors::messages::{SendToClientBinary, SendToClientText};
+use crate::actors::messages::{CloseConnection, SendToClientBinary, SendToClientText};
 
 impl Handler<SendToClientBinary> for SocketFlowServer {
     type Result = ();
 
     fn handle(&mut self, msg: SendToClientBinary, ctx: &mut Self::Context) {
-        ctx.binary(msg.0);
+        // Hybrid mode already sends at its low reference rate, so frames are thinned less
+        let buckets = update_priority::bucket_count(self.simulation.source_frame_rate(), self.current_update_rate as f32);
+        if msg.keyframe {
+            // Binary frames carry no header, so the generation goes ahead as text. A
+            // client holding an older graph refetches before trusting the node ids.
+            ctx.text(serde_json::json!({ "type": "keyframe", "generation": msg.generation }).to_string());
+            if self.community_view.as_ref().is_some_and(|view| view.generation != msg.generation) {
+                self.fetch_community_view(ctx);
+            }
+        }
+        // Super-node centroids need every member, and there are few of them to send
+        if msg.keyframe || buckets == 1 || self.community_view.is_some() {
+            self.account_frame(FrameAccount::whole(binary_protocol::node_count(&msg.data)), ctx);
+            self.send_positions(ctx, msg.data, msg.timing, msg.keyframe, Some(&msg.hash));
+            return;
+        }
+
+        // Throttled: priority nodes every frame, everything else in rotating buckets
+        let positions = match binary_protocol::decode_node_data(&msg.data) {
+            Ok(positions) => positions,
+            Err(e) => {
+                warn!("[WebSocket] Could not decode frame for prioritisation, sending whole: {}", e);
+                self.account_frame(FrameAccount::whole(binary_protocol::node_count(&msg.data)), ctx);
+                self.send_positions(ctx, msg.data, msg.timing, msg.keyframe, Some(&msg.hash));
+                return;
+            }
+        };
+        let total = positions.len();
+        let (shared, own, focus, lod) = (&msg.priority, &self.client_priority, &self.focus_network, &msg.lod);
+        let selected = self.frame_scheduler.select(
+            positions,
+            |id| shared.contains(&id) || own.contains(&id) || focus.contains(&id) || lod.contains(&id),
+            buckets,
+        );
+        self.account_frame(FrameAccount::thinned(total, selected.len()), ctx);
+        if !selected.is_empty() {
+            self.send_positions(ctx, binary_protocol::encode_node_data(&selected), msg.timing, false, None);
+        }
     }
 }
 
@@ -89,13 +154,34 @@ impl Handler<SendToClientText> for SocketFlowServer {
     type Result = ();
 
     fn handle(&mut self, msg: SendToClientText, ctx: &mut Self::Context) {
+        if let Some(status) = SimulationModeStatus::from_event(&msg.0) {
+            if self.local_physics == Some(false) && !status.supports_client(false) {
+                warn!("[WebSocket] Client {:?} can't lay out the graph itself; it will see no motion in {:?} mode", self.client_id, status.mode);
+            }
+            self.simulation = status;
+        }
         ctx.text(msg.0);
     }
 }
 
+impl Handler<CloseConnection> for SocketFlowServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseConnection, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Away,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
+}
+
 pub struct SocketFlowServer {
     app_state: Arc<AppState>,
     client_id: Option<usize>,
+    // Role is fixed at connect; viewers get broadcasts but can't move nodes
+    identity: Identity,
+    room: String, // Room the client shares an anchor frame with
     client_manager_addr: actix::Addr<crate::actors::client_manager_actor::ClientManagerActor>,
     last_ping: Option<u64>,
     update_counter: usize, // Counter for throttling debug logs
@@ -127,10 +213,39 @@ pub struct SocketFlowServer {
     nodes_in_motion: usize,    // Counter for nodes currently in motion
     total_node_count: usize,   // Total node count for percentage calculation
     last_motion_check: Instant, // Last time we checked motion percentage,
+    // Per-node prioritisation of throttled frames
+    frame_scheduler: FrameScheduler,
+    client_priority: HashSet<u32>, // Nodes this client subscribed to as priority
+    focus_node: Option<u32>,
+    focus_network: HashSet<u32>,   // Gaze focus node and its neighbours
+    group_settle: std::time::Duration,
+    resync_throttle: ResyncThrottle,
+    frame_accounting: FrameAccounting,
+    frame_accounting_echo: bool,
+    // Simulation mode as last announced to this client
+    simulation: SimulationModeStatus,
+    // Whether the client said it can run its own layout; None until it says
+    local_physics: Option<bool>,
+    // Position frames get a timing header; off until the client asks, so legacy frames are unchanged
+    interpolation_hints: bool,
+    // 2-D clients get projected frames; None sends the full 3-D format
+    projection: Option<Projection>,
+    // Position frames carry a hash of their node data, and are logged with the client manager
+    frame_hashes: bool,
+    frames_hashed: u64,
+    // Communities collapse into super-nodes in this client's frames, bar the ones it expanded
+    community_aggregation: bool,
+    community_view: Option<Arc<GraphView>>,
+    // Where each member was last sent, for centroids of communities a delta only partly covers
+    community_positions: MemberPositions,
+    expanded_communities: HashSet<u32>,
+    // Latest position of each node this client moved since the last audit entry
+    pending_moves: HashMap<u32, Vec3Data>,
+    move_audit_timer: Option<SpawnHandle>,
 }
 
 impl SocketFlowServer {
-    pub fn new(app_state: Arc<AppState>, pre_read_settings: PreReadSocketSettings, client_manager_addr: actix::Addr<crate::actors::client_manager_actor::ClientManagerActor>) -> Self {
+    pub fn new(app_state: Arc<AppState>, pre_read_settings: PreReadSocketSettings, client_manager_addr: actix::Addr<crate::actors::client_manager_actor::ClientManagerActor>, identity: Identity) -> Self {
         let min_update_rate = pre_read_settings.min_update_rate;
         let max_update_rate = pre_read_settings.max_update_rate;
         let motion_threshold = pre_read_settings.motion_threshold;
@@ -148,6 +263,8 @@ impl SocketFlowServer {
         Self {
             app_state,
             client_id: None,
+            identity,
+            room: DEFAULT_ROOM.to_string(),
             client_manager_addr,
             last_ping: None,
             update_counter: 0,
@@ -173,8 +290,637 @@ impl SocketFlowServer {
             // heartbeat_timeout_ms, // Unused
             nodes_in_motion: 0,
             total_node_count: 0,
-            last_motion_check: Instant::now()
+            last_motion_check: Instant::now(),
+            frame_scheduler: FrameScheduler::new(),
+            client_priority: HashSet::new(),
+            focus_node: None,
+            focus_network: HashSet::new(),
+            group_settle: std::time::Duration::from_millis(pre_read_settings.group_settle_ms),
+            resync_throttle: ResyncThrottle::default(),
+            frame_accounting: FrameAccounting::new(),
+            frame_accounting_echo: pre_read_settings.frame_accounting_echo,
+            simulation: SimulationModeStatus::from(&SimulationSettings::default()),
+            local_physics: None,
+            interpolation_hints: false,
+            projection: None,
+            frame_hashes: false,
+            frames_hashed: 0,
+            community_aggregation: false,
+            community_view: None,
+            community_positions: MemberPositions::default(),
+            expanded_communities: HashSet::new(),
+            pending_moves: HashMap::new(),
+            move_audit_timer: None,
+        }
+    }
+
+    fn principal(&self) -> &str {
+        self.identity.pubkey.as_deref().unwrap_or("anonymous")
+    }
+
+    // Holds moves back until the drag goes quiet, so a drag is one audit entry rather than one per frame
+    fn note_moves(&mut self, moves: impl IntoIterator<Item = (u32, Vec3Data)>, ctx: &mut <Self as Actor>::Context) {
+        self.pending_moves.extend(moves);
+        if let Some(timer) = self.move_audit_timer.take() {
+            ctx.cancel_future(timer);
         }
+        self.move_audit_timer = Some(ctx.run_later(MOVE_AUDIT_QUIET, |act, _ctx| {
+            act.move_audit_timer = None;
+            act.flush_move_audit();
+        }));
+    }
+
+    fn flush_move_audit(&mut self) {
+        if self.pending_moves.is_empty() {
+            return;
+        }
+        let mut moves: Vec<(u32, Vec3Data)> = self.pending_moves.drain().collect();
+        moves.sort_by_key(|(node_id, _)| *node_id);
+        let positions: serde_json::Map<String, serde_json::Value> = moves.iter()
+            .map(|(node_id, position)| (node_id.to_string(), serde_json::json!(position)))
+            .collect();
+        let record = AuditRecord::new("move_nodes")
+            .nodes(moves.iter().map(|(node_id, _)| *node_id))
+            .change(None, Some(serde_json::Value::Object(positions)));
+        self.app_state.audit_log.record(self.principal(), record);
+    }
+
+    // Client reports the anchor pose it resolved locally; reply with the room's shared frame
+    fn handle_anchor_resolved(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let room = match msg.get("room").and_then(|r| r.as_str()).map(spatial_anchor::validate_room) {
+            Some(Ok(room)) => room,
+            Some(Err(e)) => return self.send_error(ctx, &e),
+            None => self.room.clone(),
+        };
+
+        let resolved = match msg.get("transform") {
+            Some(value) => match serde_json::from_value::<Vec<f32>>(value.clone())
+                .map_err(|e| format!("Invalid transform: {}", e))
+                .and_then(|values| spatial_anchor::validate_transform(&values))
+            {
+                Ok(transform) => Some(transform),
+                Err(e) => return self.send_error(ctx, &e),
+            },
+            None => None,
+        };
+
+        if room != self.room {
+            if let Err(e) = self.app_state.room_access.check_join(&room, &self.identity) {
+                warn!("[WebSocket] {} refused entry to room {}", self.identity.pubkey.as_deref().unwrap_or("anonymous"), room);
+                return ctx.text(serde_json::json!({
+                    "type": "error",
+                    "code": "room_forbidden",
+                    "room": room,
+                    "message": e.to_string(),
+                }).to_string());
+            }
+            self.room = room.clone();
+            if let Some(client_id) = self.client_id {
+                use crate::actors::messages::SetClientRoom;
+                self.client_manager_addr.do_send(SetClientRoom { client_id, room: room.clone() });
+            }
+        }
+
+        let anchor_service = self.app_state.anchor_service.clone();
+        let fut = async move { anchor_service.frame_for_room(&room).await };
+        ctx.spawn(fut.into_actor(self).map(move |frame, _act, ctx| {
+            let frame = match resolved {
+                Some(transform) => frame.with_resolved_anchor(&transform),
+                None => frame,
+            };
+            let response = serde_json::json!({
+                "type": "anchor_frame",
+                "frame": frame,
+            });
+            ctx.text(response.to_string());
+        }));
+    }
+
+    // Keep the gazed-at node and its neighbours in every throttled frame
+    fn update_focus(&mut self, node_id: u32, ctx: &mut <Self as Actor>::Context) {
+        if self.focus_node == Some(node_id) {
+            return;
+        }
+        self.focus_node = Some(node_id);
+        use crate::actors::messages::GetEgoNetwork;
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let fut = async move { graph_addr.send(GetEgoNetwork { node_id }).await };
+        ctx.spawn(fut.into_actor(self).map(move |result, act, _ctx| {
+            // Focus may have moved on while we waited
+            if act.focus_node != Some(node_id) {
+                return;
+            }
+            match result {
+                Ok(Ok(network)) => act.focus_network = network,
+                Ok(Err(e)) => {
+                    debug!("[WebSocket] No ego network for focus node {}: {}", node_id, e);
+                    act.focus_network = HashSet::from([node_id]);
+                }
+                Err(e) => warn!("[WebSocket] Graph service unavailable for focus lookup: {}", e),
+            }
+        }));
+    }
+
+    // Tells the client the simulation mode, as part of its connect handshake
+    fn announce_simulation_mode(&mut self, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::GetSimulationSettings;
+        let fut = self.app_state.graph_service_addr.send(GetSimulationSettings);
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| match result {
+            Ok(Ok(settings)) => {
+                act.simulation = SimulationModeStatus::from(&settings);
+                ctx.text(act.simulation.to_event());
+            }
+            Ok(Err(e)) => warn!("[WebSocket] Failed to get simulation mode: {}", e),
+            Err(e) => warn!("[WebSocket] Graph service unavailable for simulation mode: {}", e),
+        }));
+    }
+
+    // {"type":"capabilities","localPhysics":bool} says whether the client can lay the graph
+    // out itself; the reply says whether it can follow the current simulation mode. An
+    // optional "interpolationHints":true puts a timing header on every position frame,
+    // "frameHashes":true adds a CRC-32 of the node data after it, and "projection" ("xz",
+    // "xy" or a 3x2 matrix) switches frames to the projected 2-D layout, and "aggregation":
+    // "community" to community super-nodes. The full set of options is in
+    // `utils::capabilities`, published at /api/capabilities.
+    fn handle_capabilities(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let handshake = match Handshake::parse(msg) {
+            Ok(handshake) => handshake,
+            Err(e) => return self.send_error(ctx, &e),
+        };
+        let local_physics = handshake.local_physics;
+        self.local_physics = Some(local_physics);
+        self.interpolation_hints = handshake.interpolation_hints;
+        self.frame_hashes = handshake.frame_hashes;
+        self.projection = handshake.projection;
+        self.set_community_aggregation(handshake.community_aggregation, ctx);
+        let supported = self.simulation.supports_client(local_physics);
+        if !supported {
+            warn!("[WebSocket] Client without local physics connected in {:?} mode", self.simulation.mode);
+        }
+        let response = serde_json::json!({
+            "type": "capabilities_ack",
+            "protocolVersion": handshake.protocol_version,
+            "localPhysics": local_physics,
+            "interpolationHints": self.interpolation_hints,
+            "frameHashes": self.frame_hashes,
+            "projection": self.projection,
+            "aggregation": if self.community_aggregation { "community" } else { "none" },
+            "supported": supported,
+            "simulation": self.simulation,
+        });
+        ctx.text(response.to_string());
+    }
+
+    // {"type":"subscribe","priorityNodes":[..]} replaces the client's priority set
+    fn handle_subscribe(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let nodes = match msg.get("priorityNodes") {
+            Some(value) => match serde_json::from_value::<Vec<u32>>(value.clone()) {
+                Ok(nodes) => nodes,
+                Err(e) => return self.send_error(ctx, &format!("Invalid priorityNodes: {}", e)),
+            },
+            None => return self.send_error(ctx, "subscribe needs priorityNodes"),
+        };
+        if nodes.len() > MAX_CLIENT_PRIORITY_NODES {
+            return self.send_error(ctx, &format!(
+                "At most {} priority nodes per client", MAX_CLIENT_PRIORITY_NODES));
+        }
+        self.client_priority = nodes.into_iter().collect();
+        let response = serde_json::json!({
+            "type": "subscribed",
+            "priorityNodes": self.client_priority.len(),
+        });
+        ctx.text(response.to_string());
+    }
+
+    // Moves a box/lasso selection as one rigid body; the move itself goes out in the
+    // regular binary broadcast
+    fn handle_transform_nodes(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::{EditAttribution, TransformNodes};
+        use crate::models::group_transform::GroupTransformRequest;
+        let request = match serde_json::from_value::<GroupTransformRequest>(msg.clone()) {
+            Ok(request) => request,
+            Err(e) => return self.send_error(ctx, &format!("Invalid transformNodes: {}", e)),
+        };
+        let edited_by = self.client_id.map(|client_id| EditAttribution {
+            client_id,
+            room: self.room.clone(),
+        });
+        let record = AuditRecord::new("transform_nodes")
+            .nodes(request.node_ids.iter().copied())
+            .change(None, Some(serde_json::json!({
+                "translation": request.translation,
+                "rotation": request.rotation,
+                "pivot": request.pivot,
+            })));
+        let transform = TransformNodes {
+            node_ids: request.node_ids,
+            translation: glam::Vec3::from(request.translation),
+            rotation: request.rotation.map(glam::Quat::from_array),
+            pivot: glam::Vec3::from(request.pivot),
+            settle: self.group_settle,
+            edited_by,
+        };
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let fut = async move { graph_addr.send(transform).await };
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            match result {
+                Ok(Ok(moved)) => {
+                    act.app_state.audit_log.record(act.principal(), record);
+                    let response = serde_json::json!({
+                        "type": "transform_result",
+                        "moved": moved,
+                    });
+                    ctx.text(response.to_string());
+                }
+                Ok(Err(e)) => act.send_error(ctx, &e),
+                Err(e) => act.send_error(ctx, &format!("Graph service unavailable: {}", e)),
+            }
+        }));
+    }
+
+    // Hides or shows edge types for this client only. Edges of types that come back
+    // into view are resent, since the client dropped them while they were hidden.
+    fn handle_edge_type_visibility(&self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::{GetGraphSnapshot, SetEdgeTypeVisibility};
+        let Some(client_id) = self.client_id else {
+            return self.send_error(ctx, "Not registered yet");
+        };
+        let types = match msg.get("types") {
+            Some(value) => match serde_json::from_value::<HashMap<String, bool>>(value.clone()) {
+                Ok(types) => types,
+                Err(e) => return self.send_error(ctx, &format!("Invalid types: {}", e)),
+            },
+            None => return self.send_error(ctx, "setEdgeTypeVisibility needs types"),
+        };
+        let client_manager = self.client_manager_addr.clone();
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let fut = async move {
+            let shown = client_manager.send(SetEdgeTypeVisibility { client_id, types }).await
+                .map_err(|e| format!("Client manager unavailable: {}", e))??;
+            let mut edges = Vec::new();
+            if !shown.is_empty() {
+                let graph = graph_addr.send(GetGraphSnapshot).await
+                    .map_err(|e| format!("Graph service unavailable: {}", e))??;
+                let graph = crate::utils::aging::snapshot_without_archived(graph);
+                edges = graph.edges.iter()
+                    .filter(|e| shown.iter().any(|t| t == e.type_name()))
+                    .cloned()
+                    .collect();
+            }
+            Ok::<_, String>((shown, edges))
+        };
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            match result {
+                Ok((shown, edges)) => {
+                    let response = serde_json::json!({
+                        "type": "edge_visibility",
+                        "shownTypes": shown,
+                        "edges": edges,
+                    });
+                    ctx.text(response.to_string());
+                }
+                Err(e) => act.send_error(ctx, &e),
+            }
+        }));
+    }
+
+    // Watches particular nodes, or with a `session` picks up the watch a dropped
+    // connection left. Either way the reply is `watching`, carrying the session to resume by.
+    fn handle_watch_nodes(&self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::WatchNodes;
+        use crate::utils::node_watch::WatchRequest;
+        let Some(subscriber) = self.client_id else {
+            return self.send_error(ctx, "Not registered yet");
+        };
+        let request = match serde_json::from_value::<WatchRequest>(msg.clone()) {
+            Ok(request) => request,
+            Err(e) => return self.send_error(ctx, &format!("Invalid watchNodes: {}", e)),
+        };
+        let watch = WatchNodes { subscriber, session: request.session, ids: request.ids, events: request.events };
+        let fut = self.client_manager_addr.send(watch);
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            match result {
+                Ok(Ok(info)) => ctx.text(info.to_ws_message()),
+                Ok(Err(e)) => ctx.text(e.to_ws_message()),
+                Err(e) => act.send_error(ctx, &format!("Client manager unavailable: {}", e)),
+            }
+        }));
+    }
+
+    // Resends everything the client holds, for a client that thinks its copy is corrupt.
+    // Throttled per client; the frames go out in the order resync::frames defines.
+    fn handle_resync(&mut self, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::{GetGenerations, GetGraphSnapshot, GetHiddenEdgeTypes, GetPins, GetSettings};
+        let Some(client_id) = self.client_id else {
+            return self.send_error(ctx, "Not registered yet");
+        };
+        if let Err(retry_after) = self.resync_throttle.check(Instant::now()) {
+            debug!("[WebSocket] Throttled resync for client {}", client_id);
+            return ctx.text(resync::throttled_error(retry_after));
+        }
+        info!("[WebSocket] Client {} requested a full resync", client_id);
+
+        let app_state = self.app_state.clone();
+        let client_manager = self.client_manager_addr.clone();
+        let room = self.room.clone();
+        let priority_nodes: Vec<u32> = self.client_priority.iter().copied().collect();
+        let fut = async move {
+            let settings = app_state.settings_addr.send(GetSettings).await
+                .map_err(|e| format!("Settings service unavailable: {}", e))??;
+            let settings = serde_json::to_value(crate::models::UISettings::from(&settings))
+                .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+            let graph_addr = &app_state.graph_service_addr;
+            // Generation first, so the data is never older than what the marker claims
+            let generations = graph_addr.send(GetGenerations).await
+                .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            let graph = graph_addr.send(GetGraphSnapshot).await
+                .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            let graph = crate::utils::aging::snapshot_without_archived(graph);
+            let pins = graph_addr.send(GetPins).await
+                .map_err(|e| format!("Graph service unavailable: {}", e))??;
+            let hidden = client_manager.send(GetHiddenEdgeTypes { client_id }).await
+                .map_err(|e| format!("Client manager unavailable: {}", e))?;
+            let annotations = app_state.annotation_service
+                .list_many(graph.nodes.iter().map(|n| n.metadata_id.as_str()))
+                .await;
+            Ok::<_, String>(ResyncState {
+                settings,
+                generation: generations.generation,
+                nodes: graph.nodes.iter().map(|n| (n.id, n.data)).collect(),
+                edges: graph.edges.iter().filter(|e| !hidden.contains(e.type_name())).cloned().collect(),
+                annotations,
+                pins,
+                room,
+                priority_nodes,
+                hidden_edge_types: hidden.into_iter().collect(),
+            })
+        };
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            match result {
+                Ok(state) => {
+                    // The keyframe replaces whatever the client had, so deltas start over
+                    act.last_sent_positions.clear();
+                    act.last_sent_velocities.clear();
+                    for frame in resync::frames(&state) {
+                        match frame {
+                            ResyncFrame::Text(text) => ctx.text(text),
+                            ResyncFrame::Binary(data) => act.send_positions(ctx, data, FrameTiming::unstepped(), true, None),
+                        }
+                    }
+                }
+                Err(e) => act.send_error(ctx, &e),
+            }
+        }));
+    }
+
+    // Every frame is accounted for; about once a second the totals go to the client as a
+    // companion message and into the client manager's metrics. Nothing on this path
+    // filters by epsilon or LOD yet, so only backpressure shows up as skipped.
+    fn account_frame(&mut self, account: FrameAccount, ctx: &mut <Self as Actor>::Context) {
+        let Some(totals) = self.frame_accounting.record(account) else {
+            return;
+        };
+        let mut message = serde_json::json!(totals);
+        message["type"] = "frame_accounting".into();
+        ctx.text(message.to_string());
+        if let Some(client_id) = self.client_id {
+            use crate::actors::messages::RecordFrameAccounting;
+            self.client_manager_addr.do_send(RecordFrameAccounting { client_id, totals });
+        }
+    }
+
+    // Dev-only: {"type":"frameAccountingEcho","frames":N} returns the last N frames' accounting
+    fn handle_frame_accounting_echo(&self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        if !self.frame_accounting_echo {
+            return self.send_error(ctx, "Frame accounting echo is only available in debug mode");
+        }
+        let count = msg.get("frames").and_then(|f| f.as_u64()).unwrap_or(MAX_ECHO_FRAMES as u64) as usize;
+        let frames = self.frame_accounting.last(count.min(MAX_ECHO_FRAMES));
+        ctx.text(serde_json::json!({
+            "type": "frame_accounting_echo",
+            "frames": frames,
+        }).to_string());
+    }
+
+    // {"type":"setProjection","projection":"xz" | "xy" | matrix | null} changes the projection
+    // at runtime; null goes back to 3-D frames
+    fn handle_set_projection(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let projection = match msg.get("projection").filter(|p| !p.is_null()).map(Projection::parse).transpose() {
+            Ok(projection) => projection,
+            Err(e) => return self.send_error(ctx, &e),
+        };
+        self.projection = projection;
+        ctx.text(serde_json::json!({ "type": "projection_ack", "projection": self.projection }).to_string());
+    }
+
+    // Switches community aggregation on or off; the view is fetched anew when it comes on
+    fn set_community_aggregation(&mut self, enabled: bool, ctx: &mut <Self as Actor>::Context) {
+        self.community_aggregation = enabled;
+        if enabled {
+            self.fetch_community_view(ctx);
+        } else {
+            self.community_view = None;
+            self.community_positions = MemberPositions::default();
+            self.expanded_communities.clear();
+        }
+    }
+
+    // Sends the client the current generation's communities and aggregated edges. Ids
+    // from an older view mean nothing in the new one, so expansions start over. Member
+    // positions start from the current layout and follow the frames from there.
+    fn fetch_community_view(&mut self, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::{GetCommunityView, GetGraphSnapshot};
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let fut = async move { (graph_addr.send(GetCommunityView).await, graph_addr.send(GetGraphSnapshot).await) };
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            let (view, graph) = match result {
+                (Ok(view), Ok(Ok(graph))) => (view, graph),
+                (_, Ok(Err(e))) => return act.send_error(ctx, &e),
+                (Err(e), _) | (_, Err(e)) => return act.send_error(ctx, &format!("Graph service unavailable: {}", e)),
+            };
+            if !act.community_aggregation || act.community_view.as_ref().is_some_and(|v| v.generation >= view.generation) {
+                return;
+            }
+            act.expanded_communities.clear();
+            let mut message = serde_json::json!(*view);
+            message["type"] = "community_view".into();
+            ctx.text(message.to_string());
+            act.community_positions = MemberPositions::seed(&graph);
+            act.community_view = Some(view);
+        }));
+    }
+
+    // {"type":"expand_community","id":N} streams the members of super-node N in
+    // "community_members" messages, then sends them in frames in place of it
+    fn handle_expand_community(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::GetGraphSnapshot;
+        let Some(view) = self.community_view.clone() else {
+            return self.send_error(ctx, "expand_community needs aggregation: community in capabilities");
+        };
+        let Some(id) = msg.get("id").and_then(|v| v.as_u64()).and_then(|v| u32::try_from(v).ok()) else {
+            return self.send_error(ctx, "expand_community needs id");
+        };
+        if view.community(id).is_none() {
+            return self.send_error(ctx, &format!("No community {}", id));
+        }
+        let fut = self.app_state.graph_service_addr.send(GetGraphSnapshot);
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| {
+            let graph = match result {
+                Ok(Ok(graph)) => graph,
+                Ok(Err(e)) => return act.send_error(ctx, &e),
+                Err(e) => return act.send_error(ctx, &format!("Graph service unavailable: {}", e)),
+            };
+            // The view moved on while the graph was fetched; its ids may not match any more
+            if !act.community_view.as_ref().is_some_and(|current| Arc::ptr_eq(current, &view)) || graph.generation != view.generation {
+                return act.send_error(ctx, &format!("Community {} changed, expand it again", id));
+            }
+            let Some((nodes, edges)) = view.expansion(&graph, id) else { return };
+            let per = COMMUNITY_MEMBERS_PER_MESSAGE;
+            let chunks = nodes.len().max(edges.len()).div_ceil(per).max(1);
+            for chunk in 0..chunks {
+                let range = |len: usize| (chunk * per).min(len)..((chunk + 1) * per).min(len);
+                ctx.text(serde_json::json!({
+                    "type": "community_members",
+                    "id": id,
+                    "generation": view.generation,
+                    "chunk": chunk,
+                    "chunks": chunks,
+                    "nodes": &nodes[range(nodes.len())],
+                    "edges": &edges[range(edges.len())],
+                }).to_string());
+            }
+            // Only once the client knows the members do they turn up in frames
+            act.expanded_communities.insert(id);
+        }));
+    }
+
+    // {"type":"collapse_community","id":N} folds an expanded community back into its super-node
+    fn handle_collapse_community(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let Some(id) = msg.get("id").and_then(|v| v.as_u64()).and_then(|v| u32::try_from(v).ok()) else {
+            return self.send_error(ctx, "collapse_community needs id");
+        };
+        let collapsed = self.expanded_communities.remove(&id);
+        ctx.text(serde_json::json!({ "type": "community_collapsed", "id": id, "collapsed": collapsed }).to_string());
+    }
+
+    // {"type":"select","nodeId":N} sets the client's current selection and feeds co-viewed edges
+    fn handle_select(&self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let Some(node_id) = msg.get("nodeId").and_then(|v| v.as_u64()).and_then(|v| u32::try_from(v).ok()) else {
+            return self.send_error(ctx, "select needs nodeId");
+        };
+        let Some(client_id) = self.client_id else {
+            return;
+        };
+        use crate::actors::messages::{GetGraphSnapshot, RecordSelection};
+        // Resolved now, as a rebuild before the session ends can give the id to another node
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let client_manager_addr = self.client_manager_addr.clone();
+        let fut = async move {
+            let metadata_id = match graph_addr.send(GetGraphSnapshot).await {
+                Ok(Ok(graph)) => graph.nodes.iter().find(|n| n.id == node_id).map(|n| n.metadata_id.clone()),
+                _ => None,
+            };
+            client_manager_addr.do_send(RecordSelection { client_id, node_id, metadata_id });
+        };
+        ctx.spawn(fut.into_actor(self));
+    }
+
+    // Every binary position frame goes through here, so an opted-in client never gets one
+    // bare, a projecting client never gets one in 3-D, and an aggregating one never gets
+    // the members of a community it hasn't expanded
+    // `shared` is the broadcast's hash, for frames passed on exactly as broadcast
+    fn send_positions(&mut self, ctx: &mut <Self as Actor>::Context, data: Vec<u8>, timing: FrameTiming, keyframe: bool, shared: Option<&SharedHash>) {
+        let (data, shared) = match &self.community_view {
+            Some(view) => match binary_protocol::decode_node_data(&data) {
+                Ok(positions) => (binary_protocol::encode_node_data(&view.frame(&positions, &self.expanded_communities, &mut self.community_positions)), None),
+                Err(e) => return warn!("[WebSocket] Could not aggregate frame, dropping it: {}", e),
+            },
+            None => (data, shared),
+        };
+        let nodes = binary_protocol::node_count(&data);
+        let (data, shared) = match &self.projection {
+            Some(projection) => match binary_protocol::project_frame(&data, projection) {
+                Ok(projected) => (projected, None),
+                Err(e) => return warn!("[WebSocket] Could not project frame, dropping it: {}", e),
+            },
+            None => (data, shared),
+        };
+        let hash = self.frame_hashes.then(|| match shared {
+            Some(shared) => shared.get(&data),
+            None => frame_hash::hash(&data),
+        });
+        let header = self.interpolation_hints.then_some(&timing);
+        let frame = if header.is_none() && hash.is_none() {
+            data
+        } else {
+            binary_protocol::with_frame_headers(header, hash, &data)
+        };
+        if let (Some(hash), Some(client_id)) = (hash, self.client_id) {
+            use crate::actors::messages::RecordSentFrame;
+            let sent = SentFrame { seq: self.frames_hashed, hash, bytes: frame.len(), nodes, keyframe, server_time_us: timing.server_time_us };
+            self.client_manager_addr.do_send(RecordSentFrame { client_id, frame: sent });
+            self.frames_hashed += 1;
+        }
+        ctx.binary(frame);
+    }
+
+    // {"type":"time_sync","clientTime":ms} is echoed with when it arrived and when the reply
+    // left, in server microseconds, for the client to work out clock offset and latency
+    fn handle_time_sync(&self, msg: &serde_json::Value, received: Instant, ctx: &mut <Self as Actor>::Context) {
+        let Some(client_time) = msg.get("clientTime").and_then(|v| v.as_f64()) else {
+            return self.send_error(ctx, "time_sync needs clientTime");
+        };
+        let reply = TimeSyncReply {
+            client_time,
+            server_receive: time_sync::server_time_us(received),
+            server_send: time_sync::server_time_us(Instant::now()),
+        };
+        ctx.text(reply.to_event());
+    }
+
+    fn send_error(&self, ctx: &mut <Self as Actor>::Context, message: &str) {
+        let error_msg = serde_json::json!({
+            "type": "error",
+            "message": message
+        });
+        ctx.text(error_msg.to_string());
+    }
+
+    fn send_forbidden(&self, ctx: &mut <Self as Actor>::Context, required: Role) {
+        ctx.text(auth::forbidden_ws_message(self.identity.role, required));
+    }
+
+    // Undo/redo the room's most recent manual move; the position itself goes out in the
+    // regular binary broadcast
+    fn handle_position_history(&self, undo: bool, ctx: &mut <Self as Actor>::Context) {
+        use crate::actors::messages::{RedoPositionEdit, UndoPositionEdit};
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let room = self.room.clone();
+        let fut = async move {
+            if undo {
+                graph_addr.send(UndoPositionEdit { room }).await
+            } else {
+                graph_addr.send(RedoPositionEdit { room }).await
+            }
+        };
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| {
+            match result {
+                Ok(Ok(step)) => {
+                    if let Some(record) = AuditRecord::from_position_step(undo, &step) {
+                        act.app_state.audit_log.record(act.principal(), record);
+                    }
+                    let kind = if undo { "undo_result" } else { "redo_result" };
+                    let response = serde_json::json!({
+                        "type": kind,
+                        "applied": step.applied,
+                        "skipped": step.skipped,
+                    });
+                    ctx.text(response.to_string());
+                }
+                Ok(Err(e)) => act.send_error(ctx, &e),
+                Err(e) => act.send_error(ctx, &format!("Graph service unavailable: {}", e)),
+            }
+        }));
     }
 
     fn handle_ping(&mut self, msg: PingMessage) -> PongMessage {
@@ -305,9 +1051,10 @@ impl Actor for SocketFlowServer {
         
         // Use actix's runtime to avoid blocking in the actor's started method
         let cm_addr = self.client_manager_addr.clone();
+        let identity = self.identity.clone();
         actix::spawn(async move {
             use crate::actors::messages::RegisterClient;
-            match cm_addr.send(RegisterClient { addr: addr_clone }).await {
+            match cm_addr.send(RegisterClient { addr: addr_clone, identity }).await {
                 Ok(Ok(id)) => {
                     // Send a message back to the actor with its client ID
                     addr.do_send(SetClientId(id));
@@ -321,7 +1068,7 @@ impl Actor for SocketFlowServer {
             }
         });
     
-        info!("[WebSocket] New client connected");
+        info!("[WebSocket] New client connected as {}", self.identity.role.as_str());
         self.last_activity = std::time::Instant::now();
         
         // We'll retrieve client ID asynchronously via message
@@ -342,7 +1089,8 @@ impl Actor for SocketFlowServer {
         // Send simple connection established message
         let response = serde_json::json!({
             "type": "connection_established",
-            "timestamp": chrono::Utc::now().timestamp_millis()
+            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "role": self.identity.role
         });
 
         if let Ok(msg_str) = serde_json::to_string(&response) {
@@ -357,9 +1105,12 @@ impl Actor for SocketFlowServer {
         });
         ctx.text(serde_json::to_string(&loading_msg).unwrap_or_default());
         self.last_activity = std::time::Instant::now();
+
+        self.announce_simulation_mode(ctx);
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.flush_move_audit();
         // Unregister this client when it disconnects
         if let Some(client_id) = self.client_id {
             let cm_addr = self.client_manager_addr.clone();
@@ -380,10 +1131,19 @@ async fn fetch_nodes(
     app_state: Arc<AppState>,
     settings_addr: actix::Addr<crate::actors::settings_actor::SettingsActor>
 ) -> Option<(Vec<(u32, BinaryNodeData)>, bool)> {
+    // Nothing is streamed while the layout warms up; the keyframe after it brings the client in
+    use crate::actors::messages::{GetGraphSnapshot, GetWarmupStatus};
+    if let Ok(Ok(status)) = app_state.graph_service_addr.send(GetWarmupStatus).await {
+        if !status.ready {
+            debug!("[WebSocket] Layout still warming up ({:.0}%), holding initial data", status.progress * 100.0);
+            return None;
+        }
+    }
+
     // Fetch raw nodes asynchronously from GraphServiceActor
-    use crate::actors::messages::GetGraphData;
-    let graph_data = match app_state.graph_service_addr.send(GetGraphData).await {
-        Ok(Ok(data)) => data,
+    let graph_data = match app_state.graph_service_addr.send(GetGraphSnapshot).await {
+        // Archived nodes are never streamed
+        Ok(Ok(snapshot)) => crate::utils::aging::snapshot_without_archived(snapshot),
         Ok(Err(e)) => {
             error!("[WebSocket] Failed to get graph data: {}", e);
             return None;
@@ -455,8 +1215,9 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                 self.last_activity = std::time::Instant::now();
             }
             Ok(ws::Message::Text(text)) => {
+                let received = Instant::now();
                 info!("Received text message: {}", text);
-                self.last_activity = std::time::Instant::now();
+                self.last_activity = received;
                 match serde_json::from_str::<serde_json::Value>(&text) {
                     Ok(msg) => {
                         match msg.get("type").and_then(|t| t.as_str()) {
@@ -503,7 +1264,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                                                     position.clone(),
                                                     velocity.clone()
                                                 ) {
-                                                    filtered_nodes.push((*node_id, node_data.clone()));
+                                                    filtered_nodes.push((*node_id, *node_data));
                                                 }
                                                 
                                                 if detailed_debug && filtered_nodes.len() <= 5 {
@@ -592,7 +1353,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                                                         binary_data.len(), filtered_nodes.len(), elapsed, avg_bytes_per_update);
                                                 }
                                                 
-                                                ctx.binary(binary_data);
+                                                act.send_positions(ctx, binary_data, FrameTiming::unstepped(), false, None);
                                             } else if detailed_debug && should_log {
                                                 // Log keepalive
                                                 debug!("[WebSocket] Sending keepalive (no position changes)");
@@ -610,6 +1371,60 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                                     ctx.text(msg_str);
                                 }
                             }
+                            Some("pose_update") => {
+                                match serde_json::from_value::<PoseUpdate>(msg.clone()) {
+                                    Ok(update) if update.head.is_finite()
+                                        && update.cursor.is_none_or(|c| c.is_finite()) =>
+                                    {
+                                        // Rate limiting and fan-out happen in the client manager
+                                        if let Some(client_id) = self.client_id {
+                                            use crate::actors::messages::RelayPose;
+                                            self.client_manager_addr.do_send(RelayPose { client_id, update });
+                                        }
+                                    }
+                                    Ok(_) => self.send_error(ctx, "Pose contains non-finite values"),
+                                    Err(e) => self.send_error(ctx, &format!("Invalid pose_update: {}", e)),
+                                }
+                            }
+                            Some("gaze_focus") => {
+                                match serde_json::from_value::<GazeFocus>(msg.clone()) {
+                                    Ok(focus) if focus.viewpoint.is_none_or(|v| v.iter().all(|c| c.is_finite())) => {
+                                        use crate::actors::messages::RecordGazeFocus;
+                                        self.update_focus(focus.node_id, ctx);
+                                        self.app_state.graph_service_addr.do_send(RecordGazeFocus {
+                                            node_id: focus.node_id,
+                                            dwell_ms: focus.dwell_ms,
+                                            viewpoint: focus.viewpoint.map(glam::Vec3::from),
+                                        });
+                                    }
+                                    Ok(_) => self.send_error(ctx, "Viewpoint contains non-finite values"),
+                                    Err(e) => self.send_error(ctx, &format!("Invalid gaze_focus: {}", e)),
+                                }
+                            }
+                            Some("subscribe") => {
+                                self.handle_subscribe(&msg, ctx);
+                            }
+                            Some("capabilities") => self.handle_capabilities(&msg, ctx),
+                            Some("time_sync") => self.handle_time_sync(&msg, received, ctx),
+                            Some("setProjection") => self.handle_set_projection(&msg, ctx),
+                            Some("expand_community") => self.handle_expand_community(&msg, ctx),
+                            Some("collapse_community") => self.handle_collapse_community(&msg, ctx),
+                            Some("select") => self.handle_select(&msg, ctx),
+                            Some("anchorResolved") => {
+                                self.handle_anchor_resolved(&msg, ctx);
+                            }
+                            Some("setEdgeTypeVisibility") => {
+                                self.handle_edge_type_visibility(&msg, ctx);
+                            }
+                            Some("watchNodes") => self.handle_watch_nodes(&msg, ctx),
+                            Some("requestResync") => self.handle_resync(ctx),
+                            Some("frameAccountingEcho") => self.handle_frame_accounting_echo(&msg, ctx),
+                            Some("undo") | Some("redo") | Some("transformNodes") if !self.identity.role.can_edit() => {
+                                self.send_forbidden(ctx, Role::Editor);
+                            }
+                            Some("undo") => self.handle_position_history(true, ctx),
+                            Some("redo") => self.handle_position_history(false, ctx),
+                            Some("transformNodes") => self.handle_transform_nodes(&msg, ctx),
                             Some("enableRandomization") => {
                                 if let Ok(enable_msg) = serde_json::from_value::<serde_json::Value>(msg.clone()) {
                                     let enabled = enable_msg.get("enabled").and_then(|e| e.as_bool()).unwrap_or(false);
@@ -642,6 +1457,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                     }
                 }
             }
+            Ok(ws::Message::Binary(_)) if !self.identity.role.can_edit() => {
+                // Binary frames from clients are node moves
+                self.last_activity = std::time::Instant::now();
+                self.send_forbidden(ctx, Role::Editor);
+            }
             Ok(ws::Message::Binary(data)) => {
                 // Enhanced logging for binary message reception
                 info!("Received binary message, length: {}", data.len());
@@ -659,6 +1479,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                 match binary_protocol::decode_node_data(&data) {
                     Ok(nodes) => {
                         info!("Decoded {} nodes from binary message", nodes.len());
+                        self.note_moves(nodes.iter().map(|(node_id, node_data)| (*node_id, node_data.position)), ctx);
                         let _nodes_vec: Vec<_> = nodes.clone().into_iter().collect();
 
                         // CRITICAL FIX: Remove node count limitation to allow processing batches from randomization
@@ -666,6 +1487,12 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                         {
                             let app_state = self.app_state.clone();
                             let nodes_vec: Vec<_> = nodes.clone().into_iter().collect();
+                            // Moves from a client are manual edits and go on the room's undo stack
+                            use crate::actors::messages::EditAttribution;
+                            let edited_by = self.client_id.map(|client_id| EditAttribution {
+                                client_id,
+                                room: self.room.clone(),
+                            });
 
                             let fut = async move {
                                 for (node_id, node_data) in &nodes_vec {
@@ -689,6 +1516,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                                         node_id: node_id,
                                         position: node_data.position.into(),
                                         velocity: node_data.velocity.into(),
+                                        edited_by: edited_by.clone(),
                                     }).await {
                                         error!("Failed to update node position in GraphServiceActor: {}", e);
                                     }
@@ -788,9 +1616,16 @@ pub async fn socket_flow_handler(
     if !req.headers().contains_key("Upgrade") {
         return Ok(HttpResponse::BadRequest().body("WebSocket upgrade required"));
     }
-    
+
+    // Sessions are optional here; without one the client gets the anonymous role
+    let nostr_service = app_state_arc.nostr_service.as_ref().map(|n| n.get_ref());
+    let identity = match auth::resolve_identity(&req, nostr_service, &app_state_arc.access_control).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+
     // Pass the ClientManagerActor address to SocketFlowServer::new
-    let ws = SocketFlowServer::new(app_state_arc, pre_read_ws_settings.get_ref().clone(), client_manager_addr);
+    let ws = SocketFlowServer::new(app_state_arc, pre_read_ws_settings.get_ref().clone(), client_manager_addr, identity);
 
     // Start WebSocket with compression enabled (permessage-deflate)
     // Prefer WsResponseBuilder for setting protocols
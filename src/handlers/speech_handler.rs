@@ -0,0 +1,214 @@
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Instant;
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::handlers::api_error::ApiError;
+use crate::services::speech_recording_store::SpeechRecordingStore;
+use crate::services::speech_session_service::SessionError;
+use crate::services::utterance_store::{UtteranceStatus, UtteranceStore};
+use crate::types::speech::SpeechCapability;
+use crate::utils::byte_range::{parse_range, RangeNotSatisfiable};
+
+fn speech_not_available() -> ApiError {
+    ApiError::ServiceUnavailable { message: "Speech service is not available".to_string(), retry_after_secs: None }
+}
+
+/// GET /api/speech/sessions - open speech sessions, including ones waiting for a reconnect
+pub async fn list_sessions(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    Ok(HttpResponse::Ok().json(state.speech_sessions.list(Instant::now())))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecordingToggle {
+    pub enabled: bool,
+}
+
+/// PUT /api/speech/sessions/{id}/recording - start or stop keeping the audio a session
+/// sends for transcription. The client is told either way.
+pub async fn set_session_recording(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    session_id: web::Path<String>,
+    body: web::Json<RecordingToggle>,
+) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let speech_service = state.speech_service.as_ref().ok_or_else(speech_not_available)?;
+    match state.speech_sessions.set_recording(&session_id, body.enabled, Instant::now()) {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "sessionId": session_id.as_str(),
+            "recording": body.enabled,
+            "store": speech_service.recordings().status(),
+        }))),
+        Err(e @ (SessionError::Unknown | SessionError::Expired)) => Err(ApiError::NotFound(e.to_string())),
+        Err(e) => Err(ApiError::Conflict(e.to_string())),
+    }
+}
+
+/// GET /api/speech/sessions/{id}/segments - the recorded segments of a session
+pub async fn list_session_segments(req: HttpRequest, state: web::Data<AppState>, session_id: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let speech_service = state.speech_service.as_ref().ok_or_else(speech_not_available)?;
+    let recordings = speech_service.recordings();
+    Ok(HttpResponse::Ok().json(json!({
+        "sessionId": session_id.as_str(),
+        "segments": recordings.segments(&session_id, Instant::now()),
+        "store": recordings.status(),
+    })))
+}
+
+/// GET /api/speech/sessions/{id}/segments/{seg}/audio - a recorded segment as WAV, as the
+/// transcription provider received it
+pub async fn get_segment_audio(req: HttpRequest, state: web::Data<AppState>, path: web::Path<(String, u64)>) -> Result<HttpResponse, ApiError> {
+    if let Err(response) = state.require_role(&req, Role::Admin).await {
+        return Ok(response);
+    }
+    let speech_service = state.speech_service.as_ref().ok_or_else(speech_not_available)?;
+    let (session_id, segment_id) = path.into_inner();
+    Ok(segment_response(&speech_service.recordings(), &session_id, segment_id, Instant::now()))
+}
+
+fn segment_response(store: &SpeechRecordingStore, session_id: &str, segment_id: u64, now: Instant) -> HttpResponse {
+    match store.audio(session_id, segment_id, now) {
+        Some(wav) => HttpResponse::Ok()
+            .content_type("audio/wav")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}-{}.wav\"", session_id, segment_id)))
+            .body(wav),
+        None => ApiError::NotFound(format!("Recorded segment {} of speech session {}", segment_id, session_id)).error_response(),
+    }
+}
+
+/// GET /api/speech/providers - the active TTS and STT providers and whether each has
+/// finished warming up
+pub async fn get_providers(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let speech_service = state.speech_service.as_ref().ok_or_else(speech_not_available)?;
+    let readiness = speech_service.readiness();
+    Ok(HttpResponse::Ok().json(json!({
+        "tts": { "provider": format!("{:?}", speech_service.get_tts_provider().await), "readiness": readiness.get(&SpeechCapability::Tts) },
+        "stt": { "provider": format!("{:?}", speech_service.get_stt_provider().await), "readiness": readiness.get(&SpeechCapability::Stt) },
+    })))
+}
+
+/// GET /api/speech/utterances/{utterance_id}/audio - the whole audio of a finished TTS
+/// utterance. Honors single `Range` requests so players can seek.
+pub async fn get_utterance_audio(req: HttpRequest, state: web::Data<AppState>, utterance_id: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let speech_service = state.speech_service.as_ref().ok_or_else(speech_not_available)?;
+    let range = req.headers().get("Range").and_then(|value| value.to_str().ok());
+    Ok(utterance_response(&speech_service.utterances(), &utterance_id, range, Instant::now()))
+}
+
+fn utterance_response(store: &UtteranceStore, utterance_id: &str, range: Option<&str>, now: Instant) -> HttpResponse {
+    let (audio, content_type) = match store.status(utterance_id, now) {
+        UtteranceStatus::Ready { audio, content_type } => (audio, content_type),
+        UtteranceStatus::Synthesizing { bytes } => {
+            return HttpResponse::Accepted()
+                .insert_header(("Retry-After", "1"))
+                .json(json!({ "status": "synthesizing", "bytes": bytes }));
+        }
+        UtteranceStatus::Failed(error) => {
+            return ApiError::Internal(format!("Synthesis failed: {}", error)).error_response();
+        }
+        UtteranceStatus::Expired => return ApiError::Gone("Utterance audio has expired".to_string()).error_response(),
+        UtteranceStatus::Missing => return ApiError::NotFound(format!("Utterance {}", utterance_id)).error_response(),
+    };
+
+    match parse_range(range, audio.len()) {
+        Ok(Some(range)) => HttpResponse::PartialContent()
+            .content_type(content_type)
+            .insert_header(("Accept-Ranges", "bytes"))
+            .insert_header(("Content-Range", range.content_range(audio.len())))
+            .body(audio.slice(range.start..=range.end)),
+        Ok(None) => HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header(("Accept-Ranges", "bytes"))
+            .body(audio),
+        Err(RangeNotSatisfiable) => HttpResponse::RangeNotSatisfiable()
+            .insert_header(("Content-Range", format!("bytes */{}", audio.len())))
+            .finish(),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/speech")
+            .route("/sessions", web::get().to(list_sessions))
+            .route("/sessions/{id}/recording", web::put().to(set_session_recording))
+            .route("/sessions/{id}/segments", web::get().to(list_session_segments))
+            .route("/sessions/{id}/segments/{seg}/audio", web::get().to(get_segment_audio))
+            .route("/providers", web::get().to(get_providers))
+            .route("/utterances/{utterance_id}/audio", web::get().to(get_utterance_audio))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SpeechUtteranceSettings;
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn test_utterance_download_follows_synthesis() {
+        let store = UtteranceStore::new(SpeechUtteranceSettings { retention_secs: 60.0, max_utterances: 10 });
+        let start = Instant::now();
+        let id = store.begin(start);
+        store.set_format(&id, "mp3");
+        store.append(&id, b"0123456789");
+
+        let response = utterance_response(&store, &id, None, start);
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        store.complete(&id, start);
+        let response = utterance_response(&store, &id, None, start);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "audio/mpeg");
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), "0123456789");
+
+        let response = utterance_response(&store, &id, Some("bytes=2-5"), start);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers().get("Content-Range").unwrap(), "bytes 2-5/10");
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), "2345");
+
+        let response = utterance_response(&store, &id, Some("bytes=10-"), start);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers().get("Content-Range").unwrap(), "bytes */10");
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(utterance_response(&store, &id, None, later).status(), StatusCode::GONE);
+        assert_eq!(utterance_response(&store, "nope", None, later).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_recorded_segment_downloads_as_wav() {
+        use crate::config::SpeechRecordingSettings;
+        use crate::utils::audio_resample::{parse_wav, wav_bytes, PcmFormat};
+
+        let directory = std::env::temp_dir().join(format!("speech-segments-{}", uuid::Uuid::new_v4()));
+        let store = SpeechRecordingStore::new(SpeechRecordingSettings {
+            directory: directory.to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let samples: Vec<i16> = (0..1600).map(|n| ((n % 40) * 400 - 8000) as i16).collect();
+        let segment = store.record("session-1", &wav_bytes(PcmFormat::mono(16_000), &samples), start).unwrap();
+
+        let response = segment_response(&store, "session-1", segment, start);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "audio/wav");
+        let wav = to_bytes(response.into_body()).await.unwrap();
+        let (format, data) = parse_wav(&wav).unwrap().unwrap();
+        assert_eq!((format, data.len()), (PcmFormat::mono(16_000), samples.len() * 2));
+        assert_eq!(segment_response(&store, "session-1", segment + 1, start).status(), StatusCode::NOT_FOUND);
+        let _ = std::fs::remove_dir_all(directory);
+    }
+}
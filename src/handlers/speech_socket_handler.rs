@@ -1,20 +1,31 @@
 use actix::prelude::*;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use crate::app_state::AppState;
 use crate::actors::messages::GetSettings;
-use crate::types::speech::SpeechOptions;
+use crate::config::feature_access::Role;
+use crate::utils::auth;
+use crate::utils::audio_resample::AudioInput;
+use crate::utils::captions;
+use crate::services::dictation::{self, Dictation, DictationTarget, DictationUpdate};
+use crate::services::speech_session_service::{SessionError, SessionOptions};
+use crate::services::utterance_store::UtteranceStatus;
+use crate::types::speech::{SpeechCapability, SpeechOptions, TranscriptionOptions, TranscriptionSegment, AUTO_LANGUAGE};
+use crate::utils::voice_command::{disambiguate_title, parse_voice_command, VoiceCommand};
 use tokio::sync::broadcast;
 use futures::FutureExt;
 
 // Constants for heartbeat
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a socket waits to announce a finished utterance
+const TTS_COMPLETE_TIMEOUT: Duration = Duration::from_secs(120);
 
 // Define message types
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +35,7 @@ struct TextToSpeechRequest {
     voice: Option<String>,
     speed: Option<f32>,
     stream: Option<bool>,
+    session_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,12 +44,55 @@ struct SetProviderRequest {
     provider: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQueryRequest {
+    text: String,
+    candidate: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FocusRequest {
+    node_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnnotateRequest {
+    // Falls back to the focused node, then to the speaker's selection in their graph client
+    node_id: Option<u32>,
+    // "start" (the default) or "cancel"
+    action: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct STTActionRequest {
     action: String, // "start" or "stop"
     language: Option<String>,
     model: Option<String>,
+    session_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetLanguageRequest {
+    // A language code, or "auto"
+    language: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateSessionRequest {
+    #[serde(default)]
+    options: SessionOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResumeSessionRequest {
+    session_id: String,
 }
 
 pub struct SpeechSocket {
@@ -45,11 +100,31 @@ pub struct SpeechSocket {
     app_state: Arc<AppState>,
     heartbeat: Instant,
     audio_rx: Option<broadcast::Receiver<Vec<u8>>>,
-    transcription_rx: Option<broadcast::Receiver<String>>,
+    transcription_rx: Option<broadcast::Receiver<TranscriptionSegment>>,
+    // Transcriptions are broadcast to every socket; only the one that started STT acts on commands
+    stt_active: bool,
+    // Node the client has selected, used to link spoken notes
+    focused_node: Option<u32>,
+    // Note titles created this session, for duplicate suffixes
+    note_titles: HashMap<String, usize>,
+    // Open dictation; the next final transcription becomes an annotation on its node
+    dictation: Option<Dictation>,
+    // Viewers can listen and transcribe but spoken notes are edits
+    role: Role,
+    pubkey: Option<String>,
+    // The one speech session this connection holds, if any
+    session_id: Option<String>,
+    // What STT was started with; audio chunks are transcribed with these
+    stt_options: Option<TranscriptionOptions>,
+    language_fallback_noticed: bool,
+    // Audio arriving while STT warms up is dropped; the client hears about it once
+    stt_warming_noticed: bool,
+    // What the client was last told about its audio being recorded
+    recording_disclosed: bool,
 }
 
 impl SpeechSocket {
-    pub fn new(id: String, app_state: Arc<AppState>) -> Self {
+    pub fn new(id: String, app_state: Arc<AppState>, identity: auth::Identity) -> Self {
         let (audio_rx, transcription_rx) = if let Some(speech_service) = &app_state.speech_service {
             (
                 Some(speech_service.subscribe_to_audio()),
@@ -65,9 +140,216 @@ impl SpeechSocket {
             heartbeat: Instant::now(),
             audio_rx,
             transcription_rx,
+            stt_active: false,
+            focused_node: None,
+            note_titles: HashMap::new(),
+            dictation: None,
+            role: identity.role,
+            pubkey: identity.pubkey,
+            session_id: None,
+            stt_options: None,
+            language_fallback_noticed: false,
+            stt_warming_noticed: false,
+            recording_disclosed: false,
+        }
+    }
+
+    // Options for a request: the named session, which must be this connection's, or the
+    // connection's own session, or the defaults when it has none
+    fn session_options(&self, requested: Option<&str>) -> Result<SessionOptions, SessionError> {
+        match requested.or(self.session_id.as_deref()) {
+            Some(id) => self.app_state.speech_sessions.options(id, &self.id, Instant::now()),
+            None => Ok(SessionOptions::default()),
+        }
+    }
+
+    fn release_session(&mut self) {
+        if let Some(id) = self.session_id.take() {
+            self.app_state.speech_sessions.release(&id, &self.id, Instant::now());
+        }
+        self.recording_disclosed = false;
+    }
+
+    // Whether the session's inbound audio is being kept: an admin turned it on and the
+    // recordings have room
+    fn recording(&self) -> bool {
+        let Some(id) = &self.session_id else {
+            return false;
+        };
+        self.app_state.speech_sessions.is_recording(id)
+            && self.app_state.speech_service.as_ref().is_some_and(|service| service.recordings().accepting())
+    }
+
+    // Tells the client when recording of its audio starts or stops
+    fn disclose_recording(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let recording = self.recording();
+        if recording != self.recording_disclosed {
+            self.recording_disclosed = recording;
+            ctx.text(json!({ "type": "session_recording", "sessionId": self.session_id, "recording": recording }).to_string());
+        }
+    }
+
+    fn create_session(&mut self, options: SessionOptions, ctx: &mut ws::WebsocketContext<Self>) {
+        self.release_session();
+        self.stt_options = None;
+        match self.app_state.speech_sessions.create(&self.id, self.pubkey.clone(), options.clone(), Instant::now()) {
+            Ok(id) => {
+                info!("[SpeechSocket] {} opened speech session {}", self.id, id);
+                self.session_id = Some(id.clone());
+                self.recording_disclosed = self.recording();
+                ctx.text(json!({ "type": "session_created", "sessionId": id, "options": options, "recording": self.recording_disclosed }).to_string());
+            }
+            Err(e) => ctx.text(e.to_ws_message()),
+        }
+    }
+
+    // Switches the language of a running transcription; it applies from the next audio chunk,
+    // so a segment is never split between two languages
+    fn set_language(&mut self, language: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let language = language.trim().to_lowercase();
+        let valid = !language.is_empty() && language.len() <= 16
+            && language.chars().all(|c| c.is_ascii_alphabetic() || c == '-');
+        if !valid {
+            return ctx.text(json!({"type": "error", "message": "Invalid language"}).to_string());
+        }
+        let options = match self.stt_options.as_mut() {
+            Some(options) if self.stt_active => options,
+            _ => return ctx.text(json!({"type": "error", "message": "Transcription is not running"}).to_string()),
+        };
+        options.language = Some(language.clone());
+        self.language_fallback_noticed = false;
+        ctx.text(json!({ "type": "language_set", "language": language, "auto": language == AUTO_LANGUAGE }).to_string());
+    }
+
+    // A reconnecting client takes its session back
+    fn resume_session(&mut self, id: String, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.session_id.as_deref() != Some(id.as_str()) {
+            self.release_session();
+            self.stt_options = None;
+        }
+        match self.app_state.speech_sessions.resume(&id, &self.id, self.pubkey.as_deref(), Instant::now()) {
+            Ok(options) => {
+                info!("[SpeechSocket] {} resumed speech session {}", self.id, id);
+                self.session_id = Some(id.clone());
+                self.recording_disclosed = self.recording();
+                ctx.text(json!({ "type": "session_resumed", "sessionId": id, "options": options, "recording": self.recording_disclosed }).to_string());
+            }
+            Err(e) => ctx.text(e.to_ws_message()),
+        }
+    }
+
+    // Runs a voice command found in a transcription segment. Annotations wait for a final
+    // segment so half a sentence is never saved.
+    fn handle_voice_command(&mut self, transcription: &str, is_final: bool, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(VoiceCommand::Annotate { text }) = parse_voice_command(transcription) {
+            if is_final {
+                self.start_dictation(None, text, ctx);
+            }
+        } else if let Some(VoiceCommand::CreateNote { title }) = parse_voice_command(transcription) {
+            if !self.role.can_edit() {
+                ctx.text(auth::forbidden_ws_message(self.role, Role::Editor));
+                return;
+            }
+            let title = disambiguate_title(&title, &mut self.note_titles);
+            let app_state = self.app_state.clone();
+            let transcription = transcription.to_string();
+            let focused = self.focused_node;
+            let addr = ctx.address();
+            let fut = async move {
+                let reply = match app_state.create_spoken_note(title, transcription, focused).await {
+                    Ok((node, linked_to)) => json!({
+                        "type": "note_created",
+                        "data": {
+                            "nodeId": node.id,
+                            "label": node.label,
+                            "linkedTo": linked_to
+                        }
+                    }),
+                    Err(e) => json!({ "type": "error", "message": format!("Failed to create note: {}", e) }),
+                };
+                let _ = addr.try_send(ErrorMessage(reply.to_string()));
+            };
+            ctx.spawn(fut.into_actor(self));
+        }
+    }
+
+    // Opens a dictation on the named node, or the focused or selected one. Text that came
+    // with the command ("note on this node: ...") is saved straight away instead.
+    fn start_dictation(&mut self, node_id: Option<u32>, text: Option<String>, ctx: &mut ws::WebsocketContext<Self>) {
+        if !self.role.can_edit() {
+            ctx.text(auth::forbidden_ws_message(self.role, Role::Editor));
+            return;
+        }
+        let app_state = self.app_state.clone();
+        let focused = self.focused_node;
+        let pubkey = self.pubkey.clone();
+        let addr = ctx.address();
+        let fut = async move {
+            let target = dictation::resolve_target(
+                node_id,
+                focused,
+                pubkey.as_deref(),
+                &app_state.graph_service_addr,
+                &app_state.client_manager_addr,
+            ).await;
+            match (target, text) {
+                (Ok(target), Some(text)) => {
+                    let reply = Self::save_dictation(&app_state, &target, text, pubkey.as_deref()).await;
+                    let _ = addr.try_send(ErrorMessage(reply));
+                }
+                (Ok(target), None) => {
+                    let _ = addr.try_send(OpenDictation(target));
+                }
+                (Err(e), _) => {
+                    let _ = addr.try_send(ErrorMessage(e.to_ws_message()));
+                }
+            }
+        };
+        ctx.spawn(fut.into_actor(self));
+    }
+
+    // Stores a finished dictation; the result is the reply for the speaker
+    async fn save_dictation(app_state: &AppState, target: &DictationTarget, text: String, pubkey: Option<&str>) -> String {
+        let author = pubkey.unwrap_or("speech");
+        match dictation::save(target, text, author, &app_state.annotation_service, &app_state.client_manager_addr, &app_state.event_log).await {
+            Ok(annotation) => json!({ "type": "annotation_saved", "nodeId": target.node_id, "annotation": annotation }),
+            Err(e) => json!({ "type": "error", "code": "annotation_rejected", "message": format!("Failed to save annotation: {}", e) }),
+        }.to_string()
+    }
+
+    // Feeds a transcription segment to the open dictation: interim text goes back to this
+    // client only, a final segment closes the dictation and is saved
+    fn feed_dictation(&mut self, segment: &TranscriptionSegment, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(update) = self.dictation.as_ref().and_then(|d| d.feed(segment)) else {
+            return;
+        };
+        match update {
+            DictationUpdate::Interim(text) => {
+                let node_id = self.dictation.as_ref().map(|d| d.target.node_id);
+                ctx.text(json!({ "type": "annotation_interim", "nodeId": node_id, "text": text }).to_string());
+            }
+            DictationUpdate::Final(text) => {
+                let Some(open) = self.dictation.take() else {
+                    return;
+                };
+                let app_state = self.app_state.clone();
+                let pubkey = self.pubkey.clone();
+                let addr = ctx.address();
+                let fut = async move {
+                    let reply = Self::save_dictation(&app_state, &open.target, text, pubkey.as_deref()).await;
+                    let _ = addr.try_send(ErrorMessage(reply));
+                };
+                ctx.spawn(fut.into_actor(self));
+            }
         }
     }
 
+    // The error to send for a capability whose provider is still warming up
+    fn warming_up(&self, capability: SpeechCapability) -> Option<String> {
+        let speech_service = self.app_state.speech_service.as_ref()?;
+        speech_service.check_ready(capability).err().map(|e| e.to_ws_message())
+    }
+
     // Helper method to handle heartbeat
     fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
         ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
@@ -81,7 +363,8 @@ impl SpeechSocket {
     }
 
     // Process text-to-speech request
-    async fn process_tts_request(app_state: Arc<AppState>, req: TextToSpeechRequest) -> Result<(), String> {
+    // Returns the id of the utterance the audio is collected under
+    async fn process_tts_request(app_state: Arc<AppState>, req: TextToSpeechRequest, session: SessionOptions) -> Result<String, String> {
         if let Some(speech_service) = &app_state.speech_service {
             // Get default settings from app state, handling optional Kokoro settings
             let settings = app_state.settings_addr.send(GetSettings).await
@@ -94,16 +377,18 @@ impl SpeechSocket {
             let default_speed = kokoro_config.and_then(|k| k.default_speed).unwrap_or(1.0);
             let default_stream = kokoro_config.and_then(|k| k.stream).unwrap_or(true); // Default to streaming?
 
-            // Create options with defaults or provided values
-            let options = SpeechOptions {
-                voice: req.voice.unwrap_or(default_voice),
-                speed: req.speed.unwrap_or(default_speed),
-                stream: req.stream.unwrap_or(default_stream),
+            // The request's own values, then the session's, then the defaults
+            let defaults = SpeechOptions {
+                voice: default_voice,
+                speed: default_speed,
+                stream: default_stream,
+                format: None,
             };
+            let options = session.speech_options(req.voice, req.speed, req.stream, defaults);
 
             // Send request to TTS service
             match speech_service.text_to_speech(req.text, options).await {
-                Ok(_) => Ok(()),
+                Ok(utterance_id) => Ok(utterance_id),
                 Err(e) => Err(format!("Failed to process TTS request: {}", e)),
             }
         } else {
@@ -148,15 +433,28 @@ impl Actor for SpeechSocket {
             let addr = ctx.address();
 
             ctx.spawn(Box::pin(async move {
-                while let Ok(transcription_text) = rx.recv().await {
+                while let Ok(segment) = rx.recv().await {
                     // Send transcription to the client
-                    if addr.try_send(TranscriptionMessage(transcription_text)).is_err() {
+                    if addr.try_send(TranscriptionMessage(segment)).is_err() {
                         break;
                     }
                 }
             }.into_actor(self)));
         }
     }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        // The session outlives the socket for its TTL, so a reconnect can resume it
+        self.release_session();
+        if let Some(speech_service) = self.app_state.speech_service.clone() {
+            let stream_id = self.id.clone();
+            actix::spawn(async move {
+                if let Err(e) = speech_service.end_audio_stream(stream_id).await {
+                    warn!("[SpeechSocket] Failed to end audio stream: {}", e);
+                }
+            });
+        }
+    }
 }
 
 // Message type for audio data
@@ -176,7 +474,7 @@ impl Handler<AudioChunkMessage> for SpeechSocket {
 }
 
 // Message type for transcription data
-struct TranscriptionMessage(String);
+struct TranscriptionMessage(TranscriptionSegment);
 
 impl Message for TranscriptionMessage {
     type Result = ();
@@ -186,12 +484,40 @@ impl Handler<TranscriptionMessage> for SpeechSocket {
     type Result = ();
 
     fn handle(&mut self, msg: TranscriptionMessage, ctx: &mut Self::Context) -> Self::Result {
+        let segment = msg.0;
+        if self.stt_active {
+            // While dictating, what is said is the note, not a command
+            let dictating = self.dictation.is_some();
+            self.feed_dictation(&segment, ctx);
+            let options = self.session_options(None).ok();
+            if !dictating && options.as_ref().is_none_or(|options| options.command_mode) {
+                self.handle_voice_command(&segment.text, segment.is_final, ctx);
+            }
+            // Only the socket that started STT is the speaker
+            if let Some(caption) = options.and_then(|options| captions::caption_for(&options, self.pubkey.clone(), &segment)) {
+                self.app_state.client_manager_addr.do_send(caption);
+            }
+        }
+
+        // Once per run, tell an auto-mode client that the provider couldn't detect the language
+        let auto = self.stt_options.as_ref().is_some_and(|options| options.is_auto_language());
+        if auto && !segment.detected && segment.language.is_some() && !self.language_fallback_noticed {
+            self.language_fallback_noticed = true;
+            ctx.text(json!({
+                "type": "notice",
+                "code": "language_detection_unavailable",
+                "message": format!("Language detection unavailable; transcribing as {}", segment.language.as_deref().unwrap_or_default()),
+            }).to_string());
+        }
+
         // Send transcription as JSON to the client
         let message = json!({
             "type": "transcription",
             "data": {
-                "text": msg.0,
-                "isFinal": true,
+                "text": segment.text,
+                "language": segment.language,
+                "detected": segment.detected,
+                "isFinal": segment.is_final,
                 "timestamp": std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap_or_default()
@@ -202,6 +528,23 @@ impl Handler<TranscriptionMessage> for SpeechSocket {
     }
 }
 
+// A dictation target has been resolved; the socket starts taking the note
+struct OpenDictation(DictationTarget);
+
+impl Message for OpenDictation {
+    type Result = ();
+}
+
+impl Handler<OpenDictation> for SpeechSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: OpenDictation, ctx: &mut Self::Context) -> Self::Result {
+        let node_id = msg.0.node_id;
+        self.dictation = Some(Dictation::new(msg.0));
+        ctx.text(json!({ "type": "annotation_started", "nodeId": node_id }).to_string());
+    }
+}
+
 // Message type for error data
 struct ErrorMessage(String);
 
@@ -241,17 +584,52 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                             Some("tts") => {
                                 // Parse as TextToSpeechRequest
                                 if let Ok(tts_req) = serde_json::from_value::<TextToSpeechRequest>(msg) {
+                                    if let Some(error) = self.warming_up(SpeechCapability::Tts) {
+                                        return ctx.text(error);
+                                    }
+                                    let session = match self.session_options(tts_req.session_id.as_deref()) {
+                                        Ok(session) => session,
+                                        Err(e) => return ctx.text(e.to_ws_message()),
+                                    };
                                     // Process TTS request
                                     let app_state = self.app_state.clone();
                                     let addr = ctx.address();
                                     let fut = async move {
-                                        if let Err(e) = Self::process_tts_request(app_state, tts_req).await {
-                                            let error_msg = json!({
+                                        let utterance_id = match Self::process_tts_request(app_state.clone(), tts_req, session).await {
+                                            Ok(utterance_id) => utterance_id,
+                                            Err(e) => {
+                                                let error_msg = json!({
+                                                    "type": "error",
+                                                    "message": e
+                                                });
+                                                let _ = addr.try_send(ErrorMessage(error_msg.to_string()));
+                                                return;
+                                            }
+                                        };
+                                        let _ = addr.try_send(ErrorMessage(json!({ "type": "tts_started", "utteranceId": utterance_id }).to_string()));
+
+                                        // Once synthesis finishes, tell the client where to download the whole file
+                                        let Some(speech_service) = &app_state.speech_service else {
+                                            return;
+                                        };
+                                        let reply = match speech_service.utterances().wait_finished(&utterance_id, TTS_COMPLETE_TIMEOUT).await {
+                                            UtteranceStatus::Ready { audio, content_type } => json!({
+                                                "type": "tts_complete",
+                                                "utteranceId": utterance_id,
+                                                "url": format!("/api/speech/utterances/{}/audio", utterance_id),
+                                                "contentType": content_type,
+                                                "bytes": audio.len()
+                                            }),
+                                            UtteranceStatus::Failed(e) => json!({
                                                 "type": "error",
+                                                "code": "tts_failed",
+                                                "utteranceId": utterance_id,
                                                 "message": e
-                                            });
-                                            let _ = addr.try_send(ErrorMessage(error_msg.to_string()));
-                                        }
+                                            }),
+                                            // Still running or already gone; the client can poll the download url
+                                            _ => return,
+                                        };
+                                        let _ = addr.try_send(ErrorMessage(reply.to_string()));
                                     };
                                     ctx.spawn(fut.into_actor(self));
                                 } else {
@@ -263,14 +641,18 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                 if let Ok(stt_req) = serde_json::from_value::<STTActionRequest>(msg) {
                                     match stt_req.action.as_str() {
                                         "start" => {
+                                            if let Some(error) = self.warming_up(SpeechCapability::Stt) {
+                                                return ctx.text(error);
+                                            }
+                                            let session = match self.session_options(stt_req.session_id.as_deref()) {
+                                                Ok(session) => session,
+                                                Err(e) => return ctx.text(e.to_ws_message()),
+                                            };
                                             if let Some(speech_service) = &self.app_state.speech_service {
-                                                use crate::types::speech::TranscriptionOptions;
-                                                let options = TranscriptionOptions {
-                                                    language: stt_req.language,
-                                                    model: stt_req.model,
-                                                    temperature: None,
-                                                    stream: true,
-                                                };
+                                                self.stt_active = true;
+                                                let options = session.transcription_options(stt_req.language, stt_req.model);
+                                                self.stt_options = Some(options.clone());
+                                                self.language_fallback_noticed = false;
 
                                                 let speech_service = speech_service.clone();
                                                 let addr = ctx.address();
@@ -296,6 +678,8 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                             }
                                         },
                                         "stop" => {
+                                            self.stt_active = false;
+                                            self.stt_options = None;
                                             if let Some(speech_service) = &self.app_state.speech_service {
                                                 let speech_service = speech_service.clone();
                                                 let addr = ctx.address();
@@ -328,6 +712,60 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                     ctx.text(json!({"type": "error", "message": "Invalid STT request format"}).to_string());
                                 }
                             }
+                            Some("query") => {
+                                // Spoken graph queries go through the same pipeline as POST /api/graph/query
+                                if let Ok(query_req) = serde_json::from_value::<GraphQueryRequest>(msg) {
+                                    let app_state = self.app_state.clone();
+                                    let addr = ctx.address();
+                                    let fut = async move {
+                                        let reply = match app_state.run_graph_query(&query_req.text, query_req.candidate).await {
+                                            Ok(result) => json!({ "type": "query_result", "data": result }),
+                                            Err(e) => json!({ "type": "error", "message": e }),
+                                        };
+                                        let _ = addr.try_send(ErrorMessage(reply.to_string()));
+                                    };
+                                    ctx.spawn(fut.into_actor(self));
+                                } else {
+                                    ctx.text(json!({"type": "error", "message": "Invalid query request format"}).to_string());
+                                }
+                            }
+                            Some("set_language") => {
+                                match serde_json::from_value::<SetLanguageRequest>(msg) {
+                                    Ok(language_req) => self.set_language(language_req.language, ctx),
+                                    Err(_) => ctx.text(json!({"type": "error", "message": "Invalid set_language request format"}).to_string()),
+                                }
+                            }
+                            Some("create_session") => {
+                                match serde_json::from_value::<CreateSessionRequest>(msg) {
+                                    Ok(create_req) => self.create_session(create_req.options, ctx),
+                                    Err(_) => ctx.text(json!({"type": "error", "message": "Invalid create_session request format"}).to_string()),
+                                }
+                            }
+                            Some("resume_session") => {
+                                match serde_json::from_value::<ResumeSessionRequest>(msg) {
+                                    Ok(resume_req) => self.resume_session(resume_req.session_id, ctx),
+                                    Err(_) => ctx.text(json!({"type": "error", "message": "Invalid resume_session request format"}).to_string()),
+                                }
+                            }
+                            Some("annotate") => {
+                                match serde_json::from_value::<AnnotateRequest>(msg) {
+                                    Ok(annotate_req) if annotate_req.action.as_deref() == Some("cancel") => {
+                                        if let Some(cancelled) = self.dictation.take() {
+                                            ctx.text(json!({ "type": "annotation_cancelled", "nodeId": cancelled.target.node_id }).to_string());
+                                        }
+                                    }
+                                    Ok(annotate_req) if annotate_req.action.as_deref().is_none_or(|a| a == "start") => {
+                                        self.start_dictation(annotate_req.node_id, None, ctx);
+                                    }
+                                    _ => ctx.text(json!({"type": "error", "message": "Invalid annotate request format"}).to_string()),
+                                }
+                            }
+                            Some("focus") => {
+                                match serde_json::from_value::<FocusRequest>(msg) {
+                                    Ok(focus_req) => self.focused_node = focus_req.node_id,
+                                    Err(_) => ctx.text(json!({"type": "error", "message": "Invalid focus request format"}).to_string()),
+                                }
+                            }
                             _ => {
                                 ctx.text(json!({"type": "error", "message": "Unknown message type"}).to_string());
                             }
@@ -343,13 +781,33 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                 self.heartbeat = Instant::now();
 
                 // Process audio chunk for STT
+                if let Some(error) = self.warming_up(SpeechCapability::Stt) {
+                    if !self.stt_warming_noticed {
+                        self.stt_warming_noticed = true;
+                        ctx.text(error);
+                    }
+                    return;
+                }
+                self.stt_warming_noticed = false;
+                self.disclose_recording(ctx);
                 if let Some(speech_service) = &self.app_state.speech_service {
                     let audio_data = bin.to_vec();
+                    let (options, session) = match (self.stt_options.clone(), self.session_options(None)) {
+                        (Some(options), session) => (options, session.unwrap_or_default()),
+                        (None, Ok(session)) => (session.transcription_options(None, None), session),
+                        (None, Err(e)) => return ctx.text(e.to_ws_message()),
+                    };
+                    let input = AudioInput {
+                        stream_id: self.id.clone(),
+                        format: session.input_format,
+                        normalize_gain: session.normalize_gain,
+                        record_as: self.session_id.clone().filter(|_| self.recording_disclosed),
+                    };
 
                     // Clone the speech service Arc to move into the future
                     let speech_service = speech_service.clone();
                     let fut = async move {
-                        if let Err(e) = speech_service.process_audio_chunk(audio_data).await {
+                        if let Err(e) = speech_service.process_audio_chunk(audio_data, options, input).await {
                             error!("Failed to process audio chunk: {}", e);
                         }
                     }.boxed().into_actor(self);
@@ -373,8 +831,13 @@ pub async fn speech_socket_handler(
     stream: web::Payload,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    let nostr_service = app_state.nostr_service.as_ref().map(|n| n.get_ref());
+    let identity = match auth::resolve_identity(&req, nostr_service, &app_state.access_control).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
     let socket_id = format!("speech_{}", uuid::Uuid::new_v4());
-    let socket = SpeechSocket::new(socket_id, app_state.into_inner());
+    let socket = SpeechSocket::new(socket_id, app_state.into_inner(), identity);
 
     match ws::start(socket, &req, stream) {
         Ok(response) => {
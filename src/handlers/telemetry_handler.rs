@@ -0,0 +1,53 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::{error, warn};
+
+use crate::app_state::AppState;
+use crate::handlers::api_error::ApiError;
+use crate::services::telemetry_service::{IngestError, TelemetryBatch, RATE_LIMIT_WINDOW};
+use crate::utils::auth;
+
+/// POST /api/telemetry - batched frame-rate and interaction analytics from clients
+pub async fn ingest_telemetry(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    batch: web::Json<TelemetryBatch>,
+) -> Result<HttpResponse, ApiError> {
+    let batch = batch.into_inner();
+    // Rate limit on who the caller is, never on the client id it declares: the signed-in
+    // pubkey, or else the peer address
+    let client_key = match auth::session_pubkey(&req, state.nostr_service.as_ref().map(|n| n.get_ref())).await {
+        Some(pubkey) => format!("pubkey:{}", pubkey),
+        None => req.peer_addr().map(|a| format!("ip:{}", a.ip())).unwrap_or_else(|| "unknown".to_string()),
+    };
+
+    let service = state.telemetry_service.clone();
+    match web::block(move || service.ingest(&client_key, batch)).await {
+        // Whole batch bad is a client error; partial success still returns per-item errors
+        Ok(Ok(result)) if result.rejected > 0 && result.accepted == 0 => {
+            Ok(HttpResponse::BadRequest().json(result))
+        }
+        Ok(Ok(result)) => Ok(HttpResponse::Ok().json(result)),
+        Ok(Err(IngestError::RateLimited)) => {
+            Err(ApiError::RateLimited { retry_after_secs: RATE_LIMIT_WINDOW.as_secs() })
+        }
+        Ok(Err(e @ IngestError::BatchTooLarge(_))) => {
+            warn!("Rejected telemetry batch: {}", e);
+            Err(ApiError::PayloadTooLarge(e.to_string()))
+        }
+        Ok(Err(e)) => {
+            error!("Telemetry ingestion failed: {}", e);
+            Err(ApiError::Internal(e.to_string()))
+        }
+        Err(e) => {
+            error!("Telemetry task failed: {}", e);
+            Err(ApiError::Internal("Telemetry ingestion failed".to_string()))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/telemetry")
+            .route("", web::post().to(ingest_telemetry))
+    );
+}
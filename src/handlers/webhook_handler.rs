@@ -0,0 +1,56 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::app_state::AppState;
+use crate::config::feature_access::Role;
+use crate::config::WebhookEventKind;
+use crate::handlers::api_error::ApiError;
+use crate::services::nostr_service::NostrService;
+use crate::services::webhook_service::WebhookPayload;
+use crate::utils::auth::{check_role, verify_authenticated};
+
+#[derive(Debug, Deserialize)]
+pub struct TestWebhookRequest {
+    // Category of the sample event; endpoints not subscribed to it aren't sent anything
+    #[serde(default = "default_test_event")]
+    pub event: WebhookEventKind,
+}
+
+fn default_test_event() -> WebhookEventKind {
+    WebhookEventKind::GraphDiff
+}
+
+/// POST /api/webhooks/test - fire a sample event and report how each delivery went
+pub async fn test_webhooks(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    request: Option<web::Json<TestWebhookRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    let pubkey = match verify_authenticated(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = check_role(&state.access_control.identity(&pubkey), Role::Admin) {
+        return Ok(response);
+    }
+    if !state.webhooks.is_enabled() {
+        return Err(ApiError::Conflict("No webhook endpoints configured".to_string()));
+    }
+
+    let event = request.map_or_else(default_test_event, |r| r.event);
+    info!("Test {} webhook requested by {}", event.as_str(), pubkey);
+    // Sent inline rather than queued, so the caller sees the outcome
+    let payload = WebhookPayload::new(event, json!({ "sample": true, "requestedBy": pubkey }));
+    let deliveries = state.webhooks.deliver(&payload).await;
+    Ok(HttpResponse::Ok().json(json!({ "id": payload.id, "event": event, "deliveries": deliveries })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/webhooks")
+            .route("/test", web::post().to(test_webhooks))
+    );
+}
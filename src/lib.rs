@@ -4,6 +4,8 @@ pub mod config;
 pub mod handlers;
 pub mod models;
 pub mod services;
+#[cfg(test)]
+pub mod testing;
 pub mod types;
 pub mod utils;
 
@@ -14,3 +16,27 @@ pub use models::protected_settings::ProtectedSettings;
 pub use models::simulation_params::SimulationParams;
 pub use models::ui_settings::UISettings;
 pub use models::user_settings::UserSettings;
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    // The combinations CI builds; scripts/check-features.sh runs the same list
+    const FEATURE_SETS: &[&[&str]] = &[&[], &["websocket"], &["gpu", "speech", "websocket"], &["gpu", "loadtest", "speech", "websocket"]];
+
+    // Slow: a fresh check per combination, in its own target dir so it doesn't
+    // invalidate the main build. Run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_every_feature_combination_checks() {
+        for features in FEATURE_SETS {
+            let status = Command::new(env!("CARGO"))
+                .args(["check", "--all-targets", "--no-default-features", "--features", &features.join(",")])
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .env("CARGO_TARGET_DIR", concat!(env!("CARGO_MANIFEST_DIR"), "/target/feature-checks"))
+                .status()
+                .unwrap();
+            assert!(status.success(), "cargo check failed with features {:?}", features);
+        }
+    }
+}
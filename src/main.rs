@@ -6,8 +6,6 @@ use webxr::{
         api_handler,
         health_handler,
         pages_handler,
-        socket_flow_handler::{socket_flow_handler, PreReadSocketSettings}, // Import PreReadSocketSettings
-        speech_socket_handler::speech_socket_handler,
         nostr_handler,
     },
     services::{
@@ -15,10 +13,22 @@ use webxr::{
         graph_service::GraphService,
         github::{GitHubClient, ContentAPI, GitHubConfig},
         ragflow_service::RAGFlowService, // ADDED IMPORT
+        perplexity_service::PerplexityService,
+        topic_extraction,
     },
-    services::speech_service::SpeechService,
 };
 
+#[cfg(feature = "websocket")]
+use webxr::handlers::{
+    admin_socket_handler::admin_socket_handler,
+    agent_socket_handler::agent_socket_handler,
+    socket_flow_handler::{socket_flow_handler, PreReadSocketSettings}, // Import PreReadSocketSettings
+};
+#[cfg(feature = "speech")]
+use webxr::services::speech_service::SpeechService;
+#[cfg(all(feature = "speech", feature = "websocket"))]
+use webxr::handlers::speech_socket_handler::speech_socket_handler;
+
 use actix_web::{web, App, HttpServer, middleware};
 use actix_cors::Cors;
 // use actix_files::Files; // Removed unused import
@@ -28,7 +38,9 @@ use tokio::time::Duration;
 use dotenvy::dotenv;
 use log::{error, info, debug, warn};
 use webxr::utils::logging::{init_logging_with_config, LogConfig};
-use tokio::signal::unix::{signal, SignalKind};
+use webxr::utils::rate_limit::RateLimit;
+use webxr::utils::shutdown::{shutdown_server, ShutdownSignals, ShutdownTargets};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -98,10 +110,19 @@ async fn main() -> std::io::Result<()> {
 
     // Initialize speech service
     // SpeechService::new might need adjustment if it expects client-facing Settings
+    #[cfg(feature = "speech")]
     let speech_service = {
-        let service = SpeechService::new(settings.clone());
+        let (utterance_settings, recording_settings) = {
+            let settings = settings.read().await;
+            (settings.system.speech_utterances.clone(), settings.system.speech_recordings.clone())
+        };
+        let service = SpeechService::new(settings.clone(), utterance_settings, recording_settings);
+        // Probes the providers in the background; startup doesn't wait for them
+        service.warm_up().await;
         Some(Arc::new(service))
     };
+    #[cfg(not(feature = "speech"))]
+    let speech_service = None;
 
     // Initialize RAGFlow Service
     info!("[main] Attempting to initialize RAGFlowService...");
@@ -122,6 +143,25 @@ async fn main() -> std::io::Result<()> {
         error!("[main] ragflow_service_option is None after RAGFlowService::new attempt. Chat functionality will be unavailable.");
     }
 
+    // Perplexity is only needed for the background link refresh, so skip it without a key
+    let perplexity_configured = {
+        let settings_read = settings.read().await;
+        settings_read.perplexity.as_ref()
+            .and_then(|p| p.api_key.as_ref())
+            .is_some_and(|key| !key.is_empty())
+    };
+    let perplexity_service = if perplexity_configured {
+        match PerplexityService::new(settings.clone()).await {
+            Ok(service) => Some(Arc::new(service)),
+            Err(e) => {
+                error!("[main] PerplexityService::new FAILED: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Initialize app state asynchronously
     // AppState::new now receives AppFullSettings directly (not Arc<RwLock<>>)
     let settings_value = {
@@ -133,7 +173,7 @@ async fn main() -> std::io::Result<()> {
             settings_value,
             github_client.clone(),
             content_api.clone(),
-            None, // Perplexity placeholder
+            perplexity_service,
             ragflow_service_option, // Pass the initialized RAGFlow service
             speech_service,
             "default_session".to_string() // RAGFlow session ID placeholder
@@ -174,11 +214,12 @@ async fn main() -> std::io::Result<()> {
     // Build initial graph from metadata and initialize GPU compute
     info!("Building initial graph from existing metadata for physics simulation");
 
-    match GraphService::build_graph_from_metadata(&metadata_store).await {
+    let topic_extraction_settings = settings.read().await.system.topic_extraction.clone();
+    let (metadata_store, _) = topic_extraction::prepare_for_build(metadata_store, &topic_extraction_settings).await;
+    match GraphService::build_graph_from_metadata(&metadata_store, &app_state.node_ids).await {
         Ok(graph_data) => {
             // Update graph data in the GraphServiceActor
-            use webxr::actors::messages::{UpdateGraphData, InitializeGPU};
-            use webxr::models::graph::GraphData as ModelsGraphData;
+            use webxr::actors::messages::{GetPhysicsGraph, UpdateGraphData, InitializeGPU};
 
             // Send graph data to GraphServiceActor
             if let Err(e) = app_state.graph_service_addr.send(UpdateGraphData {
@@ -191,7 +232,11 @@ async fn main() -> std::io::Result<()> {
             // Convert GraphService::GraphData to models::graph::GraphData for GPU initialization
             // Since GraphData (aliased as ModelsGraphData) derives Clone, and graph_data is already
             // the correct type (crate::models::graph::GraphData), we can just clone it.
-            let models_graph_data = graph_data.clone();
+            // Physics-disabled edge types are left out of what the GPU sees
+            let models_graph_data = match app_state.graph_service_addr.send(GetPhysicsGraph).await {
+                Ok(Ok(physics_graph)) => physics_graph,
+                _ => graph_data.clone(),
+            };
 
             // Initialize GPU compute through GPUComputeActor
             if let Some(gpu_compute_addr) = &app_state.gpu_compute_addr {
@@ -216,6 +261,13 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    let integrity = settings.read().await.system.integrity.clone();
+    if integrity.check_on_startup {
+        if let Err(e) = app_state.verify_integrity(integrity.auto_fix).await {
+            warn!("Startup integrity check failed: {}", e);
+        }
+    }
+
     info!("Waiting for initial physics layout calculation to complete...");
     tokio::time::sleep(Duration::from_millis(500)).await;
     info!("Initial delay complete. Starting HTTP server...");
@@ -231,6 +283,8 @@ async fn main() -> std::io::Result<()> {
 
     // Create web::Data after all initialization is complete
     let app_state_data = web::Data::new(app_state);
+    // The server closure takes app_state_data; teardown keeps its own handle
+    let shutdown_state = app_state_data.clone();
 
     // Start the server
     let bind_address = {
@@ -240,6 +294,7 @@ async fn main() -> std::io::Result<()> {
     };
 
     // Pre-read WebSocket settings for SocketFlowServer
+    #[cfg(feature = "websocket")]
     let pre_read_ws_settings = {
         let s = settings.read().await;
         PreReadSocketSettings {
@@ -249,10 +304,15 @@ async fn main() -> std::io::Result<()> {
             motion_damping: s.system.websocket.motion_damping,
             heartbeat_interval_ms: s.system.websocket.heartbeat_interval, // Assuming these exist
             heartbeat_timeout_ms: s.system.websocket.heartbeat_timeout,   // Assuming these exist
+            group_settle_ms: s.system.group_transform.settle_ms,
+            frame_accounting_echo: s.system.debug.enabled,
         }
     };
+    #[cfg(feature = "websocket")]
     let pre_read_ws_settings_data = web::Data::new(pre_read_ws_settings);
 
+    let shutdown_deadline = Duration::from_secs(settings.read().await.system.shutdown.deadline_secs);
+
     info!("Starting HTTP server on {}", bind_address);
 
     let server = HttpServer::new(move || {
@@ -263,7 +323,7 @@ async fn main() -> std::io::Result<()> {
             .max_age(3600)
             .supports_credentials();
 
-        let mut app = App::new()
+        let app = App::new()
             .wrap(middleware::Logger::default())
             .wrap(cors)
             .wrap(middleware::Compress::default())
@@ -272,7 +332,6 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(github_client.clone()))
             .app_data(web::Data::new(content_api.clone()))
             .app_data(app_state_data.clone()) // Add the complete AppState
-            .app_data(pre_read_ws_settings_data.clone()) // Add pre-read WebSocket settings
             // Register actor addresses for handler access
             .app_data(web::Data::new(app_state_data.graph_service_addr.clone()))
             .app_data(web::Data::new(app_state_data.settings_addr.clone()))
@@ -280,42 +339,65 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(app_state_data.client_manager_addr.clone()))
             .app_data(app_state_data.nostr_service.clone().unwrap_or_else(|| web::Data::new(NostrService::default()))) // Provide default if None
             .app_data(app_state_data.feature_access.clone())
-            .route("/wss", web::get().to(socket_flow_handler)) // Changed from /ws to /wss
-            .route("/ws/speech", web::get().to(speech_socket_handler))
             .service(
                 web::scope("/api") // Add /api prefix for these routes
+                    // Mutations only; one limiter shared across workers
+                    .wrap(RateLimit::new(app_state_data.rate_limiter.clone()))
                     .configure(api_handler::config) // This will now serve /api/user-settings etc.
                     .service(web::scope("/health").configure(health_handler::config)) // This will now serve /api/health
                     .service(web::scope("/pages").configure(pages_handler::config))
             );
+        // Headless builds serve the HTTP API only
+        #[cfg(feature = "websocket")]
+        let app = app
+            .app_data(pre_read_ws_settings_data.clone()) // Add pre-read WebSocket settings
+            .route("/wss", web::get().to(socket_flow_handler)) // Changed from /ws to /wss
+            .route("/ws/agent", web::get().to(agent_socket_handler))
+            .route("/ws/admin", web::get().to(admin_socket_handler));
+        #[cfg(all(feature = "speech", feature = "websocket"))]
+        let app = app.route("/ws/speech", web::get().to(speech_socket_handler));
 
         app
     })
     .bind(&bind_address)?
     .workers(4) // Explicitly set the number of worker threads
+    // Signals are ours: actix would otherwise stop the server before the ordered teardown runs
+    .disable_signals()
+    .shutdown_timeout(shutdown_deadline.as_secs())
     .run();
 
-    let server_handle = server.handle();
-
-    // Set up signal handlers
-    let mut sigterm = signal(SignalKind::terminate())?;
-    let mut sigint = signal(SignalKind::interrupt())?;
-
-    tokio::spawn(async move {
-        tokio::select! {
-            _ = sigterm.recv() => {
-                info!("Received SIGTERM signal");
-            }
-            _ = sigint.recv() => {
-                info!("Received SIGINT signal");
-            }
+    let shutdown_targets = ShutdownTargets {
+        server: server.handle(),
+        client_manager: shutdown_state.client_manager_addr.clone(),
+        graph: shutdown_state.graph_service_addr.clone(),
+        metadata: Some(shutdown_state.metadata_addr.clone()),
+        annotations: shutdown_state.annotation_service.clone(),
+        layout_snapshots: Some(shutdown_state.layout_snapshot_service.clone()),
+        speech: shutdown_state.speech_service.clone(),
+    };
+    // Whichever of the signal task or a server exit gets here first runs the teardown
+    let teardown_started = Arc::new(AtomicBool::new(false));
+
+    let mut signals = ShutdownSignals::new()?;
+    let signal_targets = shutdown_targets.clone();
+    let signal_teardown_started = teardown_started.clone();
+    // Speech shutdown isn't Send, so this runs on the main arbiter rather than tokio::spawn
+    actix_web::rt::spawn(async move {
+        let signal_name = signals.recv().await;
+        info!("Received {} signal", signal_name);
+        if signal_teardown_started.swap(true, Ordering::SeqCst) {
+            return;
         }
         info!("Initiating graceful shutdown");
-        server_handle.stop(true).await;
+        shutdown_server(signal_targets, shutdown_deadline, &format!("Server shutting down ({})", signal_name)).await;
     });
 
     server.await?;
 
     info!("HTTP server stopped");
+    if !teardown_started.swap(true, Ordering::SeqCst) {
+        // Stopped some other way; still flush and release everything
+        shutdown_server(shutdown_targets, shutdown_deadline, "Server stopped").await;
+    }
     Ok(())
 }
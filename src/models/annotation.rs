@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const MAX_ANNOTATION_LENGTH: usize = 500;
+pub const MAX_ANNOTATIONS_PER_NODE: usize = 20;
+
+/// A short note attached to a node. Stored against the node's `metadata_id`
+/// (the file name) so it survives graph rebuilds that reassign numeric ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: String,
+    pub metadata_id: String,
+    pub author: String,
+    pub text: String,
+    pub created_at: i64,
+    // Offset from the node centre where the note is pinned, in graph units
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor_offset: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AnnotationError {
+    #[error("Annotation text must not be empty")]
+    EmptyText,
+    #[error("Annotation text is {0} characters, maximum is {MAX_ANNOTATION_LENGTH}")]
+    TooLong(usize),
+    #[error("Annotation anchor offset must be finite")]
+    InvalidOffset,
+    #[error("Node already has the maximum of {MAX_ANNOTATIONS_PER_NODE} annotations")]
+    NodeFull,
+    #[error("Annotation {0}")]
+    NotFound(String),
+    #[error("Only the author can delete this annotation")]
+    NotAuthor,
+    #[error("{0}")]
+    Storage(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAnnotationRequest {
+    pub text: String,
+    #[serde(default)]
+    pub anchor_offset: Option<[f32; 3]>,
+}
+
+impl CreateAnnotationRequest {
+    pub fn validate(&self) -> Result<String, AnnotationError> {
+        let text = self.text.trim();
+        if text.is_empty() {
+            return Err(AnnotationError::EmptyText);
+        }
+        let len = text.chars().count();
+        if len > MAX_ANNOTATION_LENGTH {
+            return Err(AnnotationError::TooLong(len));
+        }
+        if let Some(offset) = self.anchor_offset {
+            if offset.iter().any(|v| !v.is_finite()) {
+                return Err(AnnotationError::InvalidOffset);
+            }
+        }
+        Ok(text.to_string())
+    }
+}
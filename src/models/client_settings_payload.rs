@@ -118,6 +118,7 @@ pub struct ClientPhysicsSettings {
     pub repulsion_distance: Option<f32>,
     pub mass_scale: Option<f32>,
     pub boundary_damping: Option<f32>,
+    pub degree_repulsion_factor: Option<f32>,
 }
 
 // --- Rendering Settings DTO ---
@@ -330,4 +331,23 @@ pub struct ClientSettingsPayload {
     pub perplexity: Option<ClientPerplexitySettings>,
     pub openai: Option<ClientOpenAISettings>,
     pub kokoro: Option<ClientKokoroSettings>,
-}
\ No newline at end of file
+}
+impl ClientSettingsPayload {
+    /// Names of the top-level sections the payload sets. Audit entries record these
+    /// rather than the values, which can hold API keys.
+    pub fn sections(&self) -> Vec<&'static str> {
+        [
+            ("visualisation", self.visualisation.is_some()),
+            ("system", self.system.is_some()),
+            ("xr", self.xr.is_some()),
+            ("auth", self.auth.is_some()),
+            ("ragflow", self.ragflow.is_some()),
+            ("perplexity", self.perplexity.is_some()),
+            ("openai", self.openai.is_some()),
+            ("kokoro", self.kokoro.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+}
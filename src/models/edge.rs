@@ -1,6 +1,39 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+// Edges built from shared topic counts carry no type of their own
+pub const TOPIC_EDGE_TYPE: &str = "topic";
+// Provenance entries kept per edge; beyond this the oldest two are folded together
+pub const MAX_PROVENANCE: usize = 8;
+
+/// What changed an edge's weight
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightSource {
+    TopicCounts,
+    // Re-applying the edge weight transform
+    Transform,
+    Similarity,
+    CoSelection,
+    Manual,
+    Agent,
+    Decay,
+    // Derived from the two edges of a node removed with rewiring
+    Rewired,
+    // Older contributions from different sources, folded together under the cap
+    Merged,
+}
+
+/// One change to an edge's weight. The contributions on an edge add up to its weight.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WeightContribution {
+    pub source: WeightSource,
+    pub contribution: f32,
+    pub timestamp: DateTime<Utc>,
+}
+
 /// Edge structure representing connections between nodes
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -9,10 +42,28 @@ pub struct Edge {
     pub source: u32,
     pub target: u32,
     pub weight: f32,
+    // Shared-topic count before the configured weight transform, for metadata edges
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_weight: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub edge_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<HashMap<String, String>>,
+    // Oldest first, at most MAX_PROVENANCE entries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<WeightContribution>,
+    // Visual-only edges are drawn but never sprung; only sent when false
+    #[serde(default = "default_affects_physics", skip_serializing_if = "is_true")]
+    pub affects_physics: bool,
+}
+
+/// Edges take part in physics unless told otherwise
+pub fn default_affects_physics() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
 }
 
 impl Edge {
@@ -24,8 +75,81 @@ impl Edge {
             source,
             target,
             weight,
+            raw_weight: None,
             edge_type: None,
             metadata: None,
+            provenance: Vec::new(),
+            affects_physics: true,
+        }
+    }
+
+    /// The edge with its whole current weight credited to `source`
+    pub fn credited_to(mut self, source: WeightSource, now: DateTime<Utc>) -> Self {
+        self.provenance.clear();
+        self.record(source, self.weight, now);
+        self
+    }
+
+    /// Notes a change of `contribution` to the weight. Zero changes leave no entry.
+    pub fn record(&mut self, source: WeightSource, contribution: f32, now: DateTime<Utc>) {
+        if contribution == 0.0 || !contribution.is_finite() {
+            return;
+        }
+        self.provenance.push(WeightContribution { source, contribution, timestamp: now });
+        self.cap_provenance();
+    }
+
+    /// Takes on another edge's trail, as when its weight is added to this one
+    pub fn absorb_provenance(&mut self, other: &Edge) {
+        self.provenance.extend_from_slice(&other.provenance);
+        self.provenance.sort_by_key(|c| c.timestamp);
+        self.cap_provenance();
+    }
+
+    fn cap_provenance(&mut self) {
+        while self.provenance.len() > MAX_PROVENANCE {
+            let oldest = self.provenance.remove(0);
+            let next = &mut self.provenance[0];
+            if next.source != oldest.source {
+                next.source = WeightSource::Merged;
+            }
+            next.contribution += oldest.contribution;
+        }
+    }
+
+    /// Heap bytes held by the provenance trail
+    pub fn provenance_bytes(&self) -> usize {
+        self.provenance.capacity() * std::mem::size_of::<WeightContribution>()
+    }
+
+    /// The edge's type, with untyped (shared-topic) edges reported as "topic"
+    pub fn type_name(&self) -> &str {
+        self.edge_type.as_deref().unwrap_or(TOPIC_EDGE_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_is_capped_by_folding_the_oldest() {
+        let start = Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        let mut edge = Edge::new(1, 2, 2.0).credited_to(WeightSource::TopicCounts, at(0));
+        edge.record(WeightSource::Manual, 0.0, at(1));
+        assert_eq!(edge.provenance.len(), 1);
+
+        for i in 1..=MAX_PROVENANCE as i64 {
+            edge.weight += 1.0;
+            edge.record(WeightSource::Manual, 1.0, at(i));
         }
+        assert_eq!(edge.provenance.len(), MAX_PROVENANCE);
+        assert_eq!(edge.provenance[0].source, WeightSource::Merged);
+        assert_eq!(edge.provenance[0].contribution, 3.0);
+        assert_eq!(edge.provenance[0].timestamp, at(1));
+        let total: f32 = edge.provenance.iter().map(|c| c.contribution).sum();
+        assert_eq!(total, edge.weight);
+        assert!(edge.provenance_bytes() >= MAX_PROVENANCE * std::mem::size_of::<WeightContribution>());
     }
 }
@@ -1,32 +1,214 @@
 use crate::models::node::Node;
 use super::edge::Edge;
 use super::metadata::MetadataStore;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// One separately shared part of the graph. Reading goes straight through; the first
+/// write while it is shared copies this part only, so moving nodes leaves the edges and
+/// metadata shared with every snapshot that is still out.
+#[derive(Default, Clone, Debug)]
+pub struct Segment<T>(Arc<T>);
+
+impl<T> Segment<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    /// Whether two segments are still the same allocation, i.e. neither was written since
+    /// one was copied from the other
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> From<T> for Segment<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: FromIterator<A>, A> FromIterator<A> for Segment<T> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T> Deref for Segment<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Segment<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Segment<T>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (**self).into_iter()
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a mut Segment<T>
+where
+    &'a mut T: IntoIterator,
+{
+    type Item = <&'a mut T as IntoIterator>::Item;
+    type IntoIter = <&'a mut T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (**self).into_iter()
+    }
+}
+
+impl<T: Serialize> Serialize for Segment<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Segment<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Segment::new)
+    }
+}
 
 /// Represents the graph data structure containing nodes, edges, and metadata.
-/// All fields use camelCase serialization for client compatibility.
+/// All fields use camelCase serialization for client compatibility. Nodes, edges and
+/// metadata are each a `Segment`, so copying the graph for a write copies only the part
+/// that write touches.
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GraphData {
-    /// List of nodes in the graph.
-    pub nodes: Vec<Node>,
+    /// List of nodes in the graph, positions included.
+    pub nodes: Segment<Vec<Node>>,
     /// List of edges connecting the nodes.
-    pub edges: Vec<Edge>,
+    pub edges: Segment<Vec<Edge>>,
     /// Metadata associated with the graph, using camelCase keys.
-    pub metadata: MetadataStore,
+    pub metadata: Segment<MetadataStore>,
     /// Mapping from numeric ID to metadata ID (filename) for lookup
     #[serde(skip)]
-    pub id_to_metadata: HashMap<String, String>,
+    pub id_to_metadata: Segment<HashMap<String, String>>,
+    /// Bumped by every change to nodes, edges, node attributes or edge weights, never by
+    /// position updates. The cache key for anything derived from the graph's content.
+    #[serde(default)]
+    pub generation: u64,
 }
 
 impl GraphData {
     pub fn new() -> Self {
-        Self {
-            nodes: Vec::new(),
-            edges: Vec::new(),
-            metadata: MetadataStore::new(),
-            id_to_metadata: HashMap::new(),
-        }
+        Self::default()
+    }
+}
+
+/// The graph as it was at one moment, for readers that take a while: exports, analytics,
+/// pagination, frame building. Taking one is O(1). The graph actor only writes through
+/// `Arc::make_mut`, so a write while a snapshot is out moves the live graph onto its own
+/// copy; the reader keeps a consistent graph and the writer never waits for it. That copy
+/// shares every segment the write leaves alone.
+pub type GraphSnapshot = Arc<GraphData>;
+
+/// Live statistics for the stats API. Attention is transient and only ever
+/// reported here, never stored with the graph.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub attention: Vec<crate::utils::attention::AttentionScore>,
+    /// Node ids by level-of-detail importance, most important first
+    pub lod_ranking: Vec<u32>,
+    pub generation: u64,
+    pub position_generation: u64,
+    pub layout_quality: crate::utils::layout_quality::LayoutQuality,
+    /// Per-partition timings of the last partitioned CPU physics step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub physics_partitions: Option<crate::utils::physics_partition::PartitionStats>,
+    /// Substeps the last physics step ran, and the real time physics trails by
+    pub physics_steps: crate::utils::simulation_clock::StepAccumulatorStatus,
+    /// Weight provenance entries across all edges, and the heap they take
+    pub provenance_entries: usize,
+    pub provenance_bytes: usize,
+    /// What the background refresh still has to recompute, and how long it last took
+    pub analytics_refresh: crate::utils::analytics_refresh::AnalyticsRefreshStatus,
+}
+
+/// The graph's two change counters: content, and layout
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphGenerations {
+    pub generation: u64,
+    pub position_generation: u64,
+}
+
+impl GraphGenerations {
+    /// Weak ETag for responses that include positions
+    pub fn etag(&self) -> String {
+        format!("W/\"g{}-p{}\"", self.generation, self.position_generation)
+    }
+}
+
+/// Metadata entries changed on an existing node
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeUpdate {
+    pub node_id: u32,
+    pub metadata: HashMap<String, String>,
+}
+
+/// A set of graph changes, pushed to clients and agents as a `graph_diff` event
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphDiff {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_nodes: Vec<Node>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub updated_nodes: Vec<NodeUpdate>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_nodes: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_edges: Vec<Edge>,
+    // Existing edges whose weight changed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub updated_edges: Vec<Edge>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_edges: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_annotations: Vec<crate::models::annotation::Annotation>,
+    // Graph generation once the change was applied; absent for dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.updated_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.updated_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.added_annotations.is_empty()
+    }
+
+    /// The broadcast form, tagged with who made the change
+    pub fn to_event(&self, actor: &str) -> serde_json::Value {
+        let mut event = serde_json::to_value(self).unwrap_or_else(|_| serde_json::json!({}));
+        event["type"] = serde_json::json!("graph_diff");
+        event["actor"] = serde_json::json!(actor);
+        event
     }
 }
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::models::node::Node;
+
+/// Picks nodes by what they are rather than by id. Every field that is set must match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GraphFilter {
+    // Case-insensitive substring of the label or metadata id
+    pub text: Option<String>,
+    pub node_type: Option<String>,
+    pub group: Option<String>,
+    // Exact metadata values, e.g. {"tags": "physics"}
+    pub metadata: HashMap<String, String>,
+}
+
+impl GraphFilter {
+    /// A filter with nothing set matches every node, which is rarely what a caller meant
+    pub fn is_empty(&self) -> bool {
+        self.text.as_deref().is_none_or(|t| t.trim().is_empty())
+            && self.node_type.is_none()
+            && self.group.is_none()
+            && self.metadata.is_empty()
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        if let Some(text) = self.text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            let needle = text.to_lowercase();
+            if !node.label.to_lowercase().contains(&needle)
+                && !node.metadata_id.to_lowercase().contains(&needle)
+            {
+                return false;
+            }
+        }
+        if self.node_type.is_some() && node.node_type != self.node_type {
+            return false;
+        }
+        if self.group.is_some() && node.group != self.group {
+            return false;
+        }
+        self.metadata.iter().all(|(key, value)| node.metadata.get(key) == Some(value))
+    }
+
+    /// Ids of the matching nodes, sorted so results come back in a stable order
+    pub fn select<'a>(&self, nodes: impl IntoIterator<Item = &'a Node>) -> Vec<u32> {
+        let mut ids: Vec<u32> = nodes.into_iter().filter(|n| self.matches(n)).map(|n| n.id).collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u32, metadata_id: &str, node_type: Option<&str>, tags: &str) -> Node {
+        let mut node = Node::new_with_id(metadata_id.to_string(), Some(id))
+            .with_label(metadata_id.trim_end_matches(".md").to_string())
+            .with_metadata("tags".to_string(), tags.to_string());
+        node.node_type = node_type.map(str::to_string);
+        node
+    }
+
+    #[test]
+    fn test_filter_requires_every_set_field() {
+        let nodes = vec![
+            node(1, "Quantum Field.md", Some("concept"), "physics"),
+            node(2, "quantum-notes.md", Some("note"), "physics"),
+            node(3, "Gardening.md", Some("concept"), "hobby"),
+        ];
+
+        let text = GraphFilter { text: Some("QUANTUM".into()), ..Default::default() };
+        assert_eq!(text.select(&nodes), vec![1, 2]);
+
+        let typed = GraphFilter { text: Some("quantum".into()), node_type: Some("concept".into()), ..Default::default() };
+        assert_eq!(typed.select(&nodes), vec![1]);
+
+        let tagged = GraphFilter {
+            metadata: HashMap::from([("tags".to_string(), "physics".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(tagged.select(&nodes), vec![1, 2]);
+
+        assert!(GraphFilter { text: Some("  ".into()), ..Default::default() }.is_empty());
+        assert!(!tagged.is_empty());
+    }
+}
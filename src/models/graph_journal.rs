@@ -0,0 +1,570 @@
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::models::edge::Edge;
+use crate::models::graph::{GraphData, GraphDiff};
+use crate::models::node::Node;
+use crate::models::node_attributes::AttributeSet;
+use crate::utils::graph_transaction::update_metadata;
+use crate::utils::json_store::write_json_atomic;
+
+pub const GRAPH_JOURNAL_DIR: &str = "/app/data/journal";
+const BASE_FILE: &str = "base.json";
+const LOG_FILE: &str = "journal.jsonl";
+// Records appended since the last compaction before the log is folded into the base
+const COMPACT_AFTER: usize = 1000;
+
+/// One runtime change to the graph. Nodes are named by metadata id, as numeric ids are
+/// handed out afresh by every build. Pins and merge aliases have stores of their own, so
+/// a merge is journaled as what it did to the graph and pins not at all.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalRecord {
+    AddNode { node: Node },
+    #[serde(rename_all = "camelCase")]
+    RemoveNode { metadata_id: String },
+    // Metadata entries set on the node, tags moving it between groups as usual
+    #[serde(rename_all = "camelCase")]
+    UpdateNode { metadata_id: String, metadata: HashMap<String, String> },
+    #[serde(rename_all = "camelCase")]
+    SetAttributes { metadata_id: String, set: AttributeSet },
+    // The edge's own source and target are stale once replayed; these name its ends
+    AddEdge { source: String, target: String, edge: Edge },
+    RemoveEdge { source: String, target: String },
+    SetEdgeWeight { source: String, target: String, weight: f32 },
+}
+
+impl JournalRecord {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JournalRecord::AddNode { .. } => "add_node",
+            JournalRecord::RemoveNode { .. } => "remove_node",
+            JournalRecord::UpdateNode { .. } => "update_node",
+            JournalRecord::SetAttributes { .. } => "set_attributes",
+            JournalRecord::AddEdge { .. } => "add_edge",
+            JournalRecord::RemoveEdge { .. } => "remove_edge",
+            JournalRecord::SetEdgeWeight { .. } => "set_edge_weight",
+        }
+    }
+
+    // Whether the record changes the node or an edge touching it; removals aren't counted
+    fn mentions(&self, metadata_id: &str) -> bool {
+        match self {
+            JournalRecord::AddNode { node } => node.metadata_id == metadata_id,
+            JournalRecord::UpdateNode { metadata_id: id, .. } | JournalRecord::SetAttributes { metadata_id: id, .. } => id == metadata_id,
+            JournalRecord::AddEdge { source, target, .. }
+            | JournalRecord::RemoveEdge { source, target }
+            | JournalRecord::SetEdgeWeight { source, target, .. } => source == metadata_id || target == metadata_id,
+            JournalRecord::RemoveNode { .. } => false,
+        }
+    }
+}
+
+/// The records for a change made by swapping `before` for `after`, as described by `diff`
+pub fn records_for_diff(before: &GraphData, after: &GraphData, diff: &GraphDiff) -> Vec<JournalRecord> {
+    let old_ids: HashMap<u32, &str> = before.nodes.iter().map(|n| (n.id, n.metadata_id.as_str())).collect();
+    let new_ids: HashMap<u32, &str> = after.nodes.iter().map(|n| (n.id, n.metadata_id.as_str())).collect();
+    let ends = |edge: &Edge, ids: &HashMap<u32, &str>| {
+        Some((ids.get(&edge.source)?.to_string(), ids.get(&edge.target)?.to_string()))
+    };
+    let mut records = Vec::new();
+    for edge_id in &diff.removed_edges {
+        if let Some((source, target)) = before.edges.iter().find(|e| &e.id == edge_id).and_then(|e| ends(e, &old_ids)) {
+            records.push(JournalRecord::RemoveEdge { source, target });
+        }
+    }
+    for node_id in &diff.removed_nodes {
+        if let Some(metadata_id) = old_ids.get(node_id) {
+            records.push(JournalRecord::RemoveNode { metadata_id: metadata_id.to_string() });
+        }
+    }
+    for added in &diff.added_nodes {
+        // The graph's copy has the position it was placed at
+        let node = after.nodes.iter().find(|n| n.id == added.id).unwrap_or(added);
+        records.push(JournalRecord::AddNode { node: node.clone() });
+    }
+    for update in &diff.updated_nodes {
+        if let Some(metadata_id) = new_ids.get(&update.node_id) {
+            records.push(JournalRecord::UpdateNode { metadata_id: metadata_id.to_string(), metadata: update.metadata.clone() });
+        }
+    }
+    for edge in &diff.added_edges {
+        if let Some((source, target)) = ends(edge, &new_ids) {
+            records.push(JournalRecord::AddEdge { source, target, edge: edge.clone() });
+        }
+    }
+    for edge in &diff.updated_edges {
+        if let Some((source, target)) = ends(edge, &new_ids) {
+            records.push(JournalRecord::SetEdgeWeight { source, target, weight: edge.weight });
+        }
+    }
+    records
+}
+
+/// The records for edges that were reweighted or removed while the nodes stayed as they
+/// are, built from the edges themselves rather than from a copy of the graph before
+pub fn records_for_edge_changes(graph: &GraphData, updated: &[Edge], removed: &[Edge]) -> Vec<JournalRecord> {
+    let ids: HashMap<u32, &str> = graph.nodes.iter().map(|n| (n.id, n.metadata_id.as_str())).collect();
+    let ends = |edge: &Edge| Some((ids.get(&edge.source)?.to_string(), ids.get(&edge.target)?.to_string()));
+    let removals = removed.iter()
+        .filter_map(&ends)
+        .map(|(source, target)| JournalRecord::RemoveEdge { source, target });
+    let updates = updated.iter()
+        .filter_map(|edge| ends(edge).map(|(source, target)| JournalRecord::SetEdgeWeight { source, target, weight: edge.weight }));
+    removals.chain(updates).collect()
+}
+
+/// Folds a run of records into the shortest run with the same effect: everything about a
+/// removed node goes, a node added and removed again leaves nothing, repeated updates and
+/// weight changes fold into one record or into the record that added the node or edge
+pub fn compact(records: Vec<JournalRecord>) -> Vec<JournalRecord> {
+    let mut out: Vec<Option<JournalRecord>> = Vec::with_capacity(records.len());
+    // Where the record still standing for a node or edge sits in `out`
+    let mut added: HashMap<String, usize> = HashMap::new();
+    let mut updated: HashMap<String, usize> = HashMap::new();
+    let mut edges: HashMap<(String, String), usize> = HashMap::new();
+
+    for record in records {
+        match record {
+            JournalRecord::RemoveNode { metadata_id } => {
+                for slot in out.iter_mut() {
+                    if slot.as_ref().is_some_and(|r| r.mentions(&metadata_id)) {
+                        *slot = None;
+                    }
+                }
+                updated.remove(&metadata_id);
+                edges.retain(|(source, target), _| *source != metadata_id && *target != metadata_id);
+                // A node that only ever existed at runtime needs no removal either
+                if added.remove(&metadata_id).is_none() {
+                    out.push(Some(JournalRecord::RemoveNode { metadata_id }));
+                }
+            }
+            JournalRecord::AddNode { node } => {
+                added.insert(node.metadata_id.clone(), out.len());
+                out.push(Some(JournalRecord::AddNode { node }));
+            }
+            JournalRecord::UpdateNode { metadata_id, metadata } => {
+                let standing = added.get(&metadata_id).or_else(|| updated.get(&metadata_id)).copied();
+                match standing.and_then(|i| out[i].as_mut()) {
+                    Some(JournalRecord::AddNode { node }) => update_metadata(node, &metadata),
+                    Some(JournalRecord::UpdateNode { metadata: earlier, .. }) => earlier.extend(metadata),
+                    _ => {
+                        updated.insert(metadata_id.clone(), out.len());
+                        out.push(Some(JournalRecord::UpdateNode { metadata_id, metadata }));
+                    }
+                }
+            }
+            JournalRecord::AddEdge { source, target, edge } => {
+                if let Some(i) = edges.insert((source.clone(), target.clone()), out.len()) {
+                    out[i] = None;
+                }
+                out.push(Some(JournalRecord::AddEdge { source, target, edge }));
+            }
+            JournalRecord::RemoveEdge { source, target } => {
+                if let Some(i) = edges.remove(&(source.clone(), target.clone())) {
+                    let was_added = matches!(out[i], Some(JournalRecord::AddEdge { .. }));
+                    out[i] = None;
+                    if was_added {
+                        continue;
+                    }
+                }
+                out.push(Some(JournalRecord::RemoveEdge { source, target }));
+            }
+            JournalRecord::SetEdgeWeight { source, target, weight } => {
+                let key = (source, target);
+                match edges.get(&key).and_then(|&i| out[i].as_mut()) {
+                    Some(JournalRecord::AddEdge { edge, .. }) => edge.weight = weight,
+                    Some(JournalRecord::SetEdgeWeight { weight: earlier, .. }) => *earlier = weight,
+                    _ => {
+                        edges.insert(key.clone(), out.len());
+                        out.push(Some(JournalRecord::SetEdgeWeight { source: key.0, target: key.1, weight }));
+                    }
+                }
+            }
+            record @ JournalRecord::SetAttributes { .. } => out.push(Some(record)),
+        }
+    }
+    out.into_iter().flatten().collect()
+}
+
+/// What replaying the journal onto a fresh build did, for the build report
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalReplay {
+    pub applied: usize,
+    // Records that no longer apply to the graph metadata builds, and why
+    pub skipped: Vec<String>,
+    // Set when the journal was cut back to its last valid record on load
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<String>,
+    // Hand-set colours the replayed attribute changes carry, by metadata id
+    #[serde(skip)]
+    pub color_overrides: Vec<(String, String)>,
+}
+
+/// Applies `records` in order on top of a graph built from metadata. New nodes take the
+/// ids `id_for` gives their metadata ids. Removals of what is already gone count as applied; records whose node
+/// or edge no longer exists are skipped and listed.
+pub fn replay(graph: &mut GraphData, records: &[JournalRecord], id_for: &mut dyn FnMut(&str) -> u32) -> JournalReplay {
+    let mut ids: HashMap<String, u32> = graph.nodes.iter().map(|n| (n.metadata_id.clone(), n.id)).collect();
+    let mut replay = JournalReplay::default();
+    for (i, record) in records.iter().enumerate() {
+        match apply_record(graph, &mut ids, record, id_for, &mut replay.color_overrides) {
+            Ok(()) => replay.applied += 1,
+            Err(reason) => replay.skipped.push(format!("record {} ({}): {}", i + 1, record.name(), reason)),
+        }
+    }
+    replay
+}
+
+fn end(ids: &HashMap<String, u32>, metadata_id: &str) -> Result<u32, String> {
+    ids.get(metadata_id).copied().ok_or_else(|| format!("node {} no longer exists", metadata_id))
+}
+
+fn node_mut<'a>(graph: &'a mut GraphData, ids: &HashMap<String, u32>, metadata_id: &str) -> Result<&'a mut Node, String> {
+    let id = end(ids, metadata_id)?;
+    graph.nodes.iter_mut().find(|n| n.id == id).ok_or_else(|| format!("node {} no longer exists", metadata_id))
+}
+
+fn apply_record(
+    graph: &mut GraphData,
+    ids: &mut HashMap<String, u32>,
+    record: &JournalRecord,
+    id_for: &mut dyn FnMut(&str) -> u32,
+    colors: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    match record {
+        JournalRecord::AddNode { node } => {
+            if ids.contains_key(&node.metadata_id) {
+                return Err(format!("node {} already exists", node.metadata_id));
+            }
+            let mut node = node.clone();
+            node.id = id_for(&node.metadata_id);
+            ids.insert(node.metadata_id.clone(), node.id);
+            graph.nodes.push(node);
+        }
+        JournalRecord::RemoveNode { metadata_id } => {
+            if let Some(id) = ids.remove(metadata_id) {
+                graph.nodes.retain(|n| n.id != id);
+                graph.edges.retain(|e| e.source != id && e.target != id);
+            }
+        }
+        JournalRecord::UpdateNode { metadata_id, metadata } => {
+            update_metadata(node_mut(graph, ids, metadata_id)?, metadata);
+        }
+        JournalRecord::SetAttributes { metadata_id, set } => {
+            set.apply(node_mut(graph, ids, metadata_id)?);
+            if let Some(color) = &set.color {
+                colors.push((metadata_id.clone(), color.clone()));
+            }
+        }
+        JournalRecord::AddEdge { source, target, edge } => {
+            let (source, target) = (end(ids, source)?, end(ids, target)?);
+            let mut edge = edge.clone();
+            edge.id = Edge::new(source, target, edge.weight).id;
+            edge.source = source;
+            edge.target = target;
+            match graph.edges.iter_mut().find(|e| e.id == edge.id) {
+                Some(existing) => *existing = edge,
+                None => graph.edges.push(edge),
+            }
+        }
+        JournalRecord::RemoveEdge { source, target } => {
+            if let (Some(source), Some(target)) = (ids.get(source), ids.get(target)) {
+                graph.edges.retain(|e| !(e.source == *source && e.target == *target));
+            }
+        }
+        JournalRecord::SetEdgeWeight { source, target, weight } => {
+            let (source_id, target_id) = (end(ids, source)?, end(ids, target)?);
+            let edge = graph.edges.iter_mut().find(|e| e.source == source_id && e.target == target_id)
+                .ok_or_else(|| format!("edge {} - {} no longer exists", source, target))?;
+            edge.weight = *weight;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    record: JournalRecord,
+}
+
+// Everything up to and including `seq`, compacted
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Base {
+    seq: u64,
+    records: Vec<JournalRecord>,
+}
+
+/// The journal as read back from disk
+#[derive(Debug, Default)]
+pub struct LoadedJournal {
+    pub records: Vec<JournalRecord>,
+    pub last_seq: u64,
+    // Why the log was cut back, if it had to be
+    pub truncated: Option<String>,
+}
+
+/// Reads the base and the log after it. A log line that doesn't parse, and everything
+/// after it, is cut off the file so the next append starts from the last valid record.
+pub fn read_journal(dir: &Path) -> Result<LoadedJournal, String> {
+    let base_path = dir.join(BASE_FILE);
+    let base = match fs::read_to_string(&base_path) {
+        Ok(content) => serde_json::from_str::<Base>(&content).map_err(|e| format!("Failed to parse {:?}: {}", base_path, e))?,
+        Err(_) => Base::default(),
+    };
+    let mut loaded = LoadedJournal { last_seq: base.seq, records: base.records, truncated: None };
+
+    let log_path = dir.join(LOG_FILE);
+    let Ok(content) = fs::read(&log_path) else {
+        return Ok(loaded);
+    };
+    let mut valid_len = 0;
+    while valid_len < content.len() {
+        let rest = &content[valid_len..];
+        // A line without its newline is a write that never finished
+        let parsed = rest.iter().position(|b| *b == b'\n').and_then(|end| {
+            serde_json::from_slice::<Entry>(&rest[..end]).ok().map(|entry| (entry, end + 1))
+        });
+        let Some((entry, len)) = parsed else {
+            let reason = format!("dropped {} bytes after record {}", content.len() - valid_len, loaded.last_seq);
+            warn!("Graph journal {:?} is corrupt, {}", log_path, reason);
+            OpenOptions::new().write(true).open(&log_path)
+                .and_then(|file| file.set_len(valid_len as u64))
+                .map_err(|e| format!("Failed to truncate {:?}: {}", log_path, e))?;
+            loaded.truncated = Some(reason);
+            break;
+        };
+        // Already folded into the base by a compaction that didn't get to clear the log
+        if entry.seq > loaded.last_seq {
+            loaded.last_seq = entry.seq;
+            loaded.records.push(entry.record);
+        }
+        valid_len += len;
+    }
+    Ok(loaded)
+}
+
+fn append_entries(dir: &Path, entries: &[Entry]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory {:?}: {}", dir, e))?;
+    let mut lines = String::new();
+    for entry in entries {
+        let line = serde_json::to_string(entry).map_err(|e| format!("Failed to serialize record {}: {}", entry.seq, e))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    let path = dir.join(LOG_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    file.write_all(lines.as_bytes()).and_then(|_| file.sync_data())
+        .map_err(|e| format!("Failed to append to {:?}: {}", path, e))
+}
+
+/// Folds the log into the base. The base is replaced before the log is cleared, and
+/// records the base already holds are skipped on load, so a crash in between is harmless.
+pub fn compact_files(dir: &Path) -> Result<usize, String> {
+    let loaded = read_journal(dir)?;
+    let records = compact(loaded.records);
+    let count = records.len();
+    write_json_atomic(&dir.join(BASE_FILE), &Base { seq: loaded.last_seq, records })?;
+    let log_path = dir.join(LOG_FILE);
+    fs::write(&log_path, b"").map_err(|e| format!("Failed to clear {:?}: {}", log_path, e))?;
+    Ok(count)
+}
+
+/// Runtime graph changes, kept in memory for replay after every build and appended to
+/// the log on disk by a background writer, so the graph actor never waits on the disk
+#[derive(Debug)]
+pub struct GraphJournal {
+    records: Vec<JournalRecord>,
+    appended: usize,
+    next_seq: u64,
+    truncated: Option<String>,
+    sender: mpsc::UnboundedSender<Vec<Entry>>,
+}
+
+impl GraphJournal {
+    /// Loads the journal in `dir` and starts its writer. Must run inside a Tokio runtime.
+    pub fn open(dir: PathBuf) -> Result<Self, String> {
+        let loaded = read_journal(&dir)?;
+        info!("Loaded {} graph journal records from {:?}", loaded.records.len(), dir);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_loop(dir, receiver));
+        Ok(Self {
+            records: loaded.records,
+            appended: 0,
+            next_seq: loaded.last_seq + 1,
+            truncated: loaded.truncated,
+            sender,
+        })
+    }
+
+    pub fn records(&self) -> &[JournalRecord] {
+        &self.records
+    }
+
+    /// The load-time truncation, reported once with the first replay
+    pub fn take_truncation(&mut self) -> Option<String> {
+        self.truncated.take()
+    }
+
+    pub fn append(&mut self, records: Vec<JournalRecord>) {
+        if records.is_empty() {
+            return;
+        }
+        let entries: Vec<Entry> = records.iter().cloned().map(|record| {
+            let entry = Entry { seq: self.next_seq, record };
+            self.next_seq += 1;
+            entry
+        }).collect();
+        if self.sender.send(entries).is_err() {
+            error!("Graph journal writer has stopped; {} records not persisted", records.len());
+        }
+        self.appended += records.len();
+        self.records.extend(records);
+        if self.appended >= COMPACT_AFTER {
+            self.records = compact(std::mem::take(&mut self.records));
+            self.appended = 0;
+        }
+    }
+}
+
+async fn write_loop(dir: PathBuf, mut receiver: mpsc::UnboundedReceiver<Vec<Entry>>) {
+    let mut appended = 0;
+    while let Some(mut batch) = receiver.recv().await {
+        // Whatever queued up during the last write goes out in this one
+        while let Ok(more) = receiver.try_recv() {
+            batch.extend(more);
+        }
+        let count = batch.len();
+        let write_dir = dir.clone();
+        match tokio::task::spawn_blocking(move || append_entries(&write_dir, &batch)).await {
+            Ok(Ok(())) => appended += count,
+            Ok(Err(e)) => error!("Graph journal write failed: {}", e),
+            Err(e) => error!("Graph journal write task failed: {}", e),
+        }
+        if appended >= COMPACT_AFTER {
+            let compact_dir = dir.clone();
+            match tokio::task::spawn_blocking(move || compact_files(&compact_dir)).await {
+                Ok(Ok(kept)) => info!("Compacted graph journal to {} records", kept),
+                Ok(Err(e)) => error!("Graph journal compaction failed: {}", e),
+                Err(e) => error!("Graph journal compaction task failed: {}", e),
+            }
+            appended = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("graph-journal-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn node(metadata_id: &str) -> Node {
+        Node::new_with_id(metadata_id.to_string(), Some(0)).with_label(metadata_id.to_string())
+    }
+
+    fn add_edge(source: &str, target: &str, weight: f32) -> JournalRecord {
+        JournalRecord::AddEdge { source: source.into(), target: target.into(), edge: Edge::new(0, 0, weight) }
+    }
+
+    fn entries(records: Vec<JournalRecord>, first_seq: u64) -> Vec<Entry> {
+        records.into_iter().zip(first_seq..).map(|(record, seq)| Entry { seq, record }).collect()
+    }
+
+    #[test]
+    fn test_replay_and_compaction_give_the_same_graph() {
+        // The graph a metadata build produces
+        let mut built = GraphData::new();
+        for (id, metadata_id) in [(1, "a"), (2, "b"), (3, "c")] {
+            built.nodes.push(Node::new_with_id(metadata_id.to_string(), Some(id)));
+        }
+        built.edges.push(Edge::new(1, 2, 1.0));
+
+        let records = vec![
+            JournalRecord::AddNode { node: node("runtime-1") },
+            add_edge("runtime-1", "a", 1.0),
+            JournalRecord::SetEdgeWeight { source: "runtime-1".into(), target: "a".into(), weight: 3.0 },
+            JournalRecord::UpdateNode { metadata_id: "runtime-1".into(), metadata: HashMap::from([("tags".into(), "draft".into())]) },
+            JournalRecord::AddNode { node: node("runtime-2") },
+            add_edge("runtime-2", "b", 1.0),
+            JournalRecord::RemoveNode { metadata_id: "runtime-2".into() },
+            JournalRecord::RemoveEdge { source: "a".into(), target: "b".into() },
+            JournalRecord::SetAttributes { metadata_id: "c".into(), set: AttributeSet { color: Some("#ff0000".into()), ..Default::default() } },
+            JournalRecord::RemoveNode { metadata_id: "gone".into() },
+            add_edge("runtime-1", "missing", 1.0),
+        ];
+
+        // Written in two batches with a compaction between, then read back
+        let dir = temp_dir();
+        append_entries(&dir, &entries(records[..6].to_vec(), 1)).unwrap();
+        compact_files(&dir).unwrap();
+        append_entries(&dir, &entries(records[6..].to_vec(), 7)).unwrap();
+        let loaded = read_journal(&dir).unwrap();
+        assert_eq!(loaded.last_seq, 11);
+        assert!(loaded.truncated.is_none());
+
+        let compacted = compact(records.clone());
+        assert_eq!(compacted.iter().map(JournalRecord::name).collect::<Vec<_>>(),
+            vec!["add_node", "add_edge", "remove_edge", "set_attributes", "remove_node", "add_edge"]);
+
+        let mut results = Vec::new();
+        for run in [records, loaded.records, compacted] {
+            let mut graph = built.clone();
+            let mut next_id = 10;
+            let replay = replay(&mut graph, &run, &mut |_| { next_id += 1; next_id - 1 });
+            results.push((graph, replay));
+        }
+        for (graph, replay) in &results {
+            let mut labels: Vec<&str> = graph.nodes.iter().map(|n| n.metadata_id.as_str()).collect();
+            labels.sort();
+            assert_eq!(labels, vec!["a", "b", "c", "runtime-1"]);
+            let runtime = graph.nodes.iter().find(|n| n.metadata_id == "runtime-1").unwrap();
+            assert!(runtime.id >= 10);
+            assert_eq!(runtime.group.as_deref(), Some("draft"));
+            assert_eq!(graph.edges.len(), 1);
+            assert_eq!((graph.edges[0].source, graph.edges[0].target, graph.edges[0].weight), (runtime.id, 1, 3.0));
+            assert_eq!(graph.nodes.iter().find(|n| n.metadata_id == "c").unwrap().color.as_deref(), Some("#ff0000"));
+            assert_eq!(replay.color_overrides, vec![("c".to_string(), "#ff0000".to_string())]);
+            // The edge to a node metadata no longer has is the one record that can't apply
+            assert_eq!(replay.skipped.len(), 1);
+            assert!(replay.skipped[0].contains("node missing no longer exists"), "{:?}", replay.skipped);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_log_is_truncated_to_the_last_valid_record() {
+        let dir = temp_dir();
+        let records: Vec<JournalRecord> = ["x", "y", "z"].iter().map(|id| JournalRecord::AddNode { node: node(id) }).collect();
+        append_entries(&dir, &entries(records, 1)).unwrap();
+        let valid_len = fs::metadata(dir.join(LOG_FILE)).unwrap().len();
+
+        // A torn line in the middle, and a good record after it that can't be trusted
+        let mut file = OpenOptions::new().append(true).open(dir.join(LOG_FILE)).unwrap();
+        file.write_all(b"{\"seq\":4,\"record\":{\"op\":\"add_no\n").unwrap();
+        drop(file);
+        append_entries(&dir, &entries(vec![JournalRecord::RemoveNode { metadata_id: "x".into() }], 5)).unwrap();
+
+        let loaded = read_journal(&dir).unwrap();
+        assert_eq!(loaded.records.len(), 3);
+        assert_eq!(loaded.last_seq, 3);
+        assert!(loaded.truncated.unwrap().contains("after record 3"));
+        assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), valid_len);
+
+        // Appends carry on from the last valid record, and the file reads clean again
+        append_entries(&dir, &entries(vec![JournalRecord::RemoveNode { metadata_id: "y".into() }], 4)).unwrap();
+        let loaded = read_journal(&dir).unwrap();
+        assert!(loaded.truncated.is_none());
+        assert_eq!(loaded.last_seq, 4);
+        assert_eq!(loaded.records.last().unwrap().name(), "remove_node");
+    }
+}
@@ -0,0 +1,185 @@
+use glam::{Quat, Vec3};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+// Larger selections should go through a layout operation, not a drag
+pub const MAX_GROUP_SIZE: usize = 2000;
+const UNIT_QUAT_TOLERANCE: f32 = 1e-3;
+
+/// A box/lasso selection moved as one rigid body, as sent by the client
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupTransformRequest {
+    pub node_ids: Vec<u32>,
+    pub translation: [f32; 3],
+    // x, y, z, w
+    #[serde(default)]
+    pub rotation: Option<[f32; 4]>,
+    pub pivot: [f32; 3],
+}
+
+/// A validated rotation about `pivot` followed by `translation`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RigidTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub pivot: Vec3,
+}
+
+impl RigidTransform {
+    pub fn new(translation: Vec3, rotation: Option<Quat>, pivot: Vec3) -> Result<Self, String> {
+        if !translation.is_finite() || !pivot.is_finite() {
+            return Err("Transform contains non-finite values".to_string());
+        }
+        let rotation = match rotation {
+            Some(q) if !q.is_finite() => return Err("Rotation contains non-finite values".to_string()),
+            Some(q) if (q.length() - 1.0).abs() > UNIT_QUAT_TOLERANCE => {
+                return Err(format!("Rotation must be a unit quaternion (length {:.4})", q.length()));
+            }
+            // Renormalise so small client-side drift can't scale the group
+            Some(q) => q.normalize(),
+            None => Quat::IDENTITY,
+        };
+        Ok(Self { translation, rotation, pivot })
+    }
+
+    pub fn apply(&self, position: Vec3) -> Vec3 {
+        self.pivot + self.rotation * (position - self.pivot) + self.translation
+    }
+}
+
+/// Node ids in request order with duplicates dropped
+pub fn dedup_group(node_ids: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    node_ids.iter().copied().filter(|id| seen.insert(*id)).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NodeLock {
+    client_id: usize,
+    until: Instant,
+}
+
+/// Short-lived per-node ownership, so two clients can't drag the same nodes at once.
+/// Locks lapse on their own; a client that disconnects mid-drag just lets them expire.
+#[derive(Debug, Default)]
+pub struct NodeLocks {
+    held: HashMap<u32, NodeLock>,
+}
+
+impl NodeLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks every node for `client_id` or none of them. Fails with the nodes held by
+    /// someone else; the client's own locks are extended.
+    pub fn try_lock_all(&mut self, client_id: usize, node_ids: &[u32], until: Instant, now: Instant) -> Result<(), Vec<u32>> {
+        let mut conflicts: Vec<u32> = node_ids.iter()
+            .copied()
+            .filter(|id| self.is_held_by_other(*id, client_id, now))
+            .collect();
+        if !conflicts.is_empty() {
+            conflicts.sort_unstable();
+            return Err(conflicts);
+        }
+        for id in node_ids {
+            self.held.insert(*id, NodeLock { client_id, until });
+        }
+        Ok(())
+    }
+
+    pub fn is_held_by_other(&self, node_id: u32, client_id: usize, now: Instant) -> bool {
+        self.held.get(&node_id).is_some_and(|lock| lock.client_id != client_id && lock.until > now)
+    }
+
+    pub fn release(&mut self, node_id: u32) {
+        self.held.remove(&node_id);
+    }
+
+    pub fn prune(&mut self, now: Instant) {
+        self.held.retain(|_, lock| lock.until > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TOLERANCE: f32 = 1e-4;
+
+    fn group() -> Vec<Vec3> {
+        vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(3.0, 1.0, -2.0),
+            Vec3::new(-4.5, 2.0, 7.0),
+            Vec3::new(10.0, -6.0, 0.5),
+        ]
+    }
+
+    fn assert_rigid(before: &[Vec3], after: &[Vec3]) {
+        for i in 0..before.len() {
+            for j in (i + 1)..before.len() {
+                let (d0, d1) = (before[i].distance(before[j]), after[i].distance(after[j]));
+                assert!((d0 - d1).abs() < TOLERANCE, "pair {}-{}: {} vs {}", i, j, d0, d1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_translation_moves_every_node_alike() {
+        let transform = RigidTransform::new(Vec3::new(5.0, -2.0, 1.0), None, Vec3::ZERO).unwrap();
+        let before = group();
+        let after: Vec<Vec3> = before.iter().map(|p| transform.apply(*p)).collect();
+        for (b, a) in before.iter().zip(&after) {
+            assert!((*a - *b).distance(Vec3::new(5.0, -2.0, 1.0)) < TOLERANCE);
+        }
+        assert_rigid(&before, &after);
+    }
+
+    #[test]
+    fn test_rotation_about_pivot_is_rigid() {
+        let rotation = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 0.5).normalize(), 1.1);
+        let pivot = Vec3::new(2.0, 2.0, 2.0);
+        let transform = RigidTransform::new(Vec3::new(0.0, 4.0, 0.0), Some(rotation), pivot).unwrap();
+        let before = group();
+        let after: Vec<Vec3> = before.iter().map(|p| transform.apply(*p)).collect();
+        assert_rigid(&before, &after);
+        // The pivot itself only translates
+        assert!(transform.apply(pivot).distance(pivot + Vec3::new(0.0, 4.0, 0.0)) < TOLERANCE);
+    }
+
+    #[test]
+    fn test_rejects_non_unit_rotation() {
+        assert!(RigidTransform::new(Vec3::ZERO, Some(Quat::from_xyzw(0.0, 0.0, 0.0, 2.0)), Vec3::ZERO).is_err());
+        assert!(RigidTransform::new(Vec3::ZERO, Some(Quat::from_xyzw(f32::NAN, 0.0, 0.0, 1.0)), Vec3::ZERO).is_err());
+        assert!(RigidTransform::new(Vec3::new(f32::INFINITY, 0.0, 0.0), None, Vec3::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_group_lock_is_all_or_nothing() {
+        let now = Instant::now();
+        let until = now + Duration::from_secs(2);
+        let mut locks = NodeLocks::new();
+        locks.try_lock_all(1, &[5], until, now).unwrap();
+
+        // Client 2 can't take a group that overlaps client 1's node, and gets nothing
+        assert_eq!(locks.try_lock_all(2, &[3, 4, 5], until, now), Err(vec![5]));
+        assert!(!locks.is_held_by_other(3, 1, now));
+
+        // Client 1 can extend its own lock over a larger group
+        locks.try_lock_all(1, &[3, 4, 5], until, now).unwrap();
+        assert!(locks.is_held_by_other(4, 2, now));
+
+        // Once the locks lapse anyone can take them
+        let later = until + Duration::from_millis(1);
+        locks.try_lock_all(2, &[3, 4, 5], later + Duration::from_secs(1), later).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_keeps_order() {
+        assert_eq!(dedup_group(&[4, 2, 4, 9, 2]), vec![4, 2, 9]);
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::vec3::Vec3Data;
+
+pub const LAYOUT_FORMAT_VERSION: u32 = 1;
+
+/// A node's saved position, keyed by metadata id so it survives id reassignment on rebuild
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeLayout {
+    pub metadata_id: String,
+    pub position: Vec3Data,
+}
+
+/// On-disk layout format shared by snapshots and save-on-shutdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutFile {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    // Position generation of the graph when the layout was taken
+    pub generation: u64,
+    pub nodes: Vec<NodeLayout>,
+}
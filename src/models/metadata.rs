@@ -27,6 +27,64 @@ pub struct Metadata {
     pub last_perplexity_process: Option<DateTime<Utc>>,
     #[serde(default)]
     pub topic_counts: HashMap<String, usize>,
+    // Topics the file covers, where the pipeline supplies them; edge recomputation
+    // derives topic_counts from these
+    #[serde(default)]
+    pub topics: Vec<String>,
+    // Manually set or accepted tags; the auto-tag job never writes these
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Proposed by the auto-tag job, waiting for review
+    #[serde(default)]
+    pub auto_tags: Vec<String>,
+    #[serde(default)]
+    pub rejected_tags: Vec<String>,
+    // sha1 of the content the current proposals were made for
+    #[serde(default)]
+    pub auto_tagged_sha1: String,
+}
+
+impl Metadata {
+    /// Stores proposals for `sha1`, dropping any that are already tags or were rejected before
+    pub fn set_auto_tags(&mut self, proposals: Vec<String>, sha1: &str) {
+        let mut auto_tags: Vec<String> = Vec::new();
+        for tag in proposals {
+            if !self.tags.contains(&tag) && !self.rejected_tags.contains(&tag) && !auto_tags.contains(&tag) {
+                auto_tags.push(tag);
+            }
+        }
+        self.auto_tags = auto_tags;
+        self.auto_tagged_sha1 = sha1.to_string();
+    }
+
+    /// Promotes accepted proposals to `tags` and remembers rejected ones. Tags that
+    /// aren't pending proposals are ignored.
+    pub fn review_auto_tags(&mut self, accept: &[String], reject: &[String]) {
+        for tag in accept {
+            if let Some(pos) = self.auto_tags.iter().position(|t| t == tag) {
+                let tag = self.auto_tags.remove(pos);
+                if !self.tags.contains(&tag) {
+                    self.tags.push(tag);
+                }
+            }
+        }
+        for tag in reject {
+            if let Some(pos) = self.auto_tags.iter().position(|t| t == tag) {
+                let tag = self.auto_tags.remove(pos);
+                if !self.rejected_tags.contains(&tag) {
+                    self.rejected_tags.push(tag);
+                }
+            }
+        }
+    }
+
+    /// Keeps tag state from the previous entry when a file is re-fetched
+    pub fn carry_over_tags(&mut self, previous: &Metadata) {
+        self.tags = previous.tags.clone();
+        self.auto_tags = previous.auto_tags.clone();
+        self.rejected_tags = previous.rejected_tags.clone();
+        self.auto_tagged_sha1 = previous.auto_tagged_sha1.clone();
+    }
 }
 
 // Default function for node_id to ensure backward compatibility
@@ -0,0 +1,225 @@
+//! Which node metadata keys exist and what their values look like. Node metadata is a
+//! map of strings, so the schema is what tells a client that `fileSize` is a whole number
+//! and stops a misspelt key quietly becoming a new one. The keys the server writes itself
+//! are built in; `system.metadata_schema` declares more. Keys the schema doesn't know are
+//! allowed unless the schema is strict.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::MetadataSchemaSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataType {
+    Int,
+    Float,
+    String,
+    // RFC 3339, or a plain YYYY-MM-DD
+    Date,
+    // One of the key's `values`
+    Enum,
+}
+
+/// One key's declaration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataKey {
+    #[serde(rename = "type")]
+    pub value_type: MetadataType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl MetadataKey {
+    fn of(value_type: MetadataType, description: &str) -> Self {
+        Self { value_type, values: Vec::new(), description: description.to_string() }
+    }
+}
+
+/// Why a value was turned down
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataViolation {
+    pub key: String,
+    pub value: String,
+    // Absent for keys the strict schema doesn't know
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<MetadataType>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaEntry {
+    #[serde(flatten)]
+    pub key: MetadataKey,
+    // Written by the server; settings can't redeclare these
+    pub builtin: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataSchema {
+    pub strict: bool,
+    pub keys: BTreeMap<String, SchemaEntry>,
+}
+
+fn builtin_keys() -> [(&'static str, MetadataKey); 15] {
+    use MetadataType::*;
+    [
+        ("metadataId", MetadataKey::of(String, "The file the node was built from")),
+        ("name", MetadataKey::of(String, "")),
+        ("fileName", MetadataKey::of(String, "")),
+        ("fileSize", MetadataKey::of(Int, "Bytes")),
+        ("nodeSize", MetadataKey::of(Float, "")),
+        ("hyperlinkCount", MetadataKey::of(Int, "")),
+        ("sha1", MetadataKey::of(String, "")),
+        ("lastModified", MetadataKey::of(Date, "")),
+        ("perplexityLink", MetadataKey::of(String, "")),
+        ("lastPerplexityProcess", MetadataKey::of(Date, "")),
+        ("tags", MetadataKey::of(String, "Comma-separated")),
+        ("autoTags", MetadataKey::of(String, "Comma-separated proposals awaiting review")),
+        ("aliases", MetadataKey::of(String, "Comma-separated")),
+        ("source", MetadataKey::of(String, "What created the node, e.g. agent")),
+        ("createdBy", MetadataKey::of(String, "")),
+    ]
+}
+
+fn parses_as_date(value: &str) -> bool {
+    DateTime::parse_from_rfc3339(value).is_ok()
+        // How chrono prints a UTC time, which some builds store
+        || NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f UTC").is_ok()
+        || NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+}
+
+impl MetadataSchema {
+    pub fn new(settings: &MetadataSchemaSettings) -> Self {
+        let mut keys: BTreeMap<String, SchemaEntry> = builtin_keys().into_iter()
+            .map(|(name, key)| (name.to_string(), SchemaEntry { key, builtin: true }))
+            .collect();
+        for (name, key) in &settings.keys {
+            if keys.contains_key(name) {
+                warn!("Ignoring metadata schema entry for built-in key {}", name);
+                continue;
+            }
+            if key.value_type == MetadataType::Enum && key.values.is_empty() {
+                warn!("Metadata key {} is an enum with no values; nothing will be accepted for it", name);
+            }
+            keys.insert(name.clone(), SchemaEntry { key: key.clone(), builtin: false });
+        }
+        Self { strict: settings.strict, keys }
+    }
+
+    /// Checks one value against its key's declaration
+    pub fn check(&self, key: &str, value: &str) -> Result<(), MetadataViolation> {
+        let violation = |expected: Option<MetadataType>, allowed: Vec<String>, reason: String| MetadataViolation {
+            key: key.to_string(),
+            value: value.to_string(),
+            expected,
+            allowed,
+            reason,
+        };
+        let Some(entry) = self.keys.get(key) else {
+            if self.strict {
+                return Err(violation(None, Vec::new(), "unknown key; the metadata schema is strict".to_string()));
+            }
+            return Ok(());
+        };
+        let expected = entry.key.value_type;
+        let valid = match expected {
+            MetadataType::Int => value.trim().parse::<i64>().is_ok(),
+            MetadataType::Float => value.trim().parse::<f64>().is_ok_and(f64::is_finite),
+            MetadataType::String => true,
+            MetadataType::Date => parses_as_date(value.trim()),
+            MetadataType::Enum => entry.key.values.iter().any(|allowed| allowed == value),
+        };
+        if valid {
+            return Ok(());
+        }
+        let (allowed, reason) = match expected {
+            MetadataType::Int => (Vec::new(), "expected a whole number".to_string()),
+            MetadataType::Float => (Vec::new(), "expected a number".to_string()),
+            MetadataType::Date => (Vec::new(), "expected an RFC 3339 timestamp or YYYY-MM-DD date".to_string()),
+            MetadataType::Enum => (entry.key.values.clone(), "expected one of the allowed values".to_string()),
+            MetadataType::String => unreachable!("every string is valid"),
+        };
+        Err(violation(Some(expected), allowed, reason))
+    }
+
+    /// Every violation among `entries`, by key
+    pub fn validate(&self, entries: &HashMap<String, String>) -> Vec<MetadataViolation> {
+        let mut violations: Vec<MetadataViolation> = entries.iter()
+            .filter_map(|(key, value)| self.check(key, value).err())
+            .collect();
+        violations.sort_by(|a, b| a.key.cmp(&b.key));
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(strict: bool) -> MetadataSchema {
+        let status = MetadataKey {
+            value_type: MetadataType::Enum,
+            values: vec!["draft".to_string(), "published".to_string()],
+            description: String::new(),
+        };
+        let settings = MetadataSchemaSettings {
+            strict,
+            keys: BTreeMap::from([
+                ("status".to_string(), status),
+                ("rating".to_string(), MetadataKey::of(MetadataType::Float, "")),
+                // Built in already, so this is ignored
+                ("fileSize".to_string(), MetadataKey::of(MetadataType::String, "")),
+            ]),
+        };
+        MetadataSchema::new(&settings)
+    }
+
+    #[test]
+    fn test_each_type_is_checked() {
+        let schema = schema(false);
+        assert!(schema.check("fileSize", "1024").is_ok());
+        assert_eq!(schema.check("fileSize", "1.5k").unwrap_err().expected, Some(MetadataType::Int));
+        assert!(schema.check("rating", "4.5").is_ok());
+        assert!(schema.check("rating", "NaN").is_err());
+        assert!(schema.check("lastModified", "2024-03-01T12:00:00Z").is_ok());
+        assert!(schema.check("lastModified", "2024-03-01").is_ok());
+        assert!(schema.check("lastModified", "2024-03-01 12:00:00 UTC").is_ok());
+        assert!(schema.check("lastModified", "yesterday").is_err());
+        assert!(schema.check("sha1", "anything at all").is_ok());
+        assert!(schema.check("status", "draft").is_ok());
+        let violation = schema.check("status", "Draft").unwrap_err();
+        assert_eq!((violation.expected, violation.allowed.len()), (Some(MetadataType::Enum), 2));
+    }
+
+    #[test]
+    fn test_strict_schema_rejects_unknown_keys() {
+        let entries = HashMap::from([
+            ("statsu".to_string(), "draft".to_string()),
+            ("rating".to_string(), "high".to_string()),
+        ]);
+        // Lax: only the typed key is wrong
+        let lax = schema(false).validate(&entries);
+        assert_eq!(lax.iter().map(|v| v.key.as_str()).collect::<Vec<_>>(), ["rating"]);
+        let strict = schema(true).validate(&entries);
+        assert_eq!(strict.iter().map(|v| v.key.as_str()).collect::<Vec<_>>(), ["rating", "statsu"]);
+        assert_eq!(strict[1].expected, None);
+
+        // What clients read to build their forms
+        let exposed = serde_json::to_value(schema(true)).unwrap();
+        assert_eq!(exposed["strict"], true);
+        assert_eq!(exposed["keys"]["fileSize"], serde_json::json!({ "type": "int", "description": "Bytes", "builtin": true }));
+        assert_eq!(exposed["keys"]["status"]["values"], serde_json::json!(["draft", "published"]));
+        assert_eq!(exposed["keys"]["status"]["builtin"], false);
+    }
+}
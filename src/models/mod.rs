@@ -1,14 +1,27 @@
+pub mod annotation;
 pub mod edge;
 pub mod graph;
+pub mod graph_filter;
+pub mod graph_journal;
+pub mod group_transform;
+pub mod layout;
 pub mod metadata;
+pub mod metadata_schema;
 pub mod node;
+pub mod node_aliases;
+pub mod node_attributes;
+pub mod node_ids;
 pub mod pagination;
+pub mod pins;
+pub mod position_history;
 pub mod protected_settings;
+pub mod saved_view;
 pub mod simulation_params;
 pub mod ui_settings;
 pub mod user_settings;
 pub mod client_settings_payload; // Add new module
 pub mod ragflow_chat;
+pub mod spatial_anchor;
 
 pub use metadata::MetadataStore;
 pub use pagination::PaginationParams;
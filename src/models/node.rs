@@ -17,7 +17,7 @@ pub struct Node {
     pub data: BinaryNodeData,
 
     // Metadata
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub metadata: HashMap<String, String>,
     #[serde(skip)]
     pub file_size: u64,
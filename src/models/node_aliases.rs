@@ -0,0 +1,144 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::models::metadata::MetadataStore;
+use crate::utils::json_store::write_json_atomic;
+
+pub const NODE_ALIASES_PATH: &str = "/app/data/node_aliases.json";
+// Guards against a hand-edited file with a cycle in it
+const MAX_ALIAS_HOPS: usize = 16;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AliasFile {
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+}
+
+/// Metadata ids merged into another node, keyed by the merged id. Rebuilds fold an
+/// aliased file into the node it was merged into instead of giving it a node of its own.
+#[derive(Debug, Default)]
+pub struct AliasStore {
+    path: Option<PathBuf>,
+    aliases: HashMap<String, String>,
+}
+
+impl AliasStore {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let file = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<AliasFile>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse node aliases {:?}: {}. Starting without aliases.", path, e);
+                AliasFile::default()
+            }),
+            Err(_) => AliasFile::default(),
+        };
+        if !file.aliases.is_empty() {
+            info!("Loaded {} node aliases from {:?}", file.aliases.len(), path);
+        }
+        Self { path: Some(path), aliases: file.aliases }
+    }
+
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    /// The metadata id `metadata_id` ends up as, following merges of merges
+    pub fn resolve<'a>(&'a self, metadata_id: &'a str) -> &'a str {
+        let mut current = metadata_id;
+        for _ in 0..MAX_ALIAS_HOPS {
+            match self.aliases.get(current) {
+                Some(next) if next != current => current = next,
+                _ => break,
+            }
+        }
+        current
+    }
+
+    /// Ids that resolve to `metadata_id`, sorted
+    pub fn aliases_of(&self, metadata_id: &str) -> Vec<String> {
+        let mut aliases: Vec<String> = self.aliases.keys()
+            .filter(|alias| alias.as_str() != metadata_id && self.resolve(alias) == metadata_id)
+            .cloned()
+            .collect();
+        aliases.sort();
+        aliases
+    }
+
+    /// Records `merged` as aliases of `keep`, written through to disk when file-backed
+    pub fn add(&mut self, merged: &[String], keep: &str) -> Result<(), String> {
+        let previous = self.aliases.clone();
+        for id in merged {
+            self.aliases.insert(id.clone(), keep.to_string());
+        }
+        // The kept id may itself have been merged away before; it's a node again now
+        self.aliases.remove(keep);
+        self.save().inspect_err(|_| {
+            self.aliases = previous;
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => write_json_atomic(path, &AliasFile { aliases: self.aliases.clone() }),
+            None => Ok(()),
+        }
+    }
+
+    /// `metadata` with every aliased file folded into the one it was merged into: its
+    /// topic counts and tags move over, and links to it point at the kept file. An alias
+    /// whose kept file is gone is ignored, so the file gets its node back.
+    pub fn fold(&self, metadata: &MetadataStore) -> MetadataStore {
+        if self.aliases.is_empty() {
+            return metadata.clone();
+        }
+        let target_file = |file_name: &str| -> Option<String> {
+            let metadata_id = file_name.trim_end_matches(".md");
+            let resolved = self.resolve(metadata_id);
+            let resolved_file = format!("{}.md", resolved);
+            (resolved != metadata_id && metadata.contains_key(&resolved_file)).then_some(resolved_file)
+        };
+
+        let mut folded = MetadataStore::new();
+        let mut absorbed = Vec::new();
+        for (file_name, entry) in metadata {
+            match target_file(file_name) {
+                Some(target) => absorbed.push((target, entry)),
+                None => {
+                    folded.insert(file_name.clone(), entry.clone());
+                }
+            }
+        }
+        // Sorted so tags come out in the same order on every rebuild
+        absorbed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.file_name.cmp(&b.1.file_name)));
+        for (target, entry) in absorbed {
+            let Some(kept) = folded.get_mut(&target) else {
+                continue;
+            };
+            for (topic, count) in &entry.topic_counts {
+                *kept.topic_counts.entry(topic.clone()).or_insert(0) += count;
+            }
+            for tag in &entry.tags {
+                if !kept.tags.contains(tag) {
+                    kept.tags.push(tag.clone());
+                }
+            }
+        }
+        for entry in folded.values_mut() {
+            if entry.topic_counts.keys().any(|topic| target_file(topic).is_some()) {
+                let mut counts = HashMap::new();
+                for (topic, count) in entry.topic_counts.drain() {
+                    let topic = target_file(&topic).unwrap_or(topic);
+                    *counts.entry(topic).or_insert(0) += count;
+                }
+                entry.topic_counts = counts;
+            }
+        }
+        folded
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::models::node::Node;
+use crate::utils::physics_flags::{self, PhysicsFlagsPatch};
+
+const MAX_METADATA_KEY_LEN: usize = 64;
+const MAX_METADATA_VALUE_LEN: usize = 1000;
+
+/// Attributes to set on a batch of nodes; anything left out is left alone
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AttributeSet {
+    pub group: Option<String>,
+    pub color: Option<String>,
+    pub node_type: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub physics_flags: Option<PhysicsFlagsPatch>,
+}
+
+/// `#rgb` or `#rrggbb`, the forms clients already render
+pub fn is_valid_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+impl AttributeSet {
+    pub fn is_empty(&self) -> bool {
+        self.group.is_none() && self.color.is_none() && self.node_type.is_none() && self.metadata.is_empty()
+            && self.physics_flags.is_none_or(|patch| patch.is_empty())
+    }
+
+    /// Checks the values before anything is applied. An empty `known_types` allows any type.
+    /// Returns the offending field and why.
+    pub fn validate(&self, known_types: &[String]) -> Result<(), (String, String)> {
+        if let Some(color) = &self.color {
+            if !is_valid_color(color) {
+                return Err(("set.color".into(), format!("'{}' is not a #rgb or #rrggbb colour", color)));
+            }
+        }
+        if let Some(node_type) = &self.node_type {
+            if !known_types.is_empty() && !known_types.contains(node_type) {
+                return Err(("set.nodeType".into(), format!("unknown node type '{}'", node_type)));
+            }
+        }
+        if let Some(group) = &self.group {
+            if group.trim().is_empty() {
+                return Err(("set.group".into(), "must not be empty".into()));
+            }
+        }
+        for (key, value) in &self.metadata {
+            if key.trim().is_empty() || key.len() > MAX_METADATA_KEY_LEN {
+                return Err(("set.metadata".into(), format!("keys must be 1-{} characters", MAX_METADATA_KEY_LEN)));
+            }
+            // The id ties a node to its file; changing it here would orphan the node
+            if key == "metadataId" {
+                return Err(("set.metadata".into(), "metadataId cannot be changed".into()));
+            }
+            if value.len() > MAX_METADATA_VALUE_LEN {
+                return Err((
+                    "set.metadata".into(),
+                    format!("value for '{}' is over {} characters", key, MAX_METADATA_VALUE_LEN),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the attributes onto a node. Returns the keys whose value actually changed.
+    pub fn apply(&self, node: &mut Node) -> Vec<String> {
+        let mut changed = Vec::new();
+        if let Some(group) = &self.group {
+            if node.group.as_ref() != Some(group) {
+                node.group = Some(group.clone());
+                changed.push("group".to_string());
+            }
+        }
+        if let Some(color) = &self.color {
+            if node.color.as_ref() != Some(color) {
+                node.color = Some(color.clone());
+                changed.push("color".to_string());
+            }
+        }
+        if let Some(node_type) = &self.node_type {
+            if node.node_type.as_ref() != Some(node_type) {
+                node.node_type = Some(node_type.clone());
+                changed.push("nodeType".to_string());
+            }
+        }
+        if let Some(patch) = &self.physics_flags {
+            let flags = patch.apply(node.data.flags);
+            if flags != node.data.flags {
+                node.data.flags = flags;
+                changed.push("physicsFlags".to_string());
+            }
+        }
+        let mut keys: Vec<&String> = self.metadata.keys().collect();
+        keys.sort();
+        for key in keys {
+            let value = &self.metadata[key];
+            if node.metadata.get(key) != Some(value) {
+                node.metadata.insert(key.clone(), value.clone());
+                changed.push(format!("metadata.{}", key));
+            }
+        }
+        changed
+    }
+
+    /// The node's current values of just the attributes this set touches, for the
+    /// before/after of an audit entry
+    pub fn snapshot(&self, node: &Node) -> serde_json::Value {
+        let mut values = serde_json::Map::new();
+        if self.group.is_some() {
+            values.insert("group".into(), serde_json::json!(node.group));
+        }
+        if self.color.is_some() {
+            values.insert("color".into(), serde_json::json!(node.color));
+        }
+        if self.node_type.is_some() {
+            values.insert("nodeType".into(), serde_json::json!(node.node_type));
+        }
+        if self.physics_flags.is_some() {
+            values.insert("physicsFlags".into(), physics_flags::describe(node.data.flags));
+        }
+        for key in self.metadata.keys() {
+            values.insert(format!("metadata.{}", key), serde_json::json!(node.metadata.get(key)));
+        }
+        serde_json::Value::Object(values)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeUpdateStatus {
+    Updated,
+    Unchanged,
+    NotFound,
+}
+
+/// How one requested node fared in a bulk update
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAttributeResult {
+    pub node_id: u32,
+    pub status: AttributeUpdateStatus,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
+    // The touched attributes before and after, kept for the audit trail when they changed
+    #[serde(skip)]
+    pub before: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub after: Option<serde_json::Value>,
+}
+
+/// What a bulk update did, and the diff it broadcast if anything changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeUpdateOutcome {
+    pub results: Vec<NodeAttributeResult>,
+    pub updated: usize,
+    pub generation: u64,
+    #[serde(skip)]
+    pub event: Option<serde_json::Value>,
+}
+
+/// The `node_attribute_diff` event sent to clients: which nodes changed, which keys
+/// changed on any of them, and the values those keys now hold
+pub fn attribute_diff_event(set: &AttributeSet, results: &[NodeAttributeResult], actor: &str, generation: u64) -> serde_json::Value {
+    let node_ids: Vec<u32> = results.iter()
+        .filter(|r| r.status == AttributeUpdateStatus::Updated)
+        .map(|r| r.node_id)
+        .collect();
+    let mut changed_keys: Vec<&String> = results.iter().flat_map(|r| r.changed.iter()).collect();
+    changed_keys.sort();
+    changed_keys.dedup();
+    serde_json::json!({
+        "type": "node_attribute_diff",
+        "actor": actor,
+        "generation": generation,
+        "nodeIds": node_ids,
+        "changedKeys": changed_keys,
+        "set": set,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_bad_values() {
+        let types = vec!["concept".to_string(), "note".to_string()];
+        let ok = AttributeSet { color: Some("#A0f".into()), node_type: Some("note".into()), ..Default::default() };
+        assert!(ok.validate(&types).is_ok());
+
+        for color in ["red", "#12345", "#ggg", "123456"] {
+            let set = AttributeSet { color: Some(color.into()), ..Default::default() };
+            assert_eq!(set.validate(&types).unwrap_err().0, "set.color");
+        }
+
+        let unknown = AttributeSet { node_type: Some("widget".into()), ..Default::default() };
+        assert_eq!(unknown.validate(&types).unwrap_err().0, "set.nodeType");
+        assert!(unknown.validate(&[]).is_ok());
+
+        let id = AttributeSet { metadata: HashMap::from([("metadataId".into(), "x".into())]), ..Default::default() };
+        assert!(id.validate(&types).is_err());
+    }
+
+    #[test]
+    fn test_apply_reports_only_changed_keys() {
+        let mut node = Node::new_with_id("a.md".into(), Some(1)).with_metadata("tags".into(), "x".into());
+        node.group = Some("x".into());
+        let set = AttributeSet {
+            group: Some("x".into()),
+            color: Some("#ff0000".into()),
+            metadata: HashMap::from([("tags".into(), "x".into()), ("reviewed".into(), "yes".into())]),
+            ..Default::default()
+        };
+        let before = set.snapshot(&node);
+        assert_eq!(set.apply(&mut node), vec!["color", "metadata.reviewed"]);
+        assert!(set.apply(&mut node).is_empty());
+        assert_eq!(before["color"], serde_json::Value::Null);
+        assert_eq!(before["metadata.reviewed"], serde_json::Value::Null);
+        assert_eq!(set.snapshot(&node)["metadata.reviewed"], "yes");
+    }
+}
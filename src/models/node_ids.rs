@@ -0,0 +1,298 @@
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::utils::json_store::write_json_atomic;
+
+pub const NODE_ID_MAP_PATH: &str = "/app/data/id_map.json";
+
+/// One map for every id assignment path in the process
+pub type SharedNodeIds = Arc<Mutex<NodeIdMap>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IdMapFile {
+    #[serde(default)]
+    ids: BTreeMap<String, u32>,
+}
+
+/// Two metadata ids that claimed one numeric id, and where the loser went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdConflict {
+    pub id: u32,
+    pub kept: String,
+    pub reassigned: String,
+    pub new_id: u32,
+}
+
+/// Numeric node ids by metadata id, persisted so a document keeps its id across builds,
+/// restarts and server instances sharing the data directory.
+///
+/// Other instances may write the file between our reads, so every sync merges it with
+/// what this process holds. A metadata id with two ids keeps the lower one; an id claimed
+/// by two metadata ids stays with the one that sorts first and the other moves to a fresh
+/// id above the highest. Any instance merging the same entries reaches the same map.
+#[derive(Debug, Default)]
+pub struct NodeIdMap {
+    path: Option<PathBuf>,
+    ids: HashMap<String, u32>,
+    taken: HashMap<u32, String>,
+    conflicts: Vec<IdConflict>,
+    // Allocations not yet written
+    dirty: bool,
+}
+
+impl NodeIdMap {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let mut map = Self { path: Some(path), ..Self::default() };
+        map.sync();
+        if !map.ids.is_empty() {
+            info!("Loaded {} node ids from {:?}", map.ids.len(), map.path.as_ref().unwrap());
+        }
+        map
+    }
+
+    pub fn shared(self) -> SharedNodeIds {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn get(&self, metadata_id: &str) -> Option<u32> {
+        self.ids.get(metadata_id).copied()
+    }
+
+    fn next_free(&self, floor: u32) -> u32 {
+        let mut id = self.taken.keys().max().map_or(1, |max| max + 1).max(floor).max(1);
+        while self.taken.contains_key(&id) {
+            id += 1;
+        }
+        id
+    }
+
+    fn insert(&mut self, metadata_id: &str, id: u32) {
+        self.ids.insert(metadata_id.to_string(), id);
+        self.taken.insert(id, metadata_id.to_string());
+        self.dirty = true;
+    }
+
+    /// The id `metadata_id` has, allocating the next one if it has none
+    pub fn id_for(&mut self, metadata_id: &str) -> u32 {
+        if let Some(id) = self.get(metadata_id) {
+            return id;
+        }
+        let id = self.next_free(1);
+        self.insert(metadata_id, id);
+        id
+    }
+
+    /// `count` consecutive fresh ids from no lower than `floor`, for nodes named after
+    /// their own ids; returns the first
+    pub fn allocate(&mut self, floor: u32, count: u32, name: impl Fn(u32) -> String) -> u32 {
+        let mut first = self.next_free(floor);
+        while (first..first + count).any(|id| self.ids.contains_key(&name(id))) {
+            first += 1;
+        }
+        for id in first..first + count {
+            self.insert(&name(id), id);
+        }
+        first
+    }
+
+    /// Seeds ids from an older source, such as the node ids metadata carries, for
+    /// metadata ids the map doesn't know yet. An id already taken goes to the map's
+    /// holder and the newcomer gets a fresh one.
+    pub fn backfill<'a>(&mut self, entries: impl IntoIterator<Item = (&'a str, u32)>) -> Result<usize, String> {
+        self.sync();
+        let mut entries: Vec<(&str, u32)> = entries.into_iter()
+            .filter(|(metadata_id, _)| !self.ids.contains_key(*metadata_id))
+            .collect();
+        entries.sort();
+        for &(metadata_id, id) in &entries {
+            match self.taken.get(&id) {
+                Some(holder) if holder != metadata_id => {
+                    let new_id = self.next_free(1);
+                    self.conflicts.push(IdConflict { id, kept: holder.clone(), reassigned: metadata_id.to_string(), new_id });
+                    self.insert(metadata_id, new_id);
+                }
+                _ if id == 0 => {
+                    let new_id = self.next_free(1);
+                    self.insert(metadata_id, new_id);
+                }
+                _ => self.insert(metadata_id, id),
+            }
+        }
+        self.save()?;
+        Ok(entries.len())
+    }
+
+    /// Ids for a whole build, picking up what other instances wrote first and writing
+    /// the new ones back
+    pub fn assign_all<'a>(&mut self, metadata_ids: impl IntoIterator<Item = &'a str>) -> Vec<u32> {
+        self.sync();
+        let ids = metadata_ids.into_iter().map(|metadata_id| self.id_for(metadata_id)).collect();
+        if let Err(e) = self.save() {
+            error!("Failed to save node ids: {}", e);
+        }
+        ids
+    }
+
+    /// Merges the file into this map
+    pub fn sync(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let file = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str::<IdMapFile>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse node id map {:?}: {}. Keeping the ids held in memory.", path, e);
+                IdMapFile::default()
+            }),
+            Err(_) => IdMapFile::default(),
+        };
+        let mut entries: Vec<(String, u32)> = file.ids.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        entries.extend(self.ids.drain());
+        let (ids, conflicts) = resolve(entries);
+        // Whatever the file lacks or has differently still needs writing
+        self.dirty |= ids.len() != file.ids.len() || ids.iter().any(|(k, v)| file.ids.get(k) != Some(v));
+        self.taken = ids.iter().map(|(metadata_id, id)| (*id, metadata_id.clone())).collect();
+        self.ids = ids;
+        for conflict in &conflicts {
+            warn!("Node id {} was claimed by both {} and {}; {} keeps it and {} moves to {}",
+                conflict.id, conflict.kept, conflict.reassigned, conflict.kept, conflict.reassigned, conflict.new_id);
+        }
+        self.conflicts.extend(conflicts);
+    }
+
+    /// Writes the map through to disk when file-backed, merging in other writers first
+    pub fn save(&mut self) -> Result<(), String> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+        self.sync();
+        if !self.dirty {
+            return Ok(());
+        }
+        let file = IdMapFile { ids: self.ids.iter().map(|(k, v)| (k.clone(), *v)).collect() };
+        write_json_atomic(self.path.as_ref().unwrap(), &file)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Conflicts resolved since the last call
+    pub fn take_conflicts(&mut self) -> Vec<IdConflict> {
+        std::mem::take(&mut self.conflicts)
+    }
+}
+
+fn resolve(mut entries: Vec<(String, u32)>) -> (HashMap<String, u32>, Vec<IdConflict>) {
+    // Lowest id first for each metadata id, so duplicates keep the lower one
+    entries.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+    entries.dedup_by(|later, first| later.0 == first.0);
+    entries.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+    let mut next = entries.iter().map(|(_, id)| *id).max().unwrap_or(0) + 1;
+    let mut ids = HashMap::with_capacity(entries.len());
+    let mut owner: Option<(u32, String)> = None;
+    let mut conflicts = Vec::new();
+    for (metadata_id, id) in entries {
+        match &owner {
+            // 0 is never a node id
+            _ if id == 0 => {
+                ids.insert(metadata_id, next);
+                next += 1;
+            }
+            Some((owned, kept)) if *owned == id => {
+                conflicts.push(IdConflict { id, kept: kept.clone(), reassigned: metadata_id.clone(), new_id: next });
+                ids.insert(metadata_id, next);
+                next += 1;
+            }
+            _ => {
+                owner = Some((id, metadata_id.clone()));
+                ids.insert(metadata_id, id);
+            }
+        }
+    }
+    (ids, conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("id-map-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_ids_survive_restart_and_runtime_nodes() {
+        let path = temp_path();
+        let mut map = NodeIdMap::load(path.clone());
+        assert_eq!(map.assign_all(["alpha", "beta", "gamma"]), [1, 2, 3]);
+        let runtime = map.allocate(10, 2, |id| format!("runtime-{}", id));
+        assert_eq!(runtime, 10);
+        map.save().unwrap();
+
+        // A restart, with a document gone and a new one added, keeps everything in place
+        let mut map = NodeIdMap::load(path.clone());
+        assert_eq!(map.get("runtime-11"), Some(11));
+        assert_eq!(map.assign_all(["gamma", "alpha", "delta"]), [3, 1, 12]);
+        assert_eq!(map.assign_all(["beta"]), [2]);
+        assert!(map.take_conflicts().is_empty());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_concurrent_instances_converge_on_unique_ids() {
+        let path = temp_path();
+        // Two instances start from the same file and allocate before seeing each other
+        NodeIdMap::load(path.clone()).assign_all(["shared"]);
+        let (mut a, mut b) = (NodeIdMap::load(path.clone()), NodeIdMap::load(path.clone()));
+        assert_eq!(a.id_for("from-a"), 2);
+        assert_eq!(b.id_for("from-b"), 2);
+        assert_eq!(b.id_for("both"), 3);
+        assert_eq!(a.id_for("both"), 3);
+        a.save().unwrap();
+        b.save().unwrap();
+        a.sync();
+
+        // The second writer finds the collision; both end up with the same unique map
+        assert_eq!(b.take_conflicts(), [IdConflict { id: 2, kept: "from-a".into(), reassigned: "from-b".into(), new_id: 4 }]);
+        for map in [&a, &b] {
+            let ids = ["shared", "from-a", "from-b", "both"].map(|m| map.get(m).unwrap());
+            assert_eq!(ids, [1, 2, 4, 3]);
+        }
+        assert_eq!(NodeIdMap::load(path.clone()).get("from-b"), Some(4));
+
+        // Builds sharing one map across threads never hand out an id twice
+        let shared = NodeIdMap::load(path.clone()).shared();
+        let handles: Vec<_> = (0..4).map(|t| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let names: Vec<String> = (0..25).map(|i| format!("doc-{}", (t * 10 + i) % 60)).collect();
+                shared.lock().unwrap().assign_all(names.iter().map(String::as_str))
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let map = shared.lock().unwrap();
+        let mut ids: Vec<u32> = map.ids.values().copied().collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), map.len());
+        let _ = fs::remove_file(path);
+    }
+}
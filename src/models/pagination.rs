@@ -17,4 +17,5 @@ pub struct PaginatedGraphData {
     pub total_nodes: usize,
     pub total_edges: usize,
     pub metadata: serde_json::Value,
+    pub generation: u64,
 }
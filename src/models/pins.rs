@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::utils::json_store::write_json_atomic;
+
+pub const LAYOUT_STATE_PATH: &str = "/app/data/layout/layout_state.json";
+
+/// A node held in place by hand. Keyed by metadata id so it survives rebuilds and restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinnedNode {
+    pub position: [f32; 3],
+    pub pinned_at: DateTime<Utc>,
+}
+
+// The sidecar file; room for other persisted layout state later
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LayoutState {
+    #[serde(default)]
+    pins: HashMap<String, PinnedNode>,
+}
+
+/// Pins, written through to `layout_state.json` on every change when backed by a file
+#[derive(Debug, Default)]
+pub struct PinStore {
+    path: Option<PathBuf>,
+    pins: HashMap<String, PinnedNode>,
+}
+
+impl PinStore {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let state = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<LayoutState>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse layout state {:?}: {}. Starting without pins.", path, e);
+                LayoutState::default()
+            }),
+            Err(_) => LayoutState::default(),
+        };
+        info!("Loaded {} pinned nodes from {:?}", state.pins.len(), path);
+        Self { path: Some(path), pins: state.pins }
+    }
+
+    pub fn pins(&self) -> &HashMap<String, PinnedNode> {
+        &self.pins
+    }
+
+    pub fn get(&self, metadata_id: &str) -> Option<&PinnedNode> {
+        self.pins.get(metadata_id)
+    }
+
+    pub fn pin(&mut self, metadata_id: &str, pin: PinnedNode) -> Result<(), String> {
+        let previous = self.pins.insert(metadata_id.to_string(), pin);
+        self.save().inspect_err(|_| {
+            // Keep memory and disk in step
+            match previous {
+                Some(previous) => self.pins.insert(metadata_id.to_string(), previous),
+                None => self.pins.remove(metadata_id),
+            };
+        })
+    }
+
+    /// Returns whether the node was pinned
+    pub fn unpin(&mut self, metadata_id: &str) -> Result<bool, String> {
+        let Some(previous) = self.pins.remove(metadata_id) else {
+            return Ok(false);
+        };
+        if let Err(e) = self.save() {
+            self.pins.insert(metadata_id.to_string(), previous);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => write_json_atomic(path, &LayoutState { pins: self.pins.clone() }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinSource {
+    // Pinned through the API since the server started
+    Runtime,
+    // Restored from the layout state file at build time
+    Metadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinInfo {
+    pub node_id: u32,
+    pub metadata_id: String,
+    pub position: [f32; 3],
+    pub pinned_at: DateTime<Utc>,
+    pub source: PinSource,
+}
+
+/// Pins that couldn't be placed because their file is gone from the metadata store.
+/// They are kept, so the pin comes back if the file does.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinConflict {
+    pub metadata_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinReport {
+    pub pins: Vec<PinInfo>,
+    pub conflicts: Vec<PinConflict>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("pins-test-{}", uuid::Uuid::new_v4()))
+            .join("layout_state.json")
+    }
+
+    #[test]
+    fn test_pins_survive_reload() {
+        let path = temp_path();
+        let mut store = PinStore::load(path.clone());
+        assert!(store.pins().is_empty());
+
+        let pin = PinnedNode { position: [1.0, 2.0, 3.0], pinned_at: Utc::now() };
+        store.pin("hub", pin.clone()).unwrap();
+        store.pin("other", PinnedNode { position: [0.0; 3], pinned_at: Utc::now() }).unwrap();
+        assert!(store.unpin("other").unwrap());
+        assert!(!store.unpin("never-pinned").unwrap());
+
+        let reloaded = PinStore::load(path.clone());
+        assert_eq!(reloaded.pins().len(), 1);
+        assert_eq!(reloaded.get("hub"), Some(&pin));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,202 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::types::vec3::Vec3Data;
+
+pub const MAX_EDITS_PER_ROOM: usize = 100;
+// A drag arrives as a stream of position frames; frames for the same node from the
+// same client this close together are folded into one undo step
+const COALESCE_WINDOW_MS: i64 = 1000;
+
+/// One manual node move, as recorded for undo
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionEdit {
+    pub node_id: u32,
+    // Node ids are reassigned on rebuild, so entries also remember the file
+    pub metadata_id: String,
+    pub before: Vec3Data,
+    pub after: Vec3Data,
+    pub client: usize,
+    pub timestamp: i64,
+}
+
+/// Result of an undo/redo: the edit that was applied, if any, plus entries that
+/// were dropped because their node no longer exists
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStep {
+    pub applied: Option<PositionEdit>,
+    pub skipped: Vec<PositionEdit>,
+}
+
+#[derive(Default)]
+struct RoomHistory {
+    undo: VecDeque<PositionEdit>,
+    redo: Vec<PositionEdit>,
+}
+
+/// Bounded per-room undo/redo stacks for manual position edits
+pub struct PositionHistory {
+    rooms: HashMap<String, RoomHistory>,
+    capacity: usize,
+}
+
+impl Default for PositionHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PositionHistory {
+    pub fn new() -> Self {
+        Self::with_capacity(MAX_EDITS_PER_ROOM)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { rooms: HashMap::new(), capacity: capacity.max(1) }
+    }
+
+    pub fn record(&mut self, room: &str, edit: PositionEdit) {
+        let history = self.rooms.entry(room.to_string()).or_default();
+        // Any new edit invalidates what could be redone
+        history.redo.clear();
+
+        if let Some(last) = history.undo.back_mut() {
+            if last.node_id == edit.node_id
+                && last.client == edit.client
+                && edit.timestamp - last.timestamp <= COALESCE_WINDOW_MS
+            {
+                last.after = edit.after;
+                last.timestamp = edit.timestamp;
+                return;
+            }
+        }
+
+        if history.undo.len() == self.capacity {
+            history.undo.pop_front();
+        }
+        history.undo.push_back(edit);
+    }
+
+    /// Pops the most recent edit whose node still exists. `exists` is given the
+    /// node id and metadata id of each candidate.
+    pub fn undo<F>(&mut self, room: &str, exists: F) -> HistoryStep
+    where
+        F: Fn(u32, &str) -> bool,
+    {
+        let mut step = HistoryStep::default();
+        let history = match self.rooms.get_mut(room) {
+            Some(history) => history,
+            None => return step,
+        };
+        while let Some(edit) = history.undo.pop_back() {
+            if exists(edit.node_id, &edit.metadata_id) {
+                history.redo.push(edit.clone());
+                step.applied = Some(edit);
+                break;
+            }
+            step.skipped.push(edit);
+        }
+        step
+    }
+
+    pub fn redo<F>(&mut self, room: &str, exists: F) -> HistoryStep
+    where
+        F: Fn(u32, &str) -> bool,
+    {
+        let mut step = HistoryStep::default();
+        let history = match self.rooms.get_mut(room) {
+            Some(history) => history,
+            None => return step,
+        };
+        while let Some(edit) = history.redo.pop() {
+            if exists(edit.node_id, &edit.metadata_id) {
+                // Pushed directly so a redo is never coalesced into an older edit
+                history.undo.push_back(edit.clone());
+                step.applied = Some(edit);
+                break;
+            }
+            step.skipped.push(edit);
+        }
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(node_id: u32, client: usize, before: f32, after: f32, timestamp: i64) -> PositionEdit {
+        PositionEdit {
+            node_id,
+            metadata_id: format!("note-{}", node_id),
+            before: Vec3Data { x: before, y: 0.0, z: 0.0 },
+            after: Vec3Data { x: after, y: 0.0, z: 0.0 },
+            client,
+            timestamp,
+        }
+    }
+
+    fn any(_: u32, _: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn test_interleaved_clients_undo_most_recent_first() {
+        let mut history = PositionHistory::new();
+        history.record("lab", edit(1, 10, 0.0, 1.0, 0));
+        history.record("lab", edit(2, 20, 0.0, 5.0, 100));
+        // Client 10 drags node 1 again after client 20's edit: a separate step
+        history.record("lab", edit(1, 10, 1.0, 2.0, 200));
+        // Another room's edits never show up here
+        history.record("other", edit(3, 30, 0.0, 9.0, 300));
+
+        let first = history.undo("lab", any).applied.unwrap();
+        assert_eq!((first.node_id, first.before.x), (1, 1.0));
+        let second = history.undo("lab", any).applied.unwrap();
+        assert_eq!((second.node_id, second.client), (2, 20));
+
+        // Redo reapplies in reverse order of undo
+        let redone = history.redo("lab", any).applied.unwrap();
+        assert_eq!((redone.node_id, redone.after.x), (2, 5.0));
+
+        // A fresh edit clears the redo stack
+        history.record("lab", edit(2, 20, 5.0, 6.0, 5000));
+        assert!(history.redo("lab", any).applied.is_none());
+
+        let third = history.undo("lab", any).applied.unwrap();
+        assert_eq!(third.after.x, 6.0);
+        assert_eq!(history.undo("lab", any).applied.unwrap().node_id, 2);
+        assert_eq!(history.undo("lab", any).applied.unwrap().node_id, 1);
+        assert!(history.undo("lab", any).applied.is_none());
+    }
+
+    #[test]
+    fn test_drag_frames_coalesce() {
+        let mut history = PositionHistory::new();
+        history.record("lab", edit(1, 10, 0.0, 1.0, 0));
+        history.record("lab", edit(1, 10, 1.0, 2.0, 500));
+        history.record("lab", edit(1, 10, 2.0, 3.0, 900));
+
+        let step = history.undo("lab", any).applied.unwrap();
+        assert_eq!((step.before.x, step.after.x), (0.0, 3.0));
+        assert!(history.undo("lab", any).applied.is_none());
+    }
+
+    #[test]
+    fn test_undo_skips_nodes_removed_by_rebuild() {
+        let mut history = PositionHistory::with_capacity(2);
+        history.record("lab", edit(1, 10, 0.0, 1.0, 0));
+        history.record("lab", edit(2, 10, 0.0, 2.0, 5000));
+        history.record("lab", edit(3, 10, 0.0, 3.0, 10000));
+
+        // After the rebuild node 3 is gone and id 2 now belongs to a different file
+        let exists = |id: u32, metadata_id: &str| id == 1 || (id == 2 && metadata_id == "renamed");
+        let step = history.undo("lab", exists);
+        assert!(step.applied.is_none());
+        assert_eq!(step.skipped.iter().map(|e| e.node_id).collect::<Vec<_>>(), vec![3, 2]);
+        // Node 1 fell off the bounded stack
+        assert!(history.undo("lab", any).applied.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{ColorMappingSettings, ColorStrategy};
+use crate::models::graph_filter::GraphFilter;
+use crate::models::node::Node;
+use crate::models::simulation_params::SimulationMode;
+
+pub const MAX_VIEW_NAME_LEN: usize = 100;
+
+/// Who an applied view's layout and colouring reach. The filter, edge visibility, focus
+/// and camera only ever go to the client that applied it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewScope {
+    #[default]
+    Client,
+    Room,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ViewLayout {
+    // Server simulation mode; there is one simulation, so it is only switched room-wide
+    pub mode: Option<SimulationMode>,
+    // Node the view centres on, by metadata id so it survives rebuilds
+    pub focus: Option<String>,
+}
+
+/// Where the client's camera should start; clients may ignore it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraHint {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+/// A named combination of filter, layout, colouring and edge visibility
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedView {
+    pub name: String,
+    #[serde(default)]
+    pub filter: GraphFilter,
+    #[serde(default)]
+    pub layout: ViewLayout,
+    #[serde(default)]
+    pub color_mapping: Option<ColorMappingSettings>,
+    // Edge types switched on or off, as `setEdgeTypeVisibility` takes them
+    #[serde(default)]
+    pub edge_types: HashMap<String, bool>,
+    #[serde(default)]
+    pub camera: Option<CameraHint>,
+    #[serde(default)]
+    pub created_by: String,
+    // Unix millis
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl SavedView {
+    /// Checks a view before it is stored. `nodes` is the current graph: a focus node has to
+    /// exist when the view is saved, even though it may be gone by the time it is applied.
+    pub fn validate(&self, nodes: &[Node]) -> Result<(), (String, String)> {
+        let invalid = |field: &str, reason: &str| Err((field.to_string(), reason.to_string()));
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > MAX_VIEW_NAME_LEN {
+            return invalid("name", "must be 1 to 100 characters");
+        }
+        if let Some(mode) = self.layout.mode {
+            if !mode.is_selectable() {
+                return invalid("layout.mode", "must be remote, local or hybrid");
+            }
+        }
+        if let Some(focus) = &self.layout.focus {
+            if !nodes.iter().any(|n| &n.metadata_id == focus) {
+                return invalid("layout.focus", "no node with that metadata id");
+            }
+        }
+        if let Some(mapping) = &self.color_mapping {
+            if mapping.strategy == ColorStrategy::ByMetadataKey && mapping.metadata_key.trim().is_empty() {
+                return invalid("colorMapping", "by_metadata_key needs a metadata_key");
+            }
+        }
+        if let Some(camera) = &self.camera {
+            if camera.position.iter().chain(&camera.target).any(|v| !v.is_finite()) {
+                return invalid("camera", "coordinates must be finite");
+            }
+        }
+        Ok(())
+    }
+
+    /// The view against the graph as it is now. References to nodes a rebuild removed are
+    /// dropped with a notice rather than failing the whole view.
+    pub fn resolve(&self, nodes: &[Node]) -> ResolvedView {
+        let mut notices = Vec::new();
+        let node_ids = if self.filter.is_empty() {
+            Vec::new()
+        } else {
+            let ids = self.filter.select(nodes);
+            if ids.is_empty() {
+                notices.push("The view's filter matches no nodes in the current graph".to_string());
+            }
+            ids
+        };
+        let focus_node_id = self.layout.focus.as_ref().and_then(|focus| {
+            let id = nodes.iter().find(|n| &n.metadata_id == focus).map(|n| n.id);
+            if id.is_none() {
+                notices.push(format!("Focus node {} is no longer in the graph; focus dropped", focus));
+            }
+            id
+        });
+        ResolvedView { node_ids, focus_node_id, notices }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedView {
+    // Nodes the filter picks; empty when the view has no filter
+    pub node_ids: Vec<u32>,
+    pub focus_node_id: Option<u32>,
+    pub notices: Vec<String>,
+}
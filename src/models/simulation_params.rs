@@ -1,12 +1,25 @@
 use serde::{Deserialize, Serialize};
 use bytemuck::{Pod, Zeroable};
+#[cfg(feature = "gpu")]
+use cudarc::driver::{DeviceRepr, ValidAsZeroBits};
+
+use crate::config::{PhaseOverrides, PhysicsPhases, PhysicsSettings};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum SimulationMode {
     Remote,  // GPU-accelerated remote computation (default)
     GPU,     // Local GPU computation (deprecated)
-    Local,   // CPU-based computation (disabled)
+    Local,   // Clients lay the graph out themselves; no server physics
+    Hybrid,  // Low-rate server reference with periodic authoritative corrections
+    Replay,  // Positions played back from a recording, no physics
+}
+
+impl SimulationMode {
+    /// Modes that can be chosen in settings; replay is entered by starting a replay
+    pub fn is_selectable(self) -> bool {
+        matches!(self, SimulationMode::Remote | SimulationMode::Local | SimulationMode::Hybrid)
+    }
 }
 
 impl Default for SimulationMode {
@@ -29,6 +42,40 @@ impl Default for SimulationPhase {
     }
 }
 
+impl SimulationPhase {
+    pub const ALL: [SimulationPhase; 3] = [SimulationPhase::Initial, SimulationPhase::Dynamic, SimulationPhase::Finalize];
+
+    /// Slot in the profile table the kernel indexes
+    pub fn index(self) -> usize {
+        match self {
+            SimulationPhase::Initial => 0,
+            SimulationPhase::Dynamic => 1,
+            SimulationPhase::Finalize => 2,
+        }
+    }
+}
+
+// What the force kernel reads for one phase. Must match PhaseProfile in compute_forces.cu.
+#[repr(C)]
+#[derive(Default, Clone, Copy, Pod, Zeroable, Debug, PartialEq)]
+pub struct PhaseProfile {
+    pub spring_strength: f32,
+    pub damping: f32,
+    pub repulsion: f32,
+    pub time_step: f32,
+    pub max_repulsion_distance: f32,
+    pub viewport_bounds: f32,     // 0 turns the bounds off
+    pub boundary_damping: f32,    // Velocity kept by a node pushed back at the bounds
+}
+
+#[cfg(feature = "gpu")]
+unsafe impl DeviceRepr for PhaseProfile {}
+#[cfg(feature = "gpu")]
+unsafe impl ValidAsZeroBits for PhaseProfile {}
+
+// One profile per phase, in SimulationPhase::index order; uploaded to the GPU as a table
+pub type PhaseProfiles = [PhaseProfile; 3];
+
 // GPU-compatible simulation parameters
 #[repr(C)]
 #[derive(Default, Clone, Copy, Pod, Zeroable, Debug)]
@@ -54,6 +101,7 @@ pub struct SimulationParams {
     pub spring_strength: f32,      // Range: 0.1-10, Default: 0.5
     pub repulsion: f32,           // Default: 100
     pub max_repulsion_distance: f32, // Default: 500
+    pub degree_repulsion_factor: f32, // Default: 0 (off)
     
     // Mass and damping
     pub mass_scale: f32,          // Default: 1.0, Affects force scaling
@@ -67,6 +115,10 @@ pub struct SimulationParams {
     // Simulation state
     pub phase: SimulationPhase,   // Current simulation phase
     pub mode: SimulationMode,     // Computation mode
+
+    // Per-phase overrides of the profiles derived from the fields above
+    #[serde(default)]
+    pub phases: PhysicsPhases,
 }
 
 impl SimulationParams {
@@ -77,63 +129,64 @@ impl SimulationParams {
             spring_strength: 0.5,
             repulsion: 100.0,
             max_repulsion_distance: 500.0,
+            degree_repulsion_factor: 0.0,
             mass_scale: 1.0,
             damping: 0.5,
             boundary_damping: 0.9,
             viewport_bounds: 1000.0,
             enable_bounds: true,
-            phase: SimulationPhase::Initial,
+            phase: SimulationPhase::Dynamic,
             mode: SimulationMode::Remote,
+            phases: PhysicsPhases::default(),
         }
     }
 
+    /// The baseline parameters set to run `phase`. The kernel-facing values for the
+    /// phase come from `profile`, which derives them from this baseline.
     pub fn with_phase(phase: SimulationPhase) -> Self {
-        match phase {
-            SimulationPhase::Initial => Self {
-                iterations: 300,
-                time_step: 0.2,
-                spring_strength: 0.3,      // Reduced for initial spread
-                repulsion: 200.0,          // Increased for better separation
-                max_repulsion_distance: 800.0, // Larger range for initial layout
-                mass_scale: 1.2,           // Slightly higher mass influence
-                damping: 0.95,             // High damping for stability
-                boundary_damping: 0.95,
-                viewport_bounds: 1000.0,
-                enable_bounds: true,
-                phase,
-                mode: SimulationMode::Remote,
-            },
-            SimulationPhase::Dynamic => Self {
-                iterations: 50,
-                time_step: 0.2,
-                spring_strength: 0.5,
-                repulsion: 100.0,
-                max_repulsion_distance: 500.0,
-                mass_scale: 1.0,
-                damping: 0.5,
-                boundary_damping: 0.9,
-                viewport_bounds: 1000.0,
-                enable_bounds: true,
-                phase,
-                mode: SimulationMode::Remote,
-            },
-            SimulationPhase::Finalize => Self {
-                iterations: 200,
-                time_step: 0.2,
-                spring_strength: 0.1,      // Minimal spring forces
-                repulsion: 50.0,           // Reduced repulsion
-                max_repulsion_distance: 300.0, // Tighter packing
-                mass_scale: 0.8,           // Reduced mass influence
-                damping: 0.95,             // High damping for stability
-                boundary_damping: 0.95,
-                viewport_bounds: 1000.0,
-                enable_bounds: true,
-                phase,
-                mode: SimulationMode::Remote,
-            },
+        let (iterations, mass_scale) = match phase {
+            SimulationPhase::Initial => (300, 1.2),
+            SimulationPhase::Dynamic => (50, 1.0),
+            SimulationPhase::Finalize => (200, 0.8),
+        };
+        Self { iterations, mass_scale, phase, ..Self::new() }
+    }
+
+    /// The kernel parameters for `phase`. The flat fields are the dynamic phase's; the
+    /// initial phase spreads the graph out (weaker springs, stronger and longer-range
+    /// repulsion) and finalize settles it (minimal springs, tighter packing), both
+    /// heavily damped. Overrides configured for the phase go on top.
+    pub fn profile(&self, phase: SimulationPhase) -> PhaseProfile {
+        let (spring, repulsion, distance, min_damping) = match phase {
+            SimulationPhase::Initial => (0.6, 2.0, 1.6, 0.95),
+            SimulationPhase::Dynamic => (1.0, 1.0, 1.0, 0.0),
+            SimulationPhase::Finalize => (0.2, 0.5, 0.6, 0.95),
+        };
+        let overrides: &PhaseOverrides = match phase {
+            SimulationPhase::Initial => &self.phases.initial,
+            SimulationPhase::Dynamic => &self.phases.dynamic,
+            SimulationPhase::Finalize => &self.phases.finalize,
+        };
+        let enable_bounds = overrides.enable_bounds.unwrap_or(self.enable_bounds);
+        PhaseProfile {
+            spring_strength: overrides.spring_strength.unwrap_or(self.spring_strength * spring),
+            damping: overrides.damping.unwrap_or(self.damping.max(min_damping)),
+            repulsion: overrides.repulsion_strength.unwrap_or(self.repulsion * repulsion),
+            time_step: overrides.time_step.unwrap_or(self.time_step),
+            max_repulsion_distance: overrides.repulsion_distance.unwrap_or(self.max_repulsion_distance * distance),
+            viewport_bounds: if enable_bounds { overrides.bounds_size.unwrap_or(self.viewport_bounds) } else { 0.0 },
+            boundary_damping: overrides.boundary_damping.unwrap_or(self.boundary_damping.max(min_damping)),
         }
     }
 
+    pub fn profiles(&self) -> PhaseProfiles {
+        SimulationPhase::ALL.map(|phase| self.profile(phase))
+    }
+
+    pub fn active_profile(&self) -> PhaseProfile {
+        self.profile(self.phase)
+    }
+
     // Convert to GPU-compatible parameters
     pub fn to_gpu_params(&self) -> GPUSimulationParams {
         GPUSimulationParams {
@@ -148,3 +201,91 @@ impl SimulationParams {
         }
     }
 }
+
+// What a physics loop runs with for the given settings
+impl From<&PhysicsSettings> for SimulationParams {
+    fn from(physics: &PhysicsSettings) -> Self {
+        Self {
+            iterations: physics.iterations,
+            spring_strength: physics.spring_strength,
+            repulsion: physics.repulsion_strength,
+            damping: physics.damping,
+            max_repulsion_distance: physics.repulsion_distance,
+            degree_repulsion_factor: physics.degree_repulsion_factor,
+            viewport_bounds: physics.bounds_size,
+            mass_scale: physics.mass_scale,
+            boundary_damping: physics.boundary_damping,
+            enable_bounds: physics.enable_bounds,
+            time_step: 0.016,
+            phase: SimulationPhase::Dynamic,
+            mode: SimulationMode::Remote,
+            phases: physics.phases.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::edge::Edge;
+    use crate::models::graph::GraphData;
+    use crate::models::node::Node;
+    use crate::services::graph_service::GraphService;
+
+    #[test]
+    fn test_profiles_derive_from_flat_params() {
+        let params = SimulationParams::new();
+        let dynamic = params.profile(SimulationPhase::Dynamic);
+        assert_eq!(dynamic.spring_strength, params.spring_strength);
+        assert_eq!(dynamic.repulsion, params.repulsion);
+        assert_eq!(dynamic.damping, params.damping);
+        assert_eq!(dynamic.viewport_bounds, params.viewport_bounds);
+
+        // The values the initial phase used to hardcode
+        let initial = params.profile(SimulationPhase::Initial);
+        assert!((initial.spring_strength - 0.3).abs() < 1e-6);
+        assert_eq!(initial.repulsion, 200.0);
+        assert_eq!(initial.max_repulsion_distance, 800.0);
+        assert_eq!(initial.damping, 0.95);
+
+        let disabled = SimulationParams { enable_bounds: false, ..SimulationParams::new() };
+        assert!(disabled.profiles().iter().all(|p| p.viewport_bounds == 0.0));
+        assert_eq!(std::mem::size_of::<PhaseProfile>(), 28);
+    }
+
+    #[test]
+    fn test_phase_overrides_apply() {
+        let mut params = SimulationParams::new();
+        params.phases.finalize = PhaseOverrides { damping: Some(0.99), time_step: Some(0.05), ..Default::default() };
+        let finalize = params.profile(SimulationPhase::Finalize);
+        assert_eq!(finalize.damping, 0.99);
+        assert_eq!(finalize.time_step, 0.05);
+        assert_eq!(params.profile(SimulationPhase::Dynamic).time_step, params.time_step);
+    }
+
+    fn pair() -> GraphData {
+        let mut graph = GraphData::default();
+        for (id, x) in [(1, -10.0), (2, 10.0)] {
+            let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+            node.set_x(x);
+            graph.nodes.push(node);
+        }
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        graph
+    }
+
+    #[test]
+    fn test_phase_switch_changes_next_cpu_step() {
+        let step = |phase| {
+            let params = SimulationParams { phase, ..SimulationParams::new() };
+            let mut graph = pair();
+            GraphService::calculate_layout_cpu(&mut graph, &mut HashMap::new(), &params).unwrap();
+            graph.nodes[0].data.velocity.x
+        };
+        let dynamic = step(SimulationPhase::Dynamic);
+        let finalize = step(SimulationPhase::Finalize);
+        assert_ne!(dynamic, finalize);
+        assert_ne!(dynamic, step(SimulationPhase::Initial));
+    }
+}
@@ -0,0 +1,182 @@
+use glam::Mat4;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_ROOM: &str = "default";
+pub const MAX_ANCHOR_LABEL_LEN: usize = 64;
+pub const MAX_ROOM_NAME_LEN: usize = 64;
+
+// Column-major 4x4 matrix, same layout as XRRigidTransform.matrix / three.js Matrix4.elements
+pub type AnchorTransform = [f32; 16];
+
+pub const IDENTITY_TRANSFORM: AnchorTransform = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// A named spatial anchor placing the graph inside a physical room.
+///
+/// `transform` maps graph space into the anchor's local frame (graph-to-anchor).
+/// Clients combine it with their own resolved anchor pose to get graph-to-world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpatialAnchor {
+    pub id: String,
+    pub label: String,
+    pub transform: AnchorTransform,
+    pub room: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAnchorRequest {
+    pub label: String,
+    pub transform: Vec<f32>,
+    pub room: Option<String>,
+}
+
+/// Graph-to-anchor mapping sent to clients when they resolve an anchor or the active anchor changes
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorFrame {
+    pub room: String,
+    pub anchor_id: Option<String>,
+    pub graph_to_anchor: AnchorTransform,
+    // Only present when the client supplied its resolved anchor pose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graph_to_world: Option<AnchorTransform>,
+}
+
+impl AnchorFrame {
+    pub fn for_anchor(room: &str, anchor: Option<&SpatialAnchor>) -> Self {
+        Self {
+            room: room.to_string(),
+            anchor_id: anchor.map(|a| a.id.clone()),
+            graph_to_anchor: anchor.map(|a| a.transform).unwrap_or(IDENTITY_TRANSFORM),
+            graph_to_world: None,
+        }
+    }
+
+    /// Fill in graph_to_world from the client's resolved anchor-to-world pose
+    pub fn with_resolved_anchor(mut self, anchor_to_world: &AnchorTransform) -> Self {
+        self.graph_to_world = Some(compose_transforms(anchor_to_world, &self.graph_to_anchor));
+        self
+    }
+}
+
+/// Returns `a * b` for two column-major transforms
+pub fn compose_transforms(a: &AnchorTransform, b: &AnchorTransform) -> AnchorTransform {
+    (Mat4::from_cols_array(a) * Mat4::from_cols_array(b)).to_cols_array()
+}
+
+/// Checks that a transform is a finite, invertible affine matrix
+pub fn validate_transform(values: &[f32]) -> Result<AnchorTransform, String> {
+    if values.len() != 16 {
+        return Err(format!("Transform must have 16 values, got {}", values.len()));
+    }
+    if values.iter().any(|v| !v.is_finite()) {
+        return Err("Transform contains non-finite values".to_string());
+    }
+
+    let mut transform = [0.0f32; 16];
+    transform.copy_from_slice(values);
+
+    // Bottom row of a column-major affine matrix lives at indices 3, 7, 11, 15
+    const EPS: f32 = 1e-4;
+    if transform[3].abs() > EPS || transform[7].abs() > EPS || transform[11].abs() > EPS
+        || (transform[15] - 1.0).abs() > EPS
+    {
+        return Err("Transform must be affine (bottom row 0, 0, 0, 1)".to_string());
+    }
+
+    if Mat4::from_cols_array(&transform).determinant().abs() < 1e-8 {
+        return Err("Transform is degenerate (zero determinant)".to_string());
+    }
+
+    Ok(transform)
+}
+
+pub fn validate_label(label: &str) -> Result<String, String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("Anchor label must not be empty".to_string());
+    }
+    if label.chars().count() > MAX_ANCHOR_LABEL_LEN {
+        return Err(format!("Anchor label exceeds {} characters", MAX_ANCHOR_LABEL_LEN));
+    }
+    Ok(label.to_string())
+}
+
+/// Room names are used as keys and in URLs, so keep them to a simple charset
+pub fn validate_room(room: &str) -> Result<String, String> {
+    let room = room.trim();
+    if room.is_empty() {
+        return Err("Room name must not be empty".to_string());
+    }
+    if room.len() > MAX_ROOM_NAME_LEN {
+        return Err(format!("Room name exceeds {} characters", MAX_ROOM_NAME_LEN));
+    }
+    if !room.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Room name may only contain letters, digits, '-' and '_'".to_string());
+    }
+    Ok(room.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_transform() {
+        assert!(validate_transform(&IDENTITY_TRANSFORM).is_ok());
+        assert!(validate_transform(&[1.0; 15]).is_err());
+
+        let mut nan = IDENTITY_TRANSFORM;
+        nan[12] = f32::NAN;
+        assert!(validate_transform(&nan).is_err());
+
+        let mut projective = IDENTITY_TRANSFORM;
+        projective[3] = 0.5;
+        assert!(validate_transform(&projective).is_err());
+
+        let mut degenerate = IDENTITY_TRANSFORM;
+        degenerate[0] = 0.0;
+        assert!(validate_transform(&degenerate).is_err());
+    }
+
+    #[test]
+    fn test_validate_room_and_label() {
+        assert_eq!(validate_room(" lab-1 ").unwrap(), "lab-1");
+        assert!(validate_room("").is_err());
+        assert!(validate_room("../etc").is_err());
+        assert!(validate_label("   ").is_err());
+        assert!(validate_label(&"x".repeat(MAX_ANCHOR_LABEL_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_frame_composes_resolved_anchor() {
+        let mut graph_to_anchor = IDENTITY_TRANSFORM;
+        graph_to_anchor[12] = 1.0; // translate x by 1
+        let anchor = SpatialAnchor {
+            id: "a".to_string(),
+            label: "table".to_string(),
+            transform: graph_to_anchor,
+            room: DEFAULT_ROOM.to_string(),
+            created_at: 0,
+        };
+        let mut anchor_to_world = IDENTITY_TRANSFORM;
+        anchor_to_world[13] = 2.0; // translate y by 2
+
+        let frame = AnchorFrame::for_anchor(DEFAULT_ROOM, Some(&anchor)).with_resolved_anchor(&anchor_to_world);
+        let world = frame.graph_to_world.unwrap();
+        assert_eq!(world[12], 1.0);
+        assert_eq!(world[13], 2.0);
+        assert_eq!(frame.anchor_id.as_deref(), Some("a"));
+
+        let empty = AnchorFrame::for_anchor(DEFAULT_ROOM, None);
+        assert_eq!(empty.graph_to_anchor, IDENTITY_TRANSFORM);
+        assert!(empty.anchor_id.is_none());
+    }
+}
@@ -0,0 +1,340 @@
+//! Live feed for the ops dashboard behind `/ws/admin`: simulation diagnostics when they
+//! change, stats once a second, event-log entries as they are recorded, and client
+//! connects and disconnects. Everything is read from the same sources as the REST
+//! endpoints; when nobody is subscribed the sampler does no work.
+
+use actix::Addr;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::actors::messages::{GetClientCount, GetGPUStatus, GetGraphStats, GetIdleStatus, GetWarmupStatus};
+use crate::actors::{ClientManagerActor, GPUComputeActor, GraphServiceActor};
+use crate::services::event_log::{EventLog, GraphEvent};
+use crate::services::graph_service::GraphRebuilds;
+
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+// LOD ranking entries in each stats snapshot
+const STATS_LOD_LIMIT: usize = 20;
+// Stats snapshots a slow subscriber may have queued; older ones are dropped
+const MAX_QUEUED_STATS: usize = 5;
+// Other messages a subscriber may have queued before it is disconnected as lagging
+const MAX_QUEUED_MESSAGES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminTopic {
+    Diagnostics,
+    Stats,
+    Events,
+    Clients,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientNotice {
+    // "connected" or "disconnected"
+    pub event: String,
+    pub client_id: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub clients: usize,
+}
+
+/// One message on the admin channel, sent as `{"type": ..., "data": ...}`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum AdminMessage {
+    Diagnostics(Value),
+    Stats(Value),
+    Event(GraphEvent),
+    Client(ClientNotice),
+}
+
+impl AdminMessage {
+    pub fn topic(&self) -> AdminTopic {
+        match self {
+            AdminMessage::Diagnostics(_) => AdminTopic::Diagnostics,
+            AdminMessage::Stats(_) => AdminTopic::Stats,
+            AdminMessage::Event(_) => AdminTopic::Events,
+            AdminMessage::Client(_) => AdminTopic::Clients,
+        }
+    }
+}
+
+/// What a dashboard asked for on connect. Empty `event_kinds` means every kind.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AdminFilter {
+    pub topics: HashSet<AdminTopic>,
+    pub event_kinds: HashSet<String>,
+}
+
+impl AdminFilter {
+    pub fn matches(&self, message: &AdminMessage) -> bool {
+        if !self.topics.contains(&message.topic()) {
+            return false;
+        }
+        match message {
+            AdminMessage::Event(event) => self.event_kinds.is_empty() || self.event_kinds.contains(&event.kind),
+            _ => true,
+        }
+    }
+}
+
+/// Messages waiting for a subscriber's socket. Stats are the only thing ever dropped:
+/// past the stats cap the oldest snapshot goes, and past the message cap the subscriber
+/// is marked lagging instead, so it can be disconnected without losing events silently.
+pub struct AdminOutbox {
+    queue: VecDeque<AdminMessage>,
+    stats: usize,
+    max_stats: usize,
+    max_messages: usize,
+    dropped_stats: u64,
+    lagging: bool,
+}
+
+impl AdminOutbox {
+    pub fn new(max_stats: usize, max_messages: usize) -> Self {
+        Self { queue: VecDeque::new(), stats: 0, max_stats: max_stats.max(1), max_messages, dropped_stats: 0, lagging: false }
+    }
+
+    pub fn push(&mut self, message: AdminMessage) {
+        if self.lagging {
+            return;
+        }
+        if let AdminMessage::Stats(_) = message {
+            if self.stats == self.max_stats {
+                if let Some(oldest) = self.queue.iter().position(|m| matches!(m, AdminMessage::Stats(_))) {
+                    self.queue.remove(oldest);
+                    self.stats -= 1;
+                    self.dropped_stats += 1;
+                }
+            }
+            self.stats += 1;
+        } else if self.queue.len() - self.stats >= self.max_messages {
+            self.lagging = true;
+            return;
+        }
+        self.queue.push_back(message);
+    }
+
+    pub fn drain(&mut self) -> Vec<AdminMessage> {
+        self.stats = 0;
+        self.queue.drain(..).collect()
+    }
+
+    pub fn is_lagging(&self) -> bool {
+        self.lagging
+    }
+
+    pub fn dropped_stats(&self) -> u64 {
+        self.dropped_stats
+    }
+}
+
+/// One connected dashboard
+pub struct AdminSubscriber {
+    filter: Mutex<AdminFilter>,
+    outbox: Mutex<AdminOutbox>,
+    notify: Notify,
+}
+
+impl AdminSubscriber {
+    pub fn set_filter(&self, filter: AdminFilter) {
+        *self.filter.lock().unwrap() = filter;
+    }
+
+    fn offer(&self, message: &AdminMessage) {
+        if !self.filter.lock().unwrap().matches(message) {
+            return;
+        }
+        self.outbox.lock().unwrap().push(message.clone());
+        self.notify.notify_one();
+    }
+
+    /// Queued messages, and whether the subscriber fell too far behind to keep
+    pub fn take(&self) -> (Vec<AdminMessage>, bool) {
+        let mut outbox = self.outbox.lock().unwrap();
+        (outbox.drain(), outbox.is_lagging())
+    }
+
+    /// Resolves once something is queued or the subscriber is removed
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+}
+
+pub struct AdminFeed {
+    event_log: Arc<EventLog>,
+    subscribers: Mutex<HashMap<usize, Arc<AdminSubscriber>>>,
+    next_id: AtomicUsize,
+}
+
+impl AdminFeed {
+    pub fn new(event_log: Arc<EventLog>) -> Self {
+        Self { event_log, subscribers: Mutex::new(HashMap::new()), next_id: AtomicUsize::new(1) }
+    }
+
+    /// Registers a dashboard with an empty filter; nothing arrives until it sets one
+    pub fn subscribe(&self) -> (usize, Arc<AdminSubscriber>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscriber = Arc::new(AdminSubscriber {
+            filter: Mutex::new(AdminFilter::default()),
+            outbox: Mutex::new(AdminOutbox::new(MAX_QUEUED_STATS, MAX_QUEUED_MESSAGES)),
+            notify: Notify::new(),
+        });
+        self.subscribers.lock().unwrap().insert(id, subscriber.clone());
+        (id, subscriber)
+    }
+
+    pub fn unsubscribe(&self, id: usize) {
+        if let Some(subscriber) = self.subscribers.lock().unwrap().remove(&id) {
+            // Wake its pump so it notices the socket is gone
+            subscriber.notify.notify_one();
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    fn wants(&self, topic: AdminTopic) -> bool {
+        self.subscribers.lock().unwrap().values().any(|s| s.filter.lock().unwrap().topics.contains(&topic))
+    }
+
+    /// Queues `message` for every subscriber whose filter takes it. Never blocks on a socket.
+    pub fn publish(&self, message: AdminMessage) {
+        let subscribers = self.subscribers.lock().unwrap();
+        for subscriber in subscribers.values() {
+            subscriber.offer(&message);
+        }
+    }
+
+    pub fn start(
+        self: Arc<Self>,
+        graph_addr: Addr<GraphServiceActor>,
+        client_manager_addr: Addr<ClientManagerActor>,
+        gpu_addr: Option<Addr<GPUComputeActor>>,
+        rebuilds: Arc<GraphRebuilds>,
+    ) {
+        info!("Starting admin feed");
+        self.clone().follow_events();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(STATS_INTERVAL);
+            let mut last_diagnostics = None;
+            loop {
+                ticker.tick().await;
+                if self.subscriber_count() == 0 {
+                    // A dashboard that connects later gets the current diagnostics first
+                    last_diagnostics = None;
+                    continue;
+                }
+                self.sample(&graph_addr, &client_manager_addr, gpu_addr.as_ref(), &rebuilds, &mut last_diagnostics).await;
+            }
+        });
+    }
+
+    /// Publishes event-log entries as they are recorded, in seq order
+    pub fn follow_events(self: Arc<Self>) {
+        let mut latest = self.event_log.subscribe();
+        let mut cursor = self.event_log.latest_seq();
+        tokio::spawn(async move {
+            while latest.changed().await.is_ok() {
+                let events = self.event_log.since(cursor);
+                if let Some(last) = events.last() {
+                    cursor = last.seq;
+                }
+                if !self.wants(AdminTopic::Events) {
+                    continue;
+                }
+                for event in events {
+                    self.publish(AdminMessage::Event(event));
+                }
+            }
+        });
+    }
+
+    async fn sample(
+        &self,
+        graph_addr: &Addr<GraphServiceActor>,
+        client_manager_addr: &Addr<ClientManagerActor>,
+        gpu_addr: Option<&Addr<GPUComputeActor>>,
+        rebuilds: &GraphRebuilds,
+        last_diagnostics: &mut Option<Value>,
+    ) {
+        if self.wants(AdminTopic::Diagnostics) {
+            let warmup = graph_addr.send(GetWarmupStatus).await.ok().and_then(Result::ok);
+            let idle = graph_addr.send(GetIdleStatus).await.ok().and_then(Result::ok);
+            let gpu = match gpu_addr {
+                Some(addr) => addr.send(GetGPUStatus).await.ok().map(|status| serde_json::json!({
+                    "initialized": status.is_initialized,
+                    "cpuFallback": status.cpu_fallback_active,
+                    "failureCount": status.failure_count,
+                    "reconnecting": status.reconnect.reconnecting,
+                    "nodes": status.num_nodes,
+                })),
+                None => None,
+            };
+            let diagnostics = serde_json::json!({ "warmup": warmup, "idle": idle, "gpu": gpu, "rebuilds": rebuilds.status() });
+            if last_diagnostics.as_ref() != Some(&diagnostics) {
+                *last_diagnostics = Some(diagnostics.clone());
+                self.publish(AdminMessage::Diagnostics(diagnostics));
+            }
+        }
+        if self.wants(AdminTopic::Stats) {
+            match graph_addr.send(GetGraphStats { lod_limit: STATS_LOD_LIMIT }).await {
+                Ok(Ok(stats)) => {
+                    let clients = client_manager_addr.send(GetClientCount).await.ok().and_then(Result::ok).unwrap_or(0);
+                    self.publish(AdminMessage::Stats(serde_json::json!({ "graph": stats, "clients": clients })));
+                }
+                Ok(Err(e)) => debug!("Admin feed skipped a stats snapshot: {}", e),
+                Err(e) => debug!("Admin feed skipped a stats snapshot: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(seq: u64, kind: &str) -> AdminMessage {
+        AdminMessage::Event(GraphEvent { seq, at: chrono::Utc::now(), actor: "test".to_string(), kind: kind.to_string(), detail: json!({}) })
+    }
+
+    #[test]
+    fn test_backpressure_drops_oldest_stats_but_never_events() {
+        let mut outbox = AdminOutbox::new(2, 3);
+        for i in 0..5 {
+            outbox.push(AdminMessage::Stats(json!(i)));
+            if i < 3 {
+                outbox.push(event(i, "create_node"));
+            }
+        }
+        let drained = outbox.drain();
+        let stats: Vec<&Value> = drained.iter().filter_map(|m| match m { AdminMessage::Stats(v) => Some(v), _ => None }).collect();
+        assert_eq!(stats, vec![&json!(3), &json!(4)]);
+        assert_eq!(drained.iter().filter(|m| matches!(m, AdminMessage::Event(_))).count(), 3);
+        assert_eq!(outbox.dropped_stats(), 3);
+        assert!(!outbox.is_lagging());
+
+        // Past the message cap the subscriber is cut off rather than losing an event
+        for i in 0..4 {
+            outbox.push(event(i, "create_node"));
+        }
+        assert!(outbox.is_lagging());
+        assert_eq!(outbox.drain().len(), 3);
+
+        let filter = AdminFilter { topics: [AdminTopic::Events].into(), event_kinds: ["gpu_failure".to_string()].into() };
+        assert!(filter.matches(&event(1, "gpu_failure")));
+        assert!(!filter.matches(&event(1, "create_node")));
+        assert!(!filter.matches(&AdminMessage::Stats(json!({}))));
+    }
+}
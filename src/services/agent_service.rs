@@ -0,0 +1,622 @@
+use actix::Addr;
+use chrono::Utc;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::actors::messages::{
+    AddEdge, BroadcastMessage, CreateNode, GetGenerations, GetGraphData, GetGraphStats, RemoveEdge, UpdateNodeMetadata,
+};
+use crate::actors::{ClientManagerActor, GraphServiceActor};
+use crate::config::AgentSettings;
+use crate::models::annotation::{Annotation, CreateAnnotationRequest};
+use crate::models::edge::{default_affects_physics, Edge, WeightSource};
+use crate::models::graph::{GraphData, GraphDiff, GraphStats, NodeUpdate};
+use crate::models::node::Node;
+use crate::services::annotation_service::AnnotationService;
+use crate::services::audit_log::{AuditLog, AuditRecord};
+use crate::services::event_log::EventLog;
+
+pub const AGENT_EDGE_TYPE: &str = "agent";
+const MAX_LABEL_CHARS: usize = 200;
+const DEFAULT_LOD_LIMIT: usize = 50;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+// Keys agents may not overwrite; they tie nodes back to their source files
+const RESERVED_METADATA_KEYS: [&str; 2] = ["metadataId", "fileName"];
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// One mutation in an agent batch, tagged by `op`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AgentCommand {
+    #[serde(rename_all = "camelCase")]
+    CreateNode {
+        label: String,
+        #[serde(default)]
+        metadata: HashMap<String, String>,
+        #[serde(default)]
+        link_to: Option<u32>,
+        #[serde(default)]
+        edge_type: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    UpdateNode {
+        node_id: u32,
+        metadata: HashMap<String, String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    CreateEdge {
+        source: u32,
+        target: u32,
+        #[serde(default = "default_weight")]
+        weight: f32,
+        #[serde(default)]
+        edge_type: Option<String>,
+        #[serde(default = "default_affects_physics")]
+        affects_physics: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveEdge { edge_id: String },
+    #[serde(rename_all = "camelCase")]
+    CreateAnnotation {
+        node_id: u32,
+        text: String,
+        #[serde(default)]
+        anchor_offset: Option<[f32; 3]>,
+    },
+    #[serde(rename_all = "camelCase")]
+    RunAnalytics {
+        #[serde(default)]
+        lod_limit: Option<usize>,
+    },
+}
+
+/// A `mutate` message from an agent
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentBatch {
+    #[serde(default)]
+    pub request_id: Option<String>,
+    // Validate and return the would-be diff without touching the graph
+    #[serde(default)]
+    pub dry_run: bool,
+    pub commands: Vec<AgentCommand>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    pub request_id: Option<String>,
+    pub dry_run: bool,
+    // Commands applied; less than the batch size if one failed part way
+    pub applied: usize,
+    pub diff: GraphDiff,
+    pub analytics: Vec<GraphStats>,
+    pub errors: Vec<String>,
+}
+
+struct RateWindow {
+    started: Instant,
+    used: u32,
+}
+
+/// Validates and applies agent mutation batches through the same actor messages
+/// and services the REST API uses, attributing every change to the agent.
+pub struct AgentService {
+    settings: AgentSettings,
+    graph_addr: Addr<GraphServiceActor>,
+    client_manager_addr: Addr<ClientManagerActor>,
+    annotation_service: Arc<AnnotationService>,
+    event_log: Arc<EventLog>,
+    audit_log: Option<Arc<AuditLog>>,
+    rate: Mutex<HashMap<String, RateWindow>>,
+}
+
+impl AgentService {
+    pub fn new(
+        settings: AgentSettings,
+        graph_addr: Addr<GraphServiceActor>,
+        client_manager_addr: Addr<ClientManagerActor>,
+        annotation_service: Arc<AnnotationService>,
+        event_log: Arc<EventLog>,
+    ) -> Self {
+        Self {
+            settings,
+            graph_addr,
+            client_manager_addr,
+            annotation_service,
+            event_log,
+            audit_log: None,
+            rate: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_audit_log(mut self, audit_log: Arc<AuditLog>) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    fn audit(&self, actor: &str, record: AuditRecord) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(actor, record);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Agent name for an API key
+    pub fn authenticate(&self, key: &str) -> Option<String> {
+        if key.is_empty() {
+            return None;
+        }
+        self.settings.api_keys.iter()
+            .find(|k| k.key == key)
+            .map(|k| k.name.clone())
+    }
+
+    pub async fn handle_batch(&self, agent: &str, batch: AgentBatch) -> Result<BatchResult, Vec<String>> {
+        self.take_budget(agent, batch.commands.len() as u32).map_err(|e| vec![e])?;
+
+        let graph = self.graph_addr.send(GetGraphData).await
+            .map_err(|e| vec![format!("Graph service unavailable: {}", e)])?
+            .map_err(|e| vec![e])?;
+        let errors = validate_batch(&batch.commands, &graph, self.settings.max_batch_size);
+        if !errors.is_empty() {
+            debug!("Rejected batch from agent {}: {:?}", agent, errors);
+            return Err(errors);
+        }
+
+        let actor = format!("agent:{}", agent);
+        let (mut diff, applied, errors) = if batch.dry_run {
+            (preview_diff(&batch.commands, &graph, &actor), 0, Vec::new())
+        } else {
+            self.apply(&actor, &batch.commands, &graph).await
+        };
+        if !batch.dry_run {
+            diff.generation = self.graph_addr.send(GetGenerations).await.ok()
+                .and_then(|r| r.ok())
+                .map(|g| g.generation);
+        }
+        let analytics = self.run_analytics(&batch.commands).await;
+
+        if !batch.dry_run && !diff.is_empty() {
+            info!("Agent {} applied {} commands", agent, applied);
+            self.client_manager_addr.do_send(BroadcastMessage { message: diff.to_event(&actor).to_string() });
+        }
+        Ok(BatchResult {
+            request_id: batch.request_id,
+            dry_run: batch.dry_run,
+            applied,
+            diff,
+            analytics,
+            errors,
+        })
+    }
+
+    // Fixed one-minute window per agent; every command counts, dry runs included
+    fn take_budget(&self, agent: &str, commands: u32) -> Result<(), String> {
+        let mut rate = self.rate.lock().unwrap();
+        let now = Instant::now();
+        let window = rate.entry(agent.to_string()).or_insert(RateWindow { started: now, used: 0 });
+        if now.duration_since(window.started) >= RATE_WINDOW {
+            window.started = now;
+            window.used = 0;
+        }
+        if window.used + commands > self.settings.commands_per_minute {
+            return Err(format!(
+                "Rate limit exceeded: {} commands per minute",
+                self.settings.commands_per_minute
+            ));
+        }
+        window.used += commands;
+        Ok(())
+    }
+
+    // Applies commands in order, stopping at the first failure
+    async fn apply(&self, actor: &str, commands: &[AgentCommand], graph: &GraphData) -> (GraphDiff, usize, Vec<String>) {
+        let mut diff = GraphDiff::default();
+        let mut applied = 0;
+        for (i, command) in commands.iter().enumerate() {
+            if let Err(e) = self.apply_one(actor, command, graph, &mut diff).await {
+                warn!("Agent command {} failed for {}: {}", i, actor, e);
+                return (diff, applied, vec![format!("command {}: {}", i, e)]);
+            }
+            applied += 1;
+        }
+        (diff, applied, Vec::new())
+    }
+
+    async fn apply_one(&self, actor: &str, command: &AgentCommand, graph: &GraphData, diff: &mut GraphDiff) -> Result<(), String> {
+        match command {
+            AgentCommand::CreateNode { label, metadata, link_to, edge_type } => {
+                let mut metadata = metadata.clone();
+                metadata.insert("source".to_string(), "agent".to_string());
+                metadata.insert("createdBy".to_string(), actor.to_string());
+                let (node, edge) = self.graph_addr.send(CreateNode {
+                    label: label.trim().to_string(),
+                    metadata,
+                    link_to: *link_to,
+                    edge_type: edge_type.clone().unwrap_or_else(|| AGENT_EDGE_TYPE.to_string()),
+                }).await.map_err(|e| e.to_string())??;
+                self.event_log.record(actor, "create_node", json!({ "nodeId": node.id, "label": node.label }));
+                self.audit(actor, AuditRecord::new("create_node")
+                    .nodes([node.id])
+                    .edges(edge.iter().map(|e| e.id.clone()))
+                    .change(None, Some(json!({ "label": node.label }))));
+                diff.added_nodes.push(node);
+                diff.added_edges.extend(edge);
+            }
+            AgentCommand::UpdateNode { node_id, metadata } => {
+                let metadata_id = node_metadata_id(graph, *node_id)?;
+                let before: HashMap<&String, Option<&String>> = graph.nodes.iter()
+                    .find(|n| n.id == *node_id)
+                    .map(|n| metadata.keys().map(|k| (k, n.metadata.get(k))).collect())
+                    .unwrap_or_default();
+                self.graph_addr.send(UpdateNodeMetadata {
+                    metadata_id,
+                    entries: metadata.clone(),
+                }).await.map_err(|e| e.to_string())??;
+                self.event_log.record(actor, "update_node", json!({ "nodeId": node_id, "metadata": metadata }));
+                self.audit(actor, AuditRecord::new("update_node")
+                    .nodes([*node_id])
+                    .change(Some(json!(before)), Some(json!(metadata))));
+                diff.updated_nodes.push(NodeUpdate { node_id: *node_id, metadata: metadata.clone() });
+            }
+            AgentCommand::CreateEdge { source, target, weight, edge_type, affects_physics } => {
+                let edge = new_edge(*source, *target, *weight, edge_type, *affects_physics);
+                self.graph_addr.send(AddEdge { edge: edge.clone() }).await.map_err(|e| e.to_string())??;
+                self.event_log.record(actor, "create_edge", json!({ "edgeId": edge.id }));
+                self.audit(actor, AuditRecord::new("create_edge").nodes([edge.source, edge.target]).edges([edge.id.clone()]));
+                diff.added_edges.push(edge);
+            }
+            AgentCommand::RemoveEdge { edge_id } => {
+                self.graph_addr.send(RemoveEdge { edge_id: edge_id.clone() }).await.map_err(|e| e.to_string())??;
+                self.event_log.record(actor, "remove_edge", json!({ "edgeId": edge_id }));
+                let ends = graph.edges.iter().filter(|e| &e.id == edge_id).flat_map(|e| [e.source, e.target]);
+                self.audit(actor, AuditRecord::new("remove_edge").nodes(ends).edges([edge_id.clone()]));
+                diff.removed_edges.push(edge_id.clone());
+            }
+            AgentCommand::CreateAnnotation { node_id, text, anchor_offset } => {
+                let metadata_id = node_metadata_id(graph, *node_id)?;
+                let annotation = self.annotation_service.create(&metadata_id, actor, CreateAnnotationRequest {
+                    text: text.clone(),
+                    anchor_offset: *anchor_offset,
+                }).await.map_err(|e| e.to_string())?;
+                // Same event the REST endpoint sends, for clients that don't read graph diffs
+                let event = json!({
+                    "type": "annotation_created",
+                    "nodeId": node_id,
+                    "annotation": annotation
+                });
+                self.client_manager_addr.do_send(BroadcastMessage { message: event.to_string() });
+                self.event_log.record(actor, "create_annotation", json!({ "nodeId": node_id, "annotationId": annotation.id }));
+                self.audit(actor, AuditRecord::new("create_annotation")
+                    .nodes([*node_id])
+                    .change(None, Some(json!({ "annotationId": annotation.id, "text": annotation.text }))));
+                diff.added_annotations.push(annotation);
+            }
+            AgentCommand::RunAnalytics { .. } => {}
+        }
+        Ok(())
+    }
+
+    // Analytics are read-only, so they run for dry runs too
+    async fn run_analytics(&self, commands: &[AgentCommand]) -> Vec<GraphStats> {
+        let mut results = Vec::new();
+        for command in commands {
+            if let AgentCommand::RunAnalytics { lod_limit } = command {
+                match self.graph_addr.send(GetGraphStats { lod_limit: lod_limit.unwrap_or(DEFAULT_LOD_LIMIT) }).await {
+                    Ok(Ok(stats)) => results.push(stats),
+                    _ => warn!("Graph stats unavailable for agent analytics"),
+                }
+            }
+        }
+        results
+    }
+}
+
+fn node_metadata_id(graph: &GraphData, node_id: u32) -> Result<String, String> {
+    graph.nodes.iter()
+        .find(|n| n.id == node_id)
+        .map(|n| n.metadata_id.clone())
+        .ok_or_else(|| format!("Node {} not found", node_id))
+}
+
+fn new_edge(source: u32, target: u32, weight: f32, edge_type: &Option<String>, affects_physics: bool) -> Edge {
+    let mut edge = Edge::new(source, target, weight).credited_to(WeightSource::Agent, Utc::now());
+    edge.edge_type = Some(edge_type.clone().unwrap_or_else(|| AGENT_EDGE_TYPE.to_string()));
+    edge.affects_physics = affects_physics;
+    edge
+}
+
+fn validate_metadata(metadata: &HashMap<String, String>) -> Option<String> {
+    if let Some(key) = metadata.keys().find(|k| k.trim().is_empty()) {
+        return Some(format!("invalid metadata key '{}'", key));
+    }
+    RESERVED_METADATA_KEYS.iter()
+        .find(|k| metadata.contains_key(**k))
+        .map(|k| format!("metadata key '{}' is reserved", k))
+}
+
+/// Checks a batch against the current graph without changing anything. Returns
+/// every problem found, prefixed with the command index.
+pub fn validate_batch(commands: &[AgentCommand], graph: &GraphData, max_batch_size: usize) -> Vec<String> {
+    let mut errors = Vec::new();
+    if commands.is_empty() {
+        errors.push("Batch has no commands".to_string());
+    }
+    if commands.len() > max_batch_size {
+        errors.push(format!("Batch has {} commands, maximum is {}", commands.len(), max_batch_size));
+        return errors;
+    }
+
+    let node_ids: HashSet<u32> = graph.nodes.iter().map(|n| n.id).collect();
+    let mut edge_ids: HashSet<&str> = graph.edges.iter().map(|e| e.id.as_str()).collect();
+    let missing = |id: &u32| (!node_ids.contains(id)).then(|| format!("node {} not found", id));
+
+    for (i, command) in commands.iter().enumerate() {
+        let mut problems: Vec<String> = Vec::new();
+        match command {
+            AgentCommand::CreateNode { label, metadata, link_to, .. } => {
+                let len = label.trim().chars().count();
+                if len == 0 || len > MAX_LABEL_CHARS {
+                    problems.push(format!("label must be 1-{} characters", MAX_LABEL_CHARS));
+                }
+                problems.extend(validate_metadata(metadata));
+                problems.extend(link_to.as_ref().and_then(missing));
+            }
+            AgentCommand::UpdateNode { node_id, metadata } => {
+                problems.extend(missing(node_id));
+                if metadata.is_empty() {
+                    problems.push("no metadata to update".to_string());
+                }
+                problems.extend(validate_metadata(metadata));
+            }
+            AgentCommand::CreateEdge { source, target, weight, .. } => {
+                problems.extend(missing(source));
+                problems.extend(missing(target));
+                if source == target {
+                    problems.push("edge source and target must differ".to_string());
+                }
+                if !weight.is_finite() || *weight <= 0.0 {
+                    problems.push("edge weight must be positive".to_string());
+                }
+            }
+            AgentCommand::RemoveEdge { edge_id } => {
+                // Removing the same edge twice in one batch is an error too
+                if !edge_ids.remove(edge_id.as_str()) {
+                    problems.push(format!("edge {} not found", edge_id));
+                }
+            }
+            AgentCommand::CreateAnnotation { node_id, text, anchor_offset } => {
+                problems.extend(missing(node_id));
+                let request = CreateAnnotationRequest { text: text.clone(), anchor_offset: *anchor_offset };
+                problems.extend(request.validate().err().map(|e| e.to_string()));
+            }
+            AgentCommand::RunAnalytics { .. } => {}
+        }
+        errors.extend(problems.into_iter().map(|p| format!("command {}: {}", i, p)));
+    }
+    errors
+}
+
+/// The diff a valid batch would produce. Nodes that would be created have id 0
+/// until the batch is applied for real.
+pub fn preview_diff(commands: &[AgentCommand], graph: &GraphData, actor: &str) -> GraphDiff {
+    let mut diff = GraphDiff::default();
+    for command in commands {
+        match command {
+            AgentCommand::CreateNode { label, metadata, link_to, edge_type } => {
+                // Explicit id so the preview doesn't draw from the global id counter
+                let mut node = Node::new_with_id(String::new(), Some(u32::MAX)).with_label(label.trim().to_string());
+                node.id = 0;
+                node.metadata = metadata.clone();
+                node.metadata.insert("createdBy".to_string(), actor.to_string());
+                diff.added_nodes.push(node);
+                if let Some(target) = link_to {
+                    diff.added_edges.push(new_edge(0, *target, 1.0, edge_type, true));
+                }
+            }
+            AgentCommand::UpdateNode { node_id, metadata } => {
+                diff.updated_nodes.push(NodeUpdate { node_id: *node_id, metadata: metadata.clone() });
+            }
+            AgentCommand::CreateEdge { source, target, weight, edge_type, affects_physics } => {
+                diff.added_edges.push(new_edge(*source, *target, *weight, edge_type, *affects_physics));
+            }
+            AgentCommand::RemoveEdge { edge_id } => diff.removed_edges.push(edge_id.clone()),
+            AgentCommand::CreateAnnotation { node_id, text, anchor_offset } => {
+                diff.added_annotations.push(Annotation {
+                    id: String::new(),
+                    metadata_id: node_metadata_id(graph, *node_id).unwrap_or_default(),
+                    author: actor.to_string(),
+                    text: text.trim().to_string(),
+                    created_at: Utc::now().timestamp_millis(),
+                    anchor_offset: *anchor_offset,
+                });
+            }
+            AgentCommand::RunAnalytics { .. } => {}
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::{BuildGraphFromMetadata, CloseConnection, GetNodeMap, RegisterAgent, SendToClientText};
+    use crate::config::AgentKey;
+    use crate::models::metadata::{Metadata, MetadataStore};
+    use actix::prelude::*;
+    use std::path::PathBuf;
+
+    // Stands in for the agent's websocket, collecting broadcast events
+    struct Collector {
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Actor for Collector {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(msg.0);
+        }
+    }
+
+    impl Handler<CloseConnection> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, _msg: CloseConnection, ctx: &mut Self::Context) {
+            ctx.stop();
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("agent-test-{}", uuid::Uuid::new_v4())).join("annotations.json")
+    }
+
+    fn batch(json: serde_json::Value) -> AgentBatch {
+        serde_json::from_value(json).expect("batch should parse")
+    }
+
+    #[actix_web::test]
+    async fn test_scripted_agent_end_to_end() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), Metadata { file_name: "a.md".to_string(), ..Default::default() });
+        store.insert("b.md".to_string(), Metadata { file_name: "b.md".to_string(), ..Default::default() });
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let node_map = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let id_of = |name: &str| *node_map.iter().find(|(_, n)| n.metadata_id == name).unwrap().0;
+        let (a, b) = (id_of("a"), id_of("b"));
+
+        let annotations = Arc::new(AnnotationService::with_path(temp_path()));
+        let event_log = Arc::new(EventLog::new());
+        let settings = AgentSettings {
+            enabled: true,
+            api_keys: vec![AgentKey { name: "scripted".to_string(), key: "secret".to_string() }],
+            max_batch_size: 10,
+            commands_per_minute: 8,
+        };
+        let service = AgentService::new(settings, graph.clone(), client_manager.clone(), annotations.clone(), event_log.clone());
+
+        // Connect: authenticate and subscribe to events
+        assert_eq!(service.authenticate("wrong"), None);
+        let agent = service.authenticate("secret").expect("key should authenticate");
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let collector = Collector { received: received.clone() }.start();
+        client_manager.send(RegisterAgent {
+            name: agent.clone(),
+            text: collector.clone().recipient(),
+            close: collector.recipient(),
+        }).await.unwrap().unwrap();
+
+        let commands = json!([
+            { "op": "create_node", "label": "Vector clocks", "linkTo": a },
+            { "op": "create_edge", "source": a, "target": b, "weight": 2.0 },
+            { "op": "create_annotation", "nodeId": b, "text": "check this" }
+        ]);
+
+        // Dry run returns the would-be diff and changes nothing
+        let preview = service.handle_batch(&agent, batch(json!({ "requestId": "r1", "dryRun": true, "commands": commands })))
+            .await.unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.applied, 0);
+        assert_eq!(preview.diff.added_nodes.len(), 1);
+        assert_eq!(preview.diff.added_nodes[0].id, 0);
+        assert_eq!(preview.diff.added_edges.len(), 2);
+        assert_eq!(preview.diff.added_annotations[0].metadata_id, "b");
+        assert_eq!(graph.send(GetGraphData).await.unwrap().unwrap().nodes.len(), 2);
+        assert!(annotations.list("b").await.is_empty());
+        assert!(event_log.recent(10).is_empty());
+
+        // Applying it for real
+        let result = service.handle_batch(&agent, batch(json!({ "requestId": "r2", "commands": commands })))
+            .await.unwrap();
+        assert_eq!(result.applied, 3);
+        assert!(result.errors.is_empty());
+        let created = result.diff.added_nodes[0].id;
+        assert_ne!(created, 0);
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        assert_eq!(data.nodes.len(), 3);
+        assert!(data.edges.iter().any(|e| e.source == created && e.target == a && e.edge_type.as_deref() == Some(AGENT_EDGE_TYPE)));
+        assert_eq!(annotations.list("b").await[0].author, "agent:scripted");
+
+        let events = event_log.recent(10);
+        assert_eq!(events.iter().map(|e| e.kind.as_str()).collect::<Vec<_>>(), vec!["create_node", "create_edge", "create_annotation"]);
+        assert!(events.iter().all(|e| e.actor == "agent:scripted"));
+
+        // The subscribed agent sees its own change as a JSON graph diff
+        let mut diff_event = None;
+        for _ in 0..100 {
+            diff_event = received.lock().unwrap().iter()
+                .map(|m| serde_json::from_str::<serde_json::Value>(m).unwrap())
+                .find(|v| v["type"] == "graph_diff");
+            if diff_event.is_some() {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        let diff_event = diff_event.expect("graph diff should be broadcast");
+        assert_eq!(diff_event["actor"], "agent:scripted");
+        assert_eq!(diff_event["addedNodes"][0]["id"], created);
+        assert_eq!(diff_event["generation"], data.generation);
+
+        // Invalid batches are rejected whole
+        let errors = service.handle_batch(&agent, batch(json!({ "commands": [{ "op": "update_node", "nodeId": 9999, "metadata": { "k": "v" } }] })))
+            .await.unwrap_err();
+        assert!(errors[0].starts_with("command 0: node 9999 not found"));
+        assert_eq!(event_log.recent(10).len(), 3);
+
+        // 3 + 3 + 1 commands used of 8 this minute
+        let errors = service.handle_batch(&agent, batch(json!({ "commands": [
+            { "op": "run_analytics" },
+            { "op": "run_analytics" }
+        ] }))).await.unwrap_err();
+        assert!(errors[0].contains("Rate limit"));
+
+        let stats = service.handle_batch(&agent, batch(json!({ "commands": [{ "op": "run_analytics", "lodLimit": 2 }] })))
+            .await.unwrap();
+        assert_eq!(stats.analytics[0].node_count, 3);
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let mut graph = GraphData::new();
+        graph.nodes.push(Node::new_with_id("a".to_string(), Some(1)));
+        graph.nodes.push(Node::new_with_id("b".to_string(), Some(2)));
+        graph.edges.push(Edge::new(1, 2, 1.0));
+
+        let commands: Vec<AgentCommand> = serde_json::from_value(json!([
+            { "op": "create_node", "label": "  ", "metadata": { "metadataId": "x" } },
+            { "op": "create_edge", "source": 1, "target": 1, "weight": -1.0 },
+            { "op": "remove_edge", "edgeId": "1-2" },
+            { "op": "remove_edge", "edgeId": "1-2" },
+            { "op": "create_annotation", "nodeId": 3, "text": "" }
+        ])).unwrap();
+        let errors = validate_batch(&commands, &graph, 10);
+        assert_eq!(errors, vec![
+            "command 0: label must be 1-200 characters",
+            "command 0: metadata key 'metadataId' is reserved",
+            "command 1: edge source and target must differ",
+            "command 1: edge weight must be positive",
+            "command 3: edge 1-2 not found",
+            "command 4: node 3 not found",
+            "command 4: Annotation text must not be empty",
+        ]);
+
+        assert_eq!(validate_batch(&commands, &graph, 2), vec!["Batch has 5 commands, maximum is 2"]);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::models::spatial_anchor::{
+    validate_label, validate_room, validate_transform, AnchorFrame, CreateAnchorRequest,
+    SpatialAnchor, DEFAULT_ROOM,
+};
+use crate::utils::json_store::write_json_atomic;
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const ANCHORS_PATH: &str = "/app/data/anchors/anchors.json";
+const MAX_ANCHORS_PER_ROOM: usize = 32;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AnchorError {
+    #[error("{0}")]
+    InvalidLabel(String),
+    #[error("{0}")]
+    InvalidRoom(String),
+    #[error("{0}")]
+    InvalidTransform(String),
+    #[error("Room '{0}' already has the maximum of {MAX_ANCHORS_PER_ROOM} anchors")]
+    RoomFull(String),
+    #[error("Anchor {0}")]
+    NotFound(String),
+    #[error("Anchor {anchor_id} belongs to room '{anchor_room}', not '{room}'")]
+    WrongRoom { anchor_id: String, anchor_room: String, room: String },
+    #[error("{0}")]
+    Storage(String),
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnchorStore {
+    anchors: HashMap<String, SpatialAnchor>,
+    // room -> anchor id currently defining the shared frame
+    active: HashMap<String, String>,
+}
+
+/// Stores named spatial anchors per room and which one is active.
+///
+/// The server never transforms node positions; it only keeps and hands out the
+/// graph-to-anchor mapping so every client in a room shares one frame.
+pub struct AnchorService {
+    store: RwLock<AnchorStore>,
+    path: PathBuf,
+}
+
+impl Default for AnchorService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnchorService {
+    pub fn new() -> Self {
+        Self::with_path(PathBuf::from(ANCHORS_PATH))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        let store = match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<AnchorStore>(&content) {
+                Ok(store) => {
+                    info!("Loaded {} spatial anchors from {:?}", store.anchors.len(), path);
+                    store
+                }
+                Err(e) => {
+                    error!("Failed to parse anchors file {:?}: {}. Starting empty.", path, e);
+                    AnchorStore::default()
+                }
+            },
+            Err(_) => {
+                info!("No anchors file at {:?}, starting empty", path);
+                AnchorStore::default()
+            }
+        };
+
+        Self {
+            store: RwLock::new(store),
+            path,
+        }
+    }
+
+    fn persist(&self, store: &AnchorStore) -> Result<(), AnchorError> {
+        write_json_atomic(&self.path, store).map_err(AnchorError::Storage)
+    }
+
+    pub async fn list(&self, room: Option<&str>) -> Vec<SpatialAnchor> {
+        let store = self.store.read().await;
+        let mut anchors: Vec<SpatialAnchor> = store
+            .anchors
+            .values()
+            .filter(|a| room.is_none_or(|r| a.room == r))
+            .cloned()
+            .collect();
+        anchors.sort_by_key(|a| a.created_at);
+        anchors
+    }
+
+    pub async fn create(&self, request: CreateAnchorRequest) -> Result<SpatialAnchor, AnchorError> {
+        let label = validate_label(&request.label).map_err(AnchorError::InvalidLabel)?;
+        let room = validate_room(request.room.as_deref().unwrap_or(DEFAULT_ROOM)).map_err(AnchorError::InvalidRoom)?;
+        let transform = validate_transform(&request.transform).map_err(AnchorError::InvalidTransform)?;
+
+        let mut store = self.store.write().await;
+        let room_count = store.anchors.values().filter(|a| a.room == room).count();
+        if room_count >= MAX_ANCHORS_PER_ROOM {
+            return Err(AnchorError::RoomFull(room));
+        }
+
+        let anchor = SpatialAnchor {
+            id: Uuid::new_v4().to_string(),
+            label,
+            transform,
+            room: room.clone(),
+            created_at: Utc::now().timestamp_millis(),
+        };
+
+        let mut updated = store.clone();
+        updated.anchors.insert(anchor.id.clone(), anchor.clone());
+        // First anchor in a room becomes active automatically
+        updated.active.entry(room).or_insert_with(|| anchor.id.clone());
+        self.persist(&updated)?;
+        *store = updated;
+
+        info!("Created spatial anchor {} ('{}') in room {}", anchor.id, anchor.label, anchor.room);
+        Ok(anchor)
+    }
+
+    /// Deletes an anchor. Returns the deleted anchor and, if it was active, the room's new frame.
+    pub async fn delete(&self, id: &str) -> Result<(SpatialAnchor, Option<AnchorFrame>), AnchorError> {
+        let mut store = self.store.write().await;
+        let anchor = store
+            .anchors
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AnchorError::NotFound(id.to_string()))?;
+
+        let mut updated = store.clone();
+        updated.anchors.remove(id);
+        let was_active = updated.active.get(&anchor.room).is_some_and(|a| a == id);
+        if was_active {
+            updated.active.remove(&anchor.room);
+        }
+        self.persist(&updated)?;
+        *store = updated;
+
+        info!("Deleted spatial anchor {} from room {}", id, anchor.room);
+        let frame = if was_active {
+            warn!("Active anchor for room {} was deleted, room falls back to graph space", anchor.room);
+            Some(AnchorFrame::for_anchor(&anchor.room, None))
+        } else {
+            None
+        };
+        Ok((anchor, frame))
+    }
+
+    pub async fn set_active(&self, room: &str, anchor_id: &str) -> Result<AnchorFrame, AnchorError> {
+        let room = validate_room(room).map_err(AnchorError::InvalidRoom)?;
+        let mut store = self.store.write().await;
+        let anchor = store
+            .anchors
+            .get(anchor_id)
+            .cloned()
+            .ok_or_else(|| AnchorError::NotFound(anchor_id.to_string()))?;
+        if anchor.room != room {
+            return Err(AnchorError::WrongRoom { anchor_id: anchor_id.to_string(), anchor_room: anchor.room, room });
+        }
+
+        let mut updated = store.clone();
+        updated.active.insert(room.clone(), anchor.id.clone());
+        self.persist(&updated)?;
+        *store = updated;
+
+        info!("Active anchor for room {} set to {}", room, anchor.id);
+        Ok(AnchorFrame::for_anchor(&room, Some(&anchor)))
+    }
+
+    /// Current frame for a room; identity when no anchor is active
+    pub async fn frame_for_room(&self, room: &str) -> AnchorFrame {
+        let store = self.store.read().await;
+        let anchor = store.active.get(room).and_then(|id| store.anchors.get(id));
+        AnchorFrame::for_anchor(room, anchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(label: &str, room: &str) -> CreateAnchorRequest {
+        let mut transform = vec![0.0; 16];
+        for i in 0..4 {
+            transform[i * 5] = 1.0;
+        }
+        CreateAnchorRequest { label: label.to_string(), room: Some(room.to_string()), transform }
+    }
+
+    #[tokio::test]
+    async fn test_errors_say_what_went_wrong() {
+        let path = std::env::temp_dir().join(format!("anchors-{}.json", Uuid::new_v4()));
+        let service = AnchorService::with_path(path.clone());
+
+        assert!(matches!(service.create(request(" ", "lab")).await, Err(AnchorError::InvalidLabel(_))));
+        assert!(matches!(service.create(request("desk", "../etc")).await, Err(AnchorError::InvalidRoom(_))));
+        let anchor = service.create(request("desk", "lab")).await.unwrap();
+
+        assert_eq!(service.delete("missing").await.unwrap_err(), AnchorError::NotFound("missing".to_string()));
+        assert_eq!(
+            service.set_active("studio", &anchor.id).await.unwrap_err(),
+            AnchorError::WrongRoom { anchor_id: anchor.id.clone(), anchor_room: "lab".to_string(), room: "studio".to_string() },
+        );
+
+        for i in 1..MAX_ANCHORS_PER_ROOM {
+            service.create(request(&format!("desk {}", i), "lab")).await.unwrap();
+        }
+        assert_eq!(service.create(request("one more", "lab")).await.unwrap_err(), AnchorError::RoomFull("lab".to_string()));
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::models::annotation::{Annotation, AnnotationError, CreateAnnotationRequest, MAX_ANNOTATIONS_PER_NODE};
+use crate::utils::json_store::write_json_atomic;
+use chrono::Utc;
+use log::{error, info};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const ANNOTATIONS_PATH: &str = "/app/data/annotations/annotations.json";
+
+// metadata_id -> annotations, oldest first
+type AnnotationStore = HashMap<String, Vec<Annotation>>;
+
+/// Sticky-note store that sits alongside the graph. Keyed by metadata id so
+/// notes re-resolve to the right node after a rebuild.
+///
+/// Every write rewrites the one file. With at most `MAX_ANNOTATIONS_PER_NODE` short notes
+/// per node that stays small, and a change is made in place and undone if the write
+/// fails, so the store itself is never copied.
+pub struct AnnotationService {
+    store: RwLock<AnnotationStore>,
+    path: PathBuf,
+}
+
+impl Default for AnnotationService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnnotationService {
+    pub fn new() -> Self {
+        Self::with_path(PathBuf::from(ANNOTATIONS_PATH))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        let store = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<AnnotationStore>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse annotations file {:?}: {}. Starting empty.", path, e);
+                AnnotationStore::new()
+            }),
+            Err(_) => AnnotationStore::new(),
+        };
+        info!("Loaded annotations for {} nodes", store.len());
+
+        Self {
+            store: RwLock::new(store),
+            path,
+        }
+    }
+
+    /// Rewrites the file from memory. Writes are already write-through, so this only
+    /// matters if a previous write failed.
+    pub async fn flush(&self) -> Result<(), String> {
+        let store = self.store.read().await;
+        write_json_atomic(&self.path, &*store)
+    }
+
+    /// Metadata ids that have at least one annotation
+    pub async fn metadata_ids(&self) -> Vec<String> {
+        self.store.read().await.keys().cloned().collect()
+    }
+
+    pub async fn list(&self, metadata_id: &str) -> Vec<Annotation> {
+        self.store.read().await.get(metadata_id).cloned().unwrap_or_default()
+    }
+
+    /// Annotations for many nodes at once, used when paginating the graph
+    pub async fn list_many<'a, I>(&self, metadata_ids: I) -> HashMap<String, Vec<Annotation>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let store = self.store.read().await;
+        metadata_ids
+            .into_iter()
+            .filter_map(|id| store.get(id).map(|notes| (id.to_string(), notes.clone())))
+            .collect()
+    }
+
+    pub async fn create(
+        &self,
+        metadata_id: &str,
+        author: &str,
+        request: CreateAnnotationRequest,
+    ) -> Result<Annotation, AnnotationError> {
+        let text = request.validate()?;
+
+        let mut store = self.store.write().await;
+        let existing = store.get(metadata_id).map_or(0, |notes| notes.len());
+        if existing >= MAX_ANNOTATIONS_PER_NODE {
+            return Err(AnnotationError::NodeFull);
+        }
+
+        let annotation = Annotation {
+            id: Uuid::new_v4().to_string(),
+            metadata_id: metadata_id.to_string(),
+            author: author.to_string(),
+            text,
+            created_at: Utc::now().timestamp_millis(),
+            anchor_offset: request.anchor_offset,
+        };
+
+        store.entry(metadata_id.to_string()).or_default().push(annotation.clone());
+        if let Err(e) = write_json_atomic(&self.path, &*store) {
+            remove_note(&mut store, metadata_id, &annotation.id);
+            return Err(AnnotationError::Storage(e));
+        }
+
+        Ok(annotation)
+    }
+
+    /// Removes an annotation. `can_delete` decides whether the caller may remove it
+    /// (e.g. author or power user).
+    pub async fn delete<F>(&self, metadata_id: &str, annotation_id: &str, can_delete: F) -> Result<Annotation, AnnotationError>
+    where
+        F: FnOnce(&Annotation) -> bool,
+    {
+        let mut store = self.store.write().await;
+        let notes = store.get(metadata_id).map(Vec::as_slice).unwrap_or_default();
+        let Some(index) = notes.iter().position(|a| a.id == annotation_id) else {
+            return Err(AnnotationError::NotFound(annotation_id.to_string()));
+        };
+        if !can_delete(&notes[index]) {
+            return Err(AnnotationError::NotAuthor);
+        }
+
+        let annotation = remove_note(&mut store, metadata_id, annotation_id).expect("annotation was just found");
+        if let Err(e) = write_json_atomic(&self.path, &*store) {
+            let notes = store.entry(metadata_id.to_string()).or_default();
+            notes.insert(index.min(notes.len()), annotation);
+            return Err(AnnotationError::Storage(e));
+        }
+
+        Ok(annotation)
+    }
+}
+
+// Takes a note out, and the node's entry with it once that was the last one
+fn remove_note(store: &mut AnnotationStore, metadata_id: &str, annotation_id: &str) -> Option<Annotation> {
+    let notes = store.get_mut(metadata_id)?;
+    let annotation = notes.remove(notes.iter().position(|a| a.id == annotation_id)?);
+    if notes.is_empty() {
+        store.remove(metadata_id);
+    }
+    Some(annotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::annotation::MAX_ANNOTATION_LENGTH;
+
+    fn note(text: &str) -> CreateAnnotationRequest {
+        CreateAnnotationRequest { text: text.to_string(), anchor_offset: None }
+    }
+
+    fn service() -> AnnotationService {
+        AnnotationService::with_path(std::env::temp_dir().join(format!("annotations-{}.json", Uuid::new_v4())))
+    }
+
+    #[tokio::test]
+    async fn test_text_length_is_limited() {
+        let service = service();
+        let longest = "x".repeat(MAX_ANNOTATION_LENGTH);
+        assert_eq!(service.create("a.md", "alice", note(&longest)).await.unwrap().text, longest);
+        // Surrounding whitespace doesn't count, characters do rather than bytes
+        assert!(service.create("a.md", "alice", note(&format!("  {}  ", "é".repeat(MAX_ANNOTATION_LENGTH)))).await.is_ok());
+        assert_eq!(
+            service.create("a.md", "alice", note(&format!("{}x", longest))).await.unwrap_err(),
+            AnnotationError::TooLong(MAX_ANNOTATION_LENGTH + 1),
+        );
+        assert_eq!(service.create("a.md", "alice", note("   ")).await.unwrap_err(), AnnotationError::EmptyText);
+        assert_eq!(service.list("a.md").await.len(), 2);
+        let _ = fs::remove_file(&service.path);
+    }
+
+    #[tokio::test]
+    async fn test_notes_per_node_are_limited() {
+        let service = service();
+        for i in 0..MAX_ANNOTATIONS_PER_NODE {
+            service.create("a.md", "alice", note(&format!("note {}", i))).await.unwrap();
+        }
+        assert_eq!(service.create("a.md", "bob", note("one more")).await.unwrap_err(), AnnotationError::NodeFull);
+        // The limit is per node
+        assert!(service.create("b.md", "bob", note("elsewhere")).await.is_ok());
+
+        // What's on disk is what's in memory
+        let reloaded = AnnotationService::with_path(service.path.clone());
+        assert_eq!(reloaded.list("a.md").await.len(), MAX_ANNOTATIONS_PER_NODE);
+        let _ = fs::remove_file(&service.path);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_author_deletes() {
+        let service = service();
+        let annotation = service.create("a.md", "alice", note("mine")).await.unwrap();
+
+        let err = service.delete("a.md", &annotation.id, |a| a.author == "bob").await.unwrap_err();
+        assert_eq!(err, AnnotationError::NotAuthor);
+        assert_eq!(service.list("a.md").await.len(), 1);
+        assert_eq!(service.delete("b.md", &annotation.id, |_| true).await.unwrap_err(), AnnotationError::NotFound(annotation.id.clone()));
+
+        assert_eq!(service.delete("a.md", &annotation.id, |a| a.author == "alice").await.unwrap().id, annotation.id);
+        assert!(service.metadata_ids().await.is_empty());
+        let _ = fs::remove_file(&service.path);
+    }
+
+    #[tokio::test]
+    async fn test_failed_writes_leave_the_store_unchanged() {
+        // A directory where the file should be makes every write fail
+        let dir = std::env::temp_dir().join(format!("annotations-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let service = AnnotationService::with_path(dir.clone());
+        assert!(matches!(service.create("a.md", "alice", note("lost")).await, Err(AnnotationError::Storage(_))));
+        assert!(service.list("a.md").await.is_empty());
+        let _ = fs::remove_file(dir.with_extension("json.tmp"));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,395 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::config::AuditSettings;
+use crate::models::graph::GraphDiff;
+use crate::models::position_history::HistoryStep;
+use crate::models::node_attributes::{AttributeUpdateStatus, NodeAttributeResult};
+
+const AUDIT_FILE: &str = "audit.ndjson";
+const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+const REDACTED: &str = "[redacted]";
+
+/// What one mutation did, before it is attributed and written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditRecord {
+    pub operation: String,
+    pub nodes: Vec<u32>,
+    pub edges: Vec<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditRecord {
+    pub fn new(operation: &str) -> Self {
+        Self { operation: operation.to_string(), ..Default::default() }
+    }
+
+    /// Adds ids not already listed
+    pub fn nodes(mut self, ids: impl IntoIterator<Item = u32>) -> Self {
+        for id in ids {
+            if !self.nodes.contains(&id) {
+                self.nodes.push(id);
+            }
+        }
+        self
+    }
+
+    pub fn edges(mut self, ids: impl IntoIterator<Item = String>) -> Self {
+        for id in ids {
+            if !self.edges.contains(&id) {
+                self.edges.push(id);
+            }
+        }
+        self
+    }
+
+    pub fn change(mut self, before: Option<Value>, after: Option<Value>) -> Self {
+        self.before = before;
+        self.after = after;
+        self
+    }
+
+    /// Every node and edge a diff added, changed or removed
+    pub fn from_diff(operation: &str, diff: &GraphDiff) -> Self {
+        Self::new(operation)
+            .nodes(diff.added_nodes.iter().map(|n| n.id))
+            .nodes(diff.updated_nodes.iter().map(|u| u.node_id))
+            .nodes(diff.removed_nodes.iter().copied())
+            .edges(diff.added_edges.iter().chain(&diff.updated_edges).map(|e| e.id.clone()))
+            .edges(diff.removed_edges.iter().cloned())
+    }
+
+    /// An undo or redo of a manual move; None when there was nothing to apply
+    pub fn from_position_step(undo: bool, step: &HistoryStep) -> Option<Self> {
+        let edit = step.applied.as_ref()?;
+        // The edit is kept as first made, so an undo takes the node from after back to before
+        let (before, after) = if undo { (&edit.after, &edit.before) } else { (&edit.before, &edit.after) };
+        let operation = if undo { "undo_move" } else { "redo_move" };
+        Some(Self::new(operation).nodes([edit.node_id]).change(Some(serde_json::json!(before)), Some(serde_json::json!(after))))
+    }
+
+    /// The nodes an attribute update changed, with their touched values keyed by node id
+    pub fn from_attribute_results(operation: &str, results: &[NodeAttributeResult]) -> Self {
+        let updated: Vec<&NodeAttributeResult> = results.iter()
+            .filter(|r| r.status == AttributeUpdateStatus::Updated)
+            .collect();
+        let values = |pick: fn(&NodeAttributeResult) -> &Option<Value>| -> Value {
+            Value::Object(updated.iter().map(|r| (r.node_id.to_string(), pick(r).clone().unwrap_or_default())).collect())
+        };
+        Self::new(operation)
+            .nodes(updated.iter().map(|r| r.node_id))
+            .change(Some(values(|r| &r.before)), Some(values(|r| &r.after)))
+    }
+}
+
+/// One attributed mutation as stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    // Pubkey of whoever made the change, or e.g. "agent:indexer"
+    pub principal: String,
+    pub operation: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edges: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+    // The before/after values were too large to keep
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub change_omitted: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub node: Option<u32>,
+    pub principal: Option<String>,
+    pub operation: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    // Only entries older than this seq; the previous page's `nextCursor`
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.cursor.is_none_or(|cursor| entry.seq < cursor)
+            && self.since.is_none_or(|since| entry.at >= since)
+            && self.node.is_none_or(|node| entry.nodes.contains(&node))
+            && self.principal.as_ref().is_none_or(|p| &entry.principal == p)
+            && self.operation.as_ref().is_none_or(|op| &entry.operation == op)
+    }
+}
+
+/// Matching entries, newest first
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    // Pass back as `cursor` for the next page; absent on the last one
+    pub next_cursor: Option<u64>,
+}
+
+/// Keeps each key of objects and replaces every leaf value
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), redact(v))).collect()),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::Null => Value::Null,
+        _ => Value::String(REDACTED.to_string()),
+    }
+}
+
+/// Durable, mutation-only history of who changed what, kept apart from the in-memory
+/// event ring buffer. Appends go to `audit.ndjson`, which rotates to `.1`, `.2` ...
+pub struct AuditLog {
+    dir: PathBuf,
+    settings: AuditSettings,
+    // Next seq to hand out; held while appending so lines land in seq order
+    next_seq: Mutex<u64>,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog").field("dir", &self.dir).finish()
+    }
+}
+
+impl AuditLog {
+    /// Opens the log in the configured directory, carrying on from the newest seq on disk
+    pub fn new(settings: AuditSettings) -> Self {
+        let log = Self {
+            dir: PathBuf::from(&settings.directory),
+            settings,
+            next_seq: Mutex::new(1),
+        };
+        let newest = (0..=log.settings.max_files)
+            .find_map(|generation| log.read_file(generation).last().map(|e| e.seq))
+            .unwrap_or(0);
+        *log.next_seq.lock().unwrap() = newest + 1;
+        log
+    }
+
+    fn file_path(&self, generation: usize) -> PathBuf {
+        if generation == 0 {
+            self.dir.join(AUDIT_FILE)
+        } else {
+            self.dir.join(format!("{}.{}", AUDIT_FILE, generation))
+        }
+    }
+
+    fn retention(&self) -> Option<Duration> {
+        (self.settings.retention_days > 0).then(|| Duration::from_secs(self.settings.retention_days as u64 * 86400))
+    }
+
+    /// Writes one entry. Failures are logged rather than failing the mutation, which has
+    /// already happened by the time it is audited.
+    pub fn record(&self, principal: &str, record: AuditRecord) -> Option<AuditEntry> {
+        let mut entry = AuditEntry {
+            seq: 0,
+            at: Utc::now(),
+            principal: principal.to_string(),
+            operation: record.operation,
+            nodes: record.nodes,
+            edges: record.edges,
+            before: record.before,
+            after: record.after,
+            change_omitted: false,
+        };
+        if self.settings.redact_values {
+            entry.before = entry.before.as_ref().map(redact);
+            entry.after = entry.after.as_ref().map(redact);
+        }
+        let change_bytes = [&entry.before, &entry.after].iter()
+            .filter_map(|value| value.as_ref())
+            .map(|value| value.to_string().len())
+            .sum::<usize>();
+        if change_bytes > self.settings.max_change_bytes {
+            entry.before = None;
+            entry.after = None;
+            entry.change_omitted = true;
+        }
+
+        let mut next_seq = self.next_seq.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entry.seq = *next_seq;
+        let line = match serde_json::to_string(&entry) {
+            Ok(json) => json + "\n",
+            Err(e) => {
+                warn!("Failed to serialize audit entry for {}: {}", entry.operation, e);
+                return None;
+            }
+        };
+        if let Err(e) = self.append(&line) {
+            warn!("Failed to write audit entry for {} by {}: {}", entry.operation, entry.principal, e);
+            return None;
+        }
+        *next_seq += 1;
+        Some(entry)
+    }
+
+    fn append(&self, line: &str) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let current = self.file_path(0);
+        let size = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.settings.max_file_bytes {
+            if let Err(e) = self.rotate() {
+                warn!("Failed to rotate audit log: {}", e);
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&current)?;
+        file.write_all(line.as_bytes())
+    }
+
+    // Shift audit.ndjson -> .1 -> .2 ..., dropping the oldest and any past retention
+    fn rotate(&self) -> std::io::Result<()> {
+        let oldest = self.file_path(self.settings.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for generation in (0..self.settings.max_files).rev() {
+            let from = self.file_path(generation);
+            if from.exists() {
+                fs::rename(&from, self.file_path(generation + 1))?;
+            }
+        }
+        self.purge_expired();
+        Ok(())
+    }
+
+    /// Deletes rotated files whose newest entry is past retention
+    pub fn purge_expired(&self) {
+        let Some(retention) = self.retention() else {
+            return;
+        };
+        for generation in 1..=self.settings.max_files {
+            let path = self.file_path(generation);
+            let expired = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age > retention);
+            if expired {
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("Failed to remove expired audit file {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    // Entries of one file, oldest first; unreadable lines are skipped
+    fn read_file(&self, generation: usize) -> Vec<AuditEntry> {
+        let Ok(file) = File::open(self.file_path(generation)) else {
+            return Vec::new();
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect()
+    }
+
+    /// A page of matching entries, newest first
+    pub fn query(&self, query: &AuditQuery) -> AuditPage {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let cutoff = self.retention()
+            .and_then(|retention| ChronoDuration::from_std(retention).ok())
+            .map(|retention| Utc::now() - retention);
+        // Held so a rotation can't move files between reads
+        let _appending = self.next_seq.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut entries = Vec::new();
+        'files: for generation in 0..=self.settings.max_files {
+            for entry in self.read_file(generation).into_iter().rev() {
+                if cutoff.is_some_and(|cutoff| entry.at < cutoff) {
+                    break 'files;
+                }
+                if query.matches(&entry) {
+                    entries.push(entry);
+                    if entries.len() > limit {
+                        break 'files;
+                    }
+                }
+            }
+        }
+        let next_cursor = (entries.len() > limit).then(|| {
+            entries.truncate(limit);
+            entries[limit - 1].seq
+        });
+        AuditPage { entries, next_cursor }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_log(settings: AuditSettings) -> (AuditLog, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("audit-test-{}", uuid::Uuid::new_v4()));
+        let log = AuditLog::new(AuditSettings { directory: dir.to_string_lossy().into_owned(), ..settings });
+        (log, dir)
+    }
+
+    #[test]
+    fn test_trail_is_queryable_paged_and_survives_reopening() {
+        let (log, dir) = temp_log(AuditSettings { max_file_bytes: 600, ..Default::default() });
+        let started = Utc::now();
+        for i in 0..6u32 {
+            let principal = if i % 2 == 0 { "npub-alice" } else { "npub-bob" };
+            log.record(principal, AuditRecord::new("set_attributes").nodes([1, 10 + i])
+                .change(Some(json!({ "group": "a" })), Some(json!({ "group": format!("g{}", i) }))));
+        }
+        log.record("npub-bob", AuditRecord::new("delete_annotation").nodes([2]));
+        assert!(dir.join(format!("{}.1", AUDIT_FILE)).exists());
+
+        // Reopened, the trail continues where it left off
+        let log = AuditLog::new(log.settings.clone());
+        assert_eq!(log.record("npub-alice", AuditRecord::new("pin_node").nodes([1])).unwrap().seq, 8);
+
+        let page = log.query(&AuditQuery { node: Some(1), limit: Some(3), ..Default::default() });
+        assert_eq!(page.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![8, 6, 5]);
+        let rest = log.query(&AuditQuery { node: Some(1), cursor: page.next_cursor, limit: Some(10), ..Default::default() });
+        assert_eq!(rest.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+        assert_eq!(rest.next_cursor, None);
+
+        let bob = log.query(&AuditQuery { principal: Some("npub-bob".into()), ..Default::default() });
+        assert_eq!(bob.entries.iter().map(|e| (e.seq, e.operation.as_str())).collect::<Vec<_>>(),
+            vec![(7, "delete_annotation"), (6, "set_attributes"), (4, "set_attributes"), (2, "set_attributes")]);
+        assert_eq!(bob.entries[1].after, Some(json!({ "group": "g5" })));
+        assert!(log.query(&AuditQuery { since: Some(started + ChronoDuration::hours(1)), ..Default::default() }).entries.is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_values_are_redacted_or_left_out_when_large() {
+        let (log, dir) = temp_log(AuditSettings { redact_values: true, max_change_bytes: 100, ..Default::default() });
+        let small = log.record("npub-alice", AuditRecord::new("set_attributes").nodes([3])
+            .change(Some(json!({ "3": { "metadata.owner": "alice" } })), Some(json!({ "3": { "metadata.owner": "bob" } }))))
+            .unwrap();
+        assert_eq!(small.after, Some(json!({ "3": { "metadata.owner": REDACTED } })));
+        assert!(!small.change_omitted);
+
+        let log = AuditLog::new(AuditSettings { redact_values: false, ..log.settings.clone() });
+        let large = log.record("npub-alice", AuditRecord::new("set_attributes")
+            .change(None, Some(json!({ "summary": "x".repeat(200) }))))
+            .unwrap();
+        assert_eq!((large.after, large.change_omitted), (None, true));
+        let stored = log.query(&AuditQuery::default());
+        assert!(stored.entries[0].change_omitted);
+        assert_eq!(stored.entries[1].before, Some(json!({ "3": { "metadata.owner": REDACTED } })));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,168 @@
+//! Derives `co_viewed` edges from what clients select. Each run takes the sessions that
+//! ended since the last one, counts their node pairs into decaying totals and hands the
+//! pairs over the threshold to the graph as their own edge layer.
+
+use actix::Addr;
+use log::{debug, info, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::actors::messages::{SetCoViewedEdges, TakeFinishedSelections};
+use crate::actors::{ClientManagerActor, GraphServiceActor};
+use crate::config::CoViewSettings;
+use crate::utils::co_selection::CoSelectionCounts;
+
+const MIN_INTERVAL_SECS: u64 = 60;
+// Counts that have faded below this are forgotten
+const COUNT_FLOOR: f32 = 0.25;
+
+struct CoViewState {
+    counts: CoSelectionCounts,
+    last_run: Option<Instant>,
+}
+
+pub struct CoViewService {
+    settings: CoViewSettings,
+    state: Mutex<CoViewState>,
+}
+
+impl CoViewService {
+    pub fn new(settings: CoViewSettings) -> Self {
+        let counts = CoSelectionCounts::new(settings.max_pairs);
+        Self { settings, state: Mutex::new(CoViewState { counts, last_run: None }) }
+    }
+
+    pub fn start(self: Arc<Self>, graph_addr: Addr<GraphServiceActor>, client_manager_addr: Addr<ClientManagerActor>) {
+        if !self.settings.enabled {
+            info!("Co-viewed edges disabled");
+            return;
+        }
+        let interval = Duration::from_secs(self.settings.interval_secs.max(MIN_INTERVAL_SECS));
+        info!("Starting co-viewed edges (every {:?})", interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.run(&graph_addr, &client_manager_addr, Instant::now()).await {
+                    Ok(count) => debug!("Co-view pass left {} co-viewed edges", count),
+                    Err(e) => warn!("Co-view pass failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// One pass: fade the counts for the time since the last pass, add the sessions that
+    /// ended since, and publish the pairs over the threshold. Returns the edges applied.
+    pub async fn run(&self, graph_addr: &Addr<GraphServiceActor>, client_manager_addr: &Addr<ClientManagerActor>, now: Instant) -> Result<usize, String> {
+        let sessions = client_manager_addr.send(TakeFinishedSelections).await.map_err(|e| e.to_string())?;
+        let pairs = {
+            let mut state = self.state.lock().await;
+            if let Some(last_run) = state.last_run {
+                let elapsed = now.saturating_duration_since(last_run).as_secs_f64();
+                state.counts.decay(self.settings.half_life_days, elapsed, COUNT_FLOOR);
+            }
+            state.last_run = Some(now);
+            for session in &sessions {
+                state.counts.add_session(session);
+            }
+            state.counts.pairs(self.settings.threshold, self.settings.weight_per_session)
+        };
+        graph_addr.send(SetCoViewedEdges { pairs }).await.map_err(|e| e.to_string())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::client_manager_actor::ClientHandle;
+    use crate::actors::messages::{BuildGraphFromMetadata, CloseConnection, GetGraphSnapshot, RecordSelection, RegisterSimulatedClient, SendToClientBinary, SendToClientText, StopSimulation, UnregisterClient};
+    use crate::config::feature_access::Role;
+    use crate::models::metadata::{Metadata, MetadataStore};
+    use crate::utils::auth::Identity;
+    use crate::utils::co_selection::CO_VIEWED_EDGE_TYPE;
+    use actix::prelude::*;
+
+    // A connected client that ignores everything it is sent
+    struct SilentClient;
+
+    impl Actor for SilentClient {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for SilentClient {
+        type Result = ();
+        fn handle(&mut self, _msg: SendToClientText, _ctx: &mut Self::Context) {}
+    }
+
+    impl Handler<SendToClientBinary> for SilentClient {
+        type Result = ();
+        fn handle(&mut self, _msg: SendToClientBinary, _ctx: &mut Self::Context) {}
+    }
+
+    impl Handler<CloseConnection> for SilentClient {
+        type Result = ();
+        fn handle(&mut self, _msg: CloseConnection, _ctx: &mut Self::Context) {}
+    }
+
+    async fn session(client_manager: &Addr<ClientManagerActor>, selections: &[&str]) {
+        let socket = SilentClient.start();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        let identity = Identity { pubkey: Some("a1b2c3d4e5f6".to_string()), role: Role::Viewer };
+        let client_id = client_manager.send(RegisterSimulatedClient { handle, identity }).await.unwrap().unwrap();
+        for (node_id, metadata_id) in selections.iter().enumerate() {
+            client_manager.send(RecordSelection { client_id, node_id: node_id as u32, metadata_id: Some(metadata_id.to_string()) }).await.unwrap();
+        }
+        client_manager.send(UnregisterClient { client_id }).await.unwrap().unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_synthetic_selection_streams_become_co_viewed_edges() {
+        let settings = CoViewSettings { enabled: true, threshold: 3.0, weight_per_session: 0.1, half_life_days: 1.0, ..Default::default() };
+        let client_manager = ClientManagerActor::new().with_co_view(&settings).start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["alpha.md", "beta.md", "gamma.md", "delta.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        let id = |name: &str| snapshot.nodes.iter().find(|n| n.metadata_id == name).unwrap().id;
+        let (alpha, beta) = (id("alpha"), id("beta"));
+
+        // Four sessions browse alpha and beta together; gamma and delta turn up once each
+        for stream in [["alpha", "beta", "alpha", "beta"], ["beta", "alpha", "gamma", "gamma"], ["alpha", "delta", "beta", "beta"], ["alpha", "alpha", "beta", "beta"]] {
+            session(&client_manager, &stream).await;
+        }
+
+        let service = CoViewService::new(settings);
+        let start = Instant::now();
+        assert_eq!(service.run(&graph, &client_manager, start).await.unwrap(), 1);
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        let co_viewed: Vec<_> = snapshot.edges.iter().filter(|e| e.edge_type.as_deref() == Some(CO_VIEWED_EDGE_TYPE)).collect();
+        assert_eq!(co_viewed.len(), 1);
+        assert_eq!((co_viewed[0].source.min(co_viewed[0].target), co_viewed[0].source.max(co_viewed[0].target)), (alpha.min(beta), alpha.max(beta)));
+        assert!((co_viewed[0].weight - 0.4).abs() < 1e-6);
+
+        // Nothing new for a day: the count halves and falls under the threshold
+        let day_later = start + Duration::from_secs(86_400);
+        assert_eq!(service.run(&graph, &client_manager, day_later).await.unwrap(), 0);
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        assert!(snapshot.edges.iter().all(|e| e.edge_type.as_deref() != Some(CO_VIEWED_EDGE_TYPE)));
+    }
+
+    #[actix_web::test]
+    async fn test_selections_are_ignored_when_disabled() {
+        let client_manager = ClientManagerActor::new().with_co_view(&CoViewSettings::default()).start();
+        session(&client_manager, &["alpha", "beta", "gamma"]).await;
+        assert!(client_manager.send(TakeFinishedSelections).await.unwrap().is_empty());
+
+        // With no co-viewed pairs, applying the layer leaves the graph's generation alone
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let before = graph.send(GetGraphSnapshot).await.unwrap().unwrap().generation;
+        assert_eq!(graph.send(SetCoViewedEdges { pairs: Vec::new() }).await.unwrap().unwrap(), 0);
+        assert_eq!(graph.send(GetGraphSnapshot).await.unwrap().unwrap().generation, before);
+    }
+}
@@ -0,0 +1,250 @@
+//! Notes dictated onto a node. A speech socket opens a dictation on a target node; interim
+//! transcripts go back to the speaker alone so they can watch the note form, and the first
+//! final segment is stored as an annotation and broadcast like one made through the API.
+
+use actix::Addr;
+use log::info;
+use serde_json::json;
+use std::fmt;
+
+use crate::actors::messages::{BroadcastMessage, GetNodeMap, GetSelectedNode};
+use crate::actors::{ClientManagerActor, GraphServiceActor};
+use crate::models::annotation::{Annotation, CreateAnnotationRequest};
+use crate::services::annotation_service::AnnotationService;
+use crate::services::event_log::EventLog;
+use crate::types::speech::{SpeechErrorMessage, TranscriptionSegment};
+
+/// The node a dictation is attached to
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictationTarget {
+    pub node_id: u32,
+    // Annotations are stored by metadata id so they survive rebuilds
+    pub metadata_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DictationError {
+    // No node id given and nothing selected to fall back on
+    NoTarget,
+    NodeNotFound(u32),
+    Unavailable(String),
+}
+
+impl DictationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DictationError::NoTarget => "no_annotation_target",
+            DictationError::NodeNotFound(_) => "node_not_found",
+            DictationError::Unavailable(_) => "unavailable",
+        }
+    }
+
+    pub fn to_ws_message(&self) -> String {
+        SpeechErrorMessage::new(self.code(), self).to_json()
+    }
+}
+
+impl fmt::Display for DictationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictationError::NoTarget => write!(f, "No node to annotate: give a nodeId or select a node first"),
+            DictationError::NodeNotFound(id) => write!(f, "Node {} not found", id),
+            DictationError::Unavailable(e) => write!(f, "Graph service unavailable: {}", e),
+        }
+    }
+}
+
+/// The node to annotate: the one the client named, else the one the speech socket was told
+/// is focused, else the one the speaker last selected in their graph client
+pub async fn resolve_target(
+    node_id: Option<u32>,
+    focused: Option<u32>,
+    pubkey: Option<&str>,
+    graph: &Addr<GraphServiceActor>,
+    client_manager: &Addr<ClientManagerActor>,
+) -> Result<DictationTarget, DictationError> {
+    let node_id = match node_id.or(focused) {
+        Some(id) => id,
+        None => {
+            let Some(pubkey) = pubkey else {
+                return Err(DictationError::NoTarget);
+            };
+            client_manager.send(GetSelectedNode { pubkey: pubkey.to_string() }).await
+                .map_err(|e| DictationError::Unavailable(e.to_string()))?
+                .ok_or(DictationError::NoTarget)?
+        }
+    };
+    let node_map = graph.send(GetNodeMap).await
+        .map_err(|e| DictationError::Unavailable(e.to_string()))?
+        .map_err(DictationError::Unavailable)?;
+    let node = node_map.get(&node_id).ok_or(DictationError::NodeNotFound(node_id))?;
+    Ok(DictationTarget { node_id, metadata_id: node.metadata_id.clone() })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DictationUpdate {
+    // The note so far, for the speaker only
+    Interim(String),
+    // The finished note, to be saved
+    Final(String),
+}
+
+/// An open dictation on one node. It ends with the first final segment, or a cancel.
+#[derive(Debug, Clone)]
+pub struct Dictation {
+    pub target: DictationTarget,
+}
+
+impl Dictation {
+    pub fn new(target: DictationTarget) -> Self {
+        Self { target }
+    }
+
+    /// What a transcription segment means for the dictation; blank segments mean nothing
+    pub fn feed(&self, segment: &TranscriptionSegment) -> Option<DictationUpdate> {
+        let text = segment.text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        Some(if segment.is_final {
+            DictationUpdate::Final(text.to_string())
+        } else {
+            DictationUpdate::Interim(text.to_string())
+        })
+    }
+}
+
+/// Stores a dictated note and tells graph clients about it with the same event the
+/// annotation endpoint sends
+pub async fn save(
+    target: &DictationTarget,
+    text: String,
+    author: &str,
+    annotations: &AnnotationService,
+    client_manager: &Addr<ClientManagerActor>,
+    event_log: &EventLog,
+) -> Result<Annotation, String> {
+    let annotation = annotations.create(&target.metadata_id, author, CreateAnnotationRequest { text, anchor_offset: None }).await
+        .map_err(|e| e.to_string())?;
+    info!("{} dictated an annotation on node {} ({})", author, target.node_id, target.metadata_id);
+    let event = json!({
+        "type": "annotation_created",
+        "nodeId": target.node_id,
+        "annotation": annotation,
+        "source": "speech"
+    });
+    client_manager.do_send(BroadcastMessage { message: event.to_string() });
+    event_log.record(author, "create_annotation", json!({
+        "nodeId": target.node_id,
+        "annotationId": annotation.id,
+        "source": "speech"
+    }));
+    Ok(annotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::client_manager_actor::ClientHandle;
+    use crate::actors::messages::{AddNode, CloseConnection, RecordSelection, SendToClientBinary, SendToClientText, StopSimulation};
+    use crate::config::feature_access::Role;
+    use crate::models::node::Node;
+    use crate::utils::auth::Identity;
+    use actix::prelude::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::broadcast;
+
+    // Stands in for a graph client's websocket
+    struct Collector {
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Actor for Collector {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for Collector {
+        type Result = ();
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(msg.0);
+        }
+    }
+
+    impl Handler<SendToClientBinary> for Collector {
+        type Result = ();
+        fn handle(&mut self, _msg: SendToClientBinary, _ctx: &mut Self::Context) {}
+    }
+
+    impl Handler<CloseConnection> for Collector {
+        type Result = ();
+        fn handle(&mut self, _msg: CloseConnection, _ctx: &mut Self::Context) {}
+    }
+
+    fn segment(text: &str, is_final: bool) -> TranscriptionSegment {
+        TranscriptionSegment { text: text.to_string(), language: Some("en".to_string()), detected: false, is_final }
+    }
+
+    #[actix_web::test]
+    async fn test_dictation_on_the_selected_node_is_stored_and_broadcast() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let socket = Collector { received: received.clone() }.start();
+        let mut manager = ClientManagerActor::new();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        let client_id = manager.register_client(handle, Identity { pubkey: Some("alice".to_string()), role: Role::Editor });
+        let client_manager = manager.start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        graph.send(AddNode { node: Node::new_with_id("vector-clocks".to_string(), Some(7)) }).await.unwrap().unwrap();
+
+        // Nothing named and nothing selected yet
+        let err = resolve_target(None, None, Some("alice"), &graph, &client_manager).await.unwrap_err();
+        assert_eq!(err, DictationError::NoTarget);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&err.to_ws_message()).unwrap()["code"], "no_annotation_target");
+        assert_eq!(resolve_target(Some(99), None, None, &graph, &client_manager).await.unwrap_err(), DictationError::NodeNotFound(99));
+
+        client_manager.send(RecordSelection { client_id, node_id: 7, metadata_id: None }).await.unwrap();
+        let target = resolve_target(None, None, Some("alice"), &graph, &client_manager).await.unwrap();
+        assert_eq!(target, DictationTarget { node_id: 7, metadata_id: "vector-clocks".to_string() });
+
+        // The STT stream: two provisional segments, then the finished sentence
+        let (tx, mut rx) = broadcast::channel(8);
+        for (text, is_final) in [("check the", false), (" ", false), ("check the units", false), ("Check the units.", true)] {
+            tx.send(segment(text, is_final)).unwrap();
+        }
+        drop(tx);
+        let dictation = Dictation::new(target);
+        let mut interim = Vec::new();
+        let mut finished = None;
+        while let Ok(segment) = rx.recv().await {
+            match dictation.feed(&segment) {
+                Some(DictationUpdate::Interim(text)) => interim.push(text),
+                Some(DictationUpdate::Final(text)) => {
+                    finished = Some(text);
+                    break;
+                }
+                None => {}
+            }
+        }
+        assert_eq!(interim, vec!["check the", "check the units"]);
+
+        let path = std::env::temp_dir().join(format!("dictation-test-{}", uuid::Uuid::new_v4())).join("annotations.json");
+        let annotations = AnnotationService::with_path(path);
+        let event_log = EventLog::new();
+        let saved = save(&dictation.target, finished.unwrap(), "alice", &annotations, &client_manager, &event_log).await.unwrap();
+        let stored = annotations.list("vector-clocks").await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].text, "Check the units.");
+        assert_eq!(stored[0].author, "alice");
+        assert_eq!(event_log.since(0)[0].kind, "create_annotation");
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        let events: Vec<serde_json::Value> = received.lock().unwrap().iter()
+            .filter_map(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+            .filter(|v| v["type"] == "annotation_created")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["nodeId"], 7);
+        assert_eq!(events[0]["annotation"]["id"], saved.id);
+        assert_eq!(events[0]["source"], "speech");
+    }
+}
@@ -0,0 +1,126 @@
+use actix::Addr;
+use chrono::{DateTime, Utc};
+use glam::Vec3;
+use log::info;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::actors::messages::GetGraphSnapshot;
+use crate::actors::GraphServiceActor;
+use crate::services::event_log::EventLog;
+use crate::services::job_service::CancelToken;
+use crate::utils::edge_bundling::{self, BundleParams, EdgeBundle, MAX_BUNDLE_EDGES};
+
+// Bundles are recomputed once any node has drifted this far, relative to the layout's extent
+const LAYOUT_MOVE_TOLERANCE: f32 = 0.02;
+const EVENT_ACTOR: &str = "edge_bundling";
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("{0}")]
+    InvalidParams(String),
+    #[error("Graph has {edges} edges; edge bundling is limited to {limit}")]
+    TooManyEdges { edges: usize, limit: usize },
+    #[error("Edge bundling was cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Failed(String),
+}
+
+// Bundles are shared with the cache rather than copied per request
+#[derive(Debug, Clone)]
+pub struct BundleResult {
+    pub computed_at: DateTime<Utc>,
+    pub cached: bool,
+    pub bundles: Arc<Vec<EdgeBundle>>,
+}
+
+struct CachedBundles {
+    params: BundleParams,
+    // Graph generation the bundles were computed at
+    generation: u64,
+    positions: HashMap<u32, Vec3>,
+    computed_at: DateTime<Utc>,
+    bundles: Arc<Vec<EdgeBundle>>,
+}
+
+/// Server-side edge bundling for thin clients. The result is cached until the graph
+/// generation changes or the layout moves noticeably.
+pub struct EdgeBundleService {
+    event_log: Arc<EventLog>,
+    // Held across the computation, so concurrent requests wait and then hit the cache
+    cache: Mutex<Option<CachedBundles>>,
+}
+
+impl EdgeBundleService {
+    pub fn new(event_log: Arc<EventLog>) -> Self {
+        Self { event_log, cache: Mutex::new(None) }
+    }
+
+    pub async fn compute_edge_bundles(
+        &self,
+        params: BundleParams,
+        graph_addr: &Addr<GraphServiceActor>,
+        cancel: &CancelToken,
+    ) -> Result<BundleResult, BundleError> {
+        params.validate().map_err(BundleError::InvalidParams)?;
+        let graph = graph_addr.send(GetGraphSnapshot).await
+            .map_err(|e| BundleError::Failed(e.to_string()))?
+            .map_err(BundleError::Failed)?;
+        let generation = graph.generation;
+        let positions = edge_bundling::node_positions(&graph);
+
+        let mut cache = self.cache.lock().await;
+        if let Some(cached) = cache.as_ref() {
+            if cached.params == params
+                && cached.generation == generation
+                && !edge_bundling::layout_moved(&cached.positions, &positions, LAYOUT_MOVE_TOLERANCE)
+            {
+                return Ok(BundleResult { computed_at: cached.computed_at, cached: true, bundles: cached.bundles.clone() });
+            }
+        }
+
+        let segments = edge_bundling::segments_from_graph(&graph);
+        let edge_count = segments.len();
+        if edge_count > MAX_BUNDLE_EDGES {
+            return Err(BundleError::TooManyEdges { edges: edge_count, limit: MAX_BUNDLE_EDGES });
+        }
+        let event_log = self.event_log.clone();
+        let started = Instant::now();
+        event_log.record(EVENT_ACTOR, "bundling_started", json!({ "edges": edge_count }));
+
+        let token = cancel.clone();
+        let bundles = tokio::task::spawn_blocking(move || {
+            edge_bundling::compute_bundles(&segments, params, &mut |cycle, cycles| {
+                event_log.record(EVENT_ACTOR, "bundling_progress", json!({
+                    "edges": edge_count,
+                    "cycle": cycle,
+                    "cycles": cycles,
+                }));
+            }, &|| token.is_cancelled())
+        })
+        .await
+        .map_err(|e| BundleError::Failed(e.to_string()))?;
+        let bundles = match bundles {
+            Ok(bundles) => bundles,
+            Err(_) if cancel.is_cancelled() => {
+                self.event_log.record(EVENT_ACTOR, "bundling_cancelled", json!({ "edges": edge_count }));
+                return Err(BundleError::Cancelled);
+            }
+            Err(e) => return Err(BundleError::Failed(e)),
+        };
+
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        info!("Bundled {} edges in {}ms", edge_count, elapsed_ms);
+        self.event_log.record(EVENT_ACTOR, "bundling_complete", json!({ "edges": edge_count, "elapsedMs": elapsed_ms }));
+
+        let computed_at = Utc::now();
+        let bundles = Arc::new(bundles);
+        *cache = Some(CachedBundles { params, generation, positions, computed_at, bundles: bundles.clone() });
+        Ok(BundleResult { computed_at, cached: false, bundles })
+    }
+}
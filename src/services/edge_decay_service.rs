@@ -0,0 +1,159 @@
+//! Runs edge decay on a timer. The time of the last pass is kept on disk, so how much
+//! an edge has decayed depends only on the clock, never on how long the server has
+//! been up or how often it restarted.
+
+use actix::Addr;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::actors::messages::DecayEdges;
+use crate::actors::GraphServiceActor;
+use crate::config::EdgeDecaySettings;
+use crate::models::graph::GraphDiff;
+use crate::services::event_log::EventLog;
+use crate::utils::edge_decay::DecayState;
+use crate::utils::json_store::write_json_atomic;
+
+const STATE_PATH: &str = "/app/data/edge_decay.json";
+const MIN_INTERVAL_SECS: u64 = 60;
+
+pub struct EdgeDecayService {
+    settings: EdgeDecaySettings,
+    state_path: PathBuf,
+    event_log: Arc<EventLog>,
+    // One pass at a time, so two can't both decay the same interval
+    running: Mutex<()>,
+}
+
+impl EdgeDecayService {
+    pub fn new(settings: EdgeDecaySettings, event_log: Arc<EventLog>) -> Self {
+        Self::with_path(settings, PathBuf::from(STATE_PATH), event_log)
+    }
+
+    pub fn with_path(settings: EdgeDecaySettings, state_path: PathBuf, event_log: Arc<EventLog>) -> Self {
+        Self { settings, state_path, event_log, running: Mutex::new(()) }
+    }
+
+    pub fn start(self: Arc<Self>, graph_addr: Addr<GraphServiceActor>) {
+        if self.settings.half_life_days.is_empty() {
+            info!("Edge decay disabled; no edge types have a half-life");
+            return;
+        }
+        let interval = Duration::from_secs(self.settings.interval_secs.max(MIN_INTERVAL_SECS));
+        info!("Starting edge decay for {:?} (every {:?})", self.settings.half_life_days.keys().collect::<Vec<_>>(), interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.decay(&graph_addr, Utc::now()).await {
+                    Ok(diff) if diff.is_empty() => debug!("Edge decay pass changed nothing"),
+                    Ok(diff) => info!("Edge decay weakened {} edges and removed {}", diff.updated_edges.len(), diff.removed_edges.len()),
+                    Err(e) => warn!("Edge decay failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Decays edges for the time between the last pass and `now`, then records `now` as
+    /// the last pass. The first pass ever only starts the clock.
+    pub async fn decay(&self, graph_addr: &Addr<GraphServiceActor>, now: DateTime<Utc>) -> Result<GraphDiff, String> {
+        let _running = self.running.lock().await;
+        let Some(last_decay_at) = self.last_decay_at().await else {
+            self.save(now)?;
+            return Ok(GraphDiff::default());
+        };
+        let elapsed_secs = (now - last_decay_at).num_milliseconds() as f64 / 1000.0;
+        if elapsed_secs <= 0.0 {
+            return Ok(GraphDiff::default());
+        }
+
+        let diff = graph_addr.send(DecayEdges { elapsed_secs }).await.map_err(|e| e.to_string())??;
+        self.save(now)?;
+        if !diff.is_empty() {
+            self.event_log.record("decay", "edge_decay", serde_json::json!({
+                "elapsedSecs": elapsed_secs,
+                "updatedEdges": diff.updated_edges.iter().map(|e| serde_json::json!({ "id": e.id, "weight": e.weight })).collect::<Vec<_>>(),
+                "removedEdges": diff.removed_edges,
+            }));
+        }
+        Ok(diff)
+    }
+
+    async fn last_decay_at(&self) -> Option<DateTime<Utc>> {
+        let content = tokio::fs::read_to_string(&self.state_path).await.ok()?;
+        match serde_json::from_str::<DecayState>(&content) {
+            Ok(state) => Some(state.last_decay_at),
+            Err(e) => {
+                warn!("Ignoring unreadable edge decay state {:?}: {}", self.state_path, e);
+                None
+            }
+        }
+    }
+
+    fn save(&self, last_decay_at: DateTime<Utc>) -> Result<(), String> {
+        write_json_atomic(&self.state_path, &DecayState { last_decay_at })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::{AddEdge, GetGraphData, SetEdgeDecaySettings, StopSimulation};
+    use crate::actors::ClientManagerActor;
+    use crate::models::edge::Edge;
+    use actix::prelude::*;
+    use std::collections::HashMap;
+
+    fn spoken(weight: f32) -> Edge {
+        let mut edge = Edge::new(1, 2, weight);
+        edge.edge_type = Some("spoken".to_string());
+        edge
+    }
+
+    async fn weights(graph: &Addr<GraphServiceActor>) -> Vec<f32> {
+        graph.send(GetGraphData).await.unwrap().unwrap().edges.iter().map(|e| e.weight).collect()
+    }
+
+    #[actix_web::test]
+    async fn test_decay_follows_the_clock_across_restarts() {
+        let settings = EdgeDecaySettings {
+            half_life_days: HashMap::from([("spoken".to_string(), 1.0)]),
+            floor: 0.1,
+            ..Default::default()
+        };
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        graph.send(SetEdgeDecaySettings { settings: settings.clone() }).await.unwrap().unwrap();
+        graph.send(AddEdge { edge: spoken(1.0) }).await.unwrap().unwrap();
+        graph.send(AddEdge { edge: Edge::new(2, 3, 1.0) }).await.unwrap().unwrap();
+
+        let path = std::env::temp_dir().join(format!("edge-decay-{}.json", uuid::Uuid::new_v4()));
+        let start = Utc::now();
+        let day = |n: i64| start + chrono::Duration::days(n);
+        let service = EdgeDecayService::with_path(settings.clone(), path.clone(), Arc::new(EventLog::new()));
+
+        // The first pass only starts the clock
+        assert!(service.decay(&graph, day(0)).await.unwrap().is_empty());
+        let diff = service.decay(&graph, day(1)).await.unwrap();
+        assert_eq!(diff.updated_edges.len(), 1);
+        assert_eq!(weights(&graph).await, vec![0.5, 1.0]);
+
+        // A restarted service picks up from the stored time, not from its own start
+        let restarted = EdgeDecayService::with_path(settings, path, Arc::new(EventLog::new()));
+        restarted.decay(&graph, day(2)).await.unwrap();
+        assert_eq!(weights(&graph).await, vec![0.25, 1.0]);
+        assert!(restarted.decay(&graph, day(2)).await.unwrap().is_empty());
+
+        // Re-creating the edge reinforces it
+        graph.send(AddEdge { edge: spoken(1.0) }).await.unwrap().unwrap();
+        assert_eq!(weights(&graph).await, vec![1.25, 1.0]);
+
+        let diff = restarted.decay(&graph, day(6)).await.unwrap();
+        assert_eq!(diff.removed_edges, vec!["1-2".to_string()]);
+        assert_eq!(weights(&graph).await, vec![1.0]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Re-derives topic links from per-file topic lists. When the topic vocabulary changes
+//! the stored topic_counts go stale; this recomputes them from `Metadata::topics` and
+//! rebuilds the graph, which keeps surviving nodes where they are.
+//!
+//! The pairwise pass runs on a snapshot of the store, off the actors, so nothing is held
+//! while it runs on a large corpus; only the final rebuild goes through the graph actor.
+
+use actix::Addr;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::actors::messages::GetMetadata;
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::models::metadata::MetadataStore;
+use crate::services::graph_service::GraphRebuilds;
+use crate::services::metadata_import::{self, ImportDiff};
+
+// topic_counts holds integers, so fractional weights are stored in hundredths
+pub const WEIGHT_SCALE: f64 = 100.0;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weighting {
+    // Number of topics two files share
+    #[default]
+    Count,
+    // Shared topics weighted by how rare they are across the corpus
+    Tfidf,
+    // Shared topics over the union of both files' topics
+    Jaccard,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecomputeReport {
+    pub weighting: Weighting,
+    pub files_with_topics: usize,
+    pub diff: ImportDiff,
+    pub generation: u64,
+}
+
+/// Held for the length of one recomputation; clears the cancel flag when dropped
+pub struct RecomputeGuard(());
+
+impl Drop for RecomputeGuard {
+    fn drop(&mut self) {
+        CANCELLED.store(false, Ordering::SeqCst);
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn try_begin() -> Option<RecomputeGuard> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    CANCELLED.store(false, Ordering::SeqCst);
+    Some(RecomputeGuard(()))
+}
+
+/// Asks the running recomputation to stop. Returns false if none is running.
+pub fn cancel() -> bool {
+    if !RUNNING.load(Ordering::SeqCst) {
+        return false;
+    }
+    CANCELLED.store(true, Ordering::SeqCst);
+    true
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+fn normalise(topics: &[String]) -> BTreeSet<String> {
+    topics.iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// New topic_counts for every file that has topics. Each link is stored once, on the
+/// file whose name sorts first, so the rebuild doesn't count it twice. Files without
+/// topics aren't touched. Checks `cancelled` between topics and gives up with an error
+/// as soon as it is set.
+pub fn recompute_topic_counts(
+    store: &MetadataStore,
+    weighting: Weighting,
+    cancelled: impl Fn() -> bool,
+) -> Result<HashMap<String, HashMap<String, usize>>, String> {
+    let mut files: Vec<(&String, BTreeSet<String>)> = store.iter()
+        .map(|(file, metadata)| (file, normalise(&metadata.topics)))
+        .filter(|(_, topics)| !topics.is_empty())
+        .collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut postings: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, (_, topics)) in files.iter().enumerate() {
+        for topic in topics {
+            postings.entry(topic.as_str()).or_default().push(i);
+        }
+    }
+
+    // Files are sorted, so (i, j) with i < j always names the first file first
+    let mut scores: HashMap<(usize, usize), f64> = HashMap::new();
+    let corpus = files.len() as f64;
+    for posting in postings.values() {
+        if cancelled() {
+            return Err("Edge recomputation was cancelled".to_string());
+        }
+        let score = match weighting {
+            Weighting::Tfidf => (corpus / posting.len() as f64).ln(),
+            Weighting::Count | Weighting::Jaccard => 1.0,
+        };
+        for (n, &i) in posting.iter().enumerate() {
+            for &j in &posting[n + 1..] {
+                *scores.entry((i, j)).or_insert(0.0) += score;
+            }
+        }
+    }
+
+    let mut counts: HashMap<String, HashMap<String, usize>> =
+        files.iter().map(|(file, _)| ((*file).clone(), HashMap::new())).collect();
+    for ((i, j), score) in scores {
+        let weight = match weighting {
+            Weighting::Count => score,
+            Weighting::Tfidf => score * WEIGHT_SCALE,
+            Weighting::Jaccard => {
+                let union = files[i].1.len() + files[j].1.len() - score as usize;
+                score / union as f64 * WEIGHT_SCALE
+            }
+        };
+        let weight = weight.round() as usize;
+        if weight > 0 {
+            counts.get_mut(files[i].0).unwrap().insert(files[j].0.clone(), weight);
+        }
+    }
+    Ok(counts)
+}
+
+/// Writes recomputed counts into the current store and rebuilds the graph. The store is
+/// read again here, so edits made while the counts were computed aren't lost.
+pub async fn apply_counts(
+    counts: HashMap<String, HashMap<String, usize>>,
+    metadata_addr: &Addr<MetadataActor>,
+    graph_addr: &Addr<GraphServiceActor>,
+    rebuilds: &GraphRebuilds,
+) -> Result<(ImportDiff, u64), String> {
+    let current = metadata_addr.send(GetMetadata).await
+        .map_err(|e| format!("Metadata service unavailable: {}", e))??;
+    let mut store = current.clone();
+    for (file, topic_counts) in counts {
+        if let Some(metadata) = store.get_mut(&file) {
+            metadata.topic_counts = topic_counts;
+        }
+    }
+    let diff = metadata_import::import_diff(&current, &store);
+    let generation = metadata_import::apply_import(store, metadata_addr, graph_addr, rebuilds).await?;
+    Ok((diff, generation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+
+    fn store() -> MetadataStore {
+        let files = [
+            ("a.md", vec!["rust", "gpu", "physics"]),
+            ("b.md", vec!["Rust", "gpu"]),
+            ("c.md", vec!["rust", "audio"]),
+            ("d.md", vec![]),
+        ];
+        files.into_iter().map(|(name, topics)| {
+            let mut metadata = Metadata {
+                file_name: name.to_string(),
+                topics: topics.into_iter().map(String::from).collect(),
+                ..Default::default()
+            };
+            if name == "d.md" {
+                metadata.topic_counts.insert("a.md".to_string(), 3);
+            }
+            (name.to_string(), metadata)
+        }).collect()
+    }
+
+    fn weight(counts: &HashMap<String, HashMap<String, usize>>, a: &str, b: &str) -> Option<usize> {
+        counts[a].get(b).copied()
+    }
+
+    #[test]
+    fn test_weightings() {
+        let store = store();
+        let count = recompute_topic_counts(&store, Weighting::Count, || false).unwrap();
+        // d.md has no topics and keeps what it had
+        assert!(!count.contains_key("d.md"));
+        assert_eq!(weight(&count, "a.md", "b.md"), Some(2));
+        assert_eq!(weight(&count, "a.md", "c.md"), Some(1));
+        assert_eq!(weight(&count, "b.md", "c.md"), Some(1));
+        assert!(count["c.md"].is_empty());
+
+        // "rust" is in every file and says nothing; only "gpu" links a and b
+        let tfidf = recompute_topic_counts(&store, Weighting::Tfidf, || false).unwrap();
+        assert_eq!(weight(&tfidf, "a.md", "b.md"), Some((1.5f64.ln() * WEIGHT_SCALE).round() as usize));
+        assert_eq!(weight(&tfidf, "a.md", "c.md"), None);
+
+        let jaccard = recompute_topic_counts(&store, Weighting::Jaccard, || false).unwrap();
+        assert_eq!(weight(&jaccard, "a.md", "b.md"), Some(67));
+        assert_eq!(weight(&jaccard, "a.md", "c.md"), Some(25));
+        assert_eq!(weight(&jaccard, "b.md", "c.md"), Some(33));
+
+        assert!(recompute_topic_counts(&store, Weighting::Count, || true).is_err());
+    }
+}
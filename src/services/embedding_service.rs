@@ -0,0 +1,487 @@
+use actix::Addr;
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock as StdRwLock};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::actors::messages::{GetMetadata, SetSimilarityEdges};
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::config::EmbeddingSettings;
+use crate::models::metadata::MetadataStore;
+use crate::services::file_service::MARKDOWN_DIR;
+use crate::utils::json_store::write_json_atomic;
+
+const EMBEDDINGS_PATH: &str = "/app/data/embeddings/embeddings.json";
+const EMBED_BATCH_SIZE: usize = 16;
+
+pub const SIMILARITY_EDGE_TYPE: &str = "similarity";
+
+/// Turns document text into vectors. Implementations must return one vector per
+/// input, in input order.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Model identifier; cached vectors from a different model are recomputed
+    fn model(&self) -> &str;
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String>;
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingDatum>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingDatum {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Any backend speaking the OpenAI `/embeddings` API, which also covers the
+/// common local servers. The API key is optional for local backends.
+pub struct HttpEmbeddingProvider {
+    client: Client,
+    api_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl HttpEmbeddingProvider {
+    pub fn new(api_url: String, api_key: Option<String>, model: String, timeout: Duration) -> Result<Self, String> {
+        let client = Client::builder().timeout(timeout).build().map_err(|e| e.to_string())?;
+        Ok(Self { client, api_url, api_key, model })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HttpEmbeddingProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let url = format!("{}/embeddings", self.api_url.trim_end_matches('/'));
+        let mut request = self.client
+            .post(&url)
+            .json(&serde_json::json!({ "model": self.model, "input": inputs }));
+        if let Some(key) = self.api_key.as_deref().filter(|k| !k.is_empty()) {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Embedding API error {}: {}", status, body));
+        }
+        let mut body: EmbeddingResponse = response.json().await.map_err(|e| e.to_string())?;
+        if body.data.len() != inputs.len() {
+            return Err(format!("Embedding API returned {} vectors for {} inputs", body.data.len(), inputs.len()));
+        }
+        body.data.sort_by_key(|d| d.index);
+        Ok(body.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEmbedding {
+    model: String,
+    vector: Vec<f32>,
+}
+
+// file SHA1 -> embedding, so unchanged files are never re-embedded
+type EmbeddingCache = HashMap<String, CachedEmbedding>;
+
+/// Similarity edge between two documents, keyed by metadata id so it survives rebuilds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarityPair {
+    pub source: String,
+    pub target: String,
+    pub similarity: f32,
+}
+
+/// Runtime-adjustable knobs for the similarity pass
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarityParams {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub k: usize,
+}
+
+pub struct EmbeddingService {
+    provider: Option<Arc<dyn EmbeddingProvider>>,
+    settings: EmbeddingSettings,
+    cache: RwLock<EmbeddingCache>,
+    cache_path: PathBuf,
+    markdown_dir: PathBuf,
+    params: StdRwLock<SimilarityParams>,
+}
+
+impl EmbeddingService {
+    pub fn new(settings: EmbeddingSettings) -> Self {
+        let provider: Option<Arc<dyn EmbeddingProvider>> = if settings.enabled && !settings.api_url.is_empty() {
+            match HttpEmbeddingProvider::new(
+                settings.api_url.clone(),
+                settings.api_key.clone(),
+                settings.model.clone(),
+                Duration::from_secs(settings.timeout_secs),
+            ) {
+                Ok(provider) => Some(Arc::new(provider)),
+                Err(e) => {
+                    error!("Failed to create embedding provider: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self::with_provider(provider, settings, PathBuf::from(EMBEDDINGS_PATH), PathBuf::from(MARKDOWN_DIR))
+    }
+
+    pub fn with_provider(
+        provider: Option<Arc<dyn EmbeddingProvider>>,
+        settings: EmbeddingSettings,
+        cache_path: PathBuf,
+        markdown_dir: PathBuf,
+    ) -> Self {
+        let cache = match fs::read_to_string(&cache_path) {
+            Ok(content) => serde_json::from_str::<EmbeddingCache>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse embedding cache {:?}: {}. Starting empty.", cache_path, e);
+                EmbeddingCache::new()
+            }),
+            Err(_) => EmbeddingCache::new(),
+        };
+        let params = SimilarityParams {
+            enabled: settings.enabled,
+            threshold: settings.similarity_threshold,
+            k: settings.max_neighbours,
+        };
+
+        Self {
+            provider,
+            settings,
+            cache: RwLock::new(cache),
+            cache_path,
+            markdown_dir,
+            params: StdRwLock::new(params),
+        }
+    }
+
+    pub fn params(&self) -> SimilarityParams {
+        *self.params.read().unwrap()
+    }
+
+    pub fn set_params(&self, params: SimilarityParams) {
+        *self.params.write().unwrap() = params;
+    }
+
+    pub fn spring_multiplier(&self) -> f32 {
+        self.settings.spring_multiplier
+    }
+
+    /// Embeds every file whose SHA1 isn't cached yet for the current model.
+    /// Returns the number of new embeddings.
+    pub async fn embed_pending(&self, store: &MetadataStore) -> Result<usize, String> {
+        let provider = match &self.provider {
+            Some(provider) => provider.clone(),
+            None => return Ok(0),
+        };
+        let model = provider.model().to_string();
+
+        let pending: Vec<(String, String)> = {
+            let cache = self.cache.read().await;
+            let mut pending: Vec<(String, String)> = store.iter()
+                .filter(|(_, meta)| !meta.sha1.is_empty())
+                .filter(|(_, meta)| cache.get(&meta.sha1).is_none_or(|c| c.model != model))
+                .map(|(file_name, meta)| (file_name.clone(), meta.sha1.clone()))
+                .collect();
+            pending.sort_by(|a, b| a.1.cmp(&b.1));
+            pending.dedup_by(|a, b| a.1 == b.1);
+            pending
+        };
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        info!("Computing embeddings for {} files", pending.len());
+
+        let mut added = 0;
+        for batch in pending.chunks(EMBED_BATCH_SIZE) {
+            let mut hashes = Vec::with_capacity(batch.len());
+            let mut inputs = Vec::with_capacity(batch.len());
+            for (file_name, sha1) in batch {
+                match tokio::fs::read_to_string(self.markdown_dir.join(file_name)).await {
+                    Ok(mut content) => {
+                        truncate_chars(&mut content, self.settings.max_chars);
+                        hashes.push(sha1.clone());
+                        inputs.push(content);
+                    }
+                    Err(e) => debug!("Skipping embedding for {}: {}", file_name, e),
+                }
+            }
+            if inputs.is_empty() {
+                continue;
+            }
+
+            let vectors = provider.embed(&inputs).await?;
+            let mut cache = self.cache.write().await;
+            for (sha1, vector) in hashes.into_iter().zip(vectors) {
+                cache.insert(sha1, CachedEmbedding { model: model.clone(), vector });
+                added += 1;
+            }
+        }
+
+        if added > 0 {
+            let snapshot = self.cache.read().await.clone();
+            write_json_atomic(&self.cache_path, &snapshot)?;
+        }
+        Ok(added)
+    }
+
+    /// Similarity pairs for the current store using the runtime params
+    pub async fn pairs(&self, store: &MetadataStore) -> Vec<SimilarityPair> {
+        let params = self.params();
+        let cache = self.cache.read().await;
+        let model = self.provider.as_ref().map(|p| p.model().to_string());
+
+        let mut vectors: Vec<(String, &[f32])> = store.iter()
+            .filter_map(|(file_name, meta)| {
+                let cached = cache.get(&meta.sha1)?;
+                if model.as_deref().is_some_and(|m| m != cached.model) {
+                    return None;
+                }
+                Some((file_name.trim_end_matches(".md").to_string(), cached.vector.as_slice()))
+            })
+            .collect();
+        vectors.sort_by(|a, b| a.0.cmp(&b.0));
+        similarity_edges(&vectors, params.threshold, params.k)
+    }
+
+    /// Recomputes similarity pairs and hands them to the graph actor
+    pub async fn publish(&self, store: &MetadataStore, graph_addr: &Addr<GraphServiceActor>) -> Result<usize, String> {
+        let pairs = self.pairs(store).await;
+        let params = self.params();
+        graph_addr.send(SetSimilarityEdges {
+            pairs,
+            enabled: params.enabled,
+            spring_multiplier: self.settings.spring_multiplier,
+        }).await.map_err(|e| e.to_string())?
+    }
+
+    /// Background loop embedding new/changed files and refreshing similarity edges
+    pub fn start(self: Arc<Self>, metadata_addr: Addr<MetadataActor>, graph_addr: Addr<GraphServiceActor>) {
+        if self.provider.is_none() {
+            info!("Embedding pipeline disabled");
+            return;
+        }
+
+        let interval = Duration::from_secs(self.settings.interval_secs.max(30));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let mut published = false;
+            loop {
+                ticker.tick().await;
+                let store = match metadata_addr.send(GetMetadata).await {
+                    Ok(Ok(store)) => store,
+                    _ => {
+                        warn!("Embedding job could not read metadata");
+                        continue;
+                    }
+                };
+                let added = match self.embed_pending(&store).await {
+                    Ok(added) => added,
+                    Err(e) => {
+                        warn!("Embedding pass failed: {}", e);
+                        0
+                    }
+                };
+                if added > 0 || !published {
+                    match self.publish(&store, &graph_addr).await {
+                        Ok(count) => {
+                            published = true;
+                            debug!("Published {} similarity edges", count);
+                        }
+                        Err(e) => warn!("Failed to publish similarity edges: {}", e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn truncate_chars(text: &mut String, max_chars: usize) {
+    if let Some((idx, _)) = text.char_indices().nth(max_chars) {
+        text.truncate(idx);
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a * norm_b) }
+}
+
+/// The similarity pass: every document keeps at most its `k` most similar
+/// neighbours above `threshold`, and the union of those lists becomes the edge
+/// set, so there are never more than n * k edges.
+pub fn similarity_edges(vectors: &[(String, &[f32])], threshold: f32, k: usize) -> Vec<SimilarityPair> {
+    let mut best: HashMap<(usize, usize), f32> = HashMap::new();
+    if k == 0 {
+        return Vec::new();
+    }
+
+    for (i, (_, a)) in vectors.iter().enumerate() {
+        let mut neighbours: Vec<(usize, f32)> = vectors.iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(j, (_, b))| (j, cosine_similarity(a, b)))
+            .filter(|(_, sim)| *sim >= threshold)
+            .collect();
+        neighbours.sort_by(|x, y| y.1.total_cmp(&x.1).then(x.0.cmp(&y.0)));
+        for (j, sim) in neighbours.into_iter().take(k) {
+            best.insert((i.min(j), i.max(j)), sim);
+        }
+    }
+
+    let mut pairs: Vec<SimilarityPair> = best.into_iter()
+        .map(|((i, j), similarity)| SimilarityPair {
+            source: vectors[i].0.clone(),
+            target: vectors[j].0.clone(),
+            similarity,
+        })
+        .collect();
+    pairs.sort_by(|a, b| a.source.cmp(&b.source).then(a.target.cmp(&b.target)));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Bag-of-words over a tiny fixed vocabulary
+    struct MockProvider {
+        calls: AtomicUsize,
+    }
+
+    const VOCAB: [&str; 6] = ["cat", "dog", "pet", "rust", "cargo", "borrow"];
+
+    #[async_trait]
+    impl EmbeddingProvider for MockProvider {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+            self.calls.fetch_add(inputs.len(), Ordering::SeqCst);
+            Ok(inputs.iter()
+                .map(|text| VOCAB.iter().map(|w| text.matches(w).count() as f32).collect())
+                .collect())
+        }
+    }
+
+    fn settings() -> EmbeddingSettings {
+        EmbeddingSettings {
+            enabled: true,
+            similarity_threshold: 0.8,
+            max_neighbours: 5,
+            ..Default::default()
+        }
+    }
+
+    fn corpus(dir: &std::path::Path) -> MetadataStore {
+        let docs = [
+            ("cats.md", "cat pet"),
+            ("dogs.md", "dog pet cat"),
+            ("rust.md", "rust cargo borrow"),
+            ("cargo.md", "cargo rust"),
+            ("misc.md", "nothing relevant here"),
+        ];
+        let mut store = MetadataStore::new();
+        for (i, (name, text)) in docs.iter().enumerate() {
+            fs::write(dir.join(name), text).unwrap();
+            store.insert(name.to_string(), Metadata {
+                file_name: name.to_string(),
+                sha1: format!("{:040x}", i + 1),
+                ..Default::default()
+            });
+        }
+        store
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("embedding-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_similarity_edges_from_mock_corpus() {
+        let dir = temp_dir();
+        let store = corpus(&dir);
+        let provider = Arc::new(MockProvider { calls: AtomicUsize::new(0) });
+        let service = EmbeddingService::with_provider(
+            Some(provider.clone()), settings(), dir.join("cache/embeddings.json"), dir.clone());
+
+        assert_eq!(service.embed_pending(&store).await.unwrap(), 5);
+        // Cached by SHA1, so a second pass embeds nothing
+        assert_eq!(service.embed_pending(&store).await.unwrap(), 0);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 5);
+
+        let pairs = service.pairs(&store).await;
+        let names: Vec<(&str, &str)> = pairs.iter().map(|p| (p.source.as_str(), p.target.as_str())).collect();
+        assert_eq!(names, vec![("cargo", "rust"), ("cats", "dogs")]);
+
+        // Cache survives a restart
+        let reloaded = EmbeddingService::with_provider(
+            Some(provider.clone()), settings(), dir.join("cache/embeddings.json"), dir.clone());
+        assert_eq!(reloaded.embed_pending(&store).await.unwrap(), 0);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_threshold_controls_edges() {
+        let dir = temp_dir();
+        let store = corpus(&dir);
+        let provider = Arc::new(MockProvider { calls: AtomicUsize::new(0) });
+        let service = EmbeddingService::with_provider(
+            Some(provider), settings(), dir.join("cache/embeddings.json"), dir.clone());
+        service.embed_pending(&store).await.unwrap();
+
+        service.set_params(SimilarityParams { enabled: true, threshold: 0.999, k: 5 });
+        assert!(service.pairs(&store).await.is_empty());
+
+        // A low threshold links the two clusters internally but never across them
+        service.set_params(SimilarityParams { enabled: true, threshold: 0.1, k: 5 });
+        let pairs = service.pairs(&store).await;
+        assert_eq!(pairs.len(), 2);
+        assert!(pairs.iter().all(|p| p.similarity >= 0.1));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_k_caps_neighbours() {
+        let v = [1.0f32, 0.0];
+        let vectors: Vec<(String, &[f32])> = (0..6).map(|i| (format!("n{}", i), &v[..])).collect();
+        // Fully connected would be 15 edges
+        assert_eq!(similarity_edges(&vectors, 0.5, 5).len(), 15);
+        let capped = similarity_edges(&vectors, 0.5, 1);
+        assert!(capped.len() <= vectors.len());
+        assert!(!capped.is_empty());
+        assert!(similarity_edges(&vectors, 0.5, 0).is_empty());
+    }
+}
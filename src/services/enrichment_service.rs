@@ -0,0 +1,360 @@
+use actix::Addr;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::actors::messages::{GetMetadata, SetPerplexityLink, UpdateNodeMetadata};
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::config::{EnrichmentSettings, WebhookEventKind};
+use crate::models::metadata::MetadataStore;
+use crate::services::file_service::FileService;
+use crate::services::perplexity_service::PerplexityService;
+use crate::services::webhook_service::WebhookService;
+
+// First backoff after a node fails all retries; doubles per consecutive failure
+const BASE_BACKOFF_MINUTES: i64 = 5;
+const MAX_BACKOFF_HOURS: i64 = 24;
+const RECENT_FAILURES_KEPT: usize = 20;
+
+#[derive(Debug, Clone)]
+struct NodeBackoff {
+    failures: u32,
+    next_attempt: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrichmentFailure {
+    pub file_name: String,
+    pub error: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct EnrichmentState {
+    queue_depth: usize,
+    in_flight: usize,
+    processed: u64,
+    failed: u64,
+    last_run_started: Option<DateTime<Utc>>,
+    last_run_finished: Option<DateTime<Utc>>,
+    backoff: HashMap<String, NodeBackoff>,
+    recent_failures: Vec<EnrichmentFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrichmentStatus {
+    pub enabled: bool,
+    pub configured: bool,
+    pub running: bool,
+    pub queue_depth: usize,
+    pub in_flight: usize,
+    pub processed: u64,
+    pub failed: u64,
+    pub backing_off: usize,
+    pub last_run_started: Option<DateTime<Utc>>,
+    pub last_run_finished: Option<DateTime<Utc>>,
+    pub recent_failures: Vec<EnrichmentFailure>,
+}
+
+/// Keeps `perplexity_link` / `last_perplexity_process` fresh in the background.
+/// Runs on its own task and only talks to the actors with short messages, so a
+/// slow or failing API never holds up a graph rebuild.
+pub struct EnrichmentService {
+    perplexity: Option<Arc<PerplexityService>>,
+    settings: EnrichmentSettings,
+    state: Mutex<EnrichmentState>,
+    running: AtomicBool,
+    // Serialises metadata.json writes from concurrent workers
+    persist_lock: tokio::sync::Mutex<()>,
+    // Told about every refresh result
+    webhooks: Option<Arc<WebhookService>>,
+}
+
+impl EnrichmentService {
+    pub fn new(perplexity: Option<Arc<PerplexityService>>, settings: EnrichmentSettings) -> Self {
+        Self {
+            perplexity,
+            settings,
+            state: Mutex::new(EnrichmentState::default()),
+            running: AtomicBool::new(false),
+            persist_lock: tokio::sync::Mutex::new(()),
+            webhooks: None,
+        }
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Arc<WebhookService>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.perplexity.is_some()
+    }
+
+    pub fn status(&self) -> EnrichmentStatus {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        EnrichmentStatus {
+            enabled: self.settings.enabled,
+            configured: self.is_configured(),
+            running: self.running.load(Ordering::SeqCst),
+            queue_depth: state.queue_depth,
+            in_flight: state.in_flight,
+            processed: state.processed,
+            failed: state.failed,
+            backing_off: state.backoff.values().filter(|b| b.next_attempt > now).count(),
+            last_run_started: state.last_run_started,
+            last_run_finished: state.last_run_finished,
+            recent_failures: state.recent_failures.clone(),
+        }
+    }
+
+    /// Spawns the periodic refresh loop if enabled and an API client is configured
+    pub fn start(self: Arc<Self>, metadata_addr: Addr<MetadataActor>, graph_addr: Addr<GraphServiceActor>) {
+        if !self.settings.enabled {
+            info!("Perplexity enrichment job disabled");
+            return;
+        }
+        if self.perplexity.is_none() {
+            warn!("Perplexity enrichment enabled but no API key configured; job not started");
+            return;
+        }
+
+        let interval = Duration::from_secs(self.settings.interval_secs.max(60));
+        info!("Starting Perplexity enrichment job (every {:?})", interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.run_once(&metadata_addr, &graph_addr).await {
+                    Ok(count) => debug!("Enrichment pass refreshed {} nodes", count),
+                    Err(e) => warn!("Enrichment pass skipped: {}", e),
+                }
+            }
+        });
+    }
+
+    /// One pass over every stale node. Returns how many were refreshed.
+    pub async fn run_once(
+        &self,
+        metadata_addr: &Addr<MetadataActor>,
+        graph_addr: &Addr<GraphServiceActor>,
+    ) -> Result<usize, String> {
+        if self.running.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return Err("a pass is already running".to_string());
+        }
+
+        let result = async {
+            let store = metadata_addr.send(GetMetadata).await
+                .map_err(|e| e.to_string())??;
+            let now = Utc::now();
+            let stale = {
+                let mut state = self.state.lock().unwrap();
+                let stale = select_stale(&store, now, ChronoDuration::hours(self.settings.max_age_hours as i64), &state.backoff);
+                state.queue_depth = stale.len();
+                state.last_run_started = Some(now);
+                stale
+            };
+            if !stale.is_empty() {
+                info!("Enrichment pass: {} stale nodes", stale.len());
+            }
+
+            let refreshed = stream::iter(stale)
+                .map(|file_name| async move {
+                    let ok = self.refresh(&file_name, metadata_addr, graph_addr).await.is_ok();
+                    let mut state = self.state.lock().unwrap();
+                    state.queue_depth = state.queue_depth.saturating_sub(1);
+                    ok
+                })
+                .buffer_unordered(self.settings.concurrency.max(1))
+                .filter(|ok| futures::future::ready(*ok))
+                .count()
+                .await;
+
+            self.state.lock().unwrap().last_run_finished = Some(Utc::now());
+            Ok::<usize, String>(refreshed)
+        }.await;
+
+        self.running.store(false, Ordering::SeqCst);
+        result
+    }
+
+    /// Refreshes a single file, retrying transient failures. Used by the job and
+    /// by the force-refresh endpoint (which ignores age and backoff).
+    pub async fn refresh(
+        &self,
+        file_name: &str,
+        metadata_addr: &Addr<MetadataActor>,
+        graph_addr: &Addr<GraphServiceActor>,
+    ) -> Result<String, String> {
+        let perplexity = self.perplexity.as_ref()
+            .ok_or_else(|| "Perplexity service not configured".to_string())?;
+
+        self.state.lock().unwrap().in_flight += 1;
+        let mut last_error = String::new();
+        let mut link = None;
+        for attempt in 0..=self.settings.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+            }
+            match perplexity.process_file(file_name).await {
+                Ok(processed) => {
+                    link = Some(processed.metadata.perplexity_link);
+                    break;
+                }
+                Err(e) => {
+                    debug!("Perplexity attempt {} for {} failed: {}", attempt + 1, file_name, e);
+                    last_error = e.to_string();
+                }
+            }
+        }
+        self.state.lock().unwrap().in_flight -= 1;
+
+        let result = match link {
+            Some(link) => self.apply(file_name, link, metadata_addr, graph_addr).await,
+            None => Err(last_error),
+        };
+
+        if let Some(webhooks) = &self.webhooks {
+            let data = match &result {
+                Ok(link) => serde_json::json!({ "fileName": file_name, "perplexityLink": link }),
+                Err(e) => serde_json::json!({ "fileName": file_name, "error": e }),
+            };
+            webhooks.notify(WebhookEventKind::Enrichment, data);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                state.processed += 1;
+                state.backoff.remove(file_name);
+            }
+            Err(e) => {
+                warn!("Enrichment failed for {}: {}", file_name, e);
+                state.failed += 1;
+                let failures = state.backoff.get(file_name).map_or(0, |b| b.failures) + 1;
+                state.backoff.insert(file_name.to_string(), NodeBackoff {
+                    failures,
+                    next_attempt: Utc::now() + backoff_delay(failures),
+                });
+                state.recent_failures.push(EnrichmentFailure {
+                    file_name: file_name.to_string(),
+                    error: e.clone(),
+                    at: Utc::now(),
+                });
+                let excess = state.recent_failures.len().saturating_sub(RECENT_FAILURES_KEPT);
+                state.recent_failures.drain(..excess);
+            }
+        }
+        result
+    }
+
+    async fn apply(
+        &self,
+        file_name: &str,
+        link: String,
+        metadata_addr: &Addr<MetadataActor>,
+        graph_addr: &Addr<GraphServiceActor>,
+    ) -> Result<String, String> {
+        let processed_at = Utc::now();
+        let store = metadata_addr.send(SetPerplexityLink {
+            file_name: file_name.to_string(),
+            link: link.clone(),
+            processed_at,
+        }).await.map_err(|e| e.to_string())??;
+
+        let mut entries = HashMap::new();
+        entries.insert("perplexityLink".to_string(), link.clone());
+        entries.insert("lastPerplexityProcess".to_string(), processed_at.to_rfc3339());
+        // The node may not exist yet if a rebuild is pending; the metadata carries it over
+        if let Err(e) = graph_addr.send(UpdateNodeMetadata {
+            metadata_id: file_name.trim_end_matches(".md").to_string(),
+            entries,
+        }).await.map_err(|e| e.to_string()).and_then(|r| r) {
+            debug!("Node metadata not updated for {}: {}", file_name, e);
+        }
+
+        let _guard = self.persist_lock.lock().await;
+        match tokio::task::spawn_blocking(move || FileService::save_metadata(&store)).await {
+            Ok(Ok(())) => Ok(link),
+            Ok(Err(e)) => {
+                error!("Failed to persist metadata after enrichment: {}", e);
+                Err(e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Exponential per-node backoff, capped at a day
+fn backoff_delay(failures: u32) -> ChronoDuration {
+    let minutes = BASE_BACKOFF_MINUTES.saturating_mul(1i64 << failures.saturating_sub(1).min(16));
+    ChronoDuration::minutes(minutes).min(ChronoDuration::hours(MAX_BACKOFF_HOURS))
+}
+
+/// Files never processed or processed longer than `max_age` ago, skipping any
+/// still backing off. Oldest first so never-processed files lead.
+fn select_stale(
+    store: &MetadataStore,
+    now: DateTime<Utc>,
+    max_age: ChronoDuration,
+    backoff: &HashMap<String, NodeBackoff>,
+) -> Vec<String> {
+    let mut stale: Vec<(&String, Option<DateTime<Utc>>)> = store.iter()
+        .filter(|(_, meta)| meta.last_perplexity_process.is_none_or(|t| now - t > max_age))
+        .filter(|(name, _)| backoff.get(*name).is_none_or(|b| b.next_attempt <= now))
+        .map(|(name, meta)| (name, meta.last_perplexity_process))
+        .collect();
+    stale.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+    stale.into_iter().map(|(name, _)| name.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+
+    fn meta(last: Option<DateTime<Utc>>) -> Metadata {
+        Metadata { last_perplexity_process: last, ..Default::default() }
+    }
+
+    #[test]
+    fn test_select_stale_orders_and_skips_fresh() {
+        let now = Utc::now();
+        let mut store = MetadataStore::new();
+        store.insert("fresh.md".to_string(), meta(Some(now - ChronoDuration::hours(1))));
+        store.insert("old.md".to_string(), meta(Some(now - ChronoDuration::days(30))));
+        store.insert("never.md".to_string(), meta(None));
+
+        let stale = select_stale(&store, now, ChronoDuration::days(7), &HashMap::new());
+        assert_eq!(stale, vec!["never.md".to_string(), "old.md".to_string()]);
+    }
+
+    #[test]
+    fn test_backoff_excludes_until_due() {
+        let now = Utc::now();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), meta(None));
+        let mut backoff = HashMap::new();
+        backoff.insert("a.md".to_string(), NodeBackoff { failures: 1, next_attempt: now + backoff_delay(1) });
+
+        assert!(select_stale(&store, now, ChronoDuration::days(7), &backoff).is_empty());
+        let later = now + ChronoDuration::minutes(BASE_BACKOFF_MINUTES + 1);
+        assert_eq!(select_stale(&store, later, ChronoDuration::days(7), &backoff).len(), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff_delay(1), ChronoDuration::minutes(5));
+        assert_eq!(backoff_delay(2), ChronoDuration::minutes(10));
+        assert_eq!(backoff_delay(3), ChronoDuration::minutes(20));
+        assert_eq!(backoff_delay(40), ChronoDuration::hours(MAX_BACKOFF_HOURS));
+    }
+}
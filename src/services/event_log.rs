@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// One attributed graph mutation
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEvent {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    // Who made the change, e.g. "agent:indexer" or "speech"
+    pub actor: String,
+    pub kind: String,
+    pub detail: Value,
+}
+
+struct EventLogState {
+    events: VecDeque<GraphEvent>,
+    next_seq: u64,
+}
+
+/// In-memory ring buffer of recent mutations, newest last
+pub struct EventLog {
+    state: Mutex<EventLogState>,
+    capacity: usize,
+    // Seq of the newest event, for readers that follow the log as it grows
+    latest: watch::Sender<u64>,
+}
+
+impl std::fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventLog").field("capacity", &self.capacity).field("latest", &self.latest_seq()).finish()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(EventLogState { events: VecDeque::new(), next_seq: 1 }),
+            capacity: capacity.max(1),
+            latest: watch::Sender::new(0),
+        }
+    }
+
+    pub fn record(&self, actor: &str, kind: &str, detail: Value) -> GraphEvent {
+        let mut state = self.state.lock().unwrap();
+        let event = GraphEvent {
+            seq: state.next_seq,
+            at: Utc::now(),
+            actor: actor.to_string(),
+            kind: kind.to_string(),
+            detail,
+        };
+        state.next_seq += 1;
+        if state.events.len() == self.capacity {
+            state.events.pop_front();
+        }
+        state.events.push_back(event.clone());
+        drop(state);
+        self.latest.send_replace(event.seq);
+        event
+    }
+
+    /// Wakes whenever an event is recorded; the value is the newest seq
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.latest.subscribe()
+    }
+
+    pub fn latest_seq(&self) -> u64 {
+        *self.latest.borrow()
+    }
+
+    /// Events after `seq` still in the buffer, oldest first
+    pub fn since(&self, seq: u64) -> Vec<GraphEvent> {
+        let state = self.state.lock().unwrap();
+        state.events.iter().filter(|e| e.seq > seq).cloned().collect()
+    }
+
+    /// Up to `limit` most recent events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<GraphEvent> {
+        let state = self.state.lock().unwrap();
+        let skip = state.events.len().saturating_sub(limit);
+        state.events.iter().skip(skip).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ring_buffer_keeps_newest() {
+        let log = EventLog::with_capacity(2);
+        log.record("agent:a", "create_node", json!({}));
+        log.record("agent:a", "create_edge", json!({}));
+        log.record("speech", "create_node", json!({}));
+
+        let events = log.recent(10);
+        assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(log.recent(1)[0].actor, "speech");
+        assert_eq!(log.since(2).iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(log.latest_seq(), 3);
+    }
+}
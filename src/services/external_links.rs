@@ -0,0 +1,341 @@
+//! References from a document in one room to a document in another. Links come from
+//! `system.external_links` or are imported at runtime, and only imported ones are saved.
+//! They are never graph edges: clients draw them as portals, so neither room's physics
+//! sees them. Each end is checked against its room's graph whenever that room builds,
+//! and ends whose document has gone are kept but marked dangling.
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use thiserror::Error;
+
+use crate::config::ExternalLinkSettings;
+use crate::models::node::Node;
+use crate::models::spatial_anchor::validate_room;
+use crate::utils::json_store::write_json_atomic;
+
+const EXTERNAL_LINKS_PATH: &str = "/app/data/rooms/external_links.json";
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalLink {
+    pub from_room: String,
+    pub from_metadata_id: String,
+    pub to_room: String,
+    pub to_metadata_id: String,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+impl ExternalLink {
+    fn same_ends(&self, other: &ExternalLink) -> bool {
+        self.from_room == other.from_room
+            && self.from_metadata_id == other.from_metadata_id
+            && self.to_room == other.to_room
+            && self.to_metadata_id == other.to_metadata_id
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkEnd {
+    From,
+    To,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkSource {
+    Settings,
+    Import,
+}
+
+/// A link with what its rooms' last builds made of it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalLinkStatus {
+    #[serde(flatten)]
+    pub link: ExternalLink,
+    pub source: LinkSource,
+    // Ends whose document was missing when their room last built
+    pub dangling: Vec<LinkEnd>,
+    // False until a room at either end has been checked since the link was added
+    pub checked: bool,
+}
+
+/// A link as seen from one of its rooms: the local document and where the portal leads
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Portal {
+    pub metadata_id: String,
+    // The local document's node, when it is in the graph
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<u32>,
+    pub target_room: String,
+    pub target_metadata_id: String,
+    pub weight: f32,
+    // True when the link points out of this room, false when it points in
+    pub outgoing: bool,
+    pub dangling: bool,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExternalLinkError {
+    #[error("{0}")]
+    InvalidRoom(String),
+    #[error("External links need a metadata id at both ends")]
+    MissingMetadataId,
+    #[error("External links join two different rooms")]
+    SameRoom,
+    #[error("Weight must be a positive number, got {0}")]
+    InvalidWeight(f32),
+    #[error("'{0}' is not of the form room:metadata_id")]
+    InvalidAlias(String),
+    #[error("{0}")]
+    Storage(String),
+}
+
+fn validate(link: &ExternalLink) -> Result<ExternalLink, ExternalLinkError> {
+    let from_room = validate_room(&link.from_room).map_err(ExternalLinkError::InvalidRoom)?;
+    let to_room = validate_room(&link.to_room).map_err(ExternalLinkError::InvalidRoom)?;
+    if from_room == to_room {
+        return Err(ExternalLinkError::SameRoom);
+    }
+    if link.from_metadata_id.trim().is_empty() || link.to_metadata_id.trim().is_empty() {
+        return Err(ExternalLinkError::MissingMetadataId);
+    }
+    if !link.weight.is_finite() || link.weight <= 0.0 {
+        return Err(ExternalLinkError::InvalidWeight(link.weight));
+    }
+    Ok(ExternalLink { from_room, to_room, ..link.clone() })
+}
+
+// "room:metadata_id"; the room can't contain ':', so the first one splits them
+fn parse_alias_end(end: &str) -> Result<(String, String), ExternalLinkError> {
+    end.split_once(':')
+        .map(|(room, metadata_id)| (room.to_string(), metadata_id.to_string()))
+        .ok_or_else(|| ExternalLinkError::InvalidAlias(end.to_string()))
+}
+
+fn alias_link(from: &str, to: &str, weight: f32) -> Result<ExternalLink, ExternalLinkError> {
+    let (from_room, from_metadata_id) = parse_alias_end(from)?;
+    let (to_room, to_metadata_id) = parse_alias_end(to)?;
+    validate(&ExternalLink { from_room, from_metadata_id, to_room, to_metadata_id, weight })
+}
+
+pub struct ExternalLinkService {
+    links: RwLock<Vec<ExternalLinkStatus>>,
+    path: PathBuf,
+}
+
+impl ExternalLinkService {
+    pub fn new(settings: &ExternalLinkSettings) -> Self {
+        Self::with_path(settings, PathBuf::from(EXTERNAL_LINKS_PATH))
+    }
+
+    pub fn with_path(settings: &ExternalLinkSettings, path: PathBuf) -> Self {
+        let mut links = Vec::new();
+        for (from, to) in &settings.aliases {
+            match alias_link(from, to, settings.weight) {
+                Ok(link) => upsert(&mut links, link, LinkSource::Settings),
+                Err(e) => warn!("Skipping external link {} -> {}: {}", from, to, e),
+            }
+        }
+        let imported: Vec<ExternalLink> = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse external links file {:?}: {}. Ignoring it.", path, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        if !imported.is_empty() {
+            info!("Loaded {} imported external links from {:?}", imported.len(), path);
+        }
+        for link in imported {
+            upsert(&mut links, link, LinkSource::Import);
+        }
+        Self { links: RwLock::new(links), path }
+    }
+
+    fn persist(&self, links: &[ExternalLinkStatus]) -> Result<(), ExternalLinkError> {
+        let imported: Vec<&ExternalLink> = links.iter()
+            .filter(|status| status.source == LinkSource::Import)
+            .map(|status| &status.link)
+            .collect();
+        write_json_atomic(&self.path, &imported).map_err(ExternalLinkError::Storage)
+    }
+
+    /// Adds `links`, replacing the weight of any already registered between the same
+    /// ends. Nothing is added unless every link is valid.
+    pub fn import(&self, links: &[ExternalLink]) -> Result<usize, ExternalLinkError> {
+        let valid = links.iter().map(validate).collect::<Result<Vec<_>, _>>()?;
+        let mut registry = self.links.write().unwrap();
+        for link in valid {
+            upsert(&mut registry, link, LinkSource::Import);
+        }
+        self.persist(&registry)?;
+        Ok(links.len())
+    }
+
+    /// Links with an end in `room`
+    pub fn for_room(&self, room: &str) -> Vec<ExternalLinkStatus> {
+        self.links.read().unwrap().iter()
+            .filter(|status| status.link.from_room == room || status.link.to_room == room)
+            .cloned()
+            .collect()
+    }
+
+    /// Every room some link has an end in
+    pub fn rooms(&self) -> BTreeSet<String> {
+        self.links.read().unwrap().iter()
+            .flat_map(|status| [status.link.from_room.clone(), status.link.to_room.clone()])
+            .collect()
+    }
+
+    /// Checks the ends in `room` against the metadata ids its graph was just built with
+    pub fn revalidate(&self, room: &str, metadata_ids: &HashSet<String>) {
+        for status in self.links.write().unwrap().iter_mut() {
+            let ends = [
+                (LinkEnd::From, &status.link.from_room, &status.link.from_metadata_id),
+                (LinkEnd::To, &status.link.to_room, &status.link.to_metadata_id),
+            ];
+            let mut touched = false;
+            let mut dangling = status.dangling.clone();
+            for (end, end_room, metadata_id) in ends {
+                if end_room != room {
+                    continue;
+                }
+                touched = true;
+                dangling.retain(|d| *d != end);
+                if !metadata_ids.contains(metadata_id) {
+                    dangling.push(end);
+                }
+            }
+            if touched {
+                dangling.sort_by_key(|end| *end == LinkEnd::To);
+                status.dangling = dangling;
+                status.checked = true;
+            }
+        }
+    }
+
+    /// Links with an end that is missing from its room
+    pub fn dangling(&self) -> Vec<ExternalLinkStatus> {
+        self.links.read().unwrap().iter().filter(|status| !status.dangling.is_empty()).cloned().collect()
+    }
+
+    /// The room's links as portals, with the local documents found among `nodes`
+    pub fn portals(&self, room: &str, nodes: &[Node]) -> Vec<Portal> {
+        self.for_room(room).into_iter().map(|status| {
+            let link = status.link;
+            let outgoing = link.from_room == room;
+            let (metadata_id, target_room, target_metadata_id) = if outgoing {
+                (link.from_metadata_id, link.to_room, link.to_metadata_id)
+            } else {
+                (link.to_metadata_id, link.from_room, link.from_metadata_id)
+            };
+            Portal {
+                node_id: nodes.iter().find(|n| n.metadata_id == metadata_id).map(|n| n.id),
+                metadata_id,
+                target_room,
+                target_metadata_id,
+                weight: link.weight,
+                outgoing,
+                dangling: !status.dangling.is_empty(),
+            }
+        }).collect()
+    }
+}
+
+fn upsert(links: &mut Vec<ExternalLinkStatus>, link: ExternalLink, source: LinkSource) {
+    match links.iter_mut().find(|status| status.link.same_ends(&link)) {
+        Some(status) => {
+            status.link.weight = link.weight;
+            status.source = source;
+        }
+        None => links.push(ExternalLinkStatus { link, source, dangling: Vec::new(), checked: false }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use uuid::Uuid;
+
+    fn service(name: &str, aliases: &[(&str, &str)]) -> (ExternalLinkService, PathBuf) {
+        let path = std::env::temp_dir().join(format!("external-links-{}-{}.json", name, Uuid::new_v4()));
+        let settings = ExternalLinkSettings {
+            aliases: aliases.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect::<BTreeMap<_, _>>(),
+            weight: 0.5,
+        };
+        (ExternalLinkService::with_path(&settings, path.clone()), path)
+    }
+
+    fn link(from: (&str, &str), to: (&str, &str), weight: f32) -> ExternalLink {
+        ExternalLink {
+            from_room: from.0.to_string(),
+            from_metadata_id: from.1.to_string(),
+            to_room: to.0.to_string(),
+            to_metadata_id: to.1.to_string(),
+            weight,
+        }
+    }
+
+    fn service_at(path: &Path) -> ExternalLinkService {
+        ExternalLinkService::with_path(&ExternalLinkSettings::default(), path.to_path_buf())
+    }
+
+    #[test]
+    fn test_links_come_from_settings_and_imports() {
+        let (service, path) = service("create", &[("work:Rust.md", "personal:Rust notes.md"), ("broken", "personal:x.md")]);
+        // The malformed alias is skipped
+        let work = service.for_room("work");
+        assert_eq!(work.len(), 1);
+        assert_eq!((work[0].source, work[0].link.weight, work[0].checked), (LinkSource::Settings, 0.5, false));
+
+        assert_eq!(service.import(&[link(("personal", "Trips.md"), ("work", "Offsite.md"), 2.0)]), Ok(1));
+        // One bad link rejects the whole import
+        assert_eq!(
+            service.import(&[link(("work", "a.md"), ("personal", "b.md"), 1.0), link(("work", "a.md"), ("work", "b.md"), 1.0)]),
+            Err(ExternalLinkError::SameRoom)
+        );
+        assert_eq!(service.for_room("personal").len(), 2);
+        assert_eq!(service.rooms().into_iter().collect::<Vec<_>>(), ["personal", "work"]);
+
+        // Only imported links are saved
+        let reloaded = service_at(&path);
+        assert_eq!(reloaded.for_room("work").len(), 1);
+        assert_eq!(reloaded.for_room("work")[0].link.from_metadata_id, "Trips.md");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_rebuilds_mark_and_clear_dangling_ends() {
+        let (service, path) = service("rebuild", &[("work:Rust.md", "personal:Rust notes.md")]);
+        let ids = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+
+        service.revalidate("work", &ids(&["Rust.md"]));
+        service.revalidate("personal", &ids(&["Other.md"]));
+        let dangling = service.dangling();
+        assert_eq!(dangling.len(), 1);
+        assert_eq!(dangling[0].dangling, [LinkEnd::To]);
+
+        // Rebuilding one room leaves the other room's verdict alone
+        service.revalidate("work", &ids(&[]));
+        assert_eq!(service.dangling()[0].dangling, [LinkEnd::From, LinkEnd::To]);
+        service.revalidate("work", &ids(&["Rust.md"]));
+        service.revalidate("personal", &ids(&["Rust notes.md"]));
+        assert!(service.dangling().is_empty());
+        assert!(service.for_room("work")[0].checked);
+        let _ = fs::remove_file(path);
+    }
+}
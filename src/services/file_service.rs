@@ -1,9 +1,9 @@
 use crate::models::metadata::{Metadata, MetadataStore, MetadataOps};
+use crate::models::node_ids::SharedNodeIds;
 use crate::models::graph::GraphData;
 use crate::config::AppFullSettings; // Use AppFullSettings, ClientFacingSettings removed
 use serde::{Deserialize, Serialize};
 use log::{info, debug, error};
-use std::sync::atomic::{AtomicU32, Ordering};
 use regex::Regex;
 use std::fs;
 use std::path::Path;
@@ -34,42 +34,32 @@ pub struct ProcessedFile {
 
 pub struct FileService {
     _settings: Arc<RwLock<AppFullSettings>>, // Changed to AppFullSettings, prefixed with underscore
-    // Counter for assigning node IDs, initialized based on existing metadata
-    node_id_counter: AtomicU32,
+    // Node ids come from the process-wide id map, so a file keeps its id everywhere
+    node_ids: SharedNodeIds,
 }
 
 impl FileService {
-    pub fn new(_settings: Arc<RwLock<AppFullSettings>>) -> Self { // Changed to AppFullSettings, parameter prefixed
-        // Initialize with a default counter
-        let service = Self {
+    pub fn new(_settings: Arc<RwLock<AppFullSettings>>, node_ids: SharedNodeIds) -> Self { // Changed to AppFullSettings, parameter prefixed
+        Self {
             _settings, // Prefixed with underscore
-            node_id_counter: AtomicU32::new(1),
-        };
-        
-        // Try to initialize the counter based on existing metadata
-        if let Ok(metadata) = Self::load_or_create_metadata() {
-            let max_id = metadata.get_max_node_id();
-            if max_id > 0 {
-                // Start from the next ID after the maximum
-                service.node_id_counter.store(max_id + 1, Ordering::SeqCst);
-                info!("Initialized node ID counter to {} based on existing metadata", max_id + 1);
-            }
+            node_ids,
         }
-        
-        service
     }
     
-    /// Get the next unique node ID
-    fn get_next_node_id(&self) -> u32 {
-        self.node_id_counter.fetch_add(1, Ordering::SeqCst)
+    /// The node id for `file_name`, allocated and persisted if it has none yet
+    fn node_id_for(&self, file_name: &str) -> u32 {
+        let mut node_ids = self.node_ids.lock().unwrap();
+        let id = node_ids.id_for(file_name.trim_end_matches(".md"));
+        if let Err(e) = node_ids.save() {
+            error!("Failed to save node ids: {}", e);
+        }
+        id
     }
     
     /// Update node IDs for processed files
     fn update_node_ids(&self, processed_files: &mut Vec<ProcessedFile>) {
         for processed_file in processed_files {
-            if processed_file.metadata.node_id == "0" {
-                processed_file.metadata.node_id = self.get_next_node_id().to_string();
-            }
+            processed_file.metadata.node_id = self.node_id_for(&processed_file.file_name).to_string();
         }
     }
 
@@ -110,11 +100,12 @@ impl FileService {
             perplexity_link: String::new(),
             last_perplexity_process: None,
             topic_counts,
+            ..Default::default()
         };
 
         // Assign a unique node ID
         let mut file_metadata = file_metadata;
-        file_metadata.node_id = self.get_next_node_id().to_string();
+        file_metadata.node_id = self.node_id_for(&temp_filename).to_string();
 
         // Update graph data
         graph_data.metadata.insert(temp_filename.clone(), file_metadata);
@@ -169,11 +160,12 @@ impl FileService {
             perplexity_link: String::new(),
             last_perplexity_process: None,
             topic_counts,
+            ..Default::default()
         };
 
         // Assign a unique node ID
         let mut file_metadata = file_metadata;
-        file_metadata.node_id = self.get_next_node_id().to_string();
+        file_metadata.node_id = self.node_id_for(filename).to_string();
 
         // Update graph data
         graph_data.metadata.insert(filename.to_string(), file_metadata);
@@ -353,6 +345,7 @@ impl FileService {
                             perplexity_link: String::new(),
                             last_perplexity_process: None,
                             topic_counts: HashMap::new(), // Will be updated later
+                            ..Default::default()
                         };
 
                         metadata_store.insert(file_meta.name, metadata);
@@ -545,6 +538,7 @@ impl FileService {
                                         perplexity_link: String::new(),
                                         last_perplexity_process: None,
                                         topic_counts: HashMap::new(), // Will be updated later
+                                        ..Default::default()
                                     };
 
                                     Ok(Some(ProcessedFile {
@@ -573,7 +567,10 @@ impl FileService {
             
             for result in results {
                 match result {
-                    Ok(Some(processed_file)) => {
+                    Ok(Some(mut processed_file)) => {
+                        if let Some(previous) = metadata_store.get(&processed_file.file_name) {
+                            processed_file.metadata.carry_over_tags(previous);
+                        }
                         processed_files.push(processed_file);
                     }
                     Ok(None) => continue, // Skipped non-public file
@@ -0,0 +1,307 @@
+//! Cross-checks the metadata store, the built graph and the layout state persisted
+//! against metadata ids. Crashes mid-write can leave snapshots and pins pointing at
+//! files that are gone, or metadata whose node ids collide; this finds them, and
+//! fixes the ones that can be fixed without a person deciding.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::models::graph::GraphData;
+use crate::models::layout::NodeLayout;
+use crate::models::metadata::MetadataStore;
+use crate::models::node_ids::NodeIdMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+    InvalidNodeId,
+    DuplicateNodeId,
+    OrphanLayoutEntry,
+    OrphanPin,
+    OrphanAnnotation,
+    DanglingEdge,
+    NodeMapMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueSeverity {
+    AutoFixable,
+    NeedsAttention,
+}
+
+impl IssueKind {
+    pub fn severity(self) -> IssueSeverity {
+        match self {
+            IssueKind::InvalidNodeId
+            | IssueKind::DuplicateNodeId
+            | IssueKind::OrphanLayoutEntry
+            | IssueKind::OrphanPin => IssueSeverity::AutoFixable,
+            // Notes are user content, and a broken graph needs a rebuild rather than a patch
+            IssueKind::OrphanAnnotation
+            | IssueKind::DanglingEdge
+            | IssueKind::NodeMapMismatch => IssueSeverity::NeedsAttention,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityIssue {
+    pub kind: IssueKind,
+    pub severity: IssueSeverity,
+    // What the issue is about: a file name, metadata id, edge id or node id
+    pub subject: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReassignedId {
+    pub file_name: String,
+    pub from: String,
+    pub to: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedFixes {
+    pub reassigned_ids: Vec<ReassignedId>,
+    pub dropped_pins: usize,
+    pub dropped_layout_entries: usize,
+    // The graph was rebuilt so its nodes pick up the reassigned ids' files
+    pub rebuilt_graph: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    pub checked_at: DateTime<Utc>,
+    pub issues: Vec<IntegrityIssue>,
+    pub auto_fixable: usize,
+    pub needs_attention: usize,
+    // Absent unless fixes were asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixes: Option<AppliedFixes>,
+}
+
+impl IntegrityReport {
+    pub fn of_kind(&self, kind: IssueKind) -> impl Iterator<Item = &IntegrityIssue> {
+        self.issues.iter().filter(move |issue| issue.kind == kind)
+    }
+}
+
+/// The graph actor's side of a check, taken in one turn so the parts agree
+#[derive(Debug, Clone)]
+pub struct IntegrityView {
+    pub graph: Arc<GraphData>,
+    pub node_map_ids: HashSet<u32>,
+    // Metadata ids with a persisted pin, resolvable or not
+    pub pinned: Vec<String>,
+}
+
+/// Everything a check looks at
+pub struct IntegrityInput<'a> {
+    pub metadata: &'a MetadataStore,
+    pub view: &'a IntegrityView,
+    // Metadata ids with at least one annotation
+    pub annotated: &'a [String],
+    // Persisted layouts by snapshot name
+    pub layouts: &'a [(String, Vec<NodeLayout>)],
+}
+
+/// Metadata ids the store currently knows, as graph nodes name them
+pub fn metadata_ids(metadata: &MetadataStore) -> HashSet<String> {
+    metadata.keys().map(|file_name| file_name.trim_end_matches(".md").to_string()).collect()
+}
+
+pub fn check(input: &IntegrityInput) -> IntegrityReport {
+    let mut issues = Vec::new();
+    let mut issue = |kind: IssueKind, subject: String, detail: String| {
+        issues.push(IntegrityIssue { kind, severity: kind.severity(), subject, detail });
+    };
+
+    // Sorted by file name, so the first holder of a colliding id is stable
+    let files: BTreeMap<&String, &String> = input.metadata.iter().map(|(k, m)| (k, &m.node_id)).collect();
+    let mut holders: HashMap<u32, &str> = HashMap::new();
+    for (file_name, node_id) in &files {
+        match node_id.parse::<u32>() {
+            Ok(id) => match holders.get(&id) {
+                Some(first) => issue(IssueKind::DuplicateNodeId, file_name.to_string(),
+                    format!("node id {} is already used by {}", id, first)),
+                None => {
+                    holders.insert(id, file_name.as_str());
+                }
+            },
+            Err(_) => issue(IssueKind::InvalidNodeId, file_name.to_string(),
+                format!("node id '{}' is not a number", node_id)),
+        }
+    }
+
+    let known = metadata_ids(input.metadata);
+    for (name, nodes) in input.layouts {
+        for node in nodes.iter().filter(|n| !known.contains(&n.metadata_id)) {
+            issue(IssueKind::OrphanLayoutEntry, node.metadata_id.clone(),
+                format!("layout snapshot {} has a position for a file no longer in the metadata store", name));
+        }
+    }
+    for metadata_id in input.view.pinned.iter().filter(|id| !known.contains(*id)) {
+        issue(IssueKind::OrphanPin, metadata_id.clone(), "pinned file is no longer in the metadata store".to_string());
+    }
+    for metadata_id in input.annotated.iter().filter(|id| !known.contains(*id)) {
+        issue(IssueKind::OrphanAnnotation, metadata_id.clone(), "annotated file is no longer in the metadata store".to_string());
+    }
+
+    let graph = &input.view.graph;
+    let node_ids: HashSet<u32> = graph.nodes.iter().map(|n| n.id).collect();
+    for edge in &graph.edges {
+        let missing: Vec<String> = [edge.source, edge.target].iter()
+            .filter(|id| !node_ids.contains(id))
+            .map(|id| id.to_string())
+            .collect();
+        if !missing.is_empty() {
+            issue(IssueKind::DanglingEdge, edge.id.clone(), format!("endpoint {} does not exist", missing.join(" and ")));
+        }
+    }
+
+    let mut only_in_graph: Vec<&u32> = node_ids.difference(&input.view.node_map_ids).collect();
+    let mut only_in_map: Vec<&u32> = input.view.node_map_ids.difference(&node_ids).collect();
+    only_in_graph.sort();
+    only_in_map.sort();
+    for id in only_in_graph {
+        issue(IssueKind::NodeMapMismatch, id.to_string(), "in graph.nodes but missing from node_map".to_string());
+    }
+    for id in only_in_map {
+        issue(IssueKind::NodeMapMismatch, id.to_string(), "in node_map but missing from graph.nodes".to_string());
+    }
+    if graph.nodes.len() != node_ids.len() {
+        issue(IssueKind::NodeMapMismatch, "graph.nodes".to_string(),
+            format!("{} nodes share an id with another node", graph.nodes.len() - node_ids.len()));
+    }
+
+    let auto_fixable = issues.iter().filter(|i| i.severity == IssueSeverity::AutoFixable).count();
+    IntegrityReport {
+        checked_at: Utc::now(),
+        needs_attention: issues.len() - auto_fixable,
+        auto_fixable,
+        issues,
+        fixes: None,
+    }
+}
+
+/// Gives every file whose node id doesn't parse, or collides with an earlier file's, the
+/// id `ids` holds for it, which is the one the graph builds its node with. The first file
+/// by name keeps a contested id.
+pub fn reassign_node_ids(metadata: &mut MetadataStore, ids: &mut NodeIdMap) -> Vec<ReassignedId> {
+    let mut file_names: Vec<String> = metadata.keys().cloned().collect();
+    file_names.sort();
+
+    let mut seen = HashSet::new();
+    let mut reassigned = Vec::new();
+    for file_name in file_names {
+        let entry = metadata.get_mut(&file_name).expect("key taken from the store");
+        match entry.node_id.parse::<u32>() {
+            Ok(id) if seen.insert(id) => continue,
+            _ => {}
+        }
+        let to = ids.id_for(file_name.trim_end_matches(".md"));
+        reassigned.push(ReassignedId { from: entry.node_id.clone(), to, file_name });
+        entry.node_id = to.to_string();
+        seen.insert(to);
+    }
+    reassigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::metadata::Metadata;
+    use crate::models::node::Node;
+    use crate::types::vec3::Vec3Data;
+
+    fn store(entries: &[(&str, &str)]) -> MetadataStore {
+        entries.iter()
+            .map(|(name, id)| (name.to_string(), Metadata { file_name: name.to_string(), node_id: id.to_string(), ..Default::default() }))
+            .collect()
+    }
+
+    fn view(ids: &[u32]) -> IntegrityView {
+        let mut graph = GraphData::new();
+        for id in ids {
+            graph.nodes.push(Node::new_with_id(format!("n{}", id), Some(*id)));
+        }
+        IntegrityView { graph: Arc::new(graph), node_map_ids: ids.iter().copied().collect(), pinned: Vec::new() }
+    }
+
+    fn run(metadata: &MetadataStore, view: &IntegrityView, annotated: &[String], layouts: &[(String, Vec<NodeLayout>)]) -> IntegrityReport {
+        check(&IntegrityInput { metadata, view, annotated, layouts })
+    }
+
+    #[test]
+    fn test_consistent_state_has_no_issues() {
+        let metadata = store(&[("a.md", "1"), ("b.md", "2")]);
+        let mut view = view(&[1, 2]);
+        view.pinned = vec!["a".to_string()];
+        let layouts = vec![("layout-1".to_string(), vec![NodeLayout { metadata_id: "b".to_string(), position: Vec3Data::zero() }])];
+        let report = run(&metadata, &view, &["a".to_string()], &layouts);
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn test_node_id_problems_are_reassigned() {
+        let mut metadata = store(&[("a.md", "5"), ("b.md", "5"), ("c.md", "x"), ("d.md", "2")]);
+        let report = run(&metadata, &view(&[]), &[], &[]);
+        let duplicates: Vec<_> = report.of_kind(IssueKind::DuplicateNodeId).map(|i| i.subject.as_str()).collect();
+        assert_eq!(duplicates, vec!["b.md"]);
+        assert_eq!(report.of_kind(IssueKind::InvalidNodeId).count(), 1);
+        assert_eq!(report.auto_fixable, 2);
+
+        // As at startup, the id map is seeded from what metadata carries
+        let mut ids = NodeIdMap::in_memory();
+        ids.backfill(metadata.iter().map(|(name, m)| (name.trim_end_matches(".md"), m.node_id.parse().unwrap_or(0)))).unwrap();
+        let reassigned = reassign_node_ids(&mut metadata, &mut ids);
+        assert_eq!(reassigned.iter().map(|r| (r.file_name.as_str(), r.to)).collect::<Vec<_>>(), vec![("b.md", 6), ("c.md", 7)]);
+        assert_eq!(metadata["a.md"].node_id, "5");
+        assert!(run(&metadata, &view(&[]), &[], &[]).issues.is_empty());
+    }
+
+    #[test]
+    fn test_orphaned_persisted_entries() {
+        let metadata = store(&[("a.md", "1")]);
+        let mut view = view(&[1]);
+        view.pinned = vec!["a".to_string(), "gone".to_string()];
+        let layouts = vec![("layout-1".to_string(), vec![
+            NodeLayout { metadata_id: "a".to_string(), position: Vec3Data::zero() },
+            NodeLayout { metadata_id: "deleted".to_string(), position: Vec3Data::zero() },
+        ])];
+        let report = run(&metadata, &view, &["a".to_string(), "removed".to_string()], &layouts);
+
+        assert_eq!(report.of_kind(IssueKind::OrphanLayoutEntry).map(|i| i.subject.as_str()).collect::<Vec<_>>(), vec!["deleted"]);
+        assert_eq!(report.of_kind(IssueKind::OrphanPin).map(|i| i.subject.as_str()).collect::<Vec<_>>(), vec!["gone"]);
+        let annotation = report.of_kind(IssueKind::OrphanAnnotation).next().unwrap();
+        assert_eq!(annotation.subject, "removed");
+        assert_eq!(annotation.severity, IssueSeverity::NeedsAttention);
+        assert_eq!((report.auto_fixable, report.needs_attention), (2, 1));
+    }
+
+    #[test]
+    fn test_broken_graph_needs_attention() {
+        let metadata = store(&[("a.md", "1"), ("b.md", "2")]);
+        let mut view = view(&[1, 2]);
+        let graph = Arc::make_mut(&mut view.graph);
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        graph.edges.push(Edge::new(1, 9, 1.0));
+        view.node_map_ids = [1, 3].into_iter().collect();
+
+        let report = run(&metadata, &view, &[], &[]);
+        let dangling: Vec<_> = report.of_kind(IssueKind::DanglingEdge).collect();
+        assert_eq!(dangling.len(), 1);
+        assert!(dangling[0].detail.contains('9'));
+        let mismatched: Vec<_> = report.of_kind(IssueKind::NodeMapMismatch).map(|i| i.subject.as_str()).collect();
+        assert_eq!(mismatched, vec!["2", "3"]);
+        assert_eq!((report.auto_fixable, report.needs_attention), (0, 3));
+    }
+}
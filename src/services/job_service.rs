@@ -0,0 +1,398 @@
+//! Long-running graph operations (PageRank, edge bundling) as jobs. The work runs on the
+//! blocking pool and checks a cancellation token between iterations. Handlers wait a
+//! short budget for the result and otherwise answer 202 with the job id, so clients can
+//! poll `GET /api/jobs/{id}` or give up with `DELETE /api/jobs/{id}`. Jobs that report
+//! progress show the latest report in their status while they run. Only `max_running`
+//! jobs run at once; past that new ones are refused until one finishes.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::config::JobSettings;
+
+/// Set once by whoever gives up on a job; the work checks it between iterations
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Where a running job has got to, as the job last reported it
+#[derive(Debug, Clone, Default)]
+pub struct JobProgress(Arc<Mutex<Option<Value>>>);
+
+impl JobProgress {
+    pub fn report<T: Serialize>(&self, progress: &T) {
+        if let Ok(value) = serde_json::to_value(progress) {
+            *self.0.lock().unwrap() = Some(value);
+        }
+    }
+
+    fn latest(&self) -> Option<Value> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Why a job failed, with the HTTP status an inline caller should answer with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobError {
+    #[serde(skip)]
+    pub status: u16,
+    pub error: String,
+}
+
+impl JobError {
+    pub fn new(status: u16, error: impl Into<String>) -> Self {
+        Self { status, error: error.into() }
+    }
+}
+
+/// Refusal to start a job while `max_running` are already running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobsFull;
+
+// Suggested wait before trying again once the running jobs are at the cap
+pub const JOBS_FULL_RETRY_SECS: u64 = 5;
+
+impl From<String> for JobError {
+    fn from(error: String) -> Self {
+        Self::new(500, error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Completed { result: Value },
+    Failed(JobError),
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub id: String,
+    pub kind: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Value>,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+struct Job {
+    kind: String,
+    // Pubkey of whoever started it, who may cancel it
+    submitter: Option<String>,
+    token: CancelToken,
+    progress: JobProgress,
+    state: JobState,
+    created_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    // For retention; wall-clock times above are only reported
+    finished: Option<Instant>,
+}
+
+impl Job {
+    fn status(&self, id: &str) -> JobStatus {
+        JobStatus {
+            id: id.to_string(),
+            kind: self.kind.clone(),
+            created_at: self.created_at,
+            finished_at: self.finished_at,
+            progress: self.progress.latest(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+// Cancels the job if the inline wait is dropped, i.e. the client went away
+struct CancelOnDrop(Option<CancelToken>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+pub struct JobService {
+    settings: JobSettings,
+    jobs: Mutex<HashMap<String, Job>>,
+    // Ids of jobs as they finish, for waiters
+    finished_tx: broadcast::Sender<String>,
+}
+
+impl JobService {
+    pub fn new(settings: JobSettings) -> Self {
+        let (finished_tx, _) = broadcast::channel(64);
+        Self { settings, jobs: Mutex::new(HashMap::new()), finished_tx }
+    }
+
+    /// How long handlers wait for a result before answering 202
+    pub fn inline_wait(&self) -> Duration {
+        Duration::from_millis(self.settings.inline_wait_ms)
+    }
+
+    /// Runs `work` on the blocking pool for `submitter` and returns the job id
+    pub fn submit<T, F>(self: &Arc<Self>, kind: &str, submitter: Option<String>, work: F) -> Result<String, JobsFull>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(&CancelToken) -> Result<T, JobError> + Send + 'static,
+    {
+        self.submit_async(kind, submitter, move |token| async move {
+            tokio::task::spawn_blocking(move || work(&token))
+                .await
+                .map_err(|e| JobError::from(format!("Job panicked: {}", e)))?
+        })
+    }
+
+    /// Like `submit`, for work that reports how far it has got as it goes
+    pub fn submit_with_progress<T, F>(self: &Arc<Self>, kind: &str, submitter: Option<String>, work: F) -> Result<String, JobsFull>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(&CancelToken, &JobProgress) -> Result<T, JobError> + Send + 'static,
+    {
+        let progress = JobProgress::default();
+        let reporter = progress.clone();
+        let id = self.submit(kind, submitter, move |token| work(token, &reporter))?;
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.progress = progress;
+        }
+        Ok(id)
+    }
+
+    /// Like `submit`, for work that awaits before its blocking part
+    pub fn submit_async<T, F, Fut>(self: &Arc<Self>, kind: &str, submitter: Option<String>, work: F) -> Result<String, JobsFull>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(CancelToken) -> Fut,
+        Fut: Future<Output = Result<T, JobError>> + Send + 'static,
+    {
+        let (id, token) = self.begin(kind, submitter, Instant::now())?;
+        let future = work(token);
+        let jobs = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move {
+            let outcome = future.await.and_then(|result| {
+                serde_json::to_value(result).map_err(|e| JobError::from(format!("Failed to serialize job result: {}", e)))
+            });
+            jobs.finish(&job_id, outcome, Instant::now());
+        });
+        Ok(id)
+    }
+
+    fn begin(&self, kind: &str, submitter: Option<String>, now: Instant) -> Result<(String, CancelToken), JobsFull> {
+        self.purge(now);
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.values().filter(|job| job.state == JobState::Running).count() >= self.settings.max_running.max(1) {
+            return Err(JobsFull);
+        }
+        // Over the cap, make room by forgetting the oldest finished jobs
+        let mut finished: Vec<(Instant, String)> = jobs.iter()
+            .filter_map(|(id, job)| job.finished.map(|at| (at, id.clone())))
+            .collect();
+        finished.sort();
+        let excess = (jobs.len() + 1).saturating_sub(self.settings.max_jobs.max(1));
+        for (_, id) in finished.into_iter().take(excess) {
+            jobs.remove(&id);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let token = CancelToken::default();
+        jobs.insert(id.clone(), Job {
+            kind: kind.to_string(),
+            submitter,
+            token: token.clone(),
+            progress: JobProgress::default(),
+            state: JobState::Running,
+            created_at: Utc::now(),
+            finished_at: None,
+            finished: None,
+        });
+        Ok((id, token))
+    }
+
+    fn finish(&self, id: &str, outcome: Result<Value, JobError>, now: Instant) {
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(id) else {
+                return;
+            };
+            // A cancelled job's late result is dropped
+            if job.state != JobState::Running {
+                return;
+            }
+            job.state = match outcome {
+                _ if job.token.is_cancelled() => JobState::Cancelled,
+                Ok(result) => JobState::Completed { result },
+                Err(e) => JobState::Failed(e),
+            };
+            job.finished_at = Some(Utc::now());
+            job.finished = Some(now);
+        }
+        let _ = self.finished_tx.send(id.to_string());
+    }
+
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(id).map(|job| job.status(id))
+    }
+
+    /// Who started the job, if they were signed in; the outer None is an unknown job
+    pub fn submitter(&self, id: &str) -> Option<Option<String>> {
+        self.jobs.lock().unwrap().get(id).map(|job| job.submitter.clone())
+    }
+
+    /// Cancels a running job; finished jobs are left as they are
+    pub fn cancel(&self, id: &str) -> Option<JobStatus> {
+        let status = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.get_mut(id)?;
+            if job.state == JobState::Running {
+                job.token.cancel();
+                job.state = JobState::Cancelled;
+                job.finished_at = Some(Utc::now());
+                job.finished = Some(Instant::now());
+            }
+            job.status(id)
+        };
+        let _ = self.finished_tx.send(id.to_string());
+        Some(status)
+    }
+
+    /// Waits for the job to finish, up to `timeout`, and returns its status
+    pub async fn wait(&self, id: &str, timeout: Duration) -> Option<JobStatus> {
+        let mut finished = self.finished_tx.subscribe();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let status = self.status(id)?;
+            if status.state != JobState::Running {
+                return Some(status);
+            }
+            match tokio::time::timeout_at(deadline, finished.recv()).await {
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                _ => return Some(status),
+            }
+        }
+    }
+
+    /// `wait` for the inline budget on behalf of an HTTP request. If the request is
+    /// dropped first, because the client disconnected, the job is cancelled; once the
+    /// budget runs out it carries on for polling.
+    pub async fn wait_inline(&self, id: &str) -> Option<JobStatus> {
+        let mut guard = CancelOnDrop(self.jobs.lock().unwrap().get(id).map(|job| job.token.clone()));
+        let status = self.wait(id, self.inline_wait()).await;
+        guard.0 = None;
+        status
+    }
+
+    /// Forgets jobs that finished more than the retention window ago
+    pub fn purge(&self, now: Instant) {
+        let retention = Duration::from_secs_f32(self.settings.retention_secs.max(0.0));
+        self.jobs.lock().unwrap()
+            .retain(|_, job| job.finished.is_none_or(|at| now.duration_since(at) < retention));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn service() -> Arc<JobService> {
+        Arc::new(JobService::new(JobSettings { inline_wait_ms: 50, retention_secs: 60.0, max_jobs: 10, max_running: 2 }))
+    }
+
+    #[actix_web::test]
+    async fn test_jobs_complete_and_cancel() {
+        let jobs = service();
+
+        let id = jobs.submit("sum", None, |_| Ok::<_, JobError>((1..=10).sum::<u32>())).unwrap();
+        let status = jobs.wait(&id, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(status.state, JobState::Completed { result: serde_json::json!(55) });
+        // Still there for anyone polling afterwards
+        assert_eq!(jobs.status(&id).unwrap().state, status.state);
+        assert!(jobs.status("nope").is_none());
+
+        // Runs until told to stop, counting iterations
+        let iterations = Arc::new(AtomicUsize::new(0));
+        let counted = iterations.clone();
+        let id = jobs.submit("spin", None, move |token| {
+            while !token.is_cancelled() {
+                counted.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Err::<(), _>(JobError::from("Cancelled".to_string()))
+        }).unwrap();
+        while iterations.load(Ordering::SeqCst) < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(jobs.status(&id).unwrap().state, JobState::Running);
+        assert_eq!(jobs.cancel(&id).unwrap().state, JobState::Cancelled);
+        // The work notices and stops; its late error doesn't replace the cancellation
+        let stopped_at = iterations.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(iterations.load(Ordering::SeqCst) <= stopped_at + 1);
+        assert_eq!(jobs.wait(&id, Duration::from_secs(1)).await.unwrap().state, JobState::Cancelled);
+
+        // An inline wait that outlives its budget leaves the job running...
+        let id = jobs.submit("spin", None, |token| {
+            while !token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok::<_, JobError>(())
+        }).unwrap();
+        assert_eq!(jobs.wait_inline(&id).await.unwrap().state, JobState::Running);
+        assert_eq!(jobs.status(&id).unwrap().state, JobState::Running);
+        // ...but one dropped early, as when the client disconnects, cancels it
+        let dropped = tokio::time::timeout(Duration::from_millis(5), jobs.wait_inline(&id)).await;
+        assert!(dropped.is_err());
+        assert_eq!(jobs.wait(&id, Duration::from_secs(1)).await.unwrap().state, JobState::Cancelled);
+
+        // Reported progress shows in the status while the job runs
+        let id = jobs.submit_with_progress("count", Some("alice".to_string()), |token, progress| {
+            progress.report(&serde_json::json!({ "completed": 1 }));
+            while !token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok::<_, JobError>(())
+        }).unwrap();
+        while jobs.status(&id).unwrap().progress.is_none() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(jobs.status(&id).unwrap().progress, Some(serde_json::json!({ "completed": 1 })));
+        assert_eq!(jobs.submitter(&id), Some(Some("alice".to_string())));
+
+        // At the running cap new jobs are refused until one finishes
+        let spin = |token: &CancelToken| {
+            while !token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok::<_, JobError>(())
+        };
+        let second = jobs.submit("spin", None, spin).unwrap();
+        assert_eq!(jobs.submit("spin", None, spin), Err(JobsFull));
+        jobs.cancel(&id);
+        jobs.cancel(&second);
+        let third = jobs.submit("spin", None, spin).unwrap();
+        jobs.cancel(&third);
+    }
+}
@@ -0,0 +1,73 @@
+use actix::Addr;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::actors::messages::{GetGenerations, GetGraphSnapshot};
+use crate::actors::GraphServiceActor;
+use crate::models::graph::GraphGenerations;
+use crate::utils::label_placement::{self, LabelPlacement};
+use crate::utils::projection::Projection;
+
+// Projections are keyed by the bits of their matrix
+type ProjectionKey = [[u32; 3]; 2];
+
+fn key(projection: &Projection) -> ProjectionKey {
+    projection.rows.map(|row| row.map(f32::to_bits))
+}
+
+#[derive(Debug, Clone)]
+pub struct LabelPlacementResult {
+    pub generations: GraphGenerations,
+    pub cached: bool,
+    pub labels: Arc<Vec<LabelPlacement>>,
+}
+
+struct CachedPlacements {
+    generations: GraphGenerations,
+    by_projection: HashMap<ProjectionKey, Arc<Vec<LabelPlacement>>>,
+}
+
+/// Label placements for stills and exports. Placements only change when the layout or
+/// the graph does, so they are cached per projection until either generation moves on.
+pub struct LabelPlacementService {
+    // Held across the computation, so concurrent requests wait and then hit the cache
+    cache: Mutex<Option<CachedPlacements>>,
+}
+
+impl Default for LabelPlacementService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LabelPlacementService {
+    pub fn new() -> Self {
+        Self { cache: Mutex::new(None) }
+    }
+
+    pub async fn placements(&self, projection: Projection, graph_addr: &Addr<GraphServiceActor>) -> Result<LabelPlacementResult, String> {
+        // Generations first, so placements are never cached under a generation newer than
+        // the snapshot they were computed from
+        let generations = graph_addr.send(GetGenerations).await.map_err(|e| e.to_string())??;
+        let mut cache = self.cache.lock().await;
+        if let Some(cached) = cache.as_ref().filter(|c| c.generations == generations) {
+            if let Some(labels) = cached.by_projection.get(&key(&projection)) {
+                return Ok(LabelPlacementResult { generations, cached: true, labels: labels.clone() });
+            }
+        }
+
+        let graph = graph_addr.send(GetGraphSnapshot).await.map_err(|e| e.to_string())??;
+        let labels = tokio::task::spawn_blocking(move || label_placement::place_labels(&graph.nodes, &graph.edges, &projection))
+            .await
+            .map_err(|e| format!("Label placement task failed: {}", e))?;
+        let labels = Arc::new(labels);
+
+        let cached = match cache.as_mut() {
+            Some(cached) if cached.generations == generations => cached,
+            _ => cache.insert(CachedPlacements { generations, by_projection: HashMap::new() }),
+        };
+        cached.by_projection.insert(key(&projection), labels.clone());
+        Ok(LabelPlacementResult { generations, cached: false, labels })
+    }
+}
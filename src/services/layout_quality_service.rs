@@ -0,0 +1,117 @@
+//! Scores the layout on a timer and logs the score, so layout quality can be followed
+//! over time in the event log. With `auto_nudge` on, each score also feeds a `Nudger`
+//! that steps the default room's spring and repulsion when the layout stops improving.
+
+use actix::Addr;
+use log::{debug, info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::actors::messages::{GetGenerations, GetGraphSnapshot, GetSettings, UpdateSimulationParams};
+use crate::actors::{GPUComputeActor, GraphServiceActor, SettingsActor};
+use crate::config::{LayoutQualitySettings, PhysicsOverrides};
+use crate::models::spatial_anchor::DEFAULT_ROOM;
+use crate::services::event_log::EventLog;
+use crate::services::room_physics::RoomPhysicsService;
+use crate::utils::layout_quality::{self, Knob, LayoutQuality, Nudger, NudgeAction, PhysicsKnobs};
+
+const MIN_INTERVAL_SECS: u64 = 5;
+
+pub struct LayoutQualityService {
+    settings: LayoutQualitySettings,
+    event_log: Arc<EventLog>,
+    room_physics: Arc<RoomPhysicsService>,
+    nudger: Mutex<Nudger>,
+    // Position generation of the last sample; an unmoved layout isn't scored again
+    last_sampled: Mutex<Option<u64>>,
+}
+
+impl LayoutQualityService {
+    pub fn new(settings: LayoutQualitySettings, event_log: Arc<EventLog>, room_physics: Arc<RoomPhysicsService>) -> Self {
+        let nudger = Mutex::new(Nudger::new(settings.clone()));
+        Self { settings, event_log, room_physics, nudger, last_sampled: Mutex::new(None) }
+    }
+
+    pub fn start(
+        self: Arc<Self>,
+        graph_addr: Addr<GraphServiceActor>,
+        settings_addr: Addr<SettingsActor>,
+        gpu_addr: Option<Addr<GPUComputeActor>>,
+    ) {
+        let interval = Duration::from_secs(self.settings.interval_secs.max(MIN_INTERVAL_SECS));
+        info!("Scoring layout quality every {:?} (auto nudge {})", interval, self.settings.auto_nudge);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.sample(&graph_addr).await {
+                    Ok(Some(quality)) if self.settings.auto_nudge => {
+                        if let Err(e) = self.nudge(quality.score, &settings_addr, gpu_addr.as_ref()).await {
+                            warn!("Layout nudge failed: {}", e);
+                        }
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => debug!("Layout unchanged since the last quality sample"),
+                    Err(e) => warn!("Layout quality sample failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Scores the current layout and logs it. None if the layout hasn't moved since the
+    /// last sample or has too few nodes to score.
+    pub async fn sample(&self, graph_addr: &Addr<GraphServiceActor>) -> Result<Option<LayoutQuality>, String> {
+        let generations = graph_addr.send(GetGenerations).await.map_err(|e| e.to_string())??;
+        if *self.last_sampled.lock().unwrap() == Some(generations.position_generation) {
+            return Ok(None);
+        }
+        let snapshot = graph_addr.send(GetGraphSnapshot).await.map_err(|e| e.to_string())??;
+        if snapshot.nodes.len() < 2 {
+            return Ok(None);
+        }
+        let quality = layout_quality::evaluate(&snapshot.nodes, &snapshot.edges);
+        *self.last_sampled.lock().unwrap() = Some(generations.position_generation);
+        self.event_log.record("layout", "layout_quality", serde_json::json!({
+            "positionGeneration": generations.position_generation,
+            "quality": quality,
+        }));
+        Ok(Some(quality))
+    }
+
+    /// Feeds `score` to the nudger and applies whatever it asks for to the default room
+    async fn nudge(
+        &self,
+        score: f32,
+        settings_addr: &Addr<SettingsActor>,
+        gpu_addr: Option<&Addr<GPUComputeActor>>,
+    ) -> Result<(), String> {
+        let settings = settings_addr.send(GetSettings).await.map_err(|e| e.to_string())??;
+        let global = settings.visualisation.physics;
+        let effective = self.room_physics.effective(DEFAULT_ROOM, &global);
+        let current = PhysicsKnobs {
+            spring_strength: effective.spring_strength,
+            repulsion_strength: effective.repulsion_strength,
+        };
+        let Some(action) = self.nudger.lock().unwrap().observe(score, current) else {
+            return Ok(());
+        };
+
+        if let Some((knob, value)) = action.change() {
+            let patch = match knob {
+                Knob::SpringStrength => PhysicsOverrides { spring_strength: Some(value), ..Default::default() },
+                Knob::RepulsionStrength => PhysicsOverrides { repulsion_strength: Some(value), ..Default::default() },
+            };
+            self.room_physics.patch(DEFAULT_ROOM, &patch);
+            if let Some(gpu_addr) = gpu_addr {
+                gpu_addr.do_send(UpdateSimulationParams { params: self.room_physics.params(DEFAULT_ROOM, &global) });
+            }
+        }
+        match action {
+            NudgeAction::Try { knob, from, to, .. } => info!("Layout plateaued; trying {:?} {} -> {}", knob, from, to),
+            NudgeAction::Keep { knob, value, .. } => info!("Keeping {:?} at {}", knob, value),
+            NudgeAction::Revert { knob, to, .. } => info!("Reverting {:?} to {}", knob, to),
+        }
+        self.event_log.record("layout", "physics_nudge", serde_json::json!({ "room": DEFAULT_ROOM, "nudge": action }));
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use actix::Addr;
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::actors::messages::{GetLayout, WarmStartLayout};
+use crate::actors::GraphServiceActor;
+use crate::config::LayoutSnapshotSettings;
+use crate::models::layout::{LayoutFile, LAYOUT_FORMAT_VERSION};
+use crate::utils::json_store::write_json_atomic;
+
+const SNAPSHOT_DIR: &str = "/app/data/layout_snapshots";
+const MIN_INTERVAL_SECS: u64 = 10;
+// layout-20260101T120000123Z: UTC timestamp to the millisecond, so names sort by age
+const NAME_PATTERN: &str = r"^layout-(\d{8}T\d{6})(\d{3})Z$";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+/// Periodic layout snapshots with retention, plus restore into the live graph
+pub struct LayoutSnapshotService {
+    settings: LayoutSnapshotSettings,
+    dir: PathBuf,
+    // Generation of the last snapshot written; None forces the next one
+    last_generation: Mutex<Option<u64>>,
+}
+
+impl LayoutSnapshotService {
+    pub fn new(settings: LayoutSnapshotSettings) -> Self {
+        Self::with_dir(settings, PathBuf::from(SNAPSHOT_DIR))
+    }
+
+    pub fn with_dir(settings: LayoutSnapshotSettings, dir: PathBuf) -> Self {
+        Self {
+            settings,
+            dir,
+            last_generation: Mutex::new(None),
+        }
+    }
+
+    pub fn start(self: Arc<Self>, graph_addr: Addr<GraphServiceActor>) {
+        if !self.settings.enabled {
+            info!("Layout snapshots disabled");
+            return;
+        }
+        let interval = Duration::from_secs(self.settings.interval_secs.max(MIN_INTERVAL_SECS));
+        info!("Starting layout snapshots (every {:?}, keeping {})", interval, self.settings.retention);
+        self.spawn_loop(graph_addr, interval);
+    }
+
+    fn spawn_loop(self: Arc<Self>, graph_addr: Addr<GraphServiceActor>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick fires immediately; the initial layout isn't worth keeping
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match self.snapshot(&graph_addr).await {
+                    Ok(Some(info)) => debug!("Layout snapshot {} written", info.name),
+                    Ok(None) => debug!("Layout unchanged since last snapshot; skipped"),
+                    Err(e) => warn!("Layout snapshot failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Writes a snapshot unless the layout hasn't changed since the last one
+    pub async fn snapshot(&self, graph_addr: &Addr<GraphServiceActor>) -> Result<Option<SnapshotInfo>, String> {
+        let mut last_generation = self.last_generation.lock().await;
+        let (generation, nodes) = graph_addr.send(GetLayout).await.map_err(|e| e.to_string())??;
+        if nodes.is_empty() || *last_generation == Some(generation) {
+            return Ok(None);
+        }
+
+        let created_at = Utc::now();
+        let name = format!("layout-{}Z", created_at.format("%Y%m%dT%H%M%S%3f"));
+        let file = LayoutFile { version: LAYOUT_FORMAT_VERSION, created_at, generation, nodes };
+        let path = self.path_for(&name);
+        write_json_atomic(&path, &file)?;
+        *last_generation = Some(generation);
+
+        let pruned = self.prune().await?;
+        if pruned > 0 {
+            debug!("Pruned {} old layout snapshots", pruned);
+        }
+        let size_bytes = tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+        Ok(Some(SnapshotInfo { name, created_at, size_bytes }))
+    }
+
+    /// Snapshots on disk, newest first
+    pub async fn list(&self) -> Result<Vec<SnapshotInfo>, String> {
+        let pattern = Regex::new(NAME_PATTERN).unwrap();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {:?}: {}", self.dir, e)),
+        };
+
+        let mut snapshots = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = match file_name.strip_suffix(".json") {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let created_at = match parse_created_at(&pattern, &name) {
+                Some(created_at) => created_at,
+                None => continue,
+            };
+            let size_bytes = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+            snapshots.push(SnapshotInfo { name, created_at, size_bytes });
+        }
+        snapshots.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(snapshots)
+    }
+
+    /// Reads a snapshot from disk
+    pub async fn load(&self, name: &str) -> Result<LayoutFile, String> {
+        // Only names we generated, which also rules out path traversal
+        if !Regex::new(NAME_PATTERN).unwrap().is_match(name) {
+            return Err(format!("Unknown snapshot: {}", name));
+        }
+        let content = tokio::fs::read_to_string(self.path_for(name)).await
+            .map_err(|_| format!("Unknown snapshot: {}", name))?;
+        let file: LayoutFile = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse snapshot {}: {}", name, e))?;
+        if file.version != LAYOUT_FORMAT_VERSION {
+            return Err(format!("Snapshot {} has unsupported format version {}", name, file.version));
+        }
+        Ok(file)
+    }
+
+    /// Rewrites a snapshot without the nodes `keep` rejects. Returns how many were dropped.
+    pub async fn retain_nodes<F>(&self, name: &str, keep: F) -> Result<usize, String>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut file = self.load(name).await?;
+        let before = file.nodes.len();
+        file.nodes.retain(|n| keep(&n.metadata_id));
+        let dropped = before - file.nodes.len();
+        if dropped > 0 {
+            write_json_atomic(&self.path_for(name), &file)?;
+        }
+        Ok(dropped)
+    }
+
+    /// Loads a snapshot's positions into the live graph. Returns how many nodes were placed.
+    pub async fn restore(&self, name: &str, graph_addr: &Addr<GraphServiceActor>) -> Result<usize, String> {
+        let file = self.load(name).await?;
+        let placed = graph_addr.send(WarmStartLayout { nodes: file.nodes }).await.map_err(|e| e.to_string())??;
+        // The restored layout is a change in its own right
+        *self.last_generation.lock().await = None;
+        info!("Restored layout snapshot {} ({} nodes placed)", name, placed);
+        Ok(placed)
+    }
+
+    /// Deletes snapshots beyond the retention count. Returns how many were removed.
+    pub async fn prune(&self) -> Result<usize, String> {
+        let snapshots = self.list().await?;
+        let mut removed = 0;
+        for old in snapshots.iter().skip(self.settings.retention.max(1)) {
+            match tokio::fs::remove_file(self.path_for(&old.name)).await {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("Failed to remove old layout snapshot {}: {}", old.name, e),
+            }
+        }
+        Ok(removed)
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+fn parse_created_at(pattern: &Regex, name: &str) -> Option<chrono::DateTime<Utc>> {
+    let caps = pattern.captures(name)?;
+    let seconds = NaiveDateTime::parse_from_str(&caps[1], "%Y%m%dT%H%M%S").ok()?;
+    let millis: i64 = caps[2].parse().ok()?;
+    Some(Utc.from_utc_datetime(&seconds) + chrono::Duration::milliseconds(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::{BuildGraphFromMetadata, GetNodeMap, StopSimulation, UpdateNodePosition};
+    use crate::actors::ClientManagerActor;
+    use crate::models::metadata::{Metadata, MetadataStore};
+    use actix::prelude::*;
+
+    fn settings(retention: usize) -> LayoutSnapshotSettings {
+        LayoutSnapshotSettings { enabled: true, interval_secs: 60, retention }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("layout-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    // Graph with two nodes and physics stopped, so only explicit moves change the layout
+    async fn graph() -> (Addr<GraphServiceActor>, u32) {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager, None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), Metadata { file_name: "a.md".to_string(), ..Default::default() });
+        store.insert("b.md".to_string(), Metadata { file_name: "b.md".to_string(), ..Default::default() });
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let node_map = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let a = *node_map.iter().find(|(_, n)| n.metadata_id == "a").unwrap().0;
+        (graph, a)
+    }
+
+    async fn move_node(graph: &Addr<GraphServiceActor>, node_id: u32, x: f32) {
+        graph.send(UpdateNodePosition {
+            node_id,
+            position: glam::Vec3::new(x, 0.0, 0.0),
+            velocity: glam::Vec3::ZERO,
+            edited_by: None,
+        }).await.unwrap().unwrap();
+    }
+
+    async fn position_of(graph: &Addr<GraphServiceActor>, node_id: u32) -> f32 {
+        graph.send(GetNodeMap).await.unwrap().unwrap()[&node_id].data.position.x
+    }
+
+    #[actix_web::test]
+    async fn test_skip_when_unchanged() {
+        let (graph, a) = graph().await;
+        let service = LayoutSnapshotService::with_dir(settings(5), temp_dir());
+
+        assert!(service.snapshot(&graph).await.unwrap().is_some());
+        assert!(service.snapshot(&graph).await.unwrap().is_none());
+
+        move_node(&graph, a, 42.0).await;
+        assert!(service.snapshot(&graph).await.unwrap().is_some());
+        assert_eq!(service.list().await.unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_restore_places_saved_positions() {
+        let (graph, a) = graph().await;
+        let service = LayoutSnapshotService::with_dir(settings(5), temp_dir());
+
+        move_node(&graph, a, 7.0).await;
+        let saved = service.snapshot(&graph).await.unwrap().unwrap();
+        move_node(&graph, a, -3.0).await;
+
+        assert_eq!(service.restore(&saved.name, &graph).await.unwrap(), 2);
+        assert_eq!(position_of(&graph, a).await, 7.0);
+        // Restoring counts as a change, so the next tick snapshots again
+        assert!(service.snapshot(&graph).await.unwrap().is_some());
+
+        assert!(service.restore("../../etc/passwd", &graph).await.is_err());
+        assert!(service.restore("layout-20000101T000000000Z", &graph).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_prunes_beyond_retention() {
+        let (graph, a) = graph().await;
+        let service = LayoutSnapshotService::with_dir(settings(2), temp_dir());
+
+        let mut names = Vec::new();
+        for x in 1..=3 {
+            move_node(&graph, a, x as f32).await;
+            names.push(service.snapshot(&graph).await.unwrap().unwrap().name);
+            // Names are millisecond timestamps
+            actix::clock::sleep(Duration::from_millis(5)).await;
+        }
+
+        let kept: Vec<String> = service.list().await.unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(kept, vec![names[2].clone(), names[1].clone()]);
+    }
+
+    #[actix_web::test]
+    async fn test_scheduled_snapshots() {
+        let (graph, a) = graph().await;
+        let service = Arc::new(LayoutSnapshotService::with_dir(settings(10), temp_dir()));
+        service.clone().spawn_loop(graph.clone(), Duration::from_millis(50));
+
+        // Several ticks with an unchanged layout produce a single snapshot
+        actix::clock::sleep(Duration::from_millis(230)).await;
+        assert_eq!(service.list().await.unwrap().len(), 1);
+
+        move_node(&graph, a, 9.0).await;
+        actix::clock::sleep(Duration::from_millis(120)).await;
+        assert_eq!(service.list().await.unwrap().len(), 2);
+    }
+}
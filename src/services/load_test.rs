@@ -0,0 +1,432 @@
+//! Dev-only load test: spins up in-process simulated clients that register with the
+//! client manager like real sockets do, so broadcast fan-out can be measured locally.
+//! Only compiled with the `loadtest` feature.
+
+use actix::prelude::*;
+use glam::Vec3;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::actors::client_manager_actor::ClientHandle;
+use crate::actors::messages::{
+    CloseConnection, EditAttribution, GetGraphData, RegisterSimulatedClient, RelayPose,
+    SendToClientBinary, SendToClientText, SetClientRoom, UnregisterClient, UpdateNodePosition,
+};
+use crate::actors::{ClientManagerActor, GraphServiceActor};
+use crate::config::feature_access::Role;
+use crate::utils::auth::Identity;
+use crate::utils::binary_protocol;
+use crate::utils::socket_flow_messages::{Pose, PoseUpdate};
+use crate::utils::update_priority::{self, FrameScheduler, SOURCE_FRAME_RATE};
+
+pub const MAX_CLIENTS: usize = 1000;
+pub const MAX_DURATION_SECS: u64 = 300;
+// Simulated clients sit in their own room so poses and undo history stay out of real ones
+const LOADTEST_ROOM: &str = "loadtest";
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestParams {
+    pub clients: usize,
+    #[serde(rename = "duration_s", alias = "durationS")]
+    pub duration_s: u64,
+    // Frames per second each client asks for, as a socket's adaptive rate would
+    #[serde(rename = "update_rate", alias = "updateRate")]
+    pub update_rate: u32,
+    // Node drags and pose messages each client sends per second; 0 sends none
+    #[serde(default)]
+    pub position_rate: f32,
+    #[serde(default)]
+    pub pose_rate: f32,
+}
+
+impl LoadTestParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.clients == 0 || self.clients > MAX_CLIENTS {
+            return Err(format!("clients must be between 1 and {}", MAX_CLIENTS));
+        }
+        if self.duration_s == 0 || self.duration_s > MAX_DURATION_SECS {
+            return Err(format!("duration_s must be between 1 and {}", MAX_DURATION_SECS));
+        }
+        if self.update_rate == 0 || self.update_rate > SOURCE_FRAME_RATE as u32 {
+            return Err(format!("update_rate must be between 1 and {}", SOURCE_FRAME_RATE));
+        }
+        for (name, rate) in [("positionRate", self.position_rate), ("poseRate", self.pose_rate)] {
+            if !rate.is_finite() || !(0.0..=SOURCE_FRAME_RATE).contains(&rate) {
+                return Err(format!("{} must be between 0 and {}", name, SOURCE_FRAME_RATE));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientStats {
+    pub client_id: usize,
+    pub frames_received: u64,
+    // Frames that reached this client more than one frame interval after the first
+    // client got them; a real socket would already have the next frame queued
+    pub frames_dropped: u64,
+    pub nodes_received: u64,
+    pub text_messages: u64,
+    pub position_updates_sent: u64,
+    pub position_updates_rejected: u64,
+    pub poses_sent: u64,
+    #[serde(skip)]
+    pub latencies_ms: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestSummary {
+    pub clients: usize,
+    pub duration_s: u64,
+    pub update_rate: u32,
+    pub distinct_frames: usize,
+    pub frames_received: u64,
+    pub frames_dropped: u64,
+    pub nodes_received: u64,
+    pub text_messages: u64,
+    pub position_updates_sent: u64,
+    pub position_updates_rejected: u64,
+    pub poses_sent: u64,
+    // Delay behind the first client to receive the same frame, i.e. fan-out lag
+    pub latency_ms: LatencySummary,
+    pub per_client: Vec<ClientStats>,
+}
+
+/// Nearest-rank percentiles over the collected latencies
+pub fn summarize_latencies(samples: &mut [f32]) -> LatencySummary {
+    if samples.is_empty() {
+        return LatencySummary::default();
+    }
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let at = |p: f32| samples[((p * samples.len() as f32).ceil() as usize).clamp(1, samples.len()) - 1];
+    LatencySummary { p50: at(0.50), p95: at(0.95), p99: at(0.99), max: samples[samples.len() - 1] }
+}
+
+// First arrival of each frame across all clients, keyed by a hash of its bytes
+type FirstArrivals = Arc<Mutex<HashMap<u64, Instant>>>;
+
+fn frame_key(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct SimulatedClient {
+    client_id: usize,
+    update_rate: u32,
+    scheduler: FrameScheduler,
+    first_arrivals: FirstArrivals,
+    // Node positions at the start; drags jitter around these rather than teleporting
+    nodes: Arc<Vec<(u32, Vec3)>>,
+    graph_addr: Addr<GraphServiceActor>,
+    client_manager: Addr<ClientManagerActor>,
+    stats: ClientStats,
+}
+
+impl SimulatedClient {
+    fn record_arrival(&mut self, data: &[u8]) {
+        let now = Instant::now();
+        let first = *self.first_arrivals.lock().unwrap().entry(frame_key(data)).or_insert(now);
+        let lag_ms = now.duration_since(first).as_secs_f32() * 1000.0;
+        self.stats.frames_received += 1;
+        self.stats.latencies_ms.push(lag_ms);
+        if lag_ms > 1000.0 / self.update_rate as f32 {
+            self.stats.frames_dropped += 1;
+        }
+    }
+
+    fn send_position_update(&mut self, ctx: &mut Context<Self>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let (node_id, origin) = self.nodes[rand::random::<usize>() % self.nodes.len()];
+        let nudge = Vec3::new(rand::random::<f32>() - 0.5, rand::random::<f32>() - 0.5, rand::random::<f32>() - 0.5);
+        self.stats.position_updates_sent += 1;
+        let update = UpdateNodePosition {
+            node_id,
+            position: origin + nudge,
+            velocity: Vec3::ZERO,
+            edited_by: Some(EditAttribution { client_id: self.client_id, room: LOADTEST_ROOM.to_string() }),
+        };
+        // Rejections are expected when another simulated client holds the node
+        self.graph_addr.send(update)
+            .into_actor(self)
+            .map(|result, actor, _ctx| {
+                if !matches!(result, Ok(Ok(()))) {
+                    actor.stats.position_updates_rejected += 1;
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn send_pose(&mut self) {
+        let t = self.stats.poses_sent as f32 * 0.1;
+        let update = PoseUpdate {
+            head: Pose { position: [t.sin(), 1.6, t.cos()], orientation: [0.0, 0.0, 0.0, 1.0] },
+            cursor: None,
+            gaze_node: None,
+        };
+        self.stats.poses_sent += 1;
+        self.client_manager.do_send(RelayPose { client_id: self.client_id, update });
+    }
+}
+
+impl Actor for SimulatedClient {
+    type Context = Context<Self>;
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct BeginLoad {
+    client_id: usize,
+    position_rate: f32,
+    pose_rate: f32,
+}
+
+impl Handler<BeginLoad> for SimulatedClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: BeginLoad, ctx: &mut Self::Context) {
+        self.client_id = msg.client_id;
+        self.stats.client_id = msg.client_id;
+        if msg.position_rate > 0.0 {
+            ctx.run_interval(Duration::from_secs_f32(1.0 / msg.position_rate), |actor, ctx| {
+                actor.send_position_update(ctx);
+            });
+        }
+        if msg.pose_rate > 0.0 {
+            ctx.run_interval(Duration::from_secs_f32(1.0 / msg.pose_rate), |actor, _ctx| {
+                actor.send_pose();
+            });
+        }
+    }
+}
+
+// Same prioritised throttling a real socket applies before writing a frame
+impl Handler<SendToClientBinary> for SimulatedClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendToClientBinary, _ctx: &mut Self::Context) {
+        self.record_arrival(&msg.data);
+        let buckets = update_priority::bucket_count(SOURCE_FRAME_RATE, self.update_rate as f32);
+        let positions = match binary_protocol::decode_node_data(&msg.data) {
+            Ok(positions) => positions,
+            Err(_) => return,
+        };
+        let delivered = if msg.keyframe || buckets == 1 {
+            positions.len()
+        } else {
+            self.scheduler.select(positions, |id| msg.priority.contains(&id) || msg.lod.contains(&id), buckets).len()
+        };
+        self.stats.nodes_received += delivered as u64;
+    }
+}
+
+impl Handler<SendToClientText> for SimulatedClient {
+    type Result = ();
+
+    fn handle(&mut self, _msg: SendToClientText, _ctx: &mut Self::Context) {
+        self.stats.text_messages += 1;
+    }
+}
+
+impl Handler<CloseConnection> for SimulatedClient {
+    type Result = ();
+
+    fn handle(&mut self, _msg: CloseConnection, ctx: &mut Self::Context) {
+        ctx.stop();
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "ClientStats")]
+struct FinishLoad;
+
+impl Handler<FinishLoad> for SimulatedClient {
+    type Result = MessageResult<FinishLoad>;
+
+    fn handle(&mut self, _msg: FinishLoad, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+        MessageResult(std::mem::take(&mut self.stats))
+    }
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+// Held while a test runs, so one abandoned midway still lets the next start
+struct RunningGuard(());
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+// The simulated clients still registered. Should the test be dropped before it reports,
+// because the request was, they're unregistered and stopped rather than left running.
+struct SimulatedClients {
+    client_manager: Addr<ClientManagerActor>,
+    clients: Vec<(usize, Addr<SimulatedClient>)>,
+}
+
+impl Drop for SimulatedClients {
+    fn drop(&mut self) {
+        if !self.clients.is_empty() {
+            warn!("Load test abandoned; stopping {} simulated clients", self.clients.len());
+        }
+        for (client_id, client) in self.clients.drain(..) {
+            self.client_manager.do_send(UnregisterClient { client_id });
+            client.do_send(CloseConnection { reason: "Load test abandoned".to_string() });
+        }
+    }
+}
+
+/// Runs one load test to completion. Only one can run at a time.
+pub async fn run_load_test(
+    params: LoadTestParams,
+    client_manager: Addr<ClientManagerActor>,
+    graph_addr: Addr<GraphServiceActor>,
+) -> Result<LoadTestSummary, String> {
+    params.validate()?;
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("A load test is already running".to_string());
+    }
+    let _running = RunningGuard(());
+    run(params, client_manager, graph_addr).await
+}
+
+async fn run(
+    params: LoadTestParams,
+    client_manager: Addr<ClientManagerActor>,
+    graph_addr: Addr<GraphServiceActor>,
+) -> Result<LoadTestSummary, String> {
+    let graph = graph_addr.send(GetGraphData).await.map_err(|e| e.to_string())??;
+    let nodes = Arc::new(graph.nodes.iter().map(|n| (n.id, Vec3::from(n.data.position))).collect::<Vec<_>>());
+    let first_arrivals: FirstArrivals = Arc::new(Mutex::new(HashMap::new()));
+    info!("Starting load test: {} clients for {}s at {} fps", params.clients, params.duration_s, params.update_rate);
+
+    let mut clients = SimulatedClients { client_manager: client_manager.clone(), clients: Vec::with_capacity(params.clients) };
+    for _ in 0..params.clients {
+        // Timers only start once the manager has handed out the client's id
+        let client = SimulatedClient {
+            client_id: 0,
+            update_rate: params.update_rate,
+            scheduler: FrameScheduler::new(),
+            first_arrivals: first_arrivals.clone(),
+            nodes: nodes.clone(),
+            graph_addr: graph_addr.clone(),
+            client_manager: client_manager.clone(),
+            stats: ClientStats::default(),
+        }.start();
+        let handle = ClientHandle {
+            text: client.clone().recipient(),
+            binary: client.clone().recipient(),
+            close: client.clone().recipient(),
+        };
+        let identity = Identity { pubkey: None, role: Role::Editor };
+        let client_id = client_manager.send(RegisterSimulatedClient { handle, identity }).await
+            .map_err(|e| e.to_string())??;
+        client_manager.send(SetClientRoom { client_id, room: LOADTEST_ROOM.to_string() }).await
+            .map_err(|e| e.to_string())??;
+        client.do_send(BeginLoad { client_id, position_rate: params.position_rate, pose_rate: params.pose_rate });
+        clients.clients.push((client_id, client));
+    }
+
+    tokio::time::sleep(Duration::from_secs(params.duration_s)).await;
+
+    let finished = std::mem::take(&mut clients.clients);
+    let mut per_client = Vec::with_capacity(finished.len());
+    for (client_id, client) in finished {
+        client_manager.do_send(UnregisterClient { client_id });
+        match client.send(FinishLoad).await {
+            Ok(stats) => per_client.push(stats),
+            Err(e) => warn!("Simulated client {} did not report: {}", client_id, e),
+        }
+    }
+
+    let distinct_frames = first_arrivals.lock().unwrap().len();
+    let mut latencies: Vec<f32> = per_client.iter().flat_map(|s| s.latencies_ms.iter().copied()).collect();
+    let sum = |f: fn(&ClientStats) -> u64| per_client.iter().map(f).sum::<u64>();
+    let summary = LoadTestSummary {
+        clients: params.clients,
+        duration_s: params.duration_s,
+        update_rate: params.update_rate,
+        distinct_frames,
+        frames_received: sum(|s| s.frames_received),
+        frames_dropped: sum(|s| s.frames_dropped),
+        nodes_received: sum(|s| s.nodes_received),
+        text_messages: sum(|s| s.text_messages),
+        position_updates_sent: sum(|s| s.position_updates_sent),
+        position_updates_rejected: sum(|s| s.position_updates_rejected),
+        poses_sent: sum(|s| s.poses_sent),
+        latency_ms: summarize_latencies(&mut latencies),
+        per_client,
+    };
+    info!("Load test finished: {} frames, {} dropped, p95 lag {:.2}ms",
+        summary.frames_received, summary.frames_dropped, summary.latency_ms.p95);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::GetClientCount;
+
+    fn params() -> LoadTestParams {
+        LoadTestParams { clients: 10, duration_s: 5, update_rate: 30, position_rate: 0.0, pose_rate: 2.0 }
+    }
+
+    #[test]
+    fn test_params_are_bounded() {
+        assert!(params().validate().is_ok());
+        assert!(LoadTestParams { clients: 0, ..params() }.validate().is_err());
+        assert!(LoadTestParams { clients: MAX_CLIENTS + 1, ..params() }.validate().is_err());
+        assert!(LoadTestParams { duration_s: MAX_DURATION_SECS + 1, ..params() }.validate().is_err());
+        assert!(LoadTestParams { update_rate: 0, ..params() }.validate().is_err());
+        assert!(LoadTestParams { pose_rate: f32::NAN, ..params() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut samples: Vec<f32> = (1..=100).rev().map(|v| v as f32).collect();
+        let summary = summarize_latencies(&mut samples);
+        assert_eq!(summary, LatencySummary { p50: 50.0, p95: 95.0, p99: 99.0, max: 100.0 });
+        assert_eq!(summarize_latencies(&mut []), LatencySummary::default());
+    }
+
+    #[actix::test]
+    async fn test_abandoned_run_cleans_up() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph_addr = GraphServiceActor::new(client_manager.clone(), None).start();
+        let params = LoadTestParams { clients: 3, duration_s: MAX_DURATION_SECS, ..params() };
+
+        // Dropped while the clients are running, as when the request goes away
+        let abandoned = tokio::time::timeout(Duration::from_millis(200), run_load_test(params, client_manager.clone(), graph_addr)).await;
+        assert!(abandoned.is_err());
+        assert!(!is_running());
+        // The unregistrations are queued ahead of this on the manager's mailbox
+        assert_eq!(client_manager.send(GetClientCount).await.unwrap(), Ok(0));
+    }
+}
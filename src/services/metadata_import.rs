@@ -0,0 +1,364 @@
+//! Bulk replacement of the metadata store. The ingestion pipeline posts a whole
+//! metadata.json; every entry is checked before anything is applied, and a dry run
+//! reports what the import would change without touching the graph. Keys an entry
+//! has beyond the stored fields are checked against the metadata schema.
+
+use actix::Addr;
+use chrono::{DateTime, Duration, Utc};
+use flate2::read::GzDecoder;
+use serde::de::{Deserializer as _, MapAccess, Visitor};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::actors::messages::{GetGenerations, UpdateMetadata};
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::models::metadata::{Metadata, MetadataStore};
+use crate::models::metadata_schema::MetadataSchema;
+use crate::services::file_service::FileService;
+use crate::services::graph_service::{self, GraphRebuilds};
+
+// Bodies are spooled to disk, so this bounds disk use rather than memory
+pub const MAX_IMPORT_BYTES: u64 = 512 * 1024 * 1024;
+// A gzipped import may inflate to this much and no more
+pub const MAX_DECOMPRESSED_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+pub const MAX_FILE_SIZE: usize = 100 * 1024 * 1024;
+pub const MAX_NODE_SIZE: f64 = 1000.0;
+// Clock skew allowed on dates before they count as being in the future
+const FUTURE_SKEW_HOURS: i64 = 24;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportIssue {
+    pub file: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl ImportIssue {
+    fn new(file: &str, field: &str, message: impl Into<String>) -> Self {
+        Self { file: file.to_string(), field: field.to_string(), message: message.into() }
+    }
+}
+
+/// What an import changes: files by name, and the topic links between them as
+/// metadata id pairs, smaller id first
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDiff {
+    pub added_files: Vec<String>,
+    pub updated_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    pub added_links: Vec<(String, String)>,
+    pub removed_links: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub files: usize,
+    pub issues: Vec<ImportIssue>,
+    pub diff: ImportDiff,
+    // Graph generation after the import; absent unless it was applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+}
+
+/// Held for the length of one import; a second import while one is held is refused
+pub struct ImportGuard(());
+
+impl Drop for ImportGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn try_begin() -> Option<ImportGuard> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    Some(ImportGuard(()))
+}
+
+/// Parses a metadata store one entry at a time, plain or gzipped. Entries that don't
+/// deserialize are reported and left out; only malformed JSON fails the whole parse.
+pub fn parse_store<R: Read>(reader: R, schema: &MetadataSchema) -> Result<(MetadataStore, Vec<ImportIssue>), String> {
+    parse_store_within(reader, schema, MAX_DECOMPRESSED_BYTES)
+}
+
+fn parse_store_within<R: Read>(reader: R, schema: &MetadataSchema, max_decompressed: u64) -> Result<(MetadataStore, Vec<ImportIssue>), String> {
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader.fill_buf().map_err(|e| e.to_string())?.starts_with(&GZIP_MAGIC);
+    if !gzipped {
+        return parse_json(reader, schema);
+    }
+    // One byte past the limit tells a body that inflates too far from one that fits exactly
+    let mut inflated = GzDecoder::new(reader).take(max_decompressed + 1);
+    let parsed = parse_json(BufReader::new(&mut inflated), schema);
+    if inflated.limit() == 0 {
+        return Err(format!("Import inflates to more than {} bytes", max_decompressed));
+    }
+    parsed
+}
+
+fn parse_json<R: Read>(reader: R, schema: &MetadataSchema) -> Result<(MetadataStore, Vec<ImportIssue>), String> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    // The fields a stored entry has; serde checks those
+    let stored_fields: HashSet<String> = match serde_json::to_value(Metadata::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.into_iter().map(|(key, _)| key).collect(),
+        _ => HashSet::new(),
+    };
+    let parsed = (&mut deserializer).deserialize_map(StoreVisitor { schema, stored_fields })
+        .map_err(|e| format!("Invalid metadata JSON: {}", e))?;
+    deserializer.end().map_err(|e| format!("Invalid metadata JSON: {}", e))?;
+    Ok(parsed)
+}
+
+struct StoreVisitor<'a> {
+    schema: &'a MetadataSchema,
+    stored_fields: HashSet<String>,
+}
+
+impl StoreVisitor<'_> {
+    // Schema violations among the keys an entry has beyond the stored fields
+    fn check_extra_keys(&self, file: &str, entry: &serde_json::Value, issues: &mut Vec<ImportIssue>) {
+        let Some(fields) = entry.as_object() else {
+            return;
+        };
+        for (key, value) in fields.iter().filter(|(key, _)| !self.stored_fields.contains(*key)) {
+            let value = match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            if let Err(violation) = self.schema.check(key, &value) {
+                issues.push(ImportIssue::new(file, key, format!("{}, got {:?}", violation.reason, violation.value)));
+            }
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for StoreVisitor<'_> {
+    type Value = (MetadataStore, Vec<ImportIssue>);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of file names to metadata")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut store = MetadataStore::new();
+        let mut issues = Vec::new();
+        while let Some(file) = map.next_key::<String>()? {
+            let value: serde_json::Value = map.next_value()?;
+            self.check_extra_keys(&file, &value, &mut issues);
+            match serde_json::from_value::<Metadata>(value) {
+                Ok(metadata) => {
+                    if store.insert(file.clone(), metadata).is_some() {
+                        issues.push(ImportIssue::new(&file, "fileName", "appears more than once"));
+                    }
+                }
+                Err(e) => issues.push(ImportIssue::new(&file, "entry", e.to_string())),
+            }
+        }
+        Ok((store, issues))
+    }
+}
+
+/// Checks every entry of `store`. Topic links may point at files in the import or
+/// files already known.
+pub fn validate(store: &MetadataStore, existing: &MetadataStore, now: DateTime<Utc>) -> Vec<ImportIssue> {
+    let latest = now + Duration::hours(FUTURE_SKEW_HOURS);
+    let mut issues = Vec::new();
+    let mut node_ids: HashMap<u32, &str> = HashMap::new();
+
+    for (file, metadata) in store {
+        if !file.ends_with(".md") || file.len() == ".md".len() {
+            issues.push(ImportIssue::new(file, "fileName", "must be a markdown file name"));
+        }
+        if !metadata.file_name.is_empty() && metadata.file_name != *file {
+            issues.push(ImportIssue::new(file, "fileName", format!("does not match its key ({})", metadata.file_name)));
+        }
+        match metadata.node_id.parse::<u32>() {
+            // 0 is the placeholder for ids that haven't been assigned yet
+            Ok(0) => {}
+            Ok(id) => {
+                if let Some(other) = node_ids.insert(id, file) {
+                    issues.push(ImportIssue::new(file, "nodeId", format!("{} is also used by {}", id, other)));
+                }
+            }
+            Err(_) => issues.push(ImportIssue::new(file, "nodeId", format!("{:?} is not a numeric id", metadata.node_id))),
+        }
+        if metadata.file_size > MAX_FILE_SIZE {
+            issues.push(ImportIssue::new(file, "fileSize", format!("{} is over the {} byte limit", metadata.file_size, MAX_FILE_SIZE)));
+        }
+        if !metadata.node_size.is_finite() || !(0.0..=MAX_NODE_SIZE).contains(&metadata.node_size) {
+            issues.push(ImportIssue::new(file, "nodeSize", format!("{} is outside 0 to {}", metadata.node_size, MAX_NODE_SIZE)));
+        }
+        if metadata.last_modified > latest {
+            issues.push(ImportIssue::new(file, "lastModified", "is in the future"));
+        }
+        if metadata.last_perplexity_process.is_some_and(|date| date > latest) {
+            issues.push(ImportIssue::new(file, "lastPerplexityProcess", "is in the future"));
+        }
+        for target in metadata.topic_counts.keys() {
+            if !store.contains_key(target) && !existing.contains_key(target) {
+                issues.push(ImportIssue::new(file, "topicCounts", format!("references unknown file {}", target)));
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| (&a.file, &a.field, &a.message).cmp(&(&b.file, &b.field, &b.message)));
+    issues
+}
+
+// Topic links the graph builds an edge for: both ends present, never a self link
+fn links(store: &MetadataStore) -> BTreeSet<(String, String)> {
+    let ids: HashSet<&str> = store.keys().map(|f| f.trim_end_matches(".md")).collect();
+    let mut links = BTreeSet::new();
+    for (file, metadata) in store {
+        let source = file.trim_end_matches(".md");
+        for target in metadata.topic_counts.keys() {
+            let target = target.trim_end_matches(".md");
+            if source != target && ids.contains(target) {
+                let (a, b) = if source < target { (source, target) } else { (target, source) };
+                links.insert((a.to_string(), b.to_string()));
+            }
+        }
+    }
+    links
+}
+
+/// The change from `old` to `new`, as the rebuild would apply it
+pub fn import_diff(old: &MetadataStore, new: &MetadataStore) -> ImportDiff {
+    let mut diff = ImportDiff::default();
+    for (file, metadata) in new {
+        match old.get(file) {
+            None => diff.added_files.push(file.clone()),
+            Some(previous) => {
+                if serde_json::to_value(previous).ok() != serde_json::to_value(metadata).ok() {
+                    diff.updated_files.push(file.clone());
+                }
+            }
+        }
+    }
+    diff.removed_files = old.keys().filter(|f| !new.contains_key(*f)).cloned().collect();
+    diff.added_files.sort();
+    diff.updated_files.sort();
+    diff.removed_files.sort();
+
+    let (before, after) = (links(old), links(new));
+    diff.added_links = after.difference(&before).cloned().collect();
+    diff.removed_links = before.difference(&after).cloned().collect();
+    diff
+}
+
+/// Saves the store and rebuilds the graph from it. Nodes whose files survive keep
+/// their place in the layout. Returns the new graph generation.
+pub async fn apply_import(
+    store: MetadataStore,
+    metadata_addr: &Addr<MetadataActor>,
+    graph_addr: &Addr<GraphServiceActor>,
+    rebuilds: &GraphRebuilds,
+) -> Result<u64, String> {
+    FileService::save_metadata(&store).map_err(|e| format!("Failed to save metadata: {}", e))?;
+    metadata_addr.send(UpdateMetadata { metadata: store.clone() }).await
+        .map_err(|e| format!("Metadata service unavailable: {}", e))??;
+    graph_service::rebuild_graph(rebuilds, graph_addr, store).await?;
+    let generations = graph_addr.send(GetGenerations).await
+        .map_err(|e| format!("Graph service unavailable: {}", e))??;
+    Ok(generations.generation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetadataSchemaSettings;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const STORE: &str = r#"{
+        "a.md": { "fileName": "a.md", "fileSize": 100, "nodeSize": 5.0, "nodeId": "1",
+                  "lastModified": "2024-01-01T00:00:00Z", "topicCounts": { "b.md": 2 } },
+        "b.md": { "fileName": "b.md", "nodeId": "1", "topicCounts": { "ghost.md": 1 } },
+        "c.md": { "fileName": "c.md", "lastModified": "not a date" },
+        "d.md": { "fileName": "d.md", "nodeId": "x", "nodeSize": -1.0,
+                  "lastModified": "2999-01-01T00:00:00Z" }
+    }"#;
+
+    fn lax() -> MetadataSchema {
+        MetadataSchema::new(&MetadataSchemaSettings::default())
+    }
+
+    #[test]
+    fn test_bad_entries_are_reported_not_fatal() {
+        let (store, parse_issues) = parse_store(STORE.as_bytes(), &lax()).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(parse_issues.len(), 1);
+        assert_eq!((parse_issues[0].file.as_str(), parse_issues[0].field.as_str()), ("c.md", "entry"));
+
+        let issues = validate(&store, &MetadataStore::new(), Utc::now());
+        let fields: Vec<(&str, &str)> = issues.iter().map(|i| (i.file.as_str(), i.field.as_str())).collect();
+        assert!(fields.contains(&("b.md", "topicCounts")));
+        assert!(fields.contains(&("d.md", "nodeId")));
+        assert!(fields.contains(&("d.md", "nodeSize")));
+        assert!(fields.contains(&("d.md", "lastModified")));
+        // One of a and b is reported for sharing node id 1, whichever came second
+        assert_eq!(fields.iter().filter(|(_, field)| *field == "nodeId").count(), 2);
+        assert!(!fields.iter().any(|(file, field)| *file == "a.md" && *field != "nodeId"));
+
+        // Known files satisfy topic links too
+        let existing = MetadataStore::from([("ghost.md".to_string(), Metadata::default())]);
+        assert!(!validate(&store, &existing, Utc::now()).iter().any(|i| i.field == "topicCounts"));
+
+        assert!(parse_store("[1, 2]".as_bytes(), &lax()).is_err());
+        assert!(parse_store("{\"a.md\": {}".as_bytes(), &lax()).is_err());
+
+        // Extra keys pass a lax schema and are reported by a strict one
+        let typo = r#"{ "e.md": { "fileName": "e.md", "hyperlinkCuont": 3 } }"#;
+        assert!(parse_store(typo.as_bytes(), &lax()).unwrap().1.is_empty());
+        let strict = MetadataSchema::new(&MetadataSchemaSettings { strict: true, ..Default::default() });
+        let (store, issues) = parse_store(typo.as_bytes(), &strict).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!((issues[0].file.as_str(), issues[0].field.as_str()), ("e.md", "hyperlinkCuont"));
+    }
+
+    #[test]
+    fn test_gzip_and_diff() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(STORE.as_bytes()).unwrap();
+        let (new, _) = parse_store(&encoder.finish().unwrap()[..], &lax()).unwrap();
+
+        let old = MetadataStore::from([
+            ("a.md".to_string(), new["a.md"].clone()),
+            ("b.md".to_string(), Metadata { file_name: "b.md".to_string(), ..Default::default() }),
+            ("gone.md".to_string(), Metadata {
+                file_name: "gone.md".to_string(),
+                topic_counts: HashMap::from([("a.md".to_string(), 1)]),
+                ..Default::default()
+            }),
+        ]);
+        let diff = import_diff(&old, &new);
+        assert_eq!(diff.added_files, vec!["d.md"]);
+        assert_eq!(diff.updated_files, vec!["b.md"]);
+        assert_eq!(diff.removed_files, vec!["gone.md"]);
+        // a -> b existed before through a's own counts; only the link to gone.md goes
+        assert!(diff.added_links.is_empty());
+        assert_eq!(diff.removed_links, vec![("a".to_string(), "gone".to_string())]);
+
+        // A body that inflates past the limit is refused rather than parsed
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(STORE.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let limit = STORE.len() as u64;
+        assert!(parse_store_within(&gzipped[..], &lax(), limit).is_ok());
+        let error = parse_store_within(&gzipped[..], &lax(), limit - 1).unwrap_err();
+        assert!(error.contains("inflates"), "{}", error);
+    }
+}
@@ -1,7 +1,49 @@
 pub mod github;
+pub mod admin_feed;
+pub mod agent_service;
+pub mod anchor_service;
+pub mod audit_log;
+pub mod annotation_service;
+pub mod edge_bundle_service;
+pub mod edge_decay_service;
+pub mod edge_recompute;
+pub mod embedding_service;
+pub mod co_view_service;
+pub mod dictation;
+pub mod enrichment_service;
+pub mod event_log;
+pub mod external_links;
 pub mod file_service;
 pub mod graph_service;
+pub mod integrity_check;
+pub mod job_service;
+pub mod label_placement_service;
+pub mod layout_quality_service;
+pub mod layout_snapshot_service;
+#[cfg(feature = "loadtest")]
+pub mod load_test;
+pub mod metadata_import;
+pub mod pagination_session_service;
 pub mod nostr_service;
 pub mod perplexity_service;
+pub mod preview_service;
+pub mod query_service;
 pub mod ragflow_service;
+pub mod recording_service;
+pub mod room_access;
+pub mod room_physics;
+pub mod saved_view_service;
+pub mod speech_readiness;
+pub mod speech_recording_store;
+#[cfg(feature = "speech")]
 pub mod speech_service;
+#[cfg(not(feature = "speech"))]
+#[path = "speech_service_disabled.rs"]
+pub mod speech_service;
+pub mod speech_session_service;
+pub mod summary_service;
+pub mod tagging_service;
+pub mod telemetry_service;
+pub mod topic_extraction;
+pub mod utterance_store;
+pub mod webhook_service;
@@ -210,6 +210,19 @@ impl NostrService {
             false
         }
     }
+
+    // Tests can't sign auth events, so they log users in directly
+    #[cfg(test)]
+    pub(crate) async fn insert_test_session(&self, pubkey: &str, token: &str) {
+        self.users.write().await.insert(pubkey.to_string(), NostrUser {
+            pubkey: pubkey.to_string(),
+            npub: String::new(),
+            is_power_user: false,
+            api_keys: ApiKeys::default(),
+            last_seen: Utc::now().timestamp(),
+            session_token: Some(token.to_string()),
+        });
+    }
 }
 
 impl Default for NostrService {
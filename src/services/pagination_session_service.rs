@@ -0,0 +1,251 @@
+//! Pagination sessions. Paging through a graph the physics loop keeps moving mixes pages
+//! from different moments, and a sort by degree can reorder between requests. A session
+//! pins one snapshot and one node ordering so every page it serves comes from the same
+//! graph. It holds the snapshot Arc and the ordered node indices, never copies of nodes.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::PaginationSessionSettings;
+use crate::models::edge::Edge;
+use crate::models::graph::GraphSnapshot;
+use crate::models::node::Node;
+use crate::utils::aging;
+
+/// The order pages are served in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSort {
+    // As the graph holds them
+    Graph,
+    // Most connected first
+    Degree,
+    Label,
+}
+
+impl PageSort {
+    pub fn parse(sort: Option<&str>) -> Result<Self, String> {
+        match sort.map(str::trim).filter(|s| !s.is_empty()) {
+            None | Some("graph") => Ok(PageSort::Graph),
+            Some("degree") => Ok(PageSort::Degree),
+            Some("label") => Ok(PageSort::Label),
+            Some(other) => Err(format!("unknown sort '{}'; use graph, degree or label", other)),
+        }
+    }
+}
+
+/// One frozen view to page through. Cheap to clone: everything is shared.
+#[derive(Debug, Clone)]
+pub struct PageView {
+    pub snapshot: GraphSnapshot,
+    // Indices into snapshot.nodes in page order
+    pub order: Arc<Vec<u32>>,
+    // Archived nodes left out of the order, so their edges can be left out too
+    pub hidden: Arc<HashSet<u32>>,
+    // Layout generation the snapshot was taken at
+    pub position_generation: u64,
+}
+
+impl PageView {
+    pub fn new(snapshot: GraphSnapshot, position_generation: u64, sort: PageSort, include_archived: bool) -> Self {
+        let hidden: HashSet<u32> = if include_archived {
+            HashSet::new()
+        } else {
+            snapshot.nodes.iter().filter(|n| aging::is_archived(n)).map(|n| n.id).collect()
+        };
+        let mut order: Vec<u32> = (0..snapshot.nodes.len() as u32)
+            .filter(|&i| !hidden.contains(&snapshot.nodes[i as usize].id))
+            .collect();
+        match sort {
+            PageSort::Graph => {}
+            PageSort::Degree => {
+                let mut degree: HashMap<u32, usize> = HashMap::new();
+                for edge in &snapshot.edges {
+                    *degree.entry(edge.source).or_default() += 1;
+                    *degree.entry(edge.target).or_default() += 1;
+                }
+                let nodes = &snapshot.nodes;
+                order.sort_by_key(|&i| {
+                    let node = &nodes[i as usize];
+                    (std::cmp::Reverse(degree.get(&node.id).copied().unwrap_or(0)), node.id)
+                });
+            }
+            PageSort::Label => {
+                let nodes = &snapshot.nodes;
+                order.sort_by_cached_key(|&i| {
+                    let node = &nodes[i as usize];
+                    (node.label.to_lowercase(), node.id)
+                });
+            }
+        }
+        Self { snapshot, order: Arc::new(order), hidden: Arc::new(hidden), position_generation }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Nodes at positions `start..end` of the order
+    pub fn nodes(&self, start: usize, end: usize) -> Vec<Node> {
+        self.order[start..end].iter().map(|&i| self.snapshot.nodes[i as usize].clone()).collect()
+    }
+
+    /// Edges touching any of `nodes`, minus hidden types and edges to archived nodes
+    pub fn edges_for(&self, nodes: &[Node], hidden_types: &HashSet<&str>) -> Vec<Edge> {
+        let ids: HashSet<u32> = nodes.iter().map(|n| n.id).collect();
+        self.snapshot.edges.iter()
+            .filter(|e| ids.contains(&e.source) || ids.contains(&e.target))
+            .filter(|e| !self.hidden.contains(&e.source) && !self.hidden.contains(&e.target))
+            .filter(|e| !hidden_types.contains(e.type_name()))
+            .cloned()
+            .collect()
+    }
+}
+
+struct Session {
+    view: PageView,
+    last_used: Instant,
+}
+
+pub struct PaginationSessionService {
+    settings: PaginationSessionSettings,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl PaginationSessionService {
+    pub fn new(settings: PaginationSessionSettings) -> Self {
+        Self { settings, sessions: Mutex::new(HashMap::new()) }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.ttl_secs.max(0.0))
+    }
+
+    /// Keeps `view` under a new session id. At the cap, the least recently used session
+    /// makes room, so memory stays bounded however many clients page.
+    pub fn create(&self, view: PageView, now: Instant) -> String {
+        self.purge_expired(now);
+        let mut sessions = self.sessions.lock().unwrap();
+        while !sessions.is_empty() && sessions.len() >= self.settings.max_sessions.max(1) {
+            let oldest = sessions.iter().min_by_key(|(_, s)| s.last_used).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        sessions.insert(id.clone(), Session { view, last_used: now });
+        id
+    }
+
+    /// The session's view. Each read pushes its expiry back by the TTL.
+    pub fn get(&self, id: &str, now: Instant) -> Option<PageView> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id)?;
+        if now.duration_since(session.last_used) >= self.ttl() {
+            sessions.remove(id);
+            return None;
+        }
+        session.last_used = now;
+        Some(session.view.clone())
+    }
+
+    pub fn delete(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(id).is_some()
+    }
+
+    pub fn purge_expired(&self, now: Instant) -> usize {
+        let ttl = self.ttl();
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| now.duration_since(session.last_used) < ttl);
+        before - sessions.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::graph::GraphData;
+
+    fn graph() -> GraphSnapshot {
+        let mut graph = GraphData::new();
+        for id in 1..=6 {
+            graph.nodes.push(Node::new_with_id(format!("n{}", id), Some(id)));
+        }
+        // Node 4 is the hub
+        for (source, target) in [(4, 1), (4, 2), (4, 3), (5, 1), (5, 2)] {
+            graph.edges.push(Edge::new(source, target, 1.0));
+        }
+        GraphSnapshot::new(graph)
+    }
+
+    #[test]
+    fn test_pages_stay_consistent_while_the_graph_mutates() {
+        let sessions = PaginationSessionService::new(PaginationSessionSettings { ttl_secs: 60.0, max_sessions: 2 });
+        let start = Instant::now();
+        let mut live = graph();
+        let view = PageView::new(live.clone(), 7, PageSort::Degree, false);
+        let id = sessions.create(view, start);
+
+        let ids = |nodes: Vec<Node>| nodes.iter().map(|n| n.id).collect::<Vec<_>>();
+        let first = sessions.get(&id, start).unwrap();
+        assert_eq!(ids(first.nodes(0, 3)), vec![4, 1, 2]);
+
+        // Physics moves a node and a new hub appears, the way the graph actor writes
+        let graph = Arc::make_mut(&mut live);
+        graph.nodes[2].data.position.x = 99.0;
+        for target in 1..=5 {
+            graph.edges.push(Edge::new(6, target, 1.0));
+        }
+
+        // The next page still comes from the frozen ordering and positions
+        let second = sessions.get(&id, start + Duration::from_secs(30)).unwrap();
+        assert_eq!(ids(second.nodes(3, 6)), vec![5, 3, 6]);
+        assert_eq!(second.nodes(3, 6)[1].data.position.x, first.snapshot.nodes[2].data.position.x);
+        assert_ne!(second.nodes(3, 6)[1].data.position.x, 99.0);
+        assert_eq!(second.edges_for(&second.nodes(3, 6), &HashSet::new()).len(), 3);
+        assert_eq!(second.position_generation, 7);
+        // A fresh view sees the new hub first
+        assert_eq!(ids(PageView::new(live.clone(), 8, PageSort::Degree, false).nodes(0, 1)), vec![6]);
+
+        // Reads push the expiry back; idle past the TTL, the session is gone
+        assert!(sessions.get(&id, start + Duration::from_secs(80)).is_some());
+        assert!(sessions.get(&id, start + Duration::from_secs(141)).is_none());
+
+        // Deleting frees it at once, and the cap evicts the least recently used
+        let a = sessions.create(PageView::new(live.clone(), 8, PageSort::Graph, false), start);
+        assert!(sessions.delete(&a));
+        assert!(!sessions.delete(&a));
+        let b = sessions.create(PageView::new(live.clone(), 8, PageSort::Graph, false), start);
+        let c = sessions.create(PageView::new(live.clone(), 8, PageSort::Graph, false), start + Duration::from_secs(1));
+        sessions.get(&b, start + Duration::from_secs(2));
+        sessions.create(PageView::new(live, 8, PageSort::Graph, false), start + Duration::from_secs(3));
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.get(&c, start + Duration::from_secs(3)).is_none());
+        assert!(sessions.get(&b, start + Duration::from_secs(3)).is_some());
+    }
+
+    #[test]
+    fn test_archived_nodes_and_their_edges_are_left_out() {
+        let mut graph = (*graph()).clone();
+        graph.nodes[3].metadata.insert(aging::ARCHIVED_KEY.to_string(), "true".to_string());
+        let view = PageView::new(GraphSnapshot::new(graph.clone()), 0, PageSort::Graph, false);
+        assert_eq!(view.len(), 5);
+        let nodes = view.nodes(0, 3);
+        assert_eq!(view.edges_for(&nodes, &HashSet::new()).len(), 2);
+        assert_eq!(PageView::new(GraphSnapshot::new(graph), 0, PageSort::Graph, true).len(), 6);
+        assert!(PageSort::parse(Some("size")).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::config::AppFullSettings; // Use AppFullSettings, ConfigPerplexitySettings removed
 use crate::models::metadata::Metadata;
-use crate::services::file_service::ProcessedFile;
+use crate::services::file_service::{ProcessedFile, MARKDOWN_DIR};
 use chrono::Utc;
 use log::{error, info};
 use reqwest::Client;
@@ -12,8 +12,6 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
 
-const MARKDOWN_DIR: &str = "data/markdown";
-
 #[derive(Debug, Serialize, Deserialize)]
 struct PerplexityResponse {
     content: String,
@@ -151,6 +149,7 @@ impl PerplexityService {
             perplexity_link: perplexity_response.link,
             last_perplexity_process: Some(Utc::now()),
             topic_counts: HashMap::new(),
+            ..Default::default()
         };
 
         Ok(ProcessedFile {
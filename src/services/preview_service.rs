@@ -0,0 +1,208 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PREVIEW_CACHE_DIR: &str = "/app/data/previews";
+const PREVIEW_MAX_LINES: usize = 20;
+// Hard cap on excerpt/html size so hover previews stay tiny
+const PREVIEW_MAX_BYTES: usize = 4096;
+
+/// Rendered hover preview for a markdown node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodePreview {
+    pub metadata_id: String,
+    pub sha1: String,
+    pub excerpt: String,
+    pub html: String,
+    pub truncated: bool,
+}
+
+/// Renders markdown previews and caches them on disk keyed by content SHA1,
+/// so a preview is only regenerated when the file actually changes.
+pub struct PreviewService {
+    cache_dir: PathBuf,
+}
+
+impl Default for PreviewService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PreviewService {
+    pub fn new() -> Self {
+        Self::with_cache_dir(PathBuf::from(PREVIEW_CACHE_DIR))
+    }
+
+    pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    fn cache_path(&self, sha1: &str) -> PathBuf {
+        self.cache_dir.join(format!("{}.json", sha1))
+    }
+
+    /// Returns the cached preview for `sha1`, rendering and storing it on a miss.
+    /// The bool is true when the preview came from the cache.
+    pub fn get_or_render(&self, metadata_id: &str, sha1: &str, source: &Path) -> Result<(NodePreview, bool), std::io::Error> {
+        // SHA1 is hex, anything else would let a caller escape the cache dir
+        let cacheable = !sha1.is_empty() && sha1.chars().all(|c| c.is_ascii_hexdigit());
+
+        if cacheable {
+            if let Ok(content) = fs::read_to_string(self.cache_path(sha1)) {
+                if let Ok(preview) = serde_json::from_str::<NodePreview>(&content) {
+                    debug!("Preview cache hit for {} ({})", metadata_id, sha1);
+                    return Ok((preview, true));
+                }
+            }
+        }
+
+        let content = fs::read_to_string(source)?;
+        let preview = render_preview(metadata_id, sha1, &content);
+
+        if cacheable {
+            if let Err(e) = crate::utils::json_store::write_json_atomic(&self.cache_path(sha1), &preview) {
+                warn!("Failed to cache preview for {}: {}", metadata_id, e);
+            }
+        }
+        Ok((preview, false))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn truncate_to_bytes(text: &mut String, max: usize) -> bool {
+    if text.len() <= max {
+        return false;
+    }
+    let mut cut = max;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    text.truncate(cut);
+    true
+}
+
+/// Renders the first lines of a markdown document as a plain-text excerpt and a
+/// small HTML fragment. Raw HTML in the source is never passed through: script and
+/// style blocks are dropped entirely and everything else is escaped.
+pub fn render_preview(metadata_id: &str, sha1: &str, markdown: &str) -> NodePreview {
+    let blocks = Regex::new(r"(?is)<(script|style)\b.*?</(script|style)\s*>").unwrap();
+    let tags = Regex::new(r"(?s)<[^>]*>").unwrap();
+    let cleaned = blocks.replace_all(markdown, "");
+    let cleaned = tags.replace_all(&cleaned, "");
+
+    let lines: Vec<&str> = cleaned.lines()
+        .map(|l| l.trim_end())
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    let mut truncated = lines.len() > PREVIEW_MAX_LINES;
+    let lines = &lines[..lines.len().min(PREVIEW_MAX_LINES)];
+
+    let mut excerpt = lines.iter()
+        .map(|l| l.trim_start_matches(['#', '>', ' ']))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut html = String::new();
+    for line in lines {
+        let trimmed = line.trim_start();
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&level) {
+            let text = escape_html(trimmed[level..].trim());
+            html.push_str(&format!("<h{0}>{1}</h{0}>", level.min(3) + 2, text));
+        } else if let Some(item) = trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")) {
+            html.push_str(&format!("<li>{}</li>", escape_html(item)));
+        } else {
+            html.push_str(&format!("<p>{}</p>", escape_html(trimmed)));
+        }
+    }
+
+    truncated |= truncate_to_bytes(&mut excerpt, PREVIEW_MAX_BYTES);
+    if truncate_to_bytes(&mut html, PREVIEW_MAX_BYTES) {
+        // Don't leave a half-written tag behind
+        if let Some(pos) = html.rfind('<') {
+            html.truncate(pos);
+        }
+        truncated = true;
+    }
+
+    NodePreview {
+        metadata_id: metadata_id.to_string(),
+        sha1: sha1.to_string(),
+        excerpt,
+        html,
+        truncated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("preview-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_script_tags_are_stripped() {
+        let md = "# Title\n<script>alert('x')</script>\nHello <b>world</b> & <img src=x onerror=alert(1)>\n<SCRIPT type=\"text/javascript\">\nsteal()\n</SCRIPT>";
+        let preview = render_preview("a.md", "", md);
+        assert!(!preview.html.to_lowercase().contains("<script"));
+        assert!(!preview.html.contains("alert"));
+        assert!(!preview.html.contains("onerror"));
+        assert!(!preview.excerpt.contains("steal"));
+        assert!(preview.html.contains("<h3>Title</h3>"));
+        assert!(preview.html.contains("Hello world &amp;"));
+    }
+
+    #[test]
+    fn test_preview_is_bounded() {
+        let md = (0..100).map(|i| format!("line {} {}", i, "x".repeat(200))).collect::<Vec<_>>().join("\n");
+        let preview = render_preview("big.md", "", &md);
+        assert!(preview.truncated);
+        assert!(preview.html.len() <= PREVIEW_MAX_BYTES);
+        assert!(preview.excerpt.len() <= PREVIEW_MAX_BYTES);
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let dir = temp_dir();
+        let source = dir.join("note.md");
+        fs::write(&source, "first version").unwrap();
+        let service = PreviewService::with_cache_dir(dir.join("cache"));
+
+        let (preview, hit) = service.get_or_render("note.md", "abc123", &source).unwrap();
+        assert!(!hit);
+        assert_eq!(preview.excerpt, "first version");
+
+        // Same SHA1 serves the cached copy even if the file on disk changed
+        fs::write(&source, "second version").unwrap();
+        let (preview, hit) = service.get_or_render("note.md", "abc123", &source).unwrap();
+        assert!(hit);
+        assert_eq!(preview.excerpt, "first version");
+
+        // A new SHA1 is a miss and re-renders
+        let (preview, hit) = service.get_or_render("note.md", "def456", &source).unwrap();
+        assert!(!hit);
+        assert_eq!(preview.excerpt, "second version");
+
+        // Non-hex keys are never used as cache file names
+        let (_, hit) = service.get_or_render("note.md", "../evil", &source).unwrap();
+        assert!(!hit);
+        assert!(!dir.join("evil.json").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
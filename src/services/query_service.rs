@@ -0,0 +1,350 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::models::edge::Edge;
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+
+const MAX_QUERY_LEN: usize = 500;
+
+const STOPWORDS: &[&str] = &[
+    "a", "about", "all", "an", "and", "any", "anything", "are", "around", "connected", "display",
+    "documents", "everything", "files", "find", "for", "from", "give", "in", "is", "it", "me",
+    "mention", "mentioning", "nodes", "notes", "of", "on", "please", "related", "show", "that",
+    "the", "to", "what", "with", "everything's", "things", "stuff", "pages", "get", "list",
+];
+
+/// A structured reading of a natural-language query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPlan {
+    pub keywords: Vec<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    // Pull in direct neighbours of the matches ("related to", "connected to")
+    pub include_neighbours: bool,
+    pub explanation: String,
+}
+
+/// Turns free text into one or more candidate plans. More than one plan means the
+/// query was ambiguous and the caller should pick.
+#[async_trait]
+pub trait QueryInterpreter: Send + Sync {
+    fn name(&self) -> &str;
+    async fn interpret(&self, query: &str, now: DateTime<Utc>) -> Result<Vec<QueryPlan>, String>;
+}
+
+/// Rule-based interpreter: keywords plus a handful of temporal phrases
+pub struct KeywordInterpreter;
+
+// (since, until, description, matched text)
+type TemporalCandidate = (Option<DateTime<Utc>>, Option<DateTime<Utc>>, String, String);
+
+impl KeywordInterpreter {
+    // Returns the candidates for the temporal part
+    fn temporal(query: &str, now: DateTime<Utc>) -> Vec<TemporalCandidate> {
+        let relative = Regex::new(r"\b(?:in |from |during |over )?(?:the )?(last|past) (\d+ )?(day|week|month|year)s?\b").unwrap();
+        if let Some(caps) = relative.captures(query) {
+            let count: i64 = caps.get(2).and_then(|m| m.as_str().trim().parse().ok()).unwrap_or(1);
+            let unit = &caps[3];
+            let days = match unit {
+                "day" => 1,
+                "week" => 7,
+                "month" => 30,
+                _ => 365,
+            } * count;
+            let label = if count == 1 { format!("the last {}", unit) } else { format!("the last {} {}s", count, unit) };
+            return vec![(Some(now - Duration::days(days)), None, label, caps[0].to_string())];
+        }
+
+        let since = Regex::new(r"\b(since|after|before) (\d{4}-\d{2}-\d{2})\b").unwrap();
+        if let Some(caps) = since.captures(query) {
+            if let Ok(date) = NaiveDate::parse_from_str(&caps[2], "%Y-%m-%d") {
+                let at = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+                let (from, to) = if &caps[1] == "before" { (None, Some(at)) } else { (Some(at), None) };
+                return vec![(from, to, format!("{} {}", &caps[1], &caps[2]), caps[0].to_string())];
+            }
+        }
+
+        for (phrase, days, label) in [("today", 1, "today"), ("yesterday", 2, "since yesterday"), ("this week", 7, "this week"), ("this month", 30, "this month")] {
+            if query.contains(phrase) {
+                return vec![(Some(now - Duration::days(days)), None, label.to_string(), phrase.to_string())];
+            }
+        }
+
+        // "recent" has no single obvious window, so offer both rather than guess
+        let vague = Regex::new(r"\b(recent|recently|lately|latest)\b").unwrap();
+        if let Some(m) = vague.find(query) {
+            return vec![
+                (Some(now - Duration::days(7)), None, "the last week".to_string(), m.as_str().to_string()),
+                (Some(now - Duration::days(30)), None, "the last month".to_string(), m.as_str().to_string()),
+            ];
+        }
+
+        vec![(None, None, String::new(), String::new())]
+    }
+}
+
+#[async_trait]
+impl QueryInterpreter for KeywordInterpreter {
+    fn name(&self) -> &str {
+        "keyword"
+    }
+
+    async fn interpret(&self, query: &str, now: DateTime<Utc>) -> Result<Vec<QueryPlan>, String> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err("Query is empty".to_string());
+        }
+        if query.len() > MAX_QUERY_LEN {
+            return Err(format!("Query is longer than {} characters", MAX_QUERY_LEN));
+        }
+
+        let include_neighbours = ["related", "connected", "linked", "around"]
+            .iter()
+            .any(|w| query.contains(w));
+
+        let mut plans = Vec::new();
+        for (since, until, time_label, matched) in Self::temporal(&query, now) {
+            let rest = if matched.is_empty() { query.clone() } else { query.replacen(&matched, " ", 1) };
+            let mut keywords: Vec<String> = rest
+                .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+                .filter(|w| w.len() > 1 && !STOPWORDS.contains(w))
+                .map(|w| w.to_string())
+                .collect();
+            keywords.dedup();
+
+            if keywords.is_empty() && since.is_none() && until.is_none() {
+                return Err("Could not find anything to search for in the query".to_string());
+            }
+
+            let mut explanation = if keywords.is_empty() {
+                "All nodes".to_string()
+            } else {
+                format!("Nodes matching {}", keywords.iter().map(|k| format!("\"{}\"", k)).collect::<Vec<_>>().join(" or "))
+            };
+            if include_neighbours {
+                explanation.push_str(" plus their direct neighbours");
+            }
+            if !time_label.is_empty() {
+                explanation.push_str(&format!(", modified {}", time_label));
+            }
+
+            plans.push(QueryPlan { keywords, since, until, include_neighbours, explanation });
+        }
+        Ok(plans)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCandidate {
+    pub index: usize,
+    pub explanation: String,
+    pub match_count: usize,
+    pub plan: QueryPlan,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResult {
+    pub query: String,
+    pub interpreter: String,
+    pub ambiguous: bool,
+    // Set when the query resolved to one interpretation
+    pub explanation: Option<String>,
+    pub candidates: Vec<QueryCandidate>,
+    // Ids the client should highlight: direct matches, then everything in the subgraph
+    pub matched_ids: Vec<u32>,
+    pub node_ids: Vec<u32>,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Natural-language query pipeline shared by the REST endpoint and the voice path
+pub struct QueryService {
+    interpreter: Box<dyn QueryInterpreter>,
+}
+
+impl Default for QueryService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueryService {
+    pub fn new() -> Self {
+        Self::with_interpreter(Box::new(KeywordInterpreter))
+    }
+
+    pub fn with_interpreter(interpreter: Box<dyn QueryInterpreter>) -> Self {
+        Self { interpreter }
+    }
+
+    /// Interprets and runs `query`. When it is ambiguous and no `candidate` index
+    /// was given, returns the candidates without a subgraph.
+    pub async fn run(&self, query: &str, candidate: Option<usize>, graph: &GraphData) -> Result<QueryResult, String> {
+        let plans = self.interpreter.interpret(query, Utc::now()).await?;
+        if plans.is_empty() {
+            return Err("Query could not be interpreted".to_string());
+        }
+
+        let chosen = match candidate {
+            Some(index) => Some(plans.get(index).ok_or_else(|| format!("No candidate interpretation {}", index))?),
+            None if plans.len() == 1 => plans.first(),
+            None => None,
+        };
+
+        let mut result = QueryResult {
+            query: query.to_string(),
+            interpreter: self.interpreter.name().to_string(),
+            ambiguous: chosen.is_none(),
+            explanation: None,
+            candidates: Vec::new(),
+            matched_ids: Vec::new(),
+            node_ids: Vec::new(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+
+        match chosen {
+            Some(plan) => {
+                let (matched, nodes, edges) = execute_plan(plan, graph);
+                result.explanation = Some(plan.explanation.clone());
+                result.matched_ids = matched;
+                result.node_ids = nodes.iter().map(|n| n.id).collect();
+                result.nodes = nodes;
+                result.edges = edges;
+            }
+            None => {
+                result.candidates = plans.into_iter().enumerate()
+                    .map(|(index, plan)| QueryCandidate {
+                        index,
+                        explanation: plan.explanation.clone(),
+                        match_count: execute_plan(&plan, graph).0.len(),
+                        plan,
+                    })
+                    .collect();
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn node_modified(node: &Node, graph: &GraphData) -> Option<DateTime<Utc>> {
+    node.metadata.get("lastModified")
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|d| d.with_timezone(&Utc))
+        .or_else(|| graph.metadata.get(&format!("{}.md", node.metadata_id)).map(|m| m.last_modified))
+}
+
+/// Keyword + time filter, optionally widened by one hop. Returns the direct
+/// matches and the induced subgraph.
+pub fn execute_plan(plan: &QueryPlan, graph: &GraphData) -> (Vec<u32>, Vec<Node>, Vec<Edge>) {
+    let mut matched: Vec<u32> = graph.nodes.iter()
+        .filter(|node| {
+            if plan.keywords.is_empty() {
+                return true;
+            }
+            let label = node.label.to_lowercase();
+            let id = node.metadata_id.to_lowercase();
+            let tags = node.metadata.get("tags").map(|t| t.to_lowercase()).unwrap_or_default();
+            plan.keywords.iter().any(|k| label.contains(k) || id.contains(k) || tags.split(',').any(|t| t == k))
+        })
+        .filter(|node| {
+            if plan.since.is_none() && plan.until.is_none() {
+                return true;
+            }
+            match node_modified(node, graph) {
+                Some(modified) => plan.since.is_none_or(|s| modified >= s) && plan.until.is_none_or(|u| modified < u),
+                None => false,
+            }
+        })
+        .map(|node| node.id)
+        .collect();
+    matched.sort_unstable();
+
+    let mut included: HashSet<u32> = matched.iter().copied().collect();
+    if plan.include_neighbours {
+        for edge in &graph.edges {
+            if matched.binary_search(&edge.source).is_ok() {
+                included.insert(edge.target);
+            }
+            if matched.binary_search(&edge.target).is_ok() {
+                included.insert(edge.source);
+            }
+        }
+    }
+
+    let nodes: Vec<Node> = graph.nodes.iter().filter(|n| included.contains(&n.id)).cloned().collect();
+    let edges: Vec<Edge> = graph.edges.iter()
+        .filter(|e| included.contains(&e.source) && included.contains(&e.target))
+        .cloned()
+        .collect();
+    (matched, nodes, edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(now: DateTime<Utc>) -> GraphData {
+        let mut graph = GraphData::new();
+        for (id, name, age_days) in [(1, "Onboarding Guide", 3), (2, "Team Handbook", 100), (3, "Onboarding Checklist", 60), (4, "Unrelated", 1)] {
+            let mut node = Node::new_with_id(name.replace(' ', "_"), Some(id));
+            node.label = name.to_string();
+            node.metadata.insert("lastModified".to_string(), (now - Duration::days(age_days)).to_rfc3339());
+            graph.nodes.push(node);
+        }
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        graph
+    }
+
+    #[tokio::test]
+    async fn test_keywords_and_time_window() {
+        let now = Utc::now();
+        let plans = KeywordInterpreter.interpret("Show everything related to onboarding from the last month", now).await.unwrap();
+        assert_eq!(plans.len(), 1);
+        let plan = &plans[0];
+        assert_eq!(plan.keywords, vec!["onboarding".to_string()]);
+        assert!(plan.include_neighbours);
+        assert!(plan.since.unwrap() <= now - Duration::days(29));
+        assert!(plan.explanation.contains("the last month"));
+
+        let (matched, nodes, edges) = execute_plan(plan, &graph(now));
+        // The checklist is two months old; the handbook comes in as a neighbour
+        assert_eq!(matched, vec![1]);
+        assert_eq!(nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(edges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ambiguous_query_returns_candidates() {
+        let now = Utc::now();
+        let service = QueryService::new();
+        let graph = graph(now);
+
+        let result = service.run("recent onboarding", None, &graph).await.unwrap();
+        assert!(result.ambiguous);
+        assert_eq!(result.candidates.len(), 2);
+        assert!(result.node_ids.is_empty());
+
+        let result = service.run("recent onboarding", Some(1), &graph).await.unwrap();
+        assert!(!result.ambiguous);
+        assert_eq!(result.matched_ids, vec![1]);
+        assert!(service.run("recent onboarding", Some(5), &graph).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_empty_queries_are_rejected() {
+        let now = Utc::now();
+        assert!(KeywordInterpreter.interpret("   ", now).await.is_err());
+        assert!(KeywordInterpreter.interpret("show me everything", now).await.is_err());
+        let plans = KeywordInterpreter.interpret("since 2024-01-01", now).await.unwrap();
+        assert!(plans[0].keywords.is_empty());
+        assert!(plans[0].since.is_some());
+    }
+}
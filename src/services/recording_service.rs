@@ -0,0 +1,174 @@
+use actix::Addr;
+use chrono::Utc;
+use log::info;
+use regex::Regex;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::actors::messages::{StartRecording, StartReplay, StopRecording};
+use crate::actors::GraphServiceActor;
+use crate::config::RecordingSettings;
+use crate::utils::position_recording::{read_recording, RecordingStatus, ReplayStatus};
+
+const RECORDING_DIR: &str = "/app/data/recordings";
+const EXTENSION: &str = "vfrc";
+// recording-20260101T120000123Z, same timestamp scheme as layout snapshots
+const NAME_PATTERN: &str = r"^recording-\d{8}T\d{9}Z$";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingInfo {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// Names, lists and loads position stream recordings. The recording itself is
+/// written by the graph actor, which sees every broadcast frame.
+pub struct RecordingService {
+    settings: RecordingSettings,
+    dir: PathBuf,
+}
+
+impl RecordingService {
+    pub fn new(settings: RecordingSettings) -> Self {
+        Self::with_dir(settings, PathBuf::from(RECORDING_DIR))
+    }
+
+    pub fn with_dir(settings: RecordingSettings, dir: PathBuf) -> Self {
+        Self { settings, dir }
+    }
+
+    pub async fn start(&self, graph_addr: &Addr<GraphServiceActor>) -> Result<(String, RecordingStatus), String> {
+        let name = format!("recording-{}Z", Utc::now().format("%Y%m%dT%H%M%S%3f"));
+        let status = graph_addr.send(StartRecording {
+            path: self.path_for(&name),
+            max_bytes: self.settings.max_megabytes.max(1) * 1024 * 1024,
+            max_duration: Duration::from_secs(self.settings.max_duration_secs.max(1)),
+        }).await.map_err(|e| e.to_string())??;
+        Ok((name, status))
+    }
+
+    pub async fn stop(&self, graph_addr: &Addr<GraphServiceActor>) -> Result<RecordingStatus, String> {
+        graph_addr.send(StopRecording).await.map_err(|e| e.to_string())?
+    }
+
+    /// Recordings on disk, newest first
+    pub async fn list(&self) -> Result<Vec<RecordingInfo>, String> {
+        let pattern = Regex::new(NAME_PATTERN).unwrap();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {:?}: {}", self.dir, e)),
+        };
+
+        let mut recordings = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = match file_name.strip_suffix(&format!(".{}", EXTENSION)) {
+                Some(name) if pattern.is_match(name) => name.to_string(),
+                _ => continue,
+            };
+            let size_bytes = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+            recordings.push(RecordingInfo { name, size_bytes });
+        }
+        recordings.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(recordings)
+    }
+
+    /// Loads a recording and hands it to the graph actor, which stops physics and
+    /// plays it back
+    pub async fn replay(&self, name: &str, speed: f32, graph_addr: &Addr<GraphServiceActor>) -> Result<ReplayStatus, String> {
+        // Only names we generated, which also rules out path traversal
+        if !Regex::new(NAME_PATTERN).unwrap().is_match(name) {
+            return Err(format!("Unknown recording: {}", name));
+        }
+        let path = self.path_for(name);
+        if !path.exists() {
+            return Err(format!("Unknown recording: {}", name));
+        }
+        let frames = tokio::task::spawn_blocking(move || read_recording(&path))
+            .await
+            .map_err(|e| e.to_string())??;
+        let status = graph_addr.send(StartReplay { frames, speed }).await.map_err(|e| e.to_string())??;
+        info!("Started replay of {}", name);
+        Ok(status)
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::{
+        BuildGraphFromMetadata, ControlReplay, GetNodeMap, GetRecordingState, ReplayCommand,
+        StopSimulation, WarmStartLayout,
+    };
+    use crate::actors::ClientManagerActor;
+    use crate::models::layout::NodeLayout;
+    use crate::models::metadata::{Metadata, MetadataStore};
+    use crate::models::simulation_params::SimulationMode;
+    use crate::utils::position_recording::parse_recording;
+    use actix::prelude::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("recording-service-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[actix_web::test]
+    async fn test_record_then_replay_through_graph() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager, None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        store.insert("a.md".to_string(), Metadata { file_name: "a.md".to_string(), ..Default::default() });
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let node_map = graph.send(GetNodeMap).await.unwrap().unwrap();
+        let (id, node) = node_map.iter().next().unwrap();
+        let (id, metadata_id, start) = (*id, node.metadata_id.clone(), node.data.position);
+
+        let service = RecordingService::with_dir(RecordingSettings::default(), temp_dir());
+        let (name, status) = service.start(&graph).await.unwrap();
+        assert_eq!(status.frames, 1);
+        assert!(service.start(&graph).await.is_err());
+
+        // Physics is stopped, so the only broadcast is this keyframe. Offsets are in
+        // ms, so give it one of its own.
+        actix::clock::sleep(Duration::from_millis(20)).await;
+        let mut moved = start;
+        moved.x += 10.0;
+        graph.send(WarmStartLayout { nodes: vec![NodeLayout { metadata_id, position: moved }] })
+            .await.unwrap().unwrap();
+        let status = service.stop(&graph).await.unwrap();
+        let frames = parse_recording(&std::fs::read(&status.path).unwrap()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(service.list().await.unwrap()[0].name, name);
+
+        // Replay opens on the first keyframe and takes over from physics
+        let replay = service.replay(&name, 1.0, &graph).await.unwrap();
+        assert_eq!(replay.frames, 2);
+        let state = graph.send(GetRecordingState).await.unwrap().unwrap();
+        assert_eq!(state.mode, SimulationMode::Replay);
+        assert_eq!(graph.send(GetNodeMap).await.unwrap().unwrap()[&id].data.position.x, start.x);
+
+        // The replay ticks even though physics is stopped
+        actix::clock::sleep(Duration::from_millis(200)).await;
+        assert_eq!(graph.send(GetNodeMap).await.unwrap().unwrap()[&id].data.position.x, start.x + 10.0);
+        let state = graph.send(GetRecordingState).await.unwrap().unwrap();
+        assert!(!state.replay.unwrap().playing);
+
+        // Seeking back re-applies the opening layout
+        graph.send(ControlReplay { command: ReplayCommand::Seek(0) }).await.unwrap().unwrap();
+        assert_eq!(graph.send(GetNodeMap).await.unwrap().unwrap()[&id].data.position.x, start.x);
+
+        graph.send(ControlReplay { command: ReplayCommand::Stop }).await.unwrap().unwrap();
+        let state = graph.send(GetRecordingState).await.unwrap().unwrap();
+        assert!(state.replay.is_none());
+
+        assert!(service.replay("../../etc/passwd", 1.0, &graph).await.is_err());
+        assert!(service.replay(&name, 100.0, &graph).await.is_err());
+    }
+}
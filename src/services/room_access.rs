@@ -0,0 +1,395 @@
+//! Who may join which room. Rooms start open. A room nobody uses yet is claimed by the
+//! first owner or invite call on it; one already in use only by an admin. From then on
+//! only its owner, its members and admins can join or see it.
+//! Invites are signed tokens that grant membership once and lapse at their expiry.
+
+use crate::config::feature_access::Role;
+use crate::models::spatial_anchor::DEFAULT_ROOM;
+use crate::services::webhook_service::hmac_sha256;
+use crate::utils::auth::Identity;
+use crate::utils::json_store::write_json_atomic;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use log::{error, info};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use thiserror::Error;
+use uuid::Uuid;
+
+const ROOM_ACL_PATH: &str = "/app/data/rooms/acl.json";
+pub const DEFAULT_INVITE_TTL_SECS: i64 = 7 * 24 * 3600;
+const MAX_INVITE_TTL_SECS: i64 = 30 * 24 * 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomRole {
+    // Join the room and watch it
+    Viewer,
+    // Also change it, within what the member's global role allows
+    Editor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingInvite {
+    role: RoomRole,
+    expires_at: DateTime<Utc>,
+    created_by: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredAcl {
+    owner: String,
+    members: BTreeMap<String, RoomRole>,
+    // Invite id -> invite, until it is redeemed or found expired
+    #[serde(default)]
+    invites: HashMap<String, PendingInvite>,
+}
+
+/// A room's owner and members, as the API shows them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomAcl {
+    pub owner: String,
+    pub members: BTreeMap<String, RoomRole>,
+}
+
+impl From<&StoredAcl> for RoomAcl {
+    fn from(acl: &StoredAcl) -> Self {
+        Self { owner: acl.owner.clone(), members: acl.members.clone() }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RoomAclStore {
+    // Signs invite tokens
+    secret: String,
+    rooms: HashMap<String, StoredAcl>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomInvite {
+    pub room: String,
+    pub token: String,
+    pub role: RoomRole,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RoomAccessError {
+    #[error("You are not a member of room {0}")]
+    NotMember(String),
+    #[error("Only the owner of room {0} can manage its members and invites")]
+    NotOwner(String),
+    #[error("Room {0} is already in use; only an admin can restrict it")]
+    RoomInUse(String),
+    #[error("Sign in to manage room membership")]
+    AnonymousCaller,
+    #[error("The default room is open to everyone")]
+    DefaultRoom,
+    #[error("Invalid invite")]
+    InvalidInvite,
+    #[error("This invite has expired")]
+    InviteExpired,
+    #[error("{0}")]
+    Storage(String),
+}
+
+pub struct RoomAccessService {
+    store: RwLock<RoomAclStore>,
+    path: PathBuf,
+}
+
+impl Default for RoomAccessService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoomAccessService {
+    pub fn new() -> Self {
+        Self::with_path(PathBuf::from(ROOM_ACL_PATH))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        let mut store = match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<RoomAclStore>(&content) {
+                Ok(store) => {
+                    info!("Loaded access lists for {} rooms from {:?}", store.rooms.len(), path);
+                    store
+                }
+                Err(e) => {
+                    error!("Failed to parse room access file {:?}: {}. Starting empty.", path, e);
+                    RoomAclStore::default()
+                }
+            },
+            Err(_) => {
+                info!("No room access file at {:?}, all rooms are open", path);
+                RoomAclStore::default()
+            }
+        };
+        if store.secret.is_empty() {
+            let mut secret = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut secret);
+            store.secret = URL_SAFE_NO_PAD.encode(secret);
+        }
+
+        Self {
+            store: RwLock::new(store),
+            path,
+        }
+    }
+
+    fn persist(&self, store: &RoomAclStore) -> Result<(), RoomAccessError> {
+        write_json_atomic(&self.path, store).map_err(RoomAccessError::Storage)
+    }
+
+    /// The room's access list, or None while the room is open
+    pub fn acl(&self, room: &str) -> Option<RoomAcl> {
+        let store = self.store.read().unwrap();
+        store.rooms.get(room).map(RoomAcl::from)
+    }
+
+    /// Whether `identity` may join and see `room`. Open rooms and admins always pass.
+    pub fn can_access(&self, room: &str, identity: &Identity) -> bool {
+        if room == DEFAULT_ROOM || identity.role >= Role::Admin {
+            return true;
+        }
+        let store = self.store.read().unwrap();
+        match (store.rooms.get(room), identity.pubkey.as_deref()) {
+            (None, _) => true,
+            (Some(acl), Some(pubkey)) => acl.owner == pubkey || acl.members.contains_key(pubkey),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn check_join(&self, room: &str, identity: &Identity) -> Result<(), RoomAccessError> {
+        if self.can_access(room, identity) {
+            Ok(())
+        } else {
+            Err(RoomAccessError::NotMember(room.to_string()))
+        }
+    }
+
+    /// The room's access list as `identity` may see it: the owner is only shown to the
+    /// owner and admins
+    pub fn acl_for(&self, room: &str, identity: &Identity) -> Option<RoomAcl> {
+        let mut acl = self.acl(room)?;
+        if identity.role < Role::Admin && identity.pubkey.as_deref() != Some(acl.owner.as_str()) {
+            acl.owner.clear();
+        }
+        Some(acl)
+    }
+
+    /// The rooms in `rooms` the caller can see
+    pub fn visible<'a>(&self, rooms: impl IntoIterator<Item = &'a str>, identity: &Identity) -> Vec<String> {
+        rooms.into_iter().filter(|room| self.can_access(room, identity)).map(str::to_string).collect()
+    }
+
+    /// Every room with an access list
+    pub fn rooms(&self) -> Vec<String> {
+        self.store.read().unwrap().rooms.keys().cloned().collect()
+    }
+
+    // The room's list for a call that manages it, claiming an open room for the caller.
+    // An open room others already use (`in_use`) is only claimed by an admin.
+    fn managed<'a>(store: &'a mut RoomAclStore, room: &str, caller: &Identity, in_use: bool) -> Result<&'a mut StoredAcl, RoomAccessError> {
+        if room == DEFAULT_ROOM {
+            return Err(RoomAccessError::DefaultRoom);
+        }
+        let pubkey = caller.pubkey.clone().ok_or(RoomAccessError::AnonymousCaller)?;
+        if in_use && caller.role < Role::Admin && !store.rooms.contains_key(room) {
+            return Err(RoomAccessError::RoomInUse(room.to_string()));
+        }
+        let acl = store.rooms.entry(room.to_string()).or_insert_with(|| {
+            info!("Room {} claimed by {}", room, pubkey);
+            StoredAcl { owner: pubkey.clone(), members: BTreeMap::new(), invites: HashMap::new() }
+        });
+        if acl.owner != pubkey && caller.role < Role::Admin {
+            return Err(RoomAccessError::NotOwner(room.to_string()));
+        }
+        Ok(acl)
+    }
+
+    /// Adds or changes a member, or removes them when `role` is None. `in_use` says the
+    /// room already has anchors or settings, so claiming it takes an admin.
+    pub fn set_member(
+        &self,
+        room: &str,
+        caller: &Identity,
+        pubkey: &str,
+        role: Option<RoomRole>,
+        in_use: bool,
+    ) -> Result<RoomAcl, RoomAccessError> {
+        let mut store = self.store.write().unwrap();
+        let acl = Self::managed(&mut store, room, caller, in_use)?;
+        match role {
+            Some(role) => { acl.members.insert(pubkey.to_string(), role); }
+            None => { acl.members.remove(pubkey); }
+        }
+        let updated = RoomAcl::from(&*acl);
+        self.persist(&store)?;
+        Ok(updated)
+    }
+
+    /// A token that makes whoever redeems it first a member with `role`
+    pub fn create_invite(
+        &self,
+        room: &str,
+        caller: &Identity,
+        role: RoomRole,
+        ttl_secs: Option<i64>,
+        in_use: bool,
+        now: DateTime<Utc>,
+    ) -> Result<RoomInvite, RoomAccessError> {
+        let mut store = self.store.write().unwrap();
+        let secret = store.secret.clone();
+        let acl = Self::managed(&mut store, room, caller, in_use)?;
+        acl.invites.retain(|_, invite| invite.expires_at > now);
+
+        let ttl = ttl_secs.unwrap_or(DEFAULT_INVITE_TTL_SECS).clamp(1, MAX_INVITE_TTL_SECS);
+        // Tokens carry whole seconds, so the stored expiry does too
+        let expires_at = Utc.timestamp_opt((now + Duration::seconds(ttl)).timestamp(), 0).unwrap();
+        let id = Uuid::new_v4().simple().to_string();
+        acl.invites.insert(id.clone(), PendingInvite {
+            role,
+            expires_at,
+            created_by: caller.pubkey.clone().unwrap_or_default(),
+        });
+        let token = format!("{}.{}.{}", id, expires_at.timestamp(), invite_signature(&secret, room, &id, expires_at.timestamp(), role));
+        self.persist(&store)?;
+        Ok(RoomInvite { room: room.to_string(), token, role, expires_at })
+    }
+
+    /// Makes `pubkey` a member of `room` and uses the invite up
+    pub fn redeem(&self, room: &str, token: &str, pubkey: &str, now: DateTime<Utc>) -> Result<RoomRole, RoomAccessError> {
+        let mut parts = token.splitn(3, '.');
+        let (id, expires, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(expires), Some(signature)) => (id, expires, signature),
+            _ => return Err(RoomAccessError::InvalidInvite),
+        };
+        let expires: i64 = expires.parse().map_err(|_| RoomAccessError::InvalidInvite)?;
+
+        let mut store = self.store.write().unwrap();
+        let secret = store.secret.clone();
+        let acl = store.rooms.get_mut(room).ok_or(RoomAccessError::InvalidInvite)?;
+        let invite = acl.invites.get(id).ok_or(RoomAccessError::InvalidInvite)?;
+        let expected = invite_signature(&secret, room, id, expires, invite.role);
+        if expires != invite.expires_at.timestamp() || !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+            return Err(RoomAccessError::InvalidInvite);
+        }
+        if invite.expires_at <= now {
+            acl.invites.remove(id);
+            self.persist(&store)?;
+            return Err(RoomAccessError::InviteExpired);
+        }
+
+        let role = invite.role;
+        acl.invites.remove(id);
+        // Redeeming never lowers a role the member already has
+        let granted = *acl.members.entry(pubkey.to_string()).and_modify(|r| *r = (*r).max(role)).or_insert(role);
+        info!("{} joined room {} by invite as {:?}", pubkey, room, granted);
+        self.persist(&store)?;
+        Ok(granted)
+    }
+}
+
+fn invite_signature(secret: &str, room: &str, id: &str, expires: i64, role: RoomRole) -> String {
+    let message = format!("{}\n{}\n{}\n{:?}", room, id, expires, role);
+    URL_SAFE_NO_PAD.encode(hmac_sha256(secret.as_bytes(), message.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(pubkey: &str, role: Role) -> Identity {
+        Identity { pubkey: Some(pubkey.to_string()), role }
+    }
+
+    fn service(name: &str) -> (RoomAccessService, PathBuf) {
+        let path = std::env::temp_dir().join(format!("room-acl-{}-{}.json", name, Uuid::new_v4()));
+        (RoomAccessService::with_path(path.clone()), path)
+    }
+
+    #[test]
+    fn test_members_join_and_only_the_owner_manages() {
+        let (service, path) = service("members");
+        let (owner, member, stranger) = (user("owner", Role::Editor), user("member", Role::Viewer), user("stranger", Role::Editor));
+        let anonymous = Identity { pubkey: None, role: Role::Viewer };
+
+        // Open until claimed
+        assert!(service.can_access("vault", &stranger));
+        assert_eq!(service.set_member("vault", &anonymous, "member", Some(RoomRole::Viewer), false), Err(RoomAccessError::AnonymousCaller));
+        assert_eq!(service.set_member(DEFAULT_ROOM, &owner, "member", Some(RoomRole::Viewer), false), Err(RoomAccessError::DefaultRoom));
+        service.set_member("vault", &owner, "member", Some(RoomRole::Viewer), false).unwrap();
+
+        assert!(service.check_join("vault", &owner).is_ok());
+        assert!(service.check_join("vault", &member).is_ok());
+        assert_eq!(service.check_join("vault", &stranger), Err(RoomAccessError::NotMember("vault".into())));
+        assert!(service.check_join("vault", &anonymous).is_err());
+        assert!(service.check_join(DEFAULT_ROOM, &stranger).is_ok());
+        assert!(service.check_join("vault", &user("root", Role::Admin)).is_ok());
+        assert_eq!(service.visible(["vault", "lobby", DEFAULT_ROOM], &stranger), ["lobby", DEFAULT_ROOM]);
+
+        // Members can't manage, even to add themselves more rights; admins can
+        assert_eq!(service.set_member("vault", &member, "member", Some(RoomRole::Editor), false), Err(RoomAccessError::NotOwner("vault".into())));
+        assert!(service.create_invite("vault", &stranger, RoomRole::Viewer, None, false, Utc::now()).is_err());
+        service.set_member("vault", &user("root", Role::Admin), "member", None, false).unwrap();
+        assert!(service.check_join("vault", &member).is_err());
+
+        // Only the owner and admins see who owns it
+        assert_eq!(service.acl_for("vault", &owner).unwrap().owner, "owner");
+        assert_eq!(service.acl_for("vault", &user("root", Role::Admin)).unwrap().owner, "owner");
+        assert_eq!(service.acl_for("vault", &member).unwrap().owner, "");
+
+        // A room others already use can't be taken by the first editor to ask; an admin can
+        assert_eq!(service.set_member("lobby", &stranger, "stranger", Some(RoomRole::Editor), true), Err(RoomAccessError::RoomInUse("lobby".into())));
+        assert!(service.acl("lobby").is_none());
+        service.set_member("lobby", &user("root", Role::Admin), "member", Some(RoomRole::Viewer), true).unwrap();
+        assert_eq!(service.acl("lobby").unwrap().owner, "root");
+
+        // The list survives a restart
+        let reloaded = RoomAccessService::with_path(path.clone());
+        assert_eq!(reloaded.acl("vault").unwrap().owner, "owner");
+        assert!(reloaded.check_join("vault", &stranger).is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_invites_redeem_once_and_expire() {
+        let (service, path) = service("invites");
+        let owner = user("owner", Role::Editor);
+        let now = Utc::now();
+
+        let invite = service.create_invite("vault", &owner, RoomRole::Editor, Some(60), false, now).unwrap();
+        assert!(service.check_join("vault", &user("guest", Role::Viewer)).is_err());
+        // Tampered or misdirected tokens are rejected
+        let forged = format!("{}x", invite.token);
+        assert_eq!(service.redeem("vault", &forged, "guest", now), Err(RoomAccessError::InvalidInvite));
+        assert_eq!(service.redeem("lobby", &invite.token, "guest", now), Err(RoomAccessError::InvalidInvite));
+
+        // Outlives a restart, then works once
+        let service = RoomAccessService::with_path(path.clone());
+        assert_eq!(service.redeem("vault", &invite.token, "guest", now), Ok(RoomRole::Editor));
+        assert!(service.check_join("vault", &user("guest", Role::Viewer)).is_ok());
+        assert_eq!(service.redeem("vault", &invite.token, "other", now), Err(RoomAccessError::InvalidInvite));
+
+        let stale = service.create_invite("vault", &owner, RoomRole::Viewer, Some(60), false, now).unwrap();
+        assert_eq!(service.redeem("vault", &stale.token, "late", now + Duration::seconds(61)), Err(RoomAccessError::InviteExpired));
+        assert!(service.check_join("vault", &user("late", Role::Viewer)).is_err());
+        let _ = fs::remove_file(path);
+    }
+}
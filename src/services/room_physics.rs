@@ -0,0 +1,76 @@
+//! Physics overrides per room. Each room's loop runs with the global physics settings
+//! with its own overrides merged on top; overrides can be changed at runtime.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::config::{PhysicsOverrides, PhysicsSettings};
+use crate::models::simulation_params::SimulationParams;
+
+pub struct RoomPhysicsService {
+    overrides: RwLock<HashMap<String, PhysicsOverrides>>,
+}
+
+impl RoomPhysicsService {
+    pub fn new(overrides: HashMap<String, PhysicsOverrides>) -> Self {
+        Self { overrides: RwLock::new(overrides) }
+    }
+
+    pub fn overrides(&self, room: &str) -> PhysicsOverrides {
+        self.overrides.read().unwrap().get(room).cloned().unwrap_or_default()
+    }
+
+    /// Rooms with overrides of their own
+    pub fn rooms(&self) -> Vec<String> {
+        self.overrides.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Merges `patch` into the room's overrides and returns the result
+    pub fn patch(&self, room: &str, patch: &PhysicsOverrides) -> PhysicsOverrides {
+        let mut overrides = self.overrides.write().unwrap();
+        let entry = overrides.entry(room.to_string()).or_default();
+        entry.merge(patch);
+        entry.clone()
+    }
+
+    pub fn effective(&self, room: &str, global: &PhysicsSettings) -> PhysicsSettings {
+        self.overrides(room).apply(global)
+    }
+
+    /// What the room's physics loop runs with
+    pub fn params(&self, room: &str, global: &PhysicsSettings) -> SimulationParams {
+        SimulationParams::from(&self.effective(room, global))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global() -> PhysicsSettings {
+        PhysicsSettings { spring_strength: 0.5, repulsion_strength: 100.0, damping: 0.5, iterations: 100, ..Default::default() }
+    }
+
+    #[test]
+    fn test_rooms_run_with_their_own_overrides() {
+        let vault = PhysicsOverrides { spring_strength: Some(2.0), ..Default::default() };
+        let corporate = PhysicsOverrides { repulsion_strength: Some(800.0), ..Default::default() };
+        let service = RoomPhysicsService::new(HashMap::from([
+            ("vault".to_string(), vault),
+            ("corporate".to_string(), corporate),
+        ]));
+
+        let (vault, corporate) = (service.params("vault", &global()), service.params("corporate", &global()));
+        assert_eq!((vault.spring_strength, vault.repulsion), (2.0, 100.0));
+        assert_eq!((corporate.spring_strength, corporate.repulsion), (0.5, 800.0));
+        // Rooms without overrides get the global settings
+        assert_eq!(service.params("lobby", &global()).spring_strength, 0.5);
+
+        // A runtime patch touches only that room and keeps its other overrides
+        let patch = PhysicsOverrides { damping: Some(0.9), ..Default::default() };
+        let merged = service.patch("vault", &patch);
+        assert_eq!((merged.spring_strength, merged.damping), (Some(2.0), Some(0.9)));
+        assert_eq!(service.params("vault", &global()).damping, 0.9);
+        assert_eq!(service.params("corporate", &global()).damping, 0.5);
+    }
+}
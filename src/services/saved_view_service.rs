@@ -0,0 +1,294 @@
+//! Saved views: named combinations of filter, layout, colouring, edge visibility and a
+//! camera hint, persisted server-side. Applying one goes through the same runtime paths
+//! as setting each piece by hand.
+
+use actix::Addr;
+use chrono::Utc;
+use log::{error, info};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+use crate::actors::messages::{GetClientIdentity, GetGraphSnapshot, GetHiddenEdgeTypes, GetSimulationSettings, SendToClient, SetColorMapping, SetEdgeTypeVisibility, SetSimulationSettings};
+use crate::actors::{ClientManagerActor, GraphServiceActor};
+use crate::models::saved_view::{ResolvedView, SavedView, ViewScope};
+use crate::utils::aging;
+use crate::utils::coloring;
+use crate::utils::json_store::write_json_atomic;
+
+const SAVED_VIEWS_PATH: &str = "/app/data/views/saved_views.json";
+
+// name -> view
+type ViewStore = BTreeMap<String, SavedView>;
+
+/// What applying a view did, for the caller
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewApplication {
+    pub name: String,
+    pub scope: ViewScope,
+    #[serde(flatten)]
+    pub resolved: ResolvedView,
+    // Nodes recoloured room-wide; zero for client scope
+    pub recolored: usize,
+}
+
+pub struct SavedViewService {
+    store: RwLock<ViewStore>,
+    path: PathBuf,
+}
+
+impl Default for SavedViewService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SavedViewService {
+    pub fn new() -> Self {
+        Self::with_path(PathBuf::from(SAVED_VIEWS_PATH))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        let store = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<ViewStore>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse saved views {:?}: {}. Starting empty.", path, e);
+                ViewStore::new()
+            }),
+            Err(_) => ViewStore::new(),
+        };
+        info!("Loaded {} saved views", store.len());
+        Self { store: RwLock::new(store), path }
+    }
+
+    pub async fn list(&self) -> Vec<SavedView> {
+        self.store.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, name: &str) -> Option<SavedView> {
+        self.store.read().await.get(name).cloned()
+    }
+
+    /// Stores a validated view. Returns None, storing nothing, if a view of that name
+    /// already exists and `replace` wasn't set.
+    pub async fn save(&self, mut view: SavedView, author: &str, replace: bool) -> Result<Option<SavedView>, String> {
+        view.name = view.name.trim().to_string();
+        view.created_by = author.to_string();
+        view.updated_at = Utc::now().timestamp_millis();
+
+        let mut store = self.store.write().await;
+        if store.contains_key(&view.name) && !replace {
+            return Ok(None);
+        }
+        let mut updated = store.clone();
+        updated.insert(view.name.clone(), view.clone());
+        write_json_atomic(&self.path, &updated)?;
+        *store = updated;
+        Ok(Some(view))
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<Option<SavedView>, String> {
+        let mut store = self.store.write().await;
+        if !store.contains_key(name) {
+            return Ok(None);
+        }
+        let mut updated = store.clone();
+        let removed = updated.remove(name);
+        write_json_atomic(&self.path, &updated)?;
+        *store = updated;
+        Ok(removed)
+    }
+
+    /// Applies `view` for `client_id`. The client is sent a `view_applied` message with the
+    /// filter's nodes, focus, camera and edge visibility. Colouring and layout mode change
+    /// for everyone with room scope; with client scope the colours only go to this client
+    /// and the mode, which has no per-client form, is left alone.
+    pub async fn apply(
+        view: &SavedView,
+        scope: ViewScope,
+        client_id: usize,
+        graph_addr: &Addr<GraphServiceActor>,
+        client_manager_addr: &Addr<ClientManagerActor>,
+    ) -> Result<ViewApplication, String> {
+        if client_manager_addr.send(GetClientIdentity { client_id }).await.map_err(|e| e.to_string())?.is_none() {
+            return Err(format!("Client {} is not connected", client_id));
+        }
+        let graph = graph_addr.send(GetGraphSnapshot).await.map_err(|e| e.to_string())??;
+        let graph = aging::snapshot_without_archived(graph);
+        let mut resolved = view.resolve(&graph.nodes);
+
+        let mut edges = Vec::new();
+        if !view.edge_types.is_empty() {
+            let shown = client_manager_addr.send(SetEdgeTypeVisibility { client_id, types: view.edge_types.clone() })
+                .await.map_err(|e| e.to_string())??;
+            edges = graph.edges.iter().filter(|e| shown.iter().any(|t| t == e.type_name())).cloned().collect();
+        }
+        let hidden_edge_types = client_manager_addr.send(GetHiddenEdgeTypes { client_id }).await.map_err(|e| e.to_string())?;
+
+        let mut recolored = 0;
+        let mut colors = None;
+        if let Some(mapping) = &view.color_mapping {
+            match scope {
+                ViewScope::Room => {
+                    recolored = graph_addr.send(SetColorMapping { mapping: mapping.clone() }).await.map_err(|e| e.to_string())??;
+                }
+                ViewScope::Client => colors = Some(coloring::compute_colors(mapping, &graph.nodes, &graph.edges)),
+            }
+        }
+        if let Some(mode) = view.layout.mode {
+            match scope {
+                ViewScope::Room => {
+                    let mut settings = graph_addr.send(GetSimulationSettings).await.map_err(|e| e.to_string())??;
+                    settings.mode = mode;
+                    graph_addr.send(SetSimulationSettings { settings }).await.map_err(|e| e.to_string())??;
+                }
+                ViewScope::Client => resolved.notices.push(format!("Layout mode {:?} only applies room-wide; left unchanged", mode)),
+            }
+        }
+
+        let message = json!({
+            "type": "view_applied",
+            "name": view.name,
+            "scope": scope,
+            "filter": view.filter,
+            "nodeIds": resolved.node_ids,
+            "focusNodeId": resolved.focus_node_id,
+            "camera": view.camera,
+            "colorMapping": view.color_mapping,
+            "colors": colors.unwrap_or_else(HashMap::new),
+            "hiddenEdgeTypes": hidden_edge_types,
+            "edges": edges,
+            "notices": resolved.notices,
+        });
+        client_manager_addr.send(SendToClient { client_id, message: message.to_string() }).await.map_err(|e| e.to_string())??;
+        info!("Applied view {:?} for client {} ({:?} scope)", view.name, client_id, scope);
+        Ok(ViewApplication { name: view.name.clone(), scope, resolved, recolored })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::client_manager_actor::ClientHandle;
+    use crate::actors::messages::{BuildGraphFromMetadata, CloseConnection, GetColorMapping, RegisterSimulatedClient, SendToClientBinary, SendToClientText, StopSimulation};
+    use crate::config::feature_access::Role;
+    use crate::config::{ColorMappingSettings, ColorStrategy};
+    use crate::models::graph_filter::GraphFilter;
+    use crate::models::metadata::{Metadata, MetadataStore};
+    use crate::models::saved_view::ViewLayout;
+    use crate::models::simulation_params::SimulationMode;
+    use crate::utils::auth::Identity;
+    use actix::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    // A connected client that keeps the text it is sent
+    struct RecordingClient {
+        received: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl Actor for RecordingClient {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(serde_json::from_str(&msg.0).unwrap());
+        }
+    }
+
+    impl Handler<SendToClientBinary> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, _msg: SendToClientBinary, _ctx: &mut Self::Context) {}
+    }
+
+    impl Handler<CloseConnection> for RecordingClient {
+        type Result = ();
+        fn handle(&mut self, _msg: CloseConnection, _ctx: &mut Self::Context) {}
+    }
+
+    fn view(name: &str, focus: &str) -> SavedView {
+        SavedView {
+            name: name.to_string(),
+            filter: GraphFilter { text: Some("al".to_string()), ..Default::default() },
+            layout: ViewLayout { mode: Some(SimulationMode::Local), focus: Some(focus.to_string()) },
+            color_mapping: Some(ColorMappingSettings { strategy: ColorStrategy::ByPagerank, ..Default::default() }),
+            edge_types: HashMap::from([("similarity".to_string(), false)]),
+            camera: None,
+            created_by: String::new(),
+            updated_at: 0,
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("saved-views-{}", uuid::Uuid::new_v4())).join("saved_views.json")
+    }
+
+    #[actix_web::test]
+    async fn test_views_persist_across_restarts() {
+        let path = temp_path();
+        let service = SavedViewService::with_path(path.clone());
+        assert!(service.save(view(" research ", "alpha"), "editor", false).await.unwrap().is_some());
+        assert!(service.save(view("research", "beta"), "editor", false).await.unwrap().is_none());
+        service.save(view("second", "beta"), "editor", false).await.unwrap();
+
+        let reloaded = SavedViewService::with_path(path.clone());
+        assert_eq!(reloaded.list().await.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(), vec!["research", "second"]);
+        assert_eq!(reloaded.get("research").await.unwrap().layout.focus.as_deref(), Some("alpha"));
+        assert!(reloaded.delete("second").await.unwrap().is_some());
+        assert!(reloaded.delete("second").await.unwrap().is_none());
+        assert_eq!(SavedViewService::with_path(path.clone()).list().await.len(), 1);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_apply_per_scope_and_with_a_deleted_focus() {
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut store = MetadataStore::new();
+        for name in ["alpha.md", "palette.md", "gamma.md"] {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+        let snapshot = graph.send(GetGraphSnapshot).await.unwrap().unwrap();
+        let id = |name: &str| snapshot.nodes.iter().find(|n| n.metadata_id == name).unwrap().id;
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let socket = RecordingClient { received: received.clone() }.start();
+        let handle = ClientHandle { text: socket.clone().recipient(), binary: socket.clone().recipient(), close: socket.recipient() };
+        let identity = Identity { pubkey: None, role: Role::Viewer };
+        let client_id = client_manager.send(RegisterSimulatedClient { handle, identity }).await.unwrap().unwrap();
+        let last = || received.lock().unwrap().iter().rev().find(|m| m["type"] == "view_applied").cloned().unwrap();
+
+        // Client scope: colours and focus go to this client only; shared settings stay put
+        let applied = SavedViewService::apply(&view("research", "alpha"), ViewScope::Client, client_id, &graph, &client_manager).await.unwrap();
+        let mut expected = vec![id("alpha"), id("palette")];
+        expected.sort();
+        assert_eq!(applied.resolved.node_ids, expected);
+        assert_eq!(applied.resolved.focus_node_id, Some(id("alpha")));
+        assert_eq!(applied.recolored, 0);
+        assert_eq!(applied.resolved.notices.len(), 1);
+        let message = last();
+        assert_eq!(message["colors"].as_object().unwrap().len(), 3);
+        assert_eq!(message["hiddenEdgeTypes"], json!(["similarity"]));
+        assert_eq!(graph.send(GetColorMapping).await.unwrap().unwrap().strategy, ColorStrategy::None);
+        assert_eq!(graph.send(GetSimulationSettings).await.unwrap().unwrap().mode, SimulationMode::Remote);
+
+        // Room scope: the shared mapping and mode change; the focus was deleted by a rebuild
+        let applied = SavedViewService::apply(&view("research", "deleted"), ViewScope::Room, client_id, &graph, &client_manager).await.unwrap();
+        assert_eq!(applied.resolved.focus_node_id, None);
+        assert!(applied.resolved.notices[0].contains("focus dropped"));
+        assert_eq!(applied.recolored, 3);
+        let message = last();
+        assert!(message["focusNodeId"].is_null());
+        assert!(message["colors"].as_object().unwrap().is_empty());
+        assert_eq!(graph.send(GetColorMapping).await.unwrap().unwrap().strategy, ColorStrategy::ByPagerank);
+        assert_eq!(graph.send(GetSimulationSettings).await.unwrap().unwrap().mode, SimulationMode::Local);
+
+        assert!(SavedViewService::apply(&view("research", "alpha"), ViewScope::Client, 999, &graph, &client_manager).await.is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Startup warm-up for the speech providers. Right after boot the TTS and STT backends
+//! often aren't accepting requests yet, so each capability is probed in the background,
+//! with a tiny utterance for TTS and a short silent buffer for STT, and retried with
+//! backoff until it answers. Until then requests for it get a `ProviderWarmingUp` error
+//! with an estimate of when to retry, rather than whatever the provider failed with.
+
+use async_trait::async_trait;
+use log::{info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::config::AppFullSettings;
+use crate::types::speech::{ProviderWarmingUp, SpeechCapability, STTProvider, TTSProvider};
+use crate::utils::audio_resample::wav_bytes;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// Whisper's default address, as the speech service uses it
+const DEFAULT_WHISPER_URL: &str = "http://172.18.0.4:8000";
+// Silence sent to STT, long enough for the provider to accept it
+const PROBE_SILENCE_MS: u32 = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeOutcome {
+    Ready,
+    Failed(String),
+    // Nothing configured to warm up; requests fail as they would have anyway
+    Unavailable(String),
+}
+
+/// One warm-up attempt against the provider behind a capability
+#[async_trait]
+pub trait SpeechProbe: Send + Sync {
+    async fn probe(&self, capability: SpeechCapability) -> ProbeOutcome;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WarmupBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for WarmupBackoff {
+    fn default() -> Self {
+        Self { initial: Duration::from_secs(1), max: Duration::from_secs(60) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "state")]
+pub enum CapabilityStatus {
+    Warming {
+        attempts: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_error: Option<String>,
+        retry_after_secs: f32,
+    },
+    Ready,
+    Unavailable { reason: String },
+}
+
+impl CapabilityStatus {
+    // Unavailable capabilities have nothing to wait for
+    pub fn settled(&self) -> bool {
+        !matches!(self, CapabilityStatus::Warming { .. })
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Warming { attempts: u32, last_error: Option<String>, next_attempt: Instant },
+    Ready,
+    Unavailable(String),
+}
+
+/// Per-capability readiness, shared by the warm-up task, the speech service and health checks
+#[derive(Debug)]
+pub struct SpeechReadiness {
+    states: Mutex<HashMap<SpeechCapability, State>>,
+}
+
+impl SpeechReadiness {
+    pub fn new(now: Instant) -> Self {
+        let states = SpeechCapability::ALL.iter()
+            .map(|&capability| (capability, State::Warming { attempts: 0, last_error: None, next_attempt: now }))
+            .collect();
+        Self { states: Mutex::new(states) }
+    }
+
+    fn set(&self, capability: SpeechCapability, state: State) {
+        self.states.lock().unwrap().insert(capability, state);
+    }
+
+    pub fn mark_ready(&self, capability: SpeechCapability) {
+        self.set(capability, State::Ready);
+    }
+
+    pub fn mark_unavailable(&self, capability: SpeechCapability, reason: String) {
+        self.set(capability, State::Unavailable(reason));
+    }
+
+    fn mark_failed(&self, capability: SpeechCapability, error: String, next_attempt: Instant) {
+        let mut states = self.states.lock().unwrap();
+        let attempts = match states.get(&capability) {
+            Some(State::Warming { attempts, .. }) => attempts + 1,
+            _ => 1,
+        };
+        states.insert(capability, State::Warming { attempts, last_error: Some(error), next_attempt });
+    }
+
+    /// Ok once the capability can take requests; otherwise the error to hand the caller
+    pub fn check(&self, capability: SpeechCapability, now: Instant) -> Result<(), ProviderWarmingUp> {
+        match self.states.lock().unwrap().get(&capability) {
+            Some(State::Warming { next_attempt, .. }) => Err(ProviderWarmingUp {
+                capability,
+                retry_after_secs: retry_after(*next_attempt, now),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn status(&self, now: Instant) -> BTreeMap<SpeechCapability, CapabilityStatus> {
+        self.states.lock().unwrap().iter()
+            .map(|(&capability, state)| {
+                let status = match state {
+                    State::Warming { attempts, last_error, next_attempt } => CapabilityStatus::Warming {
+                        attempts: *attempts,
+                        last_error: last_error.clone(),
+                        retry_after_secs: retry_after(*next_attempt, now),
+                    },
+                    State::Ready => CapabilityStatus::Ready,
+                    State::Unavailable(reason) => CapabilityStatus::Unavailable { reason: reason.clone() },
+                };
+                (capability, status)
+            })
+            .collect()
+    }
+}
+
+// A probe is in flight once its time has come, so there's always a little to wait
+fn retry_after(next_attempt: Instant, now: Instant) -> f32 {
+    next_attempt.saturating_duration_since(now).as_secs_f32().max(1.0)
+}
+
+/// Probes every capability in the background until each is ready or found unavailable.
+/// Returns straight away.
+pub fn spawn_warm_up(readiness: Arc<SpeechReadiness>, probe: Arc<dyn SpeechProbe>, backoff: WarmupBackoff) {
+    for capability in SpeechCapability::ALL {
+        let (readiness, probe) = (Arc::clone(&readiness), Arc::clone(&probe));
+        tokio::spawn(async move {
+            let mut delay = backoff.initial;
+            loop {
+                match probe.probe(capability).await {
+                    ProbeOutcome::Ready => {
+                        info!("Speech {} provider is ready", capability.name());
+                        readiness.mark_ready(capability);
+                        return;
+                    }
+                    ProbeOutcome::Unavailable(reason) => {
+                        info!("Speech {} not warmed up: {}", capability.name(), reason);
+                        readiness.mark_unavailable(capability, reason);
+                        return;
+                    }
+                    ProbeOutcome::Failed(e) => {
+                        warn!("Speech {} warm-up failed, retrying in {:?}: {}", capability.name(), delay, e);
+                        readiness.mark_failed(capability, e, Instant::now() + delay);
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(backoff.max);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Probes the configured Kokoro and Whisper endpoints the way the speech service calls them
+pub struct HttpSpeechProbe {
+    client: Client,
+    settings: Arc<RwLock<AppFullSettings>>,
+    tts: TTSProvider,
+    stt: STTProvider,
+}
+
+impl HttpSpeechProbe {
+    pub fn new(settings: Arc<RwLock<AppFullSettings>>, tts: TTSProvider, stt: STTProvider) -> Self {
+        let client = Client::builder().timeout(PROBE_TIMEOUT).build().unwrap_or_default();
+        Self { client, settings, tts, stt }
+    }
+
+    async fn probe_tts(&self) -> ProbeOutcome {
+        if matches!(self.tts, TTSProvider::OpenAI) {
+            return ProbeOutcome::Unavailable("OpenAI TTS is not implemented".to_string());
+        }
+        let Some(config) = self.settings.read().await.kokoro.clone() else {
+            return ProbeOutcome::Unavailable("Kokoro is not configured".to_string());
+        };
+        let Some(api_url) = config.api_url.filter(|url| !url.is_empty()) else {
+            return ProbeOutcome::Unavailable("Kokoro API URL is not configured".to_string());
+        };
+        let body = serde_json::json!({
+            "model": "kokoro",
+            "input": ".",
+            "voice": config.default_voice.unwrap_or_else(|| "af_heart".to_string()),
+            "response_format": "wav",
+            "stream": false,
+        });
+        let url = format!("{}/v1/audio/speech", api_url.trim_end_matches('/'));
+        match self.client.post(&url).json(&body).send().await {
+            Ok(response) if response.status().is_success() => match response.bytes().await {
+                Ok(audio) if !audio.is_empty() => ProbeOutcome::Ready,
+                Ok(_) => ProbeOutcome::Failed("Kokoro returned no audio".to_string()),
+                Err(e) => ProbeOutcome::Failed(format!("Failed to read Kokoro audio: {}", e)),
+            },
+            Ok(response) => ProbeOutcome::Failed(format!("Kokoro API error {}", response.status())),
+            Err(e) => ProbeOutcome::Failed(format!("Failed to connect to Kokoro API: {}", e)),
+        }
+    }
+
+    async fn probe_stt(&self) -> ProbeOutcome {
+        if matches!(self.stt, STTProvider::OpenAI) {
+            return ProbeOutcome::Unavailable("OpenAI STT is not implemented".to_string());
+        }
+        let Some(config) = self.settings.read().await.whisper.clone() else {
+            return ProbeOutcome::Unavailable("Whisper is not configured".to_string());
+        };
+        let api_url = config.api_url.unwrap_or_else(|| DEFAULT_WHISPER_URL.to_string());
+        let format = self.stt.input_format();
+        let silence = vec![0i16; (format.sample_rate * PROBE_SILENCE_MS / 1000) as usize];
+        let part = match reqwest::multipart::Part::bytes(wav_bytes(format, &silence)).file_name("probe.wav").mime_str("audio/wav") {
+            Ok(part) => part,
+            Err(e) => return ProbeOutcome::Failed(e.to_string()),
+        };
+        let url = format!("{}/transcription/", api_url.trim_end_matches('/'));
+        match self.client.post(&url).multipart(reqwest::multipart::Form::new().part("file", part)).send().await {
+            Ok(response) if response.status().is_success() => ProbeOutcome::Ready,
+            Ok(response) => ProbeOutcome::Failed(format!("Whisper API error {}", response.status())),
+            Err(e) => ProbeOutcome::Failed(format!("Failed to connect to Whisper API: {}", e)),
+        }
+    }
+}
+
+#[async_trait]
+impl SpeechProbe for HttpSpeechProbe {
+    async fn probe(&self, capability: SpeechCapability) -> ProbeOutcome {
+        match capability {
+            SpeechCapability::Tts => self.probe_tts().await,
+            SpeechCapability::Stt => self.probe_stt().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // TTS answers on its third try after a slow start; STT has nothing configured
+    struct SlowProvider {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl SpeechProbe for SlowProvider {
+        async fn probe(&self, capability: SpeechCapability) -> ProbeOutcome {
+            if capability == SpeechCapability::Stt {
+                return ProbeOutcome::Unavailable("Whisper is not configured".to_string());
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            match self.calls.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => ProbeOutcome::Failed("connection refused".to_string()),
+                _ => ProbeOutcome::Ready,
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_requests_fail_with_a_retry_hint_until_the_provider_answers() {
+        let readiness = Arc::new(SpeechReadiness::new(Instant::now()));
+        let backoff = WarmupBackoff { initial: Duration::from_millis(30), max: Duration::from_millis(50) };
+        spawn_warm_up(readiness.clone(), Arc::new(SlowProvider { calls: AtomicU32::new(0) }), backoff);
+
+        let error = readiness.check(SpeechCapability::Tts, Instant::now()).unwrap_err();
+        assert_eq!(error.capability, SpeechCapability::Tts);
+        assert!(error.retry_after_secs >= 1.0);
+        let message: serde_json::Value = serde_json::from_str(&error.to_ws_message()).unwrap();
+        assert_eq!((message["code"].as_str(), message["capability"].as_str()), (Some("provider_warming_up"), Some("tts")));
+
+        // The first failure is on record before the provider comes up
+        tokio::time::sleep(Duration::from_millis(35)).await;
+        match &readiness.status(Instant::now())[&SpeechCapability::Tts] {
+            CapabilityStatus::Warming { attempts, last_error, .. } => {
+                assert_eq!((*attempts, last_error.as_deref()), (1, Some("connection refused")));
+            }
+            status => panic!("expected warming, got {:?}", status),
+        }
+        assert!(readiness.check(SpeechCapability::Stt, Instant::now()).is_ok());
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(readiness.check(SpeechCapability::Tts, Instant::now()).is_ok());
+        let status = readiness.status(Instant::now());
+        assert_eq!(status[&SpeechCapability::Tts], CapabilityStatus::Ready);
+        assert!(status.values().all(CapabilityStatus::settled));
+    }
+}
@@ -0,0 +1,352 @@
+//! Inbound speech audio kept for debugging transcripts. While an admin has recording on
+//! for a session, every segment sent for transcription is written out as the provider
+//! received it, after resampling, one WAV file per segment under
+//! `<directory>/<session>/<segment>.wav`. Each session keeps its newest
+//! `max_segments_per_session`; anything older than the retention period is deleted; and
+//! once the files reach `max_total_bytes` recording stops until deletions make room.
+
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+use crate::config::SpeechRecordingSettings;
+use crate::utils::audio_resample::{parse_wav, PcmFormat, BITS_PER_SAMPLE};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RecordError {
+    #[error("speech recording quota of {0} bytes is used up")]
+    QuotaExceeded(u64),
+    #[error("not recordable audio: {0}")]
+    NotWav(String),
+    #[error("invalid session id {0:?}")]
+    InvalidSession(String),
+    #[error("failed to write recording: {0}")]
+    Io(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentInfo {
+    pub segment_id: u64,
+    pub bytes: u64,
+    pub format: PcmFormat,
+    pub duration_secs: f32,
+    pub age_secs: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingStoreStatus {
+    // False while the quota is used up
+    pub accepting: bool,
+    pub total_bytes: u64,
+    pub max_total_bytes: u64,
+    pub sessions: usize,
+    pub segments: usize,
+}
+
+struct Segment {
+    bytes: u64,
+    format: PcmFormat,
+    recorded_at: Instant,
+}
+
+#[derive(Default)]
+struct Recordings {
+    sessions: HashMap<String, BTreeMap<u64, Segment>>,
+    total_bytes: u64,
+    quota_exceeded: bool,
+}
+
+pub struct SpeechRecordingStore {
+    settings: SpeechRecordingSettings,
+    directory: PathBuf,
+    recordings: Mutex<Recordings>,
+}
+
+// Session ids become directory names, so only the characters uuids are made of
+fn valid_session_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+impl SpeechRecordingStore {
+    /// Picks up what an earlier run recorded, so retention and the quota count it
+    pub fn new(settings: SpeechRecordingSettings) -> Self {
+        let directory = PathBuf::from(&settings.directory);
+        let store = Self { settings, directory, recordings: Mutex::new(Recordings::default()) };
+        store.load_existing(Instant::now());
+        store
+    }
+
+    fn retention(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.retention_secs.max(0.0))
+    }
+
+    fn segment_path(&self, session_id: &str, segment_id: u64) -> PathBuf {
+        self.directory.join(session_id).join(format!("{:06}.wav", segment_id))
+    }
+
+    fn load_existing(&self, now: Instant) {
+        let Ok(sessions) = std::fs::read_dir(&self.directory) else {
+            return;
+        };
+        let mut recordings = self.recordings.lock().unwrap();
+        for session_dir in sessions.flatten() {
+            let session_id = session_dir.file_name().to_string_lossy().to_string();
+            let Ok(files) = std::fs::read_dir(session_dir.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let Some(segment_id) = file.path().file_stem().and_then(|s| s.to_str()?.parse::<u64>().ok()) else {
+                    continue;
+                };
+                let Some(segment) = Self::read_segment(&file.path(), now) else {
+                    continue;
+                };
+                recordings.total_bytes += segment.bytes;
+                recordings.sessions.entry(session_id.clone()).or_default().insert(segment_id, segment);
+            }
+        }
+        let segments: usize = recordings.sessions.values().map(BTreeMap::len).sum();
+        if segments > 0 {
+            info!("Found {} recorded speech segments ({} bytes) from an earlier run", segments, recordings.total_bytes);
+        }
+    }
+
+    // A segment file written by an earlier run, dated by its modification time
+    fn read_segment(path: &Path, now: Instant) -> Option<Segment> {
+        let data = std::fs::read(path).ok()?;
+        let (format, _) = parse_wav(&data)?.ok()?;
+        let age = std::fs::metadata(path).ok()?.modified().ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        Some(Segment { bytes: data.len() as u64, format, recorded_at: now.checked_sub(age).unwrap_or(now) })
+    }
+
+    /// Writes one segment of WAV audio for the session and returns its id
+    pub fn record(&self, session_id: &str, wav: &[u8], now: Instant) -> Result<u64, RecordError> {
+        if !valid_session_id(session_id) {
+            return Err(RecordError::InvalidSession(session_id.to_string()));
+        }
+        let format = match parse_wav(wav) {
+            Some(Ok((format, _))) => format,
+            Some(Err(e)) => return Err(RecordError::NotWav(e)),
+            None => return Err(RecordError::NotWav("raw PCM in an undeclared format".to_string())),
+        };
+        self.purge_expired(now);
+        let mut recordings = self.recordings.lock().unwrap();
+        let bytes = wav.len() as u64;
+        if recordings.quota_exceeded || recordings.total_bytes + bytes > self.settings.max_total_bytes {
+            if !recordings.quota_exceeded {
+                warn!("Speech recordings reached their {} byte quota; recording is off until old segments expire",
+                    self.settings.max_total_bytes);
+                recordings.quota_exceeded = true;
+            }
+            return Err(RecordError::QuotaExceeded(self.settings.max_total_bytes));
+        }
+
+        let segment_id = recordings.sessions.get(session_id)
+            .and_then(|segments| segments.keys().next_back())
+            .map_or(1, |last| last + 1);
+        let path = self.segment_path(session_id, segment_id);
+        std::fs::create_dir_all(self.directory.join(session_id))
+            .and_then(|_| std::fs::write(&path, wav))
+            .map_err(|e| RecordError::Io(e.to_string()))?;
+        recordings.total_bytes += bytes;
+        let segments = recordings.sessions.entry(session_id.to_string()).or_default();
+        segments.insert(segment_id, Segment { bytes, format, recorded_at: now });
+
+        // Oldest segments rotate out past the per-session cap
+        let mut freed = 0;
+        while segments.len() > self.settings.max_segments_per_session.max(1) {
+            let Some((oldest, segment)) = segments.pop_first() else {
+                break;
+            };
+            let _ = std::fs::remove_file(self.segment_path(session_id, oldest));
+            freed += segment.bytes;
+        }
+        recordings.total_bytes -= freed;
+        Ok(segment_id)
+    }
+
+    /// The segment as a WAV file
+    pub fn audio(&self, session_id: &str, segment_id: u64, now: Instant) -> Option<Vec<u8>> {
+        self.purge_expired(now);
+        let known = self.recordings.lock().unwrap().sessions.get(session_id)
+            .is_some_and(|segments| segments.contains_key(&segment_id));
+        if !known {
+            return None;
+        }
+        std::fs::read(self.segment_path(session_id, segment_id)).ok()
+    }
+
+    /// A session's recorded segments, oldest first
+    pub fn segments(&self, session_id: &str, now: Instant) -> Vec<SegmentInfo> {
+        self.purge_expired(now);
+        let recordings = self.recordings.lock().unwrap();
+        let Some(segments) = recordings.sessions.get(session_id) else {
+            return Vec::new();
+        };
+        segments.iter()
+            .map(|(segment_id, segment)| {
+                let frame_bytes = segment.format.channels as u64 * (BITS_PER_SAMPLE / 8) as u64;
+                let frames = segment.bytes.saturating_sub(44) / frame_bytes.max(1);
+                SegmentInfo {
+                    segment_id: *segment_id,
+                    bytes: segment.bytes,
+                    format: segment.format,
+                    duration_secs: frames as f32 / segment.format.sample_rate as f32,
+                    age_secs: now.saturating_duration_since(segment.recorded_at).as_secs_f32(),
+                }
+            })
+            .collect()
+    }
+
+    /// Deletes segments past the retention period; recording resumes once the quota has room
+    pub fn purge_expired(&self, now: Instant) -> usize {
+        let retention = self.retention();
+        let mut recordings = self.recordings.lock().unwrap();
+        let mut removed = Vec::new();
+        for (session_id, segments) in recordings.sessions.iter_mut() {
+            segments.retain(|segment_id, segment| {
+                let keep = now.saturating_duration_since(segment.recorded_at) < retention;
+                if !keep {
+                    removed.push((self.segment_path(session_id, *segment_id), segment.bytes));
+                }
+                keep
+            });
+        }
+        recordings.sessions.retain(|session_id, segments| {
+            if segments.is_empty() {
+                let _ = std::fs::remove_dir(self.directory.join(session_id));
+            }
+            !segments.is_empty()
+        });
+        for (path, bytes) in &removed {
+            let _ = std::fs::remove_file(path);
+            recordings.total_bytes -= bytes;
+        }
+        if recordings.quota_exceeded && !removed.is_empty() && recordings.total_bytes < self.settings.max_total_bytes {
+            info!("Expired speech recordings freed room under the quota; recording is back on");
+            recordings.quota_exceeded = false;
+        }
+        removed.len()
+    }
+
+    /// Whether anything would be recorded right now
+    pub fn accepting(&self) -> bool {
+        !self.recordings.lock().unwrap().quota_exceeded
+    }
+
+    pub fn status(&self) -> RecordingStoreStatus {
+        let recordings = self.recordings.lock().unwrap();
+        RecordingStoreStatus {
+            accepting: !recordings.quota_exceeded,
+            total_bytes: recordings.total_bytes,
+            max_total_bytes: self.settings.max_total_bytes,
+            sessions: recordings.sessions.len(),
+            segments: recordings.sessions.values().map(BTreeMap::len).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::audio_resample::{wav_bytes, AudioInput, AudioStreams};
+
+    fn store(max_total_bytes: u64, max_segments_per_session: usize) -> (SpeechRecordingStore, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("speech-recordings-{}", uuid::Uuid::new_v4()));
+        let settings = SpeechRecordingSettings {
+            directory: directory.to_string_lossy().to_string(),
+            retention_secs: 60.0,
+            max_total_bytes,
+            max_segments_per_session,
+        };
+        (SpeechRecordingStore::new(settings), directory)
+    }
+
+    // Half a second of a 440Hz tone from a 48kHz stereo headset, preprocessed for a 16kHz mono provider
+    fn preprocessed_tone() -> Vec<u8> {
+        let input = PcmFormat { sample_rate: 48_000, channels: 2 };
+        let samples: Vec<i16> = (0..24_000)
+            .flat_map(|n| {
+                let value = ((n as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 8000.0) as i16;
+                [value, value]
+            })
+            .collect();
+        let chunk = wav_bytes(input, &samples);
+        let stream = AudioInput { stream_id: "s".into(), format: None, normalize_gain: false, record_as: None };
+        AudioStreams::default().prepare(&stream, &chunk, PcmFormat::mono(16_000)).unwrap()
+    }
+
+    #[test]
+    fn test_segments_come_back_as_valid_wav() {
+        let (store, directory) = store(10_000_000, 2);
+        let start = Instant::now();
+        let session = "4a1c-session";
+        let audio = preprocessed_tone();
+
+        assert_eq!(store.record(session, &audio, start), Ok(1));
+        let wav = store.audio(session, 1, start).unwrap();
+        assert_eq!(wav, audio);
+        assert_eq!((&wav[0..4], &wav[8..16], &wav[36..40]), (&b"RIFF"[..], &b"WAVEfmt "[..], &b"data"[..]));
+        let u16_at = |at: usize| u16::from_le_bytes([wav[at], wav[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(wav[at..at + 4].try_into().unwrap());
+        assert_eq!(u32_at(4) as usize, wav.len() - 8);
+        assert_eq!((u16_at(20), u16_at(22), u32_at(24)), (1, 1, 16_000));
+        assert_eq!((u32_at(28), u16_at(32), u16_at(34)), (32_000, 2, 16));
+        assert_eq!(u32_at(40) as usize, wav.len() - 44);
+        let info = &store.segments(session, start)[0];
+        assert!((info.duration_secs - 0.5).abs() < 0.01, "{} secs", info.duration_secs);
+
+        // Raw PCM with no declared format can't be told apart from noise, so isn't kept
+        assert!(matches!(store.record(session, &[0u8; 64], start), Err(RecordError::NotWav(_))));
+        assert!(matches!(store.record("../etc", &audio, start), Err(RecordError::InvalidSession(_))));
+
+        // Rotation keeps the newest two; retention empties the session
+        store.record(session, &audio, start).unwrap();
+        store.record(session, &audio, start).unwrap();
+        let ids: Vec<u64> = store.segments(session, start).iter().map(|s| s.segment_id).collect();
+        assert_eq!(ids, [2, 3]);
+        assert!(store.audio(session, 1, start).is_none());
+        assert!(!directory.join(session).join("000001.wav").exists());
+        assert_eq!(store.status().total_bytes, 2 * audio.len() as u64);
+        let later = start + Duration::from_secs(61);
+        assert!(store.segments(session, later).is_empty());
+        assert_eq!(store.status().total_bytes, 0);
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_quota_turns_recording_off_until_space_frees() {
+        let audio = preprocessed_tone();
+        let (store, directory) = store(audio.len() as u64 * 3 / 2, 10);
+        let start = Instant::now();
+
+        assert_eq!(store.record("a", &audio, start), Ok(1));
+        assert_eq!(store.record("b", &audio, start), Err(RecordError::QuotaExceeded(audio.len() as u64 * 3 / 2)));
+        assert!(!store.accepting());
+        // Stays off even for audio that would fit, until something expires
+        assert!(matches!(store.record("b", &audio[..100], start), Err(RecordError::QuotaExceeded(_))));
+        let status = store.status();
+        assert_eq!((status.accepting, status.segments, status.total_bytes), (false, 1, audio.len() as u64));
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(store.purge_expired(later), 1);
+        assert!(store.accepting());
+        assert_eq!(store.record("b", &audio, later), Ok(1));
+
+        // A restart picks the files up again
+        let settings = store.settings.clone();
+        let reloaded = SpeechRecordingStore::new(settings);
+        assert_eq!(reloaded.status().segments, 1);
+        // Dated by file times, so asked about now rather than on the test's clock
+        assert_eq!(reloaded.audio("b", 1, Instant::now()), Some(audio));
+        let _ = std::fs::remove_dir_all(directory);
+    }
+}
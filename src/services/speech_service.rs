@@ -3,20 +3,27 @@ use tokio_tungstenite::{connect_async, WebSocketStream, MaybeTlsStream, tungsten
 use tungstenite::http::Request;
 use serde_json::json;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::task;
 use tokio::sync::broadcast;
-use crate::config::AppFullSettings;
+use crate::config::{AppFullSettings, SpeechRecordingSettings, SpeechUtteranceSettings};
+use crate::services::speech_readiness::{self, CapabilityStatus, HttpSpeechProbe, SpeechReadiness, WarmupBackoff};
+use crate::services::speech_recording_store::{RecordError, SpeechRecordingStore};
+use crate::services::utterance_store::UtteranceStore;
 // use crate::config::Settings; // AppFullSettings is used from self.settings
-use log::{info, error, debug};
+use log::{info, error, debug, warn};
 use futures::{SinkExt, StreamExt};
 use std::error::Error;
 use tokio::net::TcpStream;
 use url::Url;
 use base64::Engine as _;
 use base64::engine::general_purpose::{STANDARD as BASE64};
-use crate::types::speech::{SpeechError, SpeechCommand, TTSProvider, STTProvider, SpeechOptions, TranscriptionOptions};
+use crate::types::speech::{ProviderWarmingUp, SpeechCapability, SpeechError, SpeechCommand, TTSProvider, STTProvider, SpeechOptions, TranscriptionOptions, TranscriptionSegment};
+use crate::utils::audio_resample::{AudioInput, AudioStreams};
 use reqwest::Client;
 
+// How often recorded inbound audio is checked for expiry
+const RECORDING_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
 /// Centralized speech service managing both Text-to-Speech (TTS) and Speech-to-Text (STT) operations
 ///
@@ -41,11 +48,17 @@ pub struct SpeechService {
     /// Buffer size of 100 allows multiple clients without blocking
     audio_tx: broadcast::Sender<Vec<u8>>,
     /// Broadcast channel for distributing STT transcription results to all connected clients
-    /// Each transcription result is sent as a String to all subscribers
-    transcription_tx: broadcast::Sender<String>,
+    /// Each transcription result is sent as a segment with its language to all subscribers
+    transcription_tx: broadcast::Sender<TranscriptionSegment>,
     /// Shared HTTP client for making API requests to external services (Kokoro, Whisper)
     /// Reused across all requests for connection pooling and efficiency
     http_client: Arc<Client>,
+    /// Complete TTS audio per utterance, for clients that download instead of streaming
+    utterances: Arc<UtteranceStore>,
+    /// Inbound audio of sessions an admin is recording, as sent for transcription
+    recordings: Arc<SpeechRecordingStore>,
+    /// Whether each capability's provider has answered its warm-up probe
+    readiness: Arc<SpeechReadiness>,
 }
 
 impl SpeechService {
@@ -53,6 +66,8 @@ impl SpeechService {
     ///
     /// # Arguments
     /// * `settings` - Shared application settings containing API configurations for TTS/STT providers
+    /// * `utterance_settings` - How long finished TTS audio stays downloadable
+    /// * `recording_settings` - Where recorded inbound audio goes and how much is kept
     ///
     /// # Returns
     /// * `SpeechService` - A new service instance ready for speech operations
@@ -68,7 +83,7 @@ impl SpeechService {
     /// - Command channel: 100 commands (prevents blocking on rapid command submission)
     /// - Audio broadcast: 100 audio chunks (handles multiple clients with buffering)
     /// - Transcription broadcast: 100 transcriptions (handles multiple clients with buffering)
-    pub fn new(settings: Arc<RwLock<AppFullSettings>>) -> Self {
+    pub fn new(settings: Arc<RwLock<AppFullSettings>>, utterance_settings: SpeechUtteranceSettings, recording_settings: SpeechRecordingSettings) -> Self {
         // Create internal command channel for async command processing
         let (tx, rx) = mpsc::channel(100);
         let sender = Arc::new(Mutex::new(tx));
@@ -93,6 +108,9 @@ impl SpeechService {
             audio_tx,
             transcription_tx,
             http_client,
+            utterances: Arc::new(UtteranceStore::new(utterance_settings)),
+            recordings: Arc::new(SpeechRecordingStore::new(recording_settings)),
+            readiness: Arc::new(SpeechReadiness::new(Instant::now())),
         };
 
         // Start the internal service task for async command processing
@@ -107,9 +125,23 @@ impl SpeechService {
         let stt_provider = Arc::clone(&self.stt_provider);
         let audio_tx = self.audio_tx.clone();
         let transcription_tx = self.transcription_tx.clone();
+        let utterances = Arc::clone(&self.utterances);
+        let recordings = Arc::clone(&self.recordings);
+
+        // Recordings past their retention are deleted even while nobody asks for them
+        let expiring = Arc::clone(&self.recordings);
+        task::spawn(async move {
+            let mut interval = tokio::time::interval(RECORDING_PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                expiring.purge_expired(Instant::now());
+            }
+        });
 
         task::spawn(async move {
             let mut ws_stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>> = None;
+            // Conversion state per audio stream; commands arrive in order, so chunks do too
+            let mut audio_streams = AudioStreams::default();
 
             while let Some(command) = receiver.recv().await {
                 match command {
@@ -263,12 +295,16 @@ impl SpeechService {
                         *current_provider = provider.clone();
                         info!("TTS provider updated to: {:?}", provider);
                     },
-                    SpeechCommand::TextToSpeech(text, options) => {
+                    SpeechCommand::TextToSpeech(text, options, utterance_id) => {
                         let provider = tts_provider.read().await.clone();
+                        let fail = |error: &str| {
+                            error!("{}", error);
+                            utterances.fail(&utterance_id, error.to_string(), Instant::now());
+                        };
 
                         match provider {
                             TTSProvider::OpenAI => {
-                                info!("TextToSpeech command with OpenAI provider not implemented");
+                                fail("TextToSpeech command with OpenAI provider not implemented");
                             },
                             TTSProvider::Kokoro => {
                                 info!("Processing TextToSpeech command with Kokoro provider");
@@ -281,14 +317,17 @@ impl SpeechService {
                                     let api_url_base = match config.api_url.as_deref() {
                                         Some(url) if !url.is_empty() => url,
                                         _ => {
-                                            error!("Kokoro API URL not configured or empty.");
+                                            fail("Kokoro API URL not configured or empty.");
                                             continue;
                                         }
                                     };
                                     let api_url = format!("{}/v1/audio/speech", api_url_base.trim_end_matches('/'));
                                     info!("Sending TTS request to Kokoro API: {}", api_url);
 
-                                    let response_format = config.default_format.as_deref().unwrap_or("mp3");
+                                    let response_format = options.format.as_deref()
+                                        .or(config.default_format.as_deref())
+                                        .unwrap_or("mp3");
+                                    utterances.set_format(&utterance_id, response_format);
 
                                     let request_body = json!({
                                         "model": "kokoro",
@@ -310,13 +349,13 @@ impl SpeechService {
                                             if !response.status().is_success() {
                                                 let status = response.status();
                                                 let error_text = response.text().await.unwrap_or_default();
-                                                error!("Kokoro API error {}: {}", status, error_text);
+                                                fail(&format!("Kokoro API error {}: {}", status, error_text));
                                                 continue;
                                             }
                                             response
                                         }
                                         Err(e) => {
-                                            error!("Failed to connect to Kokoro API: {}", e);
+                                            fail(&format!("Failed to connect to Kokoro API: {}", e));
                                             continue;
                                         }
                                     };
@@ -324,6 +363,7 @@ impl SpeechService {
                                     if options.stream {
                                         let stream = response.bytes_stream();
                                         let audio_broadcaster = audio_tx.clone();
+                                        let utterances = Arc::clone(&utterances);
 
                                         tokio::spawn(async move {
                                             let mut stream = Box::pin(stream);
@@ -331,21 +371,27 @@ impl SpeechService {
                                             while let Some(item) = stream.next().await {
                                                 match item {
                                                     Ok(bytes) => {
+                                                        utterances.append(&utterance_id, &bytes);
                                                         if let Err(e) = audio_broadcaster.send(bytes.to_vec()) {
                                                             error!("Failed to broadcast audio chunk: {}", e);
                                                         }
                                                     }
                                                     Err(e) => {
                                                         error!("Error receiving audio stream: {}", e);
+                                                        utterances.fail(&utterance_id, format!("Audio stream failed: {}", e), Instant::now());
                                                         break;
                                                     }
                                                 }
                                             }
+                                            // A no-op if the stream failed
+                                            utterances.complete(&utterance_id, Instant::now());
                                             debug!("Finished streaming audio from Kokoro");
                                         });
                                     } else {
                                         match response.bytes().await {
                                             Ok(bytes) => {
+                                                utterances.append(&utterance_id, &bytes);
+                                                utterances.complete(&utterance_id, Instant::now());
                                                 if let Err(e) = audio_tx.send(bytes.to_vec()) {
                                                     error!("Failed to send audio data: {}", e);
                                                 } else {
@@ -353,12 +399,12 @@ impl SpeechService {
                                                 }
                                             }
                                             Err(e) => {
-                                                error!("Failed to get audio bytes: {}", e);
+                                                fail(&format!("Failed to get audio bytes: {}", e));
                                             }
                                         }
                                     }
                                 } else {
-                                    error!("Kokoro configuration not found");
+                                    fail("Kokoro configuration not found");
                                 }
                             }
                         }
@@ -384,10 +430,10 @@ impl SpeechService {
                                     let api_url = config.api_url.as_deref().unwrap_or("http://172.18.0.4:8000");
                                     info!("Whisper STT initialized with API URL: {}", api_url);
 
-                                    let _ = transcription_tx.send("Whisper STT ready".to_string());
+                                    let _ = transcription_tx.send(TranscriptionSegment::status("Whisper STT ready"));
                                 } else {
                                     error!("Whisper configuration not found");
-                                    let _ = transcription_tx.send("Whisper STT configuration missing".to_string());
+                                    let _ = transcription_tx.send(TranscriptionSegment::status("Whisper STT configuration missing"));
                                 }
                             },
                             STTProvider::OpenAI => {
@@ -400,10 +446,27 @@ impl SpeechService {
                         info!("Stopping transcription");
                         // TODO: Implement stop logic
                     },
-                    SpeechCommand::ProcessAudioChunk(audio_data) => {
+                    SpeechCommand::EndAudioStream(stream_id) => audio_streams.end(&stream_id),
+                    SpeechCommand::ProcessAudioChunk(audio_data, options, input) => {
                         debug!("Processing audio chunk of size: {} bytes", audio_data.len());
 
                         let provider = stt_provider.read().await.clone();
+                        let audio_data = match audio_streams.prepare(&input, &audio_data, provider.input_format()) {
+                            Ok(audio_data) if audio_data.is_empty() => continue,
+                            Ok(audio_data) => audio_data,
+                            Err(e) => {
+                                warn!("Dropping audio chunk from {}: {}", input.stream_id, e);
+                                continue;
+                            }
+                        };
+                        if let Some(session_id) = &input.record_as {
+                            match recordings.record(session_id, &audio_data, Instant::now()) {
+                                Ok(segment_id) => debug!("Recorded segment {} of speech session {}", segment_id, session_id),
+                                // Logged once by the store when it fills up
+                                Err(RecordError::QuotaExceeded(_)) => {}
+                                Err(e) => warn!("Not recording audio of speech session {}: {}", session_id, e),
+                            }
+                        }
 
                         match provider {
                             STTProvider::Whisper => {
@@ -422,16 +485,23 @@ impl SpeechService {
                                             .mime_str("audio/wav").unwrap_or_else(|_| reqwest::multipart::Part::bytes(vec![]).mime_str("audio/wav").unwrap()));
 
                                     let mut form = form;
-                                    if let Some(model) = &config.default_model {
+                                    // The session's options win over the configured defaults
+                                    if let Some(model) = options.model.as_ref().or(config.default_model.as_ref()) {
                                         form = form.text("model", model.clone());
                                     }
-                                    if let Some(language) = &config.default_language {
+                                    // Whisper detects the language itself when none is given
+                                    let language = if options.is_auto_language() {
+                                        None
+                                    } else {
+                                        options.language.as_ref().or(config.default_language.as_ref())
+                                    };
+                                    if let Some(language) = language {
                                         form = form.text("language", language.clone());
                                     }
-                                    if let Some(temperature) = config.temperature {
+                                    if let Some(temperature) = options.temperature.or(config.temperature) {
                                         form = form.text("temperature", temperature.to_string());
                                     }
-                                    if let Some(vad_filter) = config.vad_filter {
+                                    if let Some(vad_filter) = options.vad_filter.or(config.vad_filter) {
                                         form = form.text("vad_filter", vad_filter.to_string());
                                     }
                                     if let Some(word_timestamps) = config.word_timestamps {
@@ -443,6 +513,7 @@ impl SpeechService {
 
                                     let http_client_clone = Arc::clone(&http_client);
                                     let transcription_broadcaster = transcription_tx.clone();
+                                    let default_language = config.default_language.clone();
 
                                     tokio::spawn(async move {
                                         match http_client_clone
@@ -455,13 +526,14 @@ impl SpeechService {
                                                 if response.status().is_success() {
                                                     match response.json::<serde_json::Value>().await {
                                                         Ok(json) => {
-                                                            if let Some(text) = json.get("text").and_then(|t| t.as_str()) {
-                                                                if !text.trim().is_empty() {
-                                                                    debug!("Whisper transcription: {}", text);
-                                                                    let _ = transcription_broadcaster.send(text.to_string());
-                                                                }
-                                                            } else {
+                                                            if json.get("text").and_then(|t| t.as_str()).is_none() {
                                                                 error!("No text field in Whisper response: {:?}", json);
+                                                            } else if let Some(segment) = segment_from_response(&json, &options, default_language.as_deref()) {
+                                                                debug!("Whisper transcription ({:?}): {}", segment.language, segment.text);
+                                                                if options.is_auto_language() && !segment.detected {
+                                                                    warn!("Whisper returned no language; using {:?}", segment.language);
+                                                                }
+                                                                let _ = transcription_broadcaster.send(segment);
                                                             }
                                                         }
                                                         Err(e) => {
@@ -494,6 +566,26 @@ impl SpeechService {
         });
     }
 
+    /// Starts probing the providers in the background; requests for a capability are
+    /// turned away with `ProviderWarmingUp` until its probe succeeds
+    pub async fn warm_up(&self) {
+        let probe = HttpSpeechProbe::new(
+            Arc::clone(&self.settings),
+            self.tts_provider.read().await.clone(),
+            self.stt_provider.read().await.clone(),
+        );
+        speech_readiness::spawn_warm_up(Arc::clone(&self.readiness), Arc::new(probe), WarmupBackoff::default());
+    }
+
+    /// Ok once `capability` can take requests
+    pub fn check_ready(&self, capability: SpeechCapability) -> Result<(), ProviderWarmingUp> {
+        self.readiness.check(capability, Instant::now())
+    }
+
+    pub fn readiness(&self) -> std::collections::BTreeMap<SpeechCapability, CapabilityStatus> {
+        self.readiness.status(Instant::now())
+    }
+
     pub async fn initialize(&self) -> Result<(), Box<dyn Error>> {
         let command = SpeechCommand::Initialize;
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
@@ -513,7 +605,8 @@ impl SpeechService {
     /// * `options` - Speech generation options including voice, speed, and streaming preferences
     ///
     /// # Returns
-    /// * `Ok(())` if the command was successfully queued for processing
+    /// * `Ok(utterance_id)` if the command was successfully queued for processing; the
+    ///   finished audio can be fetched from the utterance store under this id
     /// * `Err` if the command channel is closed or other error occurs
     ///
     /// # Behavior
@@ -521,10 +614,26 @@ impl SpeechService {
     /// - Audio output is broadcast to all subscribers via the audio channel
     /// - Supports both streaming and non-streaming audio generation
     /// - Uses Kokoro API by default with fallback error handling
-    pub async fn text_to_speech(&self, text: String, options: SpeechOptions) -> Result<(), Box<dyn Error>> {
-        let command = SpeechCommand::TextToSpeech(text, options);
-        self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
-        Ok(())
+    /// - Fails with `ProviderWarmingUp` until the TTS provider has answered its warm-up probe
+    pub async fn text_to_speech(&self, text: String, options: SpeechOptions) -> Result<String, Box<dyn Error>> {
+        self.check_ready(SpeechCapability::Tts)?;
+        let utterance_id = self.utterances.begin(Instant::now());
+        let command = SpeechCommand::TextToSpeech(text, options, utterance_id.clone());
+        if let Err(e) = self.sender.lock().await.send(command).await {
+            self.utterances.fail(&utterance_id, e.to_string(), Instant::now());
+            return Err(Box::new(SpeechError::from(e)));
+        }
+        Ok(utterance_id)
+    }
+
+    /// Finished TTS audio by utterance id
+    pub fn utterances(&self) -> Arc<UtteranceStore> {
+        Arc::clone(&self.utterances)
+    }
+
+    /// Recorded inbound audio by session and segment
+    pub fn recordings(&self) -> Arc<SpeechRecordingStore> {
+        Arc::clone(&self.recordings)
     }
 
     pub async fn close(&self) -> Result<(), Box<dyn Error>> {
@@ -557,6 +666,10 @@ impl SpeechService {
         self.tts_provider.read().await.clone()
     }
 
+    pub async fn get_stt_provider(&self) -> STTProvider {
+        self.stt_provider.read().await.clone()
+    }
+
     pub async fn set_stt_provider(&self, provider: STTProvider) -> Result<(), Box<dyn Error>> {
         let command = SpeechCommand::SetSTTProvider(provider);
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
@@ -564,6 +677,7 @@ impl SpeechService {
     }
 
     pub async fn start_transcription(&self, options: TranscriptionOptions) -> Result<(), Box<dyn Error>> {
+        self.check_ready(SpeechCapability::Stt)?;
         let command = SpeechCommand::StartTranscription(options);
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
         Ok(())
@@ -578,7 +692,9 @@ impl SpeechService {
     /// Processes audio data for speech-to-text transcription using the configured STT provider
     ///
     /// # Arguments
-    /// * `audio_data` - Raw audio bytes in WAV format from client microphone input
+    /// * `audio_data` - WAV or raw 16-bit PCM bytes from client microphone input
+    /// * `options` - The speaker's session options; unset fields use the Whisper config
+    /// * `input` - The stream the chunk belongs to and its declared format
     ///
     /// # Returns
     /// * `Ok(())` if the audio chunk was successfully queued for processing
@@ -586,12 +702,22 @@ impl SpeechService {
     ///
     /// # Behavior
     /// - Queues audio data for async STT processing by the service task
+    /// - Downmixes, resamples and optionally normalizes the audio to the provider's format,
+    ///   carrying conversion state across the stream's chunks
     /// - Sends audio to Whisper API at configured endpoint (default: http://172.18.0.4:8000)
     /// - Transcription results are broadcast to all subscribers via transcription channel
     /// - Supports configurable Whisper parameters (model, language, temperature, etc.)
     /// - Handles multipart form upload format required by Whisper-WebUI-Backend
-    pub async fn process_audio_chunk(&self, audio_data: Vec<u8>) -> Result<(), Box<dyn Error>> {
-        let command = SpeechCommand::ProcessAudioChunk(audio_data);
+    pub async fn process_audio_chunk(&self, audio_data: Vec<u8>, options: TranscriptionOptions, input: AudioInput) -> Result<(), Box<dyn Error>> {
+        self.check_ready(SpeechCapability::Stt)?;
+        let command = SpeechCommand::ProcessAudioChunk(audio_data, options, input);
+        self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
+        Ok(())
+    }
+
+    /// Drops the conversion state of a stream that won't send any more audio
+    pub async fn end_audio_stream(&self, stream_id: String) -> Result<(), Box<dyn Error>> {
+        let command = SpeechCommand::EndAudioStream(stream_id);
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
         Ok(())
     }
@@ -599,13 +725,88 @@ impl SpeechService {
     /// Creates a new subscriber to the transcription broadcast channel for receiving STT results
     ///
     /// # Returns
-    /// * `broadcast::Receiver<String>` - A receiver that will get all transcription text from STT operations
+    /// * `broadcast::Receiver<TranscriptionSegment>` - A receiver that will get all transcription segments from STT operations
     ///
     /// # Usage
     /// Multiple WebSocket connections can subscribe to receive the same transcription results simultaneously.
     /// Each subscriber gets its own independent receiver with a buffer to handle temporary disconnections.
-    /// Transcription results are broadcast as plain text strings from Whisper STT processing.
-    pub fn subscribe_to_transcriptions(&self) -> broadcast::Receiver<String> {
+    /// Each segment carries its text and the language it was detected or transcribed in.
+    pub fn subscribe_to_transcriptions(&self) -> broadcast::Receiver<TranscriptionSegment> {
         self.transcription_tx.subscribe()
     }
 }
+
+// Whisper reports full names ("german") in some modes and codes in others
+fn language_code(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    let code = match language.as_str() {
+        "english" => "en",
+        "german" => "de",
+        "french" => "fr",
+        "spanish" => "es",
+        "italian" => "it",
+        "dutch" => "nl",
+        "portuguese" => "pt",
+        "polish" => "pl",
+        "japanese" => "ja",
+        "chinese" => "zh",
+        _ => return language,
+    };
+    code.to_string()
+}
+
+/// The segment for one Whisper response, or None if nothing was said. In auto mode the
+/// language is the one Whisper detected; a response without one falls back to
+/// `default_language` and is marked as not detected.
+pub fn segment_from_response(json: &serde_json::Value, options: &TranscriptionOptions, default_language: Option<&str>) -> Option<TranscriptionSegment> {
+    let text = json.get("text").and_then(|t| t.as_str())?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    let reported = json.get("language").and_then(|l| l.as_str()).filter(|l| !l.trim().is_empty());
+    let (language, detected) = match (options.is_auto_language(), reported) {
+        (true, Some(reported)) => (Some(language_code(reported)), true),
+        (true, None) => (default_language.map(str::to_string), false),
+        (false, _) => (options.language.clone().or_else(|| default_language.map(str::to_string)), false),
+    };
+    Some(TranscriptionSegment { text: text.to_string(), language, detected, is_final: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detected_languages_reach_subscribers_per_segment() {
+        let auto = TranscriptionOptions { language: Some("auto".to_string()), ..Default::default() };
+        // A provider that detects English, then German, then stops reporting
+        let responses = [
+            json!({ "text": "Show me the roadmap", "language": "en" }),
+            json!({ "text": "und die Notizen von gestern", "language": "german" }),
+            json!({ "text": "   " }),
+            json!({ "text": "thanks" }),
+        ];
+        let (tx, mut rx) = broadcast::channel(8);
+        for response in &responses {
+            if let Some(segment) = segment_from_response(response, &auto, Some("en")) {
+                tx.send(segment).unwrap();
+            }
+        }
+        let mut received = Vec::new();
+        while let Ok(segment) = rx.try_recv() {
+            received.push((segment.text, segment.language, segment.detected));
+        }
+        assert_eq!(received, vec![
+            ("Show me the roadmap".to_string(), Some("en".to_string()), true),
+            ("und die Notizen von gestern".to_string(), Some("de".to_string()), true),
+            // No detection: the configured default, flagged
+            ("thanks".to_string(), Some("en".to_string()), false),
+        ]);
+
+        // A fixed language is reported as is, whatever the provider says
+        let fixed = TranscriptionOptions { language: Some("de".to_string()), ..Default::default() };
+        let segment = segment_from_response(&responses[0], &fixed, Some("en")).unwrap();
+        assert_eq!((segment.language.as_deref(), segment.detected), (Some("de"), false));
+    }
+}
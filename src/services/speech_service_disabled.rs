@@ -0,0 +1,33 @@
+//! Stand-in for `speech_service` in builds without the `speech` feature.
+//!
+//! There is no way to construct one, so `AppState::speech_service` is always `None`;
+//! the methods exist only so callers holding an `Option<Arc<SpeechService>>` compile.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use crate::services::speech_readiness::CapabilityStatus;
+use crate::types::speech::{SpeechCapability, SpeechError, SpeechOptions};
+
+const DISABLED: &str = "built without the speech feature";
+
+pub struct SpeechService {
+    _private: (),
+}
+
+impl SpeechService {
+    pub fn readiness(&self) -> BTreeMap<SpeechCapability, CapabilityStatus> {
+        SpeechCapability::ALL
+            .into_iter()
+            .map(|capability| (capability, CapabilityStatus::Unavailable { reason: DISABLED.to_string() }))
+            .collect()
+    }
+
+    pub async fn text_to_speech(&self, _text: String, _options: SpeechOptions) -> Result<String, Box<dyn Error>> {
+        Err(Box::new(SpeechError::TTSError(DISABLED.to_string())))
+    }
+
+    pub async fn close(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
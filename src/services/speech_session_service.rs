@@ -0,0 +1,346 @@
+//! Speech sessions. A session keeps the options a client speaks and listens with under an
+//! id the client holds on to, so they don't reset when its socket drops. A speech socket
+//! holds at most one session; once it lets go, the session lives on for the TTL and a
+//! reconnecting client can resume it.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SpeechSessionSettings;
+use crate::types::speech::{SpeechErrorMessage, SpeechOptions, TranscriptionOptions};
+use crate::utils::audio_resample::PcmFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VadMode {
+    // Whisper drops silence before transcribing
+    Auto,
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SessionOptions {
+    pub voice: Option<String>,
+    pub speed: Option<f32>,
+    pub language: Option<String>,
+    pub model: Option<String>,
+    pub vad_mode: Option<VadMode>,
+    // TTS output format: mp3, wav, opus...
+    pub audio_format: Option<String>,
+    // Whether voice commands in the session's transcriptions are acted on
+    pub command_mode: bool,
+    // Forward transcriptions to the speaker's room as live captions
+    pub share_captions: bool,
+    // Also forward provisional segments, not just final ones
+    pub share_interim_captions: bool,
+    // Name captions are shown under; defaults to a shortened pubkey
+    pub display_name: Option<String>,
+    // Format of the raw PCM the client streams for STT; WAV chunks carry their own
+    pub input_format: Option<PcmFormat>,
+    // Even out the microphone level before transcribing
+    pub normalize_gain: bool,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            voice: None,
+            speed: None,
+            language: None,
+            model: None,
+            vad_mode: None,
+            audio_format: None,
+            command_mode: true,
+            share_captions: false,
+            share_interim_captions: false,
+            display_name: None,
+            input_format: None,
+            normalize_gain: false,
+        }
+    }
+}
+
+impl SessionOptions {
+    /// TTS options: what the request asks for, then the session's, then `defaults`
+    pub fn speech_options(&self, voice: Option<String>, speed: Option<f32>, stream: Option<bool>, defaults: SpeechOptions) -> SpeechOptions {
+        SpeechOptions {
+            voice: voice.or_else(|| self.voice.clone()).unwrap_or(defaults.voice),
+            speed: speed.or(self.speed).unwrap_or(defaults.speed),
+            stream: stream.unwrap_or(defaults.stream),
+            format: self.audio_format.clone().or(defaults.format),
+        }
+    }
+
+    /// STT options: what the request asks for, then the session's. Anything still unset
+    /// falls back to the Whisper config.
+    pub fn transcription_options(&self, language: Option<String>, model: Option<String>) -> TranscriptionOptions {
+        TranscriptionOptions {
+            language: language.or_else(|| self.language.clone()),
+            model: model.or_else(|| self.model.clone()),
+            temperature: None,
+            stream: true,
+            vad_filter: self.vad_mode.map(|mode| mode == VadMode::Auto),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionError {
+    Unknown,
+    Expired,
+    // The session belongs to another user
+    Forbidden,
+    TooMany,
+}
+
+impl SessionError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SessionError::Unknown => "unknown_session",
+            SessionError::Expired => "session_expired",
+            SessionError::Forbidden => "session_forbidden",
+            SessionError::TooMany => "too_many_sessions",
+        }
+    }
+
+    pub fn to_ws_message(&self) -> String {
+        SpeechErrorMessage::new(self.code(), self).to_json()
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Unknown => write!(f, "No such speech session on this connection"),
+            SessionError::Expired => write!(f, "Speech session has expired"),
+            SessionError::Forbidden => write!(f, "Speech session belongs to another user"),
+            SessionError::TooMany => write!(f, "Too many speech sessions"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    pub options: SessionOptions,
+    pub created_at: DateTime<Utc>,
+    pub connected: bool,
+    // An admin has turned on recording of its inbound audio
+    pub recording: bool,
+    // Only counts down while no socket holds the session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<f32>,
+}
+
+struct Session {
+    owner: Option<String>,
+    options: SessionOptions,
+    created_at: DateTime<Utc>,
+    socket: Option<String>,
+    released_at: Option<Instant>,
+    recording: bool,
+}
+
+pub struct SpeechSessionService {
+    settings: SpeechSessionSettings,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SpeechSessionService {
+    pub fn new(settings: SpeechSessionSettings) -> Self {
+        Self { settings, sessions: Mutex::new(HashMap::new()) }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.ttl_secs.max(0.0))
+    }
+
+    fn expires_in(&self, session: &Session, now: Instant) -> Option<Duration> {
+        session.released_at.map(|at| self.ttl().saturating_sub(now.duration_since(at)))
+    }
+
+    fn is_expired(&self, session: &Session, now: Instant) -> bool {
+        self.expires_in(session, now).is_some_and(|left| left.is_zero())
+    }
+
+    /// Opens a session held by `socket` and returns its id
+    pub fn create(&self, socket: &str, owner: Option<String>, options: SessionOptions, now: Instant) -> Result<String, SessionError> {
+        self.purge_expired(now);
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= self.settings.max_sessions {
+            return Err(SessionError::TooMany);
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        sessions.insert(id.clone(), Session {
+            owner,
+            options,
+            created_at: Utc::now(),
+            socket: Some(socket.to_string()),
+            released_at: None,
+            recording: false,
+        });
+        Ok(id)
+    }
+
+    /// Hands an existing session to `socket`, taking it from any socket that still holds it
+    pub fn resume(&self, id: &str, socket: &str, owner: Option<&str>, now: Instant) -> Result<SessionOptions, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(id).ok_or(SessionError::Unknown)?;
+        if self.is_expired(session, now) {
+            sessions.remove(id);
+            return Err(SessionError::Expired);
+        }
+        if session.owner.is_some() && session.owner.as_deref() != owner {
+            return Err(SessionError::Forbidden);
+        }
+        let session = sessions.get_mut(id).ok_or(SessionError::Unknown)?;
+        session.socket = Some(socket.to_string());
+        session.released_at = None;
+        Ok(session.options.clone())
+    }
+
+    /// Options of a session `socket` holds
+    pub fn options(&self, id: &str, socket: &str, now: Instant) -> Result<SessionOptions, SessionError> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(id).ok_or(SessionError::Unknown)?;
+        if self.is_expired(session, now) {
+            return Err(SessionError::Expired);
+        }
+        if session.socket.as_deref() != Some(socket) {
+            return Err(SessionError::Unknown);
+        }
+        Ok(session.options.clone())
+    }
+
+    /// Turns recording of the session's inbound audio on or off
+    pub fn set_recording(&self, id: &str, recording: bool, now: Instant) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id).ok_or(SessionError::Unknown)?;
+        if self.is_expired(session, now) {
+            return Err(SessionError::Expired);
+        }
+        session.recording = recording;
+        Ok(())
+    }
+
+    pub fn is_recording(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().get(id).is_some_and(|session| session.recording)
+    }
+
+    /// Lets go of the session if `socket` still holds it; the TTL starts from `now`
+    pub fn release(&self, id: &str, socket: &str, now: Instant) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(id) {
+            if session.socket.as_deref() == Some(socket) {
+                session.socket = None;
+                session.released_at = Some(now);
+            }
+        }
+    }
+
+    pub fn purge_expired(&self, now: Instant) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| !self.is_expired(session, now));
+        before - sessions.len()
+    }
+
+    /// Live sessions, oldest first
+    pub fn list(&self, now: Instant) -> Vec<SessionInfo> {
+        self.purge_expired(now);
+        let sessions = self.sessions.lock().unwrap();
+        let mut list: Vec<SessionInfo> = sessions.iter()
+            .map(|(id, session)| SessionInfo {
+                id: id.clone(),
+                owner: session.owner.clone(),
+                options: session.options.clone(),
+                created_at: session.created_at,
+                connected: session.socket.is_some(),
+                recording: session.recording,
+                expires_in_secs: self.expires_in(session, now).map(|left| left.as_secs_f32()),
+            })
+            .collect();
+        list.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> SpeechSessionService {
+        SpeechSessionService::new(SpeechSessionSettings { ttl_secs: 60.0, max_sessions: 2 })
+    }
+
+    #[test]
+    fn test_session_options_are_inherited() {
+        let options = SessionOptions {
+            voice: Some("bf_emma".to_string()),
+            language: Some("de".to_string()),
+            vad_mode: Some(VadMode::Off),
+            audio_format: Some("wav".to_string()),
+            ..Default::default()
+        };
+        // The request's own values win, then the session's, then the defaults
+        let tts = options.speech_options(None, Some(1.5), None, SpeechOptions::default());
+        assert_eq!((tts.voice.as_str(), tts.speed, tts.stream, tts.format.as_deref()), ("bf_emma", 1.5, true, Some("wav")));
+        let tts = options.speech_options(Some("af_heart".to_string()), None, Some(false), SpeechOptions::default());
+        assert_eq!((tts.voice.as_str(), tts.speed, tts.stream), ("af_heart", 1.0, false));
+
+        let stt = options.transcription_options(None, Some("large-v3".to_string()));
+        assert_eq!((stt.language.as_deref(), stt.model.as_deref(), stt.vad_filter), (Some("de"), Some("large-v3"), Some(false)));
+        let stt = SessionOptions::default().transcription_options(Some("fr".to_string()), None);
+        assert_eq!((stt.language.as_deref(), stt.model, stt.vad_filter), (Some("fr"), None, None));
+    }
+
+    #[test]
+    fn test_sessions_survive_reconnects_within_the_ttl() {
+        let sessions = service();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let options = SessionOptions { language: Some("en".to_string()), ..Default::default() };
+
+        let id = sessions.create("socket-1", Some("alice".to_string()), options.clone(), at(0)).unwrap();
+        assert_eq!(sessions.options(&id, "socket-1", at(0)), Ok(options.clone()));
+        // Other connections can't use it, and held sessions never expire
+        assert_eq!(sessions.options(&id, "socket-2", at(0)), Err(SessionError::Unknown));
+        assert_eq!(sessions.options(&id, "socket-1", at(1000)), Ok(options.clone()));
+
+        // The socket drops; its replacement adopts the session within the TTL
+        sessions.release(&id, "socket-1", at(1000));
+        assert_eq!(sessions.list(at(1030))[0].expires_in_secs, Some(30.0));
+        assert_eq!(sessions.resume(&id, "socket-2", Some("bob"), at(1030)), Err(SessionError::Forbidden));
+        assert_eq!(sessions.resume(&id, "socket-2", Some("alice"), at(1030)), Ok(options));
+        assert_eq!(sessions.options(&id, "socket-1", at(1030)), Err(SessionError::Unknown));
+        // A late release from the old socket doesn't touch it
+        sessions.release(&id, "socket-1", at(1031));
+        assert!(sessions.list(at(2000))[0].connected);
+
+        // Recording is per session and shows in the listing
+        assert!(!sessions.is_recording(&id));
+        sessions.set_recording(&id, true, at(1500)).unwrap();
+        assert!(sessions.is_recording(&id) && sessions.list(at(1500))[0].recording);
+        assert_eq!(sessions.set_recording("nope", true, at(1500)), Err(SessionError::Unknown));
+
+        // Left alone past the TTL, it's gone
+        sessions.release(&id, "socket-2", at(2000));
+        assert_eq!(sessions.resume(&id, "socket-3", Some("alice"), at(2060)), Err(SessionError::Expired));
+        assert_eq!(sessions.resume(&id, "socket-3", Some("alice"), at(2060)), Err(SessionError::Unknown));
+
+        let a = sessions.create("socket-4", None, SessionOptions::default(), at(3000)).unwrap();
+        sessions.create("socket-5", None, SessionOptions::default(), at(3000)).unwrap();
+        assert_eq!(sessions.create("socket-6", None, SessionOptions::default(), at(3000)), Err(SessionError::TooMany));
+        // Expired sessions are cleaned up to make room
+        sessions.release(&a, "socket-4", at(3000));
+        assert!(sessions.create("socket-6", None, SessionOptions::default(), at(3060)).is_ok());
+        assert_eq!(sessions.list(at(3060)).len(), 2);
+    }
+}
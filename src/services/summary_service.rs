@@ -0,0 +1,432 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::config::SummarySettings;
+use crate::utils::json_store::write_json_atomic;
+
+const SUMMARIES_PATH: &str = "/app/data/summaries/summaries.json";
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+const SUMMARY_PROMPT: &str = "Summarise the document in exactly two plain sentences.";
+
+/// Prompt-in, text-out LLM backend shared by summaries and auto-tagging
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn complete(&self, instructions: &str, text: &str, max_tokens: u32) -> Result<String, String>;
+}
+
+/// OpenAI-compatible `/chat/completions` backend
+pub struct ChatCompletionBackend {
+    client: Client,
+    api_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl ChatCompletionBackend {
+    pub fn new(api_url: String, api_key: Option<String>, model: String, timeout: Duration) -> Result<Self, String> {
+        let client = Client::builder().timeout(timeout).build().map_err(|e| e.to_string())?;
+        Ok(Self { client, api_url, api_key, model })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for ChatCompletionBackend {
+    async fn complete(&self, instructions: &str, text: &str, max_tokens: u32) -> Result<String, String> {
+        let url = format!("{}/chat/completions", self.api_url.trim_end_matches('/'));
+        let body = serde_json::json!({
+            "model": self.model,
+            "max_tokens": max_tokens,
+            "temperature": 0.2,
+            "messages": [
+                { "role": "system", "content": instructions },
+                { "role": "user", "content": text }
+            ]
+        });
+        let mut request = self.client.post(&url).json(&body);
+        if let Some(key) = self.api_key.as_deref().filter(|k| !k.is_empty()) {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("Summary API error {}: {}", status, text));
+        }
+        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        value.pointer("/choices/0/message/content")
+            .and_then(|c| c.as_str())
+            .map(|c| c.trim().to_string())
+            .ok_or_else(|| "Summary API response had no content".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSummary {
+    pub metadata_id: String,
+    pub sha1: String,
+    pub summary: String,
+    pub generated_at: i64,
+}
+
+#[derive(Debug)]
+pub enum SummaryLookup {
+    Ready(NodeSummary),
+    // Generation is queued or running; ask again after this many seconds
+    Pending { retry_after_secs: u64 },
+}
+
+#[derive(Debug, Error)]
+pub enum SummaryError {
+    #[error("Summary backend not configured")]
+    NotConfigured,
+    #[error("Summary queue is full")]
+    QueueFull,
+}
+
+struct SummaryJob {
+    metadata_id: String,
+    sha1: String,
+    source: PathBuf,
+}
+
+// sha1 -> summary, so a summary is only regenerated when the content changes
+type SummaryCache = HashMap<String, NodeSummary>;
+
+/// Two-sentence hover summaries generated by an LLM backend on a bounded worker
+/// pool. Requests past the queue size are refused rather than buffered.
+pub struct SummaryService {
+    backend: Option<Arc<dyn LlmBackend>>,
+    settings: SummarySettings,
+    cache: Mutex<SummaryCache>,
+    path: PathBuf,
+    pending: Mutex<HashSet<String>>,
+    queue_tx: mpsc::Sender<SummaryJob>,
+    queue_rx: Mutex<Option<mpsc::Receiver<SummaryJob>>>,
+    // Start times of backend calls in the last minute
+    recent_calls: tokio::sync::Mutex<VecDeque<Instant>>,
+    redactions: Vec<Regex>,
+}
+
+impl SummaryService {
+    pub fn new(settings: SummarySettings) -> Self {
+        let backend: Option<Arc<dyn LlmBackend>> = if settings.enabled && !settings.api_url.is_empty() {
+            match ChatCompletionBackend::new(
+                settings.api_url.clone(),
+                settings.api_key.clone(),
+                settings.model.clone(),
+                Duration::from_secs(settings.timeout_secs),
+            ) {
+                Ok(backend) => Some(Arc::new(backend)),
+                Err(e) => {
+                    error!("Failed to create summary backend: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self::with_backend(backend, settings, PathBuf::from(SUMMARIES_PATH))
+    }
+
+    pub fn with_backend(backend: Option<Arc<dyn LlmBackend>>, settings: SummarySettings, path: PathBuf) -> Self {
+        let cache = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<SummaryCache>(&content).unwrap_or_else(|e| {
+                error!("Failed to parse summaries file {:?}: {}. Starting empty.", path, e);
+                SummaryCache::new()
+            }),
+            Err(_) => SummaryCache::new(),
+        };
+        let redactions = settings.redact_patterns.iter()
+            .filter_map(|p| match Regex::new(p) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    warn!("Ignoring invalid redaction pattern '{}': {}", p, e);
+                    None
+                }
+            })
+            .collect();
+        let (queue_tx, queue_rx) = mpsc::channel(settings.queue_size.max(1));
+
+        Self {
+            backend,
+            settings,
+            cache: Mutex::new(cache),
+            path,
+            pending: Mutex::new(HashSet::new()),
+            queue_tx,
+            queue_rx: Mutex::new(Some(queue_rx)),
+            recent_calls: tokio::sync::Mutex::new(VecDeque::new()),
+            redactions,
+        }
+    }
+
+    /// Spawns the worker pool. Workers share one bounded queue.
+    pub fn start(self: Arc<Self>) {
+        if self.backend.is_none() {
+            info!("Summary generation disabled");
+            return;
+        }
+        let receiver = match self.queue_rx.lock().unwrap().take() {
+            Some(receiver) => Arc::new(tokio::sync::Mutex::new(receiver)),
+            None => return,
+        };
+
+        let workers = self.settings.workers.max(1);
+        info!("Starting {} summary workers", workers);
+        for _ in 0..workers {
+            let service = self.clone();
+            let receiver = receiver.clone();
+            tokio::spawn(async move {
+                loop {
+                    let job = match receiver.lock().await.recv().await {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    service.process(job).await;
+                }
+            });
+        }
+    }
+
+    /// Returns the cached summary for this content, or queues generation
+    pub fn request(&self, metadata_id: &str, sha1: &str, source: PathBuf) -> Result<SummaryLookup, SummaryError> {
+        if let Some(summary) = self.cache.lock().unwrap().get(sha1) {
+            return Ok(SummaryLookup::Ready(summary.clone()));
+        }
+        if self.backend.is_none() {
+            return Err(SummaryError::NotConfigured);
+        }
+
+        let retry_after_secs = self.settings.retry_after_secs.max(1);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.contains(sha1) {
+            return Ok(SummaryLookup::Pending { retry_after_secs });
+        }
+        let job = SummaryJob {
+            metadata_id: metadata_id.to_string(),
+            sha1: sha1.to_string(),
+            source,
+        };
+        match self.queue_tx.try_send(job) {
+            Ok(()) => {
+                pending.insert(sha1.to_string());
+                Ok(SummaryLookup::Pending { retry_after_secs })
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => Err(SummaryError::QueueFull),
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(SummaryError::NotConfigured),
+        }
+    }
+
+    /// The configured LLM backend, if any, for other jobs that want to reuse it
+    pub fn backend(&self) -> Option<Arc<dyn LlmBackend>> {
+        self.backend.clone()
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    async fn process(&self, job: SummaryJob) {
+        let result = self.generate(&job).await;
+        match result {
+            Ok(summary) => {
+                let snapshot = {
+                    let mut cache = self.cache.lock().unwrap();
+                    // Older summaries for the same node are stale now
+                    cache.retain(|_, s| s.metadata_id != job.metadata_id);
+                    cache.insert(job.sha1.clone(), summary);
+                    cache.clone()
+                };
+                if let Err(e) = write_json_atomic(&self.path, &snapshot) {
+                    error!("Failed to persist summaries: {}", e);
+                }
+            }
+            Err(e) => warn!("Summary generation failed for {}: {}", job.metadata_id, e),
+        }
+        self.pending.lock().unwrap().remove(&job.sha1);
+    }
+
+    async fn generate(&self, job: &SummaryJob) -> Result<NodeSummary, String> {
+        let backend = self.backend.as_ref().ok_or_else(|| SummaryError::NotConfigured.to_string())?;
+        let content = tokio::fs::read_to_string(&job.source).await.map_err(|e| e.to_string())?;
+        let input = self.prepare_input(&content);
+
+        self.wait_for_rate_slot().await;
+        debug!("Generating summary for {}", job.metadata_id);
+        let raw = backend.complete(SUMMARY_PROMPT, &input, self.settings.max_tokens).await?;
+
+        Ok(NodeSummary {
+            metadata_id: job.metadata_id.clone(),
+            sha1: job.sha1.clone(),
+            summary: clamp_summary(&raw, self.settings.max_summary_chars),
+            generated_at: Utc::now().timestamp_millis(),
+        })
+    }
+
+    /// Redacts sensitive patterns and truncates to the input limit before anything leaves the server
+    fn prepare_input(&self, content: &str) -> String {
+        let mut text = content.to_string();
+        for re in &self.redactions {
+            text = re.replace_all(&text, "[REDACTED]").into_owned();
+        }
+        if let Some((idx, _)) = text.char_indices().nth(self.settings.max_input_chars) {
+            text.truncate(idx);
+        }
+        text
+    }
+
+    // Blocks until a backend call fits under the per-minute cap
+    async fn wait_for_rate_slot(&self) {
+        let cap = self.settings.requests_per_minute.max(1) as usize;
+        loop {
+            let wait = {
+                let mut calls = self.recent_calls.lock().await;
+                let now = Instant::now();
+                while calls.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+                    calls.pop_front();
+                }
+                if calls.len() < cap {
+                    calls.push_back(now);
+                    return;
+                }
+                RATE_WINDOW - now.duration_since(*calls.front().unwrap())
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Keeps at most two sentences and `max_chars` characters
+fn clamp_summary(raw: &str, max_chars: usize) -> String {
+    let text = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut end = text.len();
+    let mut sentences = 0;
+    for (idx, c) in text.char_indices() {
+        if matches!(c, '.' | '!' | '?') {
+            sentences += 1;
+            if sentences == 2 {
+                end = idx + c.len_utf8();
+                break;
+            }
+        }
+    }
+    let mut summary = text[..end].to_string();
+    if let Some((idx, _)) = summary.char_indices().nth(max_chars) {
+        summary.truncate(idx);
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockBackend {
+        calls: AtomicUsize,
+        last_input: Mutex<String>,
+    }
+
+    #[async_trait]
+    impl LlmBackend for MockBackend {
+        async fn complete(&self, _instructions: &str, text: &str, _max_tokens: u32) -> Result<String, String> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            *self.last_input.lock().unwrap() = text.to_string();
+            Ok(format!("Summary {}. Second sentence. Third sentence.", n))
+        }
+    }
+
+    fn settings() -> SummarySettings {
+        SummarySettings {
+            enabled: true,
+            queue_size: 1,
+            workers: 1,
+            redact_patterns: vec![r"sk-[A-Za-z0-9]+".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("summary-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn wait_ready(service: &SummaryService, id: &str, sha1: &str, source: &Path) -> NodeSummary {
+        for _ in 0..100 {
+            if let Ok(SummaryLookup::Ready(summary)) = service.request(id, sha1, source.to_path_buf()) {
+                return summary;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("summary for {} never became ready", sha1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_and_regeneration_on_sha1_change() {
+        let dir = temp_dir();
+        let source = dir.join("note.md");
+        fs::write(&source, "Secret key sk-abc123 lives here.").unwrap();
+        let backend = Arc::new(MockBackend { calls: AtomicUsize::new(0), last_input: Mutex::new(String::new()) });
+        let service = Arc::new(SummaryService::with_backend(Some(backend.clone()), settings(), dir.join("summaries.json")));
+        service.clone().start();
+
+        assert!(matches!(service.request("note", "aaa", source.clone()), Ok(SummaryLookup::Pending { .. })));
+        let summary = wait_ready(&service, "note", "aaa", &source).await;
+        assert_eq!(summary.summary, "Summary 1. Second sentence.");
+        assert!(!backend.last_input.lock().unwrap().contains("sk-abc123"));
+
+        // Same content hash is served from cache
+        wait_ready(&service, "note", "aaa", &source).await;
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+
+        // New hash regenerates and replaces the old entry
+        let summary = wait_ready(&service, "note", "bbb", &source).await;
+        assert_eq!(summary.summary, "Summary 2. Second sentence.");
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 2);
+
+        // Persisted across restarts
+        let reloaded = SummaryService::with_backend(None, settings(), dir.join("summaries.json"));
+        assert!(matches!(reloaded.request("note", "bbb", source.clone()), Ok(SummaryLookup::Ready(_))));
+        assert!(matches!(reloaded.request("note", "aaa", source.clone()), Err(SummaryError::NotConfigured)));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_applies_backpressure() {
+        let dir = temp_dir();
+        let backend = Arc::new(MockBackend { calls: AtomicUsize::new(0), last_input: Mutex::new(String::new()) });
+        // Workers not started, so the single queue slot stays occupied
+        let service = SummaryService::with_backend(Some(backend), settings(), dir.join("summaries.json"));
+
+        assert!(matches!(service.request("a", "111", dir.join("a.md")), Ok(SummaryLookup::Pending { .. })));
+        // Asking again for queued content doesn't take another slot
+        assert!(matches!(service.request("a", "111", dir.join("a.md")), Ok(SummaryLookup::Pending { .. })));
+        assert!(matches!(service.request("b", "222", dir.join("b.md")), Err(SummaryError::QueueFull)));
+        assert_eq!(service.queue_depth(), 1);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_clamp_summary() {
+        assert_eq!(clamp_summary("One.  Two!\nThree?", 100), "One. Two!");
+        assert_eq!(clamp_summary("No terminator at all", 5), "No te");
+    }
+}
@@ -0,0 +1,349 @@
+use actix::Addr;
+use log::{debug, error, info, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::actors::messages::{GetMetadata, ReviewAutoTags, SetAutoTags, UpdateNodeMetadata};
+use crate::actors::{GraphServiceActor, MetadataActor};
+use crate::config::TaggingSettings;
+use crate::models::metadata::{Metadata, MetadataStore};
+use crate::services::file_service::{FileService, MARKDOWN_DIR};
+use crate::services::summary_service::LlmBackend;
+
+const MAX_TAG_CHARS: usize = 40;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingTags {
+    pub file_name: String,
+    pub metadata_id: String,
+    pub tags: Vec<String>,
+    pub auto_tags: Vec<String>,
+}
+
+/// Proposes `autoTags` for untagged or changed files using the shared LLM backend.
+/// Proposals only become `tags` through review, so manual tagging always wins.
+pub struct TaggingService {
+    backend: Option<Arc<dyn LlmBackend>>,
+    settings: TaggingSettings,
+    // Serialises metadata.json writes between the job and review requests
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl TaggingService {
+    pub fn new(backend: Option<Arc<dyn LlmBackend>>, settings: TaggingSettings) -> Self {
+        Self {
+            backend,
+            settings,
+            persist_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Spawns the periodic tagging pass if enabled and a backend is configured
+    pub fn start(self: Arc<Self>, metadata_addr: Addr<MetadataActor>, graph_addr: Addr<GraphServiceActor>) {
+        if !self.settings.enabled {
+            info!("Auto-tagging disabled");
+            return;
+        }
+        if self.backend.is_none() {
+            warn!("Auto-tagging enabled but no LLM backend configured; job not started");
+            return;
+        }
+        if self.settings.vocabulary.is_empty() && !self.settings.open_vocabulary {
+            warn!("Auto-tagging enabled with an empty vocabulary; job not started");
+            return;
+        }
+
+        let interval = Duration::from_secs(self.settings.interval_secs.max(60));
+        info!("Starting auto-tag job (every {:?})", interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.run_once(&metadata_addr, &graph_addr).await {
+                    Ok(count) => debug!("Auto-tag pass proposed tags for {} nodes", count),
+                    Err(e) => warn!("Auto-tag pass skipped: {}", e),
+                }
+            }
+        });
+    }
+
+    /// One pass over every file needing proposals. Returns how many were tagged.
+    pub async fn run_once(
+        &self,
+        metadata_addr: &Addr<MetadataActor>,
+        graph_addr: &Addr<GraphServiceActor>,
+    ) -> Result<usize, String> {
+        let store = metadata_addr.send(GetMetadata).await.map_err(|e| e.to_string())??;
+        let candidates: Vec<(String, Metadata)> = store.into_iter()
+            .filter(|(_, meta)| needs_proposal(meta))
+            .collect();
+
+        let mut tagged = 0;
+        let mut latest = None;
+        for (file_name, meta) in candidates {
+            let content = match tokio::fs::read_to_string(Path::new(MARKDOWN_DIR).join(&file_name)).await {
+                Ok(content) => content,
+                Err(e) => {
+                    debug!("Skipping auto-tag for {}: {}", file_name, e);
+                    continue;
+                }
+            };
+            let proposals = match self.propose(&content, &meta).await {
+                Ok(proposals) => proposals,
+                Err(e) => {
+                    warn!("Auto-tag proposal failed for {}: {}", file_name, e);
+                    continue;
+                }
+            };
+            match metadata_addr.send(SetAutoTags {
+                file_name: file_name.clone(),
+                sha1: meta.sha1.clone(),
+                proposals,
+            }).await.map_err(|e| e.to_string()).and_then(|r| r) {
+                Ok(store) => {
+                    sync_node_tags(graph_addr, &file_name, &store).await;
+                    latest = Some(store);
+                    tagged += 1;
+                }
+                Err(e) => debug!("Auto-tags not stored for {}: {}", file_name, e),
+            }
+        }
+
+        if let Some(store) = latest {
+            self.persist(store).await?;
+        }
+        Ok(tagged)
+    }
+
+    /// Accepts/rejects proposals for one file and returns its updated metadata
+    pub async fn review(
+        &self,
+        file_name: &str,
+        accept: Vec<String>,
+        reject: Vec<String>,
+        metadata_addr: &Addr<MetadataActor>,
+        graph_addr: &Addr<GraphServiceActor>,
+    ) -> Result<Metadata, String> {
+        let store = metadata_addr.send(ReviewAutoTags {
+            file_name: file_name.to_string(),
+            accept,
+            reject,
+        }).await.map_err(|e| e.to_string())??;
+
+        sync_node_tags(graph_addr, file_name, &store).await;
+        let meta = store.get(file_name).cloned()
+            .ok_or_else(|| format!("No metadata for {}", file_name))?;
+        self.persist(store).await?;
+        Ok(meta)
+    }
+
+    async fn propose(&self, content: &str, meta: &Metadata) -> Result<Vec<String>, String> {
+        let backend = self.backend.as_ref().ok_or_else(|| "No LLM backend configured".to_string())?;
+        let mut text = content.to_string();
+        if let Some((idx, _)) = text.char_indices().nth(self.settings.max_input_chars) {
+            text.truncate(idx);
+        }
+        let raw = backend.complete(&self.instructions(), &text, self.settings.max_tokens).await?;
+        Ok(parse_tags(&raw, &self.settings, meta))
+    }
+
+    fn instructions(&self) -> String {
+        if self.settings.open_vocabulary {
+            format!(
+                "Propose up to {} short lowercase topic tags for the document. Reply with a comma-separated list only.",
+                self.settings.max_tags
+            )
+        } else {
+            format!(
+                "Choose up to {} tags for the document from this list only: {}. Reply with a comma-separated list only.",
+                self.settings.max_tags,
+                self.settings.vocabulary.join(", ")
+            )
+        }
+    }
+
+    async fn persist(&self, store: MetadataStore) -> Result<(), String> {
+        let _guard = self.persist_lock.lock().await;
+        match tokio::task::spawn_blocking(move || FileService::save_metadata(&store)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                error!("Failed to persist metadata after tagging: {}", e);
+                Err(e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Files with unreviewed proposals, for the bulk review endpoint
+pub fn pending_proposals(store: &MetadataStore) -> Vec<PendingTags> {
+    let mut pending: Vec<PendingTags> = store.iter()
+        .filter(|(_, meta)| !meta.auto_tags.is_empty())
+        .map(|(file_name, meta)| PendingTags {
+            file_name: file_name.clone(),
+            metadata_id: file_name.trim_end_matches(".md").to_string(),
+            tags: meta.tags.clone(),
+            auto_tags: meta.auto_tags.clone(),
+        })
+        .collect();
+    pending.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    pending
+}
+
+// Untagged files, or files whose content changed since their last proposal
+fn needs_proposal(meta: &Metadata) -> bool {
+    if meta.sha1.is_empty() || meta.auto_tagged_sha1 == meta.sha1 {
+        return false;
+    }
+    meta.tags.is_empty() || !meta.auto_tagged_sha1.is_empty()
+}
+
+/// Normalises the model's reply into at most `max_tags` new tags. With a controlled
+/// vocabulary anything outside it is dropped and matches take the vocabulary's spelling.
+fn parse_tags(raw: &str, settings: &TaggingSettings, meta: &Metadata) -> Vec<String> {
+    let list_marker = Regex::new(r"^(?:[-*#]|\d+[.)])\s*").unwrap();
+    let mut tags: Vec<String> = Vec::new();
+    for candidate in raw.split([',', '\n']) {
+        // Models like to number or bullet their lists
+        let candidate = list_marker.replace(candidate.trim(), "");
+        let candidate = candidate.trim_matches(|c| c == '"' || c == '\'' || c == '`').to_lowercase();
+        if candidate.is_empty() || candidate.chars().count() > MAX_TAG_CHARS {
+            continue;
+        }
+        let tag = if settings.open_vocabulary {
+            Some(candidate)
+        } else {
+            settings.vocabulary.iter().find(|v| v.to_lowercase() == candidate).cloned()
+        };
+        if let Some(tag) = tag {
+            if !tags.contains(&tag) && !meta.tags.contains(&tag) && !meta.rejected_tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() >= settings.max_tags {
+            break;
+        }
+    }
+    tags
+}
+
+// Mirrors a file's tag state onto its node so grouping and filters pick it up
+async fn sync_node_tags(graph_addr: &Addr<GraphServiceActor>, file_name: &str, store: &MetadataStore) {
+    let meta = match store.get(file_name) {
+        Some(meta) => meta,
+        None => return,
+    };
+    let mut entries = HashMap::new();
+    entries.insert("tags".to_string(), meta.tags.join(","));
+    entries.insert("autoTags".to_string(), meta.auto_tags.join(","));
+    // The node may not exist yet if a rebuild is pending; the metadata carries it over
+    if let Err(e) = graph_addr.send(UpdateNodeMetadata {
+        metadata_id: file_name.trim_end_matches(".md").to_string(),
+        entries,
+    }).await.map_err(|e| e.to_string()).and_then(|r| r) {
+        debug!("Node tags not updated for {}: {}", file_name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct MockBackend {
+        reply: String,
+    }
+
+    #[async_trait]
+    impl LlmBackend for MockBackend {
+        async fn complete(&self, _instructions: &str, _text: &str, _max_tokens: u32) -> Result<String, String> {
+            Ok(self.reply.clone())
+        }
+    }
+
+    fn service(reply: &str, settings: TaggingSettings) -> TaggingService {
+        TaggingService::new(Some(Arc::new(MockBackend { reply: reply.to_string() })), settings)
+    }
+
+    fn vocabulary() -> TaggingSettings {
+        TaggingSettings {
+            enabled: true,
+            max_tags: 3,
+            vocabulary: vec!["AI".to_string(), "rust".to_string(), "graphs".to_string(), "xr".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_controlled_vocabulary_filters_and_caps() {
+        let meta = Metadata { sha1: "a".to_string(), ..Default::default() };
+        let tags = service("1. ai\n- Rust, cooking, graphs, xr", vocabulary()).propose("text", &meta).await.unwrap();
+        assert_eq!(tags, vec!["AI", "rust", "graphs"]);
+    }
+
+    #[tokio::test]
+    async fn test_open_vocabulary() {
+        let settings = TaggingSettings { open_vocabulary: true, ..vocabulary() };
+        let meta = Metadata::default();
+        let tags = service("Cooking, \"baking\"", settings).propose("text", &meta).await.unwrap();
+        assert_eq!(tags, vec!["cooking", "baking"]);
+    }
+
+    #[tokio::test]
+    async fn test_manual_tags_take_precedence() {
+        let mut meta = Metadata {
+            sha1: "a".to_string(),
+            tags: vec!["rust".to_string()],
+            rejected_tags: vec!["xr".to_string()],
+            ..Default::default()
+        };
+        let proposals = service("rust, xr, graphs, ai", vocabulary()).propose("text", &meta).await.unwrap();
+        meta.set_auto_tags(proposals, "a");
+
+        // Existing and rejected tags are never re-proposed, and proposals don't touch tags
+        assert_eq!(meta.tags, vec!["rust"]);
+        assert_eq!(meta.auto_tags, vec!["graphs", "AI"]);
+
+        meta.review_auto_tags(&["graphs".to_string(), "cooking".to_string()], &["AI".to_string()]);
+        assert_eq!(meta.tags, vec!["rust", "graphs"]);
+        assert!(meta.auto_tags.is_empty());
+        assert_eq!(meta.rejected_tags, vec!["xr", "AI"]);
+
+        // A later pass with the same content leaves it alone
+        assert!(!needs_proposal(&meta));
+    }
+
+    #[test]
+    fn test_needs_proposal() {
+        let untagged = Metadata { sha1: "a".to_string(), ..Default::default() };
+        assert!(needs_proposal(&untagged));
+
+        // Manually tagged and never auto-tagged: leave it to the human
+        let manual = Metadata { sha1: "a".to_string(), tags: vec!["rust".to_string()], ..Default::default() };
+        assert!(!needs_proposal(&manual));
+
+        let changed = Metadata {
+            sha1: "b".to_string(),
+            tags: vec!["rust".to_string()],
+            auto_tagged_sha1: "a".to_string(),
+            ..Default::default()
+        };
+        assert!(needs_proposal(&changed));
+    }
+
+    #[test]
+    fn test_pending_proposals_lists_only_unreviewed() {
+        let mut store = MetadataStore::new();
+        store.insert("b.md".to_string(), Metadata { auto_tags: vec!["xr".to_string()], ..Default::default() });
+        store.insert("a.md".to_string(), Metadata { auto_tags: vec!["ai".to_string()], ..Default::default() });
+        store.insert("c.md".to_string(), Metadata { tags: vec!["rust".to_string()], ..Default::default() });
+
+        let pending = pending_proposals(&store);
+        assert_eq!(pending.iter().map(|p| p.metadata_id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+}
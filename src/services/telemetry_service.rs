@@ -0,0 +1,362 @@
+use chrono::Utc;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const TELEMETRY_DIR: &str = "/app/data/telemetry";
+const TELEMETRY_FILE: &str = "telemetry.ndjson";
+pub const MAX_BATCH_SIZE: usize = 500;
+const MAX_BATCHES_PER_WINDOW: u32 = 30;
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 5;
+
+/// A single client-side measurement. Unknown fields are rejected so a schema
+/// drift on the client shows up as per-item errors instead of silent garbage.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TelemetryEvent {
+    #[serde(rename_all = "camelCase")]
+    FrameRate { fps: f32, timestamp: i64 },
+    #[serde(rename_all = "camelCase")]
+    DroppedFrames { count: u32, timestamp: i64 },
+    #[serde(rename_all = "camelCase")]
+    InteractionLatency { action: String, latency_ms: f32, timestamp: i64 },
+    #[serde(rename_all = "camelCase")]
+    NodeFocus { node_id: u32, duration_ms: u64, timestamp: i64 },
+}
+
+impl TelemetryEvent {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            TelemetryEvent::FrameRate { fps, .. } if !fps.is_finite() || *fps < 0.0 || *fps > 1000.0 => {
+                Err("fps must be between 0 and 1000".to_string())
+            }
+            TelemetryEvent::InteractionLatency { latency_ms, .. } if !latency_ms.is_finite() || *latency_ms < 0.0 => {
+                Err("latencyMs must be a non-negative number".to_string())
+            }
+            TelemetryEvent::InteractionLatency { action, .. } if action.is_empty() || action.len() > 64 => {
+                Err("action must be 1-64 characters".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryBatch {
+    pub client_id: Option<String>,
+    pub events: Vec<Value>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemError {
+    pub index: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestResult {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub errors: Vec<ItemError>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IngestError {
+    RateLimited,
+    BatchTooLarge(usize),
+    Io(String),
+}
+
+impl std::fmt::Display for IngestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::RateLimited => write!(f, "Too many telemetry batches, slow down"),
+            IngestError::BatchTooLarge(n) => write!(f, "Batch has {} events, maximum is {}", n, MAX_BATCH_SIZE),
+            IngestError::Io(e) => write!(f, "Failed to store telemetry: {}", e),
+        }
+    }
+}
+
+/// Aggregated counters exposed on the metrics endpoint
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryCounters {
+    pub batches_accepted: u64,
+    pub batches_rate_limited: u64,
+    pub events_accepted: u64,
+    pub events_rejected: u64,
+    pub frame_rate_samples: u64,
+    pub avg_fps: f64,
+    pub dropped_frames_total: u64,
+    pub interaction_samples: u64,
+    pub avg_interaction_latency_ms: f64,
+    pub node_focus_samples: u64,
+    pub node_focus_total_ms: u64,
+}
+
+impl TelemetryCounters {
+    fn record(&mut self, event: &TelemetryEvent) {
+        self.events_accepted += 1;
+        match event {
+            TelemetryEvent::FrameRate { fps, .. } => {
+                self.frame_rate_samples += 1;
+                self.avg_fps += (*fps as f64 - self.avg_fps) / self.frame_rate_samples as f64;
+            }
+            TelemetryEvent::DroppedFrames { count, .. } => {
+                self.dropped_frames_total += *count as u64;
+            }
+            TelemetryEvent::InteractionLatency { latency_ms, .. } => {
+                self.interaction_samples += 1;
+                self.avg_interaction_latency_ms +=
+                    (*latency_ms as f64 - self.avg_interaction_latency_ms) / self.interaction_samples as f64;
+            }
+            TelemetryEvent::NodeFocus { duration_ms, .. } => {
+                self.node_focus_samples += 1;
+                self.node_focus_total_ms += duration_ms;
+            }
+        }
+    }
+}
+
+struct TelemetryState {
+    counters: TelemetryCounters,
+    // client key -> (window start, batches in window)
+    windows: HashMap<String, (Instant, u32)>,
+    // Windows that ran out are dropped at most once a window, not on every batch
+    last_prune: Option<Instant>,
+}
+
+/// Accepts client telemetry batches, appends valid events to a size-rotated
+/// NDJSON log and keeps running aggregates for the metrics endpoint.
+pub struct TelemetryService {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    state: Mutex<TelemetryState>,
+}
+
+impl Default for TelemetryService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TelemetryService {
+    pub fn new() -> Self {
+        Self::with_dir(PathBuf::from(TELEMETRY_DIR), MAX_FILE_BYTES)
+    }
+
+    pub fn with_dir(dir: PathBuf, max_file_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_file_bytes,
+            state: Mutex::new(TelemetryState {
+                counters: TelemetryCounters::default(),
+                windows: HashMap::new(),
+                last_prune: None,
+            }),
+        }
+    }
+
+    pub fn counters(&self) -> TelemetryCounters {
+        self.state.lock().map(|s| s.counters.clone()).unwrap_or_default()
+    }
+
+    fn check_rate_limit(state: &mut TelemetryState, client_key: &str, now: Instant) -> bool {
+        if state.last_prune.is_none_or(|last| now.duration_since(last) >= RATE_LIMIT_WINDOW) {
+            state.windows.retain(|_, (start, _)| now.duration_since(*start) < RATE_LIMIT_WINDOW);
+            state.last_prune = Some(now);
+        }
+        let entry = state.windows.entry(client_key.to_string()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= RATE_LIMIT_WINDOW {
+            *entry = (now, 0);
+        }
+        if entry.1 >= MAX_BATCHES_PER_WINDOW {
+            return false;
+        }
+        entry.1 += 1;
+        true
+    }
+
+    pub fn ingest(&self, client_key: &str, batch: TelemetryBatch) -> Result<IngestResult, IngestError> {
+        self.ingest_at(client_key, batch, Instant::now())
+    }
+
+    fn ingest_at(&self, client_key: &str, batch: TelemetryBatch, now: Instant) -> Result<IngestResult, IngestError> {
+        if batch.events.len() > MAX_BATCH_SIZE {
+            return Err(IngestError::BatchTooLarge(batch.events.len()));
+        }
+
+        let mut state = self.state.lock().map_err(|_| IngestError::Io("telemetry state poisoned".to_string()))?;
+        if !Self::check_rate_limit(&mut state, client_key, now) {
+            state.counters.batches_rate_limited += 1;
+            return Err(IngestError::RateLimited);
+        }
+
+        let mut valid = Vec::with_capacity(batch.events.len());
+        let mut errors = Vec::new();
+        for (index, raw) in batch.events.into_iter().enumerate() {
+            match serde_json::from_value::<TelemetryEvent>(raw) {
+                Ok(event) => match event.validate() {
+                    Ok(()) => valid.push(event),
+                    Err(error) => errors.push(ItemError { index, error }),
+                },
+                Err(e) => errors.push(ItemError { index, error: e.to_string() }),
+            }
+        }
+
+        if !valid.is_empty() {
+            let received_at = Utc::now().timestamp_millis();
+            let mut lines = String::new();
+            for event in &valid {
+                let line = serde_json::json!({
+                    "clientId": client_key,
+                    "receivedAt": received_at,
+                    "event": event,
+                });
+                lines.push_str(&line.to_string());
+                lines.push('\n');
+            }
+            self.append(&lines).map_err(|e| IngestError::Io(e.to_string()))?;
+        }
+
+        state.counters.batches_accepted += 1;
+        state.counters.events_rejected += errors.len() as u64;
+        for event in &valid {
+            state.counters.record(event);
+        }
+        debug!("Telemetry from {}: {} accepted, {} rejected", client_key, valid.len(), errors.len());
+
+        Ok(IngestResult {
+            accepted: valid.len(),
+            rejected: errors.len(),
+            errors,
+        })
+    }
+
+    fn file_path(&self, generation: usize) -> PathBuf {
+        if generation == 0 {
+            self.dir.join(TELEMETRY_FILE)
+        } else {
+            self.dir.join(format!("{}.{}", TELEMETRY_FILE, generation))
+        }
+    }
+
+    // Shift telemetry.ndjson -> .1 -> .2 ..., dropping the oldest
+    fn rotate(&self) -> std::io::Result<()> {
+        let oldest = self.file_path(MAX_ROTATED_FILES);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for generation in (0..MAX_ROTATED_FILES).rev() {
+            let from = self.file_path(generation);
+            if from.exists() {
+                fs::rename(&from, self.file_path(generation + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&self, lines: &str) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let current = self.file_path(0);
+        let size = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + lines.len() as u64 > self.max_file_bytes {
+            if let Err(e) = self.rotate() {
+                warn!("Failed to rotate telemetry log: {}", e);
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&current)?;
+        file.write_all(lines.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_service(max_bytes: u64) -> (TelemetryService, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("telemetry-test-{}", uuid::Uuid::new_v4()));
+        (TelemetryService::with_dir(dir.clone(), max_bytes), dir)
+    }
+
+    fn batch(events: Vec<Value>) -> TelemetryBatch {
+        TelemetryBatch { client_id: Some("headset-1".to_string()), events }
+    }
+
+    #[test]
+    fn test_schema_validation_reports_per_item_errors() {
+        let (service, dir) = temp_service(MAX_FILE_BYTES);
+        let result = service.ingest("c1", batch(vec![
+            json!({"kind": "frame_rate", "fps": 72.0, "timestamp": 1}),
+            json!({"kind": "frame_rate", "fps": -5.0, "timestamp": 2}),
+            json!({"kind": "teleport", "timestamp": 3}),
+            json!({"kind": "node_focus", "nodeId": 4, "durationMs": 1500, "timestamp": 4, "extra": true}),
+            json!({"kind": "interaction_latency", "action": "grab", "latencyMs": 18.5, "timestamp": 5}),
+        ])).unwrap();
+
+        assert_eq!(result.accepted, 2);
+        assert_eq!(result.rejected, 3);
+        let bad: Vec<usize> = result.errors.iter().map(|e| e.index).collect();
+        assert_eq!(bad, vec![1, 2, 3]);
+
+        let counters = service.counters();
+        assert_eq!(counters.frame_rate_samples, 1);
+        assert_eq!(counters.avg_fps, 72.0);
+        assert_eq!(counters.events_rejected, 3);
+
+        let logged = fs::read_to_string(dir.join(TELEMETRY_FILE)).unwrap();
+        assert_eq!(logged.lines().count(), 2);
+
+        assert_eq!(
+            service.ingest("c1", batch(vec![json!({}); MAX_BATCH_SIZE + 1])).unwrap_err(),
+            IngestError::BatchTooLarge(MAX_BATCH_SIZE + 1)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rate_limiting_per_client() {
+        let (service, dir) = temp_service(MAX_FILE_BYTES);
+        let start = Instant::now();
+        for _ in 0..MAX_BATCHES_PER_WINDOW {
+            assert!(service.ingest_at("c1", batch(vec![]), start).is_ok());
+        }
+        assert_eq!(service.ingest_at("c1", batch(vec![]), start).unwrap_err(), IngestError::RateLimited);
+        // Other clients have their own budget
+        assert!(service.ingest_at("c2", batch(vec![]), start).is_ok());
+        // Budget resets after the window
+        assert!(service.ingest_at("c1", batch(vec![]), start + RATE_LIMIT_WINDOW).is_ok());
+        assert_eq!(service.counters().batches_rate_limited, 1);
+        // Windows that ran out are dropped, so clients that come and go don't pile up
+        assert!(service.ingest_at("c3", batch(vec![]), start + RATE_LIMIT_WINDOW * 3).is_ok());
+        assert_eq!(service.state.lock().unwrap().windows.len(), 1);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_rotation() {
+        let (service, dir) = temp_service(200);
+        let event = json!({"kind": "dropped_frames", "count": 3, "timestamp": 1});
+        for _ in 0..(MAX_ROTATED_FILES + 3) {
+            service.ingest("c1", batch(vec![event.clone()])).unwrap();
+        }
+        assert!(dir.join(TELEMETRY_FILE).exists());
+        assert!(dir.join(format!("{}.1", TELEMETRY_FILE)).exists());
+        assert!(dir.join(format!("{}.{}", TELEMETRY_FILE, MAX_ROTATED_FILES)).exists());
+        assert!(!dir.join(format!("{}.{}", TELEMETRY_FILE, MAX_ROTATED_FILES + 1)).exists());
+        assert_eq!(service.counters().dropped_frames_total, 3 * (MAX_ROTATED_FILES as u64 + 3));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
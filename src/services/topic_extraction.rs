@@ -0,0 +1,322 @@
+//! Fallback topic extraction. Entries whose topic_counts came back empty from the
+//! upstream extractor would otherwise be isolated nodes; when enabled, their markdown is
+//! read before the rebuild and its wiki-links and case-matched mentions of other file
+//! names become topic_counts. The synthesized counts only feed edge construction; the
+//! metadata store on disk is left alone.
+
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::TopicExtractionSettings;
+use crate::models::graph_journal::JournalReplay;
+use crate::models::node_ids::IdConflict;
+use crate::models::metadata::MetadataStore;
+use crate::services::external_links::ExternalLinkStatus;
+
+// [[Name]], [[Name|label]] and [[Name#heading]] all link to Name
+static WIKI_LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[\[([^\]|#]+)(?:[|#][^\]]*)?\]\]").unwrap());
+
+static LAST_REPORT: Lazy<Mutex<Option<BuildReport>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum FileExtraction {
+    Extracted { references: usize, targets: usize },
+    NoReferences,
+    TooLarge { bytes: u64 },
+    ReadFailed { error: String },
+    // The pass ran out of time before reaching this file
+    OutOfBudget,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractionReport {
+    // Keyed by metadata key, only entries that had no topic_counts
+    pub files: BTreeMap<String, FileExtraction>,
+    pub elapsed_ms: u64,
+    pub budget_exhausted: bool,
+}
+
+/// What happened before the last rebuild's edges were built
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic_extraction: Option<ExtractionReport>,
+    // The runtime changes replayed on top of the build
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub journal: Option<JournalReplay>,
+    // Node ids two documents claimed at once, and how they were split
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub id_conflicts: Vec<IdConflict>,
+    // Links to or from another room with an end whose document is missing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dangling_external_links: Vec<ExternalLinkStatus>,
+}
+
+pub fn last_report() -> Option<BuildReport> {
+    LAST_REPORT.lock().ok().and_then(|report| report.clone())
+}
+
+/// Adds the graph journal's replay to the last build's report. The graph actor replays
+/// after the report is written, so this fills it in afterwards.
+pub fn record_journal_replay(replay: JournalReplay) {
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        last.get_or_insert_with(BuildReport::default).journal = Some(replay);
+    }
+}
+
+/// Adds node id collisions found while assigning ids to the last build's report
+pub fn record_id_conflicts(conflicts: Vec<IdConflict>) {
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        last.get_or_insert_with(BuildReport::default).id_conflicts.extend(conflicts);
+    }
+}
+
+/// Puts the external links left dangling by the last build in its report, replacing
+/// those of any earlier check
+pub fn record_dangling_external_links(links: Vec<ExternalLinkStatus>) {
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        last.get_or_insert_with(BuildReport::default).dangling_external_links = links;
+    }
+}
+
+/// Links from `content` to the other `known` names. Wiki-link targets match a name
+/// regardless of case, as the editors that write them resolve them that way; plain
+/// mentions only count when the case matches, so "rust" in prose doesn't link to Rust.
+pub fn extract_topic_counts(content: &str, own_name: &str, known: &[(String, Regex)]) -> HashMap<String, usize> {
+    let by_lower: HashMap<String, &str> = known.iter().map(|(name, _)| (name.to_lowercase(), name.as_str())).collect();
+    let mut counts = HashMap::new();
+    for link in WIKI_LINK.captures_iter(content) {
+        if let Some(&name) = by_lower.get(&link[1].trim().to_lowercase()) {
+            *counts.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+    // Linked names aren't counted again as mentions
+    let prose = WIKI_LINK.replace_all(content, " ");
+    for (name, pattern) in known {
+        let mentions = pattern.find_iter(&prose).count();
+        if mentions > 0 {
+            *counts.entry(name.clone()).or_insert(0) += mentions;
+        }
+    }
+    counts.remove(own_name);
+    counts
+}
+
+fn known_names(store: &MetadataStore) -> Vec<(String, Regex)> {
+    store.keys()
+        .map(|key| key.trim_end_matches(".md").to_string())
+        .filter(|name| !name.trim().is_empty())
+        .filter_map(|name| {
+            let pattern = Regex::new(&format!(r"\b{}\b", regex::escape(&name))).ok()?;
+            Some((name, pattern))
+        })
+        .collect()
+}
+
+fn extract_file(path: &Path, own_name: &str, known: &[(String, Regex)], max_bytes: u64) -> (FileExtraction, HashMap<String, usize>) {
+    match fs::metadata(path) {
+        Ok(meta) if meta.len() > max_bytes => return (FileExtraction::TooLarge { bytes: meta.len() }, HashMap::new()),
+        Ok(_) => {}
+        Err(e) => return (FileExtraction::ReadFailed { error: e.to_string() }, HashMap::new()),
+    }
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return (FileExtraction::ReadFailed { error: e.to_string() }, HashMap::new()),
+    };
+    let counts = extract_topic_counts(&content, own_name, known);
+    if counts.is_empty() {
+        return (FileExtraction::NoReferences, counts);
+    }
+    let status = FileExtraction::Extracted { references: counts.values().sum(), targets: counts.len() };
+    (status, counts)
+}
+
+/// Fills the empty topic_counts in `store` from the markdown under `markdown_dir`
+pub fn fill_missing(store: &mut MetadataStore, settings: &TopicExtractionSettings) -> ExtractionReport {
+    let started = Instant::now();
+    let deadline = started + Duration::from_millis(settings.time_budget_ms);
+    let mut missing: Vec<String> = store.iter()
+        .filter(|(_, meta)| meta.topic_counts.is_empty())
+        .map(|(key, _)| key.clone())
+        .collect();
+    missing.sort();
+
+    let mut report = ExtractionReport { files: BTreeMap::new(), elapsed_ms: 0, budget_exhausted: false };
+    if missing.is_empty() {
+        return report;
+    }
+    let known = known_names(store);
+    let dir = Path::new(&settings.markdown_dir);
+    for key in missing {
+        if report.budget_exhausted || Instant::now() >= deadline {
+            report.budget_exhausted = true;
+            report.files.insert(key, FileExtraction::OutOfBudget);
+            continue;
+        }
+        let file_name = if key.ends_with(".md") { key.clone() } else { format!("{}.md", key) };
+        let (status, counts) = extract_file(&dir.join(&file_name), key.trim_end_matches(".md"), &known, settings.max_file_bytes);
+        if let Some(meta) = store.get_mut(&key) {
+            meta.topic_counts = counts;
+        }
+        report.files.insert(key, status);
+    }
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+    report
+}
+
+/// Runs the pass off the async runtime if it's enabled, and records the build report.
+/// The store is returned unchanged when the pass is off or can't run.
+pub async fn prepare_for_build(mut store: MetadataStore, settings: &TopicExtractionSettings) -> (MetadataStore, BuildReport) {
+    let mut report = BuildReport::default();
+    if settings.enabled {
+        let settings = settings.clone();
+        let original = store.clone();
+        match tokio::task::spawn_blocking(move || {
+            let extraction = fill_missing(&mut store, &settings);
+            (store, extraction)
+        }).await {
+            Ok((filled, extraction)) => {
+                let extracted = extraction.files.values().filter(|f| matches!(f, FileExtraction::Extracted { .. })).count();
+                info!("Topic extraction filled {} of {} files without topic_counts in {}ms",
+                    extracted, extraction.files.len(), extraction.elapsed_ms);
+                if extraction.budget_exhausted {
+                    warn!("Topic extraction ran out of its time budget; remaining files were skipped");
+                }
+                debug!("Topic extraction results: {:?}", extraction.files);
+                store = filled;
+                report.topic_extraction = Some(extraction);
+            }
+            Err(e) => {
+                warn!("Topic extraction task failed: {}", e);
+                store = original;
+            }
+        }
+    }
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        *last = Some(report.clone());
+    }
+    (store, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use crate::actors::messages::{BuildGraphFromMetadata, GetGraphData, StopSimulation};
+    use crate::actors::{ClientManagerActor, GraphServiceActor};
+    use crate::models::metadata::Metadata;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    fn fixture_dir(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("topic-extraction-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        dir
+    }
+
+    fn store(entries: &[(&str, &[(&str, usize)])]) -> MetadataStore {
+        entries.iter().map(|(name, counts)| {
+            let meta = Metadata {
+                file_name: name.to_string(),
+                topic_counts: counts.iter().map(|(t, c)| (t.to_string(), *c)).collect(),
+                ..Default::default()
+            };
+            (name.to_string(), meta)
+        }).collect()
+    }
+
+    fn settings(dir: &Path) -> TopicExtractionSettings {
+        TopicExtractionSettings {
+            enabled: true,
+            markdown_dir: dir.to_string_lossy().to_string(),
+            max_file_bytes: 1024,
+            time_budget_ms: 10_000,
+        }
+    }
+
+    #[test]
+    fn test_wiki_links_and_case_matched_mentions() {
+        let known = known_names(&store(&[("Rust.md", &[]), ("Graph Theory.md", &[]), ("Notes.md", &[])]));
+        let content = "See [[graph theory|graphs]] and [[Rust#Ownership]]. Rust is used here; rust is not. \
+                       Notes mention Notes and [[Missing Page]].";
+        let counts = extract_topic_counts(content, "Notes", &known);
+        assert_eq!(counts.get("Graph Theory"), Some(&1));
+        // One link plus one case-matched mention
+        assert_eq!(counts.get("Rust"), Some(&2));
+        assert!(!counts.contains_key("Notes"));
+        assert_eq!(counts.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_extracted_counts_become_edges() {
+        let big = format!("Alpha {}", "x".repeat(2048));
+        let dir = fixture_dir(&[
+            ("Alpha.md", "Links to [[Beta]] twice: [[beta|b]]. Also mentions Gamma."),
+            ("Beta.md", "Nothing to see here."),
+            ("Delta.md", &big),
+        ]);
+        // Gamma already has counts and Epsilon's file is missing
+        let mut metadata = store(&[
+            ("Alpha.md", &[]), ("Beta.md", &[]), ("Gamma.md", &[("Beta", 3)]), ("Delta.md", &[]), ("Epsilon.md", &[]),
+        ]);
+
+        let report = fill_missing(&mut metadata, &settings(&dir));
+        assert_eq!(report.files.get("Alpha.md"), Some(&FileExtraction::Extracted { references: 3, targets: 2 }));
+        assert_eq!(report.files.get("Beta.md"), Some(&FileExtraction::NoReferences));
+        assert_eq!(report.files.get("Delta.md"), Some(&FileExtraction::TooLarge { bytes: big.len() as u64 }));
+        assert!(matches!(report.files.get("Epsilon.md"), Some(FileExtraction::ReadFailed { .. })));
+        assert!(!report.files.contains_key("Gamma.md"));
+        assert!(!report.budget_exhausted);
+
+        let graph = GraphServiceActor::new(ClientManagerActor::new().start(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        graph.send(BuildGraphFromMetadata { metadata }).await.unwrap().unwrap();
+        let data = graph.send(GetGraphData).await.unwrap().unwrap();
+        let names: HashMap<u32, &str> = data.nodes.iter().map(|n| (n.id, n.metadata_id.as_str())).collect();
+        let edges: HashSet<(String, String, u32)> = data.edges.iter().map(|e| {
+            let mut pair = [names[&e.source], names[&e.target]];
+            pair.sort();
+            (pair[0].to_string(), pair[1].to_string(), e.raw_weight.unwrap_or(0.0) as u32)
+        }).collect();
+        assert_eq!(edges, HashSet::from([
+            ("Alpha".to_string(), "Beta".to_string(), 2),
+            ("Alpha".to_string(), "Gamma".to_string(), 1),
+            ("Beta".to_string(), "Gamma".to_string(), 3),
+        ]));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn test_pass_is_bounded_and_optional() {
+        let dir = fixture_dir(&[("A.md", "[[B]]"), ("B.md", "[[A]]")]);
+        let metadata = store(&[("A.md", &[]), ("B.md", &[])]);
+
+        let out_of_time = TopicExtractionSettings { time_budget_ms: 0, ..settings(&dir) };
+        let (unchanged, report) = prepare_for_build(metadata.clone(), &out_of_time).await;
+        let extraction = report.topic_extraction.unwrap();
+        assert!(extraction.budget_exhausted);
+        assert!(extraction.files.values().all(|f| *f == FileExtraction::OutOfBudget));
+        assert!(unchanged.values().all(|m| m.topic_counts.is_empty()));
+
+        let disabled = TopicExtractionSettings { enabled: false, ..settings(&dir) };
+        let (_, report) = prepare_for_build(metadata.clone(), &disabled).await;
+        assert!(report.topic_extraction.is_none());
+
+        let (filled, _) = prepare_for_build(metadata, &settings(&dir)).await;
+        assert_eq!(filled["A.md"].topic_counts.get("B"), Some(&1));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,234 @@
+//! TTS audio by utterance. Audio collects here as it's synthesized. Once finished, it stays
+//! downloadable for the retention window, for clients that would rather fetch a whole file
+//! than put websocket chunks back together.
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::config::SpeechUtteranceSettings;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UtteranceStatus {
+    Missing,
+    Synthesizing { bytes: usize },
+    Ready { audio: Bytes, content_type: String },
+    Failed(String),
+    Expired,
+}
+
+enum Stage {
+    Synthesizing(Vec<u8>),
+    Ready(Bytes),
+    Failed(String),
+    // Audio dropped; the id is kept a while longer so it answers 410, not 404
+    Expired,
+}
+
+struct Utterance {
+    content_type: String,
+    stage: Stage,
+    created_at: Instant,
+    finished_at: Option<Instant>,
+}
+
+/// Content type for a TTS `response_format`
+pub fn content_type_for(format: &str) -> &'static str {
+    match format.to_lowercase().as_str() {
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "opus" | "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "aac" => "audio/aac",
+        "pcm" => "audio/L16",
+        _ => "application/octet-stream",
+    }
+}
+
+pub struct UtteranceStore {
+    settings: SpeechUtteranceSettings,
+    utterances: Mutex<HashMap<String, Utterance>>,
+    // Ids of utterances as they finish, for waiters
+    finished_tx: broadcast::Sender<String>,
+}
+
+impl UtteranceStore {
+    pub fn new(settings: SpeechUtteranceSettings) -> Self {
+        let (finished_tx, _) = broadcast::channel(64);
+        Self { settings, utterances: Mutex::new(HashMap::new()), finished_tx }
+    }
+
+    fn retention(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.retention_secs.max(0.0))
+    }
+
+    /// Starts a new utterance and returns its id
+    pub fn begin(&self, now: Instant) -> String {
+        self.purge(now);
+        let mut utterances = self.utterances.lock().unwrap();
+        // Over the cap, make room by dropping the oldest finished audio
+        let mut finished: Vec<(Instant, String)> = utterances.iter()
+            .filter(|(_, u)| u.finished_at.is_some())
+            .map(|(id, u)| (u.created_at, id.clone()))
+            .collect();
+        finished.sort();
+        let excess = (utterances.len() + 1).saturating_sub(self.settings.max_utterances.max(1));
+        for (_, id) in finished.into_iter().take(excess) {
+            utterances.remove(&id);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        utterances.insert(id.clone(), Utterance {
+            content_type: content_type_for("").to_string(),
+            stage: Stage::Synthesizing(Vec::new()),
+            created_at: now,
+            finished_at: None,
+        });
+        id
+    }
+
+    pub fn set_format(&self, id: &str, format: &str) {
+        if let Some(utterance) = self.utterances.lock().unwrap().get_mut(id) {
+            utterance.content_type = content_type_for(format).to_string();
+        }
+    }
+
+    pub fn append(&self, id: &str, chunk: &[u8]) {
+        if let Some(Utterance { stage: Stage::Synthesizing(audio), .. }) = self.utterances.lock().unwrap().get_mut(id) {
+            audio.extend_from_slice(chunk);
+        }
+    }
+
+    pub fn complete(&self, id: &str, now: Instant) {
+        self.finish(id, now, |audio| Stage::Ready(Bytes::from(audio)));
+    }
+
+    pub fn fail(&self, id: &str, error: String, now: Instant) {
+        self.finish(id, now, |_| Stage::Failed(error));
+    }
+
+    fn finish(&self, id: &str, now: Instant, stage: impl FnOnce(Vec<u8>) -> Stage) {
+        {
+            let mut utterances = self.utterances.lock().unwrap();
+            let Some(utterance) = utterances.get_mut(id) else {
+                return;
+            };
+            let Stage::Synthesizing(audio) = &mut utterance.stage else {
+                return;
+            };
+            utterance.stage = stage(std::mem::take(audio));
+            utterance.finished_at = Some(now);
+        }
+        let _ = self.finished_tx.send(id.to_string());
+    }
+
+    pub fn status(&self, id: &str, now: Instant) -> UtteranceStatus {
+        let utterances = self.utterances.lock().unwrap();
+        let Some(utterance) = utterances.get(id) else {
+            return UtteranceStatus::Missing;
+        };
+        if utterance.finished_at.is_some_and(|at| now.duration_since(at) >= self.retention()) {
+            return UtteranceStatus::Expired;
+        }
+        match &utterance.stage {
+            Stage::Synthesizing(audio) => UtteranceStatus::Synthesizing { bytes: audio.len() },
+            Stage::Ready(audio) => UtteranceStatus::Ready { audio: audio.clone(), content_type: utterance.content_type.clone() },
+            Stage::Failed(error) => UtteranceStatus::Failed(error.clone()),
+            Stage::Expired => UtteranceStatus::Expired,
+        }
+    }
+
+    /// Waits for the utterance to finish, up to `timeout`, and returns its status
+    pub async fn wait_finished(&self, id: &str, timeout: Duration) -> UtteranceStatus {
+        let mut finished = self.finished_tx.subscribe();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let status = self.status(id, Instant::now());
+            if !matches!(status, UtteranceStatus::Synthesizing { .. }) {
+                return status;
+            }
+            match tokio::time::timeout_at(deadline, finished.recv()).await {
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                _ => return status,
+            }
+        }
+    }
+
+    /// Drops audio past the retention window, and forgets ids a window after that.
+    /// Synthesis that never finished is given up on after two windows.
+    pub fn purge(&self, now: Instant) {
+        let retention = self.retention();
+        let mut utterances = self.utterances.lock().unwrap();
+        utterances.retain(|_, utterance| {
+            let since = now.duration_since(utterance.finished_at.unwrap_or(utterance.created_at));
+            since < retention * 2
+        });
+        for utterance in utterances.values_mut() {
+            if utterance.finished_at.is_some_and(|at| now.duration_since(at) >= retention) {
+                utterance.stage = Stage::Expired;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_is_kept_for_the_retention_window() {
+        let store = UtteranceStore::new(SpeechUtteranceSettings { retention_secs: 60.0, max_utterances: 2 });
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        let id = store.begin(at(0));
+        store.set_format(&id, "wav");
+        store.append(&id, b"RIFF");
+        assert_eq!(store.status(&id, at(1)), UtteranceStatus::Synthesizing { bytes: 4 });
+        store.append(&id, b"data");
+        store.complete(&id, at(2));
+        assert_eq!(store.status(&id, at(61)), UtteranceStatus::Ready {
+            audio: Bytes::from_static(b"RIFFdata"),
+            content_type: "audio/wav".to_string(),
+        });
+        // Late chunks don't change a finished utterance
+        store.append(&id, b"more");
+        store.fail(&id, "late".to_string(), at(3));
+
+        assert_eq!(store.status(&id, at(62)), UtteranceStatus::Expired);
+        store.purge(at(62));
+        assert_eq!(store.status(&id, at(62)), UtteranceStatus::Expired);
+        store.purge(at(122));
+        assert_eq!(store.status(&id, at(122)), UtteranceStatus::Missing);
+
+        let failed = store.begin(at(200));
+        store.fail(&failed, "Kokoro API error".to_string(), at(201));
+        assert_eq!(store.status(&failed, at(201)), UtteranceStatus::Failed("Kokoro API error".to_string()));
+
+        // At the cap, the oldest finished utterance makes room; running ones are kept
+        let running = store.begin(at(202));
+        let newest = store.begin(at(203));
+        assert_eq!(store.status(&failed, at(203)), UtteranceStatus::Missing);
+        assert_eq!(store.status(&running, at(203)), UtteranceStatus::Synthesizing { bytes: 0 });
+        assert_eq!(store.status(&newest, at(203)), UtteranceStatus::Synthesizing { bytes: 0 });
+    }
+
+    #[actix_web::test]
+    async fn test_waiters_see_the_finished_status() {
+        let store = std::sync::Arc::new(UtteranceStore::new(SpeechUtteranceSettings::default()));
+        let id = store.begin(Instant::now());
+        let waiter = {
+            let (store, id) = (store.clone(), id.clone());
+            tokio::spawn(async move { store.wait_finished(&id, Duration::from_secs(5)).await })
+        };
+        tokio::task::yield_now().await;
+        store.append(&id, b"abc");
+        store.complete(&id, Instant::now());
+        assert!(matches!(waiter.await.unwrap(), UtteranceStatus::Ready { .. }));
+
+        let stuck = store.begin(Instant::now());
+        assert_eq!(store.wait_finished(&stuck, Duration::from_millis(20)).await, UtteranceStatus::Synthesizing { bytes: 0 });
+    }
+}
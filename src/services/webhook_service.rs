@@ -0,0 +1,567 @@
+//! Outbound webhooks, for integrations that want to follow the graph without holding a
+//! websocket open. Events are queued and posted by a single worker in the order they
+//! happened; each one goes to every subscribed endpoint at once, retried with backoff.
+//! Endpoints that keep failing are circuit-broken and re-probed on a timer.
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use log::{debug, info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::config::{WebhookEndpoint, WebhookEventKind, WebhookSettings};
+use crate::services::event_log::EventLog;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+const EVENT_ACTOR: &str = "webhooks";
+// How often the prober looks for open circuits that are due a retry
+const PROBE_TICK: Duration = Duration::from_secs(1);
+
+/// The JSON body every endpoint receives
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub id: String,
+    pub event: WebhookEventKind,
+    pub at: DateTime<Utc>,
+    pub data: Value,
+}
+
+impl WebhookPayload {
+    pub fn new(event: WebhookEventKind, data: Value) -> Self {
+        Self { id: uuid::Uuid::new_v4().to_string(), event, at: Utc::now(), data }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    Delivered { attempts: u32, code: u16 },
+    Failed { attempts: u32, error: String },
+    // The endpoint's circuit is open, so nothing was sent
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointDelivery {
+    pub url: String,
+    #[serde(flatten)]
+    pub outcome: DeliveryOutcome,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCounters {
+    pub delivered: u64,
+    pub failed: u64,
+    pub retries: u64,
+    // Events not sent because the circuit was open
+    pub dropped: u64,
+    pub consecutive_failures: u32,
+    pub circuit_open: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookCounters {
+    pub queued: u64,
+    // Events dropped because the worker had fallen too far behind
+    pub queue_full: u64,
+    pub endpoints: BTreeMap<String, EndpointCounters>,
+}
+
+#[derive(Default)]
+struct EndpointState {
+    counters: EndpointCounters,
+    // Set while the circuit is open
+    next_probe: Option<Instant>,
+}
+
+pub struct WebhookService {
+    settings: WebhookSettings,
+    client: Client,
+    event_log: Arc<EventLog>,
+    endpoints: Mutex<HashMap<String, EndpointState>>,
+    sender: mpsc::Sender<WebhookPayload>,
+    // Taken by the worker when it starts
+    receiver: Mutex<Option<mpsc::Receiver<WebhookPayload>>>,
+    queued: AtomicU64,
+    queue_full: AtomicU64,
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body`, as sent in the signature header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mac = hmac_sha256(secret.as_bytes(), body);
+    format!("sha256={}", mac.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|k| k ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().into()
+}
+
+/// The webhook category a client broadcast belongs to, if any
+pub fn broadcast_kind(message: &str) -> Option<WebhookEventKind> {
+    let event: Value = serde_json::from_str(message).ok()?;
+    match event.get("type")?.as_str()? {
+        "graph_diff" => Some(WebhookEventKind::GraphDiff),
+        "annotation_created" | "annotation_deleted" => Some(WebhookEventKind::Annotations),
+        _ => None,
+    }
+}
+
+impl WebhookService {
+    pub fn new(settings: WebhookSettings, event_log: Arc<EventLog>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_ms.max(1)))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity.max(1));
+        let endpoints = settings.endpoints.iter()
+            .map(|endpoint| (endpoint.url.clone(), EndpointState::default()))
+            .collect();
+        Self {
+            settings,
+            client,
+            event_log,
+            endpoints: Mutex::new(endpoints),
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            queued: AtomicU64::new(0),
+            queue_full: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.settings.endpoints.is_empty()
+    }
+
+    fn subscribers(&self, kind: WebhookEventKind) -> impl Iterator<Item = &WebhookEndpoint> {
+        self.settings.endpoints.iter()
+            .filter(move |endpoint| endpoint.events.is_empty() || endpoint.events.contains(&kind))
+    }
+
+    /// Spawns the delivery worker and the prober for open circuits
+    pub fn start(self: Arc<Self>) {
+        if !self.is_enabled() {
+            info!("No webhook endpoints configured; delivery worker not started");
+            return;
+        }
+        let Some(mut receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+        info!("Starting webhook delivery to {} endpoints", self.settings.endpoints.len());
+
+        let worker = self.clone();
+        tokio::spawn(async move {
+            while let Some(payload) = receiver.recv().await {
+                worker.deliver(&payload).await;
+            }
+        });
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PROBE_TICK);
+            loop {
+                ticker.tick().await;
+                self.probe_open_endpoints(Instant::now()).await;
+            }
+        });
+    }
+
+    /// Queues `data` for the endpoints subscribed to `kind`. Never blocks; when the
+    /// worker is too far behind the event is dropped and counted.
+    pub fn notify(&self, kind: WebhookEventKind, data: Value) {
+        if self.subscribers(kind).next().is_none() {
+            return;
+        }
+        match self.sender.try_send(WebhookPayload::new(kind, data)) {
+            Ok(()) => {
+                self.queued.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                self.queue_full.fetch_add(1, Ordering::Relaxed);
+                warn!("Dropped {} webhook: {}", kind.as_str(), e);
+            }
+        }
+    }
+
+    /// Forwards a client broadcast if it's a graph diff or annotation event
+    pub fn notify_broadcast(&self, message: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some(kind) = broadcast_kind(message) {
+            if let Ok(data) = serde_json::from_str(message) {
+                self.notify(kind, data);
+            }
+        }
+    }
+
+    /// Posts `payload` to every subscribed endpoint, retrying each as configured
+    pub async fn deliver(&self, payload: &WebhookPayload) -> Vec<EndpointDelivery> {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialise {} webhook: {}", payload.event.as_str(), e);
+                return Vec::new();
+            }
+        };
+        let deliveries = self.subscribers(payload.event).map(|endpoint| async {
+            let outcome = self.deliver_to(endpoint, payload, &body).await;
+            EndpointDelivery { url: endpoint.url.clone(), outcome }
+        });
+        join_all(deliveries).await
+    }
+
+    async fn deliver_to(&self, endpoint: &WebhookEndpoint, payload: &WebhookPayload, body: &[u8]) -> DeliveryOutcome {
+        {
+            let mut endpoints = self.endpoints.lock().unwrap();
+            let state = endpoints.entry(endpoint.url.clone()).or_default();
+            if state.next_probe.is_some() {
+                state.counters.dropped += 1;
+                return DeliveryOutcome::Skipped;
+            }
+        }
+
+        let event = payload.event.as_str();
+        let max_attempts = self.settings.max_attempts.max(1);
+        let mut attempts = 0;
+        let outcome = loop {
+            attempts += 1;
+            let error = match self.post(endpoint, event, body).await {
+                Ok(code) => break DeliveryOutcome::Delivered { attempts, code },
+                Err((error, retryable)) if retryable && attempts < max_attempts => error,
+                Err((error, _)) => break DeliveryOutcome::Failed { attempts, error },
+            };
+            let delay = self.backoff(attempts);
+            debug!("Webhook {} to {} failed ({}); retrying in {:?}", event, endpoint.url, error, delay);
+            tokio::time::sleep(delay).await;
+        };
+
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let state = endpoints.entry(endpoint.url.clone()).or_default();
+        state.counters.retries += u64::from(attempts - 1);
+        match &outcome {
+            DeliveryOutcome::Delivered { code, .. } => {
+                state.counters.delivered += 1;
+                state.counters.consecutive_failures = 0;
+                self.event_log.record(EVENT_ACTOR, "webhook_delivered", json!({
+                    "url": endpoint.url, "event": event, "id": payload.id, "attempts": attempts, "code": code,
+                }));
+            }
+            DeliveryOutcome::Failed { error, .. } => {
+                warn!("Webhook {} to {} failed after {} attempts: {}", event, endpoint.url, attempts, error);
+                state.counters.failed += 1;
+                state.counters.consecutive_failures += 1;
+                state.counters.last_error = Some(error.clone());
+                self.event_log.record(EVENT_ACTOR, "webhook_failed", json!({
+                    "url": endpoint.url, "event": event, "id": payload.id, "attempts": attempts, "error": error,
+                }));
+                let threshold = self.settings.failure_threshold;
+                if threshold > 0 && state.counters.consecutive_failures >= threshold {
+                    warn!("Opening circuit for webhook {} after {} failures", endpoint.url, threshold);
+                    state.next_probe = Some(Instant::now() + self.probe_interval());
+                    state.counters.circuit_open = true;
+                    self.event_log.record(EVENT_ACTOR, "webhook_circuit_open", json!({ "url": endpoint.url }));
+                }
+            }
+            DeliveryOutcome::Skipped => {}
+        }
+        outcome
+    }
+
+    /// One POST. Errors say whether they're worth retrying: timeouts, connection
+    /// failures, 5xx and 429 are; other rejections won't change on a retry.
+    async fn post(&self, endpoint: &WebhookEndpoint, event: &str, body: &[u8]) -> Result<u16, (String, bool)> {
+        let mut request = self.client.post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, event)
+            .body(body.to_vec());
+        if let Some(secret) = &endpoint.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(response.status().as_u16()),
+            Ok(response) => {
+                let status = response.status();
+                let retryable = status.is_server_error() || status.as_u16() == 429;
+                Err((format!("HTTP {}", status.as_u16()), retryable))
+            }
+            Err(e) => Err((e.to_string(), true)),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(self.settings.initial_backoff_ms.saturating_mul(factor).min(self.settings.max_backoff_ms))
+    }
+
+    fn probe_interval(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.probe_interval_secs.max(1.0))
+    }
+
+    /// Sends a `ping` to each open-circuit endpoint that's due one, closing the circuit
+    /// of any that answers. Returns how many were closed.
+    pub async fn probe_open_endpoints(&self, now: Instant) -> usize {
+        let due: Vec<&WebhookEndpoint> = {
+            let endpoints = self.endpoints.lock().unwrap();
+            self.settings.endpoints.iter()
+                .filter(|endpoint| {
+                    endpoints.get(&endpoint.url)
+                        .and_then(|state| state.next_probe)
+                        .is_some_and(|next| next <= now)
+                })
+                .collect()
+        };
+        if due.is_empty() {
+            return 0;
+        }
+
+        let body = json!({ "id": uuid::Uuid::new_v4().to_string(), "event": "ping", "at": Utc::now() }).to_string();
+        let results = join_all(due.iter().map(|endpoint| self.post(endpoint, "ping", body.as_bytes()))).await;
+
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let mut closed = 0;
+        for (endpoint, result) in due.into_iter().zip(results) {
+            let state = endpoints.entry(endpoint.url.clone()).or_default();
+            match result {
+                Ok(_) => {
+                    info!("Webhook {} answered a probe; closing its circuit", endpoint.url);
+                    state.next_probe = None;
+                    state.counters.circuit_open = false;
+                    state.counters.consecutive_failures = 0;
+                    self.event_log.record(EVENT_ACTOR, "webhook_circuit_closed", json!({ "url": endpoint.url }));
+                    closed += 1;
+                }
+                Err((error, _)) => {
+                    debug!("Webhook {} still failing probes: {}", endpoint.url, error);
+                    state.next_probe = Some(now + self.probe_interval());
+                    state.counters.last_error = Some(error);
+                }
+            }
+        }
+        closed
+    }
+
+    pub fn counters(&self) -> WebhookCounters {
+        let endpoints = self.endpoints.lock().unwrap();
+        WebhookCounters {
+            queued: self.queued.load(Ordering::Relaxed),
+            queue_full: self.queue_full.load(Ordering::Relaxed),
+            endpoints: endpoints.iter().map(|(url, state)| (url.clone(), state.counters.clone())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Debug, Clone)]
+    struct ReceivedRequest {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Answers each request with the next scripted status, then `fallback`
+    #[derive(Clone)]
+    struct MockServer {
+        url: String,
+        requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+        statuses: Arc<Mutex<VecDeque<u16>>>,
+        fallback: Arc<Mutex<u16>>,
+    }
+
+    impl MockServer {
+        async fn start(statuses: Vec<u16>, fallback: u16) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = Self {
+                url: format!("http://{}/hook", listener.local_addr().unwrap()),
+                requests: Arc::new(Mutex::new(Vec::new())),
+                statuses: Arc::new(Mutex::new(statuses.into())),
+                fallback: Arc::new(Mutex::new(fallback)),
+            };
+            let handle = server.clone();
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let request = read_request(&mut stream).await;
+                    handle.requests.lock().unwrap().push(request);
+                    let status = handle.statuses.lock().unwrap().pop_front()
+                        .unwrap_or(*handle.fallback.lock().unwrap());
+                    let response = format!("HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+            server
+        }
+
+        fn requests(&self) -> Vec<ReceivedRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> ReceivedRequest {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        let header_end = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            data.extend_from_slice(&buf[..n]);
+            if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            assert!(n > 0, "connection closed mid-headers");
+        };
+        let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+        let headers: HashMap<String, String> = head.lines().skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let length: usize = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+        while data.len() < header_end + length {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed mid-body");
+            data.extend_from_slice(&buf[..n]);
+        }
+        let body = String::from_utf8_lossy(&data[header_end..header_end + length]).to_string();
+        ReceivedRequest { headers, body }
+    }
+
+    fn settings(endpoints: Vec<WebhookEndpoint>) -> WebhookSettings {
+        WebhookSettings {
+            endpoints,
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            timeout_ms: 2000,
+            failure_threshold: 2,
+            probe_interval_secs: 1.0,
+            queue_capacity: 10,
+        }
+    }
+
+    fn endpoint(url: &str, secret: Option<&str>, events: Vec<WebhookEventKind>) -> WebhookEndpoint {
+        WebhookEndpoint { url: url.to_string(), secret: secret.map(str::to_string), events }
+    }
+
+    #[test]
+    fn test_hmac_matches_rfc_4231() {
+        let mac = sign("Jefe", b"what do ya want for nothing?");
+        assert_eq!(mac, "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_retries_server_errors() {
+        let server = MockServer::start(vec![500, 503], 200).await;
+        let event_log = Arc::new(EventLog::new());
+        let service = WebhookService::new(
+            settings(vec![endpoint(&server.url, Some("s3cret"), vec![])]),
+            event_log.clone(),
+        );
+
+        let payload = WebhookPayload::new(WebhookEventKind::GraphDiff, json!({ "removedNodes": [4] }));
+        let deliveries = service.deliver(&payload).await;
+        assert_eq!(deliveries[0].outcome, DeliveryOutcome::Delivered { attempts: 3, code: 200 });
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        for request in &requests {
+            assert_eq!(request.headers["x-webhook-signature"], sign("s3cret", request.body.as_bytes()));
+            assert_eq!(request.headers["x-webhook-event"], "graph_diff");
+        }
+        let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!((body["event"].as_str(), body["data"]["removedNodes"][0].as_u64()), (Some("graph_diff"), Some(4)));
+
+        let counters = &service.counters().endpoints[&server.url];
+        assert_eq!((counters.delivered, counters.retries, counters.failed), (1, 2, 0));
+        assert_eq!(event_log.recent(1)[0].kind, "webhook_delivered");
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let server = MockServer::start(vec![], 400).await;
+        let service = WebhookService::new(settings(vec![endpoint(&server.url, None, vec![])]), Arc::new(EventLog::new()));
+
+        let deliveries = service.deliver(&WebhookPayload::new(WebhookEventKind::Enrichment, json!({}))).await;
+        assert!(matches!(deliveries[0].outcome, DeliveryOutcome::Failed { attempts: 1, .. }));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].headers.contains_key("x-webhook-signature"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_endpoint_is_circuit_broken_and_reprobed() {
+        let server = MockServer::start(vec![], 503).await;
+        let event_log = Arc::new(EventLog::new());
+        let service = WebhookService::new(settings(vec![endpoint(&server.url, None, vec![])]), event_log.clone());
+        let payload = WebhookPayload::new(WebhookEventKind::BuildReport, json!({}));
+
+        for _ in 0..2 {
+            assert!(matches!(service.deliver(&payload).await[0].outcome, DeliveryOutcome::Failed { attempts: 3, .. }));
+        }
+        // Open now: nothing more is sent until a probe gets through
+        assert_eq!(service.deliver(&payload).await[0].outcome, DeliveryOutcome::Skipped);
+        assert_eq!(server.requests().len(), 6);
+        let counters = &service.counters().endpoints[&server.url];
+        assert!(counters.circuit_open);
+        assert_eq!((counters.failed, counters.dropped), (2, 1));
+
+        // Not due yet, then due but still failing
+        assert_eq!(service.probe_open_endpoints(Instant::now()).await, 0);
+        assert_eq!(server.requests().len(), 6);
+        let later = Instant::now() + Duration::from_secs(2);
+        assert_eq!(service.probe_open_endpoints(later).await, 0);
+        assert_eq!(server.requests()[6].headers["x-webhook-event"], "ping");
+
+        *server.fallback.lock().unwrap() = 200;
+        assert_eq!(service.probe_open_endpoints(later + Duration::from_secs(2)).await, 1);
+        assert!(!service.counters().endpoints[&server.url].circuit_open);
+        assert_eq!(event_log.recent(1)[0].kind, "webhook_circuit_closed");
+        assert!(matches!(service.deliver(&payload).await[0].outcome, DeliveryOutcome::Delivered { attempts: 1, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_worker_delivers_subscribed_broadcasts() {
+        let server = MockServer::start(vec![], 200).await;
+        let service = Arc::new(WebhookService::new(
+            settings(vec![endpoint(&server.url, None, vec![WebhookEventKind::Annotations])]),
+            Arc::new(EventLog::new()),
+        ));
+        service.clone().start();
+
+        service.notify_broadcast(&json!({ "type": "graph_diff", "removedNodes": [1] }).to_string());
+        service.notify_broadcast(&json!({ "type": "node_metadata_update", "nodes": [] }).to_string());
+        service.notify_broadcast(&json!({ "type": "annotation_created", "nodeId": 7 }).to_string());
+        for _ in 0..100 {
+            if !server.requests().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!((body["event"].as_str(), body["data"]["nodeId"].as_u64()), (Some("annotations"), Some(7)));
+        assert_eq!(service.counters().queued, 1);
+    }
+}
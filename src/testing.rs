@@ -0,0 +1,172 @@
+//! A graph service wired to one recording client, for tests of what clients are sent. The
+//! simulation runs in deterministic mode: frames only happen when the harness steps them,
+//! on a virtual clock, so a test never sleeps for pacing and two runs with the same seed
+//! send the same bytes.
+
+use actix::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::actors::client_manager_actor::{ClientHandle, ClientManagerActor};
+use crate::actors::graph_actor::GraphServiceActor;
+use crate::actors::messages::{
+    BuildGraphFromMetadata, CloseConnection, GetClientCount, GetSimulationSettings, SendToClientBinary,
+    SendToClientText, SetSimulationSettings, SimulationStep, StopSimulation,
+};
+use crate::config::feature_access::Role;
+use crate::config::SimulationSettings;
+use crate::models::metadata::{Metadata, MetadataStore};
+use crate::utils::auth::Identity;
+use crate::utils::simulation_clock::SimulationModeStatus;
+
+/// One message the recording client was sent
+#[derive(Debug, Clone, PartialEq)]
+pub enum Captured {
+    Text(String),
+    Frame { keyframe: bool, data: Vec<u8> },
+}
+
+impl Captured {
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Captured::Text(text) => Some(text),
+            Captured::Frame { .. } => None,
+        }
+    }
+
+    /// "keyframe" or "delta" for a position frame
+    pub fn frame_kind(&self) -> Option<&'static str> {
+        match self {
+            Captured::Frame { keyframe: true, .. } => Some("keyframe"),
+            Captured::Frame { keyframe: false, .. } => Some("delta"),
+            Captured::Text(_) => None,
+        }
+    }
+}
+
+/// The kinds of the position frames in `captured`, in order
+pub fn frame_kinds(captured: &[Captured]) -> Vec<&'static str> {
+    captured.iter().filter_map(Captured::frame_kind).collect()
+}
+
+struct RecordingClient {
+    captured: Arc<Mutex<Vec<Captured>>>,
+}
+
+impl Actor for RecordingClient {
+    type Context = Context<Self>;
+}
+
+impl Handler<SendToClientText> for RecordingClient {
+    type Result = ();
+    fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+        self.captured.lock().unwrap().push(Captured::Text(msg.0));
+    }
+}
+
+impl Handler<SendToClientBinary> for RecordingClient {
+    type Result = ();
+    fn handle(&mut self, msg: SendToClientBinary, _ctx: &mut Self::Context) {
+        self.captured.lock().unwrap().push(Captured::Frame { keyframe: msg.keyframe, data: msg.data });
+    }
+}
+
+impl Handler<CloseConnection> for RecordingClient {
+    type Result = ();
+    fn handle(&mut self, _msg: CloseConnection, _ctx: &mut Self::Context) {}
+}
+
+// Answered once everything sent to the client before it has been recorded
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Flush;
+
+impl Handler<Flush> for RecordingClient {
+    type Result = ();
+    fn handle(&mut self, _msg: Flush, _ctx: &mut Self::Context) {}
+}
+
+pub struct TestHarness {
+    pub graph: Addr<GraphServiceActor>,
+    pub client_manager: Addr<ClientManagerActor>,
+    client: Addr<RecordingClient>,
+    captured: Arc<Mutex<Vec<Captured>>>,
+    seen: usize,
+}
+
+impl TestHarness {
+    /// A graph service in deterministic mode with `settings`' mode and seed, and one viewer
+    pub async fn new(settings: SimulationSettings) -> Self {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let client = RecordingClient { captured: captured.clone() }.start();
+        let mut client_manager = ClientManagerActor::new();
+        let handle = ClientHandle { text: client.clone().recipient(), binary: client.clone().recipient(), close: client.clone().recipient() };
+        client_manager.register_client(handle, Identity { pubkey: None, role: Role::Viewer });
+        let client_manager = client_manager.start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        graph.send(StopSimulation).await.unwrap().unwrap();
+        let mut harness = Self { graph, client_manager, client, captured, seen: 0 };
+        harness.set_simulation(settings).await.unwrap();
+        harness.received().await;
+        harness
+    }
+
+    /// Switches simulation settings, staying deterministic
+    pub async fn set_simulation(&self, settings: SimulationSettings) -> Result<SimulationModeStatus, String> {
+        let settings = SimulationSettings { deterministic: true, ..settings };
+        self.graph.send(SetSimulationSettings { settings }).await.unwrap()
+    }
+
+    /// Builds the graph from empty files with these names
+    pub async fn build(&self, files: &[&str]) {
+        let mut store = MetadataStore::new();
+        for name in files {
+            store.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        self.graph.send(BuildGraphFromMetadata { metadata: store }).await.unwrap().unwrap();
+    }
+
+    /// Runs `frames` ticks of the loop and returns what the client was sent meanwhile
+    pub async fn step(&mut self, frames: usize) -> Vec<Captured> {
+        for _ in 0..frames {
+            self.graph.send(SimulationStep).await.unwrap().unwrap();
+        }
+        self.received().await
+    }
+
+    /// Everything the client has been sent since the last call
+    pub async fn received(&mut self) -> Vec<Captured> {
+        // Each hop forwards with do_send, so a round trip through each in turn means
+        // whatever was sent before has arrived
+        self.graph.send(GetSimulationSettings).await.unwrap().unwrap();
+        self.client_manager.send(GetClientCount).await.unwrap().unwrap();
+        self.client.send(Flush).await.unwrap();
+        let captured = self.captured.lock().unwrap();
+        let new = captured[self.seen..].to_vec();
+        self.seen = captured.len();
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::SetWarmupSettings;
+    use crate::config::WarmupSettings;
+
+    // The position frames of 20 steps over a fresh graph
+    async fn run(seed: u64) -> Vec<Captured> {
+        let mut harness = TestHarness::new(SimulationSettings { seed, ..Default::default() }).await;
+        let settings = WarmupSettings { skip_warmup: true, ..Default::default() };
+        harness.graph.send(SetWarmupSettings { settings }).await.unwrap().unwrap();
+        harness.build(&["a.md", "b.md", "c.md"]).await;
+        harness.step(20).await.into_iter().filter(|c| c.frame_kind().is_some()).collect()
+    }
+
+    #[actix_web::test]
+    async fn test_same_seed_sends_the_same_frames() {
+        let first = run(7).await;
+        assert_eq!(frame_kinds(&first).len(), 20);
+        assert_eq!(first, run(7).await);
+        assert_ne!(first, run(8).await);
+    }
+}
@@ -1,9 +1,16 @@
 use tokio::sync::mpsc;
+use serde::Serialize;
 use std::error::Error;
 use std::fmt;
 
+use crate::utils::audio_resample::{AudioInput, PcmFormat};
+
+// `TranscriptionOptions::language` value that lets the provider detect the language
+pub const AUTO_LANGUAGE: &str = "auto";
+
 #[derive(Debug)]
 pub enum SpeechError {
+    #[cfg(feature = "speech")]
     WebSocketError(tungstenite::Error),
     ConnectionError(String),
     SendError(mpsc::error::SendError<SpeechCommand>),
@@ -12,11 +19,81 @@ pub enum SpeechError {
     Base64Error(base64::DecodeError),
     BroadcastError(String),
     TTSError(String),
+    ProviderWarmingUp(ProviderWarmingUp),
+}
+
+/// The two things the speech service does, each warmed up on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeechCapability {
+    Tts,
+    Stt,
+}
+
+impl SpeechCapability {
+    pub const ALL: [SpeechCapability; 2] = [SpeechCapability::Tts, SpeechCapability::Stt];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpeechCapability::Tts => "tts",
+            SpeechCapability::Stt => "stt",
+        }
+    }
+}
+
+/// The provider behind a capability hasn't answered its warm-up probe yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProviderWarmingUp {
+    pub capability: SpeechCapability,
+    pub retry_after_secs: f32,
+}
+
+/// The `error` message speech sockets send
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeechErrorMessage {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capability: Option<SpeechCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<f32>,
+}
+
+impl SpeechErrorMessage {
+    pub fn new(code: &'static str, message: impl fmt::Display) -> Self {
+        Self { kind: "error", code, message: message.to_string(), capability: None, retry_after_secs: None }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl ProviderWarmingUp {
+    pub fn to_ws_message(&self) -> String {
+        SpeechErrorMessage {
+            capability: Some(self.capability),
+            retry_after_secs: Some(self.retry_after_secs),
+            ..SpeechErrorMessage::new("provider_warming_up", self)
+        }.to_json()
+    }
 }
 
+impl fmt::Display for ProviderWarmingUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} provider is warming up; retry in {:.0}s", self.capability.name().to_uppercase(), self.retry_after_secs.ceil())
+    }
+}
+
+impl Error for ProviderWarmingUp {}
+
 impl fmt::Display for SpeechError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            #[cfg(feature = "speech")]
             SpeechError::WebSocketError(e) => write!(f, "WebSocket error: {}", e),
             SpeechError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
             SpeechError::SendError(e) => write!(f, "Send error: {}", e),
@@ -25,12 +102,14 @@ impl fmt::Display for SpeechError {
             SpeechError::Base64Error(e) => write!(f, "Base64 error: {}", e),
             SpeechError::BroadcastError(msg) => write!(f, "Broadcast error: {}", msg),
             SpeechError::TTSError(msg) => write!(f, "TTS error: {}", msg),
+            SpeechError::ProviderWarmingUp(e) => write!(f, "{}", e),
         }
     }
 }
 
 impl Error for SpeechError {}
 
+#[cfg(feature = "speech")]
 impl From<tungstenite::Error> for SpeechError {
     fn from(err: tungstenite::Error) -> Self {
         SpeechError::WebSocketError(err)
@@ -55,6 +134,12 @@ impl From<std::io::Error> for SpeechError {
     }
 }
 
+impl From<ProviderWarmingUp> for SpeechError {
+    fn from(err: ProviderWarmingUp) -> Self {
+        SpeechError::ProviderWarmingUp(err)
+    }
+}
+
 impl From<base64::DecodeError> for SpeechError {
     fn from(err: base64::DecodeError) -> Self {
         SpeechError::Base64Error(err)
@@ -73,17 +158,30 @@ pub enum STTProvider {
     OpenAI,
 }
 
+impl STTProvider {
+    /// The audio the provider transcribes reliably; anything else is converted first
+    pub fn input_format(&self) -> PcmFormat {
+        match self {
+            STTProvider::Whisper => PcmFormat::mono(16_000),
+            STTProvider::OpenAI => PcmFormat::mono(24_000),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SpeechCommand {
     Initialize,
     SendMessage(String),
-    TextToSpeech(String, SpeechOptions),
+    // Text, options, and the utterance the audio is collected under
+    TextToSpeech(String, SpeechOptions, String),
     Close,
     SetTTSProvider(TTSProvider),
     SetSTTProvider(STTProvider),
     StartTranscription(TranscriptionOptions),
     StopTranscription,
-    ProcessAudioChunk(Vec<u8>),
+    ProcessAudioChunk(Vec<u8>, TranscriptionOptions, AudioInput),
+    // The stream's socket went away; its preprocessing state can go
+    EndAudioStream(String),
 }
 
 #[derive(Debug, Clone)]
@@ -91,6 +189,8 @@ pub struct SpeechOptions {
     pub voice: String,
     pub speed: f32,
     pub stream: bool,
+    // Falls back to kokoro.default_format
+    pub format: Option<String>,
 }
 
 impl Default for SpeechOptions {
@@ -99,6 +199,7 @@ impl Default for SpeechOptions {
             voice: "af_heart".to_string(), // Default Kokoro voice
             speed: 1.0,
             stream: true,
+            format: None,
         }
     }
 }
@@ -109,6 +210,14 @@ pub struct TranscriptionOptions {
     pub model: Option<String>,
     pub temperature: Option<f32>,
     pub stream: bool,
+    // Falls back to whisper.vad_filter
+    pub vad_filter: Option<bool>,
+}
+
+impl TranscriptionOptions {
+    pub fn is_auto_language(&self) -> bool {
+        self.language.as_deref().is_some_and(|language| language.eq_ignore_ascii_case(AUTO_LANGUAGE))
+    }
 }
 
 impl Default for TranscriptionOptions {
@@ -118,6 +227,27 @@ impl Default for TranscriptionOptions {
             model: Some("whisper-1".to_string()),
             temperature: None,
             stream: true,
+            vad_filter: None,
         }
     }
 }
+
+/// A piece of transcribed speech, as broadcast to every speech socket
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptionSegment {
+    pub text: String,
+    // The detected language in auto mode, otherwise the one transcribed with
+    pub language: Option<String>,
+    // False when auto mode fell back to the configured language
+    pub detected: bool,
+    // False for a provisional segment a later one will replace
+    pub is_final: bool,
+}
+
+impl TranscriptionSegment {
+    // Status lines from the service itself carry no language
+    pub fn status(text: &str) -> Self {
+        Self { text: text.to_string(), language: None, detected: false, is_final: true }
+    }
+}
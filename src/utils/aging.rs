@@ -0,0 +1,140 @@
+//! Node aging: documents nobody has touched in a while shrink, fade and are eventually
+//! archived out of the default view.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::AgingSettings;
+use crate::models::graph::{GraphData, GraphSnapshot};
+use crate::models::node::Node;
+
+pub const AGE_OPACITY_KEY: &str = "ageOpacity";
+pub const ARCHIVED_KEY: &str = "archived";
+
+/// How an aging pass left one node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAge {
+    pub node_id: u32,
+    // 1.0 for a fresh document, halving every half-life
+    pub age_opacity: f32,
+    pub size_scale: f32,
+    pub archived: bool,
+}
+
+/// 0.5 ^ (age / half-life), so 1.0 when fresh
+pub fn age_factor(age_days: f32, half_life_days: f32) -> f32 {
+    if age_days <= 0.0 || half_life_days <= 0.0 {
+        return 1.0;
+    }
+    0.5f32.powf(age_days / half_life_days)
+}
+
+/// Size multiplier; nodes shrink towards `min_scale` but never vanish
+pub fn size_scale(factor: f32, min_scale: f32) -> f32 {
+    let min_scale = min_scale.clamp(0.0, 1.0);
+    min_scale + (1.0 - min_scale) * factor.clamp(0.0, 1.0)
+}
+
+pub fn last_modified(node: &Node) -> Option<DateTime<Utc>> {
+    node.metadata.get("lastModified")
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Age of a node, or None for nodes that don't age (no file behind them, or aging off)
+pub fn evaluate(node: &Node, now: DateTime<Utc>, settings: &AgingSettings) -> Option<NodeAge> {
+    if !settings.enabled {
+        return None;
+    }
+    let modified = last_modified(node)?;
+    let age_days = (now - modified).num_seconds().max(0) as f32 / 86_400.0;
+    let factor = age_factor(age_days, settings.half_life_days);
+    Some(NodeAge {
+        node_id: node.id,
+        age_opacity: factor,
+        size_scale: size_scale(factor, settings.min_size_scale),
+        archived: settings.hide_after_days > 0.0 && age_days >= settings.hide_after_days,
+    })
+}
+
+pub fn is_archived(node: &Node) -> bool {
+    node.metadata.get(ARCHIVED_KEY).is_some_and(|v| v == "true")
+}
+
+/// Drops archived nodes and every edge touching one
+pub fn without_archived(graph: &mut GraphData) {
+    let archived: std::collections::HashSet<u32> = graph.nodes.iter()
+        .filter(|n| is_archived(n))
+        .map(|n| n.id)
+        .collect();
+    if archived.is_empty() {
+        return;
+    }
+    graph.nodes.retain(|n| !archived.contains(&n.id));
+    graph.edges.retain(|e| !archived.contains(&e.source) && !archived.contains(&e.target));
+}
+
+/// `without_archived` for a snapshot. Only copies when something is actually archived.
+pub fn snapshot_without_archived(snapshot: GraphSnapshot) -> GraphSnapshot {
+    if !snapshot.nodes.iter().any(is_archived) {
+        return snapshot;
+    }
+    let mut graph = (*snapshot).clone();
+    without_archived(&mut graph);
+    GraphSnapshot::new(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use chrono::Duration;
+
+    fn settings() -> AgingSettings {
+        AgingSettings { enabled: true, half_life_days: 30.0, hide_after_days: 180.0, min_size_scale: 0.2, ..Default::default() }
+    }
+
+    fn node_modified(id: u32, days_ago: i64, now: DateTime<Utc>) -> Node {
+        let mut node = Node::new_with_id(format!("note-{}", id), Some(id));
+        node.metadata.insert("lastModified".to_string(), (now - Duration::days(days_ago)).to_rfc3339());
+        node
+    }
+
+    #[test]
+    fn test_size_curve_halves_towards_floor() {
+        assert_eq!(age_factor(0.0, 30.0), 1.0);
+        assert!((age_factor(30.0, 30.0) - 0.5).abs() < 1e-6);
+        assert!((age_factor(60.0, 30.0) - 0.25).abs() < 1e-6);
+        assert_eq!(size_scale(1.0, 0.2), 1.0);
+        assert!((size_scale(0.5, 0.2) - 0.6).abs() < 1e-6);
+        // Very old nodes bottom out at the floor rather than disappearing
+        assert!((size_scale(age_factor(10_000.0, 30.0), 0.2) - 0.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_hide_threshold() {
+        let now = Utc::now();
+        let settings = settings();
+        assert!(!evaluate(&node_modified(1, 179, now), now, &settings).unwrap().archived);
+        assert!(evaluate(&node_modified(2, 180, now), now, &settings).unwrap().archived);
+        // Zero disables hiding, runtime nodes without a file never age
+        let keep = AgingSettings { hide_after_days: 0.0, ..settings.clone() };
+        assert!(!evaluate(&node_modified(3, 5000, now), now, &keep).unwrap().archived);
+        assert!(evaluate(&Node::new_with_id("runtime".to_string(), Some(4)), now, &settings).is_none());
+        assert!(evaluate(&node_modified(5, 5000, now), now, &AgingSettings::default()).is_none());
+    }
+
+    #[test]
+    fn test_archived_nodes_take_their_edges() {
+        let now = Utc::now();
+        let mut graph = GraphData::new();
+        let mut old = node_modified(1, 400, now);
+        old.metadata.insert(ARCHIVED_KEY.to_string(), "true".to_string());
+        *graph.nodes = vec![old, node_modified(2, 1, now), node_modified(3, 1, now)];
+        *graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 3, 1.0)];
+        without_archived(&mut graph);
+        assert_eq!(graph.nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(graph.edges.len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+//! Refreshing graph analytics in the background. PageRank, the spanning skeleton and the
+//! layout quality score are cached per generation, so without this the first request
+//! after a change pays for the recompute. While the layout is settled (or physics is
+//! idle) and the frame budget has headroom, the graph actor recomputes whichever are
+//! stale, one at a time and in priority order, on the blocking pool. A change landing
+//! mid-run cancels it and its result is dropped.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::config::AnalyticsRefreshSettings;
+use crate::models::graph::GraphGenerations;
+use crate::services::job_service::CancelToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalyticsKind {
+    PageRank,
+    // The maximum-weight spanning tree, the skeleton the API defaults to
+    Skeleton,
+    LayoutQuality,
+}
+
+impl AnalyticsKind {
+    /// Highest priority first: PageRank also decides which hubs go out in every frame
+    pub const ALL: [AnalyticsKind; 3] = [AnalyticsKind::PageRank, AnalyticsKind::Skeleton, AnalyticsKind::LayoutQuality];
+
+    /// The generations a result of this kind is valid for; only the layout score
+    /// depends on positions
+    pub fn key(self, generations: GraphGenerations) -> GraphGenerations {
+        match self {
+            AnalyticsKind::LayoutQuality => generations,
+            _ => GraphGenerations { position_generation: 0, ..generations },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsRun {
+    pub generation: u64,
+    pub position_generation: u64,
+    pub duration_ms: f32,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsRefreshStatus {
+    pub enabled: bool,
+    // Stale analytics waiting to be refreshed, next first
+    pub queue: Vec<AnalyticsKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running: Option<AnalyticsKind>,
+    // The last completed refresh of each kind
+    pub last_runs: BTreeMap<AnalyticsKind, AnalyticsRun>,
+    // Refreshes abandoned because the graph changed under them
+    pub cancelled: u64,
+}
+
+struct Running {
+    kind: AnalyticsKind,
+    key: GraphGenerations,
+    cancel: CancelToken,
+    started: Instant,
+}
+
+pub struct AnalyticsRefresh {
+    settings: AnalyticsRefreshSettings,
+    running: Option<Running>,
+    last_runs: BTreeMap<AnalyticsKind, AnalyticsRun>,
+    cancelled: u64,
+}
+
+impl AnalyticsRefresh {
+    pub fn new(settings: AnalyticsRefreshSettings) -> Self {
+        Self { settings, running: None, last_runs: BTreeMap::new(), cancelled: 0 }
+    }
+
+    pub fn set_settings(&mut self, settings: AnalyticsRefreshSettings) {
+        self.settings = settings;
+        if !self.settings.enabled {
+            self.cancel();
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Starts refreshing the first of `stale` if nothing is running yet
+    pub fn start(&mut self, stale: &[AnalyticsKind], generations: GraphGenerations, now: Instant) -> Option<(AnalyticsKind, CancelToken)> {
+        if !self.settings.enabled || self.running.is_some() {
+            return None;
+        }
+        let kind = AnalyticsKind::ALL.into_iter().find(|kind| stale.contains(kind))?;
+        let cancel = CancelToken::default();
+        self.running = Some(Running { kind, key: kind.key(generations), cancel: cancel.clone(), started: now });
+        Some((kind, cancel))
+    }
+
+    /// Cancels the running refresh if the graph has moved past what it is computing
+    pub fn invalidate(&mut self, generations: GraphGenerations) {
+        if matches!(&self.running, Some(running) if running.key != running.kind.key(generations)) {
+            self.cancel();
+        }
+    }
+
+    fn cancel(&mut self) {
+        if let Some(running) = &self.running {
+            if !running.cancel.is_cancelled() {
+                running.cancel.cancel();
+                self.cancelled += 1;
+            }
+        }
+    }
+
+    /// Ends the running refresh of `kind`; true if its result still holds for
+    /// `generations` and should be cached
+    pub fn finish(&mut self, kind: AnalyticsKind, generations: GraphGenerations, now: Instant) -> bool {
+        self.invalidate(generations);
+        if !matches!(&self.running, Some(running) if running.kind == kind) {
+            return false;
+        }
+        let Some(running) = self.running.take() else {
+            return false;
+        };
+        if running.cancel.is_cancelled() {
+            return false;
+        }
+        self.last_runs.insert(kind, AnalyticsRun {
+            generation: running.key.generation,
+            position_generation: running.key.position_generation,
+            duration_ms: now.saturating_duration_since(running.started).as_secs_f32() * 1000.0,
+        });
+        true
+    }
+
+    pub fn status(&self, stale: &[AnalyticsKind]) -> AnalyticsRefreshStatus {
+        let running = self.running.as_ref().map(|running| running.kind);
+        AnalyticsRefreshStatus {
+            enabled: self.settings.enabled,
+            queue: AnalyticsKind::ALL.into_iter().filter(|kind| stale.contains(kind) && Some(*kind) != running).collect(),
+            running,
+            last_runs: self.last_runs.clone(),
+            cancelled: self.cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(generation: u64, position_generation: u64) -> GraphGenerations {
+        GraphGenerations { generation, position_generation }
+    }
+
+    #[test]
+    fn test_refreshes_in_priority_order_and_drops_stale_results() {
+        let mut refresh = AnalyticsRefresh::new(AnalyticsRefreshSettings::default());
+        let now = Instant::now();
+        let stale = [AnalyticsKind::LayoutQuality, AnalyticsKind::PageRank];
+
+        let (kind, cancel) = refresh.start(&stale, at(1, 1), now).unwrap();
+        assert_eq!(kind, AnalyticsKind::PageRank);
+        // One at a time
+        assert!(refresh.start(&stale, at(1, 1), now).is_none());
+        // Positions moving doesn't touch PageRank
+        refresh.invalidate(at(1, 5));
+        assert!(!cancel.is_cancelled());
+        assert!(refresh.finish(kind, at(1, 5), now));
+        assert_eq!(refresh.status(&[AnalyticsKind::LayoutQuality]).queue, [AnalyticsKind::LayoutQuality]);
+
+        // A mutation mid-run cancels it and the result isn't kept
+        let (kind, cancel) = refresh.start(&[AnalyticsKind::LayoutQuality], at(1, 5), now).unwrap();
+        refresh.invalidate(at(2, 5));
+        assert!(cancel.is_cancelled());
+        assert!(!refresh.finish(kind, at(2, 5), now));
+        let status = refresh.status(&[]);
+        assert_eq!((status.running, status.cancelled), (None, 1));
+        assert_eq!(status.last_runs.keys().copied().collect::<Vec<_>>(), [AnalyticsKind::PageRank]);
+    }
+
+    #[test]
+    fn test_disabled_refresh_never_starts() {
+        let mut refresh = AnalyticsRefresh::new(AnalyticsRefreshSettings { enabled: false });
+        assert!(refresh.start(&AnalyticsKind::ALL, at(1, 1), Instant::now()).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+
+// A single gaze event can't count for more than this, so a stuck client can't pin a node
+pub const MAX_DWELL_MS: u64 = 10_000;
+// Ceiling on the accumulated score (in seconds of dwell)
+pub const MAX_ATTENTION_SCORE: f32 = 60.0;
+// Anything below this is treated as fully decayed and dropped
+const ATTENTION_EPSILON: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+struct AttentionEntry {
+    score: f32,
+    updated_at: Instant,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttentionScore {
+    pub node_id: u32,
+    pub score: f32,
+}
+
+/// Per-node attention built up from gaze dwell and decaying exponentially with a
+/// configurable half-life. Purely in-memory - it is never persisted.
+#[derive(Debug)]
+pub struct AttentionTracker {
+    entries: HashMap<u32, AttentionEntry>,
+    half_life: Duration,
+    // Last viewpoint reported with a gaze event, in graph space
+    viewpoint: Option<Vec3>,
+}
+
+impl AttentionTracker {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            // A zero half-life would divide by zero when decaying
+            half_life: half_life.max(Duration::from_millis(100)),
+            viewpoint: None,
+        }
+    }
+
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    pub fn set_half_life(&mut self, half_life: Duration, now: Instant) {
+        // Settle existing scores under the old half-life before switching
+        for entry in self.entries.values_mut() {
+            entry.score = decayed(entry.score, now.saturating_duration_since(entry.updated_at), self.half_life);
+            entry.updated_at = now;
+        }
+        self.half_life = half_life.max(Duration::from_millis(100));
+    }
+
+    /// Adds `dwell_ms` of gaze to a node and returns its new score
+    pub fn record(&mut self, node_id: u32, dwell_ms: u64, now: Instant) -> f32 {
+        let half_life = self.half_life;
+        let entry = self.entries.entry(node_id).or_insert(AttentionEntry { score: 0.0, updated_at: now });
+        let current = decayed(entry.score, now.saturating_duration_since(entry.updated_at), half_life);
+        let added = dwell_ms.min(MAX_DWELL_MS) as f32 / 1000.0;
+        entry.score = (current + added).min(MAX_ATTENTION_SCORE);
+        entry.updated_at = now;
+        entry.score
+    }
+
+    pub fn set_viewpoint(&mut self, viewpoint: Vec3) {
+        self.viewpoint = Some(viewpoint);
+    }
+
+    /// Where focused nodes drift towards; the graph origin (the anchor) if no client reported one
+    pub fn viewpoint(&self) -> Vec3 {
+        self.viewpoint.unwrap_or(Vec3::ZERO)
+    }
+
+    pub fn score(&self, node_id: u32, now: Instant) -> f32 {
+        self.entries.get(&node_id).map_or(0.0, |entry| {
+            let score = decayed(entry.score, now.saturating_duration_since(entry.updated_at), self.half_life);
+            if score < ATTENTION_EPSILON { 0.0 } else { score }
+        })
+    }
+
+    /// Current non-zero scores, highest first
+    pub fn scores(&self, now: Instant) -> Vec<AttentionScore> {
+        let mut scores: Vec<AttentionScore> = self.entries.keys()
+            .map(|&node_id| AttentionScore { node_id, score: self.score(node_id, now) })
+            .filter(|s| s.score > 0.0)
+            .collect();
+        scores.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.node_id.cmp(&b.node_id)));
+        scores
+    }
+
+    /// Drops fully decayed entries and nodes that no longer exist
+    pub fn prune<F>(&mut self, now: Instant, exists: F)
+    where
+        F: Fn(u32) -> bool,
+    {
+        let half_life = self.half_life;
+        self.entries.retain(|&node_id, entry| {
+            exists(node_id)
+                && decayed(entry.score, now.saturating_duration_since(entry.updated_at), half_life) >= ATTENTION_EPSILON
+        });
+        if self.entries.is_empty() {
+            self.viewpoint = None;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Per-node displacement that pulls attended nodes (and, more weakly, their
+    /// neighbours) a small step toward the viewpoint. `strength` is the fraction of the
+    /// distance covered per step at maximum attention.
+    pub fn attraction_offsets(&self, nodes: &[Node], edges: &[Edge], strength: f32, now: Instant) -> HashMap<u32, Vec3> {
+        let mut pull: HashMap<u32, f32> = HashMap::new();
+        if strength <= 0.0 || self.entries.is_empty() {
+            return HashMap::new();
+        }
+
+        for node_id in self.entries.keys() {
+            let weight = self.score(*node_id, now) / MAX_ATTENTION_SCORE;
+            if weight > 0.0 {
+                let slot = pull.entry(*node_id).or_insert(0.0);
+                *slot = slot.max(weight);
+            }
+        }
+        // Neighbours come along at half strength so the whole neighbourhood drifts
+        for edge in edges {
+            for (from, to) in [(edge.source, edge.target), (edge.target, edge.source)] {
+                let weight = 0.5 * self.score(from, now) / MAX_ATTENTION_SCORE;
+                if weight > 0.0 {
+                    let slot = pull.entry(to).or_insert(0.0);
+                    *slot = slot.max(weight);
+                }
+            }
+        }
+
+        let target = self.viewpoint();
+        nodes.iter()
+            .filter_map(|node| {
+                let weight = *pull.get(&node.id)?;
+                let position = Vec3::new(node.data.position.x, node.data.position.y, node.data.position.z);
+                Some((node.id, (target - position) * strength * weight))
+            })
+            .collect()
+    }
+}
+
+fn decayed(score: f32, elapsed: Duration, half_life: Duration) -> f32 {
+    score * 0.5f32.powf(elapsed.as_secs_f32() / half_life.as_secs_f32())
+}
+
+/// Orders node ids by level-of-detail importance, most important first.
+/// Base importance is degree plus node weight; attention adds `attention_weight`
+/// per second of (decayed) dwell when provided.
+pub fn rank_lod_importance(
+    nodes: &[Node],
+    edges: &[Edge],
+    attention: Option<(&AttentionTracker, f32)>,
+    now: Instant,
+) -> Vec<u32> {
+    let mut degree: HashMap<u32, u32> = HashMap::new();
+    for edge in edges {
+        *degree.entry(edge.source).or_insert(0) += 1;
+        *degree.entry(edge.target).or_insert(0) += 1;
+    }
+
+    let mut ranked: Vec<(u32, f32)> = nodes.iter()
+        .map(|node| {
+            let mut importance = *degree.get(&node.id).unwrap_or(&0) as f32 + node.weight.unwrap_or(0.0);
+            if let Some((tracker, weight)) = attention {
+                importance += tracker.score(node.id, now) * weight;
+            }
+            (node.id, importance)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u32) -> Node {
+        Node::new_with_id(format!("{}.md", id), Some(id))
+    }
+
+    #[test]
+    fn test_scores_accumulate() {
+        let now = Instant::now();
+        let mut tracker = AttentionTracker::new(Duration::from_secs(30));
+        tracker.record(1, 500, now);
+        tracker.record(1, 1500, now);
+        assert!((tracker.score(1, now) - 2.0).abs() < 1e-4);
+        assert_eq!(tracker.score(2, now), 0.0);
+
+        // Single events are clamped and the total is capped
+        tracker.record(3, 1_000_000, now);
+        assert!((tracker.score(3, now) - MAX_DWELL_MS as f32 / 1000.0).abs() < 1e-4);
+        for _ in 0..20 {
+            tracker.record(3, MAX_DWELL_MS, now);
+        }
+        assert_eq!(tracker.score(3, now), MAX_ATTENTION_SCORE);
+    }
+
+    #[test]
+    fn test_scores_decay_to_zero() {
+        let now = Instant::now();
+        let mut tracker = AttentionTracker::new(Duration::from_secs(10));
+        tracker.record(1, 4000, now);
+
+        let half = tracker.score(1, now + Duration::from_secs(10));
+        assert!((half - 2.0).abs() < 1e-3);
+        let quarter = tracker.score(1, now + Duration::from_secs(20));
+        assert!((quarter - 1.0).abs() < 1e-3);
+
+        let later = now + Duration::from_secs(200);
+        assert_eq!(tracker.score(1, later), 0.0);
+        tracker.prune(later, |_| true);
+        assert!(tracker.is_empty());
+        assert!(tracker.scores(later).is_empty());
+    }
+
+    #[test]
+    fn test_record_after_decay_adds_to_decayed_score() {
+        let now = Instant::now();
+        let mut tracker = AttentionTracker::new(Duration::from_secs(10));
+        tracker.record(1, 2000, now);
+        let score = tracker.record(1, 1000, now + Duration::from_secs(10));
+        assert!((score - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_lod_ranking_uses_attention() {
+        let now = Instant::now();
+        let nodes = vec![node(1), node(2), node(3)];
+        // Node 1 is the hub
+        let edges = vec![Edge::new(1, 2, 1.0), Edge::new(1, 3, 1.0)];
+
+        let base = rank_lod_importance(&nodes, &edges, None, now);
+        assert_eq!(base, vec![1, 2, 3]);
+
+        let mut tracker = AttentionTracker::new(Duration::from_secs(30));
+        tracker.record(3, 5000, now);
+        let ranked = rank_lod_importance(&nodes, &edges, Some((&tracker, 1.0)), now);
+        assert_eq!(ranked, vec![3, 1, 2]);
+
+        // Once attention has decayed the ranking falls back to structure
+        let later = now + Duration::from_secs(3600);
+        let ranked = rank_lod_importance(&nodes, &edges, Some((&tracker, 1.0)), later);
+        assert_eq!(ranked, base);
+    }
+
+    #[test]
+    fn test_attraction_pulls_neighbourhood_toward_viewpoint() {
+        let now = Instant::now();
+        let mut nodes = vec![node(1), node(2), node(3)];
+        for n in nodes.iter_mut() {
+            n.data.position.x = 10.0;
+        }
+        let edges = vec![Edge::new(1, 2, 1.0)];
+        let mut tracker = AttentionTracker::new(Duration::from_secs(30));
+        tracker.record(1, MAX_DWELL_MS, now);
+
+        let offsets = tracker.attraction_offsets(&nodes, &edges, 0.1, now);
+        assert!(offsets[&1].x < 0.0);
+        assert!(offsets[&2].x < 0.0 && offsets[&2].x > offsets[&1].x);
+        assert!(!offsets.contains_key(&3));
+        assert!(tracker.attraction_offsets(&nodes, &edges, 0.0, now).is_empty());
+    }
+}
@@ -125,12 +125,16 @@ mod tests {
 
     fn create_test_settings() -> Arc<RwLock<Settings>> {
         let settings = Settings {
-            debug_mode: false,
-            debug: crate::config::DebugSettings {
-                enable_websocket_debug: false,
-                enable_data_debug: false,
-                log_binary_headers: false,
-                log_full_json: false,
+            system: crate::config::SystemSettings {
+                debug: crate::config::DebugSettings {
+                    enabled: false,
+                    enable_websocket_debug: false,
+                    enable_data_debug: false,
+                    log_binary_headers: false,
+                    log_full_json: false,
+                    ..Default::default()
+                },
+                ..Default::default()
             },
             // Add other required fields with default values
             ..Default::default()
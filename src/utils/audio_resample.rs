@@ -0,0 +1,397 @@
+//! Audio preprocessing for STT. Headsets send whatever their hardware captures, often
+//! 48kHz stereo, while providers want something like 16kHz mono; sent as is, the result
+//! is silence or garbage. Each stream gets an `AudioPreprocessor` that downmixes,
+//! resamples (linear, behind a one-pole low-pass when downsampling) and optionally
+//! normalizes gain. All of its state carries from one chunk to the next, so chunk
+//! boundaries leave no clicks and a stream converted in pieces matches one converted
+//! whole. Samples are 16-bit little-endian PCM throughout.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_SAMPLE: usize = 2;
+const WAV_HEADER_SIZE: usize = 44;
+const MIN_SAMPLE_RATE: u32 = 8_000;
+const MAX_SAMPLE_RATE: u32 = 192_000;
+const MAX_CHANNELS: u16 = 8;
+// Low-pass cutoff as a fraction of the output rate when downsampling (Nyquist is 0.5)
+const LOW_PASS_FRACTION: f32 = 0.45;
+// Gain normalization aims the running peak here, as a fraction of full scale
+const TARGET_PEAK: f32 = 0.7;
+const MAX_GAIN: f32 = 8.0;
+const MIN_GAIN: f32 = 0.25;
+// Quieter than this is treated as silence, which shouldn't be amplified
+const NOISE_FLOOR: f32 = 0.01;
+// How much of the running peak survives each chunk, so the gain follows level changes slowly
+const PEAK_DECAY: f32 = 0.95;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl PcmFormat {
+    pub const fn mono(sample_rate: u32) -> Self {
+        Self { sample_rate, channels: 1 }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&self.sample_rate) {
+            return Err(format!("sample rate {} is outside {}-{} Hz", self.sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE));
+        }
+        if self.channels == 0 || self.channels > MAX_CHANNELS {
+            return Err(format!("{} channels is outside 1-{}", self.channels, MAX_CHANNELS));
+        }
+        Ok(())
+    }
+
+    fn frame_bytes(&self) -> usize {
+        self.channels as usize * BYTES_PER_SAMPLE
+    }
+}
+
+/// The format and sample data of a WAV chunk, or None if `data` isn't a WAV file at all
+pub fn parse_wav(data: &[u8]) -> Option<Result<(PcmFormat, &[u8]), String>> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut format = None;
+    let mut at = 12;
+    while at + 8 <= data.len() {
+        let id = &data[at..at + 4];
+        let size = u32::from_le_bytes(data[at + 4..at + 8].try_into().unwrap()) as usize;
+        let body = &data[at + 8..data.len().min(at + 8 + size)];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let audio_format = u16::from_le_bytes([body[0], body[1]]);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if audio_format != 1 || bits != BITS_PER_SAMPLE {
+                    return Some(Err(format!("only 16-bit PCM WAV is supported (format {}, {} bits)", audio_format, bits)));
+                }
+                format = Some(PcmFormat {
+                    channels: u16::from_le_bytes([body[2], body[3]]),
+                    sample_rate: u32::from_le_bytes(body[4..8].try_into().unwrap()),
+                });
+            }
+            // Streaming encoders often write a placeholder size; the rest of the chunk is the data
+            b"data" => {
+                return Some(match format {
+                    Some(format) => Ok((format, &data[at + 8..])),
+                    None => Err("WAV data chunk comes before its fmt chunk".to_string()),
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at += 8 + size + (size & 1);
+    }
+    Some(Err("WAV chunk has no data".to_string()))
+}
+
+/// A complete WAV file holding `samples`
+pub fn wav_bytes(format: PcmFormat, samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * BYTES_PER_SAMPLE) as u32;
+    let block_align = format.channels * BITS_PER_SAMPLE / 8;
+    let mut wav = Vec::with_capacity(WAV_HEADER_SIZE + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&format.channels.to_le_bytes());
+    wav.extend_from_slice(&format.sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// Converts one stream from `input` to `output`, a chunk at a time
+pub struct AudioPreprocessor {
+    input: PcmFormat,
+    output: PcmFormat,
+    normalize: bool,
+    // Bytes of a frame that was split across chunks
+    partial: Vec<u8>,
+    // Last mono sample of the previous chunk, which the next chunk interpolates from
+    previous: Option<f32>,
+    // Where the next output sample falls, in input samples after `previous`
+    phase: f64,
+    low_pass: Option<f32>,
+    low_pass_state: f32,
+    gain: f32,
+    peak: f32,
+}
+
+impl AudioPreprocessor {
+    pub fn new(input: PcmFormat, output: PcmFormat, normalize: bool) -> Self {
+        let low_pass = (output.sample_rate < input.sample_rate).then(|| {
+            let cutoff = LOW_PASS_FRACTION * output.sample_rate as f32;
+            1.0 - (-2.0 * std::f32::consts::PI * cutoff / input.sample_rate as f32).exp()
+        });
+        Self {
+            input,
+            output,
+            normalize,
+            partial: Vec::new(),
+            previous: None,
+            phase: 0.0,
+            low_pass,
+            low_pass_state: 0.0,
+            gain: 1.0,
+            peak: 0.0,
+        }
+    }
+
+    pub fn converts(&self, input: PcmFormat, output: PcmFormat, normalize: bool) -> bool {
+        self.input == input && self.output == output && self.normalize == normalize
+    }
+
+    /// Converts the next chunk of interleaved input PCM. Returns interleaved output samples;
+    /// a chunk too short to produce any returns none and is carried into the next.
+    pub fn process(&mut self, pcm: &[u8]) -> Vec<i16> {
+        let mut bytes = std::mem::take(&mut self.partial);
+        bytes.extend_from_slice(pcm);
+        let frame_bytes = self.input.frame_bytes();
+        let whole = bytes.len() - bytes.len() % frame_bytes;
+        self.partial = bytes[whole..].to_vec();
+
+        // Downmix to mono, filtering as we go
+        let channels = self.input.channels as usize;
+        let mut mono: Vec<f32> = Vec::with_capacity(whole / frame_bytes + 1);
+        mono.extend(self.previous);
+        for frame in bytes[..whole].chunks_exact(frame_bytes) {
+            let sum: f32 = frame.chunks_exact(BYTES_PER_SAMPLE)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .sum();
+            let mut sample = sum / channels as f32;
+            if let Some(alpha) = self.low_pass {
+                self.low_pass_state += alpha * (sample - self.low_pass_state);
+                sample = self.low_pass_state;
+            }
+            mono.push(sample);
+        }
+        if mono.is_empty() {
+            return Vec::new();
+        }
+
+        // Resample by linear interpolation between neighbouring input samples
+        let step = self.input.sample_rate as f64 / self.output.sample_rate as f64;
+        let mut resampled = Vec::with_capacity((mono.len() as f64 / step) as usize + 1);
+        let mut t = self.phase;
+        while (t.floor() as usize) + 1 < mono.len() || (t.fract() == 0.0 && (t as usize) < mono.len()) {
+            let i = t.floor() as usize;
+            let frac = (t - i as f64) as f32;
+            let next = mono.get(i + 1).copied().unwrap_or(mono[i]);
+            resampled.push(mono[i] + (next - mono[i]) * frac);
+            t += step;
+        }
+        let last = mono.len() - 1;
+        self.phase = t - last as f64;
+        self.previous = Some(mono[last]);
+
+        if self.normalize {
+            self.normalize_gain(&mut resampled);
+        }
+        let out_channels = self.output.channels as usize;
+        resampled.iter()
+            .flat_map(|s| std::iter::repeat_n((s * 32768.0).round().clamp(-32768.0, 32767.0) as i16, out_channels))
+            .collect()
+    }
+
+    // Follows the running peak and ramps the gain across the chunk, so it never jumps
+    fn normalize_gain(&mut self, samples: &mut [f32]) {
+        if samples.is_empty() {
+            return;
+        }
+        let chunk_peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        self.peak = chunk_peak.max(self.peak * PEAK_DECAY);
+        let target = if self.peak < NOISE_FLOOR {
+            self.gain.min(1.0)
+        } else {
+            (TARGET_PEAK / self.peak).clamp(MIN_GAIN, MAX_GAIN)
+        };
+        let start = self.gain;
+        let n = samples.len() as f32;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample *= start + (target - start) * (i + 1) as f32 / n;
+        }
+        self.gain = target;
+    }
+}
+
+/// What a client said about the audio it streams
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioInput {
+    // One preprocessor per stream, so state never leaks between speakers
+    pub stream_id: String,
+    // Format of raw PCM chunks; WAV chunks carry their own
+    pub format: Option<PcmFormat>,
+    pub normalize_gain: bool,
+    // Session to keep a copy of the preprocessed audio under, while it's being recorded
+    pub record_as: Option<String>,
+}
+
+/// Preprocessors by stream
+#[derive(Default)]
+pub struct AudioStreams {
+    streams: HashMap<String, AudioPreprocessor>,
+}
+
+impl AudioStreams {
+    /// A WAV file in `target` format for the chunk. Raw chunks from a stream that never
+    /// declared a format pass through untouched, as they always have.
+    pub fn prepare(&mut self, input: &AudioInput, chunk: &[u8], target: PcmFormat) -> Result<Vec<u8>, String> {
+        let (format, pcm) = match parse_wav(chunk) {
+            Some(parsed) => parsed?,
+            None => match input.format {
+                Some(format) => (format, chunk),
+                None => return Ok(chunk.to_vec()),
+            },
+        };
+        format.validate()?;
+        let preprocessor = self.streams.entry(input.stream_id.clone())
+            .or_insert_with(|| AudioPreprocessor::new(format, target, input.normalize_gain));
+        // A format change mid-stream starts the conversion over
+        if !preprocessor.converts(format, target, input.normalize_gain) {
+            *preprocessor = AudioPreprocessor::new(format, target, input.normalize_gain);
+        }
+        let samples = preprocessor.process(pcm);
+        Ok(if samples.is_empty() { Vec::new() } else { wav_bytes(target, &samples) })
+    }
+
+    pub fn end(&mut self, stream_id: &str) {
+        self.streams.remove(stream_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Interleaved 16-bit PCM of a sine on every channel, starting at frame `from`
+    fn sine(format: PcmFormat, frequency: f32, amplitude: f32, from: usize, frames: usize) -> Vec<u8> {
+        let mut pcm = Vec::with_capacity(frames * format.frame_bytes());
+        for n in from..from + frames {
+            let t = n as f32 / format.sample_rate as f32;
+            let sample = ((2.0 * std::f32::consts::PI * frequency * t).sin() * amplitude * 32767.0) as i16;
+            for _ in 0..format.channels {
+                pcm.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        pcm
+    }
+
+    // Frequency from rising zero crossings, skipping the low-pass settling at the start
+    fn frequency(samples: &[i16], sample_rate: u32) -> f32 {
+        let crossings: Vec<usize> = samples.windows(2).enumerate()
+            .filter(|(_, w)| w[0] < 0 && w[1] >= 0)
+            .map(|(i, _)| i)
+            .skip(1)
+            .collect();
+        let (first, last) = (crossings[0], *crossings.last().unwrap());
+        (crossings.len() - 1) as f32 * sample_rate as f32 / (last - first) as f32
+    }
+
+    // Largest jump between neighbouring samples, as a fraction of full scale
+    fn max_step(samples: &[i16]) -> f32 {
+        samples.windows(2).map(|w| (w[1] as f32 - w[0] as f32).abs() / 32767.0).fold(0.0, f32::max)
+    }
+
+    fn convert_in_chunks(input: PcmFormat, output: PcmFormat, pcm: &[u8], chunk_bytes: usize) -> Vec<i16> {
+        let mut preprocessor = AudioPreprocessor::new(input, output, false);
+        pcm.chunks(chunk_bytes).flat_map(|chunk| preprocessor.process(chunk)).collect()
+    }
+
+    #[test]
+    fn test_headset_audio_becomes_16k_mono_without_clicks() {
+        let input = PcmFormat { sample_rate: 48_000, channels: 2 };
+        let output = PcmFormat::mono(16_000);
+        let pcm = sine(input, 440.0, 0.5, 0, 48_000);
+
+        // Odd chunk sizes split frames and even samples across chunk boundaries
+        let chunked = convert_in_chunks(input, output, &pcm, 4_001);
+        assert!((chunked.len() as i64 - 16_000).abs() <= 1, "{} samples", chunked.len());
+        assert!((frequency(&chunked, 16_000) - 440.0).abs() < 2.0);
+        // A 440Hz sine at half scale moves at most 2π·440·0.5/16000 ≈ 0.086 per sample
+        assert!(max_step(&chunked) < 0.1, "step {}", max_step(&chunked));
+
+        // Converting in pieces matches converting in one go
+        let whole = convert_in_chunks(input, output, &pcm, pcm.len());
+        assert_eq!(chunked.len(), whole.len());
+        assert!(chunked.iter().zip(&whole).all(|(a, b)| (a - b).abs() <= 1));
+    }
+
+    #[test]
+    fn test_upsampling_and_identity() {
+        let input = PcmFormat::mono(8_000);
+        let output = PcmFormat::mono(16_000);
+        let pcm = sine(input, 300.0, 0.5, 0, 8_000);
+        let converted = convert_in_chunks(input, output, &pcm, 333);
+        assert!((converted.len() as i64 - 16_000).abs() <= 2, "{} samples", converted.len());
+        assert!((frequency(&converted, 16_000) - 300.0).abs() < 2.0);
+        assert!(max_step(&converted) < 0.07);
+
+        // Same format in and out: samples come back unchanged
+        let same = convert_in_chunks(output, output, &sine(output, 300.0, 0.5, 0, 1_600), 101);
+        let original: Vec<i16> = sine(output, 300.0, 0.5, 0, 1_600).chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect();
+        assert_eq!(same, original);
+    }
+
+    #[test]
+    fn test_gain_normalization_ramps_towards_the_target() {
+        let format = PcmFormat::mono(16_000);
+        let mut preprocessor = AudioPreprocessor::new(format, format, true);
+        let mut out = Vec::new();
+        for chunk in 0..20 {
+            out.extend(preprocessor.process(&sine(format, 200.0, 0.05, chunk * 1_600, 1_600)));
+        }
+        let tail_peak = out[out.len() - 1_600..].iter().map(|s| s.unsigned_abs()).max().unwrap() as f32 / 32767.0;
+        assert!(tail_peak > 0.3, "peak {}", tail_peak);
+        // Quiet input raised eightfold moves at most 8·2π·200·0.05/16000 ≈ 0.032 per sample
+        assert!(max_step(&out) < 0.035, "step {}", max_step(&out));
+    }
+
+    #[test]
+    fn test_streams_read_wav_headers_and_pass_undeclared_audio_through() {
+        let input = PcmFormat { sample_rate: 48_000, channels: 2 };
+        let target = PcmFormat::mono(16_000);
+        let samples: Vec<i16> = sine(input, 440.0, 0.5, 0, 4_800).chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect();
+        let wav = wav_bytes(input, &samples);
+
+        let mut streams = AudioStreams::default();
+        let declared = AudioInput { stream_id: "a".into(), format: None, normalize_gain: false, record_as: None };
+        let converted = streams.prepare(&declared, &wav, target).unwrap();
+        let (format, data) = parse_wav(&converted).unwrap().unwrap();
+        assert_eq!(format, target);
+        assert!((data.len() as i64 / 2 - 1_600).abs() <= 1);
+
+        // Raw audio with no declared format is left alone
+        let raw = vec![1u8, 2, 3, 4];
+        let legacy = AudioInput { stream_id: "b".into(), format: None, normalize_gain: false, record_as: None };
+        assert_eq!(streams.prepare(&legacy, &raw, target).unwrap(), raw);
+        assert_eq!(streams.len(), 1);
+        streams.end("a");
+        assert!(streams.is_empty());
+
+        assert!(parse_wav(&raw).is_none());
+        let bad = AudioInput { format: Some(PcmFormat::mono(1_000)), ..legacy };
+        assert!(streams.prepare(&bad, &raw, target).is_err());
+    }
+}
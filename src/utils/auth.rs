@@ -1,5 +1,10 @@
-use actix_web::{HttpRequest, HttpResponse};
-use log::{warn, error};
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::warn;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use crate::config::AccessSettings;
+use crate::config::feature_access::{FeatureAccess, Role};
 use crate::services::nostr_service::NostrService;
 
 pub enum AccessLevel {
@@ -67,4 +72,292 @@ pub async fn verify_authenticated(
     nostr_service: &NostrService,
 ) -> Result<String, HttpResponse> {
     verify_access(req, nostr_service, AccessLevel::Authenticated).await
-}
\ No newline at end of file
+}
+
+/// Who a request or websocket session belongs to and the role it was granted
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Identity {
+    pub pubkey: Option<String>,
+    pub role: Role,
+}
+
+/// Maps pubkeys to roles, applying the anonymous role and read-only mode
+#[derive(Clone)]
+pub struct AccessControl {
+    pub feature_access: web::Data<FeatureAccess>,
+    pub settings: AccessSettings,
+}
+
+impl AccessControl {
+    pub fn new(feature_access: web::Data<FeatureAccess>, settings: AccessSettings) -> Self {
+        Self { feature_access, settings }
+    }
+
+    pub fn role_for(&self, pubkey: Option<&str>) -> Role {
+        self.settings.effective_role(pubkey.map(|p| self.feature_access.role_for(p)))
+    }
+
+    /// Identity for a pubkey the handler has already authenticated
+    pub fn identity(&self, pubkey: &str) -> Identity {
+        Identity { pubkey: Some(pubkey.to_string()), role: self.role_for(Some(pubkey)) }
+    }
+}
+
+/// 403 body for a failed role check
+pub fn forbidden_body(role: Role, required: Role) -> serde_json::Value {
+    json!({
+        "error": "forbidden",
+        "message": format!("This operation requires the {} role", required.as_str()),
+        "role": role,
+        "requiredRole": required,
+    })
+}
+
+/// The same error as a websocket message
+pub fn forbidden_ws_message(role: Role, required: Role) -> String {
+    json!({
+        "type": "error",
+        "code": "forbidden",
+        "message": format!("This operation requires the {} role", required.as_str()),
+        "role": role,
+        "requiredRole": required,
+    }).to_string()
+}
+
+// Session from the usual headers, or `?pubkey=&token=` since browsers can't set
+// headers on a websocket upgrade
+fn session_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    if let Some(pubkey) = header("X-Nostr-Pubkey") {
+        let token = header("X-Nostr-Token").unwrap_or_default();
+        return Some((pubkey, token));
+    }
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok()?;
+    Some((query.get("pubkey")?.clone(), query.get("token").cloned().unwrap_or_default()))
+}
+
+/// Pubkey of a session that checks out, or None for anonymous or invalid credentials
+pub async fn session_pubkey(req: &HttpRequest, nostr_service: Option<&NostrService>) -> Option<String> {
+    let (pubkey, token) = session_credentials(req)?;
+    nostr_service?.validate_session(&pubkey, &token).await.then_some(pubkey)
+}
+
+/// Identity of the caller. Requests without credentials get the anonymous role;
+/// credentials that don't match a live session are rejected rather than downgraded.
+pub async fn resolve_identity(
+    req: &HttpRequest,
+    nostr_service: Option<&NostrService>,
+    access: &AccessControl,
+) -> Result<Identity, HttpResponse> {
+    let (pubkey, token) = match session_credentials(req) {
+        Some(credentials) => credentials,
+        None => return Ok(Identity { pubkey: None, role: access.role_for(None) }),
+    };
+    let valid = match nostr_service {
+        Some(nostr_service) => nostr_service.validate_session(&pubkey, &token).await,
+        None => false,
+    };
+    if !valid {
+        warn!("Invalid or expired session for user {}", pubkey);
+        return Err(HttpResponse::Unauthorized().json(json!({ "error": "Invalid or expired session" })));
+    }
+    Ok(access.identity(&pubkey))
+}
+
+/// Resolves the caller and rejects them with a structured `forbidden` error unless
+/// their role is at least `required`
+pub async fn require_role(
+    req: &HttpRequest,
+    nostr_service: Option<&NostrService>,
+    access: &AccessControl,
+    required: Role,
+) -> Result<Identity, HttpResponse> {
+    let identity = resolve_identity(req, nostr_service, access).await?;
+    check_role(&identity, required)?;
+    Ok(identity)
+}
+
+pub fn check_role(identity: &Identity, required: Role) -> Result<(), HttpResponse> {
+    if identity.role >= required {
+        return Ok(());
+    }
+    warn!("{} ({}) attempted an operation requiring {}",
+        identity.pubkey.as_deref().unwrap_or("anonymous"), identity.role.as_str(), required.as_str());
+    Err(HttpResponse::Forbidden().json(forbidden_body(identity.role, required)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    const VIEWER: &str = "viewer-pubkey";
+    const EDITOR: &str = "editor-pubkey";
+    const ADMIN: &str = "admin-pubkey";
+
+    fn access(read_only_mode: bool) -> AccessControl {
+        let feature_access = FeatureAccess {
+            approved_pubkeys: vec![VIEWER.into(), EDITOR.into(), ADMIN.into()],
+            perplexity_enabled: Vec::new(),
+            openai_enabled: Vec::new(),
+            ragflow_enabled: Vec::new(),
+            power_users: vec![ADMIN.into()],
+            settings_sync_enabled: Vec::new(),
+            viewers: vec![VIEWER.into()],
+        };
+        AccessControl::new(web::Data::new(feature_access), AccessSettings {
+            read_only_mode,
+            anonymous_role: Role::Viewer,
+        })
+    }
+
+    async fn nostr() -> NostrService {
+        let nostr = NostrService::default();
+        for pubkey in [VIEWER, EDITOR, ADMIN] {
+            nostr.insert_test_session(pubkey, &format!("{}-token", pubkey)).await;
+        }
+        nostr
+    }
+
+    // Mutation categories and the role each one requires in the real handlers
+    const ENDPOINTS: &[(&str, Role)] = &[
+        ("/positions", Role::Editor),
+        ("/nodes", Role::Editor),
+        ("/annotations", Role::Editor),
+        ("/settings", Role::Admin),
+        ("/admin", Role::Admin),
+    ];
+
+    async fn status(read_only_mode: bool, path: &str, pubkey: Option<&str>) -> StatusCode {
+        let nostr = web::Data::new(nostr().await);
+        let access = web::Data::new(access(read_only_mode));
+        let mut app = App::new().app_data(nostr).app_data(access);
+        for (endpoint, required) in ENDPOINTS {
+            let required = *required;
+            app = app.route(endpoint, web::post().to(move |req: HttpRequest, nostr: web::Data<NostrService>, access: web::Data<AccessControl>| async move {
+                match require_role(&req, Some(nostr.get_ref()), &access, required).await {
+                    Ok(_) => HttpResponse::Ok().finish(),
+                    Err(response) => response,
+                }
+            }));
+        }
+        app = app.route("/read", web::get().to(|| async { HttpResponse::Ok().finish() }));
+        let app = test::init_service(app).await;
+
+        let mut request = test::TestRequest::post().uri(path);
+        if path == "/read" {
+            request = test::TestRequest::get().uri(path);
+        }
+        if let Some(pubkey) = pubkey {
+            request = request
+                .insert_header(("X-Nostr-Pubkey", pubkey))
+                .insert_header(("X-Nostr-Token", format!("{}-token", pubkey)));
+        }
+        test::call_service(&app, request.to_request()).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_real_endpoints_per_role() {
+        use crate::app_state::AppState;
+        use crate::services::room_access::{RoomAccessService, RoomRole};
+        use std::sync::Arc;
+
+        let mut state = AppState::for_tests().await;
+        let nostr = web::Data::new(nostr().await);
+        state.nostr_service = Some(nostr.clone());
+        state.access_control = access(false);
+        // A room only the viewer belongs to
+        let rooms = std::env::temp_dir().join(format!("room-access-{}.json", uuid::Uuid::new_v4()));
+        state.room_access = Arc::new(RoomAccessService::with_path(rooms.clone()));
+        let owner = Identity { pubkey: Some(VIEWER.to_string()), role: Role::Viewer };
+        state.room_access.set_member("private", &owner, VIEWER, Some(RoomRole::Editor), false).unwrap();
+
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr)
+            .configure(crate::handlers::api_handler::graph::config)
+            .configure(crate::handlers::metadata_handler::config)).await;
+        let call = |uri: &str, body: serde_json::Value, pubkey: Option<&str>| {
+            let mut request = test::TestRequest::post().uri(uri).set_json(body);
+            if let Some(pubkey) = pubkey {
+                request = request
+                    .insert_header(("X-Nostr-Pubkey", pubkey))
+                    .insert_header(("X-Nostr-Token", format!("{}-token", pubkey)));
+            }
+            test::call_service(&app, request.to_request())
+        };
+
+        let endpoints = [
+            ("/graph/verify", serde_json::json!({}), Role::Admin),
+            ("/metadata/import?dry_run=true", serde_json::json!({}), Role::Editor),
+            ("/graph/nodes/1/annotations", serde_json::json!({ "text": "note" }), Role::Editor),
+            ("/graph/undo", serde_json::json!({}), Role::Editor),
+        ];
+        for (uri, body, required) in &endpoints {
+            for (pubkey, role) in [(None, Role::Viewer), (Some(VIEWER), Role::Viewer), (Some(EDITOR), Role::Editor), (Some(ADMIN), Role::Admin)] {
+                let status = call(uri, body.clone(), pubkey).await.status();
+                // Annotations need a signed-in author, so anonymous callers get 401 there
+                let refused = status == StatusCode::FORBIDDEN || (pubkey.is_none() && status == StatusCode::UNAUTHORIZED);
+                assert_eq!(refused, role < *required, "{} as {:?}: {}", uri, pubkey, status);
+            }
+        }
+
+        // Undo in a room also takes being let into it; admins always are
+        let private = serde_json::json!({ "room": "private" });
+        assert_eq!(call("/graph/undo", private.clone(), Some(EDITOR)).await.status(), StatusCode::FORBIDDEN);
+        assert_ne!(call("/graph/undo", private, Some(ADMIN)).await.status(), StatusCode::FORBIDDEN);
+        let _ = std::fs::remove_file(rooms);
+    }
+
+    #[actix_web::test]
+    async fn test_each_endpoint_per_role() {
+        for (endpoint, required) in ENDPOINTS {
+            for (pubkey, role) in [(None, Role::Viewer), (Some(VIEWER), Role::Viewer), (Some(EDITOR), Role::Editor), (Some(ADMIN), Role::Admin)] {
+                let expected = if role >= *required { StatusCode::OK } else { StatusCode::FORBIDDEN };
+                assert_eq!(status(false, endpoint, pubkey).await, expected, "{} as {:?}", endpoint, pubkey);
+            }
+        }
+        // Everyone can read
+        assert_eq!(status(false, "/read", None).await, StatusCode::OK);
+        assert_eq!(status(false, "/read", Some(VIEWER)).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_read_only_mode_demotes_non_admins() {
+        assert_eq!(status(true, "/positions", Some(EDITOR)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(true, "/annotations", Some(EDITOR)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(true, "/positions", Some(ADMIN)).await, StatusCode::OK);
+        assert_eq!(status(true, "/read", Some(EDITOR)).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_forbidden_is_structured_and_bad_tokens_rejected() {
+        let nostr = nostr().await;
+        let access = access(false);
+        let req = test::TestRequest::post()
+            .uri("/annotations")
+            .insert_header(("X-Nostr-Pubkey", VIEWER))
+            .insert_header(("X-Nostr-Token", "viewer-pubkey-token"))
+            .to_http_request();
+        let response = require_role(&req, Some(&nostr), &access, Role::Editor).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "forbidden");
+        assert_eq!(body["role"], "viewer");
+        assert_eq!(body["requiredRole"], "editor");
+
+        // A wrong token is an auth failure, not a silent downgrade to anonymous
+        let req = test::TestRequest::get()
+            .uri("/wss?pubkey=editor-pubkey&token=stolen")
+            .to_http_request();
+        assert_eq!(resolve_identity(&req, Some(&nostr), &access).await.unwrap_err().status(), StatusCode::UNAUTHORIZED);
+
+        // Websocket upgrades carry the session in the query string
+        let req = test::TestRequest::get()
+            .uri("/wss?pubkey=editor-pubkey&token=editor-pubkey-token")
+            .to_http_request();
+        let identity = resolve_identity(&req, Some(&nostr), &access).await.unwrap();
+        assert_eq!(identity, Identity { pubkey: Some(EDITOR.to_string()), role: Role::Editor });
+    }
+}
@@ -1,4 +1,6 @@
 use crate::utils::socket_flow_messages::BinaryNodeData;
+use crate::utils::projection::Projection;
+use crate::utils::time_sync::FrameTiming;
 use crate::types::vec3::Vec3Data;
 use bytemuck::{Pod, Zeroable};
 use log::{trace, debug};
@@ -132,6 +134,114 @@ pub fn decode_node_data(data: &[u8]) -> Result<Vec<(u32, BinaryNodeData)>, Strin
     Ok(updates)
 }
 
+// Optional frame header, only for clients that enabled interpolation hints in their
+// capabilities handshake; everyone else gets the bare node list:
+// - Server monotonic time: 8 bytes (u64, microseconds)
+// - Time since the previous physics step: 4 bytes (u32, microseconds)
+pub const FRAME_HEADER_SIZE: usize = 12;
+
+pub fn with_frame_header(timing: &FrameTiming, nodes: &[u8]) -> Vec<u8> {
+    with_frame_headers(Some(timing), None, nodes)
+}
+
+/// Splits a frame sent with a header into its timing and node data
+pub fn split_frame_header(data: &[u8]) -> Result<(FrameTiming, &[u8]), String> {
+    if data.len() < FRAME_HEADER_SIZE {
+        return Err(format!("Frame of {} bytes is shorter than the {} byte header", data.len(), FRAME_HEADER_SIZE));
+    }
+    let timing = FrameTiming {
+        server_time_us: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+        tick_delta_us: u32::from_le_bytes(data[8..12].try_into().unwrap()),
+    };
+    Ok((timing, &data[FRAME_HEADER_SIZE..]))
+}
+
+// Optional payload hash, only for clients that asked for frame hashes in the same
+// handshake. It follows the timing header when both are on:
+// - CRC-32 of the node data after it, projected or not: 4 bytes (u32)
+pub const FRAME_HASH_SIZE: usize = 4;
+
+/// A frame with whichever of the timing header and payload hash the client asked for
+pub fn with_frame_headers(timing: Option<&FrameTiming>, hash: Option<u32>, nodes: &[u8]) -> Vec<u8> {
+    let headers = timing.map_or(0, |_| FRAME_HEADER_SIZE) + hash.map_or(0, |_| FRAME_HASH_SIZE);
+    let mut buffer = Vec::with_capacity(headers + nodes.len());
+    if let Some(timing) = timing {
+        buffer.extend_from_slice(&timing.server_time_us.to_le_bytes());
+        buffer.extend_from_slice(&timing.tick_delta_us.to_le_bytes());
+    }
+    if let Some(hash) = hash {
+        buffer.extend_from_slice(&hash.to_le_bytes());
+    }
+    buffer.extend_from_slice(nodes);
+    buffer
+}
+
+/// Splits the payload hash off a frame whose timing header, if any, is already removed
+pub fn split_frame_hash(data: &[u8]) -> Result<(u32, &[u8]), String> {
+    if data.len() < FRAME_HASH_SIZE {
+        return Err(format!("Frame of {} bytes is shorter than the {} byte hash", data.len(), FRAME_HASH_SIZE));
+    }
+    Ok((u32::from_le_bytes(data[0..4].try_into().unwrap()), &data[FRAME_HASH_SIZE..]))
+}
+
+// Projected frames, only for clients that asked for a 2-D projection. A 4 byte layout
+// header comes first so a projected frame can never be read as the 28 byte 3-D format:
+// - Layout: 1 byte (FRAME_LAYOUT_PROJECTED)
+// - Components per vector: 1 byte (2)
+// - Reserved: 2 bytes
+// Then for each node (20 bytes): index u32, position 2 × f32, velocity 2 × f32.
+// The timing header, when enabled, still goes in front of the whole thing.
+pub const FRAME_LAYOUT_PROJECTED: u8 = 0x50;
+pub const PROJECTED_HEADER_SIZE: usize = 4;
+pub const PROJECTED_ITEM_SIZE: usize = 20;
+
+pub fn encode_projected_node_data(nodes: &[(u32, BinaryNodeData)], projection: &Projection) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(PROJECTED_HEADER_SIZE + nodes.len() * PROJECTED_ITEM_SIZE);
+    buffer.extend_from_slice(&[FRAME_LAYOUT_PROJECTED, 2, 0, 0]);
+    for (node_id, node) in nodes {
+        buffer.extend_from_slice(&node_id.to_le_bytes());
+        for value in projection.apply(node.position).into_iter().chain(projection.apply(node.velocity)) {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    buffer
+}
+
+/// Node index, 2-D position and 2-D velocity
+pub type ProjectedNode = (u32, [f32; 2], [f32; 2]);
+
+pub fn decode_projected_node_data(data: &[u8]) -> Result<Vec<ProjectedNode>, String> {
+    if data.len() < PROJECTED_HEADER_SIZE || data[0] != FRAME_LAYOUT_PROJECTED || data[1] != 2 {
+        return Err("Not a projected frame".to_string());
+    }
+    let body = &data[PROJECTED_HEADER_SIZE..];
+    if !body.len().is_multiple_of(PROJECTED_ITEM_SIZE) {
+        return Err(format!(
+            "Data size {} is not a multiple of projected item size {}",
+            body.len(),
+            PROJECTED_ITEM_SIZE
+        ));
+    }
+    let float = |chunk: &[u8], at: usize| f32::from_le_bytes(chunk[at..at + 4].try_into().unwrap());
+    Ok(body.chunks_exact(PROJECTED_ITEM_SIZE)
+        .map(|chunk| {
+            let id = u32::from_le_bytes(chunk[0..4].try_into().unwrap());
+            (id, [float(chunk, 4), float(chunk, 8)], [float(chunk, 12), float(chunk, 16)])
+        })
+        .collect())
+}
+
+/// Re-encodes a 3-D frame for a client with a projection. Whatever was chosen for the
+/// 3-D frame, keyframe or thinned delta, is exactly what the projected frame carries.
+pub fn project_frame(data: &[u8], projection: &Projection) -> Result<Vec<u8>, String> {
+    Ok(encode_projected_node_data(&decode_node_data(data)?, projection))
+}
+
+/// Nodes in an encoded frame, without decoding it
+pub fn node_count(data: &[u8]) -> usize {
+    data.len() / std::mem::size_of::<WireNodeDataItem>()
+}
+
 pub fn calculate_message_size(updates: &[(u32, BinaryNodeData)]) -> usize {
     // Each update uses WireNodeDataItem size
     updates.len() * std::mem::size_of::<WireNodeDataItem>()
@@ -200,6 +310,91 @@ mod tests {
         assert_eq!(result.unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_frame_header_contents() {
+        let nodes = vec![(7u32, BinaryNodeData {
+            position: Vec3Data::new(1.0, 2.0, 3.0),
+            velocity: Vec3Data::zero(),
+            mass: 100,
+            flags: 1,
+            padding: [0, 0],
+        })];
+        let body = encode_node_data(&nodes);
+        let timing = FrameTiming { server_time_us: 0x0102_0304_0506_0708, tick_delta_us: 17_250 };
+        let frame = with_frame_header(&timing, &body);
+
+        assert_eq!(frame.len(), FRAME_HEADER_SIZE + 28);
+        assert_eq!(&frame[0..8], &[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(&frame[8..12], &17_250u32.to_le_bytes());
+        // The node list after the header is exactly the legacy frame
+        assert_eq!(&frame[FRAME_HEADER_SIZE..], body.as_slice());
+
+        let (decoded, rest) = split_frame_header(&frame).unwrap();
+        assert_eq!(decoded, timing);
+        assert_eq!(decode_node_data(rest).unwrap()[0].0, 7);
+        assert!(split_frame_header(&frame[..8]).is_err());
+
+        // A payload hash goes between the timing header and the nodes
+        let frame = with_frame_headers(Some(&timing), Some(0xDEAD_BEEF), &body);
+        let (_, rest) = split_frame_header(&frame).unwrap();
+        let (hash, rest) = split_frame_hash(rest).unwrap();
+        assert_eq!((hash, rest), (0xDEAD_BEEF, body.as_slice()));
+    }
+
+    #[test]
+    fn test_projected_frames_round_trip() {
+        let node = |x: f32, y: f32, z: f32| BinaryNodeData {
+            position: Vec3Data::new(x, y, z),
+            velocity: Vec3Data::new(0.5, -1.0, 2.0),
+            mass: 100,
+            flags: 1,
+            padding: [0, 0],
+        };
+        let nodes = vec![(3u32, node(1.0, 2.0, 3.0)), (9u32, node(-4.0, 5.0, 6.0))];
+
+        let frame = encode_projected_node_data(&nodes, &Projection::XZ);
+        assert_eq!(frame.len(), PROJECTED_HEADER_SIZE + 2 * PROJECTED_ITEM_SIZE);
+        assert_eq!(frame[0], FRAME_LAYOUT_PROJECTED);
+        // The header keeps projected frames from ever being a valid 3-D frame
+        assert!(decode_node_data(&frame).is_err());
+        assert_eq!(decode_projected_node_data(&frame).unwrap(), vec![
+            (3, [1.0, 3.0], [0.5, 2.0]),
+            (9, [-4.0, 6.0], [0.5, 2.0]),
+        ]);
+        assert!(decode_projected_node_data(&encode_node_data(&nodes)).is_err());
+        assert!(decode_projected_node_data(&frame[..frame.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_projection_composes_with_thinned_deltas_and_timing() {
+        let nodes: Vec<(u32, BinaryNodeData)> = (0..6u32)
+            .map(|id| (id, BinaryNodeData {
+                position: Vec3Data::new(id as f32, 10.0, -(id as f32)),
+                velocity: Vec3Data::zero(),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }))
+            .collect();
+        // A throttled delta: node 5 is priority, the rest in two buckets
+        let mut scheduler = crate::utils::update_priority::FrameScheduler::new();
+        let delta = scheduler.select(decode_node_data(&encode_node_data(&nodes)).unwrap(), |id| id == 5, 2);
+        let delta_3d = encode_node_data(&delta);
+
+        let projected = project_frame(&delta_3d, &Projection::XY).unwrap();
+        let timing = FrameTiming { server_time_us: 42, tick_delta_us: 16_000 };
+        let frame = with_frame_header(&timing, &projected);
+        let (decoded_timing, body) = split_frame_header(&frame).unwrap();
+        assert_eq!(decoded_timing, timing);
+        let decoded = decode_projected_node_data(body).unwrap();
+        let ids: Vec<u32> = decoded.iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(ids, delta.iter().map(|(id, _)| *id).collect::<Vec<_>>());
+        assert!(ids.contains(&5) && ids.len() < nodes.len());
+        assert_eq!(decoded[1].1, [2.0, 10.0]);
+        // Positions alone take two floats instead of three
+        assert!(projected.len() < delta_3d.len());
+    }
+
     #[test]
     fn test_message_size_calculation() {
         let nodes = vec![
@@ -0,0 +1,82 @@
+//! Single `Range: bytes=...` requests against a body held in memory. Anything we don't
+//! understand, including multiple ranges, is answered with the whole body, which the
+//! spec allows.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: usize,
+    // Inclusive, as in the header
+    pub end: usize,
+}
+
+impl ByteRange {
+    pub fn content_range(&self, total: usize) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeNotSatisfiable;
+
+/// `Ok(None)` means serve the whole body
+pub fn parse_range(header: Option<&str>, len: usize) -> Result<Option<ByteRange>, RangeNotSatisfiable> {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // bytes=-N is the last N bytes
+        let Ok(suffix) = end.parse::<usize>() else {
+            return Ok(None);
+        };
+        if suffix == 0 || len == 0 {
+            return Err(RangeNotSatisfiable);
+        }
+        return Ok(Some(ByteRange { start: len.saturating_sub(suffix), end: len - 1 }));
+    }
+    let Ok(start) = start.parse::<usize>() else {
+        return Ok(None);
+    };
+    let end = if end.is_empty() {
+        None
+    } else {
+        match end.parse::<usize>() {
+            Ok(end) if end >= start => Some(end),
+            _ => return Ok(None),
+        }
+    };
+    if start >= len {
+        return Err(RangeNotSatisfiable);
+    }
+    Ok(Some(ByteRange { start, end: end.map_or(len - 1, |end| end.min(len - 1)) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let range = |header: &str| parse_range(Some(header), 1000);
+        assert_eq!(range("bytes=0-99"), Ok(Some(ByteRange { start: 0, end: 99 })));
+        assert_eq!(range("bytes=900-"), Ok(Some(ByteRange { start: 900, end: 999 })));
+        assert_eq!(range("bytes=-100"), Ok(Some(ByteRange { start: 900, end: 999 })));
+        // An end past the body is cut to fit
+        assert_eq!(range("bytes=950-5000"), Ok(Some(ByteRange { start: 950, end: 999 })));
+        assert_eq!(range("bytes=1000-"), Err(RangeNotSatisfiable));
+        assert_eq!(range("bytes=-0"), Err(RangeNotSatisfiable));
+        // Ignored, so the whole body goes out
+        assert_eq!(range("bytes=0-10,20-30"), Ok(None));
+        assert_eq!(range("bytes=50-10"), Ok(None));
+        assert_eq!(range("items=0-10"), Ok(None));
+        assert_eq!(parse_range(None, 1000), Ok(None));
+
+        assert_eq!(ByteRange { start: 10, end: 19 }.content_range(1000), "bytes 10-19/1000");
+    }
+}
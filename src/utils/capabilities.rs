@@ -0,0 +1,274 @@
+//! What a client can negotiate in the websocket `capabilities` handshake, in one registry.
+//! The handshake is read against it, and `GET /api/capabilities` publishes it with the
+//! frame encodings, analytics endpoints and compiled-in features, so a client can find out
+//! what this build supports before connecting and the two never disagree.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::utils::binary_protocol::{
+    WireNodeDataItem, FRAME_HASH_SIZE, FRAME_HEADER_SIZE, PROJECTED_HEADER_SIZE, PROJECTED_ITEM_SIZE,
+};
+use crate::utils::projection::Projection;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[PROTOCOL_VERSION];
+pub const PROJECTION_NAMES: &[&str] = &["xz", "xy"];
+pub const AGGREGATION_NAMES: &[&str] = &["none", "community"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum OptionKind {
+    Boolean,
+    // Any one of `values`
+    Integer { values: &'static [u32] },
+    // One of the named projections, or a 3x2 matrix
+    Projection { names: &'static [&'static str] },
+    // Any one of `values`
+    Choice { values: &'static [&'static str] },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HandshakeOption {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub kind: OptionKind,
+    pub required: bool,
+    // What leaving it out means; null for required options
+    pub default: Value,
+    pub description: &'static str,
+}
+
+/// Every option the `capabilities` message takes
+pub fn handshake_options() -> Vec<HandshakeOption> {
+    vec![
+        HandshakeOption {
+            name: "protocolVersion",
+            kind: OptionKind::Integer { values: SUPPORTED_PROTOCOL_VERSIONS },
+            required: false,
+            default: PROTOCOL_VERSION.into(),
+            description: "Websocket protocol version the client speaks",
+        },
+        HandshakeOption {
+            name: "localPhysics",
+            kind: OptionKind::Boolean,
+            required: true,
+            default: Value::Null,
+            description: "Whether the client can lay the graph out itself",
+        },
+        HandshakeOption {
+            name: "interpolationHints",
+            kind: OptionKind::Boolean,
+            required: false,
+            default: false.into(),
+            description: "Put a timing header on every position frame",
+        },
+        HandshakeOption {
+            name: "frameHashes",
+            kind: OptionKind::Boolean,
+            required: false,
+            default: false.into(),
+            description: "Add a CRC-32 of each frame's node data after the timing header",
+        },
+        HandshakeOption {
+            name: "projection",
+            kind: OptionKind::Projection { names: PROJECTION_NAMES },
+            required: false,
+            default: Value::Null,
+            description: "Send projected 2-D frames instead of 3-D ones",
+        },
+        HandshakeOption {
+            name: "aggregation",
+            kind: OptionKind::Choice { values: AGGREGATION_NAMES },
+            required: false,
+            default: "none".into(),
+            description: "Collapse each community into one super-node; expand_community shows its members",
+        },
+    ]
+}
+
+impl HandshakeOption {
+    /// The value the client sent, or the default; the error says what would be accepted
+    pub fn resolve(&self, value: Option<&Value>) -> Result<Value, String> {
+        let value = match value.filter(|v| !v.is_null()) {
+            Some(value) => value,
+            None if self.required => return Err(format!("capabilities needs {}", self.name)),
+            None => return Ok(self.default.clone()),
+        };
+        match self.kind {
+            OptionKind::Boolean => value.as_bool()
+                .map(Value::from)
+                .ok_or_else(|| format!("{} must be true or false, got {}", self.name, value)),
+            OptionKind::Integer { values } => match value.as_u64().and_then(|v| u32::try_from(v).ok()) {
+                Some(v) if values.contains(&v) => Ok(v.into()),
+                _ => Err(format!("{} {} isn't supported; supported: {}", self.name, value, join(values))),
+            },
+            OptionKind::Projection { .. } => Projection::parse(value)
+                .map(|_| value.clone())
+                .map_err(|e| format!("{}: {}", self.name, e)),
+            OptionKind::Choice { values } => match value.as_str() {
+                Some(v) if values.contains(&v) => Ok(value.clone()),
+                _ => Err(format!("{} {} isn't supported; supported: {}", self.name, value, join(values))),
+            },
+        }
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(T::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// A client's `capabilities` message, checked against the registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub local_physics: bool,
+    pub interpolation_hints: bool,
+    pub frame_hashes: bool,
+    pub projection: Option<Projection>,
+    // Frames carry community super-nodes instead of every node
+    pub community_aggregation: bool,
+}
+
+impl Handshake {
+    /// Reads the message, rejecting options this build doesn't know as well as values
+    /// outside what the registry lists
+    pub fn parse(msg: &Value) -> Result<Self, String> {
+        let Some(fields) = msg.as_object() else {
+            return Err("capabilities must be an object".to_string());
+        };
+        let options = handshake_options();
+        if let Some(unknown) = fields.keys().find(|key| *key != "type" && !options.iter().any(|o| o.name == *key)) {
+            let names: Vec<&str> = options.iter().map(|o| o.name).collect();
+            return Err(format!("unknown capabilities option '{}'; supported: {}", unknown, names.join(", ")));
+        }
+        let resolved = options.iter()
+            .map(|option| Ok((option.name, option.resolve(fields.get(option.name))?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
+        Ok(Self {
+            protocol_version: resolved["protocolVersion"].as_u64().unwrap_or_default() as u32,
+            local_physics: resolved["localPhysics"].as_bool().unwrap_or_default(),
+            interpolation_hints: resolved["interpolationHints"].as_bool().unwrap_or_default(),
+            frame_hashes: resolved["frameHashes"].as_bool().unwrap_or_default(),
+            projection: Some(&resolved["projection"]).filter(|p| !p.is_null()).and_then(|p| Projection::parse(p).ok()),
+            community_aggregation: resolved["aggregation"] == "community",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameEncoding {
+    pub name: &'static str,
+    pub header_bytes: usize,
+    pub bytes_per_node: usize,
+    // The handshake option that switches to it; none for the default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled_by: Option<&'static str>,
+}
+
+/// Optional prefixes to a frame, in the order they come
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameHeader {
+    pub name: &'static str,
+    pub bytes: usize,
+    pub enabled_by: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsEndpoint {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub description: &'static str,
+}
+
+pub const ANALYTICS_ENDPOINTS: &[AnalyticsEndpoint] = &[
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/stats", description: "Counts, attention, LOD ranking, layout quality and physics timings" },
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/pagerank", description: "PageRank per node" },
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/skeleton", description: "The maximum-weight spanning skeleton" },
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/edges/bundles", description: "Edge bundles for drawing dense graphs" },
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/edges/co-viewed", description: "Edges learned from co-selection" },
+    AnalyticsEndpoint { method: "GET", path: "/api/graph/labels/placement", description: "Non-overlapping label positions" },
+    AnalyticsEndpoint { method: "POST", path: "/api/graph/snapshots/compare", description: "Displacement between two layout snapshots" },
+];
+
+/// The document `GET /api/capabilities` returns
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub supported_protocol_versions: &'static [u32],
+    pub handshake_options: Vec<HandshakeOption>,
+    pub frame_encodings: Vec<FrameEncoding>,
+    pub frame_headers: Vec<FrameHeader>,
+    pub analytics_endpoints: &'static [AnalyticsEndpoint],
+    // Cargo features this build was compiled with
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        protocol_version: PROTOCOL_VERSION,
+        supported_protocol_versions: SUPPORTED_PROTOCOL_VERSIONS,
+        handshake_options: handshake_options(),
+        frame_encodings: vec![
+            FrameEncoding { name: "nodes3d", header_bytes: 0, bytes_per_node: std::mem::size_of::<WireNodeDataItem>(), enabled_by: None },
+            FrameEncoding { name: "projected2d", header_bytes: PROJECTED_HEADER_SIZE, bytes_per_node: PROJECTED_ITEM_SIZE, enabled_by: Some("projection") },
+        ],
+        frame_headers: vec![
+            FrameHeader { name: "timing", bytes: FRAME_HEADER_SIZE, enabled_by: "interpolationHints" },
+            FrameHeader { name: "hash", bytes: FRAME_HASH_SIZE, enabled_by: "frameHashes" },
+        ],
+        analytics_endpoints: ANALYTICS_ENDPOINTS,
+        features: BTreeMap::from([
+            ("gpu", cfg!(feature = "gpu")),
+            ("speech", cfg!(feature = "speech")),
+            ("websocket", cfg!(feature = "websocket")),
+            ("loadtest", cfg!(feature = "loadtest")),
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_handshake_reads_options_with_their_defaults() {
+        let minimal = Handshake::parse(&json!({ "type": "capabilities", "localPhysics": false })).unwrap();
+        assert_eq!(minimal, Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            local_physics: false,
+            interpolation_hints: false,
+            frame_hashes: false,
+            projection: None,
+            community_aggregation: false,
+        });
+        let full = Handshake::parse(&json!({
+            "type": "capabilities", "protocolVersion": 1, "localPhysics": true,
+            "interpolationHints": true, "frameHashes": true, "projection": "xz", "aggregation": "community",
+        })).unwrap();
+        assert!(full.local_physics && full.interpolation_hints && full.frame_hashes && full.community_aggregation);
+        assert_eq!(full.projection, Some(Projection::XZ));
+    }
+
+    #[test]
+    fn test_rejections_list_what_is_supported() {
+        let err = Handshake::parse(&json!({ "localPhysics": true, "compression": "zstd" })).unwrap_err();
+        assert!(err.contains("'compression'") && err.contains("localPhysics, interpolationHints"), "{}", err);
+        let err = Handshake::parse(&json!({ "localPhysics": true, "protocolVersion": 2 })).unwrap_err();
+        assert_eq!(err, "protocolVersion 2 isn't supported; supported: 1");
+        let err = Handshake::parse(&json!({ "localPhysics": "yes" })).unwrap_err();
+        assert!(err.starts_with("localPhysics must be true or false"), "{}", err);
+        let err = Handshake::parse(&json!({ "localPhysics": true, "projection": "yz" })).unwrap_err();
+        assert!(err.contains("use xz, xy or a 3x2 matrix"), "{}", err);
+        let err = Handshake::parse(&json!({ "localPhysics": true, "aggregation": "tags" })).unwrap_err();
+        assert_eq!(err, "aggregation \"tags\" isn't supported; supported: none, community");
+        assert_eq!(Handshake::parse(&json!({ "type": "capabilities" })).unwrap_err(), "capabilities needs localPhysics");
+    }
+}
@@ -0,0 +1,94 @@
+//! Live captions. A speech session that opts in with `shareCaptions` has its transcriptions
+//! forwarded to the graph clients in the speaker's room, so everyone in a shared session
+//! can read what is being said.
+
+use crate::actors::messages::ShareCaption;
+use crate::services::speech_session_service::SessionOptions;
+use crate::types::speech::TranscriptionSegment;
+
+// How much of a pubkey stands in for a speaker who didn't set a display name
+const PUBKEY_NAME_CHARS: usize = 8;
+
+/// The caption to share for a segment, or None if the session doesn't share this one
+pub fn caption_for(options: &SessionOptions, pubkey: Option<String>, segment: &TranscriptionSegment) -> Option<ShareCaption> {
+    if !options.share_captions || (!segment.is_final && !options.share_interim_captions) {
+        return None;
+    }
+    let text = segment.text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(ShareCaption {
+        pubkey,
+        display_name: options.display_name.clone(),
+        text: text.to_string(),
+        language: segment.language.clone(),
+        is_final: segment.is_final,
+    })
+}
+
+/// Cuts `text` to at most `max_chars` characters, marking the cut with an ellipsis
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// The name shown with a caption: the session's display name, else the start of the pubkey
+pub fn speaker_name(caption: &ShareCaption) -> String {
+    if let Some(name) = caption.display_name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        return name.to_string();
+    }
+    match &caption.pubkey {
+        Some(pubkey) => pubkey.chars().take(PUBKEY_NAME_CHARS).collect(),
+        None => "Guest".to_string(),
+    }
+}
+
+/// The `caption` message graph clients receive. `color` is the speaker's presence colour
+/// when they also have a graph client connected.
+pub fn caption_event(caption: &ShareCaption, color: Option<String>, max_chars: usize) -> String {
+    serde_json::json!({
+        "type": "caption",
+        "speaker": speaker_name(caption),
+        "color": color,
+        "text": truncate(&caption.text, max_chars),
+        "language": caption.language,
+        "isFinal": caption.is_final,
+        "timestamp": chrono::Utc::now().timestamp_millis(),
+    }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, is_final: bool) -> TranscriptionSegment {
+        TranscriptionSegment { text: text.to_string(), language: Some("en".to_string()), detected: false, is_final }
+    }
+
+    #[test]
+    fn test_only_opted_in_sessions_share() {
+        let sharing = SessionOptions { share_captions: true, ..Default::default() };
+        assert!(caption_for(&SessionOptions::default(), None, &segment("hello", true)).is_none());
+        assert!(caption_for(&sharing, None, &segment("  ", true)).is_none());
+        // Interim segments need their own opt-in
+        assert!(caption_for(&sharing, None, &segment("hel", false)).is_none());
+        let interim = SessionOptions { share_interim_captions: true, ..sharing.clone() };
+        assert!(!caption_for(&interim, None, &segment("hel", false)).unwrap().is_final);
+
+        let caption = caption_for(&sharing, Some("a1b2c3d4e5f6".to_string()), &segment(" hello ", true)).unwrap();
+        assert_eq!(caption.text, "hello");
+        assert_eq!(speaker_name(&caption), "a1b2c3d4");
+    }
+
+    #[test]
+    fn test_truncate_counts_characters() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("ünïcödé text", 6), "ünïcö…");
+        assert_eq!(truncate("ünïcödé text", 6).chars().count(), 6);
+    }
+}
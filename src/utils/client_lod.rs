@@ -0,0 +1,214 @@
+//! Level of detail per client. A throttled client gets the shared priority nodes in every
+//! frame and the rest in rotating buckets; on its own that keeps sending the same global
+//! hubs to a client exploring a far corner of the graph. Each client also gets its own top
+//! nodes, re-ranked every few seconds from what the client manager already sees: how
+//! recently the client selected or gazed at a node, and how close a node is to where the
+//! client is looking from, blended with the graph-wide importance.
+
+use glam::Vec3;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::ClientLodSettings;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+/// What one client has shown interest in
+#[derive(Debug, Default)]
+pub struct ClientInterest {
+    // When the client last selected or gazed at each node
+    touched: HashMap<u32, Instant>,
+    // Where the client looks from, in graph space
+    viewpoint: Option<Vec3>,
+}
+
+pub struct ClientLod {
+    settings: ClientLodSettings,
+    // Graph-wide importance, scaled so the most important node is 1
+    importance: Arc<HashMap<u32, f32>>,
+    positions: HashMap<u32, Vec3>,
+    clients: HashMap<usize, ClientInterest>,
+    // Each client's current top nodes, most important first
+    top: HashMap<usize, (Vec<u32>, Arc<HashSet<u32>>)>,
+    last_refresh: Option<Instant>,
+}
+
+impl ClientLod {
+    pub fn new(settings: ClientLodSettings) -> Self {
+        Self {
+            settings,
+            importance: Arc::new(HashMap::new()),
+            positions: HashMap::new(),
+            clients: HashMap::new(),
+            top: HashMap::new(),
+            last_refresh: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled && self.settings.top_n > 0
+    }
+
+    pub fn set_importance(&mut self, importance: &HashMap<u32, f32>) {
+        let max = importance.values().copied().fold(0.0f32, f32::max);
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+        self.importance = Arc::new(importance.iter().map(|(&id, &score)| (id, score * scale)).collect());
+        // Nodes that are gone have no position to rank by either
+        self.positions.retain(|id, _| importance.contains_key(id));
+    }
+
+    /// Takes node positions from a broadcast frame
+    pub fn observe(&mut self, positions: &[(u32, BinaryNodeData)]) {
+        for (id, data) in positions {
+            self.positions.insert(*id, Vec3::new(data.position.x, data.position.y, data.position.z));
+        }
+    }
+
+    /// Whether anything would use positions from frames right now
+    pub fn wants_positions(&self) -> bool {
+        self.enabled() && !self.clients.is_empty()
+    }
+
+    pub fn touch(&mut self, client_id: usize, node_id: u32, now: Instant) {
+        if self.enabled() {
+            self.clients.entry(client_id).or_default().touched.insert(node_id, now);
+        }
+    }
+
+    pub fn set_viewpoint(&mut self, client_id: usize, viewpoint: Vec3) {
+        if self.enabled() && viewpoint.is_finite() {
+            self.clients.entry(client_id).or_default().viewpoint = Some(viewpoint);
+        }
+    }
+
+    pub fn remove(&mut self, client_id: usize) {
+        self.clients.remove(&client_id);
+        self.top.remove(&client_id);
+    }
+
+    /// Re-ranks every client with some interest if `refresh_secs` have passed since the
+    /// last time. True if it did.
+    pub fn refresh_if_due(&mut self, now: Instant) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        let interval = Duration::from_secs_f32(self.settings.refresh_secs.max(0.1));
+        if matches!(self.last_refresh, Some(last) if now.saturating_duration_since(last) < interval) {
+            return false;
+        }
+        self.last_refresh = Some(now);
+        let half_life = Duration::from_secs_f32(self.settings.interaction_half_life_secs.max(0.1));
+        let mut top = HashMap::new();
+        for (client_id, interest) in self.clients.iter_mut() {
+            // Interactions this far gone no longer count
+            interest.touched.retain(|_, at| now.saturating_duration_since(*at) < half_life * 16);
+            let ranked = rank(&self.settings, &self.importance, &self.positions, interest, now);
+            let set = Arc::new(ranked.iter().copied().collect());
+            top.insert(*client_id, (ranked, set));
+        }
+        self.top = top;
+        true
+    }
+
+    /// The nodes the client gets in every frame for itself
+    pub fn priority(&self, client_id: usize) -> Option<Arc<HashSet<u32>>> {
+        self.top.get(&client_id).map(|(_, set)| set.clone())
+    }
+
+    /// Every client's current top nodes, most important first
+    pub fn top_nodes(&self) -> HashMap<usize, Vec<u32>> {
+        self.top.iter().map(|(id, (ranked, _))| (*id, ranked.clone())).collect()
+    }
+}
+
+/// The client's `top_n` nodes by blended score
+fn rank(
+    settings: &ClientLodSettings,
+    importance: &HashMap<u32, f32>,
+    positions: &HashMap<u32, Vec3>,
+    interest: &ClientInterest,
+    now: Instant,
+) -> Vec<u32> {
+    let half_life = settings.interaction_half_life_secs.max(0.1);
+    let scale = settings.proximity_scale.max(f32::EPSILON);
+    let score = |id: u32| {
+        let mut score = importance.get(&id).copied().unwrap_or(0.0) * settings.importance_weight;
+        if let Some(at) = interest.touched.get(&id) {
+            score += 0.5f32.powf(now.saturating_duration_since(*at).as_secs_f32() / half_life) * settings.interaction_weight;
+        }
+        if let (Some(viewpoint), Some(position)) = (interest.viewpoint, positions.get(&id)) {
+            score += (-viewpoint.distance(*position) / scale).exp() * settings.proximity_weight;
+        }
+        score
+    };
+    let candidates: HashSet<u32> = importance.keys().chain(positions.keys()).chain(interest.touched.keys()).copied().collect();
+    let mut ranked: Vec<(u32, f32)> = candidates.into_iter().map(|id| (id, score(id))).collect();
+    let order = |a: &(u32, f32), b: &(u32, f32)| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0));
+    // Only the top needs sorting
+    if ranked.len() > settings.top_n {
+        ranked.select_nth_unstable_by(settings.top_n, order);
+        ranked.truncate(settings.top_n);
+    }
+    ranked.sort_by(order);
+    ranked.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    fn at(x: f32) -> BinaryNodeData {
+        BinaryNodeData {
+            position: Vec3Data::new(x, 0.0, 0.0),
+            velocity: Vec3Data::zero(),
+            mass: 100,
+            flags: 0,
+            padding: [0, 0],
+        }
+    }
+
+    #[test]
+    fn test_focus_on_a_peripheral_cluster_shifts_the_top_nodes() {
+        let settings = ClientLodSettings {
+            top_n: 4, refresh_secs: 2.0, interaction_half_life_secs: 10.0, proximity_weight: 2.0, ..Default::default()
+        };
+        let mut lod = ClientLod::new(settings);
+        // Hubs 1-4 at the centre; a quiet cluster 10-13 far out
+        let importance: HashMap<u32, f32> = [(1, 0.4), (2, 0.3), (3, 0.3), (4, 0.2), (10, 0.05), (11, 0.04), (12, 0.04), (13, 0.03)].into();
+        lod.set_importance(&importance);
+        let positions: Vec<(u32, BinaryNodeData)> = [(1, 0.0), (2, 5.0), (3, -5.0), (4, 10.0), (10, 500.0), (11, 505.0), (12, 510.0), (13, 495.0)]
+            .into_iter().map(|(id, x)| (id, at(x))).collect();
+        lod.observe(&positions);
+        let start = Instant::now();
+        lod.set_viewpoint(7, Vec3::ZERO);
+        assert!(lod.refresh_if_due(start));
+        assert_eq!(lod.top_nodes()[&7], vec![1, 2, 3, 4]);
+
+        // The client flies out to the far cluster and selects one of its nodes. Nothing
+        // changes until the next refresh, then the cluster takes over.
+        let moved = start + Duration::from_millis(500);
+        lod.set_viewpoint(7, Vec3::new(500.0, 0.0, 0.0));
+        lod.touch(7, 12, moved);
+        assert!(!lod.refresh_if_due(moved));
+        assert_eq!(lod.top_nodes()[&7], vec![1, 2, 3, 4]);
+        let refreshed = start + Duration::from_secs(2);
+        assert!(lod.refresh_if_due(refreshed));
+        let mut top = lod.top_nodes()[&7].clone();
+        assert_eq!(top[0], 12);
+        top.sort();
+        assert_eq!(top, vec![10, 11, 12, 13]);
+        assert!(lod.priority(7).unwrap().contains(&13));
+
+        // The selection fades within its window, leaving the cluster ranked by closeness
+        assert!(lod.refresh_if_due(refreshed + Duration::from_secs(60)));
+        assert_eq!(lod.top_nodes()[&7][0], 10);
+        // Back at the centre the hubs return
+        lod.set_viewpoint(7, Vec3::ZERO);
+        assert!(lod.refresh_if_due(refreshed + Duration::from_secs(62)));
+        assert_eq!(lod.top_nodes()[&7], vec![1, 2, 3, 4]);
+
+        lod.remove(7);
+        assert!(lod.priority(7).is_none() && !lod.wants_positions());
+    }
+}
@@ -0,0 +1,235 @@
+//! Behavioural edges from co-selection. Nodes that keep being selected in the same session
+//! are probably related. Sessions are only ever a connection's in-memory selection list;
+//! once it ends, all that is kept is the set of nodes, with nothing tying it to a user.
+//! Pair counts decay with the same half-life maths as decaying edges, so pairs nobody
+//! looks at together any more fade out.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::utils::edge_decay;
+
+pub const CO_VIEWED_EDGE_TYPE: &str = "co_viewed";
+
+// A live session's recent selections and when it last selected anything
+struct LiveSelection {
+    sequence: VecDeque<String>,
+    last: Instant,
+}
+
+/// Selection sequences of live sessions, and the finished ones the next job run will count.
+/// Selections are held by metadata id, so a rebuild mid-session doesn't mix up nodes.
+pub struct SelectionTracker {
+    live: HashMap<usize, LiveSelection>,
+    finished: VecDeque<Vec<String>>,
+    max_per_session: usize,
+    max_finished: usize,
+    // A session quiet for longer than this has ended, even if its connection hasn't
+    idle: Duration,
+}
+
+impl SelectionTracker {
+    pub fn new(max_per_session: usize, max_finished: usize, idle: Duration) -> Self {
+        Self { live: HashMap::new(), finished: VecDeque::new(), max_per_session: max_per_session.max(2), max_finished, idle }
+    }
+
+    /// Adds a selection to the session, keeping only its most recent ones. After an idle
+    /// spell the old session is ended and this one starts the next.
+    pub fn record(&mut self, session: usize, metadata_id: String, now: Instant) {
+        if self.live.get(&session).is_some_and(|live| now.saturating_duration_since(live.last) > self.idle) {
+            self.finish(session);
+        }
+        let live = self.live.entry(session).or_insert_with(|| LiveSelection { sequence: VecDeque::new(), last: now });
+        live.last = now;
+        if live.sequence.back() == Some(&metadata_id) {
+            return;
+        }
+        live.sequence.push_back(metadata_id);
+        while live.sequence.len() > self.max_per_session {
+            live.sequence.pop_front();
+        }
+    }
+
+    /// Ends a session. Only what was selected is kept, and only if that was two nodes or more.
+    pub fn finish(&mut self, session: usize) {
+        let Some(live) = self.live.remove(&session) else {
+            return;
+        };
+        let distinct: HashSet<String> = live.sequence.into_iter().collect();
+        if distinct.len() < 2 || self.max_finished == 0 {
+            return;
+        }
+        if self.finished.len() >= self.max_finished {
+            self.finished.pop_front();
+        }
+        self.finished.push_back(distinct.into_iter().collect());
+    }
+
+    /// Takes the finished sessions, ending idle live ones first so connections that stay
+    /// open for days are counted too
+    pub fn take_finished(&mut self, now: Instant) -> Vec<Vec<String>> {
+        let idle: Vec<usize> = self.live.iter()
+            .filter(|(_, live)| now.saturating_duration_since(live.last) > self.idle)
+            .map(|(&session, _)| session)
+            .collect();
+        for session in idle {
+            self.finish(session);
+        }
+        self.finished.drain(..).collect()
+    }
+
+    pub fn live_sessions(&self) -> usize {
+        self.live.len()
+    }
+}
+
+/// A derived edge between two nodes, by metadata id so it survives rebuilds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoViewedPair {
+    pub source: String,
+    pub target: String,
+    pub weight: f32,
+}
+
+/// Decaying co-selection counts per node pair
+pub struct CoSelectionCounts {
+    counts: HashMap<(String, String), f32>,
+    max_pairs: usize,
+}
+
+impl CoSelectionCounts {
+    pub fn new(max_pairs: usize) -> Self {
+        Self { counts: HashMap::new(), max_pairs }
+    }
+
+    /// Counts one session: every pair of nodes it selected goes up by one
+    pub fn add_session(&mut self, nodes: &[String]) {
+        let mut nodes: Vec<&String> = nodes.iter().collect();
+        nodes.sort();
+        nodes.dedup();
+        for (i, a) in nodes.iter().enumerate() {
+            for b in &nodes[i + 1..] {
+                *self.counts.entry(((*a).clone(), (*b).clone())).or_default() += 1.0;
+            }
+        }
+        if self.counts.len() > self.max_pairs {
+            // Over the cap, the weakest pairs go first
+            let mut ranked: Vec<((String, String), f32)> = self.counts.drain().collect();
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            ranked.truncate(self.max_pairs);
+            self.counts = ranked.into_iter().collect();
+        }
+    }
+
+    /// Fades every count by the half-life over `elapsed_secs`, dropping those below `floor`
+    pub fn decay(&mut self, half_life_days: f32, elapsed_secs: f64, floor: f32) {
+        let factor = edge_decay::decay_factor(half_life_days, elapsed_secs);
+        self.counts.retain(|_, count| {
+            *count *= factor;
+            *count >= floor
+        });
+    }
+
+    /// Pairs counted at least `threshold` times, weighted by their count
+    pub fn pairs(&self, threshold: f32, weight_per_session: f32) -> Vec<CoViewedPair> {
+        let mut pairs: Vec<CoViewedPair> = self.counts.iter()
+            .filter(|(_, count)| **count >= threshold)
+            .map(|((source, target), count)| CoViewedPair {
+                source: source.clone(),
+                target: target.clone(),
+                weight: count * weight_per_session,
+            })
+            .collect();
+        pairs.sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+        pairs
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tracker_is_bounded_and_forgets_sessions() {
+        let now = Instant::now();
+        let mut tracker = SelectionTracker::new(3, 2, Duration::from_secs(600));
+        for node in ["a", "a", "b", "c", "d"] {
+            tracker.record(7, node.to_string(), now);
+        }
+        tracker.record(8, "e".to_string(), now);
+        tracker.finish(7);
+        // A single selection says nothing about pairs
+        tracker.finish(8);
+        assert_eq!(tracker.live_sessions(), 0);
+        let mut finished = tracker.take_finished(now);
+        finished[0].sort();
+        assert_eq!(finished, vec![ids(&["b", "c", "d"])]);
+        assert!(tracker.take_finished(now).is_empty());
+
+        for session in 0..5 {
+            tracker.record(session, "a".to_string(), now);
+            tracker.record(session, "b".to_string(), now);
+            tracker.finish(session);
+        }
+        assert_eq!(tracker.take_finished(now).len(), 2);
+    }
+
+    #[test]
+    fn test_idle_sessions_end_without_a_disconnect() {
+        let start = Instant::now();
+        let mut tracker = SelectionTracker::new(10, 10, Duration::from_secs(600));
+        tracker.record(1, "a".to_string(), start);
+        tracker.record(1, "b".to_string(), start);
+        // Back after lunch: what came before is its own session
+        let later = start + Duration::from_secs(3600);
+        tracker.record(1, "c".to_string(), later);
+        tracker.record(1, "d".to_string(), later);
+        assert_eq!(tracker.take_finished(later).len(), 1);
+        assert_eq!(tracker.live_sessions(), 1);
+
+        // Still connected, but quiet since
+        let mut finished = tracker.take_finished(later + Duration::from_secs(601));
+        finished[0].sort();
+        assert_eq!(finished, vec![ids(&["c", "d"])]);
+        assert_eq!(tracker.live_sessions(), 0);
+    }
+
+    #[test]
+    fn test_counts_cross_the_threshold_then_decay_away() {
+        let mut counts = CoSelectionCounts::new(100);
+        for _ in 0..3 {
+            counts.add_session(&ids(&["b", "a", "c"]));
+        }
+        counts.add_session(&ids(&["a", "d"]));
+        let pairs = counts.pairs(3.0, 0.1);
+        assert_eq!(pairs.iter().map(|p| (p.source.as_str(), p.target.as_str())).collect::<Vec<_>>(),
+            vec![("a", "b"), ("a", "c"), ("b", "c")]);
+        assert!((pairs[0].weight - 0.3).abs() < 1e-6);
+
+        // One half-life halves the counts, and (a, d) falls under the floor
+        counts.decay(1.0, 86_400.0, 0.6);
+        assert_eq!(counts.len(), 3);
+        assert!(counts.pairs(3.0, 0.1).is_empty());
+        assert_eq!(counts.pairs(1.5, 0.1).len(), 3);
+
+        let mut capped = CoSelectionCounts::new(2);
+        capped.add_session(&ids(&["a", "b"]));
+        capped.add_session(&ids(&["a", "b", "c"]));
+        assert_eq!(capped.len(), 2);
+        assert_eq!(capped.pairs(2.0, 1.0)[0].target, "b");
+    }
+}
@@ -0,0 +1,288 @@
+//! Server-side node colouring.
+//!
+//! Categorical strategies give each distinct value a palette colour by its position in
+//! the sorted value set, so the same set of groups always gets the same colours no
+//! matter how ids or node order change across rebuilds. Continuous strategies map a
+//! normalised score onto a gradient.
+
+use chrono::DateTime;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::{ColorGradient, ColorMappingSettings, ColorPalette, ColorStrategy};
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+
+// Nodes the strategy has no value for (no group, no lastModified, ...)
+pub const MISSING_COLOR: &str = "#6B6B6B";
+
+/// A node's new colour, as broadcast after a recolour. None hands colouring back to the client.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeColor {
+    pub node_id: u32,
+    pub color: Option<String>,
+}
+
+const PAGERANK_DAMPING: f32 = 0.85;
+const PAGERANK_MAX_ITERATIONS: usize = 100;
+const PAGERANK_TOLERANCE: f32 = 1e-6;
+
+impl ColorPalette {
+    pub fn colors(&self) -> &'static [&'static str] {
+        match self {
+            // Okabe & Ito, without black, which disappears on the dark background
+            ColorPalette::OkabeIto => &["#E69F00", "#56B4E9", "#009E73", "#F0E442", "#0072B2", "#D55E00", "#CC79A7"],
+            // Paul Tol's bright and muted qualitative schemes
+            ColorPalette::TolBright => &["#4477AA", "#EE6677", "#228833", "#CCBB44", "#66CCEE", "#AA3377", "#BBBBBB"],
+            ColorPalette::TolMuted => &[
+                "#CC6677", "#332288", "#DDCC77", "#117733", "#88CCEE", "#882255", "#44AA99", "#999933", "#AA4499",
+            ],
+        }
+    }
+}
+
+impl ColorGradient {
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            ColorGradient::Viridis => &[[0x44, 0x01, 0x54], [0x3B, 0x52, 0x8B], [0x21, 0x91, 0x8C], [0x5E, 0xC9, 0x62], [0xFD, 0xE7, 0x25]],
+            ColorGradient::Cividis => &[[0x00, 0x20, 0x4D], [0x41, 0x4D, 0x6B], [0x7C, 0x7B, 0x78], [0xBC, 0xAF, 0x6F], [0xFF, 0xEA, 0x46]],
+        }
+    }
+
+    /// Colour at `t` in 0..=1, interpolated between the gradient's stops
+    pub fn sample(&self, t: f32) -> String {
+        let stops = self.stops();
+        let scaled = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (scaled.floor() as usize).min(stops.len() - 2);
+        let frac = scaled - index as f32;
+        let (a, b) = (stops[index], stops[index + 1]);
+        let channel = |i: usize| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * frac).round() as u8;
+        format!("#{:02X}{:02X}{:02X}", channel(0), channel(1), channel(2))
+    }
+}
+
+/// Colours for every node under the mapping, keyed by node id. Empty for `none`.
+pub fn compute_colors(mapping: &ColorMappingSettings, nodes: &[Node], edges: &[Edge]) -> HashMap<u32, String> {
+    match mapping.strategy {
+        ColorStrategy::None => HashMap::new(),
+        ColorStrategy::ByGroup => categorical(nodes, mapping.palette, |n| n.group.clone()),
+        ColorStrategy::ByNodeType => categorical(nodes, mapping.palette, |n| n.node_type.clone()),
+        ColorStrategy::ByMetadataKey => {
+            categorical(nodes, mapping.palette, |n| n.metadata.get(&mapping.metadata_key).cloned())
+        }
+        ColorStrategy::ByAge => {
+            let ages: HashMap<u32, f32> = nodes.iter()
+                .filter_map(|n| {
+                    let modified = DateTime::parse_from_rfc3339(n.metadata.get("lastModified")?).ok()?;
+                    Some((n.id, modified.timestamp() as f32))
+                })
+                .collect();
+            // Newest at the bright end of the gradient
+            continuous(nodes, &ages, mapping.gradient)
+        }
+        ColorStrategy::ByPagerank => continuous(nodes, &pagerank(nodes, edges), mapping.gradient),
+    }
+}
+
+fn categorical<F>(nodes: &[Node], palette: ColorPalette, value: F) -> HashMap<u32, String>
+where
+    F: Fn(&Node) -> Option<String>,
+{
+    let values: HashMap<u32, String> = nodes.iter()
+        .filter_map(|n| value(n).filter(|v| !v.is_empty()).map(|v| (n.id, v)))
+        .collect();
+    // Index in the sorted set of distinct values, never insertion order
+    let distinct: BTreeSet<&String> = values.values().collect();
+    let colors = palette.colors();
+    let assigned: HashMap<&String, &str> = distinct.into_iter()
+        .enumerate()
+        .map(|(i, v)| (v, colors[i % colors.len()]))
+        .collect();
+
+    nodes.iter()
+        .map(|n| {
+            let color = values.get(&n.id).map(|v| assigned[v]).unwrap_or(MISSING_COLOR);
+            (n.id, color.to_string())
+        })
+        .collect()
+}
+
+fn continuous(nodes: &[Node], scores: &HashMap<u32, f32>, gradient: ColorGradient) -> HashMap<u32, String> {
+    let min = scores.values().cloned().fold(f32::INFINITY, f32::min);
+    let max = scores.values().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+
+    nodes.iter()
+        .map(|n| {
+            let color = match scores.get(&n.id) {
+                // Everything equal sits at the top of the scale
+                Some(score) if range > f32::EPSILON => gradient.sample((score - min) / range),
+                Some(_) => gradient.sample(1.0),
+                None => MISSING_COLOR.to_string(),
+            };
+            (n.id, color)
+        })
+        .collect()
+}
+
+/// PageRank over the graph with edges treated as undirected and weighted. Scores sum to 1.
+pub fn pagerank(nodes: &[Node], edges: &[Edge]) -> HashMap<u32, f32> {
+    pagerank_until(nodes, edges, &|| false).unwrap_or_default()
+}
+
+/// `pagerank`, checking `cancelled` between iterations; `None` if it was
+pub fn pagerank_until(nodes: &[Node], edges: &[Edge], cancelled: &dyn Fn() -> bool) -> Option<HashMap<u32, f32>> {
+    let n = nodes.len();
+    if n == 0 {
+        return Some(HashMap::new());
+    }
+    let index: HashMap<u32, usize> = nodes.iter().enumerate().map(|(i, node)| (node.id, i)).collect();
+    let mut links: Vec<Vec<(usize, f32)>> = vec![Vec::new(); n];
+    for edge in edges {
+        if let (Some(&s), Some(&t)) = (index.get(&edge.source), index.get(&edge.target)) {
+            if s != t && edge.weight > 0.0 {
+                links[s].push((t, edge.weight));
+                links[t].push((s, edge.weight));
+            }
+        }
+    }
+    let out_weight: Vec<f32> = links.iter().map(|l| l.iter().map(|(_, w)| w).sum()).collect();
+
+    let base = (1.0 - PAGERANK_DAMPING) / n as f32;
+    let mut rank = vec![1.0 / n as f32; n];
+    for _ in 0..PAGERANK_MAX_ITERATIONS {
+        if cancelled() {
+            return None;
+        }
+        // Rank held by isolated nodes is spread evenly rather than lost
+        let dangling: f32 = (0..n).filter(|&i| out_weight[i] == 0.0).map(|i| rank[i]).sum();
+        let mut next = vec![base + PAGERANK_DAMPING * dangling / n as f32; n];
+        for (i, targets) in links.iter().enumerate() {
+            for &(j, w) in targets {
+                next[j] += PAGERANK_DAMPING * rank[i] * w / out_weight[i];
+            }
+        }
+        let delta: f32 = next.iter().zip(&rank).map(|(a, b)| (a - b).abs()).sum();
+        rank = next;
+        if delta < PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+
+    Some(nodes.iter().map(|node| node.id).zip(rank).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u32, group: Option<&str>) -> Node {
+        let mut node = Node::new_with_id(format!("note-{}", id), Some(id));
+        node.group = group.map(str::to_string);
+        node
+    }
+
+    fn mapping(strategy: ColorStrategy) -> ColorMappingSettings {
+        ColorMappingSettings { strategy, ..Default::default() }
+    }
+
+    #[test]
+    fn test_by_group_is_deterministic_across_rebuilds() {
+        let nodes = vec![node(1, Some("rust")), node(2, Some("ai")), node(3, Some("rust")), node(4, None)];
+        let colors = compute_colors(&mapping(ColorStrategy::ByGroup), &nodes, &[]);
+        let palette = ColorPalette::OkabeIto.colors();
+        // Sorted group set: ai, rust
+        assert_eq!(colors[&2], palette[0]);
+        assert_eq!(colors[&1], palette[1]);
+        assert_eq!(colors[&3], colors[&1]);
+        assert_eq!(colors[&4], MISSING_COLOR);
+
+        // Same groups after a rebuild: new ids, different order
+        let rebuilt = vec![node(20, None), node(21, Some("rust")), node(22, Some("ai"))];
+        let recolored = compute_colors(&mapping(ColorStrategy::ByGroup), &rebuilt, &[]);
+        assert_eq!(recolored[&21], colors[&1]);
+        assert_eq!(recolored[&22], colors[&2]);
+    }
+
+    #[test]
+    fn test_palettes_cycle_past_their_length() {
+        let nodes: Vec<Node> = (1..=9).map(|i| node(i, Some(&format!("g{}", i)))).collect();
+        let settings = ColorMappingSettings { palette: ColorPalette::TolBright, ..mapping(ColorStrategy::ByGroup) };
+        let colors = compute_colors(&settings, &nodes, &[]);
+        // g1 and g8 are 7 apart in the sorted set, and TolBright has 7 colours
+        assert_eq!(colors[&1], colors[&8]);
+        assert_ne!(colors[&1], colors[&2]);
+    }
+
+    #[test]
+    fn test_by_node_type_and_metadata_key() {
+        let mut a = node(1, None);
+        a.node_type = Some("paper".to_string());
+        a.metadata.insert("status".to_string(), "draft".to_string());
+        let mut b = node(2, None);
+        b.node_type = Some("person".to_string());
+        b.metadata.insert("status".to_string(), "final".to_string());
+        let nodes = vec![a, b, node(3, None)];
+
+        let by_type = compute_colors(&mapping(ColorStrategy::ByNodeType), &nodes, &[]);
+        assert_ne!(by_type[&1], by_type[&2]);
+        assert_eq!(by_type[&3], MISSING_COLOR);
+
+        let settings = ColorMappingSettings {
+            metadata_key: "status".to_string(),
+            palette: ColorPalette::TolMuted,
+            ..mapping(ColorStrategy::ByMetadataKey)
+        };
+        let by_key = compute_colors(&settings, &nodes, &[]);
+        // draft < final
+        assert_eq!(by_key[&1], ColorPalette::TolMuted.colors()[0]);
+        assert_eq!(by_key[&2], ColorPalette::TolMuted.colors()[1]);
+        assert_eq!(by_key[&3], MISSING_COLOR);
+    }
+
+    #[test]
+    fn test_by_age_gradient() {
+        let mut old = node(1, None);
+        old.metadata.insert("lastModified".to_string(), "2020-01-01T00:00:00+00:00".to_string());
+        let mut new = node(2, None);
+        new.metadata.insert("lastModified".to_string(), "2024-01-01T00:00:00+00:00".to_string());
+        let nodes = vec![old, new, node(3, None)];
+
+        let colors = compute_colors(&mapping(ColorStrategy::ByAge), &nodes, &[]);
+        assert_eq!(colors[&1], "#440154");
+        assert_eq!(colors[&2], "#FDE725");
+        assert_eq!(colors[&3], MISSING_COLOR);
+
+        let settings = ColorMappingSettings { gradient: ColorGradient::Cividis, ..mapping(ColorStrategy::ByAge) };
+        assert_eq!(compute_colors(&settings, &nodes, &[])[&2], "#FFEA46");
+    }
+
+    #[test]
+    fn test_by_pagerank_highlights_hubs() {
+        // Star: node 1 is the hub
+        let nodes: Vec<Node> = (1..=5).map(|i| node(i, None)).collect();
+        let edges: Vec<Edge> = (2..=5).map(|i| Edge::new(1, i, 1.0)).collect();
+
+        let ranks = pagerank(&nodes, &edges);
+        assert!((ranks.values().sum::<f32>() - 1.0).abs() < 1e-3);
+        assert!(ranks[&1] > ranks[&2]);
+
+        let colors = compute_colors(&mapping(ColorStrategy::ByPagerank), &nodes, &edges);
+        assert_eq!(colors[&1], "#FDE725");
+        assert_eq!(colors[&2], "#440154");
+        assert_eq!(colors[&2], colors[&5]);
+    }
+
+    #[test]
+    fn test_none_leaves_nodes_uncoloured() {
+        assert!(compute_colors(&mapping(ColorStrategy::None), &[node(1, Some("a"))], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_gradient_interpolates_between_stops() {
+        assert_eq!(ColorGradient::Viridis.sample(0.5), "#21918C");
+        assert_eq!(ColorGradient::Viridis.sample(-1.0), "#440154");
+        assert_eq!(ColorGradient::Viridis.sample(0.125), "#402A70");
+    }
+}
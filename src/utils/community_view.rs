@@ -0,0 +1,354 @@
+//! Aggregated view of the graph for clients that can't draw all of it. Communities come
+//! from weighted label propagation over the edges; each collapses into one super-node
+//! sized by its member count, and the edges between two communities into one edge
+//! carrying their summed weight. The view only depends on the topology, so it's cached
+//! per graph generation; physics keeps running on the full graph, and a super-node's
+//! position is its members' centroid, worked out per frame. Which communities a client
+//! has expanded is the client's own.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::models::edge::Edge;
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+// Super-node ids live above every id the node id map hands out
+pub const SUPER_NODE_ID_BASE: u32 = 0x8000_0000;
+const MAX_PROPAGATION_ROUNDS: usize = 20;
+
+/// Communities by label propagation: every node repeatedly takes the label carrying the
+/// most edge weight among its neighbours, ties to the smallest. Deterministic for a
+/// given graph; largest community first, members in id order.
+pub fn detect_communities(node_ids: &[u32], edges: &[Edge]) -> Vec<Vec<u32>> {
+    let mut order: Vec<u32> = node_ids.to_vec();
+    order.sort_unstable();
+    order.dedup();
+    let mut neighbours: HashMap<u32, Vec<(u32, f32)>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.source != e.target && e.weight > 0.0) {
+        neighbours.entry(edge.source).or_default().push((edge.target, edge.weight));
+        neighbours.entry(edge.target).or_default().push((edge.source, edge.weight));
+    }
+    let mut labels: HashMap<u32, u32> = order.iter().map(|&id| (id, id)).collect();
+    for _ in 0..MAX_PROPAGATION_ROUNDS {
+        let mut changed = false;
+        for id in &order {
+            let Some(links) = neighbours.get(id) else { continue };
+            let mut weights: BTreeMap<u32, f32> = BTreeMap::new();
+            for (other, weight) in links {
+                if let Some(&label) = labels.get(other) {
+                    *weights.entry(label).or_default() += weight;
+                }
+            }
+            // BTreeMap order makes the smallest label win a tie
+            let best = weights.iter().fold(None, |best: Option<(u32, f32)>, (&label, &weight)| match best {
+                Some((_, top)) if top >= weight => best,
+                _ => Some((label, weight)),
+            });
+            if let Some((label, weight)) = best {
+                let own = labels[id];
+                // Keeping its label on a tie stops two labels swapping back and forth
+                if label != own && weights.get(&own).is_none_or(|w| *w < weight) {
+                    labels.insert(*id, label);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut by_label: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for id in &order {
+        by_label.entry(labels[id]).or_default().push(*id);
+    }
+    let mut communities: Vec<Vec<u32>> = by_label.into_values().collect();
+    communities.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+    communities
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Community {
+    // The super-node's id in frames
+    pub id: u32,
+    // Named after its most connected member
+    pub label: String,
+    pub member_count: usize,
+    #[serde(skip)]
+    pub members: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedEdge {
+    pub source: u32,
+    pub target: u32,
+    // Summed weight of the edges between the two communities
+    pub weight: f32,
+    pub edge_count: usize,
+}
+
+/// Last-known position of each node a client has been sent, so a super-node's centroid
+/// covers all its members even when a delta carries only the ones that moved. Pinned,
+/// held and archived nodes can go many frames without one.
+#[derive(Debug, Clone, Default)]
+pub struct MemberPositions(HashMap<u32, BinaryNodeData>);
+
+impl MemberPositions {
+    pub fn seed(graph: &GraphData) -> Self {
+        Self(graph.nodes.iter().map(|n| (n.id, n.data)).collect())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphView {
+    // Graph generation the view was derived at
+    pub generation: u64,
+    pub communities: Vec<Community>,
+    pub edges: Vec<AggregatedEdge>,
+    // Node id to its community's index
+    #[serde(skip)]
+    member_of: HashMap<u32, usize>,
+}
+
+impl GraphView {
+    pub fn aggregate(graph: &GraphData) -> Self {
+        let ids: Vec<u32> = graph.nodes.iter().map(|n| n.id).collect();
+        let groups = detect_communities(&ids, &graph.edges);
+        let member_of: HashMap<u32, usize> = groups.iter().enumerate()
+            .flat_map(|(index, members)| members.iter().map(move |id| (*id, index)))
+            .collect();
+
+        let mut degree: HashMap<u32, f32> = HashMap::new();
+        let mut between: BTreeMap<(usize, usize), (f32, usize)> = BTreeMap::new();
+        for edge in &graph.edges {
+            let (Some(&a), Some(&b)) = (member_of.get(&edge.source), member_of.get(&edge.target)) else { continue };
+            *degree.entry(edge.source).or_default() += edge.weight;
+            *degree.entry(edge.target).or_default() += edge.weight;
+            if a != b {
+                let total = between.entry((a.min(b), a.max(b))).or_default();
+                total.0 += edge.weight;
+                total.1 += 1;
+            }
+        }
+        let labels: HashMap<u32, &str> = graph.nodes.iter().map(|n| (n.id, n.label.as_str())).collect();
+        let communities = groups.into_iter().enumerate().map(|(index, members)| {
+            let hub = members.iter()
+                .max_by(|a, b| {
+                    let (da, db) = (degree.get(a).copied().unwrap_or(0.0), degree.get(b).copied().unwrap_or(0.0));
+                    da.total_cmp(&db).then(b.cmp(a))
+                })
+                .copied()
+                .unwrap_or_default();
+            Community {
+                id: super_node_id(index),
+                label: labels.get(&hub).copied().unwrap_or_default().to_string(),
+                member_count: members.len(),
+                members,
+            }
+        }).collect();
+        let edges = between.into_iter()
+            .map(|((a, b), (weight, edge_count))| AggregatedEdge { source: super_node_id(a), target: super_node_id(b), weight, edge_count })
+            .collect();
+        Self { generation: graph.generation, communities, edges, member_of }
+    }
+
+    pub fn community(&self, id: u32) -> Option<&Community> {
+        id.checked_sub(SUPER_NODE_ID_BASE).and_then(|index| self.communities.get(index as usize))
+    }
+
+    /// Id of the super-node a node is collapsed into
+    pub fn community_of(&self, node_id: u32) -> Option<u32> {
+        self.member_of.get(&node_id).map(|&index| super_node_id(index))
+    }
+
+    /// One entry per collapsed community with a member in `positions`, at the centroid
+    /// of all its members' last-known positions and moving at their mean velocity, and
+    /// the members of `expanded` communities as they are. `known` is updated from
+    /// `positions` first.
+    pub fn frame(&self, positions: &[(u32, BinaryNodeData)], expanded: &HashSet<u32>, known: &mut MemberPositions) -> Vec<(u32, BinaryNodeData)> {
+        let mut touched = vec![false; self.communities.len()];
+        let mut frame = Vec::new();
+        for (id, data) in positions {
+            let Some(&index) = self.member_of.get(id) else { continue };
+            known.0.insert(*id, *data);
+            if expanded.contains(&super_node_id(index)) {
+                frame.push((*id, *data));
+            } else {
+                touched[index] = true;
+            }
+        }
+        for (index, community) in self.communities.iter().enumerate().filter(|(index, _)| touched[*index]) {
+            let (mut position, mut velocity, mut count) = (Vec3::ZERO, Vec3::ZERO, 0);
+            for data in community.members.iter().filter_map(|id| known.0.get(id)) {
+                position += data.position.as_vec3();
+                velocity += data.velocity.as_vec3();
+                count += 1;
+            }
+            let scale = 1.0 / count as f32;
+            frame.push((super_node_id(index), BinaryNodeData {
+                position: (position * scale).into(),
+                velocity: (velocity * scale).into(),
+                mass: community.member_count.min(u8::MAX as usize) as u8,
+                flags: 1,
+                padding: [0, 0],
+            }));
+        }
+        frame
+    }
+
+    /// The view as a graph of super-nodes and aggregated edges, positioned at the
+    /// centroids of `graph`, for the paginated API
+    pub fn to_graph(&self, graph: &GraphData) -> GraphData {
+        let positions: Vec<(u32, BinaryNodeData)> = graph.nodes.iter().map(|n| (n.id, n.data)).collect();
+        let mut known = MemberPositions::default();
+        let centroids: HashMap<u32, BinaryNodeData> = self.frame(&positions, &HashSet::new(), &mut known).into_iter().collect();
+        let mut aggregated = GraphData::new();
+        aggregated.generation = graph.generation;
+        for community in &self.communities {
+            let mut node = Node::new_with_id(format!("community-{}", community.id - SUPER_NODE_ID_BASE), Some(community.id));
+            node.label = community.label.clone();
+            node.size = Some((community.member_count as f32).sqrt());
+            node.node_type = Some("community".to_string());
+            node.metadata.insert("memberCount".to_string(), community.member_count.to_string());
+            if let Some(centroid) = centroids.get(&community.id) {
+                node.data = *centroid;
+            }
+            aggregated.nodes.push(node);
+        }
+        aggregated.edges = self.edges.iter().map(|edge| {
+            let mut aggregated = Edge::new(edge.source, edge.target, edge.weight);
+            aggregated.edge_type = Some("community".to_string());
+            aggregated
+        }).collect();
+        aggregated
+    }
+
+    /// A community's members and the edges among them, plus each member's edges out to
+    /// other communities summed per community
+    pub fn expansion(&self, graph: &GraphData, id: u32) -> Option<(Vec<Node>, Vec<Edge>)> {
+        let community = self.community(id)?;
+        let members: HashSet<u32> = community.members.iter().copied().collect();
+        let nodes = graph.nodes.iter().filter(|n| members.contains(&n.id)).cloned().collect();
+        let mut edges = Vec::new();
+        let mut outward: BTreeMap<(u32, u32), f32> = BTreeMap::new();
+        for edge in &graph.edges {
+            match (members.contains(&edge.source), members.contains(&edge.target)) {
+                (true, true) => edges.push(edge.clone()),
+                (true, false) => if let Some(other) = self.community_of(edge.target) {
+                    *outward.entry((edge.source, other)).or_default() += edge.weight;
+                },
+                (false, true) => if let Some(other) = self.community_of(edge.source) {
+                    *outward.entry((edge.target, other)).or_default() += edge.weight;
+                },
+                (false, false) => {}
+            }
+        }
+        edges.extend(outward.into_iter().map(|((member, other), weight)| {
+            let mut edge = Edge::new(member, other, weight);
+            edge.edge_type = Some("community".to_string());
+            edge
+        }));
+        Some((nodes, edges))
+    }
+}
+
+fn super_node_id(index: usize) -> u32 {
+    SUPER_NODE_ID_BASE + index as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    // Two triangles, 1-3 and 10-12, joined by two edges; 20 sits alone
+    fn two_triangles() -> GraphData {
+        let mut graph = GraphData::new();
+        for (id, x) in [(1, 0.0), (2, 3.0), (3, 6.0), (10, 100.0), (11, 110.0), (12, 120.0), (20, -50.0)] {
+            let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+            node.label = format!("n{}", id);
+            node.data.position = Vec3Data::new(x, 2.0 * x, 0.0);
+            node.data.velocity = Vec3Data::new(1.0, 0.0, 0.0);
+            graph.nodes.push(node);
+        }
+        for (a, b) in [(1, 2), (2, 3), (1, 3), (10, 11), (11, 12), (10, 12)] {
+            graph.edges.push(Edge::new(a, b, 5.0));
+        }
+        graph.edges.push(Edge::new(3, 10, 0.5));
+        graph.edges.push(Edge::new(12, 2, 1.5));
+        graph.generation = 7;
+        graph
+    }
+
+    #[test]
+    fn test_edges_between_communities_are_summed_and_internal_ones_dropped() {
+        let graph = two_triangles();
+        let view = GraphView::aggregate(&graph);
+        let members: Vec<&[u32]> = view.communities.iter().map(|c| c.members.as_slice()).collect();
+        assert_eq!(members, [&[1, 2, 3][..], &[10, 11, 12], &[20]]);
+        let (a, b) = (view.communities[0].id, view.communities[1].id);
+        assert_eq!(view.edges, vec![AggregatedEdge { source: a, target: b, weight: 2.0, edge_count: 2 }]);
+        assert_eq!(view.generation, 7);
+        assert_eq!(view.community_of(11), Some(b));
+
+        // The paginated graph carries the same edge; expanding shows a community's own
+        // edges and its members' summed links out
+        let aggregated = view.to_graph(&graph);
+        assert_eq!(aggregated.nodes.len(), 3);
+        assert_eq!((aggregated.edges[0].source, aggregated.edges[0].target, aggregated.edges[0].weight), (a, b, 2.0));
+        let (nodes, edges) = view.expansion(&graph, a).unwrap();
+        assert_eq!(nodes.iter().map(|n| n.id).collect::<Vec<_>>(), [1, 2, 3]);
+        let outward: Vec<(u32, u32, f32)> = edges.iter().filter(|e| e.target == b).map(|e| (e.source, e.target, e.weight)).collect();
+        assert_eq!(outward, [(2, b, 1.5), (3, b, 0.5)]);
+        assert_eq!(edges.len(), 5);
+    }
+
+    #[test]
+    fn test_super_nodes_sit_at_their_members_centroid() {
+        let graph = two_triangles();
+        let view = GraphView::aggregate(&graph);
+        let positions: Vec<(u32, BinaryNodeData)> = graph.nodes.iter().map(|n| (n.id, n.data)).collect();
+        let (a, b) = (view.communities[0].id, view.communities[1].id);
+
+        let mut known = MemberPositions::default();
+        let frame: HashMap<u32, BinaryNodeData> = view.frame(&positions, &HashSet::new(), &mut known).into_iter().collect();
+        assert_eq!(frame.len(), 3);
+        assert_eq!(frame[&a].position.as_array(), [3.0, 6.0, 0.0]);
+        assert_eq!(frame[&b].position.as_array(), [110.0, 220.0, 0.0]);
+        assert_eq!(frame[&a].velocity.as_array(), [1.0, 0.0, 0.0]);
+        assert_eq!(frame[&a].mass, 3);
+
+        // A delta with just one member moves the centroid by a third of its move; the
+        // others count where they were last seen, and untouched communities aren't sent
+        let mut moved = positions[0];
+        moved.1.position = Vec3Data::new(9.0, 0.0, 0.0);
+        let frame: HashMap<u32, BinaryNodeData> = view.frame(&[moved], &HashSet::new(), &mut known).into_iter().collect();
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame[&a].position.as_array(), [6.0, 6.0, 0.0]);
+
+        // An expanded community sends its members instead; the rest stay collapsed
+        let mut ids: Vec<u32> = view.frame(&positions, &HashSet::from([b]), &mut known).into_iter().map(|(id, _)| id).collect();
+        ids.sort();
+        assert_eq!(ids, [10, 11, 12, a, view.communities[2].id]);
+    }
+
+    #[test]
+    fn test_partial_delta_leaves_the_centroid_in_place() {
+        let graph = two_triangles();
+        let view = GraphView::aggregate(&graph);
+        let a = view.communities[0].id;
+
+        // Seeded on connect, then a delta with only node 1, which hasn't moved; 2 and 3
+        // are pinned and sit the frame out
+        let mut known = MemberPositions::seed(&graph);
+        let delta = [(1, graph.nodes[0].data)];
+        let frame: HashMap<u32, BinaryNodeData> = view.frame(&delta, &HashSet::new(), &mut known).into_iter().collect();
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame[&a].position.as_array(), [3.0, 6.0, 0.0]);
+    }
+}
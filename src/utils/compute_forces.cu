@@ -1,6 +1,13 @@
 #include <cuda_runtime.h>
 
 extern "C" {
+    // Per-node physics flags, matching src/utils/physics_flags.rs
+    #define FLAG_ACTIVE 0x01
+    #define FLAG_FIXED 0x02
+    #define FLAG_NO_REPULSION 0x04
+    #define FLAG_EXTRA_DAMPING 0x08
+    #define EXTRA_DAMPING_RETAIN 0.25f
+
     // Vec3Data struct definition to match Rust's Vec3Data
     struct Vec3Data {
         float x;    // 4 bytes
@@ -24,17 +31,34 @@ extern "C" {
         unsigned char padding[2]; // 2 bytes - matches Rust padding
     };
 
+    // Parameters for one simulation phase, matching Rust's PhaseProfile.
+    // The table is uploaded once; each launch only passes the phase index.
+    struct PhaseProfile {
+        float spring_k;
+        float damping;
+        float repel_k;
+        float dt;
+        float max_repulsion_dist;
+        float viewport_bounds;   // 0 disables the bounds
+        float boundary_damping;  // Velocity kept when pushed back at the bounds
+    };
+
     __global__ void compute_forces_kernel(
         BinaryNodeData* nodes,
         int num_nodes,
-        float spring_k,
-        float damping,
-        float repel_k,
-        float dt,
-        float max_repulsion_dist,
-        float viewport_bounds,
+        const PhaseProfile* profiles,
+        int phase,
         int iteration_count
     ) {
+        const PhaseProfile profile = profiles[phase];
+        const float spring_k = profile.spring_k;
+        float damping = profile.damping;
+        const float repel_k = profile.repel_k;
+        const float dt = profile.dt;
+        const float max_repulsion_dist = profile.max_repulsion_dist;
+        const float viewport_bounds = profile.viewport_bounds;
+        const float boundary_damping = profile.boundary_damping;
+
         int idx = blockIdx.x * blockDim.x + threadIdx.x;
         if (idx >= num_nodes) return;
 
@@ -72,16 +96,24 @@ extern "C" {
             mass = (nodes[idx].mass + 1.0f) / 256.0f; // Add 1 to avoid zero mass
         }
 
-        bool is_active = true; // All nodes are active by default
+        const unsigned char flags = nodes[idx].flags;
+        if (!(flags & FLAG_ACTIVE)) return; // Inactive nodes are left exactly as they are
 
-        if (!is_active) return; // Skip inactive nodes
+        // Fixed nodes hold still but still act on everyone else
+        if (flags & FLAG_FIXED) {
+            nodes[idx].velocity.x = 0.0f;
+            nodes[idx].velocity.y = 0.0f;
+            nodes[idx].velocity.z = 0.0f;
+            return;
+        }
 
         // Process all node interactions
         for (int j = 0; j < num_nodes; j++) {
             if (j == idx) continue;
 
-            // All nodes are considered active by default
-            // We no longer check the flags since all nodes are treated as active
+            // Inactive nodes exert nothing
+            if (!(nodes[j].flags & FLAG_ACTIVE)) continue;
+            const bool repels = !((flags | nodes[j].flags) & FLAG_NO_REPULSION);
 
             // Handle other node's mass the same way
             float other_mass = (nodes[j].mass == 0) ? 0.5f : (nodes[j].mass + 1.0f) / 256.0f;
@@ -126,7 +158,7 @@ extern "C" {
                     float force_magnitude = spring_force * spring_scale;
 
                     // Repulsion forces - only apply at close distances
-                    if (dist < max_repulsion_dist) {
+                    if (repels && dist < max_repulsion_dist) {
                         float repel_scale = repel_k * mass * other_mass;
                         // Apply the ramp_up_factor to gradually increase repulsion forces
                         float dist_sq = fmaxf(dist * dist, MIN_DISTANCE);
@@ -179,6 +211,12 @@ extern "C" {
         vel.y = vel.y * (1.0f - damping) + fminf(MAX_FORCE, fmaxf(-MAX_FORCE, total_force.y)) * dt;
         vel.z = vel.z * (1.0f - damping) + fminf(MAX_FORCE, fmaxf(-MAX_FORCE, total_force.z)) * dt;
 
+        if (flags & FLAG_EXTRA_DAMPING) {
+            vel.x *= EXTRA_DAMPING_RETAIN;
+            vel.y *= EXTRA_DAMPING_RETAIN;
+            vel.z *= EXTRA_DAMPING_RETAIN;
+        }
+
         // Apply STRICT velocity cap to prevent runaway momentum
         float vel_magnitude = sqrtf(vel.x*vel.x + vel.y*vel.y + vel.z*vel.z);
         if (vel_magnitude > MAX_VELOCITY) {
@@ -202,15 +240,15 @@ extern "C" {
             if (fabsf(pos.x) > bound_with_margin) {
                 pos.x *= 0.92f; // Pull back by 8%
                 // Also add dampening to velocity in this direction
-                vel.x *= 0.85f;
+                vel.x *= boundary_damping;
             }
             if (fabsf(pos.y) > bound_with_margin) {
                 pos.y *= 0.92f; // Pull back by 8%
-                vel.y *= 0.85f;
+                vel.y *= boundary_damping;
             }
             if (fabsf(pos.z) > bound_with_margin) {
                 pos.z *= 0.92f; // Pull back by 8%
-                vel.z *= 0.85f;
+                vel.z *= boundary_damping;
             }
         }
 
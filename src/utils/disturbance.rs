@@ -0,0 +1,276 @@
+//! Calming the layout after something outside physics moves it. A dragged node or a new
+//! one carries energy physics never gave it, and the springs pass it on until the whole
+//! graph wobbles for seconds. A move past the threshold starts a ramp of extra damping
+//! that eases back to nothing, so the disturbance settles quickly without leaving
+//! physics deadened.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::config::{DisturbanceScope, DisturbanceSettings};
+use crate::models::edge::Edge;
+use crate::models::simulation_params::SimulationPhase;
+use crate::types::vec3::Vec3Data;
+use crate::utils::placement;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DisturbanceStatus {
+    pub enabled: bool,
+    pub active: bool,
+    pub scope: DisturbanceScope,
+    // The phase physics runs in; a disturbance while finalizing goes back to dynamic
+    pub phase: SimulationPhase,
+    // Extra damping on the next step; 0 while no ramp runs
+    pub damping: f32,
+    pub remaining_secs: f32,
+    // Nodes a neighbourhood ramp covers
+    pub damped_nodes: usize,
+    pub disturbances: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_displacement: Option<f32>,
+}
+
+pub struct DisturbanceRamp {
+    settings: DisturbanceSettings,
+    started: Option<Instant>,
+    // The disturbed nodes and their neighbours, in neighbourhood scope
+    nodes: HashSet<u32>,
+    disturbances: u64,
+    last_displacement: Option<f32>,
+}
+
+impl DisturbanceRamp {
+    pub fn new(settings: DisturbanceSettings) -> Self {
+        Self { settings, started: None, nodes: HashSet::new(), disturbances: 0, last_displacement: None }
+    }
+
+    /// Ends any ramp running under the old settings
+    pub fn set_settings(&mut self, settings: DisturbanceSettings) {
+        let (disturbances, last_displacement) = (self.disturbances, self.last_displacement);
+        *self = Self { disturbances, last_displacement, ..Self::new(settings) };
+    }
+
+    /// Starts the ramp over when any node moved at least the threshold. True if it did.
+    pub fn record_moves(&mut self, moves: impl IntoIterator<Item = (u32, f32)>, edges: &[Edge], now: Instant) -> bool {
+        if !self.settings.enabled {
+            return false;
+        }
+        let threshold = self.settings.displacement_threshold;
+        let moved: Vec<(u32, f32)> = moves.into_iter()
+            .filter(|(_, displacement)| displacement.is_finite() && *displacement >= threshold)
+            .collect();
+        let Some(largest) = moved.iter().map(|(_, displacement)| *displacement).reduce(f32::max) else {
+            return false;
+        };
+        self.last_displacement = Some(largest);
+        self.disturb(moved.into_iter().map(|(id, _)| id), edges, now)
+    }
+
+    /// Edges joining or leaving physics change the forces on their ends without moving
+    /// anything, so like insertions they always count
+    pub fn record_rewiring(&mut self, ids: impl IntoIterator<Item = u32>, edges: &[Edge], now: Instant) -> bool {
+        if !self.settings.enabled {
+            return false;
+        }
+        self.disturb(ids, edges, now)
+    }
+
+    /// New nodes pull on their neighbours however close they're placed, so they always count
+    pub fn record_insertions(&mut self, ids: impl IntoIterator<Item = u32>, edges: &[Edge], now: Instant) -> bool {
+        if !self.settings.enabled {
+            return false;
+        }
+        self.disturb(ids, edges, now)
+    }
+
+    fn disturb(&mut self, ids: impl IntoIterator<Item = u32>, edges: &[Edge], now: Instant) -> bool {
+        let ids: HashSet<u32> = ids.into_iter().collect();
+        if ids.is_empty() {
+            return false;
+        }
+        // A fresh ramp forgets what the last one covered; one still running grows
+        if self.damping(now).is_none() {
+            self.nodes.clear();
+        }
+        if self.settings.scope == DisturbanceScope::Neighbourhood {
+            for edge in edges.iter().filter(|e| ids.contains(&e.source) || ids.contains(&e.target)) {
+                self.nodes.extend([edge.source, edge.target]);
+            }
+            self.nodes.extend(ids);
+        }
+        self.started = Some(now);
+        self.disturbances += 1;
+        true
+    }
+
+    /// Ends any running ramp; a reheat wants the motion it adds
+    pub fn end(&mut self) {
+        self.started = None;
+        self.nodes.clear();
+    }
+
+    /// Extra damping for a step at `now`, falling linearly from the peak to nothing
+    pub fn damping(&self, now: Instant) -> Option<f32> {
+        let started = self.started.filter(|_| self.settings.enabled)?;
+        let ramp = self.settings.ramp_secs;
+        let elapsed = now.saturating_duration_since(started).as_secs_f32();
+        if elapsed >= ramp {
+            return None;
+        }
+        Some(self.settings.peak_damping.clamp(0.0, 1.0) * (1.0 - elapsed / ramp))
+    }
+
+    /// Damps a physics step's moves while a ramp runs; `before` is where each node was
+    /// ahead of the step
+    pub fn damp(&mut self, positions: &mut [(u32, BinaryNodeData)], before: impl Fn(u32) -> Option<Vec3Data>, now: Instant) {
+        let Some(damping) = self.damping(now) else {
+            self.started = None;
+            self.nodes.clear();
+            return;
+        };
+        let global = self.settings.scope == DisturbanceScope::Global;
+        for (node_id, data) in positions.iter_mut() {
+            if let (true, Some(position)) = (global || self.nodes.contains(node_id), before(*node_id)) {
+                placement::damp_step(position, data, damping);
+            }
+        }
+    }
+
+    pub fn status(&self, now: Instant, phase: SimulationPhase) -> DisturbanceStatus {
+        let damping = self.damping(now);
+        let remaining_secs = match (damping, self.started) {
+            (Some(_), Some(started)) => self.settings.ramp_secs - now.saturating_duration_since(started).as_secs_f32(),
+            _ => 0.0,
+        };
+        DisturbanceStatus {
+            enabled: self.settings.enabled,
+            active: damping.is_some(),
+            scope: self.settings.scope,
+            phase,
+            damping: damping.unwrap_or(0.0),
+            remaining_secs,
+            damped_nodes: if damping.is_some() { self.nodes.len() } else { 0 },
+            disturbances: self.disturbances,
+            last_displacement: self.last_displacement,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::graph::GraphData;
+    use crate::models::node::Node;
+    use crate::models::simulation_params::SimulationParams;
+    use crate::services::graph_service::GraphService;
+    use glam::Vec3;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    // The lightly damped physics data/settings.yaml ships with, which wobbles for a while
+    fn shipped_params() -> SimulationParams {
+        SimulationParams { damping: 0.95, spring_strength: 0.2, ..SimulationParams::new() }
+    }
+
+    // A chain of nodes along x, settled until it barely moves
+    fn settled_chain() -> GraphData {
+        let mut graph = GraphData::new();
+        for i in 0..8u32 {
+            let mut node = Node::new_with_id(format!("n{}", i), Some(i + 1));
+            node.set_file_size(1000);
+            node.data.position = Vec3Data::new(i as f32 * 5.0, 0.0, 0.0);
+            graph.nodes.push(node);
+        }
+        for i in 1..8u32 {
+            graph.edges.push(Edge::new(i, i + 1, 1.0));
+        }
+        let params = shipped_params();
+        for _ in 0..2000 {
+            GraphService::calculate_layout_cpu(&mut graph, &mut HashMap::new(), &params).unwrap();
+        }
+        graph
+    }
+
+    // How far node 2, the dragged node's neighbour, moves on each of `steps` frames after
+    // node 1 is dragged away
+    fn neighbour_motion(settings: DisturbanceSettings, steps: usize) -> Vec<f32> {
+        let mut graph = settled_chain();
+        let start = Instant::now();
+        let mut ramp = DisturbanceRamp::new(settings);
+        graph.nodes[0].data.position.y += 30.0;
+        ramp.record_moves([(1, 30.0)], &graph.edges, start);
+
+        let params = shipped_params();
+        let mut motion = Vec::new();
+        for step in 0..steps {
+            let before: HashMap<u32, Vec3Data> = graph.nodes.iter().map(|n| (n.id, n.data.position)).collect();
+            GraphService::calculate_layout_cpu(&mut graph, &mut HashMap::new(), &params).unwrap();
+            let mut positions: Vec<(u32, BinaryNodeData)> = graph.nodes.iter().map(|n| (n.id, n.data)).collect();
+            ramp.damp(&mut positions, |id| before.get(&id).copied(), start + FRAME * step as u32);
+            for (node, (_, data)) in graph.nodes.iter_mut().zip(positions) {
+                node.data = data;
+            }
+            motion.push(Vec3::from(graph.nodes[1].data.position).distance(before[&2].into()));
+        }
+        motion
+    }
+
+    #[test]
+    fn test_ramp_settles_a_drag_faster() {
+        let without = neighbour_motion(DisturbanceSettings { enabled: false, ..Default::default() }, 400);
+        let with = neighbour_motion(DisturbanceSettings::default(), 400);
+        let peak = without.iter().copied().fold(0.0, f32::max);
+        // The last frame the neighbour still moves more than 1% of its largest undamped step
+        let settled = |motion: &[f32]| motion.iter().rposition(|d| *d > peak * 0.01).unwrap();
+        assert!(settled(&with) * 3 < settled(&without) * 2, "settled after {} frames, {} without", settled(&with), settled(&without));
+        assert!(with.iter().copied().fold(0.0, f32::max) < peak / 2.0);
+        assert!(with.iter().sum::<f32>() * 2.0 < without.iter().sum::<f32>());
+    }
+
+    #[test]
+    fn test_ramp_eases_out_and_reports_status() {
+        let settings = DisturbanceSettings { scope: DisturbanceScope::Neighbourhood, ..Default::default() };
+        let mut ramp = DisturbanceRamp::new(settings);
+        let edges = [Edge::new(1, 2, 1.0), Edge::new(2, 3, 1.0), Edge::new(4, 5, 1.0)];
+        let start = Instant::now();
+
+        // Small moves don't count
+        assert!(!ramp.record_moves([(1, 0.5)], &edges, start));
+        assert!(!ramp.status(start, SimulationPhase::Dynamic).active);
+
+        assert!(ramp.record_moves([(1, 0.5), (2, 4.0)], &edges, start));
+        let status = ramp.status(start + Duration::from_secs(1), SimulationPhase::Dynamic);
+        assert!(status.active);
+        assert!((status.damping - 0.15).abs() < 1e-5);
+        assert!((status.remaining_secs - 1.0).abs() < 1e-5);
+        assert_eq!((status.damped_nodes, status.last_displacement), (3, Some(4.0)));
+
+        // Only the neighbourhood is damped
+        let moved = |x: f32| BinaryNodeData { position: Vec3Data::new(x, 0.0, 0.0), ..Node::new_with_id("n".into(), Some(9)).data };
+        let mut positions = vec![(3, moved(10.0)), (4, moved(10.0))];
+        ramp.damp(&mut positions, |_| Some(Vec3Data::zero()), start + Duration::from_secs(1));
+        assert!((positions[0].1.position.x - 8.5).abs() < 1e-4);
+        assert_eq!(positions[1].1.position.x, 10.0);
+
+        // Then it runs out and physics is back to normal
+        let done = start + Duration::from_secs(2);
+        assert_eq!(ramp.damping(done), None);
+        ramp.damp(&mut positions, |_| Some(Vec3Data::zero()), done);
+        assert_eq!(ramp.status(done, SimulationPhase::Dynamic), DisturbanceStatus {
+            enabled: true,
+            active: false,
+            scope: DisturbanceScope::Neighbourhood,
+            phase: SimulationPhase::Dynamic,
+            damping: 0.0,
+            remaining_secs: 0.0,
+            damped_nodes: 0,
+            disturbances: 1,
+            last_displacement: Some(4.0),
+        });
+    }
+}
@@ -0,0 +1,290 @@
+//! Force-directed edge bundling (Holten & van Wijk, 2009) over the current layout.
+//!
+//! Each edge is cut into control points that are pulled towards the matching points
+//! of compatible edges (similar direction, length, position and mutual visibility)
+//! and held in line by springs. Every cycle doubles the subdivision and halves the
+//! step, so bundles form coarse first and are refined after.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::models::graph::GraphData;
+
+// Compatibility is computed pairwise, so this bounds the work quadratically
+pub const MAX_BUNDLE_EDGES: usize = 2000;
+pub const MAX_ITERATIONS: usize = 200;
+// Subdivision goes 1, 3, 7, 15 control points
+const CYCLES: usize = 4;
+// First-cycle step as a fraction of the mean edge length
+const INITIAL_STEP_FRACTION: f32 = 0.04;
+const SPRING_CONSTANT: f32 = 0.1;
+const EPSILON: f32 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BundleParams {
+    // Iterations in the first cycle; later cycles run two thirds of the previous one
+    pub iterations: usize,
+    // Edge pairs less compatible than this (0..1) don't attract each other
+    pub compatibility_threshold: f32,
+}
+
+impl Default for BundleParams {
+    fn default() -> Self {
+        Self { iterations: 50, compatibility_threshold: 0.6 }
+    }
+}
+
+impl BundleParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.iterations == 0 || self.iterations > MAX_ITERATIONS {
+            return Err(format!("iterations must be between 1 and {}", MAX_ITERATIONS));
+        }
+        if !(0.0..=1.0).contains(&self.compatibility_threshold) {
+            return Err("compatibilityThreshold must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleSegment {
+    pub edge_id: String,
+    pub source: Vec3,
+    pub target: Vec3,
+}
+
+/// Polyline for one edge, endpoints included
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeBundle {
+    pub edge_id: String,
+    pub points: Vec<[f32; 3]>,
+}
+
+/// Edge segments for the current layout. Edges to missing nodes are left out.
+pub fn segments_from_graph(graph: &GraphData) -> Vec<BundleSegment> {
+    let positions = node_positions(graph);
+    graph.edges.iter()
+        .filter_map(|e| {
+            let (source, target) = (positions.get(&e.source)?, positions.get(&e.target)?);
+            Some(BundleSegment { edge_id: e.id.clone(), source: *source, target: *target })
+        })
+        .collect()
+}
+
+pub fn node_positions(graph: &GraphData) -> HashMap<u32, Vec3> {
+    graph.nodes.iter().map(|n| (n.id, Vec3::from(n.data.position))).collect()
+}
+
+/// Whether any node has moved more than `tolerance` times the layout's extent
+pub fn layout_moved(before: &HashMap<u32, Vec3>, after: &HashMap<u32, Vec3>, tolerance: f32) -> bool {
+    let (min, max) = after.values().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    );
+    let extent = if after.is_empty() { 0.0 } else { min.distance(max) };
+    let limit = (extent * tolerance).max(EPSILON);
+    after.iter().any(|(id, p)| before.get(id).is_none_or(|old| old.distance(*p) > limit))
+}
+
+/// Bundles the segments. `progress` is called after each cycle with (done, total);
+/// `cancelled` is checked between iterations and stops the work early.
+pub fn compute_bundles(
+    segments: &[BundleSegment],
+    params: BundleParams,
+    progress: &mut dyn FnMut(usize, usize),
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Vec<EdgeBundle>, String> {
+    params.validate()?;
+    if segments.len() > MAX_BUNDLE_EDGES {
+        return Err(format!(
+            "Graph has {} edges; edge bundling is limited to {}", segments.len(), MAX_BUNDLE_EDGES));
+    }
+
+    let compatible = compatibility_lists(segments, params.compatibility_threshold);
+    let lengths: Vec<f32> = segments.iter().map(|s| s.source.distance(s.target)).collect();
+    let mean_length = if lengths.is_empty() { 0.0 } else { lengths.iter().sum::<f32>() / lengths.len() as f32 };
+
+    let mut points: Vec<Vec<Vec3>> = segments.iter()
+        .map(|s| vec![s.source, (s.source + s.target) * 0.5, s.target])
+        .collect();
+    let mut step = INITIAL_STEP_FRACTION * mean_length;
+    let mut iterations = params.iterations;
+
+    for cycle in 0..CYCLES {
+        for _ in 0..iterations {
+            if cancelled() {
+                return Err("Edge bundling cancelled".to_string());
+            }
+            points = relax(&points, &compatible, &lengths, step);
+        }
+        progress(cycle + 1, CYCLES);
+        if cycle + 1 < CYCLES {
+            points = points.iter().map(|p| subdivide(p)).collect();
+            step *= 0.5;
+            iterations = (iterations * 2 / 3).max(1);
+        }
+    }
+
+    Ok(segments.iter()
+        .zip(points)
+        .map(|(s, p)| EdgeBundle { edge_id: s.edge_id.clone(), points: p.into_iter().map(|v| v.to_array()).collect() })
+        .collect())
+}
+
+// For each edge, the edges it attracts with their compatibility and whether they run
+// the opposite way (so control points are matched back to front)
+fn compatibility_lists(segments: &[BundleSegment], threshold: f32) -> Vec<Vec<(usize, f32, bool)>> {
+    let mut lists = vec![Vec::new(); segments.len()];
+    for i in 0..segments.len() {
+        for j in (i + 1)..segments.len() {
+            let c = compatibility(&segments[i], &segments[j]);
+            if c > 0.0 && c >= threshold {
+                let reversed = (segments[i].target - segments[i].source)
+                    .dot(segments[j].target - segments[j].source) < 0.0;
+                lists[i].push((j, c, reversed));
+                lists[j].push((i, c, reversed));
+            }
+        }
+    }
+    lists
+}
+
+fn compatibility(p: &BundleSegment, q: &BundleSegment) -> f32 {
+    let (pv, qv) = (p.target - p.source, q.target - q.source);
+    let (lp, lq) = (pv.length(), qv.length());
+    // Self-loops and collapsed edges have no direction to bundle along
+    if lp < EPSILON || lq < EPSILON {
+        return 0.0;
+    }
+    let angle = (pv.dot(qv) / (lp * lq)).abs();
+    let avg = (lp + lq) * 0.5;
+    let scale = 2.0 / (avg / lp.min(lq) + lp.max(lq) / avg);
+    let (mp, mq) = ((p.source + p.target) * 0.5, (q.source + q.target) * 0.5);
+    let position = avg / (avg + mp.distance(mq));
+    let visibility = visibility(p, q).min(visibility(q, p));
+    angle * scale * position * visibility
+}
+
+// How much of q, projected onto p's line, overlaps p around its midpoint
+fn visibility(p: &BundleSegment, q: &BundleSegment) -> f32 {
+    let project = |point: Vec3| {
+        let dir = p.target - p.source;
+        p.source + dir * ((point - p.source).dot(dir) / dir.length_squared())
+    };
+    let (i0, i1) = (project(q.source), project(q.target));
+    let span = i0.distance(i1);
+    if span < EPSILON {
+        return 0.0;
+    }
+    let mid_i = (i0 + i1) * 0.5;
+    let mid_p = (p.source + p.target) * 0.5;
+    (1.0 - 2.0 * mid_p.distance(mid_i) / span).max(0.0)
+}
+
+fn relax(points: &[Vec<Vec3>], compatible: &[Vec<(usize, f32, bool)>], lengths: &[f32], step: f32) -> Vec<Vec<Vec3>> {
+    points.iter()
+        .enumerate()
+        .map(|(e, pts)| {
+            let last = pts.len() - 1;
+            if lengths[e] < EPSILON {
+                return pts.clone();
+            }
+            let kp = SPRING_CONSTANT / (lengths[e] * last as f32);
+            let mut next = pts.clone();
+            for i in 1..last {
+                let spring = (pts[i - 1] - pts[i] + pts[i + 1] - pts[i]) * kp;
+                let mut electro = Vec3::ZERO;
+                for &(other, c, reversed) in &compatible[e] {
+                    let target = points[other][if reversed { last - i } else { i }];
+                    let d = target - pts[i];
+                    let dist = d.length();
+                    if dist > EPSILON {
+                        // Never pull a point past the halfway mark, or close pairs
+                        // leapfrog each other every iteration
+                        electro += d / dist * c * (dist / (2.0 * step)).min(1.0);
+                    }
+                }
+                next[i] = pts[i] + (spring + electro) * step;
+            }
+            next
+        })
+        .collect()
+}
+
+// Inserts a midpoint between every pair of consecutive points
+fn subdivide(points: &[Vec3]) -> Vec<Vec3> {
+    let mut out = Vec::with_capacity(points.len() * 2 - 1);
+    for pair in points.windows(2) {
+        out.push(pair[0]);
+        out.push((pair[0] + pair[1]) * 0.5);
+    }
+    out.push(points[points.len() - 1]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(id: &str, source: [f32; 3], target: [f32; 3]) -> BundleSegment {
+        BundleSegment { edge_id: id.to_string(), source: Vec3::from(source), target: Vec3::from(target) }
+    }
+
+    // Largest distance of any control point from the straight line between the endpoints
+    fn deviation(bundle: &EdgeBundle) -> f32 {
+        let (a, b) = (Vec3::from(bundle.points[0]), Vec3::from(*bundle.points.last().unwrap()));
+        let dir = (b - a).normalize();
+        bundle.points.iter()
+            .map(|p| {
+                let v = Vec3::from(*p) - a;
+                (v - dir * v.dot(dir)).length()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_parallel_edges_bend_towards_each_other() {
+        let segments = vec![
+            segment("a", [0.0, 0.0, 0.0], [10.0, 0.0, 0.0]),
+            // Runs the other way; bundling is direction-agnostic
+            segment("b", [10.0, 1.0, 0.0], [0.0, 1.0, 0.0]),
+            // Perpendicular and far away, so compatible with neither
+            segment("c", [50.0, -20.0, 0.0], [50.0, 20.0, 0.0]),
+        ];
+        let mut cycles = Vec::new();
+        let bundles = compute_bundles(&segments, BundleParams::default(), &mut |done, total| cycles.push((done, total)), &|| false).unwrap();
+        assert_eq!(cycles.last(), Some(&(CYCLES, CYCLES)));
+
+        let (a, b, c) = (&bundles[0], &bundles[1], &bundles[2]);
+        assert_eq!(a.points.len(), 17);
+        assert!(deviation(a) > 0.1, "a deviates {}", deviation(a));
+        assert!(deviation(b) > 0.1, "b deviates {}", deviation(b));
+        // The middles are pulled together, endpoints stay put
+        let mid = a.points.len() / 2;
+        assert!(a.points[mid][1] > 0.1 && b.points[mid][1] < 0.9);
+        assert_eq!(a.points[0], [0.0, 0.0, 0.0]);
+        assert!(deviation(c) < 1e-4);
+    }
+
+    #[test]
+    fn test_too_many_edges_is_an_error() {
+        let segments: Vec<_> = (0..=MAX_BUNDLE_EDGES)
+            .map(|i| segment(&i.to_string(), [0.0, i as f32, 0.0], [1.0, i as f32, 0.0]))
+            .collect();
+        let err = compute_bundles(&segments, BundleParams::default(), &mut |_, _| {}, &|| false).unwrap_err();
+        assert!(err.contains("limited to"));
+        assert!(BundleParams { iterations: 0, compatibility_threshold: 0.5 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_layout_moved_is_relative_to_extent() {
+        let before: HashMap<u32, Vec3> = [(1, Vec3::ZERO), (2, Vec3::new(100.0, 0.0, 0.0))].into_iter().collect();
+        let mut after = before.clone();
+        after.insert(1, Vec3::new(0.5, 0.0, 0.0));
+        assert!(!layout_moved(&before, &after, 0.02));
+        after.insert(1, Vec3::new(5.0, 0.0, 0.0));
+        assert!(layout_moved(&before, &after, 0.02));
+    }
+}
@@ -1,4 +1,5 @@
 use bytemuck::{Pod, Zeroable};
+#[cfg(feature = "gpu")]
 use cudarc::driver::{DeviceRepr, ValidAsZeroBits};
 
 #[repr(C)]
@@ -10,7 +11,9 @@ pub struct EdgeData {
 }
 
 // Implement DeviceRepr for EdgeData
+#[cfg(feature = "gpu")]
 unsafe impl DeviceRepr for EdgeData {}
 
 // Implement ValidAsZeroBits for EdgeData
+#[cfg(feature = "gpu")]
 unsafe impl ValidAsZeroBits for EdgeData {}
\ No newline at end of file
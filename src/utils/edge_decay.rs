@@ -0,0 +1,114 @@
+//! Weight decay for edges built from transient signals. Each pass scales the weight of
+//! every decaying edge by the half-life factor for the time since the last pass, and
+//! removes edges that drop below the floor. The time of the last pass is persisted, so
+//! a restart neither skips nor repeats any decay.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::models::edge::{Edge, WeightSource};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecayState {
+    pub last_decay_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DecayResult {
+    pub updated: Vec<Edge>,
+    pub removed: Vec<Edge>,
+}
+
+/// What's left of a weight after `elapsed_secs` with the given half-life
+pub fn decay_factor(half_life_days: f32, elapsed_secs: f64) -> f32 {
+    if half_life_days <= 0.0 || elapsed_secs <= 0.0 {
+        return 1.0;
+    }
+    let half_lives = elapsed_secs / (half_life_days as f64 * 86_400.0);
+    0.5f64.powf(half_lives) as f32
+}
+
+/// Decays `edges` in place. Returns the edges that were weakened and those that fell
+/// below `floor` and were removed.
+pub fn decay_edges(edges: &mut Vec<Edge>, half_life_days: &HashMap<String, f32>, floor: f32, elapsed_secs: f64) -> DecayResult {
+    let mut result = DecayResult::default();
+    if half_life_days.is_empty() || elapsed_secs <= 0.0 {
+        return result;
+    }
+    let now = Utc::now();
+    let mut kept = Vec::with_capacity(edges.len());
+    for mut edge in edges.drain(..) {
+        let Some(half_life) = half_life_days.get(edge.type_name()) else {
+            kept.push(edge);
+            continue;
+        };
+        let factor = decay_factor(*half_life, elapsed_secs);
+        if factor >= 1.0 {
+            kept.push(edge);
+            continue;
+        }
+        let before = edge.weight;
+        edge.weight *= factor;
+        edge.record(WeightSource::Decay, edge.weight - before, now);
+        if edge.weight < floor {
+            result.removed.push(edge);
+            continue;
+        }
+        result.updated.push(edge.clone());
+        kept.push(edge);
+    }
+    *edges = kept;
+    result
+}
+
+/// Re-creating a decaying edge adds to what's left of it instead of resetting it
+pub fn reinforce(existing: &mut Edge, edge: &Edge) {
+    existing.weight += edge.weight;
+    existing.absorb_provenance(edge);
+    if edge.metadata.is_some() {
+        existing.metadata = edge.metadata.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(source: u32, target: u32, weight: f32, edge_type: &str) -> Edge {
+        let mut edge = Edge::new(source, target, weight);
+        edge.edge_type = Some(edge_type.to_string());
+        edge
+    }
+
+    #[test]
+    fn test_decay_halves_per_half_life_and_drops_below_floor() {
+        let day = 86_400.0;
+        assert_eq!(decay_factor(2.0, 2.0 * day), 0.5);
+        assert!((decay_factor(2.0, 6.0 * day) - 0.125).abs() < 1e-6);
+
+        let policies = HashMap::from([("spoken".to_string(), 1.0)]);
+        let mut edges = vec![
+            typed(1, 2, 1.0, "spoken"),
+            typed(2, 3, 0.15, "spoken"),
+            Edge::new(3, 4, 1.0),
+        ];
+        let result = decay_edges(&mut edges, &policies, 0.1, day);
+        assert_eq!(result.removed.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["2-3"]);
+        assert_eq!(result.updated.len(), 1);
+        assert_eq!(result.updated[0].weight, 0.5);
+        // Topic edges have no policy and are left alone
+        assert_eq!(edges.iter().map(|e| e.weight).collect::<Vec<_>>(), vec![0.5, 1.0]);
+
+        // Two half-day passes come to the same as one full day
+        let mut split = vec![typed(1, 2, 1.0, "spoken")];
+        decay_edges(&mut split, &policies, 0.0, day / 2.0);
+        decay_edges(&mut split, &policies, 0.0, day / 2.0);
+        assert!((split[0].weight - 0.5).abs() < 1e-6);
+
+        let mut existing = edges[0].clone();
+        reinforce(&mut existing, &typed(1, 2, 1.0, "spoken"));
+        assert_eq!(existing.weight, 1.5);
+    }
+}
@@ -0,0 +1,76 @@
+//! Edge types a client has switched off. Visibility only changes what a client is sent;
+//! physics keeps every edge unless its type is disabled for physics in settings or the
+//! edge itself is visual-only.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::edge::{Edge, TOPIC_EDGE_TYPE};
+
+/// Applies `changes` (type -> visible) to a client's hidden set. Returns the types that
+/// were hidden and are now shown, whose edges the client no longer has.
+pub fn apply_changes(hidden: &mut HashSet<String>, changes: HashMap<String, bool>) -> Vec<String> {
+    let mut shown = Vec::new();
+    for (edge_type, visible) in changes {
+        if visible {
+            if hidden.remove(&edge_type) {
+                shown.push(edge_type);
+            }
+        } else {
+            hidden.insert(edge_type);
+        }
+    }
+    shown.sort();
+    shown
+}
+
+/// A text event with edges of hidden types taken out of `addedEdges` and `updatedEdges`.
+/// Anything that isn't a JSON event carrying edges is passed through untouched.
+pub fn filter_event(message: &str, hidden: &HashSet<String>) -> String {
+    if hidden.is_empty() || !(message.contains("\"addedEdges\"") || message.contains("\"updatedEdges\"")) {
+        return message.to_string();
+    }
+    let Ok(mut event) = serde_json::from_str::<serde_json::Value>(message) else {
+        return message.to_string();
+    };
+    for key in ["addedEdges", "updatedEdges"] {
+        if let Some(edges) = event.get_mut(key).and_then(|e| e.as_array_mut()) {
+            edges.retain(|edge| {
+                let edge_type = edge.get("edgeType").and_then(|t| t.as_str()).unwrap_or(TOPIC_EDGE_TYPE);
+                !hidden.contains(edge_type)
+            });
+        }
+    }
+    event.to_string()
+}
+
+/// Edges that affect physics and whose type isn't disabled for it
+pub fn physics_edges(edges: &[Edge], disabled: &HashSet<String>) -> Vec<Edge> {
+    edges.iter().filter(|e| e.affects_physics && !disabled.contains(e.type_name())).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hiding_and_reshowing_types() {
+        let mut hidden = HashSet::new();
+        let changes = HashMap::from([("similarity".to_string(), false), ("spoken".to_string(), false)]);
+        assert!(apply_changes(&mut hidden, changes).is_empty());
+        // Showing a type that was never hidden needs no resend
+        let changes = HashMap::from([("similarity".to_string(), true), ("topic".to_string(), true)]);
+        assert_eq!(apply_changes(&mut hidden, changes), vec!["similarity"]);
+        assert_eq!(hidden, HashSet::from(["spoken".to_string()]));
+
+        let mut spoken = Edge::new(1, 3, 1.0);
+        spoken.edge_type = Some("spoken".to_string());
+        let event = serde_json::json!({
+            "type": "graph_diff",
+            "addedEdges": [Edge::new(1, 2, 1.0), spoken],
+        }).to_string();
+        let filtered: serde_json::Value = serde_json::from_str(&filter_event(&event, &hidden)).unwrap();
+        assert_eq!(filtered["addedEdges"].as_array().unwrap().len(), 1);
+        assert_eq!(filtered["addedEdges"][0]["id"], "1-2");
+        assert_eq!(filter_event("{\"type\":\"pose\"}", &hidden), "{\"type\":\"pose\"}");
+    }
+}
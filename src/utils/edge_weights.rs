@@ -0,0 +1,115 @@
+//! Turns raw shared-topic counts into the edge weights physics uses. The raw count stays
+//! on the edge so exports can show it and the transform can be changed without a rebuild.
+
+use crate::config::{EdgeWeightSettings, EdgeWeightTransform};
+use chrono::Utc;
+
+use crate::models::edge::{Edge, WeightSource};
+
+/// Transformed and clamped weights, in the same order as `raw`
+pub fn transform_weights(raw: &[f32], settings: &EdgeWeightSettings) -> Vec<f32> {
+    let transformed: Vec<f32> = match settings.transform {
+        EdgeWeightTransform::Linear => raw.to_vec(),
+        EdgeWeightTransform::Log => raw.iter().map(|w| w.max(0.0).ln_1p()).collect(),
+        EdgeWeightTransform::Sqrt => raw.iter().map(|w| w.max(0.0).sqrt()).collect(),
+        EdgeWeightTransform::Rank => rank(raw),
+    };
+    let (min, max) = (settings.min_weight, settings.max_weight.max(settings.min_weight));
+    transformed.into_iter().map(|w| w.clamp(min, max)).collect()
+}
+
+// Percentile among the distinct values, so equal counts get equal weights and the
+// largest count maps to 1.0
+fn rank(raw: &[f32]) -> Vec<f32> {
+    let mut distinct: Vec<f32> = raw.iter().copied().filter(|w| w.is_finite()).collect();
+    distinct.sort_by(|a, b| a.total_cmp(b));
+    distinct.dedup();
+    let n = distinct.len() as f32;
+    raw.iter()
+        .map(|w| match distinct.binary_search_by(|d| d.total_cmp(w)) {
+            Ok(i) => (i + 1) as f32 / n,
+            Err(_) => 0.0,
+        })
+        .collect()
+}
+
+/// Re-derives the weight of every edge that carries a raw count; edges without one
+/// (similarity, agent-created) keep their weight. Returns how many changed.
+pub fn reweight_edges(edges: &mut [Edge], settings: &EdgeWeightSettings) -> usize {
+    let indices: Vec<usize> = edges.iter()
+        .enumerate()
+        .filter_map(|(i, e)| e.raw_weight.map(|_| i))
+        .collect();
+    let raw: Vec<f32> = indices.iter().filter_map(|&i| edges[i].raw_weight).collect();
+    let weights = transform_weights(&raw, settings);
+    let mut changed = 0;
+    let now = Utc::now();
+    for (&i, weight) in indices.iter().zip(weights) {
+        if edges[i].weight != weight {
+            let delta = weight - edges[i].weight;
+            edges[i].weight = weight;
+            edges[i].record(WeightSource::Transform, delta, now);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTS: [f32; 5] = [1.0, 3.0, 3.0, 8.0, 99.0];
+
+    fn settings(transform: EdgeWeightTransform) -> EdgeWeightSettings {
+        EdgeWeightSettings { transform, ..Default::default() }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_transforms_on_known_counts() {
+        assert_close(&transform_weights(&COUNTS, &settings(EdgeWeightTransform::Linear)), &COUNTS);
+        assert_close(
+            &transform_weights(&COUNTS, &settings(EdgeWeightTransform::Log)),
+            &[2f32.ln(), 4f32.ln(), 4f32.ln(), 9f32.ln(), 100f32.ln()],
+        );
+        assert_close(
+            &transform_weights(&COUNTS, &settings(EdgeWeightTransform::Sqrt)),
+            &[1.0, 3f32.sqrt(), 3f32.sqrt(), 8f32.sqrt(), 99f32.sqrt()],
+        );
+        // Four distinct counts, ties share a rank
+        assert_close(
+            &transform_weights(&COUNTS, &settings(EdgeWeightTransform::Rank)),
+            &[0.25, 0.5, 0.5, 0.75, 1.0],
+        );
+    }
+
+    #[test]
+    fn test_clamp_applies_after_transform() {
+        let clamped = EdgeWeightSettings { transform: EdgeWeightTransform::Sqrt, min_weight: 1.5, max_weight: 5.0 };
+        assert_close(&transform_weights(&COUNTS, &clamped), &[1.5, 3f32.sqrt(), 3f32.sqrt(), 8f32.sqrt(), 5.0]);
+    }
+
+    #[test]
+    fn test_reweight_keeps_raw_and_skips_derived_edges() {
+        let mut counted = Edge::new(1, 2, 16.0);
+        counted.raw_weight = Some(16.0);
+        let similarity = Edge::new(2, 3, 0.8);
+        let mut edges = vec![counted, similarity];
+
+        assert_eq!(reweight_edges(&mut edges, &settings(EdgeWeightTransform::Sqrt)), 1);
+        assert_eq!(edges[0].weight, 4.0);
+        assert_eq!(edges[0].raw_weight, Some(16.0));
+        assert_eq!(edges[1].weight, 0.8);
+
+        // Switching back restores the original count
+        reweight_edges(&mut edges, &settings(EdgeWeightTransform::Linear));
+        assert_eq!(edges[0].weight, 16.0);
+    }
+}
@@ -0,0 +1,156 @@
+//! Per-client accounting of position frames: how many nodes each frame carried and why
+//! the others were left out. Sockets keep a short history of their own frames and
+//! report totals to the client manager every `REPORT_INTERVAL_FRAMES`, along with a
+//! `frame_accounting` message to the client, so clients can tell how stale their
+//! view may be.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+// About once a second at the physics tick rate
+pub const REPORT_INTERVAL_FRAMES: u64 = 60;
+// Frames a socket remembers for the dev-only echo
+pub const MAX_ECHO_FRAMES: usize = 300;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameAccount {
+    pub included: u64,
+    // Moved less than the position epsilon since they were last sent
+    pub skipped_epsilon: u64,
+    // Below the client's level of detail
+    pub skipped_lod: u64,
+    // Left for a later bucket because the client can't take every frame
+    pub skipped_backpressure: u64,
+}
+
+impl FrameAccount {
+    /// A frame that went out whole
+    pub fn whole(nodes: usize) -> Self {
+        Self { included: nodes as u64, ..Default::default() }
+    }
+
+    /// A frame of `total` nodes thinned to `included` by the frame scheduler
+    pub fn thinned(total: usize, included: usize) -> Self {
+        Self {
+            included: included as u64,
+            skipped_backpressure: total.saturating_sub(included) as u64,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, other: &FrameAccount) {
+        self.included += other.included;
+        self.skipped_epsilon += other.skipped_epsilon;
+        self.skipped_lod += other.skipped_lod;
+        self.skipped_backpressure += other.skipped_backpressure;
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped_epsilon + self.skipped_lod + self.skipped_backpressure
+    }
+}
+
+/// Totals over a run of frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameTotals {
+    pub frames: u64,
+    #[serde(flatten)]
+    pub nodes: FrameAccount,
+}
+
+impl FrameTotals {
+    pub fn add(&mut self, other: &FrameTotals) {
+        self.frames += other.frames;
+        self.nodes.add(&other.nodes);
+    }
+}
+
+/// One socket's recent frames and the totals since its last report
+#[derive(Debug, Default)]
+pub struct FrameAccounting {
+    recent: VecDeque<FrameAccount>,
+    pending: FrameTotals,
+}
+
+impl FrameAccounting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a frame; returns the totals to report once a full interval has built up
+    pub fn record(&mut self, account: FrameAccount) -> Option<FrameTotals> {
+        if self.recent.len() == MAX_ECHO_FRAMES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(account);
+        self.pending.add(&FrameTotals { frames: 1, nodes: account });
+        if self.pending.frames < REPORT_INTERVAL_FRAMES {
+            return None;
+        }
+        Some(std::mem::take(&mut self.pending))
+    }
+
+    /// The last `count` frames, oldest first
+    pub fn last(&self, count: usize) -> Vec<FrameAccount> {
+        let skip = self.recent.len().saturating_sub(count);
+        self.recent.iter().skip(skip).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+    use crate::utils::socket_flow_messages::BinaryNodeData;
+    use crate::utils::update_priority::{bucket_count, FrameScheduler, SOURCE_FRAME_RATE};
+
+    fn frame(count: u32) -> Vec<(u32, BinaryNodeData)> {
+        (0..count)
+            .map(|id| (id, BinaryNodeData {
+                position: Vec3Data { x: id as f32, y: 0.0, z: 0.0 },
+                velocity: Vec3Data { x: 0.0, y: 0.0, z: 0.0 },
+                mass: 100,
+                flags: 0,
+                padding: [0, 0],
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn test_accounting_matches_the_schedulers_decisions() {
+        // A client at a quarter of the source rate, with 4 of 100 nodes as priority
+        let mut scheduler = FrameScheduler::new();
+        let mut accounting = FrameAccounting::new();
+        let buckets = bucket_count(SOURCE_FRAME_RATE, SOURCE_FRAME_RATE / 4.0);
+        let priority = [1u32, 2, 3, 5];
+
+        let mut reports = Vec::new();
+        for _ in 0..REPORT_INTERVAL_FRAMES * 2 {
+            let selected = scheduler.select(frame(100), |id| priority.contains(&id), buckets);
+            if let Some(report) = accounting.record(FrameAccount::thinned(100, selected.len())) {
+                reports.push(report);
+            }
+        }
+
+        assert_eq!(reports.len(), 2);
+        for report in &reports {
+            assert_eq!(report.frames, REPORT_INTERVAL_FRAMES);
+            assert_eq!(report.nodes.included + report.nodes.skipped(), 100 * REPORT_INTERVAL_FRAMES);
+            // On average a frame has the priority nodes plus a quarter of the other 96
+            assert_eq!(report.nodes.included, (4 + 24) * REPORT_INTERVAL_FRAMES);
+            assert_eq!((report.nodes.skipped_epsilon, report.nodes.skipped_lod), (0, 0));
+        }
+
+        // Buckets differ by how many priority nodes they already hold
+        let included: Vec<u64> = accounting.last(4).iter().map(|a| a.included).collect();
+        assert_eq!(included, vec![29, 27, 28, 28]);
+        // Only the most recent frames are kept
+        assert_eq!(accounting.last(usize::MAX).len(), (REPORT_INTERVAL_FRAMES * 2) as usize);
+        for _ in 0..MAX_ECHO_FRAMES {
+            accounting.record(FrameAccount::whole(100));
+        }
+        assert_eq!(accounting.last(usize::MAX), vec![FrameAccount::whole(100); MAX_ECHO_FRAMES]);
+    }
+}
@@ -0,0 +1,193 @@
+//! Backing off when physics can't keep up. A step that overruns the frame budget blocks
+//! the actor's thread, and the loop's timer then fires every tick it missed back to back,
+//! so an overloaded loop never yields. Instead, a step that overruns holds physics off for
+//! the next `skip_factor` frames: the factor doubles while steps keep overrunning, up to
+//! the number of frames the step took, and halves once they fit again. Skipped frames
+//! resend the last positions at the usual cadence so clients keep getting frames.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::config::FrameBudgetSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetTick {
+    Run,
+    // Physics is held off; `resend` is set once a frame's time has passed since the last send
+    Skip { resend: bool },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameBudgetStatus {
+    pub enabled: bool,
+    pub budget_ms: f32,
+    // Frames held off after each overrunning step
+    pub skip_factor: u32,
+    pub overloaded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_step_ms: Option<f32>,
+    // Frames skipped since the last step that fit the budget
+    pub consecutive_skips: u64,
+    pub skipped_frames: u64,
+}
+
+pub struct FrameBudget {
+    settings: FrameBudgetSettings,
+    skip_factor: u32,
+    skip_until: Option<Instant>,
+    last_send: Option<Instant>,
+    last_step: Option<Duration>,
+    consecutive_skips: u64,
+    skipped_frames: u64,
+    // Set once the consecutive skips pass the cap, until a step fits again
+    warned: bool,
+}
+
+impl FrameBudget {
+    pub fn new(settings: FrameBudgetSettings) -> Self {
+        Self {
+            settings,
+            skip_factor: 0,
+            skip_until: None,
+            last_send: None,
+            last_step: None,
+            consecutive_skips: 0,
+            skipped_frames: 0,
+            warned: false,
+        }
+    }
+
+    /// Starts over under the new settings
+    pub fn set_settings(&mut self, settings: FrameBudgetSettings) {
+        let skipped_frames = self.skipped_frames;
+        *self = Self::new(settings);
+        self.skipped_frames = skipped_frames;
+    }
+
+    fn budget(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.budget_ms.max(0.1) / 1000.0)
+    }
+
+    /// Whether physics runs on the tick at `now`
+    pub fn tick(&mut self, now: Instant) -> BudgetTick {
+        match self.skip_until {
+            Some(until) if self.settings.enabled && now < until => {
+                let resend = self.last_send.is_none_or(|sent| now.duration_since(sent) >= self.budget());
+                if resend {
+                    self.last_send = Some(now);
+                }
+                BudgetTick::Skip { resend }
+            }
+            _ => BudgetTick::Run,
+        }
+    }
+
+    /// Records a step that took `elapsed` and finished at `now`. True the first time in an
+    /// overload that the consecutive skips pass `max_consecutive_skips`.
+    pub fn record_step(&mut self, elapsed: Duration, now: Instant) -> bool {
+        self.last_step = Some(elapsed);
+        self.last_send = Some(now);
+        if !self.settings.enabled {
+            return false;
+        }
+        let budget = self.budget();
+        // Frames the step ran over by
+        let overrun = ((elapsed.as_secs_f64() / budget.as_secs_f64()).ceil() as u32).saturating_sub(1);
+        if overrun == 0 {
+            self.skip_factor /= 2;
+            self.consecutive_skips = 0;
+            self.warned = false;
+        } else {
+            let target = overrun.min(self.settings.max_skip_factor.max(1));
+            if self.skip_factor < target {
+                self.skip_factor = (self.skip_factor * 2).clamp(1, target);
+            }
+            self.consecutive_skips += self.skip_factor as u64;
+        }
+        self.skipped_frames += self.skip_factor as u64;
+        self.skip_until = (self.skip_factor > 0).then(|| now + budget * self.skip_factor);
+
+        if self.consecutive_skips > self.settings.max_consecutive_skips as u64 && !self.warned {
+            self.warned = true;
+            return true;
+        }
+        false
+    }
+
+    /// Whether recent steps have overrun and physics is still being held off between them
+    pub fn backing_off(&self) -> bool {
+        self.skip_factor > 0
+    }
+
+    pub fn status(&self) -> FrameBudgetStatus {
+        FrameBudgetStatus {
+            enabled: self.settings.enabled,
+            budget_ms: self.settings.budget_ms,
+            skip_factor: self.skip_factor,
+            overloaded: self.consecutive_skips > 0,
+            last_step_ms: self.last_step.map(|step| step.as_secs_f32() * 1000.0),
+            consecutive_skips: self.consecutive_skips,
+            skipped_frames: self.skipped_frames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> FrameBudgetSettings {
+        FrameBudgetSettings { enabled: true, budget_ms: 10.0, max_skip_factor: 8, max_consecutive_skips: 20 }
+    }
+
+    #[test]
+    fn test_skip_factor_converges_and_decays() {
+        let mut budget = FrameBudget::new(settings());
+        let start = Instant::now();
+        let ms = |ms: u64| Duration::from_millis(ms);
+
+        // A 45ms step overruns by four frames; the factor doubles up to that and stays
+        let factors: Vec<u32> = (0..5).map(|i| {
+            budget.record_step(ms(45), start + ms(100 * i));
+            budget.status().skip_factor
+        }).collect();
+        assert_eq!(factors, [1, 2, 4, 4, 4]);
+        // Ticks inside the window skip, resending once per frame; the first tick after it runs
+        let after = start + ms(400);
+        assert_eq!(budget.tick(after), BudgetTick::Skip { resend: false });
+        assert_eq!(budget.tick(after + ms(10)), BudgetTick::Skip { resend: true });
+        assert_eq!(budget.tick(after + ms(12)), BudgetTick::Skip { resend: false });
+        assert_eq!(budget.tick(after + ms(40)), BudgetTick::Run);
+
+        // Far slower steps are capped
+        budget.record_step(ms(500), start + ms(1000));
+        assert_eq!(budget.status().skip_factor, 8);
+
+        // Steps that fit halve it back to nothing
+        let decay: Vec<u32> = (0..4).map(|i| {
+            budget.record_step(ms(5), start + ms(2000 + 100 * i));
+            budget.status().skip_factor
+        }).collect();
+        assert_eq!(decay, [4, 2, 1, 0]);
+        assert!(!budget.status().overloaded);
+        assert_eq!(budget.tick(start + ms(3000)), BudgetTick::Run);
+    }
+
+    #[test]
+    fn test_long_overloads_warn_once() {
+        let mut budget = FrameBudget::new(settings());
+        let now = Instant::now();
+        // 1 + 2 + 4 + 8 + 8 skipped frames passes 20 on the fifth step
+        let warnings: Vec<bool> = (0..8).map(|_| budget.record_step(Duration::from_millis(200), now)).collect();
+        assert_eq!(warnings, [false, false, false, false, true, false, false, false]);
+        budget.record_step(Duration::from_millis(1), now);
+        assert_eq!(budget.status().consecutive_skips, 0);
+        let warnings: Vec<bool> = (0..5).map(|_| budget.record_step(Duration::from_millis(200), now)).collect();
+        assert_eq!(warnings.iter().filter(|w| **w).count(), 1);
+
+        let mut off = FrameBudget::new(FrameBudgetSettings { enabled: false, ..settings() });
+        assert!(!off.record_step(Duration::from_secs(1), now));
+        assert_eq!(off.tick(now), BudgetTick::Run);
+    }
+}
@@ -0,0 +1,92 @@
+//! Content hashes of position frames, so a client that thinks its graph looks wrong can
+//! tell a bad decode from bad data. Clients that ask for frame hashes in their capabilities
+//! handshake get a CRC-32 of each frame's node data in its header, and the client manager
+//! keeps the last `SENT_FRAME_HISTORY` hashes it sent each of them, listed at
+//! `GET /api/clients/{id}/frames`.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, OnceLock};
+
+// About five seconds of frames at the physics tick rate
+pub const SENT_FRAME_HISTORY: usize = 300;
+
+/// CRC-32 (IEEE) of a frame's node data
+pub fn hash(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// A broadcast frame's hash, worked out by the first client that needs it and shared with
+/// every other client sent the same bytes. Nobody asking costs nothing.
+#[derive(Debug, Clone, Default)]
+pub struct SharedHash(Arc<OnceLock<u32>>);
+
+impl SharedHash {
+    pub fn get(&self, data: &[u8]) -> u32 {
+        *self.0.get_or_init(|| hash(data))
+    }
+}
+
+/// One frame as it went to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentFrame {
+    // Counts every frame sent to the client since it asked for hashes
+    pub seq: u64,
+    // Of the node data, after any projection or thinning, as in the frame header
+    pub hash: u32,
+    // The whole binary message, headers included
+    pub bytes: usize,
+    pub nodes: usize,
+    pub keyframe: bool,
+    pub server_time_us: u64,
+}
+
+/// The most recent frames sent to one client, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct SentFrameLog {
+    frames: VecDeque<SentFrame>,
+}
+
+impl SentFrameLog {
+    pub fn record(&mut self, frame: SentFrame) {
+        if self.frames.len() == SENT_FRAME_HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn frames(&self) -> Vec<SentFrame> {
+        self.frames.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_is_stable_and_shared() {
+        // The standard CRC-32 check value, so clients can verify their implementation
+        assert_eq!(hash(b"123456789"), 0xCBF4_3926);
+        let data = vec![7u8; 28 * 1000];
+        let shared = SharedHash::default();
+        let copy = shared.clone();
+        assert_eq!(shared.get(&data), hash(&data));
+        // Once worked out, every holder gets the same answer without hashing again
+        assert_eq!(copy.get(&[]), hash(&data));
+        assert_ne!(hash(&data[1..]), hash(&data));
+    }
+
+    #[test]
+    fn test_log_keeps_the_most_recent_frames() {
+        let mut log = SentFrameLog::default();
+        for seq in 0..(SENT_FRAME_HISTORY as u64 + 20) {
+            log.record(SentFrame { seq, hash: seq as u32, bytes: 28, nodes: 1, keyframe: false, server_time_us: seq });
+        }
+        let frames = log.frames();
+        assert_eq!(frames.len(), SENT_FRAME_HISTORY);
+        assert_eq!(frames[0].seq, 20);
+        assert_eq!(frames.last().unwrap().seq, SENT_FRAME_HISTORY as u64 + 19);
+    }
+}
@@ -0,0 +1,494 @@
+//! glTF 2.0 export of a graph layout snapshot.
+//!
+//! Nodes become instances of a shared unit sphere (one mesh per distinct colour so
+//! each can carry its own material), edges become instances of a unit cylinder
+//! stretched between endpoints. Geometry lives in a single embedded base64 buffer.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use glam::{Quat, Vec3};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use crate::actors::client_manager_actor::client_color;
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::services::external_links::Portal;
+use crate::utils::label_placement::LabelPlacement;
+
+const SPHERE_RINGS: u16 = 8;
+const SPHERE_SEGMENTS: u16 = 12;
+const CYLINDER_SEGMENTS: u16 = 8;
+
+// Node sizes from metadata are roughly 5..50, scaled down to scene units
+pub const NODE_RADIUS_PER_SIZE: f32 = 0.01;
+pub const DEFAULT_NODE_SIZE: f32 = 10.0;
+const EDGE_BASE_RADIUS: f32 = 0.01;
+const DEFAULT_NODE_COLOR: [f32; 3] = [0.4, 0.6, 1.0];
+const EDGE_COLOR: [f32; 4] = [0.7, 0.7, 0.7, 1.0];
+
+// glTF constants
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+struct Geometry {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u16>,
+}
+
+fn unit_sphere() -> Geometry {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    for r in 0..=SPHERE_RINGS {
+        let phi = std::f32::consts::PI * r as f32 / SPHERE_RINGS as f32;
+        for s in 0..=SPHERE_SEGMENTS {
+            let theta = 2.0 * std::f32::consts::PI * s as f32 / SPHERE_SEGMENTS as f32;
+            let p = [phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin()];
+            positions.push(p);
+            normals.push(p);
+        }
+    }
+    let mut indices = Vec::new();
+    let row = SPHERE_SEGMENTS + 1;
+    for r in 0..SPHERE_RINGS {
+        for s in 0..SPHERE_SEGMENTS {
+            let a = r * row + s;
+            let b = a + row;
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+    Geometry { positions, normals, indices }
+}
+
+// Open cylinder of radius 1 along +Y, from y = -0.5 to 0.5
+fn unit_cylinder() -> Geometry {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    for s in 0..=CYLINDER_SEGMENTS {
+        let theta = 2.0 * std::f32::consts::PI * s as f32 / CYLINDER_SEGMENTS as f32;
+        let (x, z) = (theta.cos(), theta.sin());
+        positions.push([x, -0.5, z]);
+        positions.push([x, 0.5, z]);
+        normals.push([x, 0.0, z]);
+        normals.push([x, 0.0, z]);
+    }
+    let mut indices = Vec::new();
+    for s in 0..CYLINDER_SEGMENTS {
+        let a = s * 2;
+        indices.extend_from_slice(&[a, a + 1, a + 2, a + 2, a + 1, a + 3]);
+    }
+    Geometry { positions, normals, indices }
+}
+
+/// Parses "#rrggbb" / "rrggbb" into linear-ish 0..1 floats
+fn parse_hex_color(color: &str) -> Option<[f32; 3]> {
+    let hex = color.trim().trim_start_matches('#');
+    // Sliced by byte below, so anything multi-byte is out before it can split a char
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|v| v as f32 / 255.0);
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Stable colour for a group name so the same group always exports the same material;
+// the name's hash picks a hue the way a client id does for cursors
+fn group_color(group: &str) -> [f32; 3] {
+    let hash = group.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+    parse_hex_color(&client_color(hash as usize)).unwrap_or(DEFAULT_NODE_COLOR)
+}
+
+fn node_color(node: &Node) -> [f32; 3] {
+    node.color.as_deref().and_then(parse_hex_color)
+        .or_else(|| node.group.as_deref().map(group_color))
+        .unwrap_or(DEFAULT_NODE_COLOR)
+}
+
+fn bounds(positions: &[[f32; 3]]) -> (Value, Value) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    (json!(min), json!(max))
+}
+
+struct BufferBuilder {
+    data: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BufferBuilder {
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // Keep every view 4-byte aligned as required for float accessors
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+        let offset = self.data.len();
+        self.data.extend_from_slice(bytes);
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.views.len() - 1
+    }
+
+    fn push_vec3(&mut self, values: &[[f32; 3]], with_bounds: bool) -> usize {
+        let view = self.push_view(bytemuck::cast_slice(values), ARRAY_BUFFER);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": values.len(),
+            "type": "VEC3",
+        });
+        if with_bounds {
+            let (min, max) = bounds(values);
+            accessor["min"] = min;
+            accessor["max"] = max;
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u16]) -> usize {
+        let view = self.push_view(bytemuck::cast_slice(indices), ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_SHORT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_geometry(&mut self, geometry: &Geometry) -> (usize, usize, usize) {
+        let position = self.push_vec3(&geometry.positions, true);
+        let normal = self.push_vec3(&geometry.normals, false);
+        let indices = self.push_indices(&geometry.indices);
+        (position, normal, indices)
+    }
+}
+
+/// Builds a self-contained glTF 2.0 JSON document for the given nodes and edges.
+/// Edges whose endpoints are not both present are skipped.
+pub fn build_gltf(nodes: &[Node], edges: &[Edge]) -> Value {
+    build_gltf_with_labels(nodes, edges, &HashMap::new())
+}
+
+/// As `build_gltf`, with each node's label placement in its extras where there is one
+pub fn build_gltf_with_labels(nodes: &[Node], edges: &[Edge], labels: &HashMap<u32, LabelPlacement>) -> Value {
+    let mut buffer = BufferBuilder { data: Vec::new(), views: Vec::new(), accessors: Vec::new() };
+    let (sphere_pos, sphere_norm, sphere_idx) = buffer.push_geometry(&unit_sphere());
+    let (cyl_pos, cyl_norm, cyl_idx) = buffer.push_geometry(&unit_cylinder());
+
+    let mut materials: Vec<Value> = Vec::new();
+    let mut meshes: Vec<Value> = Vec::new();
+    let mut gltf_nodes: Vec<Value> = Vec::new();
+
+    // One sphere mesh per distinct colour, all sharing the same accessors
+    let mut mesh_for_color: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut positions: HashMap<u32, Vec3> = HashMap::with_capacity(nodes.len());
+    let max_weight = edges.iter().map(|e| e.weight).fold(0.0f32, f32::max).max(f32::EPSILON);
+
+    for node in nodes {
+        let color = node_color(node);
+        let key = [color[0].to_bits(), color[1].to_bits(), color[2].to_bits()];
+        let mesh = *mesh_for_color.entry(key).or_insert_with(|| {
+            materials.push(json!({
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [color[0], color[1], color[2], 1.0],
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 0.8,
+                }
+            }));
+            meshes.push(json!({
+                "primitives": [{
+                    "attributes": { "POSITION": sphere_pos, "NORMAL": sphere_norm },
+                    "indices": sphere_idx,
+                    "material": materials.len() - 1,
+                }]
+            }));
+            meshes.len() - 1
+        });
+
+        let position = Vec3::new(node.data.position.x, node.data.position.y, node.data.position.z);
+        positions.insert(node.id, position);
+        let radius = node.size.unwrap_or(DEFAULT_NODE_SIZE) * NODE_RADIUS_PER_SIZE;
+
+        let mut extras = json!({
+            "nodeId": node.id,
+            "metadataId": node.metadata_id,
+            "label": node.label,
+        });
+        if let Some(placement) = labels.get(&node.id) {
+            extras["labelPlacement"] = json!(placement);
+        }
+        gltf_nodes.push(json!({
+            "name": if node.label.is_empty() { node.metadata_id.clone() } else { node.label.clone() },
+            "mesh": mesh,
+            "translation": position.to_array(),
+            "scale": [radius, radius, radius],
+            "extras": extras,
+        }));
+    }
+
+    let mut edge_count = 0;
+    if !edges.is_empty() {
+        materials.push(json!({
+            "pbrMetallicRoughness": {
+                "baseColorFactor": EDGE_COLOR,
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            }
+        }));
+        meshes.push(json!({
+            "primitives": [{
+                "attributes": { "POSITION": cyl_pos, "NORMAL": cyl_norm },
+                "indices": cyl_idx,
+                "material": materials.len() - 1,
+            }]
+        }));
+        let edge_mesh = meshes.len() - 1;
+
+        for edge in edges {
+            let (Some(&a), Some(&b)) = (positions.get(&edge.source), positions.get(&edge.target)) else {
+                continue;
+            };
+            let delta = b - a;
+            let length = delta.length();
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let rotation = Quat::from_rotation_arc(Vec3::Y, delta / length);
+            // Thickness grows with relative weight, never thinner than half the base radius
+            let radius = EDGE_BASE_RADIUS * (0.5 + edge.weight.max(0.0) / max_weight);
+
+            gltf_nodes.push(json!({
+                "name": edge.id,
+                "mesh": edge_mesh,
+                "translation": ((a + b) * 0.5).to_array(),
+                "rotation": rotation.to_array(),
+                "scale": [radius, length, radius],
+                "extras": {
+                    "source": edge.source,
+                    "target": edge.target,
+                    "weight": edge.weight,
+                    "affectsPhysics": edge.affects_physics,
+                }
+            }));
+            edge_count += 1;
+        }
+    }
+
+    let root_nodes: Vec<usize> = (0..gltf_nodes.len()).collect();
+    json!({
+        "asset": { "version": "2.0", "generator": "webxr graph export" },
+        "scene": 0,
+        "scenes": [{ "nodes": root_nodes, "extras": { "nodeCount": nodes.len(), "edgeCount": edge_count } }],
+        "nodes": gltf_nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.views,
+        "buffers": [{
+            "byteLength": buffer.data.len(),
+            "uri": format!("data:application/octet-stream;base64,{}", BASE64.encode(&buffer.data)),
+        }],
+    })
+}
+
+/// Adds links to other rooms to a document from `build_gltf`, as mesh-less nodes at the
+/// local document with the far end in their extras, for clients to show as portals.
+/// Portals whose local document isn't among `nodes` are skipped.
+pub fn add_portals(doc: &mut Value, nodes: &[Node], portals: &[Portal]) {
+    let positions: HashMap<u32, Vec3> = nodes.iter()
+        .map(|n| (n.id, Vec3::new(n.data.position.x, n.data.position.y, n.data.position.z)))
+        .collect();
+    let mut added = 0;
+    for portal in portals {
+        let Some(position) = portal.node_id.and_then(|id| positions.get(&id)) else {
+            continue;
+        };
+        let Some(gltf_nodes) = doc["nodes"].as_array_mut() else {
+            return;
+        };
+        gltf_nodes.push(json!({
+            "name": format!("portal:{}/{}", portal.target_room, portal.target_metadata_id),
+            "translation": position.to_array(),
+            "extras": {
+                "externalLink": true,
+                "source": portal.node_id,
+                "targetRoom": portal.target_room,
+                "targetMetadataId": portal.target_metadata_id,
+                "weight": portal.weight,
+                "outgoing": portal.outgoing,
+                "dangling": portal.dangling,
+                "affectsPhysics": false,
+            }
+        }));
+        let index = gltf_nodes.len() - 1;
+        if let Some(root_nodes) = doc["scenes"][0]["nodes"].as_array_mut() {
+            root_nodes.push(json!(index));
+        }
+        added += 1;
+    }
+    doc["scenes"][0]["extras"]["portalCount"] = json!(added);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_graph() -> (Vec<Node>, Vec<Edge>) {
+        let a = Node::new_with_id("a.md".to_string(), Some(1)).with_position(0.0, 0.0, 0.0).with_label("A".to_string());
+        let b = Node::new_with_id("b.md".to_string(), Some(2)).with_position(1.0, 2.0, 0.0).with_color("#ff0000".to_string());
+        let c = Node::new_with_id("c.md".to_string(), Some(3)).with_position(-1.0, 0.0, 3.0).with_group("docs".to_string());
+        let edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 3, 0.5), Edge::new(3, 99, 1.0)];
+        (vec![a, b, c], edges)
+    }
+
+    // Structural checks mirroring the glTF 2.0 schema requirements we rely on
+    fn validate(doc: &Value) {
+        assert_eq!(doc["asset"]["version"], "2.0");
+
+        let buffer = &doc["buffers"][0];
+        let uri = buffer["uri"].as_str().unwrap();
+        let data = BASE64.decode(uri.trim_start_matches("data:application/octet-stream;base64,")).unwrap();
+        assert_eq!(data.len() as u64, buffer["byteLength"].as_u64().unwrap());
+
+        let views = doc["bufferViews"].as_array().unwrap();
+        for view in views {
+            let offset = view["byteOffset"].as_u64().unwrap();
+            let length = view["byteLength"].as_u64().unwrap();
+            assert_eq!(offset % 4, 0, "bufferView must be aligned");
+            assert!(offset + length <= data.len() as u64, "bufferView exceeds buffer");
+        }
+
+        let accessors = doc["accessors"].as_array().unwrap();
+        for accessor in accessors {
+            let view = &views[accessor["bufferView"].as_u64().unwrap() as usize];
+            let (component_size, components) = match (accessor["componentType"].as_u64().unwrap(), accessor["type"].as_str().unwrap()) {
+                (5126, "VEC3") => (4, 3),
+                (5123, "SCALAR") => (2, 1),
+                other => panic!("unexpected accessor layout {:?}", other),
+            };
+            let needed = accessor["count"].as_u64().unwrap() * component_size * components;
+            assert!(needed <= view["byteLength"].as_u64().unwrap(), "accessor exceeds bufferView");
+        }
+
+        let materials = doc["materials"].as_array().unwrap();
+        let meshes = doc["meshes"].as_array().unwrap();
+        for mesh in meshes {
+            for primitive in mesh["primitives"].as_array().unwrap() {
+                let position = &accessors[primitive["attributes"]["POSITION"].as_u64().unwrap() as usize];
+                assert!(position.get("min").is_some() && position.get("max").is_some(), "POSITION needs min/max");
+                let vertex_count = position["count"].as_u64().unwrap();
+                assert!((primitive["material"].as_u64().unwrap() as usize) < materials.len());
+
+                // Every index must reference an existing vertex
+                let index_accessor = &accessors[primitive["indices"].as_u64().unwrap() as usize];
+                let view = &views[index_accessor["bufferView"].as_u64().unwrap() as usize];
+                let start = view["byteOffset"].as_u64().unwrap() as usize;
+                let count = index_accessor["count"].as_u64().unwrap() as usize;
+                for i in 0..count {
+                    let idx = u16::from_le_bytes([data[start + i * 2], data[start + i * 2 + 1]]);
+                    assert!((idx as u64) < vertex_count);
+                }
+            }
+        }
+
+        let nodes = doc["nodes"].as_array().unwrap();
+        for node in nodes {
+            // Portals carry no mesh
+            if let Some(mesh) = node.get("mesh") {
+                assert!((mesh.as_u64().unwrap() as usize) < meshes.len());
+            }
+            if let Some(rotation) = node.get("rotation") {
+                let q: Vec<f64> = rotation.as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect();
+                let norm = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+                assert!((norm - 1.0).abs() < 1e-3, "rotation must be a unit quaternion");
+            }
+        }
+        for root in doc["scenes"][0]["nodes"].as_array().unwrap() {
+            assert!((root.as_u64().unwrap() as usize) < nodes.len());
+        }
+    }
+
+    #[test]
+    fn test_gltf_export_is_valid() {
+        let (nodes, edges) = sample_graph();
+        let doc = build_gltf(&nodes, &edges);
+        validate(&doc);
+
+        // 3 nodes + 2 edges (the dangling edge to node 99 is skipped)
+        assert_eq!(doc["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(doc["scenes"][0]["extras"]["edgeCount"], 2);
+        assert_eq!(doc["nodes"][0]["extras"]["label"], "A");
+        // Default, red and group colours -> 3 sphere meshes plus the edge mesh
+        assert_eq!(doc["meshes"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_label_placements_go_in_node_extras() {
+        let (nodes, edges) = sample_graph();
+        let labels: HashMap<u32, LabelPlacement> = crate::utils::label_placement::place_labels(
+            &nodes[..1], &edges, &crate::utils::projection::Projection::XY,
+        ).into_iter().map(|l| (l.node_id, l)).collect();
+        let doc = build_gltf_with_labels(&nodes, &edges, &labels);
+        validate(&doc);
+        assert_eq!(doc["nodes"][0]["extras"]["labelPlacement"]["visible"], true);
+        assert!(doc["nodes"][1]["extras"].get("labelPlacement").is_none());
+    }
+
+    #[test]
+    fn test_portals_are_extra_nodes_outside_physics() {
+        let (nodes, edges) = sample_graph();
+        let portal = |metadata_id: &str, node_id: Option<u32>| Portal {
+            metadata_id: metadata_id.to_string(),
+            node_id,
+            target_room: "personal".to_string(),
+            target_metadata_id: "Rust notes.md".to_string(),
+            weight: 0.5,
+            outgoing: true,
+            dangling: false,
+        };
+        let mut doc = build_gltf(&nodes, &edges);
+        // The second portal's document isn't in this export
+        add_portals(&mut doc, &nodes, &[portal("b.md", Some(2)), portal("gone.md", None)]);
+        validate(&doc);
+
+        let gltf_nodes = doc["nodes"].as_array().unwrap();
+        assert_eq!(gltf_nodes.len(), 6);
+        let added = &gltf_nodes[5];
+        assert!(added.get("mesh").is_none());
+        assert_eq!(added["translation"], json!([1.0, 2.0, 0.0]));
+        assert_eq!(added["extras"]["targetRoom"], "personal");
+        assert_eq!(added["extras"]["affectsPhysics"], false);
+        assert_eq!(doc["scenes"][0]["extras"]["portalCount"], 1);
+        // Ordinary edges are untouched
+        assert_eq!(doc["scenes"][0]["extras"]["edgeCount"], 2);
+    }
+
+    #[test]
+    fn test_gltf_export_empty_graph() {
+        let doc = build_gltf(&[], &[]);
+        validate(&doc);
+        assert!(doc["nodes"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#ff0000"), Some([1.0, 0.0, 0.0]));
+        assert_eq!(parse_hex_color("zzzzzz"), None);
+        assert_eq!(parse_hex_color("#fff"), None);
+        // Six bytes, but not six hex digits; mustn't panic slicing mid-char
+        assert_eq!(parse_hex_color("#ééé"), None);
+    }
+}
@@ -1,19 +1,23 @@
 use cudarc::driver::{CudaDevice, CudaFunction, CudaSlice, LaunchConfig, LaunchAsync};
 use cudarc::nvrtc::Ptx;
-use cudarc::driver::sys::CUdevice_attribute_enum;
+use cudarc::driver::sys::{CUdevice_attribute_enum, CUevent_flags, CUresult};
+use cudarc::driver::result::{event, DriverError};
 
 use std::io::{Error, ErrorKind};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use log::{error, warn, info, trace};
 use crate::models::graph::GraphData;
 use std::collections::HashMap;
-use crate::models::simulation_params::SimulationParams;
+use crate::models::simulation_params::{PhaseProfile, PhaseProfiles, SimulationParams, SimulationPhase};
 use crate::utils::socket_flow_messages::BinaryNodeData;
+use crate::utils::degree_repulsion::{repulsion_scales, scaled_mass};
 use crate::types::vec3::Vec3Data;
+use crate::utils::gpu_timing::{self, GpuTimingSummary, GpuTimings, StepBreakdown};
+use crate::utils::gpu_reconnect::{FaultInjector, GpuError};
 use std::path::Path;
 use std::env;
 use tokio::sync::RwLock;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 
 // Constants for GPU computation
@@ -39,7 +43,71 @@ pub struct GPUCompute {
     pub num_nodes: u32,
     pub node_indices: HashMap<u32, usize>,
     pub simulation_params: SimulationParams,
+    // The kernel's per-phase parameter table, and what was last uploaded to it
+    pub phase_profiles: CudaSlice<PhaseProfile>,
+    uploaded_profiles: Option<PhaseProfiles>,
+    pub active_phase: SimulationPhase,
     pub iteration_count: u32,
+    // Per-stage step timings while switched on; readback only has a shared borrow
+    timing: Mutex<GpuTimings>,
+    // Errors queued here are returned by the next step in place of launching
+    faults: FaultInjector,
+}
+
+/// Sorts driver errors by whether the context survives them. Sticky faults inside a
+/// kernel, uncorrectable ECC errors and a torn-down driver poison the context for good.
+impl From<DriverError> for GpuError {
+    fn from(e: DriverError) -> Self {
+        match e.0 {
+            CUresult::CUDA_ERROR_DEINITIALIZED
+            | CUresult::CUDA_ERROR_DEVICE_UNAVAILABLE
+            | CUresult::CUDA_ERROR_NO_DEVICE
+            | CUresult::CUDA_ERROR_INVALID_CONTEXT
+            | CUresult::CUDA_ERROR_CONTEXT_IS_DESTROYED
+            | CUresult::CUDA_ERROR_ECC_UNCORRECTABLE
+            | CUresult::CUDA_ERROR_ILLEGAL_ADDRESS
+            | CUresult::CUDA_ERROR_HARDWARE_STACK_ERROR
+            | CUresult::CUDA_ERROR_ILLEGAL_INSTRUCTION
+            | CUresult::CUDA_ERROR_MISALIGNED_ADDRESS
+            | CUresult::CUDA_ERROR_INVALID_ADDRESS_SPACE
+            | CUresult::CUDA_ERROR_INVALID_PC
+            | CUresult::CUDA_ERROR_LAUNCH_FAILED
+            | CUresult::CUDA_ERROR_SYSTEM_NOT_READY => GpuError::ContextLost(e.to_string()),
+            _ => GpuError::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Runs `launch` between two events on the device's stream and waits for it, returning
+/// its result and the milliseconds the device spent between the events
+pub(crate) fn time_on_device<T>(device: &Arc<CudaDevice>, launch: impl FnOnce() -> Result<T, GpuError>) -> Result<(T, f32), GpuError> {
+    let driver_error = GpuError::from;
+    device.bind_to_thread().map_err(driver_error)?;
+    let start = event::create(CUevent_flags::CU_EVENT_DEFAULT).map_err(driver_error)?;
+    let end = match event::create(CUevent_flags::CU_EVENT_DEFAULT) {
+        Ok(end) => end,
+        Err(e) => {
+            // SAFETY: created above and not yet destroyed
+            let _ = unsafe { event::destroy(start) };
+            return Err(driver_error(e));
+        }
+    };
+    let timed = (|| {
+        let stream = *device.cu_stream();
+        // SAFETY: both events were created on this device's context and are live
+        unsafe { event::record(start, stream) }.map_err(driver_error)?;
+        let value = launch()?;
+        unsafe { event::record(end, stream) }.map_err(driver_error)?;
+        device.synchronize().map_err(driver_error)?;
+        let ms = unsafe { event::elapsed(start, end) }.map_err(driver_error)?;
+        Ok((value, ms))
+    })();
+    // SAFETY: neither event is used again
+    unsafe {
+        let _ = event::destroy(start);
+        let _ = event::destroy(end);
+    }
+    timed
 }
 
 impl GPUCompute {
@@ -280,6 +348,8 @@ impl GPUCompute {
         info!("Allocating device memory for {} nodes", num_nodes);
         let node_data = device.alloc_zeros::<BinaryNodeData>(num_nodes as usize)
             .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+        let phase_profiles = device.alloc_zeros::<PhaseProfile>(SimulationPhase::ALL.len())
+            .map_err(|e| Error::other(e.to_string()))?;
         
         info!("Creating GPU compute instance");
         let mut node_indices = HashMap::new();
@@ -294,7 +364,12 @@ impl GPUCompute {
             num_nodes,
             node_indices,
             simulation_params: SimulationParams::default(),
+            phase_profiles,
+            uploaded_profiles: None,
+            active_phase: SimulationPhase::default(),
             iteration_count: 0,
+            timing: Mutex::new(GpuTimings::default()),
+            faults: FaultInjector::default(),
         };
 
         info!("Copying initial graph data to device memory");
@@ -303,7 +378,7 @@ impl GPUCompute {
         Ok(Arc::new(RwLock::new(instance)))
     }
 
-    pub fn update_graph_data(&mut self, graph: &GraphData) -> Result<(), Error> {
+    pub fn update_graph_data(&mut self, graph: &GraphData) -> Result<(), GpuError> {
         trace!("Updating graph data for {} nodes", graph.nodes.len());
         self.node_indices.clear();
         for (idx, node) in graph.nodes.iter().enumerate() {
@@ -312,7 +387,7 @@ impl GPUCompute {
         if graph.nodes.len() as u32 != self.num_nodes {
             info!("Reallocating GPU buffer for {} nodes", graph.nodes.len());
             self.node_data = self.device.alloc_zeros::<BinaryNodeData>(graph.nodes.len())
-                .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+                .map_err(GpuError::from)?;
             self.num_nodes = graph.nodes.len() as u32;
             self.iteration_count = 0;
         }
@@ -330,11 +405,13 @@ impl GPUCompute {
                 );
             }
         }
-        for node in &graph.nodes {
+        // The kernel has no per-node repulsion input; degree scaling rides on the mass byte
+        let scales = repulsion_scales(graph, self.simulation_params.degree_repulsion_factor);
+        for (node, scale) in graph.nodes.iter().zip(&scales) {
             node_data.push(BinaryNodeData {
                 position: node.data.position.clone(),
                 velocity: node.data.velocity.clone(),
-                mass: node.data.mass,
+                mass: scaled_mass(node.data.mass, *scale),
                 flags: node.data.flags,
                 padding: node.data.padding,
             });
@@ -348,19 +425,43 @@ impl GPUCompute {
             }
         }
         trace!("Copying {} nodes to GPU", graph.nodes.len());
+        let started = Instant::now();
         self.device.htod_sync_copy_into(&node_data, &mut self.node_data)
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy node data to GPU: {}", e)))?;
+            .map_err(|e| GpuError::from(e).context("Failed to copy node data to GPU"))?;
+        self.timings().record_upload(gpu_timing::millis_since(started));
         Ok(())
     }
 
-    pub fn update_simulation_params(&mut self, params: &SimulationParams) -> Result<(), Error> {
+    /// Takes new parameters. The phase profile table is only re-uploaded when a
+    /// profile changed; the phase itself is just the index the next launch passes.
+    pub fn update_simulation_params(&mut self, params: &SimulationParams) -> Result<(), GpuError> {
         trace!("Updating simulation parameters: {:?}", params);
         self.simulation_params = params.clone();
+        self.upload_phase_profiles(params.profiles())?;
+        self.active_phase = params.phase;
+        Ok(())
+    }
+
+    fn upload_phase_profiles(&mut self, profiles: PhaseProfiles) -> Result<(), GpuError> {
+        if self.uploaded_profiles == Some(profiles) {
+            return Ok(());
+        }
+        trace!("Uploading phase profiles: {:?}", profiles);
+        self.device.htod_sync_copy_into(&profiles, &mut self.phase_profiles)
+            .map_err(|e| GpuError::from(e).context("Failed to copy phase profiles to GPU"))?;
+        self.uploaded_profiles = Some(profiles);
         Ok(())
     }
 
+    /// Switches the phase the kernel runs from the next step on
+    pub fn set_phase(&mut self, phase: SimulationPhase) {
+        self.active_phase = phase;
+        self.simulation_params.phase = phase;
+    }
+
     /// Computes forces on the GPU. To reduce log clutter from repeated messages, some logging is gated.
-    pub fn compute_forces(&mut self) -> Result<(), Error> {
+    pub fn compute_forces(&mut self) -> Result<(), GpuError> {
+        self.faults.check()?;
         // Only log detailed GPU computation info every DEBUG_THROTTLE iterations.
         if self.iteration_count % DEBUG_THROTTLE == 0 {
             trace!("Starting force computation on GPU");
@@ -378,20 +479,12 @@ impl GPUCompute {
             self.force_kernel.clone().launch(cfg, (
                 &self.node_data,
                 self.num_nodes as i32,
-                self.simulation_params.spring_strength,
-                self.simulation_params.damping,
-                self.simulation_params.repulsion,
-                self.simulation_params.time_step,
-                self.simulation_params.max_repulsion_distance,
-                if self.simulation_params.enable_bounds {
-                    self.simulation_params.viewport_bounds
-                } else {
-                    f32::MAX // disable bounds
-                },
+                &self.phase_profiles,
+                self.active_phase.index() as i32,
                 self.iteration_count as i32,
             )).map_err(|e| {
                 error!("Kernel launch failed: {}", e);
-                Error::new(ErrorKind::Other, e.to_string())
+                GpuError::from(e)
             })?;
         }
         if self.iteration_count % DEBUG_THROTTLE == 0 {
@@ -401,7 +494,7 @@ impl GPUCompute {
         Ok(())
     }
 
-    pub fn get_node_data(&self) -> Result<Vec<BinaryNodeData>, Error> {
+    pub fn get_node_data(&self) -> Result<Vec<BinaryNodeData>, GpuError> {
         let mut gpu_raw_data = vec![BinaryNodeData {
             position: Vec3Data::zero(),
             velocity: Vec3Data::zero(),
@@ -409,8 +502,10 @@ impl GPUCompute {
             flags: 0,
             padding: [0, 0],
         }; self.num_nodes as usize];
+        let started = Instant::now();
         self.device.dtoh_sync_copy_into(&self.node_data, &mut gpu_raw_data)
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy data from GPU: {}", e)))?;
+            .map_err(|e| GpuError::from(e).context("Failed to copy data from GPU"))?;
+        self.timings().record_readback(gpu_timing::millis_since(started));
         if !gpu_raw_data.is_empty() {
             let sample_size = std::cmp::min(5, gpu_raw_data.len());
             trace!("Sample of first {} nodes after GPU calculation:", sample_size);
@@ -431,30 +526,62 @@ impl GPUCompute {
     }
 
     /// Advances one simulation step.
-    pub fn step(&mut self) -> Result<(), Error> {
+    pub fn step(&mut self) -> Result<(), GpuError> {
         trace!("Executing physics step (iteration {})", self.iteration_count);
-        self.compute_forces()?;
+        if self.timings().is_enabled() {
+            let started = Instant::now();
+            let device = self.device.clone();
+            let ((), kernel_ms) = time_on_device(&device, || self.compute_forces())?;
+            self.timings().record_kernel(kernel_ms, gpu_timing::millis_since(started));
+        } else {
+            self.compute_forces()?;
+        }
         if self.iteration_count % DEBUG_THROTTLE == 0 {
             trace!("Detailed simulation status:");
             trace!("  - Iteration: {}", self.iteration_count);
             trace!("  - Node count: {}", self.num_nodes);
-            trace!("  - Spring strength: {}", self.simulation_params.spring_strength);
-            trace!("  - Repulsion: {}", self.simulation_params.repulsion);
-            trace!("  - Damping: {}", self.simulation_params.damping);
+            let profile = self.simulation_params.profile(self.active_phase);
+            trace!("  - Phase: {:?}", self.active_phase);
+            trace!("  - Spring strength: {}", profile.spring_strength);
+            trace!("  - Repulsion: {}", profile.repulsion);
+            trace!("  - Damping: {}", profile.damping);
         } else {
             trace!("Physics step complete, iteration count: {}", self.iteration_count);
         }
         Ok(())
     }
     
+    fn timings(&self) -> std::sync::MutexGuard<'_, GpuTimings> {
+        self.timing.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Switches per-stage step timing on or off; device events add a little to every step
+    pub fn set_timing_enabled(&self, enabled: bool) {
+        self.timings().set_enabled(enabled);
+    }
+
+    pub fn timing_summary(&self) -> GpuTimingSummary {
+        self.timings().summary()
+    }
+
+    /// The last `count` timed steps, oldest first
+    pub fn recent_timings(&self, count: usize) -> Vec<StepBreakdown> {
+        self.timings().recent(count)
+    }
+
+    /// Queues errors for the next step to return, to exercise the reconnect path
+    pub fn fault_injector(&self) -> FaultInjector {
+        self.faults.clone()
+    }
+
     /// Runs a minimal test computation on the GPU.
-    pub fn test_compute(&self) -> Result<(), Error> {
+    pub fn test_compute(&self) -> Result<(), GpuError> {
         info!("Running test computation on GPU instance");
         match self.device.synchronize() {
             Ok(_) => { info!("GPU device access test passed"); },
             Err(e) => {
                 error!("GPU device access test failed: {}", e);
-                return Err(Error::new(ErrorKind::Other, format!("GPU device access test failed: {}", e)));
+                return Err(GpuError::from(e).context("GPU device access test failed"));
             }
         }
         info!("GPU test computation successful");
@@ -490,4 +617,70 @@ mod tests {
         use std::mem::size_of;
         assert_eq!(size_of::<BinaryNodeData>(), 28);
     }
+
+    #[tokio::test]
+    async fn test_phase_switch_changes_next_step() {
+        if !std::panic::catch_unwind(CudaDevice::count).is_ok_and(|count| count.unwrap_or(0) > 0) {
+            warn!("No CUDA device available, skipping phase switch test");
+            return;
+        }
+        let mut graph = GraphData::default();
+        for (id, x) in [(1, -10.0), (2, 10.0)] {
+            let mut node = crate::models::node::Node::new_with_id(format!("n{}", id), Some(id));
+            node.set_x(x);
+            graph.nodes.push(node);
+        }
+        graph.edges.push(crate::models::edge::Edge::new(1, 2, 1.0));
+
+        let gpu_compute = GPUCompute::new(&graph).await.unwrap();
+        let mut gpu_compute = gpu_compute.write().await;
+        gpu_compute.update_simulation_params(&SimulationParams::new()).unwrap();
+        let mut velocities = Vec::new();
+        for phase in SimulationPhase::ALL {
+            gpu_compute.update_graph_data(&graph).unwrap();
+            gpu_compute.iteration_count = 0;
+            gpu_compute.set_phase(phase);
+            gpu_compute.step().unwrap();
+            velocities.push(gpu_compute.get_node_data().unwrap()[0].velocity.x);
+        }
+        assert_ne!(velocities[0], velocities[1]);
+        assert_ne!(velocities[1], velocities[2]);
+    }
+
+    #[tokio::test]
+    async fn test_step_timing_breakdown_adds_up() {
+        if !std::panic::catch_unwind(CudaDevice::count).is_ok_and(|count| count.unwrap_or(0) > 0) {
+            warn!("No CUDA device available, skipping step timing test");
+            return;
+        }
+        let mut graph = GraphData::default();
+        for id in 1..=2000u32 {
+            let mut node = crate::models::node::Node::new_with_id(format!("n{}", id), Some(id));
+            node.set_x((id % 50) as f32);
+            node.set_y((id / 50) as f32);
+            graph.nodes.push(node);
+            if id > 1 {
+                graph.edges.push(crate::models::edge::Edge::new(id - 1, id, 1.0));
+            }
+        }
+
+        let gpu_compute = GPUCompute::new(&graph).await.unwrap();
+        let mut gpu_compute = gpu_compute.write().await;
+        gpu_compute.update_simulation_params(&SimulationParams::new()).unwrap();
+        gpu_compute.set_timing_enabled(true);
+        for _ in 0..5 {
+            gpu_compute.update_graph_data(&graph).unwrap();
+            gpu_compute.step().unwrap();
+            gpu_compute.get_node_data().unwrap();
+        }
+
+        let steps = gpu_compute.recent_timings(10);
+        assert_eq!(steps.len(), 5);
+        for step in &steps {
+            assert!(step.upload_ms > 0.0 && step.kernel_ms > 0.0 && step.readback_ms > 0.0, "{:?}", step);
+            let stages = step.upload_ms + step.kernel_ms + step.launch_overhead_ms + step.readback_ms;
+            assert!((stages - step.total_ms).abs() <= step.total_ms * 0.05 + 0.01, "{:?}", step);
+        }
+        assert_eq!(gpu_compute.timing_summary().histograms["total"].count, 5);
+    }
 }
@@ -0,0 +1,80 @@
+//! Stand-in for `gpu_compute` in builds without the `gpu` feature.
+//!
+//! `new` always fails, so callers take the same CPU-fallback path they take when no
+//! CUDA device is found.
+
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::models::graph::GraphData;
+use crate::models::simulation_params::{SimulationParams, SimulationPhase};
+use crate::utils::gpu_reconnect::{FaultInjector, GpuError};
+use crate::utils::gpu_timing::{GpuTimingSummary, StepBreakdown};
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+fn disabled() -> Error {
+    Error::new(ErrorKind::Unsupported, "built without the gpu feature")
+}
+
+#[derive(Debug)]
+pub struct GPUCompute {
+    pub num_nodes: u32,
+    pub simulation_params: SimulationParams,
+    pub active_phase: SimulationPhase,
+    pub iteration_count: u32,
+}
+
+impl GPUCompute {
+    pub async fn test_gpu() -> Result<(), Error> {
+        Err(disabled())
+    }
+
+    pub async fn new(_graph: &GraphData) -> Result<Arc<RwLock<Self>>, Error> {
+        Err(disabled())
+    }
+
+    pub fn update_graph_data(&mut self, _graph: &GraphData) -> Result<(), GpuError> {
+        Err(disabled().into())
+    }
+
+    pub fn update_simulation_params(&mut self, params: &SimulationParams) -> Result<(), GpuError> {
+        self.simulation_params = params.clone();
+        Ok(())
+    }
+
+    pub fn set_phase(&mut self, phase: SimulationPhase) {
+        self.active_phase = phase;
+        self.simulation_params.phase = phase;
+    }
+
+    pub fn compute_forces(&mut self) -> Result<(), GpuError> {
+        Err(disabled().into())
+    }
+
+    pub fn get_node_data(&self) -> Result<Vec<BinaryNodeData>, GpuError> {
+        Err(disabled().into())
+    }
+
+    pub fn step(&mut self) -> Result<(), GpuError> {
+        Err(disabled().into())
+    }
+
+    pub fn fault_injector(&self) -> FaultInjector {
+        FaultInjector::default()
+    }
+
+    pub fn test_compute(&self) -> Result<(), GpuError> {
+        Err(disabled().into())
+    }
+
+    pub fn set_timing_enabled(&self, _enabled: bool) {}
+
+    pub fn timing_summary(&self) -> GpuTimingSummary {
+        GpuTimingSummary::default()
+    }
+
+    pub fn recent_timings(&self, _count: usize) -> Vec<StepBreakdown> {
+        Vec::new()
+    }
+}
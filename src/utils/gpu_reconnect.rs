@@ -0,0 +1,286 @@
+//! Getting the GPU back after its CUDA context dies. A driver update or an Xid error
+//! leaves the context poisoned: every later call on it fails until a new one is made.
+//! GPU calls classify their failures so a lost context is told apart from a failed
+//! launch; on a loss the owner drops the instance, keeps physics on the CPU, and builds
+//! a new instance in the background until one passes a test computation.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Waits between reconnect attempts double from the first up to the last
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GpuError {
+    // The context is gone (driver reset, Xid error, sticky kernel fault); only a new one recovers
+    #[error("GPU context lost: {0}")]
+    ContextLost(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl GpuError {
+    pub fn is_context_lost(&self) -> bool {
+        matches!(self, GpuError::ContextLost(_))
+    }
+
+    /// Prefixes the message with what was being done, keeping the class
+    pub fn context(self, doing: &str) -> Self {
+        match self {
+            GpuError::ContextLost(message) => GpuError::ContextLost(format!("{}: {}", doing, message)),
+            GpuError::Failed(message) => GpuError::Failed(format!("{}: {}", doing, message)),
+        }
+    }
+}
+
+impl From<std::io::Error> for GpuError {
+    fn from(e: std::io::Error) -> Self {
+        GpuError::Failed(e.to_string())
+    }
+}
+
+impl From<GpuError> for std::io::Error {
+    fn from(e: GpuError) -> Self {
+        std::io::Error::other(e.to_string())
+    }
+}
+
+/// Fault-injection hook: queued errors are returned by the next GPU calls in turn in
+/// place of running them, so the reconnect path can be driven without a failing device
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector(Arc<Mutex<VecDeque<GpuError>>>);
+
+impl FaultInjector {
+    pub fn inject(&self, error: GpuError) {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push_back(error);
+    }
+
+    /// Takes the oldest queued error, if any; called at the top of each guarded GPU call
+    pub fn check(&self) -> Result<(), GpuError> {
+        match self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop_front() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconnectStats {
+    // True from a lost context until a new instance passes its test
+    pub reconnecting: bool,
+    pub contexts_lost: u32,
+    pub attempts: u32,
+    pub failed_attempts: u32,
+    pub reconnects: u32,
+    // Time spent on the CPU, for the current outage while one is going on
+    pub current_downtime_ms: Option<u64>,
+    pub last_downtime_ms: Option<u64>,
+    pub total_downtime_ms: u64,
+    pub last_error: Option<String>,
+}
+
+impl ReconnectStats {
+    /// Prometheus exposition lines, appended to the GPU timing metrics
+    pub fn prometheus_text(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 6] = [
+            ("gpu_reconnecting", "gauge", "1 while physics runs on the CPU waiting for a new GPU context", self.reconnecting as u64),
+            ("gpu_contexts_lost_total", "counter", "GPU contexts lost to driver resets or sticky faults", self.contexts_lost as u64),
+            ("gpu_reconnect_attempts_total", "counter", "Attempts to build a new GPU instance", self.attempts as u64),
+            ("gpu_reconnect_failures_total", "counter", "Reconnect attempts that failed to build or test", self.failed_attempts as u64),
+            ("gpu_reconnects_total", "counter", "Outages ended by a new GPU instance", self.reconnects as u64),
+            ("gpu_downtime_milliseconds_total", "counter", "Time physics spent on the CPU after a lost context", self.total_downtime_ms + self.current_downtime_ms.unwrap_or(0)),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+/// Tracks outages of the GPU context: when one began, the attempts made since, and the
+/// totals across the process's life
+#[derive(Debug, Default)]
+pub struct GpuReconnect {
+    // Start of the current outage; None while the GPU is in use
+    lost_at: Option<Instant>,
+    // Attempts made in the current outage
+    attempt: u32,
+    stats: ReconnectStats,
+}
+
+impl GpuReconnect {
+    pub fn is_reconnecting(&self) -> bool {
+        self.lost_at.is_some()
+    }
+
+    /// Records a lost context; true when it starts an outage, false when one is already
+    /// being recovered from
+    pub fn context_lost(&mut self, error: &GpuError, now: Instant) -> bool {
+        self.stats.last_error = Some(error.to_string());
+        if self.lost_at.is_some() {
+            return false;
+        }
+        self.lost_at = Some(now);
+        self.attempt = 0;
+        self.stats.contexts_lost += 1;
+        true
+    }
+
+    /// Starts an attempt, returning its number within the outage, from 1
+    pub fn begin_attempt(&mut self) -> u32 {
+        self.attempt += 1;
+        self.stats.attempts += 1;
+        self.attempt
+    }
+
+    /// Records a failed attempt and returns how long to wait before the next
+    pub fn attempt_failed(&mut self, error: &GpuError) -> Duration {
+        self.stats.failed_attempts += 1;
+        self.stats.last_error = Some(error.to_string());
+        let doublings = self.attempt.saturating_sub(1).min(16);
+        (FIRST_RETRY_DELAY * (1 << doublings)).min(MAX_RETRY_DELAY)
+    }
+
+    /// Ends the outage, returning how long the GPU was out
+    pub fn recovered(&mut self, now: Instant) -> Duration {
+        let downtime = self.lost_at.take().map_or(Duration::ZERO, |lost_at| now.saturating_duration_since(lost_at));
+        self.stats.reconnects += 1;
+        self.stats.last_downtime_ms = Some(downtime.as_millis() as u64);
+        self.stats.total_downtime_ms += downtime.as_millis() as u64;
+        downtime
+    }
+
+    pub fn stats(&self, now: Instant) -> ReconnectStats {
+        ReconnectStats {
+            reconnecting: self.is_reconnecting(),
+            current_downtime_ms: self.lost_at.map(|lost_at| now.saturating_duration_since(lost_at).as_millis() as u64),
+            ..self.stats.clone()
+        }
+    }
+}
+
+/// One reconnect attempt: builds a fresh instance and hands it back only once a test
+/// computation on it passes
+pub async fn recreate<T, E>(
+    create: impl Future<Output = Result<T, E>>,
+    test_compute: impl FnOnce(&T) -> Result<(), GpuError>,
+) -> Result<T, GpuError>
+where
+    E: Into<GpuError>,
+{
+    let instance = create.await.map_err(|e| e.into().context("Recreating GPU instance"))?;
+    test_compute(&instance).map_err(|e| e.context("Test computation on new GPU instance"))?;
+    Ok(instance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stands in for a GPU instance: steps fail with whatever the injector queues, and a
+    // poisoned one fails its test computation
+    struct MockGpu {
+        faults: FaultInjector,
+        poisoned: bool,
+        steps: u32,
+    }
+
+    impl MockGpu {
+        fn step(&mut self) -> Result<(), GpuError> {
+            self.faults.check()?;
+            self.steps += 1;
+            Ok(())
+        }
+
+        fn test_compute(&self) -> Result<(), GpuError> {
+            if self.poisoned {
+                return Err(GpuError::ContextLost("CUDA_ERROR_DEINITIALIZED".into()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lost_context_runs_on_cpu_until_a_new_instance_passes() {
+        let faults = FaultInjector::default();
+        let mut gpu = Some(MockGpu { faults: faults.clone(), poisoned: false, steps: 0 });
+        let mut reconnect = GpuReconnect::default();
+        let (mut gpu_steps, mut cpu_steps) = (0, 0);
+        let start = Instant::now();
+        // The first new instance comes up on a device the driver hasn't finished resetting
+        let mut builds = vec![Ok(true), Err(GpuError::Failed("no CUDA devices".into())), Ok(false)].into_iter();
+
+        for frame in 0..12u64 {
+            let now = start + Duration::from_millis(frame * 100);
+            if frame == 3 {
+                faults.inject(GpuError::ContextLost("CUDA_ERROR_ILLEGAL_ADDRESS".into()));
+            }
+            if reconnect.is_reconnecting() && frame % 2 == 0 {
+                reconnect.begin_attempt();
+                let build: Result<bool, GpuError> = builds.next().unwrap();
+                let created = build.map(|poisoned| MockGpu { faults: faults.clone(), poisoned, steps: 0 });
+                match recreate(async { created }, MockGpu::test_compute).await {
+                    Ok(instance) => {
+                        gpu = Some(instance);
+                        assert_eq!(reconnect.recovered(now), Duration::from_millis(500));
+                    }
+                    Err(e) => {
+                        assert!(reconnect.attempt_failed(&e) >= FIRST_RETRY_DELAY);
+                    }
+                }
+            }
+            match gpu.as_mut().map(MockGpu::step) {
+                Some(Ok(())) => gpu_steps += 1,
+                Some(Err(e)) => {
+                    assert!(e.is_context_lost());
+                    assert!(reconnect.context_lost(&e, now));
+                    gpu = None;
+                    cpu_steps += 1;
+                }
+                None => cpu_steps += 1,
+            }
+        }
+
+        // Every frame was stepped on one side or the other; frames 3 to 7 ran on the CPU
+        assert_eq!((gpu_steps, cpu_steps), (7, 5));
+        assert_eq!(gpu.unwrap().steps, 4);
+        let stats = reconnect.stats(start);
+        assert!(!stats.reconnecting);
+        assert_eq!((stats.contexts_lost, stats.attempts, stats.failed_attempts, stats.reconnects), (1, 3, 2, 1));
+        assert_eq!((stats.last_downtime_ms, stats.total_downtime_ms), (Some(500), 500));
+        assert!(stats.prometheus_text().contains("gpu_reconnects_total 1\n"));
+    }
+
+    #[test]
+    fn test_retry_delays_back_off_and_outages_count_once() {
+        let mut reconnect = GpuReconnect::default();
+        let now = Instant::now();
+        let lost = GpuError::ContextLost("CUDA_ERROR_LAUNCH_FAILED".into());
+        assert!(reconnect.context_lost(&lost, now));
+        // Errors from calls still in flight belong to the same outage
+        assert!(!reconnect.context_lost(&lost, now));
+
+        let delays: Vec<Duration> = (0..9).map(|_| {
+            reconnect.begin_attempt();
+            reconnect.attempt_failed(&GpuError::Failed("busy".into()))
+        }).collect();
+        assert_eq!(delays[..3], [Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)]);
+        assert_eq!(delays[8], MAX_RETRY_DELAY);
+
+        let stats = reconnect.stats(now + Duration::from_secs(5));
+        assert!(stats.reconnecting);
+        assert_eq!((stats.contexts_lost, stats.current_downtime_ms), (1, Some(5000)));
+        assert_eq!(stats.last_error.as_deref(), Some("busy"));
+        assert_eq!(GpuError::Failed("x".into()).context("Copying").to_string(), "Copying: x");
+        assert!(lost.context("Launching").is_context_lost());
+    }
+}
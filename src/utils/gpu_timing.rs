@@ -0,0 +1,247 @@
+//! Where a GPU physics step's time goes. The force kernel is timed on the device with a
+//! pair of events around its launch; the node upload and readback, and the launch itself,
+//! are timed on the host. The kernel is a single launch that runs repulsion, springs,
+//! integration and bounds together, so it is one stage here. Events cost a little on
+//! every step, so collection is off until switched on at runtime.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::time::Instant;
+
+// Steps kept for the per-frame breakdown
+pub const TIMING_WINDOW: usize = 120;
+// Upper bounds of the histogram buckets in milliseconds; the last bucket is unbounded
+pub const BUCKET_BOUNDS_MS: [f32; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0];
+
+/// One step's time by stage, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepBreakdown {
+    // Copying node data to the device; zero on steps that didn't upload
+    pub upload_ms: f32,
+    // Device time between the events around the force kernel
+    pub kernel_ms: f32,
+    // Host time around the kernel the events don't cover: the launch and synchronising
+    pub launch_overhead_ms: f32,
+    // Copying node data back; zero on steps whose results weren't read
+    pub readback_ms: f32,
+    // Host time of the whole step, upload to readback
+    pub total_ms: f32,
+}
+
+impl StepBreakdown {
+    fn stages(&self) -> [(&'static str, f32); 5] {
+        [
+            ("upload", self.upload_ms),
+            ("kernel", self.kernel_ms),
+            ("launch_overhead", self.launch_overhead_ms),
+            ("readback", self.readback_ms),
+            ("total", self.total_ms),
+        ]
+    }
+}
+
+/// Cumulative bucket counts over `BUCKET_BOUNDS_MS`, as Prometheus histograms count
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Histogram {
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, ms: f32) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; BUCKET_BOUNDS_MS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKET_BOUNDS_MS) {
+            if ms <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_ms += ms as f64;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuTimingSummary {
+    pub enabled: bool,
+    // Steps recorded since collection was last switched on
+    pub steps: u64,
+    // Mean of each stage over the window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<StepBreakdown>,
+    // By stage, over every recorded step
+    pub histograms: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Debug, Default)]
+pub struct GpuTimings {
+    enabled: bool,
+    // The step being timed, closed by its readback or by the next step's kernel
+    pending: Option<StepBreakdown>,
+    window: VecDeque<StepBreakdown>,
+    histograms: BTreeMap<&'static str, Histogram>,
+    steps: u64,
+}
+
+impl GpuTimings {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Switching on starts the window and histograms afresh
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            *self = Self { enabled, ..Self::default() };
+        }
+        self.enabled = enabled;
+        self.pending = None;
+    }
+
+    pub fn record_upload(&mut self, host_ms: f32) {
+        if !self.enabled {
+            return;
+        }
+        // An upload after a kernel starts the next step
+        if self.pending.is_some_and(|step| step.kernel_ms > 0.0 || step.launch_overhead_ms > 0.0) {
+            self.flush();
+        }
+        let step = self.pending.get_or_insert_with(StepBreakdown::default);
+        step.upload_ms += host_ms;
+        step.total_ms += host_ms;
+    }
+
+    pub fn record_kernel(&mut self, device_ms: f32, host_ms: f32) {
+        if !self.enabled {
+            return;
+        }
+        // A step whose results were never read ends at the next kernel
+        if self.pending.is_some_and(|step| step.kernel_ms > 0.0 || step.launch_overhead_ms > 0.0) {
+            self.flush();
+        }
+        let step = self.pending.get_or_insert_with(StepBreakdown::default);
+        step.kernel_ms = device_ms;
+        step.launch_overhead_ms = (host_ms - device_ms).max(0.0);
+        step.total_ms += host_ms;
+    }
+
+    pub fn record_readback(&mut self, host_ms: f32) {
+        if !self.enabled {
+            return;
+        }
+        let step = self.pending.get_or_insert_with(StepBreakdown::default);
+        step.readback_ms += host_ms;
+        step.total_ms += host_ms;
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        let Some(step) = self.pending.take() else {
+            return;
+        };
+        for (stage, ms) in step.stages() {
+            self.histograms.entry(stage).or_default().observe(ms);
+        }
+        if self.window.len() == TIMING_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(step);
+        self.steps += 1;
+    }
+
+    /// The last `count` complete steps, oldest first
+    pub fn recent(&self, count: usize) -> Vec<StepBreakdown> {
+        self.window.iter().skip(self.window.len().saturating_sub(count)).copied().collect()
+    }
+
+    pub fn summary(&self) -> GpuTimingSummary {
+        let n = self.window.len() as f32;
+        let mean = (!self.window.is_empty()).then(|| {
+            let sum = |stage: fn(&StepBreakdown) -> f32| self.window.iter().map(stage).sum::<f32>() / n;
+            StepBreakdown {
+                upload_ms: sum(|s| s.upload_ms),
+                kernel_ms: sum(|s| s.kernel_ms),
+                launch_overhead_ms: sum(|s| s.launch_overhead_ms),
+                readback_ms: sum(|s| s.readback_ms),
+                total_ms: sum(|s| s.total_ms),
+            }
+        });
+        GpuTimingSummary {
+            enabled: self.enabled,
+            steps: self.steps,
+            mean,
+            histograms: self.histograms.clone(),
+        }
+    }
+}
+
+/// Host time since `started`, in milliseconds
+pub fn millis_since(started: Instant) -> f32 {
+    started.elapsed().as_secs_f32() * 1000.0
+}
+
+/// The histograms in the Prometheus text exposition format
+pub fn prometheus_text(summary: &GpuTimingSummary) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP gpu_step_stage_milliseconds Time spent in each stage of a GPU physics step\n");
+    out.push_str("# TYPE gpu_step_stage_milliseconds histogram\n");
+    for (stage, histogram) in &summary.histograms {
+        for (bound, count) in BUCKET_BOUNDS_MS.iter().zip(&histogram.buckets) {
+            let _ = writeln!(out, "gpu_step_stage_milliseconds_bucket{{stage=\"{}\",le=\"{}\"}} {}", stage, bound, count);
+        }
+        let _ = writeln!(out, "gpu_step_stage_milliseconds_bucket{{stage=\"{}\",le=\"+Inf\"}} {}", stage, histogram.count);
+        let _ = writeln!(out, "gpu_step_stage_milliseconds_sum{{stage=\"{}\"}} {}", stage, histogram.sum_ms);
+        let _ = writeln!(out, "gpu_step_stage_milliseconds_count{{stage=\"{}\"}} {}", stage, histogram.count);
+    }
+    out
+}
+
+/// One row per step, newest last, for reading in a terminal
+pub fn breakdown_table(steps: &[StepBreakdown]) -> String {
+    let mut out = format!("{:>5} {:>10} {:>10} {:>12} {:>11} {:>10}\n", "step", "upload_ms", "kernel_ms", "overhead_ms", "readback_ms", "total_ms");
+    for (i, step) in steps.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{:>5} {:>10.3} {:>10.3} {:>12.3} {:>11.3} {:>10.3}",
+            i, step.upload_ms, step.kernel_ms, step.launch_overhead_ms, step.readback_ms, step.total_ms
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_group_into_steps() {
+        let mut timings = GpuTimings::default();
+        // Nothing is kept while collection is off
+        timings.record_kernel(1.0, 1.5);
+        timings.set_enabled(true);
+
+        timings.record_upload(0.5);
+        timings.record_kernel(2.0, 2.25);
+        timings.record_readback(0.25);
+        // Two kernels in a row without a readback are two steps
+        timings.record_kernel(4.0, 4.0);
+        timings.record_kernel(8.0, 9.0);
+        timings.record_readback(1.0);
+
+        let steps = timings.recent(10);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0], StepBreakdown { upload_ms: 0.5, kernel_ms: 2.0, launch_overhead_ms: 0.25, readback_ms: 0.25, total_ms: 3.0 });
+        assert_eq!(steps[1].total_ms, 4.0);
+        assert_eq!(steps[2].total_ms, 10.0);
+        let summary = timings.summary();
+        assert_eq!(summary.steps, 3);
+        // 2ms falls in the 2.5ms bucket and every larger one
+        assert_eq!(summary.histograms["kernel"].buckets[..6], [0, 0, 0, 0, 1, 2]);
+        assert!(prometheus_text(&summary).contains("gpu_step_stage_milliseconds_count{stage=\"kernel\"} 3"));
+    }
+}
@@ -0,0 +1,573 @@
+//! Scripted graph edits applied all-or-nothing. A transaction is an ordered list of
+//! operations run against a copy of the graph; operations can refer to nodes created
+//! earlier in the same transaction by a handle of the caller's choosing. The first
+//! operation that fails discards the copy, so the live graph never sees half a batch.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::config::RewireSettings;
+use crate::models::edge::{default_affects_physics, Edge, WeightSource};
+use crate::models::graph::{GraphData, GraphDiff, NodeUpdate};
+use crate::models::node::Node;
+use crate::utils::rewire;
+
+pub const MAX_TRANSACTION_OPS: usize = 500;
+// Transactions that can still be undone, most recent last
+pub const MAX_UNDO_TRANSACTIONS: usize = 20;
+pub const TRANSACTION_EDGE_TYPE: &str = "scripted";
+const MAX_LABEL_CHARS: usize = 200;
+// Keys that tie nodes back to their source files
+const RESERVED_METADATA_KEYS: [&str; 2] = ["metadataId", "fileName"];
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// A node named by id, or by the handle a `create_node` earlier in the transaction gave it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum NodeRef {
+    Id(u32),
+    Handle(String),
+}
+
+/// One step of a transaction, tagged by `op`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransactionOp {
+    #[serde(rename_all = "camelCase")]
+    CreateNode {
+        #[serde(default)]
+        handle: Option<String>,
+        label: String,
+        #[serde(default)]
+        metadata: HashMap<String, String>,
+        // Without one the node starts next to the neighbours the transaction links it to
+        #[serde(default)]
+        position: Option<[f32; 3]>,
+    },
+    #[serde(rename_all = "camelCase")]
+    UpdateNode {
+        node: NodeRef,
+        metadata: HashMap<String, String>,
+    },
+    // Replaces the node's tags; the first tag becomes its group
+    #[serde(rename_all = "camelCase")]
+    Retag {
+        node: NodeRef,
+        tags: Vec<String>,
+    },
+    // With `rewire` the node's neighbours are linked directly before it goes
+    #[serde(rename_all = "camelCase")]
+    RemoveNode {
+        node: NodeRef,
+        #[serde(default)]
+        rewire: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    CreateEdge {
+        source: NodeRef,
+        target: NodeRef,
+        #[serde(default = "default_weight")]
+        weight: f32,
+        #[serde(default)]
+        edge_type: Option<String>,
+        #[serde(default = "default_affects_physics")]
+        affects_physics: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveEdge { edge_id: String },
+}
+
+impl TransactionOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionOp::CreateNode { .. } => "create_node",
+            TransactionOp::UpdateNode { .. } => "update_node",
+            TransactionOp::Retag { .. } => "retag",
+            TransactionOp::RemoveNode { .. } => "remove_node",
+            TransactionOp::CreateEdge { .. } => "create_edge",
+            TransactionOp::RemoveEdge { .. } => "remove_edge",
+        }
+    }
+}
+
+/// Why a transaction was rejected. `op_index` is absent when the batch as a whole is at fault.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionError {
+    pub op_index: Option<usize>,
+    pub op: Option<String>,
+    pub reason: String,
+}
+
+impl TransactionError {
+    fn batch(reason: impl Into<String>) -> Self {
+        Self { op_index: None, op: None, reason: reason.into() }
+    }
+
+    fn at(index: usize, op: &TransactionOp, reason: impl Into<String>) -> Self {
+        Self { op_index: Some(index), op: Some(op.name().to_string()), reason: reason.into() }
+    }
+}
+
+impl std::fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.op_index, &self.op) {
+            (Some(index), Some(op)) => write!(f, "operation {} ({}): {}", index, op, self.reason),
+            _ => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// How a node looked before a transaction changed its metadata
+#[derive(Debug, Clone)]
+pub struct NodeBefore {
+    pub node_id: u32,
+    pub metadata: HashMap<String, String>,
+    pub group: Option<String>,
+}
+
+/// What it takes to reverse one applied transaction: its net effect, inverted
+#[derive(Debug, Clone)]
+pub struct TransactionUndo {
+    pub transaction_id: u64,
+    pub actor: String,
+    pub created_nodes: Vec<u32>,
+    pub created_edges: Vec<String>,
+    pub removed_nodes: Vec<Node>,
+    pub removed_edges: Vec<Edge>,
+    pub updated_nodes: Vec<NodeBefore>,
+}
+
+/// A transaction applied to a graph, before it is committed
+#[derive(Debug, Clone)]
+pub struct AppliedTransaction {
+    // Net effect, as one diff
+    pub diff: GraphDiff,
+    pub handles: BTreeMap<String, u32>,
+    // Created nodes that were given no position
+    pub unplaced: HashSet<u32>,
+    pub undo: TransactionUndo,
+    // Rewiring that was skipped, and why
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionOutcome {
+    pub transaction_id: u64,
+    pub diff: GraphDiff,
+    // Node ids the handles resolved to
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub handles: BTreeMap<String, u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+fn check_metadata(metadata: &HashMap<String, String>) -> Result<(), String> {
+    if let Some(key) = metadata.keys().find(|k| k.trim().is_empty()) {
+        return Err(format!("invalid metadata key '{}'", key));
+    }
+    match RESERVED_METADATA_KEYS.iter().find(|k| metadata.contains_key(**k)) {
+        Some(key) => Err(format!("metadata key '{}' is reserved", key)),
+        None => Ok(()),
+    }
+}
+
+// Keeps node metadata, group and the updates in the diff in step, as UpdateNodeMetadata does
+pub(crate) fn update_metadata(node: &mut Node, entries: &HashMap<String, String>) {
+    if let Some(tags) = entries.get("tags") {
+        node.group = tags.split(',').next().filter(|t| !t.is_empty()).map(str::to_string);
+    }
+    node.metadata.extend(entries.clone());
+}
+
+// The node an update or retag names, and the metadata entries it sets
+fn metadata_entries(op: &TransactionOp) -> Result<(&NodeRef, HashMap<String, String>), String> {
+    match op {
+        TransactionOp::UpdateNode { node, metadata } => {
+            if metadata.is_empty() {
+                return Err("no metadata to update".to_string());
+            }
+            check_metadata(metadata)?;
+            Ok((node, metadata.clone()))
+        }
+        TransactionOp::Retag { node, tags } => {
+            let tags: Vec<&str> = tags.iter().map(|t| t.trim()).collect();
+            if tags.iter().any(|t| t.is_empty() || t.contains(',')) {
+                return Err("tags must be non-empty and can't contain commas".to_string());
+            }
+            Ok((node, HashMap::from([("tags".to_string(), tags.join(","))])))
+        }
+        _ => Err(format!("{} doesn't set metadata", op.name())),
+    }
+}
+
+/// Runs `ops` in order against `graph`. New nodes take consecutive ids from `first_id`.
+/// On error `graph` is left part way through and should be thrown away, so callers apply
+/// to a copy.
+pub fn apply(
+    graph: &mut GraphData,
+    ops: &[TransactionOp],
+    actor: &str,
+    first_id: u32,
+    rewiring: &RewireSettings,
+) -> Result<AppliedTransaction, TransactionError> {
+    if ops.is_empty() {
+        return Err(TransactionError::batch("Transaction has no operations"));
+    }
+    if ops.len() > MAX_TRANSACTION_OPS {
+        return Err(TransactionError::batch(format!(
+            "Transaction has {} operations, maximum is {}", ops.len(), MAX_TRANSACTION_OPS
+        )));
+    }
+
+    let mut index: HashMap<u32, usize> = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+    let mut handles: BTreeMap<String, u32> = BTreeMap::new();
+    let mut next_id = first_id;
+    let mut created_nodes: Vec<u32> = Vec::new();
+    let mut created_edges: Vec<String> = Vec::new();
+    let mut removed_nodes: Vec<Node> = Vec::new();
+    let mut removed_edges: Vec<Edge> = Vec::new();
+    let mut before: Vec<NodeBefore> = Vec::new();
+    let mut updates: BTreeMap<u32, HashMap<String, String>> = BTreeMap::new();
+    let mut unplaced = HashSet::new();
+    let mut warnings = Vec::new();
+
+    for (i, op) in ops.iter().enumerate() {
+        let fail = |reason: String| TransactionError::at(i, op, reason);
+        let resolve = |node: &NodeRef, index: &HashMap<u32, usize>| -> Result<u32, String> {
+            let id = match node {
+                NodeRef::Id(id) => *id,
+                NodeRef::Handle(handle) => *handles.get(handle)
+                    .ok_or_else(|| format!("no node created earlier with handle '{}'", handle))?,
+            };
+            if index.contains_key(&id) { Ok(id) } else { Err(format!("node {} not found", id)) }
+        };
+
+        match op {
+            TransactionOp::CreateNode { handle, label, metadata, position } => {
+                let label = label.trim();
+                let len = label.chars().count();
+                if len == 0 || len > MAX_LABEL_CHARS {
+                    return Err(fail(format!("label must be 1-{} characters", MAX_LABEL_CHARS)));
+                }
+                check_metadata(metadata).map_err(fail)?;
+                if let Some(handle) = handle {
+                    if handle.trim().is_empty() {
+                        return Err(fail("handle can't be empty".to_string()));
+                    }
+                    if handles.contains_key(handle) {
+                        return Err(fail(format!("handle '{}' is already used in this transaction", handle)));
+                    }
+                }
+                if position.is_some_and(|p| p.iter().any(|v| !v.is_finite())) {
+                    return Err(fail("position must be finite".to_string()));
+                }
+
+                let node_id = next_id;
+                next_id += 1;
+                let metadata_id = format!("runtime-{}", node_id);
+                let mut node = Node::new_with_id(metadata_id.clone(), Some(node_id)).with_label(label.to_string());
+                update_metadata(&mut node, metadata);
+                node.metadata.insert("metadataId".to_string(), metadata_id);
+                node.metadata.insert("createdBy".to_string(), actor.to_string());
+                match position {
+                    Some([x, y, z]) => {
+                        node.data.position.x = *x;
+                        node.data.position.y = *y;
+                        node.data.position.z = *z;
+                    }
+                    None => {
+                        unplaced.insert(node_id);
+                    }
+                }
+                index.insert(node_id, graph.nodes.len());
+                graph.nodes.push(node);
+                created_nodes.push(node_id);
+                if let Some(handle) = handle {
+                    handles.insert(handle.clone(), node_id);
+                }
+            }
+            TransactionOp::UpdateNode { .. } | TransactionOp::Retag { .. } => {
+                let (node, entries) = metadata_entries(op).map_err(fail)?;
+                let node_id = resolve(node, &index).map_err(fail)?;
+                let target = &mut graph.nodes[index[&node_id]];
+                if !created_nodes.contains(&node_id) && !updates.contains_key(&node_id) {
+                    before.push(NodeBefore { node_id, metadata: target.metadata.clone(), group: target.group.clone() });
+                }
+                update_metadata(target, &entries);
+                if !created_nodes.contains(&node_id) {
+                    updates.entry(node_id).or_default().extend(entries);
+                }
+            }
+            TransactionOp::RemoveNode { node, rewire } => {
+                let node_id = resolve(node, &index).map_err(fail)?;
+                if *rewire {
+                    match rewire::rewire_through(&graph.edges, node_id, rewiring, Utc::now()) {
+                        Ok(edges) => {
+                            created_edges.extend(edges.iter().map(|e| e.id.clone()));
+                            graph.edges.extend(edges);
+                        }
+                        Err(e) => warnings.push(format!("operation {} (remove_node): {}", i, e)),
+                    }
+                }
+                let (gone, kept): (Vec<Edge>, Vec<Edge>) = std::mem::take(&mut *graph.edges).into_iter()
+                    .partition(|e| e.source == node_id || e.target == node_id);
+                graph.edges = kept.into();
+                for edge in gone {
+                    match created_edges.iter().position(|id| *id == edge.id) {
+                        Some(pos) => {
+                            created_edges.remove(pos);
+                        }
+                        None => removed_edges.push(edge),
+                    }
+                }
+                let node = graph.nodes.remove(index[&node_id]);
+                index = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+                handles.retain(|_, id| *id != node_id);
+                updates.remove(&node_id);
+                unplaced.remove(&node_id);
+                match created_nodes.iter().position(|id| *id == node_id) {
+                    Some(pos) => {
+                        created_nodes.remove(pos);
+                    }
+                    None => removed_nodes.push(node),
+                }
+            }
+            TransactionOp::CreateEdge { source, target, weight, edge_type, affects_physics } => {
+                let source = resolve(source, &index).map_err(fail)?;
+                let target = resolve(target, &index).map_err(fail)?;
+                if source == target {
+                    return Err(fail("edge source and target must differ".to_string()));
+                }
+                if !weight.is_finite() || *weight <= 0.0 {
+                    return Err(fail("edge weight must be positive".to_string()));
+                }
+                let mut edge = Edge::new(source, target, *weight).credited_to(WeightSource::Manual, Utc::now());
+                edge.edge_type = Some(edge_type.clone().unwrap_or_else(|| TRANSACTION_EDGE_TYPE.to_string()));
+                edge.affects_physics = *affects_physics;
+                if graph.edges.iter().any(|e| e.id == edge.id) {
+                    return Err(fail(format!("edge {} already exists", edge.id)));
+                }
+                created_edges.push(edge.id.clone());
+                graph.edges.push(edge);
+            }
+            TransactionOp::RemoveEdge { edge_id } => {
+                let Some(pos) = graph.edges.iter().position(|e| &e.id == edge_id) else {
+                    return Err(fail(format!("edge {} not found", edge_id)));
+                };
+                let edge = graph.edges.remove(pos);
+                match created_edges.iter().position(|id| *id == edge.id) {
+                    Some(pos) => {
+                        created_edges.remove(pos);
+                    }
+                    None => removed_edges.push(edge),
+                }
+            }
+        }
+    }
+
+    let node_of = |id: &u32| graph.nodes.iter().find(|n| n.id == *id).cloned();
+    let edge_of = |id: &String| graph.edges.iter().find(|e| &e.id == id).cloned();
+    let diff = GraphDiff {
+        added_nodes: created_nodes.iter().filter_map(node_of).collect(),
+        updated_nodes: updates.into_iter().map(|(node_id, metadata)| NodeUpdate { node_id, metadata }).collect(),
+        removed_nodes: removed_nodes.iter().map(|n| n.id).collect(),
+        added_edges: created_edges.iter().filter_map(edge_of).collect(),
+        removed_edges: removed_edges.iter().map(|e| e.id.clone()).collect(),
+        ..Default::default()
+    };
+    Ok(AppliedTransaction {
+        diff,
+        handles,
+        unplaced,
+        undo: TransactionUndo {
+            transaction_id: 0,
+            actor: actor.to_string(),
+            created_nodes,
+            created_edges,
+            removed_nodes,
+            removed_edges,
+            updated_nodes: before,
+        },
+        warnings,
+    })
+}
+
+/// Reverses `undo` within `graph` and returns the diff that does it. Fails without
+/// changing anything if the graph has moved on in a way the undo can't be squared with,
+/// such as a rebuild dropping a node the transaction touched.
+pub fn revert(graph: &mut GraphData, undo: &TransactionUndo) -> Result<GraphDiff, String> {
+    let present: HashSet<u32> = graph.nodes.iter().map(|n| n.id).collect();
+    let restored: HashSet<u32> = undo.removed_nodes.iter().map(|n| n.id).collect();
+    // Nodes updated and then removed by the transaction come back with the removal
+    let gone = undo.created_nodes.iter()
+        .chain(undo.updated_nodes.iter().map(|b| &b.node_id).filter(|id| !restored.contains(id)))
+        .find(|id| !present.contains(id));
+    if let Some(id) = gone {
+        return Err(format!("node {} is no longer in the graph", id));
+    }
+    if let Some(node) = undo.removed_nodes.iter().find(|n| present.contains(&n.id)) {
+        return Err(format!("node {} has been re-created since", node.id));
+    }
+    let created: HashSet<u32> = undo.created_nodes.iter().copied().collect();
+    let dangling = undo.removed_edges.iter().find(|e| {
+        [e.source, e.target].iter().any(|id| created.contains(id) || !(present.contains(id) || restored.contains(id)))
+    });
+    if let Some(edge) = dangling {
+        return Err(format!("edge {} can't be restored, an endpoint is gone", edge.id));
+    }
+
+    let mut diff = GraphDiff::default();
+    let created_edges: HashSet<&str> = undo.created_edges.iter().map(String::as_str).collect();
+    graph.edges.retain(|e| {
+        let drop = created_edges.contains(e.id.as_str()) || created.contains(&e.source) || created.contains(&e.target);
+        if drop {
+            diff.removed_edges.push(e.id.clone());
+        }
+        !drop
+    });
+    graph.nodes.retain(|n| !created.contains(&n.id));
+    diff.removed_nodes = undo.created_nodes.clone();
+
+    for node in &undo.removed_nodes {
+        graph.nodes.push(node.clone());
+    }
+    for before in &undo.updated_nodes {
+        if let Some(node) = graph.nodes.iter_mut().find(|n| n.id == before.node_id) {
+            node.metadata = before.metadata.clone();
+            node.group = before.group.clone();
+            diff.updated_nodes.push(NodeUpdate { node_id: before.node_id, metadata: before.metadata.clone() });
+        }
+    }
+    // Restored nodes go out with their pre-transaction metadata
+    diff.added_nodes = undo.removed_nodes.iter()
+        .filter_map(|n| graph.nodes.iter().find(|g| g.id == n.id).cloned())
+        .collect();
+    for edge in &undo.removed_edges {
+        graph.edges.retain(|e| e.id != edge.id);
+        graph.edges.push(edge.clone());
+        diff.added_edges.push(edge.clone());
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> GraphData {
+        let mut graph = GraphData::new();
+        for id in 1..=3 {
+            graph.nodes.push(Node::new_with_id(format!("note-{}", id), Some(id)).with_label(format!("Note {}", id)));
+        }
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        graph
+    }
+
+    fn ops(json: serde_json::Value) -> Vec<TransactionOp> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_handles_resolve_and_undo_restores_the_graph() {
+        let mut graph = graph();
+        let original = graph.clone();
+        let batch = ops(serde_json::json!([
+            { "op": "create_node", "handle": "topic", "label": "Topic", "metadata": { "tags": "physics,notes" } },
+            { "op": "create_node", "handle": "child", "label": "Child" },
+            { "op": "create_edge", "source": "topic", "target": "child" },
+            { "op": "create_edge", "source": "topic", "target": 1, "weight": 2.0, "edgeType": "mentions" },
+            { "op": "retag", "node": 2, "tags": ["physics"] },
+            { "op": "update_node", "node": 2, "metadata": { "reviewed": "yes" } },
+            { "op": "remove_edge", "edgeId": "1-2" },
+            { "op": "remove_node", "node": 3 }
+        ]));
+        let applied = apply(&mut graph, &batch, "script", 100, &RewireSettings::default()).unwrap();
+
+        assert_eq!(applied.handles, BTreeMap::from([("topic".to_string(), 100), ("child".to_string(), 101)]));
+        let topic = graph.nodes.iter().find(|n| n.id == 100).unwrap();
+        assert_eq!(topic.group.as_deref(), Some("physics"));
+        assert_eq!(topic.metadata["createdBy"], "script");
+        let edges: Vec<(&str, &str)> = graph.edges.iter().map(|e| (e.id.as_str(), e.type_name())).collect();
+        assert_eq!(edges, vec![("100-101", "scripted"), ("100-1", "mentions")]);
+
+        // One diff with the net effect; node 2's two updates are folded together
+        let diff = &applied.diff;
+        assert_eq!(diff.added_nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![100, 101]);
+        assert_eq!(diff.removed_nodes, vec![3]);
+        assert_eq!(diff.removed_edges, vec!["1-2"]);
+        assert_eq!(diff.updated_nodes.len(), 1);
+        assert_eq!(diff.updated_nodes[0].metadata.len(), 2);
+        assert_eq!(applied.unplaced, HashSet::from([100, 101]));
+
+        let reverted = revert(&mut graph, &applied.undo).unwrap();
+        assert_eq!(reverted.removed_nodes, vec![100, 101]);
+        let mut ids: Vec<u32> = graph.nodes.iter().map(|n| n.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(graph.edges.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["1-2"]);
+        let node = graph.nodes.iter().find(|n| n.id == 2).unwrap();
+        assert_eq!(node.metadata, original.nodes[1].metadata);
+        assert_eq!(node.group, None);
+    }
+
+    #[test]
+    fn test_failures_name_the_operation() {
+        let batch = ops(serde_json::json!([
+            { "op": "create_node", "handle": "a", "label": "A" },
+            { "op": "create_edge", "source": "a", "target": "b" }
+        ]));
+        let err = apply(&mut graph(), &batch, "script", 100, &RewireSettings::default()).unwrap_err();
+        assert_eq!(err.op_index, Some(1));
+        assert_eq!(err.op.as_deref(), Some("create_edge"));
+        assert_eq!(err.to_string(), "operation 1 (create_edge): no node created earlier with handle 'b'");
+
+        // A handle only names nodes created before it is used
+        let batch = ops(serde_json::json!([
+            { "op": "retag", "node": "later", "tags": ["x"] },
+            { "op": "create_node", "handle": "later", "label": "Later" }
+        ]));
+        assert_eq!(apply(&mut graph(), &batch, "script", 100, &RewireSettings::default()).unwrap_err().op_index, Some(0));
+
+        let batch = ops(serde_json::json!([{ "op": "update_node", "node": 1, "metadata": { "fileName": "x" } }]));
+        assert_eq!(apply(&mut graph(), &batch, "script", 100, &RewireSettings::default()).unwrap_err().reason, "metadata key 'fileName' is reserved");
+        assert_eq!(apply(&mut graph(), &[], "script", 100, &RewireSettings::default()).unwrap_err().op_index, None);
+    }
+
+    #[test]
+    fn test_removing_a_node_rewires_only_when_asked() {
+        // 2 links 1 and 3, which have no other path between them
+        let mut linked = graph();
+        linked.edges.push(Edge::new(2, 3, 0.5));
+        let remove = |rewire: bool| ops(serde_json::json!([{ "op": "remove_node", "node": 2, "rewire": rewire }]));
+
+        let mut graph = linked.clone();
+        let plain = apply(&mut graph, &ops(serde_json::json!([{ "op": "remove_node", "node": 2 }])), "script", 100, &RewireSettings::default()).unwrap();
+        assert!(graph.edges.is_empty());
+        assert!(plain.diff.added_edges.is_empty());
+
+        let mut graph = linked.clone();
+        let applied = apply(&mut graph, &remove(true), "script", 100, &RewireSettings::default()).unwrap();
+        assert_eq!(applied.diff.removed_nodes, vec![2]);
+        assert_eq!(applied.diff.removed_edges, vec!["1-2", "2-3"]);
+        let added: Vec<(&str, f32)> = applied.diff.added_edges.iter().map(|e| (e.id.as_str(), e.weight)).collect();
+        assert_eq!(added, vec![("rewired-1-3", 0.5)]);
+        assert!(applied.warnings.is_empty());
+        // Undo drops the rewired edge along with restoring the node
+        revert(&mut graph, &applied.undo).unwrap();
+        let mut ids: Vec<&str> = graph.edges.iter().map(|e| e.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["1-2", "2-3"]);
+
+        let mut graph = linked.clone();
+        let capped = RewireSettings { max_edges: 0, ..Default::default() };
+        let applied = apply(&mut graph, &remove(true), "script", 100, &capped).unwrap();
+        assert!(graph.edges.is_empty());
+        assert_eq!(applied.warnings.len(), 1);
+    }
+}
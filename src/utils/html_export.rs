@@ -0,0 +1,286 @@
+//! Self-contained HTML export of a graph snapshot, for sharing with people who can't run
+//! the client. The page embeds the layout as JSON next to a small canvas viewer that is
+//! compiled into the binary, so the one file is all anyone needs.
+//!
+//! Pages are kept to a bounded size: past the budget the most connected nodes and the
+//! heaviest edges are kept, metadata is trimmed, and the page says so in a banner.
+//! The page is produced in pieces so a large export streams rather than being built whole.
+
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+
+const VIEWER_SCRIPT: &str = include_str!("html_viewer.js");
+const NODES_PER_CHUNK: usize = 500;
+const EDGES_PER_CHUNK: usize = 2000;
+
+/// Size limits for one exported page
+#[derive(Debug, Clone, Copy)]
+pub struct HtmlBudget {
+    pub max_nodes: usize,
+    pub max_edges: usize,
+    pub max_metadata_keys: usize,
+    // Applies to labels and metadata values alike
+    pub max_value_chars: usize,
+}
+
+impl Default for HtmlBudget {
+    fn default() -> Self {
+        Self { max_nodes: 5000, max_edges: 20000, max_metadata_keys: 12, max_value_chars: 160 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PageNode {
+    id: u32,
+    label: String,
+    position: [f32; 3],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<f32>,
+    metadata: BTreeMap<String, String>,
+}
+
+enum Stage {
+    Head,
+    Nodes(usize),
+    Edges(usize),
+    Tail,
+    Done,
+}
+
+/// An export ready to be written out; iterating yields the page in order
+pub struct HtmlSnapshot {
+    title: String,
+    nodes: Vec<PageNode>,
+    edges: Vec<(u32, u32, f32)>,
+    notices: Vec<String>,
+    stage: Stage,
+}
+
+fn trim_chars(text: &str, max_chars: usize) -> (String, bool) {
+    if text.chars().count() <= max_chars {
+        return (text.to_string(), false);
+    }
+    let mut cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    cut.push('…');
+    (cut, true)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// JSON that can sit inside a <script> element: no "</script>" or "<!--" can appear
+fn script_safe_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "null".to_string())
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+/// Picks what goes on the page from `nodes` and `edges`, which should come from one snapshot
+pub fn build(nodes: &[Node], edges: &[Edge], title: &str, budget: HtmlBudget) -> HtmlSnapshot {
+    let mut notices = Vec::new();
+    let mut degree: HashMap<u32, usize> = HashMap::new();
+    for edge in edges {
+        *degree.entry(edge.source).or_default() += 1;
+        *degree.entry(edge.target).or_default() += 1;
+    }
+
+    let mut picked: Vec<&Node> = nodes.iter().collect();
+    if picked.len() > budget.max_nodes {
+        picked.sort_by_key(|n| (std::cmp::Reverse(degree.get(&n.id).copied().unwrap_or(0)), n.id));
+        picked.truncate(budget.max_nodes);
+        notices.push(format!("Showing the {} most connected of {} nodes", budget.max_nodes, nodes.len()));
+    }
+    let kept: HashSet<u32> = picked.iter().map(|n| n.id).collect();
+
+    let mut trimmed = false;
+    let page_nodes: Vec<PageNode> = picked.iter().map(|node| {
+        let (label, cut) = trim_chars(&node.label, budget.max_value_chars);
+        trimmed |= cut;
+        let mut keys: Vec<&String> = node.metadata.keys().collect();
+        keys.sort();
+        trimmed |= keys.len() > budget.max_metadata_keys;
+        let metadata = keys.into_iter().take(budget.max_metadata_keys).map(|key| {
+            let (value, cut) = trim_chars(&node.metadata[key], budget.max_value_chars);
+            trimmed |= cut;
+            (key.clone(), value)
+        }).collect();
+        let p = node.data.position;
+        PageNode { id: node.id, label, position: [p.x, p.y, p.z], color: node.color.clone(), size: node.size, metadata }
+    }).collect();
+    if trimmed {
+        notices.push(format!(
+            "Metadata trimmed to {} keys and {} characters per value",
+            budget.max_metadata_keys, budget.max_value_chars
+        ));
+    }
+
+    let mut page_edges: Vec<(u32, u32, f32)> = edges.iter()
+        .filter(|e| kept.contains(&e.source) && kept.contains(&e.target))
+        .map(|e| (e.source, e.target, e.weight))
+        .collect();
+    if page_edges.len() > budget.max_edges {
+        notices.push(format!("Showing the {} heaviest of {} edges", budget.max_edges, page_edges.len()));
+        page_edges.sort_by(|a, b| b.2.total_cmp(&a.2));
+        page_edges.truncate(budget.max_edges);
+    }
+
+    HtmlSnapshot { title: title.to_string(), nodes: page_nodes, edges: page_edges, notices, stage: Stage::Head }
+}
+
+impl HtmlSnapshot {
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// What was cut to keep the page within budget; shown in the page's banner
+    pub fn notices(&self) -> &[String] {
+        &self.notices
+    }
+
+    fn head(&self) -> String {
+        let title = escape_html(&self.title);
+        let banner = if self.notices.is_empty() {
+            String::new()
+        } else {
+            let lines: Vec<String> = self.notices.iter().map(|n| escape_html(n)).collect();
+            format!("<div class=\"banner\">{}</div>\n", lines.join("<br>"))
+        };
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>\nbody{{margin:0;background:#111;color:#ddd;font-family:sans-serif;overflow:hidden}}\n\
+             .banner{{position:fixed;top:0;left:0;right:0;padding:6px 10px;background:#7a5a00;color:#fff;font-size:13px}}\n\
+             #info{{display:none;position:fixed;right:10px;bottom:10px;max-width:360px;max-height:50%;overflow:auto;\
+             padding:8px;background:rgba(0,0,0,0.8);font-size:12px}}\n</style>\n</head>\n<body>\n{banner}\
+             <canvas id=\"graph\"></canvas>\n<div id=\"info\"></div>\n\
+             <script id=\"graph-data\" type=\"application/json\">{{\"title\":{json_title},\"nodes\":[",
+            title = title,
+            banner = banner,
+            json_title = script_safe_json(&self.title),
+        )
+    }
+
+    fn tail(&self) -> String {
+        format!("]}}</script>\n<script>\n{}</script>\n</body>\n</html>\n", VIEWER_SCRIPT)
+    }
+}
+
+impl Iterator for HtmlSnapshot {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        let chunk = match self.stage {
+            Stage::Head => {
+                self.stage = Stage::Nodes(0);
+                self.head()
+            }
+            Stage::Nodes(start) => {
+                let end = (start + NODES_PER_CHUNK).min(self.nodes.len());
+                let items: Vec<String> = self.nodes[start..end].iter().map(script_safe_json).collect();
+                let mut chunk = if start > 0 && !items.is_empty() { "," } else { "" }.to_string();
+                chunk.push_str(&items.join(","));
+                if end == self.nodes.len() {
+                    chunk.push_str("],\"edges\":[");
+                    self.stage = Stage::Edges(0);
+                } else {
+                    self.stage = Stage::Nodes(end);
+                }
+                chunk
+            }
+            Stage::Edges(start) => {
+                let end = (start + EDGES_PER_CHUNK).min(self.edges.len());
+                let items: Vec<String> = self.edges[start..end].iter().map(script_safe_json).collect();
+                let mut chunk = if start > 0 && !items.is_empty() { "," } else { "" }.to_string();
+                chunk.push_str(&items.join(","));
+                self.stage = if end == self.edges.len() { Stage::Tail } else { Stage::Edges(end) };
+                chunk
+            }
+            Stage::Tail => {
+                self.stage = Stage::Done;
+                self.tail()
+            }
+            Stage::Done => return None,
+        };
+        Some(Bytes::from(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(count: u32) -> (Vec<Node>, Vec<Edge>) {
+        let nodes = (1..=count).map(|id| {
+            let mut node = Node::new_with_id(format!("note-{}", id), Some(id)).with_label(format!("Note {}", id));
+            node.data.position.x = id as f32;
+            node
+        }).collect();
+        let edges = (2..=count).map(|id| Edge::new(1, id, id as f32)).collect();
+        (nodes, edges)
+    }
+
+    // The page as one string, checked for balanced tags and a single document
+    fn render(snapshot: HtmlSnapshot) -> String {
+        let page: Vec<u8> = snapshot.flat_map(|chunk| chunk.to_vec()).collect();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.trim_end().ends_with("</html>"));
+        assert_eq!(page.matches("<html").count(), 1);
+        for tag in ["html", "head", "body", "script", "style", "title"] {
+            assert_eq!(page.matches(&format!("<{}", tag)).count(), page.matches(&format!("</{}>", tag)).count(), "unbalanced <{}>", tag);
+        }
+        page
+    }
+
+    fn embedded(page: &str) -> serde_json::Value {
+        let start = page.find("type=\"application/json\">").unwrap() + "type=\"application/json\">".len();
+        let end = start + page[start..].find("</script>").unwrap();
+        serde_json::from_str(&page[start..end]).unwrap()
+    }
+
+    #[test]
+    fn test_page_embeds_every_node_across_chunks() {
+        let (nodes, edges) = graph(1200);
+        let snapshot = build(&nodes, &edges, "Knowledge graph", HtmlBudget::default());
+        assert!(snapshot.notices().is_empty());
+        let page = render(snapshot);
+        let data = embedded(&page);
+        assert_eq!(data["nodes"].as_array().unwrap().len(), 1200);
+        assert_eq!(data["edges"].as_array().unwrap().len(), 1199);
+        assert_eq!(data["nodes"][4]["position"][0], 5.0);
+        assert!(!page.contains("class=\"banner\""));
+        assert!(page.contains("getElementById(\"graph-data\")"));
+    }
+
+    #[test]
+    fn test_over_budget_pages_are_capped_with_a_banner() {
+        let (mut nodes, edges) = graph(50);
+        nodes[0].label = "Hub </script><script>alert(1)</script>".to_string();
+        nodes[0].metadata.insert("summary".to_string(), "x".repeat(500));
+        let budget = HtmlBudget { max_nodes: 10, max_edges: 5, max_metadata_keys: 12, max_value_chars: 40 };
+        let snapshot = build(&nodes, &edges, "Big <graph>", budget);
+        assert_eq!(snapshot.node_count(), 10);
+        assert_eq!(snapshot.edge_count(), 5);
+        assert_eq!(snapshot.notices().len(), 3);
+
+        let page = render(snapshot);
+        assert!(page.contains("Showing the 10 most connected of 50 nodes"));
+        assert!(page.contains("<title>Big &lt;graph&gt;</title>"));
+        let data = embedded(&page);
+        // The hub is the most connected and ties go to the lowest ids; its label survives intact but harmless
+        assert_eq!(data["nodes"][0]["label"], "Hub </script><script>alert(1)</script>");
+        assert_eq!(data["nodes"][0]["metadata"]["summary"].as_str().unwrap().chars().count(), 40);
+        assert_eq!(data["edges"][0], serde_json::json!([1, 10, 10.0]));
+    }
+}
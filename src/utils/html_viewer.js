@@ -0,0 +1,121 @@
+// Minimal viewer for graph snapshots exported as HTML. Reads the embedded JSON and
+// draws the layout from above (x/y) on a 2-D canvas; drag to pan, scroll to zoom,
+// hover for a label, click for the node's metadata.
+(function () {
+  "use strict";
+  var data = JSON.parse(document.getElementById("graph-data").textContent);
+  var canvas = document.getElementById("graph");
+  var info = document.getElementById("info");
+  var ctx = canvas.getContext("2d");
+  var byId = {};
+  data.nodes.forEach(function (n) { byId[n.id] = n; });
+
+  var view = { x: 0, y: 0, scale: 1 };
+  var hover = null;
+
+  function fit() {
+    if (!data.nodes.length) return;
+    var minX = Infinity, minY = Infinity, maxX = -Infinity, maxY = -Infinity;
+    data.nodes.forEach(function (n) {
+      minX = Math.min(minX, n.position[0]); maxX = Math.max(maxX, n.position[0]);
+      minY = Math.min(minY, n.position[1]); maxY = Math.max(maxY, n.position[1]);
+    });
+    var w = Math.max(maxX - minX, 1), h = Math.max(maxY - minY, 1);
+    view.scale = 0.9 * Math.min(canvas.width / w, canvas.height / h);
+    view.x = (minX + maxX) / 2;
+    view.y = (minY + maxY) / 2;
+  }
+
+  function toScreen(n) {
+    return [
+      canvas.width / 2 + (n.position[0] - view.x) * view.scale,
+      canvas.height / 2 - (n.position[1] - view.y) * view.scale
+    ];
+  }
+
+  function radius(n) {
+    return Math.max(2, Math.min(12, (n.size || 10) * 0.3));
+  }
+
+  function draw() {
+    ctx.clearRect(0, 0, canvas.width, canvas.height);
+    ctx.strokeStyle = "rgba(180,180,180,0.35)";
+    ctx.lineWidth = 1;
+    ctx.beginPath();
+    data.edges.forEach(function (e) {
+      var a = byId[e[0]], b = byId[e[1]];
+      if (!a || !b) return;
+      var p = toScreen(a), q = toScreen(b);
+      ctx.moveTo(p[0], p[1]);
+      ctx.lineTo(q[0], q[1]);
+    });
+    ctx.stroke();
+    data.nodes.forEach(function (n) {
+      var p = toScreen(n);
+      ctx.fillStyle = n.color || "#6699ff";
+      ctx.beginPath();
+      ctx.arc(p[0], p[1], radius(n), 0, 2 * Math.PI);
+      ctx.fill();
+    });
+    if (hover) {
+      var p = toScreen(hover);
+      ctx.fillStyle = "#fff";
+      ctx.font = "13px sans-serif";
+      ctx.fillText(hover.label, p[0] + radius(hover) + 4, p[1] - 4);
+    }
+  }
+
+  function nodeAt(x, y) {
+    var best = null, bestDist = 100;
+    data.nodes.forEach(function (n) {
+      var p = toScreen(n), d = (p[0] - x) * (p[0] - x) + (p[1] - y) * (p[1] - y);
+      if (d < bestDist) { best = n; bestDist = d; }
+    });
+    return best;
+  }
+
+  function resize() {
+    canvas.width = window.innerWidth;
+    canvas.height = window.innerHeight;
+    draw();
+  }
+
+  var drag = null;
+  canvas.addEventListener("mousedown", function (ev) { drag = [ev.clientX, ev.clientY]; });
+  window.addEventListener("mouseup", function () { drag = null; });
+  canvas.addEventListener("mousemove", function (ev) {
+    if (drag) {
+      view.x -= (ev.clientX - drag[0]) / view.scale;
+      view.y += (ev.clientY - drag[1]) / view.scale;
+      drag = [ev.clientX, ev.clientY];
+    } else {
+      hover = nodeAt(ev.clientX, ev.clientY);
+    }
+    draw();
+  });
+  canvas.addEventListener("wheel", function (ev) {
+    ev.preventDefault();
+    view.scale *= ev.deltaY < 0 ? 1.1 : 1 / 1.1;
+    draw();
+  }, { passive: false });
+  canvas.addEventListener("click", function (ev) {
+    var n = nodeAt(ev.clientX, ev.clientY);
+    if (!n) { info.style.display = "none"; return; }
+    info.textContent = "";
+    var title = document.createElement("h3");
+    title.textContent = n.label;
+    info.appendChild(title);
+    Object.keys(n.metadata).forEach(function (k) {
+      var row = document.createElement("div");
+      row.textContent = k + ": " + n.metadata[k];
+      info.appendChild(row);
+    });
+    info.style.display = "block";
+  });
+  window.addEventListener("resize", resize);
+
+  canvas.width = window.innerWidth;
+  canvas.height = window.innerHeight;
+  fit();
+  draw();
+})();
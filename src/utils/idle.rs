@@ -0,0 +1,121 @@
+//! Pausing the simulation while nobody is watching. The graph actor reports the number
+//! of connected clients every so often; once it has been zero for the idle period the
+//! loop stops computing, and the next client to connect wakes it.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::config::IdleSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleChange {
+    None,
+    Sleep,
+    Wake,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdleStatus {
+    pub idle: bool,
+    pub always_on: bool,
+    pub clients: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_for_secs: Option<f32>,
+    // Physics steps computed since startup; frozen while idle
+    pub iterations: u64,
+}
+
+pub struct IdleTracker {
+    settings: IdleSettings,
+    clients: usize,
+    empty_since: Option<Instant>,
+    idle_since: Option<Instant>,
+}
+
+impl IdleTracker {
+    pub fn new(settings: IdleSettings) -> Self {
+        Self { settings, clients: 0, empty_since: None, idle_since: None }
+    }
+
+    pub fn settings(&self) -> &IdleSettings {
+        &self.settings
+    }
+
+    /// Switching to `always_on` wakes an idle loop straight away
+    pub fn set_settings(&mut self, settings: IdleSettings) -> IdleChange {
+        self.settings = settings;
+        if self.settings.always_on && self.idle_since.take().is_some() {
+            return IdleChange::Wake;
+        }
+        IdleChange::None
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle_since.is_some()
+    }
+
+    /// Records the connected-client count seen at `now`
+    pub fn observe(&mut self, clients: usize, now: Instant) -> IdleChange {
+        self.clients = clients;
+        if clients > 0 {
+            self.empty_since = None;
+            return match self.idle_since.take() {
+                Some(_) => IdleChange::Wake,
+                None => IdleChange::None,
+            };
+        }
+        let empty_since = *self.empty_since.get_or_insert(now);
+        if self.settings.always_on || self.idle_since.is_some() {
+            return IdleChange::None;
+        }
+        let idle_after = Duration::from_secs_f32(self.settings.idle_after_secs.max(0.0));
+        if now.duration_since(empty_since) >= idle_after {
+            self.idle_since = Some(now);
+            return IdleChange::Sleep;
+        }
+        IdleChange::None
+    }
+
+    pub fn status(&self, now: Instant, iterations: u64) -> IdleStatus {
+        IdleStatus {
+            idle: self.is_idle(),
+            always_on: self.settings.always_on,
+            clients: self.clients,
+            idle_for_secs: self.idle_since.map(|since| now.duration_since(since).as_secs_f32()),
+            iterations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleeps_after_idle_period_and_wakes_on_connect() {
+        let settings = IdleSettings { idle_after_secs: 10.0, ..Default::default() };
+        let mut tracker = IdleTracker::new(settings.clone());
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert_eq!(tracker.observe(1, at(0)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(1)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(10)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(11)), IdleChange::Sleep);
+        assert_eq!(tracker.observe(0, at(20)), IdleChange::None);
+        assert_eq!(tracker.status(at(20), 5).idle_for_secs, Some(9.0));
+        assert_eq!(tracker.observe(2, at(21)), IdleChange::Wake);
+        assert!(!tracker.is_idle());
+
+        // A brief reconnect restarts the idle period
+        assert_eq!(tracker.observe(0, at(30)), IdleChange::None);
+        assert_eq!(tracker.observe(1, at(35)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(36)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(45)), IdleChange::None);
+        assert_eq!(tracker.observe(0, at(46)), IdleChange::Sleep);
+
+        assert_eq!(tracker.set_settings(IdleSettings { always_on: true, ..settings }), IdleChange::Wake);
+        assert_eq!(tracker.observe(0, at(500)), IdleChange::None);
+    }
+}
@@ -0,0 +1,17 @@
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Serializes `value` to `path` via a temp file and rename, so a crash mid-write
+/// never leaves a truncated store behind.
+pub fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {:?}: {}", parent, e))?;
+    }
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {:?}: {}", path, e))?;
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, json).map_err(|e| format!("Failed to write {:?}: {}", tmp_path, e))?;
+    fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace {:?}: {}", path, e))
+}
@@ -0,0 +1,211 @@
+//! Label placement for stills and exports. Labels are laid out in a 2-D projection of
+//! the layout, most important node first: each label takes the first spot around its
+//! node that no earlier label covers, and a label with no free spot is hidden. Placed
+//! boxes are bucketed in a coarse grid, so each candidate spot only checks its
+//! neighbourhood and the pass stays near-linear in the node count.
+
+use glam::{Vec2, Vec3};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::utils::gltf_export::{DEFAULT_NODE_SIZE, NODE_RADIUS_PER_SIZE};
+use crate::utils::projection::Projection;
+
+// Longer labels are measured as if cut here, so no box spans more than a few grid cells
+pub const MAX_LABEL_CHARS: usize = 40;
+// Label box size in scene units, for a label drawn at the default size
+const CHAR_WIDTH: f32 = 0.06;
+const LABEL_HEIGHT: f32 = 0.12;
+const GAP: f32 = 0.02;
+// Rings of candidate spots, each one label height further out
+const RINGS: usize = 3;
+const GRID_CELL: f32 = 4.0 * LABEL_HEIGHT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelPlacement {
+    pub node_id: u32,
+    // 0 is drawn last, on top of everything; hidden labels keep their rank
+    pub priority: usize,
+    pub visible: bool,
+    // Label centre relative to the node, in projected units
+    pub offset: [f32; 2],
+    // The same offset in scene space, for 3-D renderers
+    pub offset3d: [f32; 3],
+    // min x, min y, max x, max y of the label box in projected space
+    pub bounds: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LabelBox {
+    min: Vec2,
+    max: Vec2,
+}
+
+impl LabelBox {
+    fn at(min: Vec2, size: Vec2) -> Self {
+        Self { min, max: min + size }
+    }
+
+    fn overlaps(&self, other: &LabelBox) -> bool {
+        self.min.x < other.max.x && other.min.x < self.max.x && self.min.y < other.max.y && other.min.y < self.max.y
+    }
+
+    fn center(&self) -> Vec2 {
+        (self.min + self.max) * 0.5
+    }
+}
+
+fn cell(v: f32) -> i64 {
+    (v / GRID_CELL).floor() as i64
+}
+
+fn cells(b: &LabelBox) -> impl Iterator<Item = (i64, i64)> {
+    let (x0, x1, y0, y1) = (cell(b.min.x), cell(b.max.x), cell(b.min.y), cell(b.max.y));
+    (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+}
+
+/// Where a label box of `size` may go around a node at `p` with radius `r`, closest first
+fn candidates(p: Vec2, r: f32, size: Vec2) -> impl Iterator<Item = LabelBox> {
+    (0..RINGS).flat_map(move |ring| {
+        let d = r + GAP + ring as f32 * LABEL_HEIGHT;
+        let (w, h) = (size.x, size.y);
+        [
+            Vec2::new(p.x + d, p.y - h * 0.5),
+            Vec2::new(p.x - d - w, p.y - h * 0.5),
+            Vec2::new(p.x - w * 0.5, p.y + d),
+            Vec2::new(p.x - w * 0.5, p.y - d - h),
+            Vec2::new(p.x + d, p.y + d),
+            Vec2::new(p.x - d - w, p.y + d),
+            Vec2::new(p.x + d, p.y - d - h),
+            Vec2::new(p.x - d - w, p.y - d - h),
+        ]
+        .into_iter()
+        .map(move |min| LabelBox::at(min, size))
+    })
+}
+
+// Maps a projected offset back into the scene along the projection's rows
+fn unproject(offset: Vec2, projection: &Projection) -> Vec3 {
+    let [a, b] = projection.rows.map(Vec3::from_array);
+    let along = |row: Vec3, v: f32| if row.length_squared() > f32::EPSILON { row * (v / row.length_squared()) } else { Vec3::ZERO };
+    along(a, offset.x) + along(b, offset.y)
+}
+
+/// Places a label for every node. Higher degree wins the better spots, then larger nodes,
+/// then lower ids, so the ordering is stable for a given graph.
+pub fn place_labels(nodes: &[Node], edges: &[Edge], projection: &Projection) -> Vec<LabelPlacement> {
+    let mut degree: HashMap<u32, usize> = HashMap::new();
+    for edge in edges {
+        *degree.entry(edge.source).or_default() += 1;
+        *degree.entry(edge.target).or_default() += 1;
+    }
+    let size = |n: &Node| n.size.unwrap_or(DEFAULT_NODE_SIZE).max(0.0);
+    let mut order: Vec<&Node> = nodes.iter().collect();
+    order.sort_by(|a, b| {
+        degree.get(&b.id).unwrap_or(&0).cmp(degree.get(&a.id).unwrap_or(&0))
+            .then(size(b).total_cmp(&size(a)))
+            .then(a.id.cmp(&b.id))
+    });
+
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    let mut placed: Vec<LabelBox> = Vec::new();
+    order.iter().enumerate()
+        .map(|(priority, node)| {
+            let p = Vec2::from(projection.apply(node.data.position));
+            let text = if node.label.is_empty() { &node.metadata_id } else { &node.label };
+            let chars = text.chars().count().clamp(1, MAX_LABEL_CHARS);
+            let label_size = Vec2::new(chars as f32 * CHAR_WIDTH, LABEL_HEIGHT);
+            let free = candidates(p, size(node) * NODE_RADIUS_PER_SIZE, label_size).find(|candidate| {
+                !cells(candidate).any(|c| grid.get(&c).is_some_and(|ids| ids.iter().any(|&i| placed[i].overlaps(candidate))))
+            });
+            let (visible, label) = match free {
+                Some(label) => {
+                    for c in cells(&label) {
+                        grid.entry(c).or_default().push(placed.len());
+                    }
+                    placed.push(label);
+                    (true, label)
+                }
+                // Hidden labels still report the preferred spot, for renderers that fade rather than drop
+                None => (false, candidates(p, size(node) * NODE_RADIUS_PER_SIZE, label_size).next().unwrap()),
+            };
+            let offset = label.center() - p;
+            LabelPlacement {
+                node_id: node.id,
+                priority,
+                visible,
+                offset: offset.to_array(),
+                offset3d: unproject(offset, projection).to_array(),
+                bounds: [label.min.x, label.min.y, label.max.x, label.max.y],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn node(id: u32, label: &str, x: f32, y: f32) -> Node {
+        let mut node = Node::new_with_id(format!("{}.md", label), Some(id)).with_label(label.to_string());
+        node.data.position.x = x;
+        node.data.position.y = y;
+        node.data.position.z = 0.0;
+        node
+    }
+
+    fn assert_no_overlaps(labels: &[LabelPlacement]) {
+        let boxes: Vec<LabelBox> = labels.iter()
+            .filter(|l| l.visible)
+            .map(|l| LabelBox { min: Vec2::new(l.bounds[0], l.bounds[1]), max: Vec2::new(l.bounds[2], l.bounds[3]) })
+            .collect();
+        for (i, a) in boxes.iter().enumerate() {
+            for b in &boxes[i + 1..] {
+                assert!(!a.overlaps(b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_worst_case_pile_has_no_overlapping_labels() {
+        // Twenty long-labelled nodes on the same spot, plus a tight cluster beside it
+        let mut nodes: Vec<Node> = (0..20).map(|i| node(i, "a fairly long label for a node", 0.0, 0.0)).collect();
+        nodes.extend((20..36).map(|i| node(i, "dense", 0.3 + (i % 4) as f32 * 0.05, (i / 4) as f32 * 0.05)));
+        let edges = vec![Edge::new(7, 1, 1.0), Edge::new(7, 2, 1.0)];
+
+        let labels = place_labels(&nodes, &edges, &Projection::XY);
+        assert_eq!(labels.len(), nodes.len());
+        assert_no_overlaps(&labels);
+        // The best-connected node goes first and always gets its label
+        assert_eq!((labels[0].node_id, labels[0].visible), (7, true));
+        assert!(labels.iter().filter(|l| l.visible).count() > 8);
+        assert!(labels.iter().any(|l| !l.visible));
+
+        // The 3-D offset lies in the projection plane
+        let xz = place_labels(&nodes[..1], &[], &Projection::XZ);
+        assert_eq!(xz[0].offset3d[1], 0.0);
+        assert_eq!(xz[0].offset3d[0], xz[0].offset[0]);
+    }
+
+    #[test]
+    fn test_large_graph_is_placed_quickly() {
+        // Deterministic scatter of 10k nodes over a 40x40 area
+        let mut seed: u32 = 12345;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1u32 << 24) as f32 * 40.0
+        };
+        let nodes: Vec<Node> = (0..10_000).map(|i| node(i, &format!("node {}", i), next(), next())).collect();
+        let edges: Vec<Edge> = (1..10_000).map(|i| Edge::new(i, i / 2, 1.0)).collect();
+
+        let start = Instant::now();
+        let labels = place_labels(&nodes, &edges, &Projection::XY);
+        assert!(start.elapsed() < Duration::from_secs(3), "took {:?}", start.elapsed());
+        assert_eq!(labels.len(), 10_000);
+        assert_no_overlaps(&labels);
+    }
+}
@@ -0,0 +1,201 @@
+//! How far one layout of a graph is from another. Raw displacement counts every move;
+//! the Procrustes RMS first finds the rotation and translation that best line the two
+//! layouts up, so a layout that merely turned or drifted as a whole scores zero.
+
+use glam::{DQuat, DVec3, Vec3};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBin {
+    pub from: f32,
+    pub to: f32,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplacementStats {
+    pub count: usize,
+    pub mean: f32,
+    pub p95: f32,
+    pub max: f32,
+    pub histogram: Vec<HistogramBin>,
+}
+
+/// Nodes of two layouts matched by key
+pub struct LayoutPairing<K> {
+    pub matched: Vec<(K, Vec3, Vec3)>,
+    pub only_a: Vec<K>,
+    pub only_b: Vec<K>,
+}
+
+pub fn pair_layouts<K: Clone + Eq + std::hash::Hash + Ord>(a: &HashMap<K, Vec3>, b: &HashMap<K, Vec3>) -> LayoutPairing<K> {
+    let mut matched: Vec<(K, Vec3, Vec3)> = a.iter()
+        .filter_map(|(key, pa)| b.get(key).map(|pb| (key.clone(), *pa, *pb)))
+        .collect();
+    matched.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut only_a: Vec<K> = a.keys().filter(|k| !b.contains_key(*k)).cloned().collect();
+    let mut only_b: Vec<K> = b.keys().filter(|k| !a.contains_key(*k)).cloned().collect();
+    only_a.sort();
+    only_b.sort();
+    LayoutPairing { matched, only_a, only_b }
+}
+
+/// Mean, 95th percentile, max and an even-width histogram over `[0, max]`
+pub fn displacement_stats(distances: &[f32], bins: usize) -> DisplacementStats {
+    if distances.is_empty() {
+        return DisplacementStats::default();
+    }
+    let mut sorted = distances.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let max = sorted[sorted.len() - 1];
+    let mean = (sorted.iter().map(|d| *d as f64).sum::<f64>() / sorted.len() as f64) as f32;
+    // Nearest-rank percentile
+    let rank = ((0.95 * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    let p95 = sorted[rank - 1];
+
+    let bins = bins.max(1);
+    let width = if max > 0.0 { max / bins as f32 } else { 1.0 };
+    let mut histogram: Vec<HistogramBin> = (0..bins)
+        .map(|i| HistogramBin { from: i as f32 * width, to: (i + 1) as f32 * width, count: 0 })
+        .collect();
+    for d in &sorted {
+        let bin = ((d / width) as usize).min(bins - 1);
+        histogram[bin].count += 1;
+    }
+    DisplacementStats { count: sorted.len(), mean, p95, max, histogram }
+}
+
+fn centroid(points: &[Vec3]) -> DVec3 {
+    points.iter().map(|p| p.as_dvec3()).sum::<DVec3>() / points.len().max(1) as f64
+}
+
+// Eigenvector of the largest eigenvalue of a symmetric 4x4 matrix, by Jacobi rotations
+fn dominant_eigenvector(mut m: [[f64; 4]; 4]) -> [f64; 4] {
+    let mut v = [[0.0; 4]; 4];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for _ in 0..64 {
+        let off: f64 = (0..4).flat_map(|i| (0..4).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| m[i][j] * m[i][j])
+            .sum();
+        if off < 1e-18 {
+            break;
+        }
+        for p in 0..3 {
+            for q in p + 1..4 {
+                if m[p][q].abs() < 1e-15 {
+                    continue;
+                }
+                let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in m.iter_mut() {
+                    let (mkp, mkq) = (row[p], row[q]);
+                    row[p] = c * mkp - s * mkq;
+                    row[q] = s * mkp + c * mkq;
+                }
+                let (row_p, row_q) = (m[p], m[q]);
+                for (k, (mpk, mqk)) in row_p.iter().zip(&row_q).enumerate() {
+                    m[p][k] = c * mpk - s * mqk;
+                    m[q][k] = s * mpk + c * mqk;
+                }
+                for row in v.iter_mut() {
+                    let (vp, vq) = (row[p], row[q]);
+                    row[p] = c * vp - s * vq;
+                    row[q] = s * vp + c * vq;
+                }
+            }
+        }
+    }
+    let best = (0..4).max_by(|a, b| m[*a][*a].total_cmp(&m[*b][*b])).unwrap_or(0);
+    [v[0][best], v[1][best], v[2][best], v[3][best]]
+}
+
+/// Rotation and translation taking `a` as close to `b` as possible (Horn's quaternion
+/// method). Points are matched by index.
+pub fn align(a: &[Vec3], b: &[Vec3]) -> (DQuat, DVec3) {
+    let (ca, cb) = (centroid(a), centroid(b));
+    let mut s = [[0.0f64; 3]; 3];
+    for (pa, pb) in a.iter().zip(b) {
+        let (pa, pb) = (pa.as_dvec3() - ca, pb.as_dvec3() - cb);
+        for (row, ai) in s.iter_mut().zip(pa.to_array()) {
+            for (cell, bj) in row.iter_mut().zip(pb.to_array()) {
+                *cell += ai * bj;
+            }
+        }
+    }
+    let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = s;
+    let n = [
+        [sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+        [syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+        [szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+        [sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+    ];
+    let [w, x, y, z] = dominant_eigenvector(n);
+    let rotation = DQuat::from_xyzw(x, y, z, w).normalize();
+    let rotation = if rotation.is_finite() { rotation } else { DQuat::IDENTITY };
+    (rotation, cb - rotation * ca)
+}
+
+/// RMS distance between matched points once `a` is rotated and translated onto `b`
+pub fn procrustes_rms(a: &[Vec3], b: &[Vec3]) -> f32 {
+    let len = a.len().min(b.len());
+    if len == 0 {
+        return 0.0;
+    }
+    let (a, b) = (&a[..len], &b[..len]);
+    let (rotation, translation) = align(a, b);
+    let sum: f64 = a.iter().zip(b)
+        .map(|(pa, pb)| (rotation * pa.as_dvec3() + translation - pb.as_dvec3()).length_squared())
+        .sum();
+    (sum / len as f64).sqrt() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloud() -> Vec<Vec3> {
+        (0..40).map(|i| {
+            let t = i as f32 * 0.37;
+            Vec3::new(t.sin() * 10.0, (t * 1.7).cos() * 6.0, t * 0.5 - 5.0)
+        }).collect()
+    }
+
+    #[test]
+    fn test_rigid_motion_is_not_drift() {
+        let a = cloud();
+        let rotation = glam::Quat::from_euler(glam::EulerRot::XYZ, 0.4, -1.1, 2.3);
+        let b: Vec<Vec3> = a.iter().map(|p| rotation * *p + Vec3::new(30.0, -4.0, 12.0)).collect();
+
+        let raw: Vec<f32> = a.iter().zip(&b).map(|(pa, pb)| pa.distance(*pb)).collect();
+        assert!(displacement_stats(&raw, 10).mean > 10.0);
+        assert!(procrustes_rms(&a, &b) < 1e-3, "rms {}", procrustes_rms(&a, &b));
+
+        // A real change on top of the rigid motion still shows up
+        let mut moved = b.clone();
+        moved[0] += Vec3::new(0.0, 0.0, 20.0);
+        let rms = procrustes_rms(&a, &moved);
+        assert!(rms > 1.0 && rms < 20.0, "rms {}", rms);
+    }
+
+    #[test]
+    fn test_stats_and_pairing() {
+        let distances: Vec<f32> = (1..=20).map(|d| d as f32).collect();
+        let stats = displacement_stats(&distances, 4);
+        assert_eq!((stats.count, stats.mean, stats.p95, stats.max), (20, 10.5, 19.0, 20.0));
+        assert_eq!(stats.histogram.iter().map(|b| b.count).collect::<Vec<_>>(), vec![4, 5, 5, 6]);
+        assert_eq!(displacement_stats(&[], 4), DisplacementStats::default());
+
+        let a = HashMap::from([("x", Vec3::ZERO), ("y", Vec3::ONE)]);
+        let b = HashMap::from([("y", Vec3::ONE), ("z", Vec3::X)]);
+        let pairing = pair_layouts(&a, &b);
+        assert_eq!(pairing.matched.len(), 1);
+        assert_eq!((pairing.only_a, pairing.only_b), (vec!["x"], vec!["z"]));
+    }
+}
@@ -0,0 +1,339 @@
+//! One number for how good a layout is. Three parts, each in [0, 1] where 1 is best:
+//! normalized stress of edge lengths against the lengths their weights ask for, the
+//! spread of weighted edge lengths, and how many nodes sit on top of each other. The
+//! `Nudger` hill-climbs spring and repulsion when the score stops improving.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::config::LayoutQualitySettings;
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::utils::gltf_export::{DEFAULT_NODE_SIZE, NODE_RADIUS_PER_SIZE};
+
+// Weights this small would ask for near-infinite edges
+const MIN_WEIGHT: f32 = 0.01;
+const STRESS_WEIGHT: f32 = 0.4;
+const SPREAD_WEIGHT: f32 = 0.3;
+const OVERLAP_WEIGHT: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutQuality {
+    // Composite, 0 (bad) to 1 (good)
+    pub score: f32,
+    // Edge lengths against 1/weight at the best-fitting scale; 0 is a perfect fit, 1 no fit at all
+    pub stress: f32,
+    // Coefficient of variation of length × weight; 0 when every edge is as long as its weight asks
+    pub edge_length_cv: f32,
+    pub mean_edge_length: f32,
+    // Node pairs closer than their radii allow
+    pub overlaps: usize,
+}
+
+fn radius(node: &Node) -> f32 {
+    node.size.unwrap_or(DEFAULT_NODE_SIZE).max(0.0) * NODE_RADIUS_PER_SIZE
+}
+
+fn position(node: &Node) -> Vec3 {
+    Vec3::new(node.data.position.x, node.data.position.y, node.data.position.z)
+}
+
+// Pairs of overlapping nodes, found through a grid of cells one node diameter wide
+fn count_overlaps(nodes: &[Node]) -> usize {
+    let max_radius = nodes.iter().map(radius).fold(0.0f32, f32::max);
+    if max_radius <= 0.0 {
+        return 0;
+    }
+    let cell = 2.0 * max_radius;
+    let key = |p: Vec3| ((p.x / cell).floor() as i64, (p.y / cell).floor() as i64, (p.z / cell).floor() as i64);
+    let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        grid.entry(key(position(node))).or_default().push(i);
+    }
+
+    let mut overlaps = 0;
+    for (i, node) in nodes.iter().enumerate() {
+        let p = position(node);
+        let (cx, cy, cz) = key(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(cell) = grid.get(&(cx + dx, cy + dy, cz + dz)) else { continue };
+                    // Each pair once
+                    for &j in cell.iter().filter(|&&j| j > i) {
+                        if p.distance(position(&nodes[j])) < radius(node) + radius(&nodes[j]) {
+                            overlaps += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    overlaps
+}
+
+pub fn evaluate(nodes: &[Node], edges: &[Edge]) -> LayoutQuality {
+    let positions: HashMap<u32, Vec3> = nodes.iter().map(|n| (n.id, position(n))).collect();
+    // (actual length, length the weight asks for up to scale)
+    let lengths: Vec<(f32, f32)> = edges.iter()
+        .filter_map(|e| {
+            let (a, b) = (positions.get(&e.source)?, positions.get(&e.target)?);
+            Some((a.distance(*b), 1.0 / e.weight.max(MIN_WEIGHT)))
+        })
+        .collect();
+
+    let (stress, edge_length_cv, mean_edge_length) = if lengths.is_empty() {
+        (0.0, 0.0, 0.0)
+    } else {
+        let n = lengths.len() as f64;
+        let sum_ll: f64 = lengths.iter().map(|&(l, _)| (l as f64).powi(2)).sum();
+        let sum_ld: f64 = lengths.iter().map(|&(l, d)| l as f64 * d as f64).sum();
+        let sum_dd: f64 = lengths.iter().map(|&(_, d)| (d as f64).powi(2)).sum();
+        let mean_length = lengths.iter().map(|&(l, _)| l as f64).sum::<f64>() / n;
+        if sum_ll <= f64::EPSILON {
+            // Every edge collapsed to a point
+            (1.0, 1.0, 0.0)
+        } else {
+            // Residual at the best scale over total, so it doesn't matter how big the layout is
+            let scale = sum_ld / sum_dd;
+            let residual: f64 = lengths.iter().map(|&(l, d)| (l as f64 - scale * d as f64).powi(2)).sum();
+            let weighted: Vec<f64> = lengths.iter().map(|&(l, d)| l as f64 / d as f64).collect();
+            let mean = weighted.iter().sum::<f64>() / n;
+            let variance = weighted.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / n;
+            ((residual / sum_ll) as f32, (variance.sqrt() / mean) as f32, mean_length as f32)
+        }
+    };
+
+    let overlaps = count_overlaps(nodes);
+    let overlap_ratio = if nodes.is_empty() { 0.0 } else { (overlaps as f32 / nodes.len() as f32).min(1.0) };
+    let score = STRESS_WEIGHT * (1.0 - stress.clamp(0.0, 1.0))
+        + SPREAD_WEIGHT / (1.0 + edge_length_cv)
+        + OVERLAP_WEIGHT * (1.0 - overlap_ratio);
+
+    LayoutQuality { score: score.clamp(0.0, 1.0), stress, edge_length_cv, mean_edge_length, overlaps }
+}
+
+/// The physics parameters the nudger may move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Knob {
+    SpringStrength,
+    RepulsionStrength,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicsKnobs {
+    pub spring_strength: f32,
+    pub repulsion_strength: f32,
+}
+
+impl PhysicsKnobs {
+    pub fn get(&self, knob: Knob) -> f32 {
+        match knob {
+            Knob::SpringStrength => self.spring_strength,
+            Knob::RepulsionStrength => self.repulsion_strength,
+        }
+    }
+}
+
+/// A change the nudger wants applied, or the verdict on the last one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum NudgeAction {
+    // Set `knob` to `to` and see whether the score improves
+    Try { knob: Knob, from: f32, to: f32, baseline: f32 },
+    // The trial helped; nothing to change
+    Keep { knob: Knob, value: f32, baseline: f32, score: f32 },
+    // The trial didn't help; set `knob` back to `to`
+    Revert { knob: Knob, from: f32, to: f32, baseline: f32, score: f32 },
+}
+
+impl NudgeAction {
+    /// The value to set, if the action changes anything
+    pub fn change(&self) -> Option<(Knob, f32)> {
+        match *self {
+            NudgeAction::Try { knob, to, .. } | NudgeAction::Revert { knob, to, .. } => Some((knob, to)),
+            NudgeAction::Keep { .. } => None,
+        }
+    }
+}
+
+// The moves tried in turn; a kept move is tried again before moving on
+const MOVES: [(Knob, f32); 4] = [
+    (Knob::SpringStrength, 1.0),
+    (Knob::RepulsionStrength, 1.0),
+    (Knob::SpringStrength, -1.0),
+    (Knob::RepulsionStrength, -1.0),
+];
+
+struct Trial {
+    knob: Knob,
+    previous: f32,
+    to: f32,
+    baseline: f32,
+}
+
+/// Hill-climbs spring and repulsion. Fed one score per sample; once the last
+/// `plateau_samples` scores sit within `plateau_tolerance` of each other below
+/// `target_score`, it tries one small step and keeps it only if the next score beats
+/// the plateau. Values never leave the configured ranges.
+pub struct Nudger {
+    settings: LayoutQualitySettings,
+    recent: VecDeque<f32>,
+    trial: Option<Trial>,
+    next_move: usize,
+}
+
+impl Nudger {
+    pub fn new(settings: LayoutQualitySettings) -> Self {
+        Self { settings, recent: VecDeque::new(), trial: None, next_move: 0 }
+    }
+
+    fn range(&self, knob: Knob) -> (f32, f32) {
+        let [a, b] = match knob {
+            Knob::SpringStrength => self.settings.spring_range,
+            Knob::RepulsionStrength => self.settings.repulsion_range,
+        };
+        (a.min(b), a.max(b))
+    }
+
+    fn plateaued(&self) -> bool {
+        let window = self.settings.plateau_samples.max(2);
+        if self.recent.len() < window {
+            return false;
+        }
+        let (min, max) = self.recent.iter().fold((f32::MAX, f32::MIN), |(lo, hi), s| (lo.min(*s), hi.max(*s)));
+        max - min <= self.settings.plateau_tolerance
+    }
+
+    pub fn observe(&mut self, score: f32, current: PhysicsKnobs) -> Option<NudgeAction> {
+        if let Some(trial) = self.trial.take() {
+            // The layout has new parameters; the plateau starts over
+            self.recent.clear();
+            self.recent.push_back(score);
+            if score > trial.baseline + self.settings.plateau_tolerance {
+                return Some(NudgeAction::Keep { knob: trial.knob, value: trial.to, baseline: trial.baseline, score });
+            }
+            self.next_move = (self.next_move + 1) % MOVES.len();
+            return Some(NudgeAction::Revert { knob: trial.knob, from: trial.to, to: trial.previous, baseline: trial.baseline, score });
+        }
+
+        self.recent.push_back(score);
+        while self.recent.len() > self.settings.plateau_samples.max(2) {
+            self.recent.pop_front();
+        }
+        if score >= self.settings.target_score || !self.plateaued() {
+            return None;
+        }
+
+        let baseline = self.recent.iter().sum::<f32>() / self.recent.len() as f32;
+        for _ in 0..MOVES.len() {
+            let (knob, direction) = MOVES[self.next_move];
+            let (min, max) = self.range(knob);
+            let from = current.get(knob);
+            let to = (from * (1.0 + direction * self.settings.step_fraction)).clamp(min, max);
+            if (to - from).abs() > f32::EPSILON {
+                self.trial = Some(Trial { knob, previous: from, to, baseline });
+                self.recent.clear();
+                return Some(NudgeAction::Try { knob, from, to, baseline });
+            }
+            // Already at the bound in this direction
+            self.next_move = (self.next_move + 1) % MOVES.len();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_at(id: u32, x: f32, y: f32, z: f32) -> Node {
+        let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+        node.data.position.x = x;
+        node.data.position.y = y;
+        node.data.position.z = z;
+        node
+    }
+
+    #[test]
+    fn test_good_layouts_score_higher_than_bad_ones() {
+        // A ring where every edge has the length its weight asks for
+        let good: Vec<Node> = (0..8)
+            .map(|i| {
+                let angle = i as f32 / 8.0 * std::f32::consts::TAU;
+                node_at(i, 5.0 * angle.cos(), 5.0 * angle.sin(), 0.0)
+            })
+            .collect();
+        let edges: Vec<Edge> = (0..8).map(|i| Edge::new(i, (i + 1) % 8, 1.0)).collect();
+        let good_quality = evaluate(&good, &edges);
+        assert!(good_quality.stress < 1e-4);
+        assert!(good_quality.edge_length_cv < 1e-3);
+        assert_eq!(good_quality.overlaps, 0);
+        assert!(good_quality.score > 0.99);
+
+        // Same graph, squashed into a pile with wildly uneven edges
+        let bad: Vec<Node> = (0..8).map(|i| node_at(i, if i == 3 { 40.0 } else { 0.01 * i as f32 }, 0.0, 0.0)).collect();
+        let bad_quality = evaluate(&bad, &edges);
+        assert!(bad_quality.overlaps > 8);
+        assert!(bad_quality.stress > 0.3);
+        assert!(bad_quality.score < 0.5 * good_quality.score);
+
+        // A heavy edge should be short: stretching it costs stress
+        let pair = vec![node_at(1, 0.0, 0.0, 0.0), node_at(2, 10.0, 0.0, 0.0), node_at(3, 15.0, 0.0, 0.0)];
+        let right = evaluate(&pair, &[Edge::new(1, 2, 0.5), Edge::new(2, 3, 1.0)]);
+        let wrong = evaluate(&pair, &[Edge::new(1, 2, 1.0), Edge::new(2, 3, 0.5)]);
+        assert!(right.stress < 1e-4);
+        assert!(wrong.stress > right.stress + 0.1);
+    }
+
+    #[test]
+    fn test_nudging_stays_within_bounds() {
+        let settings = LayoutQualitySettings {
+            target_score: 0.9,
+            plateau_samples: 3,
+            plateau_tolerance: 0.01,
+            step_fraction: 0.5,
+            spring_range: [0.1, 0.3],
+            repulsion_range: [0.05, 0.12],
+            ..Default::default()
+        };
+        let mut nudger = Nudger::new(settings.clone());
+        let mut knobs = PhysicsKnobs { spring_strength: 0.2, repulsion_strength: 0.1 };
+        let mut score = 0.5;
+        let mut tried = 0;
+        let mut kept = Vec::new();
+        for step in 0..400 {
+            if let Some(action) = nudger.observe(score, knobs) {
+                match action {
+                    NudgeAction::Try { .. } => tried += 1,
+                    NudgeAction::Keep { knob, value, .. } => kept.push((knob, value)),
+                    NudgeAction::Revert { .. } => {}
+                }
+                if let Some((knob, value)) = action.change() {
+                    match knob {
+                        Knob::SpringStrength => knobs.spring_strength = value,
+                        Knob::RepulsionStrength => knobs.repulsion_strength = value,
+                    }
+                }
+            }
+            assert!((0.1..=0.3).contains(&knobs.spring_strength), "spring {}", knobs.spring_strength);
+            assert!((0.05..=0.12).contains(&knobs.repulsion_strength), "repulsion {}", knobs.repulsion_strength);
+            // Stronger springs help until the bound, every other change hurts
+            score = if knobs.spring_strength > 0.2 { 0.6 } else { 0.5 } + if step % 2 == 0 { 0.001 } else { 0.0 };
+        }
+        assert!(tried > 10);
+        assert_eq!(kept, vec![(Knob::SpringStrength, 0.3)]);
+
+        // Nothing happens until the score plateaus, or once it is good enough
+        let mut nudger = Nudger::new(settings);
+        assert!(nudger.observe(0.2, knobs).is_none());
+        assert!(nudger.observe(0.6, knobs).is_none());
+        for _ in 0..5 {
+            assert!(nudger.observe(0.95, knobs).is_none());
+        }
+    }
+}
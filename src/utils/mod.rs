@@ -1,7 +1,64 @@
+pub mod aging;
+pub mod analytics_refresh;
 pub mod audio_processor;
+pub mod audio_resample;
+pub mod attention;
+pub mod auth;
 pub mod binary_protocol;
+pub mod byte_range;
+pub mod capabilities;
+pub mod captions;
+pub mod client_lod;
+pub mod co_selection;
+pub mod community_view;
+pub mod coloring;
+pub mod degree_repulsion;
+pub mod edge_bundling;
+pub mod edge_decay;
 pub mod edge_data;
+pub mod edge_visibility;
+pub mod edge_weights;
+pub mod frame_accounting;
+pub mod frame_budget;
+pub mod disturbance;
+pub mod frame_hash;
+pub mod gltf_export;
+#[cfg(feature = "gpu")]
 pub mod gpu_compute;
+#[cfg(not(feature = "gpu"))]
+#[path = "gpu_compute_disabled.rs"]
+pub mod gpu_compute;
+pub mod gpu_reconnect;
+pub mod gpu_timing;
+pub mod graph_transaction;
+pub mod html_export;
+pub mod idle;
+pub mod json_store;
+pub mod label_placement;
+pub mod layout_metrics;
+pub mod layout_quality;
 pub mod logging;
+pub mod node_merge;
+pub mod node_watch;
+pub mod param_sweep;
+pub mod physics_flags;
+pub mod physics_partition;
+pub mod placement;
+pub mod position_recording;
+pub mod projection;
+pub mod rate_limit;
+pub mod rebuild_queue;
+pub mod reheat;
+pub mod resync;
+pub mod rewire;
+pub mod shutdown;
+pub mod simulation_clock;
+pub mod skeleton;
+pub mod spatial_index;
+pub mod still_render;
 pub mod socket_flow_constants;
 pub mod socket_flow_messages;
+pub mod time_sync;
+pub mod update_priority;
+pub mod voice_command;
+pub mod warmup;
@@ -0,0 +1,207 @@
+//! Merging nodes that stand for the same thing, e.g. "GraphQL" and "Graph QL". The
+//! merged nodes' edges move to the kept node, with weights summed where it already has
+//! that edge and edges between the merged nodes dropped rather than left as self-loops.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::models::edge::Edge;
+use crate::models::graph::{GraphData, GraphDiff, NodeUpdate};
+
+// Node metadata describing the node's own file; the kept node's values always stand
+const FILE_KEYS: [&str; 8] = ["fileName", "name", "metadataId", "fileSize", "nodeSize", "sha1", "lastModified", "hyperlinkCount"];
+const ALIASES_KEY: &str = "aliases";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeOutcome {
+    pub diff: GraphDiff,
+    // Metadata ids of the merged nodes, now aliases of the kept one
+    pub aliases: Vec<String>,
+    // Metadata keys the merged nodes disagreed on
+    pub conflicts: Vec<String>,
+}
+
+/// Merges `merge` into `keep` within `graph`
+pub fn merge_nodes(graph: &mut GraphData, keep: u32, merge: &[u32]) -> Result<MergeOutcome, String> {
+    let merged: BTreeSet<u32> = merge.iter().copied().collect();
+    if merged.is_empty() {
+        return Err("Nothing to merge".to_string());
+    }
+    if merged.contains(&keep) {
+        return Err(format!("Node {} can't be merged into itself", keep));
+    }
+    let node_index: HashMap<u32, usize> = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+    for id in std::iter::once(&keep).chain(&merged) {
+        if !node_index.contains_key(id) {
+            return Err(format!("Node {} not found", id));
+        }
+    }
+
+    // Metadata: the kept node's values win, anything else is marked as a conflict
+    let mut metadata = graph.nodes[node_index[&keep]].metadata.clone();
+    let mut conflicts = BTreeSet::new();
+    let split = |aliases: Option<&String>| -> Vec<String> {
+        aliases.map(|a| a.split(',').filter(|a| !a.is_empty()).map(str::to_string).collect()).unwrap_or_default()
+    };
+    let mut merged_ids = Vec::new();
+    let mut aliases = split(metadata.get(ALIASES_KEY));
+    for id in &merged {
+        let node = &graph.nodes[node_index[id]];
+        merged_ids.push(node.metadata_id.clone());
+        aliases.push(node.metadata_id.clone());
+        aliases.extend(split(node.metadata.get(ALIASES_KEY)));
+        let mut keys: Vec<&String> = node.metadata.keys().collect();
+        keys.sort();
+        for key in keys {
+            if FILE_KEYS.contains(&key.as_str()) || key == ALIASES_KEY {
+                continue;
+            }
+            let value = &node.metadata[key];
+            match metadata.get(key) {
+                None => {
+                    metadata.insert(key.clone(), value.clone());
+                }
+                Some(existing) if existing == value => {}
+                Some(_) => {
+                    metadata.insert(format!("{}@{}", key, node.metadata_id), value.clone());
+                    conflicts.insert(key.clone());
+                }
+            }
+        }
+    }
+    metadata.insert(ALIASES_KEY.to_string(), aliases.join(","));
+    if !conflicts.is_empty() {
+        metadata.insert("mergeConflicts".to_string(), conflicts.iter().cloned().collect::<Vec<_>>().join(","));
+    }
+
+    // Edges: untouched ones first, so moved edges add onto what the kept node already has
+    let remap = |id: u32| if merged.contains(&id) { keep } else { id };
+    let touches = |edge: &Edge| merged.contains(&edge.source) || merged.contains(&edge.target);
+    let key = |edge: &Edge| (edge.source.min(edge.target), edge.source.max(edge.target), edge.type_name().to_string());
+    let (moved, mut edges): (Vec<Edge>, Vec<Edge>) = std::mem::take(&mut *graph.edges).into_iter().partition(|e| touches(e));
+    let mut index: HashMap<(u32, u32, String), usize> = edges.iter().enumerate().map(|(i, e)| (key(e), i)).collect();
+    let mut added = HashSet::new();
+    let mut updated = BTreeSet::new();
+    let mut removed_edges = Vec::new();
+    for edge in moved {
+        removed_edges.push(edge.id.clone());
+        let (source, target) = (remap(edge.source), remap(edge.target));
+        if source == target {
+            continue;
+        }
+        let mut rekeyed = edge.clone();
+        rekeyed.id = if edge.edge_type.is_none() && edge.id == format!("{}-{}", edge.source, edge.target) {
+            format!("{}-{}", source, target)
+        } else {
+            format!("{}-{}-{}", edge.type_name(), source, target)
+        };
+        rekeyed.source = source;
+        rekeyed.target = target;
+        match index.get(&key(&rekeyed)) {
+            Some(&i) => {
+                let existing = &mut edges[i];
+                existing.weight += rekeyed.weight;
+                existing.absorb_provenance(&rekeyed);
+                if let (Some(total), Some(raw)) = (existing.raw_weight.as_mut(), rekeyed.raw_weight) {
+                    *total += raw;
+                }
+                if !added.contains(&i) {
+                    updated.insert(i);
+                }
+            }
+            None => {
+                index.insert(key(&rekeyed), edges.len());
+                added.insert(edges.len());
+                edges.push(rekeyed);
+            }
+        }
+    }
+
+    let mut added: Vec<usize> = added.into_iter().collect();
+    added.sort();
+    let diff = GraphDiff {
+        updated_nodes: vec![NodeUpdate { node_id: keep, metadata: metadata.clone() }],
+        removed_nodes: merged.iter().copied().collect(),
+        added_edges: added.iter().map(|&i| edges[i].clone()).collect(),
+        updated_edges: updated.iter().map(|&i| edges[i].clone()).collect(),
+        // Re-added under their new id when they weren't folded into another edge
+        removed_edges,
+        ..Default::default()
+    };
+
+    graph.edges = edges.into();
+    graph.nodes.retain(|n| !merged.contains(&n.id));
+    if let Some(node) = graph.nodes.iter_mut().find(|n| n.id == keep) {
+        node.metadata = metadata;
+    }
+    for id in &merged {
+        graph.id_to_metadata.remove(&id.to_string());
+    }
+
+    Ok(MergeOutcome {
+        diff,
+        aliases: merged_ids,
+        conflicts: conflicts.into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::node::Node;
+
+    fn node(id: u32, metadata_id: &str, metadata: &[(&str, &str)]) -> Node {
+        let mut node = Node::new_with_id(metadata_id.to_string(), Some(id));
+        for (key, value) in metadata {
+            node.metadata.insert(key.to_string(), value.to_string());
+        }
+        node
+    }
+
+    fn weights(graph: &GraphData) -> Vec<(String, f32)> {
+        let mut weights: Vec<(String, f32)> = graph.edges.iter().map(|e| (e.id.clone(), e.weight)).collect();
+        weights.sort_by(|a, b| a.0.cmp(&b.0));
+        weights
+    }
+
+    #[test]
+    fn test_merge_moves_edges_onto_the_kept_node() {
+        let mut graph = GraphData::new();
+        *graph.nodes = vec![
+            node(1, "GraphQL", &[("tags", "api"), ("sha1", "aaa")]),
+            node(2, "Graph QL", &[("tags", "query"), ("author", "sam"), ("sha1", "bbb")]),
+            node(3, "REST", &[]),
+            node(4, "Schemas", &[]),
+        ];
+        *graph.edges = vec![
+            Edge::new(1, 3, 2.0),
+            Edge::new(2, 3, 1.0),
+            Edge::new(2, 4, 0.5),
+            // Would be a self-loop once 2 is folded into 1
+            Edge::new(1, 2, 3.0),
+        ];
+
+        let outcome = merge_nodes(&mut graph, 1, &[2]).unwrap();
+        assert_eq!(weights(&graph), vec![("1-3".to_string(), 3.0), ("1-4".to_string(), 0.5)]);
+        assert_eq!(outcome.diff.removed_nodes, vec![2]);
+        assert_eq!(outcome.diff.removed_edges, vec!["2-3", "2-4", "1-2"]);
+        assert_eq!(outcome.diff.updated_edges.iter().map(|e| e.weight).collect::<Vec<_>>(), vec![3.0]);
+        assert_eq!(outcome.diff.added_edges.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["1-4"]);
+        assert_eq!(outcome.aliases, vec!["Graph QL"]);
+
+        // Disagreements are kept alongside under a marker; the files' own details aren't merged
+        let kept = &graph.nodes.iter().find(|n| n.id == 1).unwrap().metadata;
+        assert_eq!(outcome.conflicts, vec!["tags"]);
+        assert_eq!(kept["tags"], "api");
+        assert_eq!(kept["tags@Graph QL"], "query");
+        assert_eq!(kept["author"], "sam");
+        assert_eq!(kept["sha1"], "aaa");
+        assert_eq!(kept["mergeConflicts"], "tags");
+        assert_eq!(kept["aliases"], "Graph QL");
+        assert_eq!(graph.nodes.len(), 3);
+
+        assert!(merge_nodes(&mut graph, 1, &[1]).is_err());
+        assert!(merge_nodes(&mut graph, 1, &[2]).unwrap_err().contains("not found"));
+    }
+}
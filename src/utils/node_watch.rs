@@ -0,0 +1,312 @@
+//! Watches on particular nodes, for clients and agents that only care when a handful of
+//! nodes change and would rather not read every graph event to find out. A watch is a
+//! set of node ids and the kinds of change wanted; graph events touching those nodes are
+//! boiled down to a `node_changes` event sent to that watcher alone. A watch lives under
+//! a session id like a speech session: when its socket drops it's kept for the TTL, and a
+//! reconnecting client can resume it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::config::NodeWatchSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchEvent {
+    // Metadata and attributes, colour and aging included; also the node being added or removed
+    Metadata,
+    Annotations,
+    Edges,
+}
+
+const ALL_EVENTS: [WatchEvent; 3] = [WatchEvent::Metadata, WatchEvent::Annotations, WatchEvent::Edges];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchError {
+    Unknown,
+    Expired,
+    // The watch belongs to another user
+    Forbidden,
+    TooManyIds(usize),
+    TooMany,
+}
+
+impl WatchError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            WatchError::Unknown => "unknown_watch",
+            WatchError::Expired => "watch_expired",
+            WatchError::Forbidden => "watch_forbidden",
+            WatchError::TooManyIds(_) => "too_many_watched_nodes",
+            WatchError::TooMany => "too_many_watches",
+        }
+    }
+
+    /// The `error` message sockets send
+    pub fn to_ws_message(&self) -> String {
+        serde_json::json!({ "type": "error", "code": self.code(), "message": self.to_string() }).to_string()
+    }
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::Unknown => write!(f, "No such node watch"),
+            WatchError::Expired => write!(f, "Node watch has expired"),
+            WatchError::Forbidden => write!(f, "Node watch belongs to another user"),
+            WatchError::TooManyIds(max) => write!(f, "A watch covers at most {} nodes", max),
+            WatchError::TooMany => write!(f, "Too many node watches"),
+        }
+    }
+}
+
+/// What a watcher is told when it sets up or resumes a watch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchInfo {
+    pub session: String,
+    pub ids: Vec<u32>,
+    pub events: Vec<WatchEvent>,
+}
+
+impl WatchInfo {
+    /// The `watching` reply sockets send
+    pub fn to_ws_message(&self) -> String {
+        let mut message = serde_json::to_value(self).unwrap_or_else(|_| serde_json::json!({}));
+        message["type"] = serde_json::json!("watching");
+        message.to_string()
+    }
+}
+
+/// A socket's `watchNodes` message; with a session it resumes that watch instead
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchRequest {
+    #[serde(default)]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub ids: Vec<u32>,
+    #[serde(default)]
+    pub events: Vec<WatchEvent>,
+}
+
+struct Watch {
+    owner: Option<String>,
+    ids: HashSet<u32>,
+    events: BTreeSet<WatchEvent>,
+    subscriber: Option<usize>,
+    released_at: Option<Instant>,
+}
+
+impl Watch {
+    fn info(&self, session: &str) -> WatchInfo {
+        let mut ids: Vec<u32> = self.ids.iter().copied().collect();
+        ids.sort_unstable();
+        WatchInfo { session: session.to_string(), ids, events: self.events.iter().copied().collect() }
+    }
+}
+
+pub struct NodeWatches {
+    settings: NodeWatchSettings,
+    watches: HashMap<String, Watch>,
+    // The session each connected client or agent holds
+    held: HashMap<usize, String>,
+}
+
+impl NodeWatches {
+    pub fn new(settings: NodeWatchSettings) -> Self {
+        Self { settings, watches: HashMap::new(), held: HashMap::new() }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs_f32(self.settings.ttl_secs.max(0.0))
+    }
+
+    fn is_expired(&self, watch: &Watch, now: Instant) -> bool {
+        watch.released_at.is_some_and(|at| now.duration_since(at) >= self.ttl())
+    }
+
+    /// True when no connected subscriber watches anything
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Sets what `subscriber` watches, replacing its previous ids and events. No events
+    /// means all of them. Returns the session to resume the watch by.
+    pub fn watch(&mut self, subscriber: usize, owner: Option<String>, ids: Vec<u32>, events: Vec<WatchEvent>, now: Instant) -> Result<WatchInfo, WatchError> {
+        let ids: HashSet<u32> = ids.into_iter().collect();
+        if ids.len() > self.settings.max_ids {
+            return Err(WatchError::TooManyIds(self.settings.max_ids));
+        }
+        let events: BTreeSet<WatchEvent> = if events.is_empty() { ALL_EVENTS.into() } else { events.into_iter().collect() };
+        if let Some(session) = self.held.get(&subscriber) {
+            if let Some(watch) = self.watches.get_mut(session) {
+                watch.ids = ids;
+                watch.events = events;
+                return Ok(watch.info(session));
+            }
+        }
+        self.purge_expired(now);
+        if self.watches.len() >= self.settings.max_sessions {
+            return Err(WatchError::TooMany);
+        }
+        let session = uuid::Uuid::new_v4().to_string();
+        let watch = Watch { owner, ids, events, subscriber: Some(subscriber), released_at: None };
+        let info = watch.info(&session);
+        self.watches.insert(session.clone(), watch);
+        self.held.insert(subscriber, session);
+        Ok(info)
+    }
+
+    /// Hands an existing watch to `subscriber`, taking it from any subscriber that still
+    /// holds it. A watch `subscriber` held before is dropped.
+    pub fn resume(&mut self, session: &str, subscriber: usize, owner: Option<&str>, now: Instant) -> Result<WatchInfo, WatchError> {
+        let watch = self.watches.get(session).ok_or(WatchError::Unknown)?;
+        if self.is_expired(watch, now) {
+            self.watches.remove(session);
+            return Err(WatchError::Expired);
+        }
+        if watch.owner.is_some() && watch.owner.as_deref() != owner {
+            return Err(WatchError::Forbidden);
+        }
+        if let Some(previous) = self.held.remove(&subscriber).filter(|s| s != session) {
+            self.watches.remove(&previous);
+        }
+        let watch = self.watches.get_mut(session).ok_or(WatchError::Unknown)?;
+        if let Some(holder) = watch.subscriber.replace(subscriber) {
+            self.held.remove(&holder);
+        }
+        watch.released_at = None;
+        self.held.insert(subscriber, session.to_string());
+        Ok(watch.info(session))
+    }
+
+    /// Lets go of whatever `subscriber` watches; the TTL starts from `now`
+    pub fn release(&mut self, subscriber: usize, now: Instant) {
+        if let Some(watch) = self.held.remove(&subscriber).and_then(|session| self.watches.get_mut(&session)) {
+            watch.subscriber = None;
+            watch.released_at = Some(now);
+        }
+    }
+
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        let before = self.watches.len();
+        let ttl = self.ttl();
+        self.watches.retain(|_, watch| watch.released_at.is_none_or(|at| now.duration_since(at) < ttl));
+        before - self.watches.len()
+    }
+
+    /// The `node_changes` event for each connected subscriber watching any of `changes`
+    pub fn notifications(&self, source: &str, changes: &[(u32, WatchEvent)]) -> Vec<(usize, String)> {
+        let mut notifications = Vec::new();
+        for (subscriber, session) in &self.held {
+            let Some(watch) = self.watches.get(session) else { continue };
+            let mut nodes: BTreeMap<u32, BTreeSet<WatchEvent>> = BTreeMap::new();
+            for (node_id, event) in changes {
+                if watch.ids.contains(node_id) && watch.events.contains(event) {
+                    nodes.entry(*node_id).or_default().insert(*event);
+                }
+            }
+            if !nodes.is_empty() {
+                notifications.push((*subscriber, node_changes_event(source, &nodes)));
+            }
+        }
+        notifications
+    }
+}
+
+fn node_changes_event(source: &str, nodes: &BTreeMap<u32, BTreeSet<WatchEvent>>) -> String {
+    let nodes: Vec<serde_json::Value> = nodes.iter()
+        .map(|(node_id, events)| serde_json::json!({ "nodeId": node_id, "events": events }))
+        .collect();
+    serde_json::json!({ "type": "node_changes", "source": source, "nodes": nodes }).to_string()
+}
+
+/// The event type of a broadcast and the node changes it carries. `updatedNodes` in a
+/// graph diff aren't counted: the graph actor reports metadata updates as it applies them.
+pub fn changes_in(message: &str) -> Option<(String, Vec<(u32, WatchEvent)>)> {
+    let event: serde_json::Value = serde_json::from_str(message).ok()?;
+    let source = event.get("type")?.as_str()?.to_string();
+    let ids = |key: &str, field: Option<&str>| -> Vec<u32> {
+        event.get(key).and_then(|v| v.as_array()).into_iter().flatten()
+            .filter_map(|v| match field {
+                Some(field) => v.get(field),
+                None => Some(v),
+            })
+            .filter_map(|v| v.as_u64())
+            .map(|id| id as u32)
+            .collect()
+    };
+    let mut changes = Vec::new();
+    match source.as_str() {
+        "graph_diff" => {
+            changes.extend(ids("addedNodes", Some("id")).into_iter().map(|id| (id, WatchEvent::Metadata)));
+            changes.extend(ids("removedNodes", None).into_iter().map(|id| (id, WatchEvent::Metadata)));
+            for key in ["addedEdges", "updatedEdges"] {
+                for id in ids(key, Some("source")).into_iter().chain(ids(key, Some("target"))) {
+                    changes.push((id, WatchEvent::Edges));
+                }
+            }
+            // Removed edges are listed by their "source-target" ids
+            let removed = event.get("removedEdges").and_then(|v| v.as_array()).into_iter().flatten();
+            for edge_id in removed.filter_map(|v| v.as_str()) {
+                if let Some((source, target)) = edge_id.split_once('-') {
+                    changes.extend([source, target].iter().filter_map(|id| id.parse().ok()).map(|id| (id, WatchEvent::Edges)));
+                }
+            }
+        }
+        "node_metadata_update" => changes.extend(ids("nodes", Some("nodeId")).into_iter().map(|id| (id, WatchEvent::Metadata))),
+        "node_attribute_diff" => changes.extend(ids("nodeIds", None).into_iter().map(|id| (id, WatchEvent::Metadata))),
+        "annotation_created" | "annotation_deleted" => {
+            if let Some(id) = event.get("nodeId").and_then(|v| v.as_u64()) {
+                changes.push((id as u32, WatchEvent::Annotations));
+            }
+        }
+        _ => {}
+    }
+    Some((source, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watches() -> NodeWatches {
+        NodeWatches::new(NodeWatchSettings { ttl_secs: 60.0, max_ids: 3, max_sessions: 2 })
+    }
+
+    #[test]
+    fn test_watches_survive_reconnects_within_the_ttl() {
+        let mut watches = watches();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert_eq!(watches.watch(1, Some("alice".into()), vec![1, 2, 3, 4], vec![], start), Err(WatchError::TooManyIds(3)));
+        let info = watches.watch(1, Some("alice".into()), vec![3, 1, 1], vec![WatchEvent::Annotations], start).unwrap();
+        assert_eq!((info.ids.clone(), info.events.clone()), (vec![1, 3], vec![WatchEvent::Annotations]));
+
+        // The socket drops and nothing is sent while it's away
+        watches.release(1, at(10));
+        assert!(watches.is_empty());
+        assert!(watches.notifications("annotation_created", &[(1, WatchEvent::Annotations)]).is_empty());
+
+        // Its replacement picks the watch back up, but nobody else can
+        assert_eq!(watches.resume(&info.session, 2, Some("bob"), at(20)), Err(WatchError::Forbidden));
+        assert_eq!(watches.resume(&info.session, 2, Some("alice"), at(20)), Ok(info.clone()));
+        let sent = watches.notifications("annotation_created", &[(1, WatchEvent::Annotations), (1, WatchEvent::Metadata)]);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, 2);
+        let event: serde_json::Value = serde_json::from_str(&sent[0].1).unwrap();
+        assert_eq!(event["nodes"], serde_json::json!([{ "nodeId": 1, "events": ["annotations"] }]));
+
+        // Left alone past the TTL, it's gone
+        watches.release(2, at(30));
+        assert_eq!(watches.resume(&info.session, 3, Some("alice"), at(90)), Err(WatchError::Expired));
+        assert_eq!(watches.resume(&info.session, 3, Some("alice"), at(90)), Err(WatchError::Unknown));
+
+        watches.watch(4, None, vec![1], vec![], at(90)).unwrap();
+        watches.watch(5, None, vec![1], vec![], at(90)).unwrap();
+        assert_eq!(watches.watch(6, None, vec![1], vec![], at(90)), Err(WatchError::TooMany));
+    }
+}
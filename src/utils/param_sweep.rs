@@ -0,0 +1,305 @@
+//! Offline tuning of the physics constants. Every combination of a grid of repulsion,
+//! spring and damping values runs the deterministic CPU simulation for a fixed number of
+//! iterations from the same start, on a copy of the graph or a generated one, and is
+//! scored with the layout quality metric. Combinations run in parallel; the ranking only
+//! depends on the scores, so it comes out the same however the work is split.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::config::PhysicsOverrides;
+use crate::models::edge::Edge;
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+use crate::models::simulation_params::SimulationParams;
+use crate::services::graph_service::GraphService;
+use crate::utils::layout_quality::{self, LayoutQuality};
+use crate::utils::placement::fibonacci_sphere;
+
+pub const MAX_COMBINATIONS: usize = 1000;
+pub const MAX_ITERATIONS: u32 = 10_000;
+pub const MAX_GENERATED_NODES: usize = 2000;
+// Generated graphs start spread over a sphere this big
+const GENERATED_RADIUS: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParamRange {
+    pub min: f32,
+    pub max: f32,
+    // Evenly spaced values from min to max; one step is just min
+    pub steps: usize,
+}
+
+impl ParamRange {
+    pub fn values(&self) -> Vec<f32> {
+        if self.steps <= 1 {
+            return vec![self.min];
+        }
+        let step = (self.max - self.min) / (self.steps - 1) as f32;
+        (0..self.steps).map(|i| self.min + step * i as f32).collect()
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if !self.min.is_finite() || !self.max.is_finite() || self.min < 0.0 || self.max < self.min {
+            return Err(format!("{} needs 0 <= min <= max", name));
+        }
+        if self.steps == 0 {
+            return Err(format!("{} needs at least one step", name));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepGrid {
+    pub repulsion: ParamRange,
+    pub spring: ParamRange,
+    pub damping: ParamRange,
+}
+
+impl SweepGrid {
+    pub fn validate(&self) -> Result<(), String> {
+        self.repulsion.validate("repulsion")?;
+        self.spring.validate("spring")?;
+        self.damping.validate("damping")?;
+        if self.damping.max > 1.0 {
+            return Err("damping can't exceed 1".to_string());
+        }
+        let combinations = self.repulsion.steps.saturating_mul(self.spring.steps).saturating_mul(self.damping.steps);
+        if combinations > MAX_COMBINATIONS {
+            return Err(format!("{} combinations exceeds the limit of {}", combinations, MAX_COMBINATIONS));
+        }
+        Ok(())
+    }
+
+    /// Every combination, repulsion varying slowest
+    pub fn points(&self) -> Vec<SweepPoint> {
+        let mut points = Vec::new();
+        for repulsion in self.repulsion.values() {
+            for spring in self.spring.values() {
+                for damping in self.damping.values() {
+                    points.push(SweepPoint { repulsion, spring, damping });
+                }
+            }
+        }
+        points
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepPoint {
+    pub repulsion: f32,
+    pub spring: f32,
+    pub damping: f32,
+}
+
+impl SweepPoint {
+    fn params(&self, base: &SimulationParams) -> SimulationParams {
+        SimulationParams { repulsion: self.repulsion, spring_strength: self.spring, damping: self.damping, ..base.clone() }
+    }
+
+    /// The room physics overrides that apply these values
+    pub fn preset(&self) -> PhysicsOverrides {
+        PhysicsOverrides {
+            repulsion_strength: Some(self.repulsion),
+            spring_strength: Some(self.spring),
+            damping: Some(self.damping),
+            ..Default::default()
+        }
+    }
+}
+
+/// What the sweep lays out
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SweepGraph {
+    // The current graph, from its current positions
+    #[default]
+    Snapshot,
+    // A random graph from `seed`, each node linked to up to `edges_per_node` earlier ones
+    #[serde(rename_all = "camelCase")]
+    Generated { nodes: usize, edges_per_node: usize, seed: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepResult {
+    // 1 is best
+    pub rank: usize,
+    #[serde(flatten)]
+    pub point: SweepPoint,
+    pub quality: LayoutQuality,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepReport {
+    pub iterations: u32,
+    pub node_count: usize,
+    pub edge_count: usize,
+    // Best first
+    pub results: Vec<SweepResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best: Option<SweepPoint>,
+    // The best values as room physics overrides, for PATCH /api/graphs/{room}/physics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<PhysicsOverrides>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepProgress {
+    pub completed: usize,
+    pub total: usize,
+}
+
+/// A random graph that is the same for the same arguments
+pub fn generated_graph(nodes: usize, edges_per_node: usize, seed: u64) -> GraphData {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut graph = GraphData::new();
+    for i in 0..nodes {
+        let mut node = Node::new_with_id(format!("sweep-{}", i), Some(i as u32 + 1));
+        node.set_file_size(rng.gen_range(100..10_000));
+        node.data.position = fibonacci_sphere(i, nodes, GENERATED_RADIUS).into();
+        graph.nodes.push(node);
+    }
+    let mut linked: HashSet<(u32, u32)> = HashSet::new();
+    for i in 1..nodes as u32 {
+        for _ in 0..edges_per_node {
+            let target = rng.gen_range(0..i);
+            if linked.insert((target, i)) {
+                graph.edges.push(Edge::new(target + 1, i + 1, rng.gen_range(0.2..1.0)));
+            }
+        }
+    }
+    graph
+}
+
+fn simulate(graph: &GraphData, params: &SimulationParams, iterations: u32, cancelled: &(dyn Fn() -> bool + Sync)) -> Option<LayoutQuality> {
+    let mut graph = graph.clone();
+    let mut node_map = HashMap::new();
+    for _ in 0..iterations {
+        if cancelled() {
+            return None;
+        }
+        GraphService::calculate_layout_cpu(&mut graph, &mut node_map, params).ok()?;
+    }
+    Some(layout_quality::evaluate(&graph.nodes, &graph.edges))
+}
+
+/// Runs every combination in `grid` over `graph` with `base` for the other parameters,
+/// on up to `workers` threads. None if `cancelled` turned true before the end.
+pub fn run(
+    graph: &GraphData,
+    base: &SimulationParams,
+    grid: &SweepGrid,
+    iterations: u32,
+    workers: usize,
+    cancelled: &(dyn Fn() -> bool + Sync),
+    progress: &(dyn Fn(SweepProgress) + Sync),
+) -> Option<SweepReport> {
+    let points = grid.points();
+    let total = points.len();
+    let next = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let scores: Mutex<Vec<Option<LayoutQuality>>> = Mutex::new(vec![None; total]);
+    progress(SweepProgress { completed: 0, total });
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, total.max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= total {
+                    return;
+                }
+                let Some(quality) = simulate(graph, &points[i].params(base), iterations, cancelled) else {
+                    return;
+                };
+                scores.lock().unwrap()[i] = Some(quality);
+                let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                progress(SweepProgress { completed: done, total });
+            });
+        }
+    });
+
+    if cancelled() {
+        return None;
+    }
+    let scores = scores.into_inner().unwrap();
+    let mut ranked: Vec<(usize, LayoutQuality)> = scores.into_iter().enumerate()
+        .map(|(i, quality)| quality.map(|quality| (i, quality)))
+        .collect::<Option<_>>()?;
+    // Ties go to the earlier grid point
+    ranked.sort_by(|a, b| b.1.score.total_cmp(&a.1.score).then(a.0.cmp(&b.0)));
+    let results: Vec<SweepResult> = ranked.into_iter().enumerate()
+        .map(|(rank, (i, quality))| SweepResult { rank: rank + 1, point: points[i], quality })
+        .collect();
+    let best = results.first().map(|result| result.point);
+    Some(SweepReport {
+        iterations,
+        node_count: graph.nodes.len(),
+        edge_count: graph.edges.len(),
+        results,
+        best,
+        preset: best.map(|point| point.preset()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> SweepGrid {
+        SweepGrid {
+            repulsion: ParamRange { min: 50.0, max: 200.0, steps: 2 },
+            spring: ParamRange { min: 0.2, max: 1.0, steps: 2 },
+            damping: ParamRange { min: 0.5, max: 0.5, steps: 1 },
+        }
+    }
+
+    #[test]
+    fn test_sweep_ranks_the_same_however_it_is_split() {
+        let graph = generated_graph(12, 2, 7);
+        let base = SimulationParams::new();
+        let reported = Mutex::new(Vec::new());
+        let report = |workers| run(&graph, &base, &grid(), 20, workers, &|| false, &|p| reported.lock().unwrap().push(p)).unwrap();
+
+        let (serial, parallel) = (report(1), report(4));
+        assert_eq!(serial, parallel);
+        assert_eq!(serial.results.len(), 4);
+        assert_eq!(serial.results.iter().map(|r| r.rank).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert!(serial.results.windows(2).all(|pair| pair[0].quality.score >= pair[1].quality.score));
+        assert_eq!(serial.best, Some(serial.results[0].point));
+        assert!(reported.lock().unwrap().contains(&SweepProgress { completed: 4, total: 4 }));
+
+        let json = serde_json::to_value(&serial).unwrap();
+        let first = &json["results"][0];
+        for key in ["rank", "repulsion", "spring", "damping", "quality"] {
+            assert!(first.get(key).is_some(), "result is missing {}", key);
+        }
+        assert!(first["quality"]["score"].is_number());
+        assert_eq!(json["preset"]["repulsion_strength"], json["best"]["repulsion"]);
+        assert_eq!((json["nodeCount"].as_u64(), json["iterations"].as_u64()), (Some(12), Some(20)));
+    }
+
+    #[test]
+    fn test_cancelled_sweep_and_bad_grids() {
+        let graph = generated_graph(6, 1, 1);
+        assert!(run(&graph, &SimulationParams::new(), &grid(), 10, 2, &|| true, &|_| {}).is_none());
+
+        let mut too_big = grid();
+        too_big.repulsion.steps = MAX_COMBINATIONS + 1;
+        assert!(too_big.validate().is_err());
+        let mut inverted = grid();
+        inverted.spring = ParamRange { min: 1.0, max: 0.5, steps: 2 };
+        assert!(inverted.validate().is_err());
+        assert!(grid().validate().is_ok());
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-node physics flags, kept in `BinaryNodeData::flags`. The CPU layout, the GPU kernel
+//! (compute_forces.cu) and the graph actor all read these bits.
+
+use serde::{Deserialize, Serialize};
+
+/// Takes part in the simulation at all. Inactive nodes neither move nor push or pull others.
+pub const ACTIVE: u8 = 1 << 0;
+/// Held in place; still pushes and pulls other nodes
+pub const FIXED: u8 = 1 << 1;
+/// Left out of repulsion both ways; springs still apply
+pub const NO_REPULSION: u8 = 1 << 2;
+/// Loses most of its velocity every step, for nodes that should drift rather than swing
+pub const EXTRA_DAMPING: u8 = 1 << 3;
+pub const ALL: u8 = ACTIVE | FIXED | NO_REPULSION | EXTRA_DAMPING;
+
+/// What a node keeps of its velocity each step under EXTRA_DAMPING, on top of normal damping
+pub const EXTRA_DAMPING_RETAIN: f32 = 0.25;
+
+pub fn is_active(flags: u8) -> bool {
+    flags & ACTIVE != 0
+}
+
+/// Whether physics may move the node
+pub fn moves(flags: u8) -> bool {
+    is_active(flags) && flags & FIXED == 0
+}
+
+pub fn repels(flags: u8) -> bool {
+    is_active(flags) && flags & NO_REPULSION == 0
+}
+
+/// Bits to set or clear; anything left out is left alone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PhysicsFlagsPatch {
+    pub active: Option<bool>,
+    pub fixed: Option<bool>,
+    pub no_repulsion: Option<bool>,
+    pub extra_damping: Option<bool>,
+}
+
+impl PhysicsFlagsPatch {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, flags: u8) -> u8 {
+        [(self.active, ACTIVE), (self.fixed, FIXED), (self.no_repulsion, NO_REPULSION), (self.extra_damping, EXTRA_DAMPING)]
+            .into_iter()
+            .fold(flags, |flags, (set, bit)| match set {
+                Some(true) => flags | bit,
+                Some(false) => flags & !bit,
+                None => flags,
+            })
+    }
+}
+
+/// The flags as named booleans, for API responses
+pub fn describe(flags: u8) -> serde_json::Value {
+    serde_json::json!({
+        "flags": flags,
+        "active": is_active(flags),
+        "fixed": flags & FIXED != 0,
+        "noRepulsion": flags & NO_REPULSION != 0,
+        "extraDamping": flags & EXTRA_DAMPING != 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::graph::GraphData;
+    use crate::models::node::Node;
+    use crate::models::simulation_params::SimulationParams;
+    use crate::services::graph_service::GraphService;
+    use glam::Vec3;
+    use std::collections::HashMap;
+
+    // Three unconnected nodes in a row, one unit apart, with the middle one's flags set
+    fn run(middle: u8, steps: usize) -> Vec<Vec3> {
+        let mut graph = GraphData::new();
+        for (id, x) in [(1, -1.0), (2, 0.0), (3, 1.0)] {
+            let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+            node.data.mass = 40;
+            node.set_x(x);
+            graph.nodes.push(node);
+        }
+        graph.nodes[1].data.flags = middle;
+        graph.nodes[1].data.velocity.x = 1.0;
+        let params = SimulationParams { repulsion: 1.0, ..SimulationParams::new() };
+        for _ in 0..steps {
+            GraphService::calculate_layout_cpu(&mut graph, &mut HashMap::new(), &params).unwrap();
+        }
+        graph.nodes.iter().map(|n| Vec3::from(n.data.position)).collect()
+    }
+
+    #[test]
+    fn test_patch_sets_and_clears_only_what_it_names() {
+        let patch = PhysicsFlagsPatch { fixed: Some(true), extra_damping: Some(false), ..Default::default() };
+        assert_eq!(patch.apply(ACTIVE | EXTRA_DAMPING), ACTIVE | FIXED);
+        assert!(PhysicsFlagsPatch::default().is_empty());
+        assert!(!moves(ACTIVE | FIXED) && repels(ACTIVE | FIXED));
+        assert!(!repels(NO_REPULSION) && !moves(0));
+    }
+
+    #[test]
+    fn test_each_flag_changes_the_cpu_layout() {
+        let plain = run(ACTIVE, 10);
+        // Fixed and inactive nodes stay put despite their starting velocity
+        assert_eq!(run(ACTIVE | FIXED, 10)[1], Vec3::ZERO);
+        let inactive = run(0, 10);
+        assert_eq!(inactive[1], Vec3::ZERO);
+        // With the middle node out of the picture, the outer two are pushed apart less
+        assert!(inactive[2].x - inactive[0].x < plain[2].x - plain[0].x);
+        let no_repulsion = run(ACTIVE | NO_REPULSION, 10);
+        assert!(no_repulsion[2].x - no_repulsion[0].x < plain[2].x - plain[0].x);
+        // Extra damping bleeds off the starting push
+        assert!(run(ACTIVE | EXTRA_DAMPING, 10)[1].x.abs() < plain[1].x.abs());
+    }
+}
@@ -0,0 +1,410 @@
+//! Partitioned CPU physics. The graph is split into k partitions grown by BFS, so most
+//! edges stay inside one partition, and each step runs one blocking task per partition.
+//! A task computes the forces on its own nodes and integrates them. Repulsion reads every
+//! node's position from the step's shared snapshot. Edges crossing into another
+//! partition are boundary edges: each side reads the remote end's position from the same
+//! snapshot (the boundary exchange) and applies the spring to its own end only. Every
+//! force is the same one the single-threaded step applies; only the order of summation
+//! differs.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::PhysicsPartitionSettings;
+use crate::models::graph::GraphData;
+use crate::models::simulation_params::{PhaseProfile, SimulationParams};
+use crate::utils::degree_repulsion::repulsion_scales;
+use crate::utils::physics_flags;
+
+/// Node indices of each partition, and the partition of each node index
+#[derive(Debug, Clone)]
+pub struct Partitioning {
+    pub parts: Vec<Vec<usize>>,
+    assignment: Vec<usize>,
+    // Fraction of edges crossing partitions when this was computed
+    cut_fraction: f32,
+}
+
+impl Partitioning {
+    pub fn part_of(&self, index: usize) -> usize {
+        self.assignment[index]
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.assignment.len()
+    }
+}
+
+/// Splits `node_count` nodes into `k` partitions of near-equal size. Each partition grows
+/// breadth-first from the lowest unassigned node until it is full, restarting from the
+/// next unassigned node when its component runs out.
+pub fn partition_bfs(node_count: usize, edges: &[(usize, usize)], k: usize) -> Partitioning {
+    let k = k.clamp(1, node_count.max(1));
+    let mut adjacency = vec![Vec::new(); node_count];
+    for &(a, b) in edges.iter().filter(|(a, b)| a != b) {
+        adjacency[a].push(b);
+        adjacency[b].push(a);
+    }
+
+    let mut assignment = vec![usize::MAX; node_count];
+    let mut parts: Vec<Vec<usize>> = Vec::with_capacity(k);
+    let mut next_seed = 0;
+    for part in 0..k {
+        // Sizes differ by at most one
+        let target = node_count / k + usize::from(part < node_count % k);
+        let mut members = Vec::with_capacity(target);
+        let mut queue = VecDeque::new();
+        while members.len() < target {
+            let Some(index) = queue.pop_front() else {
+                while assignment[next_seed] != usize::MAX {
+                    next_seed += 1;
+                }
+                assignment[next_seed] = part;
+                members.push(next_seed);
+                queue.push_back(next_seed);
+                continue;
+            };
+            for &neighbour in &adjacency[index] {
+                if members.len() < target && assignment[neighbour] == usize::MAX {
+                    assignment[neighbour] = part;
+                    members.push(neighbour);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        parts.push(members);
+    }
+
+    let mut partitioning = Partitioning { parts, assignment, cut_fraction: 0.0 };
+    partitioning.cut_fraction = cut_fraction(&partitioning, edges);
+    partitioning
+}
+
+fn cut_fraction(partitioning: &Partitioning, edges: &[(usize, usize)]) -> f32 {
+    if edges.is_empty() {
+        return 0.0;
+    }
+    let cut = edges.iter().filter(|(a, b)| partitioning.part_of(*a) != partitioning.part_of(*b)).count();
+    cut as f32 / edges.len() as f32
+}
+
+/// How many partitions to run: the configured count, or one per available core when it
+/// is 0, but never so many that a partition gets fewer than `min_nodes_per_partition`
+pub fn partition_count(settings: &PhysicsPartitionSettings, node_count: usize) -> usize {
+    let wanted = match settings.partitions {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    wanted.min(node_count / settings.min_nodes_per_partition.max(1)).max(1)
+}
+
+/// Repulsion between two nodes `delta` apart, as the force on the first, pointing at
+/// the second; `strength` already folds in both masses and repulsion scales
+pub fn repulsion(delta: Vec3, strength: f32, profile: &PhaseProfile) -> Option<Vec3> {
+    let distance_squared = delta.length_squared();
+    // Avoid division by zero and limit maximum repulsion distance
+    if distance_squared < 0.0001 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    if distance > profile.max_repulsion_distance {
+        return None;
+    }
+    Some(delta / distance * (strength / distance_squared))
+}
+
+/// Spring pull on an edge's source towards its target, `delta` away
+pub fn spring(delta: Vec3, weight: f32, profile: &PhaseProfile) -> Option<Vec3> {
+    let distance_squared = delta.length_squared();
+    if distance_squared < 0.0001 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    // Spring force increases with distance and edge weight
+    Some(delta / distance * (profile.spring_strength * weight * distance))
+}
+
+/// One damped Euler step; nodes past the bounds are held at them and lose velocity on that
+/// axis. Fixed and inactive nodes stay put.
+pub fn integrate(position: &mut Vec3, velocity: &mut Vec3, force: Vec3, profile: &PhaseProfile, flags: u8) {
+    if !physics_flags::moves(flags) {
+        *velocity = Vec3::ZERO;
+        return;
+    }
+    *velocity = *velocity * profile.damping + force * profile.time_step;
+    if flags & physics_flags::EXTRA_DAMPING != 0 {
+        *velocity *= physics_flags::EXTRA_DAMPING_RETAIN;
+    }
+    *position += *velocity * profile.time_step;
+    if profile.viewport_bounds > 0.0 {
+        let bound = profile.viewport_bounds;
+        for axis in 0..3 {
+            if position[axis].abs() > bound {
+                position[axis] = position[axis].clamp(-bound, bound);
+                velocity[axis] *= profile.boundary_damping;
+            }
+        }
+    }
+}
+
+/// Per-node mass as the CPU step weighs repulsion
+pub fn repulsion_mass(mass: u8, params: &SimulationParams) -> f32 {
+    (mass as f32 / 255.0) * 10.0 * params.mass_scale
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionTiming {
+    pub nodes: usize,
+    pub boundary_edges: usize,
+    pub micros: u64,
+}
+
+/// The last partitioned step, for the stats API
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionStats {
+    pub partitions: Vec<PartitionTiming>,
+    pub edge_cut: f32,
+    pub repartitions: u64,
+    pub step_micros: u64,
+}
+
+// Everything a partition task reads, shared by all of them for one step
+struct StepSnapshot {
+    positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    flags: Vec<u8>,
+    // Repulsion mass times repulsion scale, per node
+    weights: Vec<f32>,
+    edges: Vec<(usize, usize, f32)>,
+    profile: PhaseProfile,
+}
+
+// A partition's result: new position and velocity of each of its nodes, in its order
+struct PartitionResult {
+    moved: Vec<(Vec3, Vec3)>,
+    timing: PartitionTiming,
+}
+
+fn step_partition(snapshot: &StepSnapshot, nodes: &[usize], edges: &[usize], partitioning: &Partitioning, part: usize) -> PartitionResult {
+    let start = Instant::now();
+    let local: HashMap<usize, usize> = nodes.iter().enumerate().map(|(slot, &index)| (index, slot)).collect();
+    let mut forces = vec![Vec3::ZERO; nodes.len()];
+
+    for (slot, &i) in nodes.iter().enumerate() {
+        if !physics_flags::repels(snapshot.flags[i]) {
+            continue;
+        }
+        let position = snapshot.positions[i];
+        for (j, other) in snapshot.positions.iter().enumerate() {
+            if j == i || !physics_flags::repels(snapshot.flags[j]) {
+                continue;
+            }
+            let strength = snapshot.profile.repulsion * snapshot.weights[i] * snapshot.weights[j];
+            if let Some(force) = repulsion(*other - position, strength, &snapshot.profile) {
+                forces[slot] -= force;
+            }
+        }
+    }
+
+    let mut boundary_edges = 0;
+    for &edge in edges {
+        let (source, target, weight) = snapshot.edges[edge];
+        let crossing = partitioning.part_of(source) != partitioning.part_of(target);
+        boundary_edges += usize::from(crossing);
+        if !physics_flags::is_active(snapshot.flags[source]) || !physics_flags::is_active(snapshot.flags[target]) {
+            continue;
+        }
+        let Some(force) = spring(snapshot.positions[target] - snapshot.positions[source], weight, &snapshot.profile) else {
+            continue;
+        };
+        if partitioning.part_of(source) == part {
+            forces[local[&source]] += force;
+        }
+        if partitioning.part_of(target) == part {
+            forces[local[&target]] -= force;
+        }
+    }
+
+    let moved = nodes.iter().zip(forces)
+        .map(|(&i, force)| {
+            let (mut position, mut velocity) = (snapshot.positions[i], snapshot.velocities[i]);
+            integrate(&mut position, &mut velocity, force, &snapshot.profile, snapshot.flags[i]);
+            (position, velocity)
+        })
+        .collect();
+    PartitionResult {
+        moved,
+        timing: PartitionTiming { nodes: nodes.len(), boundary_edges, micros: start.elapsed().as_micros() as u64 },
+    }
+}
+
+/// The partitioned CPU step and the partitioning it keeps between steps
+pub struct PartitionedPhysics {
+    settings: PhysicsPartitionSettings,
+    partitioning: Option<Arc<Partitioning>>,
+    repartitions: u64,
+}
+
+impl PartitionedPhysics {
+    pub fn new(settings: PhysicsPartitionSettings) -> Self {
+        Self { settings, partitioning: None, repartitions: 0 }
+    }
+
+    // A new node count always repartitions; otherwise only once the edge cut has drifted
+    // `repartition_threshold` above what it was
+    fn needs_repartition(&self, node_count: usize, edges: &[(usize, usize)]) -> bool {
+        match &self.partitioning {
+            None => true,
+            Some(current) if current.node_count() != node_count => true,
+            Some(current) => cut_fraction(current, edges) > current.cut_fraction + self.settings.repartition_threshold,
+        }
+    }
+
+    /// Runs one step over `graph` on a blocking task per partition
+    pub async fn step(&mut self, graph: &mut GraphData, params: &SimulationParams) -> std::io::Result<PartitionStats> {
+        let start = Instant::now();
+        let node_count = graph.nodes.len();
+        // Edges resolve to the first node with each id, as in the single-threaded step
+        let mut index_of: HashMap<u32, usize> = HashMap::with_capacity(node_count);
+        for (index, node) in graph.nodes.iter().enumerate() {
+            index_of.entry(node.id).or_insert(index);
+        }
+        let edges: Vec<(usize, usize, f32)> = graph.edges.iter()
+            .filter(|e| e.affects_physics)
+            .filter_map(|e| Some((*index_of.get(&e.source)?, *index_of.get(&e.target)?, e.weight)))
+            .collect();
+        let endpoints: Vec<(usize, usize)> = edges.iter().map(|&(a, b, _)| (a, b)).collect();
+
+        if self.needs_repartition(node_count, &endpoints) {
+            let k = partition_count(&self.settings, node_count);
+            self.partitioning = Some(Arc::new(partition_bfs(node_count, &endpoints, k)));
+            self.repartitions += 1;
+        }
+        let partitioning = self.partitioning.clone().unwrap();
+
+        // Each edge goes to its source's partition, and to its target's too when that differs
+        let mut edges_by_part = vec![Vec::new(); partitioning.parts.len()];
+        for (edge, &(source, target)) in endpoints.iter().enumerate() {
+            let (a, b) = (partitioning.part_of(source), partitioning.part_of(target));
+            edges_by_part[a].push(edge);
+            if b != a {
+                edges_by_part[b].push(edge);
+            }
+        }
+
+        let scales = repulsion_scales(graph, params.degree_repulsion_factor);
+        let snapshot = Arc::new(StepSnapshot {
+            positions: graph.nodes.iter().map(|n| Vec3::from(n.data.position)).collect(),
+            velocities: graph.nodes.iter().map(|n| Vec3::from(n.data.velocity)).collect(),
+            flags: graph.nodes.iter().map(|n| n.data.flags).collect(),
+            weights: graph.nodes.iter().zip(&scales).map(|(n, scale)| repulsion_mass(n.data.mass, params) * scale).collect(),
+            edges,
+            profile: params.active_profile(),
+        });
+
+        let tasks: Vec<_> = edges_by_part.into_iter().enumerate()
+            .map(|(part, part_edges)| {
+                let (snapshot, partitioning) = (snapshot.clone(), partitioning.clone());
+                tokio::task::spawn_blocking(move || {
+                    step_partition(&snapshot, &partitioning.parts[part], &part_edges, &partitioning, part)
+                })
+            })
+            .collect();
+
+        let mut timings = Vec::with_capacity(tasks.len());
+        for (part, task) in tasks.into_iter().enumerate() {
+            let result = task.await.map_err(|e| std::io::Error::other(format!("Partition {} failed: {}", part, e)))?;
+            for (&index, (position, velocity)) in partitioning.parts[part].iter().zip(result.moved) {
+                let data = &mut graph.nodes[index].data;
+                data.position = position.into();
+                data.velocity = velocity.into();
+            }
+            timings.push(result.timing);
+        }
+
+        Ok(PartitionStats {
+            partitions: timings,
+            edge_cut: cut_fraction(&partitioning, &endpoints),
+            repartitions: self.repartitions,
+            step_micros: start.elapsed().as_micros() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+    use crate::services::graph_service::GraphService;
+
+    // Two 30-node rings joined by one edge, with deterministic scattered positions
+    fn two_rings() -> GraphData {
+        let mut graph = GraphData::new();
+        let mut seed: u32 = 7;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1u32 << 24) as f32 * 20.0 - 10.0
+        };
+        for id in 1..=60 {
+            let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+            node.data.mass = 40;
+            node.set_x(next());
+            node.set_y(next());
+            node.set_z(next());
+            graph.nodes.push(node);
+        }
+        for ring in [0, 30] {
+            for i in 0..30 {
+                graph.edges.push(Edge::new(ring + i + 1, ring + (i + 1) % 30 + 1, 1.0));
+            }
+        }
+        graph.edges.push(Edge::new(1, 31, 1.0));
+        graph
+    }
+
+    #[test]
+    fn test_bfs_partitions_are_balanced_with_a_small_cut() {
+        let graph = two_rings();
+        let edges: Vec<(usize, usize)> = graph.edges.iter().map(|e| (e.source as usize - 1, e.target as usize - 1)).collect();
+        let partitioning = partition_bfs(60, &edges, 2);
+        assert_eq!(partitioning.parts.iter().map(Vec::len).collect::<Vec<_>>(), vec![30, 30]);
+        // Grown along the rings, so only a handful of edges cross; a random split cuts half
+        assert!(partitioning.cut_fraction < 0.1, "cut {}", partitioning.cut_fraction);
+        assert_eq!(partition_bfs(5, &[], 8).parts.len(), 5);
+
+        let settings = PhysicsPartitionSettings { partitions: 8, min_nodes_per_partition: 20, ..Default::default() };
+        assert_eq!(partition_count(&settings, 60), 3);
+        assert_eq!(partition_count(&settings, 10), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_partitioned_step_matches_single_threaded() {
+        let params = SimulationParams { repulsion: 1.0, degree_repulsion_factor: 0.5, ..SimulationParams::new() };
+        let mut single = two_rings();
+        let mut partitioned = single.clone();
+        let settings = PhysicsPartitionSettings { enabled: true, partitions: 4, min_nodes_per_partition: 1, ..Default::default() };
+        let mut physics = PartitionedPhysics::new(settings);
+
+        for _ in 0..50 {
+            GraphService::calculate_layout_cpu(&mut single, &mut HashMap::new(), &params).unwrap();
+            let stats = physics.step(&mut partitioned, &params).await.unwrap();
+            assert_eq!(stats.partitions.len(), 4);
+        }
+        for (a, b) in single.nodes.iter().zip(&partitioned.nodes) {
+            let (pa, pb) = (Vec3::from(a.data.position), Vec3::from(b.data.position));
+            assert!(pa.distance(pb) < 1e-3, "node {} drifted: {} vs {}", a.id, pa, pb);
+        }
+
+        // Same topology: the partitioning is kept; a node more forces a new one
+        assert_eq!(physics.repartitions, 1);
+        partitioned.nodes.push(Node::new_with_id("n61".to_string(), Some(61)));
+        physics.step(&mut partitioned, &params).await.unwrap();
+        assert_eq!(physics.repartitions, 2);
+    }
+}
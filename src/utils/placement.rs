@@ -0,0 +1,195 @@
+//! Where nodes that join a live layout start out. A node dropped far from its
+//! neighbours gets yanked across the scene by its springs and drags the settled layout
+//! with it, so new nodes start next to the neighbours that are already placed.
+
+use glam::Vec3;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+use crate::models::graph::GraphData;
+use crate::types::vec3::Vec3Data;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+// Offset from the neighbour centroid, so siblings added together don't stack up
+pub const PLACEMENT_JITTER: f32 = 0.5;
+pub const SPHERE_RADIUS: f32 = 3.0;
+// New nodes and their neighbours move at a fraction of the usual rate for this many frames
+pub const SETTLE_FRAMES: u32 = 30;
+pub const SETTLE_DAMPING: f32 = 0.8;
+
+/// Point `i` of `n` spread evenly over a sphere
+pub fn fibonacci_sphere(i: usize, n: usize, radius: f32) -> Vec3 {
+    let golden_ratio = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let n = n.max(1) as f32;
+    let i = i as f32;
+    let theta = 2.0 * std::f32::consts::PI * i / golden_ratio;
+    let phi = (1.0 - 2.0 * (i + 0.5) / n).clamp(-1.0, 1.0).acos();
+    Vec3::new(phi.sin() * theta.cos(), phi.sin() * theta.sin(), phi.cos()) * radius
+}
+
+pub fn centroid(points: &[Vec3]) -> Option<Vec3> {
+    if points.is_empty() {
+        return None;
+    }
+    Some(points.iter().copied().sum::<Vec3>() / points.len() as f32)
+}
+
+/// Places every node in `new_ids` at the centroid of its already-placed neighbours plus a
+/// small random offset, with zero velocity. A new node whose neighbours are all new waits
+/// until one of them is placed; nodes with no placed neighbour at all go on the sphere,
+/// or at the origin when the graph has no other node.
+/// Returns the new nodes and their neighbours, which should settle gently.
+pub fn place_new_nodes<R: Rng>(graph: &mut GraphData, new_ids: &HashSet<u32>, jitter: f32, sphere_radius: f32, rng: &mut R) -> HashSet<u32> {
+    let mut neighbours: HashMap<u32, Vec<u32>> = HashMap::new();
+    for edge in &graph.edges {
+        if new_ids.contains(&edge.source) || new_ids.contains(&edge.target) {
+            neighbours.entry(edge.source).or_default().push(edge.target);
+            neighbours.entry(edge.target).or_default().push(edge.source);
+        }
+    }
+    let index: HashMap<u32, usize> = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+    let mut pending: Vec<u32> = graph.nodes.iter().map(|n| n.id).filter(|id| new_ids.contains(id)).collect();
+    let mut placed: HashSet<u32> = graph.nodes.iter().map(|n| n.id).filter(|id| !new_ids.contains(id)).collect();
+
+    loop {
+        let before = pending.len();
+        pending.retain(|id| {
+            let anchors: Vec<Vec3> = neighbours.get(id).into_iter().flatten()
+                .filter(|n| placed.contains(n))
+                .filter_map(|n| index.get(n))
+                .map(|&i| graph.nodes[i].data.position.into())
+                .collect();
+            let Some(center) = centroid(&anchors) else {
+                return true;
+            };
+            let offset = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)) * jitter;
+            let node = &mut graph.nodes[index[id]];
+            node.data.position = (center + offset).into();
+            node.data.velocity = Vec3Data::zero();
+            placed.insert(*id);
+            false
+        });
+        if pending.is_empty() || pending.len() == before {
+            break;
+        }
+    }
+
+    // A graph of one node has nothing to spread it out from; it sits at the origin
+    let lone = graph.nodes.len() == 1;
+    for (i, id) in pending.iter().enumerate() {
+        let node = &mut graph.nodes[index[id]];
+        node.data.position = if lone { Vec3::ZERO } else { fibonacci_sphere(i, pending.len(), sphere_radius) }.into();
+        node.data.velocity = Vec3Data::zero();
+    }
+
+    let mut settling: HashSet<u32> = new_ids.clone();
+    for id in new_ids {
+        settling.extend(neighbours.get(id).into_iter().flatten().copied());
+    }
+    settling
+}
+
+/// Keeps only `1 - damping` of a physics step's movement
+pub fn damp_step(before: Vec3Data, after: &mut BinaryNodeData, damping: f32) {
+    let keep = 1.0 - damping.clamp(0.0, 1.0);
+    let before: Vec3 = before.into();
+    let moved: Vec3 = Vec3::from(after.position) - before;
+    after.position = (before + moved * keep).into();
+    after.velocity = (Vec3::from(after.velocity) * keep).into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+    use crate::models::simulation_params::SimulationParams;
+    use crate::services::graph_service::GraphService;
+
+    const NEW_NODE: u32 = 100;
+    // The transient, before the new springs have had time to reshape the cluster
+    const STEPS: usize = 5;
+
+    // A cluster well away from the origin, and a new node linked to most of it
+    fn cluster() -> GraphData {
+        let mut graph = GraphData::new();
+        for i in 0..10u32 {
+            let mut node = Node::new_with_id(format!("n{}", i), Some(i + 1));
+            node.set_file_size(1000);
+            let angle = i as f32 * 0.628;
+            node.data.position = Vec3Data::new(40.0 + 4.0 * angle.cos(), 4.0 * angle.sin(), 0.0);
+            graph.nodes.push(node);
+        }
+        for i in 1..10u32 {
+            graph.edges.push(Edge::new(i, i + 1, 1.0));
+        }
+        graph
+    }
+
+    fn with_new_node(mut graph: GraphData) -> GraphData {
+        let mut node = Node::new_with_id("new".to_string(), Some(NEW_NODE));
+        node.set_file_size(1000);
+        graph.nodes.push(node);
+        for i in 1..=8u32 {
+            graph.edges.push(Edge::new(NEW_NODE, i, 1.0));
+        }
+        graph
+    }
+
+    fn simulate(mut graph: GraphData) -> HashMap<u32, Vec3> {
+        let params = SimulationParams::new();
+        let mut node_map = HashMap::new();
+        for _ in 0..STEPS {
+            GraphService::calculate_layout_cpu(&mut graph, &mut node_map, &params).unwrap();
+        }
+        graph.nodes.iter().map(|n| (n.id, n.data.position.into())).collect()
+    }
+
+    // How far the new node pushed the existing ones compared with leaving it out entirely
+    fn max_disturbance(placed: GraphData) -> f32 {
+        let baseline = simulate(cluster());
+        let after = simulate(placed);
+        baseline.iter().map(|(id, p)| p.distance(after[id])).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_neighbour_placement_disturbs_layout_far_less() {
+        let mut on_sphere = with_new_node(cluster());
+        let last = on_sphere.nodes.len() - 1;
+        on_sphere.nodes[last].data.position = fibonacci_sphere(0, 1, SPHERE_RADIUS).into();
+
+        let mut near_neighbours = with_new_node(cluster());
+        place_new_nodes(&mut near_neighbours, &HashSet::from([NEW_NODE]), PLACEMENT_JITTER, SPHERE_RADIUS, &mut rand::thread_rng());
+
+        let (sphere, neighbour) = (max_disturbance(on_sphere), max_disturbance(near_neighbours));
+        assert!(neighbour * 5.0 < sphere, "sphere {} vs neighbour placement {}", sphere, neighbour);
+    }
+
+    #[test]
+    fn test_isolated_and_chained_nodes() {
+        let mut graph = cluster();
+        for (id, name) in [(50, "a"), (51, "b"), (52, "lonely")] {
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+        }
+        // 51 only links to 50, which only links to the cluster
+        graph.edges.push(Edge::new(50, 1, 1.0));
+        graph.edges.push(Edge::new(51, 50, 1.0));
+        let settling = place_new_nodes(&mut graph, &HashSet::from([50, 51, 52]), 0.0, SPHERE_RADIUS, &mut rand::thread_rng());
+
+        let position = |id: u32| -> Vec3 { graph.nodes.iter().find(|n| n.id == id).unwrap().data.position.into() };
+        assert!(position(50).distance(position(1)) < 1e-4);
+        assert!(position(51).distance(position(50)) < 1e-4);
+        assert!((position(52).length() - SPHERE_RADIUS).abs() < 1e-3);
+        assert_eq!(settling, HashSet::from([1, 50, 51, 52]));
+    }
+
+    #[test]
+    fn test_damping_keeps_a_fraction_of_the_step() {
+        let mut node = Node::new_with_id("n".to_string(), Some(1));
+        node.data.position = Vec3Data::new(10.0, 0.0, 0.0);
+        node.data.velocity = Vec3Data::new(5.0, 0.0, 0.0);
+        damp_step(Vec3Data::zero(), &mut node.data, 0.8);
+        assert!((node.data.position.x - 2.0).abs() < 1e-5);
+        assert!((node.data.velocity.x - 1.0).abs() < 1e-5);
+    }
+}
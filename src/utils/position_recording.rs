@@ -0,0 +1,464 @@
+//! Recording and replay of broadcast position frames.
+//!
+//! A recording is a short header followed by frames. Each frame holds its offset in
+//! ms from the start of the recording, whether it is a keyframe (every node) or a
+//! delta (only the nodes that moved), and the payload exactly as it was broadcast
+//! (binary_protocol encoding). The first frame is always a keyframe, so the full
+//! position table at any point can be rebuilt by folding frames up to it.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::models::simulation_params::SimulationMode;
+use crate::utils::binary_protocol;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+const MAGIC: &[u8; 4] = b"VFRC";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: u64 = 6;
+// offset_ms (u32) + kind (u8) + payload length (u32)
+const FRAME_HEADER_LEN: u64 = 9;
+
+pub const MIN_REPLAY_SPEED: f32 = 0.1;
+pub const MAX_REPLAY_SPEED: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameKind {
+    Keyframe,
+    Delta,
+}
+
+impl FrameKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameKind::Keyframe => 0,
+            FrameKind::Delta => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(FrameKind::Keyframe),
+            1 => Some(FrameKind::Delta),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub offset_ms: u32,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingStatus {
+    pub path: PathBuf,
+    pub frames: usize,
+    pub bytes: u64,
+    pub duration_ms: u32,
+    // Set once a size or duration cap stopped the recording
+    pub capped: bool,
+}
+
+/// Appends broadcast frames to a recording file until stopped or a cap is hit
+pub struct PositionRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+    frames: usize,
+    bytes: u64,
+    last_offset_ms: u32,
+    max_bytes: u64,
+    max_duration: Duration,
+    capped: bool,
+}
+
+impl PositionRecorder {
+    pub fn create(path: &Path, max_bytes: u64, max_duration: Duration) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC).map_err(|e| e.to_string())?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes()).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            started: Instant::now(),
+            frames: 0,
+            bytes: HEADER_LEN,
+            last_offset_ms: 0,
+            max_bytes,
+            max_duration,
+            capped: false,
+        })
+    }
+
+    /// Appends a frame. Returns false, without writing, once a cap has been reached;
+    /// the caller should then finish the recording.
+    pub fn record(&mut self, kind: FrameKind, payload: &[u8]) -> Result<bool, String> {
+        let elapsed = self.started.elapsed();
+        self.record_at(elapsed, kind, payload)
+    }
+
+    fn record_at(&mut self, elapsed: Duration, kind: FrameKind, payload: &[u8]) -> Result<bool, String> {
+        if self.capped {
+            return Ok(false);
+        }
+        // A recording has to open with a keyframe or it can't be replayed
+        if self.frames == 0 && kind != FrameKind::Keyframe {
+            return Err("Recording must start with a keyframe".to_string());
+        }
+        let frame_len = FRAME_HEADER_LEN + payload.len() as u64;
+        if elapsed > self.max_duration || self.bytes + frame_len > self.max_bytes {
+            self.capped = true;
+            return Ok(false);
+        }
+
+        let offset_ms = elapsed.as_millis().min(u32::MAX as u128) as u32;
+        self.writer.write_all(&offset_ms.to_le_bytes())
+            .and_then(|_| self.writer.write_all(&[kind.to_byte()]))
+            .and_then(|_| self.writer.write_all(&(payload.len() as u32).to_le_bytes()))
+            .and_then(|_| self.writer.write_all(payload))
+            .map_err(|e| format!("Failed to write to {:?}: {}", self.path, e))?;
+        self.frames += 1;
+        self.bytes += frame_len;
+        self.last_offset_ms = offset_ms;
+        Ok(true)
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        RecordingStatus {
+            path: self.path.clone(),
+            frames: self.frames,
+            bytes: self.bytes,
+            duration_ms: self.last_offset_ms,
+            capped: self.capped,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<RecordingStatus, String> {
+        self.writer.flush().map_err(|e| format!("Failed to flush {:?}: {}", self.path, e))?;
+        Ok(self.status())
+    }
+}
+
+/// Parses a recording. A frame cut short (the server died mid-write) ends the
+/// recording rather than failing it.
+pub fn parse_recording(data: &[u8]) -> Result<Vec<RecordedFrame>, String> {
+    if data.len() < HEADER_LEN as usize || &data[..4] != MAGIC {
+        return Err("Not a position recording".to_string());
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version != FORMAT_VERSION {
+        return Err(format!("Unsupported recording format version {}", version));
+    }
+
+    let mut frames = Vec::new();
+    let mut rest = &data[HEADER_LEN as usize..];
+    while rest.len() >= FRAME_HEADER_LEN as usize {
+        let offset_ms = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+        let kind = FrameKind::from_byte(rest[4]).ok_or_else(|| format!("Unknown frame kind {}", rest[4]))?;
+        let len = u32::from_le_bytes(rest[5..9].try_into().unwrap()) as usize;
+        rest = &rest[FRAME_HEADER_LEN as usize..];
+        if rest.len() < len {
+            break;
+        }
+        frames.push(RecordedFrame { offset_ms, kind, payload: rest[..len].to_vec() });
+        rest = &rest[len..];
+    }
+
+    match frames.first() {
+        Some(first) if first.kind == FrameKind::Keyframe => Ok(frames),
+        Some(_) => Err("Recording does not start with a keyframe".to_string()),
+        None => Err("Recording has no frames".to_string()),
+    }
+}
+
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedFrame>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    parse_recording(&data)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayStatus {
+    pub playing: bool,
+    pub speed: f32,
+    pub position_ms: u32,
+    pub duration_ms: u32,
+    pub frame: usize,
+    pub frames: usize,
+}
+
+/// What the graph actor is doing with position streams right now
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingState {
+    pub mode: SimulationMode,
+    pub recording: Option<RecordingStatus>,
+    pub replay: Option<ReplayStatus>,
+}
+
+/// Plays recorded frames back against the clock, tracking the full position table
+/// so clients joining mid-stream, or a seek, can be given a keyframe
+pub struct PositionReplay {
+    frames: Vec<RecordedFrame>,
+    // Next frame to emit
+    next: usize,
+    cursor_ms: f64,
+    speed: f32,
+    playing: bool,
+    state: BTreeMap<u32, BinaryNodeData>,
+}
+
+impl PositionReplay {
+    pub fn new(frames: Vec<RecordedFrame>, speed: f32) -> Result<Self, String> {
+        if frames.first().map(|f| f.kind) != Some(FrameKind::Keyframe) {
+            return Err("Recording does not start with a keyframe".to_string());
+        }
+        let mut replay = Self {
+            frames,
+            next: 0,
+            cursor_ms: 0.0,
+            speed: 1.0,
+            playing: false,
+            state: BTreeMap::new(),
+        };
+        replay.set_speed(speed)?;
+        replay.seek(0);
+        Ok(replay)
+    }
+
+    pub fn play(&mut self) {
+        // Playing from the end starts over
+        if self.next >= self.frames.len() {
+            self.seek(0);
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) -> Result<(), String> {
+        if !(MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED).contains(&speed) {
+            return Err(format!("speed must be between {} and {}", MIN_REPLAY_SPEED, MAX_REPLAY_SPEED));
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        self.frames.last().map(|f| f.offset_ms).unwrap_or(0)
+    }
+
+    /// Moves the clock on by `elapsed` wall time (scaled by speed) and returns the
+    /// frames that fell due, in recorded order. Pauses itself at the end.
+    pub fn advance(&mut self, elapsed: Duration) -> Vec<RecordedFrame> {
+        if !self.playing {
+            return Vec::new();
+        }
+        self.cursor_ms += elapsed.as_secs_f64() * 1000.0 * self.speed as f64;
+
+        let mut due = Vec::new();
+        while let Some(frame) = self.frames.get(self.next) {
+            if frame.offset_ms as f64 > self.cursor_ms {
+                break;
+            }
+            self.apply(self.next);
+            due.push(self.frames[self.next].clone());
+            self.next += 1;
+        }
+        if self.next >= self.frames.len() {
+            self.playing = false;
+            self.cursor_ms = self.duration_ms() as f64;
+        }
+        due
+    }
+
+    /// Jumps to `position_ms` and returns the keyframe for that point. Playback
+    /// state (playing or paused) is kept.
+    pub fn seek(&mut self, position_ms: u32) -> Vec<(u32, BinaryNodeData)> {
+        let position_ms = position_ms.min(self.duration_ms());
+        self.state.clear();
+        self.next = 0;
+        // The opening keyframe always applies, even when seeking to before it
+        while self.next < self.frames.len()
+            && (self.next == 0 || self.frames[self.next].offset_ms <= position_ms)
+        {
+            self.apply(self.next);
+            self.next += 1;
+        }
+        self.cursor_ms = position_ms.max(self.frames[0].offset_ms) as f64;
+        self.keyframe()
+    }
+
+    /// Every node's position as of the current point in the replay
+    pub fn keyframe(&self) -> Vec<(u32, BinaryNodeData)> {
+        self.state.iter().map(|(id, data)| (*id, *data)).collect()
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            playing: self.playing,
+            speed: self.speed,
+            position_ms: self.cursor_ms as u32,
+            duration_ms: self.duration_ms(),
+            frame: self.next,
+            frames: self.frames.len(),
+        }
+    }
+
+    fn apply(&mut self, index: usize) {
+        let frame = &self.frames[index];
+        // Frames were written by encode_node_data, so they always decode
+        let positions = binary_protocol::decode_node_data(&frame.payload).unwrap_or_default();
+        if frame.kind == FrameKind::Keyframe {
+            self.state.clear();
+        }
+        self.state.extend(positions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    fn node(id: u32, x: f32) -> (u32, BinaryNodeData) {
+        (id, BinaryNodeData {
+            position: Vec3Data { x, y: 0.0, z: 0.0 },
+            velocity: Vec3Data { x: 0.0, y: 0.0, z: 0.0 },
+            mass: 100,
+            flags: 0,
+            padding: [0, 0],
+        })
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("recording-test-{}", uuid::Uuid::new_v4()))
+            .join("run.vfrc")
+    }
+
+    // Keyframe of three nodes, then node 1 drifting right every 100ms
+    fn synthetic_run(recorder: &mut PositionRecorder) -> Vec<RecordedFrame> {
+        let mut written = Vec::new();
+        let keyframe = binary_protocol::encode_node_data(&[node(1, 0.0), node(2, 5.0), node(3, 9.0)]);
+        assert!(recorder.record_at(Duration::ZERO, FrameKind::Keyframe, &keyframe).unwrap());
+        written.push(RecordedFrame { offset_ms: 0, kind: FrameKind::Keyframe, payload: keyframe });
+        for step in 1..=5u32 {
+            let delta = binary_protocol::encode_node_data(&[node(1, step as f32)]);
+            let offset = Duration::from_millis(step as u64 * 100);
+            assert!(recorder.record_at(offset, FrameKind::Delta, &delta).unwrap());
+            written.push(RecordedFrame { offset_ms: step * 100, kind: FrameKind::Delta, payload: delta });
+        }
+        written
+    }
+
+    #[test]
+    fn test_replayed_frames_match_recording() {
+        let path = temp_path();
+        let mut recorder = PositionRecorder::create(&path, 1 << 20, Duration::from_secs(60)).unwrap();
+        let written = synthetic_run(&mut recorder);
+        let status = recorder.finish().unwrap();
+        assert_eq!((status.frames, status.duration_ms), (6, 500));
+
+        let frames = read_recording(&path).unwrap();
+        assert_eq!(frames, written);
+
+        // Real time: frames come out as their offsets pass
+        let mut replay = PositionReplay::new(frames, 1.0).unwrap();
+        replay.play();
+        let mut replayed = Vec::new();
+        for _ in 0..6 {
+            replayed.extend(replay.advance(Duration::from_millis(100)));
+        }
+        // The opening keyframe was applied on load, so only the deltas are emitted
+        assert_eq!(replayed, written[1..].to_vec());
+        assert!(!replay.status().playing);
+
+        // Double speed gets through the run in half the wall time
+        let mut fast = PositionReplay::new(written.clone(), 2.0).unwrap();
+        fast.play();
+        assert_eq!(fast.advance(Duration::from_millis(250)).len(), 5);
+    }
+
+    #[test]
+    fn test_seek_and_mid_stream_keyframe() {
+        let path = temp_path();
+        let mut recorder = PositionRecorder::create(&path, 1 << 20, Duration::from_secs(60)).unwrap();
+        let written = synthetic_run(&mut recorder);
+        recorder.finish().unwrap();
+
+        let mut replay = PositionReplay::new(written, 1.0).unwrap();
+        replay.play();
+        replay.advance(Duration::from_millis(250));
+        // A client joining now needs every node, not just the last delta
+        let keyframe = replay.keyframe();
+        assert_eq!(keyframe.iter().map(|(id, d)| (*id, d.position.x)).collect::<Vec<_>>(),
+            vec![(1, 2.0), (2, 5.0), (3, 9.0)]);
+
+        let keyframe = replay.seek(400);
+        assert_eq!(keyframe[0].1.position.x, 4.0);
+        assert_eq!(replay.advance(Duration::from_millis(100)).len(), 1);
+
+        // Seeking back before any delta gives the opening layout
+        let keyframe = replay.seek(0);
+        assert_eq!(keyframe[0].1.position.x, 0.0);
+        assert_eq!(replay.status().frame, 1);
+    }
+
+    #[test]
+    fn test_caps_stop_recording() {
+        let path = temp_path();
+        let keyframe = binary_protocol::encode_node_data(&[node(1, 0.0), node(2, 5.0)]);
+        let delta = binary_protocol::encode_node_data(&[node(1, 1.0)]);
+
+        // Room for the keyframe and two deltas
+        let max_bytes = HEADER_LEN + 3 * FRAME_HEADER_LEN + keyframe.len() as u64 + 2 * delta.len() as u64;
+        let mut recorder = PositionRecorder::create(&path, max_bytes, Duration::from_secs(60)).unwrap();
+        assert!(recorder.record_at(Duration::ZERO, FrameKind::Keyframe, &keyframe).unwrap());
+        assert!(recorder.record_at(Duration::from_millis(10), FrameKind::Delta, &delta).unwrap());
+        assert!(recorder.record_at(Duration::from_millis(20), FrameKind::Delta, &delta).unwrap());
+        assert!(!recorder.record_at(Duration::from_millis(30), FrameKind::Delta, &delta).unwrap());
+        let status = recorder.finish().unwrap();
+        assert!(status.capped);
+        assert_eq!(status.bytes, max_bytes);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), max_bytes);
+
+        let mut recorder = PositionRecorder::create(&temp_path(), 1 << 20, Duration::from_secs(1)).unwrap();
+        assert!(recorder.record_at(Duration::ZERO, FrameKind::Keyframe, &keyframe).unwrap());
+        assert!(!recorder.record_at(Duration::from_secs(2), FrameKind::Delta, &delta).unwrap());
+        assert_eq!(recorder.status().frames, 1);
+    }
+
+    #[test]
+    fn test_truncated_and_invalid_recordings() {
+        let path = temp_path();
+        let mut recorder = PositionRecorder::create(&path, 1 << 20, Duration::from_secs(60)).unwrap();
+        synthetic_run(&mut recorder);
+        recorder.finish().unwrap();
+
+        // Losing the tail mid-frame drops only that frame
+        let mut data = std::fs::read(&path).unwrap();
+        data.truncate(data.len() - 3);
+        assert_eq!(parse_recording(&data).unwrap().len(), 5);
+
+        assert!(parse_recording(b"nope").is_err());
+        let mut recorder = PositionRecorder::create(&temp_path(), 1 << 20, Duration::from_secs(60)).unwrap();
+        assert!(recorder.record(FrameKind::Delta, &[]).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! 2-D projections for clients that only draw a flat view, such as a top-down minimap.
+//! A projection is a 3×2 matrix: each output component is a dot product of one row
+//! with the 3-D vector.
+
+use serde::Serialize;
+
+use crate::types::vec3::Vec3Data;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Projection {
+    pub rows: [[f32; 3]; 2],
+}
+
+impl Projection {
+    /// Top-down: x across, z down
+    pub const XZ: Projection = Projection { rows: [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]] };
+    /// Front-on: x across, y up
+    pub const XY: Projection = Projection { rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] };
+
+    /// Reads `"xz"`, `"xy"`, or a custom matrix as two rows of three or six numbers in row order
+    pub fn parse(value: &serde_json::Value) -> Result<Self, String> {
+        if let Some(name) = value.as_str() {
+            return match name {
+                "xz" => Ok(Self::XZ),
+                "xy" => Ok(Self::XY),
+                other => Err(format!("unknown projection '{}'; use xz, xy or a 3x2 matrix", other)),
+            };
+        }
+        let Some(values) = value.as_array() else {
+            return Err("projection must be xz, xy or a 3x2 matrix".to_string());
+        };
+        let flat: Vec<&serde_json::Value> = if values.len() == 2 && values.iter().all(|r| r.is_array()) {
+            values.iter().flat_map(|row| row.as_array().into_iter().flatten()).collect()
+        } else {
+            values.iter().collect()
+        };
+        if flat.len() != 6 {
+            return Err(format!("projection matrix needs 6 numbers, got {}", flat.len()));
+        }
+        let mut numbers = [0.0f32; 6];
+        for (slot, value) in numbers.iter_mut().zip(flat) {
+            *slot = value.as_f64().filter(|v| v.is_finite()).ok_or("projection matrix entries must be finite numbers")? as f32;
+        }
+        Ok(Self { rows: [[numbers[0], numbers[1], numbers[2]], [numbers[3], numbers[4], numbers[5]]] })
+    }
+
+    /// The query-string form: `xz`, `xy`, or six comma-separated numbers
+    pub fn parse_query(value: &str) -> Result<Self, String> {
+        if !value.contains(',') {
+            return Self::parse(&serde_json::Value::from(value.trim()));
+        }
+        let numbers: Vec<f64> = value.split(',')
+            .map(|n| n.trim().parse::<f64>().map_err(|_| format!("'{}' is not a number", n.trim())))
+            .collect::<Result<_, _>>()?;
+        Self::parse(&serde_json::Value::from(numbers))
+    }
+
+    pub fn apply(&self, v: Vec3Data) -> [f32; 2] {
+        let [a, b] = self.rows;
+        [a[0] * v.x + a[1] * v.y + a[2] * v.z, b[0] * v.x + b[1] * v.y + b[2] * v.z]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_named_and_custom_projections() {
+        assert_eq!(Projection::parse(&json!("xz")).unwrap(), Projection::XZ);
+        assert_eq!(Projection::XZ.apply(Vec3Data::new(1.0, 2.0, 3.0)), [1.0, 3.0]);
+
+        let nested = Projection::parse(&json!([[0.5, 0, 0], [0, 0, -2]])).unwrap();
+        let flat = Projection::parse(&json!([0.5, 0, 0, 0, 0, -2])).unwrap();
+        assert_eq!(nested, flat);
+        assert_eq!(flat.apply(Vec3Data::new(4.0, 9.0, 1.0)), [2.0, -2.0]);
+
+        assert_eq!(Projection::parse_query("0.5,0,0, 0,0,-2").unwrap(), flat);
+        assert_eq!(Projection::parse_query("xy").unwrap(), Projection::XY);
+        assert!(Projection::parse_query("1,0,x,0,1,0").is_err());
+
+        assert!(Projection::parse(&json!("yz")).is_err());
+        assert!(Projection::parse(&json!([1, 0, 0, 0, 1])).is_err());
+        assert!(Projection::parse(&json!([1, 0, 0, 0, 1, "z"])).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! Token-bucket limits on REST mutations, so a runaway script can't hold the actors'
+//! locks. Each caller gets a bucket per budget: the session's pubkey when it has a
+//! valid one, the client address otherwise. Reads and websocket upgrades are GETs and
+//! pass straight through; they're throttled elsewhere. The client address is the socket
+//! peer; `X-Forwarded-For` is only believed when that peer is a configured proxy.
+//!
+//! One `RateLimiter` is built at startup and shared by every worker's middleware, and a
+//! background task drops the buckets that have refilled.
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{web, Error, ResponseError};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crate::config::{RateBudget, RateLimitSettings};
+use crate::handlers::api_error::ApiError;
+use crate::services::nostr_service::NostrService;
+use crate::utils::auth::session_pubkey;
+
+// Full buckets are dropped this often; a full bucket is the same as none
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_RETRY_AFTER_SECS: f64 = 3600.0;
+// Counter key for routes without their own budget
+pub const DEFAULT_ROUTE: &str = "default";
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, budget: &RateBudget, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * budget.per_second).min(budget.burst);
+        self.updated = now;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteCounters {
+    pub allowed: u64,
+    pub limited: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitCounters {
+    pub allowed: u64,
+    pub limited: u64,
+    pub routes: BTreeMap<String, RouteCounters>,
+}
+
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+    counters: Mutex<BTreeMap<String, RouteCounters>>,
+}
+
+pub fn is_mutation(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE)
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self { settings, buckets: Mutex::new(HashMap::new()), counters: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// The budget `path` falls under: the longest matching route prefix, or the default
+    pub fn budget_for(&self, path: &str) -> (&str, RateBudget) {
+        self.settings.routes.iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.trim_end_matches('/'))
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, budget)| (prefix.as_str(), *budget))
+            .unwrap_or((DEFAULT_ROUTE, self.settings.default))
+    }
+
+    /// Takes a token from `caller`'s bucket for `path`, or says how many seconds until
+    /// there is one
+    pub fn check(&self, caller: &str, path: &str, now: Instant) -> Result<(), u64> {
+        let (route, budget) = self.budget_for(path);
+        let result = {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+            let bucket = buckets.entry((caller.to_string(), route.to_string()))
+                .or_insert(Bucket { tokens: budget.burst, updated: now });
+            bucket.refill(&budget, now);
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                Ok(())
+            } else {
+                let wait = ((1.0 - bucket.tokens) / budget.per_second).ceil();
+                Err(wait.clamp(1.0, MAX_RETRY_AFTER_SECS) as u64)
+            }
+        };
+
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let route_counters = counters.entry(route.to_string()).or_default();
+        match result {
+            Ok(()) => route_counters.allowed += 1,
+            Err(_) => route_counters.limited += 1,
+        }
+        result
+    }
+
+    /// Drops the buckets that have refilled by `now`. Returns how many are left.
+    pub fn prune(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|(_, route), bucket| {
+            let budget = self.budget_for(route).1;
+            bucket.refill(&budget, now);
+            bucket.tokens < budget.burst
+        });
+        buckets.len()
+    }
+
+    pub fn start(self: Arc<Self>) {
+        if !self.settings.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let left = self.prune(Instant::now());
+                debug!("Rate limiter holds {} buckets after pruning", left);
+            }
+        });
+    }
+
+    /// The address to charge: the socket peer, or the forwarded client address when the
+    /// peer is one of the trusted proxies
+    pub fn client_addr(&self, req: &ServiceRequest) -> String {
+        let Some(peer) = req.peer_addr().map(|addr| addr.ip()) else {
+            return "unknown".to_string();
+        };
+        let trusted = self.settings.trusted_proxies.iter()
+            .any(|proxy| proxy.parse::<IpAddr>().is_ok_and(|proxy| proxy == peer));
+        if trusted {
+            if let Some(forwarded) = req.connection_info().realip_remote_addr() {
+                return forwarded.to_string();
+            }
+        }
+        peer.to_string()
+    }
+
+    pub fn counters(&self) -> RateLimitCounters {
+        let routes = self.counters.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        RateLimitCounters {
+            allowed: routes.values().map(|c| c.allowed).sum(),
+            limited: routes.values().map(|c| c.limited).sum(),
+            routes,
+        }
+    }
+}
+
+/// Middleware applying a shared `RateLimiter` to mutation requests
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), limiter: Arc::clone(&self.limiter) }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let limiter = Arc::clone(&self.limiter);
+        Box::pin(async move {
+            if !limiter.enabled() || !is_mutation(req.method()) {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            }
+            let nostr_service = req.app_data::<web::Data<NostrService>>().cloned();
+            let caller = match session_pubkey(req.request(), nostr_service.as_ref().map(|s| s.get_ref())).await {
+                Some(pubkey) => format!("user:{}", pubkey),
+                None => format!("ip:{}", limiter.client_addr(&req)),
+            };
+            match limiter.check(&caller, req.path(), Instant::now()) {
+                Ok(()) => service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(retry_after_secs) => {
+                    warn!("Rate limited {} {} for {}", req.method(), req.path(), caller);
+                    let response = ApiError::RateLimited { retry_after_secs }.error_response();
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test as actix_test, App, HttpResponse};
+
+    fn settings() -> RateLimitSettings {
+        RateLimitSettings {
+            enabled: true,
+            default: RateBudget { burst: 3.0, per_second: 1.0 },
+            routes: HashMap::from([
+                ("/api/graph".to_string(), RateBudget { burst: 5.0, per_second: 1.0 }),
+                ("/api/graph/refresh".to_string(), RateBudget { burst: 1.0, per_second: 0.25 }),
+            ]),
+            trusted_proxies: vec!["127.0.0.1".to_string()],
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_bursts_over_the_budget_get_429() {
+        let limiter = Arc::new(RateLimiter::new(settings()));
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(RateLimit::new(Arc::clone(&limiter)))
+                .route("/api/files/{name}", web::post().to(HttpResponse::Ok))
+                .route("/api/files/{name}", web::get().to(HttpResponse::Ok)),
+        ).await;
+        let post = |ip: &str| actix_test::TestRequest::post().uri("/api/files/a").peer_addr(format!("{}:5000", ip).parse().unwrap()).to_request();
+
+        // A burst within the budget goes through
+        for _ in 0..3 {
+            assert_eq!(actix_test::call_service(&app, post("10.0.0.1")).await.status(), StatusCode::OK);
+        }
+        let limited = actix_test::call_service(&app, post("10.0.0.1")).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers().get("Retry-After").unwrap(), "1");
+        let body: serde_json::Value = actix_test::read_body_json(limited).await;
+        assert_eq!(body["code"], "rate_limited");
+
+        // Other callers and reads aren't affected
+        assert_eq!(actix_test::call_service(&app, post("10.0.0.2")).await.status(), StatusCode::OK);
+        for _ in 0..10 {
+            let get = actix_test::TestRequest::get().uri("/api/files/a").peer_addr("10.0.0.1:5000".parse().unwrap()).to_request();
+            assert_eq!(actix_test::call_service(&app, get).await.status(), StatusCode::OK);
+        }
+
+        let counters = limiter.counters();
+        assert_eq!((counters.allowed, counters.limited), (4, 1));
+        assert_eq!(counters.routes[DEFAULT_ROUTE].limited, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_forwarded_address_only_trusted_from_proxies() {
+        let limiter = Arc::new(RateLimiter::new(settings()));
+        let app = actix_test::init_service(
+            App::new().wrap(RateLimit::new(Arc::clone(&limiter))).route("/api/files/{name}", web::post().to(HttpResponse::Ok)),
+        ).await;
+        let post = |peer: &str, forwarded: &str| actix_test::TestRequest::post().uri("/api/files/a")
+            .peer_addr(format!("{}:5000", peer).parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded.to_string()))
+            .to_request();
+
+        // A direct client can't dodge its budget by making up forwarded addresses
+        for i in 0..3 {
+            assert_eq!(actix_test::call_service(&app, post("10.0.0.9", &format!("1.1.1.{}", i))).await.status(), StatusCode::OK);
+        }
+        assert_eq!(actix_test::call_service(&app, post("10.0.0.9", "1.1.1.99")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Through the proxy each forwarded client has its own budget
+        for i in 0..4 {
+            assert_eq!(actix_test::call_service(&app, post("127.0.0.1", &format!("2.2.2.{}", i))).await.status(), StatusCode::OK);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_limiter_lets_everything_through() {
+        let limiter = Arc::new(RateLimiter::new(RateLimitSettings { enabled: false, ..settings() }));
+        let app = actix_test::init_service(
+            App::new().wrap(RateLimit::new(limiter)).route("/api/files/{name}", web::delete().to(HttpResponse::Ok)),
+        ).await;
+        for _ in 0..10 {
+            let req = actix_test::TestRequest::delete().uri("/api/files/a").to_request();
+            assert_eq!(actix_test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+    }
+
+    #[test]
+    fn test_route_budgets_and_refill() {
+        let limiter = RateLimiter::new(settings());
+        assert_eq!(limiter.budget_for("/api/graph/refresh").0, "/api/graph/refresh");
+        assert_eq!(limiter.budget_for("/api/graph/nodes/3").0, "/api/graph");
+        assert_eq!(limiter.budget_for("/api/graphs").0, DEFAULT_ROUTE);
+
+        let start = Instant::now();
+        assert_eq!(limiter.check("user:a", "/api/graph/refresh", start), Ok(()));
+        // One token at 0.25/s is four seconds away
+        assert_eq!(limiter.check("user:a", "/api/graph/refresh", start), Err(4));
+        assert_eq!(limiter.check("user:a", "/api/graph/refresh", start + Duration::from_secs(2)), Err(2));
+        assert_eq!(limiter.check("user:a", "/api/graph/refresh", start + Duration::from_secs(4)), Ok(()));
+        // The refresh bucket is separate from the rest of the graph routes
+        for _ in 0..5 {
+            assert_eq!(limiter.check("user:a", "/api/graph/nodes", start), Ok(()));
+        }
+        assert!(limiter.check("user:a", "/api/graph/edges", start).is_err());
+        assert_eq!(limiter.check("user:b", "/api/graph/edges", start), Ok(()));
+
+        // Once every bucket has refilled the prune drops them all
+        assert!(limiter.prune(start) > 0);
+        assert_eq!(limiter.prune(start + Duration::from_secs(60)), 0);
+    }
+}
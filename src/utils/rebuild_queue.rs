@@ -0,0 +1,206 @@
+//! Coalescing queue for graph rebuilds. A rebuild asked for while another runs waits for
+//! it rather than being refused; only the newest waiting request is kept, and it runs as
+//! soon as the current build finishes. Every caller gets the newest graph built, so a
+//! superseded request still sees its data or something newer applied.
+
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+type Waiter<O> = oneshot::Sender<Result<O, String>>;
+
+struct Pending<R, O> {
+    request: R,
+    // Every caller whose request this one replaced, and its own
+    waiters: Vec<Waiter<O>>,
+}
+
+struct QueueState<R, O> {
+    running: bool,
+    pending: Option<Pending<R, O>>,
+    builds: u64,
+    coalesced: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildStatus {
+    pub in_progress: bool,
+    // Callers waiting on the pending rebuild
+    pub queued: usize,
+    pub builds: u64,
+    // Requests replaced by a newer one before they ran
+    pub coalesced: u64,
+}
+
+pub struct RebuildQueue<R, O> {
+    state: Arc<Mutex<QueueState<R, O>>>,
+}
+
+impl<R, O> Default for RebuildQueue<R, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, O> RebuildQueue<R, O> {
+    pub fn new() -> Self {
+        Self { state: Arc::new(Mutex::new(QueueState { running: false, pending: None, builds: 0, coalesced: 0 })) }
+    }
+
+    pub fn status(&self) -> RebuildStatus {
+        let state = self.state.lock().unwrap();
+        RebuildStatus {
+            in_progress: state.running,
+            queued: state.pending.as_ref().map_or(0, |p| p.waiters.len()),
+            builds: state.builds,
+            coalesced: state.coalesced,
+        }
+    }
+}
+
+impl<R: Send + 'static, O: Clone + Send + 'static> RebuildQueue<R, O> {
+    /// Builds `request`, or if a build is running queues it to run next, replacing any
+    /// request already waiting. Resolves with the newest build once nothing newer waits.
+    /// The builds run on their own task, so a caller that stops waiting cancels nothing.
+    pub async fn submit<F, Fut>(&self, request: R, build: F) -> Result<O, String>
+    where
+        F: Fn(R) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<O, String>> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let first = {
+            let mut state = self.state.lock().unwrap();
+            if state.running {
+                let mut waiters = match state.pending.take() {
+                    Some(replaced) => {
+                        state.coalesced += 1;
+                        replaced.waiters
+                    }
+                    None => Vec::new(),
+                };
+                waiters.push(tx);
+                state.pending = Some(Pending { request, waiters });
+                None
+            } else {
+                state.running = true;
+                Some(Pending { request, waiters: vec![tx] })
+            }
+        };
+        if let Some(first) = first {
+            tokio::spawn(Self::run(self.state.clone(), build, first));
+        }
+        rx.await.unwrap_or_else(|_| Err("Rebuild stopped before finishing".to_string()))
+    }
+
+    // Builds `first`, then whatever is pending until nothing is, then answers everyone
+    // who waited
+    async fn run<F, Fut>(state: Arc<Mutex<QueueState<R, O>>>, build: F, first: Pending<R, O>)
+    where
+        F: Fn(R) -> Fut,
+        Fut: Future<Output = Result<O, String>>,
+    {
+        // Should a build panic, whoever waits is told rather than left hanging
+        let running = scopeguard::guard(state.clone(), |state| {
+            let mut state = state.lock().unwrap();
+            state.running = false;
+            state.pending = None;
+        });
+        let mut waiters = first.waiters;
+        let mut result = build(first.request).await;
+        loop {
+            let next = {
+                let mut state = state.lock().unwrap();
+                state.builds += 1;
+                match state.pending.take() {
+                    Some(next) => next,
+                    None => {
+                        state.running = false;
+                        break;
+                    }
+                }
+            };
+            waiters.extend(next.waiters);
+            result = build(next.request).await;
+        }
+        scopeguard::ScopeGuard::into_inner(running);
+
+        for waiter in waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    // A 20ms build that counts how often it ran
+    fn counted(builds: &Arc<AtomicUsize>) -> impl Fn(&'static str) -> BoxFuture<'static, Result<String, String>> + Clone {
+        let builds = builds.clone();
+        move |store| {
+            builds.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(format!("graph of {}", store))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rapid_rebuilds_coalesce_into_the_last_one() {
+        let queue: RebuildQueue<&str, String> = RebuildQueue::new();
+        let builds = Arc::new(AtomicUsize::new(0));
+        let build = counted(&builds);
+
+        // The first starts building; the second waits, then is replaced by the third
+        let (first, second, third) = tokio::join!(
+            queue.submit("store 1", build.clone()),
+            async {
+                tokio::task::yield_now().await;
+                queue.submit("store 2", build.clone()).await
+            },
+            async {
+                tokio::task::yield_now().await;
+                tokio::task::yield_now().await;
+                queue.submit("store 3", build.clone()).await
+            },
+        );
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+        for result in [first, second, third] {
+            assert_eq!(result.unwrap(), "graph of store 3");
+        }
+        assert_eq!(queue.status(), RebuildStatus { in_progress: false, queued: 0, builds: 2, coalesced: 1 });
+
+        // With nothing running a rebuild goes straight through
+        assert_eq!(queue.submit("store 4", build).await.unwrap(), "graph of store 4");
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_queued_rebuilds_survive_the_first_caller_going_away() {
+        let queue: RebuildQueue<&str, String> = RebuildQueue::new();
+        let builds = Arc::new(AtomicUsize::new(0));
+        let build = counted(&builds);
+
+        // The first caller stops waiting mid-build, as a disconnected HTTP client does
+        let gave_up = tokio::time::timeout(Duration::from_millis(5), queue.submit("store 1", build.clone())).await;
+        assert!(gave_up.is_err());
+        assert!(queue.status().in_progress);
+
+        let (second, third) = tokio::join!(
+            queue.submit("store 2", build.clone()),
+            async {
+                tokio::task::yield_now().await;
+                queue.submit("store 3", build.clone()).await
+            },
+        );
+        assert_eq!(second.unwrap(), "graph of store 3");
+        assert_eq!(third.unwrap(), "graph of store 3");
+        assert_eq!(queue.status(), RebuildStatus { in_progress: false, queued: 0, builds: 2, coalesced: 1 });
+    }
+}
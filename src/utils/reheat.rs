@@ -0,0 +1,125 @@
+//! Shaking a settled layout out of a poor local minimum. A reheat gives every chosen node
+//! a velocity kick in a random direction, sized so each adds `intensity` of kinetic
+//! energy; the kicks' directions are independent, so the layout as a whole gains close to
+//! `intensity` per node. Physics then spends that energy moving the graph somewhere new.
+
+use glam::Vec3;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::models::graph_filter::GraphFilter;
+use crate::models::node::Node;
+use crate::models::simulation_params::SimulationPhase;
+
+// Beyond this the kicks throw nodes far past anything physics pulls back in a few seconds
+pub const MAX_INTENSITY: f32 = 100.0;
+
+/// Which nodes get kicked
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReheatScope {
+    #[default]
+    Global,
+    // Only the nodes the filter matches
+    Filter(GraphFilter),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReheatRequest {
+    // Kinetic energy added per kicked node
+    pub intensity: f32,
+    #[serde(default)]
+    pub scope: ReheatScope,
+}
+
+impl ReheatRequest {
+    pub fn validate(&self) -> Result<(), (String, String)> {
+        if !self.intensity.is_finite() || self.intensity <= 0.0 || self.intensity > MAX_INTENSITY {
+            return Err(("intensity".into(), format!("must be above 0 and at most {}", MAX_INTENSITY)));
+        }
+        if let ReheatScope::Filter(filter) = &self.scope {
+            if filter.is_empty() {
+                return Err(("scope.filter".into(), "set at least one filter field".into()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReheatOutcome {
+    pub kicked: usize,
+    // Matched but pinned, fixed or inactive
+    pub held: usize,
+    // Kinetic energy of the whole layout either side of the kicks
+    pub energy_before: f32,
+    pub energy_after: f32,
+    pub phase: SimulationPhase,
+}
+
+impl ReheatOutcome {
+    /// What clients are told so they expect the layout to move
+    pub fn to_event(&self, intensity: f32) -> String {
+        serde_json::json!({
+            "type": "physics_reheat",
+            "intensity": intensity,
+            "kicked": self.kicked,
+        }).to_string()
+    }
+}
+
+/// Kinetic energy of `nodes`, counting each as unit mass as the layout step does
+pub fn kinetic_energy<'a>(nodes: impl IntoIterator<Item = &'a Node>) -> f32 {
+    nodes.into_iter().map(|node| 0.5 * Vec3::from(node.data.velocity).length_squared()).sum()
+}
+
+/// A velocity kick carrying `intensity` of kinetic energy, in a direction uniform over the sphere
+pub fn kick(intensity: f32, rng: &mut impl Rng) -> Vec3 {
+    let z: f32 = rng.gen_range(-1.0..=1.0);
+    let angle: f32 = rng.gen_range(0.0..TAU);
+    let ring = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(ring * angle.cos(), ring * angle.sin(), z) * (2.0 * intensity).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_kicks_carry_the_requested_energy() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut nodes: Vec<Node> = (1..=200).map(|id| Node::new_with_id(format!("n{}", id), Some(id))).collect();
+        for node in &mut nodes {
+            node.data.velocity = Vec3::new(0.5, 0.0, 0.0).into();
+        }
+        let before = kinetic_energy(&nodes);
+        for node in &mut nodes {
+            let kick = kick(2.0, &mut rng);
+            assert!((0.5 * kick.length_squared() - 2.0).abs() < 1e-4);
+            node.data.velocity = (Vec3::from(node.data.velocity) + kick).into();
+        }
+        // Moving nodes gain or lose a little depending on direction; over many it evens out
+        let gained = kinetic_energy(&nodes) - before;
+        assert!((gained / 400.0 - 1.0).abs() < 0.1, "gained {}", gained);
+    }
+
+    #[test]
+    fn test_requests_are_checked() {
+        let request: ReheatRequest = serde_json::from_str(r#"{ "intensity": 1.5 }"#).unwrap();
+        assert_eq!(request.scope, ReheatScope::Global);
+        assert!(request.validate().is_ok());
+        let filtered: ReheatRequest = serde_json::from_str(r#"{ "intensity": 1, "scope": { "filter": { "group": "a" } } }"#).unwrap();
+        assert!(matches!(&filtered.scope, ReheatScope::Filter(filter) if filter.group.as_deref() == Some("a")));
+
+        for intensity in [0.0, -1.0, f32::NAN, MAX_INTENSITY + 1.0] {
+            assert_eq!(ReheatRequest { intensity, scope: ReheatScope::Global }.validate().unwrap_err().0, "intensity");
+        }
+        let unfiltered = ReheatRequest { intensity: 1.0, scope: ReheatScope::Filter(GraphFilter::default()) };
+        assert_eq!(unfiltered.validate().unwrap_err().0, "scope.filter");
+    }
+}
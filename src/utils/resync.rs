@@ -0,0 +1,162 @@
+//! Full resync for a client that thinks its local state is corrupt. Everything it
+//! needs goes out again in a fixed order, ending with `resync_complete`, and each
+//! client is throttled so resyncs can't be used to flood the server.
+
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::models::annotation::Annotation;
+use crate::models::edge::Edge;
+use crate::models::pins::PinReport;
+use crate::utils::binary_protocol;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+pub const RESYNC_MIN_INTERVAL: Duration = Duration::from_secs(2);
+pub const RESYNC_BURST: usize = 3;
+pub const RESYNC_BURST_WINDOW: Duration = Duration::from_secs(60);
+
+/// Per-client limit: resyncs must be `min_interval` apart, and at most `burst` of them
+/// may fall within `window`
+pub struct ResyncThrottle {
+    min_interval: Duration,
+    burst: usize,
+    window: Duration,
+    recent: VecDeque<Instant>,
+}
+
+impl Default for ResyncThrottle {
+    fn default() -> Self {
+        Self::new(RESYNC_MIN_INTERVAL, RESYNC_BURST, RESYNC_BURST_WINDOW)
+    }
+}
+
+impl ResyncThrottle {
+    pub fn new(min_interval: Duration, burst: usize, window: Duration) -> Self {
+        Self { min_interval, burst: burst.max(1), window, recent: VecDeque::new() }
+    }
+
+    /// Records a resync at `now` if it's allowed, otherwise returns how long to wait
+    pub fn check(&mut self, now: Instant) -> Result<(), Duration> {
+        while self.recent.front().is_some_and(|t| now.duration_since(*t) >= self.window) {
+            self.recent.pop_front();
+        }
+        if let Some(last) = self.recent.back() {
+            let since = now.duration_since(*last);
+            if since < self.min_interval {
+                return Err(self.min_interval - since);
+            }
+        }
+        if self.recent.len() >= self.burst {
+            let oldest = self.recent.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+        self.recent.push_back(now);
+        Ok(())
+    }
+}
+
+pub fn throttled_error(retry_after: Duration) -> String {
+    json!({
+        "type": "error",
+        "code": "resync_throttled",
+        "message": "Too many resync requests",
+        "retryAfterMs": retry_after.as_millis() as u64,
+    }).to_string()
+}
+
+/// What a client is resent. Edges are already filtered to the types it can see.
+pub struct ResyncState {
+    pub settings: serde_json::Value,
+    pub generation: u64,
+    pub nodes: Vec<(u32, BinaryNodeData)>,
+    pub edges: Vec<Edge>,
+    pub annotations: HashMap<String, Vec<Annotation>>,
+    pub pins: PinReport,
+    pub room: String,
+    pub priority_nodes: Vec<u32>,
+    pub hidden_edge_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResyncFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// The resync in the order it must be sent: settings, keyframe, edges, annotations,
+/// pins, subscriptions, then the completion marker
+pub fn frames(state: &ResyncState) -> Vec<ResyncFrame> {
+    let mut priority_nodes = state.priority_nodes.clone();
+    priority_nodes.sort_unstable();
+    let mut hidden_edge_types = state.hidden_edge_types.clone();
+    hidden_edge_types.sort();
+    vec![
+        ResyncFrame::Text(json!({ "type": "settings", "settings": state.settings }).to_string()),
+        ResyncFrame::Text(json!({ "type": "keyframe", "generation": state.generation }).to_string()),
+        ResyncFrame::Binary(binary_protocol::encode_node_data(&state.nodes)),
+        ResyncFrame::Text(json!({ "type": "edges", "generation": state.generation, "edges": state.edges }).to_string()),
+        ResyncFrame::Text(json!({ "type": "annotations", "annotations": state.annotations }).to_string()),
+        ResyncFrame::Text(json!({ "type": "pins", "pins": state.pins }).to_string()),
+        ResyncFrame::Text(json!({
+            "type": "subscriptions",
+            "room": state.room,
+            "priorityNodes": priority_nodes,
+            "hiddenEdgeTypes": hidden_edge_types,
+        }).to_string()),
+        ResyncFrame::Text(json!({ "type": "resync_complete", "generation": state.generation }).to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::node::Node;
+
+    fn frame_type(frame: &ResyncFrame) -> String {
+        match frame {
+            ResyncFrame::Text(text) => {
+                let value: serde_json::Value = serde_json::from_str(text).unwrap();
+                value["type"].as_str().unwrap().to_string()
+            }
+            ResyncFrame::Binary(_) => "binary".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_resync_order_and_throttle() {
+        let node = Node::new_with_id("a".to_string(), Some(7));
+        let state = ResyncState {
+            settings: json!({ "visualisation": {} }),
+            generation: 12,
+            nodes: vec![(node.id, node.data)],
+            edges: vec![Edge::new(7, 8, 1.0)],
+            annotations: HashMap::new(),
+            pins: PinReport::default(),
+            room: "lab".to_string(),
+            priority_nodes: vec![8, 7],
+            hidden_edge_types: vec!["similarity".to_string()],
+        };
+        let frames = frames(&state);
+        let order: Vec<String> = frames.iter().map(frame_type).collect();
+        assert_eq!(order, ["settings", "keyframe", "binary", "edges", "annotations", "pins", "subscriptions", "resync_complete"]);
+        let ResyncFrame::Binary(data) = &frames[2] else { unreachable!() };
+        assert_eq!(binary_protocol::decode_node_data(data).unwrap().len(), 1);
+        let ResyncFrame::Text(subscriptions) = &frames[6] else { unreachable!() };
+        assert!(subscriptions.contains("\"priorityNodes\":[7,8]"));
+
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut throttle = ResyncThrottle::new(Duration::from_secs(2), 3, Duration::from_secs(60));
+        assert!(throttle.check(at(0)).is_ok());
+        assert_eq!(throttle.check(at(1)), Err(Duration::from_secs(1)));
+        assert!(throttle.check(at(2)).is_ok());
+        assert!(throttle.check(at(4)).is_ok());
+        // Burst used up until the first one leaves the window
+        assert_eq!(throttle.check(at(10)), Err(Duration::from_secs(50)));
+        assert!(throttle.check(at(60)).is_ok());
+
+        let error: serde_json::Value = serde_json::from_str(&throttled_error(Duration::from_millis(1500))).unwrap();
+        assert_eq!((error["code"].as_str(), error["retryAfterMs"].as_u64()), (Some("resync_throttled"), Some(1500)));
+    }
+}
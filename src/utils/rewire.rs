@@ -0,0 +1,106 @@
+//! Keeping the graph connected when an intermediate node goes. Removing an index page
+//! drops its edges, leaving pages that were only linked through it apart; rewiring links
+//! each pair of its neighbours directly instead, weighted from the two edges it replaces.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::config::{RewireSettings, RewireWeight};
+use crate::models::edge::{Edge, WeightSource};
+
+pub const REWIRED_EDGE_TYPE: &str = "rewired";
+
+impl RewireWeight {
+    /// Weight of the edge replacing a path through the removed node
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            RewireWeight::Min => a.min(b),
+            RewireWeight::Product => a * b,
+        }
+    }
+}
+
+/// The edges that would link `node_id`'s neighbours once it is removed. Neighbours that
+/// are already linked are left alone; a neighbour reached by several edges counts with
+/// the heaviest. Fails, adding nothing, when the neighbourhood is over the cap.
+pub fn rewire_through(edges: &[Edge], node_id: u32, settings: &RewireSettings, now: DateTime<Utc>) -> Result<Vec<Edge>, String> {
+    let mut neighbours: BTreeMap<u32, f32> = BTreeMap::new();
+    let mut linked = HashSet::new();
+    for edge in edges {
+        let other = match (edge.source == node_id, edge.target == node_id) {
+            (true, false) => edge.target,
+            (false, true) => edge.source,
+            (true, true) => continue,
+            (false, false) => {
+                linked.insert((edge.source.min(edge.target), edge.source.max(edge.target)));
+                continue;
+            }
+        };
+        let weight = neighbours.entry(other).or_insert(edge.weight);
+        *weight = weight.max(edge.weight);
+    }
+
+    let degree = neighbours.len();
+    let pairs = degree * degree.saturating_sub(1) / 2;
+    if pairs > settings.max_edges {
+        return Err(format!(
+            "Node {} has {} neighbours; rewiring could add {} edges, over the limit of {}",
+            node_id, degree, pairs, settings.max_edges
+        ));
+    }
+
+    let neighbours: Vec<(u32, f32)> = neighbours.into_iter().collect();
+    let mut added = Vec::new();
+    for (i, &(a, weight_a)) in neighbours.iter().enumerate() {
+        for &(b, weight_b) in &neighbours[i + 1..] {
+            if linked.contains(&(a, b)) {
+                continue;
+            }
+            let mut edge = Edge::new(a, b, settings.weight.combine(weight_a, weight_b));
+            edge.id = format!("{}-{}-{}", REWIRED_EDGE_TYPE, a, b);
+            edge.edge_type = Some(REWIRED_EDGE_TYPE.to_string());
+            added.push(edge.credited_to(WeightSource::Rewired, now));
+        }
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(list: &[(u32, u32, f32)]) -> Vec<Edge> {
+        list.iter().map(|&(s, t, w)| Edge::new(s, t, w)).collect()
+    }
+
+    #[test]
+    fn test_neighbours_are_linked_with_derived_weights() {
+        // 0 is an index page linking 1, 2 and 3; 1 and 3 already link each other
+        let graph = edges(&[(0, 1, 0.5), (2, 0, 2.0), (0, 3, 4.0), (1, 3, 1.0), (3, 4, 1.0)]);
+        let now = Utc::now();
+        let min = rewire_through(&graph, 0, &RewireSettings::default(), now).unwrap();
+        let summary: Vec<(&str, u32, u32, f32)> = min.iter().map(|e| (e.id.as_str(), e.source, e.target, e.weight)).collect();
+        assert_eq!(summary, vec![("rewired-1-2", 1, 2, 0.5), ("rewired-2-3", 2, 3, 2.0)]);
+        assert_eq!(min[0].edge_type.as_deref(), Some(REWIRED_EDGE_TYPE));
+        assert_eq!(min[0].provenance.len(), 1);
+        assert_eq!((min[0].provenance[0].source, min[0].provenance[0].contribution), (WeightSource::Rewired, 0.5));
+
+        let product = RewireSettings { weight: RewireWeight::Product, ..Default::default() };
+        let weights: Vec<f32> = rewire_through(&graph, 0, &product, now).unwrap().iter().map(|e| e.weight).collect();
+        assert_eq!(weights, vec![1.0, 8.0]);
+        // A leaf has nothing to rewire
+        assert!(rewire_through(&graph, 4, &product, now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_hubs_over_the_cap_are_not_rewired() {
+        // Five neighbours make ten pairs, one of which is already linked
+        let mut graph = edges(&[(0, 1, 1.0), (0, 2, 1.0), (0, 3, 1.0), (0, 4, 1.0), (5, 0, 1.0), (1, 2, 1.0)]);
+        let at_cap = RewireSettings { max_edges: 10, ..Default::default() };
+        assert_eq!(rewire_through(&graph, 0, &at_cap, Utc::now()).unwrap().len(), 9);
+
+        graph.push(Edge::new(0, 6, 1.0));
+        let err = rewire_through(&graph, 0, &at_cap, Utc::now()).unwrap_err();
+        assert!(err.contains("6 neighbours") && err.contains("15 edges"), "{}", err);
+    }
+}
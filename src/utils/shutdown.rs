@@ -0,0 +1,324 @@
+//! Ordered server teardown on SIGTERM/SIGINT.
+//!
+//! Stages run one after another against a single deadline. A stage that fails is
+//! logged and the next one still runs; once the deadline has passed the remaining
+//! stages are skipped so the process can exit.
+
+use actix::Addr;
+use actix_web::dev::ServerHandle;
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use log::{error, info, warn};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+use crate::actors::messages::{GetMetadata, NotifyShutdown, ShutdownGraph};
+use crate::actors::{ClientManagerActor, GraphServiceActor, MetadataActor};
+use crate::services::annotation_service::AnnotationService;
+use crate::services::file_service::FileService;
+use crate::services::graph_service::GraphService;
+use crate::services::layout_snapshot_service::LayoutSnapshotService;
+use crate::services::speech_service::SpeechService;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageStatus {
+    Completed,
+    Failed(String),
+    TimedOut,
+    // Deadline already passed when the stage was due
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct StageOutcome {
+    pub name: &'static str,
+    pub status: StageStatus,
+    pub elapsed: Duration,
+}
+
+type Stage = Box<dyn FnOnce() -> LocalBoxFuture<'static, Result<(), String>>>;
+
+/// Stages are built lazily so each one starts only after the previous finished
+pub struct ShutdownSequence {
+    deadline: Duration,
+    stages: Vec<(&'static str, Stage)>,
+}
+
+impl ShutdownSequence {
+    pub fn new(deadline: Duration) -> Self {
+        Self { deadline, stages: Vec::new() }
+    }
+
+    pub fn stage<F, Fut>(mut self, name: &'static str, stage: F) -> Self
+    where
+        F: FnOnce() -> Fut + 'static,
+        Fut: Future<Output = Result<(), String>> + 'static,
+    {
+        let stage: Stage = Box::new(move || stage().boxed_local());
+        self.stages.push((name, stage));
+        self
+    }
+
+    pub async fn run(self) -> Vec<StageOutcome> {
+        let started = Instant::now();
+        info!("[Shutdown] Starting ordered shutdown ({} stages, deadline {:?})", self.stages.len(), self.deadline);
+
+        let mut outcomes = Vec::with_capacity(self.stages.len());
+        for (name, stage) in self.stages {
+            let remaining = self.deadline.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                warn!("[Shutdown] Skipping stage '{}': deadline reached", name);
+                outcomes.push(StageOutcome { name, status: StageStatus::Skipped, elapsed: Duration::ZERO });
+                continue;
+            }
+
+            info!("[Shutdown] Stage '{}' starting ({:?} left)", name, remaining);
+            let stage_started = Instant::now();
+            let status = match tokio::time::timeout(remaining, stage()).await {
+                Ok(Ok(())) => StageStatus::Completed,
+                Ok(Err(e)) => StageStatus::Failed(e),
+                Err(_) => StageStatus::TimedOut,
+            };
+            let elapsed = stage_started.elapsed();
+            match &status {
+                StageStatus::Completed => info!("[Shutdown] Stage '{}' completed in {:?}", name, elapsed),
+                StageStatus::Failed(e) => error!("[Shutdown] Stage '{}' failed after {:?}: {}", name, elapsed, e),
+                _ => error!("[Shutdown] Stage '{}' timed out after {:?}", name, elapsed),
+            }
+            outcomes.push(StageOutcome { name, status, elapsed });
+        }
+
+        info!("[Shutdown] Ordered shutdown finished in {:?}", started.elapsed());
+        outcomes
+    }
+}
+
+/// SIGTERM/SIGINT listener. Handlers are installed on construction, so create it
+/// before anything that might deliver the signal.
+pub struct ShutdownSignals {
+    sigterm: Signal,
+    sigint: Signal,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            sigterm: signal(SignalKind::terminate())?,
+            sigint: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    /// Waits for the first signal and returns its name
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.sigterm.recv() => "SIGTERM",
+            _ = self.sigint.recv() => "SIGINT",
+        }
+    }
+}
+
+/// Everything the server tears down, in the order it is torn down
+#[derive(Clone)]
+pub struct ShutdownTargets {
+    pub server: ServerHandle,
+    pub client_manager: Addr<ClientManagerActor>,
+    pub graph: Addr<GraphServiceActor>,
+    // None skips the metadata.json flush (tests run without /app/data)
+    pub metadata: Option<Addr<MetadataActor>>,
+    pub annotations: Arc<AnnotationService>,
+    // Final layout snapshot, skipped if nothing moved since the last scheduled one
+    pub layout_snapshots: Option<Arc<LayoutSnapshotService>>,
+    pub speech: Option<Arc<SpeechService>>,
+}
+
+pub fn server_sequence(targets: ShutdownTargets, deadline: Duration, reason: &str) -> ShutdownSequence {
+    let ShutdownTargets { server, client_manager, graph, metadata, annotations, layout_snapshots, speech } = targets;
+    let reason = reason.to_string();
+
+    ShutdownSequence::new(deadline)
+        .stage("stop accepting connections", move || async move {
+            server.pause().await;
+            Ok(())
+        })
+        .stage("notify clients", move || async move {
+            let notified = client_manager.send(NotifyShutdown { reason })
+                .await
+                .map_err(|e| e.to_string())??;
+            info!("[Shutdown] Sent close notice to {} sessions", notified);
+            Ok(())
+        })
+        .stage("stop graph services", {
+            let graph = graph.clone();
+            move || async move {
+                graph.send(ShutdownGraph).await.map_err(|e| e.to_string())??;
+                let stopped = GraphService::shutdown_all().await;
+                info!("[Shutdown] Stopped graph actor and {} GraphService instances", stopped);
+                Ok(())
+            }
+        })
+        .stage("flush persistence", move || async move {
+            if let Some(layout_snapshots) = layout_snapshots {
+                layout_snapshots.snapshot(&graph).await?;
+            }
+            if let Some(metadata) = metadata {
+                let store = metadata.send(GetMetadata).await.map_err(|e| e.to_string())??;
+                tokio::task::spawn_blocking(move || FileService::save_metadata(&store))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .map_err(|e| format!("Failed to save metadata: {}", e))?;
+            }
+            annotations.flush().await
+        })
+        .stage("stop speech service", move || async move {
+            match speech {
+                Some(speech) => speech.close().await.map_err(|e| e.to_string()),
+                None => Ok(()),
+            }
+        })
+}
+
+/// Runs the ordered teardown, then stops the HTTP server whatever the outcome
+pub async fn shutdown_server(targets: ShutdownTargets, deadline: Duration, reason: &str) -> Vec<StageOutcome> {
+    let server = targets.server.clone();
+    let outcomes = server_sequence(targets, deadline, reason).run().await;
+    info!("[Shutdown] Stopping HTTP server");
+    server.stop(true).await;
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::{CloseConnection, RegisterAgent, SendToClientText, StartSimulation};
+    use actix::prelude::*;
+    use actix_web::{web, App, HttpServer};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Stand-in websocket session that records what the server sends it
+    struct MockClient {
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Actor for MockClient {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendToClientText> for MockClient {
+        type Result = ();
+
+        fn handle(&mut self, msg: SendToClientText, _ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(msg.0);
+        }
+    }
+
+    impl Handler<CloseConnection> for MockClient {
+        type Result = ();
+
+        fn handle(&mut self, msg: CloseConnection, ctx: &mut Self::Context) {
+            self.received.lock().unwrap().push(format!("close:{}", msg.reason));
+            ctx.stop();
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_stages_respect_deadline() {
+        let outcomes = ShutdownSequence::new(Duration::from_millis(100))
+            .stage("fails", || async { Err("boom".to_string()) })
+            .stage("hangs", || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .stage("late", || async { Ok(()) })
+            .run()
+            .await;
+
+        // A failure doesn't stop the sequence, but a stage past the deadline is skipped
+        let statuses: Vec<StageStatus> = outcomes.iter().map(|o| o.status.clone()).collect();
+        assert_eq!(statuses, vec![
+            StageStatus::Failed("boom".to_string()),
+            StageStatus::TimedOut,
+            StageStatus::Skipped,
+        ]);
+        assert!(outcomes[1].elapsed < Duration::from_secs(1));
+    }
+
+    #[actix_web::test]
+    async fn test_teardown_runs_stages_in_order() {
+        let server = HttpServer::new(|| App::new().route("/", web::get().to(|| async { "ok" })))
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        let server_task = actix_web::rt::spawn(server);
+
+        // The server is up and answering
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let client_manager = ClientManagerActor::new().start();
+        let graph = GraphServiceActor::new(client_manager.clone(), None).start();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mock = MockClient { received: received.clone() }.start();
+        client_manager.send(RegisterAgent {
+            name: "mock".to_string(),
+            text: mock.clone().recipient(),
+            close: mock.clone().recipient(),
+        }).await.unwrap().unwrap();
+
+        let annotations_path = std::env::temp_dir()
+            .join(format!("shutdown-test-{}", uuid::Uuid::new_v4()))
+            .join("annotations.json");
+        let targets = ShutdownTargets {
+            server: handle,
+            client_manager,
+            graph: graph.clone(),
+            metadata: None,
+            annotations: Arc::new(AnnotationService::with_path(annotations_path.clone())),
+            layout_snapshots: None,
+            speech: None,
+        };
+
+        // Wired up as main does it, then sent a real SIGTERM
+        let mut signals = ShutdownSignals::new().unwrap();
+        let teardown = actix_web::rt::spawn(async move {
+            let signal_name = signals.recv().await;
+            shutdown_server(targets, Duration::from_secs(10), signal_name).await
+        });
+        assert_eq!(unsafe { libc::raise(libc::SIGTERM) }, 0);
+        let outcomes = tokio::time::timeout(Duration::from_secs(15), teardown).await.unwrap().unwrap();
+        let names: Vec<&str> = outcomes.iter().map(|o| o.name).collect();
+        assert_eq!(names, vec![
+            "stop accepting connections",
+            "notify clients",
+            "stop graph services",
+            "flush persistence",
+            "stop speech service",
+        ]);
+        assert!(outcomes.iter().all(|o| o.status == StageStatus::Completed), "{:?}", outcomes);
+
+        // Server future resolves and the listener is gone
+        tokio::time::timeout(Duration::from_secs(5), server_task).await.unwrap().unwrap().unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+
+        // The client saw the notice before its socket was closed
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        let notice: serde_json::Value = serde_json::from_str(&received[0]).unwrap();
+        assert_eq!(notice["type"], "server_shutdown");
+        assert_eq!(notice["reason"], "SIGTERM");
+        assert_eq!(received[1], "close:SIGTERM");
+
+        // Graph actor refuses to restart and annotations were flushed
+        assert!(graph.send(StartSimulation).await.unwrap().is_err());
+        assert!(annotations_path.exists());
+    }
+}
@@ -0,0 +1,436 @@
+//! Which ticks of the 16ms simulation loop run physics, and what each one sends, for the
+//! configured simulation mode. Remote steps and streams a delta every tick. Local never
+//! steps; clients lay the graph out themselves. Hybrid steps at `hybrid_step_hz` and
+//! turns a step into a full keyframe, the authoritative correction clients snap to,
+//! every `correction_interval_secs`. A step itself is made of fixed-size substeps, as
+//! many as the real time since the last one covers, so the layout moves at the same
+//! wall-clock pace however long each loop iteration takes.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::config::SimulationSettings;
+use crate::models::simulation_params::SimulationMode;
+use crate::utils::position_recording::FrameKind;
+use crate::utils::time_sync::{server_time_us, FrameTiming};
+use crate::utils::update_priority::SOURCE_FRAME_RATE;
+
+pub const MAX_HYBRID_STEP_HZ: f32 = 60.0;
+pub const MAX_SUBSTEPS: u32 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationModeStatus {
+    pub mode: SimulationMode,
+    // Whether the server computes the layout at all
+    pub server_physics: bool,
+    // Whether clients need to run their own layout
+    pub client_physics: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hybrid_step_hz: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correction_interval_secs: Option<f32>,
+}
+
+impl From<&SimulationSettings> for SimulationModeStatus {
+    fn from(settings: &SimulationSettings) -> Self {
+        let hybrid = settings.mode == SimulationMode::Hybrid;
+        Self {
+            mode: settings.mode,
+            server_physics: settings.mode != SimulationMode::Local,
+            client_physics: settings.mode != SimulationMode::Remote,
+            hybrid_step_hz: hybrid.then_some(settings.hybrid_step_hz),
+            correction_interval_secs: hybrid.then_some(settings.correction_interval_secs),
+        }
+    }
+}
+
+impl SimulationModeStatus {
+    /// The `simulation_mode` event clients get on connect and on every switch
+    pub fn to_event(&self) -> String {
+        let mut event = serde_json::to_value(self).unwrap_or_default();
+        event["type"] = "simulation_mode".into();
+        event.to_string()
+    }
+
+    /// The status carried by a `simulation_mode` event, or None for any other message
+    pub fn from_event(message: &str) -> Option<Self> {
+        if !message.contains("\"simulation_mode\"") {
+            return None;
+        }
+        let event: serde_json::Value = serde_json::from_str(message).ok()?;
+        if event.get("type")?.as_str()? != "simulation_mode" {
+            return None;
+        }
+        serde_json::from_value(event).ok()
+    }
+
+    /// Position frames a second the server sends, for thinning throttled clients' frames
+    pub fn source_frame_rate(&self) -> f32 {
+        self.hybrid_step_hz.unwrap_or(SOURCE_FRAME_RATE)
+    }
+
+    /// Whether a client that can or can't run its own layout sees a moving graph. Hybrid
+    /// still works without one, at the server's reference rate.
+    pub fn supports_client(&self, local_physics: bool) -> bool {
+        local_physics || self.server_physics
+    }
+}
+
+pub fn validate(settings: &SimulationSettings) -> Result<(), String> {
+    if !settings.mode.is_selectable() {
+        return Err(format!("Simulation mode {:?} can't be selected; use remote, local or hybrid", settings.mode));
+    }
+    if !(settings.hybrid_step_hz > 0.0 && settings.hybrid_step_hz <= MAX_HYBRID_STEP_HZ) {
+        return Err(format!("hybrid_step_hz must be above 0 and at most {}", MAX_HYBRID_STEP_HZ));
+    }
+    if !(settings.correction_interval_secs.is_finite() && settings.correction_interval_secs > 0.0) {
+        return Err("correction_interval_secs must be above 0".to_string());
+    }
+    if !(1..=MAX_SUBSTEPS).contains(&settings.max_substeps) {
+        return Err(format!("max_substeps must be 1-{}", MAX_SUBSTEPS));
+    }
+    Ok(())
+}
+
+pub struct SimulationClock {
+    settings: SimulationSettings,
+    last_step: Option<Instant>,
+    last_correction: Instant,
+}
+
+impl SimulationClock {
+    pub fn new(settings: SimulationSettings, now: Instant) -> Self {
+        Self { settings, last_step: None, last_correction: now }
+    }
+
+    pub fn settings(&self) -> &SimulationSettings {
+        &self.settings
+    }
+
+    pub fn mode(&self) -> SimulationMode {
+        self.settings.mode
+    }
+
+    /// Starts the new mode's schedule afresh: the next tick steps, and the first
+    /// correction is a full interval away
+    pub fn set_settings(&mut self, settings: SimulationSettings, now: Instant) {
+        self.settings = settings;
+        self.last_step = None;
+        self.last_correction = now;
+    }
+
+    pub fn status(&self) -> SimulationModeStatus {
+        SimulationModeStatus::from(&self.settings)
+    }
+
+    /// Whether the tick at `now` runs physics, and if so which frame it broadcasts
+    pub fn tick(&mut self, now: Instant) -> Option<FrameKind> {
+        match self.settings.mode {
+            SimulationMode::Local => None,
+            SimulationMode::Hybrid => {
+                let step_interval = Duration::from_secs_f32(1.0 / self.settings.hybrid_step_hz);
+                if matches!(self.last_step, Some(last) if now.duration_since(last) < step_interval) {
+                    return None;
+                }
+                self.last_step = Some(now);
+                let correction_interval = Duration::from_secs_f32(self.settings.correction_interval_secs);
+                if now.duration_since(self.last_correction) >= correction_interval {
+                    self.last_correction = now;
+                    return Some(FrameKind::Keyframe);
+                }
+                Some(FrameKind::Delta)
+            }
+            _ => Some(FrameKind::Delta),
+        }
+    }
+}
+
+/// The 16ms loop's interval
+pub const TICK: Duration = Duration::from_millis(16);
+
+/// Where the loop's notion of now comes from. Deterministic simulations run on a virtual
+/// clock that only moves a tick at a time as they are stepped, so pacing doesn't depend on
+/// how fast the test machine is.
+#[derive(Debug, Clone, Copy)]
+pub enum LoopClock {
+    Wall,
+    Virtual(Instant),
+}
+
+impl LoopClock {
+    pub fn for_settings(settings: &SimulationSettings, now: Instant) -> Self {
+        if settings.deterministic { LoopClock::Virtual(now) } else { LoopClock::Wall }
+    }
+
+    pub fn now(&self) -> Instant {
+        match self {
+            LoopClock::Wall => Instant::now(),
+            LoopClock::Virtual(now) => *now,
+        }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, LoopClock::Virtual(_))
+    }
+
+    /// Moves a virtual clock on by one tick; the wall clock moves by itself
+    pub fn advance(&mut self) {
+        if let LoopClock::Virtual(now) = self {
+            *now += TICK;
+        }
+    }
+}
+
+/// How far physics has got behind real time, for the stats API
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StepAccumulatorStatus {
+    // Substeps the last physics step ran
+    pub substeps: u32,
+    // Real time carried over to the next step, under one substep
+    pub lag_ms: f32,
+    // Real time skipped because catching up would have taken more than `max_substeps`
+    pub dropped_ms: f32,
+    pub simulated_secs: f64,
+}
+
+/// Turns the real time between physics steps into whole fixed-size substeps. Whatever is
+/// left over carries to the next step. A step that has fallen further behind than
+/// `max_substeps` skips the excess, so a loop that can't keep up slows the layout down
+/// instead of running ever longer steps.
+#[derive(Debug)]
+pub struct StepAccumulator {
+    step: Duration,
+    max_substeps: u32,
+    last: Option<Instant>,
+    lag: Duration,
+    substeps: u32,
+    dropped: Duration,
+    simulated: Duration,
+}
+
+impl StepAccumulator {
+    pub fn new(step: Duration, max_substeps: u32) -> Self {
+        Self {
+            step,
+            max_substeps: max_substeps.max(1),
+            last: None,
+            lag: Duration::ZERO,
+            substeps: 0,
+            dropped: Duration::ZERO,
+            simulated: Duration::ZERO,
+        }
+    }
+
+    pub fn set_max_substeps(&mut self, max_substeps: u32) {
+        self.max_substeps = max_substeps.max(1);
+    }
+
+    /// How many substeps the real time up to `now` pays for. The first step after a reset
+    /// runs one.
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        let Some(last) = self.last.replace(now) else {
+            self.lag = Duration::ZERO;
+            return self.run(1);
+        };
+        self.lag += now.saturating_duration_since(last);
+        let due = (self.lag.as_nanos() / self.step.as_nanos().max(1)) as u32;
+        if due > self.max_substeps {
+            let skipped = self.step * (due - self.max_substeps);
+            self.lag -= skipped;
+            self.dropped += skipped;
+        }
+        let substeps = due.min(self.max_substeps);
+        self.lag -= self.step * substeps;
+        self.run(substeps)
+    }
+
+    fn run(&mut self, substeps: u32) -> u32 {
+        self.substeps = substeps;
+        self.simulated += self.step * substeps;
+        substeps
+    }
+
+    /// Forgets the last step, so the first step after a pause doesn't try to catch it up
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Total time the substeps have simulated
+    pub fn simulated(&self) -> Duration {
+        self.simulated
+    }
+
+    /// Timing for the frame the last step produced: the simulated moment it shows, which
+    /// trails the real one by the carried lag, and the simulated time it covered
+    pub fn timing(&self) -> FrameTiming {
+        let now = self.last.unwrap_or_else(Instant::now);
+        FrameTiming {
+            server_time_us: server_time_us(now.checked_sub(self.lag).unwrap_or(now)),
+            tick_delta_us: (self.step * self.substeps).as_micros().min(u32::MAX as u128) as u32,
+        }
+    }
+
+    pub fn status(&self) -> StepAccumulatorStatus {
+        StepAccumulatorStatus {
+            substeps: self.substeps,
+            lag_ms: self.lag.as_secs_f32() * 1000.0,
+            dropped_ms: self.dropped.as_secs_f32() * 1000.0,
+            simulated_secs: self.simulated.as_secs_f64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What 16ms ticks over `secs` produce: '.' no step, 'd' delta, 'K' keyframe
+    fn pattern(clock: &mut SimulationClock, start: Instant, from_ms: u64, secs: f32) -> String {
+        let ticks = (secs * 1000.0 / 16.0) as u64;
+        (0..ticks).map(|i| match clock.tick(start + Duration::from_millis(from_ms + i * 16)) {
+            None => '.',
+            Some(FrameKind::Delta) => 'd',
+            Some(FrameKind::Keyframe) => 'K',
+        }).collect()
+    }
+
+    fn settings(mode: SimulationMode) -> SimulationSettings {
+        SimulationSettings { mode, hybrid_step_hz: 10.0, correction_interval_secs: 0.5, ..Default::default() }
+    }
+
+    #[test]
+    fn test_frame_patterns_per_mode() {
+        let start = Instant::now();
+        let remote = pattern(&mut SimulationClock::new(settings(SimulationMode::Remote), start), start, 0, 1.0);
+        assert!(remote.chars().all(|c| c == 'd'));
+
+        let local = pattern(&mut SimulationClock::new(settings(SimulationMode::Local), start), start, 0, 1.0);
+        assert!(local.chars().all(|c| c == '.'));
+
+        // 10 Hz over 16ms ticks is a step every 7th tick (112ms); the step at 560ms is
+        // the first past the 0.5s correction interval
+        let hybrid = pattern(&mut SimulationClock::new(settings(SimulationMode::Hybrid), start), start, 0, 1.0);
+        let steps: String = hybrid.chars().filter(|c| *c != '.').collect();
+        assert_eq!(steps, "dddddKddd");
+        assert!(hybrid.starts_with("d......d"));
+    }
+
+    #[test]
+    fn test_runtime_switch_restarts_the_schedule() {
+        let start = Instant::now();
+        let mut clock = SimulationClock::new(settings(SimulationMode::Hybrid), start);
+        assert_eq!(clock.tick(start), Some(FrameKind::Delta));
+        assert_eq!(clock.tick(start + Duration::from_millis(16)), None);
+
+        clock.set_settings(settings(SimulationMode::Local), start + Duration::from_millis(32));
+        assert_eq!(pattern(&mut clock, start, 32, 0.5), ".".repeat(31));
+        assert!(!clock.status().server_physics);
+
+        // Back to hybrid: steps straight away, first correction a full interval later
+        let switched = 600;
+        clock.set_settings(settings(SimulationMode::Hybrid), start + Duration::from_millis(switched));
+        assert_eq!(clock.tick(start + Duration::from_millis(switched)), Some(FrameKind::Delta));
+        assert_eq!(clock.tick(start + Duration::from_millis(switched + 400)), Some(FrameKind::Delta));
+        assert_eq!(clock.tick(start + Duration::from_millis(switched + 520)), Some(FrameKind::Keyframe));
+
+        clock.set_settings(settings(SimulationMode::Remote), start + Duration::from_millis(switched + 530));
+        assert_eq!(clock.tick(start + Duration::from_millis(switched + 531)), Some(FrameKind::Delta));
+        assert_eq!(clock.tick(start + Duration::from_millis(switched + 532)), Some(FrameKind::Delta));
+        assert_eq!(clock.status(), SimulationModeStatus {
+            mode: SimulationMode::Remote, server_physics: true, client_physics: false,
+            hybrid_step_hz: None, correction_interval_secs: None,
+        });
+    }
+
+    #[test]
+    fn test_mode_event_round_trips() {
+        let status = SimulationModeStatus::from(&settings(SimulationMode::Hybrid));
+        let event = status.to_event();
+        assert_eq!(SimulationModeStatus::from_event(&event), Some(status.clone()));
+        assert_eq!(status.source_frame_rate(), 10.0);
+        assert!(status.supports_client(false));
+        assert!(!SimulationModeStatus::from(&settings(SimulationMode::Local)).supports_client(false));
+        assert_eq!(SimulationModeStatus::from_event("{\"type\":\"keyframe\",\"generation\":3}"), None);
+    }
+
+    #[test]
+    fn test_virtual_clock_paces_hybrid_without_waiting() {
+        let start = Instant::now();
+        let deterministic = SimulationSettings { deterministic: true, ..settings(SimulationMode::Hybrid) };
+        let mut loop_clock = LoopClock::for_settings(&deterministic, start);
+        let mut clock = SimulationClock::new(deterministic, loop_clock.now());
+        let ticks: String = (0..16).map(|_| {
+            loop_clock.advance();
+            match clock.tick(loop_clock.now()) {
+                None => '.',
+                Some(FrameKind::Delta) => 'd',
+                Some(FrameKind::Keyframe) => 'K',
+            }
+        }).collect();
+        // The same 10 Hz schedule as real ticks, a step every 7th
+        assert_eq!(ticks, "d......d......d.");
+        assert_eq!(loop_clock.now(), start + TICK * 16);
+        assert!(!LoopClock::for_settings(&settings(SimulationMode::Hybrid), start).is_virtual());
+    }
+
+    #[test]
+    fn test_substeps_keep_simulated_time_with_slow_iterations() {
+        let start = Instant::now();
+        let mut loop_clock = LoopClock::Virtual(start);
+        let mut steps = StepAccumulator::new(TICK, 4);
+        assert_eq!(steps.advance(loop_clock.now()), 1);
+        let first = steps.simulated();
+        // Iterations taking one to three ticks, plus a few milliseconds of overrun
+        let mut overrun = Duration::ZERO;
+        for i in 0..30u64 {
+            for _ in 0..(i % 3 + 1) {
+                loop_clock.advance();
+            }
+            overrun += Duration::from_millis(i % 5);
+            let now = loop_clock.now() + overrun;
+            let wall = now - start;
+            let substeps = steps.advance(now);
+            assert!(substeps <= 4);
+            let behind = wall - (steps.simulated() - first);
+            assert!(behind < TICK, "simulated time {:?} behind after {:?}", behind, wall);
+            assert_eq!(steps.status().lag_ms, behind.as_secs_f32() * 1000.0);
+            assert_eq!(steps.timing().tick_delta_us, (TICK * substeps).as_micros() as u32);
+        }
+        assert_eq!(steps.status().dropped_ms, 0.0);
+    }
+
+    #[test]
+    fn test_substeps_past_the_cap_are_skipped() {
+        let start = Instant::now();
+        let mut steps = StepAccumulator::new(TICK, 4);
+        steps.advance(start);
+        // 200ms is twelve and a half ticks; four run, eight are skipped, the half carries
+        let now = start + Duration::from_millis(200);
+        assert_eq!(steps.advance(now), 4);
+        let status = steps.status();
+        assert_eq!((status.substeps, status.lag_ms, status.dropped_ms), (4, 8.0, 128.0));
+        assert_eq!(steps.simulated(), TICK * 5);
+        // The frame shows the simulated moment, the lag short of now
+        assert_eq!(steps.timing().server_time_us, server_time_us(now - Duration::from_millis(8)));
+        assert_eq!(steps.advance(now + Duration::from_millis(8)), 1);
+        assert_eq!(steps.advance(now + Duration::from_millis(10)), 0);
+
+        // A pause isn't caught up, or counted as dropped
+        steps.reset();
+        assert_eq!(steps.advance(now + Duration::from_secs(5)), 1);
+        assert_eq!(steps.status().dropped_ms, 128.0);
+        assert_eq!(steps.status().lag_ms, 0.0);
+    }
+
+    #[test]
+    fn test_only_selectable_modes_validate() {
+        assert!(validate(&settings(SimulationMode::Hybrid)).is_ok());
+        assert!(validate(&settings(SimulationMode::Replay)).is_err());
+        assert!(validate(&settings(SimulationMode::GPU)).is_err());
+        assert!(validate(&SimulationSettings { hybrid_step_hz: 0.0, ..settings(SimulationMode::Hybrid) }).is_err());
+        assert!(validate(&SimulationSettings { correction_interval_secs: f32::NAN, ..settings(SimulationMode::Local) }).is_err());
+        assert!(validate(&SimulationSettings { max_substeps: 0, ..settings(SimulationMode::Remote) }).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Graph skeletons: a small subset of the edges that keeps the graph's backbone, for
+//! overview modes and for calming hairball layouts by springing only the skeleton.
+//! Either a maximum-weight spanning tree (a forest if the graph is disconnected) or the
+//! k heaviest edges of every node. Nodes are never dropped.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::models::edge::Edge;
+
+pub const DEFAULT_TOP_K: usize = 2;
+pub const MAX_TOP_K: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "strategy", rename_all = "camelCase")]
+pub enum SkeletonStrategy {
+    // Maximum-weight spanning tree
+    Mst,
+    // Each node's k heaviest edges
+    TopK { k: usize },
+}
+
+impl SkeletonStrategy {
+    /// From the `strategy` and `k` query parameters; `k` only applies to `topk`
+    pub fn parse(name: &str, k: Option<usize>) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "mst" => Ok(SkeletonStrategy::Mst),
+            "topk" | "top_k" | "top-k" => {
+                let k = k.unwrap_or(DEFAULT_TOP_K);
+                if k == 0 || k > MAX_TOP_K {
+                    return Err(format!("k must be between 1 and {}", MAX_TOP_K));
+                }
+                Ok(SkeletonStrategy::TopK { k })
+            }
+            other => Err(format!("unknown strategy '{}', expected 'mst' or 'topk'", other)),
+        }
+    }
+}
+
+// Heaviest first, ties broken by id so the skeleton doesn't depend on edge order
+fn by_weight_desc(a: &Edge, b: &Edge) -> std::cmp::Ordering {
+    b.weight.total_cmp(&a.weight).then_with(|| a.id.cmp(&b.id))
+}
+
+/// Ids of the skeleton's edges, in `edges` order. Self-loops are never included.
+pub fn skeleton_edge_ids(edges: &[Edge], strategy: SkeletonStrategy) -> Vec<String> {
+    let keep = match strategy {
+        SkeletonStrategy::Mst => max_spanning_tree(edges),
+        SkeletonStrategy::TopK { k } => top_k_per_node(edges, k),
+    };
+    edges.iter().filter(|e| keep.contains(e.id.as_str())).map(|e| e.id.clone()).collect()
+}
+
+/// Edges of `edges` that belong to the skeleton given by `ids`
+pub fn skeleton_edges(edges: &[Edge], ids: &[String]) -> Vec<Edge> {
+    let ids: HashSet<&str> = ids.iter().map(String::as_str).collect();
+    edges.iter().filter(|e| ids.contains(e.id.as_str())).cloned().collect()
+}
+
+// Kruskal over edges heaviest first
+fn max_spanning_tree(edges: &[Edge]) -> HashSet<&str> {
+    let mut sorted: Vec<&Edge> = edges.iter().filter(|e| e.source != e.target).collect();
+    sorted.sort_by(|a, b| by_weight_desc(a, b));
+
+    let mut parent: HashMap<u32, u32> = HashMap::new();
+    fn root(parent: &mut HashMap<u32, u32>, id: u32) -> u32 {
+        let mut current = id;
+        while let Some(&up) = parent.get(&current) {
+            if up == current {
+                break;
+            }
+            current = up;
+        }
+        // Path compression
+        let mut node = id;
+        while node != current {
+            let up = parent[&node];
+            parent.insert(node, current);
+            node = up;
+        }
+        current
+    }
+
+    let mut tree = HashSet::new();
+    for edge in sorted {
+        let (a, b) = (root(&mut parent, edge.source), root(&mut parent, edge.target));
+        if a != b {
+            parent.insert(a, b);
+            tree.insert(edge.id.as_str());
+        }
+    }
+    tree
+}
+
+fn top_k_per_node(edges: &[Edge], k: usize) -> HashSet<&str> {
+    let mut incident: HashMap<u32, Vec<&Edge>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.source != e.target) {
+        incident.entry(edge.source).or_default().push(edge);
+        incident.entry(edge.target).or_default().push(edge);
+    }
+    let mut keep = HashSet::new();
+    for mut node_edges in incident.into_values() {
+        node_edges.sort_by(|a, b| by_weight_desc(a, b));
+        keep.extend(node_edges.into_iter().take(k).map(|e| e.id.as_str()));
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges() -> Vec<Edge> {
+        // Two triangles joined by a light bridge, plus a heavy self-loop that never counts
+        vec![
+            Edge::new(1, 2, 4.0),
+            Edge::new(2, 3, 1.0),
+            Edge::new(1, 3, 3.0),
+            Edge::new(3, 4, 0.5),
+            Edge::new(4, 5, 2.0),
+            Edge::new(5, 6, 5.0),
+            Edge::new(4, 6, 2.5),
+            Edge::new(6, 6, 9.0),
+        ]
+    }
+
+    fn weight(edges: &[Edge], ids: &[String]) -> f32 {
+        skeleton_edges(edges, ids).iter().map(|e| e.weight).sum()
+    }
+
+    fn is_spanning_tree(edges: &[&Edge], nodes: usize) -> bool {
+        // n - 1 edges with no cycle among them
+        let owned: Vec<Edge> = edges.iter().map(|e| (*e).clone()).collect();
+        edges.len() == nodes - 1 && max_spanning_tree(&owned).len() == edges.len()
+    }
+
+    #[test]
+    fn test_mst_is_the_heaviest_spanning_tree() {
+        let edges = edges();
+        let tree = skeleton_edge_ids(&edges, SkeletonStrategy::Mst);
+        assert_eq!(tree, vec!["1-2", "1-3", "3-4", "5-6", "4-6"]);
+        assert_eq!(weight(&edges, &tree), 15.0);
+
+        // Brute force: no other set of five edges spanning all six nodes weighs more
+        let candidates: Vec<&Edge> = edges.iter().filter(|e| e.source != e.target).collect();
+        let mut best = 0.0f32;
+        for mask in 0u32..(1 << candidates.len()) {
+            let chosen: Vec<&Edge> = (0..candidates.len()).filter(|i| mask & (1 << i) != 0).map(|i| candidates[i]).collect();
+            if is_spanning_tree(&chosen, 6) {
+                best = best.max(chosen.iter().map(|e| e.weight).sum());
+            }
+        }
+        assert_eq!(best, 15.0);
+
+        // Ordering doesn't change the result
+        let mut reversed = edges.clone();
+        reversed.reverse();
+        let mut again = skeleton_edge_ids(&reversed, SkeletonStrategy::Mst);
+        again.reverse();
+        assert_eq!(again, tree);
+    }
+
+    #[test]
+    fn test_top_k_keeps_each_nodes_heaviest_edges() {
+        let edges = edges();
+        let one = skeleton_edge_ids(&edges, SkeletonStrategy::TopK { k: 1 });
+        // Node 3's heaviest is 1-3, node 4's is 4-6; 2-3, 4-5 and the bridge go
+        assert_eq!(one, vec!["1-2", "1-3", "5-6", "4-6"]);
+        let all = skeleton_edge_ids(&edges, SkeletonStrategy::TopK { k: 10 });
+        assert_eq!(all.len(), 7);
+
+        assert_eq!(SkeletonStrategy::parse("MST", Some(4)), Ok(SkeletonStrategy::Mst));
+        assert_eq!(SkeletonStrategy::parse("topk", None), Ok(SkeletonStrategy::TopK { k: DEFAULT_TOP_K }));
+        assert!(SkeletonStrategy::parse("topk", Some(0)).is_err());
+        assert!(SkeletonStrategy::parse("tree", None).is_err());
+    }
+}
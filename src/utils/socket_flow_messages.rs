@@ -3,6 +3,7 @@ use bytemuck::{Pod, Zeroable};
 use std::collections::HashMap;
 use crate::types::vec3::Vec3Data;
 use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "gpu")]
 use cudarc::driver::{DeviceRepr, ValidAsZeroBits};
 use glam::Vec3;
 
@@ -39,9 +40,11 @@ pub struct BinaryNodeData {
 static_assertions::const_assert_eq!(std::mem::size_of::<BinaryNodeData>(), 28);
 
 // Implement DeviceRepr for BinaryNodeData
+#[cfg(feature = "gpu")]
 unsafe impl DeviceRepr for BinaryNodeData {}
 
 // Implement ValidAsZeroBits for BinaryNodeData
+#[cfg(feature = "gpu")]
 unsafe impl ValidAsZeroBits for BinaryNodeData {}
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -59,6 +62,42 @@ pub struct PongMessage {
     pub timestamp: u64,
 }
 
+/// Position plus orientation quaternion (x, y, z, w) in the room's shared frame
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Pose {
+    pub position: [f32; 3],
+    pub orientation: [f32; 4],
+}
+
+impl Pose {
+    pub fn is_finite(&self) -> bool {
+        self.position.iter().chain(self.orientation.iter()).all(|v| v.is_finite())
+    }
+}
+
+/// Head/cursor/gaze update relayed to other clients in the same room, never persisted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoseUpdate {
+    pub head: Pose,
+    #[serde(default)]
+    pub cursor: Option<Pose>,
+    // The protocol spec spells it `gaze_node`
+    #[serde(default, alias = "gaze_node")]
+    pub gaze_node: Option<u32>,
+}
+
+/// Client reports it has looked at a node for `dwell_ms`; feeds the transient attention score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GazeFocus {
+    pub node_id: u32,
+    pub dwell_ms: u64,
+    // Viewer position in graph space, used as the drift target for attraction
+    #[serde(default)]
+    pub viewpoint: Option<[f32; 3]>,
+}
+
 fn default_timestamp() -> u64 {
     chrono::Utc::now().timestamp_millis() as u64
 }
@@ -0,0 +1,265 @@
+//! Nodes near a point or inside a view frustum, for AR clients asking what's around
+//! them without downloading every position. A uniform hash grid over node positions:
+//! a query visits the cells overlapping its bounding box and tests each node there
+//! exactly, so the grid only narrows the search and never changes the answer.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::models::graph::GraphGenerations;
+use crate::models::node::Node;
+
+// Aim for about this many nodes per occupied cell
+const NODES_PER_CELL: f32 = 4.0;
+const MIN_CELL_SIZE: f32 = 1e-3;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpatialHit {
+    pub node_id: u32,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SpatialQuery {
+    Radius { center: Vec3, radius: f32 },
+    Frustum(Frustum),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpatialNode {
+    pub id: u32,
+    pub label: String,
+    pub distance: f32,
+}
+
+/// Query results as of the positions at `generations`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpatialResult {
+    #[serde(flatten)]
+    pub generations: GraphGenerations,
+    pub total: usize,
+    pub truncated: bool,
+    pub nodes: Vec<SpatialNode>,
+}
+
+/// Six inward-facing planes; a point is inside when `normal · p + d >= 0` for all of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub eye: Vec3,
+    pub planes: [(Vec3, f32); 6],
+}
+
+impl Frustum {
+    /// A perspective camera at `eye` looking along `forward`; `fov_y` in radians
+    pub fn perspective(eye: Vec3, forward: Vec3, up: Vec3, fov_y: f32, aspect: f32, near: f32, far: f32) -> Result<Self, String> {
+        let forward = forward.try_normalize().ok_or("forward must not be zero")?;
+        let right = forward.cross(up).try_normalize().ok_or("up must not be parallel to forward")?;
+        let up = right.cross(forward);
+        let valid = fov_y > 0.0 && fov_y < std::f32::consts::PI && aspect > 0.0 && aspect.is_finite()
+            && near >= 0.0 && far > near && far.is_finite() && eye.is_finite();
+        if !valid {
+            return Err("needs 0 < fov < 180 degrees, a positive aspect and 0 <= near < far".to_string());
+        }
+        let half_v = (fov_y / 2.0).tan();
+        let half_h = half_v * aspect;
+        // Side planes through the eye, normals pointing inwards
+        let side = |normal: Vec3| (normal.normalize(), -normal.normalize().dot(eye));
+        let planes = [
+            (forward, -forward.dot(eye + forward * near)),
+            (-forward, forward.dot(eye + forward * far)),
+            side(forward + right / half_h),
+            side(forward - right / half_h),
+            side(forward + up / half_v),
+            side(forward - up / half_v),
+        ];
+        Ok(Self { eye, planes })
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|(normal, d)| normal.dot(point) + d >= 0.0)
+    }
+
+    // Bounding box of the near and far corners
+    fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut corners = Vec::with_capacity(8);
+        for &near_far in &self.planes[..2] {
+            for &a in &self.planes[2..4] {
+                for &b in &self.planes[4..6] {
+                    corners.push(intersect(near_far, a, b)?);
+                }
+            }
+        }
+        let min = corners.iter().fold(Vec3::splat(f32::MAX), |m, c| m.min(*c));
+        let max = corners.iter().fold(Vec3::splat(f32::MIN), |m, c| m.max(*c));
+        Some((min, max))
+    }
+}
+
+fn intersect((n1, d1): (Vec3, f32), (n2, d2): (Vec3, f32), (n3, d3): (Vec3, f32)) -> Option<Vec3> {
+    let det = n1.dot(n2.cross(n3));
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    Some((n2.cross(n3) * -d1 + n3.cross(n1) * -d2 + n1.cross(n2) * -d3) / det)
+}
+
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32, i32), Vec<(u32, Vec3)>>,
+    len: usize,
+}
+
+impl SpatialGrid {
+    /// Nodes with non-finite positions are left out
+    pub fn build(nodes: &[Node]) -> Self {
+        let points: Vec<(u32, Vec3)> = nodes.iter()
+            .map(|n| (n.id, Vec3::from(n.data.position)))
+            .filter(|(_, p)| p.is_finite())
+            .collect();
+        let (min, max) = points.iter().fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(lo, hi), (_, p)| (lo.min(*p), hi.max(*p)));
+        let extent = (max - min).max_element().max(0.0);
+        let cells_per_axis = (points.len() as f32 / NODES_PER_CELL).cbrt().max(1.0);
+        let cell_size = (extent / cells_per_axis).max(MIN_CELL_SIZE);
+
+        let mut grid = Self { cell_size, cells: HashMap::new(), len: points.len() };
+        for (id, point) in points {
+            grid.cells.entry(grid.cell(point)).or_default().push((id, point));
+        }
+        grid
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn cell(&self, point: Vec3) -> (i32, i32, i32) {
+        let c = (point / self.cell_size).floor();
+        (c.x as i32, c.y as i32, c.z as i32)
+    }
+
+    // Every point in cells overlapping the box; all of them when the box spans more
+    // cells than are occupied
+    fn candidates(&self, min: Vec3, max: Vec3) -> Box<dyn Iterator<Item = &(u32, Vec3)> + '_> {
+        let (lo, hi) = ((min / self.cell_size).floor(), (max / self.cell_size).floor());
+        let span = (hi - lo + Vec3::ONE).max(Vec3::ZERO);
+        if !span.is_finite() || span.x * span.y * span.z > self.cells.len() as f32 {
+            return Box::new(self.cells.values().flatten());
+        }
+        let (lo, hi) = ((lo.x as i32, lo.y as i32, lo.z as i32), (hi.x as i32, hi.y as i32, hi.z as i32));
+        Box::new((lo.0..=hi.0)
+            .flat_map(move |x| (lo.1..=hi.1).flat_map(move |y| (lo.2..=hi.2).map(move |z| (x, y, z))))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten())
+    }
+
+    pub fn query(&self, query: &SpatialQuery, limit: usize) -> (Vec<SpatialHit>, usize) {
+        match query {
+            SpatialQuery::Radius { center, radius } => self.within(*center, *radius, limit),
+            SpatialQuery::Frustum(frustum) => self.in_frustum(frustum, limit),
+        }
+    }
+
+    /// Nodes within `radius` of `center`, nearest first with ties by id. Returns at most
+    /// `limit` of them and how many there were in all.
+    pub fn within(&self, center: Vec3, radius: f32, limit: usize) -> (Vec<SpatialHit>, usize) {
+        let hits = self.candidates(center - Vec3::splat(radius), center + Vec3::splat(radius))
+            .map(|(id, p)| SpatialHit { node_id: *id, distance: p.distance(center) })
+            .filter(|hit| hit.distance <= radius)
+            .collect();
+        nearest(hits, limit)
+    }
+
+    /// Nodes inside `frustum`, nearest the eye first
+    pub fn in_frustum(&self, frustum: &Frustum, limit: usize) -> (Vec<SpatialHit>, usize) {
+        let (min, max) = frustum.bounds().unwrap_or((Vec3::splat(f32::MIN), Vec3::splat(f32::MAX)));
+        let hits = self.candidates(min, max)
+            .filter(|(_, p)| frustum.contains(*p))
+            .map(|(id, p)| SpatialHit { node_id: *id, distance: p.distance(frustum.eye) })
+            .collect();
+        nearest(hits, limit)
+    }
+}
+
+fn nearest(mut hits: Vec<SpatialHit>, limit: usize) -> (Vec<SpatialHit>, usize) {
+    let total = hits.len();
+    hits.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.node_id.cmp(&b.node_id)));
+    hits.truncate(limit);
+    (hits, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    // A 5x5x5 lattice with unit spacing, ids in x-major order from 1
+    fn lattice() -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for x in 0..5 {
+            for y in 0..5 {
+                for z in 0..5 {
+                    let id = 1 + x * 25 + y * 5 + z;
+                    let mut node = Node::new_with_id(format!("n{}", id), Some(id));
+                    node.data.position = Vec3Data::new(x as f32, y as f32, z as f32);
+                    nodes.push(node);
+                }
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_radius_queries_match_a_linear_scan() {
+        let nodes = lattice();
+        let grid = SpatialGrid::build(&nodes);
+        assert_eq!(grid.len(), 125);
+
+        // The centre of the lattice and its six face neighbours, then the rest by id
+        let (hits, total) = grid.within(Vec3::new(2.0, 2.0, 2.0), 1.0, 10);
+        assert_eq!(total, 7);
+        let ids: Vec<u32> = hits.iter().map(|h| h.node_id).collect();
+        assert_eq!(ids, [63, 38, 58, 62, 64, 68, 88]);
+        assert_eq!(hits[1].distance, 1.0);
+
+        let (capped, total) = grid.within(Vec3::new(2.0, 2.0, 2.0), 1.0, 3);
+        assert_eq!((capped.len(), total), (3, 7));
+        assert_eq!(capped, hits[..3]);
+
+        // Any centre and radius agrees with checking every node
+        for (center, radius) in [(Vec3::new(0.3, 4.1, 2.7), 1.6), (Vec3::new(-3.0, 0.0, 0.0), 3.5), (Vec3::ZERO, 100.0)] {
+            let (hits, _) = grid.within(center, radius, usize::MAX);
+            let mut expected: Vec<u32> = nodes.iter()
+                .filter(|n| Vec3::from(n.data.position).distance(center) <= radius)
+                .map(|n| n.id)
+                .collect();
+            let mut ids: Vec<u32> = hits.iter().map(|h| h.node_id).collect();
+            ids.sort();
+            expected.sort();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn test_frustum_membership() {
+        let grid = SpatialGrid::build(&lattice());
+        // Looking down +x from behind the lattice's centre line, seeing 1.1 sideways per unit ahead
+        let frustum = Frustum::perspective(
+            Vec3::new(-1.0, 2.0, 2.0), Vec3::X, Vec3::Y, 2.0 * 1.1f32.atan(), 1.0, 0.5, 2.5,
+        ).unwrap();
+        let (hits, total) = grid.in_frustum(&frustum, usize::MAX);
+        // x = 0 sees a 3x3 patch, x = 1 all 5x5, and x = 2 is past the far plane
+        assert_eq!(total, 9 + 25);
+        assert_eq!(hits[0], SpatialHit { node_id: 13, distance: 1.0 });
+        assert!(hits.iter().all(|h| frustum.contains(Vec3::new(((h.node_id - 1) / 25) as f32, ((h.node_id - 1) / 5 % 5) as f32, ((h.node_id - 1) % 5) as f32))));
+        assert!(hits.windows(2).all(|w| w[0].distance <= w[1].distance));
+        assert!(Frustum::perspective(Vec3::ZERO, Vec3::X, Vec3::X, 1.0, 1.0, 0.1, 10.0).is_err());
+    }
+}
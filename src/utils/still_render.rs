@@ -0,0 +1,386 @@
+//! Still images of a graph snapshot for embedding in wikis and chat. The layout is
+//! projected to 2-D and fitted to the image, nodes become circles sized and coloured as
+//! the client draws them and edges become lines that fade with their weight.
+//!
+//! Both formats come from one scene: SVG writes it out as elements, PNG rasterises it
+//! with a small anti-aliased rasteriser. The binary carries no font, so labels are only
+//! drawn in SVG.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::utils::gltf_export::{DEFAULT_NODE_SIZE, NODE_RADIUS_PER_SIZE};
+use crate::utils::label_placement::LabelPlacement;
+use crate::utils::projection::Projection;
+
+pub const MIN_SIZE: u32 = 64;
+pub const MAX_SIZE: u32 = 4096;
+pub const MAX_LABELS: usize = 200;
+const MARGIN: f32 = 24.0;
+const MIN_RADIUS: f32 = 1.5;
+const MAX_RADIUS: f32 = 32.0;
+const FONT_SIZE: f32 = 11.0;
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const EDGE_COLOR: [u8; 3] = [136, 136, 136];
+const DEFAULT_NODE_COLOR: [u8; 3] = [102, 153, 255];
+const LABEL_COLOR: [u8; 3] = [34, 34, 34];
+
+#[derive(Debug, Clone, Copy)]
+pub struct StillOptions {
+    pub width: u32,
+    // Follows the layout's aspect ratio when left out
+    pub height: Option<u32>,
+    pub projection: Projection,
+    // Labels for this many of the most important nodes
+    pub labels: usize,
+    pub max_nodes: usize,
+}
+
+impl Default for StillOptions {
+    fn default() -> Self {
+        Self { width: 1200, height: None, projection: Projection::XY, labels: 0, max_nodes: 20_000 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Circle {
+    x: f32,
+    y: f32,
+    r: f32,
+    color: [u8; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    from: [f32; 2],
+    to: [f32; 2],
+    alpha: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Label {
+    x: f32,
+    y: f32,
+    text: String,
+}
+
+/// A laid-out image, ready to be written as SVG or PNG
+#[derive(Debug, Clone)]
+pub struct Still {
+    pub width: u32,
+    pub height: u32,
+    circles: Vec<Circle>,
+    lines: Vec<Line>,
+    labels: Vec<Label>,
+}
+
+fn parse_color(color: Option<&str>) -> [u8; 3] {
+    let hex = match color.and_then(|c| c.strip_prefix('#')) {
+        Some(hex) if hex.len() == 6 => hex,
+        _ => return DEFAULT_NODE_COLOR,
+    };
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match (channel(0), channel(2), channel(4)) {
+        (Some(r), Some(g), Some(b)) => [r, g, b],
+        _ => DEFAULT_NODE_COLOR,
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Lays out `nodes` and `edges`, which should come from one snapshot. `placements` are the
+/// label spots from the de-overlap pass; without them the best-connected nodes get a label
+/// just above them and may overlap.
+pub fn compose(
+    nodes: &[Node],
+    edges: &[Edge],
+    placements: &HashMap<u32, LabelPlacement>,
+    options: &StillOptions,
+) -> Result<Still, String> {
+    if nodes.len() > options.max_nodes {
+        return Err(format!("graph has {} nodes; stills are limited to {}", nodes.len(), options.max_nodes));
+    }
+    let projected: HashMap<u32, [f32; 2]> = nodes.iter()
+        .map(|n| (n.id, options.projection.apply(n.data.position)))
+        .filter(|(_, p)| p[0].is_finite() && p[1].is_finite())
+        .collect();
+
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for p in projected.values() {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    if projected.is_empty() {
+        (min, max) = ([-1.0; 2], [1.0; 2]);
+    }
+    // A single node or a flat layout still gets a square of room
+    let span = [(max[0] - min[0]).max(1e-3), (max[1] - min[1]).max(1e-3)];
+
+    let width = options.width.clamp(MIN_SIZE, MAX_SIZE);
+    let inner_width = width as f32 - 2.0 * MARGIN;
+    let height = options.height.unwrap_or_else(|| (inner_width * span[1] / span[0] + 2.0 * MARGIN).round() as u32)
+        .clamp(MIN_SIZE, MAX_SIZE);
+    let scale = (inner_width / span[0]).min((height as f32 - 2.0 * MARGIN) / span[1]);
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    // Projected y points up, as in the xy view
+    let to_image = |p: [f32; 2]| [
+        width as f32 / 2.0 + (p[0] - center[0]) * scale,
+        height as f32 / 2.0 - (p[1] - center[1]) * scale,
+    ];
+
+    let max_weight = edges.iter().map(|e| e.weight).filter(|w| w.is_finite()).fold(0.0f32, f32::max);
+    let lines = edges.iter().filter_map(|edge| {
+        let (from, to) = (projected.get(&edge.source)?, projected.get(&edge.target)?);
+        let strength = if max_weight > 0.0 { (edge.weight / max_weight).clamp(0.0, 1.0) } else { 1.0 };
+        Some(Line { from: to_image(*from), to: to_image(*to), alpha: 0.15 + 0.6 * strength })
+    }).collect();
+
+    let mut ordered: Vec<&Node> = nodes.iter().filter(|n| projected.contains_key(&n.id)).collect();
+    ordered.sort_by_key(|n| n.id);
+    let circles = ordered.iter().map(|node| {
+        let [x, y] = to_image(projected[&node.id]);
+        let size = node.size.unwrap_or(DEFAULT_NODE_SIZE);
+        let r = (size * NODE_RADIUS_PER_SIZE * scale).clamp(MIN_RADIUS, MAX_RADIUS);
+        Circle { x, y, r, color: parse_color(node.color.as_deref()) }
+    }).collect();
+
+    let wanted = options.labels.min(MAX_LABELS);
+    let labels = if wanted == 0 {
+        Vec::new()
+    } else if !placements.is_empty() {
+        let mut placed: Vec<&LabelPlacement> = placements.values()
+            .filter(|p| p.visible && p.priority < wanted && projected.contains_key(&p.node_id))
+            .collect();
+        placed.sort_by_key(|p| p.priority);
+        placed.iter().filter_map(|p| {
+            let node = ordered.iter().find(|n| n.id == p.node_id)?;
+            let [x, y] = to_image(projected[&node.id]);
+            Some(Label { x: x + p.offset[0] * scale, y: y - p.offset[1] * scale, text: node.label.clone() })
+        }).collect()
+    } else {
+        let mut degree: HashMap<u32, usize> = HashMap::new();
+        for edge in edges {
+            *degree.entry(edge.source).or_default() += 1;
+            *degree.entry(edge.target).or_default() += 1;
+        }
+        let mut ranked = ordered.clone();
+        ranked.sort_by_key(|n| (std::cmp::Reverse(degree.get(&n.id).copied().unwrap_or(0)), n.id));
+        ranked.iter().take(wanted).map(|node| {
+            let [x, y] = to_image(projected[&node.id]);
+            let size = node.size.unwrap_or(DEFAULT_NODE_SIZE);
+            let r = (size * NODE_RADIUS_PER_SIZE * scale).clamp(MIN_RADIUS, MAX_RADIUS);
+            Label { x, y: y - r - 3.0, text: node.label.clone() }
+        }).collect()
+    };
+
+    Ok(Still { width, height, circles, lines, labels })
+}
+
+impl Still {
+    pub fn node_count(&self) -> usize {
+        self.circles.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.width, h = self.height);
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(BACKGROUND));
+        let _ = writeln!(svg, r#"<g stroke="{}" stroke-width="1">"#, hex(EDGE_COLOR));
+        for line in &self.lines {
+            let _ = writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke-opacity="{:.2}"/>"#,
+                line.from[0], line.from[1], line.to[0], line.to[1], line.alpha);
+        }
+        svg.push_str("</g>\n<g>\n");
+        for circle in &self.circles {
+            let _ = writeln!(svg, r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="{}"/>"#,
+                circle.x, circle.y, circle.r, hex(circle.color));
+        }
+        svg.push_str("</g>\n");
+        if !self.labels.is_empty() {
+            let _ = writeln!(svg, r#"<g font-family="sans-serif" font-size="{}" fill="{}" text-anchor="middle">"#,
+                FONT_SIZE, hex(LABEL_COLOR));
+            for label in &self.labels {
+                let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}">{}</text>"#, label.x, label.y, escape_xml(&label.text));
+            }
+            svg.push_str("</g>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The scene rasterised to an RGB PNG, without labels
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut canvas = Canvas::new(self.width, self.height, BACKGROUND);
+        for line in &self.lines {
+            canvas.line(line.from, line.to, EDGE_COLOR, line.alpha);
+        }
+        for circle in &self.circles {
+            canvas.circle(circle.x, circle.y, circle.r, circle.color);
+        }
+        canvas.encode_png()
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: [u8; 3]) -> Self {
+        let fill = background.map(|c| c as f32);
+        Self { width, height, pixels: vec![fill; (width * height) as usize] }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 3], alpha: f32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 || alpha <= 0.0 {
+            return;
+        }
+        let pixel = &mut self.pixels[(y as u32 * self.width + x as u32) as usize];
+        let alpha = alpha.min(1.0);
+        for (channel, c) in pixel.iter_mut().zip(color) {
+            *channel += (c as f32 - *channel) * alpha;
+        }
+    }
+
+    // Coverage from the distance to the segment, so lines come out about a pixel wide
+    fn line(&mut self, from: [f32; 2], to: [f32; 2], color: [u8; 3], alpha: f32) {
+        let (x0, x1) = (from[0].min(to[0]).floor() as i64 - 1, from[0].max(to[0]).ceil() as i64 + 1);
+        let (y0, y1) = (from[1].min(to[1]).floor() as i64 - 1, from[1].max(to[1]).ceil() as i64 + 1);
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length_sq = dx * dx + dy * dy;
+        for y in y0.max(0)..=y1.min(self.height as i64 - 1) {
+            for x in x0.max(0)..=x1.min(self.width as i64 - 1) {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let t = if length_sq > 0.0 { (((px - from[0]) * dx + (py - from[1]) * dy) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+                let (ex, ey) = (from[0] + t * dx - px, from[1] + t * dy - py);
+                let coverage = (1.0 - (ex * ex + ey * ey).sqrt()).max(0.0);
+                self.blend(x, y, color, alpha * coverage);
+            }
+        }
+    }
+
+    fn circle(&mut self, cx: f32, cy: f32, r: f32, color: [u8; 3]) {
+        let (x0, x1) = ((cx - r).floor() as i64 - 1, (cx + r).ceil() as i64 + 1);
+        let (y0, y1) = ((cy - r).floor() as i64 - 1, (cy + r).ceil() as i64 + 1);
+        for y in y0.max(0)..=y1.min(self.height as i64 - 1) {
+            for x in x0.max(0)..=x1.min(self.width as i64 - 1) {
+                let (px, py) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                let coverage = (r + 0.5 - (px * px + py * py).sqrt()).clamp(0.0, 1.0);
+                self.blend(x, y, color, coverage);
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(((self.width * 3 + 1) * self.height) as usize);
+        for row in self.pixels.chunks(self.width as usize) {
+            // Filter type 0: rows as they are
+            raw.push(0);
+            raw.extend(row.iter().flat_map(|p| p.map(|c| c.round().clamp(0.0, 255.0) as u8)));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let _ = encoder.write_all(&raw);
+        let compressed = encoder.finish().unwrap_or_default();
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per channel, truecolour, deflate, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [(b"IHDR", header.as_slice()), (b"IDAT", compressed.as_slice()), (b"IEND", &[][..])] {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(kind);
+            crc.update(data);
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            png.extend_from_slice(&crc.finalize().to_be_bytes());
+        }
+        png
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    fn graph() -> (Vec<Node>, Vec<Edge>) {
+        let nodes = [(1, "Rust", [-10.0, 0.0], Some("#ff0000")), (2, "Ownership", [10.0, 5.0], None), (3, "Lifetimes & <Borrows>", [0.0, -5.0], Some("#00aa00"))]
+            .into_iter()
+            .map(|(id, label, [x, y], color)| {
+                let mut node = Node::new_with_id(label.to_string(), Some(id)).with_label(label.to_string());
+                node.data.position = Vec3Data::new(x, y, 0.0);
+                node.color = color.map(str::to_string);
+                node.size = Some(20.0);
+                node
+            })
+            .collect();
+        (nodes, vec![Edge::new(1, 2, 4.0), Edge::new(1, 3, 1.0)])
+    }
+
+    #[test]
+    fn test_svg_matches_golden_file() {
+        let (nodes, edges) = graph();
+        let options = StillOptions { width: 400, labels: 2, ..Default::default() };
+        let still = compose(&nodes, &edges, &HashMap::new(), &options).unwrap();
+        let svg = still.to_svg();
+
+        assert_eq!((still.width, still.height), (400, 224));
+        assert!(svg.contains(r#"viewBox="0 0 400 224""#));
+        assert_eq!(svg.matches("<circle ").count(), 3);
+        assert_eq!(svg.matches("<line ").count(), 2);
+        assert_eq!(svg.matches("<text ").count(), 2);
+        assert_eq!(svg, include_str!("still_render_golden.svg"));
+
+        // The label pass and the node cap
+        let placement = LabelPlacement { node_id: 3, priority: 0, visible: true, offset: [0.0, -2.0], offset3d: [0.0; 3], bounds: [0.0; 4] };
+        let placed = compose(&nodes, &edges, &HashMap::from([(3, placement)]), &options).unwrap().to_svg();
+        assert_eq!(placed.matches("<text ").count(), 1);
+        assert!(placed.contains("Lifetimes &amp; &lt;Borrows&gt;"));
+        assert!(compose(&nodes, &edges, &HashMap::new(), &StillOptions { max_nodes: 2, ..options }).is_err());
+    }
+
+    #[test]
+    fn test_png_is_a_valid_image_of_the_scene() {
+        let (nodes, edges) = graph();
+        let still = compose(&nodes, &edges, &HashMap::new(), &StillOptions { width: 200, height: Some(100), ..Default::default() }).unwrap();
+        let png = still.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 200);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 100);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        // Red node drawn where the SVG puts it
+        let mut canvas = Canvas::new(200, 100, BACKGROUND);
+        for circle in &still.circles {
+            canvas.circle(circle.x, circle.y, circle.r, circle.color);
+        }
+        let rust = &still.circles[0];
+        let pixel = canvas.pixels[(rust.y as u32 * 200 + rust.x as u32) as usize];
+        assert_eq!(pixel.map(|c| c.round() as u8), [255, 0, 0]);
+    }
+}
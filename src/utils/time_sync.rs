@@ -0,0 +1,99 @@
+//! Server timing for client-side interpolation. Position frames can carry the simulated
+//! moment they show on the server's monotonic clock and the simulated time their step
+//! covered, a whole number of 16ms substeps. A `time_sync` round trip lets a client
+//! estimate its offset from the server clock and the link latency, NTP-style.
+
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+static SERVER_EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Microseconds on the server's monotonic clock, counted from the first time it was read
+pub fn server_time_us(now: Instant) -> u64 {
+    let epoch = *SERVER_EPOCH.get_or_init(Instant::now);
+    now.saturating_duration_since(epoch).as_micros() as u64
+}
+
+/// Timing carried in a position frame's header, for clients that asked for it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    pub server_time_us: u64,
+    // Simulated time the step covered; 0 for frames no step produced, like resyncs
+    pub tick_delta_us: u32,
+}
+
+impl FrameTiming {
+    /// Timing for a frame sent now that no physics step produced
+    pub fn unstepped() -> Self {
+        Self { server_time_us: server_time_us(Instant::now()), tick_delta_us: 0 }
+    }
+}
+
+/// The server half of a time sync: the client's clock echoed back with when the
+/// request arrived and when the reply left, both in server microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeSyncReply {
+    pub client_time: f64,
+    pub server_receive: u64,
+    pub server_send: u64,
+}
+
+impl TimeSyncReply {
+    pub fn to_event(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        value["type"] = "time_sync".into();
+        value.to_string()
+    }
+}
+
+/// A completed round trip as a client sees it, in milliseconds on each side's clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeSyncSample {
+    pub client_send_ms: f64,
+    pub server_receive_ms: f64,
+    pub server_send_ms: f64,
+    pub client_receive_ms: f64,
+}
+
+impl TimeSyncSample {
+    pub fn from_reply(reply: &TimeSyncReply, client_receive_ms: f64) -> Self {
+        Self {
+            client_send_ms: reply.client_time,
+            server_receive_ms: reply.server_receive as f64 / 1000.0,
+            server_send_ms: reply.server_send as f64 / 1000.0,
+            client_receive_ms,
+        }
+    }
+
+    /// Server clock minus client clock, assuming the link is symmetric
+    pub fn offset_ms(&self) -> f64 {
+        ((self.server_receive_ms - self.client_send_ms) + (self.server_send_ms - self.client_receive_ms)) / 2.0
+    }
+
+    /// Time on the wire both ways, leaving out the server's own handling time
+    pub fn round_trip_ms(&self) -> f64 {
+        (self.client_receive_ms - self.client_send_ms) - (self.server_send_ms - self.server_receive_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_sync_round_trip_math() {
+        // Server clock 500ms ahead, 20ms each way, 2ms spent on the server
+        let reply = TimeSyncReply { client_time: 1000.0, server_receive: 1_520_000, server_send: 1_522_000 };
+        let sample = TimeSyncSample::from_reply(&reply, 1042.0);
+        assert!((sample.offset_ms() - 500.0).abs() < 1e-9);
+        assert!((sample.round_trip_ms() - 40.0).abs() < 1e-9);
+
+        let event: serde_json::Value = serde_json::from_str(&reply.to_event()).unwrap();
+        assert_eq!(event["type"], "time_sync");
+        assert_eq!(event["clientTime"], 1000.0);
+        assert_eq!(event["serverReceive"], 1_520_000);
+        assert_eq!(event["serverSend"], 1_522_000);
+    }
+}
@@ -0,0 +1,170 @@
+//! Per-node prioritisation of position frames for throttled clients.
+//!
+//! A client that can only take every k-th frame doesn't drop whole frames. Priority
+//! nodes (grabbed, the client's focus and its neighbours, hubs, anything the client
+//! asked for) go out in every frame; the rest are split into k buckets by node id and
+//! each frame carries the next bucket, so every node is still refreshed once every k
+//! frames.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::utils::coloring::pagerank;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+// Physics broadcasts on a 16ms tick
+pub const SOURCE_FRAME_RATE: f32 = 60.0;
+// Beyond this a node would go stale for too long; better to just send bigger frames
+pub const MAX_BUCKETS: usize = 16;
+// Hubs that are always sent, by PageRank
+pub const PRIORITY_HUB_COUNT: usize = 10;
+// Nodes a single client may mark as priority
+pub const MAX_CLIENT_PRIORITY_NODES: usize = 64;
+
+/// How many rotating buckets a client taking `effective_rate` frames a second needs
+pub fn bucket_count(source_rate: f32, effective_rate: f32) -> usize {
+    if effective_rate <= 0.0 || effective_rate >= source_rate {
+        return 1;
+    }
+    ((source_rate / effective_rate).ceil() as usize).clamp(1, MAX_BUCKETS)
+}
+
+/// The `count` highest-PageRank nodes
+pub fn hub_nodes(nodes: &[Node], edges: &[Edge], count: usize) -> Vec<u32> {
+    top_ranked(&pagerank(nodes, edges), count)
+}
+
+/// The `count` highest-ranked nodes of an already computed PageRank
+pub fn top_ranked(ranks: &HashMap<u32, f32>, count: usize) -> Vec<u32> {
+    let mut ranked: Vec<(u32, f32)> = ranks.iter().map(|(&id, &rank)| (id, rank)).collect();
+    // Ties broken by id so the set is stable between recomputes
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+    ranked.into_iter().take(count).map(|(id, _)| id).collect()
+}
+
+/// A node and everything one edge away from it
+pub fn ego_network(node_id: u32, edges: &[Edge]) -> HashSet<u32> {
+    let mut network: HashSet<u32> = edges.iter()
+        .filter_map(|e| {
+            if e.source == node_id {
+                Some(e.target)
+            } else if e.target == node_id {
+                Some(e.source)
+            } else {
+                None
+            }
+        })
+        .collect();
+    network.insert(node_id);
+    network
+}
+
+/// Picks which nodes of each frame a throttled client is sent
+#[derive(Debug, Default)]
+pub struct FrameScheduler {
+    frame: u64,
+}
+
+impl FrameScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Priority nodes plus the current bucket of the rest; advances to the next bucket
+    pub fn select<F>(&mut self, positions: Vec<(u32, BinaryNodeData)>, is_priority: F, buckets: usize) -> Vec<(u32, BinaryNodeData)>
+    where
+        F: Fn(u32) -> bool,
+    {
+        let buckets = buckets.max(1) as u64;
+        let current = self.frame % buckets;
+        self.frame = self.frame.wrapping_add(1);
+        if buckets == 1 {
+            return positions;
+        }
+        positions.into_iter()
+            .filter(|(id, _)| is_priority(*id) || *id as u64 % buckets == current)
+            .collect()
+    }
+}
+
+/// Counts how often each node shows up across frames; used by tests and diagnostics
+pub fn appearance_counts(frames: &[Vec<(u32, BinaryNodeData)>]) -> HashMap<u32, usize> {
+    let mut counts = HashMap::new();
+    for frame in frames {
+        for (id, _) in frame {
+            *counts.entry(*id).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    fn frame(count: u32) -> Vec<(u32, BinaryNodeData)> {
+        (0..count)
+            .map(|id| (id, BinaryNodeData {
+                position: Vec3Data { x: id as f32, y: 0.0, z: 0.0 },
+                velocity: Vec3Data { x: 0.0, y: 0.0, z: 0.0 },
+                mass: 100,
+                flags: 0,
+                padding: [0, 0],
+            }))
+            .collect()
+    }
+
+    fn edge(source: u32, target: u32) -> Edge {
+        Edge::new(source, target, 1.0)
+    }
+
+    #[test]
+    fn test_bucket_count_follows_effective_rate() {
+        assert_eq!(bucket_count(60.0, 60.0), 1);
+        assert_eq!(bucket_count(60.0, 15.0), 4);
+        assert_eq!(bucket_count(60.0, 25.0), 3);
+        assert_eq!(bucket_count(60.0, 0.5), MAX_BUCKETS);
+        assert_eq!(bucket_count(60.0, 0.0), 1);
+    }
+
+    #[test]
+    fn test_priority_nodes_survive_4x_throttle() {
+        let edges = vec![edge(10, 11), edge(10, 12), edge(40, 41)];
+        // Focus on 10 pulls in its neighbours, plus a grabbed node and a client pick
+        let mut priority = ego_network(10, &edges);
+        priority.insert(33);
+        priority.insert(77);
+
+        let mut scheduler = FrameScheduler::new();
+        let buckets = bucket_count(SOURCE_FRAME_RATE, SOURCE_FRAME_RATE / 4.0);
+        let frames: Vec<_> = (0..400)
+            .map(|_| scheduler.select(frame(100), |id| priority.contains(&id), buckets))
+            .collect();
+        let counts = appearance_counts(&frames);
+
+        for id in [10, 11, 12, 33, 77] {
+            assert_eq!(counts[&id], 400, "priority node {}", id);
+        }
+        for id in (0..100).filter(|id| !priority.contains(id)) {
+            let share = counts[&id] as f32 / 400.0;
+            assert!((share - 0.25).abs() < 0.01, "node {} in {:.0}% of frames", id, share * 100.0);
+        }
+        // Each frame is roughly a quarter of the graph plus the priority set
+        assert!(frames.iter().all(|f| f.len() <= 5 + 25));
+    }
+
+    #[test]
+    fn test_unthrottled_frames_pass_through() {
+        let mut scheduler = FrameScheduler::new();
+        assert_eq!(scheduler.select(frame(50), |_| false, 1).len(), 50);
+    }
+
+    #[test]
+    fn test_hubs_are_best_connected() {
+        let nodes: Vec<Node> = (1..=6).map(|id| Node::new_with_id(format!("n{}", id), Some(id))).collect();
+        let edges = vec![edge(1, 2), edge(1, 3), edge(1, 4), edge(1, 5), edge(5, 6)];
+        assert_eq!(hub_nodes(&nodes, &edges, 2), vec![1, 5]);
+    }
+}
@@ -0,0 +1,72 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Intents recognised in STT transcriptions
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceCommand {
+    // "new note: investigate vector clocks"
+    CreateNote { title: String },
+    // "note on this node: check the units"; with nothing after it the next sentence is the note
+    Annotate { text: Option<String> },
+}
+
+/// Picks a command out of a transcription segment, if it contains one
+pub fn parse_voice_command(text: &str) -> Option<VoiceCommand> {
+    // Checked first, "add a note on this node" would otherwise be a note titled "on this node"
+    let annotate = Regex::new(r"(?i)\bnote\s+on\s+this\s+node\b[:,.]?\s*(.*)$").unwrap();
+    if let Some(caps) = annotate.captures(text.trim()) {
+        let note = caps[1].trim();
+        return Some(VoiceCommand::Annotate { text: (!note.is_empty()).then(|| note.to_string()) });
+    }
+    let create_note = Regex::new(r"(?i)\b(?:new|create|add)\s+(?:a\s+)?note\b[:,.]?\s*(.*)$").unwrap();
+    let caps = create_note.captures(text.trim())?;
+    let title = caps[1].trim().trim_end_matches(['.', '!', '?']).trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some(VoiceCommand::CreateNote { title: title.to_string() })
+}
+
+/// Returns `title`, or `title (n)` if it was already used in this session
+pub fn disambiguate_title(title: &str, used: &mut HashMap<String, usize>) -> String {
+    let count = used.entry(title.to_lowercase()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        title.to_string()
+    } else {
+        format!("{} ({})", title, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_create_note() {
+        assert_eq!(
+            parse_voice_command("New note: investigate vector clocks."),
+            Some(VoiceCommand::CreateNote { title: "investigate vector clocks".to_string() })
+        );
+        assert_eq!(
+            parse_voice_command("okay so create a note check the CRDT paper"),
+            Some(VoiceCommand::CreateNote { title: "check the CRDT paper".to_string() })
+        );
+        assert_eq!(parse_voice_command("new note"), None);
+        assert_eq!(
+            parse_voice_command("Add a note on this node: check the units."),
+            Some(VoiceCommand::Annotate { text: Some("check the units.".to_string()) })
+        );
+        assert_eq!(parse_voice_command("note on this node"), Some(VoiceCommand::Annotate { text: None }));
+        assert_eq!(parse_voice_command("show me notes about rust"), None);
+    }
+
+    #[test]
+    fn test_duplicate_titles_get_suffix() {
+        let mut used = HashMap::new();
+        assert_eq!(disambiguate_title("Vector clocks", &mut used), "Vector clocks");
+        assert_eq!(disambiguate_title("vector clocks", &mut used), "vector clocks (2)");
+        assert_eq!(disambiguate_title("Vector clocks", &mut used), "Vector clocks (3)");
+        assert_eq!(disambiguate_title("Lamport", &mut used), "Lamport");
+    }
+}
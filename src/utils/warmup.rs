@@ -0,0 +1,120 @@
+//! Layout warm-up after a (re)build. Physics runs but nothing is broadcast until the
+//! layout has calmed down; clients then get a single keyframe of the settled layout.
+//! Warm-up only advances with physics steps, so it also ends after `max_secs` of wall
+//! time whatever the steps have done, and the graph never stays unready for good.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::WarmupSettings;
+use crate::models::node::Node;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupStatus {
+    pub ready: bool,
+    // 0..1; the iteration budget used so far, or 1 once ready
+    pub progress: f32,
+    pub iterations: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<f32>,
+    // No nodes, so nothing to settle; physics and broadcasts stay paused until some arrive
+    pub empty_graph: bool,
+}
+
+pub struct Warmup {
+    settings: WarmupSettings,
+    iterations: u32,
+    energy: Option<f32>,
+    done: bool,
+    started: Instant,
+}
+
+impl Warmup {
+    pub fn new(settings: WarmupSettings, now: Instant) -> Self {
+        Self { settings, iterations: 0, energy: None, done: false, started: now }
+    }
+
+    /// Whether the wall-clock cap has run out, however many steps were taken
+    pub fn timed_out(&self, now: Instant) -> bool {
+        self.settings.max_secs > 0.0
+            && now.saturating_duration_since(self.started) >= Duration::from_secs_f32(self.settings.max_secs)
+    }
+
+    /// Counts one physics step that moved nodes by `energy`; true once warm-up is over
+    pub fn record(&mut self, energy: f32) -> bool {
+        self.iterations += 1;
+        self.energy = Some(energy);
+        self.done = self.iterations >= self.settings.max_iterations || energy < self.settings.energy_threshold;
+        self.done
+    }
+
+    /// Whether this step is one where connected clients get a progress message
+    pub fn should_report(&self) -> bool {
+        let interval = self.settings.progress_interval.max(1);
+        self.iterations.is_multiple_of(interval)
+    }
+
+    pub fn status(&self) -> WarmupStatus {
+        let progress = if self.done {
+            1.0
+        } else {
+            (self.iterations as f32 / self.settings.max_iterations.max(1) as f32).min(1.0)
+        };
+        WarmupStatus { ready: self.done, progress, iterations: self.iterations, energy: self.energy, empty_graph: false }
+    }
+}
+
+/// Mean squared distance the nodes moved in one step
+pub fn step_energy(before: &HashMap<u32, Node>, after: &[(u32, BinaryNodeData)]) -> f32 {
+    let moves: Vec<f32> = after.iter()
+        .filter_map(|(id, data)| before.get(id).map(|node| (node, data)))
+        .map(|(node, data)| Vec3::from(data.position).distance_squared(Vec3::from(node.data.position)))
+        .collect();
+    if moves.is_empty() {
+        return 0.0;
+    }
+    moves.iter().sum::<f32>() / moves.len() as f32
+}
+
+pub fn progress_message(status: &WarmupStatus) -> String {
+    serde_json::json!({
+        "type": "warmup_progress",
+        "ready": status.ready,
+        "progress": status.progress,
+        "iterations": status.iterations,
+    }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup_ends_on_budget_or_energy() {
+        let settings = WarmupSettings { max_iterations: 4, energy_threshold: 0.01, progress_interval: 2, ..Default::default() };
+        let start = Instant::now();
+        let mut warmup = Warmup::new(settings.clone(), start);
+        assert!(!warmup.record(1.0));
+        assert!(!warmup.should_report());
+        assert!(!warmup.record(1.0));
+        assert!(warmup.should_report());
+        assert_eq!(warmup.status().progress, 0.5);
+        assert!(!warmup.record(0.5));
+        assert!(warmup.record(0.5));
+        assert_eq!(warmup.status().progress, 1.0);
+
+        // A calm layout finishes early
+        let mut warmup = Warmup::new(settings.clone(), start);
+        assert!(warmup.record(0.001));
+        assert!(warmup.status().ready);
+
+        // Without steps it still ends once the wall-clock cap is up
+        let warmup = Warmup::new(WarmupSettings { max_secs: 10.0, ..settings }, start);
+        assert!(!warmup.timed_out(start + Duration::from_secs(9)));
+        assert!(warmup.timed_out(start + Duration::from_secs(10)));
+    }
+}